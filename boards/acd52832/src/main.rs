@@ -51,8 +51,8 @@ const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::Panic
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 4;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 /// Dummy buffer that causes the linker to reserve enough space for the stack.
 #[no_mangle]
@@ -473,10 +473,14 @@ pub unsafe fn main() {
     //
 
     // Setup internal temperature sensor
+    let temp_sensors = static_init!(
+        [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1],
+        [&base_peripherals.temp]
+    );
     let temp = static_init!(
         capsules_extra::temperature::TemperatureSensor<'static>,
         capsules_extra::temperature::TemperatureSensor::new(
-            &base_peripherals.temp,
+            temp_sensors,
             board_kernel.create_grant(
                 capsules_extra::temperature::DRIVER_NUM,
                 &memory_allocation_capability
@@ -680,7 +684,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -64,7 +64,8 @@ mod tests;
 const NUM_PROCS: usize = 4;
 
 // Actual memory for holding the active process structures.
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] = [None; 4];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Static reference to chip for panic dumps.
 static mut CHIP: Option<&'static apollo3::chip::Apollo3<Apollo3DefaultPeripherals>> = None;
@@ -492,7 +493,7 @@ unsafe fn setup() -> (
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
@@ -17,6 +17,7 @@ use capsules_core::virtualizers::virtual_aes_ccm::MuxAES128CCM;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::hil::buzzer::Buzzer;
+use kernel::hil::device_id::DeviceId;
 use kernel::hil::i2c::I2CMaster;
 use kernel::hil::led::LedHigh;
 use kernel::hil::symmetric_encryption::AES128;
@@ -107,8 +108,8 @@ const FAULT_RESPONSE: kernel::process::StopWithDebugFaultPolicy =
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
@@ -474,11 +475,11 @@ pub unsafe fn main() {
     // Setup the CDC-ACM over USB driver that we will use for UART.
     // We use the Adafruit Vendor ID and Product ID since the device is the same.
 
-    // Create the strings we include in the USB descriptor. We use the hardcoded
-    // DEVICEADDR register on the nRF52 to set the serial number.
-    let serial_number_buf = static_init!([u8; 17], [0; 17]);
+    // Create the strings we include in the USB descriptor. The serial number
+    // is derived from the unique device ID in the FICR.
     let serial_number_string: &'static str =
-        nrf52::ficr::FICR_INSTANCE.address_str(serial_number_buf);
+        components::device_id::SerialNumberStringComponent::new(&nrf52::ficr::FICR_INSTANCE)
+            .finalize(components::serial_number_string_component_static!());
     let strings = static_init!(
         [&str; 3],
         [
@@ -712,9 +713,7 @@ pub unsafe fn main() {
     kernel::deferred_call::DeferredCallClient::register(aes_mux);
     base_peripherals.ecb.set_client(aes_mux);
 
-    let serial_num = nrf52840::ficr::FICR_INSTANCE.address();
-
-    let serial_num_bottom_16 = u16::from_le_bytes([serial_num[0], serial_num[1]]);
+    let device_short_addr = nrf52840::ficr::FICR_INSTANCE.short_address();
 
     let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
@@ -722,7 +721,7 @@ pub unsafe fn main() {
         &base_peripherals.ieee802154_radio,
         aes_mux,
         PAN_ID,
-        serial_num_bottom_16,
+        device_short_addr,
     )
     .finalize(components::ieee802154_component_static!(
         nrf52840::ieee802154_radio::Radio,
@@ -822,7 +821,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -0,0 +1,182 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for streaming ADC capture over UDP.
+//!
+//! The stream is bound to `port` and sends datagrams with `MAX_PAYLOAD_LEN`
+//! bytes at most to `dest_port` on `dest_addr`. It does not start sampling;
+//! the board calls `start()` on the returned capsule.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let adc_stream = components::adc_stream::AdcStreamComponent::new(
+//!     udp_send_mux,
+//!     udp_port_table,
+//!     &base_peripherals.adc,
+//!     &nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput2),
+//!     1000,
+//!     COLLECTOR_ADDR,
+//!     capsules_extra::adc_stream::ADC_STREAM_PORT,
+//!     capsules_extra::adc_stream::ADC_STREAM_PORT,
+//!     capsules_extra::adc_stream::DropPolicy::DropOldest,
+//! )
+//! .finalize(components::adc_stream_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     nrf52840::adc::Adc,
+//! ));
+//! adc_stream.start().unwrap();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::adc_stream::{AdcStream, DropPolicy, HEADER_LEN, NUM_BUFFERS};
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::adc;
+use kernel::hil::time::Alarm;
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+/// Samples in the buffers of the stream, as many as fill a datagram each.
+pub const SAMPLES_LEN: usize = NUM_BUFFERS * (MAX_PAYLOAD_LEN - HEADER_LEN) / 2;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! adc_stream_component_static {
+    ($A:ty, $ADC:ty $(,)?) => {{
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let tx_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let samples = kernel::static_buf!([u16; components::adc_stream::SAMPLES_LEN]);
+        let stream = kernel::static_buf!(capsules_extra::adc_stream::AdcStream<'static, $ADC>);
+
+        (udp_send, udp_vis_cap, net_cap, tx_buffer, samples, stream)
+    };};
+}
+
+pub struct AdcStreamComponent<
+    A: Alarm<'static> + 'static,
+    ADC: adc::AdcHighSpeed<'static> + 'static,
+> {
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    port_table: &'static UdpPortManager,
+    adc: &'static ADC,
+    channel: &'static ADC::Channel,
+    frequency: u32,
+    dest_addr: IPAddr,
+    dest_port: u16,
+    port: u16,
+    policy: DropPolicy,
+}
+
+impl<A: Alarm<'static>, ADC: adc::AdcHighSpeed<'static>> AdcStreamComponent<A, ADC> {
+    pub fn new(
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        port_table: &'static UdpPortManager,
+        adc: &'static ADC,
+        channel: &'static ADC::Channel,
+        frequency: u32,
+        dest_addr: IPAddr,
+        dest_port: u16,
+        port: u16,
+        policy: DropPolicy,
+    ) -> Self {
+        Self {
+            udp_send_mux,
+            port_table,
+            adc,
+            channel,
+            frequency,
+            dest_addr,
+            dest_port,
+            port,
+            policy,
+        }
+    }
+}
+
+impl<A: Alarm<'static>, ADC: adc::AdcHighSpeed<'static>> Component for AdcStreamComponent<A, ADC> {
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u16; SAMPLES_LEN]>,
+        &'static mut MaybeUninit<AdcStream<'static, ADC>>,
+    );
+    type Output = &'static AdcStream<'static, ADC>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.1.write(UdpVisibilityCapability::new(&create_cap));
+        let net_cap = s.2.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+
+        let socket = match self.port_table.create_socket() {
+            Ok(socket) => socket,
+            Err(_) => panic!("No UDP socket for the ADC stream"),
+        };
+        // The stream only sends, the receive binding is unused
+        match self.port_table.bind(socket, self.port, net_cap) {
+            Ok((send_bind, _recv_bind)) => {
+                udp_send.set_binding(send_bind);
+            }
+            Err(_) => panic!("ADC stream port bound already"),
+        }
+
+        let tx_buffer = s.3.write([0; MAX_PAYLOAD_LEN]);
+        let samples = s.4.write([0; SAMPLES_LEN]);
+        let stream = s.5.write(AdcStream::new(
+            self.adc,
+            self.channel,
+            self.frequency,
+            udp_send,
+            net_cap,
+            self.dest_addr,
+            self.dest_port,
+            self.policy,
+            samples,
+            tx_buffer,
+        ));
+        udp_send.set_client(stream);
+        adc::AdcHighSpeed::set_highspeed_client(self.adc, stream);
+        adc::Adc::set_client(self.adc, stream);
+
+        stream
+    }
+}
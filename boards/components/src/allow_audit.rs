@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the allow buffer auditor.
+//!
+//! Registers an `AllowAuditor` with the kernel that warns about buffers
+//! processes keep allowed for longer than `max_age_ms` or still hold when they
+//! terminate.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! components::allow_audit::AllowAuditComponent::new(board_kernel, mux_alarm, 10_000)
+//!     .finalize(components::allow_audit_component_static!(nrf52840::rtc::Rtc<'static>));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::allow_audit::{AllowAuditor, DEFAULT_MAX_OUTSTANDING};
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! allow_audit_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let auditor = kernel::static_buf!(
+            capsules_extra::allow_audit::AllowAuditor<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                { capsules_extra::allow_audit::DEFAULT_MAX_OUTSTANDING },
+            >
+        );
+
+        (alarm, auditor)
+    };};
+}
+
+pub struct AllowAuditComponent<A: 'static + time::Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    max_age_ms: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>> AllowAuditComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        max_age_ms: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+            max_age_ms,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> Component for AllowAuditComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            AllowAuditor<'static, VirtualMuxAlarm<'static, A>, DEFAULT_MAX_OUTSTANDING>,
+        >,
+    );
+    type Output =
+        &'static AllowAuditor<'static, VirtualMuxAlarm<'static, A>, DEFAULT_MAX_OUTSTANDING>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let process_management_cap = create_capability!(capabilities::ProcessManagementCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let auditor = static_buffer
+            .1
+            .write(AllowAuditor::new(alarm, self.max_age_ms));
+        alarm.set_alarm_client(auditor);
+
+        self.board_kernel
+            .add_process_observer(auditor, &process_management_cap);
+        auditor.start();
+
+        auditor
+    }
+}
@@ -0,0 +1,76 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for installing applications with the dynamic process loader.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_install = components::app_install::AppInstallComponent::new(
+//!     &base_peripherals.nvmc,
+//!     capsules_extra::app_update::FlashRegion::new(160, spare_flash),
+//!     loader,
+//! )
+//! .finalize(components::app_install_component_static!(nrf52840::nvmc::Nvmc));
+//! ```
+
+use capsules_extra::app_install::AppInstall;
+use capsules_extra::app_update::FlashRegion;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash::{Flash, HasClient};
+use kernel::process::DynamicProcessLoading;
+
+#[macro_export]
+macro_rules! app_install_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let app_install = kernel::static_buf!(capsules_extra::app_install::AppInstall<'static, $F>);
+
+        (page, app_install)
+    };};
+}
+
+pub struct AppInstallComponent<F: 'static + Flash + HasClient<'static, AppInstall<'static, F>>> {
+    flash: &'static F,
+    region: FlashRegion,
+    loader: &'static dyn DynamicProcessLoading,
+}
+
+impl<F: 'static + Flash + HasClient<'static, AppInstall<'static, F>>> AppInstallComponent<F> {
+    pub fn new(
+        flash: &'static F,
+        region: FlashRegion,
+        loader: &'static dyn DynamicProcessLoading,
+    ) -> Self {
+        Self {
+            flash,
+            region,
+            loader,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, AppInstall<'static, F>>> Component
+    for AppInstallComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<AppInstall<'static, F>>,
+    );
+    type Output = &'static AppInstall<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page = static_buffer.0.write(F::Page::default());
+        let app_install =
+            static_buffer
+                .1
+                .write(AppInstall::new(self.flash, page, self.region, self.loader));
+        HasClient::set_client(self.flash, app_install);
+        app_install.register();
+
+        app_install
+    }
+}
@@ -0,0 +1,90 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the preferences of processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_preferences = components::app_preferences::AppPreferencesComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_preferences::DRIVER_NUM,
+//!     kv_store,
+//! )
+//! .finalize(components::app_preferences_component_static!(
+//!     capsules_extra::tickv::TicKVStore<
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<nrf52840::nvmc::Nvmc>,
+//!     >,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! ```
+
+use capsules_extra::app_preferences::{AppPreferences, VALUE_LEN};
+use capsules_extra::kv_store::{KVStore, HEADER_LENGTH};
+use capsules_extra::provisioning::KEY_LEN;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+
+/// Size of the value buffer, including the KV store header.
+pub const VALUE_BUF_LEN: usize = VALUE_LEN + HEADER_LENGTH;
+
+#[macro_export]
+macro_rules! app_preferences_component_static {
+    ($K:ty, $T:ty $(,)?) => {{
+        let app_preferences =
+            kernel::static_buf!(capsules_extra::app_preferences::AppPreferences<'static, $K, $T>);
+        let key = kernel::static_buf!([u8; capsules_extra::provisioning::KEY_LEN]);
+        let value = kernel::static_buf!([u8; $crate::app_preferences::VALUE_BUF_LEN]);
+
+        (app_preferences, key, value)
+    };};
+}
+
+pub struct AppPreferencesComponent<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    kv_store: &'static KVStore<'static, K, T>,
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> AppPreferencesComponent<K, T> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        kv_store: &'static KVStore<'static, K, T>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            kv_store,
+        }
+    }
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> Component
+    for AppPreferencesComponent<K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<AppPreferences<'static, K, T>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUF_LEN]>,
+    );
+    type Output = &'static AppPreferences<'static, K, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let app_preferences = static_buffer.0.write(AppPreferences::new(
+            self.kv_store,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            static_buffer.1.write([0; KEY_LEN]),
+            static_buffer.2.write([0; VALUE_BUF_LEN]),
+        ));
+        self.kv_store.set_client(app_preferences);
+
+        app_preferences
+    }
+}
@@ -0,0 +1,175 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for over-the-air application updates.
+//!
+//! `AppUpdateComponent` records the boot in the boot records, so it must be
+//! finalized once at boot, after the processes were loaded.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let app_update = components::app_update::AppUpdateComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.nvmc,
+//!     slots,
+//!     records,
+//!     checker,
+//!     &["updater"],
+//!     Some(nrf52840::power::reset),
+//! )
+//! .finalize(components::app_update_component_static!(nrf52840::nvmc::Nvmc));
+//!
+//! let update_receiver = components::app_update::UartUpdateReceiverComponent::new(
+//!     uart_mux,
+//!     app_update,
+//! )
+//! .finalize(components::uart_update_receiver_component_static!(256));
+//! let _ = update_receiver.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
+use capsules_extra::app_update::{AppUpdate, AppUpdater, FlashRegion, UartUpdateReceiver};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::flash::{Flash, HasClient};
+use kernel::hil::uart;
+use kernel::process_checker::AppCredentialsChecker;
+
+#[macro_export]
+macro_rules! app_update_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let app_update = kernel::static_buf!(
+            capsules_extra::app_update::AppUpdate<'static, $F, $crate::app_update::Capability>
+        );
+
+        (page, app_update)
+    };};
+}
+
+#[macro_export]
+macro_rules! uart_update_receiver_component_static {
+    ($N:expr $(,)?) => {{
+        let uart_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_uart::UartDevice<'static>);
+        let rx_buffer = kernel::static_buf!([u8; $N]);
+        let tx_buffer = kernel::static_buf!([u8; 1]);
+        let receiver = kernel::static_buf!(capsules_extra::app_update::UartUpdateReceiver<'static>);
+
+        (uart_device, rx_buffer, tx_buffer, receiver)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct AppUpdateComponent<
+    F: 'static + Flash + HasClient<'static, AppUpdate<'static, F, Capability>>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    flash: &'static F,
+    slots: [FlashRegion; 2],
+    records: [FlashRegion; 2],
+    checker: &'static dyn AppCredentialsChecker<'static>,
+    privileged: &'static [&'static str],
+    reset: Option<fn() -> !>,
+}
+
+impl<F: 'static + Flash + HasClient<'static, AppUpdate<'static, F, Capability>>>
+    AppUpdateComponent<F>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        flash: &'static F,
+        slots: [FlashRegion; 2],
+        records: [FlashRegion; 2],
+        checker: &'static dyn AppCredentialsChecker<'static>,
+        privileged: &'static [&'static str],
+        reset: Option<fn() -> !>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            flash,
+            slots,
+            records,
+            checker,
+            privileged,
+            reset,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, AppUpdate<'static, F, Capability>>> Component
+    for AppUpdateComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<AppUpdate<'static, F, Capability>>,
+    );
+    type Output = &'static AppUpdate<'static, F, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page = static_buffer.0.write(F::Page::default());
+        let app_update = static_buffer.1.write(AppUpdate::new(
+            self.flash,
+            page,
+            self.slots,
+            self.records,
+            self.checker,
+            self.board_kernel,
+            self.privileged,
+            self.reset,
+            Capability,
+        ));
+        HasClient::set_client(self.flash, app_update);
+        self.checker.set_client(app_update);
+        let _ = app_update.boot();
+
+        app_update
+    }
+}
+
+pub struct UartUpdateReceiverComponent<const N: usize> {
+    uart_mux: &'static MuxUart<'static>,
+    updater: &'static dyn AppUpdater<'static>,
+}
+
+impl<const N: usize> UartUpdateReceiverComponent<N> {
+    pub fn new(
+        uart_mux: &'static MuxUart<'static>,
+        updater: &'static dyn AppUpdater<'static>,
+    ) -> Self {
+        Self { uart_mux, updater }
+    }
+}
+
+impl<const N: usize> Component for UartUpdateReceiverComponent<N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<UartDevice<'static>>,
+        &'static mut MaybeUninit<[u8; N]>,
+        &'static mut MaybeUninit<[u8; 1]>,
+        &'static mut MaybeUninit<UartUpdateReceiver<'static>>,
+    );
+    type Output = &'static UartUpdateReceiver<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let uart_device = static_buffer.0.write(UartDevice::new(self.uart_mux, true));
+        uart_device.setup();
+
+        let rx_buffer = static_buffer.1.write([0; N]);
+        let receiver = static_buffer.3.write(UartUpdateReceiver::new(
+            uart_device,
+            self.updater,
+            rx_buffer,
+            static_buffer.2.write([0; 1]),
+        ));
+        self.updater.set_client(receiver);
+        uart::Transmit::set_transmit_client(uart_device, receiver);
+        uart::Receive::set_receive_client(uart_device, receiver);
+
+        receiver
+    }
+}
@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the sensor value blackboard.
+//!
+//! The second argument of the macro is the number of IDs the blackboard
+//! holds values for.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let blackboard = components::blackboard::BlackboardComponent::new(
+//!     board_kernel,
+//!     capsules_extra::blackboard::DRIVER_NUM,
+//!     mux_alarm,
+//! )
+//! .finalize(components::blackboard_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     8
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::blackboard::Blackboard;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! blackboard_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let blackboard = kernel::static_buf!(
+            capsules_extra::blackboard::Blackboard<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $N,
+            >
+        );
+
+        (alarm, blackboard)
+    };};
+}
+
+pub struct BlackboardComponent<A: 'static + time::Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> BlackboardComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const N: usize> Component for BlackboardComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Blackboard<'static, VirtualMuxAlarm<'static, A>, N>>,
+    );
+    type Output = &'static Blackboard<'static, VirtualMuxAlarm<'static, A>, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let blackboard = static_buffer.1.write(Blackboard::new(
+            alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(blackboard);
+
+        blackboard
+    }
+}
@@ -0,0 +1,141 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the BLE security manager.
+//!
+//! `storage_id` is the ID the bonds are stored with in the KV store.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ble_security = components::ble_security::BleSecurityComponent::new(
+//!     link,
+//!     &base_peripherals.ecb,
+//!     rng,
+//!     kv_store,
+//!     0x8000_0001,
+//!     capsules_extra::ble_security::IoCapability::DisplayOnly,
+//!     true,
+//! )
+//! .finalize(components::ble_security_component_static!(
+//!     nrf52840::aes::AesECB<'static>,
+//!     capsules_extra::tickv::TicKVStore<
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<nrf52840::nvmc::Nvmc>,
+//!     >,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! ```
+
+use capsules_extra::ble_security::{
+    BleSecurity, IoCapability, SecureLink, BOND_KEY_LEN, BOND_LEN, SMP_PDU_LEN,
+};
+use capsules_extra::kv_store::{KVStore, HEADER_LENGTH};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::{AES128, AES128ECB, AES128_BLOCK_SIZE};
+use kernel::storage_permissions::StoragePermissions;
+
+/// Size of the value buffer, including the KV store header.
+pub const VALUE_BUF_LEN: usize = BOND_LEN + HEADER_LENGTH;
+
+#[macro_export]
+macro_rules! ble_security_component_static {
+    ($A:ty, $K:ty, $T:ty $(,)?) => {{
+        let security =
+            kernel::static_buf!(capsules_extra::ble_security::BleSecurity<'static, $A, $K, $T>);
+        let tx = kernel::static_buf!([u8; capsules_extra::ble_security::SMP_PDU_LEN]);
+        let aes = kernel::static_buf!([u8; kernel::hil::symmetric_encryption::AES128_BLOCK_SIZE]);
+        let key = kernel::static_buf!([u8; capsules_extra::ble_security::BOND_KEY_LEN]);
+        let value = kernel::static_buf!([u8; $crate::ble_security::VALUE_BUF_LEN]);
+
+        (security, tx, aes, key, value)
+    };};
+}
+
+pub struct BleSecurityComponent<
+    A: 'static + AES128<'static> + AES128ECB,
+    K: 'static + KVSystem<'static, K = T>,
+    T: 'static + KeyType,
+> {
+    link: &'static dyn SecureLink<'static>,
+    aes: &'static A,
+    rng: &'static dyn Rng<'static>,
+    kv_store: &'static KVStore<'static, K, T>,
+    storage_id: u32,
+    io_capability: IoCapability,
+    bondable: bool,
+}
+
+impl<
+        A: 'static + AES128<'static> + AES128ECB,
+        K: 'static + KVSystem<'static, K = T>,
+        T: 'static + KeyType,
+    > BleSecurityComponent<A, K, T>
+{
+    pub fn new(
+        link: &'static dyn SecureLink<'static>,
+        aes: &'static A,
+        rng: &'static dyn Rng<'static>,
+        kv_store: &'static KVStore<'static, K, T>,
+        storage_id: u32,
+        io_capability: IoCapability,
+        bondable: bool,
+    ) -> Self {
+        Self {
+            link,
+            aes,
+            rng,
+            kv_store,
+            storage_id,
+            io_capability,
+            bondable,
+        }
+    }
+}
+
+impl<
+        A: 'static + AES128<'static> + AES128ECB,
+        K: 'static + KVSystem<'static, K = T>,
+        T: 'static + KeyType,
+    > Component for BleSecurityComponent<A, K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<BleSecurity<'static, A, K, T>>,
+        &'static mut MaybeUninit<[u8; SMP_PDU_LEN]>,
+        &'static mut MaybeUninit<[u8; AES128_BLOCK_SIZE]>,
+        &'static mut MaybeUninit<[u8; BOND_KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUF_LEN]>,
+    );
+    type Output = &'static BleSecurity<'static, A, K, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+        let perms = StoragePermissions::new_kernel_permissions(self.storage_id, &storage_cap);
+
+        let security = static_buffer.0.write(BleSecurity::new(
+            self.link,
+            self.aes,
+            self.rng,
+            self.kv_store,
+            perms,
+            self.io_capability,
+            self.bondable,
+            static_buffer.1.write([0; SMP_PDU_LEN]),
+            static_buffer.2.write([0; AES128_BLOCK_SIZE]),
+            static_buffer.3.write([0; BOND_KEY_LEN]),
+            static_buffer.4.write([0; VALUE_BUF_LEN]),
+        ));
+        self.link.set_client(security);
+        self.aes.set_client(security);
+        self.aes.enable();
+        self.rng.set_client(security);
+        self.kv_store.set_client(security);
+
+        security
+    }
+}
@@ -0,0 +1,113 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for block devices on top of flash and SD cards.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let blocks = components::block_storage::FlashBlockStorageComponent::new(
+//!     wear_leveling,
+//!     0,
+//!     wear_leveling.logical_pages(),
+//! )
+//! .finalize(components::flash_block_storage_component_static!(
+//!     capsules_extra::wear_leveling::WearLeveling<'static, FlashUser, 64>
+//! ));
+//!
+//! let blocks = components::block_storage::SdCardBlockStorageComponent::new(sdcard)
+//!     .finalize(components::sdcard_block_storage_component_static!(
+//!         capsules_extra::sdcard::SDCard<'static, SpiDevice, Alarm, Pin>
+//!     ));
+//! ```
+
+use capsules_extra::block_storage::{FlashBlockStorage, SdCardBlockStorage};
+use capsules_extra::sdcard::SDCardDevice;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::flash::{Flash, HasClient};
+
+#[macro_export]
+macro_rules! flash_block_storage_component_static {
+    ($F:ty $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let blocks =
+            kernel::static_buf!(capsules_extra::block_storage::FlashBlockStorage<'static, $F>);
+
+        (page, blocks)
+    };};
+}
+
+#[macro_export]
+macro_rules! sdcard_block_storage_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::block_storage::SdCardBlockStorage<'static, $S>)
+    };};
+}
+
+pub struct FlashBlockStorageComponent<
+    F: 'static + Flash + HasClient<'static, FlashBlockStorage<'static, F>>,
+> {
+    flash: &'static F,
+    first_page: usize,
+    pages: usize,
+}
+
+impl<F: 'static + Flash + HasClient<'static, FlashBlockStorage<'static, F>>>
+    FlashBlockStorageComponent<F>
+{
+    pub fn new(flash: &'static F, first_page: usize, pages: usize) -> Self {
+        Self {
+            flash,
+            first_page,
+            pages,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, FlashBlockStorage<'static, F>>> Component
+    for FlashBlockStorageComponent<F>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<FlashBlockStorage<'static, F>>,
+    );
+    type Output = &'static FlashBlockStorage<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page = static_buffer.0.write(F::Page::default());
+        let blocks = static_buffer.1.write(FlashBlockStorage::new(
+            self.flash,
+            self.first_page,
+            self.pages,
+            page,
+        ));
+        HasClient::set_client(self.flash, blocks);
+
+        blocks
+    }
+}
+
+pub struct SdCardBlockStorageComponent<S: 'static + SDCardDevice<'static>> {
+    card: &'static S,
+}
+
+impl<S: 'static + SDCardDevice<'static>> SdCardBlockStorageComponent<S> {
+    pub fn new(card: &'static S) -> Self {
+        Self { card }
+    }
+}
+
+impl<S: 'static + SDCardDevice<'static>> Component for SdCardBlockStorageComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<SdCardBlockStorage<'static, S>>;
+    type Output = &'static SdCardBlockStorage<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let blocks = static_buffer.write(SdCardBlockStorage::new(self.card));
+        self.card.set_client(blocks);
+        blocks.start();
+
+        blocks
+    }
+}
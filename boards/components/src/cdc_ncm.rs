@@ -0,0 +1,101 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for CDC-NCM over USB support.
+//!
+//! This provides a component for using the CDC-NCM driver, which presents
+//! the board to the host as a USB network interface.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 4] = &[
+//!     "XYZ Corp.",      // Manufacturer
+//!     "The Zorpinator", // Product
+//!     "Serial No. 5",   // Serial number
+//!     "02000000AB01",   // MAC address of the host
+//! ];
+//! let cdc_ncm = components::cdc_ncm::CdcNcmComponent::new(
+//!     &nrf52::usbd::USBD,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x2341,
+//!     0x005b,
+//!     STRINGS,
+//!     [0x02, 0x00, 0x00, 0x00, 0xab, 0x02], // MAC address of the board
+//! )
+//! .finalize(components::cdc_ncm_component_static!(nrf52::usbd::Usbd));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_extra::usb::cdc_ncm::{CdcNcm, NTB_IN_MAX_SIZE, NTB_OUT_MAX_SIZE};
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::ethernet::MAC_ADDRESS_LEN;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! cdc_ncm_component_static {
+    ($U:ty $(,)?) => {{
+        let cdc = kernel::static_buf!(capsules_extra::usb::cdc_ncm::CdcNcm<'static, $U>);
+        let rx_ntb = kernel::static_buf!([u8; capsules_extra::usb::cdc_ncm::NTB_OUT_MAX_SIZE]);
+        let tx_ntb = kernel::static_buf!([u8; capsules_extra::usb::cdc_ncm::NTB_IN_MAX_SIZE]);
+
+        (cdc, rx_ntb, tx_ntb)
+    };};
+}
+
+pub struct CdcNcmComponent<U: 'static + hil::usb::UsbController<'static>> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 4],
+    mac_address: [u8; MAC_ADDRESS_LEN],
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> CdcNcmComponent<U> {
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 4],
+        mac_address: [u8; MAC_ADDRESS_LEN],
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            mac_address,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for CdcNcmComponent<U> {
+    type StaticInput = (
+        &'static mut MaybeUninit<CdcNcm<'static, U>>,
+        &'static mut MaybeUninit<[u8; NTB_OUT_MAX_SIZE]>,
+        &'static mut MaybeUninit<[u8; NTB_IN_MAX_SIZE]>,
+    );
+    type Output = &'static CdcNcm<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let cdc = s.0.write(CdcNcm::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.mac_address,
+            s.1.write([0; NTB_OUT_MAX_SIZE]),
+            s.2.write([0; NTB_IN_MAX_SIZE]),
+        ));
+        self.usb.set_client(cdc);
+
+        cdc
+    }
+}
@@ -0,0 +1,158 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a CMSIS-DAP debug probe over USB HID.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 3] = &[
+//!     "XYZ Corp.",            // Manufacturer
+//!     "XYZ CMSIS-DAP Probe",  // Product, must contain "CMSIS-DAP"
+//!     "Serial No. 5",         // Serial number
+//! ];
+//!
+//! let swd = static_init!(
+//!     capsules_extra::swd_bitbang::SwdBitbang<'static, RPGpioPin<'static>>,
+//!     capsules_extra::swd_bitbang::SwdBitbang::new(swclk, swdio, None, 125_000_000)
+//! );
+//! let (hid, cmsis_dap) = components::cmsis_dap::CmsisDapComponent::new(
+//!     &peripherals.usb,
+//!     0x1209,
+//!     0x0001,
+//!     STRINGS,
+//!     swd,
+//!     mux_alarm,
+//! )
+//! .finalize(components::cmsis_dap_component_static!(
+//!     rp2040::usb::UsbCtrl,
+//!     capsules_extra::swd_bitbang::SwdBitbang<'static, RPGpioPin<'static>>,
+//!     rp2040::timer::RPTimer,
+//! ));
+//!
+//! hid.enable();
+//! hid.attach();
+//! cmsis_dap.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::cmsis_dap::CmsisDap;
+use capsules_extra::usb::ctap::CtapHid;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! cmsis_dap_component_static {
+    ($U:ty, $S:ty, $A:ty $(,)?) => {{
+        let hid = kernel::static_buf!(capsules_extra::usb::ctap::CtapHid<'static, $U>);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let cmsis_dap = kernel::static_buf!(
+            capsules_extra::cmsis_dap::CmsisDap<
+                'static,
+                $S,
+                capsules_extra::usb::ctap::CtapHid<'static, $U>,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let send_buffer = kernel::static_buf!([u8; 64]);
+        let recv_buffer = kernel::static_buf!([u8; 64]);
+
+        (hid, alarm, cmsis_dap, send_buffer, recv_buffer)
+    };};
+}
+
+pub struct CmsisDapComponent<
+    U: 'static + hil::usb::UsbController<'static>,
+    S: 'static + hil::swd::Swd,
+    A: 'static + hil::time::Alarm<'static>,
+> {
+    usb: &'static U,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    swd: &'static S,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        S: 'static + hil::swd::Swd,
+        A: 'static + hil::time::Alarm<'static>,
+    > CmsisDapComponent<U, S, A>
+{
+    pub fn new(
+        usb: &'static U,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        swd: &'static S,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        CmsisDapComponent {
+            usb,
+            vendor_id,
+            product_id,
+            strings,
+            swd,
+            alarm_mux,
+        }
+    }
+}
+
+impl<
+        U: 'static + hil::usb::UsbController<'static>,
+        S: 'static + hil::swd::Swd,
+        A: 'static + hil::time::Alarm<'static>,
+    > Component for CmsisDapComponent<U, S, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<CtapHid<'static, U>>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            CmsisDap<'static, S, CtapHid<'static, U>, VirtualMuxAlarm<'static, A>>,
+        >,
+        &'static mut MaybeUninit<[u8; 64]>,
+        &'static mut MaybeUninit<[u8; 64]>,
+    );
+    type Output = (
+        &'static CtapHid<'static, U>,
+        &'static CmsisDap<'static, S, CtapHid<'static, U>, VirtualMuxAlarm<'static, A>>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hid = s.0.write(CtapHid::new_with_report(
+            self.usb,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            &capsules_extra::cmsis_dap::HID_DESCRIPTOR,
+            &capsules_extra::cmsis_dap::REPORT,
+        ));
+        self.usb.set_client(hid);
+
+        let alarm = s.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let send_buffer = s.3.write([0; 64]);
+        let recv_buffer = s.4.write([0; 64]);
+
+        let cmsis_dap = s.2.write(CmsisDap::new(
+            self.swd,
+            hid,
+            alarm,
+            self.strings,
+            send_buffer,
+            recv_buffer,
+        ));
+        hid.set_client(cmsis_dap);
+        alarm.set_alarm_client(cmsis_dap);
+
+        (hid, cmsis_dap)
+    }
+}
@@ -0,0 +1,201 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a CoAP endpoint and its userspace driver.
+//!
+//! The endpoint is bound to the CoAP port 5683 and serves the resources of
+//! processes through the driver, as well as kernel resources registered
+//! with it.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (coap, coap_driver) = components::coap::CoapComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::coap::DRIVER_NUM,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//! )
+//! .finalize(components::coap_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::coap::endpoint::{CoapEndpoint, COAP_PORT};
+use capsules_extra::net::coap::CoapDriver;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! coap_component_static {
+    ($A:ty $(,)?) => {{
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let tx_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let con_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let endpoint = kernel::static_buf!(
+            capsules_extra::net::coap::endpoint::CoapEndpoint<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let driver = kernel::static_buf!(
+            capsules_extra::net::coap::CoapDriver<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (
+            udp_send,
+            udp_recv,
+            udp_vis_cap,
+            net_cap,
+            alarm,
+            tx_buffer,
+            con_buffer,
+            endpoint,
+            driver,
+        )
+    };};
+}
+
+pub struct CoapComponent<A: Alarm<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: Alarm<'static>> CoapComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: Alarm<'static>> Component for CoapComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<CoapEndpoint<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<CoapDriver<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = (
+        &'static CoapEndpoint<'static, VirtualMuxAlarm<'static, A>>,
+        &'static CoapDriver<'static, VirtualMuxAlarm<'static, A>>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.2.write(UdpVisibilityCapability::new(&create_cap));
+        let net_cap = s.3.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        let udp_recv = s.1.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let socket = match self.port_table.create_socket() {
+            Ok(socket) => socket,
+            Err(_) => panic!("No UDP socket for CoAP"),
+        };
+        match self.port_table.bind(socket, COAP_PORT, net_cap) {
+            Ok((send_bind, recv_bind)) => {
+                udp_send.set_binding(send_bind);
+                udp_recv.set_binding(recv_bind);
+            }
+            Err(_) => panic!("CoAP port bound already"),
+        }
+
+        let alarm = s.4.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let tx_buffer = s.5.write([0; MAX_PAYLOAD_LEN]);
+        let con_buffer = s.6.write([0; MAX_PAYLOAD_LEN]);
+        let endpoint = s.7.write(CoapEndpoint::new(
+            udp_send,
+            alarm,
+            net_cap,
+            LeasableMutableBuffer::new(tx_buffer),
+            con_buffer,
+        ));
+        udp_send.set_client(endpoint);
+        udp_recv.set_client(endpoint);
+        alarm.set_alarm_client(endpoint);
+
+        let driver = s.8.write(CoapDriver::new(
+            endpoint,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        endpoint.register(driver).unwrap();
+
+        (endpoint, driver)
+    }
+}
@@ -0,0 +1,64 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for transparent compression of log entries.
+//!
+//! The scratch buffer of `$N` bytes limits the entries to `$N - 1` bytes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let compressed_log = components::compressed_log::CompressedLogComponent::new(log)
+//!     .finalize(components::compressed_log_component_static!(
+//!         capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!         256
+//!     ));
+//! ```
+
+use capsules_extra::compressed_log::CompressedLog;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::log::{LogRead, LogWrite};
+
+#[macro_export]
+macro_rules! compressed_log_component_static {
+    ($L:ty, $N:expr $(,)?) => {{
+        let scratch = kernel::static_buf!([u8; $N]);
+        let log = kernel::static_buf!(capsules_extra::compressed_log::CompressedLog<'static, $L>);
+
+        (scratch, log)
+    };};
+}
+
+pub struct CompressedLogComponent<L: 'static + LogRead<'static> + LogWrite<'static>, const N: usize>
+{
+    log: &'static L,
+}
+
+impl<L: 'static + LogRead<'static> + LogWrite<'static>, const N: usize>
+    CompressedLogComponent<L, N>
+{
+    pub fn new(log: &'static L) -> Self {
+        Self { log }
+    }
+}
+
+impl<L: 'static + LogRead<'static> + LogWrite<'static>, const N: usize> Component
+    for CompressedLogComponent<L, N>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; N]>,
+        &'static mut MaybeUninit<CompressedLog<'static, L>>,
+    );
+    type Output = &'static CompressedLog<'static, L>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scratch = static_buffer.0.write([0; N]);
+        let compressed_log = static_buffer.1.write(CompressedLog::new(self.log, scratch));
+        self.log.set_read_client(compressed_log);
+        self.log.set_append_client(compressed_log);
+
+        compressed_log
+    }
+}
@@ -14,10 +14,13 @@
 //! the kernel and userspace, whose ordering is important to maintain.
 //!
 //!
-//! This provides three Components, `ConsoleComponent` and
+//! This provides four Components, `ConsoleComponent` and
 //! `ConsoleOrderedComponent`, which implement a buffered read/write
-//! console over a serial port, and `UartMuxComponent`, which provides
-//! multiplexed access to hardware UART. As an example, the serial
+//! console over a serial port, `UartMuxComponent`, which provides
+//! multiplexed access to hardware UART, and
+//! `UartMuxTransmitBuffersComponent`, which gives the mux a transmit
+//! ring buffer so that bursts of output do not wait for the wire. As an
+//! example, the serial
 //! port used for console on Imix is typically USART3 (the DEBUG USB
 //! connector).
 //!
@@ -30,6 +33,10 @@
 //! let console = ConsoleComponent::new(board_kernel, uart_mux)
 //!    .finalize(console_component_static!());
 //! ```
+//!
+//! To let processes change the UART baud rate and framing at runtime, call
+//! `.with_runtime_configuration()` on the `ConsoleComponent` before
+//! `finalize()`.
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 1/08/2023
 
@@ -40,6 +47,7 @@ use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
 use core::mem::MaybeUninit;
 use kernel::capabilities;
+use kernel::collections::ring_buffer::RingBuffer;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
@@ -66,6 +74,16 @@ macro_rules! uart_mux_component_static {
     }};
 }
 
+#[macro_export]
+macro_rules! uart_mux_transmit_buffers_component_static {
+    ($ring_len:expr, $dma_len:expr $(,)?) => {{
+        let ring = kernel::static_buf!(kernel::collections::ring_buffer::RingBuffer<'static, u8>);
+        let ring_buffer = kernel::static_buf!([u8; $ring_len]);
+        let dma_buffer = kernel::static_buf!([u8; $dma_len]);
+        (ring, ring_buffer, dma_buffer)
+    };};
+}
+
 pub struct UartMuxComponent<const RX_BUF_LEN: usize> {
     uart: &'static dyn uart::Uart<'static>,
     baud_rate: u32,
@@ -100,14 +118,50 @@ impl<const RX_BUF_LEN: usize> Component for UartMuxComponent<RX_BUF_LEN> {
     }
 }
 
+/// Buffers the transmissions of the clients of a UART mux in a ring of
+/// `RING_LEN` bytes, sent in transfers of up to `DMA_LEN` bytes.
+pub struct UartMuxTransmitBuffersComponent<const RING_LEN: usize, const DMA_LEN: usize> {
+    uart_mux: &'static MuxUart<'static>,
+}
+
+impl<const RING_LEN: usize, const DMA_LEN: usize>
+    UartMuxTransmitBuffersComponent<RING_LEN, DMA_LEN>
+{
+    pub fn new(uart_mux: &'static MuxUart<'static>) -> Self {
+        UartMuxTransmitBuffersComponent { uart_mux }
+    }
+}
+
+impl<const RING_LEN: usize, const DMA_LEN: usize> Component
+    for UartMuxTransmitBuffersComponent<RING_LEN, DMA_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<RingBuffer<'static, u8>>,
+        &'static mut MaybeUninit<[u8; RING_LEN]>,
+        &'static mut MaybeUninit<[u8; DMA_LEN]>,
+    );
+    type Output = ();
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let ring_buffer = s.1.write([0; RING_LEN]);
+        let ring = s.0.write(RingBuffer::new(ring_buffer));
+        let dma_buffer = s.2.write([0; DMA_LEN]);
+        self.uart_mux.set_transmit_buffers(ring, dma_buffer);
+    }
+}
+
 #[macro_export]
 macro_rules! console_component_static {
     () => {{
-        use capsules_core::console::{Console, DEFAULT_BUF_SIZE};
+        use capsules_core::console::DEFAULT_BUF_SIZE;
+        $crate::console_component_static!(DEFAULT_BUF_SIZE)
+    }};
+    ($buffer_len: expr) => {{
+        use capsules_core::console::Console;
         use capsules_core::virtualizers::virtual_uart::UartDevice;
         use kernel::static_buf;
-        let read_buf = static_buf!([u8; DEFAULT_BUF_SIZE]);
-        let write_buf = static_buf!([u8; DEFAULT_BUF_SIZE]);
+        let read_buf = static_buf!([u8; $buffer_len]);
+        let write_buf = static_buf!([u8; $buffer_len]);
         // Create virtual device for console.
         let console_uart = static_buf!(UartDevice);
         let console = static_buf!(Console<'static>);
@@ -115,30 +169,42 @@ macro_rules! console_component_static {
     }};
 }
 
-pub struct ConsoleComponent {
+/// The console, with read and write buffers of `BUF_LEN` bytes. Larger
+/// buffers let prints of processes go to the UART in fewer transfers.
+pub struct ConsoleComponent<const BUF_LEN: usize = DEFAULT_BUF_SIZE> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     uart_mux: &'static MuxUart<'static>,
+    runtime_configuration: bool,
 }
 
-impl ConsoleComponent {
+impl<const BUF_LEN: usize> ConsoleComponent<BUF_LEN> {
     pub fn new(
         board_kernel: &'static kernel::Kernel,
         driver_num: usize,
         uart_mux: &'static MuxUart,
-    ) -> ConsoleComponent {
+    ) -> ConsoleComponent<BUF_LEN> {
         ConsoleComponent {
             board_kernel: board_kernel,
             driver_num: driver_num,
             uart_mux: uart_mux,
+            runtime_configuration: false,
         }
     }
+
+    /// Allow processes to reconfigure the shared UART through the console
+    /// syscall driver. Boards should restrict the configure command to
+    /// privileged processes.
+    pub fn with_runtime_configuration(mut self) -> Self {
+        self.runtime_configuration = true;
+        self
+    }
 }
 
-impl Component for ConsoleComponent {
+impl<const BUF_LEN: usize> Component for ConsoleComponent<BUF_LEN> {
     type StaticInput = (
-        &'static mut MaybeUninit<[u8; DEFAULT_BUF_SIZE]>,
-        &'static mut MaybeUninit<[u8; DEFAULT_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
         &'static mut MaybeUninit<UartDevice<'static>>,
         &'static mut MaybeUninit<console::Console<'static>>,
     );
@@ -147,9 +213,9 @@ impl Component for ConsoleComponent {
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-        let write_buffer = s.0.write([0; DEFAULT_BUF_SIZE]);
+        let write_buffer = s.0.write([0; BUF_LEN]);
 
-        let read_buffer = s.1.write([0; DEFAULT_BUF_SIZE]);
+        let read_buffer = s.1.write([0; BUF_LEN]);
 
         let console_uart = s.2.write(UartDevice::new(self.uart_mux, true));
         console_uart.setup();
@@ -162,6 +228,9 @@ impl Component for ConsoleComponent {
         ));
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
+        if self.runtime_configuration {
+            console.set_configure(console_uart);
+        }
 
         console
     }
@@ -4,14 +4,27 @@
 
 //! Component for Crc syscall interface.
 //!
-//! This provides one Component, `CrcComponent`, which implements a
-//! userspace syscall interface to the Crc peripheral.
+//! This provides two Components: `CrcComponent`, which implements a
+//! userspace syscall interface to the Crc peripheral, and
+//! `CrcSoftwareComponent`, which provides a software Crc implementation for
+//! chips without one.
 //!
 //! Usage
 //! -----
 //! ```rust
 //! let crc = components::crc::CrcComponent::new(board_kernel, &sam4l::crccu::CrcCU)
 //!     .finalize(components::crc_component_static!(sam4l::crccu::Crccu));
+//!
+//! let crc_software = components::crc::CrcSoftwareComponent::new()
+//!     .finalize(components::crc_software_component_static!());
+//! let crc = components::crc::CrcComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crc::DRIVER_NUM,
+//!     crc_software,
+//! )
+//!     .finalize(components::crc_component_static!(
+//!         capsules_extra::crc_software::CrcSoftware<'static>
+//!     ));
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -19,10 +32,12 @@
 // Last modified: 6/2/2021
 
 use capsules_extra::crc::CrcDriver;
+use capsules_extra::crc_software::CrcSoftware;
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
 use kernel::hil::crc::Crc;
 
 // Setup static space for the objects.
@@ -36,6 +51,13 @@ macro_rules! crc_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! crc_software_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::crc_software::CrcSoftware<'static>)
+    };};
+}
+
 pub struct CrcComponent<C: 'static + Crc<'static>> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
@@ -80,3 +102,23 @@ impl<C: 'static + Crc<'static>> Component for CrcComponent<C> {
         crc
     }
 }
+
+pub struct CrcSoftwareComponent;
+
+impl CrcSoftwareComponent {
+    pub fn new() -> CrcSoftwareComponent {
+        CrcSoftwareComponent
+    }
+}
+
+impl Component for CrcSoftwareComponent {
+    type StaticInput = &'static mut MaybeUninit<CrcSoftware<'static>>;
+    type Output = &'static CrcSoftware<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let crc = static_buffer.write(CrcSoftware::new());
+        crc.register();
+
+        crc
+    }
+}
@@ -0,0 +1,73 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the deadline syscall driver, which lets processes reserve
+//! CPU time with the EDF scheduler.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let deadline = components::deadline::DeadlineComponent::new(
+//!     board_kernel,
+//!     capsules_core::deadline::DRIVER_NUM,
+//!     scheduler,
+//! )
+//! .finalize(components::deadline_component_static!(
+//!     kernel::scheduler::edf::EdfSched<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
+//!     >
+//! ));
+//! ```
+
+use capsules_core::deadline::Deadline;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::scheduler::edf::DeadlineScheduler;
+
+#[macro_export]
+macro_rules! deadline_component_static {
+    ($S:ty $(,)?) => {{
+        kernel::static_buf!(capsules_core::deadline::Deadline<'static, $S>)
+    };};
+}
+
+pub struct DeadlineComponent<S: 'static + DeadlineScheduler<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    scheduler: &'static S,
+}
+
+impl<S: 'static + DeadlineScheduler<'static>> DeadlineComponent<S> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        scheduler: &'static S,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            scheduler,
+        }
+    }
+}
+
+impl<S: 'static + DeadlineScheduler<'static>> Component for DeadlineComponent<S> {
+    type StaticInput = &'static mut MaybeUninit<Deadline<'static, S>>;
+    type Output = &'static Deadline<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let deadline = static_buffer.write(Deadline::new(
+            self.scheduler,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.scheduler.set_client(deadline);
+
+        deadline
+    }
+}
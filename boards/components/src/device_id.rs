@@ -0,0 +1,59 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for creating a serial number string from a chip's unique ID.
+//!
+//! The string is the MAC address derived from the device ID, in the format
+//! `46:db:52:cd:93:9e`, and is suitable for use as a USB serial number.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let serial_number = components::device_id::SerialNumberStringComponent::new(
+//!     &nrf52840::ficr::FICR_INSTANCE,
+//! )
+//! .finalize(components::serial_number_string_component_static!());
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::device_id::DeviceId;
+
+/// Length of the serial number string.
+pub const SERIAL_NUMBER_STRING_LEN: usize = 17;
+
+#[macro_export]
+macro_rules! serial_number_string_component_static {
+    () => {{
+        kernel::static_buf!([u8; $crate::device_id::SERIAL_NUMBER_STRING_LEN])
+    };};
+}
+
+pub struct SerialNumberStringComponent<'a, D: DeviceId> {
+    device_id: &'a D,
+}
+
+impl<'a, D: DeviceId> SerialNumberStringComponent<'a, D> {
+    pub fn new(device_id: &'a D) -> Self {
+        Self { device_id }
+    }
+}
+
+impl<'a, D: DeviceId> Component for SerialNumberStringComponent<'a, D> {
+    type StaticInput = &'static mut MaybeUninit<[u8; SERIAL_NUMBER_STRING_LEN]>;
+    type Output = &'static str;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        const HEX: &[u8; 16] = b"0123456789abcdef";
+
+        let buf = s.write([b':'; SERIAL_NUMBER_STRING_LEN]);
+        for (i, byte) in self.device_id.mac48().iter().enumerate() {
+            buf[i * 3] = HEX[(byte >> 4) as usize];
+            buf[i * 3 + 1] = HEX[(byte & 0xf) as usize];
+        }
+
+        // Only ASCII characters are written to the buffer.
+        core::str::from_utf8(buf).unwrap_or("")
+    }
+}
@@ -0,0 +1,261 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a DTLS session over UDP.
+//!
+//! The session is bound to `port`, and runs its cryptography on the
+//! AES-128-CCM engine `ccm`, which has to accept 12 byte nonces, and on the
+//! SHA-256 engine `sha`. The application sends through the returned session,
+//! and sets itself as its receive client.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let dtls = components::dtls::DtlsComponent::new(
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     ccm,
+//!     sha,
+//!     rng,
+//!     capsules_extra::net::dtls::Role::Client,
+//!     capsules_extra::net::dtls::DTLS_PORT,
+//! )
+//! .finalize(components::dtls_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<
+//!         'static,
+//!         nrf52840::aes::AesECB<'static>,
+//!     >,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     nrf52840::trng::Trng<'static>,
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::dtls::session::{FLIGHT_LEN, TRANSCRIPT_LEN};
+use capsules_extra::net::dtls::{DtlsCrypto, DtlsSession, Role};
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest::{Digest, Sha256};
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::AES128CCM;
+use kernel::hil::time::Alarm;
+
+const MAX_PAYLOAD_LEN: usize = super::udp_mux::MAX_PAYLOAD_LEN;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! dtls_component_static {
+    ($A:ty, $C:ty, $D:ty, $R:ty $(,)?) => {{
+        use capsules_extra::net::dtls::session::{FLIGHT_LEN, TRANSCRIPT_LEN};
+        use components::udp_mux::MAX_PAYLOAD_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let crypto_buffer = kernel::static_buf!([u8; TRANSCRIPT_LEN]);
+        let digest_buffer = kernel::static_buf!([u8; 32]);
+        let crypto = kernel::static_buf!(capsules_extra::net::dtls::DtlsCrypto<'static, $C, $D>);
+        let tx_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let rx_buffer = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let flight_buffer = kernel::static_buf!([u8; FLIGHT_LEN]);
+        let transcript_buffer = kernel::static_buf!([u8; TRANSCRIPT_LEN]);
+        let session = kernel::static_buf!(
+            capsules_extra::net::dtls::DtlsSession<
+                'static,
+                $C,
+                $D,
+                $R,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (
+            udp_send,
+            udp_recv,
+            udp_vis_cap,
+            net_cap,
+            alarm,
+            crypto_buffer,
+            digest_buffer,
+            crypto,
+            tx_buffer,
+            rx_buffer,
+            flight_buffer,
+            transcript_buffer,
+            session,
+        )
+    };};
+}
+
+pub struct DtlsComponent<
+    A: Alarm<'static> + 'static,
+    C: AES128CCM<'static> + 'static,
+    D: Digest<'static, 32> + Sha256 + 'static,
+    R: Rng<'static> + 'static,
+> {
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    ccm: &'static C,
+    sha: &'static D,
+    rng: &'static R,
+    role: Role,
+    port: u16,
+}
+
+impl<
+        A: Alarm<'static>,
+        C: AES128CCM<'static>,
+        D: Digest<'static, 32> + Sha256,
+        R: Rng<'static>,
+    > DtlsComponent<A, C, D, R>
+{
+    pub fn new(
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        ccm: &'static C,
+        sha: &'static D,
+        rng: &'static R,
+        role: Role,
+        port: u16,
+    ) -> Self {
+        Self {
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            alarm_mux,
+            ccm,
+            sha,
+            rng,
+            role,
+            port,
+        }
+    }
+}
+
+impl<
+        A: Alarm<'static>,
+        C: AES128CCM<'static>,
+        D: Digest<'static, 32> + Sha256,
+        R: Rng<'static>,
+    > Component for DtlsComponent<A, C, D, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; TRANSCRIPT_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+        &'static mut MaybeUninit<DtlsCrypto<'static, C, D>>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; FLIGHT_LEN]>,
+        &'static mut MaybeUninit<[u8; TRANSCRIPT_LEN]>,
+        &'static mut MaybeUninit<DtlsSession<'static, C, D, R, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static DtlsSession<'static, C, D, R, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.2.write(UdpVisibilityCapability::new(&create_cap));
+        let net_cap = s.3.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        let udp_recv = s.1.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let socket = match self.port_table.create_socket() {
+            Ok(socket) => socket,
+            Err(_) => panic!("No UDP socket for DTLS"),
+        };
+        match self.port_table.bind(socket, self.port, net_cap) {
+            Ok((send_bind, recv_bind)) => {
+                udp_send.set_binding(send_bind);
+                udp_recv.set_binding(recv_bind);
+            }
+            Err(_) => panic!("DTLS port bound already"),
+        }
+
+        let alarm = s.4.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let crypto_buffer = s.5.write([0; TRANSCRIPT_LEN]);
+        let digest_buffer = s.6.write([0; 32]);
+        let crypto = s.7.write(DtlsCrypto::new(
+            self.ccm,
+            self.sha,
+            crypto_buffer,
+            digest_buffer,
+        ));
+        self.ccm.set_client(crypto);
+        self.sha.set_client(crypto);
+
+        let tx_buffer = s.8.write([0; MAX_PAYLOAD_LEN]);
+        let rx_buffer = s.9.write([0; MAX_PAYLOAD_LEN]);
+        let flight_buffer = s.10.write([0; FLIGHT_LEN]);
+        let transcript_buffer = s.11.write([0; TRANSCRIPT_LEN]);
+        let session = s.12.write(DtlsSession::new(
+            udp_send,
+            crypto,
+            self.rng,
+            alarm,
+            net_cap,
+            self.role,
+            tx_buffer,
+            rx_buffer,
+            flight_buffer,
+            transcript_buffer,
+        ));
+        crypto.set_client(session);
+        udp_send.set_client(session);
+        udp_recv.set_client(session);
+        alarm.set_alarm_client(session);
+        self.rng.set_client(session);
+
+        session
+    }
+}
@@ -0,0 +1,220 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for the factory reset facility.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let factory_reset = components::factory_reset::FactoryResetComponent::new(
+//!     board_kernel,
+//!     &["settings"],
+//!     Some(reset_function),
+//! )
+//! .finalize(components::factory_reset_component_static!());
+//!
+//! let kv_region = components::factory_reset::FlashRegionComponent::new(kv_flash, 64, 32)
+//!     .finalize(components::factory_reset_flash_region_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>
+//!     ));
+//! factory_reset.register(kv_region).unwrap();
+//!
+//! components::factory_reset::LongPressTriggerComponent::new(
+//!     mux_alarm,
+//!     &nrf52840::gpio::PORT[BUTTON_PIN],
+//!     kernel::hil::gpio::ActivationMode::ActiveLow,
+//!     5000,
+//!     factory_reset,
+//! )
+//! .finalize(components::factory_reset_long_press_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::gpio::GPIOPin<'static>,
+//! ));
+//! ```
+
+use capsules_core::factory_reset::{
+    FactoryReset, FlashRegion, LongPressTrigger, Trigger, DEFAULT_MAX_REGIONS,
+};
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash;
+use kernel::hil::gpio;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! factory_reset_component_static {
+    ($N:expr $(,)?) => {{
+        kernel::static_buf!(
+            capsules_core::factory_reset::FactoryReset<
+                'static,
+                $crate::factory_reset::Capability,
+                $N,
+            >
+        )
+    };};
+    () => {{
+        $crate::factory_reset_component_static!(capsules_core::factory_reset::DEFAULT_MAX_REGIONS)
+    };};
+}
+
+#[macro_export]
+macro_rules! factory_reset_flash_region_component_static {
+    ($F:ty $(,)?) => {{
+        kernel::static_buf!(capsules_core::factory_reset::FlashRegion<'static, $F>)
+    };};
+}
+
+#[macro_export]
+macro_rules! factory_reset_long_press_component_static {
+    ($A:ty, $P:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let trigger = kernel::static_buf!(
+            capsules_core::factory_reset::LongPressTrigger<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $P,
+            >
+        );
+
+        (alarm, trigger)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct FactoryResetComponent<const MAX_REGIONS: usize = DEFAULT_MAX_REGIONS> {
+    board_kernel: &'static kernel::Kernel,
+    privileged: &'static [&'static str],
+    reset_function: Option<fn() -> !>,
+}
+
+impl<const MAX_REGIONS: usize> FactoryResetComponent<MAX_REGIONS> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        privileged: &'static [&'static str],
+        reset_function: Option<fn() -> !>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            privileged,
+            reset_function,
+        }
+    }
+}
+
+impl<const MAX_REGIONS: usize> Component for FactoryResetComponent<MAX_REGIONS> {
+    type StaticInput = &'static mut MaybeUninit<FactoryReset<'static, Capability, MAX_REGIONS>>;
+    type Output = &'static FactoryReset<'static, Capability, MAX_REGIONS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let factory_reset = static_buffer.write(FactoryReset::new(
+            self.board_kernel,
+            self.privileged,
+            self.reset_function,
+            Capability,
+        ));
+        DeferredCallClient::register(factory_reset);
+
+        factory_reset
+    }
+}
+
+pub struct FlashRegionComponent<F: 'static + flash::Flash> {
+    flash: &'static F,
+    start_page: usize,
+    num_pages: usize,
+}
+
+impl<F: 'static + flash::Flash> FlashRegionComponent<F> {
+    pub fn new(flash: &'static F, start_page: usize, num_pages: usize) -> Self {
+        Self {
+            flash,
+            start_page,
+            num_pages,
+        }
+    }
+}
+
+impl<F: 'static + flash::Flash + flash::HasClient<'static, FlashRegion<'static, F>>> Component
+    for FlashRegionComponent<F>
+{
+    type StaticInput = &'static mut MaybeUninit<FlashRegion<'static, F>>;
+    type Output = &'static FlashRegion<'static, F>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let region = static_buffer.write(FlashRegion::new(
+            self.flash,
+            self.start_page,
+            self.num_pages,
+        ));
+        self.flash.set_client(region);
+
+        region
+    }
+}
+
+pub struct LongPressTriggerComponent<
+    A: 'static + time::Alarm<'static>,
+    P: 'static + gpio::InterruptPin<'static>,
+> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    pin: &'static P,
+    mode: gpio::ActivationMode,
+    hold_ms: u32,
+    factory_reset: &'static dyn Trigger,
+}
+
+impl<A: 'static + time::Alarm<'static>, P: 'static + gpio::InterruptPin<'static>>
+    LongPressTriggerComponent<A, P>
+{
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        pin: &'static P,
+        mode: gpio::ActivationMode,
+        hold_ms: u32,
+        factory_reset: &'static dyn Trigger,
+    ) -> Self {
+        Self {
+            alarm_mux,
+            pin,
+            mode,
+            hold_ms,
+            factory_reset,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, P: 'static + gpio::InterruptPin<'static>> Component
+    for LongPressTriggerComponent<A, P>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<LongPressTrigger<'static, VirtualMuxAlarm<'static, A>, P>>,
+    );
+    type Output = &'static LongPressTrigger<'static, VirtualMuxAlarm<'static, A>, P>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let trigger = static_buffer.1.write(LongPressTrigger::new(
+            alarm,
+            self.pin,
+            self.mode,
+            self.hold_ms,
+            self.factory_reset,
+        ));
+        alarm.set_alarm_client(trigger);
+        self.pin.set_client(trigger);
+        trigger.start();
+
+        trigger
+    }
+}
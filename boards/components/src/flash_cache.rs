@@ -0,0 +1,67 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a write-back page cache in front of a flash.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let flash_cache = components::flash_cache::FlashCacheComponent::new(flash_user)
+//!     .finalize(components::flash_cache_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>,
+//!         4
+//!     ));
+//! ```
+
+use capsules_extra::flash_cache::FlashCache;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash::{Flash, HasClient};
+
+#[macro_export]
+macro_rules! flash_cache_component_static {
+    ($F:ty, $N:expr $(,)?) => {{
+        let pages = kernel::static_buf!([<$F as kernel::hil::flash::Flash>::Page; $N]);
+        let cache = kernel::static_buf!(capsules_extra::flash_cache::FlashCache<'static, $F, $N>);
+
+        (pages, cache)
+    };};
+}
+
+pub struct FlashCacheComponent<
+    F: 'static + Flash + HasClient<'static, FlashCache<'static, F, N>>,
+    const N: usize,
+> {
+    flash: &'static F,
+}
+
+impl<F: 'static + Flash + HasClient<'static, FlashCache<'static, F, N>>, const N: usize>
+    FlashCacheComponent<F, N>
+{
+    pub fn new(flash: &'static F) -> Self {
+        Self { flash }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, FlashCache<'static, F, N>>, const N: usize> Component
+    for FlashCacheComponent<F, N>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[F::Page; N]>,
+        &'static mut MaybeUninit<FlashCache<'static, F, N>>,
+    );
+    type Output = &'static FlashCache<'static, F, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let pages = static_buffer
+            .0
+            .write(core::array::from_fn(|_| F::Page::default()));
+        let cache = static_buffer.1.write(FlashCache::new(self.flash, pages));
+        HasClient::set_client(self.flash, cache);
+        cache.register();
+
+        cache
+    }
+}
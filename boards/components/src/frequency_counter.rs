@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the frequency counter syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let frequency_counter = components::frequency_counter::FrequencyCounterComponent::new(
+//!     board_kernel,
+//!     capsules_extra::frequency_counter::DRIVER_NUM,
+//!     mux_alarm,
+//!     edge_counter,
+//! )
+//! .finalize(components::frequency_counter_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     nrf52840::edge_counter::EdgeCounter<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::frequency_counter::FrequencyCounter;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::counter::EdgeCounter;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! frequency_counter_component_static {
+    ($A:ty, $C:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let frequency_counter = kernel::static_buf!(
+            capsules_extra::frequency_counter::FrequencyCounter<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $C,
+            >
+        );
+
+        (alarm, frequency_counter)
+    };};
+}
+
+pub struct FrequencyCounterComponent<A: 'static + time::Alarm<'static>, C: 'static + EdgeCounter> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    counter: &'static C,
+}
+
+impl<A: 'static + time::Alarm<'static>, C: 'static + EdgeCounter> FrequencyCounterComponent<A, C> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        counter: &'static C,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            counter,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, C: 'static + EdgeCounter> Component
+    for FrequencyCounterComponent<A, C>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<FrequencyCounter<'static, VirtualMuxAlarm<'static, A>, C>>,
+    );
+    type Output = &'static FrequencyCounter<'static, VirtualMuxAlarm<'static, A>, C>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let driver = static_buffer.1.write(FrequencyCounter::new(
+            self.counter,
+            alarm,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(driver);
+
+        driver
+    }
+}
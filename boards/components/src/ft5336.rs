@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for the FT5336 Touch Panel.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ft5336 = components::ft5336::Ft5336Component::new(
+//!    i2c_mux,
+//!    0x38,
+//!    base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PI13).unwrap(),
+//!    true,
+//! )
+//!    .finalize(components::ft5336_component_static!(stm32f746ng::i2c::I2C));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::ft5336::{Ft5336, BUFFER_LEN, MAX_TOUCHES, NO_TOUCH};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+use kernel::hil::touch::TouchEvent;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ft5336_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::ft5336::BUFFER_LEN]);
+        let events_buffer = kernel::static_buf!(
+            [kernel::hil::touch::TouchEvent; capsules_extra::ft5336::MAX_TOUCHES]
+        );
+        let ft5336 = kernel::static_buf!(
+            capsules_extra::ft5336::Ft5336<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>,
+            >
+        );
+
+        (i2c_device, ft5336, buffer, events_buffer)
+    };};
+}
+
+pub struct Ft5336Component<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    swap_xy: bool,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Ft5336Component<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        pin: &'static dyn gpio::InterruptPin,
+        swap_xy: bool,
+    ) -> Ft5336Component<I> {
+        Ft5336Component {
+            i2c_mux,
+            i2c_address,
+            interrupt_pin: pin,
+            swap_xy,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Ft5336Component<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<Ft5336<'static, I2CDevice<'static, I>>>,
+        &'static mut MaybeUninit<[u8; BUFFER_LEN]>,
+        &'static mut MaybeUninit<[TouchEvent; MAX_TOUCHES]>,
+    );
+    type Output = &'static Ft5336<'static, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let ft5336_i2c = static_buffer
+            .0
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+
+        let buffer = static_buffer.2.write([0; BUFFER_LEN]);
+        let events_buffer = static_buffer.3.write([NO_TOUCH; MAX_TOUCHES]);
+
+        let ft5336 = static_buffer.1.write(Ft5336::new(
+            ft5336_i2c,
+            self.interrupt_pin,
+            self.swap_xy,
+            buffer,
+            events_buffer,
+        ));
+        ft5336_i2c.set_client(ft5336);
+        self.interrupt_pin.set_client(ft5336);
+
+        ft5336
+    }
+}
@@ -20,6 +20,7 @@
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 6/03/2020
 
+use capsules_core::i2c_register::I2CRegisterClient;
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules_extra::fxos8700cq::Fxos8700cq;
 
@@ -36,9 +37,11 @@ macro_rules! fxos8700_component_static {
         let i2c_device =
             kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>);
         let buffer = kernel::static_buf!([u8; capsules_extra::fxos8700cq::BUF_LEN]);
+        let registers =
+            kernel::static_buf!(capsules_core::i2c_register::I2CRegisterClient<'static>);
         let fxo = kernel::static_buf!(capsules_extra::fxos8700cq::Fxos8700cq<'static>);
 
-        (i2c_device, buffer, fxo)
+        (i2c_device, buffer, fxo, registers)
     };};
 }
 
@@ -67,15 +70,19 @@ impl<I: 'static + i2c::I2CMaster<'static>> Component for Fxos8700Component<I> {
         &'static mut MaybeUninit<I2CDevice<'static, I>>,
         &'static mut MaybeUninit<[u8; capsules_extra::fxos8700cq::BUF_LEN]>,
         &'static mut MaybeUninit<Fxos8700cq<'static>>,
+        &'static mut MaybeUninit<I2CRegisterClient<'static>>,
     );
     type Output = &'static Fxos8700cq<'static>;
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
         let fxos8700_i2c = s.0.write(I2CDevice::new(self.i2c_mux, self.i2c_address));
         let buffer = s.1.write([0; capsules_extra::fxos8700cq::BUF_LEN]);
-        let fxos8700 = s.2.write(Fxos8700cq::new(fxos8700_i2c, self.gpio, buffer));
+        // The FXOS8700CQ moves to the next register without being asked.
+        let registers = s.3.write(I2CRegisterClient::new(fxos8700_i2c, buffer, 0));
+        let fxos8700 = s.2.write(Fxos8700cq::new(registers, self.gpio));
 
-        fxos8700_i2c.set_client(fxos8700);
+        fxos8700_i2c.set_client(registers);
+        registers.set_client(fxos8700);
         self.gpio.set_client(fxos8700);
 
         fxos8700
@@ -0,0 +1,128 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a USB HID keyboard and mouse controlled by userspace.
+//!
+//! Usage
+//! -----
+//!
+//! ```
+//! let strings = static_init!(
+//!     [&str; 3],
+//!     [
+//!         "Nordic Semiconductor", // Manufacturer
+//!         "nRF52840dk - TockOS",  // Product
+//!         "serial0001",           // Serial number
+//!     ]
+//! );
+//!
+//! let (hid, hid_input) = components::hid_input::HidInputComponent::new(
+//!     board_kernel,
+//!     capsules_extra::hid_input::DRIVER_NUM,
+//!     &nrf52840_peripherals.usbd,
+//!     0x1915, // Nordic Semiconductor
+//!     0x503b,
+//!     strings,
+//! )
+//! .finalize(components::hid_input_component_static!(
+//!     nrf52840::usbd::Usbd
+//! ));
+//!
+//! hid.enable();
+//! hid.attach();
+//! ```
+
+use capsules_extra::hid_input::HidInput;
+use capsules_extra::usb::hid_composite::{self, CompositeHid};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! hid_input_component_static {
+    ($U:ty $(,)?) => {{
+        let hid =
+            kernel::static_buf!(capsules_extra::usb::hid_composite::CompositeHid<'static, $U>);
+        let driver = kernel::static_buf!(
+            capsules_extra::hid_input::HidInput<
+                'static,
+                capsules_extra::usb::hid_composite::CompositeHid<'static, $U>,
+            >
+        );
+        let report = kernel::static_buf!([u8; 64]);
+
+        (hid, driver, report)
+    };};
+}
+
+pub struct HidInputComponent<U: 'static + hil::usb::UsbController<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    usb: &'static U,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> HidInputComponent<U> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        usb: &'static U,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+    ) -> HidInputComponent<U> {
+        HidInputComponent {
+            board_kernel,
+            driver_num,
+            usb,
+            vendor_id,
+            product_id,
+            strings,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for HidInputComponent<U> {
+    type StaticInput = (
+        &'static mut MaybeUninit<CompositeHid<'static, U>>,
+        &'static mut MaybeUninit<HidInput<'static, CompositeHid<'static, U>>>,
+        &'static mut MaybeUninit<[u8; 64]>,
+    );
+    type Output = (
+        &'static CompositeHid<'static, U>,
+        &'static HidInput<'static, CompositeHid<'static, U>>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let hid = s.0.write(CompositeHid::new(
+            self.usb,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            &hid_composite::KEYBOARD_MOUSE_HID_DESCRIPTOR,
+            &hid_composite::KEYBOARD_MOUSE_REPORT,
+            hid_composite::KEYBOARD_MOUSE_REPORTS,
+        ));
+        self.usb.set_client(hid);
+
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let report = s.2.write([0; 64]);
+
+        let hid_input = s.1.write(HidInput::new(
+            hid,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            report,
+        ));
+
+        hid.set_client(hid_input);
+
+        (hid, hid_input)
+    }
+}
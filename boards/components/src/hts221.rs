@@ -13,6 +13,7 @@
 //! let humidity = components::humidity::HumidityComponent::new(board_kernel, hts221).finalize(());
 //! ```
 
+use capsules_core::i2c_register::I2CRegisterClient;
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules_extra::hts221::Hts221;
 use core::mem::MaybeUninit;
@@ -26,14 +27,11 @@ macro_rules! hts221_component_static {
         let i2c_device =
             kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>);
         let buffer = kernel::static_buf!([u8; 17]);
-        let hts221 = kernel::static_buf!(
-            capsules_extra::hts221::Hts221<
-                'static,
-                capsules_core::virtualizers::virtual_i2c::I2CDevice<$I>,
-            >
-        );
+        let registers =
+            kernel::static_buf!(capsules_core::i2c_register::I2CRegisterClient<'static>);
+        let hts221 = kernel::static_buf!(capsules_extra::hts221::Hts221<'static>);
 
-        (i2c_device, buffer, hts221)
+        (i2c_device, buffer, hts221, registers)
     };};
 }
 
@@ -55,18 +53,25 @@ impl<I: 'static + i2c::I2CMaster<'static>> Component for Hts221Component<I> {
     type StaticInput = (
         &'static mut MaybeUninit<I2CDevice<'static, I>>,
         &'static mut MaybeUninit<[u8; 17]>,
-        &'static mut MaybeUninit<Hts221<'static, I2CDevice<'static, I>>>,
+        &'static mut MaybeUninit<Hts221<'static>>,
+        &'static mut MaybeUninit<I2CRegisterClient<'static>>,
     );
-    type Output = &'static Hts221<'static, I2CDevice<'static, I>>;
+    type Output = &'static Hts221<'static>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let hts221_i2c = static_buffer
             .0
             .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
         let buffer = static_buffer.1.write([0; 17]);
-        let hts221 = static_buffer.2.write(Hts221::new(hts221_i2c, buffer));
+        let registers = static_buffer.3.write(I2CRegisterClient::new(
+            hts221_i2c,
+            buffer,
+            capsules_extra::hts221::REG_AUTO_INCREMENT,
+        ));
+        let hts221 = static_buffer.2.write(Hts221::new(registers));
 
-        hts221_i2c.set_client(hts221);
+        hts221_i2c.set_client(registers);
+        registers.set_client(hts221);
         hts221
     }
 }
@@ -21,7 +21,10 @@ use kernel::hil;
 #[macro_export]
 macro_rules! humidity_component_static {
     () => {{
-        kernel::static_buf!(capsules_extra::humidity::HumiditySensor<'static>)
+        let sensor = kernel::static_buf!(capsules_extra::humidity::HumiditySensor<'static>);
+        let drivers =
+            kernel::static_buf!([&'static dyn kernel::hil::sensors::HumidityDriver<'static>; 1]);
+        (sensor, drivers)
     };};
 }
 
@@ -46,14 +49,18 @@ impl<T: 'static + hil::sensors::HumidityDriver<'static>> HumidityComponent<T> {
 }
 
 impl<T: 'static + hil::sensors::HumidityDriver<'static>> Component for HumidityComponent<T> {
-    type StaticInput = &'static mut MaybeUninit<HumiditySensor<'static>>;
+    type StaticInput = (
+        &'static mut MaybeUninit<HumiditySensor<'static>>,
+        &'static mut MaybeUninit<[&'static dyn hil::sensors::HumidityDriver<'static>; 1]>,
+    );
     type Output = &'static HumiditySensor<'static>;
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-        let humidity = s.write(HumiditySensor::new(
-            self.sensor,
+        let drivers = s.1.write([self.sensor]);
+        let humidity = s.0.write(HumiditySensor::new(
+            drivers,
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
         ));
 
@@ -0,0 +1,193 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for an IPv4 interface on an Ethernet adapter, and for its
+//! DHCP client.
+//!
+//! The interface is configured statically with `Some(config)`, or left
+//! without an address for the DHCP client to configure.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ipv4 = components::ipv4::IPv4Component::new(
+//!     cdc_ncm,
+//!     mux_alarm,
+//!     Some(IPv4Config {
+//!         address: IPv4Addr([192, 168, 7, 2]),
+//!         netmask: IPv4Addr([255, 255, 255, 0]),
+//!         gateway: IPv4Addr([192, 168, 7, 1]),
+//!     }),
+//! )
+//! .finalize(components::ipv4_component_static!(
+//!     capsules_extra::usb::cdc_ncm::CdcNcm<'static, nrf52::usbd::Usbd>,
+//!     nrf52840::rtc::Rtc,
+//! ));
+//! ```
+//!
+//! or, with DHCP:
+//!
+//! ```rust
+//! let ipv4 = components::ipv4::IPv4Component::new(cdc_ncm, mux_alarm, None)
+//!     .finalize(components::ipv4_component_static!(
+//!         capsules_extra::usb::cdc_ncm::CdcNcm<'static, nrf52::usbd::Usbd>,
+//!         nrf52840::rtc::Rtc,
+//!     ));
+//! let dhcp = components::ipv4::DhcpComponent::new(ipv4, mux_alarm)
+//!     .finalize(components::dhcp_component_static!(
+//!         capsules_extra::usb::cdc_ncm::CdcNcm<'static, nrf52::usbd::Usbd>,
+//!         nrf52840::rtc::Rtc,
+//!     ));
+//! dhcp.start().unwrap();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::ipv4::dhcp::{Dhcp, DHCP_CLIENT_PORT, DHCP_MESSAGE_LEN};
+use capsules_extra::net::ipv4::interface::{IPv4Interface, UdpSocket, CONTROL_FRAME_LEN};
+use capsules_extra::net::ipv4::IPv4Config;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::ethernet::{EthernetAdapter, MAX_FRAME_LEN};
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! ipv4_component_static {
+    ($E:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let data_frame = kernel::static_buf!([u8; kernel::hil::ethernet::MAX_FRAME_LEN]);
+        let control_frame =
+            kernel::static_buf!([u8; capsules_extra::net::ipv4::interface::CONTROL_FRAME_LEN]);
+        let iface = kernel::static_buf!(
+            capsules_extra::net::ipv4::IPv4Interface<
+                'static,
+                $E,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, data_frame, control_frame, iface)
+    };};
+}
+
+pub struct IPv4Component<E: EthernetAdapter<'static> + 'static, A: Alarm<'static> + 'static> {
+    adapter: &'static E,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    config: Option<IPv4Config>,
+}
+
+impl<E: EthernetAdapter<'static>, A: Alarm<'static>> IPv4Component<E, A> {
+    pub fn new(
+        adapter: &'static E,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        config: Option<IPv4Config>,
+    ) -> Self {
+        Self {
+            adapter,
+            alarm_mux,
+            config,
+        }
+    }
+}
+
+impl<E: EthernetAdapter<'static>, A: Alarm<'static>> Component for IPv4Component<E, A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[u8; MAX_FRAME_LEN]>,
+        &'static mut MaybeUninit<[u8; CONTROL_FRAME_LEN]>,
+        &'static mut MaybeUninit<IPv4Interface<'static, E, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static IPv4Interface<'static, E, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let data_frame = s.1.write([0; MAX_FRAME_LEN]);
+        let control_frame = s.2.write([0; CONTROL_FRAME_LEN]);
+        let iface = s.3.write(IPv4Interface::new(
+            self.adapter,
+            alarm,
+            data_frame,
+            control_frame,
+        ));
+        iface.set_config(self.config);
+        self.adapter.set_client(iface);
+        alarm.set_alarm_client(iface);
+
+        iface
+    }
+}
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! dhcp_component_static {
+    ($E:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let socket = kernel::static_buf!(
+            capsules_extra::net::ipv4::UdpSocket<
+                'static,
+                $E,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let tx_buffer =
+            kernel::static_buf!([u8; capsules_extra::net::ipv4::dhcp::DHCP_MESSAGE_LEN]);
+        let dhcp = kernel::static_buf!(
+            capsules_extra::net::ipv4::Dhcp<
+                'static,
+                $E,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, socket, tx_buffer, dhcp)
+    };};
+}
+
+pub struct DhcpComponent<E: EthernetAdapter<'static> + 'static, A: Alarm<'static> + 'static> {
+    iface: &'static IPv4Interface<'static, E, VirtualMuxAlarm<'static, A>>,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<E: EthernetAdapter<'static>, A: Alarm<'static>> DhcpComponent<E, A> {
+    pub fn new(
+        iface: &'static IPv4Interface<'static, E, VirtualMuxAlarm<'static, A>>,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self { iface, alarm_mux }
+    }
+}
+
+impl<E: EthernetAdapter<'static>, A: Alarm<'static>> Component for DhcpComponent<E, A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<UdpSocket<'static, E, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; DHCP_MESSAGE_LEN]>,
+        &'static mut MaybeUninit<Dhcp<'static, E, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Dhcp<'static, E, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let socket = s.1.write(UdpSocket::new(self.iface));
+        self.iface.add_socket(socket);
+        if socket.bind(DHCP_CLIENT_PORT).is_err() {
+            panic!("DHCP client port bound already");
+        }
+
+        let tx_buffer = s.2.write([0; DHCP_MESSAGE_LEN]);
+        let dhcp = s.3.write(Dhcp::new(self.iface, socket, alarm, tx_buffer));
+        socket.set_client(dhcp);
+        alarm.set_alarm_client(dhcp);
+
+        dhcp
+    }
+}
@@ -26,6 +26,7 @@
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 6/20/2018
 
+use capsules_core::i2c_sequence::I2CSequence;
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules_extra::ambient_light::AmbientLight;
@@ -48,6 +49,13 @@ macro_rules! isl29035_component_static {
         let i2c_device =
             kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
         let i2c_buffer = kernel::static_buf!([u8; capsules_extra::isl29035::BUF_LEN]);
+        let data_buffer = kernel::static_buf!([u8; capsules_extra::isl29035::DATA_LEN]);
+        let sequence = kernel::static_buf!(
+            capsules_core::i2c_sequence::I2CSequence<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
         let isl29035 = kernel::static_buf!(
             capsules_extra::isl29035::Isl29035<
                 'static,
@@ -55,7 +63,14 @@ macro_rules! isl29035_component_static {
             >
         );
 
-        (alarm, i2c_device, i2c_buffer, isl29035)
+        (
+            alarm,
+            i2c_device,
+            i2c_buffer,
+            isl29035,
+            data_buffer,
+            sequence,
+        )
     };};
 }
 
@@ -93,6 +108,8 @@ impl<A: 'static + time::Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Co
         &'static mut MaybeUninit<I2CDevice<'static, I>>,
         &'static mut MaybeUninit<[u8; capsules_extra::isl29035::BUF_LEN]>,
         &'static mut MaybeUninit<Isl29035<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::isl29035::DATA_LEN]>,
+        &'static mut MaybeUninit<I2CSequence<'static, VirtualMuxAlarm<'static, A>>>,
     );
     type Output = &'static Isl29035<'static, VirtualMuxAlarm<'static, A>>;
 
@@ -104,13 +121,21 @@ impl<A: 'static + time::Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Co
         let isl29035_virtual_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
         isl29035_virtual_alarm.setup();
 
-        let isl29035 = static_buffer.3.write(Isl29035::new(
+        let isl29035_data_buffer = static_buffer
+            .4
+            .write([0; capsules_extra::isl29035::DATA_LEN]);
+
+        let isl29035_sequence = static_buffer.5.write(I2CSequence::new(
             isl29035_i2c,
             isl29035_virtual_alarm,
             isl29035_i2c_buffer,
+            isl29035_data_buffer,
         ));
-        isl29035_i2c.set_client(isl29035);
-        isl29035_virtual_alarm.set_alarm_client(isl29035);
+        isl29035_i2c.set_client(isl29035_sequence);
+        isl29035_virtual_alarm.set_alarm_client(isl29035_sequence);
+
+        let isl29035 = static_buffer.3.write(Isl29035::new(isl29035_sequence));
+        isl29035_sequence.set_client(isl29035);
         isl29035
     }
 }
@@ -88,8 +88,10 @@ macro_rules! kv_store_component_static {
         let kv_store = kernel::static_buf!(capsules_extra::kv_store::KVStore<'static, $K, $T>);
         let key = kernel::static_buf!($T);
         let buffer = kernel::static_buf!([u8; 9]);
+        let journal = kernel::static_buf!([u8; 65]);
+        let staged_value = kernel::static_buf!([u8; 48]);
 
-        (kv_store, key, buffer)
+        (kv_store, key, buffer, journal, staged_value)
     };};
 }
 
@@ -110,15 +112,27 @@ impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType + Default> Comp
         &'static mut MaybeUninit<KVStore<'static, K, T>>,
         &'static mut MaybeUninit<T>,
         &'static mut MaybeUninit<[u8; 9]>,
+        &'static mut MaybeUninit<[u8; 65]>,
+        &'static mut MaybeUninit<[u8; 48]>,
     );
     type Output = &'static KVStore<'static, K, T>;
 
     fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
         let key_buf = static_buffer.1.write(T::default());
         let buffer = static_buffer.2.write([0; 9]);
-        static_buffer
-            .0
-            .write(KVStore::new(self.kv_store, key_buf, buffer))
+        // Room for 8 changes of 8 byte keys in a transaction.
+        let journal = static_buffer.3.write([0; 65]);
+        // As large as the values of the KV driver.
+        let staged_value = static_buffer.4.write([0; 48]);
+        let kv_store = static_buffer.0.write(KVStore::new(
+            self.kv_store,
+            key_buf,
+            buffer,
+            journal,
+            staged_value,
+        ));
+        kv_store.setup();
+        kv_store
     }
 }
 
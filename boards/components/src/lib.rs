@@ -6,12 +6,20 @@
 
 pub mod adc;
 pub mod adc_microphone;
+pub mod adc_stream;
 pub mod air_quality;
 pub mod alarm;
+pub mod allow_audit;
 pub mod analog_comparator;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_install;
+pub mod app_preferences;
+pub mod app_update;
+pub mod blackboard;
 pub mod ble;
+pub mod ble_security;
+pub mod block_storage;
 pub mod bme280;
 pub mod bmp280;
 pub mod bus;
@@ -19,50 +27,80 @@ pub mod button;
 pub mod can;
 pub mod ccs811;
 pub mod cdc;
+pub mod cdc_ncm;
+pub mod cmsis_dap;
+pub mod coap;
+pub mod compressed_log;
 pub mod console;
 pub mod crc;
 pub mod ctap;
 pub mod dac;
+pub mod deadline;
 pub mod debug_queue;
 pub mod debug_writer;
+pub mod device_id;
 pub mod digest;
+pub mod dtls;
+pub mod factory_reset;
 pub mod flash;
+pub mod flash_cache;
 pub mod fm25cl;
+pub mod frequency_counter;
+pub mod ft5336;
 pub mod ft6x06;
 pub mod fxos8700;
 pub mod gpio;
 pub mod hd44780;
+pub mod hid_input;
 pub mod hmac;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
 pub mod ieee802154;
+pub mod ipv4;
 pub mod isl29035;
 pub mod keyboard_hid;
 pub mod kv_system;
 pub mod l3gd20;
 pub mod led;
 pub mod led_matrix;
+pub mod littlefs;
+pub mod liveness;
 pub mod lldb;
+pub mod logic_analyzer;
 pub mod lpm013m126;
 pub mod lps25hb;
 pub mod lsm303agr;
 pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
+pub mod message_queue;
 pub mod mlx90614;
+pub mod mqttsn;
 pub mod mx25r6435f;
 pub mod ninedof;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
 pub mod panic_button;
+pub mod postmortem;
 pub mod process_console;
+pub mod process_info;
+pub mod process_loader;
 pub mod process_printer;
+pub mod process_snapshot;
+pub mod provisioning;
 pub mod proximity;
+pub mod pulse_counter;
+pub mod pulse_generator;
 pub mod pwm;
+pub mod pwm_audio;
+pub mod qspi_flash;
+pub mod record_log;
+pub mod restart_backoff;
 pub mod rf233;
 pub mod rng;
 pub mod sched;
+pub mod scheduler_control;
 pub mod screen;
 pub mod segger_rtt;
 pub mod sha;
@@ -71,13 +109,24 @@ pub mod si7021;
 pub mod sound_pressure;
 pub mod spi;
 pub mod st77xx;
+pub mod system_info;
+pub mod tcp;
+pub mod telemetry;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod test;
 pub mod text_screen;
+pub mod thread_credentials;
+pub mod thread_mtd;
 pub mod tickv;
 pub mod touch;
 pub mod udp_driver;
 pub mod udp_mux;
 pub mod usb;
+pub mod usb_dfu;
+pub mod usb_host;
+pub mod usb_midi;
+pub mod usb_msc;
+pub mod uuid;
+pub mod wear_leveling;
@@ -0,0 +1,171 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for a littlefs filesystem and the syscall driver for files.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! // Up to 4 open files in the first 512 blocks of the flash.
+//! let fs = components::littlefs::LittleFsComponent::new(mx25r6435f, 0, 512).finalize(
+//!     components::littlefs_component_static!(
+//!         capsules_extra::mx25r6435f::MX25R6435F<'static, ...>,
+//!         4,
+//!         512
+//!     ),
+//! );
+//!
+//! let filesystem = components::littlefs::FileSystemDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::filesystem_driver::DRIVER_NUM,
+//!     fs,
+//! )
+//! .finalize(components::filesystem_driver_component_static!(
+//!     capsules_extra::littlefs::filesystem::LittleFs<
+//!         'static,
+//!         capsules_extra::mx25r6435f::MX25R6435F<'static, ...>,
+//!         4,
+//!     >,
+//!     256
+//! ));
+//! ```
+
+use capsules_extra::filesystem_driver::FileSystemDriver;
+use capsules_extra::littlefs::filesystem::{LittleFs, NAME_MAX};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::filesystem::FileSystem;
+use kernel::hil::flash::{Flash, HasClient};
+
+#[macro_export]
+macro_rules! littlefs_component_static {
+    ($F:ty, $FILES:expr, $BLOCKS:expr $(,)?) => {{
+        let root = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let io = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let used = kernel::static_buf!([u8; ($BLOCKS + 7) / 8]);
+        let fs = kernel::static_buf!(
+            capsules_extra::littlefs::filesystem::LittleFs<'static, $F, $FILES>
+        );
+
+        (root, io, used, fs)
+    };};
+}
+
+#[macro_export]
+macro_rules! filesystem_driver_component_static {
+    ($FS:ty, $BUF_LEN:expr $(,)?) => {{
+        let name = kernel::static_buf!([u8; capsules_extra::littlefs::filesystem::NAME_MAX]);
+        let data = kernel::static_buf!([u8; $BUF_LEN]);
+        let driver =
+            kernel::static_buf!(capsules_extra::filesystem_driver::FileSystemDriver<'static, $FS>);
+
+        (name, data, driver)
+    };};
+}
+
+pub struct LittleFsComponent<
+    F: 'static + Flash + HasClient<'static, LittleFs<'static, F, FILES>>,
+    const FILES: usize,
+    const USED: usize,
+> {
+    flash: &'static F,
+    first_page: usize,
+    blocks: u32,
+}
+
+impl<
+        F: 'static + Flash + HasClient<'static, LittleFs<'static, F, FILES>>,
+        const FILES: usize,
+        const USED: usize,
+    > LittleFsComponent<F, FILES, USED>
+{
+    pub fn new(flash: &'static F, first_page: usize, blocks: u32) -> Self {
+        Self {
+            flash,
+            first_page,
+            blocks,
+        }
+    }
+}
+
+impl<
+        F: 'static + Flash + HasClient<'static, LittleFs<'static, F, FILES>>,
+        const FILES: usize,
+        const USED: usize,
+    > Component for LittleFsComponent<F, FILES, USED>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<[u8; USED]>,
+        &'static mut MaybeUninit<LittleFs<'static, F, FILES>>,
+    );
+    type Output = &'static LittleFs<'static, F, FILES>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let root = static_buffer.0.write(F::Page::default());
+        let io = static_buffer.1.write(F::Page::default());
+        let used = static_buffer.2.write([0; USED]);
+        let fs = static_buffer.3.write(LittleFs::new(
+            self.flash,
+            self.first_page,
+            self.blocks,
+            root,
+            io,
+            used,
+        ));
+        HasClient::set_client(self.flash, fs);
+        fs.register();
+
+        fs
+    }
+}
+
+pub struct FileSystemDriverComponent<FS: 'static + FileSystem<'static>, const BUF_LEN: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    fs: &'static FS,
+}
+
+impl<FS: 'static + FileSystem<'static>, const BUF_LEN: usize>
+    FileSystemDriverComponent<FS, BUF_LEN>
+{
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize, fs: &'static FS) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            fs,
+        }
+    }
+}
+
+impl<FS: 'static + FileSystem<'static>, const BUF_LEN: usize> Component
+    for FileSystemDriverComponent<FS, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; NAME_MAX]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<FileSystemDriver<'static, FS>>,
+    );
+    type Output = &'static FileSystemDriver<'static, FS>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let name = static_buffer.0.write([0; NAME_MAX]);
+        let data = static_buffer.1.write([0; BUF_LEN]);
+        let driver = static_buffer.2.write(FileSystemDriver::new(
+            self.fs,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            name,
+            data,
+        ));
+        self.fs.set_client(driver);
+
+        driver
+    }
+}
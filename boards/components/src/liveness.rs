@@ -0,0 +1,91 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the kernel liveness monitor.
+//!
+//! The monitor wraps the hardware watchdog and must be returned by the
+//! board's `KernelResources::watchdog()`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let liveness = components::liveness::LivenessMonitorComponent::new(
+//!     mux_alarm,
+//!     &peripherals.wdt,
+//!     1000,
+//! )
+//! .finalize(components::liveness_monitor_component_static!(
+//!     sam4l::ast::Ast<'static>,
+//!     sam4l::wdt::Wdt
+//! ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::liveness::LivenessMonitor;
+use kernel::platform::watchdog::WatchDog;
+
+#[macro_export]
+macro_rules! liveness_monitor_component_static {
+    ($A:ty, $W:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let monitor = kernel::static_buf!(
+            kernel::liveness::LivenessMonitor<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $W,
+            >
+        );
+
+        (alarm, monitor)
+    };};
+}
+
+pub struct LivenessMonitorComponent<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    watchdog: &'static W,
+    period_ms: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> LivenessMonitorComponent<A, W> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        watchdog: &'static W,
+        period_ms: u32,
+    ) -> Self {
+        LivenessMonitorComponent {
+            alarm_mux,
+            watchdog,
+            period_ms,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, W: 'static + WatchDog> Component
+    for LivenessMonitorComponent<A, W>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<LivenessMonitor<'static, VirtualMuxAlarm<'static, A>, W>>,
+    );
+    type Output = &'static LivenessMonitor<'static, VirtualMuxAlarm<'static, A>, W>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let monitor_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        monitor_alarm.setup();
+
+        let monitor = static_buffer.1.write(LivenessMonitor::new(
+            monitor_alarm,
+            self.watchdog,
+            self.period_ms,
+        ));
+        monitor_alarm.set_alarm_client(monitor);
+        monitor
+    }
+}
@@ -0,0 +1,93 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a SUMP logic analyzer on a UART.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sampler = static_init!(
+//!     capsules_extra::logic_analyzer::GpioSampler<'static, RPGpioPin<'static>>,
+//!     capsules_extra::logic_analyzer::GpioSampler::new(la_pins)
+//! );
+//! let logic_analyzer = components::logic_analyzer::LogicAnalyzerComponent::new(
+//!     sampler,
+//!     cdc,
+//!     125_000_000,
+//! )
+//! .finalize(components::logic_analyzer_component_static!(
+//!     capsules_extra::logic_analyzer::GpioSampler<'static, RPGpioPin<'static>>
+//! ));
+//! ```
+
+use capsules_extra::logic_analyzer::{LogicAnalyzer, Sampler, SAMPLES_LEN, TX_BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::uart;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! logic_analyzer_component_static {
+    ($S:ty $(,)?) => {{
+        let logic_analyzer =
+            kernel::static_buf!(capsules_extra::logic_analyzer::LogicAnalyzer<'static, $S>);
+        let samples = kernel::static_buf!([u8; capsules_extra::logic_analyzer::SAMPLES_LEN]);
+        let tx_buffer = kernel::static_buf!([u8; capsules_extra::logic_analyzer::TX_BUF_LEN]);
+        let rx_buffer = kernel::static_buf!([u8; 1]);
+
+        (logic_analyzer, samples, tx_buffer, rx_buffer)
+    };};
+}
+
+pub struct LogicAnalyzerComponent<S: 'static + Sampler> {
+    sampler: &'static S,
+    uart: &'static dyn uart::UartData<'static>,
+    cpu_frequency_hz: u32,
+}
+
+impl<S: 'static + Sampler> LogicAnalyzerComponent<S> {
+    pub fn new(
+        sampler: &'static S,
+        uart: &'static dyn uart::UartData<'static>,
+        cpu_frequency_hz: u32,
+    ) -> Self {
+        Self {
+            sampler,
+            uart,
+            cpu_frequency_hz,
+        }
+    }
+}
+
+impl<S: 'static + Sampler> Component for LogicAnalyzerComponent<S> {
+    type StaticInput = (
+        &'static mut MaybeUninit<LogicAnalyzer<'static, S>>,
+        &'static mut MaybeUninit<[u8; SAMPLES_LEN]>,
+        &'static mut MaybeUninit<[u8; TX_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; 1]>,
+    );
+    type Output = &'static LogicAnalyzer<'static, S>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let samples = s.1.write([0; SAMPLES_LEN]);
+        let tx_buffer = s.2.write([0; TX_BUF_LEN]);
+        let rx_buffer = s.3.write([0; 1]);
+
+        let logic_analyzer = s.0.write(LogicAnalyzer::new(
+            self.sampler,
+            self.uart,
+            self.cpu_frequency_hz,
+            samples,
+            tx_buffer,
+            rx_buffer,
+        ));
+        self.uart.set_transmit_client(logic_analyzer);
+        self.uart.set_receive_client(logic_analyzer);
+        logic_analyzer.register();
+        let _ = logic_analyzer.start();
+
+        logic_analyzer
+    }
+}
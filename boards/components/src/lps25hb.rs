@@ -4,6 +4,7 @@
 
 //! Component for LPS25HB pressure sensor.
 
+use capsules_core::i2c_register::I2CRegisterClient;
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
 use capsules_extra::lps25hb::LPS25HB;
 use core::mem::MaybeUninit;
@@ -17,16 +18,13 @@ use kernel::hil::i2c;
 macro_rules! lps25hb_component_static {
     ($I:ty $(,)?) => {{
         let i2c_device =
-            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static>);
-        let lps25hb = kernel::static_buf!(
-            capsules_extra::lps25hb::LPS25HB<
-                'static,
-                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
-            >
-        );
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let lps25hb = kernel::static_buf!(capsules_extra::lps25hb::LPS25HB<'static>);
         let buffer = kernel::static_buf!([u8; capsules_extra::lps25hb::BUF_LEN]);
+        let registers =
+            kernel::static_buf!(capsules_core::i2c_register::I2CRegisterClient<'static>);
 
-        (i2c_device, lps25hb, buffer)
+        (i2c_device, lps25hb, buffer, registers)
     };};
 }
 
@@ -59,10 +57,11 @@ impl<I: 'static + i2c::I2CMaster<'static>> Lps25hbComponent<I> {
 impl<I: 'static + i2c::I2CMaster<'static>> Component for Lps25hbComponent<I> {
     type StaticInput = (
         &'static mut MaybeUninit<I2CDevice<'static, I>>,
-        &'static mut MaybeUninit<LPS25HB<'static, I2CDevice<'static, I>>>,
+        &'static mut MaybeUninit<LPS25HB<'static>>,
         &'static mut MaybeUninit<[u8; capsules_extra::lps25hb::BUF_LEN]>,
+        &'static mut MaybeUninit<I2CRegisterClient<'static>>,
     );
-    type Output = &'static LPS25HB<'static, I2CDevice<'static, I>>;
+    type Output = &'static LPS25HB<'static>;
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
@@ -72,9 +71,16 @@ impl<I: 'static + i2c::I2CMaster<'static>> Component for Lps25hbComponent<I> {
 
         let buffer = s.2.write([0; capsules_extra::lps25hb::BUF_LEN]);
 
+        let registers = s.3.write(I2CRegisterClient::new(
+            lps25hb_i2c,
+            buffer,
+            capsules_extra::lps25hb::REGISTER_AUTO_INCREMENT,
+        ));
+
         let lps25hb =
-            s.1.write(LPS25HB::new(lps25hb_i2c, self.interrupt_pin, buffer, grant));
-        lps25hb_i2c.set_client(lps25hb);
+            s.1.write(LPS25HB::new(registers, self.interrupt_pin, grant));
+        lps25hb_i2c.set_client(registers);
+        registers.set_client(lps25hb);
         self.interrupt_pin.set_client(lps25hb);
 
         lps25hb
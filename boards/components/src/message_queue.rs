@@ -0,0 +1,69 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the IPC message queues.
+//!
+//! The queue of each process holds `DEPTH` messages of up to `MSG_LEN`
+//! bytes, taken from its grant memory.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let message_queue = components::message_queue::MessageQueueComponent::new(
+//!     board_kernel,
+//!     capsules_extra::message_queue::DRIVER_NUM,
+//! )
+//! .finalize(components::message_queue_component_static!(64, 4));
+//! ```
+
+use capsules_extra::message_queue::MessageQueue;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! message_queue_component_static {
+    ($MSG_LEN:expr, $DEPTH:expr $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::message_queue::MessageQueue<
+                $crate::message_queue::Capability,
+                $MSG_LEN,
+                $DEPTH,
+            >
+        )
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct MessageQueueComponent<const MSG_LEN: usize, const DEPTH: usize> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<const MSG_LEN: usize, const DEPTH: usize> MessageQueueComponent<MSG_LEN, DEPTH> {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<const MSG_LEN: usize, const DEPTH: usize> Component for MessageQueueComponent<MSG_LEN, DEPTH> {
+    type StaticInput = &'static mut MaybeUninit<MessageQueue<Capability, MSG_LEN, DEPTH>>;
+    type Output = &'static MessageQueue<Capability, MSG_LEN, DEPTH>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(MessageQueue::new(
+            self.board_kernel,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            Capability,
+        ))
+    }
+}
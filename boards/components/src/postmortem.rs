@@ -0,0 +1,53 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the record of the last panic.
+//!
+//! Reserves a `kernel::postmortem::Record` in the `.noinit` section and sets
+//! it as the record of the kernel, so that panics written through a
+//! `kernel::postmortem::PostmortemWriter` survive a reset. Boards that do not
+//! use this component do not record panics, and do not reserve the RAM for
+//! the record.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! components::postmortem::PostmortemComponent::new()
+//!     .finalize(components::postmortem_component_static!());
+//! ```
+
+use kernel::component::Component;
+use kernel::postmortem::{self, Record};
+
+#[macro_export]
+macro_rules! postmortem_component_static {
+    () => {{
+        // The record is not zeroed at boot, so that it keeps the record of a
+        // panic before the reset.
+        #[cfg_attr(
+            any(target_arch = "arm", target_arch = "riscv32"),
+            link_section = ".noinit"
+        )]
+        static mut RECORD: kernel::postmortem::Record = kernel::postmortem::Record::new();
+
+        unsafe { &mut *core::ptr::addr_of_mut!(RECORD) }
+    };};
+}
+
+pub struct PostmortemComponent;
+
+impl PostmortemComponent {
+    pub fn new() -> Self {
+        PostmortemComponent
+    }
+}
+
+impl Component for PostmortemComponent {
+    type StaticInput = &'static mut Record;
+    type Output = ();
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        postmortem::set_record(static_buffer);
+    }
+}
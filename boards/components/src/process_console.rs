@@ -18,7 +18,9 @@
 // Author: Philip Levis <pal@cs.stanford.edu>
 // Last modified: 6/20/2018
 
-use capsules_core::process_console::{self, ProcessConsole};
+use capsules_core::factory_reset;
+use capsules_core::process_console::{self, ConsoleCommand, ProcessConsole};
+use capsules_core::restart_backoff::FaultHistory;
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_core::virtualizers::virtual_uart::{MuxUart, UartDevice};
 use core::mem::MaybeUninit;
@@ -71,6 +73,11 @@ pub struct ProcessConsoleComponent<const COMMAND_HISTORY_LEN: usize, A: 'static
     alarm_mux: &'static MuxAlarm<'static, A>,
     process_printer: &'static dyn ProcessPrinter,
     reset_function: Option<fn() -> !>,
+    gpio_pins: Option<&'static [&'static dyn hil::gpio::Pin]>,
+    factory_reset: Option<&'static dyn factory_reset::Trigger>,
+    fault_history: Option<&'static dyn FaultHistory>,
+    aliases: Option<&'static [(&'static str, &'static str)]>,
+    commands: Option<&'static [&'static dyn ConsoleCommand]>,
 }
 
 impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
@@ -89,8 +96,47 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>>
             alarm_mux,
             process_printer,
             reset_function,
+            gpio_pins: None,
+            factory_reset: None,
+            fault_history: None,
+            aliases: None,
+            commands: None,
         }
     }
+
+    /// Let the `gpio` console command inspect and modify `pins`.
+    pub fn with_gpio_pins(mut self, pins: &'static [&'static dyn hil::gpio::Pin]) -> Self {
+        self.gpio_pins = Some(pins);
+        self
+    }
+
+    /// Let the `factory_reset` console command start a factory reset.
+    pub fn with_factory_reset(
+        mut self,
+        factory_reset: &'static dyn factory_reset::Trigger,
+    ) -> Self {
+        self.factory_reset = Some(factory_reset);
+        self
+    }
+
+    /// Let the `faults` console command show the fault history of the
+    /// processes.
+    pub fn with_fault_history(mut self, fault_history: &'static dyn FaultHistory) -> Self {
+        self.fault_history = Some(fault_history);
+        self
+    }
+
+    /// Add named command sequences, e.g. `&[("bringup", "start app1; start app2")]`.
+    pub fn with_aliases(mut self, aliases: &'static [(&'static str, &'static str)]) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
+
+    /// Add commands implemented by other capsules.
+    pub fn with_commands(mut self, commands: &'static [&'static dyn ConsoleCommand]) -> Self {
+        self.commands = Some(commands);
+        self
+    }
 }
 
 // These constants are defined in the linker script for where the
@@ -191,6 +237,21 @@ impl<const COMMAND_HISTORY_LEN: usize, A: 'static + Alarm<'static>> Component
         hil::uart::Transmit::set_transmit_client(console_uart, console);
         hil::uart::Receive::set_receive_client(console_uart, console);
         console_alarm.set_alarm_client(console);
+        if let Some(pins) = self.gpio_pins {
+            console.set_gpio_pins(pins);
+        }
+        if let Some(factory_reset) = self.factory_reset {
+            console.set_factory_reset(factory_reset);
+        }
+        if let Some(fault_history) = self.fault_history {
+            console.set_fault_history(fault_history);
+        }
+        if let Some(aliases) = self.aliases {
+            console.set_aliases(aliases);
+        }
+        if let Some(commands) = self.commands {
+            console.set_commands(commands);
+        }
 
         console
     }
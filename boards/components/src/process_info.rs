@@ -0,0 +1,48 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the process info syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_info = components::process_info::ProcessInfoComponent::new(board_kernel)
+//!     .finalize(components::process_info_component_static!());
+//! ```
+
+use capsules_core::process_info::ProcessInfo;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+
+#[macro_export]
+macro_rules! process_info_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_core::process_info::ProcessInfo<$crate::process_info::Capability>
+        )
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct ProcessInfoComponent {
+    board_kernel: &'static kernel::Kernel,
+}
+
+impl ProcessInfoComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel) -> Self {
+        Self { board_kernel }
+    }
+}
+
+impl Component for ProcessInfoComponent {
+    type StaticInput = &'static mut MaybeUninit<ProcessInfo<Capability>>;
+    type Output = &'static ProcessInfo<Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(ProcessInfo::new(self.board_kernel, Capability))
+    }
+}
@@ -0,0 +1,47 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the process loader syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_loader = components::process_loader::ProcessLoaderComponent::new(
+//!     loader,
+//!     &[ShortID::Fixed(INSTALLER_SHORT_ID)],
+//! )
+//! .finalize(components::process_loader_component_static!());
+//! ```
+
+use capsules_core::process_loader::ProcessLoader;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::process::{DynamicProcessLoading, ShortID};
+
+#[macro_export]
+macro_rules! process_loader_component_static {
+    () => {{
+        kernel::static_buf!(capsules_core::process_loader::ProcessLoader<'static>)
+    };};
+}
+
+pub struct ProcessLoaderComponent {
+    loader: &'static dyn DynamicProcessLoading,
+    privileged: &'static [ShortID],
+}
+
+impl ProcessLoaderComponent {
+    pub fn new(loader: &'static dyn DynamicProcessLoading, privileged: &'static [ShortID]) -> Self {
+        Self { loader, privileged }
+    }
+}
+
+impl Component for ProcessLoaderComponent {
+    type StaticInput = &'static mut MaybeUninit<ProcessLoader<'static>>;
+    type Output = &'static ProcessLoader<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(ProcessLoader::new(self.loader, self.privileged))
+    }
+}
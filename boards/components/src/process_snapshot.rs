@@ -0,0 +1,62 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the process snapshot syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let process_snapshot = components::process_snapshot::ProcessSnapshotComponent::new(
+//!     board_kernel,
+//!     capsules_core::process_snapshot::DRIVER_NUM,
+//! )
+//! .finalize(components::process_snapshot_component_static!());
+//! ```
+
+use capsules_core::process_snapshot::ProcessSnapshot;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+
+#[macro_export]
+macro_rules! process_snapshot_component_static {
+    () => {{
+        kernel::static_buf!(
+            capsules_core::process_snapshot::ProcessSnapshot<$crate::process_snapshot::Capability>
+        )
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct ProcessSnapshotComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl ProcessSnapshotComponent {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl Component for ProcessSnapshotComponent {
+    type StaticInput = &'static mut MaybeUninit<ProcessSnapshot<Capability>>;
+    type Output = &'static ProcessSnapshot<Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(ProcessSnapshot::new(
+            self.board_kernel,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            Capability,
+        ))
+    }
+}
@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for first boot provisioning.
+//!
+//! `storage_id` is the ID the configuration is stored with in the KV store.
+//! Processes that should read their configuration need it in their read
+//! permissions.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let provisioning = components::provisioning::ProvisioningComponent::new(
+//!     cdc,
+//!     kv_store,
+//!     0x8000_0000,
+//! )
+//! .finalize(components::provisioning_component_static!(
+//!     capsules_extra::tickv::TicKVStore<
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<lowrisc::flash_ctrl::FlashCtrl>,
+//!     >,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! let _ = provisioning.start();
+//! ```
+
+use capsules_extra::kv_store::KVStore;
+use capsules_extra::provisioning::{Provisioning, HEADER_LEN, KEY_LEN, RESPONSE_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+use kernel::hil::uart;
+use kernel::storage_permissions::StoragePermissions;
+
+/// Size of the value buffer, including the KV store header.
+pub const VALUE_BUF_LEN: usize = 128;
+
+#[macro_export]
+macro_rules! provisioning_component_static {
+    ($K:ty, $T:ty $(,)?) => {{
+        let provisioning =
+            kernel::static_buf!(capsules_extra::provisioning::Provisioning<'static, $K, $T>);
+        let header = kernel::static_buf!([u8; capsules_extra::provisioning::HEADER_LEN]);
+        let key = kernel::static_buf!([u8; capsules_extra::provisioning::KEY_LEN]);
+        let value = kernel::static_buf!([u8; $crate::provisioning::VALUE_BUF_LEN]);
+        let response = kernel::static_buf!([u8; capsules_extra::provisioning::RESPONSE_LEN]);
+
+        (provisioning, header, key, value, response)
+    };};
+}
+
+pub struct ProvisioningComponent<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> {
+    uart: &'static dyn uart::UartData<'static>,
+    kv_store: &'static KVStore<'static, K, T>,
+    storage_id: u32,
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> ProvisioningComponent<K, T> {
+    pub fn new(
+        uart: &'static dyn uart::UartData<'static>,
+        kv_store: &'static KVStore<'static, K, T>,
+        storage_id: u32,
+    ) -> Self {
+        Self {
+            uart,
+            kv_store,
+            storage_id,
+        }
+    }
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> Component
+    for ProvisioningComponent<K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Provisioning<'static, K, T>>,
+        &'static mut MaybeUninit<[u8; HEADER_LEN]>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUF_LEN]>,
+        &'static mut MaybeUninit<[u8; RESPONSE_LEN]>,
+    );
+    type Output = &'static Provisioning<'static, K, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+        let perms = StoragePermissions::new_kernel_permissions(self.storage_id, &storage_cap);
+
+        let provisioning = static_buffer.0.write(Provisioning::new(
+            self.uart,
+            self.kv_store,
+            perms,
+            static_buffer.1.write([0; HEADER_LEN]),
+            static_buffer.2.write([0; KEY_LEN]),
+            static_buffer.3.write([0; VALUE_BUF_LEN]),
+            static_buffer.4.write([0; RESPONSE_LEN]),
+        ));
+        self.kv_store.set_client(provisioning);
+        self.uart.set_transmit_client(provisioning);
+        self.uart.set_receive_client(provisioning);
+
+        provisioning
+    }
+}
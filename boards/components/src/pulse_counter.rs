@@ -0,0 +1,166 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the pulse counter of utility meters.
+//!
+//! The retained totals are placed in the `.noinit` section, so the macro must
+//! only be used once per board. `storage_id` is the ID the totals are stored
+//! with in the KV store. The totals are added up every `poll_ms`
+//! milliseconds and written to the KV store every `sync_polls` polls.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pulse_counter = components::pulse_counter::PulseCounterComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pulse_counter::DRIVER_NUM,
+//!     mux_alarm,
+//!     kv_store,
+//!     0x8000_0001,
+//!     &PULSE_COUNTERS,
+//!     10_000,
+//!     360,
+//! )
+//! .finalize(components::pulse_counter_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::tickv::TicKVStore<
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<nrf52840::nvmc::Nvmc>,
+//!     >,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! let _ = pulse_counter.start();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::kv_store::{KVStore, HEADER_LENGTH};
+use capsules_extra::provisioning::KEY_LEN;
+use capsules_extra::pulse_counter::{PulseCounter, RetainedCounts, VALUE_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::counter::EdgeCounter;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+use kernel::hil::time::{self, Alarm};
+use kernel::storage_permissions::StoragePermissions;
+
+/// Size of the value buffer, including the KV store header.
+pub const VALUE_BUF_LEN: usize = VALUE_LEN + HEADER_LENGTH;
+
+#[macro_export]
+macro_rules! pulse_counter_component_static {
+    ($A:ty, $K:ty, $T:ty $(,)?) => {{
+        #[cfg_attr(
+            any(target_arch = "arm", target_arch = "riscv32"),
+            link_section = ".noinit"
+        )]
+        static mut RETAINED: capsules_extra::pulse_counter::RetainedCounts =
+            capsules_extra::pulse_counter::RetainedCounts::new();
+
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let pulse_counter = kernel::static_buf!(
+            capsules_extra::pulse_counter::PulseCounter<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $K,
+                $T,
+            >
+        );
+        let key = kernel::static_buf!([u8; capsules_extra::provisioning::KEY_LEN]);
+        let value = kernel::static_buf!([u8; $crate::pulse_counter::VALUE_BUF_LEN]);
+        // Safety: `RETAINED` is only referenced here, and the buffers above
+        // make sure this code runs at most once.
+        let retained = unsafe { &mut *core::ptr::addr_of_mut!(RETAINED) };
+
+        (alarm, pulse_counter, key, value, retained)
+    };};
+}
+
+pub struct PulseCounterComponent<
+    A: 'static + time::Alarm<'static>,
+    K: 'static + KVSystem<'static, K = T>,
+    T: 'static + KeyType,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    kv_store: &'static KVStore<'static, K, T>,
+    storage_id: u32,
+    counters: &'static [&'static dyn EdgeCounter],
+    poll_ms: u32,
+    sync_polls: u32,
+}
+
+impl<
+        A: 'static + time::Alarm<'static>,
+        K: 'static + KVSystem<'static, K = T>,
+        T: 'static + KeyType,
+    > PulseCounterComponent<A, K, T>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        kv_store: &'static KVStore<'static, K, T>,
+        storage_id: u32,
+        counters: &'static [&'static dyn EdgeCounter],
+        poll_ms: u32,
+        sync_polls: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            kv_store,
+            storage_id,
+            counters,
+            poll_ms,
+            sync_polls,
+        }
+    }
+}
+
+impl<
+        A: 'static + time::Alarm<'static>,
+        K: 'static + KVSystem<'static, K = T>,
+        T: 'static + KeyType,
+    > Component for PulseCounterComponent<A, K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<PulseCounter<'static, VirtualMuxAlarm<'static, A>, K, T>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUF_LEN]>,
+        &'static mut RetainedCounts,
+    );
+    type Output = &'static PulseCounter<'static, VirtualMuxAlarm<'static, A>, K, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+        let perms = StoragePermissions::new_kernel_permissions(self.storage_id, &storage_cap);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let pulse_counter = static_buffer.1.write(PulseCounter::new(
+            self.counters,
+            alarm,
+            self.kv_store,
+            perms,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            static_buffer.4,
+            self.poll_ms,
+            self.sync_polls,
+            static_buffer.2.write([0; KEY_LEN]),
+            static_buffer.3.write([0; VALUE_BUF_LEN]),
+        ));
+        alarm.set_alarm_client(pulse_counter);
+        self.kv_store.set_client(pulse_counter);
+
+        pulse_counter
+    }
+}
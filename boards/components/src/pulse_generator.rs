@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the pulse generator syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pulse_generator = components::pulse_generator::PulseGeneratorComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pulse_generator::DRIVER_NUM,
+//!     &base_peripherals.tim3,
+//! )
+//! .finalize(components::pulse_generator_component_static!(
+//!     stm32f429zi::tim3::Tim3<'static>
+//! ));
+//! ```
+//!
+//! Generators that report cancelled pulses with a deferred call, like the
+//! nRF52 one, are registered for it with `with_deferred_call()`:
+//!
+//! ```rust
+//! let pulse_generator = components::pulse_generator::PulseGeneratorComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pulse_generator::DRIVER_NUM,
+//!     generator,
+//! )
+//! .with_deferred_call()
+//! .finalize(components::pulse_generator_component_static!(
+//!     nrf52840::pulse_generator::PulseGenerator<'static>
+//! ));
+//! ```
+
+use capsules_extra::pulse_generator::PulseGeneratorDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::pulse;
+
+#[macro_export]
+macro_rules! pulse_generator_component_static {
+    ($P:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::pulse_generator::PulseGeneratorDriver<'static, $P>)
+    };};
+}
+
+pub struct PulseGeneratorComponent<P: 'static + pulse::PulseGenerator<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    generator: &'static P,
+    /// Registers the deferred call of the generator, if it has one.
+    register_deferred_call: Option<fn(&'static P)>,
+}
+
+impl<P: 'static + pulse::PulseGenerator<'static>> PulseGeneratorComponent<P> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        generator: &'static P,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            generator,
+            register_deferred_call: None,
+        }
+    }
+}
+
+impl<P: 'static + pulse::PulseGenerator<'static> + DeferredCallClient> PulseGeneratorComponent<P> {
+    /// Register the deferred call of the generator in `finalize()`.
+    pub fn with_deferred_call(mut self) -> Self {
+        self.register_deferred_call = Some(<P as DeferredCallClient>::register);
+        self
+    }
+}
+
+impl<P: 'static + pulse::PulseGenerator<'static>> Component for PulseGeneratorComponent<P> {
+    type StaticInput = &'static mut MaybeUninit<PulseGeneratorDriver<'static, P>>;
+    type Output = &'static PulseGeneratorDriver<'static, P>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let driver = static_buffer.write(PulseGeneratorDriver::new(
+            self.generator,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.generator.set_client(driver);
+        if let Some(register) = self.register_deferred_call {
+            register(self.generator);
+        }
+
+        driver
+    }
+}
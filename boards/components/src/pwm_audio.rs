@@ -0,0 +1,94 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for audio output through a PWM pin.
+//!
+//! The output is both an `AudioSink` and a `Buzzer`, e.g. for the buzzer
+//! driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pwm_audio = components::pwm_audio::PwmAudioComponent::new(
+//!     speaker_pwm_pin,
+//!     mux_alarm,
+//!     125_000,
+//!     capsules_extra::pwm_audio::Filter::RcLowPass,
+//! )
+//! .finalize(components::pwm_audio_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_core::virtualizers::virtual_pwm::PwmPinUser<'static, nrf52840::pwm::Pwm>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::pwm_audio::{Filter, PwmAudio};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::pwm::PwmPin;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! pwm_audio_component_static {
+    ($A:ty, $P:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let pwm_audio = kernel::static_buf!(
+            capsules_extra::pwm_audio::PwmAudio<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $P,
+            >
+        );
+
+        (alarm, pwm_audio)
+    };};
+}
+
+pub struct PwmAudioComponent<A: 'static + time::Alarm<'static>, P: 'static + PwmPin> {
+    pwm_pin: &'static P,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    carrier_hz: usize,
+    filter: Filter,
+}
+
+impl<A: 'static + time::Alarm<'static>, P: 'static + PwmPin> PwmAudioComponent<A, P> {
+    pub fn new(
+        pwm_pin: &'static P,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        carrier_hz: usize,
+        filter: Filter,
+    ) -> Self {
+        Self {
+            pwm_pin,
+            alarm_mux,
+            carrier_hz,
+            filter,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, P: 'static + PwmPin> Component for PwmAudioComponent<A, P> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<PwmAudio<'static, VirtualMuxAlarm<'static, A>, P>>,
+    );
+    type Output = &'static PwmAudio<'static, VirtualMuxAlarm<'static, A>, P>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let pwm_audio = static_buffer.1.write(PwmAudio::new(
+            self.pwm_pin,
+            alarm,
+            self.carrier_hz,
+            self.filter,
+        ));
+        alarm.set_alarm_client(pwm_audio);
+
+        pwm_audio
+    }
+}
@@ -0,0 +1,69 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the pages of an external flash behind a quad-SPI
+//! controller.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let qspi_flash = components::qspi_flash::QspiFlashPagesComponent::new(
+//!     &nrf52840_peripherals.qspi,
+//!     Some(&nrf52840_peripherals.qspi),
+//! )
+//! .finalize(components::qspi_flash_pages_component_static!(
+//!     nrf52840::qspi::Qspi<'static>
+//! ));
+//! ```
+
+use capsules_extra::qspi_flash::{QspiFlashPages, QspiFlashSector};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::qspi::{QspiFlash, QspiMemoryMapped};
+
+#[macro_export]
+macro_rules! qspi_flash_pages_component_static {
+    ($Q:ty $(,)?) => {{
+        let pages = kernel::static_buf!(capsules_extra::qspi_flash::QspiFlashPages<'static, $Q>);
+        let buffer = kernel::static_buf!(capsules_extra::qspi_flash::QspiFlashSector);
+
+        (pages, buffer)
+    };};
+}
+
+pub struct QspiFlashPagesComponent<Q: 'static + QspiFlash<'static>> {
+    qspi: &'static Q,
+    memory_mapped: Option<&'static dyn QspiMemoryMapped>,
+}
+
+impl<Q: 'static + QspiFlash<'static>> QspiFlashPagesComponent<Q> {
+    pub fn new(qspi: &'static Q, memory_mapped: Option<&'static dyn QspiMemoryMapped>) -> Self {
+        Self {
+            qspi,
+            memory_mapped,
+        }
+    }
+}
+
+impl<Q: 'static + QspiFlash<'static>> Component for QspiFlashPagesComponent<Q> {
+    type StaticInput = (
+        &'static mut MaybeUninit<QspiFlashPages<'static, Q>>,
+        &'static mut MaybeUninit<QspiFlashSector>,
+    );
+    type Output = &'static QspiFlashPages<'static, Q>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.1.write(QspiFlashSector::new());
+        let qspi_flash = static_buffer.0.write(QspiFlashPages::new(
+            self.qspi,
+            self.memory_mapped,
+            &mut buffer.0,
+        ));
+        self.qspi.set_client(qspi_flash);
+        qspi_flash.register();
+
+        qspi_flash
+    }
+}
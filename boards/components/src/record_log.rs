@@ -0,0 +1,144 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for a log of records with sequence numbers and the syscall
+//! driver for it.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! // Records of up to 244 bytes in a log of 256 byte entries.
+//! let record_log = components::record_log::RecordLogComponent::new(log).finalize(
+//!     components::record_log_component_static!(
+//!         capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!         256
+//!     ),
+//! );
+//!
+//! let record_log_driver = components::record_log::RecordLogDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::record_log_driver::DRIVER_NUM,
+//!     record_log,
+//! )
+//! .finalize(components::record_log_driver_component_static!(
+//!     capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!     244
+//! ));
+//! ```
+
+use capsules_extra::record_log::RecordLog;
+use capsules_extra::record_log_driver::RecordLogDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::log::{LogRead, LogWrite};
+
+#[macro_export]
+macro_rules! record_log_component_static {
+    ($L:ty, $N:expr $(,)?) => {{
+        let scratch = kernel::static_buf!([u8; $N]);
+        let log = kernel::static_buf!(capsules_extra::record_log::RecordLog<'static, $L>);
+
+        (scratch, log)
+    };};
+}
+
+#[macro_export]
+macro_rules! record_log_driver_component_static {
+    ($L:ty, $BUF_LEN:expr $(,)?) => {{
+        let buffer = kernel::static_buf!([u8; $BUF_LEN]);
+        let driver =
+            kernel::static_buf!(capsules_extra::record_log_driver::RecordLogDriver<'static, $L>);
+
+        (buffer, driver)
+    };};
+}
+
+pub struct RecordLogComponent<
+    L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>,
+    const N: usize,
+> {
+    log: &'static L,
+}
+
+impl<L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>, const N: usize>
+    RecordLogComponent<L, N>
+{
+    pub fn new(log: &'static L) -> Self {
+        Self { log }
+    }
+}
+
+impl<L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>, const N: usize> Component
+    for RecordLogComponent<L, N>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; N]>,
+        &'static mut MaybeUninit<RecordLog<'static, L>>,
+    );
+    type Output = &'static RecordLog<'static, L>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scratch = static_buffer.0.write([0; N]);
+        let record_log = static_buffer.1.write(RecordLog::new(self.log, scratch));
+        self.log.set_read_client(record_log);
+        self.log.set_append_client(record_log);
+
+        // The log reports back from a deferred call, once the clients of the
+        // record log are set.
+        let _ = record_log.mount();
+
+        record_log
+    }
+}
+
+pub struct RecordLogDriverComponent<
+    L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>,
+    const BUF_LEN: usize,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    log: &'static RecordLog<'static, L>,
+}
+
+impl<L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>, const BUF_LEN: usize>
+    RecordLogDriverComponent<L, BUF_LEN>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        log: &'static RecordLog<'static, L>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            log,
+        }
+    }
+}
+
+impl<L: 'static + LogRead<'static, EntryID = usize> + LogWrite<'static>, const BUF_LEN: usize>
+    Component for RecordLogDriverComponent<L, BUF_LEN>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<RecordLogDriver<'static, L>>,
+    );
+    type Output = &'static RecordLogDriver<'static, L>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let buffer = static_buffer.0.write([0; BUF_LEN]);
+        let driver = static_buffer.1.write(RecordLogDriver::new(
+            self.log,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            buffer,
+        ));
+        self.log.set_client(driver);
+
+        driver
+    }
+}
@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the restart backoff fault policy.
+//!
+//! The policy keeps the fault history of up to `N` processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let fault_policy = components::restart_backoff::RestartBackoffComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     100,
+//!     10_000,
+//!     8,
+//! )
+//! .finalize(components::restart_backoff_component_static!(nrf52840::rtc::Rtc, 4));
+//! ```
+
+use capsules_core::restart_backoff::RestartBackoffPolicy;
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! restart_backoff_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let policy = kernel::static_buf!(
+            capsules_core::restart_backoff::RestartBackoffPolicy<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $crate::restart_backoff::Capability,
+                $N,
+            >
+        );
+
+        (alarm, policy)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct RestartBackoffComponent<A: 'static + Alarm<'static>, const N: usize> {
+    board_kernel: &'static kernel::Kernel,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    initial_backoff_ms: u32,
+    max_backoff_ms: u32,
+    max_faults: u32,
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> RestartBackoffComponent<A, N> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        initial_backoff_ms: u32,
+        max_backoff_ms: u32,
+        max_faults: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            alarm_mux,
+            initial_backoff_ms,
+            max_backoff_ms,
+            max_faults,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, const N: usize> Component for RestartBackoffComponent<A, N> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<
+            RestartBackoffPolicy<'static, VirtualMuxAlarm<'static, A>, Capability, N>,
+        >,
+    );
+    type Output =
+        &'static RestartBackoffPolicy<'static, VirtualMuxAlarm<'static, A>, Capability, N>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let policy = static_buffer.1.write(RestartBackoffPolicy::new(
+            self.board_kernel,
+            alarm,
+            self.initial_backoff_ms,
+            self.max_backoff_ms,
+            self.max_faults,
+            Capability,
+        ));
+        alarm.set_alarm_client(policy);
+
+        policy
+    }
+}
@@ -17,7 +17,7 @@
 
 use core::mem::MaybeUninit;
 use kernel::component::Component;
-use kernel::process::Process;
+use kernel::process::ProcessSlot;
 use kernel::scheduler::cooperative::{CoopProcessNode, CooperativeSched};
 
 #[macro_export]
@@ -34,12 +34,12 @@ macro_rules! cooperative_component_static {
 }
 
 pub struct CooperativeComponent<const NUM_PROCS: usize> {
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
 }
 
 impl<const NUM_PROCS: usize> CooperativeComponent<NUM_PROCS> {
     pub fn new(
-        processes: &'static [Option<&'static dyn Process>],
+        processes: &'static [ProcessSlot],
     ) -> CooperativeComponent<NUM_PROCS> {
         CooperativeComponent { processes }
     }
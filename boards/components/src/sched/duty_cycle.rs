@@ -0,0 +1,105 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a duty cycling scheduler that enforces per-process CPU time
+//! budgets.
+//!
+//! This provides one Component, DutyCycleComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! // Every process may run for 50 ms per second.
+//! let scheduler =
+//!     components::sched::duty_cycle::DutyCycleComponent::new(mux_alarm, &PROCESSES, 1_000_000, 50_000)
+//!         .finalize(components::duty_cycle_component_static!(
+//!             nrf52840::rtc::Rtc<'static>,
+//!             NUM_PROCS
+//!         ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::ProcessSlot;
+use kernel::scheduler::duty_cycle::{DutyCycleProcessNode, DutyCycleSched};
+
+#[macro_export]
+macro_rules! duty_cycle_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let duty_cycle_sched = kernel::static_buf!(
+            kernel::scheduler::duty_cycle::DutyCycleSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let duty_cycle_node = kernel::static_buf!(
+            [core::mem::MaybeUninit<kernel::scheduler::duty_cycle::DutyCycleProcessNode<'static>>;
+                $N]
+        );
+
+        (alarm, duty_cycle_sched, duty_cycle_node)
+    };};
+}
+
+pub struct DutyCycleComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static [ProcessSlot],
+    period_us: u32,
+    budget_us: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> DutyCycleComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static [ProcessSlot],
+        period_us: u32,
+        budget_us: u32,
+    ) -> DutyCycleComponent<A, NUM_PROCS> {
+        DutyCycleComponent {
+            alarm_mux,
+            processes,
+            period_us,
+            budget_us,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for DutyCycleComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<DutyCycleSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[MaybeUninit<DutyCycleProcessNode<'static>>; NUM_PROCS]>,
+    );
+    type Output = &'static DutyCycleSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler = static_buffer.1.write(DutyCycleSched::new(
+            scheduler_alarm,
+            self.period_us,
+            self.budget_us,
+        ));
+
+        const UNINIT: MaybeUninit<DutyCycleProcessNode<'static>> = MaybeUninit::uninit();
+        let nodes = static_buffer.2.write([UNINIT; NUM_PROCS]);
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(DutyCycleProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_tail(init_node);
+        }
+        let scheduler: &'static DutyCycleSched<'static, VirtualMuxAlarm<'static, A>> = scheduler;
+        scheduler_alarm.set_alarm_client(scheduler);
+        scheduler
+    }
+}
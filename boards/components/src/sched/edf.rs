@@ -0,0 +1,98 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for an earliest deadline first scheduler.
+//!
+//! This provides one Component, EDFComponent.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! // A process can reserve at most 20% of the CPU.
+//! let scheduler = components::sched::edf::EDFComponent::new(mux_alarm, &PROCESSES, 200_000)
+//!     .finalize(components::edf_component_static!(
+//!         nrf52840::rtc::Rtc<'static>,
+//!         NUM_PROCS
+//!     ));
+//! ```
+
+use core::mem::MaybeUninit;
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+use kernel::process::ProcessSlot;
+use kernel::scheduler::edf::{EdfProcessNode, EdfSched};
+
+#[macro_export]
+macro_rules! edf_component_static {
+    ($A:ty, $N:expr $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let edf_sched = kernel::static_buf!(
+            kernel::scheduler::edf::EdfSched<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let edf_node = kernel::static_buf!(
+            [core::mem::MaybeUninit<kernel::scheduler::edf::EdfProcessNode<'static>>; $N]
+        );
+
+        (alarm, edf_sched, edf_node)
+    };};
+}
+
+pub struct EDFComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    processes: &'static [ProcessSlot],
+    max_process_utilization_ppm: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> EDFComponent<A, NUM_PROCS> {
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        processes: &'static [ProcessSlot],
+        max_process_utilization_ppm: u32,
+    ) -> EDFComponent<A, NUM_PROCS> {
+        EDFComponent {
+            alarm_mux,
+            processes,
+            max_process_utilization_ppm,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> Component
+    for EDFComponent<A, NUM_PROCS>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<EdfSched<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[MaybeUninit<EdfProcessNode<'static>>; NUM_PROCS]>,
+    );
+    type Output = &'static EdfSched<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let scheduler_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        scheduler_alarm.setup();
+
+        let scheduler = static_buffer.1.write(EdfSched::new(
+            scheduler_alarm,
+            self.max_process_utilization_ppm,
+        ));
+
+        const UNINIT: MaybeUninit<EdfProcessNode<'static>> = MaybeUninit::uninit();
+        let nodes = static_buffer.2.write([UNINIT; NUM_PROCS]);
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let init_node = node.write(EdfProcessNode::new(&self.processes[i]));
+            scheduler.processes.push_tail(init_node);
+        }
+        let scheduler: &'static EdfSched<'static, VirtualMuxAlarm<'static, A>> = scheduler;
+        scheduler_alarm.set_alarm_client(scheduler);
+        scheduler
+    }
+}
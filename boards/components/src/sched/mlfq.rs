@@ -14,7 +14,7 @@ use core::mem::MaybeUninit;
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use kernel::component::Component;
 use kernel::hil::time;
-use kernel::process::Process;
+use kernel::process::ProcessSlot;
 use kernel::scheduler::mlfq::{MLFQProcessNode, MLFQSched};
 
 #[macro_export]
@@ -39,13 +39,13 @@ macro_rules! mlfq_component_static {
 
 pub struct MLFQComponent<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> {
     alarm_mux: &'static MuxAlarm<'static, A>,
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
 }
 
 impl<A: 'static + time::Alarm<'static>, const NUM_PROCS: usize> MLFQComponent<A, NUM_PROCS> {
     pub fn new(
         alarm_mux: &'static MuxAlarm<'static, A>,
-        processes: &'static [Option<&'static dyn Process>],
+        processes: &'static [ProcessSlot],
     ) -> MLFQComponent<A, NUM_PROCS> {
         MLFQComponent {
             alarm_mux,
@@ -3,6 +3,8 @@
 // Copyright Tock Contributors 2022.
 
 pub mod cooperative;
+pub mod duty_cycle;
+pub mod edf;
 pub mod mlfq;
 pub mod priority;
 pub mod round_robin;
@@ -18,7 +18,7 @@
 
 use core::mem::MaybeUninit;
 use kernel::component::Component;
-use kernel::process::Process;
+use kernel::process::ProcessSlot;
 use kernel::scheduler::round_robin::{RoundRobinProcessNode, RoundRobinSched};
 
 #[macro_export]
@@ -36,12 +36,12 @@ macro_rules! round_robin_component_static {
 }
 
 pub struct RoundRobinComponent<const NUM_PROCS: usize> {
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
 }
 
 impl<const NUM_PROCS: usize> RoundRobinComponent<NUM_PROCS> {
     pub fn new(
-        processes: &'static [Option<&'static dyn Process>],
+        processes: &'static [ProcessSlot],
     ) -> RoundRobinComponent<NUM_PROCS> {
         RoundRobinComponent { processes }
     }
@@ -0,0 +1,90 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the scheduler control syscall driver, which lets a
+//! management process change the scheduling parameters of processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let scheduler_control = components::scheduler_control::SchedulerControlComponent::new(
+//!     board_kernel,
+//!     capsules_core::scheduler_control::DRIVER_NUM,
+//!     scheduler,
+//!     &[ShortID::Fixed(MANAGER_SHORT_ID)],
+//! )
+//! .finalize(components::scheduler_control_component_static!(
+//!     nrf52840::chip::NRF52<'static, Nrf52840DefaultPeripherals<'static>>,
+//!     kernel::scheduler::round_robin::RoundRobinSched<'static>,
+//! ));
+//! ```
+
+use capsules_core::scheduler_control::SchedulerControl;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::platform::chip::Chip;
+use kernel::process::ShortID;
+use kernel::scheduler::Scheduler;
+
+#[macro_export]
+macro_rules! scheduler_control_component_static {
+    ($C:ty, $S:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_core::scheduler_control::SchedulerControl<
+                'static,
+                $C,
+                $S,
+                $crate::scheduler_control::Capability,
+            >
+        )
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct SchedulerControlComponent<C: 'static + Chip, S: 'static + Scheduler<C>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    scheduler: &'static S,
+    privileged: &'static [ShortID],
+    _chip: PhantomData<C>,
+}
+
+impl<C: 'static + Chip, S: 'static + Scheduler<C>> SchedulerControlComponent<C, S> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        scheduler: &'static S,
+        privileged: &'static [ShortID],
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            scheduler,
+            privileged,
+            _chip: PhantomData,
+        }
+    }
+}
+
+impl<C: 'static + Chip, S: 'static + Scheduler<C>> Component for SchedulerControlComponent<C, S> {
+    type StaticInput = &'static mut MaybeUninit<SchedulerControl<'static, C, S, Capability>>;
+    type Output = &'static SchedulerControl<'static, C, S, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        static_buffer.write(SchedulerControl::new(
+            self.board_kernel,
+            self.scheduler,
+            self.privileged,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            Capability,
+        ))
+    }
+}
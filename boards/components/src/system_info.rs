@@ -0,0 +1,110 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the system information syscall driver.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let system_info = components::system_info::SystemInfoComponent::new(
+//!     board_kernel,
+//!     capsules_extra::system_info::DRIVER_NUM,
+//!     mux_alarm,
+//!     "nrf52840dk",
+//!     Some(&nrf52840::ficr::FICR_INSTANCE),
+//!     Some(&base_peripherals.pwr_clk),
+//! )
+//! .finalize(components::system_info_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::system_info::SystemInfo;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::device_id::DeviceId;
+use kernel::hil::reset::ResetReasonSource;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! system_info_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let system_info = kernel::static_buf!(
+            capsules_extra::system_info::SystemInfo<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $crate::system_info::Capability,
+            >
+        );
+
+        (alarm, system_info)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct SystemInfoComponent<A: 'static + time::Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    board_name: &'static str,
+    device_id: Option<&'static dyn DeviceId>,
+    reset_reason: Option<&'static dyn ResetReasonSource>,
+}
+
+impl<A: 'static + time::Alarm<'static>> SystemInfoComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        board_name: &'static str,
+        device_id: Option<&'static dyn DeviceId>,
+        reset_reason: Option<&'static dyn ResetReasonSource>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            board_name,
+            device_id,
+            reset_reason,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> Component for SystemInfoComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<SystemInfo<'static, VirtualMuxAlarm<'static, A>, Capability>>,
+    );
+    type Output = &'static SystemInfo<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let system_info = static_buffer.1.write(SystemInfo::new(
+            self.board_kernel,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            alarm,
+            self.board_name,
+            self.device_id,
+            self.reset_reason,
+            Capability,
+        ));
+        alarm.set_alarm_client(system_info);
+        system_info.start();
+
+        system_info
+    }
+}
@@ -0,0 +1,166 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Components for TCP over a network layer, such as an IPv4 interface, and
+//! for its userspace driver.
+//!
+//! The stack has `TCP_SOCKETS` sockets, with send and receive buffers of
+//! `TCP_BUFFER_LEN` bytes. The driver is given a range of them; the others
+//! are left to kernel users.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let tcp = components::tcp::TcpComponent::new(ipv4, mux_alarm)
+//!     .finalize(components::tcp_component_static!(nrf52840::rtc::Rtc));
+//! let tcp_driver = components::tcp::TcpDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::tcp::driver::DRIVER_NUM,
+//!     tcp,
+//!     0..capsules_extra::net::tcp::TCP_SOCKETS,
+//! )
+//! .finalize(components::tcp_driver_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::net::tcp::stack::SEGMENT_BUF_LEN;
+use capsules_extra::net::tcp::{
+    TcpDriver, TcpNetwork, TcpSocket, TcpStack, TCP_BUFFER_LEN, TCP_SOCKETS,
+};
+use core::mem::MaybeUninit;
+use core::ops::Range;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let tx_buffers = kernel::static_buf!(
+            [[u8; capsules_extra::net::tcp::TCP_BUFFER_LEN]; capsules_extra::net::tcp::TCP_SOCKETS]
+        );
+        let rx_buffers = kernel::static_buf!(
+            [[u8; capsules_extra::net::tcp::TCP_BUFFER_LEN]; capsules_extra::net::tcp::TCP_SOCKETS]
+        );
+        let sockets = kernel::static_buf!(
+            [capsules_extra::net::tcp::TcpSocket<'static>; capsules_extra::net::tcp::TCP_SOCKETS]
+        );
+        let segment = kernel::static_buf!([u8; capsules_extra::net::tcp::stack::SEGMENT_BUF_LEN]);
+        let stack = kernel::static_buf!(
+            capsules_extra::net::tcp::TcpStack<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, tx_buffers, rx_buffers, sockets, segment, stack)
+    };};
+}
+
+pub struct TcpComponent<N: TcpNetwork<'static> + 'static, A: Alarm<'static> + 'static> {
+    network: &'static N,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<N: TcpNetwork<'static>, A: Alarm<'static>> TcpComponent<N, A> {
+    pub fn new(network: &'static N, alarm_mux: &'static MuxAlarm<'static, A>) -> Self {
+        Self { network, alarm_mux }
+    }
+}
+
+impl<N: TcpNetwork<'static>, A: Alarm<'static>> Component for TcpComponent<N, A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<[[u8; TCP_BUFFER_LEN]; TCP_SOCKETS]>,
+        &'static mut MaybeUninit<[[u8; TCP_BUFFER_LEN]; TCP_SOCKETS]>,
+        &'static mut MaybeUninit<[TcpSocket<'static>; TCP_SOCKETS]>,
+        &'static mut MaybeUninit<[u8; SEGMENT_BUF_LEN]>,
+        &'static mut MaybeUninit<TcpStack<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static TcpStack<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let tx_buffers = s.1.write([[0; TCP_BUFFER_LEN]; TCP_SOCKETS]);
+        let rx_buffers = s.2.write([[0; TCP_BUFFER_LEN]; TCP_SOCKETS]);
+        let mut buffers = tx_buffers.iter_mut().zip(rx_buffers.iter_mut());
+        let sockets = s.3.write(core::array::from_fn(|_| match buffers.next() {
+            Some((tx, rx)) => TcpSocket::new(tx, rx),
+            None => unreachable!(),
+        }));
+
+        let segment = s.4.write([0; SEGMENT_BUF_LEN]);
+        let stack =
+            s.5.write(TcpStack::new(self.network, alarm, sockets, segment));
+        self.network.set_client(stack);
+        alarm.set_alarm_client(stack);
+
+        stack
+    }
+}
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! tcp_driver_component_static {
+    ($A:ty $(,)?) => {{
+        kernel::static_buf!(
+            capsules_extra::net::tcp::TcpDriver<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        )
+    };};
+}
+
+pub struct TcpDriverComponent<A: Alarm<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    stack: &'static TcpStack<'static, VirtualMuxAlarm<'static, A>>,
+    sockets: Range<usize>,
+}
+
+impl<A: Alarm<'static>> TcpDriverComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        stack: &'static TcpStack<'static, VirtualMuxAlarm<'static, A>>,
+        sockets: Range<usize>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            stack,
+            sockets,
+        }
+    }
+}
+
+impl<A: Alarm<'static>> Component for TcpDriverComponent<A> {
+    type StaticInput = &'static mut MaybeUninit<TcpDriver<'static, VirtualMuxAlarm<'static, A>>>;
+    type Output = &'static TcpDriver<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let driver = s.write(TcpDriver::new(
+            self.stack,
+            self.sockets.clone(),
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        for id in self.sockets {
+            if self.stack.set_client(id, driver).is_err() {
+                panic!("No TCP socket {}", id);
+            }
+        }
+
+        driver
+    }
+}
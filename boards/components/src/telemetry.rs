@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the telemetry record mapped into processes.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let telemetry = components::telemetry::TelemetryComponent::new(
+//!     board_kernel,
+//!     capsules_extra::telemetry::DRIVER_NUM,
+//!     mux_alarm,
+//!     1000, // Update the uptime every second.
+//! )
+//! .finalize(components::telemetry_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::telemetry::{Telemetry, TelemetryRecord};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! telemetry_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let record = kernel::static_buf!(capsules_extra::telemetry::TelemetryRecord);
+        let telemetry = kernel::static_buf!(
+            capsules_extra::telemetry::Telemetry<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $crate::telemetry::Capability,
+            >
+        );
+
+        (alarm, record, telemetry)
+    };};
+}
+
+pub struct Capability;
+unsafe impl capabilities::ProcessManagementCapability for Capability {}
+
+pub struct TelemetryComponent<A: 'static + time::Alarm<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    period_ms: u32,
+}
+
+impl<A: 'static + time::Alarm<'static>> TelemetryComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        period_ms: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            period_ms,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> Component for TelemetryComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<TelemetryRecord>,
+        &'static mut MaybeUninit<Telemetry<'static, VirtualMuxAlarm<'static, A>, Capability>>,
+    );
+    type Output = &'static Telemetry<'static, VirtualMuxAlarm<'static, A>, Capability>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let record = static_buffer.1.write(TelemetryRecord::new());
+
+        let telemetry = static_buffer.2.write(Telemetry::new(
+            self.board_kernel,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            alarm,
+            record,
+            self.period_ms,
+            Capability,
+        ));
+        alarm.set_alarm_client(telemetry);
+        telemetry.start();
+
+        telemetry
+    }
+}
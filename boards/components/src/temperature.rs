@@ -21,7 +21,10 @@ use kernel::hil;
 #[macro_export]
 macro_rules! temperature_component_static {
     () => {{
-        kernel::static_buf!(capsules_extra::temperature::TemperatureSensor<'static>)
+        let sensor = kernel::static_buf!(capsules_extra::temperature::TemperatureSensor<'static>);
+        let drivers =
+            kernel::static_buf!([&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1]);
+        (sensor, drivers)
     };};
 }
 
@@ -46,14 +49,18 @@ impl<T: 'static + hil::sensors::TemperatureDriver<'static>> TemperatureComponent
 }
 
 impl<T: 'static + hil::sensors::TemperatureDriver<'static>> Component for TemperatureComponent<T> {
-    type StaticInput = &'static mut MaybeUninit<TemperatureSensor<'static>>;
+    type StaticInput = (
+        &'static mut MaybeUninit<TemperatureSensor<'static>>,
+        &'static mut MaybeUninit<[&'static dyn hil::sensors::TemperatureDriver<'static>; 1]>,
+    );
     type Output = &'static TemperatureSensor<'static>;
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
         let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 
-        let temp = s.write(TemperatureSensor::new(
-            self.temp_sensor,
+        let drivers = s.1.write([self.temp_sensor]);
+        let temp = s.0.write(TemperatureSensor::new(
+            drivers,
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
         ));
 
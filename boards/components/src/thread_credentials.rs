@@ -0,0 +1,89 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the Thread network parameters stored in the KV store.
+//!
+//! `storage_id` is the ID the parameters are stored with in the KV store. To
+//! set them with the provisioning capsule, it must use the same ID.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let thread_credentials = components::thread_credentials::ThreadCredentialsComponent::new(
+//!     kv_store,
+//!     0x8000_0000,
+//! )
+//! .finalize(components::thread_credentials_component_static!(
+//!     capsules_extra::tickv::TicKVStore<
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<nrf52840::nvmc::Nvmc>,
+//!     >,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! ```
+
+use capsules_extra::kv_store::{KVStore, HEADER_LENGTH};
+use capsules_extra::net::thread::credentials::{ThreadCredentials, PARAMS_LEN};
+use capsules_extra::provisioning::KEY_LEN;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::kv_system::{KVSystem, KeyType};
+use kernel::storage_permissions::StoragePermissions;
+
+/// Size of the value buffer, including the KV store header.
+pub const VALUE_BUF_LEN: usize = PARAMS_LEN + HEADER_LENGTH;
+
+#[macro_export]
+macro_rules! thread_credentials_component_static {
+    ($K:ty, $T:ty $(,)?) => {{
+        let credentials = kernel::static_buf!(
+            capsules_extra::net::thread::credentials::ThreadCredentials<'static, $K, $T>
+        );
+        let key = kernel::static_buf!([u8; capsules_extra::provisioning::KEY_LEN]);
+        let value = kernel::static_buf!([u8; $crate::thread_credentials::VALUE_BUF_LEN]);
+
+        (credentials, key, value)
+    };};
+}
+
+pub struct ThreadCredentialsComponent<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> {
+    kv_store: &'static KVStore<'static, K, T>,
+    storage_id: u32,
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> ThreadCredentialsComponent<K, T> {
+    pub fn new(kv_store: &'static KVStore<'static, K, T>, storage_id: u32) -> Self {
+        Self {
+            kv_store,
+            storage_id,
+        }
+    }
+}
+
+impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType> Component
+    for ThreadCredentialsComponent<K, T>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<ThreadCredentials<'static, K, T>>,
+        &'static mut MaybeUninit<[u8; KEY_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUF_LEN]>,
+    );
+    type Output = &'static ThreadCredentials<'static, K, T>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let storage_cap = create_capability!(capabilities::KerneluserStorageCapability);
+        let perms = StoragePermissions::new_kernel_permissions(self.storage_id, &storage_cap);
+
+        let credentials = static_buffer.0.write(ThreadCredentials::new(
+            self.kv_store,
+            perms,
+            static_buffer.1.write([0; KEY_LEN]),
+            static_buffer.2.write([0; VALUE_BUF_LEN]),
+        ));
+        self.kv_store.set_client(credentials);
+
+        credentials
+    }
+}
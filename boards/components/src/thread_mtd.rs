@@ -0,0 +1,249 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a Minimal Thread Device that attaches to a Thread network
+//! with MLE.
+//!
+//! The device is bound to the MLE port on the UDP stack, and sets its short
+//! address on the 802.15.4 MAC through a user of `mux_mac`. It secures MLE
+//! messages on the AES-128-CCM engine `ccm`, which must not be the one of
+//! the framer, and derives its keys on the SHA-256 engine `sha`. Attaching
+//! starts when the Thread credentials load the network parameters.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let thread = components::thread_mtd::ThreadMtdComponent::new(
+//!     mux_mac,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     ccm,
+//!     sha,
+//!     rng,
+//! )
+//! .finalize(components::thread_mtd_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<
+//!         'static,
+//!         nrf52840::aes::AesECB<'static>,
+//!     >,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     nrf52840::trng::Trng<'static>,
+//! ));
+//! thread_credentials.set_client(thread);
+//! let _ = thread_credentials.load();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::thread::mle::{MLE_BUFFER_LEN, MLE_PORT};
+use capsules_extra::net::thread::mtd::{ThreadMtd, HASH_BUFFER_LEN};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_recv::{MuxUdpReceiver, UDPReceiver};
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use core::mem::MaybeUninit;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::digest::{Digest, Sha256};
+use kernel::hil::rng::Rng;
+use kernel::hil::symmetric_encryption::AES128CCM;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! thread_mtd_component_static {
+    ($A:ty, $C:ty, $D:ty, $R:ty $(,)?) => {{
+        use capsules_extra::net::thread::mle::MLE_BUFFER_LEN;
+        use capsules_extra::net::thread::mtd::HASH_BUFFER_LEN;
+
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_recv =
+            kernel::static_buf!(capsules_extra::net::udp::udp_recv::UDPReceiver<'static>);
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static>);
+        let tx_buffer = kernel::static_buf!([u8; MLE_BUFFER_LEN]);
+        let rx_buffer = kernel::static_buf!([u8; MLE_BUFFER_LEN]);
+        let hash_buffer = kernel::static_buf!([u8; HASH_BUFFER_LEN]);
+        let digest_buffer = kernel::static_buf!([u8; 32]);
+        let mtd = kernel::static_buf!(
+            capsules_extra::net::thread::mtd::ThreadMtd<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $C,
+                $D,
+                $R,
+            >
+        );
+
+        (
+            udp_send,
+            udp_recv,
+            udp_vis_cap,
+            net_cap,
+            alarm,
+            mac_user,
+            tx_buffer,
+            rx_buffer,
+            hash_buffer,
+            digest_buffer,
+            mtd,
+        )
+    };};
+}
+
+pub struct ThreadMtdComponent<
+    A: Alarm<'static> + 'static,
+    C: AES128CCM<'static> + 'static,
+    D: Digest<'static, 32> + Sha256 + 'static,
+    R: Rng<'static> + 'static,
+> {
+    mux_mac: &'static MuxMac<'static>,
+    udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    udp_recv_mux: &'static MuxUdpReceiver<'static>,
+    port_table: &'static UdpPortManager,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    ccm: &'static C,
+    sha: &'static D,
+    rng: &'static R,
+}
+
+impl<
+        A: Alarm<'static>,
+        C: AES128CCM<'static>,
+        D: Digest<'static, 32> + Sha256,
+        R: Rng<'static>,
+    > ThreadMtdComponent<A, C, D, R>
+{
+    pub fn new(
+        mux_mac: &'static MuxMac<'static>,
+        udp_send_mux: &'static MuxUdpSender<
+            'static,
+            IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>,
+        >,
+        udp_recv_mux: &'static MuxUdpReceiver<'static>,
+        port_table: &'static UdpPortManager,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        ccm: &'static C,
+        sha: &'static D,
+        rng: &'static R,
+    ) -> Self {
+        Self {
+            mux_mac,
+            udp_send_mux,
+            udp_recv_mux,
+            port_table,
+            alarm_mux,
+            ccm,
+            sha,
+            rng,
+        }
+    }
+}
+
+impl<
+        A: Alarm<'static>,
+        C: AES128CCM<'static>,
+        D: Digest<'static, 32> + Sha256,
+        R: Rng<'static>,
+    > Component for ThreadMtdComponent<A, C, D, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UDPReceiver<'static>>,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<MacUser<'static>>,
+        &'static mut MaybeUninit<[u8; MLE_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; MLE_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; HASH_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; 32]>,
+        &'static mut MaybeUninit<ThreadMtd<'static, VirtualMuxAlarm<'static, A>, C, D, R>>,
+    );
+    type Output = &'static ThreadMtd<'static, VirtualMuxAlarm<'static, A>, C, D, R>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+        let udp_vis = s.2.write(UdpVisibilityCapability::new(&create_cap));
+        let net_cap = s.3.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+
+        let udp_send = s.0.write(UDPSendStruct::new(self.udp_send_mux, udp_vis));
+        let udp_recv = s.1.write(UDPReceiver::new());
+        self.udp_recv_mux.add_client(udp_recv);
+
+        let socket = match self.port_table.create_socket() {
+            Ok(socket) => socket,
+            Err(_) => panic!("No UDP socket for MLE"),
+        };
+        match self.port_table.bind(socket, MLE_PORT, net_cap) {
+            Ok((send_bind, recv_bind)) => {
+                udp_send.set_binding(send_bind);
+                udp_recv.set_binding(recv_bind);
+            }
+            Err(_) => panic!("MLE port bound already"),
+        }
+
+        let alarm = s.4.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let mac_user = s.5.write(MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(mac_user);
+
+        let tx_buffer = s.6.write([0; MLE_BUFFER_LEN]);
+        let rx_buffer = s.7.write([0; MLE_BUFFER_LEN]);
+        let hash_buffer = s.8.write([0; HASH_BUFFER_LEN]);
+        let digest_buffer = s.9.write([0; 32]);
+        let mtd = s.10.write(ThreadMtd::new(
+            mac_user,
+            udp_send,
+            net_cap,
+            alarm,
+            self.ccm,
+            self.sha,
+            self.rng,
+            tx_buffer,
+            rx_buffer,
+            hash_buffer,
+            digest_buffer,
+        ));
+        udp_send.set_client(mtd);
+        udp_recv.set_client(mtd);
+        alarm.set_alarm_client(mtd);
+        self.ccm.set_client(mtd);
+        self.sha.set_client(mtd);
+        self.rng.set_client(mtd);
+
+        mtd
+    }
+}
@@ -52,6 +52,7 @@ use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
 use kernel::hil;
 use kernel::hil::flash::HasClient;
 use kernel::hil::hasher::Hasher;
@@ -97,6 +98,7 @@ pub struct TicKVComponent<
     flash_size: usize,
     tickfs_read_buf: &'static mut [u8; PAGE_SIZE],
     flash_read_buffer: &'static mut F::Page,
+    compaction: bool,
 }
 
 impl<
@@ -120,8 +122,17 @@ impl<
             flash_size,
             tickfs_read_buf,
             flash_read_buffer,
+            compaction: false,
         }
     }
+
+    /// Compact the store after garbage collections. The flash page after
+    /// the `flash_size` bytes of the store is used as the spare region of
+    /// the compaction, and must not be used for anything else.
+    pub fn with_compaction(mut self) -> Self {
+        self.compaction = true;
+        self
+    }
 }
 
 impl<
@@ -150,6 +161,10 @@ impl<
             self.flash_size,
         ));
         virtual_flash.set_client(driver);
+        driver.register();
+        if self.compaction {
+            driver.enable_compaction();
+        }
         driver.initialise();
         driver
     }
@@ -167,6 +182,7 @@ pub struct TicKVDedicatedFlashComponent<
     region_offset: usize,
     flash_size: usize,
     flash_read_buffer: &'static mut F::Page,
+    compaction: bool,
 }
 
 impl<
@@ -190,8 +206,17 @@ impl<
             region_offset,
             flash_size,
             flash_read_buffer,
+            compaction: false,
         }
     }
+
+    /// Compact the store after garbage collections. The flash page after
+    /// the `flash_size` bytes of the store is used as the spare region of
+    /// the compaction, and must not be used for anything else.
+    pub fn with_compaction(mut self) -> Self {
+        self.compaction = true;
+        self
+    }
 }
 
 impl<
@@ -223,6 +248,10 @@ impl<
         ));
         self.flash.set_client(tickv);
         self.hasher.set_client(tickv);
+        tickv.register();
+        if self.compaction {
+            tickv.enable_compaction();
+        }
         tickv.initialise();
         tickv
     }
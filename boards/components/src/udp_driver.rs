@@ -15,7 +15,7 @@
 //!        udp_send_mux,
 //!        udp_recv_mux,
 //!        udp_port_table,
-//!        local_ip_ifaces,
+//!        ipv6_nd.addresses(),
 //!        PAYLOAD_LEN,
 //!     )
 //!     .finalize(components::udp_driver_component_static!());
@@ -23,7 +23,7 @@
 
 use capsules_core;
 use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
-use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_interface::InterfaceAddresses;
 use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules_extra::net::network_capabilities::{
     AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
@@ -77,7 +77,7 @@ pub struct UDPDriverComponent<A: Alarm<'static> + 'static> {
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
     udp_recv_mux: &'static MuxUdpReceiver<'static>,
     port_table: &'static UdpPortManager,
-    interface_list: &'static [IPAddr],
+    interface_list: &'static InterfaceAddresses,
 }
 
 impl<A: Alarm<'static>> UDPDriverComponent<A> {
@@ -90,7 +90,7 @@ impl<A: Alarm<'static>> UDPDriverComponent<A> {
         >,
         udp_recv_mux: &'static MuxUdpReceiver<'static>,
         port_table: &'static UdpPortManager,
-        interface_list: &'static [IPAddr],
+        interface_list: &'static InterfaceAddresses,
     ) -> Self {
         Self {
             board_kernel,
@@ -8,10 +8,16 @@
 //! exposes a MuxUdpSender that other components can implement
 //! UDPSenders on top of to use the UDP/6Lowpan stack.
 //!
+//! It also sets up IPv6 neighbor discovery on the interface, which configures
+//! addresses in addition to `local_ip_ifaces` once started, and the 6LoWPAN
+//! contexts routers advertise. Its addresses are the interface addresses of
+//! the UDP driver. RPL routes the packets of the interface and forwards the
+//! packets of other nodes once started, as a node or as the root of a DODAG.
+//!
 //! Usage
 //! -----
 //! ```rust
-//!    let (udp_mux, udp_recv) = UDPMuxComponent::new(
+//!    let (udp_mux, udp_recv, udp_port_table, ipv6_nd, rpl) = UDPMuxComponent::new(
 //!        mux_mac,
 //!        DEFAULT_CTX_PREFIX_LEN,
 //!        DEFAULT_CTX_PREFIX,
@@ -21,7 +27,9 @@
 //!        mux_alarm,
 //!        MAX_PAYLOAD_LEN,
 //!    )
-//!    .finalize(components::udp_mux_component_static!());
+//!    .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+//!    ipv6_nd.start().unwrap();
+//!    rpl.start().unwrap();
 //! ```
 
 // Author: Hudson Ayers <hayers@stanford.edu>
@@ -30,14 +38,20 @@
 use capsules_core;
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
 use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
 use capsules_extra::net::ieee802154::MacAddress;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_interface::InterfaceAddresses;
+use capsules_extra::net::ipv6::ipv6_nd::{NeighborDiscovery, ND_PAYLOAD_LEN};
 use capsules_extra::net::ipv6::ipv6_recv::IP6Receiver;
 use capsules_extra::net::ipv6::ipv6_recv::IP6RecvStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
+use capsules_extra::net::ipv6::rpl::{Rpl, RPL_PAYLOAD_LEN};
 use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
-use capsules_extra::net::network_capabilities::{IpVisibilityCapability, UdpVisibilityCapability};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
 use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
 use capsules_extra::net::udp::udp_port_table::{
     SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS,
@@ -56,14 +70,21 @@ use kernel::hil::time::Alarm;
 // The UDP stack requires several packet buffers:
 //
 //   1. RADIO_BUF: buffer the IP6_Sender uses to pass frames to the radio after fragmentation
-//   2. SIXLOWPAN_RX_BUF: Buffer to hold full IP packets after they are decompressed by 6LoWPAN
+//   2. SIXLOWPAN_RX_BUF: Buffers to hold full IP packets after they are decompressed by 6LoWPAN,
+//      one per datagram reassembled at the same time
 //   3. UDP_DGRAM: The payload of the IP6_Packet, which holds full IP Packets before they are tx'd.
 //
+//   Neighbor discovery sends through its own IP6_Sender, with its own RADIO_BUF and IP6_Packet.
+//   So does RPL, whose IP6_Packet holds the packets it forwards.
+//
 //   Additionally, every capsule using the stack needs an additional buffer to craft packets for
 //   tx which can then be passed to the MuxUdpSender for tx.
 
 pub const MAX_PAYLOAD_LEN: usize = 200; //The max size UDP message that can be sent by userspace apps or capsules
 
+// Number of fragmented datagrams 6LoWPAN reassembles at the same time
+pub const SIXLOWPAN_RX_STATES: usize = 2;
+
 // Setup static space for the objects.
 #[macro_export]
 macro_rules! udp_mux_component_static {
@@ -72,7 +93,7 @@ macro_rules! udp_mux_component_static {
         use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
         use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
         use capsules_extra::net::udp::udp_send::MuxUdpSender;
-        use components::udp_mux::MAX_PAYLOAD_LEN;
+        use components::udp_mux::{MAX_PAYLOAD_LEN, SIXLOWPAN_RX_STATES};
         use core::mem::MaybeUninit;
 
         let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
@@ -82,10 +103,11 @@ macro_rules! udp_mux_component_static {
             sixlowpan_state::Sixlowpan<
                 'static,
                 VirtualMuxAlarm<'static, $A>,
-                sixlowpan_compression::Context,
+                sixlowpan_compression::ContextTable,
             >
         );
-        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let rx_state =
+            kernel::static_buf!([sixlowpan_state::RxState<'static>; SIXLOWPAN_RX_STATES]);
         let ip6_send = kernel::static_buf!(
             capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
@@ -127,7 +149,8 @@ macro_rules! udp_mux_component_static {
         );
 
         let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
-        let sixlowpan_rx = kernel::static_buf!([u8; 1280]);
+        let sixlowpan_rx =
+            kernel::static_buf!([[u8; sixlowpan_state::MAX_DGRAM_SIZE]; SIXLOWPAN_RX_STATES]);
         let udp_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
 
         let udp_vis_cap =
@@ -135,6 +158,51 @@ macro_rules! udp_mux_component_static {
         let ip_vis_cap =
             kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
 
+        let nd_send_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let nd_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let nd_mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static>);
+        let nd_ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let nd_ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let nd_dgram =
+            kernel::static_buf!([u8; capsules_extra::net::ipv6::ipv6_nd::ND_PAYLOAD_LEN]);
+        let nd_radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let nd_tx_buf =
+            kernel::static_buf!([u8; capsules_extra::net::ipv6::ipv6_nd::ND_PAYLOAD_LEN]);
+        let nd_net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let interface_addresses =
+            kernel::static_buf!(capsules_extra::net::ipv6::ipv6_interface::InterfaceAddresses);
+        let ipv6_nd = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_nd::NeighborDiscovery<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        let rpl_send_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let rpl_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let rpl_mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static>);
+        let rpl_ip6_send = kernel::static_buf!(
+            capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let rpl_ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let rpl_dgram = kernel::static_buf!([u8; MAX_PAYLOAD_LEN]);
+        let rpl_radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let rpl_tx_buf = kernel::static_buf!([u8; capsules_extra::net::ipv6::rpl::RPL_PAYLOAD_LEN]);
+        let rpl = kernel::static_buf!(
+            capsules_extra::net::ipv6::rpl::Rpl<'static, VirtualMuxAlarm<'static, $A>>
+        );
+
         (
             alarm,
             mac_user,
@@ -152,6 +220,30 @@ macro_rules! udp_mux_component_static {
             udp_dgram,
             udp_vis_cap,
             ip_vis_cap,
+            (
+                nd_send_alarm,
+                nd_alarm,
+                nd_mac_user,
+                nd_ip6_send,
+                nd_ip6_packet,
+                nd_dgram,
+                nd_radio_buf,
+                nd_tx_buf,
+                nd_net_cap,
+                interface_addresses,
+                ipv6_nd,
+            ),
+            (
+                rpl_send_alarm,
+                rpl_alarm,
+                rpl_mac_user,
+                rpl_ip6_send,
+                rpl_ip6_packet,
+                rpl_dgram,
+                rpl_radio_buf,
+                rpl_tx_buf,
+                rpl,
+            ),
         )
     };};
 }
@@ -196,10 +288,10 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
             sixlowpan_state::Sixlowpan<
                 'static,
                 VirtualMuxAlarm<'static, A>,
-                sixlowpan_compression::Context,
+                sixlowpan_compression::ContextTable,
             >,
         >,
-        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<[sixlowpan_state::RxState<'static>; SIXLOWPAN_RX_STATES]>,
         &'static mut MaybeUninit<
             capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
@@ -221,15 +313,41 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
         &'static mut MaybeUninit<IP6RecvStruct<'static>>,
         &'static mut MaybeUninit<[Option<SocketBindingEntry>; MAX_NUM_BOUND_PORTS]>,
         &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
-        &'static mut MaybeUninit<[u8; 1280]>,
+        &'static mut MaybeUninit<[[u8; sixlowpan_state::MAX_DGRAM_SIZE]; SIXLOWPAN_RX_STATES]>,
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
         &'static mut MaybeUninit<UdpVisibilityCapability>,
         &'static mut MaybeUninit<IpVisibilityCapability>,
+        (
+            &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+            &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+            &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static>>,
+            &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+            &'static mut MaybeUninit<IP6Packet<'static>>,
+            &'static mut MaybeUninit<[u8; ND_PAYLOAD_LEN]>,
+            &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+            &'static mut MaybeUninit<[u8; ND_PAYLOAD_LEN]>,
+            &'static mut MaybeUninit<NetworkCapability>,
+            &'static mut MaybeUninit<InterfaceAddresses>,
+            &'static mut MaybeUninit<NeighborDiscovery<'static, VirtualMuxAlarm<'static, A>>>,
+        ),
+        (
+            &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+            &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+            &'static mut MaybeUninit<capsules_extra::ieee802154::virtual_mac::MacUser<'static>>,
+            &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+            &'static mut MaybeUninit<IP6Packet<'static>>,
+            &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
+            &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+            &'static mut MaybeUninit<[u8; RPL_PAYLOAD_LEN]>,
+            &'static mut MaybeUninit<Rpl<'static, VirtualMuxAlarm<'static, A>>>,
+        ),
     );
     type Output = (
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
         &'static MuxUdpReceiver<'static>,
         &'static UdpPortManager,
+        &'static NeighborDiscovery<'static, VirtualMuxAlarm<'static, A>>,
+        &'static Rpl<'static, VirtualMuxAlarm<'static, A>>,
     );
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
@@ -246,21 +364,28 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
         let ip_vis = s.15.write(IpVisibilityCapability::new(&create_cap));
 
         let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
-            sixlowpan_compression::Context {
+            sixlowpan_compression::ContextTable::new(sixlowpan_compression::Context {
                 prefix: self.ctx_pfix,
                 prefix_len: self.ctx_pfix_len,
                 id: 0,
                 compress: false,
-            },
+            }),
             ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
         ));
 
-        let sixlowpan_rx_buffer = s.12.write([0; 1280]);
+        let sixlowpan_rx_buffers =
+            s.12.write([[0; sixlowpan_state::MAX_DGRAM_SIZE]; SIXLOWPAN_RX_STATES]);
         let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
         let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
-        let default_rx_state =
-            s.3.write(sixlowpan_state::RxState::new(sixlowpan_rx_buffer));
-        sixlowpan_state.add_rx_state(default_rx_state);
+        let mut buffers = sixlowpan_rx_buffers.iter_mut();
+        let rx_states: &'static [sixlowpan_state::RxState<'static>] =
+            s.3.write(core::array::from_fn(|_| {
+                // Unwrap fail = there is one buffer per state
+                sixlowpan_state::RxState::new(buffers.next().unwrap())
+            }));
+        for rx_state in rx_states {
+            sixlowpan_state.add_rx_state(rx_state);
+        }
         udp_mac.set_receive_client(sixlowpan);
 
         let udp_dgram_buffer = s.13.write([0; MAX_PAYLOAD_LEN]);
@@ -273,13 +398,9 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
 
         let radio_buf = s.11.write([0; radio::MAX_BUF_SIZE]);
 
-        // In current design, all udp senders share same IP sender, and the IP
-        // sender holds the destination mac address. This means all UDP senders
-        // must send to the same mac address...this works fine under the
-        // assumption of all packets being routed via a single gateway router,
-        // but doesn't work if multiple senders want to send to different
-        // addresses on a local network. This will be fixed once we have an
-        // ipv6_nd cache mapping IP addresses to dst macs
+        // All udp senders share the same IP sender. It sends to the mac
+        // address RPL or neighbor discovery resolve for the destination, and
+        // to `dst_mac_addr` if neither knows one.
         let ip_send =
             s.4.write(capsules_extra::net::ipv6::ipv6_send::IP6SendStruct::new(
                 ip6_dg,
@@ -293,11 +414,16 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
             ));
         ipsender_virtual_alarm.set_alarm_client(ip_send);
 
-        // Initially, set src IP of the sender to be the first IP in the
-        // Interface list. Userland apps can change this if they so choose.
-        // Notably, the src addr is the same regardless of if messages are sent
-        // from userland or capsules.
-        ip_send.set_addr(self.interface_list[0]);
+        // The src IP of the sender is selected from the interface addresses
+        // for each destination. The first IP in the Interface list is used if
+        // none of them fits. Notably, the src addr is the same regardless of
+        // if messages are sent from userland or capsules.
+        let nd = s.16;
+        let interface_addresses = nd.9.write(InterfaceAddresses::new(self.interface_list));
+        if let Some(addr) = self.interface_list.first() {
+            ip_send.set_addr(*addr);
+        }
+        ip_send.set_interface_addresses(interface_addresses);
         udp_mac.set_transmit_client(ip_send);
 
         let ip_receive =
@@ -309,6 +435,96 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
         let udp_send_mux = s.5.write(MuxUdpSender::new(ip_send));
         ip_send.set_client(udp_send_mux);
 
+        let nd_send_alarm = nd.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        nd_send_alarm.setup();
+        let nd_alarm = nd.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        nd_alarm.setup();
+        let nd_mac =
+            nd.2.write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(nd_mac);
+        let nd_ip_pyld = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type133)),
+            payload: nd.5.write([0; ND_PAYLOAD_LEN]),
+        };
+        let nd_ip_send = nd.3.write(IP6SendStruct::new(
+            nd.4.write(IP6Packet::new(nd_ip_pyld)),
+            nd_send_alarm,
+            nd.6.write([0; radio::MAX_BUF_SIZE]),
+            sixlowpan_state::TxState::new(sixlowpan_state),
+            nd_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        nd_send_alarm.set_alarm_client(nd_ip_send);
+        nd_mac.set_transmit_client(nd_ip_send);
+
+        let nd_net_cap = nd.8.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let ipv6_nd = nd.10.write(NeighborDiscovery::new(
+            nd_alarm,
+            nd_ip_send,
+            nd_net_cap,
+            interface_addresses,
+            self.src_mac_addr,
+            nd.7.write([0; ND_PAYLOAD_LEN]),
+        ));
+        ipv6_nd.set_context_table(&sixlowpan.ctx_store);
+        nd_alarm.set_alarm_client(ipv6_nd);
+        nd_ip_send.set_client(ipv6_nd);
+        nd_ip_send.set_next_hop(ipv6_nd);
+        let _ = ip_receive.add_icmp_client(ipv6_nd);
+
+        let rpl_state = s.17;
+        let rpl_send_alarm = rpl_state.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        rpl_send_alarm.setup();
+        let rpl_alarm = rpl_state.1.write(VirtualMuxAlarm::new(self.alarm_mux));
+        rpl_alarm.setup();
+        let rpl_mac = rpl_state
+            .2
+            .write(capsules_extra::ieee802154::virtual_mac::MacUser::new(
+                self.mux_mac,
+            ));
+        self.mux_mac.add_user(rpl_mac);
+        let rpl_ip_pyld = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type155)),
+            payload: rpl_state.5.write([0; MAX_PAYLOAD_LEN]),
+        };
+        let rpl_ip_send = rpl_state.3.write(IP6SendStruct::new(
+            rpl_state.4.write(IP6Packet::new(rpl_ip_pyld)),
+            rpl_send_alarm,
+            rpl_state.6.write([0; radio::MAX_BUF_SIZE]),
+            sixlowpan_state::TxState::new(sixlowpan_state),
+            rpl_mac,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        rpl_send_alarm.set_alarm_client(rpl_ip_send);
+        rpl_mac.set_transmit_client(rpl_ip_send);
+
+        let rpl = rpl_state.8.write(Rpl::new(
+            rpl_alarm,
+            rpl_ip_send,
+            nd_net_cap,
+            interface_addresses,
+            self.src_mac_addr,
+            rpl_state.7.write([0; RPL_PAYLOAD_LEN]),
+        ));
+        rpl.set_neighbors(ipv6_nd);
+        rpl_alarm.set_alarm_client(rpl);
+        rpl_ip_send.set_client(rpl);
+        rpl_ip_send.set_next_hop(rpl);
+        ip_send.set_next_hop(rpl);
+        let _ = ip_receive.add_icmp_client(rpl);
+        ip_receive.set_forwarder(interface_addresses, rpl);
+
         let kernel_ports = s.10.write([None; MAX_NUM_BOUND_PORTS]);
         let create_table_cap = create_capability!(capabilities::CreatePortTableCapability);
         let udp_port_table = s.7.write(UdpPortManager::new(
@@ -317,6 +533,6 @@ impl<A: Alarm<'static> + 'static> Component for UDPMuxComponent<A> {
             udp_vis,
         ));
 
-        (udp_send_mux, udp_recv_mux, udp_port_table)
+        (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd, rpl)
     }
 }
@@ -0,0 +1,104 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for flashing applications with `dfu-util` over USB.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 3] = &[
+//!     "XYZ Corp.",      // Manufacturer
+//!     "The Zorpinator", // Product
+//!     "Serial No. 5",   // Serial number
+//! ];
+//! let dfu = components::usb_dfu::UsbDfuComponent::new(
+//!     &nrf52::usbd::USBD,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x2341,
+//!     0x005d,
+//!     STRINGS,
+//!     app_install,
+//!     None,
+//! )
+//! .finalize(components::usb_dfu_component_static!(nrf52::usbd::Usbd, 1024));
+//!
+//! dfu.enable();
+//! dfu.attach();
+//! ```
+
+use capsules_extra::app_update::AppUpdater;
+use capsules_extra::usb::dfu::Dfu;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! usb_dfu_component_static {
+    ($U:ty, $N:expr $(,)?) => {{
+        let dfu = kernel::static_buf!(capsules_extra::usb::dfu::Dfu<'static, $U>);
+        let block = kernel::static_buf!([u8; $N]);
+
+        (dfu, block)
+    };};
+}
+
+pub struct UsbDfuComponent<U: 'static + hil::usb::UsbController<'static>, const N: usize> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    updater: &'static dyn AppUpdater<'static>,
+    bootloader: Option<fn() -> !>,
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>, const N: usize> UsbDfuComponent<U, N> {
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        updater: &'static dyn AppUpdater<'static>,
+        bootloader: Option<fn() -> !>,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            updater,
+            bootloader,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>, const N: usize> Component
+    for UsbDfuComponent<U, N>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<Dfu<'static, U>>,
+        &'static mut MaybeUninit<[u8; N]>,
+    );
+    type Output = &'static Dfu<'static, U>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let dfu = s.0.write(Dfu::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.updater,
+            self.bootloader,
+            s.1.write([0; N]),
+        ));
+        self.usb.set_client(dfu);
+        self.updater.set_client(dfu);
+
+        dfu
+    }
+}
@@ -0,0 +1,138 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the USB host stack, with the keyboard and CDC ACM class
+//! drivers.
+//!
+//! The keyboard reports keys to a `KeyboardClient` set by the board, the
+//! serial adapter is a `hil::uart` at the baud rate passed to `new()`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let (usb_host, keyboard, serial) = components::usb_host::UsbHostComponent::new(
+//!     &peripherals.usb_otg_fs,
+//!     mux_alarm,
+//!     115200,
+//! )
+//! .finalize(components::usb_host_component_static!(
+//!     stm32f429zi::usb_otg_fs::UsbOtgFs<'static>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//!
+//! usb_host.start().unwrap();
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::usb_host::cdc_acm::{self, CdcAcm};
+use capsules_extra::usb_host::keyboard::{self, Keyboard};
+use capsules_extra::usb_host::stack::{self, Class, UsbHostStack};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::time::{self, Alarm};
+use kernel::hil::usb_host::UsbHost;
+
+#[macro_export]
+macro_rules! usb_host_component_static {
+    ($H:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let keyboard =
+            kernel::static_buf!(capsules_extra::usb_host::keyboard::Keyboard<'static, $H>);
+        let cdc_acm = kernel::static_buf!(capsules_extra::usb_host::cdc_acm::CdcAcm<'static, $H>);
+        let classes = kernel::static_buf!([&'static dyn capsules_extra::usb_host::stack::Class; 2]);
+        let stack = kernel::static_buf!(
+            capsules_extra::usb_host::stack::UsbHostStack<
+                'static,
+                $H,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let descriptor_buffer =
+            kernel::static_buf!([u8; capsules_extra::usb_host::stack::DESCRIPTOR_BUFFER_LEN]);
+        let report_buffer =
+            kernel::static_buf!([u8; capsules_extra::usb_host::keyboard::REPORT_LEN]);
+        let packet_buffer =
+            kernel::static_buf!([u8; capsules_extra::usb_host::cdc_acm::PACKET_BUFFER_LEN]);
+
+        (
+            alarm,
+            keyboard,
+            cdc_acm,
+            classes,
+            stack,
+            descriptor_buffer,
+            report_buffer,
+            packet_buffer,
+        )
+    };};
+}
+
+pub struct UsbHostComponent<H: 'static + UsbHost<'static>, A: 'static + time::Alarm<'static>> {
+    host: &'static H,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    baud_rate: u32,
+}
+
+impl<H: 'static + UsbHost<'static>, A: 'static + time::Alarm<'static>> UsbHostComponent<H, A> {
+    pub fn new(host: &'static H, alarm_mux: &'static MuxAlarm<'static, A>, baud_rate: u32) -> Self {
+        Self {
+            host,
+            alarm_mux,
+            baud_rate,
+        }
+    }
+}
+
+impl<H: 'static + UsbHost<'static>, A: 'static + time::Alarm<'static>> Component
+    for UsbHostComponent<H, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Keyboard<'static, H>>,
+        &'static mut MaybeUninit<CdcAcm<'static, H>>,
+        &'static mut MaybeUninit<[&'static dyn Class; 2]>,
+        &'static mut MaybeUninit<UsbHostStack<'static, H, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; stack::DESCRIPTOR_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; keyboard::REPORT_LEN]>,
+        &'static mut MaybeUninit<[u8; cdc_acm::PACKET_BUFFER_LEN]>,
+    );
+    type Output = (
+        &'static UsbHostStack<'static, H, VirtualMuxAlarm<'static, A>>,
+        &'static Keyboard<'static, H>,
+        &'static CdcAcm<'static, H>,
+    );
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let report_buffer = static_buffer.6.write([0; keyboard::REPORT_LEN]);
+        let keyboard = static_buffer
+            .1
+            .write(Keyboard::new(self.host, report_buffer));
+
+        let packet_buffer = static_buffer.7.write([0; cdc_acm::PACKET_BUFFER_LEN]);
+        let cdc_acm = static_buffer
+            .2
+            .write(CdcAcm::new(self.host, self.baud_rate, packet_buffer));
+        cdc_acm.register();
+
+        let classes = static_buffer.3.write([keyboard, cdc_acm]);
+
+        let descriptor_buffer = static_buffer.5.write([0; stack::DESCRIPTOR_BUFFER_LEN]);
+        let usb_host = static_buffer.4.write(UsbHostStack::new(
+            self.host,
+            alarm,
+            classes,
+            descriptor_buffer,
+        ));
+        self.host.set_client(usb_host);
+        alarm.set_alarm_client(usb_host);
+
+        (usb_host, keyboard, cdc_acm)
+    }
+}
@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a USB MIDI port used by userspace.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let strings = static_init!(
+//!     [&str; 3],
+//!     [
+//!         "Nordic Semiconductor", // Manufacturer
+//!         "nRF52840dk - TockOS",  // Product
+//!         "serial0001",           // Serial number
+//!     ]
+//! );
+//!
+//! let (midi, midi_driver) = components::usb_midi::UsbMidiComponent::new(
+//!     board_kernel,
+//!     capsules_extra::midi::DRIVER_NUM,
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915, // Nordic Semiconductor
+//!     0x503c,
+//!     strings,
+//! )
+//! .finalize(components::usb_midi_component_static!(
+//!     nrf52840::usbd::Usbd
+//! ));
+//!
+//! midi.enable();
+//! midi.attach();
+//! ```
+
+use capsules_extra::midi::MidiDriver;
+use capsules_extra::usb::midi::UsbMidi;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil;
+use kernel::hil::midi::Midi;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! usb_midi_component_static {
+    ($U:ty $(,)?) => {{
+        let midi = kernel::static_buf!(capsules_extra::usb::midi::UsbMidi<'static, $U>);
+        let driver = kernel::static_buf!(
+            capsules_extra::midi::MidiDriver<
+                'static,
+                capsules_extra::usb::midi::UsbMidi<'static, $U>,
+            >
+        );
+        let send_buffer = kernel::static_buf!([u8; 64]);
+        let receive_buffer = kernel::static_buf!([u8; 64]);
+
+        (midi, driver, send_buffer, receive_buffer)
+    };};
+}
+
+pub struct UsbMidiComponent<U: 'static + hil::usb::UsbController<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> UsbMidiComponent<U> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+    ) -> UsbMidiComponent<U> {
+        UsbMidiComponent {
+            board_kernel,
+            driver_num,
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>> Component for UsbMidiComponent<U> {
+    type StaticInput = (
+        &'static mut MaybeUninit<UsbMidi<'static, U>>,
+        &'static mut MaybeUninit<MidiDriver<'static, UsbMidi<'static, U>>>,
+        &'static mut MaybeUninit<[u8; 64]>,
+        &'static mut MaybeUninit<[u8; 64]>,
+    );
+    type Output = (
+        &'static UsbMidi<'static, U>,
+        &'static MidiDriver<'static, UsbMidi<'static, U>>,
+    );
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let midi = s.0.write(UsbMidi::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+        ));
+        self.usb.set_client(midi);
+        midi.register();
+
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let send_buffer = s.2.write([0; 64]);
+        let receive_buffer = s.3.write([0; 64]);
+
+        let midi_driver = s.1.write(MidiDriver::new(
+            midi,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+            send_buffer,
+            receive_buffer,
+        ));
+
+        midi.set_client(midi_driver);
+
+        (midi, midi_driver)
+    }
+}
@@ -0,0 +1,108 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a USB drive exporting a block device.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! static STRINGS: &'static [&str; 3] = &[
+//!     "XYZ Corp.",      // Manufacturer
+//!     "Data Logger",    // Product
+//!     "0123456789AB",   // Serial number
+//! ];
+//! let msc = components::usb_msc::UsbMscComponent::new(
+//!     &nrf52::usbd::USBD,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x2341,
+//!     0x005c,
+//!     STRINGS,
+//!     blocks,
+//! )
+//! .finalize(components::usb_msc_component_static!(
+//!     nrf52::usbd::Usbd,
+//!     capsules_extra::block_storage::FlashBlockStorage<'static, Flash>
+//! ));
+//!
+//! msc.enable();
+//! msc.attach();
+//! ```
+
+use capsules_extra::block_storage::BLOCK_SIZE;
+use capsules_extra::usb::msc::MassStorage;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil;
+use kernel::hil::block_storage::BlockStorage;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! usb_msc_component_static {
+    ($U:ty, $B:ty $(,)?) => {{
+        let msc = kernel::static_buf!(capsules_extra::usb::msc::MassStorage<'static, $U, $B>);
+        let block = kernel::static_buf!([u8; capsules_extra::block_storage::BLOCK_SIZE]);
+
+        (msc, block)
+    };};
+}
+
+pub struct UsbMscComponent<
+    U: 'static + hil::usb::UsbController<'static>,
+    B: 'static + BlockStorage<'static>,
+> {
+    usb: &'static U,
+    max_ctrl_packet_size: u8,
+    vendor_id: u16,
+    product_id: u16,
+    strings: &'static [&'static str; 3],
+    storage: &'static B,
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>, B: 'static + BlockStorage<'static>>
+    UsbMscComponent<U, B>
+{
+    pub fn new(
+        usb: &'static U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'static B,
+    ) -> Self {
+        Self {
+            usb,
+            max_ctrl_packet_size,
+            vendor_id,
+            product_id,
+            strings,
+            storage,
+        }
+    }
+}
+
+impl<U: 'static + hil::usb::UsbController<'static>, B: 'static + BlockStorage<'static>> Component
+    for UsbMscComponent<U, B>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<MassStorage<'static, U, B>>,
+        &'static mut MaybeUninit<[u8; BLOCK_SIZE]>,
+    );
+    type Output = &'static MassStorage<'static, U, B>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let msc = s.0.write(MassStorage::new(
+            self.usb,
+            self.max_ctrl_packet_size,
+            self.vendor_id,
+            self.product_id,
+            self.strings,
+            self.storage,
+            s.1.write([0; BLOCK_SIZE]),
+        ));
+        self.usb.set_client(msc);
+        self.storage.set_client(msc);
+
+        msc
+    }
+}
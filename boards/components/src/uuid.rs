@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for the UUID and nonce driver.
+//!
+//! Each process can make `burst` requests at once and gets another one every
+//! `interval_ms` milliseconds.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let uuid = components::uuid::UuidComponent::new(
+//!     board_kernel,
+//!     capsules_extra::uuid::DRIVER_NUM,
+//!     rng,
+//!     &base_peripherals.rtc,
+//!     4,
+//!     1000,
+//! )
+//! .finalize(components::uuid_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use capsules_extra::uuid::UuidDriver;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::rng::Rng;
+use kernel::hil::time::Time;
+
+#[macro_export]
+macro_rules! uuid_component_static {
+    ($A:ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::uuid::UuidDriver<'static, $A>)
+    };};
+}
+
+pub struct UuidComponent<A: 'static + Time> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    rng: &'static dyn Rng<'static>,
+    time: &'static A,
+    burst: u32,
+    interval_ms: u32,
+}
+
+impl<A: 'static + Time> UuidComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        rng: &'static dyn Rng<'static>,
+        time: &'static A,
+        burst: u32,
+        interval_ms: u32,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            rng,
+            time,
+            burst,
+            interval_ms,
+        }
+    }
+}
+
+impl<A: 'static + Time> Component for UuidComponent<A> {
+    type StaticInput = &'static mut MaybeUninit<UuidDriver<'static, A>>;
+    type Output = &'static UuidDriver<'static, A>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let uuid = static_buffer.write(UuidDriver::new(
+            self.rng,
+            self.time,
+            self.burst,
+            self.interval_ms,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        self.rng.set_client(uuid);
+
+        uuid
+    }
+}
@@ -0,0 +1,77 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a wear-leveling flash translation layer.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let wear_leveling = components::wear_leveling::WearLevelingComponent::new(flash_user, 192, 4)
+//!     .finalize(components::wear_leveling_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>,
+//!         64
+//!     ));
+//! ```
+
+use capsules_extra::wear_leveling::WearLeveling;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::flash::{Flash, HasClient};
+
+#[macro_export]
+macro_rules! wear_leveling_component_static {
+    ($F:ty, $P:expr $(,)?) => {{
+        let page = kernel::static_buf!(<$F as kernel::hil::flash::Flash>::Page);
+        let wear_leveling =
+            kernel::static_buf!(capsules_extra::wear_leveling::WearLeveling<'static, $F, $P>);
+
+        (page, wear_leveling)
+    };};
+}
+
+pub struct WearLevelingComponent<
+    F: 'static + Flash + HasClient<'static, WearLeveling<'static, F, P>>,
+    const P: usize,
+> {
+    flash: &'static F,
+    first_page: usize,
+    spare: usize,
+}
+
+impl<F: 'static + Flash + HasClient<'static, WearLeveling<'static, F, P>>, const P: usize>
+    WearLevelingComponent<F, P>
+{
+    pub fn new(flash: &'static F, first_page: usize, spare: usize) -> Self {
+        Self {
+            flash,
+            first_page,
+            spare,
+        }
+    }
+}
+
+impl<F: 'static + Flash + HasClient<'static, WearLeveling<'static, F, P>>, const P: usize> Component
+    for WearLevelingComponent<F, P>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<F::Page>,
+        &'static mut MaybeUninit<WearLeveling<'static, F, P>>,
+    );
+    type Output = &'static WearLeveling<'static, F, P>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let page = static_buffer.0.write(F::Page::default());
+        let wear_leveling = static_buffer.1.write(WearLeveling::new(
+            self.flash,
+            self.first_page,
+            self.spare,
+            page,
+        ));
+        HasClient::set_client(self.flash, wear_leveling);
+        wear_leveling.register();
+
+        wear_leveling
+    }
+}
@@ -25,6 +25,7 @@ use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::device_id::DeviceId;
 use kernel::hil::digest::Digest;
 use kernel::hil::i2c::I2CMaster;
 use kernel::hil::radio;
@@ -98,8 +99,8 @@ const PAN_ID: u16 = 0xABCD;
 // how should the kernel respond when a process faults
 const FAULT_RESPONSE: kernel::process::StopFaultPolicy = kernel::process::StopFaultPolicy {};
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static sam4l::chip::Sam4l<Sam4lDefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
@@ -157,6 +158,14 @@ struct Imix {
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     ninedof: &'static capsules_extra::ninedof::NineDof<'static>,
     udp_driver: &'static capsules_extra::net::udp::UDPDriver<'static>,
+    coap: &'static capsules_extra::net::coap::CoapDriver<
+        'static,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
+    mqttsn: &'static capsules_extra::net::mqttsn::MqttSnDriver<
+        'static,
+        VirtualMuxAlarm<'static, sam4l::ast::Ast<'static>>,
+    >,
     crc: &'static capsules_extra::crc::CrcDriver<'static, sam4l::crccu::Crccu<'static>>,
     usb_driver: &'static capsules_extra::usb::usb_user::UsbSyscallDriver<
         'static,
@@ -206,6 +215,8 @@ impl SyscallDriverLookup for Imix {
             capsules_extra::crc::DRIVER_NUM => f(Some(self.crc)),
             capsules_extra::usb::usb_user::DRIVER_NUM => f(Some(self.usb_driver)),
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
+            capsules_extra::net::coap::DRIVER_NUM => f(Some(self.coap)),
+            capsules_extra::net::mqttsn::DRIVER_NUM => f(Some(self.mqttsn)),
             capsules_extra::nrf51822_serialization::DRIVER_NUM => f(Some(self.nrf51822)),
             capsules_extra::nonvolatile_storage_driver::DRIVER_NUM => {
                 f(Some(self.nonvolatile_storage))
@@ -620,14 +631,11 @@ pub unsafe fn main() {
     )
     .finalize(components::rng_component_static!());
 
-    // For now, assign the 802.15.4 MAC address on the device as
-    // simply a 16-bit short address which represents the last 16 bits
-    // of the serial number of the sam4l for this device.  In the
-    // future, we could generate the MAC address by hashing the full
-    // 120-bit serial number
+    // For now, assign the 802.15.4 MAC address on the device as simply a
+    // 16-bit short address derived from the serial number of the sam4l.
     let serial_num: sam4l::serial_num::SerialNum = sam4l::serial_num::SerialNum::new();
-    let serial_num_bottom_16 = (serial_num.get_lower_64() & 0x0000_0000_0000_ffff) as u16;
-    let src_mac_from_serial_num: MacAddress = MacAddress::Short(serial_num_bottom_16);
+    let device_short_addr = serial_num.short_address();
+    let src_mac_from_device_id: MacAddress = MacAddress::Short(device_short_addr);
 
     let aes_mux = static_init!(
         MuxAES128CCM<'static, sam4l::aes::Aes>,
@@ -644,7 +652,7 @@ pub unsafe fn main() {
         rf233,
         aes_mux,
         PAN_ID,
-        serial_num_bottom_16,
+        device_short_addr,
     )
     .finalize(components::ieee802154_component_static!(
         capsules_extra::rf233::RF233<
@@ -693,21 +701,24 @@ pub unsafe fn main() {
                 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
                 0x1e, 0x1f,
             ]),
-            IPAddr::generate_from_mac(src_mac_from_serial_num),
+            IPAddr::generate_from_mac(src_mac_from_device_id),
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num, //comment out for dual rx test only
-        //MacAddress::Short(49138), //comment in for dual rx test only
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(sam4l::ast::Ast));
+    // RPL is not started, packets are sent to neighbors and the default router
+    let (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd, _rpl) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            src_mac_from_device_id, //comment out for dual rx test only
+            //MacAddress::Short(49138), //comment in for dual rx test only
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(sam4l::ast::Ast));
+    ipv6_nd.start().unwrap();
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
@@ -716,10 +727,34 @@ pub unsafe fn main() {
         udp_send_mux,
         udp_recv_mux,
         udp_port_table,
-        local_ip_ifaces,
+        ipv6_nd.addresses(),
     )
     .finalize(components::udp_driver_component_static!(sam4l::ast::Ast));
 
+    let (_coap_endpoint, coap) = components::coap::CoapComponent::new(
+        board_kernel,
+        capsules_extra::net::coap::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        mux_alarm,
+    )
+    .finalize(components::coap_component_static!(sam4l::ast::Ast));
+
+    // The MQTT-SN gateway is the neighbor packets are sent to
+    let (_mqttsn_client, mqttsn) = components::mqttsn::MqttSnComponent::new(
+        board_kernel,
+        capsules_extra::net::mqttsn::DRIVER_NUM,
+        udp_send_mux,
+        udp_recv_mux,
+        udp_port_table,
+        mux_alarm,
+        IPAddr::generate_from_mac(DST_MAC_ADDR),
+        capsules_extra::net::mqttsn::client::MQTTSN_PORT,
+        b"imix",
+    )
+    .finalize(components::mqttsn_component_static!(sam4l::ast::Ast));
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -741,6 +776,8 @@ pub unsafe fn main() {
         ipc: kernel::ipc::IPC::new(board_kernel, kernel::ipc::DRIVER_NUM, &grant_cap),
         ninedof,
         udp_driver,
+        coap,
+        mqttsn,
         usb_driver,
         nrf51822: nrf_serialization,
         nonvolatile_storage,
@@ -835,7 +872,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_mgmt_cap,
     )
@@ -14,6 +14,7 @@
 
 use kernel::capabilities;
 use kernel::component::Component;
+use kernel::hil::device_id::DeviceId;
 use kernel::hil::gpio::Configure;
 use kernel::hil::gpio::Output;
 use kernel::hil::led::LedLow;
@@ -87,8 +88,8 @@ const FAULT_RESPONSE: kernel::process::StopWithDebugFaultPolicy =
 const NUM_PROCS: usize = 8;
 
 // State for loading and holding applications.
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
@@ -343,11 +344,11 @@ pub unsafe fn main() {
     // Setup the CDC-ACM over USB driver that we will use for UART.
     // We use the Arduino Vendor ID and Product ID since the device is the same.
 
-    // Create the strings we include in the USB descriptor. We use the hardcoded
-    // DEVICEADDR register on the nRF52 to set the serial number.
-    let serial_number_buf = static_init!([u8; 17], [0; 17]);
+    // Create the strings we include in the USB descriptor. The serial number
+    // is derived from the unique device ID in the FICR.
     let serial_number_string: &'static str =
-        nrf52::ficr::FICR_INSTANCE.address_str(serial_number_buf);
+        components::device_id::SerialNumberStringComponent::new(&nrf52::ficr::FICR_INSTANCE)
+            .finalize(components::serial_number_string_component_static!());
     let strings = static_init!(
         [&str; 3],
         [
@@ -539,21 +540,25 @@ pub unsafe fn main() {
             nrf52840::aes::AesECB
         ));
 
-    let serial_num = nrf52840::ficr::FICR_INSTANCE.address();
-    let serial_num_bottom_16 = u16::from_le_bytes([serial_num[0], serial_num[1]]);
-    let src_mac_from_serial_num: MacAddress = MacAddress::Short(serial_num_bottom_16);
+    let device_short_addr = nrf52840::ficr::FICR_INSTANCE.short_address();
+    let src_mac_from_device_id: MacAddress = MacAddress::Short(device_short_addr);
     let (ieee802154_radio, mux_mac) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         &base_peripherals.ieee802154_radio,
         aes_mux,
         PAN_ID,
-        serial_num_bottom_16,
+        device_short_addr,
     )
     .finalize(components::ieee802154_component_static!(
         nrf52840::ieee802154_radio::Radio,
         nrf52840::aes::AesECB<'static>
     ));
+    ieee802154_radio.set_channel_quality(&base_peripherals.ieee802154_radio);
+    kernel::hil::radio::RadioChannelQuality::set_energy_detect_client(
+        &base_peripherals.ieee802154_radio,
+        ieee802154_radio,
+    );
     use capsules_extra::net::ipv6::ip_utils::IPAddr;
 
     let local_ip_ifaces = static_init!(
@@ -568,21 +573,24 @@ pub unsafe fn main() {
                 0x1e, 0x1f,
             ]),
             IPAddr::generate_from_mac(capsules_extra::net::ieee802154::MacAddress::Short(
-                serial_num_bottom_16
+                device_short_addr
             )),
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num,
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+    // RPL is not started, packets are sent to neighbors and the default router
+    let (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd, _rpl) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            src_mac_from_device_id,
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+    ipv6_nd.start().unwrap();
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
@@ -591,7 +599,7 @@ pub unsafe fn main() {
         udp_send_mux,
         udp_recv_mux,
         udp_port_table,
-        local_ip_ifaces,
+        ipv6_nd.addresses(),
     )
     .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
 
@@ -684,7 +692,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -71,8 +71,8 @@ const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::Panic
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 4;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static Rp2040<Rp2040DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
@@ -340,12 +340,19 @@ pub unsafe fn main() {
     .finalize(components::alarm_component_static!(RPTimer));
 
     // CDC
+    // The RP2040 has no unique ID, so derive the USB serial number from the
+    // unique ID of the flash chip.
+    let flash_id = rp2040::flash_id::FlashUniqueId::read();
+    let serial_number_string: &'static str =
+        components::device_id::SerialNumberStringComponent::new(&flash_id)
+            .finalize(components::serial_number_string_component_static!());
+
     let strings = static_init!(
         [&str; 3],
         [
             "Arduino",                      // Manufacturer
             "Nano RP2040 Connect - TockOS", // Product
-            "00000000000000000",            // Serial number
+            serial_number_string,           // Serial number
         ]
     );
 
@@ -483,9 +490,13 @@ pub unsafe fn main() {
     let grant_temperature =
         board_kernel.create_grant(capsules_extra::temperature::DRIVER_NUM, &grant_cap);
 
+    let temp_sensors = static_init!(
+        [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1],
+        [temp_sensor]
+    );
     let temp = static_init!(
         capsules_extra::temperature::TemperatureSensor<'static>,
-        capsules_extra::temperature::TemperatureSensor::new(temp_sensor, grant_temperature)
+        capsules_extra::temperature::TemperatureSensor::new(temp_sensors, grant_temperature)
     );
 
     let _ = lsm6dsoxtr
@@ -507,9 +518,13 @@ pub unsafe fn main() {
     // There is only a single driver, thus either for userspace is exclusive.
     // Uncomment this block in order to use the temperature sensor from lsm6dsoxtr
 
+    // let temp_sensors = static_init!(
+    //     [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1],
+    //     [lsm6dsoxtr]
+    // );
     // let temp = static_init!(
     //     capsules_extra::temperature::TemperatureSensor<'static>,
-    //     capsules_extra::temperature::TemperatureSensor::new(lsm6dsoxtr, grant_temperature)
+    //     capsules_extra::temperature::TemperatureSensor::new(temp_sensors, grant_temperature)
     // );
 
     kernel::hil::sensors::TemperatureDriver::set_client(temp_sensor, temp);
@@ -608,7 +623,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -10,6 +10,7 @@ use kernel::debug::IoWrite;
 use kernel::hil::led;
 use kernel::hil::uart;
 use kernel::hil::uart::Configure;
+use kernel::postmortem::PostmortemWriter;
 use nrf52840::gpio::Pin;
 
 use crate::CHIP;
@@ -100,7 +101,8 @@ pub unsafe extern "C" fn panic_fmt(pi: &PanicInfo) -> ! {
     // The nRF52840DK LEDs (see back of board)
     let led_kernel_pin = &nrf52840::gpio::GPIOPin::new(Pin::P0_13);
     let led = &mut led::LedLow::new(led_kernel_pin);
-    let writer = &mut WRITER;
+    // Record the panic so it can be printed with `lastpanic` after reset.
+    let writer = &mut PostmortemWriter::new(&mut WRITER);
     debug::panic(
         &mut [led],
         writer,
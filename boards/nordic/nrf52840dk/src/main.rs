@@ -80,10 +80,13 @@ use capsules_extra::net::ieee802154::MacAddress;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use kernel::component::Component;
 use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::ble_advertising::BleAdvertisementDriver;
+use kernel::hil::device_id::DeviceId;
 use kernel::hil::i2c::{I2CMaster, I2CSlave};
 use kernel::hil::led::LedLow;
+use kernel::hil::radio::{RadioChannelQuality, RadioData};
 use kernel::hil::symmetric_encryption::AES128;
-use kernel::hil::time::Counter;
+use kernel::hil::time::{Alarm, Counter};
 #[allow(unused_imports)]
 use kernel::hil::usb::Client;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
@@ -147,8 +150,8 @@ const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::Panic
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
 static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
@@ -168,11 +171,16 @@ fn reset() -> ! {
     }
 }
 
+type RadioArbiter = nrf52840::radio_arbiter::RadioArbiter<
+    'static,
+    VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
+>;
+
 /// Supported drivers by the platform
 pub struct Platform {
     ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
         'static,
-        nrf52840::ble_radio::Radio<'static>,
+        RadioArbiter,
         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
     >,
     ieee802154_radio: &'static capsules_extra::ieee802154::RadioDriver<'static>,
@@ -193,6 +201,8 @@ pub struct Platform {
     rng: &'static capsules_core::rng::RngDriver<'static>,
     temp: &'static capsules_extra::temperature::TemperatureSensor<'static>,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    ipc_registry: &'static kernel::ipc_registry::ServiceRegistry<8>,
+    buffer_lending: &'static kernel::ipc_lending::BufferLending<4>,
     analog_comparator: &'static capsules_extra::analog_comparator::AnalogComparator<
         'static,
         nrf52840::acomp::Comparator<'static>,
@@ -243,6 +253,8 @@ impl SyscallDriverLookup for Platform {
             }
             capsules_extra::net::udp::DRIVER_NUM => f(Some(self.udp_driver)),
             kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            kernel::ipc_registry::DRIVER_NUM => f(Some(self.ipc_registry)),
+            kernel::ipc_lending::DRIVER_NUM => f(Some(self.buffer_lending)),
             capsules_core::i2c_master_slave_driver::DRIVER_NUM => f(Some(self.i2c_master_slave)),
             capsules_core::spi_controller::DRIVER_NUM => f(Some(self.spi_controller)),
             _ => f(None),
@@ -307,6 +319,11 @@ impl KernelResources<nrf52840::chip::NRF52<'static, Nrf52840DefaultPeripherals<'
 pub unsafe fn main() {
     nrf52840::init();
 
+    // Record panics, so that `lastpanic` on the process console shows them
+    // after the reset.
+    components::postmortem::PostmortemComponent::new()
+        .finalize(components::postmortem_component_static!());
+
     let nrf52840_peripherals = create_peripherals();
 
     // set up circular peripheral dependencies
@@ -447,6 +464,11 @@ pub unsafe fn main() {
     // Create a shared UART channel for the console and for kernel debug.
     let uart_mux = components::console::UartMuxComponent::new(channel, 115200)
         .finalize(components::uart_mux_component_static!());
+    // Absorb bursts of debug output and prints with a transmit ring, sent
+    // by the UARTE's DMA.
+    components::console::UartMuxTransmitBuffersComponent::new(uart_mux).finalize(
+        components::uart_mux_transmit_buffers_component_static!(1024, 128),
+    );
 
     let pconsole = components::process_console::ProcessConsoleComponent::new(
         board_kernel,
@@ -465,20 +487,44 @@ pub unsafe fn main() {
         capsules_core::console::DRIVER_NUM,
         uart_mux,
     )
-    .finalize(components::console_component_static!());
+    .finalize(components::console_component_static!(128));
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux)
         .finalize(components::debug_writer_component_static!());
 
+    // BLE and 802.15.4 share the radio in timeslots.
+    let arbiter_alarm = static_init!(
+        VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+        VirtualMuxAlarm::new(mux_alarm)
+    );
+    arbiter_alarm.setup();
+    let radio_arbiter = static_init!(
+        RadioArbiter,
+        nrf52840::radio_arbiter::RadioArbiter::new(
+            &base_peripherals.ble_radio,
+            &base_peripherals.ieee802154_radio,
+            arbiter_alarm,
+        )
+    );
+    arbiter_alarm.set_alarm_client(radio_arbiter);
+    BleAdvertisementDriver::set_transmit_client(&base_peripherals.ble_radio, radio_arbiter);
+    BleAdvertisementDriver::set_receive_client(&base_peripherals.ble_radio, radio_arbiter);
+    RadioData::set_transmit_client(&base_peripherals.ieee802154_radio, radio_arbiter);
+    RadioChannelQuality::set_energy_detect_client(
+        &base_peripherals.ieee802154_radio,
+        radio_arbiter,
+    );
+    kernel::deferred_call::DeferredCallClient::register(radio_arbiter);
+
     let ble_radio = components::ble::BLEComponent::new(
         board_kernel,
         capsules_extra::ble_advertising_driver::DRIVER_NUM,
-        &base_peripherals.ble_radio,
+        radio_arbiter,
         mux_alarm,
     )
     .finalize(components::ble_component_static!(
         nrf52840::rtc::Rtc,
-        nrf52840::ble_radio::Radio
+        RadioArbiter
     ));
 
     let aes_mux = static_init!(
@@ -488,21 +534,22 @@ pub unsafe fn main() {
     aes_mux.register();
     base_peripherals.ecb.set_client(aes_mux);
 
-    let serial_num = nrf52840::ficr::FICR_INSTANCE.address();
-    let serial_num_bottom_16 = serial_num[0] as u16 + ((serial_num[1] as u16) << 8);
-    let src_mac_from_serial_num: MacAddress = MacAddress::Short(serial_num_bottom_16);
+    let device_short_addr = nrf52840::ficr::FICR_INSTANCE.short_address();
+    let src_mac_from_device_id: MacAddress = MacAddress::Short(device_short_addr);
     let (ieee802154_radio, mux_mac) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
-        &base_peripherals.ieee802154_radio,
+        radio_arbiter,
         aes_mux,
         PAN_ID,
-        serial_num_bottom_16,
+        device_short_addr,
     )
     .finalize(components::ieee802154_component_static!(
-        nrf52840::ieee802154_radio::Radio,
+        RadioArbiter,
         nrf52840::aes::AesECB<'static>
     ));
+    ieee802154_radio.set_channel_quality(radio_arbiter);
+    radio_arbiter.set_energy_detect_client(ieee802154_radio);
 
     let local_ip_ifaces = static_init!(
         [IPAddr; 3],
@@ -516,21 +563,24 @@ pub unsafe fn main() {
                 0x1e, 0x1f,
             ]),
             IPAddr::generate_from_mac(capsules_extra::net::ieee802154::MacAddress::Short(
-                serial_num_bottom_16
+                device_short_addr
             )),
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num,
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+    // RPL is not started, packets are sent to neighbors and the default router
+    let (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd, _rpl) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            src_mac_from_device_id,
+            local_ip_ifaces,
+            mux_alarm,
+        )
+        .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+    ipv6_nd.start().unwrap();
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
@@ -539,7 +589,7 @@ pub unsafe fn main() {
         udp_send_mux,
         udp_recv_mux,
         udp_port_table,
-        local_ip_ifaces,
+        ipv6_nd.addresses(),
     )
     .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
 
@@ -705,6 +755,26 @@ pub unsafe fn main() {
     // keyboard_hid.enable();
     // keyboard_hid.attach();
 
+    let ipc_registry = static_init!(
+        kernel::ipc_registry::ServiceRegistry<8>,
+        kernel::ipc_registry::ServiceRegistry::new(
+            board_kernel,
+            kernel::ipc_registry::DRIVER_NUM,
+            &memory_allocation_capability,
+        )
+    );
+    board_kernel.add_process_observer(ipc_registry, &process_management_capability);
+
+    let buffer_lending = static_init!(
+        kernel::ipc_lending::BufferLending<4>,
+        kernel::ipc_lending::BufferLending::new(
+            board_kernel,
+            kernel::ipc_lending::DRIVER_NUM,
+            &memory_allocation_capability,
+        )
+    );
+    board_kernel.add_process_observer(buffer_lending, &process_management_capability);
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -727,6 +797,8 @@ pub unsafe fn main() {
             kernel::ipc::DRIVER_NUM,
             &memory_allocation_capability,
         ),
+        ipc_registry,
+        buffer_lending,
         i2c_master_slave,
         spi_controller,
         scheduler,
@@ -762,7 +834,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -4,8 +4,10 @@
 
 #![no_std]
 
+pub mod pdm;
 pub mod startup;
 
+pub use self::pdm::NrfPdmComponent;
 pub use self::startup::{
     NrfClockComponent, NrfStartupComponent, UartChannel, UartChannelComponent, UartPins,
 };
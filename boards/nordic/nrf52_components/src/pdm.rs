@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Component for a PDM microphone on the nRF52 PDM peripheral.
+//!
+//! Connects the microphone and gives the peripheral its sample buffer. The
+//! result is a sound pressure sensor, e.g. for the `SoundPressureComponent`.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let pdm = nrf52_components::NrfPdmComponent::new(
+//!     &base_peripherals.pdm,
+//!     PDM_CLK_PIN,
+//!     PDM_DIN_PIN,
+//!     120,
+//!     Some(&nrf52840_peripherals.gpio_port[PDM_POWER_PIN]),
+//! )
+//! .finalize(nrf52_components::nrf_pdm_component_static!(512));
+//! ```
+
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use nrf52::gpio::Pin;
+use nrf52::pdm::Pdm;
+
+#[macro_export]
+macro_rules! nrf_pdm_component_static {
+    ($N:expr $(,)?) => {{
+        kernel::static_buf!([i16; $N])
+    };};
+}
+
+pub struct NrfPdmComponent<const N: usize> {
+    pdm: &'static Pdm<'static>,
+    clk: Pin,
+    din: Pin,
+    full_scale_spl: u8,
+    power_pin: Option<&'static dyn gpio::Pin>,
+}
+
+impl<const N: usize> NrfPdmComponent<N> {
+    /// `full_scale_spl` is the sound pressure level in dB SPL at which the
+    /// microphone reaches full scale, i.e. 94 dB minus its sensitivity in
+    /// dBFS.
+    pub fn new(
+        pdm: &'static Pdm<'static>,
+        clk: Pin,
+        din: Pin,
+        full_scale_spl: u8,
+        power_pin: Option<&'static dyn gpio::Pin>,
+    ) -> Self {
+        Self {
+            pdm,
+            clk,
+            din,
+            full_scale_spl,
+            power_pin,
+        }
+    }
+}
+
+impl<const N: usize> Component for NrfPdmComponent<N> {
+    type StaticInput = &'static mut MaybeUninit<[i16; N]>;
+    type Output = &'static Pdm<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let buffer = s.write([0; N]);
+
+        unsafe {
+            self.pdm.configure(
+                nrf52::pinmux::Pinmux::new(self.clk as u32),
+                nrf52::pinmux::Pinmux::new(self.din as u32),
+                self.full_scale_spl,
+            );
+        }
+        self.pdm.set_buffer(buffer);
+        if let Some(pin) = self.power_pin {
+            self.pdm.set_power_pin(pin);
+        }
+
+        self.pdm
+    }
+}
@@ -74,8 +74,8 @@ const FAULT_RESPONSE: kernel::process::StopWithDebugFaultPolicy =
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 8;
 
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None; NUM_PROCS];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Static reference to chip for panic dumps
 static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
@@ -376,11 +376,11 @@ pub unsafe fn main() {
     // Setup the CDC-ACM over USB driver that we will use for UART.
     // We use the Arduino Vendor ID and Product ID since the device is the same.
 
-    // Create the strings we include in the USB descriptor. We use the hardcoded
-    // DEVICEADDR register on the nRF52 to set the serial number.
-    let serial_number_buf = static_init!([u8; 17], [0; 17]);
+    // Create the strings we include in the USB descriptor. The serial number
+    // is derived from the unique device ID in the FICR.
     let serial_number_string: &'static str =
-        nrf52::ficr::FICR_INSTANCE.address_str(serial_number_buf);
+        components::device_id::SerialNumberStringComponent::new(&nrf52::ficr::FICR_INSTANCE)
+            .finalize(components::serial_number_string_component_static!());
     let strings = static_init!(
         [&str; 3],
         [
@@ -643,7 +643,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -38,8 +38,8 @@ mod virtual_uart_rx_test;
 const NUM_PROCS: usize = 4;
 
 // Actual memory for holding the active process structures.
-static mut PROCESSES: [Option<&'static dyn kernel::process::Process>; NUM_PROCS] =
-    [None, None, None, None];
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
 
 // Static reference to chip for panic dumps.
 static mut CHIP: Option<&'static stm32f303xc::chip::Stm32f3xx<Stm32f3xxDefaultPeripherals>> = None;
@@ -669,9 +669,13 @@ pub unsafe fn main() {
         board_kernel.create_grant(capsules_extra::temperature::DRIVER_NUM, &grant_cap);
 
     // Comment this if you want to use the ADC MCU temp sensor
+    let temp_sensors = static_init!(
+        [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1],
+        [l3gd20]
+    );
     let temp = static_init!(
         capsules_extra::temperature::TemperatureSensor<'static>,
-        capsules_extra::temperature::TemperatureSensor::new(l3gd20, grant_temperature)
+        capsules_extra::temperature::TemperatureSensor::new(temp_sensors, grant_temperature)
     );
     kernel::hil::sensors::TemperatureDriver::set_client(l3gd20, temp);
 
@@ -725,9 +729,13 @@ pub unsafe fn main() {
     // let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
     // let grant_temperature = board_kernel.create_grant(&grant_cap);
 
+    // let temp_sensors = static_init!(
+    //     [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 1],
+    //     [temp_sensor]
+    // );
     // let temp = static_init!(
     //     capsules_extra::temperature::TemperatureSensor<'static>,
-    //     capsules_extra::temperature::TemperatureSensor::new(temp_sensor, grant_temperature)
+    //     capsules_extra::temperature::TemperatureSensor::new(temp_sensors, grant_temperature)
     // );
     // kernel::hil::sensors::TemperatureDriver::set_client(temp_sensor, temp);
 
@@ -855,7 +863,7 @@ pub unsafe fn main() {
             &mut _sappmem as *mut u8,
             &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
         ),
-        &mut PROCESSES,
+        &PROCESSES,
         &FAULT_RESPONSE,
         &process_management_capability,
     )
@@ -0,0 +1,681 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Board file for STM32F746G Discovery kit development board
+//!
+//! - <https://www.st.com/en/evaluation-tools/32f746gdiscovery.html>
+
+#![no_std]
+// Disable this attribute when documenting, as a workaround for
+// https://github.com/rust-lang/rust/issues/62184.
+#![cfg_attr(not(doc), no_main)]
+#![deny(missing_docs)]
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use components::gpio::GpioComponent;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::led::LedHigh;
+use kernel::hil::screen::{Screen, ScreenPixelFormat};
+use kernel::platform::{KernelResources, SyscallDriverLookup};
+use kernel::scheduler::round_robin::RoundRobinSched;
+use kernel::{create_capability, debug, static_init};
+use stm32f746ng::interrupt_service::Stm32f746ngDefaultPeripherals;
+
+/// Support routines for debugging I/O.
+pub mod io;
+
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 4;
+
+// Actual memory for holding the active process structures.
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
+
+static mut CHIP: Option<&'static stm32f746ng::chip::Stm32f7xx<Stm32f746ngDefaultPeripherals>> =
+    None;
+static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
+
+// How should the kernel respond when a process faults.
+const FAULT_RESPONSE: kernel::process::PanicFaultPolicy = kernel::process::PanicFaultPolicy {};
+
+/// Dummy buffer that causes the linker to reserve enough space for the stack.
+#[no_mangle]
+#[link_section = ".stack_buffer"]
+pub static mut STACK_MEMORY: [u8; 0x2000] = [0; 0x2000];
+
+/// The IS42S32400F SDRAM, of which the board uses the lower 16 bits, with
+/// SDCLK at 90 MHz.
+const SDRAM_CONFIG: stm32f746ng::fmc::SdramConfig = stm32f746ng::fmc::SdramConfig {
+    column_bits: 8,
+    row_bits: 12,
+    width: stm32f746ng::fmc::SdramWidth::Bits16,
+    four_banks: true,
+    cas_latency: 2,
+    load_to_active: 2,
+    exit_self_refresh: 7,
+    self_refresh: 4,
+    row_cycle: 7,
+    write_recovery: 2,
+    row_precharge: 2,
+    row_to_column: 2,
+    // 64 ms for 4096 rows
+    refresh_interval_ns: 15625,
+};
+
+/// The RK043FN48H 4.3" 480x272 panel.
+const PANEL: stm32f746ng::ltdc::PanelTimings = stm32f746ng::ltdc::PanelTimings {
+    width: 480,
+    height: 272,
+    hsync_width: 41,
+    hback_porch: 13,
+    hfront_porch: 32,
+    vsync_height: 10,
+    vback_porch: 2,
+    vfront_porch: 2,
+    active_high: false,
+    pixel_clock_khz: 9600,
+};
+
+/// Size of the framebuffer, large enough for a frame in ARGB_8888.
+const FRAMEBUFFER_LEN: usize = 480 * 272 * 4;
+
+// Function for the process console to use to reboot the board
+fn reset() -> ! {
+    unsafe {
+        cortexm7::scb::reset();
+    }
+    loop {
+        cortexm7::support::nop();
+    }
+}
+
+/// A structure representing this platform that holds references to all
+/// capsules for this platform.
+struct STM32F746GDiscovery {
+    console: &'static capsules_core::console::Console<'static>,
+    ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    led: &'static capsules_core::led::LedDriver<
+        'static,
+        LedHigh<'static, stm32f746ng::gpio::Pin<'static>>,
+        1,
+    >,
+    button: &'static capsules_core::button::Button<'static, stm32f746ng::gpio::Pin<'static>>,
+    alarm: &'static capsules_core::alarm::AlarmDriver<
+        'static,
+        VirtualMuxAlarm<'static, stm32f746ng::tim2::Tim2<'static>>,
+    >,
+    gpio: &'static capsules_core::gpio::GPIO<'static, stm32f746ng::gpio::Pin<'static>>,
+    touch: &'static capsules_extra::touch::Touch<'static>,
+    screen: &'static capsules_extra::screen::Screen<'static>,
+
+    scheduler: &'static RoundRobinSched<'static>,
+    systick: cortexm7::systick::SysTick,
+}
+
+/// Mapping of integer syscalls to objects that implement syscalls.
+impl SyscallDriverLookup for STM32F746GDiscovery {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_core::led::DRIVER_NUM => f(Some(self.led)),
+            capsules_core::button::DRIVER_NUM => f(Some(self.button)),
+            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
+            capsules_extra::touch::DRIVER_NUM => f(Some(self.touch)),
+            capsules_extra::screen::DRIVER_NUM => f(Some(self.screen)),
+            _ => f(None),
+        }
+    }
+}
+
+impl
+    KernelResources<
+        stm32f746ng::chip::Stm32f7xx<
+            'static,
+            stm32f746ng::interrupt_service::Stm32f746ngDefaultPeripherals<'static>,
+        >,
+    > for STM32F746GDiscovery
+{
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type CredentialsCheckingPolicy = ();
+    type Scheduler = RoundRobinSched<'static>;
+    type SchedulerTimer = cortexm7::systick::SysTick;
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        &self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+    fn credentials_checking_policy(&self) -> &'static Self::CredentialsCheckingPolicy {
+        &()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.scheduler
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &self.systick
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// Helper function called during bring-up that configures multiplexed I/O.
+unsafe fn set_pin_primary_functions(
+    syscfg: &stm32f746ng::syscfg::Syscfg,
+    i2c3: &stm32f746ng::i2c::I2C,
+    gpio_ports: &'static stm32f746ng::gpio::GpioPorts<'static>,
+) {
+    use kernel::hil::gpio::Configure;
+    use stm32f746ng::gpio::{AlternateFunction, Mode, PinId, PortId};
+
+    syscfg.enable_clock();
+
+    // Enable clocks for GPIO Ports
+    // Disable some of them if you don't need some of the GPIOs
+    gpio_ports.get_port_from_port_id(PortId::A).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::B).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::C).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::D).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::E).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::F).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::G).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::H).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::I).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::J).enable_clock();
+    gpio_ports.get_port_from_port_id(PortId::K).enable_clock();
+
+    // User LD1 is connected to PI01. Configure PI01 as `debug_gpio!(0, ...)`
+    gpio_ports.get_pin(PinId::PI01).map(|pin| {
+        pin.make_output();
+
+        // Configure kernel debug gpios as early as possible
+        kernel::debug::assign_gpios(Some(pin), None, None);
+    });
+
+    // pa9 and pb7 (USART1) is connected to ST-LINK virtual COM port
+    gpio_ports.get_pin(PinId::PA09).map(|pin| {
+        pin.set_mode(Mode::AlternateFunctionMode);
+        // AF7 is USART1_TX
+        pin.set_alternate_function(AlternateFunction::AF7);
+    });
+    gpio_ports.get_pin(PinId::PB07).map(|pin| {
+        pin.set_mode(Mode::AlternateFunctionMode);
+        // AF7 is USART1_RX
+        pin.set_alternate_function(AlternateFunction::AF7);
+    });
+
+    // User button is connected on pi11
+    gpio_ports.get_pin(PinId::PI11).map(|pin| {
+        pin.enable_interrupt();
+    });
+
+    // I2C3 has the TouchPanel connected
+    gpio_ports.get_pin(PinId::PH07).map(|pin| {
+        pin.set_mode_output_opendrain();
+        pin.set_mode(Mode::AlternateFunctionMode);
+        pin.set_floating_state(kernel::hil::gpio::FloatingState::PullNone);
+        // AF4 is I2C
+        pin.set_alternate_function(AlternateFunction::AF4);
+    });
+    gpio_ports.get_pin(PinId::PH08).map(|pin| {
+        pin.set_mode_output_opendrain();
+        pin.set_floating_state(kernel::hil::gpio::FloatingState::PullNone);
+        pin.set_mode(Mode::AlternateFunctionMode);
+        // AF4 is I2C
+        pin.set_alternate_function(AlternateFunction::AF4);
+    });
+
+    i2c3.enable_clock();
+    i2c3.set_speed(stm32f746ng::i2c::I2CSpeed::Speed100k);
+
+    // FT5336 interrupt
+    gpio_ports.get_pin(PinId::PI13).map(|pin| {
+        pin.enable_interrupt();
+    });
+
+    // EXTI15_10 interrupts is delivered at IRQn 40 (EXTI15_10)
+    cortexm7::nvic::Nvic::new(stm32f746ng::nvic::EXTI15_10).enable();
+
+    // SDRAM
+
+    let pins = [
+        PinId::PC03,
+        PinId::PD00,
+        PinId::PD01,
+        PinId::PD08,
+        PinId::PD09,
+        PinId::PD10,
+        PinId::PD14,
+        PinId::PD15,
+        PinId::PE00,
+        PinId::PE01,
+        PinId::PE07,
+        PinId::PE08,
+        PinId::PE09,
+        PinId::PE10,
+        PinId::PE11,
+        PinId::PE12,
+        PinId::PE13,
+        PinId::PE14,
+        PinId::PE15,
+        PinId::PF00,
+        PinId::PF01,
+        PinId::PF02,
+        PinId::PF03,
+        PinId::PF04,
+        PinId::PF05,
+        PinId::PF11,
+        PinId::PF12,
+        PinId::PF13,
+        PinId::PF14,
+        PinId::PF15,
+        PinId::PG00,
+        PinId::PG01,
+        PinId::PG04,
+        PinId::PG05,
+        PinId::PG08,
+        PinId::PG15,
+        PinId::PH03,
+        PinId::PH05,
+    ];
+
+    for pin in pins.iter() {
+        gpio_ports.get_pin(*pin).map(|pin| {
+            pin.set_mode(Mode::AlternateFunctionMode);
+            pin.set_floating_state(gpio::FloatingState::PullNone);
+            pin.set_speed();
+            // AF12 is FMC
+            pin.set_alternate_function(AlternateFunction::AF12);
+        });
+    }
+
+    // LCD
+
+    let pins = [
+        PinId::PE04,
+        PinId::PI09,
+        PinId::PI10,
+        PinId::PI14,
+        PinId::PI15,
+        PinId::PJ00,
+        PinId::PJ01,
+        PinId::PJ02,
+        PinId::PJ03,
+        PinId::PJ04,
+        PinId::PJ05,
+        PinId::PJ06,
+        PinId::PJ07,
+        PinId::PJ08,
+        PinId::PJ09,
+        PinId::PJ10,
+        PinId::PJ11,
+        PinId::PJ13,
+        PinId::PJ14,
+        PinId::PJ15,
+        PinId::PK00,
+        PinId::PK01,
+        PinId::PK02,
+        PinId::PK04,
+        PinId::PK05,
+        PinId::PK06,
+        PinId::PK07,
+    ];
+
+    for pin in pins.iter() {
+        gpio_ports.get_pin(*pin).map(|pin| {
+            pin.set_mode(Mode::AlternateFunctionMode);
+            pin.set_floating_state(gpio::FloatingState::PullNone);
+            pin.set_speed();
+            // AF14 is LTDC
+            pin.set_alternate_function(AlternateFunction::AF14);
+        });
+    }
+
+    // LCD_B4 is the only LTDC signal on AF9
+    gpio_ports.get_pin(PinId::PG12).map(|pin| {
+        pin.set_mode(Mode::AlternateFunctionMode);
+        pin.set_floating_state(gpio::FloatingState::PullNone);
+        pin.set_speed();
+        pin.set_alternate_function(AlternateFunction::AF9);
+    });
+}
+
+/// Helper function for miscellaneous peripheral functions
+unsafe fn setup_peripherals(tim2: &stm32f746ng::tim2::Tim2, fmc: &stm32f746ng::fmc::Fmc) {
+    // USART1 IRQn is 37
+    cortexm7::nvic::Nvic::new(stm32f746ng::nvic::USART1).enable();
+
+    // I2C3 IRQn are 72 and 73
+    cortexm7::nvic::Nvic::new(stm32f746ng::nvic::I2C3_EV).enable();
+    cortexm7::nvic::Nvic::new(stm32f746ng::nvic::I2C3_ER).enable();
+
+    // TIM2 IRQn is 28
+    tim2.enable_clock();
+    tim2.start();
+    cortexm7::nvic::Nvic::new(stm32f746ng::nvic::TIM2).enable();
+
+    // FMC
+    fmc.enable_clock();
+    fmc.init_sdram(&SDRAM_CONFIG);
+}
+
+/// Statically initialize the core peripherals for the chip.
+///
+/// This is in a separate, inline(never) function so that its stack frame is
+/// removed when this function returns. Otherwise, the stack space used for
+/// these static_inits is wasted.
+#[inline(never)]
+unsafe fn create_peripherals() -> (
+    &'static mut Stm32f746ngDefaultPeripherals<'static>,
+    &'static stm32f746ng::syscfg::Syscfg<'static>,
+) {
+    let rcc = static_init!(stm32f746ng::rcc::Rcc, stm32f746ng::rcc::Rcc::new());
+    // The peripherals below derive their clocks from the PLL
+    rcc.enable_pll_system_clock();
+
+    let syscfg = static_init!(
+        stm32f746ng::syscfg::Syscfg,
+        stm32f746ng::syscfg::Syscfg::new(rcc)
+    );
+
+    let exti = static_init!(
+        stm32f746ng::exti::Exti,
+        stm32f746ng::exti::Exti::new(syscfg)
+    );
+
+    let peripherals = static_init!(
+        Stm32f746ngDefaultPeripherals,
+        Stm32f746ngDefaultPeripherals::new(rcc, exti)
+    );
+    (peripherals, syscfg)
+}
+
+/// Main function.
+///
+/// This is called after RAM initialization is complete.
+#[no_mangle]
+pub unsafe fn main() {
+    stm32f746ng::init();
+
+    let (peripherals, syscfg) = create_peripherals();
+    peripherals.init();
+    let base_peripherals = &peripherals.stm32f7;
+
+    set_pin_primary_functions(syscfg, &base_peripherals.i2c3, &base_peripherals.gpio_ports);
+
+    setup_peripherals(&base_peripherals.tim2, &base_peripherals.fmc);
+
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+
+    let chip = static_init!(
+        stm32f746ng::chip::Stm32f7xx<Stm32f746ngDefaultPeripherals>,
+        stm32f746ng::chip::Stm32f7xx::new(peripherals)
+    );
+    CHIP = Some(chip);
+
+    // UART
+
+    // Create a shared UART channel for kernel debug.
+    base_peripherals.usart1.enable_clock();
+    let uart_mux = components::console::UartMuxComponent::new(&base_peripherals.usart1, 115200)
+        .finalize(components::uart_mux_component_static!());
+
+    io::WRITER.set_initialized();
+
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+    // Create the debugger object that handles calls to `debug!()`.
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    // LEDs
+
+    // Clock to Port I is enabled in `set_pin_primary_functions()`
+
+    let led = components::led::LedsComponent::new().finalize(components::led_component_static!(
+        LedHigh<'static, stm32f746ng::gpio::Pin>,
+        LedHigh::new(
+            base_peripherals
+                .gpio_ports
+                .get_pin(stm32f746ng::gpio::PinId::PI01)
+                .unwrap()
+        ),
+    ));
+
+    // BUTTONs
+    let button = components::button::ButtonComponent::new(
+        board_kernel,
+        capsules_core::button::DRIVER_NUM,
+        components::button_component_helper!(
+            stm32f746ng::gpio::Pin,
+            // User
+            (
+                base_peripherals
+                    .gpio_ports
+                    .get_pin(stm32f746ng::gpio::PinId::PI11)
+                    .unwrap(),
+                kernel::hil::gpio::ActivationMode::ActiveHigh,
+                kernel::hil::gpio::FloatingState::PullNone
+            )
+        ),
+    )
+    .finalize(components::button_component_static!(stm32f746ng::gpio::Pin));
+
+    // ALARM
+
+    let tim2 = &base_peripherals.tim2;
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(tim2).finalize(
+        components::alarm_mux_component_static!(stm32f746ng::tim2::Tim2),
+    );
+
+    let alarm = components::alarm::AlarmDriverComponent::new(
+        board_kernel,
+        capsules_core::alarm::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::alarm_component_static!(stm32f746ng::tim2::Tim2));
+
+    // GPIO
+    let gpio = GpioComponent::new(
+        board_kernel,
+        capsules_core::gpio::DRIVER_NUM,
+        components::gpio_component_helper!(
+            stm32f746ng::gpio::Pin,
+            // Arduino like RX/TX
+            0 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PC07).unwrap(), //D0
+            1 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PC06).unwrap(), //D1
+            2 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PG06).unwrap(), //D2
+            3 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PB04).unwrap(), //D3
+            4 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PG07).unwrap(), //D4
+            5 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PI00).unwrap(), //D5
+            6 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PH06).unwrap(), //D6
+            7 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PI03).unwrap(), //D7
+            8 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PI02).unwrap(), //D8
+            9 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PA15).unwrap(), //D9
+            // SPI Pins
+            10 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PA08).unwrap(), //D10
+            11 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PB15).unwrap(), //D11
+            12 => base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PB14).unwrap() //D12
+        ),
+    )
+    .finalize(components::gpio_component_static!(stm32f746ng::gpio::Pin));
+
+    // LCD
+
+    let ltdc = &peripherals.ltdc;
+    ltdc.set_pins(
+        base_peripherals
+            .gpio_ports
+            .get_pin(stm32f746ng::gpio::PinId::PI12)
+            .map(|pin| pin as &dyn gpio::Pin),
+        base_peripherals
+            .gpio_ports
+            .get_pin(stm32f746ng::gpio::PinId::PK03)
+            .map(|pin| pin as &dyn gpio::Pin),
+    );
+
+    // The framebuffer is at the start of the SDRAM, which is initialized in
+    // `setup_peripherals()`
+    let framebuffer = core::slice::from_raw_parts_mut(
+        stm32f746ng::fmc::SDRAM_BANK1_ADDRESS as *mut u8,
+        FRAMEBUFFER_LEN,
+    );
+    ltdc.init(&PANEL, framebuffer, ScreenPixelFormat::RGB_565)
+        .unwrap_or_else(|err| debug!("Failed to initialize the LCD: {:?}", err));
+    let _ = ltdc.set_power(true);
+
+    let screen = components::screen::ScreenComponent::new(
+        board_kernel,
+        capsules_extra::screen::DRIVER_NUM,
+        ltdc,
+        Some(ltdc),
+    )
+    .finalize(components::screen_component_static!(57600));
+
+    // FT5336
+
+    let mux_i2c = components::i2c::I2CMuxComponent::new(&base_peripherals.i2c3, None)
+        .finalize(components::i2c_mux_component_static!(stm32f746ng::i2c::I2C));
+
+    let ft5336 = components::ft5336::Ft5336Component::new(
+        mux_i2c,
+        0x38,
+        base_peripherals
+            .gpio_ports
+            .get_pin(stm32f746ng::gpio::PinId::PI13)
+            .unwrap(),
+        true,
+    )
+    .finalize(components::ft5336_component_static!(stm32f746ng::i2c::I2C));
+    let _ = ft5336.init();
+
+    let touch = components::touch::MultiTouchComponent::new(
+        board_kernel,
+        capsules_extra::touch::DRIVER_NUM,
+        ft5336,
+        Some(ft5336),
+        Some(ltdc),
+    )
+    .finalize(components::touch_component_static!());
+
+    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+    PROCESS_PRINTER = Some(process_printer);
+
+    // PROCESS CONSOLE
+    let process_console = components::process_console::ProcessConsoleComponent::new(
+        board_kernel,
+        uart_mux,
+        mux_alarm,
+        process_printer,
+        Some(reset),
+    )
+    .finalize(components::process_console_component_static!(
+        stm32f746ng::tim2::Tim2
+    ));
+    let _ = process_console.start();
+
+    let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
+        .finalize(components::round_robin_component_static!(NUM_PROCS));
+
+    let stm32f746g = STM32F746GDiscovery {
+        console,
+        ipc: kernel::ipc::IPC::new(
+            board_kernel,
+            kernel::ipc::DRIVER_NUM,
+            &memory_allocation_capability,
+        ),
+        led,
+        button,
+        alarm,
+        gpio,
+        touch,
+        screen,
+
+        scheduler,
+        systick: cortexm7::systick::SysTick::new_with_calibration(
+            stm32f746ng::rcc::PLL_SYSTEM_CLOCK_FREQUENCY,
+        ),
+    };
+
+    debug!("Initialization complete. Entering main loop");
+
+    extern "C" {
+        /// Beginning of the ROM region containing app images.
+        ///
+        /// This symbol is defined in the linker script.
+        static _sapps: u8;
+
+        /// End of the ROM region containing app images.
+        ///
+        /// This symbol is defined in the linker script.
+        static _eapps: u8;
+
+        /// Beginning of the RAM region for app memory.
+        ///
+        /// This symbol is defined in the linker script.
+        static mut _sappmem: u8;
+
+        /// End of the RAM region for app memory.
+        ///
+        /// This symbol is defined in the linker script.
+        static _eappmem: u8;
+    }
+
+    kernel::process::load_processes(
+        board_kernel,
+        chip,
+        core::slice::from_raw_parts(
+            &_sapps as *const u8,
+            &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+        ),
+        core::slice::from_raw_parts_mut(
+            &mut _sappmem as *mut u8,
+            &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
+        ),
+        &PROCESSES,
+        &FAULT_RESPONSE,
+        &process_management_capability,
+    )
+    .unwrap_or_else(|err| {
+        debug!("Error loading processes!");
+        debug!("{:?}", err);
+    });
+
+    board_kernel.kernel_loop(
+        &stm32f746g,
+        chip,
+        Some(&stm32f746g.ipc),
+        &main_loop_capability,
+    );
+}
@@ -0,0 +1,669 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tock kernel for the Seeed Studio XIAO nRF52840 Sense.
+//!
+//! It is based on nRF52840 SoC (Cortex M4 core with a BLE + IEEE 802.15.4 transceiver),
+//! with an LSM6DS3TR-C IMU and a PDM microphone on board.
+
+#![no_std]
+// Disable this attribute when documenting, as a workaround for
+// https://github.com/rust-lang/rust/issues/62184.
+#![cfg_attr(not(doc), no_main)]
+#![deny(missing_docs)]
+
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::hil::device_id::DeviceId;
+use kernel::hil::gpio::Configure;
+use kernel::hil::gpio::Output;
+use kernel::hil::led::LedLow;
+use kernel::hil::time::Counter;
+use kernel::hil::usb::Client;
+use kernel::platform::chip::Chip;
+use kernel::platform::mpu::MPU;
+use kernel::platform::{KernelResources, SyscallDriverLookup};
+use kernel::scheduler::round_robin::RoundRobinSched;
+#[allow(unused_imports)]
+use kernel::{create_capability, debug, debug_gpio, debug_verbose, static_init};
+
+use nrf52840::gpio::Pin;
+use nrf52840::interrupt_service::Nrf52840DefaultPeripherals;
+
+// Three-color LED.
+const LED_RED_PIN: Pin = Pin::P0_26;
+const LED_GREEN_PIN: Pin = Pin::P0_30;
+const LED_BLUE_PIN: Pin = Pin::P0_06;
+
+const LED_KERNEL_PIN: Pin = Pin::P0_26;
+
+const GPIO_D0: Pin = Pin::P0_02;
+const GPIO_D1: Pin = Pin::P0_03;
+const GPIO_D2: Pin = Pin::P0_28;
+const GPIO_D3: Pin = Pin::P0_29;
+const GPIO_D4: Pin = Pin::P0_04;
+const GPIO_D5: Pin = Pin::P0_05;
+const GPIO_D6: Pin = Pin::P1_11;
+const GPIO_D7: Pin = Pin::P1_12;
+const GPIO_D8: Pin = Pin::P1_13;
+const GPIO_D9: Pin = Pin::P1_14;
+const GPIO_D10: Pin = Pin::P1_15;
+
+/// I2C pins of the IMU.
+const I2C_SDA_PIN: Pin = Pin::P0_07;
+const I2C_SCL_PIN: Pin = Pin::P0_27;
+
+/// GPIO supplying the IMU.
+const IMU_POWER_PIN: Pin = Pin::P1_08;
+/// I2C address of the IMU.
+const IMU_ADDRESS: u8 = 0x6A;
+
+/// PDM microphone pins.
+const PDM_CLK_PIN: Pin = Pin::P1_00;
+const PDM_DIN_PIN: Pin = Pin::P0_16;
+/// GPIO supplying the microphone.
+const PDM_POWER_PIN: Pin = Pin::P1_10;
+/// The microphone has a sensitivity of -26 dBFS at 94 dB SPL.
+const PDM_FULL_SCALE_SPL: u8 = 120;
+
+// Constants related to the configuration of the 15.4 network stack
+/// Personal Area Network ID for the IEEE 802.15.4 radio
+const PAN_ID: u16 = 0xABCD;
+
+/// UART Writer for panic!()s.
+pub mod io;
+
+// How should the kernel respond when a process faults. For this board we choose
+// to stop the app and print a notice, but not immediately panic. This allows
+// users to debug their apps, but avoids issues with using the USB/CDC stack
+// synchronously for panic! too early after the board boots.
+const FAULT_RESPONSE: kernel::process::StopWithDebugFaultPolicy =
+    kernel::process::StopWithDebugFaultPolicy {};
+
+// Number of concurrent processes this platform supports.
+const NUM_PROCS: usize = 8;
+
+// State for loading and holding applications.
+static mut PROCESSES: [kernel::process::ProcessSlot; NUM_PROCS] =
+    [kernel::process::ProcessSlot::EMPTY; NUM_PROCS];
+
+static mut CHIP: Option<&'static nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>> = None;
+static mut PROCESS_PRINTER: Option<&'static kernel::process::ProcessPrinterText> = None;
+static mut CDC_REF_FOR_PANIC: Option<
+    &'static capsules_extra::usb::cdc::CdcAcm<
+        'static,
+        nrf52::usbd::Usbd,
+        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, nrf52::rtc::Rtc>,
+    >,
+> = None;
+static mut NRF52_POWER: Option<&'static nrf52840::power::Power> = None;
+
+/// Dummy buffer that causes the linker to reserve enough space for the stack.
+#[no_mangle]
+#[link_section = ".stack_buffer"]
+pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
+
+// Function for the CDC/USB stack to use to enter the Adafruit nRF52 Bootloader
+fn baud_rate_reset_bootloader_enter() {
+    unsafe {
+        // 0x57 is the magic value the Adafruit nRF52 Bootloader expects to
+        // show its UF2 drive, as defined by
+        // https://github.com/adafruit/Adafruit_nRF52_Bootloader/blob/master/src/main.c
+        NRF52_POWER.unwrap().set_gpregret(0x57);
+        cortexm4::scb::reset();
+    }
+}
+
+// Function for the process console to use to reboot the board.
+fn reset() -> ! {
+    unsafe {
+        cortexm4::scb::reset();
+    }
+    loop {
+        cortexm4::support::nop();
+    }
+}
+
+/// Supported drivers by the platform
+pub struct Platform {
+    ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
+        'static,
+        nrf52::ble_radio::Radio<'static>,
+        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
+            'static,
+            nrf52::rtc::Rtc<'static>,
+        >,
+    >,
+    ieee802154_radio: &'static capsules_extra::ieee802154::RadioDriver<'static>,
+    console: &'static capsules_core::console::Console<'static>,
+    pconsole: &'static capsules_core::process_console::ProcessConsole<
+        'static,
+        { capsules_core::process_console::DEFAULT_COMMAND_HISTORY_LEN },
+        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
+            'static,
+            nrf52::rtc::Rtc<'static>,
+        >,
+        components::process_console::Capability,
+    >,
+    lsm6dsoxtr: &'static capsules_extra::lsm6dsoxtr::Lsm6dsoxtrI2C<
+        'static,
+        capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, nrf52840::i2c::TWI<'static>>,
+    >,
+    ninedof: &'static capsules_extra::ninedof::NineDof<'static>,
+    temperature: &'static capsules_extra::temperature::TemperatureSensor<'static>,
+    sound_pressure: &'static capsules_extra::sound_pressure::SoundPressureSensor<'static>,
+    gpio: &'static capsules_core::gpio::GPIO<'static, nrf52::gpio::GPIOPin<'static>>,
+    led: &'static capsules_core::led::LedDriver<
+        'static,
+        LedLow<'static, nrf52::gpio::GPIOPin<'static>>,
+        3,
+    >,
+    adc: &'static capsules_core::adc::AdcVirtualized<'static>,
+    rng: &'static capsules_core::rng::RngDriver<'static>,
+    ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
+    alarm: &'static capsules_core::alarm::AlarmDriver<
+        'static,
+        capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<
+            'static,
+            nrf52::rtc::Rtc<'static>,
+        >,
+    >,
+    scheduler: &'static RoundRobinSched<'static>,
+    systick: cortexm4::systick::SysTick,
+}
+
+impl SyscallDriverLookup for Platform {
+    fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
+    where
+        F: FnOnce(Option<&dyn kernel::syscall::SyscallDriver>) -> R,
+    {
+        match driver_num {
+            capsules_core::console::DRIVER_NUM => f(Some(self.console)),
+            capsules_extra::lsm6dsoxtr::DRIVER_NUM => f(Some(self.lsm6dsoxtr)),
+            capsules_extra::ninedof::DRIVER_NUM => f(Some(self.ninedof)),
+            capsules_extra::temperature::DRIVER_NUM => f(Some(self.temperature)),
+            capsules_extra::sound_pressure::DRIVER_NUM => f(Some(self.sound_pressure)),
+            capsules_core::gpio::DRIVER_NUM => f(Some(self.gpio)),
+            capsules_core::alarm::DRIVER_NUM => f(Some(self.alarm)),
+            capsules_core::led::DRIVER_NUM => f(Some(self.led)),
+            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
+            capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
+            capsules_extra::ieee802154::DRIVER_NUM => f(Some(self.ieee802154_radio)),
+            kernel::ipc::DRIVER_NUM => f(Some(&self.ipc)),
+            _ => f(None),
+        }
+    }
+}
+
+impl KernelResources<nrf52::chip::NRF52<'static, Nrf52840DefaultPeripherals<'static>>>
+    for Platform
+{
+    type SyscallDriverLookup = Self;
+    type SyscallFilter = ();
+    type ProcessFault = ();
+    type CredentialsCheckingPolicy = ();
+    type Scheduler = RoundRobinSched<'static>;
+    type SchedulerTimer = cortexm4::systick::SysTick;
+    type WatchDog = ();
+    type ContextSwitchCallback = ();
+
+    fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
+        self
+    }
+    fn syscall_filter(&self) -> &Self::SyscallFilter {
+        &()
+    }
+    fn process_fault(&self) -> &Self::ProcessFault {
+        &()
+    }
+    fn credentials_checking_policy(&self) -> &'static Self::CredentialsCheckingPolicy {
+        &()
+    }
+    fn scheduler(&self) -> &Self::Scheduler {
+        self.scheduler
+    }
+    fn scheduler_timer(&self) -> &Self::SchedulerTimer {
+        &self.systick
+    }
+    fn watchdog(&self) -> &Self::WatchDog {
+        &()
+    }
+    fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
+        &()
+    }
+}
+
+/// This is in a separate, inline(never) function so that its stack frame is
+/// removed when this function returns. Otherwise, the stack space used for
+/// these static_inits is wasted.
+#[inline(never)]
+unsafe fn create_peripherals() -> &'static mut Nrf52840DefaultPeripherals<'static> {
+    // Initialize chip peripheral drivers
+    let nrf52840_peripherals = static_init!(
+        Nrf52840DefaultPeripherals,
+        Nrf52840DefaultPeripherals::new()
+    );
+
+    nrf52840_peripherals
+}
+
+/// Main function called after RAM initialized.
+#[no_mangle]
+pub unsafe fn main() {
+    nrf52840::init();
+
+    let nrf52840_peripherals = create_peripherals();
+
+    // set up circular peripheral dependencies
+    nrf52840_peripherals.init();
+    let base_peripherals = &nrf52840_peripherals.nrf52;
+
+    // Save a reference to the power module for resetting the board into the
+    // bootloader.
+    NRF52_POWER = Some(&base_peripherals.pwr_clk);
+
+    let board_kernel = static_init!(kernel::Kernel, kernel::Kernel::new(&PROCESSES));
+
+    //--------------------------------------------------------------------------
+    // CAPABILITIES
+    //--------------------------------------------------------------------------
+
+    // Create capabilities that the board needs to call certain protected kernel
+    // functions.
+    let process_management_capability =
+        create_capability!(capabilities::ProcessManagementCapability);
+    let main_loop_capability = create_capability!(capabilities::MainLoopCapability);
+    let memory_allocation_capability = create_capability!(capabilities::MemoryAllocationCapability);
+
+    //--------------------------------------------------------------------------
+    // DEBUG GPIO
+    //--------------------------------------------------------------------------
+
+    // Configure kernel debug GPIOs as early as possible. These are used by the
+    // `debug_gpio!(0, toggle)` macro. We configure these early so that the
+    // macro is available during most of the setup code and kernel execution.
+    kernel::debug::assign_gpios(
+        Some(&nrf52840_peripherals.gpio_port[LED_KERNEL_PIN]),
+        None,
+        None,
+    );
+
+    //--------------------------------------------------------------------------
+    // GPIO
+    //--------------------------------------------------------------------------
+
+    let gpio = components::gpio::GpioComponent::new(
+        board_kernel,
+        capsules_core::gpio::DRIVER_NUM,
+        components::gpio_component_helper!(
+            nrf52840::gpio::GPIOPin,
+            0 => &nrf52840_peripherals.gpio_port[GPIO_D0],
+            1 => &nrf52840_peripherals.gpio_port[GPIO_D1],
+            2 => &nrf52840_peripherals.gpio_port[GPIO_D2],
+            3 => &nrf52840_peripherals.gpio_port[GPIO_D3],
+            4 => &nrf52840_peripherals.gpio_port[GPIO_D4],
+            5 => &nrf52840_peripherals.gpio_port[GPIO_D5],
+            6 => &nrf52840_peripherals.gpio_port[GPIO_D6],
+            7 => &nrf52840_peripherals.gpio_port[GPIO_D7],
+            8 => &nrf52840_peripherals.gpio_port[GPIO_D8],
+            9 => &nrf52840_peripherals.gpio_port[GPIO_D9],
+            10 => &nrf52840_peripherals.gpio_port[GPIO_D10]
+        ),
+    )
+    .finalize(components::gpio_component_static!(nrf52840::gpio::GPIOPin));
+
+    //--------------------------------------------------------------------------
+    // LEDs
+    //--------------------------------------------------------------------------
+
+    let led = components::led::LedsComponent::new().finalize(components::led_component_static!(
+        LedLow<'static, nrf52840::gpio::GPIOPin>,
+        LedLow::new(&nrf52840_peripherals.gpio_port[LED_RED_PIN]),
+        LedLow::new(&nrf52840_peripherals.gpio_port[LED_GREEN_PIN]),
+        LedLow::new(&nrf52840_peripherals.gpio_port[LED_BLUE_PIN]),
+    ));
+
+    //--------------------------------------------------------------------------
+    // ALARM & TIMER
+    //--------------------------------------------------------------------------
+
+    let rtc = &base_peripherals.rtc;
+    let _ = rtc.start();
+
+    let mux_alarm = components::alarm::AlarmMuxComponent::new(rtc)
+        .finalize(components::alarm_mux_component_static!(nrf52::rtc::Rtc));
+    let alarm = components::alarm::AlarmDriverComponent::new(
+        board_kernel,
+        capsules_core::alarm::DRIVER_NUM,
+        mux_alarm,
+    )
+    .finalize(components::alarm_component_static!(nrf52::rtc::Rtc));
+
+    //--------------------------------------------------------------------------
+    // UART & CONSOLE & DEBUG
+    //--------------------------------------------------------------------------
+
+    // Setup the CDC-ACM over USB driver that we will use for UART.
+    // We use the Seeed Vendor ID and Product ID since the device is the same.
+
+    // Create the strings we include in the USB descriptor. The serial number
+    // is derived from the unique device ID in the FICR.
+    let serial_number_string: &'static str =
+        components::device_id::SerialNumberStringComponent::new(&nrf52::ficr::FICR_INSTANCE)
+            .finalize(components::serial_number_string_component_static!());
+    let strings = static_init!(
+        [&str; 3],
+        [
+            "Seeed Studio",                 // Manufacturer
+            "XIAO nRF52840 Sense - TockOS", // Product
+            serial_number_string,           // Serial number
+        ]
+    );
+
+    let cdc = components::cdc::CdcAcmComponent::new(
+        &nrf52840_peripherals.usbd,
+        capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+        0x2886,
+        0x8045,
+        strings,
+        mux_alarm,
+        Some(&baud_rate_reset_bootloader_enter),
+    )
+    .finalize(components::cdc_acm_component_static!(
+        nrf52::usbd::Usbd,
+        nrf52::rtc::Rtc
+    ));
+    CDC_REF_FOR_PANIC = Some(cdc); //for use by panic handler
+
+    // Process Printer for displaying process information.
+    let process_printer = components::process_printer::ProcessPrinterTextComponent::new()
+        .finalize(components::process_printer_text_component_static!());
+    PROCESS_PRINTER = Some(process_printer);
+
+    // Create a shared UART channel for the console and for kernel debug.
+    let uart_mux = components::console::UartMuxComponent::new(cdc, 115200)
+        .finalize(components::uart_mux_component_static!());
+
+    let pconsole = components::process_console::ProcessConsoleComponent::new(
+        board_kernel,
+        uart_mux,
+        mux_alarm,
+        process_printer,
+        Some(reset),
+    )
+    .finalize(components::process_console_component_static!(
+        nrf52::rtc::Rtc<'static>
+    ));
+
+    // Setup the console.
+    let console = components::console::ConsoleComponent::new(
+        board_kernel,
+        capsules_core::console::DRIVER_NUM,
+        uart_mux,
+    )
+    .finalize(components::console_component_static!());
+    // Create the debugger object that handles calls to `debug!()`.
+    components::debug_writer::DebugWriterComponent::new(uart_mux)
+        .finalize(components::debug_writer_component_static!());
+
+    //--------------------------------------------------------------------------
+    // RANDOM NUMBERS
+    //--------------------------------------------------------------------------
+
+    let rng = components::rng::RngComponent::new(
+        board_kernel,
+        capsules_core::rng::DRIVER_NUM,
+        &base_peripherals.trng,
+    )
+    .finalize(components::rng_component_static!());
+
+    //--------------------------------------------------------------------------
+    // ADC
+    //--------------------------------------------------------------------------
+    base_peripherals.adc.calibrate();
+
+    let adc_mux = components::adc::AdcMuxComponent::new(&base_peripherals.adc)
+        .finalize(components::adc_mux_component_static!(nrf52840::adc::Adc));
+
+    let adc_syscall =
+        components::adc::AdcVirtualComponent::new(board_kernel, capsules_core::adc::DRIVER_NUM)
+            .finalize(components::adc_syscall_component_helper!(
+                // A0
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput0)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+                // A1
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput1)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+                // A2
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput4)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+                // A3
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput5)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+                // A4
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput2)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+                // A5
+                components::adc::AdcComponent::new(
+                    adc_mux,
+                    nrf52840::adc::AdcChannelSetup::new(nrf52840::adc::AdcChannel::AnalogInput3)
+                )
+                .finalize(components::adc_component_static!(nrf52840::adc::Adc)),
+            ));
+
+    //--------------------------------------------------------------------------
+    // SENSORS
+    //--------------------------------------------------------------------------
+
+    // The IMU is supplied by a GPIO, and needs a few milliseconds after
+    // power up before it answers on the bus.
+    let _ = &nrf52840_peripherals.gpio_port[IMU_POWER_PIN].make_output();
+    let _ = &nrf52840_peripherals.gpio_port[IMU_POWER_PIN].set();
+    for _ in 0..1_000_000 {
+        cortexm4::support::nop();
+    }
+
+    let sensors_i2c_bus = components::i2c::I2CMuxComponent::new(&base_peripherals.twi0, None)
+        .finalize(components::i2c_mux_component_static!(nrf52840::i2c::TWI));
+    base_peripherals.twi0.configure(
+        nrf52840::pinmux::Pinmux::new(I2C_SCL_PIN as u32),
+        nrf52840::pinmux::Pinmux::new(I2C_SDA_PIN as u32),
+    );
+
+    let lsm6dsoxtr = components::lsm6dsox::Lsm6dsoxtrI2CComponent::new(
+        sensors_i2c_bus,
+        IMU_ADDRESS,
+        board_kernel,
+        capsules_extra::lsm6dsoxtr::DRIVER_NUM,
+    )
+    .finalize(components::lsm6ds_i2c_component_static!(nrf52840::i2c::TWI));
+
+    let ninedof = components::ninedof::NineDofComponent::new(
+        board_kernel,
+        capsules_extra::ninedof::DRIVER_NUM,
+    )
+    .finalize(components::ninedof_component_static!(lsm6dsoxtr));
+
+    let temperature = components::temperature::TemperatureComponent::new(
+        board_kernel,
+        capsules_extra::temperature::DRIVER_NUM,
+        lsm6dsoxtr,
+    )
+    .finalize(components::temperature_component_static!());
+
+    let _ = lsm6dsoxtr
+        .configure(
+            capsules_extra::lsm6dsoxtr::LSM6DSOXGyroDataRate::LSM6DSOX_GYRO_RATE_12_5_HZ,
+            capsules_extra::lsm6dsoxtr::LSM6DSOXAccelDataRate::LSM6DSOX_ACCEL_RATE_12_5_HZ,
+            capsules_extra::lsm6dsoxtr::LSM6DSOXAccelRange::LSM6DSOX_ACCEL_RANGE_2_G,
+            capsules_extra::lsm6dsoxtr::LSM6DSOXTRGyroRange::LSM6DSOX_GYRO_RANGE_250_DPS,
+            true,
+        )
+        .map_err(|e| {
+            panic!(
+                "ERROR Failed to start LSM6DS3TR-C sensor configuration ({:?})",
+                e
+            )
+        });
+
+    // The microphone is powered on when a process enables it.
+    let pdm = nrf52_components::NrfPdmComponent::new(
+        &base_peripherals.pdm,
+        PDM_CLK_PIN,
+        PDM_DIN_PIN,
+        PDM_FULL_SCALE_SPL,
+        Some(&nrf52840_peripherals.gpio_port[PDM_POWER_PIN]),
+    )
+    .finalize(nrf52_components::nrf_pdm_component_static!(512));
+
+    let sound_pressure = components::sound_pressure::SoundPressureComponent::new(
+        board_kernel,
+        capsules_extra::sound_pressure::DRIVER_NUM,
+        pdm,
+    )
+    .finalize(components::sound_pressure_component_static!());
+
+    //--------------------------------------------------------------------------
+    // WIRELESS
+    //--------------------------------------------------------------------------
+
+    let ble_radio = components::ble::BLEComponent::new(
+        board_kernel,
+        capsules_extra::ble_advertising_driver::DRIVER_NUM,
+        &base_peripherals.ble_radio,
+        mux_alarm,
+    )
+    .finalize(components::ble_component_static!(
+        nrf52840::rtc::Rtc,
+        nrf52840::ble_radio::Radio
+    ));
+
+    let aes_mux = components::ieee802154::MuxAes128ccmComponent::new(&base_peripherals.ecb)
+        .finalize(components::mux_aes128ccm_component_static!(
+            nrf52840::aes::AesECB
+        ));
+
+    let device_short_addr = nrf52840::ficr::FICR_INSTANCE.short_address();
+    let (ieee802154_radio, _mux_mac) = components::ieee802154::Ieee802154Component::new(
+        board_kernel,
+        capsules_extra::ieee802154::DRIVER_NUM,
+        &base_peripherals.ieee802154_radio,
+        aes_mux,
+        PAN_ID,
+        device_short_addr,
+    )
+    .finalize(components::ieee802154_component_static!(
+        nrf52840::ieee802154_radio::Radio,
+        nrf52840::aes::AesECB<'static>
+    ));
+    ieee802154_radio.set_channel_quality(&base_peripherals.ieee802154_radio);
+    kernel::hil::radio::RadioChannelQuality::set_energy_detect_client(
+        &base_peripherals.ieee802154_radio,
+        ieee802154_radio,
+    );
+
+    //--------------------------------------------------------------------------
+    // FINAL SETUP AND BOARD BOOT
+    //--------------------------------------------------------------------------
+
+    // Start all of the clocks. Low power operation will require a better
+    // approach than this.
+    nrf52_components::NrfClockComponent::new(&base_peripherals.clock).finalize(());
+
+    let scheduler = components::sched::round_robin::RoundRobinComponent::new(&PROCESSES)
+        .finalize(components::round_robin_component_static!(NUM_PROCS));
+
+    let platform = Platform {
+        ble_radio,
+        ieee802154_radio,
+        console,
+        pconsole,
+        lsm6dsoxtr,
+        ninedof,
+        temperature,
+        sound_pressure,
+        adc: adc_syscall,
+        led,
+        gpio,
+        rng,
+        alarm,
+        ipc: kernel::ipc::IPC::new(
+            board_kernel,
+            kernel::ipc::DRIVER_NUM,
+            &memory_allocation_capability,
+        ),
+        scheduler,
+        systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
+    };
+
+    let chip = static_init!(
+        nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>,
+        nrf52840::chip::NRF52::new(nrf52840_peripherals)
+    );
+    CHIP = Some(chip);
+
+    // Need to disable the MPU because the bootloader seems to set it up.
+    chip.mpu().clear_mpu();
+
+    // Configure the USB stack to enable a serial port over CDC-ACM.
+    cdc.enable();
+    cdc.attach();
+
+    debug!("Initialization complete. Entering main loop.");
+    let _ = platform.pconsole.start();
+
+    //--------------------------------------------------------------------------
+    // PROCESSES AND MAIN LOOP
+    //--------------------------------------------------------------------------
+
+    // These symbols are defined in the linker script.
+    extern "C" {
+        /// Beginning of the ROM region containing app images.
+        static _sapps: u8;
+        /// End of the ROM region containing app images.
+        static _eapps: u8;
+        /// Beginning of the RAM region for app memory.
+        static mut _sappmem: u8;
+        /// End of the RAM region for app memory.
+        static _eappmem: u8;
+    }
+
+    kernel::process::load_processes(
+        board_kernel,
+        chip,
+        core::slice::from_raw_parts(
+            &_sapps as *const u8,
+            &_eapps as *const u8 as usize - &_sapps as *const u8 as usize,
+        ),
+        core::slice::from_raw_parts_mut(
+            &mut _sappmem as *mut u8,
+            &_eappmem as *const u8 as usize - &_sappmem as *const u8 as usize,
+        ),
+        &PROCESSES,
+        &FAULT_RESPONSE,
+        &process_management_capability,
+    )
+    .unwrap_or_else(|err| {
+        debug!("Error loading processes!");
+        debug!("{:?}", err);
+    });
+
+    board_kernel.kernel_loop(&platform, chip, Some(&platform.ipc), &main_loop_capability);
+}
@@ -51,6 +51,22 @@
 //! );
 //! sam4l::adc::ADC0.set_client(adc);
 //! ```
+//!
+//! Timestamps
+//! ----------
+//!
+//! A board can give `AdcDedicated` a clock, with which each buffer of samples
+//! is timestamped. Processes can then read when a buffer started to fill and
+//! how long it took, and the sample rate that was actually achieved, to
+//! correct for an inaccurate ADC clock:
+//!
+//! ```rust,ignore
+//! let adc_clock = static_init!(
+//!     capsules_core::adc::TimeSampleClock<'static, sam4l::ast::Ast>,
+//!     capsules_core::adc::TimeSampleClock::new(ast)
+//! );
+//! adc.set_clock(adc_clock);
+//! ```
 
 use core::cell::Cell;
 use core::cmp;
@@ -58,6 +74,7 @@ use core::convert::TryFrom;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
+use kernel::hil::time::{Frequency, Ticks, Time};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -65,6 +82,7 @@ use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
 use crate::driver;
+use crate::resource_acl::ResourceAcl;
 use crate::virtualizers::virtual_adc::Operation;
 pub const DRIVER_NUM: usize = driver::NUM::Adc as usize;
 
@@ -75,6 +93,7 @@ pub struct AdcVirtualized<'a> {
     drivers: &'a [&'a dyn hil::adc::AdcChannel<'a>],
     apps: Grant<AppSys, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     current_process: OptionalCell<ProcessId>,
+    acl: OptionalCell<&'a dyn ResourceAcl>,
 }
 
 /// ADC syscall driver, used by applications to interact with ADC.
@@ -94,6 +113,8 @@ pub struct AdcDedicated<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> {
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<2>>,
     processid: OptionalCell<ProcessId>,
     channel: Cell<usize>,
+    acl: OptionalCell<&'a dyn ResourceAcl>,
+    clock: OptionalCell<&'a dyn SampleClock>,
 
     // ADC buffers
     adc_buf1: TakeCell<'static, [u16]>,
@@ -126,6 +147,15 @@ pub struct App {
     samples_outstanding: Cell<usize>,
     next_samples_outstanding: Cell<usize>,
     using_app_buf0: Cell<bool>,
+
+    // timing of buffered sampling, if there is a clock
+    frequency: Cell<u32>,
+    buffer_start_us: Cell<u32>,
+    buffer_timing: [Cell<(u32, u32)>; 2],
+    run_start_us: Cell<u32>,
+    run_us: Cell<u32>,
+    run_samples: Cell<u32>,
+    max_jitter_us: Cell<u32>,
 }
 
 impl Default for App {
@@ -136,6 +166,13 @@ impl Default for App {
             samples_outstanding: Cell::new(0),
             next_samples_outstanding: Cell::new(0),
             using_app_buf0: Cell::new(true),
+            frequency: Cell::new(0),
+            buffer_start_us: Cell::new(0),
+            buffer_timing: [Cell::new((0, 0)), Cell::new((0, 0))],
+            run_start_us: Cell::new(0),
+            run_us: Cell::new(0),
+            run_samples: Cell::new(0),
+            max_jitter_us: Cell::new(0),
         }
     }
 }
@@ -155,6 +192,50 @@ impl Default for AppSys {
 /// swap. In testing, it seems to keep up fine.
 pub const BUF_LEN: usize = 128;
 
+/// A clock to timestamp buffers of samples with.
+pub trait SampleClock {
+    /// Microseconds since an arbitrary point in time, wrapping around at
+    /// `u32::MAX`.
+    fn now_us(&self) -> u32;
+}
+
+/// A `SampleClock` that counts the ticks of a `Time`, e.g. the counter
+/// underneath the alarms.
+///
+/// The ticks are only counted when the clock is read, which has to happen
+/// at least once before `T` wraps around. Buffers are short enough for that
+/// while sampling; the first timestamp after the ADC was idle for longer is
+/// only meaningful relative to the following ones.
+pub struct TimeSampleClock<'a, T: Time> {
+    time: &'a T,
+    last: Cell<T::Ticks>,
+    ticks: Cell<u64>,
+}
+
+impl<'a, T: Time> TimeSampleClock<'a, T> {
+    pub fn new(time: &'a T) -> TimeSampleClock<'a, T> {
+        TimeSampleClock {
+            time,
+            last: Cell::new(time.now()),
+            ticks: Cell::new(0),
+        }
+    }
+}
+
+impl<'a, T: Time> SampleClock for TimeSampleClock<'a, T> {
+    fn now_us(&self) -> u32 {
+        let now = self.time.now();
+        let elapsed = now.wrapping_sub(self.last.get());
+        self.last.set(now);
+        let ticks = self.ticks.get() + elapsed.into_u32() as u64;
+        self.ticks.set(ticks);
+
+        let frequency = T::Frequency::frequency() as u64;
+        let us = (ticks / frequency) * 1_000_000 + (ticks % frequency) * 1_000_000 / frequency;
+        us as u32
+    }
+}
+
 impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A> {
     /// Create a new `Adc` application interface.
     ///
@@ -183,6 +264,8 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
             apps: grant,
             processid: OptionalCell::empty(),
             channel: Cell::new(0),
+            acl: OptionalCell::empty(),
+            clock: OptionalCell::empty(),
 
             // ADC buffers
             adc_buf1: TakeCell::new(adc_buf1),
@@ -386,6 +469,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
 
                                 // begin sampling
                                 app.using_app_buf0.set(true);
+                                self.start_timing(app, frequency);
                                 app.samples_remaining.set(request_len - len1 - len2);
                                 app.samples_outstanding.set(len1 + len2);
                                 self.adc
@@ -530,6 +614,7 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
 
                                 // begin sampling
                                 app.using_app_buf0.set(true);
+                                self.start_timing(app, frequency);
                                 self.adc
                                     .sample_highspeed(&chan, frequency, buf1, len1, buf2, len2)
                                     .map_or_else(
@@ -624,6 +709,63 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> AdcDedicated<'a, A>
         })
     }
 
+    /// Restrict the channels to the processes allowed by `acl`. Sampling a
+    /// channel the process may not use fails with `NODEVICE`.
+    pub fn set_acl(&self, acl: &'a dyn ResourceAcl) {
+        self.acl.set(acl);
+    }
+
+    /// Timestamp buffers of samples with `clock`.
+    pub fn set_clock(&self, clock: &'a dyn SampleClock) {
+        self.clock.set(clock);
+    }
+
+    /// Reset the timing of `app` as buffered sampling at `frequency` starts.
+    fn start_timing(&self, app: &App, frequency: u32) {
+        let now = self.clock.map_or(0, |clock| clock.now_us());
+        app.frequency.set(frequency);
+        app.buffer_start_us.set(now);
+        app.buffer_timing[0].set((now, 0));
+        app.buffer_timing[1].set((now, 0));
+        app.run_start_us.set(now);
+        app.run_us.set(0);
+        app.run_samples.set(0);
+        app.max_jitter_us.set(0);
+    }
+
+    /// Record the timing of app buffer `index`, which was just filled with
+    /// `samples` samples. The next buffer starts to fill now.
+    fn buffer_filled(&self, app: &App, index: usize, samples: usize) {
+        self.clock.map(|clock| {
+            let now = clock.now_us();
+            let start = app.buffer_start_us.get();
+            let duration = now.wrapping_sub(start);
+            app.buffer_timing[index].set((start, duration));
+            app.buffer_start_us.set(now);
+            app.run_us.set(now.wrapping_sub(app.run_start_us.get()));
+            app.run_samples
+                .set(app.run_samples.get().saturating_add(samples as u32));
+
+            // the duration the buffer should have taken at the requested rate
+            let nominal = samples as u64 * 1_000_000 / cmp::max(app.frequency.get(), 1) as u64;
+            let jitter = (duration as u64).abs_diff(nominal);
+            app.max_jitter_us.set(cmp::max(
+                app.max_jitter_us.get(),
+                cmp::min(jitter, u32::MAX as u64) as u32,
+            ));
+        });
+    }
+
+    /// The sample rate achieved since buffered sampling started, in mHz.
+    fn achieved_rate(app: &App) -> u32 {
+        let run_us = app.run_us.get() as u64;
+        if run_us == 0 {
+            return 0;
+        }
+        let rate = app.run_samples.get() as u64 * 1_000_000_000 / run_us;
+        cmp::min(rate, u32::MAX as u64) as u32
+    }
+
     fn get_resolution_bits(&self) -> usize {
         self.adc.get_resolution_bits()
     }
@@ -646,9 +788,16 @@ impl<'a> AdcVirtualized<'a> {
             drivers: drivers,
             apps: grant,
             current_process: OptionalCell::empty(),
+            acl: OptionalCell::empty(),
         }
     }
 
+    /// Restrict the channels to the processes allowed by `acl`. Sampling a
+    /// channel the process may not use fails with `NODEVICE`.
+    pub fn set_acl(&self, acl: &'a dyn ResourceAcl) {
+        self.acl.set(acl);
+    }
+
     /// Enqueue the command to be executed when the ADC is available.
     fn enqueue_command(
         &self,
@@ -1061,6 +1210,8 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> hil::adc::HighSpeedC
                         };
                         // if the app_buffer is filled, perform callback
                         if perform_callback {
+                            self.buffer_filled(app, if use0 { 0 } else { 1 }, buf_len / 2);
+
                             // actually schedule the callback
                             let len_chan = ((buf_len / 2) << 8) | (self.channel.get() & 0xFF);
                             kernel_data
@@ -1159,6 +1310,12 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
         frequency: usize,
         processid: ProcessId,
     ) -> CommandReturn {
+        if (1..=4).contains(&command_num)
+            && !self.acl.map_or(true, |acl| acl.allowed(processid, channel))
+        {
+            return CommandReturn::failure(ErrorCode::NODEVICE);
+        }
+
         // Return true if this app already owns the ADC capsule, if no app owns
         // the ADC capsule, or if the app that is marked as owning the ADC
         // capsule no longer exists.
@@ -1257,6 +1414,36 @@ impl<'a, A: hil::adc::Adc<'a> + hil::adc::AdcHighSpeed<'a>> SyscallDriver for Ad
                     CommandReturn::failure(ErrorCode::NOSUPPORT)
                 }
             }
+            // Get start timestamp and duration of app buffer `channel`
+            103 => {
+                if self.clock.is_none() {
+                    CommandReturn::failure(ErrorCode::NOSUPPORT)
+                } else if channel > 1 {
+                    CommandReturn::failure(ErrorCode::INVAL)
+                } else {
+                    self.apps
+                        .enter(processid, |app, _| {
+                            let (start, duration) = app.buffer_timing[channel].get();
+                            CommandReturn::success_u32_u32(start, duration)
+                        })
+                        .unwrap_or(CommandReturn::failure(ErrorCode::NOMEM))
+                }
+            }
+            // Get achieved sample rate in mHz and maximum jitter in us
+            104 => {
+                if self.clock.is_none() {
+                    CommandReturn::failure(ErrorCode::NOSUPPORT)
+                } else {
+                    self.apps
+                        .enter(processid, |app, _| {
+                            CommandReturn::success_u32_u32(
+                                Self::achieved_rate(app),
+                                app.max_jitter_us.get(),
+                            )
+                        })
+                        .unwrap_or(CommandReturn::failure(ErrorCode::NOMEM))
+                }
+            }
 
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
@@ -1288,6 +1475,9 @@ impl SyscallDriver for AdcVirtualized<'_> {
             0 => CommandReturn::success_u32(self.drivers.len() as u32),
 
             // Single sample.
+            1 if !self.acl.map_or(true, |acl| acl.allowed(processid, channel)) => {
+                CommandReturn::failure(ErrorCode::NODEVICE)
+            }
             1 => {
                 let res = self.enqueue_command(Operation::OneSample, channel, processid);
                 if res == Ok(()) {
@@ -0,0 +1,83 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Counters of hardware errors on buses.
+//!
+//! The bus muxes count the errors the hardware reports, e.g. NACKs on an I2C
+//! bus or framing errors on a UART. A lot of NACKs from one address on an
+//! otherwise healthy bus point to a device that is not connected properly,
+//! while errors on all devices of a bus point to its wiring. The counters are
+//! read through `BusErrors`, e.g. by `capsules_extra::bus_diagnostics`.
+
+use core::cell::Cell;
+
+/// Kinds of buses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bus {
+    I2c = 0,
+    Spi = 1,
+    Uart = 2,
+    Can = 3,
+}
+
+impl Bus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bus::I2c => "i2c",
+            Bus::Spi => "spi",
+            Bus::Uart => "uart",
+            Bus::Can => "can",
+        }
+    }
+}
+
+/// Error counters of a bus.
+pub trait BusErrors {
+    fn bus(&self) -> Bus;
+
+    /// Names of the counters, counter `i` is named by element `i`.
+    fn counter_names(&self) -> &'static [&'static str];
+
+    /// Number of errors counted by counter `index`, or 0 if there is no such
+    /// counter.
+    fn counter(&self, index: usize) -> u32;
+
+    /// Set all counters to 0.
+    fn reset_counters(&self);
+}
+
+/// `N` counters that saturate at `u32::MAX`.
+pub struct ErrorCounters<const N: usize> {
+    counts: [Cell<u32>; N],
+}
+
+impl<const N: usize> Default for ErrorCounters<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ErrorCounters<N> {
+    pub fn new() -> Self {
+        ErrorCounters {
+            counts: [(); N].map(|()| Cell::new(0)),
+        }
+    }
+
+    pub fn increment(&self, index: usize) {
+        if let Some(count) = self.counts.get(index) {
+            count.set(count.get().saturating_add(1));
+        }
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        self.counts.get(index).map_or(0, |count| count.get())
+    }
+
+    pub fn reset(&self) {
+        for count in self.counts.iter() {
+            count.set(0);
+        }
+    }
+}
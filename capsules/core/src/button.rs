@@ -25,6 +25,27 @@
 //! }
 //! ```
 //!
+//! Debouncing and events
+//! ---------------------
+//!
+//! By default processes are told about each edge of a button as it happens.
+//! A board can instead attach a `ButtonClassifier`, which debounces the edges
+//! and classifies them into clicks, double clicks and long presses, with
+//! timings configured per button:
+//!
+//! ```rust,ignore
+//! let classifier = static_init!(
+//!     capsules_core::button::ButtonClassifier<'static, VirtualMuxAlarm<'static, Rtc>, 1>,
+//!     capsules_core::button::ButtonClassifier::new(
+//!         classifier_alarm,
+//!         [capsules_core::button::ButtonConfig::default()],
+//!     )
+//! );
+//! classifier_alarm.set_alarm_client(classifier);
+//! classifier.set_client(button);
+//! button.set_classifier(classifier);
+//! ```
+//!
 //! Syscall Interface
 //! -----------------
 //!
@@ -51,16 +72,19 @@
 //!
 //! - `0`: Set callback for pin interrupts. Note setting this callback has
 //!   no reliance on individual pins being configured as interrupts. The
-//!   interrupt will be called with two parameters: the index of the button
-//!   that triggered the interrupt and the pressed (1) or not pressed (0) state
-//!   of the button.
+//!   interrupt will be called with three parameters: the index of the button
+//!   that triggered the interrupt, the pressed (1) or not pressed (0) state
+//!   of the button and, if the board classifies button events, the
+//!   `ButtonEvent`, 0 otherwise.
 
 use core::cell::Cell;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::gpio;
 use kernel::hil::gpio::{Configure, Input, InterruptWithValue};
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
 use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -89,6 +113,7 @@ pub struct Button<'a, P: gpio::InterruptPin<'a>> {
         gpio::FloatingState,
     )],
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    classifier: OptionalCell<&'a dyn EdgeClassifier<'a>>,
 }
 
 impl<'a, P: gpio::InterruptPin<'a>> Button<'a, P> {
@@ -109,22 +134,51 @@ impl<'a, P: gpio::InterruptPin<'a>> Button<'a, P> {
         Self {
             pins: pins,
             apps: grant,
+            classifier: OptionalCell::empty(),
         }
     }
 
+    /// Deliver the events of `classifier` instead of the raw edges of the
+    /// buttons.
+    pub fn set_classifier(&self, classifier: &'a dyn EdgeClassifier<'a>) {
+        self.classifier.set(classifier);
+    }
+
     fn get_button_state(&self, pin_num: u32) -> gpio::ActivationState {
         let pin = &self.pins[pin_num as usize];
         pin.0.read_activation(pin.1)
     }
+
+    /// The number of processes with interrupts enabled for button `pin_num`.
+    fn subscribers(&self, pin_num: u32) -> usize {
+        let mut count = 0;
+        self.apps.each(|_, cntr, _| {
+            if cntr.subscribe_map & (1 << pin_num) != 0 {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    fn notify(&self, pin_num: u32, event: usize) {
+        let button_state = self.get_button_state(pin_num);
+        self.apps.each(|_, cntr, upcalls| {
+            if cntr.subscribe_map & (1 << pin_num) != 0 {
+                upcalls
+                    .schedule_upcall(UPCALL_NUM, (pin_num as usize, button_state as usize, event))
+                    .ok();
+            }
+        });
+    }
 }
 
 /// ### `subscribe_num`
 ///
 /// - `0`: Set callback for pin interrupts. Note setting this callback has
 ///   no reliance on individual pins being configured as interrupts. The
-///   interrupt will be called with two parameters: the index of the button
-///   that triggered the interrupt and the pressed/not pressed state of the
-///   button.
+///   interrupt will be called with three parameters: the index of the button
+///   that triggered the interrupt, the pressed/not pressed state of the
+///   button and the `ButtonEvent`, or 0 for raw edges.
 const UPCALL_NUM: usize = 0;
 
 impl<'a, P: gpio::InterruptPin<'a>> SyscallDriver for Button<'a, P> {
@@ -228,25 +282,286 @@ impl<'a, P: gpio::InterruptPin<'a>> SyscallDriver for Button<'a, P> {
 
 impl<'a, P: gpio::InterruptPin<'a>> gpio::ClientWithValue for Button<'a, P> {
     fn fired(&self, pin_num: u32) {
-        // Read the value of the pin and get the button state.
-        let button_state = self.get_button_state(pin_num);
-        let interrupt_count = Cell::new(0);
-
-        // schedule callback with the pin number and value
-        self.apps.each(|_, cntr, upcalls| {
-            if cntr.subscribe_map & (1 << pin_num) != 0 {
-                interrupt_count.set(interrupt_count.get() + 1);
-                upcalls
-                    .schedule_upcall(UPCALL_NUM, (pin_num as usize, button_state as usize, 0))
-                    .ok();
-            }
-        });
-
         // It's possible we got an interrupt for a process that has since died
         // (and didn't unregister the interrupt). Lazily disable interrupts for
         // this button if so.
-        if interrupt_count.get() == 0 {
+        if self.subscribers(pin_num) == 0 {
             self.pins[pin_num as usize].0.disable_interrupts();
+            return;
+        }
+
+        // schedule callback with the pin number and value, or leave it to the
+        // classifier to tell processes about the edge later
+        self.classifier.map_or_else(
+            || self.notify(pin_num, 0),
+            |classifier| classifier.edge(pin_num as usize),
+        );
+    }
+}
+
+impl<'a, P: gpio::InterruptPin<'a>> ClassifierClient for Button<'a, P> {
+    fn pressed(&self, index: usize) -> bool {
+        index < self.pins.len()
+            && self.get_button_state(index as u32) == gpio::ActivationState::Active
+    }
+
+    fn event(&self, index: usize, event: ButtonEvent) {
+        if index < self.pins.len() {
+            self.notify(index as u32, event as usize);
+        }
+    }
+}
+
+/// Events of a button once its edges are debounced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Pressed = 1,
+    Released = 2,
+    /// The button was pressed and released once.
+    Click = 3,
+    /// The button was pressed and released twice in quick succession.
+    DoubleClick = 4,
+    /// The button is held down. Releasing it afterwards is not a click.
+    LongPress = 5,
+}
+
+/// Turns the raw edges of buttons into `ButtonEvent`s.
+pub trait EdgeClassifier<'a> {
+    fn set_client(&self, client: &'a dyn ClassifierClient);
+
+    /// Button `index` may have changed its state.
+    fn edge(&self, index: usize);
+}
+
+pub trait ClassifierClient {
+    /// Whether button `index` is currently pressed.
+    fn pressed(&self, index: usize) -> bool;
+
+    /// Button `index` had `event`.
+    fn event(&self, index: usize, event: ButtonEvent);
+}
+
+/// Timings of the events of a button, in milliseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonConfig {
+    /// How long a button must stay in a state before the state counts.
+    pub debounce_ms: u32,
+    /// How long a button must be held for a long press, or 0 to not detect
+    /// long presses.
+    pub long_press_ms: u32,
+    /// How soon after a click the button must be pressed again for a double
+    /// click, or 0 to not detect double clicks. Clicks are only reported once
+    /// this time has passed without a second press.
+    pub double_click_ms: u32,
+}
+
+impl Default for ButtonConfig {
+    fn default() -> Self {
+        ButtonConfig {
+            debounce_ms: 20,
+            long_press_ms: 1000,
+            double_click_ms: 300,
+        }
+    }
+}
+
+/// A timeout `dt` after `reference`.
+#[derive(Clone, Copy)]
+pub(crate) struct Timeout<T: Ticks> {
+    pub(crate) reference: T,
+    pub(crate) dt: T,
+}
+
+impl<T: Ticks> Timeout<T> {
+    pub(crate) fn expired(&self, now: T) -> bool {
+        !now.within_range(self.reference, self.reference.wrapping_add(self.dt))
+    }
+
+    pub(crate) fn remaining(&self, now: T) -> T {
+        if self.expired(now) {
+            T::from(0)
+        } else {
+            self.reference.wrapping_add(self.dt).wrapping_sub(now)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ButtonState<T: Ticks> {
+    /// The debounced state of the button.
+    pressed: bool,
+    /// The presses so far of a possible double click.
+    presses: u8,
+    /// A long press was reported for the current press.
+    long_press: bool,
+    /// Pending edge, the state of the button is read once it settled.
+    debounce: Option<Timeout<T>>,
+    /// End of a long press while pressed, or of the double click window
+    /// while released.
+    event: Option<Timeout<T>>,
+}
+
+/// Debounces and classifies the edges of `N` buttons with one alarm.
+///
+/// Edges of buttons with an index of `N` or more are ignored.
+pub struct ButtonClassifier<'a, A: Alarm<'a>, const N: usize> {
+    alarm: &'a A,
+    configs: [ButtonConfig; N],
+    states: [Cell<ButtonState<A::Ticks>>; N],
+    client: OptionalCell<&'a dyn ClassifierClient>,
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> ButtonClassifier<'a, A, N> {
+    pub fn new(alarm: &'a A, configs: [ButtonConfig; N]) -> Self {
+        ButtonClassifier {
+            alarm,
+            configs,
+            states: [(); N].map(|()| {
+                Cell::new(ButtonState {
+                    pressed: false,
+                    presses: 0,
+                    long_press: false,
+                    debounce: None,
+                    event: None,
+                })
+            }),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn timeout(&self, now: A::Ticks, ms: u32) -> Option<Timeout<A::Ticks>> {
+        Some(Timeout {
+            reference: now,
+            dt: self.alarm.ticks_from_ms(ms),
+        })
+    }
+
+    fn emit(&self, index: usize, event: ButtonEvent) {
+        self.client.map(|client| client.event(index, event));
+    }
+
+    /// The button settled after an edge.
+    fn settled(&self, index: usize, now: A::Ticks) {
+        let config = self.configs[index];
+        let mut state = self.states[index].get();
+        state.debounce = None;
+        let pressed = self.client.map_or(false, |client| client.pressed(index));
+        if pressed == state.pressed {
+            // Only a bounce.
+            self.states[index].set(state);
+            return;
+        }
+        state.pressed = pressed;
+
+        let mut event = None;
+        if pressed {
+            state.presses = state.presses.saturating_add(1);
+            state.long_press = false;
+            state.event = if config.long_press_ms > 0 {
+                self.timeout(now, config.long_press_ms)
+            } else {
+                None
+            };
+        } else if state.long_press {
+            state.presses = 0;
+            state.event = None;
+        } else if state.presses >= 2 {
+            event = Some(ButtonEvent::DoubleClick);
+            state.presses = 0;
+            state.event = None;
+        } else if config.double_click_ms == 0 {
+            event = Some(ButtonEvent::Click);
+            state.presses = 0;
+            state.event = None;
+        } else {
+            state.event = self.timeout(now, config.double_click_ms);
+        }
+        self.states[index].set(state);
+
+        self.emit(
+            index,
+            if pressed {
+                ButtonEvent::Pressed
+            } else {
+                ButtonEvent::Released
+            },
+        );
+        if let Some(event) = event {
+            self.emit(index, event);
+        }
+    }
+
+    /// A long press or the double click window ended.
+    fn timed_out(&self, index: usize) {
+        let mut state = self.states[index].get();
+        state.event = None;
+        let event = if state.pressed {
+            state.long_press = true;
+            Some(ButtonEvent::LongPress)
+        } else if state.presses == 1 {
+            state.presses = 0;
+            Some(ButtonEvent::Click)
+        } else {
+            state.presses = 0;
+            None
+        };
+        self.states[index].set(state);
+        if let Some(event) = event {
+            self.emit(index, event);
+        }
+    }
+
+    /// Arm the alarm for the next timeout of any button, if any.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let next = self
+            .states
+            .iter()
+            .flat_map(|state| {
+                let state = state.get();
+                [state.debounce, state.event]
+            })
+            .flatten()
+            .map(|timeout| timeout.remaining(now))
+            .min();
+        match next {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> EdgeClassifier<'a> for ButtonClassifier<'a, A, N> {
+    fn set_client(&self, client: &'a dyn ClassifierClient) {
+        self.client.set(client);
+    }
+
+    fn edge(&self, index: usize) {
+        if index >= N {
+            return;
+        }
+        let mut state = self.states[index].get();
+        state.debounce = self.timeout(self.alarm.now(), self.configs[index].debounce_ms);
+        self.states[index].set(state);
+        self.arm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> time::AlarmClient for ButtonClassifier<'a, A, N> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for index in 0..N {
+            let state = self.states[index].get();
+            if state.debounce.map_or(false, |timeout| timeout.expired(now)) {
+                self.settled(index, now);
+            }
+            let state = self.states[index].get();
+            if state.event.map_or(false, |timeout| timeout.expired(now)) {
+                self.timed_out(index);
+            }
         }
+        self.arm();
     }
 }
@@ -40,6 +40,15 @@
 //! When the buffer has been written successfully, the buffer is released from
 //! the driver. Successive writes must call `allow` each time a buffer is to be
 //! written.
+//!
+//! Runtime configuration
+//! ---------------------
+//!
+//! If the board enables it with `Console::set_configure()`, command `4`
+//! changes the baud rate and framing of the underlying UART. As this affects
+//! every user of the UART (including kernel debug output), boards should
+//! restrict this command to privileged processes, for example with TBF header
+//! permissions and `TbfHeaderFilterDefaultAllow`.
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::uart;
@@ -52,6 +61,57 @@ use kernel::{ErrorCode, ProcessId};
 use crate::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Console as usize;
 
+/// Encoding of the framing argument of the configure command.
+mod framing {
+    /// Bits 0-1: `0` no parity, `1` odd parity, `2` even parity.
+    pub const PARITY_MASK: usize = 0b11;
+    /// Bit 2: set for two stop bits, clear for one.
+    pub const TWO_STOP_BITS: usize = 1 << 2;
+    /// Bit 3: set to enable hardware flow control.
+    pub const HW_FLOW_CONTROL: usize = 1 << 3;
+    /// Bits 4-7: word width in bits (6, 7 or 8). `0` selects 8 bits.
+    pub const WIDTH_SHIFT: usize = 4;
+    pub const WIDTH_MASK: usize = 0b1111;
+}
+
+/// Configure `uart` with the baud rate and framing arguments of the configure
+/// command. UARTs return `NOSUPPORT` for configurations they cannot support,
+/// which is passed on to the process.
+fn reconfigure(
+    uart: &dyn uart::Configure,
+    baud_rate: usize,
+    framing: usize,
+) -> Result<(), ErrorCode> {
+    let parity = match framing & framing::PARITY_MASK {
+        0 => uart::Parity::None,
+        1 => uart::Parity::Odd,
+        2 => uart::Parity::Even,
+        _ => return Err(ErrorCode::INVAL),
+    };
+    let stop_bits = if framing & framing::TWO_STOP_BITS != 0 {
+        uart::StopBits::Two
+    } else {
+        uart::StopBits::One
+    };
+    let width = match (framing >> framing::WIDTH_SHIFT) & framing::WIDTH_MASK {
+        0 | 8 => uart::Width::Eight,
+        7 => uart::Width::Seven,
+        6 => uart::Width::Six,
+        _ => return Err(ErrorCode::INVAL),
+    };
+    if baud_rate == 0 || baud_rate > u32::MAX as usize {
+        return Err(ErrorCode::INVAL);
+    }
+
+    uart.configure(uart::Parameters {
+        baud_rate: baud_rate as u32,
+        width,
+        parity,
+        stop_bits,
+        hw_flow_control: framing & framing::HW_FLOW_CONTROL != 0,
+    })
+}
+
 /// Default size for the read and write buffers used by the console.
 /// Boards may pass different-size buffers if needed.
 pub const DEFAULT_BUF_SIZE: usize = 64;
@@ -96,6 +156,7 @@ pub struct Console<'a> {
     tx_buffer: TakeCell<'static, [u8]>,
     rx_in_progress: OptionalCell<ProcessId>,
     rx_buffer: TakeCell<'static, [u8]>,
+    configure: OptionalCell<&'a dyn uart::Configure>,
 }
 
 impl<'a> Console<'a> {
@@ -117,9 +178,29 @@ impl<'a> Console<'a> {
             tx_buffer: TakeCell::new(tx_buffer),
             rx_in_progress: OptionalCell::empty(),
             rx_buffer: TakeCell::new(rx_buffer),
+            configure: OptionalCell::empty(),
         }
     }
 
+    /// Allow processes to reconfigure the UART at runtime through this
+    /// console. Without this the configure command returns `NOSUPPORT`.
+    pub fn set_configure(&self, configure: &'a dyn uart::Configure) {
+        self.configure.set(configure);
+    }
+
+    /// Internal helper function for reconfiguring the UART.
+    fn configure(&self, baud_rate: usize, framing: usize) -> Result<(), ErrorCode> {
+        let configure = self.configure.extract().ok_or(ErrorCode::NOSUPPORT)?;
+
+        // Changing the configuration in the middle of a transmission would
+        // corrupt it.
+        if self.tx_in_progress.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        reconfigure(configure, baud_rate, framing)
+    }
+
     /// Internal helper function for setting up a new send transaction
     fn send_new(
         &self,
@@ -265,11 +346,16 @@ impl SyscallDriver for Console<'_> {
     ///        passed in `arg1`
     /// - `3`: Cancel any in progress receives and return (via callback)
     ///        what has been received so far.
+    /// - `4`: Reconfigure the UART with the baud rate in `arg1` and the
+    ///        framing in `arg2`: bits 0-1 parity (none, odd, even), bit 2 two
+    ///        stop bits, bit 3 hardware flow control, bits 4-7 word width (`0`
+    ///        for the default of 8). Returns `NOSUPPORT` if the board has not
+    ///        enabled runtime configuration.
     fn command(
         &self,
         cmd_num: usize,
         arg1: usize,
-        _: usize,
+        arg2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
         let res = self
@@ -292,6 +378,7 @@ impl SyscallDriver for Console<'_> {
                         let _ = self.uart.receive_abort();
                         Ok(())
                     }
+                    4 => self.configure(arg1, arg2),
                     _ => Err(ErrorCode::NOSUPPORT),
                 }
             })
@@ -460,3 +547,59 @@ impl uart::ReceiveClient for Console<'_> {
         self.rx_buffer.replace(buffer);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{framing, reconfigure};
+    use core::cell::Cell;
+    use kernel::hil::uart;
+    use kernel::ErrorCode;
+
+    /// A UART that, like the STM32 USARTs, only supports 115200 8N1.
+    struct FixedUart {
+        configured: Cell<Option<u32>>,
+    }
+
+    impl uart::Configure for FixedUart {
+        fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+            if params.baud_rate != 115200
+                || params.stop_bits != uart::StopBits::One
+                || params.parity != uart::Parity::None
+                || params.width != uart::Width::Eight
+            {
+                return Err(ErrorCode::NOSUPPORT);
+            }
+            self.configured.set(Some(params.baud_rate));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unsupported_configuration_fails() {
+        let uart = FixedUart {
+            configured: Cell::new(None),
+        };
+        assert_eq!(reconfigure(&uart, 9600, 0), Err(ErrorCode::NOSUPPORT));
+        assert_eq!(
+            reconfigure(&uart, 115200, framing::TWO_STOP_BITS),
+            Err(ErrorCode::NOSUPPORT)
+        );
+        assert_eq!(uart.configured.get(), None);
+
+        assert_eq!(reconfigure(&uart, 115200, 0), Ok(()));
+        assert_eq!(uart.configured.get(), Some(115200));
+    }
+
+    #[test]
+    fn invalid_framing() {
+        let uart = FixedUart {
+            configured: Cell::new(None),
+        };
+        assert_eq!(reconfigure(&uart, 115200, 3), Err(ErrorCode::INVAL));
+        assert_eq!(
+            reconfigure(&uart, 115200, 5 << framing::WIDTH_SHIFT),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(reconfigure(&uart, 0, 0), Err(ErrorCode::INVAL));
+    }
+}
@@ -0,0 +1,129 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Lets processes reserve CPU time with a deadline scheduler.
+//!
+//! A process reserves a budget of CPU time per period with the earliest
+//! deadline first scheduler (`kernel::scheduler::edf`). The scheduler admits
+//! the reservation only if it can still meet all deadlines, and if it does not
+//! exceed the share of the CPU the board lets a single process reserve. If the
+//! process still has work to do when its period ends, it missed its deadline
+//! and receives an upcall.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let scheduler = components::sched::edf::EDFComponent::new(mux_alarm, &PROCESSES, 200_000)
+//!     .finalize(components::edf_component_static!(
+//!         nrf52840::rtc::Rtc<'static>,
+//!         NUM_PROCS
+//!     ));
+//! let deadline = components::deadline::DeadlineComponent::new(
+//!     board_kernel,
+//!     capsules_core::deadline::DRIVER_NUM,
+//!     scheduler,
+//! )
+//! .finalize(components::deadline_component_static!(
+//!     kernel::scheduler::edf::EdfSched<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
+//!     >
+//! ));
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::scheduler::edf::{DeadlineMissClient, DeadlineScheduler};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Deadline as usize;
+
+#[derive(Default)]
+pub struct App {
+    /// Number of deadlines the process missed.
+    missed: usize,
+}
+
+pub struct Deadline<'a, S: DeadlineScheduler<'a>> {
+    scheduler: &'a S,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, S: DeadlineScheduler<'a>> Deadline<'a, S> {
+    pub fn new(
+        scheduler: &'a S,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Deadline {
+            scheduler,
+            apps: grant,
+        }
+    }
+}
+
+impl<'a, S: DeadlineScheduler<'a>> DeadlineMissClient for Deadline<'a, S> {
+    fn deadline_missed(&self, processid: ProcessId) {
+        let _ = self.apps.enter(processid, |app, upcalls| {
+            app.missed += 1;
+            upcalls.schedule_upcall(0, (app.missed, 0, 0)).ok();
+        });
+    }
+}
+
+impl<'a, S: DeadlineScheduler<'a>> SyscallDriver for Deadline<'a, S> {
+    // Setup callbacks.
+    //
+    // ### `subscribe_num`
+    //
+    // - `0`: Called when the process missed a deadline, with the number of
+    //   deadlines it missed so far.
+
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Reserve `data2` microseconds of CPU time every `data1`
+    ///   microseconds, replacing the current reservation. The first period
+    ///   starts now. Returns `NOMEM` if the reservation is not admitted,
+    ///   because of the other reservations or because it exceeds the share of
+    ///   the CPU one process can reserve, and `INVAL` if `data2` is 500 or
+    ///   less or larger than `data1`, or `data1` is too long.
+    /// - `2`: Remove the reservation. Returns `OFF` if there is none.
+    /// - `3`: Return the number of deadlines the process missed.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self
+                .scheduler
+                .reserve(processid, data1 as u32, data2 as u32)
+                .into(),
+
+            2 => self.scheduler.release(processid).into(),
+
+            3 => self
+                .apps
+                .enter(processid, |app, _| {
+                    CommandReturn::success_u32(app.missed as u32)
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
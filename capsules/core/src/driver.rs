@@ -22,10 +22,26 @@ pub enum NUM {
     AnalogComparator      = 0x00007,
     LowLevelDebug         = 0x00008,
     ReadOnlyState         = 0x00009,
+    GpioPort              = 0x0000A,
     Pwm                   = 0x00010,
 
     // Kernel
     Ipc                   = 0x10000,
+    FactoryReset          = 0x10001,
+    ProcessInfo           = 0x10002,
+    Deadline              = 0x10003,
+    SchedulerControl      = 0x10004,
+    ProcessLoader         = 0x10005,
+    AppUpdate             = 0x10006,
+    ProcessSnapshot       = 0x10007,
+    RestartPolicy         = 0x10008,
+    MessageQueue          = 0x10009,
+    Passthrough           = 0x1000A,
+    IpcRegistry           = 0x1000B,
+    BufferLending         = 0x1000C,
+    SyscallRing           = 0x1000D,
+    SystemInfo            = 0x1000E,
+    Telemetry             = 0x1000F,
 
     // HW Buses
     Spi                   = 0x20001,
@@ -34,6 +50,9 @@ pub enum NUM {
     UsbUser               = 0x20005,
     I2cMasterSlave        = 0x20006,
     Can                   = 0x20007,
+    PulseGenerator        = 0x20008,
+    FrequencyCounter      = 0x20009,
+    PulseCounter          = 0x2000A,
 
     // Radio
     BleAdvertising        = 0x30000,
@@ -41,6 +60,9 @@ pub enum NUM {
     Udp                   = 0x30002,
     LoRaPhySPI            = 0x30003,
     LoRaPhyGPIO           = 0x30004,
+    Coap                  = 0x30005,
+    MqttSn                = 0x30006,
+    Tcp                   = 0x30007,
 
     // Cryptography
     Rng                   = 0x40001,
@@ -49,12 +71,16 @@ pub enum NUM {
     CtapHid               = 0x40004,
     Sha                   = 0x40005,
     Aes                   = 0x40006,
+    Uuid                  = 0x40007,
 
     // Storage
     AppFlash              = 0x50000,
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     KVSystem              = 0x50003,
+    FileSystem            = 0x50004,
+    RecordLog             = 0x50005,
+    AppPreferences        = 0x50006,
 
     // Sensors
     Temperature           = 0x60000,
@@ -64,6 +90,7 @@ pub enum NUM {
     Proximity             = 0x60005,
     SoundPressure         = 0x60006,
     AirQuality            = 0x60007,
+    Blackboard            = 0x60008,
 
     // Sensor ICs
     Tsl2561               = 0x70000,
@@ -88,5 +115,8 @@ pub enum NUM {
     TextScreen            = 0x90003,
     SevenSegment          = 0x90004,
     KeyboardHid           = 0x90005,
+    BusDiagnostics        = 0x90006,
+    HidInput              = 0x90007,
+    Midi                  = 0x90008,
 }
 }
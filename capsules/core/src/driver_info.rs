@@ -0,0 +1,137 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Description of a syscall driver returned by a dedicated command.
+//!
+//! Command 0 of a driver tells userspace whether the driver exists, and
+//! keeps the return value each driver always had, e.g. the number of pins
+//! for GPIO. So that userspace libraries can also tell which version of the
+//! interface the driver implements, and which of its optional features the
+//! board supports, drivers return `success_u32_u32` from command
+//! `COMMAND_NUM` with:
+//!
+//! | Argument | Bits   | Field                                            |
+//! |----------|--------|--------------------------------------------------|
+//! | 0        | 0..16  | Version of the interface, starting at 1          |
+//! | 0        | 16..32 | Number of instances, e.g. pins or sensors, or 0  |
+//! | 1        | 0..32  | Bitmap of the optional features of the driver    |
+//!
+//! Each driver defines the bits of its features in a `feature` module, and
+//! increases its version when the semantics of a command change. Drivers
+//! that return `NOSUPPORT` from `COMMAND_NUM` describe themselves as version
+//! 0 without features.
+//!
+//! Usage
+//! -----
+//! ```rust,ignore
+//! mod feature {
+//!     /// Readings can be requested periodically
+//!     pub const PERIODIC: u32 = 1 << 0;
+//! }
+//!
+//! 0 => CommandReturn::success(),
+//!
+//! driver_info::COMMAND_NUM => DriverInfo::new(1)
+//!     .count(self.sensors.len())
+//!     .feature(feature::PERIODIC, self.periodic.is_some())
+//!     .into(),
+//! ```
+
+use kernel::syscall::CommandReturn;
+
+/// The command number that returns the description of a driver. It is the
+/// last command number, so that it does not collide with the commands of
+/// existing drivers.
+pub const COMMAND_NUM: usize = 0xffff_ffff;
+
+/// The description of a driver, as returned by command `COMMAND_NUM`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DriverInfo {
+    pub version: u16,
+    pub count: u16,
+    pub features: u32,
+}
+
+impl DriverInfo {
+    /// A driver implementing `version` of its interface, with no instances
+    /// and no features.
+    pub const fn new(version: u16) -> Self {
+        DriverInfo {
+            version,
+            count: 0,
+            features: 0,
+        }
+    }
+
+    /// Set the number of instances. Counts that do not fit saturate.
+    pub const fn count(mut self, count: usize) -> Self {
+        self.count = if count > u16::MAX as usize {
+            u16::MAX
+        } else {
+            count as u16
+        };
+        self
+    }
+
+    /// Set the bits of `feature` if `supported`.
+    pub const fn feature(mut self, feature: u32, supported: bool) -> Self {
+        if supported {
+            self.features |= feature;
+        }
+        self
+    }
+
+    /// Whether all bits of `feature` are set.
+    pub const fn supports(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+
+    /// The arguments of the command return.
+    pub const fn encode(&self) -> (u32, u32) {
+        (
+            self.version as u32 | (self.count as u32) << 16,
+            self.features,
+        )
+    }
+
+    /// The description in the arguments of a command return, as decoded by
+    /// userspace.
+    pub const fn decode(info: u32, features: u32) -> Self {
+        DriverInfo {
+            version: info as u16,
+            count: (info >> 16) as u16,
+            features,
+        }
+    }
+}
+
+impl From<DriverInfo> for CommandReturn {
+    fn from(info: DriverInfo) -> Self {
+        let (info, features) = info.encode();
+        CommandReturn::success_u32_u32(info, features)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let info = DriverInfo::new(2)
+            .count(13)
+            .feature(1 << 0, true)
+            .feature(1 << 1, false)
+            .feature(1 << 31, true);
+        assert_eq!(info.encode(), (0x000d_0002, 0x8000_0001));
+        assert_eq!(DriverInfo::decode(0x000d_0002, 0x8000_0001), info);
+        assert!(info.supports(1 << 31));
+        assert!(!info.supports(1 << 1 | 1 << 0));
+    }
+
+    #[test]
+    fn count_saturates() {
+        assert_eq!(DriverInfo::new(1).count(70_000).count, u16::MAX);
+    }
+}
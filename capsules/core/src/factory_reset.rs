@@ -0,0 +1,367 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Coordinated factory reset.
+//!
+//! Capsules that keep persistent state (the KV store, application state,
+//! 802.15.4 frame counters, ...) opt in to the factory reset by registering a
+//! `Region` with `FactoryReset`. When a factory reset is triggered, every
+//! registered region is erased in the order it was registered. Afterwards the
+//! board is reset with the board's reset function, or, if the board does not
+//! provide one, every process is restarted. Both happen from a deferred call,
+//! so that a process that triggered the reset gets its command return first.
+//!
+//! A factory reset can be triggered by:
+//!
+//! - the `factory_reset` process console command,
+//! - holding a button for a while, with `LongPressTrigger`,
+//! - a process, with command 1 of this syscall driver. Only processes whose
+//!   name is in the privileged list passed to `FactoryReset::new()` may do
+//!   so.
+//!
+//! `FlashRegion` erases a range of flash pages and is suitable for most
+//! regions.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let factory_reset = components::factory_reset::FactoryResetComponent::new(
+//!     board_kernel,
+//!     &["settings"],
+//!     Some(reset_function),
+//! )
+//! .finalize(components::factory_reset_component_static!());
+//!
+//! let kv_region = components::factory_reset::FlashRegionComponent::new(kv_flash, 64, 32)
+//!     .finalize(components::factory_reset_flash_region_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>
+//!     ));
+//! factory_reset.register(kv_region).unwrap();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::debug;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::flash;
+use kernel::hil::gpio;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FactoryReset as usize;
+
+/// Default number of regions that can be registered.
+pub const DEFAULT_MAX_REGIONS: usize = 4;
+
+/// Persistent state that is erased by a factory reset.
+pub trait Region<'a> {
+    fn set_client(&self, client: &'a dyn RegionClient);
+
+    /// Start erasing the region. On success, `erase_complete()` is called
+    /// once the region is erased.
+    fn erase(&self) -> Result<(), ErrorCode>;
+}
+
+pub trait RegionClient {
+    fn erase_complete(&self, result: Result<(), ErrorCode>);
+}
+
+/// Interface used by the triggers to start a factory reset.
+pub trait Trigger {
+    /// Start a factory reset. Returns `BUSY` if one is already running.
+    fn factory_reset(&self) -> Result<(), ErrorCode>;
+}
+
+pub struct FactoryReset<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize> {
+    kernel: &'static Kernel,
+    regions: [OptionalCell<&'a dyn Region<'a>>; MAX_REGIONS],
+    /// Index of the region being erased while a reset is running.
+    current: OptionalCell<usize>,
+    /// Whether the regions are erased and the reset or restart is pending on
+    /// `deferred_call`.
+    finishing: Cell<bool>,
+    deferred_call: DeferredCall,
+    /// Names of the processes allowed to trigger a reset.
+    privileged: &'a [&'a str],
+    reset_function: Option<fn() -> !>,
+    capability: C,
+}
+
+impl<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize>
+    FactoryReset<'a, C, MAX_REGIONS>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        privileged: &'a [&'a str],
+        reset_function: Option<fn() -> !>,
+        capability: C,
+    ) -> Self {
+        FactoryReset {
+            kernel,
+            regions: core::array::from_fn(|_| OptionalCell::empty()),
+            current: OptionalCell::empty(),
+            finishing: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+            privileged,
+            reset_function,
+            capability,
+        }
+    }
+
+    /// Add `region` to the regions erased by a factory reset.
+    pub fn register(&'a self, region: &'a dyn Region<'a>) -> Result<(), ErrorCode> {
+        let slot = self
+            .regions
+            .iter()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        region.set_client(self);
+        slot.set(region);
+        Ok(())
+    }
+
+    /// Start erasing regions from `index` on. Regions that fail to start
+    /// erasing are skipped.
+    fn erase_from(&self, index: usize) {
+        for (i, slot) in self.regions.iter().enumerate().skip(index) {
+            if let Some(region) = slot.extract() {
+                match region.erase() {
+                    Ok(()) => {
+                        self.current.set(i);
+                        return;
+                    }
+                    Err(e) => debug!("Factory reset: region {} failed to erase: {:?}", i, e),
+                }
+            }
+        }
+        self.finish();
+    }
+
+    /// All regions are erased: reset the board, or restart the processes,
+    /// once the current call stack (possibly a command of the process that
+    /// triggered the reset) has returned.
+    fn finish(&self) {
+        self.current.clear();
+        self.finishing.set(true);
+        self.deferred_call.set();
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| self.privileged.contains(&process.get_process_name()),
+            &self.capability,
+        )
+    }
+}
+
+impl<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize> Trigger
+    for FactoryReset<'a, C, MAX_REGIONS>
+{
+    fn factory_reset(&self) -> Result<(), ErrorCode> {
+        if self.current.is_some() || self.finishing.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        debug!("Factory reset");
+        self.erase_from(0);
+        Ok(())
+    }
+}
+
+impl<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize> RegionClient
+    for FactoryReset<'a, C, MAX_REGIONS>
+{
+    fn erase_complete(&self, result: Result<(), ErrorCode>) {
+        if let Some(index) = self.current.extract() {
+            if let Err(e) = result {
+                debug!("Factory reset: region {} failed to erase: {:?}", index, e);
+            }
+            self.erase_from(index + 1);
+        }
+    }
+}
+
+impl<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize> DeferredCallClient
+    for FactoryReset<'a, C, MAX_REGIONS>
+{
+    fn handle_deferred_call(&self) {
+        match self.reset_function {
+            Some(reset) => reset(),
+            None => self
+                .kernel
+                .process_each_capability(&self.capability, |process| process.try_restart(None)),
+        }
+        self.finishing.set(false);
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+/// Provide a syscall interface for triggering a factory reset.
+///
+/// ### `command_num`
+///
+/// - `0`: Driver existence check.
+/// - `1`: Start a factory reset. Returns `NODEVICE`, like a command denied by
+///   the kernel, if the calling process is not privileged. On success all
+///   processes are restarted, or the board resets, once the regions are
+///   erased.
+impl<'a, C: ProcessManagementCapability, const MAX_REGIONS: usize> SyscallDriver
+    for FactoryReset<'a, C, MAX_REGIONS>
+{
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => {
+                if !self.is_privileged(processid) {
+                    return CommandReturn::failure(ErrorCode::NODEVICE);
+                }
+                self.factory_reset().into()
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
+
+/// Region of `num_pages` flash pages starting at `start_page`.
+pub struct FlashRegion<'a, F: flash::Flash> {
+    flash: &'a F,
+    start_page: usize,
+    num_pages: usize,
+    /// Next page to erase while erasing.
+    next_page: Cell<usize>,
+    client: OptionalCell<&'a dyn RegionClient>,
+}
+
+impl<'a, F: flash::Flash> FlashRegion<'a, F> {
+    pub fn new(flash: &'a F, start_page: usize, num_pages: usize) -> Self {
+        FlashRegion {
+            flash,
+            start_page,
+            num_pages,
+            next_page: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    fn erase_next(&self) -> Result<(), ErrorCode> {
+        let page = self.next_page.get();
+        self.next_page.set(page + 1);
+        self.flash.erase_page(page)
+    }
+}
+
+impl<'a, F: flash::Flash> Region<'a> for FlashRegion<'a, F> {
+    fn set_client(&self, client: &'a dyn RegionClient) {
+        self.client.set(client);
+    }
+
+    fn erase(&self) -> Result<(), ErrorCode> {
+        if self.num_pages == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.next_page.set(self.start_page);
+        self.erase_next()
+    }
+}
+
+impl<'a, F: flash::Flash> flash::Client<F> for FlashRegion<'a, F> {
+    fn read_complete(&self, _read_buffer: &'static mut F::Page, _error: flash::Error) {}
+
+    fn write_complete(&self, _write_buffer: &'static mut F::Page, _error: flash::Error) {}
+
+    fn erase_complete(&self, error: flash::Error) {
+        let result = match error {
+            flash::Error::CommandComplete => {
+                if self.next_page.get() < self.start_page + self.num_pages {
+                    match self.erase_next() {
+                        Ok(()) => return,
+                        Err(e) => Err(e),
+                    }
+                } else {
+                    Ok(())
+                }
+            }
+            flash::Error::FlashError | flash::Error::FlashMemoryProtectionError => {
+                Err(ErrorCode::FAIL)
+            }
+        };
+        self.client.map(|client| client.erase_complete(result));
+    }
+}
+
+/// Trigger a factory reset when a button is held for `hold_ms`.
+pub struct LongPressTrigger<'a, A: Alarm<'a>, P: gpio::InterruptPin<'a>> {
+    alarm: &'a A,
+    pin: &'a P,
+    mode: gpio::ActivationMode,
+    hold_ms: u32,
+    reset: &'a dyn Trigger,
+}
+
+impl<'a, A: Alarm<'a>, P: gpio::InterruptPin<'a>> LongPressTrigger<'a, A, P> {
+    pub fn new(
+        alarm: &'a A,
+        pin: &'a P,
+        mode: gpio::ActivationMode,
+        hold_ms: u32,
+        reset: &'a dyn Trigger,
+    ) -> Self {
+        LongPressTrigger {
+            alarm,
+            pin,
+            mode,
+            hold_ms,
+            reset,
+        }
+    }
+
+    /// Configure the pin and start watching the button.
+    pub fn start(&self) {
+        self.pin.make_input();
+        self.pin.enable_interrupts(gpio::InterruptEdge::EitherEdge);
+    }
+
+    fn pressed(&self) -> bool {
+        self.pin.read_activation(self.mode) == gpio::ActivationState::Active
+    }
+}
+
+impl<'a, A: Alarm<'a>, P: gpio::InterruptPin<'a>> gpio::Client for LongPressTrigger<'a, A, P> {
+    fn fired(&self) {
+        if self.pressed() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.hold_ms));
+        } else {
+            let _ = self.alarm.disarm();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, P: gpio::InterruptPin<'a>> AlarmClient for LongPressTrigger<'a, A, P> {
+    fn alarm(&self) {
+        if self.pressed() {
+            let _ = self.reset.factory_reset();
+        }
+    }
+}
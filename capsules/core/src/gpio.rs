@@ -34,6 +34,27 @@
 //! }
 //! ```
 //!
+//! Watching pins
+//! -------------
+//!
+//! Processes can watch pins for level changes with command 10, which selects
+//! the edges to report and how long a level must stay before it counts. The
+//! debouncing is done in the kernel by a `GpioWatcher`, which a board
+//! attaches with one alarm for all pins:
+//!
+//! ```rust,ignore
+//! let watcher = static_init!(
+//!     capsules_core::gpio::GpioWatcher<'static, VirtualMuxAlarm<'static, Rtc>, 4>,
+//!     capsules_core::gpio::GpioWatcher::new(watcher_alarm)
+//! );
+//! watcher_alarm.set_alarm_client(watcher);
+//! watcher.set_client(gpio);
+//! gpio.set_watcher(watcher);
+//! ```
+//!
+//! Without a watcher, command 10 only accepts a debounce interval of 0, and
+//! reports every edge like command 7.
+//!
 //! Syscall Interface
 //! -----------------
 //!
@@ -45,20 +66,35 @@
 //!
 //! Commands control and query GPIO information, namely how many GPIOs are
 //! present, the GPIO direction and state, and whether they should interrupt.
+//! Command `driver_info::COMMAND_NUM` describes the driver as in
+//! `capsules_core::driver_info`, with the number of pins as its count.
 //!
 //! ### Subscribes
 //!
 //! The GPIO interface provides only one callback, which is used for pins that
-//! have had interrupts enabled.
+//! have had interrupts enabled or are watched.
+//!
+//! ### Access Control
+//!
+//! A board can restrict pins to some processes with `set_acl()`. Commands on
+//! a pin the process may not use fail with `NODEVICE`, and the process gets
+//! no interrupts from it.
 
 /// Syscall driver number.
+use crate::button::Timeout;
 use crate::driver;
+use crate::driver_info::{self, DriverInfo};
+use crate::resource_acl::ResourceAcl;
 pub const DRIVER_NUM: usize = driver::NUM::Gpio as usize;
 
+use core::cell::Cell;
+
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::gpio;
 use kernel::hil::gpio::{Configure, Input, InterruptWithValue, Output};
+use kernel::hil::time::{self, Alarm, ConvertTicks};
 use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// ### `subscribe_num`
@@ -67,9 +103,19 @@ use kernel::{ErrorCode, ProcessId};
 ///        The callback signature is `fn(pin_num: usize, pin_state: bool)`
 const UPCALL_NUM: usize = 0;
 
+/// Optional features reported by command `driver_info::COMMAND_NUM`.
+pub mod feature {
+    /// Pins are restricted to some processes
+    pub const ACCESS_CONTROL: u32 = 1 << 0;
+    /// Watched pins are debounced
+    pub const DEBOUNCE: u32 = 1 << 1;
+}
+
 pub struct GPIO<'a, IP: gpio::InterruptPin<'a>> {
     pins: &'a [Option<&'a gpio::InterruptValueWrapper<'a, IP>>],
     apps: Grant<(), UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    acl: OptionalCell<&'a dyn ResourceAcl>,
+    watcher: OptionalCell<&'a dyn PinWatcher<'a>>,
 }
 
 impl<'a, IP: gpio::InterruptPin<'a>> GPIO<'a, IP> {
@@ -85,9 +131,26 @@ impl<'a, IP: gpio::InterruptPin<'a>> GPIO<'a, IP> {
         Self {
             pins: pins,
             apps: grant,
+            acl: OptionalCell::empty(),
+            watcher: OptionalCell::empty(),
         }
     }
 
+    /// Restrict the pins to the processes allowed by `acl`.
+    pub fn set_acl(&self, acl: &'a dyn ResourceAcl) {
+        self.acl.set(acl);
+    }
+
+    /// Debounce the pins watched with command 10 with `watcher`.
+    pub fn set_watcher(&self, watcher: &'a dyn PinWatcher<'a>) {
+        self.watcher.set(watcher);
+    }
+
+    fn allowed(&self, processid: ProcessId, pin_index: usize) -> bool {
+        self.acl
+            .map_or(true, |acl| acl.allowed(processid, pin_index))
+    }
+
     fn configure_input_pin(&self, pin_num: u32, config: usize) -> CommandReturn {
         let maybe_pin = self.pins[pin_num as usize];
         if let Some(pin) = maybe_pin {
@@ -116,6 +179,7 @@ impl<'a, IP: gpio::InterruptPin<'a>> GPIO<'a, IP> {
         let pins = self.pins.as_ref();
         let index = pin_num as usize;
         if let Some(pin) = pins[index] {
+            self.unwatch(index);
             match config {
                 0 => {
                     let _ = pin.enable_interrupts(gpio::InterruptEdge::EitherEdge);
@@ -138,21 +202,85 @@ impl<'a, IP: gpio::InterruptPin<'a>> GPIO<'a, IP> {
             CommandReturn::failure(ErrorCode::NODEVICE)
         }
     }
+
+    /// Watch a pin with the edges in the low byte of `config` and the
+    /// debounce interval in milliseconds in the bytes above.
+    fn watch(&self, index: usize, config: usize) -> CommandReturn {
+        let edge = match config & 0xff {
+            0 => gpio::InterruptEdge::EitherEdge,
+            1 => gpio::InterruptEdge::RisingEdge,
+            2 => gpio::InterruptEdge::FallingEdge,
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+        let debounce_ms = (config >> 8) as u32;
+        if let Some(pin) = self.pins[index] {
+            match self.watcher.extract() {
+                Some(watcher) => {
+                    if let Err(err) = watcher.watch(index, edge, debounce_ms, pin.read()) {
+                        return CommandReturn::failure(err);
+                    }
+                    // The watcher filters the edges once the pin settled.
+                    let _ = pin.enable_interrupts(gpio::InterruptEdge::EitherEdge);
+                    CommandReturn::success()
+                }
+                None if debounce_ms == 0 => {
+                    let _ = pin.enable_interrupts(edge);
+                    CommandReturn::success()
+                }
+                None => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            }
+        } else {
+            CommandReturn::failure(ErrorCode::NODEVICE)
+        }
+    }
+
+    fn unwatch(&self, index: usize) {
+        self.watcher.map(|watcher| watcher.unwatch(index));
+    }
+
+    fn notify(&self, pin_num: usize, pin_state: bool) {
+        // schedule callback with the pin number and value
+        self.apps.each(|processid, _, upcalls| {
+            if self.allowed(processid, pin_num) {
+                upcalls
+                    .schedule_upcall(UPCALL_NUM, (pin_num, pin_state as usize, 0))
+                    .ok();
+            }
+        });
+    }
 }
 
 impl<'a, IP: gpio::InterruptPin<'a>> gpio::ClientWithValue for GPIO<'a, IP> {
     fn fired(&self, pin_num: u32) {
-        // read the value of the pin
-        let pins = self.pins.as_ref();
-        if let Some(pin) = pins[pin_num as usize] {
-            let pin_state = pin.read();
+        let index = pin_num as usize;
+        // watched pins are reported once they settle
+        match self
+            .watcher
+            .extract()
+            .filter(|watcher| watcher.watching(index))
+        {
+            Some(watcher) => watcher.edge(index),
+            None => {
+                // read the value of the pin
+                if let Some(pin) = self.pins[index] {
+                    self.notify(index, pin.read());
+                }
+            }
+        }
+    }
+}
 
-            // schedule callback with the pin number and value
-            self.apps.each(|_, _, upcalls| {
-                upcalls
-                    .schedule_upcall(UPCALL_NUM, (pin_num as usize, pin_state as usize, 0))
-                    .ok();
-            });
+impl<'a, IP: gpio::InterruptPin<'a>> WatchClient for GPIO<'a, IP> {
+    fn level(&self, pin: usize) -> bool {
+        self.pins
+            .get(pin)
+            .and_then(|maybe_pin| *maybe_pin)
+            .map_or(false, |pin| pin.read())
+    }
+
+    fn changed(&self, pin: usize, level: bool) {
+        if pin < self.pins.len() {
+            self.notify(pin, level);
         }
     }
 }
@@ -175,6 +303,8 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
     ///                   Set to `0` to interrupt on either edge.
     ///                   Set to `1` for rising edge.
     ///                   Set to `2` for falling edge.
+    ///   - `watch_config`: `irq_config` in the low byte, and the debounce
+    ///                     interval in milliseconds in the bytes above.
     ///
     /// ### `command_num`
     ///
@@ -188,19 +318,35 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
     /// - `7`: Configure interrupt on `pin` with `irq_config` in 0x00XX00000
     /// - `8`: Disable interrupt on `pin`.
     /// - `9`: Disable `pin`.
+    /// - `10`: Watch `pin` for level changes with `watch_config`.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, with the number of
+    ///   pins as its count.
     fn command(
         &self,
         command_num: usize,
         data1: usize,
         data2: usize,
-        _: ProcessId,
+        processid: ProcessId,
     ) -> CommandReturn {
         let pins = self.pins.as_ref();
         let pin_index = data1;
+        if (1..=10).contains(&command_num)
+            && pin_index < pins.len()
+            && !self.allowed(processid, pin_index)
+        {
+            return CommandReturn::failure(ErrorCode::NODEVICE);
+        }
         match command_num {
             // number of pins
             0 => CommandReturn::success_u32(pins.len() as u32),
 
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1)
+                .count(pins.len())
+                .feature(feature::ACCESS_CONTROL, self.acl.is_some())
+                .feature(feature::DEBOUNCE, self.watcher.is_some())
+                .into(),
+
             // enable output
             1 => {
                 if pin_index >= pins.len() {
@@ -307,6 +453,7 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
                     CommandReturn::failure(ErrorCode::INVAL)
                 } else {
                     if let Some(pin) = pins[pin_index] {
+                        self.unwatch(pin_index);
                         pin.disable_interrupts();
                         pin.deactivate_to_low_power();
                         CommandReturn::success()
@@ -323,6 +470,7 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
                     CommandReturn::failure(ErrorCode::INVAL)
                 } else {
                     if let Some(pin) = pins[pin_index] {
+                        self.unwatch(pin_index);
                         pin.deactivate_to_low_power();
                         CommandReturn::success()
                     } else {
@@ -331,6 +479,17 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
                 }
             }
 
+            // watch pin for level changes
+            10 => {
+                let watch_config = data2;
+                if pin_index >= pins.len() {
+                    /* impossible pin */
+                    CommandReturn::failure(ErrorCode::INVAL)
+                } else {
+                    self.watch(pin_index, watch_config)
+                }
+            }
+
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -340,3 +499,181 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
         self.apps.enter(processid, |_, _| {})
     }
 }
+
+/// Debounces the edges of watched pins.
+pub trait PinWatcher<'a> {
+    fn set_client(&self, client: &'a dyn WatchClient);
+
+    /// Report the changes of `pin` to the levels of `edge`, once the pin
+    /// stayed at a level for `debounce_ms`. `level` is its current level.
+    /// Returns `INVAL` for pins the watcher has no state for.
+    fn watch(
+        &self,
+        pin: usize,
+        edge: gpio::InterruptEdge,
+        debounce_ms: u32,
+        level: bool,
+    ) -> Result<(), ErrorCode>;
+    fn unwatch(&self, pin: usize);
+    fn watching(&self, pin: usize) -> bool;
+
+    /// Watched pin `pin` may have changed its level.
+    fn edge(&self, pin: usize);
+}
+
+pub trait WatchClient {
+    /// The current level of `pin`.
+    fn level(&self, pin: usize) -> bool;
+
+    /// Watched pin `pin` settled at `level`.
+    fn changed(&self, pin: usize, level: bool);
+}
+
+#[derive(Clone, Copy)]
+struct WatchState<T: time::Ticks> {
+    /// The edges reported, or `None` if the pin is not watched.
+    edge: Option<gpio::InterruptEdge>,
+    debounce_ms: u32,
+    /// The debounced level of the pin.
+    level: bool,
+    /// Pending edge, the level of the pin is read once it settled.
+    debounce: Option<Timeout<T>>,
+}
+
+/// Debounces the edges of `N` pins with one alarm.
+///
+/// Pins with an index of `N` or more cannot be watched.
+pub struct GpioWatcher<'a, A: Alarm<'a>, const N: usize> {
+    alarm: &'a A,
+    states: [Cell<WatchState<A::Ticks>>; N],
+    client: OptionalCell<&'a dyn WatchClient>,
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> GpioWatcher<'a, A, N> {
+    pub fn new(alarm: &'a A) -> Self {
+        GpioWatcher {
+            alarm,
+            states: [(); N].map(|()| {
+                Cell::new(WatchState {
+                    edge: None,
+                    debounce_ms: 0,
+                    level: false,
+                    debounce: None,
+                })
+            }),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// The pin settled after an edge.
+    fn settled(&self, pin: usize) {
+        let mut state = self.states[pin].get();
+        state.debounce = None;
+        let level = self.client.map_or(state.level, |client| client.level(pin));
+        if level == state.level {
+            // Only a bounce.
+            self.states[pin].set(state);
+            return;
+        }
+        state.level = level;
+        self.states[pin].set(state);
+
+        let report = match state.edge {
+            Some(gpio::InterruptEdge::EitherEdge) => true,
+            Some(gpio::InterruptEdge::RisingEdge) => level,
+            Some(gpio::InterruptEdge::FallingEdge) => !level,
+            None => false,
+        };
+        if report {
+            self.client.map(|client| client.changed(pin, level));
+        }
+    }
+
+    /// Arm the alarm for the next pin to settle, if any.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let next = self
+            .states
+            .iter()
+            .filter_map(|state| state.get().debounce)
+            .map(|timeout| timeout.remaining(now))
+            .min();
+        match next {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> PinWatcher<'a> for GpioWatcher<'a, A, N> {
+    fn set_client(&self, client: &'a dyn WatchClient) {
+        self.client.set(client);
+    }
+
+    fn watch(
+        &self,
+        pin: usize,
+        edge: gpio::InterruptEdge,
+        debounce_ms: u32,
+        level: bool,
+    ) -> Result<(), ErrorCode> {
+        if pin >= N {
+            return Err(ErrorCode::INVAL);
+        }
+        self.states[pin].set(WatchState {
+            edge: Some(edge),
+            debounce_ms,
+            level,
+            debounce: None,
+        });
+        self.arm();
+        Ok(())
+    }
+
+    fn unwatch(&self, pin: usize) {
+        if pin < N {
+            let mut state = self.states[pin].get();
+            state.edge = None;
+            state.debounce = None;
+            self.states[pin].set(state);
+            self.arm();
+        }
+    }
+
+    fn watching(&self, pin: usize) -> bool {
+        pin < N && self.states[pin].get().edge.is_some()
+    }
+
+    fn edge(&self, pin: usize) {
+        if !self.watching(pin) {
+            return;
+        }
+        let mut state = self.states[pin].get();
+        if state.debounce_ms == 0 {
+            self.settled(pin);
+            return;
+        }
+        // Each edge restarts the interval the level must stay.
+        state.debounce = Some(Timeout {
+            reference: self.alarm.now(),
+            dt: self.alarm.ticks_from_ms(state.debounce_ms),
+        });
+        self.states[pin].set(state);
+        self.arm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, const N: usize> time::AlarmClient for GpioWatcher<'a, A, N> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for pin in 0..N {
+            let state = self.states[pin].get();
+            if state.debounce.map_or(false, |timeout| timeout.expired(now)) {
+                self.settled(pin);
+            }
+        }
+        self.arm();
+    }
+}
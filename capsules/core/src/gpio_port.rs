@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with access to several GPIO pins of a port at once.
+//!
+//! Where the `gpio` driver takes one system call per pin, this driver reads,
+//! sets, clears or toggles any subset of the pins of a port with a single
+//! command, e.g. to bit-bang a parallel bus or to drive an LED array. The
+//! board selects, for each port, the pins that processes may touch with a
+//! mask. The masks passed by processes are restricted to those pins.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! // Expose pins 8 to 15 of port 0.
+//! let port0 = static_init!(nrf52840::gpio::Bank, nrf52840::gpio::Bank::new(0));
+//! let ports = static_init!(
+//!     [capsules_core::gpio_port::ExposedPort<'static>; 1],
+//!     [capsules_core::gpio_port::ExposedPort {
+//!         port: port0,
+//!         mask: 0x0000_ff00,
+//!     }]
+//! );
+//! let gpio_port = static_init!(
+//!     capsules_core::gpio_port::GpioPort<'static>,
+//!     capsules_core::gpio_port::GpioPort::new(ports)
+//! );
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! All commands but 0 and the description take the port in `data1` and a mask or value in
+//! `data2`, in which bit `i` is pin `i` of the port.
+
+use crate::driver_info::{self, DriverInfo};
+use kernel::hil::gpio;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::GpioPort as usize;
+
+/// A port and the pins of it that processes may use.
+pub struct ExposedPort<'a> {
+    pub port: &'a dyn gpio::Port,
+    pub mask: u32,
+}
+
+pub struct GpioPort<'a> {
+    ports: &'a [ExposedPort<'a>],
+}
+
+impl<'a> GpioPort<'a> {
+    pub fn new(ports: &'a [ExposedPort<'a>]) -> Self {
+        GpioPort { ports }
+    }
+}
+
+impl<'a> SyscallDriver for GpioPort<'a> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return the number of ports.
+    /// - `1`: Make the pins `data2` of port `data1` outputs.
+    /// - `2`: Set the pins `data2` of port `data1` high.
+    /// - `3`: Set the pins `data2` of port `data1` low.
+    /// - `4`: Toggle the pins `data2` of port `data1`.
+    /// - `5`: Make the pins `data2` of port `data1` inputs.
+    /// - `6`: Read the pins of port `data1`. Pins that are not exposed read
+    ///   as 0.
+    /// - `7`: Set every exposed pin of port `data1` to its bit in `data2`.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, with the number of
+    ///   ports as its count.
+    ///
+    /// Pins that are not exposed by the board are ignored. Returns `INVAL`
+    /// if there is no port `data1`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success_u32(self.ports.len() as u32);
+        }
+        if command_num == driver_info::COMMAND_NUM {
+            return DriverInfo::new(1).count(self.ports.len()).into();
+        }
+        let exposed = match self.ports.get(data1) {
+            Some(exposed) => exposed,
+            None => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        let port = exposed.port;
+        let mask = data2 as u32 & exposed.mask;
+        match command_num {
+            1 => port.make_output(mask),
+            2 => port.set(mask),
+            3 => port.clear(mask),
+            4 => port.toggle(mask),
+            5 => port.make_input(mask),
+            6 => return CommandReturn::success_u32(port.read() & exposed.mask),
+            7 => port.write(exposed.mask, data2 as u32),
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+        CommandReturn::success()
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
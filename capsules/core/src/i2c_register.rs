@@ -0,0 +1,252 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Register access to I2C devices.
+//!
+//! Most I2C sensors are configured and read through 8-bit registers: a
+//! transfer starts with the address of a register, followed by the values
+//! to write, or by reading the values of that register and the ones after
+//! it. `I2CRegisterClient` does the buffer handling, enabling the bus and
+//! error handling of such transfers, so that a sensor driver only chains
+//! register reads and writes in its callbacks:
+//!
+//! ```rust,ignore
+//! self.registers.modify(CTRL_REG1, CTRL_REG1_ODR, field_set(0, CTRL_REG1_ODR, 2))?;
+//!
+//! impl RegisterClient for Sensor<'_> {
+//!     fn registers_written(&self, _reg: u8, result: Result<(), ErrorCode>) {
+//!         let _ = self.registers.read(OUT_X_L, 6);
+//!     }
+//!
+//!     fn registers_read(&self, _reg: u8, data: &[u8], result: Result<(), ErrorCode>) {
+//!         let x = i16::from_le_bytes([data[0], data[1]]);
+//!     }
+//! }
+//! ```
+//!
+//! Reads and writes of several registers set the `auto_increment` bits in
+//! the register address, for devices such as many ST sensors that only move
+//! to the next register when the address asks them to. The buffer must hold
+//! the register address and the values of the longest write, and the longest
+//! read, of at most `MAX_READ_LEN` registers.
+//!
+//! Usage
+//! -----
+//! ```rust,ignore
+//! let registers = static_init!(
+//!     capsules_core::i2c_register::I2CRegisterClient<'static>,
+//!     capsules_core::i2c_register::I2CRegisterClient::new(sensor_i2c, buffer, 0x80)
+//! );
+//! sensor_i2c.set_client(registers);
+//! registers.set_client(sensor);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::i2c::{Error, I2CClient, I2CDevice};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// The most registers one read returns.
+pub const MAX_READ_LEN: usize = 32;
+
+/// The value of the bits of `mask` in `register`, shifted down to bit 0.
+pub const fn field_get(register: u8, mask: u8) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    (register & mask) >> mask.trailing_zeros()
+}
+
+/// `register` with the bits of `mask` set to `value`, shifted up from bit 0.
+pub const fn field_set(register: u8, mask: u8, value: u8) -> u8 {
+    if mask == 0 {
+        return register;
+    }
+    (register & !mask) | ((value << mask.trailing_zeros()) & mask)
+}
+
+pub trait RegisterClient {
+    /// A read of the registers from `reg` finished. `data` holds their
+    /// values, or is empty if the read failed.
+    fn registers_read(&self, reg: u8, data: &[u8], result: Result<(), ErrorCode>);
+
+    /// A write, or read-modify-write, of the registers from `reg` finished.
+    fn registers_written(&self, reg: u8, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    Read {
+        reg: u8,
+        len: usize,
+    },
+    Write {
+        reg: u8,
+    },
+    /// Reading the register, to write `value` to the bits of `mask`.
+    ModifyRead {
+        reg: u8,
+        mask: u8,
+        value: u8,
+    },
+    ModifyWrite {
+        reg: u8,
+    },
+}
+
+pub struct I2CRegisterClient<'a> {
+    i2c: &'a dyn I2CDevice,
+    buffer: TakeCell<'static, [u8]>,
+    /// Bits set in the register address to access several registers.
+    auto_increment: u8,
+    operation: Cell<Operation>,
+    client: OptionalCell<&'a dyn RegisterClient>,
+}
+
+impl<'a> I2CRegisterClient<'a> {
+    pub fn new(i2c: &'a dyn I2CDevice, buffer: &'static mut [u8], auto_increment: u8) -> Self {
+        I2CRegisterClient {
+            i2c,
+            buffer: TakeCell::new(buffer),
+            auto_increment,
+            operation: Cell::new(Operation::Idle),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn RegisterClient) {
+        self.client.set(client);
+    }
+
+    /// Whether a transfer is in progress.
+    pub fn busy(&self) -> bool {
+        self.operation.get() != Operation::Idle
+    }
+
+    /// Read `len` registers from `reg`. Returns `BUSY` during another
+    /// transfer, and `SIZE` if the values do not fit in the buffer.
+    pub fn read(&self, reg: u8, len: usize) -> Result<(), ErrorCode> {
+        if len == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if len > MAX_READ_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        self.start(Operation::Read { reg, len }, reg, &[], len)
+    }
+
+    /// Write `values` to the registers from `reg`. Returns `BUSY` during
+    /// another transfer, and `SIZE` if the values do not fit in the buffer.
+    pub fn write(&self, reg: u8, values: &[u8]) -> Result<(), ErrorCode> {
+        if values.is_empty() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.start(Operation::Write { reg }, reg, values, 0)
+    }
+
+    /// Set the bits of `mask` in register `reg` to the ones of `value`, and
+    /// keep its other bits.
+    pub fn modify(&self, reg: u8, mask: u8, value: u8) -> Result<(), ErrorCode> {
+        self.start(Operation::ModifyRead { reg, mask, value }, reg, &[], 1)
+    }
+
+    /// Write the address of `reg` and `values`, then read `read_len` bytes.
+    fn start(
+        &self,
+        operation: Operation,
+        reg: u8,
+        values: &[u8],
+        read_len: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        let write_len = values.len() + 1;
+        if buffer.len() < write_len.max(read_len) {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[0] = if values.len().max(read_len) > 1 {
+            reg | self.auto_increment
+        } else {
+            reg
+        };
+        buffer[1..write_len].copy_from_slice(values);
+
+        self.i2c.enable();
+        let result = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+        match result {
+            Ok(()) => {
+                self.operation.set(operation);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                Err(error.into())
+            }
+        }
+    }
+}
+
+impl I2CClient for I2CRegisterClient<'_> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        let operation = self.operation.replace(Operation::Idle);
+        // Hand the buffer back before calling the client, so that it can
+        // start the next transfer right away.
+        let mut data = [0; MAX_READ_LEN];
+        if let Operation::Read { len, .. } = operation {
+            data[..len].copy_from_slice(&buffer[..len]);
+        }
+        let old_value = buffer[0];
+        self.buffer.replace(buffer);
+        self.i2c.disable();
+
+        let result: Result<(), ErrorCode> = status.map_err(Into::into);
+        match operation {
+            Operation::Idle => {}
+            Operation::Read { reg, len } => {
+                let len = if result.is_ok() { len } else { 0 };
+                self.client
+                    .map(|client| client.registers_read(reg, &data[..len], result));
+            }
+            Operation::Write { reg } | Operation::ModifyWrite { reg } => {
+                self.client
+                    .map(|client| client.registers_written(reg, result));
+            }
+            Operation::ModifyRead { reg, mask, value } => {
+                let result = result.and_then(|()| {
+                    let new_value = (old_value & !mask) | (value & mask);
+                    self.start(Operation::ModifyWrite { reg }, reg, &[new_value], 0)
+                });
+                if let Err(error) = result {
+                    self.client
+                        .map(|client| client.registers_written(reg, Err(error)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{field_get, field_set};
+
+    #[test]
+    fn bitfields() {
+        assert_eq!(field_get(0b1011_0110, 0b0011_1000), 0b110);
+        assert_eq!(field_get(0xff, 0), 0);
+        assert_eq!(field_set(0b1000_0001, 0b0011_1000, 0b101), 0b1010_1001);
+        // Bits of the value outside the field are dropped.
+        assert_eq!(field_set(0, 0b0000_1100, 0b111), 0b0000_1100);
+        assert_eq!(field_set(0x5a, 0, 0xff), 0x5a);
+    }
+}
@@ -0,0 +1,268 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Declarative sequences of I2C register operations.
+//!
+//! Most I2C sensor drivers configure the sensor by writing a few registers,
+//! wait for a conversion, and then read the result registers. Written by
+//! hand, every step is a state of the driver and every callback has to
+//! handle errors and move to the next state. `I2CSequence` runs such a list
+//! of operations instead, and calls its client once with the bytes that were
+//! read, or with the first error.
+//!
+//! Sequences are usually constants, declared with the `i2c_sequence!` macro:
+//!
+//! ```rust,ignore
+//! const READ_LUX: &[Op] = capsules_core::i2c_sequence![
+//!     write(0x00, 0b1010_0000, 0b0000_1001),
+//!     delay_us(410),
+//!     read(0x02, 2),
+//!     write(0x00, 0x00),
+//! ];
+//! ```
+//!
+//! `write` sends its bytes, usually a register address followed by the
+//! values to write. `read` writes the register address and reads `len`
+//! bytes, which are appended to the data handed to the client. `delay_us`
+//! waits before the next operation.
+//!
+//! The transfer buffer must hold the longest `write`, as well as the longest
+//! `read`. The data buffer must hold the bytes of all reads of a sequence,
+//! see `read_len()`.
+
+use core::cell::Cell;
+
+use kernel::hil::i2c::{Error, I2CClient, I2CDevice};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// One operation of a sequence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    /// Write the bytes.
+    Write(&'static [u8]),
+    /// Write the register address `reg`, then read `len` bytes.
+    Read { reg: u8, len: usize },
+    /// Wait for the given number of microseconds.
+    DelayUs(u32),
+}
+
+/// Declare a sequence of I2C operations, see the module documentation.
+#[macro_export]
+macro_rules! i2c_sequence {
+    (@op write $($byte:expr),+ $(,)?) => {
+        $crate::i2c_sequence::Op::Write(&[$($byte),+])
+    };
+    (@op read $reg:expr, $len:expr $(,)?) => {
+        $crate::i2c_sequence::Op::Read { reg: $reg, len: $len }
+    };
+    (@op delay_us $us:expr $(,)?) => {
+        $crate::i2c_sequence::Op::DelayUs($us)
+    };
+    ($($op:ident($($arg:expr),* $(,)?)),* $(,)?) => {
+        &[$($crate::i2c_sequence!(@op $op $($arg),*)),*]
+    };
+}
+
+/// The number of bytes the reads of `sequence` return.
+pub const fn read_len(sequence: &[Op]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < sequence.len() {
+        if let Op::Read { len: read, .. } = sequence[i] {
+            len += read;
+        }
+        i += 1;
+    }
+    len
+}
+
+pub trait I2CSequenceClient {
+    /// Called when a sequence finished, or stopped at an operation that
+    /// failed. `data` holds the bytes read until then.
+    fn sequence_done(&self, data: &[u8], result: Result<(), ErrorCode>);
+}
+
+pub struct I2CSequence<'a, A: time::Alarm<'a>> {
+    i2c: &'a dyn I2CDevice,
+    alarm: &'a A,
+    sequence: Cell<&'static [Op]>,
+    /// Index of the running operation.
+    step: Cell<usize>,
+    running: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+    data: TakeCell<'static, [u8]>,
+    data_capacity: usize,
+    /// Number of bytes in `data`.
+    data_len: Cell<usize>,
+    client: OptionalCell<&'a dyn I2CSequenceClient>,
+}
+
+impl<'a, A: time::Alarm<'a>> I2CSequence<'a, A> {
+    pub fn new(
+        i2c: &'a dyn I2CDevice,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+        data: &'static mut [u8],
+    ) -> Self {
+        I2CSequence {
+            data_capacity: data.len(),
+            i2c,
+            alarm,
+            sequence: Cell::new(&[]),
+            step: Cell::new(0),
+            running: Cell::new(false),
+            buffer: TakeCell::new(buffer),
+            data: TakeCell::new(data),
+            data_len: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn I2CSequenceClient) {
+        self.client.set(client);
+    }
+
+    /// Start running `sequence`. Returns `BUSY` if a sequence is running, and
+    /// `SIZE` if the data buffer is too small for its reads. If the first
+    /// operation fails, its error is returned and the client is not called.
+    pub fn run(&self, sequence: &'static [Op]) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.data_capacity < read_len(sequence) {
+            return Err(ErrorCode::SIZE);
+        }
+        if sequence.is_empty() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.sequence.set(sequence);
+        self.step.set(0);
+        self.data_len.set(0);
+        self.running.set(true);
+        self.i2c.enable();
+        let result = self.start_step();
+        if result.is_err() {
+            self.i2c.disable();
+            self.running.set(false);
+        }
+        result
+    }
+
+    /// Start the operation at `step`.
+    fn start_step(&self) -> Result<(), ErrorCode> {
+        let op = match self.sequence.get().get(self.step.get()) {
+            Some(op) => *op,
+            None => return Err(ErrorCode::FAIL),
+        };
+        match op {
+            Op::DelayUs(us) => {
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(us));
+                Ok(())
+            }
+            Op::Write(bytes) => self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+                if buffer.len() < bytes.len() {
+                    self.buffer.replace(buffer);
+                    return Err(ErrorCode::SIZE);
+                }
+                buffer[..bytes.len()].copy_from_slice(bytes);
+                self.i2c
+                    .write(buffer, bytes.len())
+                    .map_err(|(error, buffer)| {
+                        self.buffer.replace(buffer);
+                        error.into()
+                    })
+            }),
+            Op::Read { reg, len } => self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+                if buffer.len() < len.max(1) {
+                    self.buffer.replace(buffer);
+                    return Err(ErrorCode::SIZE);
+                }
+                buffer[0] = reg;
+                self.i2c
+                    .write_read(buffer, 1, len)
+                    .map_err(|(error, buffer)| {
+                        self.buffer.replace(buffer);
+                        error.into()
+                    })
+            }),
+        }
+    }
+
+    /// Start the next operation, or finish if there is none left.
+    fn next_step(&self) {
+        self.step.set(self.step.get() + 1);
+        if self.step.get() == self.sequence.get().len() {
+            self.finish(Ok(()));
+        } else if let Err(e) = self.start_step() {
+            self.finish(Err(e));
+        }
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.i2c.disable();
+        self.running.set(false);
+        self.data.map(|data| {
+            self.client
+                .map(|client| client.sequence_done(&data[..self.data_len.get()], result));
+        });
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> I2CClient for I2CSequence<'a, A> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        if status.is_ok() {
+            if let Some(Op::Read { len, .. }) = self.sequence.get().get(self.step.get()) {
+                let start = self.data_len.get();
+                self.data.map(|data| {
+                    data[start..start + len].copy_from_slice(&buffer[..*len]);
+                });
+                self.data_len.set(start + len);
+            }
+        }
+        self.buffer.replace(buffer);
+
+        match status {
+            Ok(()) => self.next_step(),
+            Err(e) => self.finish(Err(e.into())),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for I2CSequence<'a, A> {
+    fn alarm(&self) {
+        if self.running.get() {
+            self.next_step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_len, Op};
+
+    const SEQUENCE: &[Op] = crate::i2c_sequence![
+        write(0x2a, 0x01),
+        delay_us(1000),
+        read(0x01, 6),
+        read(0x33, 1,),
+    ];
+
+    #[test]
+    fn declare_sequence() {
+        assert_eq!(
+            SEQUENCE,
+            &[
+                Op::Write(&[0x2a, 0x01]),
+                Op::DelayUs(1000),
+                Op::Read { reg: 0x01, len: 6 },
+                Op::Read { reg: 0x33, len: 1 },
+            ]
+        );
+        assert_eq!(read_len(SEQUENCE), 7);
+        assert_eq!(read_len(&[]), 0);
+    }
+}
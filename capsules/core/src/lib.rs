@@ -12,17 +12,31 @@ pub mod stream;
 
 pub mod adc;
 pub mod alarm;
+pub mod bus_errors;
 pub mod button;
 pub mod console;
 pub mod console_ordered;
+pub mod deadline;
 pub mod driver;
+pub mod driver_info;
+pub mod factory_reset;
 pub mod gpio;
+pub mod gpio_port;
 pub mod i2c_master;
 pub mod i2c_master_slave_driver;
+pub mod i2c_register;
+pub mod i2c_sequence;
 pub mod led;
 pub mod low_level_debug;
 pub mod process_console;
+pub mod process_info;
+pub mod process_loader;
+pub mod process_snapshot;
+pub mod resource_acl;
+pub mod restart_backoff;
 pub mod rng;
+pub mod scheduler_control;
+pub mod sensor_upcall;
 pub mod spi_controller;
 pub mod spi_peripheral;
 pub mod virtualizers;
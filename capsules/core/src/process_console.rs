@@ -11,17 +11,23 @@ use core::cmp;
 use core::fmt;
 use core::fmt::write;
 use core::str;
+
+use crate::factory_reset;
+use crate::restart_backoff::FaultHistory;
 use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::gpio;
 use kernel::hil::time::ConvertTicks;
 use kernel::utilities::cells::MapCell;
+use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::cells::TakeCell;
 use kernel::ProcessId;
 
 use kernel::debug;
-use kernel::hil::time::{Alarm, AlarmClient};
+use kernel::hil::time::{Alarm, AlarmClient, Ticks};
 use kernel::hil::uart;
 use kernel::introspection::KernelInfo;
-use kernel::process::{ProcessPrinter, ProcessPrinterContext, State};
+use kernel::postmortem;
+use kernel::process::{Process, ProcessPrinter, ProcessPrinterContext, State};
 use kernel::utilities::binary_write::BinaryWrite;
 use kernel::ErrorCode;
 use kernel::Kernel;
@@ -34,16 +40,33 @@ pub const QUEUE_BUF_LEN: usize = 300;
 /// Since reads are byte-by-byte, to properly echo what's typed,
 /// we can use a very small read buffer.
 pub const READ_BUF_LEN: usize = 4;
-/// Commands can be up to 32 bytes long: since commands themselves are 4-5
-/// characters, limiting arguments to 25 bytes or so seems fine for now.
-pub const COMMAND_BUF_LEN: usize = 32;
+/// Commands can be up to 64 bytes long, which leaves room for arguments such
+/// as a 128 bit key in hexadecimal.
+pub const COMMAND_BUF_LEN: usize = 64;
 /// Default size for the history command.
 pub const DEFAULT_COMMAND_HISTORY_LEN: usize = 10;
+/// Number of processes the `top` command keeps statistics for. Processes
+/// beyond this are listed without CPU and syscall rates.
+pub const TOP_MAX_PROCESSES: usize = 16;
+/// Refresh interval of the `top` command if none is given.
+const TOP_DEFAULT_INTERVAL_MS: u32 = 1000;
+/// Shortest refresh interval the `top` command accepts.
+const TOP_MIN_INTERVAL_MS: u32 = 100;
 
 /// List of valid commands for printing help. Consolidated as these are
 /// displayed in a few different cases.
 const VALID_COMMANDS_STR: &[u8] =
-    b"help status list stop start fault boot terminate process kernel reset panic\r\n";
+    b"help status list top stop start faults fault boot terminate process kernel gpio alias reset factory_reset panic lastpanic\r\n";
+
+/// A command provided by another capsule, run by the process console.
+pub trait ConsoleCommand {
+    /// Name of the command, i.e. the first word of the command line.
+    fn name(&self) -> &'static str;
+
+    /// Run the command with the rest of the command line as `args`, writing
+    /// the response to `out`.
+    fn execute(&self, args: &str, out: &mut dyn fmt::Write);
+}
 
 /// Escape character for ANSI escape sequences.
 const ESC: u8 = '\x1B' as u8;
@@ -89,6 +112,13 @@ enum WriterState {
         index: isize,
         total: isize,
     },
+    Top {
+        index: isize,
+        total: isize,
+    },
+    LastPanic {
+        offset: usize,
+    },
 }
 
 impl Default for WriterState {
@@ -187,6 +217,15 @@ impl EscState {
     }
 }
 
+/// Counters of a process recorded at the last `top` refresh, used to compute
+/// rates over the refresh interval.
+#[derive(Copy, Clone, Default)]
+struct TopSample {
+    process_id: Option<ProcessId>,
+    execution_time_us: u64,
+    syscalls: usize,
+}
+
 /// Data structure to hold addresses about how the kernel is stored in memory on
 /// the chip.
 ///
@@ -253,6 +292,39 @@ pub struct ProcessConsole<
     /// Function used to reset the device in bootloader mode
     reset_function: Option<fn() -> !>,
 
+    /// Refresh interval of the `top` command. Set while the live view is
+    /// displayed.
+    top_interval_ms: OptionalCell<u32>,
+
+    /// Time of the last `top` refresh.
+    top_last_refresh: Cell<A::Ticks>,
+
+    /// Time covered by the `top` refresh currently being printed.
+    top_elapsed_us: Cell<u32>,
+
+    /// Per-process counters at the last `top` refresh.
+    top_samples: [Cell<TopSample>; TOP_MAX_PROCESSES],
+
+    /// GPIO pins the `gpio` command can inspect and modify, indexed by their
+    /// position in the slice.
+    gpio_pins: OptionalCell<&'a [&'a dyn gpio::Pin]>,
+
+    /// Started by the `factory_reset` command.
+    factory_reset: OptionalCell<&'a dyn factory_reset::Trigger>,
+
+    /// Fault history shown by the `faults` command.
+    fault_history: OptionalCell<&'a dyn FaultHistory>,
+
+    /// Named command sequences provided by the board, as pairs of the alias
+    /// name and its commands separated by `;`.
+    aliases: OptionalCell<&'a [(&'a str, &'a str)]>,
+
+    /// Commands provided by other capsules.
+    commands: OptionalCell<&'a [&'a dyn ConsoleCommand]>,
+
+    /// Commands of the running alias that have not been executed yet.
+    alias_remaining: OptionalCell<&'a str>,
+
     /// This capsule needs to use potentially dangerous APIs related to
     /// processes, and requires a capability to access those APIs.
     capability: C,
@@ -479,10 +551,49 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
             kernel: kernel,
             kernel_addresses: kernel_addresses,
             reset_function: reset_function,
+            top_interval_ms: OptionalCell::empty(),
+            top_last_refresh: Cell::new(A::Ticks::from(0)),
+            top_elapsed_us: Cell::new(1),
+            top_samples: core::array::from_fn(|_| Cell::new(TopSample::default())),
+            gpio_pins: OptionalCell::empty(),
+            factory_reset: OptionalCell::empty(),
+            fault_history: OptionalCell::empty(),
+            aliases: OptionalCell::empty(),
+            commands: OptionalCell::empty(),
+            alias_remaining: OptionalCell::empty(),
             capability: capability,
         }
     }
 
+    /// Provide the GPIO pins that the `gpio` command can access. Pins are
+    /// referred to by their index in `pins`.
+    pub fn set_gpio_pins(&self, pins: &'a [&'a dyn gpio::Pin]) {
+        self.gpio_pins.set(pins);
+    }
+
+    /// Let the `factory_reset` command start a factory reset.
+    pub fn set_factory_reset(&self, factory_reset: &'a dyn factory_reset::Trigger) {
+        self.factory_reset.set(factory_reset);
+    }
+
+    /// Let the `faults` command show the fault history of the processes.
+    pub fn set_fault_history(&self, fault_history: &'a dyn FaultHistory) {
+        self.fault_history.set(fault_history);
+    }
+
+    /// Provide named command sequences. Entering the name of an alias runs
+    /// its commands, separated by `;`, one after the other. For example
+    /// `("bringup", "start app1; start app2; list")`.
+    pub fn set_aliases(&self, aliases: &'a [(&'a str, &'a str)]) {
+        self.aliases.set(aliases);
+    }
+
+    /// Provide commands implemented by other capsules. They take precedence
+    /// over the built-in commands of the same name.
+    pub fn set_commands(&self, commands: &'a [&'a dyn ConsoleCommand]) {
+        self.commands.set(commands);
+    }
+
     /// Start the process console listening for user commands.
     pub fn start(&self) -> Result<(), ErrorCode> {
         if self.running.get() == false {
@@ -553,6 +664,24 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                     }
                 }
             }
+            WriterState::Top { index, total } => {
+                if index + 1 == total {
+                    WriterState::Empty
+                } else {
+                    WriterState::Top {
+                        index: index + 1,
+                        total,
+                    }
+                }
+            }
+            WriterState::LastPanic { offset } => {
+                let len = postmortem::with_last_panic(|record| record.len()).unwrap_or(0);
+                if offset >= len {
+                    WriterState::Empty
+                } else {
+                    WriterState::LastPanic { offset }
+                }
+            }
             WriterState::Empty => WriterState::Empty,
         }
     }
@@ -719,6 +848,57 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                         }
                     });
             }
+            WriterState::Top { index, total: _ } => {
+                let mut local_index = -1;
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        local_index += 1;
+                        if local_index == index {
+                            let elapsed_us = self.top_elapsed_us.get() as u64;
+                            let (time_us, syscalls) = self
+                                .top_update_sample(index as usize, process)
+                                .unwrap_or((0, 0));
+                            // CPU share in tenths of a percent.
+                            let cpu = cmp::min(time_us * 1000 / elapsed_us, 1000);
+                            let syscall_rate = syscalls as u64 * 1_000_000 / elapsed_us;
+
+                            let addresses = process.get_addresses();
+                            let used = (addresses.sram_app_brk - addresses.sram_start)
+                                + (addresses.sram_end - addresses.sram_grant_start);
+                            let allocated = addresses.sram_end - addresses.sram_start;
+
+                            let mut console_writer = ConsoleWriter::new();
+                            let _ = write(
+                                &mut console_writer,
+                                format_args!(
+                                    " {:<7?}{:<20}{:3}.{}{:12}{:8}/{:<8}{:?}\r\n",
+                                    process.processid(),
+                                    process.get_process_name(),
+                                    cpu / 10,
+                                    cpu % 10,
+                                    syscall_rate,
+                                    used,
+                                    allocated,
+                                    process.get_state(),
+                                ),
+                            );
+
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        }
+                    });
+            }
+            WriterState::LastPanic { offset } => {
+                let chunk = self.tx_buffer.map_or(0, |buffer| buffer.len());
+                let end = postmortem::with_last_panic(|record| {
+                    let end = cmp::min(offset + chunk, record.len());
+                    let _ = self.write_bytes(&record[offset..end]);
+                    end
+                });
+                if let Some(end) = end {
+                    self.writer_state
+                        .replace(WriterState::LastPanic { offset: end });
+                }
+            }
             WriterState::Empty => {
                 self.prompt();
             }
@@ -726,6 +906,101 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         }
     }
 
+    /// Start the live `top` view, redrawing every `interval_ms`.
+    fn top_start(&self, interval_ms: u32) {
+        // Take a baseline so the first refresh reports usage over one
+        // interval rather than since boot.
+        let mut index = 0;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                let _ = self.top_update_sample(index, process);
+                index += 1;
+            });
+
+        let now = self.alarm.now();
+        self.top_interval_ms.set(interval_ms);
+        self.top_last_refresh.set(now);
+        self.alarm
+            .set_alarm(now, self.alarm.ticks_from_ms(interval_ms));
+        let _ = self.write_bytes(b"Starting top, press any key to exit.\r\n");
+    }
+
+    /// Leave the live `top` view and return to the prompt.
+    fn top_stop(&self) {
+        self.top_interval_ms.clear();
+        let _ = self.alarm.disarm();
+        let _ = self.write_bytes(&[CR, NLINE]);
+        self.prompt();
+    }
+
+    /// Redraw the `top` view.
+    fn top_refresh(&self, interval_ms: u32) {
+        // If the previous refresh is still being printed skip this one. The
+        // next refresh then covers the longer interval.
+        if self.writer_state.get() != WriterState::Empty || self.tx_in_progress.get() {
+            return;
+        }
+
+        let now = self.alarm.now();
+        let elapsed = now.wrapping_sub(self.top_last_refresh.get());
+        self.top_last_refresh.set(now);
+        self.top_elapsed_us
+            .set(cmp::max(self.alarm.ticks_to_us(elapsed), 1));
+
+        let mut count = 0;
+        self.kernel.process_each_capability(&self.capability, |_| {
+            count += 1;
+        });
+
+        // Clear the screen and move the cursor to the top left corner.
+        let mut console_writer = ConsoleWriter::new();
+        let _ = write(
+            &mut console_writer,
+            format_args!(
+                "\x1B[2J\x1B[H\
+                 top - {} processes, refresh every {} ms, press any key to exit\r\n\
+                 \r\n PID    Name                 CPU%  Syscalls/s  Memory           State\r\n",
+                count, interval_ms
+            ),
+        );
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+        if count > 0 {
+            self.write_state(WriterState::Top {
+                index: -1,
+                total: count,
+            });
+        }
+    }
+
+    /// Record the current counters of the process in slot `index` and return
+    /// how much execution time and how many syscalls it accumulated since the
+    /// last sample. Returns `None` if there is no slot for this process.
+    fn top_update_sample(&self, index: usize, process: &dyn Process) -> Option<(u64, usize)> {
+        self.top_samples.get(index).map(|slot| {
+            let previous = slot.get();
+            let current = TopSample {
+                process_id: Some(process.processid()),
+                execution_time_us: process.debug_execution_time_us(),
+                syscalls: process.debug_syscall_count(),
+            };
+            slot.set(current);
+
+            if previous.process_id == current.process_id {
+                (
+                    current
+                        .execution_time_us
+                        .saturating_sub(previous.execution_time_us),
+                    current.syscalls.saturating_sub(previous.syscalls),
+                )
+            } else {
+                // A different process now occupies this slot, there is no
+                // meaningful baseline yet.
+                (0, 0)
+            }
+        })
+    }
+
     // Process the command in the command buffer and clear the buffer.
     fn read_command(&self) {
         self.command_buffer.map(|command| {
@@ -758,245 +1033,513 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
                             }
                         }
 
-                        if clean_str.starts_with("help") {
-                            let _ = self.write_bytes(b"Welcome to the process console.\r\n");
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
-                        } else if clean_str.starts_with("start") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.resume();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} resumed.\r\n", name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("stop") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.stop();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} stopped\r\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("fault") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.set_fault_state();
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!(
-                                                    "Process {} now faulted\r\n",
-                                                    proc_name
-                                                ),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("terminate") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            proc.terminate(None);
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let _ = write(
-                                                &mut console_writer,
-                                                format_args!("Process {} terminated\n", proc_name),
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("boot") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name
-                                            && proc.get_state() == State::Terminated
-                                        {
-                                            proc.try_restart(None);
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("list") {
-                            let _ = self.write_bytes(b" PID    Name                Quanta  ");
-                            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
-
-                            // Count the number of current processes.
-                            let mut count = 0;
-                            self.kernel.process_each_capability(&self.capability, |_| {
-                                count += 1;
-                            });
-
-                            if count > 0 {
-                                // Start the state machine to print each separately.
-                                self.write_state(WriterState::List {
-                                    index: -1,
-                                    total: count,
-                                });
+                        match self
+                            .aliases
+                            .extract()
+                            .and_then(|aliases| aliases.iter().find(|(name, _)| *name == clean_str))
+                        {
+                            Some(&(_, commands)) => {
+                                // Started by `prompt()` below.
+                                self.alias_remaining.set(commands);
                             }
-                        } else if clean_str.starts_with("status") {
-                            let info: KernelInfo = KernelInfo::new(self.kernel);
+                            None => self.execute_command(clean_str),
+                        }
+                    }
+                    Err(_e) => {
+                        let mut console_writer = ConsoleWriter::new();
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Invalid command: {:?}", command),
+                        );
+                        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                    }
+                }
+            }
+        });
+        self.command_buffer.map(|command| {
+            command[0] = 0;
+        });
+        self.command_index.set(0);
+        if self.writer_state.get() == WriterState::Empty {
+            self.prompt();
+        }
+    }
+
+    /// Run a single console command.
+    fn execute_command(&self, clean_str: &str) {
+        let mut words = clean_str.splitn(2, ' ');
+        let name = words.next().unwrap_or("");
+        let command = self.commands.extract().and_then(|commands| {
+            commands
+                .iter()
+                .find(|command| command.name() == name)
+                .copied()
+        });
+        if let Some(command) = command {
+            let mut console_writer = ConsoleWriter::new();
+            command.execute(words.next().unwrap_or("").trim(), &mut console_writer);
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            return;
+        }
+
+        if clean_str.starts_with("help") {
+            let _ = self.write_bytes(b"Welcome to the process console.\r\n");
+            let _ = self.write_bytes(b"Valid commands are: ");
+            let _ = self.write_bytes(VALID_COMMANDS_STR);
+        } else if clean_str.starts_with("start") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name {
+                            proc.resume();
                             let mut console_writer = ConsoleWriter::new();
                             let _ = write(
                                 &mut console_writer,
-                                format_args!(
-                                    "Total processes: {}\r\n",
-                                    info.number_loaded_processes(&self.capability)
-                                ),
+                                format_args!("Process {} resumed.\r\n", name),
                             );
+
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
+                        }
+                    });
+            });
+        } else if clean_str.starts_with("stop") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name {
+                            proc.stop();
+                            let mut console_writer = ConsoleWriter::new();
                             let _ = write(
                                 &mut console_writer,
-                                format_args!(
-                                    "Active processes: {}\r\n",
-                                    info.number_active_processes(&self.capability)
-                                ),
+                                format_args!("Process {} stopped\r\n", proc_name),
                             );
+
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
+                        }
+                    });
+            });
+        } else if clean_str.starts_with("faults") {
+            let argument = clean_str.split_whitespace().nth(1);
+            let mut console_writer = ConsoleWriter::new();
+            match (argument, self.fault_history.extract()) {
+                (Some(name), Some(history)) => match history.fault_record(name) {
+                    Some(record) => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!(
+                                "Process {}: {} faults, {:?}, last {} ms ago, backoff {} ms\r\n",
+                                name,
+                                record.faults,
+                                record.state,
+                                record.last_fault_ms,
+                                record.backoff_ms
+                            ),
+                        );
+                    }
+                    None => {
+                        let _ = write(
+                            &mut console_writer,
+                            format_args!("Process {} has not faulted\r\n", name),
+                        );
+                    }
+                },
+                (None, Some(_)) => {
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!("Usage: faults <name>\r\n"),
+                    );
+                }
+                (_, None) => {
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!("Fault history is not available\r\n"),
+                    );
+                }
+            }
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        } else if clean_str.starts_with("fault") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name {
+                            proc.set_fault_state();
+                            let mut console_writer = ConsoleWriter::new();
                             let _ = write(
                                 &mut console_writer,
-                                format_args!(
-                                    "Timeslice expirations: {}\r\n",
-                                    info.timeslice_expirations(&self.capability)
-                                ),
+                                format_args!("Process {} now faulted\r\n", proc_name),
                             );
-                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                        } else if clean_str.starts_with("process") {
-                            let argument = clean_str.split_whitespace().nth(1);
-                            argument.map(|name| {
-                                // If two processes have the same name, only
-                                // print the first one we find.
-                                let mut found = false;
-                                self.kernel
-                                    .process_each_capability(&self.capability, |proc| {
-                                        if found {
-                                            return;
-                                        }
-                                        let proc_name = proc.get_process_name();
-                                        if proc_name == name {
-                                            let mut console_writer = ConsoleWriter::new();
-                                            let mut context: Option<ProcessPrinterContext> = None;
-                                            context = self.process_printer.print_overview(
-                                                proc,
-                                                &mut console_writer,
-                                                context,
-                                            );
-
-                                            let _ = self.write_bytes(
-                                                &(console_writer.buf)[..console_writer.size],
-                                            );
-
-                                            if context.is_some() {
-                                                self.writer_state.replace(
-                                                    WriterState::ProcessPrint {
-                                                        process_id: proc.processid(),
-                                                        context: context,
-                                                    },
-                                                );
-                                            }
 
-                                            found = true;
-                                        }
-                                    });
-                            });
-                        } else if clean_str.starts_with("kernel") {
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                        }
+                    });
+            });
+        } else if clean_str.starts_with("terminate") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name {
+                            proc.terminate(None);
                             let mut console_writer = ConsoleWriter::new();
                             let _ = write(
                                 &mut console_writer,
-                                format_args!(
-                                    "Kernel version: {}.{} (build {})\r\n",
-                                    kernel::KERNEL_MAJOR_VERSION,
-                                    kernel::KERNEL_MINOR_VERSION,
-                                    option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
-                                ),
+                                format_args!("Process {} terminated\n", proc_name),
                             );
+
                             let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
-                            console_writer.clear();
-
-                            // Prints kernel memory by moving the writer to the
-                            // start state.
-                            self.writer_state.replace(WriterState::KernelStart);
-                        } else if clean_str.starts_with("reset") {
-                            self.reset_function.map_or_else(
-                                || {
-                                    let _ = self.write_bytes(b"Reset function is not implemented");
-                                },
-                                |f| {
-                                    f();
-                                },
+                        }
+                    });
+            });
+        } else if clean_str.starts_with("boot") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name && proc.get_state() == State::Terminated {
+                            proc.try_restart(None);
+                        }
+                    });
+            });
+        } else if clean_str.starts_with("list") {
+            let _ = self.write_bytes(b" PID    Name                Quanta  ");
+            let _ = self.write_bytes(b"Syscalls  Restarts  Grants  State\r\n");
+
+            // Count the number of current processes.
+            let mut count = 0;
+            self.kernel.process_each_capability(&self.capability, |_| {
+                count += 1;
+            });
+
+            if count > 0 {
+                // Start the state machine to print each separately.
+                self.write_state(WriterState::List {
+                    index: -1,
+                    total: count,
+                });
+            }
+        } else if clean_str.starts_with("top") {
+            let interval_ms = clean_str
+                .split_whitespace()
+                .nth(1)
+                .and_then(|arg| arg.parse::<u32>().ok())
+                .unwrap_or(TOP_DEFAULT_INTERVAL_MS);
+            self.top_start(cmp::max(interval_ms, TOP_MIN_INTERVAL_MS));
+        } else if clean_str.starts_with("status") {
+            let info: KernelInfo = KernelInfo::new(self.kernel);
+            let mut console_writer = ConsoleWriter::new();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Total processes: {}\r\n",
+                    info.number_loaded_processes(&self.capability)
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            console_writer.clear();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Active processes: {}\r\n",
+                    info.number_active_processes(&self.capability)
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            console_writer.clear();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Timeslice expirations: {}\r\n",
+                    info.timeslice_expirations(&self.capability)
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+        } else if clean_str.starts_with("process") {
+            let argument = clean_str.split_whitespace().nth(1);
+            argument.map(|name| {
+                // If two processes have the same name, only
+                // print the first one we find.
+                let mut found = false;
+                self.kernel
+                    .process_each_capability(&self.capability, |proc| {
+                        if found {
+                            return;
+                        }
+                        let proc_name = proc.get_process_name();
+                        if proc_name == name {
+                            let mut console_writer = ConsoleWriter::new();
+                            let mut context: Option<ProcessPrinterContext> = None;
+                            context = self.process_printer.print_overview(
+                                proc,
+                                &mut console_writer,
+                                context,
                             );
-                        } else if clean_str.starts_with("panic") {
-                            panic!("Process Console forced a kernel panic.");
-                        } else {
-                            let _ = self.write_bytes(b"Valid commands are: ");
-                            let _ = self.write_bytes(VALID_COMMANDS_STR);
+
+                            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+
+                            if context.is_some() {
+                                self.writer_state.replace(WriterState::ProcessPrint {
+                                    process_id: proc.processid(),
+                                    context: context,
+                                });
+                            }
+
+                            found = true;
                         }
-                    }
-                    Err(_e) => {
+                    });
+            });
+        } else if clean_str.starts_with("kernel") {
+            let mut console_writer = ConsoleWriter::new();
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Kernel version: {}.{} (build {})\r\n",
+                    kernel::KERNEL_MAJOR_VERSION,
+                    kernel::KERNEL_MINOR_VERSION,
+                    option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown")
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            console_writer.clear();
+
+            let _ = write(
+                &mut console_writer,
+                format_args!("Config: {}\r\n", kernel::config::report()),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            console_writer.clear();
+
+            let info: KernelInfo = KernelInfo::new(self.kernel);
+            let (grants, grant_bytes) = info.grant_region_footprint(&self.capability);
+            let _ = write(
+                &mut console_writer,
+                format_args!(
+                    "Grant region: {} grants, up to {} bytes per process\r\n",
+                    grants, grant_bytes
+                ),
+            );
+            let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+            console_writer.clear();
+
+            // Prints kernel memory by moving the writer to the
+            // start state.
+            self.writer_state.replace(WriterState::KernelStart);
+        } else if clean_str.starts_with("gpio") {
+            self.gpio_command(clean_str.split_whitespace().skip(1));
+        } else if clean_str.starts_with("alias") {
+            match self.aliases.extract() {
+                Some(aliases) if !aliases.is_empty() => {
+                    for (name, commands) in aliases.iter() {
                         let mut console_writer = ConsoleWriter::new();
                         let _ = write(
                             &mut console_writer,
-                            format_args!("Invalid command: {:?}", command),
+                            format_args!("{} = {}\r\n", name, commands),
                         );
                         let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
                     }
                 }
+                _ => {
+                    let _ = self.write_bytes(b"No aliases defined\r\n");
+                }
+            }
+        } else if clean_str.starts_with("lastpanic") {
+            if clean_str.split_whitespace().nth(1) == Some("clear") {
+                postmortem::clear();
+                let _ = self.write_bytes(b"Panic record cleared\r\n");
+            } else if let Some(len) = postmortem::with_last_panic(|record| record.len()) {
+                let mut console_writer = ConsoleWriter::new();
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("---| Last panic ({} bytes) |---\r\n", len),
+                );
+                let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                // Print the record by moving the writer to its state.
+                self.writer_state
+                    .replace(WriterState::LastPanic { offset: 0 });
+            } else {
+                let _ = self.write_bytes(b"No panic recorded\r\n");
+            }
+        } else if clean_str.starts_with("factory_reset") {
+            match self.factory_reset.map(|f| f.factory_reset()) {
+                Some(Ok(())) => {}
+                Some(Err(e)) => {
+                    let mut console_writer = ConsoleWriter::new();
+                    let _ = write(
+                        &mut console_writer,
+                        format_args!("Factory reset failed: {:?}\r\n", e),
+                    );
+                    let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+                }
+                None => {
+                    let _ = self.write_bytes(b"Factory reset is not available\r\n");
+                }
+            }
+        } else if clean_str.starts_with("reset") {
+            self.reset_function.map_or_else(
+                || {
+                    let _ = self.write_bytes(b"Reset function is not implemented");
+                },
+                |f| {
+                    f();
+                },
+            );
+        } else if clean_str.starts_with("panic") {
+            panic!("Process Console forced a kernel panic.");
+        } else {
+            let _ = self.write_bytes(b"Valid commands are: ");
+            let _ = self.write_bytes(VALID_COMMANDS_STR);
+        }
+    }
+
+    /// Run one of the `gpio` subcommands: `read`, `set`, `toggle`, or
+    /// `mode`.
+    fn gpio_command<'b>(&self, mut args: impl Iterator<Item = &'b str>) {
+        let pins = match self.gpio_pins.extract() {
+            Some(pins) => pins,
+            None => {
+                let _ = self.write_bytes(b"No GPIO pins available\r\n");
+                return;
+            }
+        };
+
+        let subcommand = args.next();
+        let index = args.next().and_then(|arg| arg.parse::<usize>().ok());
+        let pin = index.and_then(|i| pins.get(i));
+        let argument = args.next();
+
+        let mut console_writer = ConsoleWriter::new();
+        match (subcommand, index, pin) {
+            (Some("read"), Some(index), Some(pin)) => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        "GPIO {}: {} ({:?})\r\n",
+                        index,
+                        pin.read() as u8,
+                        pin.configuration()
+                    ),
+                );
+            }
+            (Some("set"), Some(index), Some(pin)) => {
+                let value = match argument {
+                    Some("0") => {
+                        pin.clear();
+                        0
+                    }
+                    Some("1") | None => {
+                        pin.set();
+                        1
+                    }
+                    Some(_) => {
+                        let _ = self.write_bytes(b"Usage: gpio set <pin> [0|1]\r\n");
+                        return;
+                    }
+                };
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("GPIO {}: set to {}\r\n", index, value),
+                );
+            }
+            (Some("toggle"), Some(index), Some(pin)) => {
+                let value = pin.toggle();
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("GPIO {}: toggled to {}\r\n", index, value as u8),
+                );
+            }
+            (Some("mode"), Some(index), Some(pin)) => {
+                let configuration = match argument {
+                    Some("in") => {
+                        pin.set_floating_state(gpio::FloatingState::PullNone);
+                        pin.make_input()
+                    }
+                    Some("pullup") => {
+                        pin.set_floating_state(gpio::FloatingState::PullUp);
+                        pin.make_input()
+                    }
+                    Some("pulldown") => {
+                        pin.set_floating_state(gpio::FloatingState::PullDown);
+                        pin.make_input()
+                    }
+                    Some("out") => pin.make_output(),
+                    Some("off") => {
+                        pin.deactivate_to_low_power();
+                        pin.configuration()
+                    }
+                    _ => {
+                        let _ = self.write_bytes(
+                            b"Usage: gpio mode <pin> <in|pullup|pulldown|out|off>\r\n",
+                        );
+                        return;
+                    }
+                };
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("GPIO {}: {:?}\r\n", index, configuration),
+                );
+            }
+            (Some(_), Some(index), None) => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!("Invalid pin {}, {} pins available\r\n", index, pins.len()),
+                );
+            }
+            _ => {
+                let _ = write(
+                    &mut console_writer,
+                    format_args!(
+                        "Usage: gpio <read|set|toggle|mode> <pin> [value]\r\n{} pins available\r\n",
+                        pins.len()
+                    ),
+                );
+            }
+        }
+        let _ = self.write_bytes(&(console_writer.buf)[..console_writer.size]);
+    }
+
+    /// Run the remaining commands of the current alias. Returns early if a
+    /// command prints its output over several callbacks. Such commands call
+    /// `prompt()` once they are done, which continues the alias.
+    fn run_alias(&self) {
+        while let Some(remaining) = self.alias_remaining.take() {
+            let (command, rest) = remaining.split_once(';').unwrap_or((remaining, ""));
+            if !rest.trim().is_empty() {
+                self.alias_remaining.set(rest);
+            }
+
+            let command = command.trim();
+            if !command.is_empty() {
+                let _ = self.write_bytes(b"> ");
+                let _ = self.write_bytes(command.as_bytes());
+                let _ = self.write_bytes(&[CR, NLINE]);
+                self.execute_command(command);
+                if self.writer_state.get() != WriterState::Empty || self.top_interval_ms.is_some() {
+                    return;
+                }
             }
-        });
-        self.command_buffer.map(|command| {
-            command[0] = 0;
-        });
-        self.command_index.set(0);
-        if self.writer_state.get() == WriterState::Empty {
-            self.prompt();
         }
     }
 
     fn prompt(&self) {
-        let _ = self.write_bytes(b"tock$ ");
+        // Continue with the next command of a running alias instead.
+        if self.alias_remaining.is_some() {
+            self.run_alias();
+            if self.writer_state.get() != WriterState::Empty {
+                return;
+            }
+        }
+        // The live `top` view owns the screen, it prints the prompt when it
+        // exits.
+        if self.top_interval_ms.is_none() {
+            let _ = self.write_bytes(b"tock$ ");
+        }
     }
 
     /// Start or iterate the state machine for an asynchronous write operation
@@ -1094,6 +1637,13 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
     for ProcessConsole<'a, COMMAND_HISTORY_LEN, A, C>
 {
     fn alarm(&self) {
+        if let Some(interval_ms) = self.top_interval_ms.extract() {
+            self.top_refresh(interval_ms);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(interval_ms));
+            return;
+        }
+
         self.prompt();
         self.rx_buffer.take().map(|buffer| {
             self.rx_in_progress.set(true);
@@ -1148,6 +1698,16 @@ impl<'a, const COMMAND_HISTORY_LEN: usize, A: Alarm<'a>, C: ProcessManagementCap
         _rcode: Result<(), ErrorCode>,
         error: uart::Error,
     ) {
+        if self.top_interval_ms.is_some() {
+            // Any key press ends the live `top` view.
+            if error == uart::Error::None && rx_len > 0 {
+                self.top_stop();
+            }
+            self.rx_in_progress.set(true);
+            let _ = self.uart.receive_buffer(read_buf, 1);
+            return;
+        }
+
         if error == uart::Error::None {
             match rx_len {
                 0 => debug!("ProcessConsole had read of 0 bytes"),
@@ -0,0 +1,177 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with per-process CPU time accounting and grant usage.
+//!
+//! For each process the kernel accounts the time it spent executing, the part
+//! of that time the kernel spent handling its syscalls, and the number of
+//! times it was preempted. This driver lets a process read these statistics
+//! for itself and for the other processes, e.g. to find out which process
+//! drains the battery.
+//!
+//! It also reports how much memory the grants of each driver take up in the
+//! grant region of a process, and how much of the region is left, so the
+//! memory a process needs for grants can be measured instead of guessed.
+//!
+//! Time is measured with the scheduler timer, so it is only accounted with
+//! schedulers that run processes with a timeslice. Like the other statistics,
+//! it starts over when a process restarts, which gives the process a new
+//! identifier.
+//!
+//! Processes are identified by their process identifier (`ProcessId::id()`).
+//! The identifiers of all processes can be listed with commands 1 and 2.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let process_info = components::process_info::ProcessInfoComponent::new(board_kernel)
+//!     .finalize(components::process_info_component_static!());
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::process::Process;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessInfo as usize;
+
+pub struct ProcessInfo<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> ProcessInfo<C> {
+    pub fn new(kernel: &'static Kernel, capability: C) -> Self {
+        ProcessInfo { kernel, capability }
+    }
+
+    /// Run `closure` on the process with identifier `id`, or return `INVAL`
+    /// if there is no such process.
+    fn with_process<F>(&self, id: usize, closure: F) -> CommandReturn
+    where
+        F: FnOnce(&dyn Process) -> CommandReturn,
+    {
+        let mut closure = Some(closure);
+        let mut result = CommandReturn::failure(ErrorCode::INVAL);
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == id {
+                    closure.take().map(|closure| result = closure(process));
+                }
+            });
+        result
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for ProcessInfo<C> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the number of processes.
+    /// - `2`: Return the identifier of process number `data1`, in the order of
+    ///   the kernel's process table.
+    /// - `3`: Return the identifier of the calling process.
+    /// - `4`: Return the time in microseconds process `data1` spent executing,
+    ///   as a u64.
+    /// - `5`: Return the part of that time in microseconds the kernel spent
+    ///   handling syscalls of process `data1`, as a u64.
+    /// - `6`: Return the number of syscalls, the number of preemptions, and
+    ///   the number of timeslice expirations of process `data1`.
+    ///
+    /// - `7`: Return the number of bytes process `data1` uses in its grant
+    ///   region, and the number of bytes still free for grants.
+    /// - `8`: Return the driver number and the size in bytes of grant number
+    ///   `data2` process `data1` allocated, or `INVAL` if it allocated fewer
+    ///   grants.
+    ///
+    /// Commands 4 to 8 return `INVAL` if there is no process with identifier
+    /// `data1`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let mut count = 0;
+                self.kernel
+                    .process_each_capability(&self.capability, |_| count += 1);
+                CommandReturn::success_u32(count)
+            }
+
+            2 => {
+                let mut index = 0;
+                let mut result = CommandReturn::failure(ErrorCode::INVAL);
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if index == data1 {
+                            result = CommandReturn::success_u32(process.processid().id() as u32);
+                        }
+                        index += 1;
+                    });
+                result
+            }
+
+            3 => CommandReturn::success_u32(processid.id() as u32),
+
+            4 => self.with_process(data1, |process| {
+                CommandReturn::success_u64(process.debug_execution_time_us())
+            }),
+
+            5 => self.with_process(data1, |process| {
+                CommandReturn::success_u64(process.debug_syscall_time_us())
+            }),
+
+            6 => self.with_process(data1, |process| {
+                CommandReturn::success_u32_u32_u32(
+                    process.debug_syscall_count() as u32,
+                    process.debug_preemption_count() as u32,
+                    process.debug_timeslice_expiration_count() as u32,
+                )
+            }),
+
+            7 => self.with_process(data1, |process| {
+                let addresses = process.get_addresses();
+                let sizes = process.get_sizes();
+                // The grant region starts below the process struct, the upcalls
+                // and the grant pointers at the end of the process memory.
+                let grant_end = addresses.sram_end
+                    - sizes.grant_pointers
+                    - sizes.upcall_list
+                    - sizes.process_control_block;
+                CommandReturn::success_u32_u32(
+                    (grant_end - addresses.sram_grant_start) as u32,
+                    (addresses.sram_grant_start - addresses.sram_app_brk) as u32,
+                )
+            }),
+
+            8 => self.with_process(data1, |process| {
+                let mut index = 0;
+                let mut result = CommandReturn::failure(ErrorCode::INVAL);
+                process.grant_allocations(&mut |driver_num, size| {
+                    if index == data2 {
+                        result = CommandReturn::success_u32_u32(driver_num as u32, size as u32);
+                    }
+                    index += 1;
+                });
+                result
+            }),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Lets a management process load new processes at runtime.
+//!
+//! New TBF objects are written into the spare flash region of a
+//! `kernel::process::DynamicProcessLoader`, e.g. by an installer process
+//! using a flash driver, and then loaded with command 1 of this driver
+//! without rebooting. The new processes go through the board's credential
+//! checks and are started by the kernel once approved.
+//!
+//! Only processes whose `ShortID` is in the privileged list passed to
+//! `ProcessLoader::new()` may use this driver. ShortIDs are assigned by the
+//! board's credentials checking policy from the credentials of a process, so
+//! unlike its package name a process cannot choose its own ShortID. Processes
+//! with a locally unique ShortID are never privileged.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let loader = static_init!(
+//!     kernel::process::DynamicProcessLoader<nrf52840::chip::NRF52<Nrf52840DefaultPeripherals>>,
+//!     kernel::process::DynamicProcessLoader::new(
+//!         board_kernel,
+//!         chip,
+//!         spare_flash,
+//!         spare_memory,
+//!         &FAULT_RESPONSE,
+//!         &process_management_capability,
+//!     )
+//! );
+//! let process_loader = components::process_loader::ProcessLoaderComponent::new(
+//!     loader,
+//!     &[ShortID::Fixed(INSTALLER_SHORT_ID)],
+//! )
+//! .finalize(components::process_loader_component_static!());
+//! ```
+
+use kernel::process::{DynamicProcessLoading, ProcessLoadError, ShortID};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessLoader as usize;
+
+pub struct ProcessLoader<'a> {
+    loader: &'a dyn DynamicProcessLoading,
+    /// ShortIDs of the processes allowed to use this driver.
+    privileged: &'a [ShortID],
+}
+
+impl<'a> ProcessLoader<'a> {
+    pub fn new(loader: &'a dyn DynamicProcessLoading, privileged: &'a [ShortID]) -> Self {
+        ProcessLoader { loader, privileged }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        self.privileged.contains(&processid.short_app_id())
+    }
+}
+
+fn into_error_code(error: ProcessLoadError) -> ErrorCode {
+    match error {
+        ProcessLoadError::NotEnoughMemory | ProcessLoadError::NoProcessSlot => ErrorCode::NOMEM,
+        ProcessLoadError::InternalError => ErrorCode::FAIL,
+        _ => ErrorCode::INVAL,
+    }
+}
+
+/// Provide a syscall interface for loading processes.
+///
+/// ### `command_num`
+///
+/// - `0`: Driver existence check.
+/// - `1`: Load the processes written to the spare flash region since the
+///   last call. Returns the number of processes created. Returns `NOMEM` if
+///   a process does not fit in the remaining RAM or the board supports no
+///   more processes, and `INVAL` if a process is malformed or incompatible
+///   with the board. The failing process is skipped by the next call.
+/// - `2`: Returns the number of bytes of the spare flash region not used by
+///   loaded processes.
+///
+/// All commands other than 0 return `NODEVICE`, like a command denied by the
+/// kernel, if the calling process is not privileged.
+impl SyscallDriver for ProcessLoader<'_> {
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_privileged(processid) {
+            return CommandReturn::failure(ErrorCode::NODEVICE);
+        }
+
+        match command_num {
+            1 => match self.loader.load_new_processes() {
+                Ok(count) => CommandReturn::success_u32(count as u32),
+                Err(e) => CommandReturn::failure(into_error_code(e)),
+            },
+            2 => CommandReturn::success_u32(self.loader.remaining_flash() as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
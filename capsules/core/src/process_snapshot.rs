@@ -0,0 +1,228 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Lets a process keep state across restarts.
+//!
+//! A process reserves a region of its grant memory once. The kernel keeps
+//! the contents of the region when the process restarts, e.g. after a fault,
+//! and hands the region back to the new instance of the process. The process
+//! regularly saves its state to the region and, once restarted, checks
+//! whether the region was recovered and restores its state from it.
+//!
+//! The region is only kept while the process restarts. It is lost if the
+//! board resets, or if there is not enough grant memory for it when the
+//! process restarts.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let process_snapshot = components::process_snapshot::ProcessSnapshotComponent::new(
+//!     board_kernel,
+//!     capsules_core::process_snapshot::DRIVER_NUM,
+//! )
+//! .finalize(components::process_snapshot_component_static!());
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::ProcessSnapshot as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// State to save to the preserved region.
+    pub const SAVE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the preserved region is restored into.
+    pub const RESTORE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+pub struct ProcessSnapshot<C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    apps: Grant<
+        (),
+        UpcallCount<0>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability> ProcessSnapshot<C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<
+            (),
+            UpcallCount<0>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        capability: C,
+    ) -> Self {
+        ProcessSnapshot {
+            kernel,
+            apps: grant,
+            capability,
+        }
+    }
+
+    fn has_region(&self, processid: ProcessId) -> bool {
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| process.get_preserved_region_info().is_some(),
+            &self.capability,
+        )
+    }
+
+    /// Copy the save buffer of the process to its preserved region. Returns
+    /// the number of bytes copied.
+    fn save(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        if !self.has_region(processid) {
+            return Err(ErrorCode::RESERVE);
+        }
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::SAVE)
+                    .and_then(|buffer| {
+                        buffer.enter(|buffer| {
+                            let mut copied = 0;
+                            self.kernel.process_map_or_external(
+                                (),
+                                processid,
+                                |process| {
+                                    process.map_preserved_region(&mut |region| {
+                                        copied = region.len().min(buffer.len());
+                                        buffer[0..copied].copy_to_slice(&mut region[..copied]);
+                                    })
+                                },
+                                &self.capability,
+                            );
+                            copied
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Copy the preserved region of the process to its restore buffer.
+    /// Returns the number of bytes copied.
+    fn restore(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        if !self.has_region(processid) {
+            return Err(ErrorCode::RESERVE);
+        }
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::RESTORE)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            let mut copied = 0;
+                            self.kernel.process_map_or_external(
+                                (),
+                                processid,
+                                |process| {
+                                    process.map_preserved_region(&mut |region| {
+                                        copied = region.len().min(buffer.len());
+                                        buffer[0..copied].copy_from_slice(&region[..copied]);
+                                    })
+                                },
+                                &self.capability,
+                            );
+                            copied
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<C: ProcessManagementCapability> SyscallDriver for ProcessSnapshot<C> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Reserve a preserved region of `data1` bytes. Returns `ALREADY`
+    ///   if the process reserved one before, possibly as a previous instance,
+    ///   and `NOMEM` if there is not enough grant memory.
+    /// - `2`: Return the size of the preserved region, and 1 if its contents
+    ///   were recovered from a previous instance of the process or 0 if not.
+    ///   Returns `(0, 0)` if the process has no preserved region.
+    /// - `3`: Copy read-only allow buffer 0 to the preserved region. Returns
+    ///   the number of bytes copied.
+    /// - `4`: Copy the preserved region to read-write allow buffer 0. Returns
+    ///   the number of bytes copied.
+    ///
+    /// Commands 3 and 4 return `RESERVE` if the process has no preserved
+    /// region.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self
+                .kernel
+                .process_map_or_external(
+                    Err(ErrorCode::FAIL),
+                    processid,
+                    |process| process.allocate_preserved_region(data1),
+                    &self.capability,
+                )
+                .into(),
+
+            2 => {
+                let info = self.kernel.process_map_or_external(
+                    None,
+                    processid,
+                    |process| process.get_preserved_region_info(),
+                    &self.capability,
+                );
+                match info {
+                    Some((size, recovered)) => {
+                        CommandReturn::success_u32_u32(size as u32, recovered as u32)
+                    }
+                    None => CommandReturn::success_u32_u32(0, 0),
+                }
+            }
+
+            3 => match self.save(processid) {
+                Ok(copied) => CommandReturn::success_u32(copied as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            4 => match self.restore(processid) {
+                Ok(copied) => CommandReturn::success_u32(copied as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
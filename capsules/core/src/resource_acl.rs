@@ -0,0 +1,88 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Access control for the resources of a driver, such as GPIO pins or ADC
+//! channels.
+//!
+//! Drivers that expose several resources to processes, like the `gpio` and
+//! the `adc` capsules, ask a `ResourceAcl` whether a process may use a
+//! resource before using it on its behalf. A denied resource looks to the
+//! process as if it did not exist, i.e. `ErrorCode::NODEVICE` is returned.
+//!
+//! `AppNameAcl` restricts resources to processes by name, which boards
+//! configure with a list of `AclEntry`s. Resources that are not listed are
+//! available to all processes.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! // Only the motor control app may use GPIO pins 2 and 3.
+//! let acl = static_init!(
+//!     capsules_core::resource_acl::AppNameAcl<'static, MainCap>,
+//!     capsules_core::resource_acl::AppNameAcl::new(
+//!         board_kernel,
+//!         &[
+//!             AclEntry { index: 2, apps: &["motor"] },
+//!             AclEntry { index: 3, apps: &["motor"] },
+//!         ],
+//!         create_capability!(capabilities::ProcessManagementCapability),
+//!     )
+//! );
+//! gpio.set_acl(acl);
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::{Kernel, ProcessId};
+
+/// Decides which processes may use which resources of a driver.
+pub trait ResourceAcl {
+    /// Whether `processid` may use resource `index` of the driver.
+    fn allowed(&self, processid: ProcessId, index: usize) -> bool;
+}
+
+/// Processes allowed to use resource `index`.
+pub struct AclEntry<'a> {
+    pub index: usize,
+    pub apps: &'a [&'a str],
+}
+
+/// Restricts resources to the processes with the listed names.
+pub struct AppNameAcl<'a, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    entries: &'a [AclEntry<'a>],
+    capability: C,
+}
+
+impl<'a, C: ProcessManagementCapability> AppNameAcl<'a, C> {
+    pub fn new(kernel: &'static Kernel, entries: &'a [AclEntry<'a>], capability: C) -> Self {
+        AppNameAcl {
+            kernel,
+            entries,
+            capability,
+        }
+    }
+}
+
+impl<'a, C: ProcessManagementCapability> ResourceAcl for AppNameAcl<'a, C> {
+    fn allowed(&self, processid: ProcessId, index: usize) -> bool {
+        let mut entries = self
+            .entries
+            .iter()
+            .filter(|entry| entry.index == index)
+            .peekable();
+        if entries.peek().is_none() {
+            return true;
+        }
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| {
+                let name = process.get_process_name();
+                entries.any(|entry| entry.apps.contains(&name))
+            },
+            &self.capability,
+        )
+    }
+}
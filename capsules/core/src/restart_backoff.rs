@@ -0,0 +1,345 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Fault policy that restarts faulted processes with exponential backoff.
+//!
+//! When a process faults it is stopped, and restarted after a delay that
+//! starts at `initial_backoff_ms` and doubles with every further fault, up to
+//! `max_backoff_ms`. A process that keeps crashing thus cannot monopolize the
+//! processor with restarts. After `max_faults` faults the process is no longer
+//! restarted.
+//!
+//! The policy keeps the number of faults of up to `N` processes, by process
+//! name. It shows them to userspace through a syscall driver, and to the
+//! process console through the [`FaultHistory`] trait.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let fault_policy = components::restart_backoff::RestartBackoffComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     100,
+//!     10_000,
+//!     8,
+//! )
+//! .finalize(components::restart_backoff_component_static!(nrf52840::rtc::Rtc, 4));
+//!
+//! // Give `fault_policy` to the process loader, and the console:
+//! process_console.set_fault_history(fault_policy);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
+use kernel::process::{self, Process, ProcessFaultPolicy, State};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{debug, ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::RestartPolicy as usize;
+
+/// What the policy does with a process.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RestartState {
+    /// The process has not faulted or has been restarted.
+    Running = 0,
+    /// The process faulted and waits for its restart.
+    Waiting = 1,
+    /// The process faulted too often and is no longer restarted.
+    Stopped = 2,
+}
+
+/// Fault history of a process.
+#[derive(Clone, Copy)]
+pub struct FaultRecord {
+    /// Number of times the process faulted.
+    pub faults: u32,
+    pub state: RestartState,
+    /// Time since the last fault, in milliseconds.
+    pub last_fault_ms: u32,
+    /// Delay before the last restart, in milliseconds.
+    pub backoff_ms: u32,
+}
+
+/// Interface to query the fault history of the processes.
+pub trait FaultHistory {
+    /// Return the fault history of the process with the name `process_name`,
+    /// or `None` if it never faulted.
+    fn fault_record(&self, process_name: &str) -> Option<FaultRecord>;
+}
+
+/// Delay before the restart after fault number `faults`.
+pub fn backoff_ms(initial_backoff_ms: u32, max_backoff_ms: u32, faults: u32) -> u32 {
+    let shift = faults.saturating_sub(1).min(31);
+    initial_backoff_ms
+        .checked_mul(1 << shift)
+        .unwrap_or(u32::MAX)
+        .min(max_backoff_ms)
+}
+
+#[derive(Clone, Copy)]
+struct Entry<T: Ticks> {
+    name: &'static str,
+    faults: u32,
+    state: RestartState,
+    last_fault: T,
+    backoff_ms: u32,
+    /// The process is restarted `restart_dt` after `last_fault`.
+    restart_dt: T,
+}
+
+pub struct RestartBackoffPolicy<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize> {
+    kernel: &'static Kernel,
+    alarm: &'a A,
+    entries: [Cell<Option<Entry<A::Ticks>>>; N],
+    initial_backoff_ms: u32,
+    max_backoff_ms: u32,
+    max_faults: u32,
+    capability: C,
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize>
+    RestartBackoffPolicy<'a, A, C, N>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        alarm: &'a A,
+        initial_backoff_ms: u32,
+        max_backoff_ms: u32,
+        max_faults: u32,
+        capability: C,
+    ) -> Self {
+        RestartBackoffPolicy {
+            kernel,
+            alarm,
+            entries: [(); N].map(|()| Cell::new(None)),
+            initial_backoff_ms,
+            max_backoff_ms,
+            max_faults,
+            capability,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Cell<Option<Entry<A::Ticks>>>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.get().map_or(false, |entry| entry.name == name))
+    }
+
+    /// Arm the alarm for the next process to restart, if any.
+    fn arm(&self) {
+        let now = self.alarm.now();
+        let mut next: Option<(A::Ticks, A::Ticks)> = None;
+        for entry in self.entries.iter().filter_map(|entry| entry.get()) {
+            if entry.state != RestartState::Waiting {
+                continue;
+            }
+            let remaining = entry
+                .last_fault
+                .wrapping_add(entry.restart_dt)
+                .wrapping_sub(now);
+            let expired = !now.within_range(
+                entry.last_fault,
+                entry.last_fault.wrapping_add(entry.restart_dt),
+            );
+            let remaining = if expired {
+                A::Ticks::from(0)
+            } else {
+                remaining
+            };
+            if next.map_or(true, |(_, dt)| remaining < dt) {
+                next = Some((now, remaining));
+            }
+        }
+        match next {
+            Some((reference, dt)) => self.alarm.set_alarm(reference, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn record(&self, name: &str) -> Option<FaultRecord> {
+        self.find(name).and_then(|entry| entry.get()).map(|entry| {
+            let elapsed = self.alarm.now().wrapping_sub(entry.last_fault);
+            FaultRecord {
+                faults: entry.faults,
+                state: entry.state,
+                last_fault_ms: self.alarm.ticks_to_ms(elapsed),
+                backoff_ms: entry.backoff_ms,
+            }
+        })
+    }
+
+    /// Run `closure` on the fault history of the process with identifier
+    /// `id`, or return `INVAL` if there is no such process.
+    fn with_record<F>(&self, id: usize, closure: F) -> CommandReturn
+    where
+        F: FnOnce(Option<FaultRecord>) -> CommandReturn,
+    {
+        let mut name = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == id {
+                    name = Some(process.get_process_name());
+                }
+            });
+        match name {
+            Some(name) => closure(self.record(name)),
+            None => CommandReturn::failure(ErrorCode::INVAL),
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize> ProcessFaultPolicy
+    for RestartBackoffPolicy<'a, A, C, N>
+{
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        let name = process.get_process_name();
+        let now = self.alarm.now();
+        let slot = self
+            .find(name)
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()));
+        let slot = match slot {
+            Some(slot) => slot,
+            None => {
+                debug!("Process {} faulted and was stopped: no fault record.", name);
+                return process::FaultAction::Stop;
+            }
+        };
+
+        let faults = slot.get().map_or(0, |entry| entry.faults).saturating_add(1);
+        let mut entry = Entry {
+            name,
+            faults,
+            state: RestartState::Stopped,
+            last_fault: now,
+            backoff_ms: 0,
+            restart_dt: A::Ticks::from(0),
+        };
+        if faults > self.max_faults {
+            debug!("Process {} faulted {} times and was stopped.", name, faults);
+        } else {
+            entry.state = RestartState::Waiting;
+            entry.backoff_ms = backoff_ms(self.initial_backoff_ms, self.max_backoff_ms, faults);
+            entry.restart_dt = self.alarm.ticks_from_ms(entry.backoff_ms);
+        }
+        slot.set(Some(entry));
+        self.arm();
+
+        // The process is restarted by the alarm.
+        process::FaultAction::Stop
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize> time::AlarmClient
+    for RestartBackoffPolicy<'a, A, C, N>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        for slot in self.entries.iter() {
+            let mut entry = match slot.get() {
+                Some(entry) if entry.state == RestartState::Waiting => entry,
+                _ => continue,
+            };
+            if now.within_range(
+                entry.last_fault,
+                entry.last_fault.wrapping_add(entry.restart_dt),
+            ) {
+                continue;
+            }
+
+            self.kernel
+                .process_each_capability(&self.capability, |process| {
+                    // The process may have been restarted in the meantime,
+                    // e.g. from the process console.
+                    if process.get_process_name() == entry.name
+                        && process.get_state() == State::Faulted
+                    {
+                        process.try_restart(None);
+                    }
+                });
+            entry.state = RestartState::Running;
+            slot.set(Some(entry));
+        }
+        self.arm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize> FaultHistory
+    for RestartBackoffPolicy<'a, A, C, N>
+{
+    fn fault_record(&self, process_name: &str) -> Option<FaultRecord> {
+        self.record(process_name)
+    }
+}
+
+impl<'a, A: Alarm<'a>, C: ProcessManagementCapability, const N: usize> SyscallDriver
+    for RestartBackoffPolicy<'a, A, C, N>
+{
+    /// Command interface.
+    ///
+    /// Processes are identified by their process identifier, see the process
+    /// info driver.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the number of faults of process `data1` and its
+    ///   restart state: 0 running, 1 waiting for its restart, 2 no longer
+    ///   restarted.
+    /// - `2`: Return the time in milliseconds since process `data1` last
+    ///   faulted, and the delay in milliseconds before its last restart.
+    ///   Returns `FAIL` if the process never faulted.
+    ///
+    /// Commands 1 and 2 return `INVAL` if there is no process with
+    /// identifier `data1`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.with_record(data1, |record| match record {
+                Some(record) => CommandReturn::success_u32_u32(record.faults, record.state as u32),
+                None => CommandReturn::success_u32_u32(0, RestartState::Running as u32),
+            }),
+
+            2 => self.with_record(data1, |record| match record {
+                Some(record) => {
+                    CommandReturn::success_u32_u32(record.last_fault_ms, record.backoff_ms)
+                }
+                None => CommandReturn::failure(ErrorCode::FAIL),
+            }),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff_ms(100, 1000, 1), 100);
+        assert_eq!(backoff_ms(100, 1000, 2), 200);
+        assert_eq!(backoff_ms(100, 1000, 4), 800);
+        assert_eq!(backoff_ms(100, 1000, 5), 1000);
+        assert_eq!(backoff_ms(100, 1000, 40), 1000);
+    }
+}
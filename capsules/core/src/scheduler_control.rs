@@ -0,0 +1,192 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Lets a management process change the scheduling parameters of processes.
+//!
+//! The board picks the scheduler, but how the scheduler treats each process
+//! can be adjusted at runtime with hints (`kernel::scheduler::SchedulingHint`):
+//! the priority, the timeslice or a CPU time budget. Each scheduler supports
+//! the hints that map to its policy, e.g. the round robin scheduler supports
+//! timeslices, the MLFQ scheduler priorities and the EDF and duty cycling
+//! schedulers budgets.
+//! Other hints fail with `NOSUPPORT`.
+//!
+//! Only processes whose `ShortID` is in the privileged list passed to
+//! `SchedulerControl::new()` may use this driver. ShortIDs are assigned by
+//! the board's credentials checking policy, so unlike its package name a
+//! process cannot choose its own ShortID.
+//!
+//! Processes are identified by their process identifier (`ProcessId::id()`),
+//! as with the `process_info` driver. A management process first selects the
+//! process to change with command 1.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let scheduler_control = components::scheduler_control::SchedulerControlComponent::new(
+//!     board_kernel,
+//!     capsules_core::scheduler_control::DRIVER_NUM,
+//!     scheduler,
+//!     &[ShortID::Fixed(MANAGER_SHORT_ID)],
+//! )
+//! .finalize(components::scheduler_control_component_static!(
+//!     nrf52840::chip::NRF52<'static, Nrf52840DefaultPeripherals<'static>>,
+//!     kernel::scheduler::round_robin::RoundRobinSched<'static>,
+//! ));
+//! ```
+
+use core::marker::PhantomData;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::platform::chip::Chip;
+use kernel::process::ShortID;
+use kernel::scheduler::{Scheduler, SchedulingHint};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use crate::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SchedulerControl as usize;
+
+#[derive(Default)]
+pub struct App {
+    /// Process selected with command 1.
+    target: Option<ProcessId>,
+}
+
+pub struct SchedulerControl<'a, C: Chip, S: Scheduler<C>, P: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    scheduler: &'a S,
+    /// ShortIDs of the processes allowed to use this driver.
+    privileged: &'a [ShortID],
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    capability: P,
+    _chip: PhantomData<C>,
+}
+
+impl<'a, C: Chip, S: Scheduler<C>, P: ProcessManagementCapability> SchedulerControl<'a, C, S, P> {
+    pub fn new(
+        kernel: &'static Kernel,
+        scheduler: &'a S,
+        privileged: &'a [ShortID],
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+        capability: P,
+    ) -> Self {
+        SchedulerControl {
+            kernel,
+            scheduler,
+            privileged,
+            apps: grant,
+            capability,
+            _chip: PhantomData,
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        self.privileged.contains(&processid.short_app_id())
+    }
+
+    /// Returns the process with identifier `id`.
+    fn find_process(&self, id: usize) -> Option<ProcessId> {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == id {
+                    found = Some(process.processid());
+                }
+            });
+        found
+    }
+
+    /// Pass `hint` for the process selected by `processid` to the scheduler.
+    fn set_hint(&self, processid: ProcessId, hint: SchedulingHint) -> Result<(), ErrorCode> {
+        let target = self
+            .apps
+            .enter(processid, |app, _| app.target)
+            .map_err(ErrorCode::from)?
+            .ok_or(ErrorCode::RESERVE)?;
+        self.scheduler.set_hint(target, hint)
+    }
+}
+
+impl<'a, C: Chip, S: Scheduler<C>, P: ProcessManagementCapability> SyscallDriver
+    for SchedulerControl<'a, C, S, P>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Select the process with identifier `data1` for the following
+    ///   commands. Returns `INVAL` if there is no such process.
+    /// - `2`: Run the selected process at priority `data1`, `0` being the
+    ///   highest.
+    /// - `3`: Run the selected process with a timeslice of `data1`
+    ///   microseconds, at least 500.
+    /// - `4`: Give the selected process `data2` microseconds of CPU time every
+    ///   `data1` microseconds.
+    /// - `5`: Restore the default treatment of the selected process.
+    ///
+    /// Commands 2 to 5 return `RESERVE` if no process is selected,
+    /// `NOSUPPORT` if the scheduler does not support the parameter and
+    /// `INVAL` if the value is out of range or the selected process no
+    /// longer exists. All commands other than 0 return `NODEVICE`, like a
+    /// command denied by the kernel, if the calling process is not
+    /// privileged.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_privileged(processid) {
+            return CommandReturn::failure(ErrorCode::NODEVICE);
+        }
+
+        match command_num {
+            1 => match self.find_process(data1) {
+                Some(target) => self
+                    .apps
+                    .enter(processid, |app, _| {
+                        app.target = Some(target);
+                        CommandReturn::success()
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            2 => self
+                .set_hint(processid, SchedulingHint::Priority(data1 as u32))
+                .into(),
+
+            3 => self
+                .set_hint(processid, SchedulingHint::Timeslice(data1 as u32))
+                .into(),
+
+            4 => self
+                .set_hint(
+                    processid,
+                    SchedulingHint::Budget {
+                        period_us: data1 as u32,
+                        budget_us: data2 as u32,
+                    },
+                )
+                .into(),
+
+            5 => self.set_hint(processid, SchedulingHint::Reset).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
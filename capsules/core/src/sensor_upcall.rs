@@ -0,0 +1,201 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Encoding of sensor readings in upcall arguments.
+//!
+//! Sensor drivers pass readings to userspace in the three arguments of an
+//! upcall. So that userspace libraries need not know the unit each driver
+//! uses, drivers describe their readings with a header in the first
+//! argument:
+//!
+//! | Bits   | Field                                                  |
+//! |--------|--------------------------------------------------------|
+//! | 0..8   | 0 on success, otherwise the `ErrorCode` of the failure |
+//! | 8..16  | `Unit` of the values                                   |
+//! | 16..24 | Scale, a signed power of ten the values are scaled by  |
+//! | 24..32 | Number of values                                       |
+//!
+//! One or two values are passed as `i32` in the second and third argument.
+//! Three values, e.g. the axes of an accelerometer, are passed as `i16`:
+//! the first two in the low and high half of the second argument, the third
+//! in the third argument. Values that do not fit are divided by ten, and the
+//! scale increased accordingly, until they do.
+//!
+//! A temperature of 21.37 °C, for example, is `DegreesCelsius` with a scale
+//! of -2 and the value 2137.
+
+use kernel::ErrorCode;
+
+/// Unit of the values of a reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    None = 0,
+    DegreesCelsius = 1,
+    /// Percent of relative humidity.
+    RelativeHumidity = 2,
+    Pascal = 3,
+    /// Acceleration in multiples of the standard gravity (g).
+    StandardGravity = 4,
+    Gauss = 5,
+    DegreesPerSecond = 6,
+}
+
+impl TryFrom<u8> for Unit {
+    type Error = ();
+
+    fn try_from(unit: u8) -> Result<Self, ()> {
+        match unit {
+            0 => Ok(Unit::None),
+            1 => Ok(Unit::DegreesCelsius),
+            2 => Ok(Unit::RelativeHumidity),
+            3 => Ok(Unit::Pascal),
+            4 => Ok(Unit::StandardGravity),
+            5 => Ok(Unit::Gauss),
+            6 => Ok(Unit::DegreesPerSecond),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A reading decoded from the upcall arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reading {
+    pub unit: Unit,
+    pub scale: i8,
+    pub values: [i32; 3],
+    /// Number of valid entries in `values`.
+    pub count: usize,
+}
+
+/// Error codes by their value, starting at 1.
+const ERRORS: [ErrorCode; 13] = [
+    ErrorCode::FAIL,
+    ErrorCode::BUSY,
+    ErrorCode::ALREADY,
+    ErrorCode::OFF,
+    ErrorCode::RESERVE,
+    ErrorCode::INVAL,
+    ErrorCode::SIZE,
+    ErrorCode::CANCEL,
+    ErrorCode::NOMEM,
+    ErrorCode::NOSUPPORT,
+    ErrorCode::NODEVICE,
+    ErrorCode::UNINSTALLED,
+    ErrorCode::NOACK,
+];
+
+fn header(status: u8, unit: Unit, scale: i8, count: usize) -> usize {
+    status as usize | (unit as usize) << 8 | (scale as u8 as usize) << 16 | count << 24
+}
+
+/// Encode the upcall arguments for a reading of up to three `values`.
+pub fn encode(unit: Unit, scale: i8, values: &[i32]) -> (usize, usize, usize) {
+    match *values {
+        [value] => (header(0, unit, scale, 1), value as usize, 0),
+        [first, second] => (header(0, unit, scale, 2), first as usize, second as usize),
+        [mut x, mut y, mut z] => {
+            let mut scale = scale;
+            let fits = |value: i32| value >= i16::MIN as i32 && value <= i16::MAX as i32;
+            while !(fits(x) && fits(y) && fits(z)) {
+                x /= 10;
+                y /= 10;
+                z /= 10;
+                scale += 1;
+            }
+            (
+                header(0, unit, scale, 3),
+                (x as u16 as usize) | (y as u16 as usize) << 16,
+                z as usize,
+            )
+        }
+        _ => (header(0, unit, scale, 0), 0, 0),
+    }
+}
+
+/// Encode the upcall arguments for a reading that failed.
+pub fn encode_error(error: ErrorCode) -> (usize, usize, usize) {
+    (header(error as u8, Unit::None, 0, 0), 0, 0)
+}
+
+/// Decode the upcall arguments of a reading.
+pub fn decode(args: (usize, usize, usize)) -> Result<Reading, ErrorCode> {
+    let (header, arg1, arg2) = args;
+    let status = header as u8;
+    if status != 0 {
+        return Err(ERRORS
+            .get(status as usize - 1)
+            .copied()
+            .unwrap_or(ErrorCode::FAIL));
+    }
+    let unit = Unit::try_from((header >> 8) as u8).map_err(|()| ErrorCode::INVAL)?;
+    let scale = (header >> 16) as u8 as i8;
+    let count = (header >> 24) as u8 as usize;
+    let values = match count {
+        0 => [0, 0, 0],
+        1 => [arg1 as i32, 0, 0],
+        2 => [arg1 as i32, arg2 as i32, 0],
+        3 => [
+            arg1 as u16 as i16 as i32,
+            (arg1 >> 16) as u16 as i16 as i32,
+            arg2 as i32,
+        ],
+        _ => return Err(ErrorCode::INVAL),
+    };
+    Ok(Reading {
+        unit,
+        scale,
+        values,
+        count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(
+            decode(encode(Unit::DegreesCelsius, -2, &[-1234])),
+            Ok(Reading {
+                unit: Unit::DegreesCelsius,
+                scale: -2,
+                values: [-1234, 0, 0],
+                count: 1,
+            })
+        );
+        assert_eq!(
+            decode(encode(Unit::Pascal, -1, &[1_013_250, i32::MIN])),
+            Ok(Reading {
+                unit: Unit::Pascal,
+                scale: -1,
+                values: [1_013_250, i32::MIN, 0],
+                count: 2,
+            })
+        );
+        assert_eq!(
+            decode(encode(Unit::StandardGravity, -3, &[-981, 12, 32767])),
+            Ok(Reading {
+                unit: Unit::StandardGravity,
+                scale: -3,
+                values: [-981, 12, 32767],
+                count: 3,
+            })
+        );
+        assert_eq!(decode(encode_error(ErrorCode::BUSY)), Err(ErrorCode::BUSY));
+    }
+
+    #[test]
+    fn rescale_axes() {
+        assert_eq!(
+            decode(encode(Unit::DegreesPerSecond, -3, &[-2_000_000, 5, 40_000])),
+            Ok(Reading {
+                unit: Unit::DegreesPerSecond,
+                scale: -1,
+                values: [-20_000, 0, 400],
+                count: 3,
+            })
+        );
+    }
+}
@@ -10,6 +10,9 @@
 //! fields: the AuthData and either the PlaintextData or the CiphertextData.
 //! Then, two passes of AES are performed with one block of overlap.
 //!
+//! The nonce is 13 bytes for CCM*, but plain CCM with shorter nonces, such
+//! as the 12-byte nonces of (D)TLS, is supported as well.
+//!
 //! ```text
 //! crypt_buf: [ -------- AuthData -------- | -------- PData/CData -------- ]
 //! aes_cbc:    \__________________________/
@@ -87,7 +90,8 @@ use kernel::debug;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::symmetric_encryption;
 use kernel::hil::symmetric_encryption::{
-    AES128Ctr, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE, CCM_NONCE_LENGTH,
+    AES128Ctr, AES128, AES128CBC, AES128ECB, AES128_BLOCK_SIZE, AES128_KEY_SIZE,
+    CCM_MIN_NONCE_LENGTH, CCM_NONCE_LENGTH,
 };
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
@@ -249,6 +253,7 @@ pub struct VirtualAES128CCM<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128EC
     pos: Cell<(usize, usize, usize, usize)>,
     key: Cell<[u8; AES128_KEY_SIZE]>,
     nonce: Cell<[u8; CCM_NONCE_LENGTH]>,
+    nonce_len: Cell<usize>,
     saved_tag: Cell<[u8; AES128_BLOCK_SIZE]>,
     queued_up: OptionalCell<CryptFunctionParameters>,
 }
@@ -273,6 +278,7 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
             pos: Cell::new((0, 0, 0, 0)),
             key: Cell::new(Default::default()),
             nonce: Cell::new(Default::default()),
+            nonce_len: Cell::new(CCM_NONCE_LENGTH),
             saved_tag: Cell::new(Default::default()),
             queued_up: OptionalCell::empty(),
         }
@@ -288,7 +294,7 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
     /// not present or if it is not long enough.
     fn prepare_ccm_buffer(
         &self,
-        nonce: &[u8; CCM_NONCE_LENGTH],
+        nonce: &[u8],
         mic_len: usize,
         a_data: &[u8],
         m_data: &[u8],
@@ -326,7 +332,7 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
     /// guaranteed to be >= AES128_BLOCK_SIZE
     fn encode_ccm_buffer(
         buf: &mut [u8],
-        nonce: &[u8; CCM_NONCE_LENGTH],
+        nonce: &[u8],
         mic_len: usize,
         a_data: &[u8],
         m_data: &[u8],
@@ -334,7 +340,7 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
         // IEEE 802.15.4-2015: Appendix B.4.1.2, CCM* authentication
         // The authentication tag T is computed with AES128-CBC-MAC on
         // B_0 | AuthData, where
-        //   B_0 = Flags (1 byte) | nonce (13 bytes) | m length (2 bytes)
+        //   B_0 = Flags (1 byte) | nonce (15 - L bytes) | m length (L bytes)
         //   Flags = 0 | A data present? (1 bit) | M (3 bits) | L (3 bits)
         //   AuthData = AddAuthData | PlaintextData
         //   AddAuthData = L(a) (encoding of a_data.len()) | a_data
@@ -350,14 +356,18 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
         if mic_len != 0 {
             flags |= (((mic_len - 2) / 2) as u8) << 3;
         }
-        flags |= 1;
+        // L is 2 for the 13-byte nonces of CCM*
+        let l = AES128_BLOCK_SIZE - 1 - nonce.len();
+        flags |= (l - 1) as u8;
 
         stream_len_cond!(buf, AES128_BLOCK_SIZE);
         // The first block is flags | nonce | m length
         buf[0] = flags;
-        buf[1..14].copy_from_slice(nonce.as_ref());
-        let mut off = enc_consume!(buf, 14; encode_u16,
-                                            (m_data.len() as u16).to_le());
+        buf[1..1 + nonce.len()].copy_from_slice(nonce);
+        for i in 0..l {
+            buf[AES128_BLOCK_SIZE - 1 - i] = (m_data.len() >> (8 * i)) as u8;
+        }
+        let mut off = AES128_BLOCK_SIZE;
 
         // After that comes L(a) | a, where L(a) is the following
         // encoding of a_len:
@@ -466,9 +476,10 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
 
         let mut iv = [0u8; AES128_BLOCK_SIZE];
         // flags = reserved | reserved | 0 | (L - 1)
-        // Since L = 2, flags = 1.
-        iv[0] = 1;
-        iv[1..1 + CCM_NONCE_LENGTH].copy_from_slice(&self.nonce.get());
+        // With 13-byte nonces, L = 2 and flags = 1.
+        let nonce_len = self.nonce_len.get();
+        iv[0] = (AES128_BLOCK_SIZE - 2 - nonce_len) as u8;
+        iv[1..1 + nonce_len].copy_from_slice(&self.nonce.get()[..nonce_len]);
         let res = self.aes.set_iv(&iv);
         if res != Ok(()) {
             return res;
@@ -626,7 +637,7 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> VirtualAES128CCM<'a,
         self.encrypting.set(encrypting);
 
         let res = self.prepare_ccm_buffer(
-            &self.nonce.get(),
+            &self.nonce.get()[..self.nonce_len.get()],
             mic_len,
             &buf[a_off..m_off],
             &buf[m_off..m_off + m_len],
@@ -678,12 +689,13 @@ impl<'a, A: AES128<'a> + AES128Ctr + AES128CBC + AES128ECB> symmetric_encryption
     }
 
     fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode> {
-        if nonce.len() < CCM_NONCE_LENGTH {
+        if nonce.len() < CCM_MIN_NONCE_LENGTH || nonce.len() > CCM_NONCE_LENGTH {
             Err(ErrorCode::INVAL)
         } else {
             let mut new_nonce = [0u8; CCM_NONCE_LENGTH];
-            new_nonce.copy_from_slice(nonce);
+            new_nonce[..nonce.len()].copy_from_slice(nonce);
             self.nonce.set(new_nonce);
+            self.nonce_len.set(nonce.len());
             Ok(())
         }
     }
@@ -12,6 +12,10 @@
 //! must use a `FlashUser` instance to contain the per-user state for the
 //! virtualization.
 //!
+//! If the flash can suspend erases, an erase in progress is suspended while
+//! reads and writes of other pages are pending, so that they do not wait for
+//! it.
+//!
 //! Usage
 //! -----
 //!
@@ -44,6 +48,10 @@ pub struct MuxFlash<'a, F: hil::flash::Flash + 'static> {
     flash: &'a F,
     users: List<'a, FlashUser<'a, F>>,
     inflight: OptionalCell<&'a FlashUser<'a, F>>,
+    /// Page of the request in flight, if it is an erase.
+    erasing: OptionalCell<usize>,
+    /// User and page of an erase suspended for reads and writes.
+    suspended: OptionalCell<(&'a FlashUser<'a, F>, usize)>,
 }
 
 impl<F: hil::flash::Flash> hil::flash::Client<F> for MuxFlash<'_, F> {
@@ -62,6 +70,7 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for MuxFlash<'_, F> {
     }
 
     fn erase_complete(&self, error: hil::flash::Error) {
+        self.erasing.clear();
         self.inflight.take().map(move |user| {
             user.erase_complete(error);
         });
@@ -75,51 +84,103 @@ impl<'a, F: hil::flash::Flash> MuxFlash<'a, F> {
             flash: flash,
             users: List::new(),
             inflight: OptionalCell::empty(),
+            erasing: OptionalCell::empty(),
+            suspended: OptionalCell::empty(),
         }
     }
 
+    /// Find the first user with a pending read or write of a page other than
+    /// `erase_page`.
+    fn next_read_write(&self, erase_page: usize) -> Option<&'a FlashUser<'a, F>> {
+        self.users.iter().find(|node| match node.operation.get() {
+            Op::Read(page_number) | Op::Write(page_number) => page_number != erase_page,
+            _ => false,
+        })
+    }
+
     /// Scan the list of users and find the first user that has a pending
     /// request, then issue that request to the flash hardware.
+    ///
+    /// An erase in flight is suspended for a pending read or write, and
+    /// resumed once no more reads or writes are pending.
     fn do_next_op(&self) {
-        if self.inflight.is_none() {
-            let mnode = self
+        if self.inflight.is_some() {
+            match self.erasing.extract() {
+                Some(page_number) if self.next_read_write(page_number).is_some() => {
+                    if self.flash.suspend_erase().is_err() {
+                        return;
+                    }
+                    self.erasing.clear();
+                    self.inflight
+                        .take()
+                        .map(|user| self.suspended.set((user, page_number)));
+                }
+                _ => return,
+            }
+        }
+
+        let mnode = match self.suspended.extract() {
+            Some((_, erase_page)) => self.next_read_write(erase_page),
+            None => self
                 .users
                 .iter()
-                .find(|node| node.operation.get() != Op::Idle);
-            mnode.map(|node| {
-                node.buffer.take().map_or_else(
-                    || {
-                        // Don't need a buffer for erase.
-                        match node.operation.get() {
-                            Op::Erase(page_number) => {
-                                let _ = self.flash.erase_page(page_number);
-                            }
-                            _ => {}
-                        };
-                    },
-                    |buf| {
-                        match node.operation.get() {
-                            Op::Write(page_number) => {
-                                if let Err((_, buf)) = self.flash.write_page(page_number, buf) {
-                                    node.buffer.replace(buf);
-                                }
-                            }
-                            Op::Read(page_number) => {
-                                if let Err((_, buf)) = self.flash.read_page(page_number, buf) {
-                                    node.buffer.replace(buf);
-                                }
-                            }
-                            Op::Erase(page_number) => {
-                                let _ = self.flash.erase_page(page_number);
-                            }
-                            Op::Idle => {} // Can't get here...
+                .find(|node| node.operation.get() != Op::Idle),
+        };
+        match mnode {
+            Some(node) => self.start_op(node),
+            None => {
+                if let Some((user, page_number)) = self.suspended.take() {
+                    match self.flash.resume_erase() {
+                        Ok(()) => {
+                            self.erasing.set(page_number);
+                            self.inflight.set(user);
+                        }
+                        Err(_) => {
+                            hil::flash::Client::erase_complete(user, hil::flash::Error::FlashError);
+                            self.do_next_op();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Issue the pending request of `node` to the flash hardware.
+    fn start_op(&self, node: &'a FlashUser<'a, F>) {
+        node.buffer.take().map_or_else(
+            || {
+                // Don't need a buffer for erase.
+                match node.operation.get() {
+                    Op::Erase(page_number) => {
+                        let _ = self.flash.erase_page(page_number);
+                    }
+                    _ => {}
+                };
+            },
+            |buf| {
+                match node.operation.get() {
+                    Op::Write(page_number) => {
+                        if let Err((_, buf)) = self.flash.write_page(page_number, buf) {
+                            node.buffer.replace(buf);
+                        }
+                    }
+                    Op::Read(page_number) => {
+                        if let Err((_, buf)) = self.flash.read_page(page_number, buf) {
+                            node.buffer.replace(buf);
                         }
-                    },
-                );
-                node.operation.set(Op::Idle);
-                self.inflight.set(node);
-            });
+                    }
+                    Op::Erase(page_number) => {
+                        let _ = self.flash.erase_page(page_number);
+                    }
+                    Op::Idle => {} // Can't get here...
+                }
+            },
+        );
+        if let Op::Erase(page_number) = node.operation.get() {
+            self.erasing.set(page_number);
         }
+        node.operation.set(Op::Idle);
+        self.inflight.set(node);
     }
 }
 
@@ -9,6 +9,7 @@
 
 use core::cell::Cell;
 
+use crate::bus_errors::{Bus, BusErrors, ErrorCounters};
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::i2c::{self, Error, I2CClient, I2CHwMasterClient, NoSMBus};
@@ -23,10 +24,21 @@ pub struct MuxI2C<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBus>
     i2c_inflight: OptionalCell<&'a I2CDevice<'a, I, S>>,
     smbus_inflight: OptionalCell<&'a SMBusDevice<'a, I, S>>,
     deferred_call: DeferredCall,
+    errors: ErrorCounters<4>,
 }
 
+/// Names of the error counters of `MuxI2C`.
+const I2C_ERRORS: [&str; 4] = ["address_nak", "data_nak", "arbitration_lost", "overrun"];
+
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CHwMasterClient for MuxI2C<'a, I, S> {
     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        match status {
+            Err(Error::AddressNak) => self.errors.increment(0),
+            Err(Error::DataNak) => self.errors.increment(1),
+            Err(Error::ArbitrationLost) => self.errors.increment(2),
+            Err(Error::Overrun) => self.errors.increment(3),
+            _ => {}
+        }
         if self.i2c_inflight.is_some() {
             self.i2c_inflight.take().map(move |device| {
                 device.command_complete(buffer, status);
@@ -40,6 +52,24 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CHwMasterClient for M
     }
 }
 
+impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> BusErrors for MuxI2C<'a, I, S> {
+    fn bus(&self) -> Bus {
+        Bus::I2c
+    }
+
+    fn counter_names(&self) -> &'static [&'static str] {
+        &I2C_ERRORS
+    }
+
+    fn counter(&self, index: usize) -> u32 {
+        self.errors.get(index)
+    }
+
+    fn reset_counters(&self) {
+        self.errors.reset();
+    }
+}
+
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
     pub fn new(i2c: &'a I, smbus: Option<&'a S>) -> Self {
         Self {
@@ -51,6 +81,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
             i2c_inflight: OptionalCell::empty(),
             smbus_inflight: OptionalCell::empty(),
             deferred_call: DeferredCall::new(),
+            errors: ErrorCounters::new(),
         }
     }
 
@@ -4,6 +4,7 @@
 
 //! Virtualize a SPI master bus to enable multiple users of the SPI bus.
 
+use crate::bus_errors::{Bus, BusErrors, ErrorCounters};
 use core::cell::Cell;
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
@@ -19,8 +20,13 @@ pub struct MuxSpiMaster<'a, Spi: hil::spi::SpiMaster<'a>> {
     devices: List<'a, VirtualSpiMasterDevice<'a, Spi>>,
     inflight: OptionalCell<&'a VirtualSpiMasterDevice<'a, Spi>>,
     deferred_call: DeferredCall,
+    errors: ErrorCounters<2>,
 }
 
+/// Names of the error counters of `MuxSpiMaster`. Chips report overruns with
+/// `SIZE`.
+const SPI_ERRORS: [&str; 2] = ["overrun", "other"];
+
 impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient for MuxSpiMaster<'a, Spi> {
     fn read_write_done(
         &self,
@@ -29,6 +35,11 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient for MuxSpiMaste
         len: usize,
         status: Result<(), ErrorCode>,
     ) {
+        match status {
+            Err(ErrorCode::SIZE) => self.errors.increment(0),
+            Err(_) => self.errors.increment(1),
+            Ok(()) => {}
+        }
         let dev = self.inflight.take();
         // Need to do next op before signaling so we get some kind of
         // sharing. Otherwise a call to read_write in the callback
@@ -41,6 +52,24 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient for MuxSpiMaste
     }
 }
 
+impl<'a, Spi: hil::spi::SpiMaster<'a>> BusErrors for MuxSpiMaster<'a, Spi> {
+    fn bus(&self) -> Bus {
+        Bus::Spi
+    }
+
+    fn counter_names(&self) -> &'static [&'static str] {
+        &SPI_ERRORS
+    }
+
+    fn counter(&self, index: usize) -> u32 {
+        self.errors.get(index)
+    }
+
+    fn reset_counters(&self) {
+        self.errors.reset();
+    }
+}
+
 impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
     pub fn new(spi: &'a Spi) -> Self {
         Self {
@@ -48,6 +77,7 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
             devices: List::new(),
             inflight: OptionalCell::empty(),
             deferred_call: DeferredCall::new(),
+            errors: ErrorCounters::new(),
         }
     }
 
@@ -11,9 +11,28 @@
 //! Clients can choose if they want to receive. Incoming messages will be sent
 //! to all clients that have enabled receiving.
 //!
+//! Each `UartDevice` also implements `hil::uart::Configure`. Because all
+//! devices share the same bus, a new configuration applies to every client of
+//! the mux. Configuration is refused with `BUSY` while a transmission is in
+//! progress.
+//!
 //! `MuxUart` provides shared access to a single UART bus for multiple users.
 //! `UartDevice` provides access for a single client.
 //!
+//! Transmit buffering
+//! ------------------
+//!
+//! By default a transmission of a client waits until the transmissions of the
+//! other clients are on the wire, and then goes to the UART as one transfer.
+//! Boards can give the mux a transmit ring buffer and a transfer buffer with
+//! `set_transmit_buffers()`. Transmissions that fit into the ring are then
+//! copied into it and complete right away, so e.g. kernel debug output and
+//! the console no longer wait for each other, and bursts of output are
+//! absorbed by the ring rather than dropped by the clients. The ring is sent
+//! in chunks of the length of the transfer buffer, which UARTs with DMA
+//! transmit without involving the CPU. Transmissions that do not fit are sent
+//! after the ring emptied, as without the buffers.
+//!
 //! Usage
 //! -----
 //!
@@ -48,7 +67,11 @@
 use core::cell::Cell;
 use core::cmp;
 
+use crate::bus_errors::{Bus, BusErrors, ErrorCounters};
+
 use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::collections::queue::Queue;
+use kernel::collections::ring_buffer::RingBuffer;
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::uart;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -58,14 +81,24 @@ pub const RX_BUF_LEN: usize = 64;
 
 pub struct MuxUart<'a> {
     uart: &'a dyn uart::Uart<'a>,
-    speed: u32,
+    params: Cell<uart::Parameters>,
     devices: List<'a, UartDevice<'a>>,
     inflight: OptionalCell<&'a UartDevice<'a>>,
     buffer: TakeCell<'static, [u8]>,
     completing_read: Cell<bool>,
+    /// Transmissions of the devices that were not sent yet.
+    tx_ring: TakeCell<'static, RingBuffer<'static, u8>>,
+    /// Buffer the ring is sent from.
+    tx_dma: TakeCell<'static, [u8]>,
+    /// `tx_dma` is being transmitted.
+    ring_inflight: Cell<bool>,
     deferred_call: DeferredCall,
+    errors: ErrorCounters<4>,
 }
 
+/// Names of the error counters of `MuxUart`.
+const UART_ERRORS: [&str; 4] = ["parity", "framing", "overrun", "reset"];
+
 impl<'a> uart::TransmitClient for MuxUart<'a> {
     fn transmitted_buffer(
         &self,
@@ -73,14 +106,36 @@ impl<'a> uart::TransmitClient for MuxUart<'a> {
         tx_len: usize,
         rcode: Result<(), ErrorCode>,
     ) {
-        self.inflight.map(move |device| {
-            self.inflight.clear();
-            device.transmitted_buffer(tx_buffer, tx_len, rcode);
-        });
+        if self.ring_inflight.take() {
+            self.tx_dma.replace(tx_buffer);
+        } else {
+            self.inflight.map(move |device| {
+                self.inflight.clear();
+                device.transmitted_buffer(tx_buffer, tx_len, rcode);
+            });
+        }
         self.do_next_op();
     }
 }
 
+impl<'a> BusErrors for MuxUart<'a> {
+    fn bus(&self) -> Bus {
+        Bus::Uart
+    }
+
+    fn counter_names(&self) -> &'static [&'static str] {
+        &UART_ERRORS
+    }
+
+    fn counter(&self, index: usize) -> u32 {
+        self.errors.get(index)
+    }
+
+    fn reset_counters(&self) {
+        self.errors.reset();
+    }
+}
+
 impl<'a> uart::ReceiveClient for MuxUart<'a> {
     fn received_buffer(
         &self,
@@ -89,6 +144,14 @@ impl<'a> uart::ReceiveClient for MuxUart<'a> {
         rcode: Result<(), ErrorCode>,
         error: uart::Error,
     ) {
+        match error {
+            uart::Error::ParityError => self.errors.increment(0),
+            uart::Error::FramingError => self.errors.increment(1),
+            uart::Error::OverrunError => self.errors.increment(2),
+            uart::Error::ResetError => self.errors.increment(3),
+            _ => {}
+        }
+
         // Likely we will issue another receive in response to the previous one
         // finishing. `next_read_len` keeps track of the shortest outstanding
         // receive requested by any client. We start with the longest it can be,
@@ -193,27 +256,130 @@ impl<'a> MuxUart<'a> {
     pub fn new(uart: &'a dyn uart::Uart<'a>, buffer: &'static mut [u8], speed: u32) -> MuxUart<'a> {
         MuxUart {
             uart,
-            speed,
+            params: Cell::new(uart::Parameters {
+                baud_rate: speed,
+                width: uart::Width::Eight,
+                stop_bits: uart::StopBits::One,
+                parity: uart::Parity::None,
+                hw_flow_control: false,
+            }),
             devices: List::new(),
             inflight: OptionalCell::empty(),
             buffer: TakeCell::new(buffer),
             completing_read: Cell::new(false),
+            tx_ring: TakeCell::empty(),
+            tx_dma: TakeCell::empty(),
+            ring_inflight: Cell::new(false),
             deferred_call: DeferredCall::new(),
+            errors: ErrorCounters::new(),
         }
     }
 
     pub fn initialize(&self) {
-        let _ = self.uart.configure(uart::Parameters {
-            baud_rate: self.speed,
-            width: uart::Width::Eight,
-            stop_bits: uart::StopBits::One,
-            parity: uart::Parity::None,
-            hw_flow_control: false,
+        let _ = self.uart.configure(self.params.get());
+    }
+
+    /// Buffer transmissions of the devices in `ring`, and send them from
+    /// `dma_buffer`.
+    pub fn set_transmit_buffers(
+        &self,
+        ring: &'static mut RingBuffer<'static, u8>,
+        dma_buffer: &'static mut [u8],
+    ) {
+        self.tx_ring.replace(ring);
+        self.tx_dma.replace(dma_buffer);
+    }
+
+    /// Returns the configuration currently applied to the underlying UART.
+    pub fn parameters(&self) -> uart::Parameters {
+        self.params.get()
+    }
+
+    /// Reconfigure the underlying UART. This affects all devices sharing the
+    /// mux. Returns `BUSY` if a transmission is currently in progress.
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        if self.inflight.is_some() || self.ring_inflight.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.uart.configure(params)?;
+        self.params.set(params);
+        Ok(())
+    }
+
+    /// Copy the transmissions that fit into the transmit ring, and complete
+    /// them.
+    fn buffer_transmissions(&self) {
+        if self.tx_ring.is_none() {
+            return;
+        }
+        self.devices.iter().for_each(|node| {
+            if let Some(Operation::Transmit { len }) = node.operation.extract() {
+                let buffered = self.tx_ring.map_or(false, |ring| {
+                    node.tx_buffer.map_or(false, |buf| {
+                        let len = cmp::min(len, buf.len());
+                        if ring.available_len() < len {
+                            return false;
+                        }
+                        for byte in buf[..len].iter() {
+                            ring.enqueue(*byte);
+                        }
+                        true
+                    })
+                });
+                if buffered {
+                    node.operation.clear();
+                    node.tx_buffer.take().map(|buf| {
+                        let len = cmp::min(len, buf.len());
+                        uart::TransmitClient::transmitted_buffer(node, buf, len, Ok(()));
+                    });
+                }
+            }
+        });
+    }
+
+    /// Start sending the next chunk of the transmit ring. Returns whether a
+    /// transmission started.
+    fn transmit_ring(&self) -> bool {
+        let dma = match self.tx_dma.take() {
+            Some(dma) => dma,
+            None => return false,
+        };
+        let len = self.tx_ring.map_or(0, |ring| {
+            let mut len = 0;
+            while len < dma.len() {
+                match ring.dequeue() {
+                    Some(byte) => dma[len] = byte,
+                    None => break,
+                }
+                len += 1;
+            }
+            len
         });
+        if len == 0 {
+            self.tx_dma.replace(dma);
+            return false;
+        }
+        match self.uart.transmit_buffer(dma, len) {
+            Ok(()) => {
+                self.ring_inflight.set(true);
+                true
+            }
+            Err((_, dma)) => {
+                // The chunk is lost, like bytes a client would drop.
+                self.tx_dma.replace(dma);
+                false
+            }
+        }
     }
 
     fn do_next_op(&self) {
-        if self.inflight.is_none() {
+        self.buffer_transmissions();
+        if self.inflight.is_none() && !self.ring_inflight.get() {
+            // Buffered transmissions go first, they were requested before
+            // those that did not fit.
+            if self.transmit_ring() {
+                return;
+            }
             let mnode = self.devices.iter().find(|node| node.operation.is_some());
             mnode.map(|node| {
                 node.tx_buffer.take().map(|buf| {
@@ -473,3 +639,9 @@ impl<'a> uart::Receive<'a> for UartDevice<'a> {
         Err(ErrorCode::FAIL)
     }
 }
+
+impl<'a> uart::Configure for UartDevice<'a> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.mux.configure(params)
+    }
+}
@@ -0,0 +1,403 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Streams continuous ADC capture over UDP, e.g. over 6LoWPAN and 802.15.4.
+//!
+//! The ADC samples one channel at a fixed frequency into a pool of
+//! `NUM_BUFFERS` sample buffers. Each full buffer gets the next sequence
+//! number and is queued, and queued buffers are sent to one destination as
+//! UDP datagrams, one at a time.
+//!
+//! The radio is usually slower than the ADC, so the kernel applies
+//! backpressure when the queue is full: the ADC needs a buffer back for each
+//! one it fills, and the `DropPolicy` decides whether the buffer that just
+//! filled or the oldest queued one is discarded. Sequence numbers are given
+//! at capture, so a receiver sees the gaps of the dropped buffers. `stats()`
+//! counts the captured, sent and dropped buffers and the failed sends.
+//!
+//! Datagram format
+//! ---------------
+//!
+//! ```text
+//! 0       4       6    7     8
+//! +-------+-------+----+-----+-------------------------------+
+//! | seq   | count | res| 0   | count samples, u16 big-endian |
+//! +-------+-------+----+-----+-------------------------------+
+//! ```
+//!
+//! `res` is the resolution of the ADC in bits. Samples are the raw,
+//! left-justified values of the ADC.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let adc_stream = components::adc_stream::AdcStreamComponent::new(
+//!     udp_send_mux,
+//!     udp_port_table,
+//!     &base_peripherals.adc,
+//!     &sam4l::adc::CHANNEL_AD1,
+//!     1000,
+//!     COLLECTOR_ADDR,
+//!     capsules_extra::adc_stream::ADC_STREAM_PORT,
+//!     capsules_extra::adc_stream::ADC_STREAM_PORT,
+//!     capsules_extra::adc_stream::DropPolicy::DropOldest,
+//! )
+//! .finalize(components::adc_stream_component_static!(
+//!     sam4l::ast::Ast,
+//!     sam4l::adc::Adc,
+//! ));
+//! adc_stream.start().unwrap();
+//! ```
+
+use core::cell::Cell;
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+use kernel::hil::adc;
+use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+/// The UDP port datagrams are sent from and to.
+pub const ADC_STREAM_PORT: u16 = 4790;
+/// Sample buffers: two with the ADC, the others queued or free.
+pub const NUM_BUFFERS: usize = 6;
+/// Length of the header of a datagram.
+pub const HEADER_LEN: usize = 8;
+
+/// What is discarded when the ADC fills a buffer and none is free.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Discard the buffer that just filled, keeping the queue in order.
+    DropNewest,
+    /// Discard the oldest queued buffer, keeping the latest samples.
+    DropOldest,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// Buffers filled by the ADC
+    pub captured: u32,
+    /// Datagrams sent
+    pub sent: u32,
+    /// Buffers discarded by the drop policy
+    pub dropped: u32,
+    /// Datagrams the UDP stack failed to send
+    pub send_errors: u32,
+}
+
+/// Encode the datagram of a buffer with `seq` into `buf`, and return its
+/// length. Samples that do not fit are left out.
+pub fn encode_datagram(buf: &mut [u8], seq: u32, resolution: u8, samples: &[u16]) -> usize {
+    let count = usize::min(samples.len(), buf.len().saturating_sub(HEADER_LEN) / 2);
+    if buf.len() < HEADER_LEN {
+        return 0;
+    }
+    buf[0..4].copy_from_slice(&seq.to_be_bytes());
+    buf[4..6].copy_from_slice(&(count as u16).to_be_bytes());
+    buf[6] = resolution;
+    buf[7] = 0;
+    for (dst, sample) in buf[HEADER_LEN..].chunks_exact_mut(2).zip(&samples[..count]) {
+        dst.copy_from_slice(&sample.to_be_bytes());
+    }
+    HEADER_LEN + 2 * count
+}
+
+/// A sample buffer of the pool. It is with the ADC while `samples` is
+/// empty, and queued while `queued` is set.
+struct Slot {
+    samples: TakeCell<'static, [u16]>,
+    queued: Cell<bool>,
+    seq: Cell<u32>,
+    len: Cell<usize>,
+}
+
+impl Slot {
+    fn new() -> Slot {
+        Slot {
+            samples: TakeCell::empty(),
+            queued: Cell::new(false),
+            seq: Cell::new(0),
+            len: Cell::new(0),
+        }
+    }
+}
+
+pub struct AdcStream<'a, A: adc::AdcHighSpeed<'a>> {
+    adc: &'a A,
+    channel: &'a A::Channel,
+    frequency: u32,
+    sender: &'a dyn UDPSender<'a>,
+    net_cap: &'static NetworkCapability,
+    dest: IPAddr,
+    dst_port: u16,
+    policy: Cell<DropPolicy>,
+    slots: [Slot; NUM_BUFFERS],
+    /// Samples per buffer
+    buffer_len: usize,
+    tx_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    running: Cell<bool>,
+    next_seq: Cell<u32>,
+    stats: Cell<StreamStats>,
+}
+
+impl<'a, A: adc::AdcHighSpeed<'a>> AdcStream<'a, A> {
+    /// `samples` is split into the `NUM_BUFFERS` sample buffers, of at most
+    /// as many samples as fit in a datagram of `tx_buffer`.
+    pub fn new(
+        adc: &'a A,
+        channel: &'a A::Channel,
+        frequency: u32,
+        sender: &'a dyn UDPSender<'a>,
+        net_cap: &'static NetworkCapability,
+        dest: IPAddr,
+        dst_port: u16,
+        policy: DropPolicy,
+        samples: &'static mut [u16],
+        tx_buffer: &'static mut [u8],
+    ) -> AdcStream<'a, A> {
+        let buffer_len = usize::min(
+            samples.len() / NUM_BUFFERS,
+            tx_buffer.len().saturating_sub(HEADER_LEN) / 2,
+        );
+        let slots = [(); NUM_BUFFERS].map(|_| Slot::new());
+        if buffer_len > 0 {
+            for (slot, buffer) in slots.iter().zip(samples.chunks_mut(buffer_len)) {
+                slot.samples.replace(buffer);
+            }
+        }
+        AdcStream {
+            adc,
+            channel,
+            frequency,
+            sender,
+            net_cap,
+            dest,
+            dst_port,
+            policy: Cell::new(policy),
+            slots,
+            buffer_len,
+            tx_buffer: MapCell::new(LeasableMutableBuffer::new(tx_buffer)),
+            running: Cell::new(false),
+            next_seq: Cell::new(0),
+            stats: Cell::new(StreamStats::default()),
+        }
+    }
+
+    pub fn set_policy(&self, policy: DropPolicy) {
+        self.policy.set(policy);
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.stats.get()
+    }
+
+    pub fn reset_stats(&self) {
+        self.stats.set(StreamStats::default());
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.get()
+    }
+
+    /// Start sampling and streaming.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The ADC is sampling.
+    /// - `ALREADY`: The stream is running.
+    /// - `NOMEM`: There are not enough free buffers.
+    /// - Errors of `AdcHighSpeed::sample_highspeed()`.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let (first, second) = match (self.take_free(), self.take_free()) {
+            (Some(first), Some(second)) => (first, second),
+            (first, second) => {
+                [first, second]
+                    .into_iter()
+                    .flatten()
+                    .for_each(|buffer| self.put_free(buffer));
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+        let len = self.buffer_len;
+        match self
+            .adc
+            .sample_highspeed(self.channel, self.frequency, first, len, second, len)
+        {
+            Ok(()) => {
+                self.running.set(true);
+                Ok(())
+            }
+            Err((e, first, second)) => {
+                self.put_free(first);
+                self.put_free(second);
+                Err(e)
+            }
+        }
+    }
+
+    /// Stop sampling. The queued buffers are still sent.
+    pub fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.running.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.adc.stop_sampling()?;
+        self.running.set(false);
+        if let Ok((first, second)) = self.adc.retrieve_buffers() {
+            [first, second]
+                .into_iter()
+                .flatten()
+                .for_each(|buffer| self.put_free(buffer));
+        }
+        Ok(())
+    }
+
+    fn update_stats<F: FnOnce(&mut StreamStats)>(&self, f: F) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
+    }
+
+    fn take_free(&self) -> Option<&'static mut [u16]> {
+        self.slots
+            .iter()
+            .find(|slot| !slot.queued.get() && slot.samples.is_some())
+            .and_then(|slot| slot.samples.take())
+    }
+
+    /// The oldest queued slot.
+    fn oldest(&self) -> Option<&Slot> {
+        let next = self.next_seq.get();
+        self.slots
+            .iter()
+            .filter(|slot| slot.queued.get())
+            .max_by_key(|slot| next.wrapping_sub(slot.seq.get()))
+    }
+
+    fn put_free(&self, buffer: &'static mut [u16]) {
+        if let Some(slot) = self.slots.iter().find(|slot| slot.samples.is_none()) {
+            slot.queued.set(false);
+            slot.samples.replace(buffer);
+        }
+    }
+
+    fn enqueue(&self, buffer: &'static mut [u16], seq: u32, len: usize) {
+        if let Some(slot) = self.slots.iter().find(|slot| slot.samples.is_none()) {
+            slot.seq.set(seq);
+            slot.len.set(len);
+            slot.queued.set(true);
+            slot.samples.replace(buffer);
+        }
+    }
+
+    /// Send the oldest queued buffer, if the UDP sender is free.
+    fn send_next(&self) {
+        let slot = match self.oldest() {
+            Some(slot) => slot,
+            None => return,
+        };
+        if let Some(mut tx) = self.tx_buffer.take() {
+            tx.reset();
+            let resolution = self.adc.get_resolution_bits() as u8;
+            let len = slot.samples.map_or(0, |samples| {
+                encode_datagram(
+                    &mut tx[..],
+                    slot.seq.get(),
+                    resolution,
+                    &samples[..slot.len.get()],
+                )
+            });
+            // The samples are copied, the buffer is free again
+            slot.queued.set(false);
+            tx.slice(..len);
+            if let Err(mut tx) = self
+                .sender
+                .send_to(self.dest, self.dst_port, tx, self.net_cap)
+            {
+                self.update_stats(|stats| stats.send_errors += 1);
+                tx.reset();
+                self.tx_buffer.replace(tx);
+            }
+        }
+    }
+}
+
+impl<'a, A: adc::AdcHighSpeed<'a>> adc::HighSpeedClient for AdcStream<'a, A> {
+    fn samples_ready(&self, buf: &'static mut [u16], length: usize) {
+        if !self.running.get() {
+            self.put_free(buf);
+            return;
+        }
+        let seq = self.next_seq.get();
+        self.next_seq.set(seq.wrapping_add(1));
+        self.update_stats(|stats| stats.captured += 1);
+
+        // The ADC gets a free buffer back, or one chosen by the drop policy
+        let replacement = self.take_free().or_else(|| match self.policy.get() {
+            DropPolicy::DropOldest => self.oldest().and_then(|slot| {
+                self.update_stats(|stats| stats.dropped += 1);
+                slot.queued.set(false);
+                slot.samples.take()
+            }),
+            DropPolicy::DropNewest => None,
+        });
+        let replacement = match replacement {
+            Some(replacement) => {
+                self.enqueue(buf, seq, usize::min(length, self.buffer_len));
+                replacement
+            }
+            None => {
+                self.update_stats(|stats| stats.dropped += 1);
+                buf
+            }
+        };
+        if let Err((_, buffer)) = self.adc.provide_buffer(replacement, self.buffer_len) {
+            self.put_free(buffer);
+        }
+        self.send_next();
+    }
+}
+
+impl<'a, A: adc::AdcHighSpeed<'a>> UDPSendClient for AdcStream<'a, A> {
+    fn send_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        mut dgram: LeasableMutableBuffer<'static, u8>,
+    ) {
+        match result {
+            Ok(()) => self.update_stats(|stats| stats.sent += 1),
+            Err(_) => self.update_stats(|stats| stats.send_errors += 1),
+        }
+        dgram.reset();
+        self.tx_buffer.replace(dgram);
+        self.send_next();
+    }
+}
+
+impl<'a, A: adc::AdcHighSpeed<'a>> adc::Client for AdcStream<'a, A> {
+    fn sample_ready(&self, _sample: u16) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datagram() {
+        let mut buf = [0xff; HEADER_LEN + 6];
+        let len = encode_datagram(&mut buf, 0x01020304, 12, &[0x1230, 0xabc0, 0x0010, 0x2000]);
+        assert_eq!(len, HEADER_LEN + 6);
+        assert_eq!(
+            buf,
+            [1, 2, 3, 4, 0, 3, 12, 0, 0x12, 0x30, 0xab, 0xc0, 0x00, 0x10]
+        );
+        assert_eq!(encode_datagram(&mut buf[..4], 0, 12, &[1]), 0);
+    }
+}
@@ -23,6 +23,7 @@
 //! kernel::hil::sensors::AirQualityDriver::set_client(si7021, temp);
 //! ```
 
+use capsules_core::driver_info::{self, DriverInfo};
 use core::cell::Cell;
 use core::convert::TryFrom;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
@@ -146,6 +147,9 @@ impl SyscallDriver for AirQualitySensor<'_> {
             // check whether the driver exists!!
             0 => CommandReturn::success(),
 
+            // describe the driver, see `capsules_core::driver_info`
+            driver_info::COMMAND_NUM => DriverInfo::new(1).into(),
+
             // specify temperature and humidity (TODO)
             1 => CommandReturn::failure(ErrorCode::NOSUPPORT),
 
@@ -0,0 +1,203 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Debug capsule that audits how long processes keep buffers allowed.
+//!
+//! The auditor registers with the kernel as a `ProcessObserver` and records
+//! every buffer a process shares with a syscall driver. It prints a warning on
+//! the debug console when:
+//!
+//! - a buffer stays allowed for longer than a configurable time, or
+//! - a process terminates (faults, restarts, or is stopped) while buffers are
+//!   still allowed to drivers.
+//!
+//! Both usually point to a capsule that never completes an operation and so
+//! never gives the process a reason to revoke its buffer. Some buffers are
+//! legitimately long lived (e.g. a console read buffer waiting for input), so
+//! this is a debugging aid and not an error check.
+//!
+//! Only a fixed number of buffers are tracked. If more buffers are outstanding
+//! a single warning is printed and the excess buffers are not audited.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let allow_audit = components::allow_audit::AllowAuditComponent::new(
+//!     board_kernel,
+//!     mux_alarm,
+//!     10_000, // Warn about buffers held for more than 10 seconds.
+//! )
+//! .finalize(components::allow_audit_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::collections::list::ListLink;
+use kernel::debug;
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
+use kernel::process_observer::{AllowType, ProcessObserver};
+use kernel::ProcessId;
+
+/// Default number of allowed buffers the auditor tracks.
+pub const DEFAULT_MAX_OUTSTANDING: usize = 16;
+
+#[derive(Copy, Clone)]
+struct Outstanding<T: Ticks> {
+    processid: ProcessId,
+    driver_num: usize,
+    allow_num: usize,
+    allow_type: AllowType,
+    address: usize,
+    size: usize,
+    since: T,
+    reported: bool,
+}
+
+impl<T: Ticks> Outstanding<T> {
+    fn is_slot(
+        &self,
+        processid: ProcessId,
+        driver_num: usize,
+        allow_num: usize,
+        allow_type: AllowType,
+    ) -> bool {
+        self.processid == processid
+            && self.driver_num == driver_num
+            && self.allow_num == allow_num
+            && self.allow_type == allow_type
+    }
+}
+
+pub struct AllowAuditor<'a, A: Alarm<'a>, const MAX_OUTSTANDING: usize> {
+    alarm: &'a A,
+    max_age_ms: u32,
+    entries: [Cell<Option<Outstanding<A::Ticks>>>; MAX_OUTSTANDING],
+    overflowed: Cell<bool>,
+    next_observer: ListLink<'static, dyn ProcessObserver>,
+}
+
+impl<'a, A: Alarm<'a>, const MAX_OUTSTANDING: usize> AllowAuditor<'a, A, MAX_OUTSTANDING> {
+    /// Create an auditor that warns about buffers allowed for more than
+    /// `max_age_ms` milliseconds.
+    pub fn new(alarm: &'a A, max_age_ms: u32) -> Self {
+        AllowAuditor {
+            alarm,
+            max_age_ms,
+            entries: core::array::from_fn(|_| Cell::new(None)),
+            overflowed: Cell::new(false),
+            next_observer: ListLink::empty(),
+        }
+    }
+
+    /// Start periodically checking the age of outstanding buffers.
+    pub fn start(&self) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.max_age_ms));
+    }
+}
+
+impl<'a, A: Alarm<'a>, const MAX_OUTSTANDING: usize> ProcessObserver
+    for AllowAuditor<'a, A, MAX_OUTSTANDING>
+{
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        &self.next_observer
+    }
+
+    fn allowed(
+        &self,
+        processid: ProcessId,
+        driver_num: usize,
+        allow_num: usize,
+        allow_type: AllowType,
+        address: *const u8,
+        size: usize,
+    ) {
+        let existing = self.entries.iter().find(|entry| {
+            entry.get().map_or(false, |outstanding| {
+                outstanding.is_slot(processid, driver_num, allow_num, allow_type)
+            })
+        });
+
+        if size == 0 {
+            // The buffer was revoked, stop tracking this slot.
+            existing.map(|entry| entry.set(None));
+            return;
+        }
+
+        let entry = existing.or_else(|| self.entries.iter().find(|entry| entry.get().is_none()));
+        match entry {
+            Some(entry) => entry.set(Some(Outstanding {
+                processid,
+                driver_num,
+                allow_num,
+                allow_type,
+                address: address as usize,
+                size,
+                since: self.alarm.now(),
+                reported: false,
+            })),
+            None => {
+                if !self.overflowed.get() {
+                    self.overflowed.set(true);
+                    debug!(
+                        "allow audit: more than {} buffers allowed, not all are tracked",
+                        MAX_OUTSTANDING
+                    );
+                }
+            }
+        }
+    }
+
+    fn process_terminated(&self, processid: ProcessId) {
+        for entry in self.entries.iter() {
+            if let Some(outstanding) = entry.get() {
+                if outstanding.processid == processid {
+                    debug!(
+                        "[{:?}] allow audit: terminated while {:?} allow {} of driver {:#x} held {} bytes at {:#x}",
+                        processid,
+                        outstanding.allow_type,
+                        outstanding.allow_num,
+                        outstanding.driver_num,
+                        outstanding.size,
+                        outstanding.address,
+                    );
+                    entry.set(None);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, const MAX_OUTSTANDING: usize> time::AlarmClient
+    for AllowAuditor<'a, A, MAX_OUTSTANDING>
+{
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let max_age = self.alarm.ticks_from_ms(self.max_age_ms);
+
+        for entry in self.entries.iter() {
+            if let Some(mut outstanding) = entry.get() {
+                if !outstanding.reported && now.wrapping_sub(outstanding.since) > max_age {
+                    debug!(
+                        "[{:?}] allow audit: {:?} allow {} of driver {:#x} held {} bytes at {:#x} for over {} ms",
+                        outstanding.processid,
+                        outstanding.allow_type,
+                        outstanding.allow_num,
+                        outstanding.driver_num,
+                        outstanding.size,
+                        outstanding.address,
+                        self.max_age_ms,
+                    );
+                    outstanding.reported = true;
+                    entry.set(Some(outstanding));
+                }
+            }
+        }
+
+        self.alarm.set_alarm(now, max_age);
+    }
+}
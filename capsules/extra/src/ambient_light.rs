@@ -18,6 +18,7 @@
 
 use core::cell::Cell;
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -88,6 +89,8 @@ impl SyscallDriver for AmbientLight<'_> {
     ///
     /// - `0`: Check driver presence
     /// - `1`: Start a light sensor reading
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`
     fn command(
         &self,
         command_num: usize,
@@ -97,6 +100,7 @@ impl SyscallDriver for AmbientLight<'_> {
     ) -> CommandReturn {
         match command_num {
             0 /* check if present */ => CommandReturn::success(),
+            driver_info::COMMAND_NUM /* describe the driver */ => DriverInfo::new(1).into(),
             1 => {
                 let _ = self.enqueue_sensor_reading(processid);
                 CommandReturn::success()
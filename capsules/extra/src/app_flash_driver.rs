@@ -28,6 +28,7 @@
 
 use core::cmp;
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::processbuffer::ReadableProcessBuffer;
@@ -212,6 +213,8 @@ impl SyscallDriver for AppFlash<'_> {
     ///
     /// - `0`: Driver check.
     /// - `1`: Write the memory from the `allow` buffer to the address in flash.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`.
     fn command(
         &self,
         command_num: usize,
@@ -224,6 +227,8 @@ impl SyscallDriver for AppFlash<'_> {
                 CommandReturn::success()
             }
 
+            driver_info::COMMAND_NUM /* Describe the driver */ => DriverInfo::new(1).into(),
+
             1 /* Write to flash from the allowed buffer */ => {
                 let flash_address = arg1;
 
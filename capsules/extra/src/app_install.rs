@@ -0,0 +1,288 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Installs applications at runtime with the dynamic process loader.
+//!
+//! [`AppInstall`] receives a TBF object through the
+//! [`AppUpdater`](crate::app_update::AppUpdater) trait, so by any update
+//! transport, e.g. the USB DFU class. It appends the object to the flash
+//! region of a `kernel::process::DynamicProcessLoader`, after the processes
+//! already in it, and then loads it. The new process goes through the
+//! board's credential checks and runs without a reset.
+//!
+//! Unlike `app_update::AppUpdate`, which replaces all applications and
+//! boots them after a reset, this only adds applications. Space in the
+//! region is not reclaimed, the region is erased by flashing the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let app_install = components::app_install::AppInstallComponent::new(
+//!     &base_peripherals.nvmc,
+//!     capsules_extra::app_update::FlashRegion::new(160, spare_flash),
+//!     loader,
+//! )
+//! .finalize(components::app_install_component_static!(nrf52840::nvmc::Nvmc));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::flash::{self, Flash};
+use kernel::process::{DynamicProcessLoading, ProcessLoadError};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::app_update::{AppUpdater, AppUpdaterClient, FlashRegion};
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Waiting for more of the object.
+    Receiving,
+    ErasingPage,
+    WritingPage,
+    /// Waiting for the deferred call to load the object.
+    Loading,
+}
+
+pub struct AppInstall<'a, F: Flash + 'static> {
+    flash: &'a F,
+    buffer: TakeCell<'static, F::Page>,
+    page_size: usize,
+    /// Flash region of the loader.
+    region: FlashRegion,
+    loader: &'a dyn DynamicProcessLoading,
+    client: OptionalCell<&'a dyn AppUpdaterClient>,
+    state: Cell<State>,
+    /// Offset in the region the object is written to.
+    offset: Cell<usize>,
+    /// Length of the object being received.
+    length: Cell<usize>,
+    /// Number of bytes of the object passed in so far.
+    received: Cell<usize>,
+    /// Index in the region of the page in the buffer.
+    page: Cell<usize>,
+    /// Number of bytes in the buffer, including the processes before the
+    /// object in its first page.
+    fill: Cell<usize>,
+    /// Whether the page being written is the last one of the object.
+    last_page: Cell<bool>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, F: Flash + 'static> AppInstall<'a, F> {
+    /// `region` must be the flash region of `loader`, starting at a page
+    /// boundary.
+    pub fn new(
+        flash: &'a F,
+        buffer: &'static mut F::Page,
+        region: FlashRegion,
+        loader: &'a dyn DynamicProcessLoading,
+    ) -> Self {
+        let page_size = buffer.as_mut().len();
+        AppInstall {
+            flash,
+            buffer: TakeCell::new(buffer),
+            page_size,
+            region,
+            loader,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            offset: Cell::new(0),
+            length: Cell::new(0),
+            received: Cell::new(0),
+            page: Cell::new(0),
+            fill: Cell::new(0),
+            last_page: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Writes the buffered page to flash.
+    fn write_buffer(&self) -> Result<(), ErrorCode> {
+        self.flash
+            .erase_page(self.region.start_page() + self.page.get())?;
+        self.state.set(State::ErasingPage);
+        Ok(())
+    }
+
+    fn update_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.update_done(result));
+    }
+
+    /// Loads the written object, which must be a TBF object of the announced
+    /// length.
+    fn load(&self) {
+        let offset = self.offset.get();
+        let valid = self
+            .region
+            .memory()
+            .get(offset..offset + 8)
+            .and_then(|header| header.try_into().ok())
+            .and_then(|header| tock_tbf::parse::parse_tbf_header_lengths(header).ok())
+            .map_or(false, |(_, _, total_size)| {
+                total_size as usize == self.length.get()
+            });
+        if !valid {
+            return self.update_done(Err(ErrorCode::INVAL));
+        }
+
+        self.update_done(match self.loader.load_new_processes() {
+            Ok(0) => Err(ErrorCode::INVAL),
+            Ok(_) => Ok(()),
+            Err(ProcessLoadError::NotEnoughMemory) | Err(ProcessLoadError::NoProcessSlot) => {
+                Err(ErrorCode::NOMEM)
+            }
+            Err(_) => Err(ErrorCode::FAIL),
+        });
+    }
+}
+
+impl<'a, F: Flash + 'static> AppUpdater<'a> for AppInstall<'a, F> {
+    fn set_client(&self, client: &'a dyn AppUpdaterClient) {
+        self.client.set(client);
+    }
+
+    fn start(&self, length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let memory = self.region.memory();
+        let offset = memory.len() - self.loader.remaining_flash();
+        if length < 8 || length > memory.len() - offset {
+            return Err(ErrorCode::SIZE);
+        }
+
+        // The first page may hold the end of the previous process, keep it.
+        let page = offset / self.page_size;
+        let fill = offset % self.page_size;
+        let page_start = page * self.page_size;
+        self.buffer.map(|buffer| {
+            buffer.as_mut()[..fill].copy_from_slice(&memory[page_start..offset]);
+        });
+
+        self.offset.set(offset);
+        self.length.set(length);
+        self.received.set(0);
+        self.page.set(page);
+        self.fill.set(fill);
+        self.state.set(State::Receiving);
+        Ok(())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, ErrorCode> {
+        match self.state.get() {
+            State::Receiving => {}
+            State::Idle => return Err(ErrorCode::OFF),
+            _ => return Err(ErrorCode::BUSY),
+        }
+        let fill = self.fill.get();
+        let count = data
+            .len()
+            .min(self.page_size - fill)
+            .min(self.length.get() - self.received.get());
+        self.buffer.map(|buffer| {
+            buffer.as_mut()[fill..fill + count].copy_from_slice(&data[..count]);
+        });
+        self.fill.set(fill + count);
+        self.received.set(self.received.get() + count);
+
+        if self.fill.get() == self.page_size {
+            self.last_page.set(false);
+            self.write_buffer()?;
+        }
+        Ok(count)
+    }
+
+    fn finish(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Receiving {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.received.get() != self.length.get() {
+            return Err(ErrorCode::SIZE);
+        }
+        if self.fill.get() == 0 {
+            self.state.set(State::Loading);
+            self.deferred_call.set();
+            return Ok(());
+        }
+        // Leave the rest of the page erased, which ends the scan of the
+        // loader.
+        let fill = self.fill.get();
+        self.buffer.map(|buffer| buffer.as_mut()[fill..].fill(0xFF));
+        self.last_page.set(true);
+        self.write_buffer()
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Receiving => {
+                self.state.set(State::Idle);
+                Ok(())
+            }
+            State::Idle => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static> flash::Client<F> for AppInstall<'a, F> {
+    fn read_complete(&self, buffer: &'static mut F::Page, _error: flash::Error) {
+        self.buffer.replace(buffer);
+    }
+
+    fn write_complete(&self, buffer: &'static mut F::Page, error: flash::Error) {
+        self.buffer.replace(buffer);
+        if self.state.get() != State::WritingPage {
+            return;
+        }
+        if error != flash::Error::CommandComplete {
+            return self.update_done(Err(ErrorCode::FAIL));
+        }
+        self.page.set(self.page.get() + 1);
+        self.fill.set(0);
+        if self.last_page.get() {
+            self.load();
+        } else {
+            self.state.set(State::Receiving);
+            self.client.map(|client| client.write_done(Ok(())));
+        }
+    }
+
+    fn erase_complete(&self, error: flash::Error) {
+        if self.state.get() != State::ErasingPage {
+            return;
+        }
+        let result = if error == flash::Error::CommandComplete {
+            let page = self.region.start_page() + self.page.get();
+            self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+                self.flash.write_page(page, buffer).map_err(|(e, buffer)| {
+                    self.buffer.replace(buffer);
+                    e
+                })
+            })
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+        match result {
+            Ok(()) => self.state.set(State::WritingPage),
+            Err(_) => self.update_done(Err(ErrorCode::FAIL)),
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static> DeferredCallClient for AppInstall<'a, F> {
+    fn handle_deferred_call(&self) {
+        if self.state.get() == State::Loading {
+            self.load();
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,522 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Persistent preferences of processes, stored in the KV store.
+//!
+//! A preference is a small value, either a 32-bit integer or up to
+//! [`MAX_VALUE_LEN`] bytes, that a process stores under a numeric ID of its
+//! choosing. Preferences are kept in the KV store under a key made from the
+//! ShortID of the process and the preference ID, so every application has
+//! its own namespace, and it keeps its preferences when it is updated or the
+//! board restarts. Only processes with a fixed ShortID can use preferences.
+//!
+//! Preferences are read and written with the storage permissions of the
+//! process, the same as with the KV syscall driver, so a process needs a
+//! write ID to store them.
+//!
+//! The key is `pref/`, followed by the ShortID and the preference ID as
+//! 32-bit little endian integers, zero padded to `KEY_LEN` bytes. The value
+//! is a type byte, the length of the data and the data.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let app_preferences = components::app_preferences::AppPreferencesComponent::new(
+//!     board_kernel,
+//!     capsules_extra::app_preferences::DRIVER_NUM,
+//!     kv_store,
+//! )
+//! .finalize(components::app_preferences_component_static!(
+//!     capsules_extra::tickv::TicKVStore<...>,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! Commands that read or write a preference return success when they were
+//! queued, and schedule upcall 0 with the status and a value when they are
+//! done. Bytes to store are passed in read-only allow 0, and bytes are read
+//! into read-write allow 0.
+
+use core::cell::Cell;
+
+use crate::kv_store::KVStore;
+use crate::provisioning::KEY_LEN;
+use capsules_core::driver_info::{self, DriverInfo};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::kv_system::{self, KVSystem};
+use kernel::process::ShortID;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppPreferences as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const VALUE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const VALUE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcalls {
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Prefix of the keys of preferences.
+pub const KEY_PREFIX: &[u8] = b"pref/";
+/// Largest number of bytes of a preference.
+pub const MAX_VALUE_LEN: usize = 32;
+/// Length of a stored preference: the type, the length and the data.
+pub const VALUE_LEN: usize = 2 + MAX_VALUE_LEN;
+
+const TYPE_INTEGER: u8 = 1;
+const TYPE_BYTES: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    GetInteger,
+    SetInteger(u32),
+    GetBytes,
+    SetBytes,
+    Delete,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Getting,
+    /// Removing the old value before writing the new one.
+    Replacing,
+    Setting,
+    Deleting,
+}
+
+#[derive(Default)]
+pub struct App {
+    /// The queued command and its preference ID.
+    pending: Option<(Command, u32)>,
+}
+
+pub struct AppPreferences<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> {
+    kv: &'a KVStore<'a, K, T>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The process whose command runs, and the command.
+    current: OptionalCell<(ProcessId, Command)>,
+    state: Cell<State>,
+    perms: OptionalCell<StoragePermissions>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    value_len: Cell<usize>,
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> AppPreferences<'a, K, T> {
+    /// `value_buffer` must have room for `VALUE_LEN` bytes and the header of
+    /// the KV store.
+    pub fn new(
+        kv: &'a KVStore<'a, K, T>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        key_buffer: &'static mut [u8; KEY_LEN],
+        value_buffer: &'static mut [u8],
+    ) -> AppPreferences<'a, K, T> {
+        AppPreferences {
+            kv,
+            apps: grant,
+            current: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            perms: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            value_len: Cell::new(0),
+        }
+    }
+
+    fn start(
+        &self,
+        processid: ProcessId,
+        command: Command,
+        pref: u32,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        let short_id = match processid.short_app_id() {
+            ShortID::Fixed(id) => id.get(),
+            ShortID::LocallyUnique => return Err(ErrorCode::NOSUPPORT),
+        };
+        let perms = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::INVAL)?;
+
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        set_key(key, short_id, pref);
+
+        let result = match command {
+            Command::GetInteger | Command::GetBytes => self.get(key, perms),
+            Command::SetInteger(value) => {
+                self.value_buffer.map(|buffer| {
+                    let len = encode(buffer, TYPE_INTEGER, &value.to_le_bytes());
+                    self.value_len.set(len);
+                });
+                self.delete(key, perms, State::Replacing)
+            }
+            Command::SetBytes => {
+                self.value_buffer.map(|buffer| {
+                    let len = kernel_data
+                        .get_readonly_processbuffer(ro_allow::VALUE)
+                        .and_then(|src| {
+                            src.enter(|src| {
+                                let mut data = [0; MAX_VALUE_LEN];
+                                let len = src.len().min(MAX_VALUE_LEN);
+                                src[..len].copy_to_slice(&mut data[..len]);
+                                encode(buffer, TYPE_BYTES, &data[..len])
+                            })
+                        })
+                        .unwrap_or_else(|_| encode(buffer, TYPE_BYTES, &[]));
+                    self.value_len.set(len);
+                });
+                self.delete(key, perms, State::Replacing)
+            }
+            Command::Delete => self.delete(key, perms, State::Deleting),
+        };
+        if result.is_ok() {
+            self.perms.set(perms);
+            self.current.set((processid, command));
+        }
+        result
+    }
+
+    fn get(&self, key: &'static mut [u8], perms: StoragePermissions) -> Result<(), ErrorCode> {
+        let value = match self.value_buffer.take() {
+            Some(value) => value,
+            None => {
+                self.key_buffer.replace(key);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+        self.state.set(State::Getting);
+        self.kv.get(key, value, perms).map_err(|(key, value, e)| {
+            self.key_buffer.replace(key);
+            self.value_buffer.replace(value);
+            self.state.set(State::Idle);
+            e.err().unwrap_or(ErrorCode::FAIL)
+        })
+    }
+
+    fn delete(
+        &self,
+        key: &'static mut [u8],
+        perms: StoragePermissions,
+        state: State,
+    ) -> Result<(), ErrorCode> {
+        self.state.set(state);
+        self.kv.delete(key, perms).map_err(|(key, e)| {
+            self.key_buffer.replace(key);
+            self.state.set(State::Idle);
+            e.err().unwrap_or(ErrorCode::FAIL)
+        })
+    }
+
+    /// Write the new value once the old one was removed.
+    fn set(&self) {
+        let result = self.key_buffer.take().map_or(Err(ErrorCode::NOMEM), |key| {
+            match (self.value_buffer.take(), self.perms.extract()) {
+                (Some(value), Some(perms)) => {
+                    self.state.set(State::Setting);
+                    self.kv
+                        .set(key, value, self.value_len.get(), perms)
+                        .map_err(|(key, value, e)| {
+                            self.key_buffer.replace(key);
+                            self.value_buffer.replace(value);
+                            e.err().unwrap_or(ErrorCode::FAIL)
+                        })
+                }
+                (value, _) => {
+                    self.key_buffer.replace(key);
+                    value.map(|value| self.value_buffer.replace(value));
+                    Err(ErrorCode::NOMEM)
+                }
+            }
+        });
+        if let Err(e) = result {
+            self.done(Err(e), 0);
+        }
+    }
+
+    /// Starts the next queued command.
+    fn check_queue(&self) {
+        if self.current.is_some() {
+            return;
+        }
+
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started = cntr.enter(|app, kernel_data| {
+                app.pending.take().map_or(false, |(command, pref)| {
+                    match self.start(processid, command, pref, kernel_data) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            // The command was accepted when it was queued,
+                            // so the process learns about the failure from
+                            // the upcall.
+                            kernel_data
+                                .schedule_upcall(
+                                    upcalls::DONE,
+                                    (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+                                )
+                                .ok();
+                            false
+                        }
+                    }
+                })
+            });
+            if started {
+                break;
+            }
+        }
+    }
+
+    /// Ends the command of the current process with `result` and the value
+    /// of the upcall.
+    fn done(&self, result: Result<(), ErrorCode>, value: usize) {
+        self.state.set(State::Idle);
+        self.perms.clear();
+        self.current.take().map(|(processid, _)| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcalls::DONE,
+                        (kernel::errorcode::into_statuscode(result), value, 0),
+                    )
+                    .ok();
+            });
+        });
+        self.check_queue();
+    }
+
+    /// Hand the value read for the current command to its process.
+    fn got(&self, value: &[u8]) -> (Result<(), ErrorCode>, usize) {
+        let command = self.current.map_or(None, |(_, command)| Some(*command));
+        match (command, decode(value)) {
+            (Some(Command::GetInteger), Some((TYPE_INTEGER, data))) if data.len() == 4 => {
+                let integer = u32::from_le_bytes(data.try_into().unwrap_or([0; 4]));
+                (Ok(()), integer as usize)
+            }
+            (Some(Command::GetBytes), Some((TYPE_BYTES, data))) => {
+                self.current.map(|(processid, _)| {
+                    let _ = self.apps.enter(*processid, |_, kernel_data| {
+                        kernel_data
+                            .get_readwrite_processbuffer(rw_allow::VALUE)
+                            .and_then(|dest| {
+                                dest.mut_enter(|dest| {
+                                    let len = data.len().min(dest.len());
+                                    dest[..len].copy_from_slice(&data[..len]);
+                                })
+                            })
+                    });
+                });
+                (Ok(()), data.len())
+            }
+            // The preference was stored with another type.
+            (_, Some(_)) => (Err(ErrorCode::INVAL), 0),
+            (_, None) => (Err(ErrorCode::FAIL), 0),
+        }
+    }
+}
+
+/// Zero `key` and write the key of preference `pref` of the application
+/// with ShortID `short_id` to its start.
+fn set_key(key: &mut [u8], short_id: u32, pref: u32) {
+    key.fill(0);
+    let prefix = KEY_PREFIX.len();
+    key[..prefix].copy_from_slice(KEY_PREFIX);
+    key[prefix..prefix + 4].copy_from_slice(&short_id.to_le_bytes());
+    key[prefix + 4..prefix + 8].copy_from_slice(&pref.to_le_bytes());
+}
+
+/// Write a value of type `kind` with `data` to `buf`, returning its length.
+fn encode(buf: &mut [u8], kind: u8, data: &[u8]) -> usize {
+    buf[0] = kind;
+    buf[1] = data.len() as u8;
+    buf[2..2 + data.len()].copy_from_slice(data);
+    2 + data.len()
+}
+
+/// The type and data of a stored value.
+fn decode(buf: &[u8]) -> Option<(u8, &[u8])> {
+    let len = *buf.get(1)? as usize;
+    if len > MAX_VALUE_LEN {
+        return None;
+    }
+    Some((buf[0], buf.get(2..2 + len)?))
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> kv_system::StoreClient<T>
+    for AppPreferences<'a, K, T>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        let (result, upcall_value) = match result {
+            Ok(()) => self.got(value),
+            Err(e) => (Err(e), 0),
+        };
+        self.value_buffer.replace(value);
+        if self.state.get() == State::Getting {
+            self.done(result, upcall_value);
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() == State::Setting {
+            self.done(result, 0);
+        }
+    }
+
+    fn delete_complete(&self, result: Result<(), ErrorCode>, key: &'static mut [u8]) {
+        self.key_buffer.replace(key);
+        match self.state.get() {
+            // Deleting fails if there is no old value, which is fine.
+            State::Replacing => self.set(),
+            State::Deleting => self.done(result, 0),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> SyscallDriver
+    for AppPreferences<'a, K, T>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Read integer preference `data1`. The upcall gets its value.
+    /// - `2`: Set integer preference `data1` to `data2`.
+    /// - `3`: Read bytes preference `data1` into read-write allow 0. The
+    ///   upcall gets its length, which may be larger than the buffer.
+    /// - `4`: Set bytes preference `data1` to read-only allow 0, of which up
+    ///   to `MAX_VALUE_LEN` bytes are stored.
+    /// - `5`: Delete preference `data1`.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`.
+    ///
+    /// Reading a preference stored with the other type reports `INVAL`, and
+    /// reading one that is not set reports `FAIL`. Commands return
+    /// `NOSUPPORT` if the process has no fixed ShortID and `INVAL` if it has
+    /// no storage permissions.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            driver_info::COMMAND_NUM => return DriverInfo::new(1).into(),
+            1 => Command::GetInteger,
+            2 => Command::SetInteger(data2 as u32),
+            3 => Command::GetBytes,
+            4 => Command::SetBytes,
+            5 => Command::Delete,
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        if let ShortID::LocallyUnique = processid.short_app_id() {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+        if processid.get_storage_permissions().is_none() {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                let running = self.current.map_or(false, |(id, _)| *id == processid);
+                if app.pending.is_some() || running {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.pending = Some((command, data1 as u32));
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match queued {
+            Ok(()) => {
+                self.check_queue();
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_round_trip() {
+        let mut buf = [0; VALUE_LEN];
+        let len = encode(&mut buf, TYPE_BYTES, b"metric");
+        assert_eq!(len, 8);
+        assert_eq!(decode(&buf), Some((TYPE_BYTES, &b"metric"[..])));
+
+        buf[1] = MAX_VALUE_LEN as u8 + 1;
+        assert_eq!(decode(&buf), None);
+    }
+
+    #[test]
+    fn keys_are_namespaced() {
+        let mut a = [0xff; KEY_LEN];
+        let mut b = [0; KEY_LEN];
+        set_key(&mut a, 7, 1);
+        set_key(&mut b, 8, 1);
+        assert_eq!(&a[..5], b"pref/");
+        assert_ne!(a, b);
+        assert!(a[13..].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,974 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Over-the-air updates of the applications with A/B slots and rollback.
+//!
+//! The applications of the board are stored in one of two flash slots. An
+//! update is a TBF image of the applications streamed to [`AppUpdate`]
+//! through the [`AppUpdater`] trait, by any transport: [`UartUpdateReceiver`]
+//! receives it over a `hil::uart`, a network stack would pass it on the same
+//! way. The image is written to the slot that is not running and its TBF
+//! footers are checked with the board's `AppCredentialsChecker`. An image is
+//! only installed if one of its credentials is accepted, e.g. if it has a
+//! valid signature.
+//!
+//! Which slot the board boots is stored in two boot record pages. Installing
+//! an update writes a new record to the page that does not hold the current
+//! record, so a power loss at any point leaves one of the slots bootable. The
+//! new image runs after the next reset, which a privileged process can
+//! trigger with this driver. Its first boot is a trial: the image must be
+//! confirmed by a privileged process, typically the updated application once
+//! it works, before the board resets again. Otherwise the board boots the
+//! previous slot again on the next reset.
+//!
+//! The board loads its processes from the slot returned by [`boot_slot()`],
+//! e.g. with `kernel::process::load_processes()` or a
+//! `kernel::process::DynamicProcessLoader`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let slots = [
+//!     capsules_extra::app_update::FlashRegion::new(64, &_sapps_a),
+//!     capsules_extra::app_update::FlashRegion::new(128, &_sapps_b),
+//! ];
+//! let records = [
+//!     capsules_extra::app_update::FlashRegion::new(192, &_sboot_record_a),
+//!     capsules_extra::app_update::FlashRegion::new(193, &_sboot_record_b),
+//! ];
+//! let boot_slot = capsules_extra::app_update::boot_slot(&records);
+//! kernel::process::load_processes(
+//!     board_kernel,
+//!     chip,
+//!     slots[boot_slot.index()].memory(),
+//!     &mut APP_MEMORY,
+//!     &mut PROCESSES,
+//!     &FAULT_RESPONSE,
+//!     &process_management_capability,
+//! );
+//!
+//! let app_update = components::app_update::AppUpdateComponent::new(
+//!     board_kernel,
+//!     &base_peripherals.nvmc,
+//!     slots,
+//!     records,
+//!     checker,
+//!     &["updater"],
+//!     Some(nrf52840::power::reset),
+//! )
+//! .finalize(components::app_update_component_static!(nrf52840::nvmc::Nvmc));
+//! ```
+//!
+//! The checker must be a separate instance from the checker of the kernel,
+//! as each checker has a single client.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::hil::crc::CrcAlgorithm;
+use kernel::hil::flash::{self, Flash};
+use kernel::hil::uart;
+use kernel::process_checker::{self, AppCredentialsChecker, CheckResult};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::crc;
+use kernel::{ErrorCode, Kernel, ProcessId};
+use tock_tbf::types::{TbfFooterV2Credentials, TbfParseError};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::AppUpdate as usize;
+
+/// "TKUP" in little endian.
+const RECORD_MAGIC: u32 = 0x5055_4B54;
+const RECORD_LEN: usize = 16;
+
+/// One of the two application slots.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Slot {
+    A = 0,
+    B = 1,
+}
+
+impl Slot {
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    fn other(self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+/// State of the image in the active slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageState {
+    /// The image was confirmed to work.
+    Confirmed = 0,
+    /// The image was installed and has not booted yet.
+    Pending = 1,
+    /// The image is booting for the first time. If the board resets before
+    /// the image is confirmed, the other slot is booted.
+    Trial = 2,
+}
+
+/// Contents of a boot record page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BootRecord {
+    /// Incremented by each new record; the record with the highest sequence
+    /// number is current.
+    sequence: u32,
+    active: Slot,
+    state: ImageState,
+}
+
+impl BootRecord {
+    fn decode(page: &[u8]) -> Option<BootRecord> {
+        let record = page.get(..RECORD_LEN)?;
+        let word =
+            |i: usize| u32::from_le_bytes([record[i], record[i + 1], record[i + 2], record[i + 3]]);
+        if word(0) != RECORD_MAGIC
+            || word(12) != crc::compute(CrcAlgorithm::Crc32C, &record[..12]).value()
+        {
+            return None;
+        }
+        let active = match record[8] {
+            0 => Slot::A,
+            1 => Slot::B,
+            _ => return None,
+        };
+        let state = match record[9] {
+            0 => ImageState::Confirmed,
+            1 => ImageState::Pending,
+            2 => ImageState::Trial,
+            _ => return None,
+        };
+        Some(BootRecord {
+            sequence: word(4),
+            active,
+            state,
+        })
+    }
+
+    fn encode(&self, page: &mut [u8]) {
+        page.fill(0xFF);
+        page[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        page[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        page[8] = self.active as u8;
+        page[9] = self.state as u8;
+        page[10..12].fill(0);
+        let crc = crc::compute(CrcAlgorithm::Crc32C, &page[..12]).value();
+        page[12..16].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    /// Returns the record following this one, in the other record page.
+    fn next(&self, active: Slot, state: ImageState) -> BootRecord {
+        BootRecord {
+            sequence: self.sequence.wrapping_add(1),
+            active,
+            state,
+        }
+    }
+}
+
+/// Returns the current boot record and the index of the page holding it.
+/// Without a valid record, slot A is confirmed.
+fn current_record(records: &[&[u8]; 2]) -> (BootRecord, usize) {
+    match (
+        BootRecord::decode(records[0]),
+        BootRecord::decode(records[1]),
+    ) {
+        (Some(a), Some(b)) => {
+            if (b.sequence.wrapping_sub(a.sequence) as i32) > 0 {
+                (b, 1)
+            } else {
+                (a, 0)
+            }
+        }
+        (Some(a), None) => (a, 0),
+        (None, Some(b)) => (b, 1),
+        (None, None) => (
+            BootRecord {
+                sequence: 0,
+                active: Slot::A,
+                state: ImageState::Confirmed,
+            },
+            1,
+        ),
+    }
+}
+
+/// Returns the slot to boot for the given boot record.
+fn slot_to_boot(record: &BootRecord) -> Slot {
+    match record.state {
+        ImageState::Trial => record.active.other(),
+        ImageState::Confirmed | ImageState::Pending => record.active,
+    }
+}
+
+/// A region of flash, both as pages of the flash device and as memory.
+#[derive(Clone, Copy)]
+pub struct FlashRegion {
+    start_page: usize,
+    memory: &'static [u8],
+}
+
+impl FlashRegion {
+    pub const fn new(start_page: usize, memory: &'static [u8]) -> FlashRegion {
+        FlashRegion { start_page, memory }
+    }
+
+    pub fn start_page(&self) -> usize {
+        self.start_page
+    }
+
+    pub fn memory(&self) -> &'static [u8] {
+        self.memory
+    }
+}
+
+/// Returns the slot the board must load its processes from at boot.
+pub fn boot_slot(records: &[FlashRegion; 2]) -> Slot {
+    slot_to_boot(&current_record(&[records[0].memory, records[1].memory]).0)
+}
+
+/// Receives an update image, independently of the transport it arrives by.
+pub trait AppUpdater<'a> {
+    fn set_client(&self, client: &'a dyn AppUpdaterClient);
+
+    /// Starts an update with an image of `length` bytes.
+    ///
+    /// Returns `SIZE` if the image does not fit in a slot, `ALREADY` if an
+    /// update is installed and waiting for a reset, `BUSY` if another update
+    /// is in progress.
+    fn start(&self, length: usize) -> Result<(), ErrorCode>;
+
+    /// Passes the next bytes of the image. Returns how many bytes were
+    /// consumed. If fewer than `data.len()` bytes were consumed, wait for
+    /// `write_done` before passing the rest.
+    fn write(&self, data: &[u8]) -> Result<usize, ErrorCode>;
+
+    /// Verifies and installs the image once all of it was passed.
+    /// `update_done` is called with the result.
+    fn finish(&self) -> Result<(), ErrorCode>;
+
+    /// Abandons the update in progress.
+    fn abort(&self) -> Result<(), ErrorCode>;
+}
+
+pub trait AppUpdaterClient {
+    /// More of the image can be passed to `write`.
+    fn write_done(&self, result: Result<(), ErrorCode>);
+
+    /// The update finished. The error is `INVAL` if the image is not a valid
+    /// TBF object, `FAIL` if none of its credentials were accepted or a
+    /// flash operation failed.
+    fn update_done(&self, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Waiting for more of the image.
+    Receiving,
+    /// Erasing the page of the slot the buffer is written to.
+    ErasingPage,
+    WritingPage,
+    /// Checking the credentials of the received image.
+    Verifying,
+    /// Erasing the page of the next boot record. `install` is whether the
+    /// record installs an update.
+    ErasingRecord {
+        install: bool,
+    },
+    WritingRecord {
+        install: bool,
+    },
+}
+
+pub struct AppUpdate<'a, F: Flash + 'static, P: ProcessManagementCapability> {
+    flash: &'a F,
+    buffer: TakeCell<'static, F::Page>,
+    page_size: usize,
+    slots: [FlashRegion; 2],
+    records: [FlashRegion; 2],
+    checker: &'a dyn AppCredentialsChecker<'a>,
+    client: OptionalCell<&'a dyn AppUpdaterClient>,
+    state: Cell<State>,
+    /// Slot the processes were loaded from.
+    booted: Slot,
+    /// Length of the image being received.
+    length: Cell<usize>,
+    /// Number of bytes of the image passed in so far.
+    received: Cell<usize>,
+    /// Index in the slot of the page in the buffer.
+    page: Cell<usize>,
+    /// Number of bytes of the image in the buffer.
+    fill: Cell<usize>,
+    /// Whether the page being written is the last one of the image.
+    last_page: Cell<bool>,
+    /// Index of the footer of the image being checked.
+    footer: Cell<usize>,
+    /// Record being written, and the index of its page.
+    next_record: Cell<Option<(BootRecord, usize)>>,
+    kernel: &'static Kernel,
+    /// Names of the processes allowed to use the syscall driver.
+    privileged: &'a [&'a str],
+    reset: Option<fn() -> !>,
+    capability: P,
+}
+
+impl<'a, F: Flash + 'static, P: ProcessManagementCapability> AppUpdate<'a, F, P> {
+    pub fn new(
+        flash: &'a F,
+        buffer: &'static mut F::Page,
+        slots: [FlashRegion; 2],
+        records: [FlashRegion; 2],
+        checker: &'a dyn AppCredentialsChecker<'a>,
+        kernel: &'static Kernel,
+        privileged: &'a [&'a str],
+        reset: Option<fn() -> !>,
+        capability: P,
+    ) -> Self {
+        let page_size = buffer.as_mut().len();
+        AppUpdate {
+            flash,
+            buffer: TakeCell::new(buffer),
+            page_size,
+            slots,
+            records,
+            checker,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            booted: boot_slot(&records),
+            length: Cell::new(0),
+            received: Cell::new(0),
+            page: Cell::new(0),
+            fill: Cell::new(0),
+            last_page: Cell::new(false),
+            footer: Cell::new(0),
+            next_record: Cell::new(None),
+            kernel,
+            privileged,
+            reset,
+            capability,
+        }
+    }
+
+    fn current_record(&self) -> (BootRecord, usize) {
+        current_record(&[self.records[0].memory, self.records[1].memory])
+    }
+
+    /// Records the boot in the boot records. Must be called once at boot,
+    /// after the flash client was set: a pending image starts its trial, a
+    /// failed trial is rolled back.
+    pub fn boot(&self) -> Result<(), ErrorCode> {
+        let (record, _) = self.current_record();
+        match record.state {
+            ImageState::Confirmed => Ok(()),
+            ImageState::Pending => self.write_record(record.active, ImageState::Trial, false),
+            ImageState::Trial => {
+                self.write_record(record.active.other(), ImageState::Confirmed, false)
+            }
+        }
+    }
+
+    /// Confirms that the image on its trial boot works, so it keeps being
+    /// booted.
+    pub fn confirm(&self) -> Result<(), ErrorCode> {
+        let (record, _) = self.current_record();
+        match record.state {
+            ImageState::Trial if record.active == self.booted => {
+                self.write_record(record.active, ImageState::Confirmed, false)
+            }
+            ImageState::Confirmed if record.active == self.booted => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Returns the slot updates are written to.
+    fn target(&self) -> Slot {
+        self.booted.other()
+    }
+
+    fn write_record(
+        &self,
+        active: Slot,
+        state: ImageState,
+        install: bool,
+    ) -> Result<(), ErrorCode> {
+        let (current, index) = self.current_record();
+        let record = current.next(active, state);
+        let page = 1 - index;
+        self.flash.erase_page(self.records[page].start_page)?;
+        self.next_record.set(Some((record, page)));
+        self.state.set(State::ErasingRecord { install });
+        Ok(())
+    }
+
+    /// Writes the buffered part of the image to flash.
+    fn write_buffer(&self) -> Result<(), ErrorCode> {
+        let page = self.slots[self.target().index()].start_page + self.page.get();
+        self.flash.erase_page(page)?;
+        self.state.set(State::ErasingPage);
+        Ok(())
+    }
+
+    fn update_done(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.update_done(result));
+    }
+
+    /// Returns the integrity region and the footers of the received image.
+    fn parse_image(&self) -> Result<(&'static [u8], &'static [u8]), ErrorCode> {
+        let image = self.slots[self.target().index()]
+            .memory
+            .get(..self.length.get())
+            .ok_or(ErrorCode::INVAL)?;
+        let lengths = image
+            .get(..8)
+            .and_then(|h| h.try_into().ok())
+            .ok_or(ErrorCode::INVAL)?;
+        let (version, header_length, total_size) =
+            tock_tbf::parse::parse_tbf_header_lengths(lengths).map_err(|_| ErrorCode::INVAL)?;
+        if total_size as usize != image.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        let header = image
+            .get(..header_length as usize)
+            .ok_or(ErrorCode::INVAL)
+            .and_then(|h| {
+                tock_tbf::parse::parse_tbf_header(h, version).map_err(|_| ErrorCode::INVAL)
+            })?;
+        let binary_end = header.get_binary_end() as usize;
+        match (image.get(..binary_end), image.get(binary_end..)) {
+            (Some(binary), Some(footers)) => Ok((binary, footers)),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    /// Passes the next footer of the received image to the checker, or
+    /// finishes the update if no footer is left.
+    fn check_next_footer(&self) {
+        let (binary, mut footers) = match self.parse_image() {
+            Ok(image) => image,
+            Err(e) => return self.update_done(Err(e)),
+        };
+        let mut index = 0;
+        loop {
+            let footer = match tock_tbf::parse::parse_tbf_footer(footers) {
+                Ok((footer, len)) => match footers.get(len as usize + 4..) {
+                    Some(rest) => {
+                        footers = rest;
+                        footer
+                    }
+                    None => return self.update_done(Err(ErrorCode::INVAL)),
+                },
+                // No credential was accepted.
+                Err(TbfParseError::NotEnoughFlash) => {
+                    return self.update_done(Err(ErrorCode::FAIL))
+                }
+                Err(_) => return self.update_done(Err(ErrorCode::INVAL)),
+            };
+            if index == self.footer.get() {
+                match self.checker.check_credentials(footer, binary) {
+                    Ok(()) => return,
+                    // The checker does not handle this footer.
+                    Err(_) => self.footer.set(index + 1),
+                }
+            }
+            index += 1;
+        }
+    }
+
+    fn is_privileged(&self, processid: ProcessId) -> bool {
+        self.kernel.process_map_or_external(
+            false,
+            processid,
+            |process| self.privileged.contains(&process.get_process_name()),
+            &self.capability,
+        )
+    }
+}
+
+impl<'a, F: Flash + 'static, P: ProcessManagementCapability> AppUpdater<'a>
+    for AppUpdate<'a, F, P>
+{
+    fn set_client(&self, client: &'a dyn AppUpdaterClient) {
+        self.client.set(client);
+    }
+
+    fn start(&self, length: usize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.current_record().0.state == ImageState::Pending {
+            return Err(ErrorCode::ALREADY);
+        }
+        if length < 8 || length > self.slots[self.target().index()].memory.len() {
+            return Err(ErrorCode::SIZE);
+        }
+        self.length.set(length);
+        self.received.set(0);
+        self.page.set(0);
+        self.fill.set(0);
+        self.footer.set(0);
+        self.state.set(State::Receiving);
+        Ok(())
+    }
+
+    fn write(&self, data: &[u8]) -> Result<usize, ErrorCode> {
+        match self.state.get() {
+            State::Receiving => {}
+            State::Idle => return Err(ErrorCode::OFF),
+            _ => return Err(ErrorCode::BUSY),
+        }
+        let fill = self.fill.get();
+        let count = data
+            .len()
+            .min(self.page_size - fill)
+            .min(self.length.get() - self.received.get());
+        self.buffer.map(|buffer| {
+            buffer.as_mut()[fill..fill + count].copy_from_slice(&data[..count]);
+        });
+        self.fill.set(fill + count);
+        self.received.set(self.received.get() + count);
+
+        if self.fill.get() == self.page_size {
+            self.last_page.set(false);
+            self.write_buffer()?;
+        }
+        Ok(count)
+    }
+
+    fn finish(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Receiving {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.received.get() != self.length.get() {
+            return Err(ErrorCode::SIZE);
+        }
+        if self.fill.get() == 0 {
+            self.state.set(State::Verifying);
+            self.check_next_footer();
+            return Ok(());
+        }
+        let fill = self.fill.get();
+        self.buffer.map(|buffer| buffer.as_mut()[fill..].fill(0xFF));
+        self.last_page.set(true);
+        self.write_buffer()
+    }
+
+    fn abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Receiving => {
+                self.state.set(State::Idle);
+                Ok(())
+            }
+            State::Idle => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static, P: ProcessManagementCapability> flash::Client<F>
+    for AppUpdate<'a, F, P>
+{
+    fn read_complete(&self, buffer: &'static mut F::Page, _error: flash::Error) {
+        self.buffer.replace(buffer);
+    }
+
+    fn write_complete(&self, buffer: &'static mut F::Page, error: flash::Error) {
+        self.buffer.replace(buffer);
+        match self.state.get() {
+            State::WritingPage => {
+                if error != flash::Error::CommandComplete {
+                    return self.update_done(Err(ErrorCode::FAIL));
+                }
+                self.page.set(self.page.get() + 1);
+                self.fill.set(0);
+                if self.last_page.get() {
+                    self.state.set(State::Verifying);
+                    self.check_next_footer();
+                } else {
+                    self.state.set(State::Receiving);
+                    self.client.map(|client| client.write_done(Ok(())));
+                }
+            }
+            State::WritingRecord { install } => {
+                let result = if error == flash::Error::CommandComplete {
+                    Ok(())
+                } else {
+                    Err(ErrorCode::FAIL)
+                };
+                self.state.set(State::Idle);
+                if install {
+                    self.update_done(result);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_complete(&self, error: flash::Error) {
+        let state = self.state.get();
+        let page = match state {
+            State::ErasingPage => self.slots[self.target().index()].start_page + self.page.get(),
+            State::ErasingRecord { .. } => match self.next_record.get() {
+                Some((record, index)) => {
+                    self.buffer.map(|buffer| record.encode(buffer.as_mut()));
+                    self.records[index].start_page
+                }
+                None => return,
+            },
+            _ => return,
+        };
+        let result = if error == flash::Error::CommandComplete {
+            self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+                self.flash.write_page(page, buffer).map_err(|(e, buffer)| {
+                    self.buffer.replace(buffer);
+                    e
+                })
+            })
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+        match (state, result) {
+            (State::ErasingPage, Ok(())) => self.state.set(State::WritingPage),
+            (State::ErasingRecord { install }, Ok(())) => {
+                self.state.set(State::WritingRecord { install })
+            }
+            (State::ErasingRecord { install: false }, Err(_)) => self.state.set(State::Idle),
+            (_, Err(_)) => self.update_done(Err(ErrorCode::FAIL)),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static, P: ProcessManagementCapability> process_checker::Client<'a>
+    for AppUpdate<'a, F, P>
+{
+    fn check_done(
+        &self,
+        result: Result<CheckResult, ErrorCode>,
+        _credentials: TbfFooterV2Credentials,
+        _binary: &'a [u8],
+    ) {
+        if self.state.get() != State::Verifying {
+            return;
+        }
+        match result {
+            Ok(CheckResult::Accept) => {
+                if let Err(e) = self.write_record(self.target(), ImageState::Pending, true) {
+                    self.update_done(Err(e));
+                }
+            }
+            Ok(CheckResult::Reject) => self.update_done(Err(ErrorCode::FAIL)),
+            Ok(CheckResult::Pass) | Err(_) => {
+                self.footer.set(self.footer.get() + 1);
+                self.check_next_footer();
+            }
+        }
+    }
+}
+
+/// Provide a syscall interface for managing updates.
+///
+/// ### `command_num`
+///
+/// - `0`: Driver existence check.
+/// - `1`: Returns the slot the processes were loaded from and the state of
+///   the current boot record: 0 if the active image is confirmed, 1 if an
+///   update is installed and waiting for a reset, 2 if the active image is on
+///   its trial boot.
+/// - `2`: Confirm that the image on its trial boot works. Returns `ALREADY`
+///   if it is confirmed and `INVAL` if it is not running.
+/// - `3`: Reset the board to run the installed update. Returns `INVAL` if no
+///   update is installed and `NOSUPPORT` if the board cannot be reset.
+///
+/// All commands other than 0 return `NODEVICE`, like a command denied by the
+/// kernel, if the calling process is not privileged.
+impl<'a, F: Flash + 'static, P: ProcessManagementCapability> SyscallDriver for AppUpdate<'a, F, P> {
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_privileged(processid) {
+            return CommandReturn::failure(ErrorCode::NODEVICE);
+        }
+
+        match command_num {
+            1 => CommandReturn::success_u32_u32(
+                self.booted.index() as u32,
+                self.current_record().0.state as u32,
+            ),
+            2 => self.confirm().into(),
+            3 => {
+                if self.current_record().0.state != ImageState::Pending {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                match self.reset {
+                    Some(reset) => reset(),
+                    None => CommandReturn::failure(ErrorCode::NOSUPPORT),
+                }
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
+
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+
+/// Receives update images over a UART.
+///
+/// The host sends the length of the image as a 4 byte little endian integer,
+/// then the image in chunks of the size of the receive buffer. The length and
+/// each chunk but the last are answered with `ACK` (0x06) once the next chunk
+/// can be sent. The last chunk is answered with `ACK` once the image was
+/// verified and installed. Any failure is answered with `NAK` (0x15), after
+/// which the host starts over by sending a length.
+pub struct UartUpdateReceiver<'a> {
+    uart: &'a dyn uart::UartData<'a>,
+    updater: &'a dyn AppUpdater<'a>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// Number of bytes of the image left to receive, `None` while receiving
+    /// the length.
+    remaining: Cell<Option<usize>>,
+    /// Received bytes in the receive buffer not yet passed to the updater.
+    pending: Cell<(usize, usize)>,
+}
+
+impl<'a> UartUpdateReceiver<'a> {
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        updater: &'a dyn AppUpdater<'a>,
+        rx_buffer: &'static mut [u8],
+        tx_buffer: &'static mut [u8],
+    ) -> Self {
+        UartUpdateReceiver {
+            uart,
+            updater,
+            rx_buffer: TakeCell::new(rx_buffer),
+            tx_buffer: TakeCell::new(tx_buffer),
+            remaining: Cell::new(None),
+            pending: Cell::new((0, 0)),
+        }
+    }
+
+    /// Starts receiving images.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.remaining.set(None);
+        self.receive(4)
+    }
+
+    fn receive(&self, len: usize) -> Result<(), ErrorCode> {
+        self.rx_buffer
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |buffer| {
+                let len = len.min(buffer.len());
+                self.uart
+                    .receive_buffer(buffer, len)
+                    .map_err(|(e, buffer)| {
+                        self.rx_buffer.replace(buffer);
+                        e
+                    })
+            })
+    }
+
+    /// Receives the next chunk, or the length of the next image.
+    fn receive_next(&self) {
+        let len = self.remaining.get().unwrap_or(4);
+        if self.receive(len).is_err() {
+            self.fail();
+        }
+    }
+
+    fn reply(&self, byte: u8) {
+        self.tx_buffer.take().map(|buffer| {
+            buffer[0] = byte;
+            if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, 1) {
+                self.tx_buffer.replace(buffer);
+            }
+        });
+    }
+
+    /// Abandons the current image and waits for the length of the next.
+    fn fail(&self) {
+        let _ = self.updater.abort();
+        self.remaining.set(None);
+        self.pending.set((0, 0));
+        self.reply(NAK);
+        let _ = self.receive(4);
+    }
+
+    /// Passes the pending bytes of the receive buffer to the updater.
+    fn write_pending(&self) {
+        let (mut start, end) = self.pending.get();
+        let result = self.rx_buffer.map_or(Err(ErrorCode::FAIL), |buffer| {
+            while start < end {
+                match self.updater.write(&buffer[start..end]) {
+                    Ok(0) => break,
+                    Ok(count) => start += count,
+                    Err(ErrorCode::BUSY) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        });
+        self.pending.set((start, end));
+        if result.is_err() {
+            return self.fail();
+        }
+        if start < end {
+            // Wait for `write_done`.
+            return;
+        }
+        match self.remaining.get() {
+            Some(0) => {
+                if self.updater.finish().is_err() {
+                    self.fail();
+                }
+            }
+            _ => {
+                self.reply(ACK);
+                self.receive_next();
+            }
+        }
+    }
+}
+
+impl<'a> uart::ReceiveClient for UartUpdateReceiver<'a> {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let length = match self.remaining.get() {
+            None if rval.is_ok() && rx_len == 4 => {
+                Some(
+                    u32::from_le_bytes([rx_buffer[0], rx_buffer[1], rx_buffer[2], rx_buffer[3]])
+                        as usize,
+                )
+            }
+            _ => None,
+        };
+        self.rx_buffer.replace(rx_buffer);
+        if rval.is_err() {
+            return self.fail();
+        }
+
+        match (self.remaining.get(), length) {
+            (None, Some(length)) => {
+                if self.updater.start(length).is_err() {
+                    return self.fail();
+                }
+                self.remaining.set(Some(length));
+                self.reply(ACK);
+                self.receive_next();
+            }
+            (Some(remaining), _) => {
+                self.remaining.set(Some(remaining - rx_len.min(remaining)));
+                self.pending.set((0, rx_len));
+                self.write_pending();
+            }
+            _ => self.receive_next(),
+        }
+    }
+}
+
+impl<'a> uart::TransmitClient for UartUpdateReceiver<'a> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+    }
+}
+
+impl<'a> AppUpdaterClient for UartUpdateReceiver<'a> {
+    fn write_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.write_pending(),
+            Err(_) => self.fail(),
+        }
+    }
+
+    fn update_done(&self, result: Result<(), ErrorCode>) {
+        self.remaining.set(None);
+        self.pending.set((0, 0));
+        self.reply(if result.is_ok() { ACK } else { NAK });
+        self.receive_next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(sequence: u32, active: Slot, state: ImageState) -> [u8; 32] {
+        let mut page = [0xFF; 32];
+        BootRecord {
+            sequence,
+            active,
+            state,
+        }
+        .encode(&mut page);
+        page
+    }
+
+    #[test]
+    fn newest_record_is_current() {
+        let erased = [0xFF; 32];
+        assert_eq!(current_record(&[&erased, &erased]).0.active, Slot::A);
+
+        let a = record(7, Slot::B, ImageState::Pending);
+        let b = record(6, Slot::A, ImageState::Confirmed);
+        assert_eq!(
+            current_record(&[&a, &b]),
+            (BootRecord::decode(&a).unwrap(), 0)
+        );
+        let a = record(u32::MAX, Slot::A, ImageState::Confirmed);
+        let b = record(0, Slot::B, ImageState::Trial);
+        assert_eq!(current_record(&[&a, &b]).1, 1);
+
+        // A corrupted record is ignored.
+        let mut b = record(8, Slot::A, ImageState::Confirmed);
+        b[4] ^= 1;
+        assert_eq!(current_record(&[&a, &b]).1, 0);
+    }
+
+    #[test]
+    fn failed_trial_boots_other_slot() {
+        let boot = |state| {
+            slot_to_boot(&BootRecord {
+                sequence: 1,
+                active: Slot::B,
+                state,
+            })
+        };
+        assert_eq!(boot(ImageState::Pending), Slot::B);
+        assert_eq!(boot(ImageState::Trial), Slot::A);
+        assert_eq!(boot(ImageState::Confirmed), Slot::B);
+    }
+}
@@ -0,0 +1,369 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Blackboard of the latest sensor values, with change notifications.
+//!
+//! Producer capsules publish the values they sample under well-known IDs
+//! (see [`id`]) through the [`Publish`] trait. The blackboard keeps the
+//! latest value of each ID. Processes read them, or subscribe to an ID with
+//! a minimum interval between notifications: changes that arrive sooner are
+//! coalesced into one notification with the latest value once the interval
+//! has passed. Sensors can thus be sampled as fast as a producer needs,
+//! without waking every process at that rate.
+//!
+//! Values are `i32` in the unit documented with their ID. Only changes are
+//! notified, publishing the same value again is not.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let blackboard = components::blackboard::BlackboardComponent::new(
+//!     board_kernel,
+//!     capsules_extra::blackboard::DRIVER_NUM,
+//!     mux_alarm,
+//! )
+//! .finalize(components::blackboard_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     8
+//! ));
+//!
+//! // In a producer capsule, holding a `&dyn Publish`:
+//! let _ = self.blackboard.publish(blackboard::id::TEMPERATURE, hundredths);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Read the latest value published under ID `data1`. Returns the
+//!   value and the number of changes of it so far. Returns `INVAL` if
+//!   nothing was published under the ID.
+//! - `2`: Subscribe to changes of ID `data1`, at most one notification
+//!   every `data2` milliseconds. Changes the interval of an existing
+//!   subscription. Returns `NOMEM` if the process has `MAX_SUBSCRIPTIONS`
+//!   subscriptions already.
+//! - `3`: Unsubscribe from ID `data1`. Returns `INVAL` if the process is not
+//!   subscribed to it.
+//!
+//! ### Subscribe
+//!
+//! - `0`: A subscribed value changed. The upcall gets the ID, the latest
+//!   value, and the number of changes since the last notification.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{self, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::time_conversion::{self, Rounding};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Blackboard as usize;
+
+/// Well-known IDs, with the unit of their values.
+///
+/// IDs from `BOARD` up are free for boards to assign.
+pub mod id {
+    /// Temperature in hundredths of a degree Celsius.
+    pub const TEMPERATURE: u32 = 0;
+    /// Relative humidity in hundredths of a percent.
+    pub const HUMIDITY: u32 = 1;
+    /// Air pressure in pascals.
+    pub const PRESSURE: u32 = 2;
+    /// Ambient light in lux.
+    pub const AMBIENT_LIGHT: u32 = 3;
+    /// Sound pressure in decibels.
+    pub const SOUND_PRESSURE: u32 = 4;
+    /// Battery voltage in millivolts.
+    pub const BATTERY_VOLTAGE: u32 = 5;
+    /// Equivalent CO2 concentration in parts per million.
+    pub const CO2: u32 = 6;
+    /// Proximity, 0 (far) to 255 (near).
+    pub const PROXIMITY: u32 = 7;
+    /// First ID for board specific values.
+    pub const BOARD: u32 = 0x1000;
+}
+
+/// Number of IDs a process can subscribe to.
+pub const MAX_SUBSCRIPTIONS: usize = 4;
+
+mod upcall {
+    pub const CHANGED: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Publishes values on a blackboard.
+pub trait Publish {
+    /// Set the latest value of `id`. Returns `NOMEM` if the blackboard has
+    /// no room for another ID.
+    fn publish(&self, id: u32, value: i32) -> Result<(), ErrorCode>;
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    id: u32,
+    value: i32,
+    /// Number of changes of the value.
+    changes: u32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Subscription {
+    active: bool,
+    id: u32,
+    /// Minimum ticks between notifications.
+    interval: u32,
+    /// When the process was last notified, in the lower bits of the ticks.
+    last: u32,
+    /// Changes since the last notification.
+    pending: u32,
+}
+
+#[derive(Default)]
+pub struct App {
+    subscriptions: [Subscription; MAX_SUBSCRIPTIONS],
+}
+
+pub struct Blackboard<'a, A: time::Alarm<'a>, const N: usize> {
+    alarm: &'a A,
+    entries: [Cell<Option<Entry>>; N],
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> Blackboard<'a, A, N> {
+    pub fn new(
+        alarm: &'a A,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        Blackboard {
+            alarm,
+            entries: core::array::from_fn(|_| Cell::new(None)),
+            apps: grant,
+        }
+    }
+
+    /// The latest value of `id`, if any was published.
+    pub fn read(&self, id: u32) -> Option<i32> {
+        self.entry(id).map(|entry| entry.value)
+    }
+
+    fn entry(&self, id: u32) -> Option<Entry> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.get())
+            .find(|entry| entry.id == id)
+    }
+
+    /// The current time, in a `u32` so it fits in the grant.
+    fn now(&self) -> u32 {
+        self.alarm.now().into_u32()
+    }
+
+    /// Ticks from `since` to `now`, which wrap at the width of the timer.
+    fn elapsed(&self, now: u32, since: u32) -> u32 {
+        now.wrapping_sub(since) & A::Ticks::max_value().into_u32()
+    }
+
+    /// The longest interval that is measured correctly.
+    fn max_interval(&self) -> u32 {
+        A::Ticks::half_max_value().into_u32()
+    }
+
+    /// Ticks until `subscription` may be notified again, 0 if it may be now.
+    ///
+    /// A subscription idle for longer than the timer wraps can wait for up
+    /// to one more interval.
+    fn remaining(&self, subscription: &Subscription, now: u32) -> u32 {
+        subscription
+            .interval
+            .saturating_sub(self.elapsed(now, subscription.last))
+    }
+
+    /// Notify `subscription`, if it has pending changes and its interval
+    /// has passed.
+    fn notify(
+        &self,
+        subscription: &mut Subscription,
+        upcalls: &kernel::grant::GrantKernelData,
+        now: u32,
+    ) {
+        if subscription.pending == 0 || self.remaining(subscription, now) > 0 {
+            return;
+        }
+        if let Some(entry) = self.entry(subscription.id) {
+            let _ = upcalls.schedule_upcall(
+                upcall::CHANGED,
+                (
+                    subscription.id as usize,
+                    entry.value as usize,
+                    subscription.pending as usize,
+                ),
+            );
+        }
+        subscription.last = now;
+        subscription.pending = 0;
+    }
+
+    /// Set the alarm for the first subscription with pending changes.
+    fn rearm(&self, now: u32) {
+        let mut next: Option<u32> = None;
+        for app in self.apps.iter() {
+            app.enter(|app, _| {
+                for subscription in app.subscriptions.iter() {
+                    if subscription.active && subscription.pending > 0 {
+                        let remaining = self.remaining(subscription, now);
+                        next = Some(next.map_or(remaining, |next| next.min(remaining)));
+                    }
+                }
+            });
+        }
+        match next {
+            Some(remaining) => self
+                .alarm
+                .set_alarm(A::Ticks::from(now), A::Ticks::from(remaining)),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn subscribe(&self, processid: ProcessId, id: u32, interval_ms: u32) -> CommandReturn {
+        let interval =
+            time_conversion::ticks_from_ms::<A::Frequency, A::Ticks>(interval_ms, Rounding::Up)
+                .map_or(self.max_interval(), |ticks| {
+                    ticks.into_u32().min(self.max_interval())
+                });
+        let now = self.now();
+        self.apps
+            .enter(processid, |app, _| {
+                let index = app
+                    .subscriptions
+                    .iter()
+                    .position(|s| s.active && s.id == id)
+                    .or_else(|| app.subscriptions.iter().position(|s| !s.active));
+                match index {
+                    Some(index) => {
+                        let subscription = &mut app.subscriptions[index];
+                        if !subscription.active {
+                            // The first change is notified right away.
+                            *subscription = Subscription {
+                                active: true,
+                                id,
+                                interval,
+                                last: now.wrapping_sub(interval),
+                                pending: 0,
+                            };
+                        }
+                        subscription.interval = interval;
+                        CommandReturn::success()
+                    }
+                    None => CommandReturn::failure(ErrorCode::NOMEM),
+                }
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+
+    fn unsubscribe(&self, processid: ProcessId, id: u32) -> CommandReturn {
+        self.apps
+            .enter(processid, |app, _| {
+                match app
+                    .subscriptions
+                    .iter_mut()
+                    .find(|s| s.active && s.id == id)
+                {
+                    Some(subscription) => {
+                        subscription.active = false;
+                        CommandReturn::success()
+                    }
+                    None => CommandReturn::failure(ErrorCode::INVAL),
+                }
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> Publish for Blackboard<'a, A, N> {
+    fn publish(&self, id: u32, value: i32) -> Result<(), ErrorCode> {
+        let slot = self
+            .entries
+            .iter()
+            .find(|entry| entry.get().map_or(false, |entry| entry.id == id))
+            .or_else(|| self.entries.iter().find(|entry| entry.get().is_none()))
+            .ok_or(ErrorCode::NOMEM)?;
+        let changes = match slot.get() {
+            Some(entry) if entry.value == value => return Ok(()),
+            Some(entry) => entry.changes.wrapping_add(1),
+            None => 1,
+        };
+        slot.set(Some(Entry { id, value, changes }));
+
+        let now = self.now();
+        for app in self.apps.iter() {
+            app.enter(|app, upcalls| {
+                for subscription in app.subscriptions.iter_mut() {
+                    if subscription.active && subscription.id == id {
+                        subscription.pending = subscription.pending.saturating_add(1);
+                        self.notify(subscription, upcalls, now);
+                    }
+                }
+            });
+        }
+        self.rearm(now);
+        Ok(())
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> time::AlarmClient for Blackboard<'a, A, N> {
+    fn alarm(&self) {
+        let now = self.now();
+        for app in self.apps.iter() {
+            app.enter(|app, upcalls| {
+                for subscription in app.subscriptions.iter_mut() {
+                    if subscription.active {
+                        self.notify(subscription, upcalls, now);
+                    }
+                }
+            });
+        }
+        self.rearm(now);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> SyscallDriver for Blackboard<'a, A, N> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.entry(data1 as u32) {
+                Some(entry) => CommandReturn::success_u32_u32(entry.value as u32, entry.changes),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            2 => {
+                let interval_ms = u32::try_from(data2).unwrap_or(u32::MAX);
+                self.subscribe(processid, data1 as u32, interval_ms)
+            }
+
+            3 => self.unsubscribe(processid, data1 as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -112,11 +112,12 @@ use kernel::debug;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::ble_advertising;
 use kernel::hil::ble_advertising::RadioChannel;
-use kernel::hil::time::{Frequency, Ticks};
+use kernel::hil::time::{Frequency, Ticks, Ticks32};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::copy_slice::CopyOrErr;
+use kernel::utilities::time_conversion::{self, Rounding};
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -317,8 +318,12 @@ impl App {
     fn set_next_alarm<F: Frequency>(&mut self, now: u32) {
         let nonce = self.random_nonce() % 10;
 
-        let period_ms = (self.advertisement_interval_ms + nonce) * F::frequency() / 1000;
-        self.alarm_data.expiration = Expiration::Enabled(now, period_ms);
+        let period = time_conversion::ticks_from_ms::<F, Ticks32>(
+            self.advertisement_interval_ms + nonce,
+            Rounding::Down,
+        )
+        .map_or(u32::MAX, |period| period.into_u32());
+        self.alarm_data.expiration = Expiration::Enabled(now, period);
     }
 }
 
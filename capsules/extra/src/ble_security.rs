@@ -0,0 +1,891 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! BLE Security Manager: LE legacy pairing and bonding.
+//!
+//! Implements the responder (peripheral) side of the Security Manager
+//! Protocol for LE legacy pairing, with the Just Works and Passkey Entry
+//! methods. Passkey Entry is used when the board can display a passkey and
+//! the central has a keyboard; the passkey is passed to the
+//! [`SecurityClient`] to be shown to the user. Otherwise Just Works is used,
+//! which does not protect against a man in the middle.
+//!
+//! When the central asks to bond, the long-term key generated during pairing
+//! is stored in the KV store with the peer address. When the peer reconnects
+//! and asks to encrypt the link with that key, it is looked up again so the
+//! peer does not need to pair again.
+//!
+//! The security manager runs on top of a link layer that supports
+//! connections, through the [`SecureLink`] trait. The link layer carries the
+//! SMP PDUs on the Security Manager L2CAP channel and encrypts the link with
+//! the keys provided by the security manager.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let ble_security = components::ble_security::BleSecurityComponent::new(
+//!     link,
+//!     &base_peripherals.ecb,
+//!     rng,
+//!     kv_store,
+//!     BLE_BONDS_STORAGE_ID,
+//!     capsules_extra::ble_security::IoCapability::DisplayOnly,
+//!     true,
+//! )
+//! .finalize(components::ble_security_component_static!(
+//!     nrf52840::aes::AesECB<'static>,
+//!     capsules_extra::tickv::TicKVStore<...>,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! ble_security.set_client(passkey_display);
+//! ```
+
+use core::cell::Cell;
+
+use crate::kv_store::KVStore;
+use kernel::hil::kv_system::{self, KVSystem, KeyType};
+use kernel::hil::rng;
+use kernel::hil::symmetric_encryption::{self, AES128, AES128ECB, AES128_BLOCK_SIZE};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Length of the longest SMP PDU sent: an opcode and a 16 byte value.
+pub const SMP_PDU_LEN: usize = 17;
+/// Length of the KV store key of a bond.
+pub const BOND_KEY_LEN: usize = 15;
+/// Length of a stored bond.
+pub const BOND_LEN: usize = 27;
+
+const BOND_KEY_PREFIX: &[u8] = b"ble_bond";
+
+/// SMP opcodes.
+mod opcode {
+    pub const PAIRING_REQUEST: u8 = 0x01;
+    pub const PAIRING_RESPONSE: u8 = 0x02;
+    pub const PAIRING_CONFIRM: u8 = 0x03;
+    pub const PAIRING_RANDOM: u8 = 0x04;
+    pub const PAIRING_FAILED: u8 = 0x05;
+    pub const ENCRYPTION_INFORMATION: u8 = 0x06;
+    pub const MASTER_IDENTIFICATION: u8 = 0x07;
+    pub const SECURITY_REQUEST: u8 = 0x0B;
+}
+
+/// Reasons sent in a Pairing Failed PDU.
+mod reason {
+    pub const CONFIRM_VALUE_FAILED: u8 = 0x04;
+    pub const PAIRING_NOT_SUPPORTED: u8 = 0x05;
+    pub const ENCRYPTION_KEY_SIZE: u8 = 0x06;
+    pub const COMMAND_NOT_SUPPORTED: u8 = 0x07;
+    pub const UNSPECIFIED_REASON: u8 = 0x08;
+    pub const INVALID_PARAMETERS: u8 = 0x0A;
+}
+
+const AUTH_REQ_BONDING: u8 = 0x01;
+const AUTH_REQ_MITM: u8 = 0x04;
+/// Key distribution flag of the long-term key.
+const KEY_DIST_ENC_KEY: u8 = 0x01;
+const MIN_KEY_SIZE: u8 = 7;
+const MAX_KEY_SIZE: u8 = 16;
+
+const IO_KEYBOARD_ONLY: u8 = 0x02;
+const IO_KEYBOARD_DISPLAY: u8 = 0x04;
+
+/// Input and output capabilities of the board used for pairing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IoCapability {
+    /// The board can display a 6 digit passkey.
+    DisplayOnly = 0x00,
+    NoInputNoOutput = 0x03,
+}
+
+/// A BLE device address, in the byte order of the air interface.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceAddress {
+    pub random: bool,
+    pub address: [u8; 6],
+}
+
+/// Link layer connection used by the security manager.
+pub trait SecureLink<'a> {
+    fn set_client(&self, client: &'a dyn SecureLinkClient);
+
+    /// Sends an SMP PDU on the Security Manager channel of the connection.
+    fn send_smp(
+        &self,
+        pdu: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Answers a `key_requested` call with the key to encrypt the link with.
+    /// `None` rejects the request.
+    fn reply_key(&self, key: Option<[u8; 16]>);
+}
+
+pub trait SecureLinkClient {
+    /// A central connected, with the given addresses.
+    fn connected(&self, local: DeviceAddress, peer: DeviceAddress);
+
+    fn disconnected(&self);
+
+    /// An SMP PDU was received.
+    fn smp_received(&self, pdu: &[u8]);
+
+    /// An SMP PDU passed to `send_smp()` was sent.
+    fn smp_sent(&self, pdu: &'static mut [u8]);
+
+    /// The central asked to encrypt the link with the key identified by
+    /// `ediv` and `rand`. Answered with `reply_key()`.
+    fn key_requested(&self, ediv: u16, rand: [u8; 8]);
+
+    /// The encryption of the link was turned on or off.
+    fn encryption_changed(&self, encrypted: bool);
+}
+
+pub trait SecurityClient {
+    /// Show `passkey` to the user, who enters it on the central.
+    fn display_passkey(&self, passkey: u32);
+
+    /// Pairing finished. `bonded` is whether the keys were stored to encrypt
+    /// later connections with the peer.
+    fn pairing_done(&self, result: Result<bool, ErrorCode>);
+}
+
+/// Keys stored for a bonded peer.
+#[derive(Clone, Copy, PartialEq)]
+struct Bond {
+    ltk: [u8; 16],
+    ediv: u16,
+    rand: [u8; 8],
+    /// Whether the key was generated by a pairing method protecting against
+    /// a man in the middle.
+    authenticated: bool,
+}
+
+impl Bond {
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0..16].copy_from_slice(&self.ltk);
+        buf[16..18].copy_from_slice(&self.ediv.to_le_bytes());
+        buf[18..26].copy_from_slice(&self.rand);
+        buf[26] = self.authenticated as u8;
+    }
+
+    fn decode(buf: &[u8]) -> Option<Bond> {
+        let buf = buf.get(..BOND_LEN)?;
+        let mut bond = Bond {
+            ltk: [0; 16],
+            ediv: u16::from_le_bytes([buf[16], buf[17]]),
+            rand: [0; 8],
+            authenticated: buf[26] != 0,
+        };
+        bond.ltk.copy_from_slice(&buf[0..16]);
+        bond.rand.copy_from_slice(&buf[18..26]);
+        Some(bond)
+    }
+}
+
+/// State of a pairing in progress.
+#[derive(Clone, Copy)]
+struct Pairing {
+    /// Pairing Request and Response PDUs.
+    preq: [u8; 7],
+    pres: [u8; 7],
+    /// Random numbers for the pairing, filled by the RNG.
+    random: [u8; 48],
+    random_len: usize,
+    tk: [u8; 16],
+    srand: [u8; 16],
+    mrand: [u8; 16],
+    mconfirm: [u8; 16],
+    stk: [u8; 16],
+    key_size: usize,
+    /// Whether the keys are stored once the link is encrypted.
+    bonding: bool,
+    /// Whether a long-term key is sent to the central.
+    distribute_key: bool,
+    /// Keys for later connections.
+    bond: Bond,
+}
+
+impl Pairing {
+    fn new(preq: [u8; 7]) -> Pairing {
+        Pairing {
+            preq,
+            pres: [0; 7],
+            random: [0; 48],
+            random_len: 0,
+            tk: [0; 16],
+            srand: [0; 16],
+            mrand: [0; 16],
+            mconfirm: [0; 16],
+            stk: [0; 16],
+            key_size: 16,
+            bonding: false,
+            distribute_key: false,
+            bond: Bond {
+                ltk: [0; 16],
+                ediv: 0,
+                rand: [0; 8],
+                authenticated: false,
+            },
+        }
+    }
+}
+
+/// The AES operations of the confirm value function `c1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    First,
+    Second,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Disconnected,
+    Connected,
+    /// Getting the random numbers for a pairing.
+    GettingRandom,
+    /// Sent the Pairing Response, waiting for the Pairing Confirm.
+    WaitingConfirm,
+    /// Computing the confirm value sent to the central.
+    ComputingConfirm(Step),
+    WaitingRandom,
+    /// Computing the confirm value of the central to check it.
+    CheckingConfirm(Step),
+    ComputingStk,
+    /// Waiting for the central to encrypt the link with the STK.
+    WaitingEncryption,
+    SendingLtk,
+    SendingIdentification,
+    StoringBond,
+}
+
+pub struct BleSecurity<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType>
+{
+    link: &'a dyn SecureLink<'a>,
+    aes: &'a A,
+    rng: &'a dyn rng::Rng<'a>,
+    kv_store: &'a KVStore<'a, K, T>,
+    perms: StoragePermissions,
+    io_capability: IoCapability,
+    /// Whether the board bonds with centrals that ask for it.
+    bondable: bool,
+    client: OptionalCell<&'a dyn SecurityClient>,
+    state: Cell<State>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    aes_buffer: TakeCell<'static, [u8]>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    local: Cell<Option<DeviceAddress>>,
+    peer: Cell<Option<DeviceAddress>>,
+    pairing: MapCell<Pairing>,
+    /// Bond with the connected peer, once loaded from the KV store.
+    bond: Cell<Option<Bond>>,
+    loading_bond: Cell<bool>,
+    /// Key request received while the key was not known yet.
+    pending_key_request: Cell<Option<(u16, [u8; 8])>>,
+}
+
+impl<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType>
+    BleSecurity<'a, A, K, T>
+{
+    pub fn new(
+        link: &'a dyn SecureLink<'a>,
+        aes: &'a A,
+        rng: &'a dyn rng::Rng<'a>,
+        kv_store: &'a KVStore<'a, K, T>,
+        perms: StoragePermissions,
+        io_capability: IoCapability,
+        bondable: bool,
+        tx_buffer: &'static mut [u8; SMP_PDU_LEN],
+        aes_buffer: &'static mut [u8; AES128_BLOCK_SIZE],
+        key_buffer: &'static mut [u8; BOND_KEY_LEN],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        BleSecurity {
+            link,
+            aes,
+            rng,
+            kv_store,
+            perms,
+            io_capability,
+            bondable,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Disconnected),
+            tx_buffer: TakeCell::new(tx_buffer),
+            aes_buffer: TakeCell::new(aes_buffer),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            local: Cell::new(None),
+            peer: Cell::new(None),
+            pairing: MapCell::empty(),
+            bond: Cell::new(None),
+            loading_bond: Cell::new(false),
+            pending_key_request: Cell::new(None),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn SecurityClient) {
+        self.client.set(client);
+    }
+
+    /// Asks the central to pair, or to encrypt the link if it is bonded.
+    pub fn request_security(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Connected {
+            return Err(ErrorCode::OFF);
+        }
+        let mut auth_req = 0;
+        if self.bondable {
+            auth_req |= AUTH_REQ_BONDING;
+        }
+        if self.io_capability == IoCapability::DisplayOnly {
+            auth_req |= AUTH_REQ_MITM;
+        }
+        self.send(&[opcode::SECURITY_REQUEST, auth_req])
+    }
+
+    fn send(&self, pdu: &[u8]) -> Result<(), ErrorCode> {
+        let buffer = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer[..pdu.len()].copy_from_slice(pdu);
+        self.link
+            .send_smp(buffer, pdu.len())
+            .map_err(|(e, buffer)| {
+                self.tx_buffer.replace(buffer);
+                e
+            })
+    }
+
+    /// Sends an opcode followed by a 16 byte value.
+    fn send_value(&self, opcode: u8, value: &[u8; 16]) -> Result<(), ErrorCode> {
+        let mut pdu = [0; SMP_PDU_LEN];
+        pdu[0] = opcode;
+        pdu[1..].copy_from_slice(value);
+        self.send(&pdu)
+    }
+
+    /// Abandons the pairing in progress and tells the central why.
+    fn fail(&self, reason: u8) {
+        let _ = self.send(&[opcode::PAIRING_FAILED, reason]);
+        self.pairing_done(Err(ErrorCode::FAIL));
+    }
+
+    fn pairing_done(&self, result: Result<bool, ErrorCode>) {
+        self.pairing.take();
+        self.state.set(State::Connected);
+        self.client.map(|client| client.pairing_done(result));
+    }
+
+    fn pairing_request(&self, pdu: &[u8]) {
+        let mut preq = [0; 7];
+        preq.copy_from_slice(&pdu[..7]);
+        let max_key_size = preq[4];
+        if max_key_size < MIN_KEY_SIZE {
+            return self.fail(reason::ENCRYPTION_KEY_SIZE);
+        }
+        if max_key_size > MAX_KEY_SIZE {
+            return self.fail(reason::INVALID_PARAMETERS);
+        }
+        let bonding = self.bondable && (preq[3] & 0x03) == AUTH_REQ_BONDING;
+
+        let mut pairing = Pairing::new(preq);
+        pairing.key_size = max_key_size as usize;
+        pairing.bonding = bonding;
+        pairing.distribute_key = bonding && preq[6] & KEY_DIST_ENC_KEY != 0;
+        self.pairing.replace(pairing);
+        self.state.set(State::GettingRandom);
+        if self.rng.get().is_err() {
+            self.fail(reason::UNSPECIFIED_REASON);
+        }
+    }
+
+    /// Sends the Pairing Response once the random numbers are available.
+    fn respond(&self) {
+        let passkey = self.pairing.map(|pairing| {
+            let random = pairing.random;
+            pairing.srand.copy_from_slice(&random[0..16]);
+            pairing.bond.ltk.copy_from_slice(&random[16..32]);
+            pairing.bond.rand.copy_from_slice(&random[32..40]);
+            pairing.bond.ediv = u16::from_le_bytes([random[40], random[41]]);
+            mask_key(&mut pairing.bond.ltk, pairing.key_size);
+
+            let peer_io = pairing.preq[1];
+            let passkey_entry = self.io_capability == IoCapability::DisplayOnly
+                && (peer_io == IO_KEYBOARD_ONLY || peer_io == IO_KEYBOARD_DISPLAY);
+            let passkey = if passkey_entry {
+                let passkey = u32::from_le_bytes([random[42], random[43], random[44], random[45]])
+                    % 1_000_000;
+                pairing.tk[0..4].copy_from_slice(&passkey.to_le_bytes());
+                Some(passkey)
+            } else {
+                None
+            };
+            pairing.bond.authenticated = passkey_entry;
+
+            let mut auth_req = 0;
+            if pairing.bonding {
+                auth_req |= AUTH_REQ_BONDING;
+            }
+            if passkey_entry {
+                auth_req |= AUTH_REQ_MITM;
+            }
+            let resp_key_dist = if pairing.distribute_key {
+                KEY_DIST_ENC_KEY
+            } else {
+                0
+            };
+            pairing.pres = [
+                opcode::PAIRING_RESPONSE,
+                self.io_capability as u8,
+                0,
+                auth_req,
+                MAX_KEY_SIZE,
+                0,
+                resp_key_dist,
+            ];
+            passkey
+        });
+        let passkey = match passkey {
+            Some(passkey) => passkey,
+            None => return,
+        };
+        let pres = self.pairing.map_or([0; 7], |pairing| pairing.pres);
+
+        self.state.set(State::WaitingConfirm);
+        if self.send(&pres).is_err() {
+            return self.pairing_done(Err(ErrorCode::FAIL));
+        }
+        if let Some(passkey) = passkey {
+            self.client.map(|client| client.display_passkey(passkey));
+        }
+    }
+
+    /// Starts computing the confirm value function `c1` for the random
+    /// number `r`.
+    fn start_confirm(&self, r: [u8; 16]) -> Result<(), ErrorCode> {
+        let (local, peer) = match (self.local.get(), self.peer.get()) {
+            (Some(local), Some(peer)) => (local, peer),
+            _ => return Err(ErrorCode::OFF),
+        };
+        let (block, tk) = self
+            .pairing
+            .map(|pairing| {
+                let mut p1 = [0; 16];
+                p1[0] = peer.random as u8;
+                p1[1] = local.random as u8;
+                p1[2..9].copy_from_slice(&pairing.preq);
+                p1[9..16].copy_from_slice(&pairing.pres);
+                (xor(&r, &p1), pairing.tk)
+            })
+            .ok_or(ErrorCode::FAIL)?;
+        self.encrypt(&tk, &block)
+    }
+
+    /// Continues the confirm value function with the result of its first
+    /// encryption.
+    fn finish_confirm(&self, first: [u8; 16]) -> Result<(), ErrorCode> {
+        let (local, peer) = match (self.local.get(), self.peer.get()) {
+            (Some(local), Some(peer)) => (local, peer),
+            _ => return Err(ErrorCode::OFF),
+        };
+        let mut p2 = [0; 16];
+        p2[0..6].copy_from_slice(&local.address);
+        p2[6..12].copy_from_slice(&peer.address);
+        let tk = self.pairing.map_or([0; 16], |pairing| pairing.tk);
+        self.encrypt(&tk, &xor(&first, &p2))
+    }
+
+    /// Starts encrypting `block` with `key`. Both are in little endian byte
+    /// order, like all values of the Security Manager Protocol.
+    fn encrypt(&self, key: &[u8; 16], block: &[u8; 16]) -> Result<(), ErrorCode> {
+        let buffer = self.aes_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let mut key_be = *key;
+        key_be.reverse();
+        for (dest, src) in buffer.iter_mut().zip(block.iter().rev()) {
+            *dest = *src;
+        }
+        let result = self
+            .aes
+            .set_key(&key_be)
+            .and_then(|()| self.aes.set_mode_aes128ecb(true));
+        if let Err(e) = result {
+            self.aes_buffer.replace(buffer);
+            return Err(e);
+        }
+        self.aes.start_message();
+        match self.aes.crypt(None, buffer, 0, AES128_BLOCK_SIZE) {
+            None => Ok(()),
+            Some((result, _, buffer)) => {
+                self.aes_buffer.replace(buffer);
+                result.and(Err(ErrorCode::FAIL))
+            }
+        }
+    }
+
+    fn encryption_done(&self, output: [u8; 16]) {
+        let result = match self.state.get() {
+            State::ComputingConfirm(Step::First) => {
+                self.state.set(State::ComputingConfirm(Step::Second));
+                self.finish_confirm(output)
+            }
+            State::ComputingConfirm(Step::Second) => {
+                self.state.set(State::WaitingRandom);
+                self.send_value(opcode::PAIRING_CONFIRM, &output)
+            }
+            State::CheckingConfirm(Step::First) => {
+                self.state.set(State::CheckingConfirm(Step::Second));
+                self.finish_confirm(output)
+            }
+            State::CheckingConfirm(Step::Second) => {
+                let values = self
+                    .pairing
+                    .map(|pairing| (pairing.mconfirm, pairing.srand, pairing.mrand, pairing.tk));
+                let (mconfirm, srand, mrand, tk) = match values {
+                    Some(values) => values,
+                    None => return,
+                };
+                if output != mconfirm {
+                    return self.fail(reason::CONFIRM_VALUE_FAILED);
+                }
+                // STK = s1(TK, Srand, Mrand).
+                let mut r = [0; 16];
+                r[0..8].copy_from_slice(&mrand[0..8]);
+                r[8..16].copy_from_slice(&srand[0..8]);
+                self.state.set(State::ComputingStk);
+                self.send_value(opcode::PAIRING_RANDOM, &srand)
+                    .and_then(|()| self.encrypt(&tk, &r))
+            }
+            State::ComputingStk => {
+                self.pairing.map(|pairing| {
+                    pairing.stk = output;
+                    mask_key(&mut pairing.stk, pairing.key_size);
+                });
+                self.state.set(State::WaitingEncryption);
+                if let Some((ediv, rand)) = self.pending_key_request.take() {
+                    self.key_requested(ediv, rand);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if result.is_err() {
+            self.fail(reason::UNSPECIFIED_REASON);
+        }
+    }
+
+    /// Sends the long-term key and its identifier to the central.
+    fn distribute_keys(&self) {
+        let ltk = self.pairing.map_or([0; 16], |pairing| pairing.bond.ltk);
+        self.state.set(State::SendingLtk);
+        if self
+            .send_value(opcode::ENCRYPTION_INFORMATION, &ltk)
+            .is_err()
+        {
+            self.pairing_done(Err(ErrorCode::FAIL));
+        }
+    }
+
+    fn send_identification(&self) {
+        let bond = match self.pairing.map(|pairing| pairing.bond) {
+            Some(bond) => bond,
+            None => return,
+        };
+        let mut pdu = [0; 11];
+        pdu[0] = opcode::MASTER_IDENTIFICATION;
+        pdu[1..3].copy_from_slice(&bond.ediv.to_le_bytes());
+        pdu[3..11].copy_from_slice(&bond.rand);
+        self.state.set(State::SendingIdentification);
+        if self.send(&pdu).is_err() {
+            self.pairing_done(Err(ErrorCode::FAIL));
+        }
+    }
+
+    /// Stores the keys of a finished pairing if the central asked to bond.
+    fn store_bond(&self) {
+        let (bonding, bond) = match self.pairing.map(|pairing| (pairing.bonding, pairing.bond)) {
+            Some(values) => values,
+            None => return,
+        };
+        if !bonding || self.peer.get().is_none() {
+            return self.pairing_done(Ok(false));
+        }
+        self.state.set(State::StoringBond);
+        let result = match (self.key_buffer.take(), self.value_buffer.take()) {
+            (Some(key), Some(value)) => {
+                self.set_bond_key(key);
+                bond.encode(value);
+                self.bond.set(Some(bond));
+                self.kv_store
+                    .set(key, value, BOND_LEN, self.perms)
+                    .map_err(|(key, value, e)| {
+                        self.key_buffer.replace(key);
+                        self.value_buffer.replace(value);
+                        e.err().unwrap_or(ErrorCode::FAIL)
+                    })
+            }
+            (key, value) => {
+                key.map(|key| self.key_buffer.replace(key));
+                value.map(|value| self.value_buffer.replace(value));
+                Err(ErrorCode::BUSY)
+            }
+        };
+        if let Err(e) = result {
+            // The link is encrypted, only the bond is lost.
+            self.pairing_done(Err(e));
+        }
+    }
+
+    /// Loads the bond with the connected peer.
+    fn load_bond(&self) {
+        if let (Some(key), Some(value)) = (self.key_buffer.take(), self.value_buffer.take()) {
+            self.set_bond_key(key);
+            match self.kv_store.get(key, value, self.perms) {
+                Ok(()) => self.loading_bond.set(true),
+                Err((key, value, _)) => {
+                    self.key_buffer.replace(key);
+                    self.value_buffer.replace(value);
+                }
+            }
+        }
+    }
+
+    fn set_bond_key(&self, key: &mut [u8]) {
+        let peer = self.peer.get().unwrap_or(DeviceAddress {
+            random: false,
+            address: [0; 6],
+        });
+        key[..BOND_KEY_PREFIX.len()].copy_from_slice(BOND_KEY_PREFIX);
+        key[BOND_KEY_PREFIX.len()] = peer.random as u8;
+        key[BOND_KEY_PREFIX.len() + 1..BOND_KEY_LEN].copy_from_slice(&peer.address);
+    }
+}
+
+/// Zeroes the most significant bytes of a key beyond `key_size` bytes.
+fn mask_key(key: &mut [u8; 16], key_size: usize) {
+    key[key_size..].fill(0);
+}
+
+fn xor(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut out = [0; 16];
+    for i in 0..16 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+impl<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType> SecureLinkClient
+    for BleSecurity<'a, A, K, T>
+{
+    fn connected(&self, local: DeviceAddress, peer: DeviceAddress) {
+        self.local.set(Some(local));
+        self.peer.set(Some(peer));
+        self.bond.set(None);
+        self.pending_key_request.set(None);
+        self.pairing.take();
+        self.state.set(State::Connected);
+        self.load_bond();
+    }
+
+    fn disconnected(&self) {
+        self.local.set(None);
+        self.peer.set(None);
+        self.bond.set(None);
+        self.pending_key_request.set(None);
+        if self.pairing.take().is_some() {
+            self.client
+                .map(|client| client.pairing_done(Err(ErrorCode::CANCEL)));
+        }
+        self.state.set(State::Disconnected);
+    }
+
+    fn smp_received(&self, pdu: &[u8]) {
+        let state = self.state.get();
+        if state == State::Disconnected || pdu.is_empty() {
+            return;
+        }
+        match (pdu[0], state) {
+            (opcode::PAIRING_REQUEST, _) if pdu.len() >= 7 => {
+                if matches!(
+                    state,
+                    State::Connected | State::GettingRandom | State::WaitingConfirm
+                ) {
+                    self.pairing_request(pdu);
+                }
+            }
+            (opcode::PAIRING_CONFIRM, State::WaitingConfirm) if pdu.len() >= 17 => {
+                let srand = self.pairing.map_or([0; 16], |pairing| {
+                    pairing.mconfirm.copy_from_slice(&pdu[1..17]);
+                    pairing.srand
+                });
+                self.state.set(State::ComputingConfirm(Step::First));
+                if self.start_confirm(srand).is_err() {
+                    self.fail(reason::UNSPECIFIED_REASON);
+                }
+            }
+            (opcode::PAIRING_RANDOM, State::WaitingRandom) if pdu.len() >= 17 => {
+                let mut mrand = [0; 16];
+                mrand.copy_from_slice(&pdu[1..17]);
+                self.pairing.map(|pairing| pairing.mrand = mrand);
+                self.state.set(State::CheckingConfirm(Step::First));
+                if self.start_confirm(mrand).is_err() {
+                    self.fail(reason::UNSPECIFIED_REASON);
+                }
+            }
+            (opcode::PAIRING_FAILED, _) => {
+                if self.pairing.is_some() {
+                    self.pairing_done(Err(ErrorCode::FAIL));
+                }
+            }
+            (opcode::PAIRING_REQUEST, _)
+            | (opcode::PAIRING_CONFIRM, _)
+            | (opcode::PAIRING_RANDOM, _) => {
+                if self.pairing.is_some() {
+                    self.fail(reason::UNSPECIFIED_REASON);
+                } else {
+                    let _ = self.send(&[opcode::PAIRING_FAILED, reason::PAIRING_NOT_SUPPORTED]);
+                }
+            }
+            _ => {
+                let _ = self.send(&[opcode::PAIRING_FAILED, reason::COMMAND_NOT_SUPPORTED]);
+            }
+        }
+    }
+
+    fn smp_sent(&self, pdu: &'static mut [u8]) {
+        self.tx_buffer.replace(pdu);
+        match self.state.get() {
+            State::SendingLtk => self.send_identification(),
+            State::SendingIdentification => self.store_bond(),
+            _ => {}
+        }
+    }
+
+    fn key_requested(&self, ediv: u16, rand: [u8; 8]) {
+        let state = self.state.get();
+        if ediv == 0 && rand == [0; 8] && self.pairing.is_some() {
+            // The central encrypts the link with the STK of the pairing.
+            match state {
+                State::WaitingEncryption => {
+                    let stk = self.pairing.map(|pairing| pairing.stk);
+                    self.link.reply_key(stk);
+                }
+                State::ComputingStk => self.pending_key_request.set(Some((ediv, rand))),
+                _ => self.link.reply_key(None),
+            }
+            return;
+        }
+        match self.bond.get() {
+            Some(bond) if bond.ediv == ediv && bond.rand == rand => {
+                self.link.reply_key(Some(bond.ltk))
+            }
+            None if self.loading_bond.get() => self.pending_key_request.set(Some((ediv, rand))),
+            _ => self.link.reply_key(None),
+        }
+    }
+
+    fn encryption_changed(&self, encrypted: bool) {
+        if encrypted && self.state.get() == State::WaitingEncryption {
+            let distribute_key = self.pairing.map_or(false, |pairing| pairing.distribute_key);
+            if distribute_key {
+                self.distribute_keys();
+            } else {
+                self.pairing_done(Ok(false));
+            }
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType> rng::Client
+    for BleSecurity<'a, A, K, T>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if self.state.get() != State::GettingRandom {
+            return rng::Continue::Done;
+        }
+        if error.is_err() {
+            self.fail(reason::UNSPECIFIED_REASON);
+            return rng::Continue::Done;
+        }
+        let done = self.pairing.map_or(true, |pairing| {
+            while pairing.random_len < pairing.random.len() {
+                match randomness.next() {
+                    Some(word) => {
+                        let len = pairing.random_len;
+                        pairing.random[len..len + 4].copy_from_slice(&word.to_le_bytes());
+                        pairing.random_len += 4;
+                    }
+                    None => return false,
+                }
+            }
+            true
+        });
+        if done {
+            self.respond();
+            rng::Continue::Done
+        } else {
+            rng::Continue::More
+        }
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType>
+    symmetric_encryption::Client<'a> for BleSecurity<'a, A, K, T>
+{
+    fn crypt_done(&'a self, _source: Option<&'static mut [u8]>, dest: &'static mut [u8]) {
+        let mut output = [0; 16];
+        for (out, byte) in output.iter_mut().zip(dest.iter().rev()) {
+            *out = *byte;
+        }
+        self.aes_buffer.replace(dest);
+        self.encryption_done(output);
+    }
+}
+
+impl<'a, A: AES128<'a> + AES128ECB, K: KVSystem<'a, K = T>, T: 'static + KeyType>
+    kv_system::StoreClient<T> for BleSecurity<'a, A, K, T>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        let bond = if result.is_ok() {
+            Bond::decode(value)
+        } else {
+            None
+        };
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        self.loading_bond.set(false);
+        if self.state.get() == State::Disconnected {
+            return;
+        }
+        self.bond.set(bond);
+        if let Some((ediv, rand)) = self.pending_key_request.take() {
+            self.key_requested(ediv, rand);
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() == State::StoringBond {
+            self.pairing_done(result.map(|()| true));
+        }
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: &'static mut [u8]) {
+        self.key_buffer.replace(key);
+    }
+}
@@ -0,0 +1,330 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Block devices on top of SD cards and flash.
+//!
+//! - `SdCardBlockStorage` uses the 512 byte blocks of an SD card, accessed
+//!   over SPI (`sdcard::SDCard`) or a host controller (`sdmmc::SdMmcCard`).
+//!   It has no blocks while no card is mounted.
+//! - `FlashBlockStorage` splits a range of pages of a flash into 512 byte
+//!   blocks. A block is written by reading its page, changing the block and
+//!   writing the page back. As that wears the flash quickly, it should be
+//!   used on top of `wear_leveling::WearLeveling`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! // 64 pages of leveled flash as a block device.
+//! let blocks = components::block_storage::FlashBlockStorageComponent::new(
+//!     wear_leveling,
+//!     0,
+//!     wear_leveling.logical_pages(),
+//! )
+//! .finalize(components::flash_block_storage_component_static!(
+//!     capsules_extra::wear_leveling::WearLeveling<'static, FlashUser, 64>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::block_storage::{self, BlockStorage};
+use kernel::hil::flash::{self, Flash};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::sdcard::{SDCardClient, SDCardDevice};
+
+/// The size of the blocks of both block devices.
+pub const BLOCK_SIZE: usize = 512;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Idle,
+    Read(u32),
+    Write(u32),
+}
+
+pub struct SdCardBlockStorage<'a, S: SDCardDevice<'a>> {
+    card: &'a S,
+    /// The number of blocks of the mounted card, 0 while none is mounted.
+    blocks: Cell<u32>,
+    operation: Cell<Operation>,
+    client: OptionalCell<&'a dyn block_storage::Client>,
+}
+
+impl<'a, S: SDCardDevice<'a>> SdCardBlockStorage<'a, S> {
+    pub fn new(card: &'a S) -> Self {
+        Self {
+            card,
+            blocks: Cell::new(0),
+            operation: Cell::new(Operation::Idle),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Mount the card if one is inserted. Cards inserted later are mounted
+    /// by the SD card driver.
+    pub fn start(&self) {
+        if self.card.is_installed() && !self.card.is_initialized() {
+            let _ = self.card.initialize();
+        }
+    }
+
+    fn check(&self, buffer: &[u8], block: u32) -> Result<(), ErrorCode> {
+        if self.operation.get() != Operation::Idle {
+            Err(ErrorCode::BUSY)
+        } else if self.blocks.get() == 0 {
+            Err(ErrorCode::OFF)
+        } else if block >= self.blocks.get() {
+            Err(ErrorCode::INVAL)
+        } else if buffer.len() < BLOCK_SIZE {
+            Err(ErrorCode::SIZE)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        match self.operation.replace(Operation::Idle) {
+            Operation::Read(_) => self
+                .client
+                .map(move |client| client.read_done(buffer, result)),
+            Operation::Write(_) => self
+                .client
+                .map(move |client| client.write_done(buffer, result)),
+            Operation::Idle => None,
+        };
+    }
+}
+
+impl<'a, S: SDCardDevice<'a>> BlockStorage<'a> for SdCardBlockStorage<'a, S> {
+    fn set_client(&self, client: &'a dyn block_storage::Client) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u32 {
+        self.blocks.get()
+    }
+
+    fn read_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(err) = self.check(buffer, block) {
+            return Err((err, buffer));
+        }
+        self.operation.set(Operation::Read(block));
+        self.card.read_blocks(buffer, block, 1).map_err(|err| {
+            self.operation.set(Operation::Idle);
+            err
+        })
+    }
+
+    fn write_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(err) = self.check(buffer, block) {
+            return Err((err, buffer));
+        }
+        self.operation.set(Operation::Write(block));
+        self.card.write_blocks(buffer, block, 1).map_err(|err| {
+            self.operation.set(Operation::Idle);
+            err
+        })
+    }
+}
+
+impl<'a, S: SDCardDevice<'a>> SDCardClient for SdCardBlockStorage<'a, S> {
+    fn card_detection_changed(&self, installed: bool) {
+        if !installed {
+            self.blocks.set(0);
+        }
+    }
+
+    fn init_done(&self, _block_size: u32, total_size: u64) {
+        self.blocks.set((total_size / BLOCK_SIZE as u64) as u32);
+    }
+
+    fn read_done(&self, data: &'static mut [u8], _len: usize) {
+        self.done(data, Ok(()));
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8]) {
+        self.done(buffer, Ok(()));
+    }
+
+    fn error(&self, _error: u32, buffer: Option<&'static mut [u8]>) {
+        buffer.map(|buffer| self.done(buffer, Err(ErrorCode::FAIL)));
+    }
+
+    fn unmounted(&self) {
+        self.blocks.set(0);
+    }
+}
+
+pub struct FlashBlockStorage<'a, F: Flash + 'static> {
+    flash: &'a F,
+    first_page: usize,
+    pages: usize,
+    page_size: usize,
+    page: TakeCell<'static, F::Page>,
+    /// The buffer of the client during an operation.
+    buffer: TakeCell<'static, [u8]>,
+    operation: Cell<Operation>,
+    client: OptionalCell<&'a dyn block_storage::Client>,
+}
+
+impl<'a, F: Flash + 'static> FlashBlockStorage<'a, F> {
+    /// Use `pages` pages of `flash` starting at `first_page`. The page size
+    /// must be a multiple of `BLOCK_SIZE`.
+    pub fn new(flash: &'a F, first_page: usize, pages: usize, page: &'static mut F::Page) -> Self {
+        Self {
+            flash,
+            first_page,
+            pages,
+            page_size: page.as_mut().len(),
+            page: TakeCell::new(page),
+            buffer: TakeCell::empty(),
+            operation: Cell::new(Operation::Idle),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// The page holding `block`, and the offset of the block in it.
+    fn locate(&self, block: u32) -> (usize, usize) {
+        let address = block as usize * BLOCK_SIZE;
+        (
+            self.first_page + address / self.page_size,
+            address % self.page_size,
+        )
+    }
+
+    fn start(
+        &self,
+        operation: Operation,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.operation.get() != Operation::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        } else if block >= self.block_count() {
+            return Err((ErrorCode::INVAL, buffer));
+        } else if buffer.len() < BLOCK_SIZE {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        let page = match self.page.take() {
+            Some(page) => page,
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+
+        // Both reads and writes start by reading the page of the block.
+        match self.flash.read_page(self.locate(block).0, page) {
+            Ok(()) => {
+                self.operation.set(operation);
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err((err, page)) => {
+                self.page.replace(page);
+                Err((err, buffer))
+            }
+        }
+    }
+
+    fn done(&self, result: Result<(), ErrorCode>) {
+        let operation = self.operation.replace(Operation::Idle);
+        self.buffer.take().map(|buffer| match operation {
+            Operation::Write(_) => self
+                .client
+                .map(move |client| client.write_done(buffer, result)),
+            _ => self
+                .client
+                .map(move |client| client.read_done(buffer, result)),
+        });
+    }
+}
+
+impl<'a, F: Flash + 'static> BlockStorage<'a> for FlashBlockStorage<'a, F> {
+    fn set_client(&self, client: &'a dyn block_storage::Client) {
+        self.client.set(client);
+    }
+
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn block_count(&self) -> u32 {
+        (self.pages * (self.page_size / BLOCK_SIZE)) as u32
+    }
+
+    fn read_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Operation::Read(block), buffer, block)
+    }
+
+    fn write_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start(Operation::Write(block), buffer, block)
+    }
+}
+
+impl<'a, F: Flash + 'static> flash::Client<F> for FlashBlockStorage<'a, F> {
+    fn read_complete(&self, page: &'static mut F::Page, error: flash::Error) {
+        if error != flash::Error::CommandComplete {
+            self.page.replace(page);
+            self.done(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        match self.operation.get() {
+            Operation::Read(block) => {
+                let (_, offset) = self.locate(block);
+                self.buffer.map(|buffer| {
+                    buffer[..BLOCK_SIZE]
+                        .copy_from_slice(&page.as_mut()[offset..offset + BLOCK_SIZE])
+                });
+                self.page.replace(page);
+                self.done(Ok(()));
+            }
+            Operation::Write(block) => {
+                let (page_number, offset) = self.locate(block);
+                self.buffer.map(|buffer| {
+                    page.as_mut()[offset..offset + BLOCK_SIZE]
+                        .copy_from_slice(&buffer[..BLOCK_SIZE])
+                });
+                if let Err((_, page)) = self.flash.write_page(page_number, page) {
+                    self.page.replace(page);
+                    self.done(Err(ErrorCode::FAIL));
+                }
+            }
+            Operation::Idle => {
+                self.page.replace(page);
+            }
+        }
+    }
+
+    fn write_complete(&self, page: &'static mut F::Page, error: flash::Error) {
+        self.page.replace(page);
+        self.done(match error {
+            flash::Error::CommandComplete => Ok(()),
+            _ => Err(ErrorCode::FAIL),
+        });
+    }
+
+    fn erase_complete(&self, _error: flash::Error) {}
+}
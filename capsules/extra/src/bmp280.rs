@@ -14,8 +14,9 @@ use core::cell::Cell;
 use kernel::debug;
 use kernel::hil;
 use kernel::hil::i2c;
-use kernel::hil::time::{Alarm, ConvertTicks};
+use kernel::hil::time::Alarm;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::time_conversion::{CheckedConvertTicks, Rounding};
 use kernel::ErrorCode;
 
 pub static BASE_ADDR: u8 = 0x76;
@@ -293,7 +294,7 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> Bmp280<'a, A, I> {
     fn arm_alarm(&self) {
         // Datasheet says temp oversampling=1 makes a reading typically take 5.5ms.
         // (Maximally 6.4ms).
-        let delay = self.alarm.ticks_from_us(6400);
+        let delay = self.alarm.saturating_ticks_from_us(6400, Rounding::Up);
         self.alarm.set_alarm(self.alarm.now(), delay);
     }
 
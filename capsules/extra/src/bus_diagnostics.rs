@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Diagnostics of the hardware errors on buses.
+//!
+//! Exposes the error counters of the buses of a board, as collected by the
+//! bus muxes and the CAN capsule, to processes and on the process console
+//! with the `buserrors` command. `buserrors reset` sets all counters to 0.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let buses = static_init!(
+//!     [&'static dyn capsules_core::bus_errors::BusErrors; 2],
+//!     [mux_i2c, uart_mux]
+//! );
+//! let bus_diagnostics = static_init!(
+//!     capsules_extra::bus_diagnostics::BusDiagnostics<'static>,
+//!     capsules_extra::bus_diagnostics::BusDiagnostics::new(buses)
+//! );
+//! ```
+
+use core::fmt;
+
+use capsules_core::bus_errors::BusErrors;
+use capsules_core::process_console::ConsoleCommand;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::BusDiagnostics as usize;
+
+pub struct BusDiagnostics<'a> {
+    buses: &'a [&'a dyn BusErrors],
+}
+
+impl<'a> BusDiagnostics<'a> {
+    pub fn new(buses: &'a [&'a dyn BusErrors]) -> Self {
+        BusDiagnostics { buses }
+    }
+}
+
+impl<'a> SyscallDriver for BusDiagnostics<'a> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the number of buses.
+    /// - `2`: Return the kind of bus `data1` (0 for I2C, 1 for SPI, 2 for
+    ///   UART, 3 for CAN) and its number of counters.
+    /// - `3`: Return counter `data2` of bus `data1`.
+    /// - `4`: Set the counters of bus `data1` to 0.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.buses.len() as u32),
+            2..=4 => {
+                let bus = match self.buses.get(data1) {
+                    Some(bus) => bus,
+                    None => return CommandReturn::failure(ErrorCode::INVAL),
+                };
+                match command_num {
+                    2 => CommandReturn::success_u32_u32(
+                        bus.bus() as u32,
+                        bus.counter_names().len() as u32,
+                    ),
+                    3 if data2 < bus.counter_names().len() => {
+                        CommandReturn::success_u32(bus.counter(data2))
+                    }
+                    3 => CommandReturn::failure(ErrorCode::INVAL),
+                    _ => {
+                        bus.reset_counters();
+                        CommandReturn::success()
+                    }
+                }
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
+
+impl<'a> ConsoleCommand for BusDiagnostics<'a> {
+    fn name(&self) -> &'static str {
+        "buserrors"
+    }
+
+    fn execute(&self, args: &str, out: &mut dyn fmt::Write) {
+        if args.trim() == "reset" {
+            for bus in self.buses {
+                bus.reset_counters();
+            }
+            let _ = write!(out, "Bus error counters reset\r\n");
+            return;
+        }
+        for (i, bus) in self.buses.iter().enumerate() {
+            let _ = write!(out, "{} {}:", bus.bus().name(), i);
+            for (index, name) in bus.counter_names().iter().enumerate() {
+                let _ = write!(out, " {}={}", name, bus.counter(index));
+            }
+            let _ = write!(out, "\r\n");
+        }
+    }
+}
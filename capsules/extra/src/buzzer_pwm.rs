@@ -54,8 +54,8 @@ use core::cmp;
 
 use kernel::hil;
 use kernel::hil::buzzer::BuzzerClient;
-use kernel::hil::time::Frequency;
 use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::time_conversion::{CheckedConvertTicks, Rounding};
 use kernel::ErrorCode;
 
 /// Standard max buzz time.
@@ -96,9 +96,11 @@ impl<'a, A: hil::time::Alarm<'a>, P: hil::pwm::PwmPin> hil::buzzer::Buzzer<'a>
             .start(frequency_hz, self.pwm_pin.get_maximum_duty_cycle() / 2)?;
 
         // Set an alarm for the given duration.
-        let interval = (duration_ms_cmp as u32) * <A::Frequency>::frequency() / 1000;
-        self.alarm
-            .set_alarm(self.alarm.now(), A::Ticks::from(interval));
+        let interval = self.alarm.saturating_ticks_from_ms(
+            u32::try_from(duration_ms_cmp).unwrap_or(u32::MAX),
+            Rounding::Nearest,
+        );
+        self.alarm.set_alarm(self.alarm.now(), interval);
         Ok(())
     }
 
@@ -59,6 +59,7 @@ use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 use kernel::ProcessId;
 
+use capsules_core::bus_errors::{Bus, BusErrors, ErrorCounters};
 use capsules_core::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Can as usize;
 pub const BYTE4_MASK: usize = 0xff000000;
@@ -111,6 +112,58 @@ pub struct CanCapsule<'a, Can: can::Can> {
     // Variable used to store the current state of the CAN peripheral
     // during an `enable` or `disable` command.
     peripheral_state: OptionalCell<can::State>,
+
+    // Counters of the errors reported by the peripheral.
+    errors: ErrorCounters<8>,
+}
+
+/// Names of the error counters of `CanCapsule`.
+const CAN_ERRORS: [&str; 8] = [
+    "arbitration_lost",
+    "ack",
+    "stuff",
+    "form",
+    "bit",
+    "crc",
+    "error_passive",
+    "bus_off",
+];
+
+/// The operation mode selected by the argument of command 2.
+fn operation_mode(number: usize) -> Option<can::OperationMode> {
+    match number {
+        0 => Some(can::OperationMode::Loopback),
+        1 => Some(can::OperationMode::Monitoring),
+        2 => Some(can::OperationMode::Freeze),
+        3 => Some(can::OperationMode::Normal),
+        4 => Some(can::OperationMode::InternalLoopback),
+        _ => None,
+    }
+}
+
+fn operation_mode_number(mode: can::OperationMode) -> usize {
+    match mode {
+        can::OperationMode::Loopback => 0,
+        can::OperationMode::Monitoring => 1,
+        can::OperationMode::Freeze => 2,
+        can::OperationMode::Normal => 3,
+        can::OperationMode::InternalLoopback => 4,
+    }
+}
+
+/// The error counter that counts `error`.
+fn error_counter(error: can::Error) -> Option<usize> {
+    match error {
+        can::Error::ArbitrationLost => Some(0),
+        can::Error::Ack | can::Error::Transmission => Some(1),
+        can::Error::Stuff => Some(2),
+        can::Error::Form => Some(3),
+        can::Error::BitRecessive | can::Error::BitDominant => Some(4),
+        can::Error::Crc => Some(5),
+        can::Error::Warning | can::Error::Passive => Some(6),
+        can::Error::BusOff => Some(7),
+        can::Error::SetBySoftware => None,
+    }
 }
 
 pub struct App {
@@ -146,6 +199,13 @@ impl<'a, Can: can::Can> CanCapsule<'a, Can> {
             processes: grant,
             peripheral_state: OptionalCell::empty(),
             processid: OptionalCell::empty(),
+            errors: ErrorCounters::new(),
+        }
+    }
+
+    fn count_error(&self, error: can::Error) {
+        if let Some(index) = error_counter(error) {
+            self.errors.increment(index);
         }
     }
 
@@ -238,17 +298,13 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
             },
 
             // Set the operation mode (Loopback, Monitoring, etc)
-            2 => {
-                match self.can.set_operation_mode(match arg1 {
-                    0 => can::OperationMode::Loopback,
-                    1 => can::OperationMode::Monitoring,
-                    2 => can::OperationMode::Freeze,
-                    _ => can::OperationMode::Normal,
-                }) {
+            2 => match operation_mode(arg1) {
+                Some(mode) => match self.can.set_operation_mode(mode) {
                     Ok(_) => CommandReturn::success(),
                     Err(err) => CommandReturn::failure(err),
-                }
-            }
+                },
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
 
             // Enable the peripheral
             3 => match self.can.enable() {
@@ -346,6 +402,12 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
                 }
             }
 
+            // Get the operation mode
+            10 => match self.can.get_operation_mode() {
+                Ok(mode) => CommandReturn::success_u32(operation_mode_number(mode) as u32),
+                Err(err) => CommandReturn::failure(err),
+            },
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -355,10 +417,31 @@ impl<'a, Can: can::Can> SyscallDriver for CanCapsule<'a, Can> {
     }
 }
 
+impl<'a, Can: can::Can> BusErrors for CanCapsule<'a, Can> {
+    fn bus(&self) -> Bus {
+        Bus::Can
+    }
+
+    fn counter_names(&self) -> &'static [&'static str] {
+        &CAN_ERRORS
+    }
+
+    fn counter(&self, index: usize) -> u32 {
+        self.errors.get(index)
+    }
+
+    fn reset_counters(&self) {
+        self.errors.reset();
+    }
+}
+
 impl<'a, Can: can::Can> can::ControllerClient for CanCapsule<'a, Can> {
     // This callback must be called after an `enable` or `disable` command was sent.
     // It stores the new state of the peripheral.
     fn state_changed(&self, state: can::State) {
+        if let can::State::Error(error) = state {
+            self.count_error(error);
+        }
         self.peripheral_state.replace(state);
     }
 
@@ -434,6 +517,7 @@ impl<'a, Can: can::Can> can::TransmitClient<{ can::STANDARD_CAN_PACKET_SIZE }>
         match status {
             Ok(()) => self.schedule_callback(up_calls::UPCALL_MESSAGE_SENT, (0, 0, 0)),
             Err(err) => {
+                self.count_error(err);
                 self.schedule_callback(
                     up_calls::UPCALL_TRANSMISSION_ERROR,
                     (error_upcalls::ERROR_TX, err as usize, 0),
@@ -531,6 +615,7 @@ impl<'a, Can: can::Can> can::ReceiveClient<{ can::STANDARD_CAN_PACKET_SIZE }>
                 }
             }
             Err(err) => {
+                self.count_error(err);
                 let kernel_err: ErrorCode = err.into();
                 self.schedule_callback(
                     up_calls::UPCALL_TRANSMISSION_ERROR,
@@ -0,0 +1,526 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! CMSIS-DAP debug probe over USB HID.
+//!
+//! Turns the board into a debug probe for another board: debuggers like
+//! OpenOCD and pyOCD send CMSIS-DAP commands in 64 byte HID reports, which
+//! this capsule runs on an SWD probe, e.g. `SwdBitbang`, and answers in the
+//! next report.
+//!
+//! The probe implements version 1 of the protocol, with the SWD commands.
+//! JTAG, the SWO trace commands and atomic command queues are not supported,
+//! and transfers are not timestamped. `DAP_SWJ_Pins` only drives the reset
+//! line and does not wait.
+//!
+//! Debuggers find CMSIS-DAP probes by their product string, which must
+//! contain "CMSIS-DAP". The HID device is created with the descriptors of
+//! this module:
+//!
+//! ```rust,ignore
+//! static STRINGS: &'static [&str; 3] = &["Tock", "Tock CMSIS-DAP", "0001"];
+//!
+//! let hid = static_init!(
+//!     capsules_extra::usb::ctap::CtapHid<'static, Usb>,
+//!     capsules_extra::usb::ctap::CtapHid::new_with_report(
+//!         usb,
+//!         0x1209,
+//!         0x0001,
+//!         STRINGS,
+//!         &capsules_extra::cmsis_dap::HID_DESCRIPTOR,
+//!         &capsules_extra::cmsis_dap::REPORT,
+//!     )
+//! );
+//! ```
+//!
+//! See also `components::cmsis_dap`.
+
+use core::cell::Cell;
+
+use kernel::hil::swd::{self, Configuration, Swd, TransferError};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::usb_hid;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+use crate::usb::descriptors::{
+    DescriptorType, HIDCountryCode, HIDDescriptor, HIDSubordinateDescriptor, ReportDescriptor,
+};
+
+/// The HID report descriptor of a CMSIS-DAP probe: 64 byte vendor-defined
+/// input and output reports.
+static REPORT_DESCRIPTOR: &'static [u8] = &[
+    0x06, 0x00, 0xFF, // HID_UsagePage ( Vendor Defined 0xFF00 ),
+    0x09, 0x01, // HID_Usage ( 0x01 ),
+    0xA1, 0x01, // HID_Collection ( HID_Application ),
+    0x15, 0x00, // HID_LogicalMin ( 0 ),
+    0x26, 0xFF, 0x00, // HID_LogicalMaxS ( 0xff ),
+    0x75, 0x08, // HID_ReportSize ( 8 ),
+    0x95, 0x40, // HID_ReportCount ( 64 ),
+    0x09, 0x01, // HID_Usage ( 0x01 ),
+    0x81, 0x02, // HID_Input ( HID_Data | HID_Absolute | HID_Variable ),
+    0x95, 0x40, // HID_ReportCount ( 64 ),
+    0x09, 0x01, // HID_Usage ( 0x01 ),
+    0x91, 0x02, // HID_Output ( HID_Data | HID_Absolute | HID_Variable ),
+    0xC0, // HID_EndCollection
+];
+
+pub static REPORT: ReportDescriptor<'static> = ReportDescriptor {
+    desc: REPORT_DESCRIPTOR,
+};
+
+static SUB_HID_DESCRIPTOR: &'static [HIDSubordinateDescriptor] = &[HIDSubordinateDescriptor {
+    typ: DescriptorType::Report,
+    len: REPORT_DESCRIPTOR.len() as u16,
+}];
+
+pub static HID_DESCRIPTOR: HIDDescriptor<'static> = HIDDescriptor {
+    hid_class: 0x0111,
+    country_code: HIDCountryCode::NotSupported,
+    sub_descriptors: SUB_HID_DESCRIPTOR,
+};
+
+/// Ids of the commands.
+mod command {
+    pub const INFO: u8 = 0x00;
+    pub const HOST_STATUS: u8 = 0x01;
+    pub const CONNECT: u8 = 0x02;
+    pub const DISCONNECT: u8 = 0x03;
+    pub const TRANSFER_CONFIGURE: u8 = 0x04;
+    pub const TRANSFER: u8 = 0x05;
+    pub const TRANSFER_BLOCK: u8 = 0x06;
+    pub const TRANSFER_ABORT: u8 = 0x07;
+    pub const WRITE_ABORT: u8 = 0x08;
+    pub const DELAY: u8 = 0x09;
+    pub const RESET_TARGET: u8 = 0x0A;
+    pub const SWJ_PINS: u8 = 0x10;
+    pub const SWJ_CLOCK: u8 = 0x11;
+    pub const SWJ_SEQUENCE: u8 = 0x12;
+    pub const SWD_CONFIGURE: u8 = 0x13;
+    pub const INVALID: u8 = 0xFF;
+}
+
+const DAP_OK: u8 = 0x00;
+const DAP_ERROR: u8 = 0xFF;
+
+/// Version of the protocol that is implemented.
+const PROTOCOL_VERSION: &str = "1.2.0";
+/// Port of `DAP_Connect` for SWD.
+const PORT_SWD: u8 = 1;
+/// Bit of `DAP_SWJ_Pins` for the reset line.
+const PIN_RESET: u8 = 1 << 7;
+
+/// Bits of the requests of `DAP_Transfer`, above the `swd::REQUEST_*` bits.
+const TRANSFER_MATCH_VALUE: u8 = 1 << 4;
+const TRANSFER_MATCH_MASK: u8 = 1 << 5;
+
+/// Responses to the requests of `DAP_Transfer`.
+const TRANSFER_OK: u8 = 0x01;
+const TRANSFER_WAIT: u8 = 0x02;
+const TRANSFER_FAULT: u8 = 0x04;
+const TRANSFER_NO_ACK: u8 = 0x07;
+const TRANSFER_ERROR: u8 = 0x08;
+const TRANSFER_MISMATCH: u8 = 0x10;
+
+/// The RDBUFF register of the debug port, which holds the result of the
+/// last access port read.
+const DP_RDBUFF: u8 = 0x0C;
+/// The ABORT register of the debug port.
+const DP_ABORT: u8 = 0x00;
+
+const REPORT_LEN: usize = 64;
+
+fn u16_at(buffer: &[u8], at: usize) -> Option<u16> {
+    buffer
+        .get(at..at + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(buffer: &[u8], at: usize) -> Option<u32> {
+    buffer
+        .get(at..at + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn transfer_response(result: Result<(), TransferError>) -> u8 {
+    match result {
+        Ok(()) => TRANSFER_OK,
+        Err(TransferError::Wait) => TRANSFER_WAIT,
+        Err(TransferError::Fault) => TRANSFER_FAULT,
+        Err(TransferError::NoAck) => TRANSFER_NO_ACK,
+        Err(TransferError::Parity) => TRANSFER_ERROR,
+    }
+}
+
+/// What to do with the response to a command.
+enum Reply {
+    /// Send the response.
+    Now,
+    /// Send the response after the given number of microseconds.
+    After(u32),
+    /// The command has no response.
+    None,
+}
+
+pub struct CmsisDap<'a, S: Swd, U: usb_hid::UsbHid<'a, [u8; 64]>, A: time::Alarm<'a>> {
+    swd: &'a S,
+    usb: &'a U,
+    alarm: &'a A,
+    /// Manufacturer, product and serial number, as in the USB descriptors.
+    strings: &'static [&'static str; 3],
+    send_buffer: TakeCell<'static, [u8; 64]>,
+    recv_buffer: TakeCell<'static, [u8; 64]>,
+    /// The response waiting for the alarm of `DAP_Delay`.
+    delayed: TakeCell<'static, [u8; 64]>,
+    configuration: Cell<Configuration>,
+    wait_retry: Cell<u16>,
+    match_retry: Cell<u16>,
+    match_mask: Cell<u32>,
+}
+
+impl<'a, S: Swd, U: usb_hid::UsbHid<'a, [u8; 64]>, A: time::Alarm<'a>> CmsisDap<'a, S, U, A> {
+    pub fn new(
+        swd: &'a S,
+        usb: &'a U,
+        alarm: &'a A,
+        strings: &'static [&'static str; 3],
+        send_buffer: &'static mut [u8; 64],
+        recv_buffer: &'static mut [u8; 64],
+    ) -> Self {
+        CmsisDap {
+            swd,
+            usb,
+            alarm,
+            strings,
+            send_buffer: TakeCell::new(send_buffer),
+            recv_buffer: TakeCell::new(recv_buffer),
+            delayed: TakeCell::empty(),
+            configuration: Cell::new(Configuration::default()),
+            wait_retry: Cell::new(100),
+            match_retry: Cell::new(0),
+            match_mask: Cell::new(u32::MAX),
+        }
+    }
+
+    /// Start receiving commands.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.receive()
+    }
+
+    fn receive(&self) -> Result<(), ErrorCode> {
+        self.recv_buffer
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |buffer| {
+                self.usb.receive_buffer(buffer).map_err(|(error, buffer)| {
+                    self.recv_buffer.replace(buffer);
+                    error
+                })
+            })
+    }
+
+    fn send(&self, buffer: &'static mut [u8; 64]) {
+        if let Err((_, buffer)) = self.usb.send_buffer(buffer) {
+            self.send_buffer.replace(buffer);
+            let _ = self.receive();
+        }
+    }
+
+    /// Run a transfer, and repeat it while the target answers with `Wait`.
+    fn transfer(&self, request: u8, data: &mut u32) -> Result<(), TransferError> {
+        let mut result = self.swd.transfer(request, data);
+        for _ in 0..self.wait_retry.get() {
+            if result != Err(TransferError::Wait) {
+                break;
+            }
+            result = self.swd.transfer(request, data);
+        }
+        result
+    }
+
+    /// Read a register. Access port reads are posted, so their value is read
+    /// from RDBUFF.
+    fn read(&self, request: u8) -> Result<u32, TransferError> {
+        let mut value = 0;
+        self.transfer(request | swd::REQUEST_READ, &mut value)?;
+        if request & swd::REQUEST_AP != 0 {
+            self.transfer(DP_RDBUFF | swd::REQUEST_READ, &mut value)?;
+        }
+        Ok(value)
+    }
+
+    /// Read a register until its value masked with the match mask equals
+    /// `expected`. Returns the transfer response.
+    fn read_match(&self, request: u8, expected: u32) -> u8 {
+        let mask = self.match_mask.get();
+        for _ in 0..=self.match_retry.get() {
+            match self.read(request) {
+                Ok(value) if value & mask == expected => return TRANSFER_OK,
+                Ok(_) => {}
+                Err(error) => return transfer_response(Err(error)),
+            }
+        }
+        TRANSFER_OK | TRANSFER_MISMATCH
+    }
+
+    fn info(&self, id: u8, response: &mut [u8; 64]) {
+        let string = |string: &str, response: &mut [u8; 64]| {
+            // Strings are sent with their terminating null.
+            let len = string.len().min(REPORT_LEN - 3);
+            response[2..2 + len].copy_from_slice(&string.as_bytes()[..len]);
+            response[2 + len] = 0;
+            response[1] = len as u8 + 1;
+        };
+        response[1] = 0;
+        match id {
+            0x01 => string(self.strings[0], response),
+            0x02 => string(self.strings[1], response),
+            0x03 => string(self.strings[2], response),
+            0x04 => string(PROTOCOL_VERSION, response),
+            // Capabilities: SWD
+            0xF0 => {
+                response[1] = 1;
+                response[2] = 0x01;
+            }
+            // Packet count
+            0xFE => {
+                response[1] = 1;
+                response[2] = 1;
+            }
+            // Packet size
+            0xFF => {
+                response[1] = 2;
+                response[2..4].copy_from_slice(&(REPORT_LEN as u16).to_le_bytes());
+            }
+            _ => {}
+        }
+    }
+
+    fn transfer_command(&self, request: &[u8; 64], response: &mut [u8; 64]) {
+        let count = request[2] as usize;
+        let mut at = 3;
+        let mut out = 3;
+        let mut done = 0;
+        let mut status = 0;
+        while done < count {
+            let transfer = match request.get(at) {
+                Some(transfer) => *transfer,
+                None => break,
+            };
+            at += 1;
+            status = if transfer & swd::REQUEST_READ != 0 {
+                if transfer & TRANSFER_MATCH_VALUE != 0 {
+                    let expected = match u32_at(request, at) {
+                        Some(expected) => expected,
+                        None => break,
+                    };
+                    at += 4;
+                    self.read_match(transfer, expected)
+                } else if out + 4 > REPORT_LEN {
+                    break;
+                } else {
+                    let result = self.read(transfer).map(|value| {
+                        response[out..out + 4].copy_from_slice(&value.to_le_bytes());
+                        out += 4;
+                    });
+                    transfer_response(result)
+                }
+            } else {
+                let mut value = match u32_at(request, at) {
+                    Some(value) => value,
+                    None => break,
+                };
+                at += 4;
+                if transfer & TRANSFER_MATCH_MASK != 0 {
+                    self.match_mask.set(value);
+                    TRANSFER_OK
+                } else {
+                    transfer_response(self.transfer(transfer, &mut value))
+                }
+            };
+            if status != TRANSFER_OK {
+                break;
+            }
+            done += 1;
+        }
+        response[1] = done as u8;
+        response[2] = status;
+    }
+
+    fn transfer_block(&self, request: &[u8; 64], response: &mut [u8; 64]) {
+        let count = u16_at(request, 2).unwrap_or(0) as usize;
+        let transfer = request[4];
+        let mut at = 5;
+        let mut out = 4;
+        let mut done = 0;
+        let mut status = 0;
+        while done < count {
+            let result = if transfer & swd::REQUEST_READ != 0 {
+                if out + 4 > REPORT_LEN {
+                    break;
+                }
+                self.read(transfer).map(|value| {
+                    response[out..out + 4].copy_from_slice(&value.to_le_bytes());
+                    out += 4;
+                })
+            } else {
+                let mut value = match u32_at(request, at) {
+                    Some(value) => value,
+                    None => break,
+                };
+                at += 4;
+                self.transfer(transfer, &mut value)
+            };
+            status = transfer_response(result);
+            if result.is_err() {
+                break;
+            }
+            done += 1;
+        }
+        response[1..3].copy_from_slice(&(done as u16).to_le_bytes());
+        response[3] = status;
+    }
+
+    /// Run the command in `request`, and write its response.
+    fn process(&self, request: &[u8; 64], response: &mut [u8; 64]) -> Reply {
+        response[0] = request[0];
+        response[1] = DAP_OK;
+        match request[0] {
+            command::INFO => self.info(request[1], response),
+            command::HOST_STATUS => {}
+            command::CONNECT => {
+                // The default port is SWD, the only one supported.
+                if request[1] == 0 || request[1] == PORT_SWD {
+                    self.swd.enable();
+                    response[1] = PORT_SWD;
+                } else {
+                    response[1] = 0;
+                }
+            }
+            command::DISCONNECT => self.swd.disable(),
+            command::TRANSFER_CONFIGURE => {
+                self.configuration.set(Configuration {
+                    idle_cycles: request[1],
+                    ..self.configuration.get()
+                });
+                self.swd.configure(self.configuration.get());
+                self.wait_retry.set(u16_at(request, 2).unwrap_or(0));
+                self.match_retry.set(u16_at(request, 4).unwrap_or(0));
+            }
+            command::TRANSFER => self.transfer_command(request, response),
+            command::TRANSFER_BLOCK => self.transfer_block(request, response),
+            // Transfers run to completion before the next command is read, so
+            // there is nothing to abort.
+            command::TRANSFER_ABORT => return Reply::None,
+            command::WRITE_ABORT => {
+                let mut value = u32_at(request, 2).unwrap_or(0);
+                if self.transfer(DP_ABORT, &mut value).is_err() {
+                    response[1] = DAP_ERROR;
+                }
+            }
+            command::DELAY => {
+                return Reply::After(u16_at(request, 1).unwrap_or(0) as u32);
+            }
+            command::RESET_TARGET => {
+                // No device-specific reset sequence is executed.
+                response[2] = 0;
+            }
+            command::SWJ_PINS => {
+                let output = request[1];
+                let select = request[2];
+                let mut pins = 0;
+                if select & PIN_RESET != 0 {
+                    let _ = self.swd.set_reset(output & PIN_RESET == 0);
+                    pins |= output & PIN_RESET;
+                }
+                response[1] = pins;
+            }
+            command::SWJ_CLOCK => {
+                let frequency_hz = u32_at(request, 1).unwrap_or(0);
+                if frequency_hz == 0 {
+                    response[1] = DAP_ERROR;
+                } else {
+                    self.swd.set_clock_hz(frequency_hz);
+                }
+            }
+            command::SWJ_SEQUENCE => {
+                // A count of 0 means 256 bits.
+                let count = match request[1] {
+                    0 => 256,
+                    count => count as usize,
+                };
+                self.swd
+                    .write_sequence(&request[2..2 + (count + 7) / 8], count);
+            }
+            command::SWD_CONFIGURE => {
+                self.configuration.set(Configuration {
+                    turnaround_cycles: (request[1] & 0b11) + 1,
+                    data_phase: request[1] & 0b100 != 0,
+                    ..self.configuration.get()
+                });
+                self.swd.configure(self.configuration.get());
+            }
+            _ => response[0] = command::INVALID,
+        }
+        Reply::Now
+    }
+}
+
+impl<'a, S: Swd, U: usb_hid::UsbHid<'a, [u8; 64]>, A: time::Alarm<'a>> usb_hid::Client<'a, [u8; 64]>
+    for CmsisDap<'a, S, U, A>
+{
+    fn packet_received(
+        &'a self,
+        result: Result<(), ErrorCode>,
+        buffer: &'static mut [u8; 64],
+        _endpoint: usize,
+    ) {
+        let reply = match (result, self.send_buffer.take()) {
+            (Ok(()), Some(response)) => Some((self.process(buffer, response), response)),
+            (_, response) => {
+                response.map(|response| self.send_buffer.replace(response));
+                None
+            }
+        };
+        self.recv_buffer.replace(buffer);
+
+        match reply {
+            Some((Reply::Now, response)) => self.send(response),
+            Some((Reply::After(us), response)) => {
+                self.delayed.replace(response);
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(us));
+            }
+            Some((Reply::None, response)) => {
+                self.send_buffer.replace(response);
+                let _ = self.receive();
+            }
+            None => {
+                let _ = self.receive();
+            }
+        }
+    }
+
+    fn packet_transmitted(
+        &'a self,
+        _result: Result<(), ErrorCode>,
+        buffer: &'static mut [u8; 64],
+        _endpoint: usize,
+    ) {
+        self.send_buffer.replace(buffer);
+        let _ = self.receive();
+    }
+
+    fn can_receive(&'a self) -> bool {
+        // A new command is only read once the last response went out.
+        self.send_buffer.is_some()
+    }
+}
+
+impl<'a, S: Swd, U: usb_hid::UsbHid<'a, [u8; 64]>, A: time::Alarm<'a>> time::AlarmClient
+    for CmsisDap<'a, S, U, A>
+{
+    fn alarm(&self) {
+        self.delayed.take().map(|response| self.send(response));
+    }
+}
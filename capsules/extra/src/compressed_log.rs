@@ -0,0 +1,431 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Transparent compression for log storage.
+//!
+//! `CompressedLog` sits on top of a log (usually `capsules_extra::log::Log`)
+//! and implements `LogRead` and `LogWrite` itself. Every appended entry is
+//! compressed with a small LZSS compressor in the style of heatshrink before
+//! it is written to the underlying log, and decompressed again when it is
+//! read, so clients see the entries they appended. Entry IDs, seeking, syncing
+//! and erasing are those of the underlying log.
+//!
+//! Each entry is compressed on its own, so entries can still be read and
+//! seeked to independently, and an entry lost to a circular log wrapping
+//! around does not affect the others. The compressor finds repetitions within
+//! an entry, so it works best when clients batch samples into entries of a
+//! few hundred bytes: telemetry of slowly changing sensors typically shrinks
+//! to a third. Entries that do not compress are stored as they are, with one
+//! byte of overhead.
+//!
+//! Compression needs a scratch buffer that holds a compressed entry. Its
+//! length limits the size of the entries: an entry must fit uncompressed in
+//! the scratch buffer, less one byte.
+//!
+//! Compressed format
+//! -----------------
+//!
+//! The first byte of an entry says how the rest is stored: `0` for stored as
+//! is, `1` for compressed. Compressed data is a sequence of groups of up to
+//! eight items, each group starting with a flag byte. Bit `i` (LSB first) of
+//! the flag byte says whether item `i` is a literal byte (`1`) or a back
+//! reference (`0`). A back reference is two bytes: the distance to the start
+//! of the repeated bytes less one, and the number of repeated bytes less
+//! `MIN_MATCH`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let compressed_log = components::compressed_log::CompressedLogComponent::new(log)
+//!     .finalize(components::compressed_log_component_static!(
+//!         capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!         256
+//!     ));
+//! compressed_log.set_read_client(log_reader);
+//! compressed_log.set_append_client(log_writer);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Entry is stored as is.
+const METHOD_STORED: u8 = 0;
+/// Entry is compressed.
+const METHOD_LZSS: u8 = 1;
+
+/// Shortest repetition encoded as a back reference.
+pub const MIN_MATCH: usize = 3;
+/// Longest repetition encoded as a back reference.
+const MAX_MATCH: usize = MIN_MATCH + 255;
+/// How far back a back reference can point.
+const WINDOW: usize = 256;
+
+/// Returns the number of bytes a compressed entry of `length` bytes takes at
+/// most.
+pub const fn max_compressed_len(length: usize) -> usize {
+    length + 1
+}
+
+/// Compress `input` into `output`, and return the length of the compressed
+/// data. Returns `None` if `output` is shorter than
+/// `max_compressed_len(input.len())`.
+pub fn compress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    if output.len() < max_compressed_len(input.len()) {
+        return None;
+    }
+    match compress_lzss(input, &mut output[1..input.len() + 1]) {
+        Some(length) => {
+            output[0] = METHOD_LZSS;
+            Some(length + 1)
+        }
+        None => {
+            // Does not compress, store it.
+            output[0] = METHOD_STORED;
+            output[1..input.len() + 1].copy_from_slice(input);
+            Some(input.len() + 1)
+        }
+    }
+}
+
+/// Compress `input` into `output`, or return `None` if the compressed data
+/// does not fit.
+fn compress_lzss(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut out = 0;
+    let mut flags = 0;
+    let mut item = 8;
+    let mut pos = 0;
+
+    while pos < input.len() {
+        if item == 8 {
+            // Start a new group.
+            flags = out;
+            *output.get_mut(out)? = 0;
+            out += 1;
+            item = 0;
+        }
+
+        // Find the longest repetition of the bytes at `pos` in the window.
+        let max_length = (input.len() - pos).min(MAX_MATCH);
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        for start in pos.saturating_sub(WINDOW)..pos {
+            let length = input[start..]
+                .iter()
+                .zip(&input[pos..pos + max_length])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length > best_length {
+                best_length = length;
+                best_distance = pos - start;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            *output.get_mut(out)? = (best_distance - 1) as u8;
+            *output.get_mut(out + 1)? = (best_length - MIN_MATCH) as u8;
+            out += 2;
+            pos += best_length;
+        } else {
+            output[flags] |= 1 << item;
+            *output.get_mut(out)? = input[pos];
+            out += 1;
+            pos += 1;
+        }
+        item += 1;
+    }
+    Some(out)
+}
+
+/// Decompress `input` into `output`, and return the length of the
+/// decompressed data. Returns `None` if `input` is not valid compressed data
+/// or does not fit in `output`.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let (&method, data) = input.split_first()?;
+    match method {
+        METHOD_STORED => {
+            output.get_mut(..data.len())?.copy_from_slice(data);
+            Some(data.len())
+        }
+        METHOD_LZSS => decompress_lzss(data, output),
+        _ => None,
+    }
+}
+
+fn decompress_lzss(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut out = 0;
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let flags = input[pos];
+        pos += 1;
+        for item in 0..8 {
+            if pos == input.len() {
+                break;
+            }
+            if flags & (1 << item) != 0 {
+                *output.get_mut(out)? = input[pos];
+                out += 1;
+                pos += 1;
+            } else {
+                let distance = *input.get(pos)? as usize + 1;
+                let length = *input.get(pos + 1)? as usize + MIN_MATCH;
+                pos += 2;
+                let start = out.checked_sub(distance)?;
+                if out + length > output.len() {
+                    return None;
+                }
+                // Byte by byte, as the repetition may overlap itself.
+                for i in 0..length {
+                    output[out + i] = output[start + i];
+                }
+                out += length;
+            }
+        }
+    }
+    Some(out)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Read,
+    Append,
+}
+
+pub struct CompressedLog<'a, L: LogRead<'a> + LogWrite<'a>> {
+    log: &'a L,
+    /// Holds the compressed entry while it is read or appended.
+    scratch: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    /// Client buffer of the read or append in progress.
+    buffer: TakeCell<'static, [u8]>,
+    /// Length passed with the read or append in progress.
+    length: Cell<usize>,
+    read_client: OptionalCell<&'a dyn LogReadClient>,
+    append_client: OptionalCell<&'a dyn LogWriteClient>,
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> CompressedLog<'a, L> {
+    pub fn new(log: &'a L, scratch: &'static mut [u8]) -> Self {
+        CompressedLog {
+            log,
+            scratch: TakeCell::new(scratch),
+            state: Cell::new(State::Idle),
+            buffer: TakeCell::empty(),
+            length: Cell::new(0),
+            read_client: OptionalCell::empty(),
+            append_client: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogRead<'a> for CompressedLog<'a, L> {
+    type EntryID = L::EntryID;
+
+    fn set_read_client(&'a self, read_client: &'a dyn LogReadClient) {
+        self.read_client.set(read_client);
+    }
+
+    /// Read the next entry into `buffer`. Fails with `SIZE` in the callback
+    /// if the decompressed entry is longer than `length`.
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < length {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+        let scratch_len = scratch.len();
+        if let Err((e, scratch)) = self.log.read(scratch, scratch_len) {
+            self.scratch.replace(scratch);
+            return Err((e, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.length.set(length);
+        self.state.set(State::Read);
+        Ok(())
+    }
+
+    fn log_start(&self) -> Self::EntryID {
+        self.log.log_start()
+    }
+
+    fn log_end(&self) -> Self::EntryID {
+        self.log.log_end()
+    }
+
+    fn next_read_entry_id(&self) -> Self::EntryID {
+        self.log.next_read_entry_id()
+    }
+
+    fn seek(&self, entry: Self::EntryID) -> Result<(), ErrorCode> {
+        self.log.seek(entry)
+    }
+
+    /// Returns the size of the underlying log, i.e. of the compressed data.
+    fn get_size(&self) -> usize {
+        self.log.get_size()
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogWrite<'a> for CompressedLog<'a, L> {
+    fn set_append_client(&'a self, append_client: &'a dyn LogWriteClient) {
+        self.append_client.set(append_client);
+    }
+
+    /// Compress and append the first `length` bytes of `buffer`. Fails with
+    /// `SIZE` if they might not fit in the scratch buffer once compressed.
+    fn append(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if length == 0 || buffer.len() < length {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+        let compressed_len = match compress(&buffer[..length], scratch) {
+            Some(compressed_len) => compressed_len,
+            None => {
+                self.scratch.replace(scratch);
+                return Err((ErrorCode::SIZE, buffer));
+            }
+        };
+        if let Err((e, scratch)) = self.log.append(scratch, compressed_len) {
+            self.scratch.replace(scratch);
+            return Err((e, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.length.set(length);
+        self.state.set(State::Append);
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), ErrorCode> {
+        self.log.sync()
+    }
+
+    fn erase(&self) -> Result<(), ErrorCode> {
+        self.log.erase()
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogReadClient for CompressedLog<'a, L> {
+    fn read_done(&self, scratch: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        let max_length = self.length.get();
+        let result = self.buffer.take().map(|buffer| {
+            let decompressed = error.and_then(|()| {
+                decompress(&scratch[..length], &mut buffer[..max_length]).ok_or(ErrorCode::SIZE)
+            });
+            (buffer, decompressed)
+        });
+        self.scratch.replace(scratch);
+
+        if let Some((buffer, decompressed)) = result {
+            self.read_client.map(move |client| match decompressed {
+                Ok(length) => client.read_done(buffer, length, Ok(())),
+                Err(e) => client.read_done(buffer, 0, Err(e)),
+            });
+        }
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        self.read_client.map(|client| client.seek_done(error));
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>> LogWriteClient for CompressedLog<'a, L> {
+    fn append_done(
+        &self,
+        scratch: &'static mut [u8],
+        _length: usize,
+        records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.state.set(State::Idle);
+        self.scratch.replace(scratch);
+        let length = self.length.get();
+        self.buffer.take().map(|buffer| {
+            self.append_client
+                .map(move |client| client.append_done(buffer, length, records_lost, error));
+        });
+    }
+
+    fn sync_done(&self, error: Result<(), ErrorCode>) {
+        self.append_client.map(|client| client.sync_done(error));
+    }
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        self.append_client.map(|client| client.erase_done(error));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(input: &[u8]) -> usize {
+        let mut compressed = [0; 600];
+        let mut decompressed = [0; 600];
+        let compressed_len = compress(input, &mut compressed).unwrap();
+        let length = decompress(&compressed[..compressed_len], &mut decompressed).unwrap();
+        assert_eq!(&decompressed[..length], input);
+        compressed_len
+    }
+
+    #[test]
+    fn compresses_repetitive_data() {
+        let mut samples = [0; 512];
+        for (i, byte) in samples.iter_mut().enumerate() {
+            // Slowly changing readings, interleaved with a constant field.
+            *byte = if i % 4 == 0 { (i / 64) as u8 } else { 0x42 };
+        }
+        assert!(round_trip(&samples) < samples.len() / 3);
+    }
+
+    #[test]
+    fn stores_incompressible_data() {
+        let mut noise = [0; 256];
+        let mut state: u32 = 1;
+        for byte in noise.iter_mut() {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            *byte = (state >> 16) as u8;
+        }
+        assert_eq!(round_trip(&noise), noise.len() + 1);
+        assert_eq!(round_trip(&[]), 1);
+        assert_eq!(round_trip(&[7]), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_data() {
+        let mut output = [0; 16];
+        // Back reference before the start of the data.
+        assert_eq!(
+            decompress(&[METHOD_LZSS, 0x00, 0x00, 0x00], &mut output),
+            None
+        );
+        // Decompressed data does not fit.
+        assert_eq!(decompress(&[METHOD_STORED; 20], &mut output), None);
+        assert_eq!(decompress(&[2, 0], &mut output), None);
+    }
+}
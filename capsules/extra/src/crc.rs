@@ -324,6 +324,22 @@ impl<'a, C: Crc<'a>> SyscallDriver for CrcDriver<'a, C> {
     ///   result is placed in the low-order bits of the returned result
     ///   value. That is, result values will always be of the form `0x0000xxxx`
     ///   for this algorithm.  It can be performed purely in hardware on the SAM4L.
+    ///
+    ///   * `3: Crc-16Modbus`  This algorithm uses polynomial 0x8005 with an
+    ///   initial value of 0xFFFF, as used by Modbus RTU. The sixteen-bit Crc
+    ///   result is placed in the low-order bits of the returned result value.
+    ///
+    ///   * `4: Crc-8Maxim`  This algorithm uses polynomial 0x31 with an
+    ///   initial value of 0, as used by 1-Wire devices. The eight-bit Crc
+    ///   result is placed in the low-order bits of the returned result value.
+    ///
+    ///   * `5: Crc-16Xmodem`  This algorithm uses polynomial 0x1021 with an
+    ///   initial value of 0, and consumes input bytes from most-significant
+    ///   bit to least-significant, as used by SD cards. The sixteen-bit Crc
+    ///   result is placed in the low-order bits of the returned result value.
+    ///
+    ///   Not every Crc unit supports every algorithm. Requesting an
+    ///   unsupported one returns `NOSUPPORT`.
     fn command(
         &self,
         command_num: usize,
@@ -343,6 +359,9 @@ impl<'a, C: Crc<'a>> SyscallDriver for CrcDriver<'a, C> {
                 } else {
                     return CommandReturn::failure(ErrorCode::INVAL);
                 };
+                if !self.crc.algorithm_supported(algorithm) {
+                    return CommandReturn::failure(ErrorCode::NOSUPPORT);
+                }
                 let res = self
                     .grant
                     .enter(process_id, |grant, kernel_data| {
@@ -576,6 +595,9 @@ fn alg_from_user_int(i: usize) -> Option<CrcAlgorithm> {
         0 => Some(CrcAlgorithm::Crc32),
         1 => Some(CrcAlgorithm::Crc32C),
         2 => Some(CrcAlgorithm::Crc16CCITT),
+        3 => Some(CrcAlgorithm::Crc16Modbus),
+        4 => Some(CrcAlgorithm::Crc8Maxim),
+        5 => Some(CrcAlgorithm::Crc16Xmodem),
         _ => None,
     }
 }
@@ -585,5 +607,8 @@ fn encode_upcall_crc_output(output: CrcOutput) -> (u32, u32) {
         CrcOutput::Crc32(val) => (val, 0),
         CrcOutput::Crc32C(val) => (val, 1),
         CrcOutput::Crc16CCITT(val) => (val as u32, 2),
+        CrcOutput::Crc16Modbus(val) => (val as u32, 3),
+        CrcOutput::Crc8Maxim(val) => (val as u32, 4),
+        CrcOutput::Crc16Xmodem(val) => (val as u32, 5),
     }
 }
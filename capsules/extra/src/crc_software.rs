@@ -0,0 +1,122 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Software implementation of the CRC HIL.
+//!
+//! Provides `hil::crc::Crc` on chips without CRC hardware, using the
+//! table-driven implementation in `kernel::utilities::crc`. All algorithms of
+//! `hil::crc::CrcAlgorithm` are supported. Input is processed in full when it
+//! is passed in; the callbacks are issued from a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let crc = components::crc::CrcSoftwareComponent::new()
+//!     .finalize(components::crc_software_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::crc::{Client, Crc, CrcAlgorithm, CrcOutput};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::crc::CrcState;
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+pub struct CrcSoftware<'a> {
+    client: OptionalCell<&'a dyn Client>,
+    state: Cell<Option<CrcState>>,
+    /// Buffer of an input whose callback is pending.
+    input: OptionalCell<LeasableMutableBuffer<'static, u8>>,
+    /// Result of a computation whose callback is pending.
+    output: OptionalCell<CrcOutput>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> CrcSoftware<'a> {
+    pub fn new() -> CrcSoftware<'a> {
+        CrcSoftware {
+            client: OptionalCell::empty(),
+            state: Cell::new(None),
+            input: OptionalCell::empty(),
+            output: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn busy(&self) -> bool {
+        self.input.is_some() || self.output.is_some()
+    }
+}
+
+impl<'a> Crc<'a> for CrcSoftware<'a> {
+    fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    fn algorithm_supported(&self, _algorithm: CrcAlgorithm) -> bool {
+        true
+    }
+
+    fn set_algorithm(&self, algorithm: CrcAlgorithm) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.state.set(Some(CrcState::new(algorithm)));
+        Ok(())
+    }
+
+    fn input(
+        &self,
+        mut data: LeasableMutableBuffer<'static, u8>,
+    ) -> Result<(), (ErrorCode, LeasableMutableBuffer<'static, u8>)> {
+        if self.busy() {
+            return Err((ErrorCode::BUSY, data));
+        }
+        let mut state = match self.state.get() {
+            Some(state) => state,
+            None => return Err((ErrorCode::RESERVE, data)),
+        };
+        state.update(&data[..]);
+        self.state.set(Some(state));
+
+        // All of the data was consumed.
+        let length = data.len();
+        data.slice(length..length);
+        self.input.set(data);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn compute(&self) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let state = self.state.get().ok_or(ErrorCode::RESERVE)?;
+        self.output.set(state.finish());
+        // Start over for the next computation.
+        self.state.set(Some(CrcState::new(state.algorithm())));
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn disable(&self) {}
+}
+
+impl<'a> DeferredCallClient for CrcSoftware<'a> {
+    fn handle_deferred_call(&self) {
+        if let Some(data) = self.input.take() {
+            self.client
+                .map(move |client| client.input_done(Ok(()), data));
+        } else if let Some(output) = self.output.take() {
+            self.client.map(|client| client.crc_done(Ok(output)));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
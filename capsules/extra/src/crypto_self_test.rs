@@ -0,0 +1,571 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Known-answer self-tests for crypto engines, run at boot.
+//!
+//! Several certification regimes require that crypto engines are checked
+//! against known answers before they are used. `CryptoSelfTest` runs a set of
+//! registered `SelfTest`s one after the other and prints the result of each.
+//! `SelfTestGate` wraps the syscall driver built on top of an engine and
+//! hides the driver from userspace until all of the engine's tests passed,
+//! so a failing engine is never exposed.
+//!
+//! The available tests are:
+//!
+//! - `Aes128SelfTest`: AES-128 in ECB, CBC, and CTR mode (NIST SP 800-38A,
+//!   first block of F.1.1, F.2.1, and F.5.1).
+//! - `DigestSelfTest`: SHA-256 (FIPS 180-2, "abc") and HMAC-SHA256 (RFC 4231,
+//!   test case 2).
+//!
+//! There is no signature verification HIL yet, so ECDSA is not covered.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let aes_test = static_init!(
+//!     capsules_extra::crypto_self_test::Aes128SelfTest<'static, earlgrey::aes::Aes>,
+//!     capsules_extra::crypto_self_test::Aes128SelfTest::new_cbc(
+//!         &peripherals.aes,
+//!         static_init!([u8; 16], [0; 16]),
+//!     )
+//! );
+//! peripherals.aes.set_client(aes_test);
+//!
+//! let self_test = static_init!(
+//!     capsules_extra::crypto_self_test::CryptoSelfTest<'static, 4>,
+//!     capsules_extra::crypto_self_test::CryptoSelfTest::new()
+//! );
+//! self_test.register(aes_test).unwrap();
+//! self_test.start();
+//!
+//! let aes_tests = static_init!([&'static dyn SelfTest<'static>; 1], [aes_test]);
+//! let aes_driver = static_init!(
+//!     capsules_extra::crypto_self_test::SelfTestGate<'static, AesDriver>,
+//!     capsules_extra::crypto_self_test::SelfTestGate::new(aes_driver, aes_tests)
+//! );
+//! ```
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::digest;
+use kernel::hil::symmetric_encryption::{self, AES128, AES128_BLOCK_SIZE, AES128_KEY_SIZE};
+use kernel::processbuffer::UserspaceReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{LeasableBuffer, LeasableMutableBuffer};
+use kernel::{ErrorCode, ProcessId};
+
+/// NIST SP 800-38A AES-128 key.
+const AES_KEY: [u8; AES128_KEY_SIZE] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const AES_PLAINTEXT: [u8; AES128_BLOCK_SIZE] = [
+    0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+];
+const AES_CBC_IV: [u8; AES128_BLOCK_SIZE] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+const AES_CTR_IV: [u8; AES128_BLOCK_SIZE] = [
+    0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff,
+];
+const AES_ECB_CIPHERTEXT: [u8; AES128_BLOCK_SIZE] = [
+    0x3a, 0xd7, 0x7b, 0xb4, 0x0d, 0x7a, 0x36, 0x60, 0xa8, 0x9e, 0xca, 0xf3, 0x24, 0x66, 0xef, 0x97,
+];
+const AES_CBC_CIPHERTEXT: [u8; AES128_BLOCK_SIZE] = [
+    0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19, 0x7d,
+];
+const AES_CTR_CIPHERTEXT: [u8; AES128_BLOCK_SIZE] = [
+    0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6, 0xce,
+];
+
+const SHA256_MESSAGE: &[u8] = b"abc";
+const SHA256_DIGEST: [u8; 32] = [
+    0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22, 0x23,
+    0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+];
+const HMAC_SHA256_KEY: &[u8] = b"Jefe";
+const HMAC_SHA256_MESSAGE: &[u8] = b"what do ya want for nothing?";
+const HMAC_SHA256_DIGEST: [u8; 32] = [
+    0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95, 0x75, 0xc7,
+    0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9, 0x64, 0xec, 0x38, 0x43,
+];
+
+/// Length of the data buffer a `DigestSelfTest` needs.
+pub const DIGEST_DATA_LEN: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Status {
+    NotRun,
+    Running,
+    Passed,
+    Failed,
+}
+
+/// A known-answer test of one engine.
+pub trait SelfTest<'a> {
+    fn set_client(&self, client: &'a dyn SelfTestClient);
+
+    /// Start the test. If this returns `Ok(())`, `test_done()` is called
+    /// when it finishes.
+    fn run(&self) -> Result<(), ErrorCode>;
+
+    fn name(&self) -> &'static str;
+
+    fn status(&self) -> Status;
+}
+
+pub trait SelfTestClient {
+    fn test_done(&self, passed: bool);
+}
+
+/// Called once all registered tests finished.
+pub trait CryptoSelfTestClient {
+    fn self_tests_done(&self, all_passed: bool);
+}
+
+/// Runs every registered test in turn.
+pub struct CryptoSelfTest<'a, const MAX_TESTS: usize> {
+    tests: [OptionalCell<&'a dyn SelfTest<'a>>; MAX_TESTS],
+    current: OptionalCell<usize>,
+    all_passed: Cell<bool>,
+    client: OptionalCell<&'a dyn CryptoSelfTestClient>,
+}
+
+impl<'a, const MAX_TESTS: usize> CryptoSelfTest<'a, MAX_TESTS> {
+    pub fn new() -> Self {
+        CryptoSelfTest {
+            tests: core::array::from_fn(|_| OptionalCell::empty()),
+            current: OptionalCell::empty(),
+            all_passed: Cell::new(true),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn CryptoSelfTestClient) {
+        self.client.set(client);
+    }
+
+    pub fn register(&'a self, test: &'a dyn SelfTest<'a>) -> Result<(), ErrorCode> {
+        let slot = self
+            .tests
+            .iter()
+            .find(|slot| slot.is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        test.set_client(self);
+        slot.set(test);
+        Ok(())
+    }
+
+    /// Run all registered tests.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.current.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.all_passed.set(true);
+        self.run_from(0);
+        Ok(())
+    }
+
+    fn run_from(&self, index: usize) {
+        for (i, slot) in self.tests.iter().enumerate().skip(index) {
+            if let Some(test) = slot.extract() {
+                match test.run() {
+                    Ok(()) => {
+                        self.current.set(i);
+                        return;
+                    }
+                    Err(e) => {
+                        debug!(
+                            "Crypto self-test {}: FAILED to start ({:?})",
+                            test.name(),
+                            e
+                        );
+                        self.all_passed.set(false);
+                    }
+                }
+            }
+        }
+        self.current.clear();
+        self.client
+            .map(|client| client.self_tests_done(self.all_passed.get()));
+    }
+}
+
+impl<'a, const MAX_TESTS: usize> SelfTestClient for CryptoSelfTest<'a, MAX_TESTS> {
+    fn test_done(&self, passed: bool) {
+        if let Some(index) = self.current.extract() {
+            self.tests[index].map(|test| {
+                debug!(
+                    "Crypto self-test {}: {}",
+                    test.name(),
+                    if passed { "passed" } else { "FAILED" }
+                );
+            });
+            if !passed {
+                self.all_passed.set(false);
+            }
+            self.run_from(index + 1);
+        }
+    }
+}
+
+/// Exposes `driver` to userspace only once all of `tests` passed. Until then
+/// the driver appears to be missing.
+pub struct SelfTestGate<'a, D: SyscallDriver> {
+    driver: &'a D,
+    tests: &'a [&'a dyn SelfTest<'a>],
+}
+
+impl<'a, D: SyscallDriver> SelfTestGate<'a, D> {
+    pub fn new(driver: &'a D, tests: &'a [&'a dyn SelfTest<'a>]) -> Self {
+        SelfTestGate { driver, tests }
+    }
+
+    fn passed(&self) -> bool {
+        self.tests
+            .iter()
+            .all(|test| test.status() == Status::Passed)
+    }
+}
+
+impl<'a, D: SyscallDriver> SyscallDriver for SelfTestGate<'a, D> {
+    fn command(
+        &self,
+        command_num: usize,
+        r2: usize,
+        r3: usize,
+        process_id: ProcessId,
+    ) -> CommandReturn {
+        if self.passed() {
+            self.driver.command(command_num, r2, r3, process_id)
+        } else {
+            CommandReturn::failure(ErrorCode::NODEVICE)
+        }
+    }
+
+    fn allow_userspace_readable(
+        &self,
+        app: ProcessId,
+        which: usize,
+        slice: UserspaceReadableProcessBuffer,
+    ) -> Result<UserspaceReadableProcessBuffer, (UserspaceReadableProcessBuffer, ErrorCode)> {
+        if self.passed() {
+            self.driver.allow_userspace_readable(app, which, slice)
+        } else {
+            Err((slice, ErrorCode::NODEVICE))
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        if self.passed() {
+            self.driver.allocate_grant(processid)
+        } else {
+            Err(kernel::process::Error::KernelError)
+        }
+    }
+}
+
+/// Encrypts one block with a known key and compares the result.
+pub struct Aes128SelfTest<'a, A: AES128<'a>> {
+    aes: &'a A,
+    name: &'static str,
+    set_mode: fn(&A) -> Result<(), ErrorCode>,
+    iv: Option<&'static [u8; AES128_BLOCK_SIZE]>,
+    expected: &'static [u8; AES128_BLOCK_SIZE],
+    buffer: TakeCell<'static, [u8]>,
+    status: Cell<Status>,
+    client: OptionalCell<&'a dyn SelfTestClient>,
+}
+
+impl<'a, A: AES128<'a>> Aes128SelfTest<'a, A> {
+    fn new(
+        aes: &'a A,
+        buffer: &'static mut [u8; AES128_BLOCK_SIZE],
+        name: &'static str,
+        set_mode: fn(&A) -> Result<(), ErrorCode>,
+        iv: Option<&'static [u8; AES128_BLOCK_SIZE]>,
+        expected: &'static [u8; AES128_BLOCK_SIZE],
+    ) -> Self {
+        Aes128SelfTest {
+            aes,
+            name,
+            set_mode,
+            iv,
+            expected,
+            buffer: TakeCell::new(buffer),
+            status: Cell::new(Status::NotRun),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn new_ecb(aes: &'a A, buffer: &'static mut [u8; AES128_BLOCK_SIZE]) -> Self
+    where
+        A: symmetric_encryption::AES128ECB,
+    {
+        Self::new(
+            aes,
+            buffer,
+            "AES-128-ECB",
+            |aes| aes.set_mode_aes128ecb(true),
+            None,
+            &AES_ECB_CIPHERTEXT,
+        )
+    }
+
+    pub fn new_cbc(aes: &'a A, buffer: &'static mut [u8; AES128_BLOCK_SIZE]) -> Self
+    where
+        A: symmetric_encryption::AES128CBC,
+    {
+        Self::new(
+            aes,
+            buffer,
+            "AES-128-CBC",
+            |aes| aes.set_mode_aes128cbc(true),
+            Some(&AES_CBC_IV),
+            &AES_CBC_CIPHERTEXT,
+        )
+    }
+
+    pub fn new_ctr(aes: &'a A, buffer: &'static mut [u8; AES128_BLOCK_SIZE]) -> Self
+    where
+        A: symmetric_encryption::AES128Ctr,
+    {
+        Self::new(
+            aes,
+            buffer,
+            "AES-128-CTR",
+            |aes| aes.set_mode_aes128ctr(true),
+            Some(&AES_CTR_IV),
+            &AES_CTR_CIPHERTEXT,
+        )
+    }
+
+    fn finish(&self, passed: bool) {
+        self.aes.disable();
+        self.status.set(if passed {
+            Status::Passed
+        } else {
+            Status::Failed
+        });
+        self.client.map(|client| client.test_done(passed));
+    }
+}
+
+impl<'a, A: AES128<'a>> SelfTest<'a> for Aes128SelfTest<'a, A> {
+    fn set_client(&self, client: &'a dyn SelfTestClient) {
+        self.client.set(client);
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer.copy_from_slice(&AES_PLAINTEXT);
+
+        self.aes.enable();
+        let configured = (self.set_mode)(self.aes)
+            .and_then(|()| self.aes.set_key(&AES_KEY))
+            .and_then(|()| self.iv.map_or(Ok(()), |iv| self.aes.set_iv(iv)));
+        if let Err(e) = configured {
+            self.buffer.replace(buffer);
+            self.aes.disable();
+            self.status.set(Status::Failed);
+            return Err(e);
+        }
+
+        self.status.set(Status::Running);
+        self.aes.start_message();
+        match self.aes.crypt(None, buffer, 0, AES128_BLOCK_SIZE) {
+            None => Ok(()),
+            Some((result, _, buffer)) => {
+                self.buffer.replace(buffer);
+                self.aes.disable();
+                self.status.set(Status::Failed);
+                Err(result.err().unwrap_or(ErrorCode::FAIL))
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn status(&self) -> Status {
+        self.status.get()
+    }
+}
+
+impl<'a, A: AES128<'a>> symmetric_encryption::Client<'a> for Aes128SelfTest<'a, A> {
+    fn crypt_done(&'a self, _source: Option<&'static mut [u8]>, dest: &'static mut [u8]) {
+        let passed = dest[..AES128_BLOCK_SIZE] == self.expected[..];
+        self.buffer.replace(dest);
+        self.finish(passed);
+    }
+}
+
+/// Hashes a known message and compares the digest.
+pub struct DigestSelfTest<'a, D: digest::Digest<'a, 32>> {
+    digest: &'a D,
+    name: &'static str,
+    set_mode: fn(&D) -> Result<(), ErrorCode>,
+    message: &'static [u8],
+    expected: &'static [u8; 32],
+    data: TakeCell<'static, [u8]>,
+    output: TakeCell<'static, [u8; 32]>,
+    status: Cell<Status>,
+    client: OptionalCell<&'a dyn SelfTestClient>,
+}
+
+impl<'a, D: digest::Digest<'a, 32>> DigestSelfTest<'a, D> {
+    fn new(
+        digest: &'a D,
+        data: &'static mut [u8; DIGEST_DATA_LEN],
+        output: &'static mut [u8; 32],
+        name: &'static str,
+        set_mode: fn(&D) -> Result<(), ErrorCode>,
+        message: &'static [u8],
+        expected: &'static [u8; 32],
+    ) -> Self {
+        DigestSelfTest {
+            digest,
+            name,
+            set_mode,
+            message,
+            expected,
+            data: TakeCell::new(data),
+            output: TakeCell::new(output),
+            status: Cell::new(Status::NotRun),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn new_sha256(
+        digest: &'a D,
+        data: &'static mut [u8; DIGEST_DATA_LEN],
+        output: &'static mut [u8; 32],
+    ) -> Self
+    where
+        D: digest::Sha256,
+    {
+        Self::new(
+            digest,
+            data,
+            output,
+            "SHA-256",
+            |digest| digest.set_mode_sha256(),
+            SHA256_MESSAGE,
+            &SHA256_DIGEST,
+        )
+    }
+
+    pub fn new_hmac_sha256(
+        digest: &'a D,
+        data: &'static mut [u8; DIGEST_DATA_LEN],
+        output: &'static mut [u8; 32],
+    ) -> Self
+    where
+        D: digest::HmacSha256,
+    {
+        Self::new(
+            digest,
+            data,
+            output,
+            "HMAC-SHA256",
+            |digest| digest.set_mode_hmacsha256(HMAC_SHA256_KEY),
+            HMAC_SHA256_MESSAGE,
+            &HMAC_SHA256_DIGEST,
+        )
+    }
+
+    fn finish(&self, passed: bool) {
+        self.digest.clear_data();
+        self.status.set(if passed {
+            Status::Passed
+        } else {
+            Status::Failed
+        });
+        self.client.map(|client| client.test_done(passed));
+    }
+}
+
+impl<'a, D: digest::Digest<'a, 32>> SelfTest<'a> for DigestSelfTest<'a, D> {
+    fn set_client(&self, client: &'a dyn SelfTestClient) {
+        self.client.set(client);
+    }
+
+    fn run(&self) -> Result<(), ErrorCode> {
+        if self.output.is_none() {
+            return Err(ErrorCode::BUSY);
+        }
+        let data = self.data.take().ok_or(ErrorCode::BUSY)?;
+        if let Err(e) = (self.set_mode)(self.digest) {
+            self.data.replace(data);
+            self.status.set(Status::Failed);
+            return Err(e);
+        }
+
+        data[..self.message.len()].copy_from_slice(self.message);
+        let mut buffer = LeasableMutableBuffer::new(data);
+        buffer.slice(0..self.message.len());
+        self.status.set(Status::Running);
+        self.digest.add_mut_data(buffer).map_err(|(e, buffer)| {
+            self.data.replace(buffer.take());
+            self.status.set(Status::Failed);
+            e
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn status(&self) -> Status {
+        self.status.get()
+    }
+}
+
+impl<'a, D: digest::Digest<'a, 32>> digest::ClientData<32> for DigestSelfTest<'a, D> {
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: LeasableBuffer<'static, u8>) {}
+
+    fn add_mut_data_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        data: LeasableMutableBuffer<'static, u8>,
+    ) {
+        if result.is_ok() && data.len() != 0 {
+            // The engine did not take all of the message yet.
+            if let Err((_, data)) = self.digest.add_mut_data(data) {
+                self.data.replace(data.take());
+                self.finish(false);
+            }
+            return;
+        }
+
+        self.data.replace(data.take());
+        if result.is_err() {
+            self.finish(false);
+            return;
+        }
+        self.output.take().map(|output| {
+            if let Err((_, output)) = self.digest.run(output) {
+                self.output.replace(output);
+                self.finish(false);
+            }
+        });
+    }
+}
+
+impl<'a, D: digest::Digest<'a, 32>> digest::ClientHash<32> for DigestSelfTest<'a, D> {
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let passed = result.is_ok() && digest == self.expected;
+        self.output.replace(digest);
+        self.finish(passed);
+    }
+}
+
+impl<'a, D: digest::Digest<'a, 32>> digest::ClientVerify<32> for DigestSelfTest<'a, D> {
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, compare: &'static mut [u8; 32]) {
+        self.output.replace(compare);
+    }
+}
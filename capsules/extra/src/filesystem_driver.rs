@@ -0,0 +1,479 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with access to the files of a filesystem.
+//!
+//! Processes share the files of a filesystem that implements
+//! `hil::filesystem::FileSystem`, such as `littlefs`. A file handle belongs
+//! to the process that opened it. The files that a process left open when it
+//! exited or restarted are closed, and thereby synced, by the driver.
+//!
+//! Each process has one command in progress at a time, and the commands of
+//! the processes run one after the other. Reads and writes are limited to
+//! the length of the kernel's data buffer, a process loops for longer ones.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let filesystem = components::littlefs::FileSystemDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::filesystem_driver::DRIVER_NUM,
+//!     fs,
+//! )
+//! .finalize(components::filesystem_driver_component_static!(
+//!     capsules_extra::littlefs::filesystem::LittleFs<'static, Flash, 4>,
+//!     256
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! Commands that finish later return success when they were queued, and
+//! schedule upcall 0 with the status, and two values given with the command,
+//! when they are done.
+//!
+//! File names are passed in read-only allow 0, data to write in read-only
+//! allow 1. Read data and names of directory entries are returned in
+//! read-write allow 0.
+
+use core::cell::Cell;
+
+use capsules_core::driver_info::{self, DriverInfo};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::filesystem::{self, DirEntry, EntryKind, FileSystem, OpenMode};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FileSystem as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const NAME: usize = 0;
+    pub const DATA: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const DATA: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcalls {
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Open(OpenMode),
+    Close(usize),
+    Read(usize, usize),
+    Write(usize, usize),
+    Remove,
+    ReadDir(usize),
+    Sync(usize),
+    Format,
+}
+
+#[derive(Default)]
+pub struct App {
+    pending: Option<Command>,
+    /// The handles of the files the process has open, a bit per handle.
+    files: u32,
+}
+
+pub struct FileSystemDriver<'a, FS: FileSystem<'a>> {
+    fs: &'a FS,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The process whose command runs.
+    current: OptionalCell<ProcessId>,
+    /// The handles of all files opened by processes.
+    open_files: Cell<u32>,
+    /// A file of a process that exited is being closed.
+    closing: Cell<bool>,
+    name_buffer: TakeCell<'static, [u8]>,
+    data_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, FS: FileSystem<'a>> FileSystemDriver<'a, FS> {
+    pub fn new(
+        fs: &'a FS,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        name_buffer: &'static mut [u8],
+        data_buffer: &'static mut [u8],
+    ) -> FileSystemDriver<'a, FS> {
+        FileSystemDriver {
+            fs,
+            apps: grant,
+            current: OptionalCell::empty(),
+            open_files: Cell::new(0),
+            closing: Cell::new(false),
+            name_buffer: TakeCell::new(name_buffer),
+            data_buffer: TakeCell::new(data_buffer),
+        }
+    }
+
+    fn start(&self, command: Command, kernel_data: &GrantKernelData) -> Result<(), ErrorCode> {
+        match command {
+            Command::Open(_) | Command::Remove => {
+                let name = self.name_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let len = kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .and_then(|buffer| {
+                        buffer.enter(|src| {
+                            let len = src.len().min(name.len());
+                            src[..len].copy_to_slice(&mut name[..len]);
+                            len
+                        })
+                    })
+                    .unwrap_or(0);
+                let result = match command {
+                    Command::Open(mode) => self.fs.open(name, len, mode),
+                    _ => self.fs.remove(name, len),
+                };
+                result.map_err(|(e, name)| {
+                    self.name_buffer.replace(name);
+                    e
+                })
+            }
+            Command::ReadDir(index) => {
+                let name = self.name_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                self.fs.read_dir(index, name).map_err(|(e, name)| {
+                    self.name_buffer.replace(name);
+                    e
+                })
+            }
+            Command::Read(file, len) => {
+                let data = self.data_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let allowed = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DATA)
+                    .map_or(0, |buffer| buffer.len());
+                self.fs
+                    .read(file, data, len.min(allowed))
+                    .map_err(|(e, data)| {
+                        self.data_buffer.replace(data);
+                        e
+                    })
+            }
+            Command::Write(file, len) => {
+                let data = self.data_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let len = kernel_data
+                    .get_readonly_processbuffer(ro_allow::DATA)
+                    .and_then(|buffer| {
+                        buffer.enter(|src| {
+                            let len = src.len().min(data.len()).min(len);
+                            src[..len].copy_to_slice(&mut data[..len]);
+                            len
+                        })
+                    })
+                    .unwrap_or(0);
+                self.fs.write(file, data, len).map_err(|(e, data)| {
+                    self.data_buffer.replace(data);
+                    e
+                })
+            }
+            Command::Close(file) => self.fs.close(file),
+            Command::Sync(file) => self.fs.sync(file),
+            Command::Format => self.fs.format(),
+        }
+    }
+
+    /// Starts the next queued command, after closing the files of processes
+    /// that exited.
+    fn check_queue(&self) {
+        if self.current.is_some() || self.closing.get() {
+            return;
+        }
+
+        let mut owned = 0;
+        for cntr in self.apps.iter() {
+            owned |= cntr.enter(|app, _| app.files);
+        }
+        let orphans = self.open_files.get() & !owned;
+        if orphans != 0 {
+            let file = orphans.trailing_zeros() as usize;
+            if self.fs.close(file).is_ok() {
+                self.closing.set(true);
+                return;
+            }
+            self.open_files.set(self.open_files.get() & !(1 << file));
+        }
+
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started = cntr.enter(|app, kernel_data| {
+                app.pending.take().map_or(false, |command| {
+                    match self.start(command, kernel_data) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            // The command was accepted when it was queued,
+                            // so the process learns about the failure from
+                            // the upcall.
+                            kernel_data
+                                .schedule_upcall(
+                                    upcalls::DONE,
+                                    (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+                                )
+                                .ok();
+                            false
+                        }
+                    }
+                })
+            });
+            if started {
+                self.current.set(processid);
+                break;
+            }
+        }
+    }
+
+    /// Ends the command of the current process with `result` and the values
+    /// of the upcall.
+    fn done(
+        &self,
+        result: Result<(), ErrorCode>,
+        values: (usize, usize),
+        f: impl FnOnce(&mut App),
+    ) {
+        self.current.take().map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                f(app);
+                kernel_data
+                    .schedule_upcall(
+                        upcalls::DONE,
+                        (
+                            kernel::errorcode::into_statuscode(result),
+                            values.0,
+                            values.1,
+                        ),
+                    )
+                    .ok();
+            });
+        });
+        self.check_queue();
+    }
+}
+
+impl<'a, FS: FileSystem<'a>> filesystem::Client for FileSystemDriver<'a, FS> {
+    fn formatted(&self, result: Result<(), ErrorCode>) {
+        // Formatting closes all files.
+        self.open_files.set(0);
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, _| app.files = 0);
+        }
+        self.done(result, (0, 0), |_| {});
+    }
+
+    fn opened(&self, result: Result<usize, ErrorCode>, name: &'static mut [u8]) {
+        self.name_buffer.replace(name);
+        match result {
+            Ok(file) if file < 32 => {
+                self.open_files.set(self.open_files.get() | 1 << file);
+                self.done(Ok(()), (file, 0), |app| app.files |= 1 << file);
+            }
+            Ok(file) => {
+                // Handles beyond the bitmasks can not be tracked.
+                let _ = self.fs.close(file);
+                self.closing.set(true);
+                self.done(Err(ErrorCode::NOMEM), (0, 0), |_| {});
+            }
+            Err(e) => self.done(Err(e), (0, 0), |_| {}),
+        }
+    }
+
+    fn closed(&self, file: usize, result: Result<(), ErrorCode>) {
+        self.open_files
+            .set(self.open_files.get() & !(1u32.checked_shl(file as u32).unwrap_or(0)));
+        if self.closing.take() {
+            self.check_queue();
+        } else {
+            self.done(result, (0, 0), |app| app.files &= !(1 << file));
+        }
+    }
+
+    fn synced(&self, _file: usize, result: Result<(), ErrorCode>) {
+        self.done(result, (0, 0), |_| {});
+    }
+
+    fn read_done(&self, _file: usize, buffer: &'static mut [u8], result: Result<usize, ErrorCode>) {
+        let len = *result.as_ref().unwrap_or(&0);
+        self.current.map(|processid| {
+            let _ = self.apps.enter(*processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::DATA)
+                    .and_then(|dest| {
+                        dest.mut_enter(|dest| {
+                            let len = len.min(dest.len());
+                            dest[..len].copy_from_slice(&buffer[..len]);
+                        })
+                    })
+            });
+        });
+        self.data_buffer.replace(buffer);
+        self.done(result.map(|_| ()), (len, 0), |_| {});
+    }
+
+    fn write_done(
+        &self,
+        _file: usize,
+        buffer: &'static mut [u8],
+        result: Result<usize, ErrorCode>,
+    ) {
+        self.data_buffer.replace(buffer);
+        let len = *result.as_ref().unwrap_or(&0);
+        self.done(result.map(|_| ()), (len, 0), |_| {});
+    }
+
+    fn removed(&self, result: Result<(), ErrorCode>, name: &'static mut [u8]) {
+        self.name_buffer.replace(name);
+        self.done(result, (0, 0), |_| {});
+    }
+
+    fn dir_entry(&self, result: Result<DirEntry, ErrorCode>, name: &'static mut [u8]) {
+        if let Ok(entry) = result {
+            self.current.map(|processid| {
+                let _ = self.apps.enter(*processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::DATA)
+                        .and_then(|dest| {
+                            dest.mut_enter(|dest| {
+                                let len = entry.name_len.min(name.len()).min(dest.len());
+                                dest[..len].copy_from_slice(&name[..len]);
+                            })
+                        })
+                });
+            });
+        }
+        self.name_buffer.replace(name);
+        let values = result.map_or((0, 0), |entry| {
+            let kind = match entry.kind {
+                EntryKind::File => 0,
+                EntryKind::Directory => 1,
+            };
+            (entry.name_len | kind << 16, entry.size as usize)
+        });
+        self.done(result.map(|_| ()), values, |_| {});
+    }
+}
+
+impl<'a, FS: FileSystem<'a>> SyscallDriver for FileSystemDriver<'a, FS> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Open the file named in read-only allow 0, to read it if `data1`
+    ///   is 0, to replace it if 1 and to append to it if 2. The upcall gets
+    ///   the handle.
+    /// - `2`: Close file `data1`.
+    /// - `3`: Read up to `data2` bytes of file `data1` into read-write allow
+    ///   0. The upcall gets the number of bytes read, 0 at the end of the
+    ///   file.
+    /// - `4`: Append up to `data2` bytes of read-only allow 1 to file
+    ///   `data1`. The upcall gets the number of bytes written.
+    /// - `5`: Move the read position of file `data1` to `data2`. Returns
+    ///   immediately.
+    /// - `6`: Return the size of file `data1`.
+    /// - `7`: Remove the file named in read-only allow 0.
+    /// - `8`: Copy the name of entry `data1` of the root directory into
+    ///   read-write allow 0. The upcall gets the length of the name, with
+    ///   bit 16 set for directories, and the size of a file. It reports
+    ///   `NODEVICE` after the last entry.
+    /// - `9`: Sync file `data1` to storage.
+    /// - `10`: Erase the storage and create an empty filesystem.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let owns = |file: usize| {
+            file < 32
+                && self
+                    .apps
+                    .enter(processid, |app, _| app.files & (1 << file) != 0)
+                    .unwrap_or(false)
+        };
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            driver_info::COMMAND_NUM => return DriverInfo::new(1).into(),
+            1 => match data1 {
+                0 => Command::Open(OpenMode::Read),
+                1 => Command::Open(OpenMode::Write),
+                2 => Command::Open(OpenMode::Append),
+                _ => return CommandReturn::failure(ErrorCode::INVAL),
+            },
+            2 | 3 | 4 | 5 | 6 | 9 if !owns(data1) => {
+                return CommandReturn::failure(ErrorCode::INVAL)
+            }
+            2 => Command::Close(data1),
+            3 => Command::Read(data1, data2),
+            4 => Command::Write(data1, data2),
+            5 => {
+                return match self.fs.seek(data1, data2 as u32) {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+            6 => {
+                return match self.fs.size(data1) {
+                    Ok(size) => CommandReturn::success_u32(size),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+            7 => Command::Remove,
+            8 => Command::ReadDir(data1),
+            9 => Command::Sync(data1),
+            10 => Command::Format,
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() || self.current.contains(&processid) {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.pending = Some(command);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match queued {
+            Ok(()) => {
+                self.check_queue();
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,460 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Write-back page cache for flash.
+//!
+//! `FlashCache` sits between a flash (`hil::flash::Flash`) and a storage stack
+//! such as TicKV, the log or `nonvolatile_to_pages`, and implements
+//! `hil::flash::Flash` itself. It holds up to `N` pages in RAM. Writes and
+//! erases only update the cached page and complete right away, so many small
+//! writes to the same page, or an erase followed by writes, end up as a
+//! single erase and write on flash. Reads of cached pages are served from RAM,
+//! other reads go to flash.
+//!
+//! Pages are written back when the cache needs a slot for another page, in
+//! least recently used order, and when the cache is flushed
+//! (`hil::flash::Flush`). Power-loss semantics are explicit: a write or erase
+//! that completed is only guaranteed to be on flash once a flush started
+//! after it completed. Pages are not written back in the order they were
+//! written, so a storage stack that relies on ordering has to flush between
+//! the writes that have to be ordered.
+//!
+//! The cache assumes that writing a page leaves it with exactly the written
+//! contents, i.e. that the storage stack writes whole pages, and erases them
+//! first where the flash needs it.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let flash_cache = components::flash_cache::FlashCacheComponent::new(flash_user)
+//!     .finalize(components::flash_cache_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>,
+//!         4
+//!     ));
+//! // Use `flash_cache` as the flash of the storage stack, e.g. TicKV.
+//! // Let processes using the KV driver flush the cache:
+//! kv_driver.set_flush(flash_cache);
+//! flash_cache.set_flush_client(kv_driver);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::flash::{self, Flash};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// A page held in the cache.
+struct Slot<F: Flash + 'static> {
+    page: TakeCell<'static, F::Page>,
+    /// Number of the page held in this slot.
+    number: OptionalCell<usize>,
+    /// The page has to be erased on flash.
+    erase: Cell<bool>,
+    /// The page has to be written to flash.
+    write: Cell<bool>,
+    /// Value of the cache's clock when the page was last used.
+    last_used: Cell<u32>,
+}
+
+impl<F: Flash + 'static> Slot<F> {
+    fn is_dirty(&self) -> bool {
+        self.erase.get() || self.write.get()
+    }
+}
+
+/// Operation requested by the client.
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Reading a page that is not cached for the client.
+    Reading,
+    /// Erasing the page of a slot on flash.
+    WriteBackErase(usize),
+    /// Writing the page of a slot to flash.
+    WriteBackWrite(usize),
+}
+
+pub struct FlashCache<'a, F: Flash + 'static, const N: usize> {
+    flash: &'a F,
+    slots: [Slot<F>; N],
+    /// Incremented on every access, to find the least recently used slot.
+    clock: Cell<u32>,
+    state: Cell<State>,
+    /// Operation of the client that did not start yet.
+    pending: Cell<Option<Op>>,
+    /// Operation of the client that completed, reported from the deferred
+    /// call.
+    completion: Cell<Option<(Op, flash::Error)>>,
+    client_buffer: TakeCell<'static, F::Page>,
+    flushing: Cell<bool>,
+    flush_result: OptionalCell<Result<(), ErrorCode>>,
+    client: OptionalCell<&'a dyn flash::Client<FlashCache<'a, F, N>>>,
+    flush_client: OptionalCell<&'a dyn flash::FlushClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, F: Flash + 'static, const N: usize> FlashCache<'a, F, N> {
+    pub fn new(flash: &'a F, pages: &'static mut [F::Page; N]) -> Self {
+        let mut pages = pages.iter_mut();
+        FlashCache {
+            flash,
+            slots: core::array::from_fn(|_| Slot {
+                page: TakeCell::new(pages.next().unwrap()),
+                number: OptionalCell::empty(),
+                erase: Cell::new(false),
+                write: Cell::new(false),
+                last_used: Cell::new(0),
+            }),
+            clock: Cell::new(0),
+            state: Cell::new(State::Idle),
+            pending: Cell::new(None),
+            completion: Cell::new(None),
+            client_buffer: TakeCell::empty(),
+            flushing: Cell::new(false),
+            flush_result: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            flush_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Returns the number of cached pages that are not on flash yet.
+    pub fn dirty_pages(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_dirty()).count()
+    }
+
+    fn is_busy(&self) -> bool {
+        self.pending.get().is_some()
+            || self.completion.get().is_some()
+            || self.state.get() == State::Reading
+    }
+
+    fn find(&self, number: usize) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.number.contains(&number))
+    }
+
+    /// Returns the least recently used slot among those matching `filter`.
+    fn least_recently_used<P: Fn(&Slot<F>) -> bool>(&self, filter: P) -> Option<usize> {
+        let now = self.clock.get();
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| filter(slot))
+            .max_by_key(|(_, slot)| now.wrapping_sub(slot.last_used.get()))
+            .map(|(index, _)| index)
+    }
+
+    /// Returns the slot holding page `number`, after assigning a free or
+    /// clean slot to it if it is not cached. Returns `None` if every slot is
+    /// dirty.
+    fn slot_for(&self, number: usize) -> Option<usize> {
+        if let Some(index) = self.find(number) {
+            return Some(index);
+        }
+        let index = self
+            .slots
+            .iter()
+            .position(|slot| slot.number.is_none())
+            .or_else(|| self.least_recently_used(|slot| !slot.is_dirty()))?;
+        let slot = &self.slots[index];
+        slot.number.set(number);
+        slot.erase.set(false);
+        slot.write.set(false);
+        Some(index)
+    }
+
+    fn touch(&self, slot: &Slot<F>) {
+        let now = self.clock.get().wrapping_add(1);
+        self.clock.set(now);
+        slot.last_used.set(now);
+    }
+
+    /// Start the pending operation of the client. This may first write back
+    /// a slot to make room, the operation then starts once that is done.
+    fn run_pending(&self) -> Result<(), ErrorCode> {
+        let op = match self.pending.get() {
+            Some(op) => op,
+            None => return Ok(()),
+        };
+
+        let number = match op {
+            Op::Write(number) | Op::Erase(number) => number,
+            Op::Read(number) => {
+                if let Some(index) = self.find(number) {
+                    let slot = &self.slots[index];
+                    self.touch(slot);
+                    slot.page.map(|page| {
+                        self.client_buffer
+                            .map(|buffer| buffer.as_mut().copy_from_slice(page.as_mut()));
+                    });
+                    self.complete(op, flash::Error::CommandComplete);
+                    return Ok(());
+                }
+                // Not cached, read it from flash into the client's buffer.
+                let buffer = self.client_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                self.state.set(State::Reading);
+                self.pending.set(None);
+                return self.flash.read_page(number, buffer).map_err(|(e, buffer)| {
+                    self.state.set(State::Idle);
+                    self.pending.set(Some(op));
+                    self.client_buffer.replace(buffer);
+                    e
+                });
+            }
+        };
+        let index = match self.slot_for(number) {
+            Some(index) => index,
+            None => {
+                // Every slot is dirty, make room first.
+                let victim = self.least_recently_used(|_| true).ok_or(ErrorCode::NOMEM)?;
+                return self.write_back(victim);
+            }
+        };
+
+        let slot = &self.slots[index];
+        self.touch(slot);
+        if let Op::Write(_) = op {
+            slot.page.map(|page| {
+                self.client_buffer
+                    .map(|buffer| page.as_mut().copy_from_slice(buffer.as_mut()));
+            });
+            slot.write.set(true);
+        } else {
+            slot.page.map(|page| page.as_mut().fill(0xFF));
+            slot.erase.set(true);
+            // The erase leaves the page as it is now cached.
+            slot.write.set(false);
+        }
+        self.complete(op, flash::Error::CommandComplete);
+        Ok(())
+    }
+
+    fn complete(&self, op: Op, error: flash::Error) {
+        self.pending.set(None);
+        self.completion.set(Some((op, error)));
+        self.deferred_call.set();
+    }
+
+    /// Start writing slot `index` back to flash.
+    fn write_back(&self, index: usize) -> Result<(), ErrorCode> {
+        let slot = &self.slots[index];
+        let number = slot.number.extract().ok_or(ErrorCode::FAIL)?;
+        if slot.erase.get() {
+            self.state.set(State::WriteBackErase(index));
+            self.flash.erase_page(number).map_err(|e| {
+                self.state.set(State::Idle);
+                e
+            })
+        } else {
+            let page = slot.page.take().ok_or(ErrorCode::BUSY)?;
+            self.state.set(State::WriteBackWrite(index));
+            self.flash.write_page(number, page).map_err(|(e, page)| {
+                self.state.set(State::Idle);
+                slot.page.replace(page);
+                e
+            })
+        }
+    }
+
+    /// Write back the next dirty slot while flushing.
+    fn flush_next(&self) {
+        let result = match self.slots.iter().position(|slot| slot.is_dirty()) {
+            Some(index) => match self.write_back(index) {
+                Ok(()) => return,
+                Err(_) => Err(ErrorCode::FAIL),
+            },
+            None => Ok(()),
+        };
+        self.flushing.set(false);
+        self.flush_result.set(result);
+        self.deferred_call.set();
+    }
+
+    /// Continue with the operations that waited for the flash.
+    fn resume(&self) {
+        if self.run_pending().is_err() {
+            self.pending
+                .get()
+                .map(|op| self.complete(op, flash::Error::FlashError));
+        }
+        if self.state.get() == State::Idle && self.flushing.get() {
+            self.flush_next();
+        }
+    }
+
+    fn write_back_failed(&self) {
+        self.state.set(State::Idle);
+        // The page stays dirty. Fail what waited for it to be written back.
+        self.pending
+            .get()
+            .map(|op| self.complete(op, flash::Error::FlashError));
+        if self.flushing.take() {
+            self.flush_result.set(Err(ErrorCode::FAIL));
+            self.deferred_call.set();
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static, C: flash::Client<Self>, const N: usize> flash::HasClient<'a, C>
+    for FlashCache<'a, F, N>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, F: Flash + 'static, const N: usize> Flash for FlashCache<'a, F, N> {
+    type Page = F::Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.is_busy() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.client_buffer.replace(buf);
+        self.pending.set(Some(Op::Read(page_number)));
+        if self.state.get() != State::Idle {
+            // Starts once the write back in progress is done.
+            return Ok(());
+        }
+        self.run_pending().map_err(|e| {
+            self.pending.set(None);
+            (e, self.client_buffer.take().unwrap())
+        })
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if self.is_busy() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        self.client_buffer.replace(buf);
+        self.pending.set(Some(Op::Write(page_number)));
+        if self.state.get() != State::Idle {
+            return Ok(());
+        }
+        self.run_pending().map_err(|e| {
+            self.pending.set(None);
+            (e, self.client_buffer.take().unwrap())
+        })
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pending.set(Some(Op::Erase(page_number)));
+        if self.state.get() != State::Idle {
+            return Ok(());
+        }
+        self.run_pending().map_err(|e| {
+            self.pending.set(None);
+            e
+        })
+    }
+}
+
+impl<'a, F: Flash + 'static, const N: usize> flash::Flush<'a> for FlashCache<'a, F, N> {
+    fn set_flush_client(&self, client: &'a dyn flash::FlushClient) {
+        self.flush_client.set(client);
+    }
+
+    fn flush(&self) -> Result<(), ErrorCode> {
+        if self.flushing.get() || self.flush_result.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.flushing.set(true);
+        if self.state.get() == State::Idle && self.pending.get().is_none() {
+            self.flush_next();
+        }
+        Ok(())
+    }
+}
+
+impl<'a, F: Flash + 'static, const N: usize> flash::Client<F> for FlashCache<'a, F, N> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, error: flash::Error) {
+        self.state.set(State::Idle);
+        self.client.map(move |client| {
+            client.read_complete(read_buffer, error);
+        });
+        if self.flushing.get() {
+            self.flush_next();
+        }
+    }
+
+    fn write_complete(&self, write_buffer: &'static mut F::Page, error: flash::Error) {
+        if let State::WriteBackWrite(index) = self.state.get() {
+            let slot = &self.slots[index];
+            slot.page.replace(write_buffer);
+            if error != flash::Error::CommandComplete {
+                self.write_back_failed();
+                return;
+            }
+            slot.write.set(false);
+            self.state.set(State::Idle);
+            self.resume();
+        }
+    }
+
+    fn erase_complete(&self, error: flash::Error) {
+        if let State::WriteBackErase(index) = self.state.get() {
+            if error != flash::Error::CommandComplete {
+                self.write_back_failed();
+                return;
+            }
+            let slot = &self.slots[index];
+            slot.erase.set(false);
+            self.state.set(State::Idle);
+            if slot.write.get() {
+                if self.write_back(index).is_err() {
+                    self.write_back_failed();
+                }
+                return;
+            }
+            self.resume();
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static, const N: usize> DeferredCallClient for FlashCache<'a, F, N> {
+    fn handle_deferred_call(&self) {
+        if let Some((op, error)) = self.completion.take() {
+            match op {
+                Op::Read(_) => self.client_buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.read_complete(buffer, error));
+                }),
+                Op::Write(_) => self.client_buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.write_complete(buffer, error));
+                }),
+                Op::Erase(_) => self.client.map(|client| client.erase_complete(error)),
+            };
+        }
+        self.flush_result.take().map(|result| {
+            self.flush_client.map(|client| client.flush_done(result));
+        });
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
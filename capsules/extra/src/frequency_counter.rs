@@ -0,0 +1,246 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with the frequency of a signal on a pin.
+//!
+//! The frequency is measured by counting the rising edges of the signal
+//! during a gate interval. This is useful for sensors that output a
+//! frequency, such as anemometers and flow meters, and for verifying clocks.
+//!
+//! The edges are counted by a `hil::counter::EdgeCounter`. Chips that can
+//! count edges in hardware (e.g. `nrf52::edge_counter`) handle signals of
+//! several MHz. On other chips, `GpioEdgeCounter` counts edges with GPIO
+//! interrupts, which is only suitable for signals up to about 1 kHz.
+//!
+//! The result is the number of edges and the measured frequency, computed
+//! from the time between starting and reading the counter, so alarm latency
+//! does not affect the accuracy. One measurement runs at a time.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let frequency_counter_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52::rtc::Rtc<'static>>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! frequency_counter_alarm.setup();
+//! let frequency_counter = static_init!(
+//!     capsules_extra::frequency_counter::FrequencyCounter<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52::rtc::Rtc<'static>>,
+//!         nrf52::edge_counter::EdgeCounter<'static>,
+//!     >,
+//!     capsules_extra::frequency_counter::FrequencyCounter::new(
+//!         edge_counter,
+//!         frequency_counter_alarm,
+//!         board_kernel.create_grant(
+//!             capsules_extra::frequency_counter::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! frequency_counter_alarm.set_alarm_client(frequency_counter);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::counter::EdgeCounter;
+use kernel::hil::gpio;
+use kernel::hil::time::{self, ConvertTicks, Frequency, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::FrequencyCounter as usize;
+
+/// Gate interval used if the process does not pick one.
+pub const DEFAULT_GATE_MS: u32 = 1000;
+/// Longest gate interval.
+pub const MAX_GATE_MS: u32 = 10_000;
+
+/// Counts rising edges with GPIO interrupts.
+pub struct GpioEdgeCounter<'a, IP: gpio::InterruptPin<'a>> {
+    pin: &'a IP,
+    counting: Cell<bool>,
+    count: Cell<u32>,
+}
+
+impl<'a, IP: gpio::InterruptPin<'a>> GpioEdgeCounter<'a, IP> {
+    pub fn new(pin: &'a IP) -> Self {
+        GpioEdgeCounter {
+            pin,
+            counting: Cell::new(false),
+            count: Cell::new(0),
+        }
+    }
+}
+
+impl<'a, IP: gpio::InterruptPin<'a>> EdgeCounter for GpioEdgeCounter<'a, IP> {
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.counting.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.count.set(0);
+        self.counting.set(true);
+        self.pin.make_input();
+        self.pin.enable_interrupts(gpio::InterruptEdge::RisingEdge);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.counting.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.pin.disable_interrupts();
+        self.counting.set(false);
+        Ok(())
+    }
+
+    fn count(&self) -> u32 {
+        self.count.get()
+    }
+
+    fn max_frequency_hz(&self) -> u32 {
+        // Depends on the interrupt latency, this is a safe estimate.
+        1000
+    }
+}
+
+impl<'a, IP: gpio::InterruptPin<'a>> gpio::Client for GpioEdgeCounter<'a, IP> {
+    fn fired(&self) {
+        if self.counting.get() {
+            self.count.set(self.count.get().wrapping_add(1));
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    subscribed: bool,
+}
+
+pub struct FrequencyCounter<'a, A: time::Alarm<'a>, C: EdgeCounter> {
+    counter: &'a C,
+    alarm: &'a A,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// When the counter was started, while a measurement is running.
+    start: OptionalCell<A::Ticks>,
+}
+
+impl<'a, A: time::Alarm<'a>, C: EdgeCounter> FrequencyCounter<'a, A, C> {
+    pub fn new(
+        counter: &'a C,
+        alarm: &'a A,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        FrequencyCounter {
+            counter,
+            alarm,
+            apps: grant,
+            start: OptionalCell::empty(),
+        }
+    }
+
+    fn measure(&self, gate_ms: u32, processid: ProcessId) -> CommandReturn {
+        let gate_ms = if gate_ms == 0 {
+            DEFAULT_GATE_MS
+        } else {
+            gate_ms
+        };
+        if gate_ms > MAX_GATE_MS {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        if self.start.is_some() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                if let Err(err) = self.counter.start() {
+                    return CommandReturn::failure(err);
+                }
+                let now = self.alarm.now();
+                self.start.set(now);
+                self.alarm.set_alarm(now, self.alarm.ticks_from_ms(gate_ms));
+                app.subscribed = true;
+                CommandReturn::success()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: EdgeCounter> time::AlarmClient for FrequencyCounter<'a, A, C> {
+    fn alarm(&self) {
+        let edges = self.counter.count();
+        let now = self.alarm.now();
+        let _ = self.counter.stop();
+
+        let result = self.start.take().map_or(Err(ErrorCode::FAIL), |start| {
+            let ticks = now.wrapping_sub(start).into_u32() as u64;
+            if ticks == 0 {
+                Err(ErrorCode::FAIL)
+            } else {
+                let frequency = edges as u64 * A::Frequency::frequency() as u64 / ticks;
+                Ok(frequency as u32)
+            }
+        });
+
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, upcalls| {
+                if app.subscribed {
+                    app.subscribed = false;
+                    let upcall = match result {
+                        Ok(frequency) => (0, frequency as usize, edges as usize),
+                        Err(err) => (kernel::errorcode::into_statuscode(Err(err)), 0, 0),
+                    };
+                    upcalls.schedule_upcall(0, upcall).ok();
+                }
+            });
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: EdgeCounter> SyscallDriver for FrequencyCounter<'a, A, C> {
+    // Setup callbacks.
+    //
+    // ### `subscribe_num`
+    //
+    // - `0`: Called once a measurement is done with the status, the frequency
+    //   in Hz, and the number of edges counted.
+
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Measure the frequency, counting edges for `data1` milliseconds.
+    ///   If `data1` is 0, the gate interval is `DEFAULT_GATE_MS`. Returns
+    ///   `BUSY` if a measurement is running and `INVAL` if `data1` is larger
+    ///   than `MAX_GATE_MS`.
+    /// - `2`: Return the highest frequency in Hz that can be measured.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.measure(data1 as u32, processid),
+
+            2 => CommandReturn::success_u32(self.counter.max_frequency_hz()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,258 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! SyscallDriver for the FT5336 Touch Panel.
+//!
+//! I2C Interface
+//!
+//! The FT5336 reports up to five touches. Its registers are laid out like
+//! the ones of the FT6x06: the gesture and the number of touches are
+//! followed by six bytes per touch. The controller is switched to trigger
+//! mode, so that it pulses its interrupt line for each new report.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ft5336 = components::ft5336::Ft5336Component::new(
+//!     mux_i2c,
+//!     0x38,
+//!     base_peripherals.gpio_ports.get_pin(stm32f746ng::gpio::PinId::PI13).unwrap(),
+//!     true,
+//! )
+//! .finalize(components::ft5336_component_static!(stm32f746ng::i2c::I2C));
+//! ft5336.init();
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+use kernel::hil::touch::{self, GestureEvent, TouchEvent, TouchStatus};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// The maximum number of touches that the FT5336 reports.
+pub const MAX_TOUCHES: usize = 5;
+
+/// Size of the buffer: the gesture, the number of touches and the touches.
+pub const BUFFER_LEN: usize = 2 + MAX_TOUCHES * 6;
+
+pub static NO_TOUCH: TouchEvent = TouchEvent {
+    id: 0,
+    x: 0,
+    y: 0,
+    status: TouchStatus::Released,
+    size: None,
+    pressure: None,
+};
+
+mod registers {
+    pub const GEST_ID: u8 = 0x01;
+    pub const G_MODE: u8 = 0xA4;
+}
+
+/// Value of `G_MODE` for an interrupt pulse per report.
+const TRIGGER_MODE: u8 = 0x01;
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    Configuring,
+    Reading,
+}
+
+pub struct Ft5336<'a, I: i2c::I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    swap_xy: bool,
+    touch_client: OptionalCell<&'a dyn touch::TouchClient>,
+    gesture_client: OptionalCell<&'a dyn touch::GestureClient>,
+    multi_touch_client: OptionalCell<&'a dyn touch::MultiTouchClient>,
+    state: Cell<State>,
+    num_touches: Cell<usize>,
+    buffer: TakeCell<'static, [u8]>,
+    events: TakeCell<'static, [TouchEvent]>,
+}
+
+impl<'a, I: i2c::I2CDevice> Ft5336<'a, I> {
+    /// `swap_xy` exchanges the coordinates, for panels whose X axis is along
+    /// the Y axis of the touch controller.
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        swap_xy: bool,
+        buffer: &'static mut [u8],
+        events: &'static mut [TouchEvent],
+    ) -> Ft5336<'a, I> {
+        Ft5336 {
+            i2c,
+            interrupt_pin,
+            swap_xy,
+            touch_client: OptionalCell::empty(),
+            gesture_client: OptionalCell::empty(),
+            multi_touch_client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            num_touches: Cell::new(0),
+            buffer: TakeCell::new(buffer),
+            events: TakeCell::new(events),
+        }
+    }
+
+    /// Switch the controller to trigger mode, and start listening for
+    /// touches.
+    pub fn init(&self) -> Result<(), ErrorCode> {
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            buffer[0] = registers::G_MODE;
+            buffer[1] = TRIGGER_MODE;
+            self.state.set(State::Configuring);
+            self.i2c.write(buffer, 2).map_err(|(err, buffer)| {
+                self.state.set(State::Idle);
+                self.buffer.replace(buffer);
+                err.into()
+            })
+        })
+    }
+
+    /// Decode touch `index` of a report read from `GEST_ID`.
+    fn touch_event(&self, buffer: &[u8], index: usize) -> Option<TouchEvent> {
+        let touch = buffer.get(2 + index * 6..2 + (index + 1) * 6)?;
+        let status = match touch[0] >> 6 {
+            0x00 => TouchStatus::Pressed,
+            0x01 => TouchStatus::Released,
+            0x02 => TouchStatus::Moved,
+            _ => return None,
+        };
+        let first = (((touch[0] & 0x0F) as u16) << 8) + (touch[1] as u16);
+        let second = (((touch[2] & 0x0F) as u16) << 8) + (touch[3] as u16);
+        let (x, y) = if self.swap_xy {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        Some(TouchEvent {
+            status,
+            x,
+            y,
+            id: (touch[2] >> 4) as usize,
+            pressure: Some(touch[4] as u16),
+            size: Some((touch[5] >> 4) as u16),
+        })
+    }
+
+    fn report(&self, buffer: &[u8]) {
+        let num_touches = usize::min((buffer[1] & 0x0F) as usize, MAX_TOUCHES);
+        self.num_touches.set(num_touches);
+
+        self.touch_client.map(|client| {
+            if let Some(event) = self.touch_event(buffer, 0) {
+                client.touch_event(event);
+            }
+        });
+        self.gesture_client.map(|client| {
+            let gesture_event = match buffer[0] {
+                0x10 => Some(GestureEvent::SwipeUp),
+                0x14 => Some(GestureEvent::SwipeRight),
+                0x18 => Some(GestureEvent::SwipeDown),
+                0x1C => Some(GestureEvent::SwipeLeft),
+                0x48 => Some(GestureEvent::ZoomIn),
+                0x49 => Some(GestureEvent::ZoomOut),
+                _ => None,
+            };
+            if let Some(gesture) = gesture_event {
+                client.gesture_event(gesture);
+            }
+        });
+        self.multi_touch_client.map(|client| {
+            self.events.map(|events| {
+                let mut len = 0;
+                for index in 0..num_touches {
+                    if let Some(event) = self.touch_event(buffer, index) {
+                        events[len] = event;
+                        len += 1;
+                    }
+                }
+                client.touch_events(events, len);
+            });
+        });
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> i2c::I2CClient for Ft5336<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        if self.state.get() == State::Reading && status.is_ok() {
+            self.report(buffer);
+        }
+        self.state.set(State::Idle);
+        self.buffer.replace(buffer);
+        self.interrupt_pin
+            .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> gpio::Client for Ft5336<'a, I> {
+    fn fired(&self) {
+        self.buffer.take().map(|buffer| {
+            self.interrupt_pin.disable_interrupts();
+
+            buffer[0] = registers::GEST_ID;
+            self.state.set(State::Reading);
+
+            match self.i2c.write_read(buffer, 1, BUFFER_LEN) {
+                Ok(()) => {}
+                Err((_err, buffer)) => {
+                    self.state.set(State::Idle);
+                    self.buffer.replace(buffer);
+                    self.interrupt_pin
+                        .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+                }
+            }
+        });
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> touch::Touch<'a> for Ft5336<'a, I> {
+    fn enable(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn touch::TouchClient) {
+        self.touch_client.replace(client);
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> touch::Gesture<'a> for Ft5336<'a, I> {
+    fn set_client(&self, client: &'a dyn touch::GestureClient) {
+        self.gesture_client.replace(client);
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> touch::MultiTouch<'a> for Ft5336<'a, I> {
+    fn enable(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        Ok(())
+    }
+
+    fn get_num_touches(&self) -> usize {
+        MAX_TOUCHES
+    }
+
+    fn get_touch(&self, index: usize) -> Option<TouchEvent> {
+        if index >= self.num_touches.get() {
+            return None;
+        }
+        self.buffer
+            .map_or(None, |buffer| self.touch_event(buffer, index))
+    }
+
+    fn set_client(&self, client: &'a dyn touch::MultiTouchClient) {
+        self.multi_touch_client.replace(client);
+    }
+}
@@ -16,25 +16,33 @@
 //! # use kernel::static_init;
 //!
 //! let fxos8700_i2c = static_init!(I2CDevice, I2CDevice::new(i2c_bus, 0x1e));
+//! let registers = static_init!(
+//!     I2CRegisterClient<'static>,
+//!     I2CRegisterClient::new(fxos8700_i2c, &mut capsules::fxos8700cq::BUF, 0)
+//! );
 //! let fxos8700 = static_init!(
 //!     capsules::fxos8700cq::Fxos8700cq<'static>,
-//!     capsules::fxos8700cq::Fxos8700cq::new(fxos8700_i2c,
-//!                                           &sam4l::gpio::PA[9], // Interrupt pin
-//!                                           &mut capsules::fxos8700cq::BUF));
-//! fxos8700_i2c.set_client(fxos8700);
+//!     capsules::fxos8700cq::Fxos8700cq::new(registers,
+//!                                           &sam4l::gpio::PA[9]) // Interrupt pin
+//! );
+//! fxos8700_i2c.set_client(registers);
+//! registers.set_client(fxos8700);
 //! sam4l::gpio::PA[9].set_client(fxos8700);
 //! ```
 
+use capsules_core::i2c_register::{I2CRegisterClient, RegisterClient};
 use core::cell::Cell;
 use kernel::hil;
 use kernel::hil::gpio;
-use kernel::hil::i2c::{Error, I2CClient, I2CDevice};
-use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
 
 /// Recommended buffer length for this driver.
 pub const BUF_LEN: usize = 6;
 
+/// Active bit of CTRL_REG1.
+const CTRL_REG1_ACTIVE: u8 = 1 << 0;
+
 #[allow(dead_code)]
 enum Registers {
     Status = 0x00,
@@ -182,112 +190,90 @@ enum State {
 }
 
 pub struct Fxos8700cq<'a> {
-    i2c: &'a dyn I2CDevice,
+    registers: &'a I2CRegisterClient<'a>,
     interrupt_pin1: &'a dyn gpio::InterruptPin<'a>,
     state: Cell<State>,
-    buffer: TakeCell<'static, [u8]>,
     callback: OptionalCell<&'a dyn hil::sensors::NineDofClient>,
 }
 
 impl<'a> Fxos8700cq<'a> {
     pub fn new(
-        i2c: &'a dyn I2CDevice,
+        registers: &'a I2CRegisterClient<'a>,
         interrupt_pin1: &'a dyn gpio::InterruptPin<'a>,
-        buffer: &'static mut [u8],
     ) -> Fxos8700cq<'a> {
         Fxos8700cq {
-            i2c: i2c,
+            registers: registers,
             interrupt_pin1: interrupt_pin1,
             state: Cell::new(State::Disabled),
-            buffer: TakeCell::new(buffer),
             callback: OptionalCell::empty(),
         }
     }
 
     fn start_read_accel(&self) -> Result<(), ErrorCode> {
-        if self.state.get() == State::Disabled {
-            self.interrupt_pin1.make_input(); // Need an interrupt pin
-            self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buf| {
-                self.i2c.enable();
-                // Configure the data ready interrupt.
-                buf[0] = Registers::CtrlReg4 as u8;
-                buf[1] = 1; // CtrlReg4 data ready interrupt
-                buf[2] = 1; // CtrlReg5 drdy on pin 1
-
-                if let Err((error, buf)) = self.i2c.write(buf, 3) {
-                    self.buffer.replace(buf);
-                    self.i2c.disable();
-                    Err(error.into())
-                } else {
-                    self.state.set(State::ReadAccelSetup);
-                    Ok(())
-                }
-            })
-        } else {
-            Err(ErrorCode::BUSY)
+        if self.state.get() != State::Disabled {
+            return Err(ErrorCode::BUSY);
         }
+        self.interrupt_pin1.make_input(); // Need an interrupt pin
+                                          // Configure the data ready interrupt: CtrlReg4 data ready interrupt,
+                                          // CtrlReg5 drdy on pin 1.
+        self.registers.write(Registers::CtrlReg4 as u8, &[1, 1])?;
+        self.state.set(State::ReadAccelSetup);
+        Ok(())
     }
 
     fn start_read_magnetometer(&self) -> Result<(), ErrorCode> {
-        if self.state.get() == State::Disabled {
-            self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buf| {
-                self.i2c.enable();
-                // Configure the magnetometer.
-                buf[0] = Registers::MCtrlReg1 as u8;
-                // Enable both accelerometer and magnetometer, and set one-shot read.
-                buf[1] = 0b00100011;
+        if self.state.get() != State::Disabled {
+            return Err(ErrorCode::BUSY);
+        }
+        // Enable both accelerometer and magnetometer, and set one-shot read.
+        self.registers
+            .write(Registers::MCtrlReg1 as u8, &[0b00100011])?;
+        self.state.set(State::ReadMagStart);
+        Ok(())
+    }
 
-                if let Err((error, buf)) = self.i2c.write(buf, 2) {
-                    self.buffer.replace(buf);
-                    self.i2c.disable();
-                    Err(error.into())
-                } else {
-                    self.state.set(State::ReadMagStart);
-                    Ok(())
-                }
-            })
-        } else {
-            Err(ErrorCode::BUSY)
+    fn read_accel_sample(&self) -> Result<(), ErrorCode> {
+        self.interrupt_pin1.disable_interrupts();
+        self.registers.read(Registers::OutXMsb as u8, 6)?;
+        self.state.set(State::ReadAccelReading);
+        Ok(())
+    }
+
+    /// Move to `next` if `result` started a transfer, otherwise reset.
+    fn next(&self, result: Result<(), ErrorCode>, next: State) {
+        match result {
+            Ok(()) => self.state.set(next),
+            Err(_) => self.fail(),
         }
     }
+
+    // If there's an I2C error, just reset and issue a callback
+    // with all 0s. Otherwise, if there's no sensor attached,
+    // it's possible to have nondeterministic behavior, where
+    // sometimes you get callbacks and sometimes you don't, based
+    // on whether a floating interrupt line triggers. -pal 3/19/21
+    fn fail(&self) {
+        self.interrupt_pin1.disable_interrupts();
+        self.state.set(State::Disabled);
+        self.callback.map(|cb| {
+            cb.callback(0, 0, 0);
+        });
+    }
 }
 
 impl gpio::Client for Fxos8700cq<'_> {
     fn fired(&self) {
-        self.buffer.take().map(|buffer| {
-            self.interrupt_pin1.disable_interrupts();
-
-            // When we get this interrupt we can read the sample.
-            self.i2c.enable();
-            buffer[0] = Registers::OutXMsb as u8;
-
-            // Upon success, this will trigger an upcall.
-            // As this particular upcall does not have any field
-            // for the status, we can ignore the error, as this
-            // yields to not scheduling the upcall.
-            if let Err((_error, buffer)) = self.i2c.write_read(buffer, 1, 6) {
-                self.buffer.replace(buffer);
-                self.i2c.disable();
-            } else {
-                self.state.set(State::ReadAccelReading);
-            }
-        });
+        // When we get this interrupt we can read the sample.
+        if self.state.get() == State::ReadAccelWaiting && self.read_accel_sample().is_err() {
+            self.fail();
+        }
     }
 }
 
-impl I2CClient for Fxos8700cq<'_> {
-    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
-        // If there's an I2C error, just reset and issue a callback
-        // with all 0s. Otherwise, if there's no sensor attached,
-        // it's possible to have nondeterministic behavior, where
-        // sometimes you get callbacks and sometimes you don't, based
-        // on whether a floating interrupt line triggers. -pal 3/19/21
-        if status != Ok(()) {
-            self.state.set(State::Disabled);
-            self.buffer.replace(buffer);
-            self.callback.map(|cb| {
-                cb.callback(0, 0, 0);
-            });
+impl RegisterClient for Fxos8700cq<'_> {
+    fn registers_written(&self, _reg: u8, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.fail();
             return;
         }
         match self.state.get() {
@@ -297,105 +283,71 @@ impl I2CClient for Fxos8700cq<'_> {
                     .enable_interrupts(gpio::InterruptEdge::FallingEdge);
 
                 // Enable the accelerometer.
-                buffer[0] = Registers::CtrlReg1 as u8;
-                buffer[1] = 1;
-
-                // The callback function has no error field,
-                // we can safely ignore the error value.
-                if let Err((_error, buffer)) = self.i2c.write(buffer, 2) {
-                    self.state.set(State::Disabled);
-                    self.buffer.replace(buffer);
-                    self.callback.map(|cb| {
-                        cb.callback(0, 0, 0);
-                    });
-                } else {
-                    self.state.set(State::ReadAccelWait);
-                }
+                let result = self.registers.modify(
+                    Registers::CtrlReg1 as u8,
+                    CTRL_REG1_ACTIVE,
+                    CTRL_REG1_ACTIVE,
+                );
+                self.next(result, State::ReadAccelWait);
             }
             State::ReadAccelWait => {
                 if self.interrupt_pin1.read() == false {
                     // Sample is already ready.
-                    self.interrupt_pin1.disable_interrupts();
-                    buffer[0] = Registers::OutXMsb as u8;
-
-                    // The callback function has no error field,
-                    // we can safely ignore the error value.
-                    if let Err((_error, buffer)) = self.i2c.write_read(buffer, 1, 6) {
-                        self.state.set(State::Disabled);
-                        self.buffer.replace(buffer);
-                        self.callback.map(|cb| {
-                            cb.callback(0, 0, 0);
-                        });
-                    } else {
-                        self.state.set(State::ReadAccelReading);
+                    if self.read_accel_sample().is_err() {
+                        self.fail();
                     }
                 } else {
                     // Wait for the interrupt to trigger
-                    self.buffer.replace(buffer);
-                    self.i2c.disable();
                     self.state.set(State::ReadAccelWaiting);
                 }
             }
-            State::ReadAccelReading => {
-                let x = (((buffer[0] as i16) << 8) | buffer[1] as i16) >> 2;
-                let y = (((buffer[2] as i16) << 8) | buffer[3] as i16) >> 2;
-                let z = (((buffer[4] as i16) << 8) | buffer[5] as i16) >> 2;
-
-                let x = ((x as isize) * 244) / 1000;
-                let y = ((y as isize) * 244) / 1000;
-                let z = ((z as isize) * 244) / 1000;
-
-                // Now put the chip into standby mode.
-                buffer[0] = Registers::CtrlReg1 as u8;
-                buffer[1] = 0; // Set the active bit to 0.
-
-                // The callback function has no error field,
-                // we can safely ignore the error value.
-                if let Err((_error, buffer)) = self.i2c.write(buffer, 2) {
-                    self.state.set(State::Disabled);
-                    self.buffer.replace(buffer);
-                    self.callback.map(|cb| {
-                        cb.callback(0, 0, 0);
-                    });
-                } else {
-                    self.state
-                        .set(State::ReadAccelDeactivating(x as i16, y as i16, z as i16));
-                }
-            }
             State::ReadAccelDeactivating(x, y, z) => {
-                self.i2c.disable();
                 self.state.set(State::Disabled);
-                self.buffer.replace(buffer);
                 self.callback.map(|cb| {
                     cb.callback(x as usize, y as usize, z as usize);
                 });
             }
             State::ReadMagStart => {
                 // One shot measurement taken, now read result.
-                buffer[0] = Registers::MOutXMsb as u8;
-                self.state.set(State::ReadMagValues);
+                let result = self.registers.read(Registers::MOutXMsb as u8, 6);
+                self.next(result, State::ReadMagValues);
+            }
+            _ => {}
+        }
+    }
 
-                // The callback function has no error field,
-                // we can safely ignore the error value.
-                if let Err((_error, buffer)) = self.i2c.write_read(buffer, 1, 6) {
-                    self.state.set(State::Disabled);
-                    self.buffer.replace(buffer);
-                    self.callback.map(|cb| {
-                        cb.callback(0, 0, 0);
-                    });
-                }
+    fn registers_read(&self, _reg: u8, data: &[u8], result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.fail();
+            return;
+        }
+        match self.state.get() {
+            State::ReadAccelReading => {
+                let x = i16::from_be_bytes([data[0], data[1]]) >> 2;
+                let y = i16::from_be_bytes([data[2], data[3]]) >> 2;
+                let z = i16::from_be_bytes([data[4], data[5]]) >> 2;
+
+                let x = ((x as isize) * 244) / 1000;
+                let y = ((y as isize) * 244) / 1000;
+                let z = ((z as isize) * 244) / 1000;
+
+                // Now put the chip into standby mode.
+                let result = self
+                    .registers
+                    .modify(Registers::CtrlReg1 as u8, CTRL_REG1_ACTIVE, 0);
+                self.next(
+                    result,
+                    State::ReadAccelDeactivating(x as i16, y as i16, z as i16),
+                );
             }
             State::ReadMagValues => {
-                let x = (((buffer[0] as u16) << 8) | buffer[1] as u16) as i16;
-                let y = (((buffer[2] as u16) << 8) | buffer[3] as u16) as i16;
-                let z = (((buffer[4] as u16) << 8) | buffer[5] as u16) as i16;
+                let x = i16::from_be_bytes([data[0], data[1]]);
+                let y = i16::from_be_bytes([data[2], data[3]]);
+                let z = i16::from_be_bytes([data[4], data[5]]);
 
                 // Can immediately return values as the one-shot mode automatically
                 // disables the fxo after taking the measurement.
-                self.i2c.disable();
                 self.state.set(State::Disabled);
-                self.buffer.replace(buffer);
-
                 self.callback
                     .map(|cb| cb.callback(x as usize, y as usize, z as usize));
             }
@@ -29,6 +29,7 @@
 //! }
 //! ```
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -158,6 +159,8 @@ impl<Port: hil::gpio_async::Port> SyscallDriver for GPIOAsync<'_, Port> {
     ///   interrupt, and 2 for a falling edge interrupt.
     /// - `8`: Disable an interrupt on a pin.
     /// - `9`: Disable a GPIO pin.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, with the number of
+    ///   ports as its count.
     fn command(
         &self,
         command_number: usize,
@@ -169,12 +172,15 @@ impl<Port: hil::gpio_async::Port> SyscallDriver for GPIOAsync<'_, Port> {
         let other = (data >> 16) & 0xFFFF;
         let ports = self.ports.as_ref();
 
-        // Special case command 0; everything else results in a process-owned,
-        // split-phase call.
+        // Special case command 0 and the description; everything else results
+        // in a process-owned, split-phase call.
         if command_number == 0 {
             // How many ports
             return CommandReturn::success_u32(ports.len() as u32);
         }
+        if command_number == driver_info::COMMAND_NUM {
+            return DriverInfo::new(1).count(ports.len()).into();
+        }
 
         // On any command other than 0, we check for ports length.
         if port >= ports.len() {
@@ -0,0 +1,444 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with a keyboard and a mouse to the USB host.
+//!
+//! Processes press keys, type text and move the pointer of a USB HID
+//! device with the keyboard and mouse reports of
+//! `usb::hid_composite::KEYBOARD_MOUSE_REPORT`. This lets a board act as an
+//! input device, e.g. a password manager that types a password into the
+//! host after the user confirmed it on the board.
+//!
+//! Text is typed with the US keyboard layout: every character is pressed
+//! and released, with shift held for upper case letters and symbols. Only
+//! printable ASCII, tab and newline can be typed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let (hid, hid_input) = components::hid_input::HidInputComponent::new(
+//!     board_kernel,
+//!     capsules_extra::hid_input::DRIVER_NUM,
+//!     &nrf52840_peripherals.usbd,
+//!     0x1915, // Nordic Semiconductor
+//!     0x503b,
+//!     strings,
+//! )
+//! .finalize(components::hid_input_component_static!(nrf52840::usbd::Usbd));
+//!
+//! hid.enable();
+//! hid.attach();
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! Commands return success when they were queued, and schedule upcall 0
+//! with the status when all their reports were sent. Text to type is passed
+//! in read-only allow 0.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::usb_hid;
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use crate::usb::hid_composite::{KEYBOARD_REPORT_ID, MOUSE_REPORT_ID};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::HidInput as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const TEXT: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for scheduled upcalls
+mod upcalls {
+    pub const DONE: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Left shift in the modifier byte of a keyboard report.
+const MODIFIER_LEFT_SHIFT: u8 = 0x02;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Key {
+        modifiers: u8,
+        key: u8,
+    },
+    Type {
+        len: usize,
+    },
+    Pointer {
+        buttons: u8,
+        dx: u8,
+        dy: u8,
+        wheel: u8,
+    },
+}
+
+/// Report of the current command being sent.
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    /// A key is pressed, it is released next.
+    Press,
+    /// The last report of the command.
+    Last,
+    /// Character `index` of the text is pressed.
+    TypePress { index: usize, len: usize },
+    /// Character `index` of the text is released.
+    TypeRelease { index: usize, len: usize },
+}
+
+#[derive(Default)]
+pub struct App {
+    pending: Option<Command>,
+}
+
+pub struct HidInput<'a, H: usb_hid::UsbHid<'a, [u8; 64]>> {
+    hid: &'a H,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<0>,
+    >,
+    /// The process whose command runs.
+    current: OptionalCell<ProcessId>,
+    step: Cell<Step>,
+    report: TakeCell<'static, [u8; 64]>,
+}
+
+/// Key code and modifiers to type `c` with the US keyboard layout.
+fn ascii_to_key(c: u8) -> Option<(u8, u8)> {
+    const SHIFTED_DIGITS: &[u8; 10] = b")!@#$%^&*(";
+    // Punctuation keys from key code 0x2d, unshifted and shifted.
+    const PUNCTUATION: &[u8; 12] = b"-=[]\\\0;'`,./";
+    const PUNCTUATION_SHIFTED: &[u8; 12] = b"_+{}|\0:\"~<>?";
+
+    match c {
+        b'a'..=b'z' => Some((0, 0x04 + c - b'a')),
+        b'A'..=b'Z' => Some((MODIFIER_LEFT_SHIFT, 0x04 + c - b'A')),
+        b'0' => Some((0, 0x27)),
+        b'1'..=b'9' => Some((0, 0x1e + c - b'1')),
+        b'\n' => Some((0, 0x28)),
+        b'\t' => Some((0, 0x2b)),
+        b' ' => Some((0, 0x2c)),
+        0 => None,
+        _ => {
+            if let Some(digit) = SHIFTED_DIGITS.iter().position(|&s| s == c) {
+                let key = if digit == 0 {
+                    0x27
+                } else {
+                    0x1e + digit as u8 - 1
+                };
+                Some((MODIFIER_LEFT_SHIFT, key))
+            } else if let Some(i) = PUNCTUATION.iter().position(|&p| p == c) {
+                Some((0, 0x2d + i as u8))
+            } else {
+                PUNCTUATION_SHIFTED
+                    .iter()
+                    .position(|&p| p == c)
+                    .map(|i| (MODIFIER_LEFT_SHIFT, 0x2d + i as u8))
+            }
+        }
+    }
+}
+
+impl<'a, H: usb_hid::UsbHid<'a, [u8; 64]>> HidInput<'a, H> {
+    pub fn new(
+        hid: &'a H,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<0>,
+        >,
+        report: &'static mut [u8; 64],
+    ) -> HidInput<'a, H> {
+        HidInput {
+            hid,
+            apps: grant,
+            current: OptionalCell::empty(),
+            step: Cell::new(Step::Last),
+            report: TakeCell::new(report),
+        }
+    }
+
+    /// Send a keyboard report with `key` pressed, or no key if it is 0.
+    fn send_key(&self, modifiers: u8, key: u8) -> Result<(), ErrorCode> {
+        self.send(&[KEYBOARD_REPORT_ID, modifiers, 0, key, 0, 0, 0, 0, 0])
+    }
+
+    fn send(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        let report = self.report.take().ok_or(ErrorCode::BUSY)?;
+        report[..data.len()].copy_from_slice(data);
+        self.hid
+            .send_buffer(report)
+            .map(|_| ())
+            .map_err(|(e, report)| {
+                self.report.replace(report);
+                e
+            })
+    }
+
+    /// Character `index` of the text of the current process.
+    fn text_char(kernel_data: &GrantKernelData, index: usize) -> Option<u8> {
+        kernel_data
+            .get_readonly_processbuffer(ro_allow::TEXT)
+            .and_then(|text| text.enter(|text| text.iter().nth(index).map(|c| c.get())))
+            .unwrap_or(None)
+    }
+
+    /// Press character `index` of the text of the current process.
+    fn press_char(&self, kernel_data: &GrantKernelData, index: usize) -> Result<(), ErrorCode> {
+        let (modifiers, key) = Self::text_char(kernel_data, index)
+            .and_then(ascii_to_key)
+            .ok_or(ErrorCode::INVAL)?;
+        self.send_key(modifiers, key)
+    }
+
+    fn start(&self, command: Command, kernel_data: &GrantKernelData) -> Result<(), ErrorCode> {
+        match command {
+            Command::Key { modifiers, key } => {
+                self.step.set(Step::Press);
+                self.send_key(modifiers, key)
+            }
+            Command::Type { len } => {
+                // Check the whole text first, so that it is not typed in
+                // part.
+                for index in 0..len {
+                    Self::text_char(kernel_data, index)
+                        .and_then(ascii_to_key)
+                        .ok_or(ErrorCode::INVAL)?;
+                }
+                self.step.set(Step::TypePress { index: 0, len });
+                self.press_char(kernel_data, 0)
+            }
+            Command::Pointer {
+                buttons,
+                dx,
+                dy,
+                wheel,
+            } => {
+                self.step.set(Step::Last);
+                self.send(&[MOUSE_REPORT_ID, buttons, dx, dy, wheel])
+            }
+        }
+    }
+
+    /// Starts the next queued command.
+    fn check_queue(&self) {
+        if self.current.is_some() {
+            return;
+        }
+
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started = cntr.enter(|app, kernel_data| {
+                app.pending.take().map_or(false, |command| {
+                    match self.start(command, kernel_data) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            // The command was accepted when it was queued,
+                            // so the process learns about the failure from
+                            // the upcall.
+                            kernel_data
+                                .schedule_upcall(
+                                    upcalls::DONE,
+                                    (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+                                )
+                                .ok();
+                            false
+                        }
+                    }
+                })
+            });
+            if started {
+                self.current.set(processid);
+                break;
+            }
+        }
+    }
+
+    /// Ends the command of the current process with `result`.
+    fn done(&self, result: Result<(), ErrorCode>) {
+        self.current.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        upcalls::DONE,
+                        (kernel::errorcode::into_statuscode(result), 0, 0),
+                    )
+                    .ok();
+            });
+        });
+        self.check_queue();
+    }
+
+    /// Send the next report of the current command, after the last one was
+    /// sent.
+    fn next(&self) -> Result<bool, ErrorCode> {
+        match self.step.get() {
+            Step::Press => {
+                self.step.set(Step::Last);
+                self.send_key(0, 0).map(|()| true)
+            }
+            Step::Last => Ok(false),
+            Step::TypePress { index, len } => {
+                self.step.set(Step::TypeRelease { index, len });
+                self.send_key(0, 0).map(|()| true)
+            }
+            Step::TypeRelease { index, len } if index + 1 < len => {
+                self.step.set(Step::TypePress {
+                    index: index + 1,
+                    len,
+                });
+                self.current
+                    .map_or(Err(ErrorCode::FAIL), |processid| {
+                        self.apps
+                            .enter(*processid, |_, kernel_data| {
+                                self.press_char(kernel_data, index + 1)
+                            })
+                            .unwrap_or_else(|err| Err(err.into()))
+                    })
+                    .map(|()| true)
+            }
+            Step::TypeRelease { .. } => Ok(false),
+        }
+    }
+}
+
+impl<'a, H: usb_hid::UsbHid<'a, [u8; 64]>> usb_hid::Client<'a, [u8; 64]> for HidInput<'a, H> {
+    fn packet_received(
+        &'a self,
+        _result: Result<(), ErrorCode>,
+        _buffer: &'static mut [u8; 64],
+        _endpoint: usize,
+    ) {
+        // There are no output reports.
+    }
+
+    fn packet_transmitted(
+        &'a self,
+        result: Result<(), ErrorCode>,
+        buffer: &'static mut [u8; 64],
+        _endpoint: usize,
+    ) {
+        self.report.replace(buffer);
+        match result.and_then(|()| self.next()) {
+            Ok(true) => {}
+            Ok(false) => self.done(Ok(())),
+            Err(e) => {
+                // Do not leave a key pressed.
+                if matches!(self.step.get(), Step::TypePress { .. }) {
+                    let _ = self.send_key(0, 0);
+                    self.step.set(Step::Last);
+                }
+                self.done(Err(e))
+            }
+        }
+    }
+
+    fn can_receive(&'a self) -> bool {
+        false
+    }
+}
+
+impl<'a, H: usb_hid::UsbHid<'a, [u8; 64]>> SyscallDriver for HidInput<'a, H> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Press and release key code `data2` (a HID usage of the
+    ///   keyboard page) with the modifiers in `data1` (bit 0 left control,
+    ///   bit 1 left shift, bit 2 left alt, bit 3 left GUI, bits 4 to 7 the
+    ///   right ones).
+    /// - `2`: Type the first `data1` characters of the text in read-only
+    ///   allow 0. Returns `INVAL` in the upcall if a character cannot be
+    ///   typed, before typing any.
+    /// - `3`: Move the pointer by the signed X and Y movements in bits 0 to 7
+    ///   and 8 to 15 of `data1`, and the wheel by bits 16 to 23, with the
+    ///   buttons in `data2` pressed (bit 0 left, bit 1 right, bit 2 middle).
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            1 => Command::Key {
+                modifiers: data1 as u8,
+                key: data2 as u8,
+            },
+            2 if data1 == 0 => return CommandReturn::failure(ErrorCode::INVAL),
+            2 => Command::Type { len: data1 },
+            3 => Command::Pointer {
+                buttons: data2 as u8,
+                dx: data1 as u8,
+                dy: (data1 >> 8) as u8,
+                wheel: (data1 >> 16) as u8,
+            },
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() || self.current.contains(&processid) {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.pending = Some(command);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match queued {
+            Ok(()) => {
+                self.check_queue();
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn us_layout() {
+        assert_eq!(ascii_to_key(b'a'), Some((0, 0x04)));
+        assert_eq!(ascii_to_key(b'Z'), Some((MODIFIER_LEFT_SHIFT, 0x1d)));
+        assert_eq!(ascii_to_key(b'1'), Some((0, 0x1e)));
+        assert_eq!(ascii_to_key(b'0'), Some((0, 0x27)));
+        assert_eq!(ascii_to_key(b'!'), Some((MODIFIER_LEFT_SHIFT, 0x1e)));
+        assert_eq!(ascii_to_key(b')'), Some((MODIFIER_LEFT_SHIFT, 0x27)));
+        assert_eq!(ascii_to_key(b'-'), Some((0, 0x2d)));
+        assert_eq!(ascii_to_key(b'?'), Some((MODIFIER_LEFT_SHIFT, 0x38)));
+        assert_eq!(ascii_to_key(b';'), Some((0, 0x33)));
+        assert_eq!(ascii_to_key(b'"'), Some((MODIFIER_LEFT_SHIFT, 0x34)));
+        assert_eq!(ascii_to_key(b'\n'), Some((0, 0x28)));
+        assert_eq!(ascii_to_key(0x7f), None);
+        assert_eq!(ascii_to_key(0), None);
+    }
+}
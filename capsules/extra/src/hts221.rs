@@ -61,25 +61,36 @@
 //! let hts221_i2c = static_init!(
 //!     capsules::virtual_i2c::I2CDevice,
 //!     capsules::virtual_i2c::I2CDevice::new(i2c_bus, 0x5f));
+//! let registers = static_init!(
+//!     I2CRegisterClient<'static>,
+//!     I2CRegisterClient::new(hts221_i2c, &mut capsules::hts221::BUFFER,
+//!         capsules::hts221::REG_AUTO_INCREMENT));
 //! let hts221 = static_init!(
 //!     capsules::hts221::Hts221<'static>,
-//!     capsules::hts221::Hts221::new(hts221_i2c,
-//!         &mut capsules::hts221::BUFFER));
-//! hts221_i2c.set_client(hts221);
+//!     capsules::hts221::Hts221::new(registers));
+//! hts221_i2c.set_client(registers);
+//! registers.set_client(hts221);
 //! ```
 
+use capsules_core::i2c_register::{field_get, I2CRegisterClient, RegisterClient};
 use core::cell::Cell;
-use kernel::hil::i2c::{self, I2CClient, I2CDevice};
 use kernel::hil::sensors::{HumidityClient, HumidityDriver, TemperatureClient, TemperatureDriver};
-use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::cells::OptionalCell;
 use kernel::ErrorCode;
 
-const REG_AUTO_INCREMENT: u8 = 1 << 7;
+/// Bit of the register address to read or write several registers.
+pub const REG_AUTO_INCREMENT: u8 = 1 << 7;
 const CTRL_REG1: u8 = 0x20;
 const STATUS_REG: u8 = 0x27;
 const HUMID0_REG: u8 = 0x28;
 const CALIB_REG_1ST: u8 = 0x30;
 
+const CTRL_REG1_PD: u8 = 1 << 7;
+const CTRL_REG1_BDU: u8 = 1 << 2;
+const CTRL_REG2_ONE_SHOT: u8 = 1 << 0;
+/// Humidity and temperature data available.
+const STATUS_REG_DATA_AVAILABLE: u8 = 0b11;
+
 #[derive(Copy, Clone, Debug)]
 struct CalibrationData {
     temp_slope: f32,
@@ -88,9 +99,8 @@ struct CalibrationData {
     humidity_intercept: f32,
 }
 
-pub struct Hts221<'a, I: I2CDevice> {
-    buffer: TakeCell<'static, [u8]>,
-    i2c: &'a I,
+pub struct Hts221<'a> {
+    registers: &'a I2CRegisterClient<'a>,
     temperature_client: OptionalCell<&'a dyn TemperatureClient>,
     humidity_client: OptionalCell<&'a dyn HumidityClient>,
     state: Cell<State>,
@@ -98,11 +108,10 @@ pub struct Hts221<'a, I: I2CDevice> {
     pending_humidity: Cell<bool>,
 }
 
-impl<'a, I: I2CDevice> Hts221<'a, I> {
-    pub fn new(i2c: &'a I, buffer: &'static mut [u8]) -> Self {
+impl<'a> Hts221<'a> {
+    pub fn new(registers: &'a I2CRegisterClient<'a>) -> Self {
         Hts221 {
-            buffer: TakeCell::new(buffer),
-            i2c,
+            registers,
             temperature_client: OptionalCell::empty(),
             humidity_client: OptionalCell::empty(),
             state: Cell::new(State::Reset),
@@ -118,41 +127,55 @@ impl<'a, I: I2CDevice> Hts221<'a, I> {
     //   2. There is calibration data already ([State::Idle])
     //   3. There is a reading already taking place
     fn start_reading(&self) -> Result<(), ErrorCode> {
-        self.buffer
-            .take()
-            .map(|buffer| {
-                self.i2c.enable();
-                match self.state.get() {
-                    State::Reset => {
-                        buffer[0] = REG_AUTO_INCREMENT | CALIB_REG_1ST;
+        match self.state.get() {
+            State::Reset => {
+                self.registers.read(CALIB_REG_1ST, 16)?;
+                self.state.set(State::Calibrating);
+            }
+            State::Idle(calibration_data, _, _) => {
+                self.start_one_shot()?;
+                self.state.set(State::InitiateReading(calibration_data));
+            }
+            _ => return Err(ErrorCode::BUSY),
+        }
+        Ok(())
+    }
 
-                        if let Err((_error, buffer)) = self.i2c.write_read(buffer, 1, 16) {
-                            self.buffer.replace(buffer);
-                            self.i2c.disable();
-                        } else {
-                            self.state.set(State::Calibrating);
-                        }
-                    }
-                    State::Idle(calibration_data, _, _) => {
-                        buffer[0] = REG_AUTO_INCREMENT | CTRL_REG1;
-                        buffer[1] = 1 << 2 | 1 << 7; // BDU + PD
-                        buffer[2] = 1; // ONE SHOT
+    fn start_one_shot(&self) -> Result<(), ErrorCode> {
+        // CTRL_REG1 and CTRL_REG2
+        self.registers.write(
+            CTRL_REG1,
+            &[CTRL_REG1_BDU | CTRL_REG1_PD, CTRL_REG2_ONE_SHOT],
+        )
+    }
 
-                        if let Err((_error, buffer)) = self.i2c.write(buffer, 3) {
-                            self.buffer.replace(buffer);
-                            self.i2c.disable();
-                        } else {
-                            self.state.set(State::InitiateReading(calibration_data));
-                        }
-                    }
-                    _ => {} // Should really never happen since we only have `buffer` available in the above two states
-                }
-            })
-            .ok_or(ErrorCode::BUSY)
+    /// Move to `next` if `result` started a transfer, otherwise report the
+    /// error.
+    fn next(&self, result: Result<(), ErrorCode>, next: State) {
+        match result {
+            Ok(()) => self.state.set(next),
+            Err(error) => self.fail(error),
+        }
+    }
+
+    fn fail(&self, error: ErrorCode) {
+        self.state.set(State::Idle(
+            CalibrationData {
+                temp_slope: 0.0,
+                temp_intercept: 0.0,
+                humidity_slope: 0.0,
+                humidity_intercept: 0.0,
+            },
+            0,
+            0,
+        ));
+        self.temperature_client
+            .map(|client| client.callback(Err(error)));
+        self.humidity_client.map(|client| client.callback(0));
     }
 }
 
-impl<'a, I: I2CDevice> TemperatureDriver<'a> for Hts221<'a, I> {
+impl<'a> TemperatureDriver<'a> for Hts221<'a> {
     fn set_client(&self, client: &'a dyn TemperatureClient) {
         self.temperature_client.set(client);
     }
@@ -167,7 +190,7 @@ impl<'a, I: I2CDevice> TemperatureDriver<'a> for Hts221<'a, I> {
     }
 }
 
-impl<'a, I: I2CDevice> HumidityDriver<'a> for Hts221<'a, I> {
+impl<'a> HumidityDriver<'a> for Hts221<'a> {
     fn set_client(&self, client: &'a dyn HumidityClient) {
         self.humidity_client.set(client);
     }
@@ -192,179 +215,88 @@ enum State {
     Idle(CalibrationData, i32, usize),
 }
 
-impl<'a, I: I2CDevice> I2CClient for Hts221<'a, I> {
-    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
-        if let Err(i2c_err) = status {
-            self.state.set(State::Idle(
-                CalibrationData {
-                    temp_slope: 0.0,
-                    temp_intercept: 0.0,
-                    humidity_slope: 0.0,
-                    humidity_intercept: 0.0,
-                },
-                0,
-                0,
-            ));
-            self.buffer.replace(buffer);
-            self.temperature_client
-                .map(|client| client.callback(Err(i2c_err.into())));
-            self.humidity_client.map(|client| client.callback(0));
+impl RegisterClient for Hts221<'_> {
+    fn registers_read(&self, _reg: u8, data: &[u8], result: Result<(), ErrorCode>) {
+        if let Err(error) = result {
+            self.fail(error);
             return;
         }
 
         match self.state.get() {
             State::Calibrating => {
-                let h0rh = buffer[0] as f32;
-                let h1rh = buffer[1] as f32;
-                let h0t0out = ((buffer[6] as i16) | ((buffer[7] as i16) << 8)) as f32;
-                let h1t0out = ((buffer[10] as i16) | ((buffer[11] as i16) << 8)) as f32;
+                let h0rh = data[0] as f32;
+                let h1rh = data[1] as f32;
+                let h0t0out = i16::from_le_bytes([data[6], data[7]]) as f32;
+                let h1t0out = i16::from_le_bytes([data[10], data[11]]) as f32;
 
                 let humidity_slope = (h1rh - h0rh) / (2.0 * (h1t0out - h0t0out));
                 let humidity_intercept = (h0rh / 2.0) - humidity_slope * h0t0out;
 
-                let t0deg_c = ((buffer[2] as i16) | (((buffer[5] & 0b11) as i16) << 8)) as f32;
-                let t1deg_c = ((buffer[3] as i16) | (((buffer[5] & 0b1100) as i16) << 6)) as f32;
+                let t0deg_c =
+                    ((data[2] as i16) | ((field_get(data[5], 0b0011) as i16) << 8)) as f32;
+                let t1deg_c =
+                    ((data[3] as i16) | ((field_get(data[5], 0b1100) as i16) << 8)) as f32;
 
-                let t0out = ((buffer[12] as i16) | ((buffer[13] as i16) << 8)) as f32;
-                let t1out = ((buffer[14] as i16) | ((buffer[15] as i16) << 8)) as f32;
+                let t0out = i16::from_le_bytes([data[12], data[13]]) as f32;
+                let t1out = i16::from_le_bytes([data[14], data[15]]) as f32;
 
                 let temp_slope = (t1deg_c - t0deg_c) / (8.0 * (t1out - t0out));
                 let temp_intercept = (t0deg_c / 8.0) - temp_slope * t0out;
 
-                buffer[0] = REG_AUTO_INCREMENT | CTRL_REG1;
-                buffer[1] = 1 << 2 | 1 << 7; // BDU + PD
-                buffer[2] = 1; // ONE SHOT
-
-                if let Err((error, buffer)) = self.i2c.write(buffer, 3) {
-                    self.state.set(State::Idle(
-                        CalibrationData {
-                            temp_slope: 0.0,
-                            temp_intercept: 0.0,
-                            humidity_slope: 0.0,
-                            humidity_intercept: 0.0,
-                        },
-                        0,
-                        0,
-                    ));
-                    self.buffer.replace(buffer);
-                    self.temperature_client
-                        .map(|client| client.callback(Err(error.into())));
-                    self.humidity_client.map(|client| client.callback(0));
-                } else {
-                    self.state.set(State::InitiateReading(CalibrationData {
+                let result = self.start_one_shot();
+                self.next(
+                    result,
+                    State::InitiateReading(CalibrationData {
                         temp_slope,
                         temp_intercept,
                         humidity_slope,
                         humidity_intercept,
-                    }));
-                }
-            }
-            State::InitiateReading(calibration_data) => {
-                buffer[0] = STATUS_REG;
-
-                if let Err((error, buffer)) = self.i2c.write_read(buffer, 1, 1) {
-                    self.state.set(State::Idle(
-                        CalibrationData {
-                            temp_slope: 0.0,
-                            temp_intercept: 0.0,
-                            humidity_slope: 0.0,
-                            humidity_intercept: 0.0,
-                        },
-                        0,
-                        0,
-                    ));
-                    self.buffer.replace(buffer);
-                    self.temperature_client
-                        .map(|client| client.callback(Err(error.into())));
-                    self.humidity_client.map(|client| client.callback(0));
-                } else {
-                    self.state.set(State::CheckStatus(calibration_data));
-                }
+                    }),
+                );
             }
             State::CheckStatus(calibration_data) => {
-                if buffer[0] & 0b11 == 0b11 {
-                    buffer[0] = REG_AUTO_INCREMENT | HUMID0_REG;
-
-                    if let Err((error, buffer)) = self.i2c.write_read(buffer, 1, 4) {
-                        self.state.set(State::Idle(
-                            CalibrationData {
-                                temp_slope: 0.0,
-                                temp_intercept: 0.0,
-                                humidity_slope: 0.0,
-                                humidity_intercept: 0.0,
-                            },
-                            0,
-                            0,
-                        ));
-                        self.buffer.replace(buffer);
-                        self.temperature_client
-                            .map(|client| client.callback(Err(error.into())));
-                        self.humidity_client.map(|client| client.callback(0));
-                    } else {
-                        self.state.set(State::Read(calibration_data));
-                    }
+                if data[0] & STATUS_REG_DATA_AVAILABLE == STATUS_REG_DATA_AVAILABLE {
+                    let result = self.registers.read(HUMID0_REG, 4);
+                    self.next(result, State::Read(calibration_data));
                 } else {
-                    buffer[0] = STATUS_REG;
-
-                    if let Err((error, buffer)) = self.i2c.write_read(buffer, 1, 1) {
-                        self.state.set(State::Idle(
-                            CalibrationData {
-                                temp_slope: 0.0,
-                                temp_intercept: 0.0,
-                                humidity_slope: 0.0,
-                                humidity_intercept: 0.0,
-                            },
-                            0,
-                            0,
-                        ));
-                        self.buffer.replace(buffer);
-                        self.temperature_client
-                            .map(|client| client.callback(Err(error.into())));
-                        self.humidity_client.map(|client| client.callback(0));
-                    }
+                    let result = self.registers.read(STATUS_REG, 1);
+                    self.next(result, State::CheckStatus(calibration_data));
                 }
             }
             State::Read(calibration_data) => {
-                let humidity_raw = ((buffer[0] as i16) | ((buffer[1] as i16) << 8)) as f32;
+                let humidity_raw = i16::from_le_bytes([data[0], data[1]]) as f32;
                 let humidity = ((humidity_raw * calibration_data.humidity_slope
                     + calibration_data.humidity_intercept)
                     * 100.0) as usize;
 
-                let temperature_raw = ((buffer[2] as i16) | ((buffer[3] as i16) << 8)) as f32;
+                let temperature_raw = i16::from_le_bytes([data[2], data[3]]) as f32;
                 let temperature = ((temperature_raw * calibration_data.temp_slope
                     + calibration_data.temp_intercept)
                     * 100.0) as i32;
-                buffer[0] = CTRL_REG1;
                 // TODO(alevy): this is a workaround for a bug. We should be able to turn
                 // off the the sensor between transactions, and turn it back on (as is done
                 // in [start_reading]), but doing so seems not to work and the sensor's
                 // Status register never updates to read after the first transaction. For
                 // now, leave it on and waste 2uA.
-                buffer[1] = 1 << 7; // Leave PD bit on
+                let result = self.registers.write(CTRL_REG1, &[CTRL_REG1_PD]);
+                self.next(result, State::Idle(calibration_data, temperature, humidity));
+            }
+            _ => {}
+        }
+    }
 
-                if let Err((error, buffer)) = self.i2c.write(buffer, 2) {
-                    self.state.set(State::Idle(
-                        CalibrationData {
-                            temp_slope: 0.0,
-                            temp_intercept: 0.0,
-                            humidity_slope: 0.0,
-                            humidity_intercept: 0.0,
-                        },
-                        0,
-                        0,
-                    ));
-                    self.buffer.replace(buffer);
-                    self.temperature_client
-                        .map(|client| client.callback(Err(error.into())));
-                    self.humidity_client.map(|client| client.callback(0));
-                } else {
-                    self.state
-                        .set(State::Idle(calibration_data, temperature, humidity));
-                }
+    fn registers_written(&self, _reg: u8, result: Result<(), ErrorCode>) {
+        if let Err(error) = result {
+            self.fail(error);
+            return;
+        }
+
+        match self.state.get() {
+            State::InitiateReading(calibration_data) => {
+                let result = self.registers.read(STATUS_REG, 1);
+                self.next(result, State::CheckStatus(calibration_data));
             }
             State::Idle(_, temperature, humidity) => {
-                self.buffer.replace(buffer);
-                self.i2c.disable();
                 if self.pending_temperature.get() {
                     self.pending_temperature.set(false);
                     self.temperature_client
@@ -375,7 +307,7 @@ impl<'a, I: I2CDevice> I2CClient for Hts221<'a, I> {
                     self.humidity_client.map(|client| client.callback(humidity));
                 }
             }
-            State::Reset => {} // should never happen
+            _ => {}
         }
     }
 }
@@ -26,7 +26,14 @@
 //! operation, currently the following cmd's are supported:
 //!
 //! * `0`: check whether the driver exist
-//! * `1`: read humidity
+//! * `1`: read humidity of sensor `data1`
+//! * `2`: return the number of sensors
+//! * `driver_info::COMMAND_NUM`: describe the driver as in
+//!   `capsules_core::driver_info`, with the number of sensors as its count
+//!
+//! The reading is passed to the upcall as described in
+//! `capsules_core::sensor_upcall`, in hundredths of percent of relative
+//! humidity.
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
@@ -36,6 +43,7 @@
 //! * `ENOSUPPORT`: Invalid `cmd`.
 //! * `NOMEM`:     No sufficient memory available.
 //! * `INVAL`:     Invalid address of the buffer or other error.
+//! * `NODEVICE`:  There is no sensor `data1`.
 //!
 //! Usage
 //! -----
@@ -45,15 +53,24 @@
 //! ```rust
 //! # use kernel::static_init;
 //!
+//! let sensors = static_init!(
+//!     [&'static dyn kernel::hil::sensors::HumidityDriver<'static>; 1],
+//!     [si7021]
+//! );
 //! let humidity = static_init!(
 //!        capsules::humidity::HumiditySensor<'static>,
-//!        capsules::humidity::HumiditySensor::new(si7021,
+//!        capsules::humidity::HumiditySensor::new(sensors,
 //!                                                board_kernel.create_grant(&grant_cap)));
 //! kernel::hil::sensors::HumidityDriver::set_client(si7021, humidity);
 //! ```
+//!
+//! Sensor `i` is element `i` of `sensors`. As only one reading is taken at a
+//! time, the sensors can share the capsule as their client.
 
 use core::cell::Cell;
 
+use capsules_core::driver_info::{self, DriverInfo};
+use capsules_core::sensor_upcall::{self, Unit};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -75,18 +92,18 @@ pub struct App {
 }
 
 pub struct HumiditySensor<'a> {
-    driver: &'a dyn hil::sensors::HumidityDriver<'a>,
+    drivers: &'a [&'a dyn hil::sensors::HumidityDriver<'a>],
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     busy: Cell<bool>,
 }
 
 impl<'a> HumiditySensor<'a> {
     pub fn new(
-        driver: &'a dyn hil::sensors::HumidityDriver<'a>,
+        drivers: &'a [&'a dyn hil::sensors::HumidityDriver<'a>],
         grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     ) -> HumiditySensor<'a> {
         HumiditySensor {
-            driver: driver,
+            drivers: drivers,
             apps: grant,
             busy: Cell::new(false),
         }
@@ -103,7 +120,12 @@ impl<'a> HumiditySensor<'a> {
                 if !self.busy.get() {
                     app.subscribed = true;
                     self.busy.set(true);
-                    self.call_driver(command, arg1)
+                    let result = self.call_driver(command, arg1);
+                    if result.is_err() {
+                        app.subscribed = false;
+                        self.busy.set(false);
+                    }
+                    result.into()
                 } else {
                     CommandReturn::failure(ErrorCode::BUSY)
                 }
@@ -111,10 +133,11 @@ impl<'a> HumiditySensor<'a> {
             .unwrap_or_else(|err| CommandReturn::failure(err.into()))
     }
 
-    fn call_driver(&self, command: HumidityCommand, _: usize) -> CommandReturn {
+    fn call_driver(&self, command: HumidityCommand, instance: usize) -> Result<(), ErrorCode> {
+        let driver = self.drivers.get(instance).ok_or(ErrorCode::NODEVICE)?;
         match command {
-            HumidityCommand::ReadHumidity => self.driver.read_humidity().into(),
-            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            HumidityCommand::ReadHumidity => driver.read_humidity(),
+            _ => Err(ErrorCode::NOSUPPORT),
         }
     }
 }
@@ -126,7 +149,12 @@ impl hil::sensors::HumidityClient for HumiditySensor<'_> {
                 if app.subscribed {
                     self.busy.set(false);
                     app.subscribed = false;
-                    upcalls.schedule_upcall(0, (tmp_val, 0, 0)).ok();
+                    upcalls
+                        .schedule_upcall(
+                            0,
+                            sensor_upcall::encode(Unit::RelativeHumidity, -2, &[tmp_val as i32]),
+                        )
+                        .ok();
                 }
             });
         }
@@ -145,9 +173,15 @@ impl SyscallDriver for HumiditySensor<'_> {
             // check whether the driver exist!!
             0 => CommandReturn::success(),
 
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1).count(self.drivers.len()).into(),
+
             // single humidity measurement
             1 => self.enqueue_command(HumidityCommand::ReadHumidity, arg1, processid),
 
+            // number of sensors
+            2 => CommandReturn::success_u32(self.drivers.len() as u32),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -0,0 +1,482 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Coordinated sampled listening (CSL) MAC layer for sleepy 802.15.4
+//! receivers.
+//!
+//! With CSL (IEEE 802.15.4-2015 6.12.2.6), a node keeps its radio off and
+//! listens in a short receive window once every period. Each frame it sends
+//! carries a CSL IE with the period and the phase, the time until the middle
+//! of its next window, from which its parent, e.g. a Thread router, schedules
+//! the frames it sends to the node. With a period of a few hundred
+//! milliseconds, the radio is on for well under one percent of the time, yet
+//! downlink frames arrive without the node polling for them.
+//!
+//! The windows are timed with an alarm, which should be a low power one such
+//! as a virtual alarm on the RTC, and the radio is powered with
+//! `RadioConfig::start()` and `stop()` between them. A window stays open
+//! until a frame that is being received or sent is done.
+//!
+//! The framer adds the CSL IE to the data frames it prepares while the
+//! period is set, and this layer refreshes the phase of unsecured frames
+//! just before sending them. The phase of a secured frame is the one of
+//! when it was prepared, as the IE is authenticated, so the window should
+//! be wide enough for the time it takes to secure and send a frame.
+//!
+//! Usage
+//! -----
+//! This capsule implements the `capsules::ieee802154::mac::Mac` interface in
+//! place of an `AwakeMac`, and `kernel::hil::radio::RadioCsl` to configure
+//! the windows:
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! type CslDevice = capsules::ieee802154::csl::CslMac<
+//!     'static,
+//!     nrf52840::ieee802154_radio::Radio<'static>,
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc<'static>>,
+//! >;
+//!
+//! let csl_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! csl_alarm.setup();
+//! let csl = static_init!(CslDevice, CslMac::new(radio, csl_alarm));
+//! csl_alarm.set_alarm_client(csl);
+//! radio.set_transmit_client(csl);
+//! radio.set_receive_client(csl, &mut RADIO_RX_BUF);
+//! radio.set_power_client(csl);
+//!
+//! // Listen for 2 ms every 500 ms.
+//! csl.set_csl_period(3125, 2000);
+//! ```
+
+use crate::ieee802154::mac::Mac;
+use crate::net::ieee802154::{Header, HeaderIE, MacAddress};
+use core::cell::Cell;
+use kernel::hil::radio::{self, RadioCsl, CSL_UNIT_US};
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Element ID of the CSL header IE.
+pub const CSL_IE_ID: u8 = 0x1a;
+/// Length of the content of the CSL IE: the phase and the period.
+pub const CSL_IE_LEN: usize = 4;
+
+const BROADCAST_ADDR: u16 = 0xffff;
+
+/// The content of a CSL IE.
+pub fn encode_ie(phase: u16, period: u16) -> [u8; CSL_IE_LEN] {
+    let phase = phase.to_le_bytes();
+    let period = period.to_le_bytes();
+    [phase[0], phase[1], period[0], period[1]]
+}
+
+/// The phase and period in the content of a CSL IE.
+pub fn decode_ie(content: &[u8]) -> Option<(u16, u16)> {
+    if content.len() < CSL_IE_LEN {
+        return None;
+    }
+    Some((
+        u16::from_le_bytes([content[0], content[1]]),
+        u16::from_le_bytes([content[2], content[3]]),
+    ))
+}
+
+/// The offset of the content of the CSL IE in `frame`, a buffer starting
+/// `radio::PSDU_OFFSET` bytes before the MAC header, if the frame has one
+/// and is not secured.
+fn unsecured_ie_offset(frame: &[u8]) -> Option<usize> {
+    let (_, (header, _)) = Header::decode(&frame[radio::PSDU_OFFSET..], false).done()?;
+    if header.security.is_some() {
+        return None;
+    }
+    header.header_ies[..header.header_ies_len]
+        .iter()
+        .find_map(|ie| match *ie {
+            HeaderIE::Undissected {
+                element_id,
+                content,
+            } if element_id == CSL_IE_ID && content.len() == CSL_IE_LEN => {
+                Some(content.as_ptr() as usize - frame.as_ptr() as usize)
+            }
+            _ => None,
+        })
+}
+
+/// Whether the time `elapsed` after a sample time falls into the receive
+/// window centred on the sample times, which repeat every `period`, and the
+/// time until the window closes or opens next.
+fn window_edge(elapsed: u32, period: u32, window: u32) -> (bool, u32) {
+    let offset = elapsed.wrapping_add(window / 2) % period;
+    if offset < window {
+        (true, window - offset)
+    } else {
+        (false, period - offset)
+    }
+}
+
+pub struct CslMac<'a, R: radio::Radio<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    tx_client: OptionalCell<&'a dyn radio::TxClient>,
+    rx_client: OptionalCell<&'a dyn radio::RxClient>,
+
+    /// The period, in units of `CSL_UNIT_US`, or 0 to listen continuously.
+    period: Cell<u16>,
+    window_us: Cell<u32>,
+    /// A sample time, in the middle of a receive window.
+    anchor: Cell<A::Ticks>,
+    listening: Cell<bool>,
+
+    /// A frame waiting for the radio to start.
+    tx_pending: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    transmitting: Cell<bool>,
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> CslMac<'a, R, A> {
+    pub fn new(radio: &'a R, alarm: &'a A) -> CslMac<'a, R, A> {
+        CslMac {
+            radio: radio,
+            alarm: alarm,
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            period: Cell::new(0),
+            window_us: Cell::new(0),
+            anchor: Cell::new(A::Ticks::from(0)),
+            listening: Cell::new(true),
+            tx_pending: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            transmitting: Cell::new(false),
+        }
+    }
+
+    fn period_ticks(&self) -> u32 {
+        self.alarm
+            .ticks_from_us(self.period.get() as u32 * CSL_UNIT_US)
+            .into_u32()
+            .max(1)
+    }
+
+    /// The ticks since the last sample time, moving the anchor forward so
+    /// that the difference never wraps.
+    fn elapsed(&self, now: A::Ticks) -> u32 {
+        let period = self.period_ticks();
+        let elapsed = now.wrapping_sub(self.anchor.get()).into_u32();
+        let passed = elapsed - elapsed % period;
+        self.anchor
+            .set(self.anchor.get().wrapping_add(A::Ticks::from(passed)));
+        elapsed - passed
+    }
+
+    /// Open or close the receive window according to the schedule, and
+    /// set the alarm for its next edge.
+    fn update(&self) {
+        if self.period.get() == 0 {
+            return;
+        }
+        let now = self.alarm.now();
+        let window = self.alarm.ticks_from_us(self.window_us.get()).into_u32();
+        let (open, next) = window_edge(self.elapsed(now), self.period_ticks(), window);
+        if open {
+            self.listen();
+        } else {
+            self.sleep();
+        }
+        self.alarm.set_alarm(now, A::Ticks::from(next));
+    }
+
+    fn listen(&self) {
+        if !self.listening.get() {
+            self.listening.set(true);
+            let _ = self.radio.start();
+        }
+    }
+
+    /// Turn the radio off, unless a frame is being sent or received. The
+    /// completion of the frame updates the schedule again.
+    fn sleep(&self) {
+        if self.listening.get()
+            && !self.transmitting.get()
+            && self.tx_pending.is_none()
+            && !self.radio.busy()
+            && self.radio.stop().is_ok()
+        {
+            self.listening.set(false);
+        }
+    }
+
+    fn send(
+        &self,
+        frame: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.period.get() != 0 {
+            if let Some(offset) = unsecured_ie_offset(frame) {
+                let ie = encode_ie(self.csl_phase(), self.period.get());
+                frame[offset..offset + CSL_IE_LEN].copy_from_slice(&ie);
+            }
+        }
+        self.transmitting.set(true);
+        self.radio.transmit(frame, frame_len).map_err(|err| {
+            self.transmitting.set(false);
+            err
+        })
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> RadioCsl for CslMac<'a, R, A> {
+    fn set_csl_period(&self, period: u16, window_us: u32) -> Result<(), ErrorCode> {
+        if period != 0 && window_us >= period as u32 * CSL_UNIT_US {
+            return Err(ErrorCode::INVAL);
+        }
+        self.period.set(period);
+        self.window_us.set(window_us);
+        if period == 0 {
+            let _ = self.alarm.disarm();
+            self.listen();
+        } else {
+            // The first window is the one now.
+            self.anchor.set(self.alarm.now());
+            self.update();
+        }
+        Ok(())
+    }
+
+    fn csl_period(&self) -> u16 {
+        self.period.get()
+    }
+
+    fn csl_phase(&self) -> u16 {
+        if self.period.get() == 0 {
+            return 0;
+        }
+        let period = self.period_ticks();
+        let until = (period - self.elapsed(self.alarm.now())) % period;
+        (self.alarm.ticks_to_us(A::Ticks::from(until)) / CSL_UNIT_US) as u16
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> Mac<'a> for CslMac<'a, R, A> {
+    fn initialize(&self, _mac_buf: &'static mut [u8]) -> Result<(), ErrorCode> {
+        // Frames waiting for the radio are kept in their own buffer.
+        Ok(())
+    }
+
+    // The radio is started to send frames between the windows.
+    fn is_on(&self) -> bool {
+        self.period.get() != 0 || self.radio.is_on()
+    }
+
+    fn csl_schedule(&self) -> Option<(u16, u16)> {
+        match self.period.get() {
+            0 => None,
+            period => Some((self.csl_phase(), period)),
+        }
+    }
+
+    fn set_config_client(&self, client: &'a dyn radio::ConfigClient) {
+        self.radio.set_config_client(client)
+    }
+
+    fn set_address(&self, addr: u16) {
+        self.radio.set_address(addr)
+    }
+
+    fn set_address_long(&self, addr: [u8; 8]) {
+        self.radio.set_address_long(addr)
+    }
+
+    fn set_pan(&self, id: u16) {
+        self.radio.set_pan(id)
+    }
+
+    fn get_address(&self) -> u16 {
+        self.radio.get_address()
+    }
+
+    fn get_address_long(&self) -> [u8; 8] {
+        self.radio.get_address_long()
+    }
+
+    fn get_pan(&self) -> u16 {
+        self.radio.get_pan()
+    }
+
+    fn config_commit(&self) {
+        self.radio.config_commit()
+    }
+
+    fn set_transmit_client(&self, client: &'a dyn radio::TxClient) {
+        self.tx_client.set(client);
+    }
+
+    fn set_receive_client(&self, client: &'a dyn radio::RxClient) {
+        self.rx_client.set(client);
+    }
+
+    fn set_receive_buffer(&self, buffer: &'static mut [u8]) {
+        self.radio.set_receive_buffer(buffer);
+    }
+
+    fn transmit(
+        &self,
+        full_mac_frame: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.transmitting.get() || self.tx_pending.is_some() {
+            return Err((ErrorCode::BUSY, full_mac_frame));
+        }
+        if self.radio.is_on() {
+            return self.send(full_mac_frame, frame_len);
+        }
+
+        // Send once the radio has started, and keep it on until then.
+        if let Err(ecode) = self.radio.start() {
+            return Err((ecode, full_mac_frame));
+        }
+        self.listening.set(true);
+        self.tx_pending.replace(full_mac_frame);
+        self.tx_len.set(frame_len);
+        Ok(())
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> time::AlarmClient for CslMac<'a, R, A> {
+    fn alarm(&self) {
+        self.update();
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::PowerClient for CslMac<'a, R, A> {
+    fn changed(&self, on: bool) {
+        if !on {
+            return;
+        }
+        if let Some(frame) = self.tx_pending.take() {
+            if let Err((ecode, frame)) = self.send(frame, self.tx_len.get()) {
+                self.tx_client.map(move |c| {
+                    c.send_done(frame, false, Err(ecode));
+                });
+                self.update();
+            }
+        }
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::TxClient for CslMac<'a, R, A> {
+    fn send_done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+        self.transmitting.set(false);
+        self.tx_client.map(move |c| {
+            c.send_done(buf, acked, result);
+        });
+        // Sleep again if the frame was sent between windows.
+        self.update();
+    }
+}
+
+impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::RxClient for CslMac<'a, R, A> {
+    fn receive(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+        crc_valid: bool,
+        result: Result<(), ErrorCode>,
+    ) {
+        // Filter frames by destination, as the radio is in promiscuous mode
+        let mut addr_match = false;
+        if let Some((_, (header, _))) = Header::decode(&buf[radio::PSDU_OFFSET..], false).done() {
+            if let Some(dst_addr) = header.dst_addr {
+                addr_match = match dst_addr {
+                    MacAddress::Short(addr) => {
+                        addr == self.radio.get_address() || addr == BROADCAST_ADDR
+                    }
+                    MacAddress::Long(long_addr) => long_addr == self.radio.get_address_long(),
+                };
+            }
+        }
+
+        if addr_match {
+            self.rx_client.map(move |c| {
+                c.receive(buf, frame_len, crc_valid, result);
+            });
+        } else {
+            self.radio.set_receive_buffer(buf);
+        }
+        // A window held open by the frame closes now.
+        self.update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::ieee802154::{FrameType, FrameVersion, MAX_HEADER_IES};
+
+    #[test]
+    fn ie_round_trip() {
+        let ie = encode_ie(0x0102, 3125);
+        assert_eq!(ie, [0x02, 0x01, 0x35, 0x0c]);
+        assert_eq!(decode_ie(&ie), Some((0x0102, 3125)));
+        assert_eq!(decode_ie(&ie[..3]), None);
+    }
+
+    #[test]
+    fn windows_centred_on_sample_times() {
+        // Windows of 10 ticks around multiples of 100 ticks.
+        assert_eq!(window_edge(0, 100, 10), (true, 5));
+        assert_eq!(window_edge(4, 100, 10), (true, 1));
+        assert_eq!(window_edge(5, 100, 10), (false, 90));
+        assert_eq!(window_edge(94, 100, 10), (false, 1));
+        assert_eq!(window_edge(95, 100, 10), (true, 10));
+    }
+
+    #[test]
+    fn finds_ie_in_unsecured_frame() {
+        let content = encode_ie(0, 3125);
+        let mut header_ies: [HeaderIE; MAX_HEADER_IES] = Default::default();
+        header_ies[0] = HeaderIE::Undissected {
+            element_id: CSL_IE_ID,
+            content: &content,
+        };
+        let header = Header {
+            frame_type: FrameType::Data,
+            frame_pending: false,
+            ack_requested: true,
+            version: FrameVersion::V2015,
+            seq: Some(7),
+            dst_pan: Some(0xabcd),
+            dst_addr: Some(MacAddress::Short(0x0001)),
+            src_pan: Some(0xabcd),
+            src_addr: Some(MacAddress::Short(0x0402)),
+            security: None,
+            header_ies: header_ies,
+            header_ies_len: 1,
+            payload_ies: Default::default(),
+            payload_ies_len: 0,
+        };
+        let mut frame = [0; radio::MAX_BUF_SIZE];
+        assert!(header
+            .encode(&mut frame[radio::PSDU_OFFSET..], true)
+            .done()
+            .is_some());
+
+        let offset = unsecured_ie_offset(&frame).unwrap();
+        assert_eq!(decode_ie(&frame[offset..]), Some((0, 3125)));
+        frame[offset..offset + CSL_IE_LEN].copy_from_slice(&encode_ie(42, 3125));
+        let (_, (header, _)) = Header::decode(&frame[radio::PSDU_OFFSET..], false)
+            .done()
+            .unwrap();
+        match header.header_ies[0] {
+            HeaderIE::Undissected { content, .. } => {
+                assert_eq!(decode_ie(content), Some((42, 3125)))
+            }
+            _ => panic!("CSL IE missing"),
+        }
+    }
+}
@@ -6,7 +6,9 @@
 //!
 //! Implements a userspace interface for sending and receiving IEEE 802.15.4
 //! frames. Also provides a minimal list-based interface for managing keys and
-//! known link neighbors, which is needed for 802.15.4 security.
+//! known link neighbors, which is needed for 802.15.4 security. With a radio
+//! that supports it, processes can also scan the energy on channels and read
+//! the link quality of the current channel.
 
 use crate::ieee802154::{device, framer};
 use crate::net::ieee802154::{AddressMode, Header, KeyId, MacAddress, PanID, SecurityLevel};
@@ -17,6 +19,7 @@ use core::cmp::min;
 
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::radio::{EnergyDetectClient, RadioChannelQuality};
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
@@ -25,6 +28,13 @@ use kernel::{ErrorCode, ProcessId};
 const MAX_NEIGHBORS: usize = 4;
 const MAX_KEYS: usize = 4;
 
+/// The channels of the 2.4 GHz O-QPSK PHY, as a bitmask of channel numbers.
+const CHANNEL_MASK: u32 = 0x07FF_F800;
+const FIRST_CHANNEL: u8 = 11;
+const NUM_CHANNELS: usize = 16;
+/// The energy of a channel not scanned in the results of an energy scan.
+const NOT_SCANNED: i8 = i8::MIN;
+
 /// Ids for read-only allow buffers
 mod ro_allow {
     pub const WRITE: usize = 0;
@@ -193,7 +203,7 @@ pub struct RadioDriver<'a> {
     /// Grant of apps that use this radio driver.
     apps: Grant<
         App,
-        UpcallCount<2>,
+        UpcallCount<3>,
         AllowRoCount<{ ro_allow::COUNT }>,
         AllowRwCount<{ rw_allow::COUNT }>,
     >,
@@ -211,6 +221,17 @@ pub struct RadioDriver<'a> {
 
     /// Used to save result for passing a callback from a deferred call.
     saved_result: OptionalCell<Result<(), ErrorCode>>,
+
+    /// The radio to measure channel quality with, if it can.
+    channel_quality: OptionalCell<&'a dyn RadioChannelQuality<'a>>,
+    /// ID of app whose energy scan is in progress.
+    scan_app: OptionalCell<ProcessId>,
+    /// Bitmask of the channels still to scan.
+    scan_channels: Cell<u32>,
+    /// How long to measure each channel, in microseconds.
+    scan_duration_us: Cell<u32>,
+    /// Peak energy in dBm of each channel, starting with channel 11.
+    scan_results: Cell<[i8; NUM_CHANNELS]>,
 }
 
 impl<'a> RadioDriver<'a> {
@@ -218,7 +239,7 @@ impl<'a> RadioDriver<'a> {
         mac: &'a dyn device::MacDevice<'a>,
         grant: Grant<
             App,
-            UpcallCount<2>,
+            UpcallCount<3>,
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
@@ -236,9 +257,20 @@ impl<'a> RadioDriver<'a> {
             deferred_call: DeferredCall::new(),
             saved_processid: OptionalCell::empty(),
             saved_result: OptionalCell::empty(),
+            channel_quality: OptionalCell::empty(),
+            scan_app: OptionalCell::empty(),
+            scan_channels: Cell::new(0),
+            scan_duration_us: Cell::new(0),
+            scan_results: Cell::new([NOT_SCANNED; NUM_CHANNELS]),
         }
     }
 
+    /// Enable energy scans and link quality with `radio`, whose energy
+    /// detect client must be this driver.
+    pub fn set_channel_quality(&self, radio: &'a dyn RadioChannelQuality<'a>) {
+        self.channel_quality.set(radio);
+    }
+
     // Neighbor management functions
 
     /// Add a new neighbor to the end of the list if there is still space
@@ -441,6 +473,83 @@ impl<'a> RadioDriver<'a> {
         })?
     }
 
+    /// Start an energy scan of the channels in `channels` for `processid`.
+    fn start_scan(&self, channels: u32, duration_us: u32, processid: ProcessId) -> CommandReturn {
+        if self.channel_quality.is_none() {
+            return CommandReturn::failure(ErrorCode::NOSUPPORT);
+        }
+        if self.scan_app.is_some() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        if channels == 0 || channels & !CHANNEL_MASK != 0 {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        let cfg_len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .map_or(0, |cfg| cfg.len())
+            })
+            .unwrap_or(0);
+        if cfg_len != NUM_CHANNELS {
+            return CommandReturn::failure(ErrorCode::SIZE);
+        }
+
+        self.scan_channels.set(channels);
+        self.scan_duration_us.set(duration_us);
+        self.scan_results.set([NOT_SCANNED; NUM_CHANNELS]);
+        self.scan_app.set(processid);
+        self.scan_next()
+            .map_err(|err| {
+                self.scan_app.clear();
+                err
+            })
+            .into()
+    }
+
+    /// Measure the energy on the lowest channel still to scan.
+    fn scan_next(&self) -> Result<(), ErrorCode> {
+        let channels = self.scan_channels.get();
+        let channel = channels.trailing_zeros();
+        self.scan_channels.set(channels & !(1 << channel));
+        self.channel_quality
+            .map_or(Err(ErrorCode::NOSUPPORT), |radio| {
+                radio.energy_detect(channel as u8, self.scan_duration_us.get())
+            })
+    }
+
+    /// Copy the results of the energy scan to the process, and report the
+    /// channel with the least energy.
+    fn scan_done(&self, result: Result<(), ErrorCode>) {
+        let results = self.scan_results.get();
+        let quietest = (0..NUM_CHANNELS)
+            .filter(|&i| results[i] != NOT_SCANNED)
+            .min_by_key(|&i| results[i]);
+        if let Some(processid) = self.scan_app.take() {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let _ = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::CFG)
+                    .and_then(|cfg| {
+                        cfg.mut_enter(|cfg| {
+                            for (dst, energy) in cfg.iter().zip(results.iter()) {
+                                dst.set(*energy as u8);
+                            }
+                        })
+                    });
+                let (channel, energy) = quietest.map_or((0, 0), |i| {
+                    (FIRST_CHANNEL as usize + i, results[i] as isize as usize)
+                });
+                kernel_data
+                    .schedule_upcall(
+                        2,
+                        (kernel::errorcode::into_statuscode(result), channel, energy),
+                    )
+                    .ok();
+            });
+        }
+    }
+
     /// Schedule the next transmission if there is one pending. Performs the
     /// transmission asynchronously, returning any errors via callbacks.
     #[inline]
@@ -545,6 +654,7 @@ impl SyscallDriver for RadioDriver<'_> {
     //
     // - `0`: Setup callback for when frame is received.
     // - `1`: Setup callback for when frame is transmitted.
+    // - `2`: Setup callback for when an energy scan is done.
 
     /// IEEE 802.15.4 MAC device control.
     ///
@@ -595,11 +705,26 @@ impl SyscallDriver for RadioDriver<'_> {
     ///                      9 bytes: the key ID (might not use all bytes) +
     ///                      16 bytes: the key.
     /// - `25`: Remove the key at an index.
+    /// - `26`: Transmit a frame to the short address `arg1`.
+    ///        app_cfg (in): 1 byte: the security level +
+    ///                      10 bytes: the key ID mode and key ID.
+    /// - `27`: Scan the energy on the channels set in the bitmask `arg1`, bit
+    ///        11 to 26 for channel 11 to 26, for `arg2` microseconds each.
+    ///        The radio does not receive frames during the scan. Calls back
+    ///        with the status, the channel with the least energy, and its
+    ///        energy in dBm. Returns `NOSUPPORT` if the radio cannot scan,
+    ///        and `BUSY` if a scan is in progress.
+    ///        app_cfg (out): 16 bytes: the peak energy in dBm of each channel
+    ///                       from 11 to 26 as `i8`, -128 if not scanned.
+    /// - `28`: Get the average link quality of the frames received on the
+    ///        current channel: the RSSI in dBm, the LQI from 0 to 255, and
+    ///        the number of frames. Returns `FAIL` if no frame was received.
+    /// - `29`: Reset the average link quality.
     fn command(
         &self,
         command_number: usize,
         arg1: usize,
-        _: usize,
+        arg2: usize,
         processid: ProcessId,
     ) -> CommandReturn {
         match command_number {
@@ -883,6 +1008,28 @@ impl SyscallDriver for RadioDriver<'_> {
                         },
                     )
             }
+            27 => self.start_scan(arg1 as u32, arg2 as u32, processid),
+            28 => {
+                self.channel_quality
+                    .map_or(
+                        CommandReturn::failure(ErrorCode::NOSUPPORT),
+                        |radio| match radio.link_quality() {
+                            Some(quality) => CommandReturn::success_u32_u32_u32(
+                                quality.rssi as i32 as u32,
+                                quality.lqi as u32,
+                                quality.frames,
+                            ),
+                            None => CommandReturn::failure(ErrorCode::FAIL),
+                        },
+                    )
+            }
+            29 => {
+                self.channel_quality
+                    .map_or(CommandReturn::failure(ErrorCode::NOSUPPORT), |radio| {
+                        radio.reset_link_quality();
+                        CommandReturn::success()
+                    })
+            }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -913,6 +1060,29 @@ impl device::TxClient for RadioDriver<'_> {
     }
 }
 
+impl EnergyDetectClient for RadioDriver<'_> {
+    fn energy_detect_done(&self, channel: u8, result: Result<i8, ErrorCode>) {
+        if self.scan_app.is_none() {
+            return;
+        }
+        match result {
+            Ok(energy) => {
+                let mut results = self.scan_results.get();
+                if let Some(slot) = results.get_mut(channel.wrapping_sub(FIRST_CHANNEL) as usize) {
+                    *slot = energy;
+                }
+                self.scan_results.set(results);
+                if self.scan_channels.get() == 0 {
+                    self.scan_done(Ok(()));
+                } else if let Err(err) = self.scan_next() {
+                    self.scan_done(Err(err));
+                }
+            }
+            Err(err) => self.scan_done(Err(err)),
+        }
+    }
+}
+
 /// Encode two PAN IDs into a single usize.
 #[inline]
 fn encode_pans(dst_pan: &Option<PanID>, src_pan: &Option<PanID>) -> usize {
@@ -75,10 +75,12 @@
 // TODO: Channel scanning
 //
 
+use crate::ieee802154::csl;
 use crate::ieee802154::device::{MacDevice, RxClient, TxClient};
 use crate::ieee802154::mac::Mac;
 use crate::net::ieee802154::{
-    FrameType, FrameVersion, Header, KeyId, MacAddress, PanID, Security, SecurityLevel,
+    FrameType, FrameVersion, Header, HeaderIE, KeyId, MacAddress, PanID, Security, SecurityLevel,
+    MAX_HEADER_IES,
 };
 use crate::net::stream::SResult;
 use crate::net::stream::{encode_bytes, encode_u32, encode_u8};
@@ -729,6 +731,23 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
             return Err(buf);
         }
 
+        // A MAC with CSL receive windows announces them in a CSL IE, which
+        // needs a 2015 frame. The MAC refreshes the phase of unsecured
+        // frames when it sends them.
+        let csl_ie = self
+            .mac
+            .csl_schedule()
+            .map(|(phase, period)| csl::encode_ie(phase, period));
+        let mut header_ies: [HeaderIE; MAX_HEADER_IES] = Default::default();
+        let mut header_ies_len = 0;
+        if let Some(content) = csl_ie.as_ref() {
+            header_ies[0] = HeaderIE::Undissected {
+                element_id: csl::CSL_IE_ID,
+                content: content,
+            };
+            header_ies_len = 1;
+        }
+
         // Construct MAC header
         let security = security_desc.map(|(sec, _, _)| sec);
         let mic_len = security.map_or(0, |sec| sec.level.mic_len());
@@ -739,15 +758,19 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
             frame_pending: false,
             // Unicast data frames request acknowledgement
             ack_requested: true,
-            version: FrameVersion::V2006,
+            version: if csl_ie.is_some() {
+                FrameVersion::V2015
+            } else {
+                FrameVersion::V2006
+            },
             seq: Some(self.data_sequence.get()),
             dst_pan: Some(dst_pan),
             dst_addr: Some(dst_addr),
             src_pan: Some(src_pan),
             src_addr: Some(src_addr),
             security: security,
-            header_ies: Default::default(),
-            header_ies_len: 0,
+            header_ies: header_ies,
+            header_ies_len: header_ies_len,
             payload_ies: Default::default(),
             payload_ies_len: 0,
         };
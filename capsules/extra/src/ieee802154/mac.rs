@@ -57,6 +57,12 @@ pub trait Mac<'a> {
     /// Indicates whether or not the MAC protocol is active and can send frames
     fn is_on(&self) -> bool;
 
+    /// The phase and period of the CSL IE of a frame sent now, if the MAC
+    /// listens in coordinated sampled listening windows
+    fn csl_schedule(&self) -> Option<(u16, u16)> {
+        None
+    }
+
     /// Transmits complete MAC frames, which must be prepared by an ieee802154::device::MacDevice
     /// before being passed to the Mac layer. Returns the frame buffer in case of an error.
     fn transmit(
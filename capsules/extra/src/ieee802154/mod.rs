@@ -4,6 +4,7 @@
 
 //! Support for IEEE 802.15.4.
 
+pub mod csl;
 pub mod device;
 pub mod framer;
 pub mod mac;
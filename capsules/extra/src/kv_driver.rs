@@ -4,15 +4,31 @@
 
 //! KV Driver
 //!
+//! If the flash below the KV store holds back writes, such as the
+//! `flash_cache` write-back cache, the board can pass it to `set_flush()`.
+//! Processes then make their changes durable with the flush command.
+//!
+//! A process can change several keys atomically in a transaction. After the
+//! begin command (5), its set and delete commands are staged until the
+//! commit command (6) makes all of them, or the abort command (7) drops
+//! them. One process at a time can have a transaction open. Commit and
+//! abort return `ALREADY` without an upcall if nothing was staged.
+//!
+//! Command `driver_info::COMMAND_NUM` describes the driver as in `capsules_core::driver_info`. It
+//! sets `feature::TRANSACTIONS`, and `feature::FLUSH` if the board set a
+//! flash to flush.
+//!
 
 use capsules_core::driver;
 /// Syscall driver number.
 pub const DRIVER_NUM: usize = driver::NUM::KVSystem as usize;
 
 use crate::kv_store::KVStore;
+use capsules_core::driver_info::{self, DriverInfo};
 use core::cell::Cell;
 use kernel::grant::Grant;
 use kernel::grant::{AllowRoCount, AllowRwCount, UpcallCount};
+use kernel::hil::flash;
 use kernel::hil::kv_system;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -36,6 +52,14 @@ mod rw_allow {
     pub const COUNT: u8 = 1;
 }
 
+/// Optional features reported by command `driver_info::COMMAND_NUM`.
+pub mod feature {
+    /// Changes can be grouped in transactions
+    pub const TRANSACTIONS: u32 = 1 << 0;
+    /// Changes can be flushed to the flash
+    pub const FLUSH: u32 = 1 << 1;
+}
+
 /// Ids for upcalls
 mod upcalls {
     pub const VALUE: usize = 0;
@@ -49,6 +73,9 @@ pub struct KVSystemDriver<
     T: 'static + kv_system::KeyType,
 > {
     kv: &'a KVStore<'a, K, T>,
+    flush: OptionalCell<&'a dyn flash::Flush<'a>>,
+    /// The process with the open transaction.
+    transaction: OptionalCell<ProcessId>,
 
     active: Cell<bool>,
 
@@ -78,6 +105,8 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
     ) -> KVSystemDriver<'a, K, T> {
         KVSystemDriver {
             kv,
+            flush: OptionalCell::empty(),
+            transaction: OptionalCell::empty(),
             active: Cell::new(false),
             apps: grant,
             processid: OptionalCell::empty(),
@@ -86,6 +115,11 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
         }
     }
 
+    /// Let processes flush `flush`, the flash the KV store is on.
+    pub fn set_flush(&self, flush: &'a dyn flash::Flush<'a>) {
+        self.flush.set(flush);
+    }
+
     fn run(&self) -> Result<(), ErrorCode> {
         self.processid.map_or(Err(ErrorCode::RESERVE), |processid| {
             self.apps
@@ -184,12 +218,22 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
                                             let perms = processid
                                                 .get_storage_permissions()
                                                 .ok_or(ErrorCode::INVAL)?;
-                                            if let Err((data, dest, e)) = self.kv.set(
-                                                data_buffer,
-                                                dest_buffer,
-                                                static_buffer_len,
-                                                perms,
-                                            ) {
+                                            let ret = if self.transaction.contains(processid) {
+                                                self.kv.stage_set(
+                                                    data_buffer,
+                                                    dest_buffer,
+                                                    static_buffer_len,
+                                                    perms,
+                                                )
+                                            } else {
+                                                self.kv.set(
+                                                    data_buffer,
+                                                    dest_buffer,
+                                                    static_buffer_len,
+                                                    perms,
+                                                )
+                                            };
+                                            if let Err((data, dest, e)) = ret {
                                                 self.data_buffer.replace(data);
                                                 self.dest_buffer.replace(dest);
                                                 return Err(e);
@@ -227,7 +271,12 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
                                     let perms = processid
                                         .get_storage_permissions()
                                         .ok_or(ErrorCode::INVAL)?;
-                                    if let Err((data, e)) = self.kv.delete(data_buffer, perms) {
+                                    let ret = if self.transaction.contains(processid) {
+                                        self.kv.stage_delete(data_buffer, perms)
+                                    } else {
+                                        self.kv.delete(data_buffer, perms)
+                                    };
+                                    if let Err((data, e)) = ret {
                                         self.data_buffer.replace(data);
                                         return Err(e);
                                     }
@@ -236,6 +285,23 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
                                     return e;
                                 }
                             }
+                            UserSpaceOp::Flush => {
+                                self.flush
+                                    .map_or(Err(ErrorCode::NOSUPPORT), |flush| flush.flush())?;
+                            }
+                            UserSpaceOp::Commit | UserSpaceOp::Abort => {
+                                let ret = if operation == UserSpaceOp::Commit {
+                                    self.kv.commit()
+                                } else {
+                                    self.kv.abort()
+                                };
+                                if ret == Err(ErrorCode::ALREADY) {
+                                    // Nothing was staged, the transaction
+                                    // is closed.
+                                    self.transaction.clear();
+                                }
+                                ret?;
+                            }
                         }
                     }
 
@@ -246,26 +312,57 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> KVSystemDrive
     }
 
     fn check_queue(&self) {
+        // If an app is already running let it complete
+        if self.processid.is_some() {
+            return;
+        }
+
         for appiter in self.apps.iter() {
-            let started_command = appiter.enter(|app, _| {
-                // If an app is already running let it complete
-                if self.processid.is_some() {
-                    return true;
+            // If this app has a pending command let's use it.
+            let pending = appiter.enter(|app, _| app.pending_run_app.take());
+            if let Some(processid) = pending {
+                // Mark this driver as being in use.
+                self.processid.set(processid);
+                let ret = self.run();
+                if ret.is_ok() {
+                    break;
                 }
 
-                // If this app has a pending command let's use it.
-                app.pending_run_app.take().map_or(false, |processid| {
-                    // Mark this driver as being in use.
-                    self.processid.set(processid);
-                    // Actually make the buzz happen.
-                    self.run() == Ok(())
-                })
-            });
-            if started_command {
-                break;
+                // The command was accepted when it was queued, so the
+                // process learns about the failure from the upcall.
+                self.processid.clear();
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .schedule_upcall(
+                            upcalls::VALUE,
+                            (kernel::errorcode::into_statuscode(ret), 0, 0),
+                        )
+                        .ok();
+                });
             }
         }
     }
+
+    /// Tell the process that started the commit or abort its `result`.
+    fn transaction_done(&self, operation: UserSpaceOp, result: Result<(), ErrorCode>) {
+        self.processid.map(move |id| {
+            self.apps.enter(*id, move |app, upcalls| {
+                if app.op.get() == Some(operation) {
+                    upcalls
+                        .schedule_upcall(
+                            upcalls::VALUE,
+                            (kernel::errorcode::into_statuscode(result), 0, 0),
+                        )
+                        .ok();
+                }
+            })
+        });
+        if !self.kv.in_transaction() {
+            self.transaction.clear();
+        }
+        self.processid.clear();
+        self.check_queue();
+    }
 }
 
 impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> kv_system::StoreClient<T>
@@ -321,12 +418,12 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> kv_system::St
                                 upcalls.schedule_upcall(upcalls::VALUE, (0, 0, 0)).ok();
                             }
                         });
-
-                        self.processid.clear();
                     }
                 }
             })
         });
+        self.processid.clear();
+        self.check_queue();
     }
 
     fn set_complete(
@@ -350,12 +447,12 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> kv_system::St
                             .ok();
                     } else {
                         upcalls.schedule_upcall(upcalls::VALUE, (0, 0, 0)).ok();
-
-                        self.processid.clear();
                     }
                 }
             })
         });
+        self.processid.clear();
+        self.check_queue();
     }
 
     fn delete_complete(&self, result: Result<(), ErrorCode>, key: &'static mut [u8]) {
@@ -373,12 +470,41 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> kv_system::St
                             .ok();
                     } else {
                         upcalls.schedule_upcall(upcalls::VALUE, (0, 0, 0)).ok();
-
-                        self.processid.clear();
                     }
                 }
             })
         });
+        self.processid.clear();
+        self.check_queue();
+    }
+
+    fn commit_complete(&self, result: Result<(), ErrorCode>) {
+        self.transaction_done(UserSpaceOp::Commit, result);
+    }
+
+    fn abort_complete(&self, result: Result<(), ErrorCode>) {
+        self.transaction_done(UserSpaceOp::Abort, result);
+    }
+}
+
+impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> flash::FlushClient
+    for KVSystemDriver<'a, K, T>
+{
+    fn flush_done(&self, result: Result<(), ErrorCode>) {
+        self.processid.map(move |id| {
+            self.apps.enter(*id, move |app, upcalls| {
+                if app.op.get() == Some(UserSpaceOp::Flush) {
+                    upcalls
+                        .schedule_upcall(
+                            upcalls::VALUE,
+                            (kernel::errorcode::into_statuscode(result), 0, 0),
+                        )
+                        .ok();
+                }
+            })
+        });
+        self.processid.clear();
+        self.check_queue();
     }
 }
 
@@ -420,14 +546,47 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> SyscallDriver
             // check if present
             0 => CommandReturn::success(),
 
-            // get, set, delete
-            1 | 2 | 3 => {
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1)
+                .feature(feature::TRANSACTIONS, true)
+                .feature(feature::FLUSH, self.flush.is_some())
+                .into(),
+
+            // begin a transaction
+            5 => {
+                let held = self.transaction.map_or(false, |owner| {
+                    // The transaction of a process that no longer exists
+                    // is dropped.
+                    owner != &processid && self.apps.enter(*owner, |_, _| ()).is_ok()
+                });
+                if held {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                match self.kv.begin() {
+                    Ok(()) => {
+                        self.transaction.set(processid);
+                        CommandReturn::success()
+                    }
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            // commit and abort need an open transaction
+            6 | 7 if !self.transaction.contains(&processid) => {
+                CommandReturn::failure(ErrorCode::INVAL)
+            }
+
+            // get, set, delete, flush, commit, abort
+            1 | 2 | 3 | 4 | 6 | 7 => {
                 if match_or_empty_or_nonexistant {
                     self.processid.set(processid);
                     let _ = self.apps.enter(processid, |app, _| match command_num {
                         1 => app.op.set(Some(UserSpaceOp::Get)),
                         2 => app.op.set(Some(UserSpaceOp::Set)),
                         3 => app.op.set(Some(UserSpaceOp::Delete)),
+                        4 => app.op.set(Some(UserSpaceOp::Flush)),
+                        6 => app.op.set(Some(UserSpaceOp::Commit)),
+                        7 => app.op.set(Some(UserSpaceOp::Abort)),
                         _ => {}
                     });
                     let ret = self.run();
@@ -455,6 +614,9 @@ impl<'a, K: kv_system::KVSystem<'a, K = T>, T: kv_system::KeyType> SyscallDriver
                                     1 => app.op.set(Some(UserSpaceOp::Get)),
                                     2 => app.op.set(Some(UserSpaceOp::Set)),
                                     3 => app.op.set(Some(UserSpaceOp::Delete)),
+                                    4 => app.op.set(Some(UserSpaceOp::Flush)),
+                                    6 => app.op.set(Some(UserSpaceOp::Commit)),
+                                    7 => app.op.set(Some(UserSpaceOp::Abort)),
                                     _ => {}
                                 }
                                 CommandReturn::success()
@@ -479,6 +641,9 @@ enum UserSpaceOp {
     Get,
     Set,
     Delete,
+    Flush,
+    Commit,
+    Abort,
 }
 
 #[derive(Default)]
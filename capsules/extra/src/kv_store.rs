@@ -32,6 +32,20 @@
 //!
 //!    hil::flash
 //! ```
+//!
+//! Transactions
+//! ------------
+//!
+//! The changes of several keys can be committed atomically. After `begin()`,
+//! `stage_set()` and `stage_delete()` write the new values under staged keys,
+//! which `get()` does not find. `commit()` then writes a journal listing the
+//! changed keys, which is the point at which the transaction is committed,
+//! copies the staged values to their keys and removes the journal. If the
+//! power fails before the journal is written, none of the changes are made.
+//! If it fails afterwards, the journal is found before the next operation
+//! and the remaining changes are made then. Values staged by a transaction
+//! that was never committed stay in flash until the same key is changed in a
+//! transaction again.
 
 use core::cell::Cell;
 use kernel::collections::list::{List, ListLink, ListNode};
@@ -45,10 +59,73 @@ enum Operation {
     Get,
     Set,
     Delete,
+    /// A change of the open transaction.
+    Stage(Change, StageStep),
+    /// Making the changes of a committed transaction.
+    Commit(CommitStep),
+    /// Removing the staged values of an aborted transaction.
+    Abort,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Change {
+    Set,
+    Delete,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StageStep {
+    /// Hashing the key.
+    Hash,
+    /// Reading the header of the current value to check the permissions.
+    CheckCurrent,
+    /// Removing a value staged for the key earlier.
+    ClearStaged,
+    /// Writing the staged value.
+    Write,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CommitStep {
+    /// Looking for the journal of a commit interrupted by a power failure.
+    ReadJournal,
+    /// Writing the journal, which commits the transaction.
+    WriteJournal,
+    /// Reading the staged value of the current change.
+    ReadStaged,
+    /// Invalidating the value of the key of the current change.
+    Invalidate,
+    /// Writing the staged value to the key.
+    Write,
+    /// Removing the staged value.
+    ClearStaged,
+    /// Removing the journal, which ends the transaction.
+    ClearJournal,
 }
 
 const HEADER_VERSION: u8 = 0;
-const HEADER_LENGTH: usize = 9;
+/// Length of the header stored in front of each value. Value buffers passed
+/// to `set()` must have room for it.
+pub const HEADER_LENGTH: usize = 9;
+
+/// Length of the header, of staged deletions, that deletes a key.
+const TOMBSTONE_LENGTH: u32 = u32::MAX;
+
+/// Hashed key of the journal, repeated to the length of the keys.
+const JOURNAL_KEY: &[u8] = b"kvjournl";
+
+/// The hashed key a change of `key` is staged under.
+fn stage_key<T: kv_system::KeyType>(key: &mut T) {
+    for byte in key.as_mut().iter_mut() {
+        *byte = !*byte;
+    }
+}
+
+fn journal_key<T: kv_system::KeyType>(key: &mut T) {
+    for (byte, journal) in key.as_mut().iter_mut().zip(JOURNAL_KEY.iter().cycle()) {
+        *byte = *journal;
+    }
+}
 
 /// This is the header used for KV stores
 struct KeyHeader {
@@ -95,6 +172,17 @@ pub struct KVStore<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static + kv_sy
 
     valid_ids: OptionalCell<StoragePermissions>,
     next_valid_ids: OptionalCell<StoragePermissions>,
+
+    /// The number of changes of the open transaction followed by their
+    /// hashed keys, which is also the journal written to commit them.
+    journal: TakeCell<'static, [u8]>,
+    /// Buffer the staged values are copied to their keys with.
+    staged_value: TakeCell<'static, [u8]>,
+    transaction: Cell<bool>,
+    /// The change of the transaction being committed or aborted.
+    change: Cell<usize>,
+    /// Making the changes of a journal found in flash.
+    recovering: Cell<bool>,
 }
 
 impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> ListNode<'a, KVStore<'a, K, T>>
@@ -110,6 +198,8 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
         mux_kv: &'a MuxKVStore<'a, K, T>,
         key: &'static mut T,
         header_value: &'static mut [u8; HEADER_LENGTH],
+        journal: &'static mut [u8],
+        staged_value: &'static mut [u8],
     ) -> KVStore<'a, K, T> {
         Self {
             mux_kv,
@@ -122,9 +212,20 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
             header_value: TakeCell::new(header_value),
             valid_ids: OptionalCell::empty(),
             next_valid_ids: OptionalCell::empty(),
+            journal: TakeCell::new(journal),
+            staged_value: TakeCell::new(staged_value),
+            transaction: Cell::new(false),
+            change: Cell::new(0),
+            recovering: Cell::new(false),
         }
     }
 
+    /// Add this user to the mux, which runs the operations queued while
+    /// another one is in progress.
+    pub fn setup(&'a self) {
+        self.mux_kv.users.push_head(self);
+    }
+
     pub fn set_client(&self, client: &'a dyn kv_system::StoreClient<T>) {
         self.client.set(client);
     }
@@ -135,6 +236,8 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
         value: &'static mut [u8],
         perms: StoragePermissions,
     ) -> Result<(), (&'static mut [u8], &'static mut [u8], Result<(), ErrorCode>)> {
+        self.recover();
+
         if self.mux_kv.operation.is_none() {
             if self.hashed_key.is_none() {
                 return Err((unhashed_key, value, Err(ErrorCode::NOMEM)));
@@ -182,6 +285,44 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
         length: usize,
         perms: StoragePermissions,
     ) -> Result<(), (&'static mut [u8], &'static mut [u8], Result<(), ErrorCode>)> {
+        self.write(unhashed_key, value, length, perms, Operation::Set)
+    }
+
+    /// Set `unhashed_key` to the `length` bytes of `value` when the open
+    /// transaction is committed. `value` may not be longer than the buffer
+    /// the staged values are copied with.
+    pub fn stage_set(
+        &self,
+        unhashed_key: &'static mut [u8],
+        value: &'static mut [u8],
+        length: usize,
+        perms: StoragePermissions,
+    ) -> Result<(), (&'static mut [u8], &'static mut [u8], Result<(), ErrorCode>)> {
+        if !self.transaction.get() {
+            return Err((unhashed_key, value, Err(ErrorCode::INVAL)));
+        }
+        if value.len() > self.staged_value.map_or(0, |buf| buf.len()) {
+            return Err((unhashed_key, value, Err(ErrorCode::SIZE)));
+        }
+        self.write(
+            unhashed_key,
+            value,
+            length,
+            perms,
+            Operation::Stage(Change::Set, StageStep::Hash),
+        )
+    }
+
+    fn write(
+        &self,
+        unhashed_key: &'static mut [u8],
+        value: &'static mut [u8],
+        length: usize,
+        perms: StoragePermissions,
+        operation: Operation,
+    ) -> Result<(), (&'static mut [u8], &'static mut [u8], Result<(), ErrorCode>)> {
+        self.recover();
+
         let write_id = match perms.get_write_id() {
             Some(write_id) => write_id,
             None => return Err((unhashed_key, value, Err(ErrorCode::INVAL))),
@@ -207,7 +348,8 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
                 return Err((unhashed_key, value, Err(ErrorCode::NOMEM)));
             }
 
-            self.mux_kv.operation.set(Operation::Set);
+            self.mux_kv.operation.set(operation);
+            self.valid_ids.set(perms);
 
             if let Some(Err((unhashed_key, e))) = self.hashed_key.take().map(|buf| {
                 if let Err((unhashed_key, hashed_key, e)) =
@@ -228,9 +370,10 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
             // Another app is already running, queue this app as long as we
             // don't already have data queued.
             if self.next_operation.is_none() {
-                self.next_operation.set(Operation::Set);
+                self.next_operation.set(operation);
                 self.unhashed_key.replace(unhashed_key);
                 self.value.replace(value);
+                self.next_valid_ids.set(perms);
                 Ok(())
             } else {
                 Err((unhashed_key, value, Err(ErrorCode::BUSY)))
@@ -243,6 +386,33 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
         unhashed_key: &'static mut [u8],
         perms: StoragePermissions,
     ) -> Result<(), (&'static mut [u8], Result<(), ErrorCode>)> {
+        self.remove(unhashed_key, perms, Operation::Delete)
+    }
+
+    /// Delete `unhashed_key` when the open transaction is committed.
+    pub fn stage_delete(
+        &self,
+        unhashed_key: &'static mut [u8],
+        perms: StoragePermissions,
+    ) -> Result<(), (&'static mut [u8], Result<(), ErrorCode>)> {
+        if !self.transaction.get() {
+            return Err((unhashed_key, Err(ErrorCode::INVAL)));
+        }
+        self.remove(
+            unhashed_key,
+            perms,
+            Operation::Stage(Change::Delete, StageStep::Hash),
+        )
+    }
+
+    fn remove(
+        &self,
+        unhashed_key: &'static mut [u8],
+        perms: StoragePermissions,
+        operation: Operation,
+    ) -> Result<(), (&'static mut [u8], Result<(), ErrorCode>)> {
+        self.recover();
+
         if self.mux_kv.operation.is_none() {
             if self.hashed_key.is_none() {
                 return Err((unhashed_key, Err(ErrorCode::NOMEM)));
@@ -250,7 +420,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
 
             self.valid_ids.set(perms);
 
-            self.mux_kv.operation.set(Operation::Delete);
+            self.mux_kv.operation.set(operation);
 
             if let Some(Err((unhashed_key, e))) = self.hashed_key.take().map(|buf| {
                 if let Err((unhashed_key, hashed_key, e)) =
@@ -270,7 +440,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
             // Another app is already running, queue this app as long as we
             // don't already have data queued.
             if self.next_operation.is_none() {
-                self.next_operation.set(Operation::Delete);
+                self.next_operation.set(operation);
                 self.unhashed_key.replace(unhashed_key);
                 self.next_valid_ids.set(perms);
 
@@ -280,6 +450,476 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType> KVStore<'a, K, T> {
             }
         }
     }
+
+    /// Start a transaction. The changes staged with `stage_set()` and
+    /// `stage_delete()` are made atomically by `commit()`.
+    ///
+    /// Changes staged for an earlier transaction that was neither committed
+    /// nor aborted are dropped.
+    pub fn begin(&self) -> Result<(), ErrorCode> {
+        match self.mux_kv.operation.extract() {
+            None
+            | Some(Operation::Get | Operation::Set | Operation::Delete)
+            | Some(Operation::Commit(CommitStep::ReadJournal)) => {}
+            Some(_) => return Err(ErrorCode::BUSY),
+        }
+        self.recover();
+        self.journal.map(|journal| journal[0] = 0);
+        self.transaction.set(true);
+        Ok(())
+    }
+
+    /// Whether a transaction is open.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.get()
+    }
+
+    /// Make the changes of the open transaction. `commit_complete()` is
+    /// called once they are made.
+    ///
+    /// Returns `ALREADY` if no changes were staged, which closes the
+    /// transaction without a callback.
+    pub fn commit(&self) -> Result<(), ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.mux_kv.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.changes() == 0 {
+            self.transaction.set(false);
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.hashed_key.is_none() || self.journal.is_none() {
+            return Err(ErrorCode::NOMEM);
+        }
+
+        if let (Some(key), Some(journal)) = (self.hashed_key.take(), self.journal.take()) {
+            journal_key(key);
+            self.mux_kv
+                .operation
+                .set(Operation::Commit(CommitStep::WriteJournal));
+            if let Err((key, journal, e)) = self.mux_kv.kv.append_key(key, journal) {
+                self.hashed_key.replace(key);
+                self.journal.replace(journal);
+                self.mux_kv.operation.clear();
+                return e;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drop the changes of the open transaction. `abort_complete()` is
+    /// called once their staged values are removed.
+    ///
+    /// Returns `ALREADY` if no changes were staged, which closes the
+    /// transaction without a callback.
+    pub fn abort(&self) -> Result<(), ErrorCode> {
+        if !self.transaction.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.mux_kv.operation.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.changes() == 0 {
+            self.transaction.set(false);
+            return Err(ErrorCode::ALREADY);
+        }
+
+        self.mux_kv.operation.set(Operation::Abort);
+        self.change.set(0);
+        self.abort_change();
+        Ok(())
+    }
+
+    /// The number of changes of the open transaction.
+    fn changes(&self) -> usize {
+        self.journal.map_or(0, |journal| journal[0] as usize)
+    }
+
+    /// Copy the hashed key of change `index` to `key`.
+    fn load_change(&self, index: usize, key: &mut T) {
+        self.journal.map(|journal| {
+            let key = key.as_mut();
+            let start = 1 + index * key.len();
+            key.copy_from_slice(&journal[start..start + key.len()]);
+        });
+    }
+
+    /// Add `key` to the changes of the open transaction, unless it is
+    /// already changed. Returns `false` if there is no room for it.
+    fn add_change(&self, key: &T) -> bool {
+        self.journal.map_or(false, |journal| {
+            let key = key.as_ref();
+            let changes = journal[0] as usize;
+            let capacity = ((journal.len() - 1) / key.len()).min(u8::MAX as usize);
+            let known = journal[1..1 + changes * key.len()]
+                .chunks(key.len())
+                .any(|change| change == key);
+            if known {
+                return true;
+            }
+            if changes >= capacity {
+                return false;
+            }
+            let start = 1 + changes * key.len();
+            journal[start..start + key.len()].copy_from_slice(key);
+            journal[0] += 1;
+            true
+        })
+    }
+
+    /// Look for the journal of a commit interrupted by a power failure, and
+    /// make its remaining changes, before the first operation.
+    fn recover(&self) {
+        if self.mux_kv.recovered.get()
+            || self.transaction.get()
+            || self.mux_kv.operation.is_some()
+            || self.hashed_key.is_none()
+            || self.journal.is_none()
+        {
+            return;
+        }
+
+        if let (Some(key), Some(journal)) = (self.hashed_key.take(), self.journal.take()) {
+            journal_key(key);
+            self.mux_kv
+                .operation
+                .set(Operation::Commit(CommitStep::ReadJournal));
+            self.recovering.set(true);
+            if let Err((key, journal, _)) = self.mux_kv.kv.get_value(key, journal) {
+                // Tried again before the next operation.
+                self.hashed_key.replace(key);
+                self.journal.replace(journal);
+                self.recovering.set(false);
+                self.mux_kv.operation.clear();
+            }
+        }
+    }
+
+    fn transaction_get(&self, key: &'static mut T, buf: &'static mut [u8]) {
+        if let Err((key, buf, e)) = self.mux_kv.kv.get_value(key, buf) {
+            self.transaction_get_complete(e, key, buf);
+        }
+    }
+
+    fn transaction_append(&self, key: &'static mut T, value: &'static mut [u8]) {
+        if let Err((key, value, e)) = self.mux_kv.kv.append_key(key, value) {
+            self.transaction_append_complete(e, key, value);
+        }
+    }
+
+    fn transaction_invalidate(&self, key: &'static mut T) {
+        if let Err((key, e)) = self.mux_kv.kv.invalidate_key(key) {
+            self.transaction_invalidate_complete(e, key);
+        }
+    }
+
+    /// The staged change is written or failed.
+    fn stage_done(&self, change: Change, result: Result<(), ErrorCode>) {
+        self.mux_kv.operation.clear();
+        self.unhashed_key.take().map(|unhashed_key| match change {
+            Change::Set => {
+                self.value.take().map(|value| {
+                    self.client.map(move |cb| {
+                        cb.set_complete(result, unhashed_key, value);
+                    });
+                });
+            }
+            Change::Delete => {
+                self.client.map(move |cb| {
+                    cb.delete_complete(result, unhashed_key);
+                });
+            }
+        });
+    }
+
+    /// Make the next change of the transaction being committed, or remove
+    /// the journal once all are made.
+    fn apply_change(&self) {
+        let index = self.change.get();
+        self.hashed_key.take().map(|key| {
+            if index >= self.changes() {
+                journal_key(key);
+                self.mux_kv
+                    .operation
+                    .set(Operation::Commit(CommitStep::ClearJournal));
+                self.transaction_invalidate(key);
+                return;
+            }
+
+            self.load_change(index, key);
+            stage_key(key);
+            self.mux_kv
+                .operation
+                .set(Operation::Commit(CommitStep::ReadStaged));
+            match self.staged_value.take() {
+                Some(buf) => {
+                    buf.fill(0xff);
+                    self.transaction_get(key, buf);
+                }
+                None => {
+                    self.hashed_key.replace(key);
+                    self.commit_done(Err(ErrorCode::NOMEM));
+                }
+            }
+        });
+    }
+
+    /// The commit ended, the transaction is closed unless no change was
+    /// made.
+    fn commit_done(&self, result: Result<(), ErrorCode>) {
+        self.mux_kv.operation.clear();
+        if result.is_err() {
+            // Whatever is left of the journal is made before the next
+            // operation.
+            self.mux_kv.recovered.set(false);
+        }
+        if self.recovering.get() {
+            // A transaction opened meanwhile starts without changes.
+            self.journal.map(|journal| journal[0] = 0);
+            self.recovering.set(false);
+        } else {
+            self.transaction.set(false);
+            self.client.map(|cb| cb.commit_complete(result));
+        }
+    }
+
+    /// Remove the staged value of the next change of the transaction being
+    /// aborted.
+    fn abort_change(&self) {
+        let index = self.change.get();
+        if index >= self.changes() {
+            self.journal.map(|journal| journal[0] = 0);
+            self.transaction.set(false);
+            self.mux_kv.operation.clear();
+            self.client.map(|cb| cb.abort_complete(Ok(())));
+            return;
+        }
+
+        self.hashed_key.take().map(|key| {
+            self.load_change(index, key);
+            stage_key(key);
+            self.transaction_invalidate(key);
+        });
+    }
+
+    fn transaction_generate_key_complete(
+        &self,
+        change: Change,
+        result: Result<(), ErrorCode>,
+        hashed_key: &'static mut T,
+    ) {
+        if let Err(e) = result {
+            self.hashed_key.replace(hashed_key);
+            self.stage_done(change, Err(e));
+            return;
+        }
+
+        // Check that the current value may be changed by the caller.
+        match self.header_value.take() {
+            Some(header) => {
+                header.fill(0xff);
+                self.mux_kv
+                    .operation
+                    .set(Operation::Stage(change, StageStep::CheckCurrent));
+                self.transaction_get(hashed_key, header);
+            }
+            None => {
+                self.hashed_key.replace(hashed_key);
+                self.stage_done(change, Err(ErrorCode::NOMEM));
+            }
+        }
+    }
+
+    fn transaction_get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        ret_buf: &'static mut [u8],
+    ) {
+        match self.mux_kv.operation.extract() {
+            Some(Operation::Stage(change, StageStep::CheckCurrent)) => {
+                // The header is left as 0xff if there is no current value.
+                let header = KeyHeader::new_from_buf(ret_buf);
+                let allowed = header.version != HEADER_VERSION
+                    || self
+                        .valid_ids
+                        .map_or(false, |perms| perms.check_write_permission(header.write_id));
+                self.header_value.replace(ret_buf);
+                if !allowed {
+                    self.hashed_key.replace(key);
+                    self.stage_done(change, Err(ErrorCode::FAIL));
+                    return;
+                }
+                if !self.add_change(key) {
+                    self.hashed_key.replace(key);
+                    self.stage_done(change, Err(ErrorCode::NOMEM));
+                    return;
+                }
+
+                stage_key(key);
+                self.mux_kv
+                    .operation
+                    .set(Operation::Stage(change, StageStep::ClearStaged));
+                self.transaction_invalidate(key);
+            }
+            Some(Operation::Commit(CommitStep::ReadJournal)) => {
+                self.hashed_key.replace(key);
+                let key_len = self.hashed_key.map_or(1, |key| key.as_ref().len());
+                let valid = result.is_ok() && 1 + ret_buf[0] as usize * key_len <= ret_buf.len();
+                if !valid {
+                    ret_buf[0] = 0;
+                }
+                self.journal.replace(ret_buf);
+                if valid {
+                    self.change.set(0);
+                    self.apply_change();
+                } else {
+                    // No interrupted commit.
+                    self.mux_kv.recovered.set(true);
+                    self.recovering.set(false);
+                    self.mux_kv.operation.clear();
+                }
+            }
+            Some(Operation::Commit(CommitStep::ReadStaged)) => {
+                self.staged_value.replace(ret_buf);
+                if result.is_err() {
+                    // Made before a power failure.
+                    self.hashed_key.replace(key);
+                    self.change.set(self.change.get() + 1);
+                    self.apply_change();
+                    return;
+                }
+
+                self.load_change(self.change.get(), key);
+                self.mux_kv
+                    .operation
+                    .set(Operation::Commit(CommitStep::Invalidate));
+                self.transaction_invalidate(key);
+            }
+            _ => {}
+        }
+    }
+
+    fn transaction_append_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut T,
+        value: &'static mut [u8],
+    ) {
+        match self.mux_kv.operation.extract() {
+            Some(Operation::Stage(change, StageStep::Write)) => {
+                self.hashed_key.replace(key);
+                match change {
+                    Change::Set => self.value.replace(value),
+                    Change::Delete => self.header_value.replace(value),
+                };
+                self.stage_done(change, result);
+            }
+            Some(Operation::Commit(CommitStep::WriteJournal)) => {
+                self.hashed_key.replace(key);
+                self.journal.replace(value);
+                match result {
+                    Ok(()) => {
+                        self.change.set(0);
+                        self.apply_change();
+                    }
+                    Err(e) => {
+                        // Nothing was changed, the transaction stays open.
+                        self.mux_kv.operation.clear();
+                        self.client.map(|cb| cb.commit_complete(Err(e)));
+                    }
+                }
+            }
+            Some(Operation::Commit(CommitStep::Write)) => {
+                self.staged_value.replace(value);
+                if let Err(e) = result {
+                    self.hashed_key.replace(key);
+                    self.commit_done(Err(e));
+                    return;
+                }
+
+                stage_key(key);
+                self.mux_kv
+                    .operation
+                    .set(Operation::Commit(CommitStep::ClearStaged));
+                self.transaction_invalidate(key);
+            }
+            _ => {}
+        }
+    }
+
+    fn transaction_invalidate_complete(&self, result: Result<(), ErrorCode>, key: &'static mut T) {
+        if result.is_ok() {
+            self.mux_kv.perform_cleanup.set(true);
+        }
+
+        match self.mux_kv.operation.extract() {
+            Some(Operation::Stage(change, StageStep::ClearStaged)) => {
+                let value = match change {
+                    Change::Set => self.value.take(),
+                    Change::Delete => self.header_value.take().map(|header| {
+                        KeyHeader {
+                            version: HEADER_VERSION,
+                            length: TOMBSTONE_LENGTH,
+                            write_id: self
+                                .valid_ids
+                                .map_or(None, |perms| perms.get_write_id())
+                                .unwrap_or(0),
+                        }
+                        .copy_to_buf(header);
+                        header
+                    }),
+                };
+                match value {
+                    Some(value) => {
+                        self.mux_kv
+                            .operation
+                            .set(Operation::Stage(change, StageStep::Write));
+                        self.transaction_append(key, value);
+                    }
+                    None => {
+                        self.hashed_key.replace(key);
+                        self.stage_done(change, Err(ErrorCode::NOMEM));
+                    }
+                }
+            }
+            Some(Operation::Commit(CommitStep::Invalidate)) => {
+                let tombstone = self.staged_value.map_or(false, |value| {
+                    KeyHeader::new_from_buf(value).length == TOMBSTONE_LENGTH
+                });
+                if tombstone {
+                    stage_key(key);
+                    self.mux_kv
+                        .operation
+                        .set(Operation::Commit(CommitStep::ClearStaged));
+                    self.transaction_invalidate(key);
+                } else if let Some(value) = self.staged_value.take() {
+                    self.mux_kv
+                        .operation
+                        .set(Operation::Commit(CommitStep::Write));
+                    self.transaction_append(key, value);
+                }
+            }
+            Some(Operation::Commit(CommitStep::ClearStaged)) | Some(Operation::Abort) => {
+                self.hashed_key.replace(key);
+                self.change.set(self.change.get() + 1);
+                if self.mux_kv.operation.contains(&Operation::Abort) {
+                    self.abort_change();
+                } else {
+                    self.apply_change();
+                }
+            }
+            Some(Operation::Commit(CommitStep::ClearJournal)) => {
+                self.hashed_key.replace(key);
+                self.mux_kv.recovered.set(true);
+                self.journal.map(|journal| journal[0] = 0);
+                self.commit_done(Ok(()));
+            }
+            _ => {}
+        }
+    }
 }
 
 impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_system::Client<T>
@@ -293,6 +933,12 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
     ) {
         self.unhashed_key.replace(unhashed_key);
 
+        if let Some(Operation::Stage(change, StageStep::Hash)) = self.mux_kv.operation.extract() {
+            self.transaction_generate_key_complete(change, result, hashed_key);
+            self.mux_kv.do_next_op();
+            return;
+        }
+
         self.mux_kv.operation.map(|op| {
             if result.is_err() {
                 self.hashed_key.replace(hashed_key);
@@ -317,6 +963,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
                             cb.delete_complete(result, unhashed_key);
                         });
                     }
+                    Operation::Stage(..) | Operation::Commit(_) | Operation::Abort => {}
                 });
             } else {
                 match op {
@@ -362,6 +1009,7 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
                             }
                         });
                     }
+                    Operation::Stage(..) | Operation::Commit(_) | Operation::Abort => {}
                 }
             }
         });
@@ -375,11 +1023,21 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
         key: &'static mut T,
         value: &'static mut [u8],
     ) {
+        if let Some(Operation::Stage(..) | Operation::Commit(_)) = self.mux_kv.operation.extract() {
+            self.transaction_append_complete(result, key, value);
+            self.mux_kv.do_next_op();
+            return;
+        }
+
         self.hashed_key.replace(key);
         self.value.replace(value);
 
         self.mux_kv.operation.map(|op| match op {
-            Operation::Get | Operation::Delete => {}
+            Operation::Get
+            | Operation::Delete
+            | Operation::Stage(..)
+            | Operation::Commit(_)
+            | Operation::Abort => {}
             Operation::Set => {
                 self.unhashed_key.take().map(|unhashed_key| {
                     self.value.take().map(|value| {
@@ -401,10 +1059,16 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
         key: &'static mut T,
         ret_buf: &'static mut [u8],
     ) {
+        if let Some(Operation::Stage(..) | Operation::Commit(_)) = self.mux_kv.operation.extract() {
+            self.transaction_get_complete(result, key, ret_buf);
+            self.mux_kv.do_next_op();
+            return;
+        }
+
         self.hashed_key.replace(key);
 
         self.mux_kv.operation.map(|op| match op {
-            Operation::Set => {}
+            Operation::Set | Operation::Stage(..) | Operation::Commit(_) | Operation::Abort => {}
             Operation::Delete => {
                 let mut access_allowed = false;
 
@@ -481,10 +1145,22 @@ impl<'a, K: KVSystem<'a, K = T>, T: kv_system::KeyType + core::fmt::Debug> kv_sy
     }
 
     fn invalidate_key_complete(&self, result: Result<(), ErrorCode>, key: &'static mut T) {
+        if let Some(Operation::Stage(..) | Operation::Commit(_) | Operation::Abort) =
+            self.mux_kv.operation.extract()
+        {
+            self.transaction_invalidate_complete(result, key);
+            self.mux_kv.do_next_op();
+            return;
+        }
+
         self.hashed_key.replace(key);
 
         self.mux_kv.operation.map(|op| match op {
-            Operation::Set | Operation::Get => {}
+            Operation::Set
+            | Operation::Get
+            | Operation::Stage(..)
+            | Operation::Commit(_)
+            | Operation::Abort => {}
             Operation::Delete => {
                 self.unhashed_key.take().map(|unhashed_key| {
                     self.client.map(move |cb| {
@@ -509,6 +1185,8 @@ pub struct MuxKVStore<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static + kv
     kv: &'a K,
     operation: OptionalCell<Operation>,
     perform_cleanup: Cell<bool>,
+    /// No journal of an interrupted commit is left in flash.
+    recovered: Cell<bool>,
     users: List<'a, KVStore<'a, K, T>>,
 }
 
@@ -520,6 +1198,7 @@ impl<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
             kv,
             operation: OptionalCell::empty(),
             perform_cleanup: Cell::new(false),
+            recovered: Cell::new(false),
             users: List::new(),
         }
     }
@@ -532,7 +1211,7 @@ impl<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
         let mnode = self.users.iter().find(|node| node.next_operation.is_some());
 
         let ret = mnode.map_or(Err(ErrorCode::NODEVICE), |node| {
-            node.next_operation.map(|op| {
+            node.next_operation.take().map(|op| {
                 self.operation.set(op.clone());
 
                 node.unhashed_key.take().map(|unhashed_key| {
@@ -575,6 +1254,19 @@ impl<'a, K: KVSystem<'a> + KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
                                     });
                                 }
                             }
+                            Operation::Stage(change, _) => {
+                                node.valid_ids.insert(node.next_valid_ids.take());
+
+                                if let Err((unhashed_key, hashed_key, e)) =
+                                    self.kv.generate_key(unhashed_key, hashed_key)
+                                {
+                                    node.hashed_key.replace(hashed_key);
+                                    node.unhashed_key.replace(unhashed_key);
+                                    node.stage_done(change, e);
+                                }
+                            }
+                            // Not queued.
+                            Operation::Commit(_) | Operation::Abort => {}
                         };
                     });
                 });
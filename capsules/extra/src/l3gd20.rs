@@ -456,16 +456,13 @@ impl spi::SpiMasterClient for L3gd20Spi<'_> {
                                     };
                                     let x: usize =
                                         ((buf[1] as i16 | ((buf[2] as i16) << 8)) as isize * scale
-                                            / 100000)
-                                            as usize;
+                                            / 100) as usize;
                                     let y: usize =
                                         ((buf[3] as i16 | ((buf[4] as i16) << 8)) as isize * scale
-                                            / 100000)
-                                            as usize;
+                                            / 100) as usize;
                                     let z: usize =
                                         ((buf[5] as i16 | ((buf[6] as i16) << 8)) as isize * scale
-                                            / 100000)
-                                            as usize;
+                                            / 100) as usize;
                                     client.callback(x, y, z);
                                 });
                                 // actual computation is this one
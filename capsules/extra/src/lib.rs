@@ -11,28 +11,46 @@ pub mod test;
 pub mod net;
 
 pub mod adc_microphone;
+pub mod adc_stream;
 pub mod air_quality;
+pub mod allow_audit;
 pub mod ambient_light;
 pub mod analog_comparator;
 pub mod analog_sensor;
 pub mod apds9960;
 pub mod app_flash_driver;
+pub mod app_install;
+pub mod app_preferences;
+pub mod app_update;
+pub mod blackboard;
 pub mod ble_advertising_driver;
+pub mod ble_security;
+pub mod block_storage;
 pub mod bme280;
 pub mod bmp280;
 pub mod bus;
+pub mod bus_diagnostics;
 pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
+pub mod cmsis_dap;
+pub mod compressed_log;
 pub mod crc;
+pub mod crc_software;
+pub mod crypto_self_test;
 pub mod dac;
 pub mod debug_process_restart;
+pub mod filesystem_driver;
+pub mod flash_cache;
 pub mod fm25cl;
+pub mod frequency_counter;
+pub mod ft5336;
 pub mod ft6x06;
 pub mod fxos8700cq;
 pub mod gpio_async;
 pub mod hd44780;
+pub mod hid_input;
 pub mod hmac;
 pub mod hts221;
 pub mod humidity;
@@ -42,7 +60,9 @@ pub mod kv_driver;
 pub mod kv_store;
 pub mod l3gd20;
 pub mod led_matrix;
+pub mod littlefs;
 pub mod log;
+pub mod logic_analyzer;
 pub mod lpm013m126;
 pub mod lps25hb;
 pub mod lsm303agr;
@@ -52,6 +72,8 @@ pub mod lsm6dsoxtr;
 pub mod ltc294x;
 pub mod max17205;
 pub mod mcp230xx;
+pub mod message_queue;
+pub mod midi;
 pub mod mlx90614;
 pub mod mx25r6435f;
 pub mod ninedof;
@@ -60,14 +82,22 @@ pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
 pub mod panic_button;
 pub mod pca9544a;
+pub mod provisioning;
 pub mod proximity;
 pub mod public_key_crypto;
+pub mod pulse_counter;
+pub mod pulse_generator;
 pub mod pwm;
+pub mod pwm_audio;
+pub mod qspi_flash;
 pub mod read_only_state;
+pub mod record_log;
+pub mod record_log_driver;
 pub mod rf233;
 pub mod rf233_const;
 pub mod screen;
 pub mod sdcard;
+pub mod sdmmc;
 pub mod segger_rtt;
 pub mod seven_segment;
 pub mod sha;
@@ -77,13 +107,23 @@ pub mod si7021;
 pub mod sip_hash;
 pub mod sound_pressure;
 pub mod st77xx;
+pub mod swd_bitbang;
 pub mod symmetric_encryption;
+pub mod syscall_latency;
+pub mod system_info;
+pub mod telemetry;
 pub mod temperature;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod text_screen;
 pub mod tickv;
 pub mod touch;
+pub mod trace_export;
 pub mod tsl2561;
+pub mod uart_flow_control;
+pub mod uart_idle;
 pub mod usb;
 pub mod usb_hid_driver;
+pub mod usb_host;
+pub mod uuid;
+pub mod wear_leveling;
@@ -0,0 +1,1386 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! The littlefs filesystem on a `hil::flash::Flash`, see the [module
+//! documentation](super).
+//!
+//! Operations are written as functions that make as much progress as they
+//! can and return `Poll::Pending` once they started a flash operation. They
+//! are called again when it completes. The progress is kept in the cells of
+//! `LittleFs`, so the functions may run again from their start, and every
+//! step they take is done once.
+
+use core::cell::Cell;
+use core::task::Poll;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::filesystem::{self, DirEntry, EntryKind, OpenMode};
+use kernel::hil::flash::{self, Flash};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::format::{self, le32, mktag, types, Commits, Dir, BLOCK_NULL};
+
+/// Evaluates to the value of a ready `Poll<Result<T, ErrorCode>>`, returns
+/// the error or `Poll::Pending` otherwise.
+macro_rules! try_ready {
+    ($e:expr) => {
+        match $e {
+            Poll::Ready(Ok(value)) => value,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+    };
+}
+
+/// Longest file name accepted.
+pub const NAME_MAX: usize = 255;
+
+/// An open file.
+struct File {
+    open: Cell<bool>,
+    writable: Cell<bool>,
+    /// The id of the file in the root directory.
+    id: Cell<usize>,
+    /// The last block of the contents, `BLOCK_NULL` while there is none.
+    head: Cell<u32>,
+    size: Cell<u32>,
+    /// Where the file is read from.
+    pos: Cell<u32>,
+    /// The contents are inline in the root directory.
+    inline: Cell<bool>,
+    /// The head block was written since the last sync, so it is not part of
+    /// the file on flash and may be changed in place.
+    fresh: Cell<bool>,
+    /// The file changed since the last sync.
+    dirty: Cell<bool>,
+}
+
+impl File {
+    fn new() -> File {
+        File {
+            open: Cell::new(false),
+            writable: Cell::new(false),
+            id: Cell::new(0),
+            head: Cell::new(BLOCK_NULL),
+            size: Cell::new(0),
+            pos: Cell::new(0),
+            inline: Cell::new(false),
+            fresh: Cell::new(false),
+            dirty: Cell::new(false),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Format,
+    Open(OpenMode),
+    Close(usize),
+    Sync(usize),
+    Read(usize),
+    Write(usize),
+    Remove,
+    ReadDir(usize),
+}
+
+/// Where a traversal of the filesystem, which finds the blocks in use, is.
+#[derive(Clone, Copy)]
+enum Traversal {
+    /// At entry `usize` of the root directory.
+    Root(usize),
+    /// Reading block `half` of the metadata pair of the next directory. `rev`
+    /// is the revision of the first block, if it is valid.
+    Fetch {
+        pair: [u32; 2],
+        half: usize,
+        rev: Option<u32>,
+    },
+    /// At `entry` of the directory in metadata block `block`.
+    Entries { block: u32, entry: usize },
+    /// At the `usize`th file handle, for files open for writing.
+    Files(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlashOp {
+    Idle,
+    Read(u32),
+    Erase,
+    Write,
+}
+
+/// What a file lookup found.
+enum Lookup {
+    Found {
+        id: usize,
+        kind: u16,
+        data_type: u16,
+        ctz: Option<(u32, u32)>,
+        size: u32,
+    },
+    /// The file does not exist, it would be created with this id.
+    Missing(usize),
+}
+
+pub struct LittleFs<'a, F: Flash + 'static, const FILES: usize> {
+    flash: &'a F,
+    first_page: usize,
+    max_blocks: u32,
+    block_size: usize,
+    block_count: Cell<u32>,
+    client: OptionalCell<&'a dyn filesystem::Client>,
+    deferred_call: DeferredCall,
+
+    /// The newer block of the root metadata pair, with its commits replayed
+    /// into `root_dir`.
+    root: TakeCell<'static, F::Page>,
+    root_dir: MapCell<Dir>,
+    /// The block of the root pair `root` is from, 0 or 1.
+    root_block: Cell<u32>,
+    root_commits: Cell<Commits>,
+    mounted: Cell<bool>,
+    mount_step: Cell<u8>,
+    /// Revision of a valid first block of the root pair, while mounting.
+    mount_rev: Cell<Option<u32>>,
+
+    /// Holds one block of file data or of a directory other than the root.
+    /// Changes are written back when the buffer is needed for another block.
+    io: TakeCell<'static, F::Page>,
+    io_block: OptionalCell<u32>,
+    io_dirty: Cell<bool>,
+    /// The block still has to be erased before `io` is written to it.
+    io_erase: Cell<bool>,
+    flash_op: Cell<FlashOp>,
+    flash_error: Cell<Option<ErrorCode>>,
+    /// A commit of the root directory is being written.
+    committing: Cell<bool>,
+
+    /// A bit per block, set for the blocks in use.
+    used: TakeCell<'static, [u8]>,
+    used_valid: Cell<bool>,
+    /// `used` was rebuilt since the last allocation or commit, so a full
+    /// `used` means that the storage is full.
+    rescanned: Cell<bool>,
+    cursor: Cell<u32>,
+    /// A block that was allocated but not used yet.
+    allocated: OptionalCell<u32>,
+    traversing: Cell<bool>,
+    traversal: Cell<Traversal>,
+    /// Block and index of the file block a traversal or read is at.
+    walk: OptionalCell<(u32, u32)>,
+    /// Directories visited by the traversal, to stop at loops.
+    visited: Cell<u32>,
+
+    files: [File; FILES],
+    op: OptionalCell<Op>,
+    /// Progress of the operation.
+    step: Cell<u8>,
+    /// The name or data buffer of the operation.
+    buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    done: Cell<usize>,
+    /// The id of the file an operation creates or removes.
+    target: Cell<usize>,
+    /// Pointers of a new block of a file.
+    pointers: [Cell<u32>; 32],
+    result: OptionalCell<Result<usize, ErrorCode>>,
+    entry: OptionalCell<DirEntry>,
+}
+
+impl<'a, F: Flash + 'static, const FILES: usize> LittleFs<'a, F, FILES> {
+    /// Uses up to `max_blocks` pages of `flash` from `first_page` on, one
+    /// block per page. `used` needs a bit per block.
+    pub fn new(
+        flash: &'a F,
+        first_page: usize,
+        max_blocks: u32,
+        root: &'static mut F::Page,
+        io: &'static mut F::Page,
+        used: &'static mut [u8],
+    ) -> LittleFs<'a, F, FILES> {
+        let block_size = root.as_mut().len();
+        LittleFs {
+            flash,
+            first_page,
+            max_blocks: max_blocks.min(used.len() as u32 * 8),
+            block_size,
+            block_count: Cell::new(max_blocks),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            root: TakeCell::new(root),
+            root_dir: MapCell::new(Dir::new()),
+            root_block: Cell::new(0),
+            root_commits: Cell::new(Commits {
+                rev: 0,
+                end: 0,
+                ptag: 0,
+            }),
+            mounted: Cell::new(false),
+            mount_step: Cell::new(0),
+            mount_rev: Cell::new(None),
+            io: TakeCell::new(io),
+            io_block: OptionalCell::empty(),
+            io_dirty: Cell::new(false),
+            io_erase: Cell::new(false),
+            flash_op: Cell::new(FlashOp::Idle),
+            flash_error: Cell::new(None),
+            committing: Cell::new(false),
+            used: TakeCell::new(used),
+            used_valid: Cell::new(false),
+            rescanned: Cell::new(false),
+            cursor: Cell::new(0),
+            allocated: OptionalCell::empty(),
+            traversing: Cell::new(false),
+            traversal: Cell::new(Traversal::Root(0)),
+            walk: OptionalCell::empty(),
+            visited: Cell::new(0),
+            files: [(); FILES].map(|()| File::new()),
+            op: OptionalCell::empty(),
+            step: Cell::new(0),
+            buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            done: Cell::new(0),
+            target: Cell::new(0),
+            pointers: [(); 32].map(|()| Cell::new(BLOCK_NULL)),
+            result: OptionalCell::empty(),
+            entry: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self, op: Op) {
+        self.op.set(op);
+        self.step.set(0);
+        self.done.set(0);
+        self.mount_step.set(0);
+        self.traversing.set(false);
+        self.walk.clear();
+        self.poll();
+    }
+
+    /// Runs the operation until it waits for the flash or is done.
+    fn poll(&self) {
+        let op = match self.op.extract() {
+            Some(op) if self.result.is_none() => op,
+            _ => return,
+        };
+        let result = if op != Op::Format && !self.mounted.get() {
+            match self.mount() {
+                Poll::Ready(Ok(())) => self.run(op),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        } else {
+            self.run(op)
+        };
+        if let Poll::Ready(result) = result {
+            if let Op::Close(file) = op {
+                self.files[file].open.set(false);
+            }
+            self.result.set(result);
+            self.deferred_call.set();
+        }
+    }
+
+    fn run(&self, op: Op) -> Poll<Result<usize, ErrorCode>> {
+        match op {
+            Op::Format => self.format().map(|result| result.map(|()| 0)),
+            Op::Open(mode) => self.open_file(mode),
+            Op::Close(file) | Op::Sync(file) => {
+                self.sync_file(file).map(|result| result.map(|()| 0))
+            }
+            Op::Read(file) => self.read_file(file),
+            Op::Write(file) => self.write_file(file),
+            Op::Remove => self.remove_file(),
+            Op::ReadDir(index) => Poll::Ready(self.read_dir_entry(index)),
+        }
+    }
+
+    fn with_root<R>(&self, f: impl FnOnce(&[u8], &Dir) -> R) -> Result<R, ErrorCode> {
+        self.root
+            .map(|root| self.root_dir.map(|dir| f(root.as_mut(), dir)))
+            .flatten()
+            .ok_or(ErrorCode::FAIL)
+    }
+
+    fn check_file(&self, file: usize) -> Result<&File, ErrorCode> {
+        match self.files.get(file) {
+            Some(f) if f.open.get() => Ok(f),
+            _ => Err(ErrorCode::INVAL),
+        }
+    }
+
+    // Blocks.
+
+    fn take_error(&self) -> Result<(), ErrorCode> {
+        match self.flash_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes `io` hold `block`.
+    fn load(&self, block: u32) -> Poll<Result<(), ErrorCode>> {
+        self.take_error()?;
+        if self.io_block.contains(&block) {
+            return Poll::Ready(Ok(()));
+        }
+        if block >= self.block_count.get() {
+            return Poll::Ready(Err(ErrorCode::FAIL));
+        }
+        try_ready!(self.flush_io());
+        self.io_block.clear();
+        match self.io.take() {
+            Some(io) => match self.flash.read_page(self.first_page + block as usize, io) {
+                Ok(()) => {
+                    self.flash_op.set(FlashOp::Read(block));
+                    Poll::Pending
+                }
+                Err((e, io)) => {
+                    self.io.replace(io);
+                    Poll::Ready(Err(e))
+                }
+            },
+            None => Poll::Ready(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// Writes `io` to its block if it changed.
+    fn flush_io(&self) -> Poll<Result<(), ErrorCode>> {
+        self.take_error()?;
+        if !self.io_dirty.get() {
+            return Poll::Ready(Ok(()));
+        }
+        let page = self.first_page + self.io_block.map_or(0, |block| *block as usize);
+        if self.io_erase.get() {
+            return match self.flash.erase_page(page) {
+                Ok(()) => {
+                    self.flash_op.set(FlashOp::Erase);
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            };
+        }
+        match self.io.take() {
+            Some(io) => match self.flash.write_page(page, io) {
+                Ok(()) => {
+                    self.flash_op.set(FlashOp::Write);
+                    Poll::Pending
+                }
+                Err((e, io)) => {
+                    self.io.replace(io);
+                    Poll::Ready(Err(e))
+                }
+            },
+            None => Poll::Ready(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// Marks `io` as changed. Blocks are always erased before they are
+    /// written, as not every flash can program bytes twice.
+    fn dirty_io(&self) {
+        self.io_dirty.set(true);
+        self.io_erase.set(true);
+    }
+
+    /// Makes `io` hold the erased `block`. `io` must have been flushed.
+    fn new_block(&self, block: u32) {
+        self.io.map(|io| io.as_mut().fill(0xff));
+        self.io_block.set(block);
+        self.dirty_io();
+    }
+
+    fn io_word(&self, off: usize) -> u32 {
+        self.io.map_or(BLOCK_NULL, |io| le32(io.as_mut(), off))
+    }
+
+    // Allocation.
+
+    fn mark(&self, block: u32) {
+        self.used.map(|used| {
+            if let Some(byte) = used.get_mut(block as usize / 8) {
+                *byte |= 1 << (block % 8);
+            }
+        });
+    }
+
+    /// Finds a free block. It stays allocated until the caller takes it out
+    /// of `allocated`.
+    fn alloc(&self) -> Poll<Result<u32, ErrorCode>> {
+        if let Some(block) = self.allocated.extract() {
+            return Poll::Ready(Ok(block));
+        }
+        loop {
+            if !self.used_valid.get() {
+                try_ready!(self.traverse());
+                self.used_valid.set(true);
+                self.rescanned.set(true);
+            }
+            let count = self.block_count.get();
+            let start = self.cursor.get();
+            let found = self.used.map_or(None, |used| {
+                (0..count).map(|i| (start + i) % count).find(|&block| {
+                    let byte = &mut used[block as usize / 8];
+                    let free = *byte & (1 << (block % 8)) == 0;
+                    if free {
+                        *byte |= 1 << (block % 8);
+                    }
+                    free
+                })
+            });
+            match found {
+                Some(block) => {
+                    self.cursor.set(block + 1);
+                    self.rescanned.set(false);
+                    self.allocated.set(block);
+                    return Poll::Ready(Ok(block));
+                }
+                None if self.rescanned.get() => return Poll::Ready(Err(ErrorCode::NOMEM)),
+                None => self.used_valid.set(false),
+            }
+        }
+    }
+
+    /// Rebuilds `used` from the blocks of all directories and files, and of
+    /// the files open for writing.
+    fn traverse(&self) -> Poll<Result<(), ErrorCode>> {
+        if !self.traversing.get() {
+            self.used.map(|used| used.fill(0));
+            self.mark(0);
+            self.mark(1);
+            self.traversal.set(Traversal::Root(0));
+            self.walk.clear();
+            self.visited.set(0);
+            self.traversing.set(true);
+        }
+        let result = self.traverse_steps();
+        if let Poll::Ready(_) = result {
+            self.traversing.set(false);
+        }
+        result
+    }
+
+    fn traverse_steps(&self) -> Poll<Result<(), ErrorCode>> {
+        loop {
+            try_ready!(self.walk_file());
+            match self.traversal.get() {
+                Traversal::Root(entry) => {
+                    let (next, tail) =
+                        self.with_root(|root, dir| (next_file(root, dir, entry), dir.tail))?;
+                    match next {
+                        Some((id, head, size)) => {
+                            self.start_walk(head, size);
+                            self.traversal.set(Traversal::Root(id + 1));
+                        }
+                        None => self.traversal.set(self.after_dir(tail)?),
+                    }
+                }
+                Traversal::Fetch { pair, half, rev } => {
+                    try_ready!(self.load(pair[half]));
+                    self.mark(pair[half]);
+                    let parsed = self
+                        .io
+                        .map(|io| format::parse(io.as_mut(), &mut Dir::new()).map(|c| c.rev))
+                        .flatten();
+                    if half == 0 {
+                        self.traversal.set(Traversal::Fetch {
+                            pair,
+                            half: 1,
+                            rev: parsed,
+                        });
+                    } else {
+                        let newer = match (rev, parsed) {
+                            (Some(rev0), Some(rev1)) => newer(rev0, rev1) as usize,
+                            (Some(_), None) => 0,
+                            (None, Some(_)) => 1,
+                            (None, None) => return Poll::Ready(Err(ErrorCode::FAIL)),
+                        };
+                        self.traversal.set(Traversal::Entries {
+                            block: pair[newer],
+                            entry: 0,
+                        });
+                    }
+                }
+                Traversal::Entries { block, entry } => {
+                    try_ready!(self.load(block));
+                    let (next, tail) = self
+                        .io
+                        .map(|io| {
+                            let mut dir = Dir::new();
+                            format::parse(io.as_mut(), &mut dir)
+                                .map(|_| (next_file(io.as_mut(), &dir, entry), dir.tail))
+                        })
+                        .flatten()
+                        .ok_or(ErrorCode::FAIL)?;
+                    match next {
+                        Some((id, head, size)) => {
+                            self.start_walk(head, size);
+                            self.traversal.set(Traversal::Entries {
+                                block,
+                                entry: id + 1,
+                            });
+                        }
+                        None => self.traversal.set(self.after_dir(tail)?),
+                    }
+                }
+                Traversal::Files(index) => {
+                    let file = match self.files.get(index) {
+                        Some(file) => file,
+                        None => return Poll::Ready(Ok(())),
+                    };
+                    if file.open.get() && file.writable.get() {
+                        self.start_walk(file.head.get(), file.size.get());
+                    }
+                    self.traversal.set(Traversal::Files(index + 1));
+                }
+            }
+        }
+    }
+
+    /// Where the traversal continues after a directory with `tail`.
+    fn after_dir(&self, tail: Option<(u16, [u32; 2])>) -> Result<Traversal, ErrorCode> {
+        match tail {
+            Some((_, pair)) => {
+                // All directories are on a list starting at the root.
+                self.visited.set(self.visited.get() + 1);
+                if self.visited.get() > self.block_count.get() || pair.contains(&0) {
+                    return Err(ErrorCode::FAIL);
+                }
+                Ok(Traversal::Fetch {
+                    pair,
+                    half: 0,
+                    rev: None,
+                })
+            }
+            None => Ok(Traversal::Files(0)),
+        }
+    }
+
+    fn start_walk(&self, head: u32, size: u32) {
+        if size > 0 && head != BLOCK_NULL {
+            self.walk
+                .set((head, format::ctz_index(self.block_size, size - 1).0));
+        }
+    }
+
+    /// Marks the blocks of the file being walked.
+    fn walk_file(&self) -> Poll<Result<(), ErrorCode>> {
+        while let Some((block, index)) = self.walk.extract() {
+            self.mark(block);
+            if index == 0 {
+                self.walk.clear();
+                break;
+            }
+            try_ready!(self.load(block));
+            self.walk.set((self.io_word(0), index - 1));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    // The root directory.
+
+    fn mount(&self) -> Poll<Result<(), ErrorCode>> {
+        if self.mount_step.get() == 0 {
+            try_ready!(self.load(0));
+            let rev = self.check_io();
+            if rev.is_some() {
+                self.copy_io_to_root(0);
+            }
+            self.mount_rev.set(rev);
+            self.mount_step.set(1);
+        }
+        try_ready!(self.load(1));
+        let first = self.mount_rev.get();
+        let second = self.check_io();
+        self.mount_step.set(0);
+        match (first, second) {
+            (Some(rev0), Some(rev1)) if newer(rev0, rev1) => self.copy_io_to_root(1),
+            (None, Some(_)) => self.copy_io_to_root(1),
+            (None, None) => return Poll::Ready(Err(ErrorCode::FAIL)),
+            _ => {}
+        }
+
+        let block_count = self
+            .root
+            .map(|root| {
+                self.root_dir.map(|dir| {
+                    let root = root.as_mut();
+                    let commits = format::parse(root, dir)?;
+                    // Whatever follows the valid commits is dropped.
+                    root[commits.end..].fill(0xff);
+                    self.root_commits.set(commits);
+                    format::check_superblock(root, dir)
+                })
+            })
+            .flatten()
+            .flatten();
+        match block_count {
+            Some(count) if count >= 2 && count <= self.max_blocks => {
+                self.block_count.set(count);
+                self.mounted.set(true);
+                self.used_valid.set(false);
+                self.allocated.clear();
+                Poll::Ready(Ok(()))
+            }
+            _ => Poll::Ready(Err(ErrorCode::FAIL)),
+        }
+    }
+
+    /// The revision of the metadata block in `io`, if it is valid.
+    fn check_io(&self) -> Option<u32> {
+        self.io
+            .map(|io| format::parse(io.as_mut(), &mut Dir::new()).map(|commits| commits.rev))
+            .flatten()
+    }
+
+    fn copy_io_to_root(&self, block: u32) {
+        self.root
+            .map(|root| self.io.map(|io| root.as_mut().copy_from_slice(io.as_mut())));
+        self.root_block.set(block);
+    }
+
+    /// Commits `attrs` to the root directory. The new state of the directory
+    /// is compacted into the other block of the root pair with a higher
+    /// revision, so a commit interrupted by a power loss leaves the old
+    /// state.
+    fn commit(&self, attrs: &[(u32, &[u8])]) -> Poll<Result<(), ErrorCode>> {
+        if !self.committing.get() {
+            try_ready!(self.flush_io());
+            let other = 1 - self.root_block.get();
+            self.stage(attrs)?;
+            self.io_block.set(other);
+            self.dirty_io();
+            self.committing.set(true);
+        }
+        match self.flush_io() {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => {
+                self.committing.set(false);
+                self.io_block.clear();
+                self.io_dirty.set(false);
+                self.unstage();
+                Poll::Ready(Err(e))
+            }
+            Poll::Ready(Ok(())) => {
+                self.committing.set(false);
+                if let (Some(root), Some(io)) = (self.root.take(), self.io.take()) {
+                    self.root.replace(io);
+                    self.io.replace(root);
+                }
+                self.io_block.clear();
+                self.root_block.set(1 - self.root_block.get());
+                self.root
+                    .map(|root| {
+                        self.root_dir
+                            .map(|dir| format::parse(root.as_mut(), dir))
+                            .flatten()
+                    })
+                    .flatten()
+                    .map(|commits| self.root_commits.set(commits));
+                // The commit may have freed blocks.
+                self.rescanned.set(false);
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    /// Appends a commit of `attrs` to `root` and compacts the result into
+    /// `io`.
+    fn stage(&self, attrs: &[(u32, &[u8])]) -> Result<(), ErrorCode> {
+        self.root
+            .map(|root| {
+                self.io.map(|io| {
+                    let root = root.as_mut();
+                    let io = io.as_mut();
+                    let mut dir = Dir::new();
+                    let mut commits = self.root_commits.get();
+                    if !append(root, commits, attrs) {
+                        // Make room by dropping what later commits override.
+                        root[commits.end..].fill(0xff);
+                        format::parse(root, &mut dir).ok_or(ErrorCode::FAIL)?;
+                        commits =
+                            format::compact(root, &dir, io, commits.rev).ok_or(ErrorCode::NOMEM)?;
+                        root.copy_from_slice(io);
+                        self.root_commits.set(commits);
+                        self.root_dir.map(|root_dir| format::parse(root, root_dir));
+                        if !append(root, commits, attrs) {
+                            root[commits.end..].fill(0xff);
+                            return Err(ErrorCode::NOMEM);
+                        }
+                    }
+                    let compacted = format::parse(root, &mut dir)
+                        .and_then(|_| format::compact(root, &dir, io, commits.rev.wrapping_add(1)));
+                    if compacted.is_none() {
+                        root[commits.end..].fill(0xff);
+                        return Err(ErrorCode::NOMEM);
+                    }
+                    Ok(())
+                })
+            })
+            .flatten()
+            .unwrap_or(Err(ErrorCode::FAIL))
+    }
+
+    /// Drops the commit `stage` appended to `root`.
+    fn unstage(&self) {
+        let end = self.root_commits.get().end;
+        self.root.map(|root| root.as_mut()[end..].fill(0xff));
+    }
+
+    /// Finds the entry named by the operation's buffer.
+    fn lookup(&self) -> Result<Lookup, ErrorCode> {
+        let len = self.len.get();
+        self.buffer
+            .map(|name| {
+                let name = &name[..len];
+                self.with_root(|root, dir| match dir.find(root, name) {
+                    Some(id) => {
+                        let entry = dir.entries[id];
+                        Lookup::Found {
+                            id,
+                            kind: entry.kind,
+                            data_type: entry.data_type,
+                            ctz: entry.ctz(root),
+                            size: entry.size(root),
+                        }
+                    }
+                    None => Lookup::Missing(dir.insert_id(root, name)),
+                })
+            })
+            .unwrap_or(Err(ErrorCode::FAIL))
+    }
+
+    /// Adjusts the ids of open files after an entry was created or removed
+    /// at `id`.
+    fn shift_ids(&self, id: usize, created: bool) {
+        for file in self.files.iter().filter(|file| file.open.get()) {
+            if created && file.id.get() >= id {
+                file.id.set(file.id.get() + 1);
+            } else if !created && file.id.get() > id {
+                file.id.set(file.id.get() - 1);
+            }
+        }
+    }
+
+    // Operations.
+
+    fn format(&self) -> Poll<Result<(), ErrorCode>> {
+        if self.step.get() == 0 {
+            for file in self.files.iter() {
+                file.open.set(false);
+            }
+            self.mounted.set(false);
+            self.committing.set(false);
+            self.io_dirty.set(false);
+            self.take_error()?;
+            // The second block of the root pair is erased, so that no older
+            // filesystem in it is mounted.
+            self.new_block(1);
+            self.step.set(1);
+        }
+        if self.step.get() == 1 {
+            try_ready!(self.flush_io());
+            self.new_block(0);
+            let count = self.max_blocks;
+            self.io.map(|io| format::format_root(io.as_mut(), count));
+            self.step.set(2);
+        }
+        try_ready!(self.flush_io());
+        self.copy_io_to_root(0);
+        let commits = self
+            .root
+            .map(|root| {
+                self.root_dir
+                    .map(|dir| format::parse(root.as_mut(), dir))
+                    .flatten()
+            })
+            .flatten()
+            .ok_or(ErrorCode::FAIL)?;
+        self.root_commits.set(commits);
+        self.block_count.set(self.max_blocks);
+        self.mounted.set(true);
+        self.used_valid.set(false);
+        self.allocated.clear();
+        self.cursor.set(0);
+        Poll::Ready(Ok(()))
+    }
+
+    fn open_file(&self, mode: OpenMode) -> Poll<Result<usize, ErrorCode>> {
+        let file = match self.files.iter().position(|file| !file.open.get()) {
+            Some(file) => file,
+            None => return Poll::Ready(Err(ErrorCode::NOMEM)),
+        };
+        let f = &self.files[file];
+
+        if self.step.get() == 0 {
+            let (id, data_type, ctz, size) = match self.lookup()? {
+                Lookup::Found { kind, .. } if kind != types::REG => {
+                    return Poll::Ready(Err(ErrorCode::INVAL))
+                }
+                Lookup::Found {
+                    id,
+                    data_type,
+                    ctz,
+                    size,
+                    ..
+                } => (id, data_type, ctz, size),
+                Lookup::Missing(_) if mode == OpenMode::Read => {
+                    return Poll::Ready(Err(ErrorCode::NODEVICE))
+                }
+                Lookup::Missing(id) => {
+                    self.target.set(id);
+                    self.step.set(1);
+                    return self.open_file(mode);
+                }
+            };
+            let conflict = self.files.iter().any(|other| {
+                other.open.get()
+                    && other.id.get() == id
+                    && (other.writable.get() || mode != OpenMode::Read)
+            });
+            if conflict {
+                return Poll::Ready(Err(ErrorCode::BUSY));
+            }
+
+            f.id.set(id);
+            f.pos.set(0);
+            f.writable.set(mode != OpenMode::Read);
+            f.inline.set(false);
+            f.fresh.set(false);
+            f.dirty.set(false);
+            match (mode, ctz) {
+                (OpenMode::Write, _) => {
+                    // Truncated, which takes effect on sync.
+                    f.head.set(BLOCK_NULL);
+                    f.size.set(0);
+                    f.dirty.set(size > 0);
+                }
+                (_, Some((head, size))) => {
+                    f.head.set(head);
+                    f.size.set(size);
+                }
+                (OpenMode::Read, None) => {
+                    f.head.set(BLOCK_NULL);
+                    f.size.set(size);
+                    f.inline.set(data_type == types::INLINESTRUCT);
+                }
+                (OpenMode::Append, None) if size > 0 => {
+                    // Inline data is moved into the first block of the file.
+                    self.target.set(id);
+                    self.step.set(2);
+                    return self.open_file(mode);
+                }
+                (OpenMode::Append, None) => {
+                    f.head.set(BLOCK_NULL);
+                    f.size.set(0);
+                }
+            }
+            f.pos.set(if mode == OpenMode::Append {
+                f.size.get()
+            } else {
+                0
+            });
+            f.open.set(true);
+            return Poll::Ready(Ok(file));
+        }
+
+        let id = self.target.get();
+        if self.step.get() == 1 {
+            // A new file starts out empty and inline.
+            let len = self.len.get();
+            let name = self.buffer.map_or([0; NAME_MAX], |buffer| {
+                let mut name = [0; NAME_MAX];
+                name[..len].copy_from_slice(&buffer[..len]);
+                name
+            });
+            try_ready!(self.commit(&[
+                (mktag(types::CREATE, id as u16, 0), &[]),
+                (mktag(types::REG, id as u16, len as u16), &name[..len]),
+                (mktag(types::INLINESTRUCT, id as u16, 0), &[]),
+            ]));
+            self.shift_ids(id, true);
+            f.head.set(BLOCK_NULL);
+            f.size.set(0);
+            f.fresh.set(false);
+            f.dirty.set(false);
+        } else {
+            let block = try_ready!(self.alloc());
+            try_ready!(self.flush_io());
+            self.allocated.clear();
+            self.new_block(block);
+            let size = self.with_root(|root, dir| {
+                let data = dir.entries[id].data(root);
+                self.io
+                    .map(|io| io.as_mut()[..data.len()].copy_from_slice(data));
+                data.len() as u32
+            })?;
+            f.head.set(block);
+            f.size.set(size);
+            f.fresh.set(true);
+            f.dirty.set(true);
+        }
+        f.id.set(id);
+        f.writable.set(true);
+        f.inline.set(false);
+        f.pos.set(f.size.get());
+        f.open.set(true);
+        Poll::Ready(Ok(file))
+    }
+
+    fn sync_file(&self, file: usize) -> Poll<Result<(), ErrorCode>> {
+        let f = &self.files[file];
+        if !f.dirty.get() {
+            return Poll::Ready(Ok(()));
+        }
+        let id = f.id.get() as u16;
+        let mut ctz = [0; 8];
+        ctz[..4].copy_from_slice(&f.head.get().to_le_bytes());
+        ctz[4..].copy_from_slice(&f.size.get().to_le_bytes());
+        let attr = if f.size.get() == 0 {
+            (mktag(types::INLINESTRUCT, id, 0), &[][..])
+        } else {
+            (mktag(types::CTZSTRUCT, id, 8), &ctz[..])
+        };
+        // The commit writes the head block first.
+        try_ready!(self.commit(&[attr]));
+        f.dirty.set(false);
+        f.fresh.set(false);
+        Poll::Ready(Ok(()))
+    }
+
+    fn read_file(&self, file: usize) -> Poll<Result<usize, ErrorCode>> {
+        let f = &self.files[file];
+        let len = self.len.get();
+        if f.inline.get() {
+            let pos = f.pos.get() as usize;
+            let read = self.with_root(|root, dir| {
+                let data = dir.entries[f.id.get()].data(root);
+                let data = data.get(pos..).unwrap_or(&[]);
+                let n = data.len().min(len);
+                self.buffer
+                    .map(|buffer| buffer[..n].copy_from_slice(&data[..n]));
+                n
+            })?;
+            f.pos.set((pos + read) as u32);
+            return Poll::Ready(Ok(read));
+        }
+
+        loop {
+            let done = self.done.get();
+            let pos = f.pos.get();
+            let size = f.size.get();
+            if done == len || pos >= size {
+                return Poll::Ready(Ok(done));
+            }
+            let (target, off) = format::ctz_index(self.block_size, pos);
+            if self.walk.is_none() {
+                let last = format::ctz_index(self.block_size, size - 1).0;
+                self.walk.set((f.head.get(), last));
+            }
+            // Follow the skip-list back to the block holding `pos`.
+            let block = loop {
+                let (block, index) = self.walk.unwrap_or((BLOCK_NULL, 0));
+                if index <= target {
+                    break block;
+                }
+                try_ready!(self.load(block));
+                let (skip, index) = format::ctz_skip(index, target);
+                self.walk.set((self.io_word(4 * skip), index));
+            };
+            try_ready!(self.load(block));
+            let n = (len - done)
+                .min(self.block_size - off)
+                .min((size - pos) as usize);
+            self.io.map(|io| {
+                self.buffer.map(|buffer| {
+                    buffer[done..done + n].copy_from_slice(&io.as_mut()[off..off + n])
+                })
+            });
+            self.done.set(done + n);
+            f.pos.set(pos + n as u32);
+            self.walk.clear();
+        }
+    }
+
+    fn write_file(&self, file: usize) -> Poll<Result<usize, ErrorCode>> {
+        let f = &self.files[file];
+        loop {
+            let done = self.done.get();
+            if done == self.len.get() {
+                return Poll::Ready(Ok(done));
+            }
+            match self.extend_file(f) {
+                Poll::Ready(Ok(())) => {}
+                // What fit is written.
+                Poll::Ready(Err(ErrorCode::NOMEM)) if done > 0 => return Poll::Ready(Ok(done)),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Appends what fits in the head block of `f`, or gets a head block with
+    /// room.
+    fn extend_file(&self, f: &File) -> Poll<Result<(), ErrorCode>> {
+        let size = f.size.get();
+        if f.head.get() == BLOCK_NULL {
+            let block = try_ready!(self.alloc());
+            try_ready!(self.flush_io());
+            self.allocated.clear();
+            self.new_block(block);
+            f.head.set(block);
+            f.fresh.set(true);
+            f.dirty.set(true);
+            return Poll::Ready(Ok(()));
+        }
+
+        let (index, off) = format::ctz_index(self.block_size, size);
+        let current = match size {
+            0 => 0,
+            _ => format::ctz_index(self.block_size, size - 1).0,
+        };
+        if index > current {
+            // The head block is full. The next block starts with pointers to
+            // the blocks `index - 2^i` before it, found in those blocks.
+            let pointers = format::ctz_pointers(index);
+            self.pointers[0].set(f.head.get());
+            let mut i = (self.step.get() as usize).max(1);
+            while i < pointers {
+                try_ready!(self.load(self.pointers[i - 1].get()));
+                self.pointers[i].set(self.io_word(4 * (i - 1)));
+                i += 1;
+                self.step.set(i as u8);
+            }
+            let block = try_ready!(self.alloc());
+            try_ready!(self.flush_io());
+            self.allocated.clear();
+            self.step.set(0);
+            self.new_block(block);
+            self.io.map(|io| {
+                for (i, pointer) in self.pointers[..pointers].iter().enumerate() {
+                    io.as_mut()[4 * i..4 * i + 4].copy_from_slice(&pointer.get().to_le_bytes());
+                }
+            });
+            f.head.set(block);
+            f.fresh.set(true);
+            f.dirty.set(true);
+            return Poll::Ready(Ok(()));
+        }
+
+        if !f.fresh.get() {
+            // The head block is part of the synced file, it is copied so
+            // that the synced file stays intact until the next sync.
+            let block = try_ready!(self.alloc());
+            try_ready!(self.load(f.head.get()));
+            self.allocated.clear();
+            self.io_block.set(block);
+            self.dirty_io();
+            f.head.set(block);
+            f.fresh.set(true);
+            f.dirty.set(true);
+            return Poll::Ready(Ok(()));
+        }
+
+        try_ready!(self.load(f.head.get()));
+        let done = self.done.get();
+        let n = (self.len.get() - done).min(self.block_size - off);
+        self.io.map(|io| {
+            self.buffer
+                .map(|buffer| io.as_mut()[off..off + n].copy_from_slice(&buffer[done..done + n]))
+        });
+        self.dirty_io();
+        f.size.set(size + n as u32);
+        f.dirty.set(true);
+        self.done.set(done + n);
+        Poll::Ready(Ok(()))
+    }
+
+    fn remove_file(&self) -> Poll<Result<usize, ErrorCode>> {
+        if self.step.get() == 0 {
+            let id = match self.lookup()? {
+                Lookup::Found { kind, .. } if kind != types::REG => {
+                    return Poll::Ready(Err(ErrorCode::NOSUPPORT))
+                }
+                Lookup::Found { id, .. } => id,
+                Lookup::Missing(_) => return Poll::Ready(Err(ErrorCode::NODEVICE)),
+            };
+            if self
+                .files
+                .iter()
+                .any(|file| file.open.get() && file.id.get() == id)
+            {
+                return Poll::Ready(Err(ErrorCode::BUSY));
+            }
+            self.target.set(id);
+            self.step.set(1);
+        }
+        let id = self.target.get();
+        try_ready!(self.commit(&[(mktag(types::DELETE, id as u16, 0), &[])]));
+        self.shift_ids(id, false);
+        Poll::Ready(Ok(0))
+    }
+
+    fn read_dir_entry(&self, index: usize) -> Result<usize, ErrorCode> {
+        let entry = self.with_root(|root, dir| {
+            dir.entries[..dir.count]
+                .iter()
+                .filter(|entry| entry.kind == types::REG || entry.kind == types::DIR)
+                .nth(index)
+                .map(|entry| {
+                    let name = entry.name(root);
+                    self.buffer.map(|buffer| {
+                        let n = name.len().min(buffer.len());
+                        buffer[..n].copy_from_slice(&name[..n]);
+                    });
+                    DirEntry {
+                        kind: match entry.kind {
+                            types::DIR => EntryKind::Directory,
+                            _ => EntryKind::File,
+                        },
+                        size: match entry.kind {
+                            types::DIR => 0,
+                            _ => entry.size(root),
+                        },
+                        name_len: name.len(),
+                    }
+                })
+        })?;
+        let entry = entry.ok_or(ErrorCode::NODEVICE)?;
+        self.entry.set(entry);
+        Ok(0)
+    }
+}
+
+/// Whether revision `b` is newer than `a`, allowing for overflow.
+fn newer(a: u32, b: u32) -> bool {
+    (b.wrapping_sub(a) as i32) > 0
+}
+
+/// The first file stored in blocks, at or after entry `from` of `dir`, with
+/// its id, head block and size.
+fn next_file(block: &[u8], dir: &Dir, from: usize) -> Option<(usize, u32, u32)> {
+    dir.entries[..dir.count]
+        .iter()
+        .enumerate()
+        .skip(from)
+        .find_map(|(id, entry)| entry.ctz(block).map(|(head, size)| (id, head, size)))
+}
+
+fn append(block: &mut [u8], commits: Commits, attrs: &[(u32, &[u8])]) -> bool {
+    let mut commit = format::Commit::new(block, commits.end, commits.ptag);
+    if attrs.iter().all(|(tag, data)| commit.push(*tag, data)) {
+        commit.finish();
+        true
+    } else {
+        false
+    }
+}
+
+impl<'a, F: Flash + 'static, const FILES: usize> filesystem::FileSystem<'a>
+    for LittleFs<'a, F, FILES>
+{
+    fn set_client(&self, client: &'a dyn filesystem::Client) {
+        self.client.set(client);
+    }
+
+    fn format(&self) -> Result<(), ErrorCode> {
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start(Op::Format);
+        Ok(())
+    }
+
+    fn open(
+        &self,
+        name: &'static mut [u8],
+        name_len: usize,
+        mode: OpenMode,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.is_some() {
+            return Err((ErrorCode::BUSY, name));
+        }
+        if name_len == 0 || name_len > name.len() || name_len > NAME_MAX {
+            return Err((ErrorCode::INVAL, name));
+        }
+        self.buffer.replace(name);
+        self.len.set(name_len);
+        self.start(Op::Open(mode));
+        Ok(())
+    }
+
+    fn close(&self, file: usize) -> Result<(), ErrorCode> {
+        self.check_file(file)?;
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start(Op::Close(file));
+        Ok(())
+    }
+
+    fn sync(&self, file: usize) -> Result<(), ErrorCode> {
+        self.check_file(file)?;
+        if self.op.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start(Op::Sync(file));
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        file: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        match self.check_file(file) {
+            Ok(f) if !f.writable.get() => {}
+            _ => return Err((ErrorCode::INVAL, buffer)),
+        }
+        if self.op.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.len.set(len.min(buffer.len()));
+        self.buffer.replace(buffer);
+        self.start(Op::Read(file));
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        file: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        match self.check_file(file) {
+            Ok(f) if f.writable.get() => {}
+            _ => return Err((ErrorCode::INVAL, buffer)),
+        }
+        if self.op.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.len.set(len.min(buffer.len()));
+        self.buffer.replace(buffer);
+        self.start(Op::Write(file));
+        Ok(())
+    }
+
+    fn seek(&self, file: usize, offset: u32) -> Result<(), ErrorCode> {
+        let f = self.check_file(file)?;
+        if f.writable.get() && offset != f.size.get() {
+            // Files are only written at their end.
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if offset > f.size.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        f.pos.set(offset);
+        Ok(())
+    }
+
+    fn size(&self, file: usize) -> Result<u32, ErrorCode> {
+        self.check_file(file).map(|f| f.size.get())
+    }
+
+    fn remove(
+        &self,
+        name: &'static mut [u8],
+        name_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.is_some() {
+            return Err((ErrorCode::BUSY, name));
+        }
+        if name_len == 0 || name_len > name.len() || name_len > NAME_MAX {
+            return Err((ErrorCode::INVAL, name));
+        }
+        self.buffer.replace(name);
+        self.len.set(name_len);
+        self.start(Op::Remove);
+        Ok(())
+    }
+
+    fn read_dir(
+        &self,
+        index: usize,
+        name: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.op.is_some() {
+            return Err((ErrorCode::BUSY, name));
+        }
+        self.buffer.replace(name);
+        self.start(Op::ReadDir(index));
+        Ok(())
+    }
+}
+
+impl<'a, F: Flash + 'static, const FILES: usize> flash::Client<F> for LittleFs<'a, F, FILES> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, error: flash::Error) {
+        self.io.replace(read_buffer);
+        if let FlashOp::Read(block) = self.flash_op.get() {
+            if error == flash::Error::CommandComplete {
+                self.io_block.set(block);
+            } else {
+                self.flash_error.set(Some(ErrorCode::FAIL));
+            }
+        }
+        self.flash_op.set(FlashOp::Idle);
+        self.poll();
+    }
+
+    fn write_complete(&self, write_buffer: &'static mut F::Page, error: flash::Error) {
+        self.io.replace(write_buffer);
+        self.io_dirty.set(false);
+        if error != flash::Error::CommandComplete {
+            // The changes are lost.
+            self.io_block.clear();
+            self.flash_error.set(Some(ErrorCode::FAIL));
+        }
+        self.flash_op.set(FlashOp::Idle);
+        self.poll();
+    }
+
+    fn erase_complete(&self, error: flash::Error) {
+        if error == flash::Error::CommandComplete {
+            self.io_erase.set(false);
+        } else {
+            self.io_dirty.set(false);
+            self.io_block.clear();
+            self.flash_error.set(Some(ErrorCode::FAIL));
+        }
+        self.flash_op.set(FlashOp::Idle);
+        self.poll();
+    }
+}
+
+impl<'a, F: Flash + 'static, const FILES: usize> DeferredCallClient for LittleFs<'a, F, FILES> {
+    fn handle_deferred_call(&self) {
+        let result = match self.result.take() {
+            Some(result) => result,
+            None => return,
+        };
+        let op = match self.op.take() {
+            Some(op) => op,
+            None => return,
+        };
+        let buffer = self.buffer.take();
+        self.client.map(|client| match (op, buffer) {
+            (Op::Format, _) => client.formatted(result.map(|_| ())),
+            (Op::Open(_), Some(name)) => client.opened(result, name),
+            (Op::Close(file), _) => client.closed(file, result.map(|_| ())),
+            (Op::Sync(file), _) => client.synced(file, result.map(|_| ())),
+            (Op::Read(file), Some(buffer)) => client.read_done(file, buffer, result),
+            (Op::Write(file), Some(buffer)) => client.write_done(file, buffer, result),
+            (Op::Remove, Some(name)) => client.removed(result.map(|_| ()), name),
+            (Op::ReadDir(_), Some(name)) => client.dir_entry(
+                result.and_then(|_| self.entry.take().ok_or(ErrorCode::FAIL)),
+                name,
+            ),
+            _ => {}
+        });
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,539 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! The on-disk format of littlefs v2.
+//!
+//! A metadata block starts with a 32-bit revision count, followed by commits.
+//! A commit is a list of tags, each optionally followed by data, closed by a
+//! CRC tag with the CRC-32 of the commit. Tags are stored big endian and
+//! XORed with the previous tag of the block. The entries of a directory are
+//! numbered by id, and the tags of an entry give its name, its contents (a
+//! skip-list of blocks, or data inline in the metadata block) and user
+//! attributes. Later tags of an id override earlier ones.
+//!
+//! Files are stored as a backwards skip-list of blocks: block `i` of a file
+//! starts with `ctz(i) + 1` pointers, to blocks `i - 1`, `i - 2`, `i - 4`,
+//! and so on, followed by data. Block 0 has no pointers.
+
+/// The null block pointer.
+pub const BLOCK_NULL: u32 = 0xffff_ffff;
+
+/// Commits end at a multiple of this, so that flashes with a program size up
+/// to it can append the next commit.
+pub const PROG_ALIGN: usize = 16;
+
+/// The most entries, including the superblock, a directory can have.
+pub const MAX_ENTRIES: usize = 32;
+
+pub const MAGIC: &[u8] = b"littlefs";
+const VERSION: u32 = 0x0002_0000;
+const NAME_MAX: u32 = 255;
+const FILE_MAX: u32 = 0x7fff_ffff;
+const ATTR_MAX: u32 = 1022;
+
+/// Length of the superblock entry.
+pub const SUPERBLOCK_LEN: usize = 24;
+
+/// Tag types.
+pub mod types {
+    pub const REG: u16 = 0x001;
+    pub const DIR: u16 = 0x002;
+    pub const SUPERBLOCK: u16 = 0x0ff;
+    pub const INLINESTRUCT: u16 = 0x201;
+    pub const CTZSTRUCT: u16 = 0x202;
+    pub const CREATE: u16 = 0x401;
+    pub const DELETE: u16 = 0x4ff;
+    pub const CRC: u16 = 0x500;
+    pub const MOVESTATE: u16 = 0x7ff;
+
+    // The classes of types, the upper 3 bits.
+    pub const NAME: u16 = 0x000;
+    pub const STRUCT: u16 = 0x200;
+    pub const SPLICE: u16 = 0x400;
+    pub const TAIL: u16 = 0x600;
+    pub const GLOBALS: u16 = 0x700;
+}
+
+/// The id of tags that do not belong to an entry.
+pub const NO_ID: u16 = 0x3ff;
+
+pub fn mktag(typ: u16, id: u16, size: u16) -> u32 {
+    (typ as u32) << 20 | (id as u32) << 10 | size as u32
+}
+
+fn tag_valid(tag: u32) -> bool {
+    tag & 0x8000_0000 == 0
+}
+
+fn tag_type1(tag: u32) -> u16 {
+    ((tag >> 20) & 0x700) as u16
+}
+
+fn tag_type3(tag: u32) -> u16 {
+    ((tag >> 20) & 0x7ff) as u16
+}
+
+fn tag_chunk(tag: u32) -> u8 {
+    (tag >> 20) as u8
+}
+
+fn tag_id(tag: u32) -> usize {
+    ((tag >> 10) & 0x3ff) as usize
+}
+
+/// Length of the tag with its data. A size of `0x3ff` marks a deleted tag
+/// without data.
+fn tag_dsize(tag: u32) -> usize {
+    let size = tag & 0x3ff;
+    4 + if size == 0x3ff { 0 } else { size as usize }
+}
+
+pub fn le32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+fn be32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+/// The CRC-32 of littlefs, with the reflected polynomial `0xedb88320` and no
+/// final XOR. Commits start with `crc` set to `0xffffffff`.
+pub fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    const TABLE: [u32; 16] = [
+        0x00000000, 0x1db71064, 0x3b6e20c8, 0x26d930ac, 0x76dc4190, 0x6b6b51f4, 0x4db26158,
+        0x5005713c, 0xedb88320, 0xf00f9344, 0xd6d6a3e8, 0xcb61b38c, 0x9b64c2b0, 0x86d3d2d4,
+        0xa00ae278, 0xbdbdf21c,
+    ];
+    for &byte in data {
+        crc = (crc >> 4) ^ TABLE[((crc ^ byte as u32) & 0xf) as usize];
+        crc = (crc >> 4) ^ TABLE[((crc ^ (byte as u32 >> 4)) & 0xf) as usize];
+    }
+    crc
+}
+
+/// The index of the block of a file that holds byte `pos`, and the offset of
+/// the byte in that block.
+pub fn ctz_index(block_size: usize, pos: u32) -> (u32, usize) {
+    let b = block_size as u32 - 8;
+    let i = pos / b;
+    if i == 0 {
+        return (0, pos as usize);
+    }
+    let i = (pos - 4 * ((i - 1).count_ones() + 2)) / b;
+    (i, (pos - b * i - 4 * i.count_ones()) as usize)
+}
+
+/// The number of pointers at the start of block `index` of a file.
+pub fn ctz_pointers(index: u32) -> usize {
+    if index == 0 {
+        0
+    } else {
+        index.trailing_zeros() as usize + 1
+    }
+}
+
+/// The pointer of block `index` to follow on the way to block `target`, and
+/// the index of the block it points to.
+pub fn ctz_skip(index: u32, target: u32) -> (usize, u32) {
+    // The exponent of the power of two at least `index - target + 1`.
+    let npw2 = 32 - (index - target).leading_zeros();
+    let skip = (npw2 - 1).min(index.trailing_zeros());
+    (skip as usize, index - (1 << skip))
+}
+
+/// An entry of a directory. Names and data are given as offset and length in
+/// the metadata block.
+#[derive(Clone, Copy, Default)]
+pub struct Entry {
+    /// `types::REG`, `DIR` or `SUPERBLOCK`, 0 until the entry has a name.
+    pub kind: u16,
+    pub name: (u16, u16),
+    /// The type of the struct tag, 0 if there is none.
+    pub data_type: u16,
+    pub data: (u16, u16),
+}
+
+impl Entry {
+    pub fn name<'b>(&self, block: &'b [u8]) -> &'b [u8] {
+        &block[self.name.0 as usize..(self.name.0 + self.name.1) as usize]
+    }
+
+    pub fn data<'b>(&self, block: &'b [u8]) -> &'b [u8] {
+        &block[self.data.0 as usize..(self.data.0 + self.data.1) as usize]
+    }
+
+    /// The head block and size of a file stored in blocks.
+    pub fn ctz(&self, block: &[u8]) -> Option<(u32, u32)> {
+        let data = self.data(block);
+        if self.data_type == types::CTZSTRUCT && data.len() >= 8 {
+            Some((le32(data, 0), le32(data, 4)))
+        } else {
+            None
+        }
+    }
+
+    /// The size of the file, inline or in blocks.
+    pub fn size(&self, block: &[u8]) -> u32 {
+        match self.data_type {
+            types::INLINESTRUCT => self.data.1 as u32,
+            _ => self.ctz(block).map_or(0, |(_, size)| size),
+        }
+    }
+}
+
+/// The state of a metadata block after replaying its commits.
+pub struct Dir {
+    pub entries: [Entry; MAX_ENTRIES],
+    pub count: usize,
+    /// Type and pair of the tail of the directory.
+    pub tail: Option<(u16, [u32; 2])>,
+    /// This block's delta of the global state.
+    pub gstate: [u8; 12],
+}
+
+impl Dir {
+    pub const fn new() -> Dir {
+        Dir {
+            entries: [Entry {
+                kind: 0,
+                name: (0, 0),
+                data_type: 0,
+                data: (0, 0),
+            }; MAX_ENTRIES],
+            count: 0,
+            tail: None,
+            gstate: [0; 12],
+        }
+    }
+
+    /// The id of the entry named `name`.
+    pub fn find(&self, block: &[u8], name: &[u8]) -> Option<usize> {
+        self.entries[..self.count].iter().position(|entry| {
+            (entry.kind == types::REG || entry.kind == types::DIR) && entry.name(block) == name
+        })
+    }
+
+    /// The id at which littlefs inserts a new entry named `name`, which keeps
+    /// the names in order.
+    pub fn insert_id(&self, block: &[u8], name: &[u8]) -> usize {
+        self.entries[..self.count]
+            .iter()
+            .position(|entry| {
+                (entry.kind == types::REG || entry.kind == types::DIR) && {
+                    let other = entry.name(block);
+                    // Ordered as by littlefs, which puts longer names first
+                    // among names with the same prefix.
+                    let len = other.len().min(name.len());
+                    match other[..len].cmp(&name[..len]) {
+                        core::cmp::Ordering::Equal => name.len() > other.len(),
+                        ordering => ordering == core::cmp::Ordering::Greater,
+                    }
+                }
+            })
+            .unwrap_or(self.count)
+    }
+}
+
+/// The revision, end and last tag of the valid commits of a metadata block.
+#[derive(Clone, Copy)]
+pub struct Commits {
+    pub rev: u32,
+    pub end: usize,
+    pub ptag: u32,
+}
+
+/// Returns the end of the last commit with a valid CRC and its last tag.
+fn valid_end(block: &[u8]) -> Option<(usize, u32)> {
+    let mut off = 4;
+    let mut ptag = 0xffff_ffff;
+    let mut crc = crc32(0xffff_ffff, &block[..4]);
+    let mut end = None;
+    while off + 4 <= block.len() {
+        let tag = be32(block, off) ^ ptag;
+        let dsize = tag_dsize(tag);
+        if !tag_valid(tag) || off + dsize > block.len() {
+            break;
+        }
+        crc = crc32(crc, &block[off..off + 4]);
+        if tag_type1(tag) == types::CRC {
+            if dsize < 8 || le32(block, off + 4) != crc {
+                break;
+            }
+            ptag = tag ^ ((tag_chunk(tag) as u32 & 1) << 31);
+            crc = 0xffff_ffff;
+            end = Some((off + dsize, ptag));
+        } else {
+            crc = crc32(crc, &block[off + 4..off + dsize]);
+            ptag = tag;
+        }
+        off += dsize;
+    }
+    end
+}
+
+/// Replays the valid commits of metadata block `block` into `dir`. Returns
+/// `None` if the block has no valid commit or more entries than a `Dir`
+/// holds.
+pub fn parse(block: &[u8], dir: &mut Dir) -> Option<Commits> {
+    if block.len() < 4 {
+        return None;
+    }
+    let rev = le32(block, 0);
+    let (end, last) = valid_end(block)?;
+    *dir = Dir::new();
+
+    let mut off = 4;
+    let mut ptag = 0xffff_ffff;
+    while off < end {
+        let tag = be32(block, off) ^ ptag;
+        let dsize = tag_dsize(tag);
+        let id = tag_id(tag);
+        let data = ((off + 4) as u16, (dsize - 4) as u16);
+        match tag_type1(tag) {
+            types::CRC => {
+                ptag = tag ^ ((tag_chunk(tag) as u32 & 1) << 31);
+                off += dsize;
+                continue;
+            }
+            types::NAME => {
+                if id >= MAX_ENTRIES {
+                    return None;
+                }
+                dir.count = dir.count.max(id + 1);
+                dir.entries[id].kind = tag_type3(tag);
+                dir.entries[id].name = data;
+            }
+            types::STRUCT if id < dir.count => {
+                dir.entries[id].data_type = tag_type3(tag);
+                dir.entries[id].data = data;
+            }
+            types::SPLICE if tag_type3(tag) == types::CREATE => {
+                if dir.count >= MAX_ENTRIES || id > dir.count {
+                    return None;
+                }
+                dir.entries.copy_within(id..dir.count, id + 1);
+                dir.entries[id] = Entry::default();
+                dir.count += 1;
+            }
+            types::SPLICE if tag_type3(tag) == types::DELETE && id < dir.count => {
+                dir.entries.copy_within(id + 1..dir.count, id);
+                dir.count -= 1;
+            }
+            types::TAIL if dsize >= 12 => {
+                dir.tail = Some((tag_type3(tag), [le32(block, off + 4), le32(block, off + 8)]));
+            }
+            types::GLOBALS if dsize >= 16 => {
+                for (delta, byte) in dir.gstate.iter_mut().zip(&block[off + 4..off + 16]) {
+                    *delta ^= byte;
+                }
+            }
+            _ => {}
+        }
+        ptag = tag;
+        off += dsize;
+    }
+    Some(Commits {
+        rev,
+        end,
+        ptag: last,
+    })
+}
+
+/// Appends a commit to a metadata block.
+pub struct Commit<'b> {
+    block: &'b mut [u8],
+    off: usize,
+    ptag: u32,
+    crc: u32,
+}
+
+impl<'b> Commit<'b> {
+    /// Starts a commit at `off`, after the commit ending with `ptag`. The
+    /// block must be erased from `off` on.
+    pub fn new(block: &'b mut [u8], off: usize, ptag: u32) -> Commit<'b> {
+        // The first commit of a block covers the revision count.
+        let crc = if off == 4 {
+            crc32(0xffff_ffff, &block[..4])
+        } else {
+            0xffff_ffff
+        };
+        Commit {
+            block,
+            off,
+            ptag,
+            crc,
+        }
+    }
+
+    /// Adds `tag` with its data. Returns false if it does not fit in the
+    /// block, leaving the commit unusable.
+    pub fn push(&mut self, tag: u32, data: &[u8]) -> bool {
+        let end = self.off + 4 + data.len();
+        // Room for the CRC tag is kept.
+        if end + 8 > self.block.len() {
+            return false;
+        }
+        self.block[self.off..self.off + 4].copy_from_slice(&(tag ^ self.ptag).to_be_bytes());
+        self.block[self.off + 4..end].copy_from_slice(data);
+        self.crc = crc32(self.crc, &self.block[self.off..end]);
+        self.ptag = tag;
+        self.off = end;
+        true
+    }
+
+    /// Closes the commit with its CRC. Returns the end of the commit and its
+    /// last tag, from which the next commit continues.
+    pub fn finish(self) -> (usize, u32) {
+        let len = self.block.len();
+        let end = ((self.off + 8 + PROG_ALIGN - 1) / PROG_ALIGN * PROG_ALIGN).min(len);
+        // The reset bit is the inverse of the valid bit of the next word, so
+        // that the erased word after the commit reads as an invalid tag.
+        let next = if end + 4 <= len {
+            be32(self.block, end)
+        } else {
+            0xffff_ffff
+        };
+        let reset = !next >> 31;
+        let tag = mktag(
+            types::CRC + reset as u16,
+            NO_ID,
+            (end - self.off - 4) as u16,
+        );
+        self.block[self.off..self.off + 4].copy_from_slice(&(tag ^ self.ptag).to_be_bytes());
+        let crc = crc32(self.crc, &self.block[self.off..self.off + 4]);
+        self.block[self.off + 4..self.off + 8].copy_from_slice(&crc.to_le_bytes());
+        (end, tag ^ (reset << 31))
+    }
+}
+
+/// Writes the entries of `dir`, replayed from `src`, as the only commit of
+/// `dst` with revision `rev`. Returns `None` if they do not fit.
+pub fn compact(src: &[u8], dir: &Dir, dst: &mut [u8], rev: u32) -> Option<Commits> {
+    dst.fill(0xff);
+    dst[..4].copy_from_slice(&rev.to_le_bytes());
+    let mut commit = Commit::new(dst, 4, 0xffff_ffff);
+    for (id, entry) in dir.entries[..dir.count].iter().enumerate() {
+        let id = id as u16;
+        if entry.kind != 0 && !commit.push(mktag(entry.kind, id, entry.name.1), entry.name(src)) {
+            return None;
+        }
+        if entry.data_type != 0
+            && !commit.push(mktag(entry.data_type, id, entry.data.1), entry.data(src))
+        {
+            return None;
+        }
+    }
+    if dir.gstate != [0; 12] && !commit.push(mktag(types::MOVESTATE, NO_ID, 12), &dir.gstate) {
+        return None;
+    }
+    if let Some((typ, pair)) = dir.tail {
+        let mut data = [0; 8];
+        data[..4].copy_from_slice(&pair[0].to_le_bytes());
+        data[4..].copy_from_slice(&pair[1].to_le_bytes());
+        if !commit.push(mktag(typ, NO_ID, 8), &data) {
+            return None;
+        }
+    }
+    let (end, ptag) = commit.finish();
+    Some(Commits { rev, end, ptag })
+}
+
+/// Writes an empty root directory with the superblock entry to `block`.
+pub fn format_root(block: &mut [u8], block_count: u32) -> Commits {
+    let mut superblock = [0; SUPERBLOCK_LEN];
+    let fields = [
+        VERSION,
+        block.len() as u32,
+        block_count,
+        NAME_MAX,
+        FILE_MAX,
+        ATTR_MAX,
+    ];
+    for (chunk, field) in superblock.chunks_mut(4).zip(fields) {
+        chunk.copy_from_slice(&field.to_le_bytes());
+    }
+
+    block.fill(0xff);
+    block[..4].copy_from_slice(&1u32.to_le_bytes());
+    let mut commit = Commit::new(block, 4, 0xffff_ffff);
+    commit.push(mktag(types::CREATE, 0, 0), &[]);
+    commit.push(mktag(types::SUPERBLOCK, 0, MAGIC.len() as u16), MAGIC);
+    commit.push(
+        mktag(types::INLINESTRUCT, 0, SUPERBLOCK_LEN as u16),
+        &superblock,
+    );
+    let (end, ptag) = commit.finish();
+    Commits { rev: 1, end, ptag }
+}
+
+/// Checks the superblock entry of a root directory. Returns the block count
+/// of the filesystem.
+pub fn check_superblock(block: &[u8], dir: &Dir) -> Option<u32> {
+    let entry = dir.entries.first().filter(|_| dir.count > 0)?;
+    let data = entry.data(block);
+    if entry.kind != types::SUPERBLOCK
+        || entry.name(block) != MAGIC
+        || entry.data_type != types::INLINESTRUCT
+        || data.len() < SUPERBLOCK_LEN
+        || le32(data, 0) >> 16 != VERSION >> 16
+        || le32(data, 4) as usize != block.len()
+    {
+        return None;
+    }
+    Some(le32(data, 8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_and_skip_list() {
+        assert_eq!(crc32(0xffff_ffff, b"123456789"), !0xcbf4_3926);
+
+        // Block 0 holds a whole block of data, block 1 starts with one
+        // pointer.
+        assert_eq!(ctz_index(512, 511), (0, 511));
+        assert_eq!(ctz_index(512, 512), (1, 4));
+        assert_eq!(ctz_index(512, 512 + 507), (1, 511));
+        assert_eq!(ctz_index(512, 512 + 508), (2, 8));
+        assert_eq!(ctz_pointers(4), 3);
+        assert_eq!(ctz_skip(4, 0), (2, 0));
+        assert_eq!(ctz_skip(5, 3), (0, 4));
+    }
+
+    #[test]
+    fn commits_replay() {
+        let mut block = [0xff; 256];
+        let commits = format_root(&mut block, 64);
+        let mut dir = Dir::new();
+        assert!(parse(&block, &mut dir).is_some());
+        assert_eq!(check_superblock(&block, &dir), Some(64));
+
+        let mut commit = Commit::new(&mut block, commits.end, commits.ptag);
+        assert!(commit.push(mktag(types::CREATE, 1, 0), &[]));
+        assert!(commit.push(mktag(types::REG, 1, 3), b"log"));
+        assert!(commit.push(mktag(types::CTZSTRUCT, 1, 8), &[2, 0, 0, 0, 10, 0, 0, 0]));
+        commit.finish();
+        let commits = parse(&block, &mut dir).unwrap();
+        assert_eq!(dir.count, 2);
+        assert_eq!(dir.find(&block, b"log"), Some(1));
+        assert_eq!(dir.entries[1].ctz(&block), Some((2, 10)));
+        assert_eq!(dir.insert_id(&block, b"a"), 1);
+        assert_eq!(dir.insert_id(&block, b"logs"), 1);
+        assert_eq!(dir.insert_id(&block, b"m"), 2);
+
+        // A torn commit is ignored.
+        let mut commit = Commit::new(&mut block, commits.end, commits.ptag);
+        assert!(commit.push(mktag(types::DELETE, 1, 0), &[]));
+        assert_eq!(parse(&block, &mut dir).unwrap().end, commits.end);
+        assert_eq!(dir.count, 2);
+
+        let mut compacted = [0; 256];
+        compact(&block, &dir, &mut compacted, 2).unwrap();
+        assert_eq!(parse(&compacted, &mut dir).unwrap().rev, 2);
+        assert_eq!(dir.find(&compacted, b"log"), Some(1));
+        assert_eq!(check_superblock(&compacted, &dir), Some(64));
+    }
+}
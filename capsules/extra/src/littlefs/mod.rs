@@ -0,0 +1,57 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A filesystem in the on-disk format of littlefs v2.
+//!
+//! `LittleFs` stores files on a range of pages of a `hil::flash::Flash`,
+//! e.g. an external SPI NOR flash, one littlefs block per page. Kernel
+//! clients use it through `hil::filesystem::FileSystem`, processes through
+//! the `filesystem_driver` capsule. Images of the flash can be read and
+//! written on a host with the littlefs tools, e.g. `littlefs-python` or
+//! `littlefs-fuse`, configured with the page size as block size. The flash
+//! has to be formatted either on a host or with `format()` first.
+//!
+//! The implementation is small and its RAM use is fixed: a page buffer for
+//! the root directory, one for file data, a bit per block and the state of
+//! `FILES` file handles. It supports a subset of littlefs:
+//!
+//! - Files are in the root directory. Subdirectories created on a host are
+//!   listed and kept, but their files can not be opened.
+//! - The root directory holds at most 31 files and has to fit in one block.
+//!   Root directories that a host split over several blocks only show the
+//!   files of the first one.
+//! - Files are read from any position, but only written at their end: a file
+//!   is opened to be replaced (`OpenMode::Write`) or to be appended to. What
+//!   was written is kept in RAM until a block is full, and becomes part of
+//!   the file on `sync()` and `close()`.
+//! - User attributes and a pending move of a host that lost power are
+//!   dropped.
+//!
+//! Power loss is handled as by littlefs: blocks of a file are never changed
+//! once they were synced, and a commit to the root directory is written,
+//! compacted and with a higher revision, to the other block of the root
+//! metadata pair. Writes to a file after the last sync are lost. As every
+//! commit rewrites a block of the root pair, these two blocks wear out
+//! faster than the others.
+//!
+//! Free blocks are found by a traversal of all files, which is done when
+//! the blocks known to be free run out.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! // A filesystem over the first 512 blocks of the MX25R6435F, with up to 4
+//! // files open.
+//! let fs = components::littlefs::LittleFsComponent::new(mx25r6435f, 0, 512).finalize(
+//!     components::littlefs_component_static!(
+//!         capsules_extra::mx25r6435f::MX25R6435F<'static, ...>,
+//!         4,
+//!         512
+//!     ),
+//! );
+//! ```
+
+pub mod filesystem;
+pub mod format;
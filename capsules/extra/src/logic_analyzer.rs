@@ -0,0 +1,435 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Logic analyzer speaking the SUMP protocol.
+//!
+//! Samples up to 8 channels into RAM and sends the captures over a UART,
+//! usually a USB CDC-ACM serial port, using the SUMP protocol of the Openbench
+//! Logic Sniffer. This is supported by sigrok (as the `ols` driver) and
+//! PulseView, so a spare board becomes a simple logic analyzer.
+//!
+//! The channels are read through a `Sampler`. `GpioSampler` reads up to 8
+//! GPIO pins one after another. A chip that can read a whole port with a
+//! single register access can implement `Sampler` for it, to sample faster
+//! and without skew between the channels.
+//!
+//! Samples are taken with a busy loop, so the sample rate is only
+//! approximate. While waiting for the trigger, the kernel runs between bursts
+//! of samples, so a capture can be cancelled. Once triggered, the capsule
+//! takes the remaining samples without interruption.
+//!
+//! Only trigger stage 0 is supported, in parallel mode. Samples are sent
+//! newest first, as the protocol requires.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let la_pins = static_init!(
+//!     [&'static RPGpioPin<'static>; 4],
+//!     [
+//!         peripherals.pins.get_pin(RPGpio::GPIO6),
+//!         peripherals.pins.get_pin(RPGpio::GPIO7),
+//!         peripherals.pins.get_pin(RPGpio::GPIO8),
+//!         peripherals.pins.get_pin(RPGpio::GPIO9),
+//!     ]
+//! );
+//! let sampler = static_init!(
+//!     capsules_extra::logic_analyzer::GpioSampler<'static, RPGpioPin<'static>>,
+//!     capsules_extra::logic_analyzer::GpioSampler::new(la_pins)
+//! );
+//! let logic_analyzer = components::logic_analyzer::LogicAnalyzerComponent::new(
+//!     sampler,
+//!     cdc,
+//!     125_000_000,
+//! )
+//! .finalize(components::logic_analyzer_component_static!(
+//!     capsules_extra::logic_analyzer::GpioSampler<'static, RPGpioPin<'static>>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::gpio;
+use kernel::hil::uart;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+pub const SAMPLES_LEN: usize = 8192;
+pub const TX_BUF_LEN: usize = 64;
+
+/// Frequency the dividers of the protocol refer to.
+const BASE_CLOCK_HZ: u32 = 100_000_000;
+/// Samples taken while waiting for the trigger before letting the kernel run.
+const BURST: usize = 1024;
+/// Approximate number of CPU cycles of one iteration of the delay loop.
+const LOOP_CYCLES: u32 = 4;
+/// Approximate number of CPU cycles it takes to sample without any delay.
+const SAMPLE_CYCLES: u32 = 32;
+
+mod command {
+    pub const RESET: u8 = 0x00;
+    pub const RUN: u8 = 0x01;
+    pub const ID: u8 = 0x02;
+    pub const METADATA: u8 = 0x04;
+    pub const XON: u8 = 0x11;
+    pub const XOFF: u8 = 0x13;
+    pub const DIVIDER: u8 = 0x80;
+    pub const READ_DELAY_COUNT: u8 = 0x81;
+    pub const FLAGS: u8 = 0x82;
+    pub const TRIGGER_MASK: u8 = 0xC0;
+    pub const TRIGGER_VALUE: u8 = 0xC1;
+}
+
+/// Bits 2 to 5 of the flags disable channel groups 0 to 3.
+const FLAGS_DISABLE_GROUPS_SHIFT: u8 = 2;
+
+/// Reads the channels of the logic analyzer.
+pub trait Sampler {
+    /// Prepare the channels for sampling.
+    fn configure(&self);
+
+    /// Number of channels, at most 8.
+    fn channels(&self) -> usize;
+
+    /// Read the levels of the channels, channel `i` in bit `i`.
+    fn sample(&self) -> u8;
+}
+
+/// Samples up to 8 GPIO pins.
+pub struct GpioSampler<'a, P: gpio::Pin> {
+    pins: &'a [&'a P],
+}
+
+impl<'a, P: gpio::Pin> GpioSampler<'a, P> {
+    pub fn new(pins: &'a [&'a P]) -> Self {
+        GpioSampler {
+            pins: &pins[..pins.len().min(8)],
+        }
+    }
+}
+
+impl<'a, P: gpio::Pin> Sampler for GpioSampler<'a, P> {
+    fn configure(&self) {
+        for pin in self.pins {
+            pin.make_input();
+        }
+    }
+
+    fn channels(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn sample(&self) -> u8 {
+        self.pins
+            .iter()
+            .enumerate()
+            .fold(0, |sample, (i, pin)| sample | (pin.read() as u8) << i)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// Sampling and waiting for the trigger.
+    Armed,
+    /// Sending `remaining` samples, the next one at `index` of the ring.
+    Sending {
+        index: usize,
+        remaining: usize,
+    },
+}
+
+pub struct LogicAnalyzer<'a, S: Sampler> {
+    sampler: &'a S,
+    uart: &'a dyn uart::UartData<'a>,
+    cpu_frequency_hz: u32,
+    /// Ring of samples.
+    samples: TakeCell<'static, [u8]>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    /// Bytes of the command being received.
+    command: Cell<[u8; 5]>,
+    command_len: Cell<usize>,
+    divider: Cell<u32>,
+    read_count: Cell<usize>,
+    delay_count: Cell<usize>,
+    flags: Cell<u8>,
+    trigger_mask: Cell<u8>,
+    trigger_value: Cell<u8>,
+    state: Cell<State>,
+    /// Index of the next sample in the ring.
+    position: Cell<usize>,
+    /// Number of valid samples in the ring.
+    filled: Cell<usize>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, S: Sampler> LogicAnalyzer<'a, S> {
+    pub fn new(
+        sampler: &'a S,
+        uart: &'a dyn uart::UartData<'a>,
+        cpu_frequency_hz: u32,
+        samples: &'static mut [u8],
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+    ) -> Self {
+        LogicAnalyzer {
+            sampler,
+            uart,
+            cpu_frequency_hz,
+            samples: TakeCell::new(samples),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            command: Cell::new([0; 5]),
+            command_len: Cell::new(0),
+            divider: Cell::new(0),
+            read_count: Cell::new(0),
+            delay_count: Cell::new(0),
+            flags: Cell::new(0),
+            trigger_mask: Cell::new(0),
+            trigger_value: Cell::new(0),
+            state: Cell::new(State::Idle),
+            position: Cell::new(0),
+            filled: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Start receiving commands.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let rx_buffer = self.rx_buffer.take().ok_or(ErrorCode::ALREADY)?;
+        self.uart
+            .receive_buffer(rx_buffer, 1)
+            .map_err(|(e, rx_buffer)| {
+                self.rx_buffer.replace(rx_buffer);
+                e
+            })
+    }
+
+    fn samples_len(&self) -> usize {
+        self.samples.map_or(0, |samples| samples.len())
+    }
+
+    fn max_sample_rate(&self) -> u32 {
+        self.cpu_frequency_hz / SAMPLE_CYCLES
+    }
+
+    /// Iterations of the delay loop between samples.
+    fn delay_loops(&self) -> u32 {
+        let rate = BASE_CLOCK_HZ / (self.divider.get() + 1);
+        let period_cycles = self.cpu_frequency_hz / rate.max(1);
+        period_cycles.saturating_sub(SAMPLE_CYCLES) / LOOP_CYCLES
+    }
+
+    /// Send up to `TX_BUF_LEN` bytes written by `write`. Replies are dropped
+    /// while samples are being sent.
+    fn reply(&self, write: impl FnOnce(&mut [u8]) -> usize) {
+        if let State::Sending { .. } = self.state.get() {
+            return;
+        }
+        if let Some(tx_buffer) = self.tx_buffer.take() {
+            let len = write(tx_buffer);
+            if let Err((_, tx_buffer)) = self.uart.transmit_buffer(tx_buffer, len) {
+                self.tx_buffer.replace(tx_buffer);
+            }
+        }
+    }
+
+    fn metadata(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut push = |bytes: &[u8]| {
+            buf[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        push(b"\x01Tock logic analyzer\0");
+        push(b"\x02Tock\0");
+        push(&[0x20]);
+        push(&(self.sampler.channels() as u32).to_be_bytes());
+        push(&[0x21]);
+        push(&(self.samples_len() as u32).to_be_bytes());
+        push(&[0x23]);
+        push(&self.max_sample_rate().to_be_bytes());
+        push(&[0x24]);
+        push(&2u32.to_be_bytes());
+        push(&[0x00]);
+        len
+    }
+
+    fn execute(&self, command: [u8; 5]) {
+        let value = u32::from_le_bytes([command[1], command[2], command[3], command[4]]);
+        match command[0] {
+            command::RESET => self.state.set(State::Idle),
+            command::RUN => {
+                if self.state.get() == State::Idle {
+                    self.sampler.configure();
+                    self.position.set(0);
+                    self.filled.set(0);
+                    self.state.set(State::Armed);
+                    self.deferred_call.set();
+                }
+            }
+            command::ID => self.reply(|buf| {
+                buf[..4].copy_from_slice(b"1ALS");
+                4
+            }),
+            command::METADATA => self.reply(|buf| self.metadata(buf)),
+            command::XON | command::XOFF => {}
+            command::DIVIDER => self.divider.set(value & 0xFF_FFFF),
+            command::READ_DELAY_COUNT => {
+                self.read_count.set(((value & 0xFFFF) as usize + 1) * 4);
+                self.delay_count.set(((value >> 16) as usize + 1) * 4);
+            }
+            command::FLAGS => self.flags.set(command[1]),
+            command::TRIGGER_MASK => self.trigger_mask.set(command[1]),
+            command::TRIGGER_VALUE => self.trigger_value.set(command[1]),
+            // Other trigger stages and commands are not supported.
+            _ => {}
+        }
+    }
+
+    /// Sample until the trigger, for at most `BURST` samples. Once triggered,
+    /// take the remaining samples and start sending.
+    fn sample(&self) {
+        let loops = self.delay_loops();
+        let delay = || {
+            for _ in 0..loops {
+                core::hint::spin_loop();
+            }
+        };
+        let mask = self.trigger_mask.get();
+        let value = self.trigger_value.get() & mask;
+
+        let triggered = self.samples.map_or(false, |samples| {
+            let len = samples.len();
+            let mut position = self.position.get();
+            let mut filled = self.filled.get();
+            let mut take = || {
+                samples[position] = self.sampler.sample();
+                let sample = samples[position];
+                position = (position + 1) % len;
+                filled = (filled + 1).min(len);
+                delay();
+                sample
+            };
+
+            let mut triggered = false;
+            for _ in 0..BURST {
+                if take() & mask == value {
+                    triggered = true;
+                    break;
+                }
+            }
+            if triggered {
+                for _ in 0..self.delay_count.get().min(len) {
+                    take();
+                }
+            }
+            self.position.set(position);
+            self.filled.set(filled);
+            triggered
+        });
+
+        if triggered {
+            let len = self.samples_len();
+            let remaining = self.read_count.get().min(self.filled.get());
+            self.state.set(State::Sending {
+                index: (self.position.get() + len - 1) % len,
+                remaining,
+            });
+            self.send_samples();
+        } else {
+            self.deferred_call.set();
+        }
+    }
+
+    /// Send the next samples, newest first, one byte for each enabled group.
+    fn send_samples(&self) {
+        let (mut index, mut remaining) = match self.state.get() {
+            State::Sending { index, remaining } => (index, remaining),
+            _ => return,
+        };
+        if remaining == 0 {
+            self.state.set(State::Idle);
+            return;
+        }
+        let disabled = self.flags.get() >> FLAGS_DISABLE_GROUPS_SHIFT;
+        let groups = (0..4).filter(|group| disabled & 1 << group == 0).count();
+        let tx_buffer = match self.tx_buffer.take() {
+            Some(tx_buffer) => tx_buffer,
+            None => return,
+        };
+        let len = self.samples.map_or(0, |samples| {
+            let mut len = 0;
+            while remaining > 0 && len + groups <= tx_buffer.len() {
+                for group in 0..4 {
+                    if disabled & 1 << group == 0 {
+                        tx_buffer[len] = if group == 0 { samples[index] } else { 0 };
+                        len += 1;
+                    }
+                }
+                index = (index + samples.len() - 1) % samples.len();
+                remaining -= 1;
+            }
+            len
+        });
+        self.state.set(State::Sending { index, remaining });
+        if let Err((_, tx_buffer)) = self.uart.transmit_buffer(tx_buffer, len) {
+            self.tx_buffer.replace(tx_buffer);
+            self.state.set(State::Idle);
+        }
+    }
+}
+
+impl<'a, S: Sampler> DeferredCallClient for LogicAnalyzer<'a, S> {
+    fn handle_deferred_call(&self) {
+        if self.state.get() == State::Armed {
+            self.sample();
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, S: Sampler> uart::TransmitClient for LogicAnalyzer<'a, S> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_buffer.replace(tx_buffer);
+        self.send_samples();
+    }
+}
+
+impl<'a, S: Sampler> uart::ReceiveClient for LogicAnalyzer<'a, S> {
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        _rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        if rx_len == 1 {
+            let mut command = self.command.get();
+            let len = self.command_len.get();
+            command[len] = rx_buffer[0];
+            // Commands with the top bit set have four bytes of arguments.
+            if command[0] & 0x80 == 0 || len == 4 {
+                self.command_len.set(0);
+                self.execute(command);
+            } else {
+                self.command.set(command);
+                self.command_len.set(len + 1);
+            }
+        }
+        if let Err((_, rx_buffer)) = self.uart.receive_buffer(rx_buffer, 1) {
+            self.rx_buffer.replace(rx_buffer);
+        }
+    }
+}
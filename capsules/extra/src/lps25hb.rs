@@ -6,29 +6,37 @@
 //!
 //! <http://www.st.com/en/mems-and-sensors/lps25hb.html>
 //!
+//! Readings are passed to the upcall as described in
+//! `capsules_core::sensor_upcall`, in tenths of pascals.
+//!
 //! Usage
 //! -----
 //!
 //! ```rust
 //! let buffer = static_init!([u8; capsules::lps25hb::BUF_LEN], [0; capsules::lps25hb::BUF_LEN]);
 //! let lps25hb_i2c = static_init!(I2CDevice, I2CDevice::new(i2c_bus, 0x5C));
+//! let registers = static_init!(
+//!     I2CRegisterClient<'static>,
+//!     I2CRegisterClient::new(lps25hb_i2c, buffer, capsules::lps25hb::REGISTER_AUTO_INCREMENT)
+//! );
 //! let lps25hb = static_init!(
 //!     capsules::lps25hb::LPS25HB<'static>,
-//!     capsules::lps25hb::LPS25HB::new(lps25hb_i2c,
+//!     capsules::lps25hb::LPS25HB::new(registers,
 //!         &sam4l::gpio::PA[10],
-//!         buffer));
-//! lps25hb_i2c.set_client(lps25hb);
+//!         grant));
+//! lps25hb_i2c.set_client(registers);
+//! registers.set_client(lps25hb);
 //! sam4l::gpio::PA[10].set_client(lps25hb);
 //! ```
 
 use core::cell::Cell;
 
-use kernel::errorcode::into_statuscode;
+use capsules_core::i2c_register::{I2CRegisterClient, RegisterClient};
+use capsules_core::sensor_upcall::{self, Unit};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil::gpio;
-use kernel::hil::i2c;
 use kernel::syscall::{CommandReturn, SyscallDriver};
-use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::cells::OptionalCell;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -38,8 +46,10 @@ pub const DRIVER_NUM: usize = driver::NUM::Lps25hb as usize;
 // Expected buffer length.
 pub const BUF_LEN: usize = 5;
 
+/// Bit of the register address to read or write several registers.
+pub const REGISTER_AUTO_INCREMENT: u8 = 0x80;
+
 /// Register values
-const REGISTER_AUTO_INCREMENT: u8 = 0x80;
 
 const CTRL_REG1_POWER_ON: u8 = 0x80;
 const CTRL_REG1_BLOCK_DATA_ENABLE: u8 = 0x04;
@@ -79,7 +89,6 @@ enum State {
     Idle,
 
     /// Read the WHO_AM_I register. This should return 0xBB.
-    SelectWhoAmI,
     ReadingWhoAmI,
 
     /// Process of taking a pressure measurement.
@@ -90,246 +99,169 @@ enum State {
     TakeMeasurementClear,
     /// Enable a single shot measurement with interrupt when data is ready.
     TakeMeasurementConfigure,
+    /// Wait for the data ready interrupt.
+    WaitMeasurement,
 
-    /// Read the 3 pressure registers.
+    /// Read the 3 pressure registers, then calculate pressure and call the
+    /// callback with the value.
     ReadMeasurement,
-    /// Calculate pressure and call the callback with the value.
-    GotMeasurement,
 
-    /// Disable I2C and release buffer
+    /// Power the chip off
     Done,
 }
 
 #[derive(Default)]
 pub struct App {}
 
-pub struct LPS25HB<'a, I: i2c::I2CDevice> {
-    i2c: &'a I,
+pub struct LPS25HB<'a> {
+    registers: &'a I2CRegisterClient<'a>,
     interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
     state: Cell<State>,
-    buffer: TakeCell<'static, [u8]>,
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     owning_process: OptionalCell<ProcessId>,
 }
 
-impl<'a, I: i2c::I2CDevice> LPS25HB<'a, I> {
+impl<'a> LPS25HB<'a> {
     pub fn new(
-        i2c: &'a I,
+        registers: &'a I2CRegisterClient<'a>,
         interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
-        buffer: &'static mut [u8],
         apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     ) -> Self {
         // setup and return struct
         Self {
-            i2c: i2c,
+            registers: registers,
             interrupt_pin: interrupt_pin,
             state: Cell::new(State::Idle),
-            buffer: TakeCell::new(buffer),
             apps,
             owning_process: OptionalCell::empty(),
         }
     }
 
     pub fn read_whoami(&self) -> Result<(), ErrorCode> {
-        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buf| {
-            // turn on i2c to send commands
-            self.i2c.enable();
-
-            buf[0] = Registers::WhoAmI as u8;
-
-            if let Err((_error, buf)) = self.i2c.write(buf, 1) {
-                self.buffer.replace(buf);
-                self.i2c.disable();
-                Err(_error.into())
-            } else {
-                self.state.set(State::SelectWhoAmI);
-                Ok(())
-            }
-        })
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.registers.read(Registers::WhoAmI as u8, 1)?;
+        self.state.set(State::ReadingWhoAmI);
+        Ok(())
     }
 
     pub fn take_measurement(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
         self.interrupt_pin.make_input();
         self.interrupt_pin
             .enable_interrupts(gpio::InterruptEdge::RisingEdge);
 
-        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buf| {
-            // turn on i2c to send commands
-            self.i2c.enable();
+        // CTRL_REG1 to CTRL_REG4
+        self.registers.write(
+            Registers::CtrlReg1 as u8,
+            &[0, 0, 0, CTRL_REG4_INTERRUPT1_DATAREADY],
+        )?;
+        self.state.set(State::TakeMeasurementInit);
+        Ok(())
+    }
 
-            buf[0] = Registers::CtrlReg1 as u8 | REGISTER_AUTO_INCREMENT;
-            buf[1] = 0;
-            buf[2] = 0;
-            buf[3] = 0;
-            buf[4] = CTRL_REG4_INTERRUPT1_DATAREADY;
+    /// Move to `next` if `result` started a transfer, otherwise report the
+    /// error.
+    fn next(&self, result: Result<(), ErrorCode>, next: State) {
+        match result {
+            Ok(()) => self.state.set(next),
+            Err(error) => self.report_error(error),
+        }
+    }
 
-            if let Err((_error, buf)) = self.i2c.write(buf, 5) {
-                self.buffer.replace(buf);
-                self.i2c.disable();
-                Err(_error.into())
-            } else {
-                self.state.set(State::TakeMeasurementInit);
-                Ok(())
-            }
-        })
+    fn report_error(&self, error: ErrorCode) {
+        self.state.set(State::Idle);
+        self.owning_process.map(|pid| {
+            let _ = self.apps.enter(*pid, |_app, upcalls| {
+                upcalls
+                    .schedule_upcall(0, sensor_upcall::encode_error(error))
+                    .ok();
+            });
+        });
     }
 }
 
-impl<I: i2c::I2CDevice> i2c::I2CClient for LPS25HB<'_, I> {
-    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
-        if status != Ok(()) {
-            self.state.set(State::Idle);
-            self.buffer.replace(buffer);
-            self.owning_process.map(|pid| {
-                let _ = self.apps.enter(*pid, |_app, upcalls| {
-                    upcalls.schedule_upcall(0, (0, 0, 0)).ok();
-                });
-            });
+impl RegisterClient for LPS25HB<'_> {
+    fn registers_written(&self, _reg: u8, result: Result<(), ErrorCode>) {
+        if let Err(error) = result {
+            self.report_error(error);
             return;
         }
         match self.state.get() {
-            State::SelectWhoAmI => {
-                if let Err((_error, buffer)) = self.i2c.read(buffer, 1) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                } else {
-                    self.state.set(State::ReadingWhoAmI);
-                }
+            State::TakeMeasurementInit => {
+                let result = self.registers.read(Registers::PressOutXl as u8, 3);
+                self.next(result, State::TakeMeasurementClear);
             }
-            State::ReadingWhoAmI => {
-                self.buffer.replace(buffer);
-                self.i2c.disable();
+            State::TakeMeasurementConfigure => {
+                self.state.set(State::WaitMeasurement);
+            }
+            State::Done => {
                 self.state.set(State::Idle);
             }
-            State::TakeMeasurementInit => {
-                buffer[0] = Registers::PressOutXl as u8 | REGISTER_AUTO_INCREMENT;
-                if let Err((error, buffer)) = self.i2c.write(buffer, 1) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                    self.owning_process.map(|pid| {
-                        let _ = self.apps.enter(*pid, |_app, upcalls| {
-                            upcalls
-                                .schedule_upcall(0, (into_statuscode(Err(error.into())), 0, 0))
-                                .ok();
-                        });
-                    });
-                } else {
-                    self.state.set(State::TakeMeasurementClear);
-                }
+            _ => {}
+        }
+    }
+
+    fn registers_read(&self, _reg: u8, data: &[u8], result: Result<(), ErrorCode>) {
+        if let Err(error) = result {
+            self.report_error(error);
+            return;
+        }
+        match self.state.get() {
+            State::ReadingWhoAmI => {
+                self.state.set(State::Idle);
             }
             State::TakeMeasurementClear => {
-                if let Err((error, buffer)) = self.i2c.read(buffer, 3) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                    self.owning_process.map(|pid| {
-                        let _ = self.apps.enter(*pid, |_app, upcalls| {
-                            upcalls
-                                .schedule_upcall(0, (into_statuscode(Err(error.into())), 0, 0))
-                                .ok();
-                        });
-                    });
-                } else {
-                    self.state.set(State::TakeMeasurementConfigure);
-                }
-            }
-            State::TakeMeasurementConfigure => {
-                buffer[0] = Registers::CtrlReg1 as u8 | REGISTER_AUTO_INCREMENT;
-                buffer[1] = CTRL_REG1_POWER_ON | CTRL_REG1_BLOCK_DATA_ENABLE;
-                buffer[2] = CTRL_REG2_ONE_SHOT;
-
-                if let Err((error, buffer)) = self.i2c.write(buffer, 3) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                    self.owning_process.map(|pid| {
-                        let _ = self.apps.enter(*pid, |_app, upcalls| {
-                            upcalls
-                                .schedule_upcall(0, (into_statuscode(Err(error.into())), 0, 0))
-                                .ok();
-                        });
-                    });
-                } else {
-                    self.state.set(State::Done);
-                }
+                // CTRL_REG1 and CTRL_REG2
+                let result = self.registers.write(
+                    Registers::CtrlReg1 as u8,
+                    &[
+                        CTRL_REG1_POWER_ON | CTRL_REG1_BLOCK_DATA_ENABLE,
+                        CTRL_REG2_ONE_SHOT,
+                    ],
+                );
+                self.next(result, State::TakeMeasurementConfigure);
             }
             State::ReadMeasurement => {
-                if let Err((error, buffer)) = self.i2c.read(buffer, 3) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                    self.owning_process.map(|pid| {
-                        let _ = self.apps.enter(*pid, |_app, upcalls| {
-                            upcalls
-                                .schedule_upcall(0, (into_statuscode(Err(error.into())), 0, 0))
-                                .ok();
-                        });
-                    });
-                } else {
-                    self.state.set(State::GotMeasurement);
-                }
-            }
-            State::GotMeasurement => {
-                let pressure = (((buffer[2] as u32) << 16)
-                    | ((buffer[1] as u32) << 8)
-                    | (buffer[0] as u32)) as u32;
+                let pressure =
+                    ((data[2] as u32) << 16) | ((data[1] as u32) << 8) | (data[0] as u32);
 
-                // Returned as microbars
+                // In microbars, which are tenths of pascals.
                 let pressure_ubar = (pressure * 1000) / 4096;
 
                 self.owning_process.map(|pid| {
                     let _ = self.apps.enter(*pid, |_app, upcalls| {
                         upcalls
-                            .schedule_upcall(0, (pressure_ubar as usize, 0, 0))
+                            .schedule_upcall(
+                                0,
+                                sensor_upcall::encode(Unit::Pascal, -1, &[pressure_ubar as i32]),
+                            )
                             .ok();
                     });
                 });
 
-                buffer[0] = Registers::CtrlReg1 as u8;
-                buffer[1] = 0;
-
-                if let Err((error, buffer)) = self.i2c.write(buffer, 2) {
-                    self.state.set(State::Idle);
-                    self.buffer.replace(buffer);
-                    self.owning_process.map(|pid| {
-                        let _ = self.apps.enter(*pid, |_app, upcalls| {
-                            upcalls
-                                .schedule_upcall(0, (into_statuscode(Err(error.into())), 0, 0))
-                                .ok();
-                        });
-                    });
-                } else {
-                    self.interrupt_pin.disable_interrupts();
-                    self.state.set(State::Done);
-                }
-            }
-            State::Done => {
-                self.buffer.replace(buffer);
-                self.i2c.disable();
-                self.state.set(State::Idle);
+                self.interrupt_pin.disable_interrupts();
+                let result = self.registers.write(Registers::CtrlReg1 as u8, &[0]);
+                self.next(result, State::Done);
             }
             _ => {}
         }
     }
 }
 
-impl<I: i2c::I2CDevice> gpio::Client for LPS25HB<'_, I> {
+impl gpio::Client for LPS25HB<'_> {
     fn fired(&self) {
-        self.buffer.take().map(|buf| {
-            // turn on i2c to send commands
-            self.i2c.enable();
-
-            // select sensor voltage register and read it
-            buf[0] = Registers::PressOutXl as u8 | REGISTER_AUTO_INCREMENT;
-
-            if let Err((_error, buf)) = self.i2c.write(buf, 1) {
-                self.buffer.replace(buf);
-                self.i2c.disable();
-            } else {
-                self.state.set(State::ReadMeasurement);
-            }
-        });
+        if self.state.get() == State::WaitMeasurement {
+            let result = self.registers.read(Registers::PressOutXl as u8, 3);
+            self.next(result, State::ReadMeasurement);
+        }
     }
 }
 
-impl<I: i2c::I2CDevice> SyscallDriver for LPS25HB<'_, I> {
+impl SyscallDriver for LPS25HB<'_> {
     fn command(
         &self,
         command_num: usize,
@@ -598,11 +598,11 @@ impl<I: i2c::I2CDevice> i2c::I2CClient for Lsm303dlhcI2C<'_, I> {
                     self.nine_dof_client.map(|client| {
                         // compute using only integers
                         let range = self.mag_range.get() as usize;
-                        x = (((buffer[1] as i16 | ((buffer[0] as i16) << 8)) as i32) * 100
+                        x = (((buffer[1] as i16 | ((buffer[0] as i16) << 8)) as i32) * 1000
                             / RANGE_FACTOR_X_Y[range] as i32) as usize;
-                        z = (((buffer[3] as i16 | ((buffer[2] as i16) << 8)) as i32) * 100
+                        z = (((buffer[3] as i16 | ((buffer[2] as i16) << 8)) as i32) * 1000
                             / RANGE_FACTOR_X_Y[range] as i32) as usize;
-                        y = (((buffer[5] as i16 | ((buffer[4] as i16) << 8)) as i32) * 100
+                        y = (((buffer[5] as i16 | ((buffer[4] as i16) << 8)) as i32) * 1000
                             / RANGE_FACTOR_Z[range] as i32) as usize;
                         client.callback(x, y, z);
                     });
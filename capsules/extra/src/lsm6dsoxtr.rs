@@ -8,6 +8,10 @@
 //!
 //! May be used with NineDof and Temperature
 //!
+//! The LSM6DS3TR-C, e.g. on the Seeed XIAO nRF52840 Sense, has the same
+//! registers at the same addresses for the features used here, and is
+//! supported as well.
+//!
 //! I2C Interface
 //!
 //! Datasheet: <https://www.digikey.sg/product-detail/en/stmicroelectronics/LSM6DSOXTR/497-18367-1-ND/9841887>
@@ -35,6 +39,7 @@ pub const DRIVER_NUM: usize = driver::NUM::Lsm6dsoxtr as usize;
 use kernel::utilities::registers::register_bitfields;
 
 pub const CHIP_ID: u8 = 0x6C;
+pub const CHIP_ID_LSM6DS3TR_C: u8 = 0x6A;
 pub const ACCELEROMETER_BASE_ADDRESS: u8 = 0x6A;
 
 enum_from_primitive! {
@@ -388,7 +393,7 @@ impl<I: i2c::I2CDevice> i2c::I2CClient for Lsm6dsoxtrI2C<'_, I> {
                 self.buffer.replace(buffer);
                 self.i2c.disable();
                 self.state.set(State::Idle);
-                if status == Ok(()) && id == 108 {
+                if status == Ok(()) && (id == CHIP_ID || id == CHIP_ID_LSM6DS3TR_C) {
                     self.is_present.set(true);
                     if self.config_in_progress.get() {
                         if let Err(_error) = self.set_accelerometer_power_mode(
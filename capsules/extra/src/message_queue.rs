@@ -0,0 +1,345 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Message queues for inter-process communication.
+//!
+//! The IPC driver of the kernel shares memory between processes and leaves
+//! the framing of the data to them. With this driver, processes instead send
+//! each other messages of up to `MSG_LEN` bytes, which the kernel copies into
+//! a queue of `DEPTH` messages in the grant of the recipient. The recipient
+//! gets an upcall when a message arrives and reads the messages in the order
+//! they were sent.
+//!
+//! If the queue of the recipient is full, sending fails with `BUSY`. The
+//! sender then gets an upcall once the recipient read a message and there is
+//! space again.
+//!
+//! Only processes that opened their queue receive messages, and processes
+//! are identified by their process identifier (`ProcessId::id()`), which
+//! changes when a process restarts.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let message_queue = components::message_queue::MessageQueueComponent::new(
+//!     board_kernel,
+//!     capsules_extra::message_queue::DRIVER_NUM,
+//! )
+//! .finalize(components::message_queue_component_static!(64, 4));
+//! ```
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::MessageQueue as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Name of the process to look up.
+    pub const SEARCH: usize = 0;
+    /// Message to send.
+    pub const MESSAGE: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer received messages are copied to.
+    pub const RECEIVE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+mod upcall {
+    /// A message arrived in the queue.
+    pub const RECEIVED: usize = 0;
+    /// The recipient of a message that failed with `BUSY` has space again.
+    pub const SPACE: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+pub struct App<const MSG_LEN: usize, const DEPTH: usize> {
+    open: bool,
+    messages: [[u8; MSG_LEN]; DEPTH],
+    lengths: [usize; DEPTH],
+    senders: [usize; DEPTH],
+    /// Index of the oldest message.
+    head: usize,
+    count: usize,
+    /// Recipient whose queue was full when this process last sent to it.
+    waiting_on: Option<ProcessId>,
+}
+
+impl<const MSG_LEN: usize, const DEPTH: usize> Default for App<MSG_LEN, DEPTH> {
+    fn default() -> Self {
+        App {
+            open: false,
+            messages: [[0; MSG_LEN]; DEPTH],
+            lengths: [0; DEPTH],
+            senders: [0; DEPTH],
+            head: 0,
+            count: 0,
+            waiting_on: None,
+        }
+    }
+}
+
+pub struct MessageQueue<C: ProcessManagementCapability, const MSG_LEN: usize, const DEPTH: usize> {
+    kernel: &'static Kernel,
+    apps: Grant<
+        App<MSG_LEN, DEPTH>,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    capability: C,
+}
+
+impl<C: ProcessManagementCapability, const MSG_LEN: usize, const DEPTH: usize>
+    MessageQueue<C, MSG_LEN, DEPTH>
+{
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<
+            App<MSG_LEN, DEPTH>,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        capability: C,
+    ) -> Self {
+        MessageQueue {
+            kernel,
+            apps: grant,
+            capability,
+        }
+    }
+
+    /// Find the process with identifier `id`.
+    fn find_process(&self, id: usize) -> Option<ProcessId> {
+        let mut found = None;
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.processid().id() == id {
+                    found = Some(process.processid());
+                }
+            });
+        found
+    }
+
+    /// Return the identifier of the process named in the search buffer.
+    fn lookup(&self, processid: ProcessId) -> CommandReturn {
+        let mut name = [0; 32];
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::SEARCH)
+                    .and_then(|search| {
+                        search.enter(|search| {
+                            let len = search.len().min(name.len());
+                            search[0..len].copy_to_slice(&mut name[..len]);
+                            len
+                        })
+                    })
+                    .unwrap_or(0)
+            })
+            .unwrap_or(0);
+        if len == 0 {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+
+        let mut result = CommandReturn::failure(ErrorCode::NODEVICE);
+        self.kernel
+            .process_each_capability(&self.capability, |process| {
+                if process.get_process_name().as_bytes() == &name[..len] {
+                    result = CommandReturn::success_u32(process.processid().id() as u32);
+                }
+            });
+        result
+    }
+
+    fn send(&self, processid: ProcessId, recipient_id: usize) -> Result<(), ErrorCode> {
+        let recipient = self.find_process(recipient_id).ok_or(ErrorCode::INVAL)?;
+
+        // Copy the message out of the sender first, so the grant of the
+        // recipient is not entered while the one of the sender is.
+        let mut message = [0; MSG_LEN];
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::MESSAGE)
+                    .map_err(ErrorCode::from)
+                    .and_then(|buffer| {
+                        buffer
+                            .enter(|buffer| {
+                                if buffer.len() > MSG_LEN {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                buffer.copy_to_slice(&mut message[..buffer.len()]);
+                                Ok(buffer.len())
+                            })
+                            .unwrap_or(Err(ErrorCode::INVAL))
+                    })
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        if len == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let result = self
+            .apps
+            .enter(recipient, |app, kernel_data| {
+                if !app.open {
+                    return Err(ErrorCode::NODEVICE);
+                }
+                if app.count == DEPTH {
+                    return Err(ErrorCode::BUSY);
+                }
+                let tail = (app.head + app.count) % DEPTH;
+                app.messages[tail][..len].copy_from_slice(&message[..len]);
+                app.lengths[tail] = len;
+                app.senders[tail] = processid.id();
+                app.count += 1;
+                kernel_data
+                    .schedule_upcall(upcall::RECEIVED, (processid.id(), len, app.count))
+                    .ok();
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+
+        let _ = self.apps.enter(processid, |app, _| {
+            if result == Err(ErrorCode::BUSY) {
+                app.waiting_on = Some(recipient);
+            } else if app.waiting_on == Some(recipient) {
+                app.waiting_on = None;
+            }
+        });
+        result
+    }
+
+    /// Copy the oldest message into the receive buffer. Returns the length
+    /// of the message and the identifier of its sender.
+    fn receive(&self, processid: ProcessId) -> CommandReturn {
+        let result = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                if app.count == 0 {
+                    return Ok((0, 0));
+                }
+                let len = app.lengths[app.head];
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::RECEIVE)
+                    .map_err(ErrorCode::from)
+                    .and_then(|buffer| {
+                        buffer
+                            .mut_enter(|buffer| match buffer.get(0..len) {
+                                Some(buffer) => {
+                                    buffer.copy_from_slice(&app.messages[app.head][..len]);
+                                    Ok(())
+                                }
+                                None => Err(ErrorCode::SIZE),
+                            })
+                            .unwrap_or(Err(ErrorCode::INVAL))
+                    })?;
+                let sender = app.senders[app.head];
+                app.head = (app.head + 1) % DEPTH;
+                app.count -= 1;
+                Ok((len, sender))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+
+        match result {
+            Ok((0, _)) => CommandReturn::success_u32_u32(0, 0),
+            Ok((len, sender)) => {
+                self.notify_senders(processid);
+                CommandReturn::success_u32_u32(len as u32, sender as u32)
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    /// Tell the processes that found the queue of `recipient` full that
+    /// there is space again.
+    fn notify_senders(&self, recipient: ProcessId) {
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, kernel_data| {
+                if app.waiting_on == Some(recipient) {
+                    app.waiting_on = None;
+                    kernel_data
+                        .schedule_upcall(upcall::SPACE, (recipient.id(), 0, 0))
+                        .ok();
+                }
+            });
+        }
+    }
+}
+
+impl<C: ProcessManagementCapability, const MSG_LEN: usize, const DEPTH: usize> SyscallDriver
+    for MessageQueue<C, MSG_LEN, DEPTH>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the identifier of the process whose name is in read-only
+    ///   allow buffer 0, or `NODEVICE` if there is none.
+    /// - `2`: Open the queue of the calling process, so others can send
+    ///   messages to it. Returns the maximum message length and the queue
+    ///   depth.
+    /// - `3`: Send the contents of read-only allow buffer 1 to process
+    ///   `data1`. Returns `INVAL` if there is no such process or the message
+    ///   is empty, `SIZE` if it is too long, `NODEVICE` if the recipient did
+    ///   not open its queue, and `BUSY` if the queue is full, in which case
+    ///   upcall 1 is scheduled once there is space.
+    /// - `4`: Copy the oldest message in the queue to read-write allow buffer
+    ///   0 and remove it from the queue. Returns the length of the message
+    ///   and the identifier of the sender, or `(0, 0)` if the queue is empty.
+    ///   Returns `SIZE` if the buffer is too small, and keeps the message.
+    ///
+    /// Upcall 0 is scheduled when a message arrives, with the identifier of
+    /// the sender, the length of the message and the number of messages in
+    /// the queue.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.lookup(processid),
+
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.open = true;
+                    CommandReturn::success_u32_u32(MSG_LEN as u32, DEPTH as u32)
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            3 => self.send(processid, data1).into(),
+
+            4 => self.receive(processid),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
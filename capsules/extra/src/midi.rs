@@ -0,0 +1,300 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with MIDI event packets, e.g. over USB MIDI.
+//!
+//! Processes send event packets (see `kernel::hil::midi`) and receive the
+//! packets from the host. As MIDI is a bus, every process listening gets
+//! all received packets. Sends of several processes are queued and sent one
+//! after the other.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let (midi, midi_driver) = components::usb_midi::UsbMidiComponent::new(
+//!     board_kernel,
+//!     capsules_extra::midi::DRIVER_NUM,
+//!     &nrf52840_peripherals.usbd,
+//!     capsules_extra::usb::cdc::MAX_CTRL_PACKET_SIZE_NRF52840,
+//!     0x1915, // Nordic Semiconductor
+//!     0x503c,
+//!     strings,
+//! )
+//! .finalize(components::usb_midi_component_static!(nrf52840::usbd::Usbd));
+//!
+//! midi.enable();
+//! midi.attach();
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Send the first `data1` bytes of read-only allow 0, a multiple of
+//!   4. Upcall 0 is scheduled with the status when they were sent.
+//!   Returns `SIZE` if the packets do not fit in the buffer of the kernel.
+//! - `2`: Start receiving. Upcall 1 is scheduled with the number of bytes
+//!   of every packets received, copied into read-write allow 0 and
+//!   truncated to its length.
+//! - `3`: Stop receiving.
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::midi::{self, EVENT_PACKET_LEN};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Midi as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const SEND: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const RECEIVE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for scheduled upcalls
+mod upcalls {
+    pub const SEND_DONE: usize = 0;
+    pub const RECEIVED: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Length of the packets waiting to be sent.
+    send_pending: Option<usize>,
+    receiving: bool,
+}
+
+pub struct MidiDriver<'a, M: midi::Midi<'a>> {
+    midi: &'a M,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The process whose packets are being sent.
+    sending: OptionalCell<ProcessId>,
+    send_buffer: TakeCell<'static, [u8]>,
+    /// The receive buffer, while no receive is in progress.
+    receive_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, M: midi::Midi<'a>> MidiDriver<'a, M> {
+    pub fn new(
+        midi: &'a M,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        send_buffer: &'static mut [u8],
+        receive_buffer: &'static mut [u8],
+    ) -> MidiDriver<'a, M> {
+        MidiDriver {
+            midi,
+            apps: grant,
+            sending: OptionalCell::empty(),
+            send_buffer: TakeCell::new(send_buffer),
+            receive_buffer: TakeCell::new(receive_buffer),
+        }
+    }
+
+    /// Send the packets of `processid`.
+    fn send(&self, processid: ProcessId, len: usize) -> Result<(), ErrorCode> {
+        let buffer = self.send_buffer.take().ok_or(ErrorCode::BUSY)?;
+        let copied = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::SEND)
+                    .and_then(|packets| {
+                        packets.enter(|packets| {
+                            if len > packets.len() {
+                                return Err(ErrorCode::SIZE);
+                            }
+                            packets[..len].copy_to_slice(&mut buffer[..len]);
+                            Ok(())
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if let Err(err) = copied {
+            self.send_buffer.replace(buffer);
+            return Err(err);
+        }
+
+        match self.midi.send(buffer, len) {
+            Ok(()) => {
+                self.sending.set(processid);
+                Ok(())
+            }
+            Err((err, buffer)) => {
+                self.send_buffer.replace(buffer);
+                Err(err)
+            }
+        }
+    }
+
+    /// Send the packets of the next process waiting to send.
+    fn send_next(&self) {
+        for app in self.apps.iter() {
+            let processid = app.processid();
+            let len = app.enter(|app, _| app.send_pending.take());
+            if let Some(len) = len {
+                if let Err(err) = self.send(processid, len) {
+                    let _ = self.apps.enter(processid, |_, kernel_data| {
+                        kernel_data.schedule_upcall(
+                            upcalls::SEND_DONE,
+                            (kernel::errorcode::into_statuscode(Err(err)), 0, 0),
+                        )
+                    });
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Start receiving, if a process listens and no receive is in progress.
+    fn receive(&self) {
+        let listening = self
+            .apps
+            .iter()
+            .any(|app| app.enter(|app, _| app.receiving));
+        if listening {
+            if let Some(buffer) = self.receive_buffer.take() {
+                if let Err((_, buffer)) = self.midi.receive(buffer) {
+                    self.receive_buffer.replace(buffer);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, M: midi::Midi<'a>> midi::Client for MidiDriver<'a, M> {
+    fn send_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.send_buffer.replace(buffer);
+        self.sending.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(
+                    upcalls::SEND_DONE,
+                    (kernel::errorcode::into_statuscode(result), 0, 0),
+                )
+            });
+        });
+        self.send_next();
+    }
+
+    fn packets_received(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    ) {
+        if result.is_ok() {
+            for app in self.apps.iter() {
+                app.enter(|app, kernel_data| {
+                    if !app.receiving {
+                        return;
+                    }
+                    let copied = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::RECEIVE)
+                        .and_then(|packets| {
+                            packets.mut_enter(|packets| {
+                                // Only whole packets.
+                                let copied =
+                                    len.min(packets.len()) / EVENT_PACKET_LEN * EVENT_PACKET_LEN;
+                                packets[..copied].copy_from_slice(&buffer[..copied]);
+                                copied
+                            })
+                        })
+                        .unwrap_or(0);
+                    let _ = kernel_data.schedule_upcall(upcalls::RECEIVED, (copied, 0, 0));
+                });
+            }
+        }
+        self.receive_buffer.replace(buffer);
+        self.receive();
+    }
+}
+
+impl<'a, M: midi::Midi<'a>> SyscallDriver for MidiDriver<'a, M> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let len = data1;
+                let capacity = self.send_buffer.map_or(usize::MAX, |buffer| buffer.len());
+                if len == 0 || len % EVENT_PACKET_LEN != 0 || len > capacity {
+                    return CommandReturn::failure(ErrorCode::SIZE);
+                }
+                let queued = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        if app.send_pending.is_some() {
+                            Err(ErrorCode::BUSY)
+                        } else {
+                            app.send_pending = Some(len);
+                            Ok(())
+                        }
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                if let Err(err) = queued {
+                    return CommandReturn::failure(err);
+                }
+                if self.sending.is_none() {
+                    let result = self.send(processid, len);
+                    let _ = self.apps.enter(processid, |app, _| app.send_pending = None);
+                    if let Err(err) = result {
+                        return CommandReturn::failure(err);
+                    }
+                }
+                CommandReturn::success()
+            }
+
+            2 => {
+                let _ = self.apps.enter(processid, |app, _| app.receiving = true);
+                self.receive();
+                CommandReturn::success()
+            }
+
+            3 => {
+                let _ = self.apps.enter(processid, |app, _| app.receiving = false);
+                CommandReturn::success()
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,274 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Userspace interface to expose resources with CoAP.
+//!
+//! Each process serves one resource at the path it shares in the `PATH`
+//! buffer, with the representation in the `CONTENT` buffer, e.g. the last
+//! reading of a sensor. GET requests are answered from `CONTENT` without
+//! involving the process, so it has to keep the buffer shared. After it
+//! updated the representation, the process notifies the observers of the
+//! resource. A writable resource accepts PUT and POST requests: the payload
+//! is copied into the `WRITE` buffer, and the process gets an upcall with
+//! its length.
+
+use crate::net::coap::endpoint::{CoapEndpoint, CoapResource, Path, MAX_PATH_LEN};
+use crate::net::coap::message::code;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantData, GrantKernelData, UpcallCount};
+use kernel::hil::time::Alarm;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Coap as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// The path of the resource, e.g. `sensors/temp`
+    pub const PATH: usize = 0;
+    /// The representation of the resource
+    pub const CONTENT: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// The payload of the last PUT or POST request
+    pub const WRITE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// A PUT or POST request was written to the `WRITE` buffer
+    pub const WRITTEN: usize = 0;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Flags of the register command.
+mod flags {
+    pub const OBSERVABLE: usize = 1 << 0;
+    pub const WRITABLE: usize = 1 << 1;
+    /// The Content-Format is the second argument.
+    pub const CONTENT_FORMAT: usize = 1 << 2;
+}
+
+#[derive(Default)]
+pub struct App {
+    path: Option<Path>,
+    flags: usize,
+    content_format: u16,
+}
+
+pub struct CoapDriver<'a, A: Alarm<'a>> {
+    endpoint: &'a CoapEndpoint<'a, A>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: Alarm<'a>> CoapDriver<'a, A> {
+    pub fn new(
+        endpoint: &'a CoapEndpoint<'a, A>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> CoapDriver<'a, A> {
+        CoapDriver {
+            endpoint,
+            apps: grant,
+        }
+    }
+
+    /// The process serving `path`.
+    fn find_app(&self, path: &[u8]) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| {
+                app.path
+                    .filter(|registered| registered.as_slice() == path)
+                    .map(|_| processid)
+            })
+        })
+    }
+
+    /// Enter the process serving `path`.
+    fn enter_app<F, R>(&self, path: &[u8], fun: F) -> Option<R>
+    where
+        F: FnOnce(&mut GrantData<App>, &GrantKernelData) -> R,
+    {
+        let processid = self.find_app(path)?;
+        self.apps.enter(processid, fun).ok()
+    }
+
+    fn register(&self, flags: usize, content_format: usize, processid: ProcessId) -> CommandReturn {
+        let path = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::PATH)
+                    .and_then(|path| {
+                        path.enter(|path| {
+                            let mut buf = [0; MAX_PATH_LEN];
+                            let len = path.len();
+                            if len > MAX_PATH_LEN {
+                                return None;
+                            }
+                            path.copy_to_slice(&mut buf[..len]);
+                            Path::new(&buf[..len])
+                        })
+                    })
+                    .unwrap_or(None)
+            })
+            .unwrap_or(None);
+        let path = match path {
+            Some(path) if !path.as_slice().is_empty() => path,
+            _ => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        if self
+            .find_app(path.as_slice())
+            .map_or(false, |owner| owner != processid)
+        {
+            return CommandReturn::failure(ErrorCode::ALREADY);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                app.path = Some(path);
+                app.flags = flags;
+                app.content_format = content_format as u16;
+                CommandReturn::success()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+}
+
+impl<'a, A: Alarm<'a>> CoapResource for CoapDriver<'a, A> {
+    fn handles(&self, path: &[u8]) -> bool {
+        self.find_app(path).is_some()
+    }
+
+    fn get(&self, path: &[u8], offset: usize, buf: &mut [u8]) -> Result<usize, u8> {
+        self.enter_app(path, |_, kernel_data| {
+            kernel_data
+                .get_readonly_processbuffer(ro_allow::CONTENT)
+                .and_then(|content| {
+                    content.enter(|content| {
+                        let len = core::cmp::min(buf.len(), content.len().saturating_sub(offset));
+                        if len > 0 {
+                            content[offset..offset + len].copy_to_slice(&mut buf[..len]);
+                        }
+                        content.len()
+                    })
+                })
+                .map_err(|_| code::INTERNAL_SERVER_ERROR)
+        })
+        .unwrap_or(Err(code::NOT_FOUND))
+    }
+
+    fn put(&self, path: &[u8], payload: &[u8]) -> Result<(), u8> {
+        self.enter_app(path, |app, kernel_data| {
+            if app.flags & flags::WRITABLE == 0 {
+                return Err(code::METHOD_NOT_ALLOWED);
+            }
+            kernel_data
+                .get_readwrite_processbuffer(rw_allow::WRITE)
+                .and_then(|write| {
+                    write.mut_enter(|write| {
+                        write
+                            .get(0..payload.len())
+                            .map(|write| write.copy_from_slice(payload))
+                            .ok_or(code::REQUEST_ENTITY_TOO_LARGE)
+                    })
+                })
+                .unwrap_or(Err(code::INTERNAL_SERVER_ERROR))?;
+            kernel_data
+                .schedule_upcall(upcall::WRITTEN, (payload.len(), 0, 0))
+                .ok();
+            Ok(())
+        })
+        .unwrap_or(Err(code::NOT_FOUND))
+    }
+
+    fn content_format(&self, path: &[u8]) -> Option<u16> {
+        self.enter_app(path, |app, _| {
+            Some(app.content_format).filter(|_| app.flags & flags::CONTENT_FORMAT != 0)
+        })
+        .flatten()
+    }
+
+    fn observable(&self, path: &[u8]) -> bool {
+        self.enter_app(path, |app, _| app.flags & flags::OBSERVABLE != 0)
+            .unwrap_or(false)
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for CoapDriver<'a, A> {
+    /// Commands for the resource of the process.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Serve the resource at the path in the `PATH` buffer. `arg1`
+    ///   are flags: bit 0 makes the resource observable, bit 1 writable, and
+    ///   with bit 2 the representation has the Content-Format `arg2`.
+    ///   Registering again changes path and flags. Returns `INVAL` for an
+    ///   empty path or a path longer than 32 bytes, and `ALREADY` if another
+    ///   process serves the path.
+    /// - `2`: Stop serving the resource.
+    /// - `3`: The representation in the `CONTENT` buffer changed: notify the
+    ///   observers of the resource. Returns `RESERVE` if the process serves
+    ///   no resource.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.register(arg1, arg2, processid),
+
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.path = None;
+                    CommandReturn::success()
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            3 => {
+                let path = self
+                    .apps
+                    .enter(processid, |app, _| app.path)
+                    .unwrap_or(None);
+                match path {
+                    Some(path) => {
+                        self.endpoint.notify(path.as_slice());
+                        CommandReturn::success()
+                    }
+                    None => CommandReturn::failure(ErrorCode::RESERVE),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
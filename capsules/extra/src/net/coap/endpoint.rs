@@ -0,0 +1,998 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A CoAP endpoint (RFC 7252) over a UDP socket.
+//!
+//! As server, the endpoint answers requests for the resources registered
+//! with `register`. Resources answer synchronously with their current
+//! representation: a resource backed by a sensor keeps its last sample, and
+//! calls `notify` when it changes. Responses to confirmable requests are
+//! piggybacked on the acknowledgement. Clients observe resources (RFC 7641)
+//! with a GET request with the Observe option 0, and receive a confirmable
+//! notification after each `notify`. An observer that rejects a
+//! notification with a reset, or does not acknowledge it, is removed.
+//! Representations longer than a block of `2^(BLOCK_SZX + 4)` bytes are
+//! transferred in blocks (RFC 7959): a response carries the requested block
+//! and a Block2 option, and the client requests the next blocks.
+//!
+//! As client, the endpoint sends one confirmable request at a time with
+//! `request`. The response, piggybacked or separate, is matched by its
+//! token, and a response in blocks is fetched block by block, each passed
+//! to the `CoapClient`.
+//!
+//! Confirmable messages are retransmitted with exponential back-off, up to
+//! `MAX_RETRANSMIT` times, and one is in flight at a time: a notification
+//! waits for the request before it and the other way around. Responses are
+//! sent from a single buffer, a request arriving while it is in use is
+//! dropped, and the client retransmits it if it is confirmable.
+//!
+//! Usage
+//! -----
+//! `components::coap::CoapComponent` binds the endpoint to `COAP_PORT` and
+//! registers the userspace driver. Kernel resources are registered too:
+//!
+//! ```rust,ignore
+//! let (coap, coap_driver) = components::coap::CoapComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::coap::DRIVER_NUM,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//! )
+//! .finalize(components::coap_component_static!(nrf52840::rtc::Rtc));
+//! coap.register(temperature_resource).unwrap();
+//! ```
+
+use crate::net::coap::message::{
+    code, decode_uint, option, Block, Header, Message, MessageType, MessageWriter, Token,
+    HEADER_LEN,
+};
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+pub const COAP_PORT: u16 = 5683;
+pub const MAX_RESOURCES: usize = 4;
+pub const MAX_OBSERVERS: usize = 4;
+/// Maximum length of a resource path, its segments joined with `/`.
+pub const MAX_PATH_LEN: usize = 32;
+/// Size exponent of the blocks sent, for blocks of 64 bytes.
+pub const BLOCK_SZX: u8 = 2;
+
+const ACK_TIMEOUT_MS: u32 = 2000;
+const MAX_RETRANSMIT: u8 = 4;
+/// How long a request waits for the separate response after it was
+/// acknowledged.
+const RESPONSE_TIMEOUT_MS: u32 = 30_000;
+/// Observe sequence numbers are 24 bits.
+const OBSERVE_SEQ_MASK: u32 = 0xff_ffff;
+
+/// The path of a resource, e.g. `sensors/temp`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Path {
+    len: u8,
+    bytes: [u8; MAX_PATH_LEN],
+}
+
+impl Path {
+    /// The path `path`, without leading `/`. `None` if it is longer than
+    /// `MAX_PATH_LEN`.
+    pub fn new(path: &[u8]) -> Option<Path> {
+        let start = path.iter().position(|&b| b != b'/').unwrap_or(path.len());
+        let path = &path[start..];
+        if path.len() > MAX_PATH_LEN {
+            return None;
+        }
+        let mut bytes = [0; MAX_PATH_LEN];
+        bytes[..path.len()].copy_from_slice(path);
+        Some(Path {
+            len: path.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// The segments of the path, the values of its Uri-Path options.
+    fn segments(&self) -> impl Iterator<Item = &[u8]> {
+        self.as_slice()
+            .split(|&b| b == b'/')
+            .filter(|segment| !segment.is_empty())
+    }
+
+    fn push_segment(&mut self, segment: &[u8]) -> Result<(), ErrorCode> {
+        let start = match self.len {
+            0 => 0,
+            len => len as usize + 1,
+        };
+        if start + segment.len() > MAX_PATH_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        if start > 0 {
+            self.bytes[start - 1] = b'/';
+        }
+        self.bytes[start..start + segment.len()].copy_from_slice(segment);
+        self.len = (start + segment.len()) as u8;
+        Ok(())
+    }
+}
+
+impl Default for Path {
+    fn default() -> Path {
+        Path {
+            len: 0,
+            bytes: [0; MAX_PATH_LEN],
+        }
+    }
+}
+
+/// A set of resources served by the endpoint. Errors are CoAP response
+/// codes from `message::code`.
+pub trait CoapResource {
+    /// Whether the resource at `path` is one of this set.
+    fn handles(&self, path: &[u8]) -> bool;
+
+    /// Copy the representation of `path` from `offset` into `buf`, as far as
+    /// it fits, and return the length of the whole representation. `buf`
+    /// may be empty.
+    fn get(&self, path: &[u8], offset: usize, buf: &mut [u8]) -> Result<usize, u8>;
+
+    /// A PUT or POST request with `payload` to `path`.
+    fn put(&self, _path: &[u8], _payload: &[u8]) -> Result<(), u8> {
+        Err(code::METHOD_NOT_ALLOWED)
+    }
+
+    /// The Content-Format of the representation of `path`.
+    fn content_format(&self, _path: &[u8]) -> Option<u16> {
+        None
+    }
+
+    /// Whether clients can observe `path`.
+    fn observable(&self, _path: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Receives the responses to the requests of the endpoint.
+pub trait CoapClient {
+    /// A response with `code`, or a block of it: `payload` is at `offset` in
+    /// the representation, and `more` is set if further blocks follow.
+    fn response(&self, code: u8, offset: usize, payload: &[u8], more: bool);
+
+    /// The request failed: `NOACK` if it was not answered, `FAIL` if the
+    /// server rejected it with a reset.
+    fn request_failed(&self, error: ErrorCode);
+}
+
+#[derive(Copy, Clone)]
+struct Observer {
+    addr: IPAddr,
+    port: u16,
+    token: Token,
+    path: Path,
+    /// The representation changed since the last notification.
+    changed: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RequestState {
+    /// The request for the next block is waiting to be sent.
+    Queued,
+    /// Sent, waiting for the acknowledgement.
+    Sent,
+    /// Acknowledged, waiting for the separate response.
+    Acknowledged,
+}
+
+#[derive(Copy, Clone)]
+struct Request {
+    addr: IPAddr,
+    port: u16,
+    method: u8,
+    token: Token,
+    path: Path,
+    /// The block to request, `None` to let the server decide.
+    block: Option<Block>,
+    state: RequestState,
+}
+
+/// The confirmable message in flight.
+#[derive(Copy, Clone)]
+struct Confirmable {
+    addr: IPAddr,
+    port: u16,
+    message_id: u16,
+    token: Token,
+    len: usize,
+    transmissions: u8,
+    timeout_ms: u32,
+    /// A notification, not the request of the endpoint.
+    notification: bool,
+}
+
+/// A time the alarm runs at: `dt` after `reference`.
+#[derive(Copy, Clone)]
+struct Deadline<T: Ticks> {
+    reference: T,
+    dt: T,
+}
+
+impl<T: Ticks> Deadline<T> {
+    fn remaining(&self, now: T) -> T {
+        let elapsed = now.wrapping_sub(self.reference);
+        if elapsed >= self.dt {
+            T::from(0)
+        } else {
+            self.dt.wrapping_sub(elapsed)
+        }
+    }
+}
+
+pub struct CoapEndpoint<'a, A: time::Alarm<'a>> {
+    sender: &'a dyn UDPSender<'a>,
+    alarm: &'a A,
+    net_cap: &'static NetworkCapability,
+    tx_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    /// The confirmable message in flight, copied into `tx_buffer` for each
+    /// transmission.
+    con_buffer: TakeCell<'static, [u8]>,
+    confirmable: Cell<Option<Confirmable>>,
+    /// The confirmable message is due for its next transmission.
+    retransmit: Cell<bool>,
+    retransmit_timer: OptionalCell<Deadline<A::Ticks>>,
+    request: Cell<Option<Request>>,
+    response_timer: OptionalCell<Deadline<A::Ticks>>,
+    client: OptionalCell<&'a dyn CoapClient>,
+    resources: [OptionalCell<&'a dyn CoapResource>; MAX_RESOURCES],
+    observers: [Cell<Option<Observer>>; MAX_OBSERVERS],
+    message_id: Cell<u16>,
+    token: Cell<u32>,
+    observe_seq: Cell<u32>,
+}
+
+impl<'a, A: time::Alarm<'a>> CoapEndpoint<'a, A> {
+    /// `tx_buffer` and `con_buffer` should have the same length.
+    pub fn new(
+        sender: &'a dyn UDPSender<'a>,
+        alarm: &'a A,
+        net_cap: &'static NetworkCapability,
+        tx_buffer: LeasableMutableBuffer<'static, u8>,
+        con_buffer: &'static mut [u8],
+    ) -> CoapEndpoint<'a, A> {
+        CoapEndpoint {
+            sender,
+            alarm,
+            net_cap,
+            tx_buffer: MapCell::new(tx_buffer),
+            con_buffer: TakeCell::new(con_buffer),
+            confirmable: Cell::new(None),
+            retransmit: Cell::new(false),
+            retransmit_timer: OptionalCell::empty(),
+            request: Cell::new(None),
+            response_timer: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            resources: Default::default(),
+            observers: Default::default(),
+            message_id: Cell::new(0),
+            token: Cell::new(0),
+            observe_seq: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn CoapClient) {
+        self.client.set(client);
+    }
+
+    /// Serve `resource`. Returns `NOMEM` if `MAX_RESOURCES` are registered.
+    pub fn register(&self, resource: &'a dyn CoapResource) -> Result<(), ErrorCode> {
+        let free = self.resources.iter().find(|entry| entry.is_none());
+        free.map_or(Err(ErrorCode::NOMEM), |entry| {
+            entry.set(resource);
+            Ok(())
+        })
+    }
+
+    /// The representation of `path` changed: notify its observers.
+    pub fn notify(&self, path: &[u8]) {
+        for entry in self.observers.iter() {
+            if let Some(observer) = entry.get() {
+                if observer.path.as_slice() == path {
+                    entry.set(Some(Observer {
+                        changed: true,
+                        ..observer
+                    }));
+                }
+            }
+        }
+        self.send_next();
+    }
+
+    /// Send a confirmable request with `method` for `path` to `addr` and
+    /// `port`, with `payload` for PUT and POST requests. The response is
+    /// passed to the client.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The request is sent.
+    /// - `BUSY`: A request is outstanding, or a notification in flight.
+    /// - `INVAL`: `method` is not a request code.
+    /// - `SIZE`: The path or the payload is too long.
+    pub fn request(
+        &self,
+        addr: IPAddr,
+        port: u16,
+        method: u8,
+        path: &[u8],
+        payload: &[u8],
+    ) -> Result<(), ErrorCode> {
+        if self.request.get().is_some() || self.confirmable.get().is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if !code::is_request(method) {
+            return Err(ErrorCode::INVAL);
+        }
+        let request = Request {
+            addr,
+            port,
+            method,
+            token: self.next_token(),
+            path: Path::new(path).ok_or(ErrorCode::SIZE)?,
+            block: None,
+            state: RequestState::Sent,
+        };
+        self.send_request(request, payload)?;
+        self.send_next();
+        Ok(())
+    }
+
+    fn next_message_id(&self) -> u16 {
+        let id = self.message_id.get().wrapping_add(1);
+        self.message_id.set(id);
+        id
+    }
+
+    fn next_token(&self) -> Token {
+        let token = self.token.get().wrapping_add(1);
+        self.token.set(token);
+        Token::from_u32(token)
+    }
+
+    fn next_observe_seq(&self) -> u32 {
+        let seq = (self.observe_seq.get() + 1) & OBSERVE_SEQ_MASK;
+        self.observe_seq.set(seq);
+        seq
+    }
+
+    fn resource(&self, path: &Path) -> Option<&'a dyn CoapResource> {
+        self.resources
+            .iter()
+            .filter_map(|entry| entry.extract())
+            .find(|resource| resource.handles(path.as_slice()))
+    }
+
+    /// Encode `request` with `payload` into the confirmable buffer, and
+    /// schedule its transmission.
+    fn send_request(&self, request: Request, payload: &[u8]) -> Result<(), ErrorCode> {
+        let header = Header {
+            mtype: MessageType::Confirmable,
+            code: request.method,
+            message_id: self.next_message_id(),
+            token: request.token,
+        };
+        let len = self.con_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            let mut writer = MessageWriter::new(buf, &header)?;
+            for segment in request.path.segments() {
+                writer.option(option::URI_PATH, segment)?;
+            }
+            if let Some(block) = request.block {
+                writer.option_uint(option::BLOCK2, block.to_uint())?;
+            }
+            if !payload.is_empty() {
+                writer.payload(payload)?;
+            }
+            Ok(writer.len())
+        })?;
+        self.request.set(Some(Request {
+            state: RequestState::Sent,
+            ..request
+        }));
+        self.start_confirmable(Confirmable {
+            addr: request.addr,
+            port: request.port,
+            message_id: header.message_id,
+            token: request.token,
+            len,
+            transmissions: 0,
+            timeout_ms: 0,
+            notification: false,
+        });
+        Ok(())
+    }
+
+    /// Encode a notification for the observer at `index` into the
+    /// confirmable buffer, and schedule its transmission.
+    fn send_notification(&self, index: usize, observer: Observer) {
+        let header = Header {
+            mtype: MessageType::Confirmable,
+            code: code::CONTENT,
+            message_id: self.next_message_id(),
+            token: observer.token,
+        };
+        let seq = self.next_observe_seq();
+        let result = self.con_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            self.encode_content(buf, header, &observer.path, None, Some(seq))
+        });
+        match result {
+            Ok((len, response_code)) => {
+                // An error response ends the observation
+                let observer = Observer {
+                    changed: false,
+                    ..observer
+                };
+                let observing = code::is_success(response_code);
+                self.observers[index].set(Some(observer).filter(|_| observing));
+                self.start_confirmable(Confirmable {
+                    addr: observer.addr,
+                    port: observer.port,
+                    message_id: header.message_id,
+                    token: observer.token,
+                    len,
+                    transmissions: 0,
+                    timeout_ms: 0,
+                    notification: true,
+                });
+            }
+            Err(_) => self.observers[index].set(None),
+        }
+    }
+
+    fn start_confirmable(&self, confirmable: Confirmable) {
+        self.confirmable.set(Some(confirmable));
+        self.retransmit.set(true);
+    }
+
+    /// Send the confirmable message if it is due, or the next request block
+    /// or notification if none is in flight.
+    fn send_next(&self) {
+        if self.tx_buffer.is_none() {
+            return;
+        }
+        if self.confirmable.get().is_none() {
+            // The request of the endpoint goes before notifications
+            match self.request.get() {
+                Some(request) if request.state == RequestState::Queued => {
+                    if self.send_request(request, &[]).is_err() {
+                        self.finish_request(Err(ErrorCode::FAIL));
+                    }
+                }
+                _ => {
+                    let changed = self
+                        .observers
+                        .iter()
+                        .enumerate()
+                        .find_map(|(index, entry)| {
+                            entry
+                                .get()
+                                .filter(|observer| observer.changed)
+                                .map(|observer| (index, observer))
+                        });
+                    if let Some((index, observer)) = changed {
+                        self.send_notification(index, observer);
+                    }
+                }
+            }
+        }
+        if self.retransmit.get() {
+            self.transmit_confirmable();
+        }
+    }
+
+    fn transmit_confirmable(&self) {
+        let confirmable = match self.confirmable.get() {
+            Some(confirmable) => confirmable,
+            None => return,
+        };
+        let mut tx = match self.tx_buffer.take() {
+            Some(tx) => tx,
+            None => return,
+        };
+        self.retransmit.set(false);
+        self.con_buffer.map(|buf| {
+            tx[..confirmable.len].copy_from_slice(&buf[..confirmable.len]);
+        });
+        tx.slice(0..confirmable.len);
+
+        // The initial timeout is between ACK_TIMEOUT and 1.5 times
+        // ACK_TIMEOUT, with the low bits of the time as random factor
+        let timeout_ms = match confirmable.transmissions {
+            0 => ACK_TIMEOUT_MS + self.alarm.now().into_u32() % (ACK_TIMEOUT_MS / 2),
+            _ => confirmable.timeout_ms.saturating_mul(2),
+        };
+        self.confirmable.set(Some(Confirmable {
+            transmissions: confirmable.transmissions + 1,
+            timeout_ms,
+            ..confirmable
+        }));
+        self.set_timer(&self.retransmit_timer, timeout_ms);
+        self.send(confirmable.addr, confirmable.port, tx);
+    }
+
+    fn send(&self, addr: IPAddr, port: u16, buf: LeasableMutableBuffer<'static, u8>) {
+        if let Err(mut buf) = self.sender.send_to(addr, port, buf, self.net_cap) {
+            buf.reset();
+            self.tx_buffer.replace(buf);
+        }
+    }
+
+    /// Send a message without options and payload, if the buffer is free.
+    fn send_header(&self, addr: IPAddr, port: u16, header: Header) {
+        if let Some(mut tx) = self.tx_buffer.take() {
+            match MessageWriter::new(&mut tx[..], &header) {
+                Ok(writer) => {
+                    let len = writer.len();
+                    tx.slice(0..len);
+                    self.send(addr, port, tx);
+                }
+                Err(_) => {
+                    self.tx_buffer.replace(tx);
+                }
+            }
+        }
+    }
+
+    fn send_empty(&self, addr: IPAddr, port: u16, mtype: MessageType, message_id: u16) {
+        let header = Header {
+            mtype,
+            code: code::EMPTY,
+            message_id,
+            token: Token::default(),
+        };
+        self.send_header(addr, port, header);
+    }
+
+    /// Encode a response with the representation of `path` into `buf`: the
+    /// requested `block`, and with the Observe option `observe`. Returns the
+    /// length of the message and its response code.
+    fn encode_content(
+        &self,
+        buf: &mut [u8],
+        mut header: Header,
+        path: &Path,
+        block: Option<Block>,
+        observe: Option<u32>,
+    ) -> Result<(usize, u8), ErrorCode> {
+        let resource = self.resource(path);
+        let path = path.as_slice();
+        let content = resource.map_or(Err(code::NOT_FOUND), |resource| {
+            resource
+                .get(path, 0, &mut [])
+                .map(|total| (resource, total))
+        });
+        let (resource, total) = match content {
+            Ok(content) => content,
+            Err(response_code) => {
+                header.code = response_code;
+                return MessageWriter::new(buf, &header).map(|writer| (writer.len(), header.code));
+            }
+        };
+
+        // Blocks are at most the size of BLOCK_SZX, smaller if requested
+        let szx = block.map_or(BLOCK_SZX, |block| core::cmp::min(block.szx, BLOCK_SZX));
+        let offset = block.map_or(0, |block| block.offset());
+        let size = 16 << szx;
+        if offset > 0 && offset >= total {
+            header.code = code::BAD_OPTION;
+            return MessageWriter::new(buf, &header).map(|writer| (writer.len(), header.code));
+        }
+
+        header.code = code::CONTENT;
+        let mut writer = MessageWriter::new(buf, &header)?;
+        if let Some(seq) = observe {
+            writer.option_uint(option::OBSERVE, seq)?;
+        }
+        if let Some(format) = resource.content_format(path) {
+            writer.option_uint(option::CONTENT_FORMAT, u32::from(format))?;
+        }
+        if block.is_some() || total > size {
+            let block = Block {
+                num: (offset / size) as u32,
+                more: offset + size < total,
+                szx,
+            };
+            writer.option_uint(option::BLOCK2, block.to_uint())?;
+        }
+        if offset == 0 && total > size {
+            writer.option_uint(option::SIZE2, total as u32)?;
+        }
+        writer.payload_with(size, |payload| {
+            resource.get(path, offset, payload).map_or(0, |total| {
+                core::cmp::min(total.saturating_sub(offset), payload.len())
+            })
+        })?;
+        Ok((writer.len(), header.code))
+    }
+
+    fn add_observer(&self, observer: Observer) -> Result<(), ErrorCode> {
+        // A client observing the resource already registers again with a
+        // new token
+        let entry = self
+            .observers
+            .iter()
+            .find(|entry| {
+                entry.get().map_or(false, |registered| {
+                    registered.addr == observer.addr
+                        && registered.port == observer.port
+                        && registered.path == observer.path
+                })
+            })
+            .or_else(|| self.observers.iter().find(|entry| entry.get().is_none()));
+        entry.map_or(Err(ErrorCode::NOMEM), |entry| {
+            entry.set(Some(observer));
+            Ok(())
+        })
+    }
+
+    fn remove_observer(&self, addr: IPAddr, port: u16, token: Token) {
+        for entry in self.observers.iter() {
+            let matches = entry.get().map_or(false, |observer| {
+                observer.addr == addr && observer.port == port && observer.token == token
+            });
+            if matches {
+                entry.set(None);
+            }
+        }
+    }
+
+    fn receive_request(&self, addr: IPAddr, port: u16, message: &Message) {
+        let mut path = Path::default();
+        let mut path_valid = true;
+        let mut observe = None;
+        let mut block = None;
+        let mut bad_option = false;
+        for (number, value) in message.options() {
+            match number {
+                option::URI_PATH => path_valid &= path.push_segment(value).is_ok(),
+                option::OBSERVE => observe = decode_uint(value),
+                option::BLOCK2 => {
+                    block = decode_uint(value).and_then(Block::from_uint);
+                    bad_option |= block.is_none();
+                }
+                // Addressing this endpoint, and queries and content
+                // negotiation, which the resources do not support
+                option::URI_HOST | option::URI_PORT | option::URI_QUERY | option::ACCEPT => {}
+                number => bad_option |= option::is_critical(number),
+            }
+        }
+
+        let request = message.header;
+        let header = match request.mtype {
+            MessageType::Confirmable => Header {
+                mtype: MessageType::Acknowledgement,
+                ..request
+            },
+            _ => Header {
+                mtype: MessageType::NonConfirmable,
+                message_id: self.next_message_id(),
+                ..request
+            },
+        };
+        let resource = Some(path)
+            .filter(|_| path_valid)
+            .and_then(|path| self.resource(&path));
+
+        let response_code = match resource {
+            _ if bad_option => Some(code::BAD_OPTION),
+            None => Some(code::NOT_FOUND),
+            Some(resource) => match request.code {
+                // GET requests are answered with the representation
+                code::GET => None,
+                code::PUT | code::POST => Some(
+                    resource
+                        .put(path.as_slice(), message.payload)
+                        .map_or_else(|error| error, |()| code::CHANGED),
+                ),
+                _ => Some(code::METHOD_NOT_ALLOWED),
+            },
+        };
+        if let Some(response_code) = response_code {
+            self.send_header(
+                addr,
+                port,
+                Header {
+                    code: response_code,
+                    ..header
+                },
+            );
+            return;
+        }
+
+        let observable = resource.map_or(false, |resource| resource.observable(path.as_slice()));
+        let observe = match observe {
+            Some(0) if observable => {
+                let observer = Observer {
+                    addr,
+                    port,
+                    token: request.token,
+                    path,
+                    changed: false,
+                };
+                // Without room for the observer, the response is sent
+                // without the Observe option
+                self.add_observer(observer)
+                    .ok()
+                    .map(|()| self.next_observe_seq())
+            }
+            Some(1) => {
+                self.remove_observer(addr, port, request.token);
+                None
+            }
+            _ => None,
+        };
+
+        let mut tx = match self.tx_buffer.take() {
+            Some(tx) => tx,
+            None => return,
+        };
+        match self.encode_content(&mut tx[..], header, &path, block, observe) {
+            Ok((len, _)) => {
+                tx.slice(0..len);
+                self.send(addr, port, tx);
+            }
+            Err(_) => {
+                self.tx_buffer.replace(tx);
+            }
+        }
+    }
+
+    /// An acknowledgement or reset of the confirmable message in flight.
+    fn receive_ack(&self, addr: IPAddr, port: u16, message: &Message) {
+        let confirmable = match self.confirmable.get() {
+            Some(confirmable)
+                if confirmable.message_id == message.header.message_id
+                    && confirmable.addr == addr =>
+            {
+                confirmable
+            }
+            _ => return,
+        };
+        self.confirmable.set(None);
+        self.retransmit.set(false);
+        self.retransmit_timer.clear();
+
+        let reset = message.header.mtype == MessageType::Reset;
+        match confirmable.notification {
+            true if reset => {
+                self.remove_observer(confirmable.addr, confirmable.port, confirmable.token)
+            }
+            true => {}
+            false if reset => self.finish_request(Err(ErrorCode::FAIL)),
+            false if message.header.code == code::EMPTY => {
+                if let Some(request) = self.request.get() {
+                    self.request.set(Some(Request {
+                        state: RequestState::Acknowledged,
+                        ..request
+                    }));
+                    self.set_timer(&self.response_timer, RESPONSE_TIMEOUT_MS);
+                }
+            }
+            false => self.receive_response(addr, port, message),
+        }
+        self.rearm();
+    }
+
+    /// A response to the request of the endpoint.
+    fn receive_response(&self, addr: IPAddr, port: u16, message: &Message) {
+        let request = match self.request.get() {
+            Some(request) if request.token == message.header.token && request.addr == addr => {
+                request
+            }
+            _ => {
+                if message.header.mtype == MessageType::Confirmable {
+                    self.send_empty(addr, port, MessageType::Reset, message.header.message_id);
+                }
+                return;
+            }
+        };
+        if message.header.mtype == MessageType::Confirmable {
+            self.send_empty(
+                addr,
+                port,
+                MessageType::Acknowledgement,
+                message.header.message_id,
+            );
+        }
+        // A separate response can overtake the acknowledgement
+        if self
+            .confirmable
+            .get()
+            .map_or(false, |confirmable| !confirmable.notification)
+        {
+            self.confirmable.set(None);
+            self.retransmit.set(false);
+            self.retransmit_timer.clear();
+        }
+        self.response_timer.clear();
+
+        let block = message
+            .block2()
+            .filter(|_| message.header.code == code::CONTENT);
+        let offset = block.map_or(0, |block| block.offset());
+        let more = block.map_or(false, |block| block.more);
+        if let Some(block) = block.filter(|block| block.more) {
+            self.request.set(Some(Request {
+                block: Some(Block {
+                    num: block.num + 1,
+                    more: false,
+                    ..block
+                }),
+                state: RequestState::Queued,
+                ..request
+            }));
+        } else {
+            self.request.set(None);
+        }
+        self.client
+            .map(|client| client.response(message.header.code, offset, message.payload, more));
+        self.rearm();
+    }
+
+    fn finish_request(&self, result: Result<(), ErrorCode>) {
+        self.request.set(None);
+        self.response_timer.clear();
+        if let Err(error) = result {
+            self.client.map(|client| client.request_failed(error));
+        }
+    }
+
+    fn set_timer(&self, timer: &OptionalCell<Deadline<A::Ticks>>, ms: u32) {
+        timer.set(Deadline {
+            reference: self.alarm.now(),
+            dt: self.alarm.ticks_from_ms(ms),
+        });
+        self.rearm();
+    }
+
+    /// Run the alarm at the earliest deadline of the timers.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let earliest = [&self.retransmit_timer, &self.response_timer]
+            .iter()
+            .filter_map(|timer| timer.extract())
+            .map(|deadline| deadline.remaining(now))
+            .min();
+        match earliest {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn retransmit_timeout(&self) {
+        let confirmable = match self.confirmable.get() {
+            Some(confirmable) => confirmable,
+            None => return,
+        };
+        if confirmable.transmissions <= MAX_RETRANSMIT {
+            self.retransmit.set(true);
+            return;
+        }
+        self.confirmable.set(None);
+        match confirmable.notification {
+            true => self.remove_observer(confirmable.addr, confirmable.port, confirmable.token),
+            false => self.finish_request(Err(ErrorCode::NOACK)),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> UDPRecvClient for CoapEndpoint<'a, A> {
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        let message = match Message::decode(payload) {
+            Ok(message) => message,
+            Err(_) => {
+                // Confirmable messages with format errors are rejected
+                if payload.len() >= HEADER_LEN && payload[0] >> 4 & 0x3 == 0 {
+                    let message_id = u16::from_be_bytes([payload[2], payload[3]]);
+                    self.send_empty(src_addr, src_port, MessageType::Reset, message_id);
+                }
+                return;
+            }
+        };
+        let header = message.header;
+        match header.mtype {
+            MessageType::Acknowledgement | MessageType::Reset => {
+                self.receive_ack(src_addr, src_port, &message)
+            }
+            _ if code::is_request(header.code) => {
+                self.receive_request(src_addr, src_port, &message)
+            }
+            _ if code::is_response(header.code) => {
+                self.receive_response(src_addr, src_port, &message)
+            }
+            // A confirmable empty message is a ping, answered with a reset
+            MessageType::Confirmable => {
+                self.send_empty(src_addr, src_port, MessageType::Reset, header.message_id)
+            }
+            _ => {}
+        }
+        self.send_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> UDPSendClient for CoapEndpoint<'a, A> {
+    fn send_done(
+        &self,
+        _result: Result<(), ErrorCode>,
+        mut dgram: LeasableMutableBuffer<'static, u8>,
+    ) {
+        // Lost confirmable messages are retransmitted, others are not
+        dgram.reset();
+        self.tx_buffer.replace(dgram);
+        self.send_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for CoapEndpoint<'a, A> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let expired = |timer: &OptionalCell<Deadline<A::Ticks>>| {
+            let expired = timer.map_or(false, |deadline| {
+                deadline.remaining(now) == A::Ticks::from(0)
+            });
+            if expired {
+                timer.clear();
+            }
+            expired
+        };
+        if expired(&self.retransmit_timer) {
+            self.retransmit_timeout();
+        }
+        if expired(&self.response_timer) {
+            self.finish_request(Err(ErrorCode::NOACK));
+        }
+        self.send_next();
+        self.rearm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        let mut path = Path::default();
+        path.push_segment(b"sensors").unwrap();
+        path.push_segment(b"temp").unwrap();
+        assert_eq!(path, Path::new(b"/sensors/temp").unwrap());
+        assert_eq!(path.as_slice(), b"sensors/temp");
+        let segments: [&[u8]; 2] = [b"sensors", b"temp"];
+        assert!(path.segments().eq(segments.iter().copied()));
+
+        assert_eq!(Path::default().segments().next(), None);
+        assert_eq!(
+            path.push_segment(&[b'a'; MAX_PATH_LEN]),
+            Err(ErrorCode::SIZE)
+        );
+        assert!(Path::new(&[b'a'; MAX_PATH_LEN + 1]).is_none());
+    }
+}
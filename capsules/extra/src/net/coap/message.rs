@@ -0,0 +1,518 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! CoAP messages (RFC 7252) with the Observe (RFC 7641) and Block2 (RFC
+//! 7959) options.
+//!
+//! A message is a 4 byte header, a token of up to 8 bytes, options and an
+//! optional payload after a `0xff` marker. Options are encoded as deltas to
+//! the previous option number, so `MessageWriter` requires them in ascending
+//! order.
+
+use kernel::ErrorCode;
+
+pub const VERSION: u8 = 1;
+pub const HEADER_LEN: usize = 4;
+pub const MAX_TOKEN_LEN: usize = 8;
+pub const PAYLOAD_MARKER: u8 = 0xff;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    Confirmable = 0,
+    NonConfirmable = 1,
+    Acknowledgement = 2,
+    Reset = 3,
+}
+
+impl MessageType {
+    fn from_bits(bits: u8) -> MessageType {
+        match bits & 0x3 {
+            0 => MessageType::Confirmable,
+            1 => MessageType::NonConfirmable,
+            2 => MessageType::Acknowledgement,
+            _ => MessageType::Reset,
+        }
+    }
+}
+
+/// Method and response codes, `class << 5 | detail`.
+pub mod code {
+    pub const EMPTY: u8 = 0x00;
+    pub const GET: u8 = 0x01;
+    pub const POST: u8 = 0x02;
+    pub const PUT: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+
+    /// 2.04
+    pub const CHANGED: u8 = 0x44;
+    /// 2.05
+    pub const CONTENT: u8 = 0x45;
+    /// 4.00
+    pub const BAD_REQUEST: u8 = 0x80;
+    /// 4.02
+    pub const BAD_OPTION: u8 = 0x82;
+    /// 4.04
+    pub const NOT_FOUND: u8 = 0x84;
+    /// 4.05
+    pub const METHOD_NOT_ALLOWED: u8 = 0x85;
+    /// 4.13
+    pub const REQUEST_ENTITY_TOO_LARGE: u8 = 0x8d;
+    /// 5.00
+    pub const INTERNAL_SERVER_ERROR: u8 = 0xa0;
+
+    pub fn is_request(code: u8) -> bool {
+        code != EMPTY && code >> 5 == 0
+    }
+
+    pub fn is_response(code: u8) -> bool {
+        code >> 5 >= 2
+    }
+
+    /// Whether `code` is a 2.xx code.
+    pub fn is_success(code: u8) -> bool {
+        code >> 5 == 2
+    }
+}
+
+/// Option numbers.
+pub mod option {
+    pub const URI_HOST: u16 = 3;
+    pub const OBSERVE: u16 = 6;
+    pub const URI_PORT: u16 = 7;
+    pub const URI_PATH: u16 = 11;
+    pub const CONTENT_FORMAT: u16 = 12;
+    pub const URI_QUERY: u16 = 15;
+    pub const ACCEPT: u16 = 17;
+    pub const BLOCK2: u16 = 23;
+    pub const SIZE2: u16 = 28;
+
+    /// Whether an endpoint must reject a message with the unrecognized option
+    /// `number`.
+    pub fn is_critical(number: u16) -> bool {
+        number & 1 == 1
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Token {
+    len: u8,
+    bytes: [u8; MAX_TOKEN_LEN],
+}
+
+impl Token {
+    /// A token of `bytes`, `None` if it is longer than `MAX_TOKEN_LEN`.
+    pub fn new(bytes: &[u8]) -> Option<Token> {
+        if bytes.len() > MAX_TOKEN_LEN {
+            return None;
+        }
+        let mut token = Token {
+            len: bytes.len() as u8,
+            bytes: [0; MAX_TOKEN_LEN],
+        };
+        token.bytes[..bytes.len()].copy_from_slice(bytes);
+        Some(token)
+    }
+
+    /// A 4 byte token of `value`.
+    pub fn from_u32(value: u32) -> Token {
+        let mut token = Token {
+            len: 4,
+            bytes: [0; MAX_TOKEN_LEN],
+        };
+        token.bytes[..4].copy_from_slice(&value.to_be_bytes());
+        token
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub mtype: MessageType,
+    pub code: u8,
+    pub message_id: u16,
+    pub token: Token,
+}
+
+impl Header {
+    /// Decode the header at the start of `buf`, and return it with the length
+    /// of header and token.
+    pub fn decode(buf: &[u8]) -> Result<(Header, usize), ErrorCode> {
+        if buf.len() < HEADER_LEN || buf[0] >> 6 != VERSION {
+            return Err(ErrorCode::INVAL);
+        }
+        let token_len = (buf[0] & 0x0f) as usize;
+        if token_len > MAX_TOKEN_LEN || buf.len() < HEADER_LEN + token_len {
+            return Err(ErrorCode::INVAL);
+        }
+        let header = Header {
+            mtype: MessageType::from_bits(buf[0] >> 4),
+            code: buf[1],
+            message_id: u16::from_be_bytes([buf[2], buf[3]]),
+            token: Token::new(&buf[HEADER_LEN..HEADER_LEN + token_len]).unwrap_or_default(),
+        };
+        Ok((header, HEADER_LEN + token_len))
+    }
+}
+
+/// Decode an option value in network byte order, `None` if it is longer than
+/// 4 bytes.
+pub fn decode_uint(value: &[u8]) -> Option<u32> {
+    if value.len() > 4 {
+        return None;
+    }
+    Some(
+        value
+            .iter()
+            .fold(0, |uint, &byte| uint << 8 | u32::from(byte)),
+    )
+}
+
+/// The value of a Block2 option.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Number of the block.
+    pub num: u32,
+    /// Whether further blocks follow.
+    pub more: bool,
+    /// Size exponent, the block size is `2^(szx + 4)` bytes.
+    pub szx: u8,
+}
+
+impl Block {
+    /// The block with the option value `value`, `None` for the reserved size
+    /// exponent 7 and block numbers above 20 bits.
+    pub fn from_uint(value: u32) -> Option<Block> {
+        let szx = (value & 0x7) as u8;
+        if szx == 7 || value >> 4 >= 1 << 20 {
+            return None;
+        }
+        Some(Block {
+            num: value >> 4,
+            more: value & 0x8 != 0,
+            szx,
+        })
+    }
+
+    pub fn to_uint(&self) -> u32 {
+        self.num << 4 | u32::from(self.more) << 3 | u32::from(self.szx)
+    }
+
+    pub fn size(&self) -> usize {
+        16 << self.szx
+    }
+
+    /// Offset of the block in the representation.
+    pub fn offset(&self) -> usize {
+        self.num as usize * self.size()
+    }
+}
+
+/// An option number, its value and the buffer after the option.
+type DecodedOption<'a> = (u16, &'a [u8], &'a [u8]);
+
+/// Decode the option at the start of `buf` following option number `prev`.
+/// Returns `None` at the payload marker or the end of `buf`.
+fn decode_option(buf: &[u8], prev: u16) -> Result<Option<DecodedOption>, ErrorCode> {
+    let first = match buf.first() {
+        None | Some(&PAYLOAD_MARKER) => return Ok(None),
+        Some(&first) => first,
+    };
+    let mut rest = &buf[1..];
+    let mut extended = |nibble: u8| -> Result<u16, ErrorCode> {
+        match nibble {
+            13 if !rest.is_empty() => {
+                let value = u16::from(rest[0]) + 13;
+                rest = &rest[1..];
+                Ok(value)
+            }
+            14 if rest.len() >= 2 => {
+                let value = u16::from_be_bytes([rest[0], rest[1]]).checked_add(269);
+                rest = &rest[2..];
+                value.ok_or(ErrorCode::INVAL)
+            }
+            0..=12 => Ok(u16::from(nibble)),
+            _ => Err(ErrorCode::INVAL),
+        }
+    };
+    let delta = extended(first >> 4)?;
+    let len = extended(first & 0x0f)? as usize;
+    let number = prev.checked_add(delta).ok_or(ErrorCode::INVAL)?;
+    if rest.len() < len {
+        return Err(ErrorCode::INVAL);
+    }
+    Ok(Some((number, &rest[..len], &rest[len..])))
+}
+
+/// The options of a message, as option numbers and values.
+#[derive(Clone)]
+pub struct Options<'a> {
+    buf: &'a [u8],
+    number: u16,
+}
+
+impl<'a> Iterator for Options<'a> {
+    type Item = (u16, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Messages are validated when decoded, errors end the iteration
+        let (number, value, rest) = decode_option(self.buf, self.number).ok()??;
+        self.buf = rest;
+        self.number = number;
+        Some((number, value))
+    }
+}
+
+pub struct Message<'a> {
+    pub header: Header,
+    options: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    /// Decode the message in `buf`. Returns `INVAL` for message format
+    /// errors.
+    pub fn decode(buf: &'a [u8]) -> Result<Message<'a>, ErrorCode> {
+        let (header, offset) = Header::decode(buf)?;
+        if header.code == code::EMPTY && buf.len() != HEADER_LEN {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let mut rest = &buf[offset..];
+        let mut number = 0;
+        while let Some((next, _, remaining)) = decode_option(rest, number)? {
+            number = next;
+            rest = remaining;
+        }
+        let options = &buf[offset..buf.len() - rest.len()];
+        let payload = match rest.split_first() {
+            // A payload marker must be followed by a payload
+            Some((_, payload)) if payload.is_empty() => return Err(ErrorCode::INVAL),
+            Some((_, payload)) => payload,
+            None => rest,
+        };
+        Ok(Message {
+            header,
+            options,
+            payload,
+        })
+    }
+
+    pub fn options(&self) -> Options<'a> {
+        Options {
+            buf: self.options,
+            number: 0,
+        }
+    }
+
+    /// The value of the first option `number`.
+    pub fn option(&self, number: u16) -> Option<&'a [u8]> {
+        self.options()
+            .find(|&(option, _)| option == number)
+            .map(|(_, value)| value)
+    }
+
+    pub fn option_uint(&self, number: u16) -> Option<u32> {
+        self.option(number).and_then(decode_uint)
+    }
+
+    pub fn block2(&self) -> Option<Block> {
+        self.option_uint(option::BLOCK2).and_then(Block::from_uint)
+    }
+}
+
+/// Encodes a message into a buffer: the header, then options in ascending
+/// order, then the payload.
+pub struct MessageWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    number: u16,
+}
+
+impl<'a> MessageWriter<'a> {
+    /// Start a message with `header` in `buf`. Returns `SIZE` if the header
+    /// does not fit.
+    pub fn new(buf: &'a mut [u8], header: &Header) -> Result<MessageWriter<'a>, ErrorCode> {
+        let token = header.token.as_slice();
+        let len = HEADER_LEN + token.len();
+        if buf.len() < len {
+            return Err(ErrorCode::SIZE);
+        }
+        buf[0] = VERSION << 6 | (header.mtype as u8) << 4 | token.len() as u8;
+        buf[1] = header.code;
+        buf[2..4].copy_from_slice(&header.message_id.to_be_bytes());
+        buf[HEADER_LEN..len].copy_from_slice(token);
+        Ok(MessageWriter {
+            buf,
+            len,
+            number: 0,
+        })
+    }
+
+    /// Length of the message so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append option `number` with `value`. Returns `INVAL` if `number` is
+    /// below the previous option and `SIZE` if the option does not fit.
+    pub fn option(&mut self, number: u16, value: &[u8]) -> Result<(), ErrorCode> {
+        if number < self.number || value.len() > usize::from(u16::MAX) - 269 {
+            return Err(ErrorCode::INVAL);
+        }
+        let (delta_nibble, delta_ext, delta_ext_len) = Self::extend(number - self.number);
+        let (len_nibble, len_ext, len_ext_len) = Self::extend(value.len() as u16);
+        let total = 1 + delta_ext_len + len_ext_len + value.len();
+        if self.buf.len() < self.len + total {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let buf = &mut self.buf[self.len..self.len + total];
+        buf[0] = delta_nibble << 4 | len_nibble;
+        let mut offset = 1;
+        buf[offset..offset + delta_ext_len].copy_from_slice(&delta_ext[2 - delta_ext_len..]);
+        offset += delta_ext_len;
+        buf[offset..offset + len_ext_len].copy_from_slice(&len_ext[2 - len_ext_len..]);
+        offset += len_ext_len;
+        buf[offset..].copy_from_slice(value);
+
+        self.len += total;
+        self.number = number;
+        Ok(())
+    }
+
+    /// Append option `number` with `value` in as few bytes as possible.
+    pub fn option_uint(&mut self, number: u16, value: u32) -> Result<(), ErrorCode> {
+        let bytes = value.to_be_bytes();
+        let skip = (value.leading_zeros() / 8) as usize;
+        self.option(number, &bytes[skip..])
+    }
+
+    /// Append `payload`. Returns `SIZE` if it does not fit.
+    pub fn payload(&mut self, payload: &[u8]) -> Result<(), ErrorCode> {
+        if self.buf.len() < self.len + 1 + payload.len() {
+            return Err(ErrorCode::SIZE);
+        }
+        self.payload_with(payload.len(), |buf| {
+            buf.copy_from_slice(payload);
+            payload.len()
+        })
+    }
+
+    /// Append a payload of up to `max_len` bytes, written by `write` into the
+    /// buffer it is passed. `write` returns the length of the payload, which
+    /// may be less than the length of the buffer, or 0 for none.
+    pub fn payload_with<F: FnOnce(&mut [u8]) -> usize>(
+        &mut self,
+        max_len: usize,
+        write: F,
+    ) -> Result<(), ErrorCode> {
+        if self.buf.len() <= self.len + 1 {
+            return Err(ErrorCode::SIZE);
+        }
+        let start = self.len + 1;
+        let end = core::cmp::min(self.buf.len(), start + max_len);
+        let len = core::cmp::min(write(&mut self.buf[start..end]), end - start);
+        if len > 0 {
+            self.buf[self.len] = PAYLOAD_MARKER;
+            self.len = start + len;
+        }
+        Ok(())
+    }
+
+    /// Nibble and extended bytes of an option delta or length.
+    fn extend(value: u16) -> (u8, [u8; 2], usize) {
+        match value {
+            0..=12 => (value as u8, [0; 2], 0),
+            13..=268 => (13, [0, (value - 13) as u8], 1),
+            _ => (14, (value - 269).to_be_bytes(), 2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        let header = Header {
+            mtype: MessageType::Confirmable,
+            code: code::GET,
+            message_id: 0x1234,
+            token: Token::new(&[0xab, 0xcd]).unwrap(),
+        };
+        let mut buf = [0; 64];
+        let mut writer = MessageWriter::new(&mut buf, &header).unwrap();
+        writer.option_uint(option::OBSERVE, 0).unwrap();
+        writer.option(option::URI_PATH, b"sensors").unwrap();
+        writer.option(option::URI_PATH, b"temp").unwrap();
+        assert_eq!(writer.option(option::OBSERVE, &[]), Err(ErrorCode::INVAL));
+        writer
+            .option_uint(option::BLOCK2, Block::from_uint(0x12).unwrap().to_uint())
+            .unwrap();
+        // A delta of 270 needs two extended bytes
+        writer.option(option::BLOCK2 + 270, &[1; 14]).unwrap();
+        writer.payload(b"21.5").unwrap();
+        let len = writer.len();
+
+        assert_eq!(&buf[..8], &[0x42, 0x01, 0x12, 0x34, 0xab, 0xcd, 0x60, 0x57]);
+        let message = Message::decode(&buf[..len]).unwrap();
+        assert_eq!(message.header, header);
+        assert_eq!(message.option_uint(option::OBSERVE), Some(0));
+        let mut paths = message
+            .options()
+            .filter(|&(number, _)| number == option::URI_PATH);
+        assert_eq!(paths.next(), Some((option::URI_PATH, &b"sensors"[..])));
+        assert_eq!(paths.next(), Some((option::URI_PATH, &b"temp"[..])));
+        assert_eq!(
+            message.block2(),
+            Some(Block {
+                num: 1,
+                more: false,
+                szx: 2
+            })
+        );
+        assert_eq!(message.option(option::BLOCK2 + 270), Some(&[1; 14][..]));
+        assert_eq!(message.payload, b"21.5");
+
+        // Format errors: a payload marker without payload, a truncated option
+        // and an empty message with a token
+        assert_eq!(
+            Message::decode(&[0x40, 0x01, 0, 0, 0xff]).err(),
+            Some(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            Message::decode(&[0x40, 0x01, 0, 0, 0xb4, b'a']).err(),
+            Some(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            Message::decode(&[0x41, 0x00, 0, 0, 0x01]).err(),
+            Some(ErrorCode::INVAL)
+        );
+    }
+
+    #[test]
+    fn blocks() {
+        let block = Block {
+            num: 3,
+            more: true,
+            szx: 2,
+        };
+        assert_eq!(block.to_uint(), 0x3a);
+        assert_eq!(Block::from_uint(0x3a), Some(block));
+        assert_eq!(block.size(), 64);
+        assert_eq!(block.offset(), 192);
+        assert_eq!(Block::from_uint(0x07), None);
+        assert_eq!(decode_uint(&[0x01, 0x00]), Some(256));
+        assert_eq!(decode_uint(&[]), Some(0));
+        assert_eq!(decode_uint(&[0; 5]), None);
+    }
+}
@@ -0,0 +1,10 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+pub mod driver;
+pub mod endpoint;
+pub mod message;
+
+pub use self::driver::CoapDriver;
+pub use self::driver::DRIVER_NUM;
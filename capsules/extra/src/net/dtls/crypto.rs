@@ -0,0 +1,386 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Cryptography of DTLS 1.2 with `TLS_PSK_WITH_AES_128_CCM_8`.
+//!
+//! Secrets are derived with the PRF of TLS 1.2, P_SHA256 (RFC 5246,
+//! section 5). Its HMAC-SHA256 (RFC 2104) runs on a SHA-256 digest engine,
+//! such as `Sha256Software` or a SHA-256 peripheral, which hashes the inner
+//! and the outer message of each HMAC in turn. Records are protected with an
+//! AES-128-CCM engine that accepts the 12 byte nonces of TLS, such as
+//! `VirtualAES128CCM`.
+//!
+//! `DtlsCrypto` runs one operation at a time, and calls its `CryptoClient`
+//! when it completes.
+
+use core::cell::Cell;
+
+use kernel::hil::digest::{self, Digest, Sha256};
+use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM, AES128_KEY_SIZE};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{LeasableBuffer, LeasableMutableBuffer};
+use kernel::ErrorCode;
+
+use super::record::{NONCE_LEN, TAG_LEN};
+
+/// Size of a SHA-256 block, and of the HMAC key pad.
+const BLOCK_LEN: usize = 64;
+pub const HASH_LEN: usize = 32;
+/// The largest output of `prf()`, the master secret.
+pub const MAX_PRF_LEN: usize = 48;
+/// The longest label and seed of `prf()`.
+pub const MAX_LABEL_SEED_LEN: usize = 80;
+/// The smallest buffer for hashing the HMAC messages of `prf()`.
+pub const MIN_BUFFER_LEN: usize = BLOCK_LEN + HASH_LEN + MAX_LABEL_SEED_LEN;
+
+pub trait CryptoClient {
+    /// A `prf()` or `hash()` completed, and its output can be read with
+    /// `output()`.
+    fn derive_done(&self, result: Result<(), ErrorCode>);
+
+    /// A `seal()` or `open()` completed. `open()` fails with `FAIL` if the
+    /// tag is not valid.
+    fn record_done(&self, buf: &'static mut [u8], result: Result<(), ErrorCode>);
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Op {
+    Idle,
+    Hash,
+    /// Hashing a secret longer than a block into the HMAC key
+    HashKey,
+    /// Computing A(i) of P_SHA256, with the inner or the outer hash
+    PrfA {
+        outer: bool,
+    },
+    /// Computing HMAC(secret, A(i) + seed)
+    PrfOutput {
+        outer: bool,
+    },
+    Crypt,
+}
+
+pub struct DtlsCrypto<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> {
+    ccm: &'a C,
+    digest: &'a D,
+    client: OptionalCell<&'a dyn CryptoClient>,
+    op: Cell<Op>,
+
+    /// The data being hashed
+    buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    digest_buf: TakeCell<'static, [u8; 32]>,
+
+    key: Cell<[u8; BLOCK_LEN]>,
+    label_seed: Cell<[u8; MAX_LABEL_SEED_LEN]>,
+    label_seed_len: Cell<usize>,
+    a: Cell<[u8; HASH_LEN]>,
+    output: Cell<[u8; MAX_PRF_LEN]>,
+    output_len: Cell<usize>,
+    output_pos: Cell<usize>,
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> DtlsCrypto<'a, C, D> {
+    /// `buffer` must hold at least `MIN_BUFFER_LEN` bytes, and the longest
+    /// data passed to `hash()`.
+    pub fn new(
+        ccm: &'a C,
+        digest: &'a D,
+        buffer: &'static mut [u8],
+        digest_buf: &'static mut [u8; 32],
+    ) -> DtlsCrypto<'a, C, D> {
+        DtlsCrypto {
+            ccm,
+            digest,
+            client: OptionalCell::empty(),
+            op: Cell::new(Op::Idle),
+            buffer: MapCell::new(LeasableMutableBuffer::new(buffer)),
+            digest_buf: TakeCell::new(digest_buf),
+            key: Cell::new([0; BLOCK_LEN]),
+            label_seed: Cell::new([0; MAX_LABEL_SEED_LEN]),
+            label_seed_len: Cell::new(0),
+            a: Cell::new([0; HASH_LEN]),
+            output: Cell::new([0; MAX_PRF_LEN]),
+            output_len: Cell::new(0),
+            output_pos: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn CryptoClient) {
+        self.client.set(client);
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.op.get() != Op::Idle
+    }
+
+    /// The output of the last `prf()` or `hash()`.
+    pub fn output(&self) -> [u8; MAX_PRF_LEN] {
+        self.output.get()
+    }
+
+    /// Compute `len` bytes of PRF(`secret`, `label`, the concatenation of
+    /// `seed`).
+    pub fn prf(
+        &self,
+        secret: &[u8],
+        label: &[u8],
+        seed: &[&[u8]],
+        len: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        let seed_len: usize = seed.iter().map(|part| part.len()).sum();
+        if len > MAX_PRF_LEN || label.len() + seed_len > MAX_LABEL_SEED_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        let mut label_seed = [0; MAX_LABEL_SEED_LEN];
+        let mut pos = label.len();
+        label_seed[..pos].copy_from_slice(label);
+        for part in seed {
+            label_seed[pos..pos + part.len()].copy_from_slice(part);
+            pos += part.len();
+        }
+        self.label_seed.set(label_seed);
+        self.label_seed_len.set(pos);
+        self.output_len.set(len);
+        self.output_pos.set(0);
+
+        if secret.len() > BLOCK_LEN {
+            self.start_hash(Op::HashKey, &[secret])
+        } else {
+            let mut key = [0; BLOCK_LEN];
+            key[..secret.len()].copy_from_slice(secret);
+            self.key.set(key);
+            // A(1) = HMAC(secret, A(0)), with A(0) = label + seed
+            self.start_hmac(Op::PrfA { outer: false }, &[&label_seed[..pos]])
+        }
+    }
+
+    /// Compute the SHA-256 hash of `data`.
+    pub fn hash(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.start_hash(Op::Hash, &[data])
+    }
+
+    /// Encrypt `m_len` bytes at `m_off` of `buf` and append the tag, with
+    /// the `AAD_LEN` bytes before them as additional data.
+    pub fn seal(
+        &self,
+        key: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.crypt(key, nonce, buf, a_off, m_off, m_len, true)
+    }
+
+    /// Decrypt `m_len` bytes at `m_off` of `buf`, followed by the tag, and
+    /// check the tag.
+    pub fn open(
+        &self,
+        key: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.crypt(key, nonce, buf, a_off, m_off, m_len, false)
+    }
+
+    fn crypt(
+        &self,
+        key: &[u8],
+        nonce: &[u8; NONCE_LEN],
+        buf: &'static mut [u8],
+        a_off: usize,
+        m_off: usize,
+        m_len: usize,
+        encrypting: bool,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.is_busy() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if let Err(e) = self
+            .ccm
+            .set_key(&key[..AES128_KEY_SIZE])
+            .and_then(|()| self.ccm.set_nonce(nonce))
+        {
+            return Err((e, buf));
+        }
+        self.ccm
+            .crypt(buf, a_off, m_off, m_len, TAG_LEN, true, encrypting)?;
+        self.op.set(Op::Crypt);
+        Ok(())
+    }
+
+    /// HMAC(key, message): hash the inner message, the key xor ipad
+    /// followed by `message`.
+    fn start_hmac(&self, op: Op, message: &[&[u8]]) -> Result<(), ErrorCode> {
+        let mut pad = self.key.get();
+        pad.iter_mut().for_each(|b| *b ^= 0x36);
+        let mut parts: [&[u8]; 3] = [&pad, &[], &[]];
+        parts[1..=message.len()].copy_from_slice(message);
+        self.start_hash(op, &parts[..=message.len()])
+    }
+
+    fn start_hash(&self, op: Op, data: &[&[u8]]) -> Result<(), ErrorCode> {
+        let mut buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer.reset();
+        let len: usize = data.iter().map(|part| part.len()).sum();
+        if len > buffer.len() {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        let mut pos = 0;
+        for part in data {
+            buffer[pos..pos + part.len()].copy_from_slice(part);
+            pos += part.len();
+        }
+        buffer.slice(..len);
+
+        self.digest.clear_data();
+        if let Err(e) = self.digest.set_mode_sha256() {
+            self.buffer.replace(buffer);
+            return Err(e);
+        }
+        match self.digest.add_mut_data(buffer) {
+            Ok(()) => {
+                self.op.set(op);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    /// Process the `hash` computed for the current operation.
+    fn hash_computed(&self, hash: &[u8; HASH_LEN]) -> Result<(), ErrorCode> {
+        match self.op.get() {
+            Op::Hash => {
+                let mut output = [0; MAX_PRF_LEN];
+                output[..HASH_LEN].copy_from_slice(hash);
+                self.output.set(output);
+                self.finish_derive(Ok(()));
+                Ok(())
+            }
+            Op::HashKey => {
+                let mut key = [0; BLOCK_LEN];
+                key[..HASH_LEN].copy_from_slice(hash);
+                self.key.set(key);
+                let label_seed = self.label_seed.get();
+                self.start_hmac(
+                    Op::PrfA { outer: false },
+                    &[&label_seed[..self.label_seed_len.get()]],
+                )
+            }
+            Op::PrfA { outer: false } | Op::PrfOutput { outer: false } => {
+                // The outer hash is of the key xor opad and the inner hash
+                let mut pad = self.key.get();
+                pad.iter_mut().for_each(|b| *b ^= 0x5c);
+                let op = match self.op.get() {
+                    Op::PrfA { .. } => Op::PrfA { outer: true },
+                    _ => Op::PrfOutput { outer: true },
+                };
+                self.start_hash(op, &[&pad, hash])
+            }
+            Op::PrfA { outer: true } => {
+                // HMAC(secret, A(i) + label + seed) gives the next output
+                self.a.set(*hash);
+                let a = self.a.get();
+                let label_seed = self.label_seed.get();
+                self.start_hmac(
+                    Op::PrfOutput { outer: false },
+                    &[&a, &label_seed[..self.label_seed_len.get()]],
+                )
+            }
+            Op::PrfOutput { outer: true } => {
+                let mut output = self.output.get();
+                let pos = self.output_pos.get();
+                let len = usize::min(HASH_LEN, self.output_len.get() - pos);
+                output[pos..pos + len].copy_from_slice(&hash[..len]);
+                self.output.set(output);
+                self.output_pos.set(pos + len);
+                if pos + len == self.output_len.get() {
+                    self.finish_derive(Ok(()));
+                    Ok(())
+                } else {
+                    // A(i + 1) = HMAC(secret, A(i))
+                    let a = self.a.get();
+                    self.start_hmac(Op::PrfA { outer: false }, &[&a])
+                }
+            }
+            Op::Idle | Op::Crypt => Err(ErrorCode::FAIL),
+        }
+    }
+
+    fn finish_derive(&self, result: Result<(), ErrorCode>) {
+        self.op.set(Op::Idle);
+        self.client.map(|client| client.derive_done(result));
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> digest::ClientData<32>
+    for DtlsCrypto<'a, C, D>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: LeasableBuffer<'static, u8>) {}
+
+    fn add_mut_data_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        data: LeasableMutableBuffer<'static, u8>,
+    ) {
+        self.buffer.replace(data);
+        let result = result.and_then(|()| {
+            let digest_buf = self.digest_buf.take().ok_or(ErrorCode::FAIL)?;
+            self.digest.run(digest_buf).map_err(|(e, digest_buf)| {
+                self.digest_buf.replace(digest_buf);
+                e
+            })
+        });
+        if result.is_err() {
+            self.finish_derive(result);
+        }
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> digest::ClientHash<32>
+    for DtlsCrypto<'a, C, D>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let hash = *digest;
+        self.digest_buf.replace(digest);
+        let result = result.and_then(|()| self.hash_computed(&hash));
+        if result.is_err() {
+            self.finish_derive(result);
+        }
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> digest::ClientVerify<32>
+    for DtlsCrypto<'a, C, D>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, compare: &'static mut [u8; 32]) {
+        self.digest_buf.replace(compare);
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256> CCMClient for DtlsCrypto<'a, C, D> {
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        self.op.set(Op::Idle);
+        let result = res.and(if tag_is_valid {
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        });
+        self.client.map(|client| client.record_done(buf, result));
+    }
+}
@@ -0,0 +1,341 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! DTLS 1.2 handshake messages with pre-shared keys (RFC 6347, RFC 5246
+//! and RFC 4279).
+//!
+//! Each handshake message has a 12 byte header: the type, the length of the
+//! message, its sequence number, and the offset and length of the fragment.
+//! Only unfragmented messages are supported, which is enough for the short
+//! messages of a PSK handshake.
+
+use kernel::ErrorCode;
+
+pub const HANDSHAKE_HEADER_LEN: usize = 12;
+pub const RANDOM_LEN: usize = 32;
+pub const VERIFY_DATA_LEN: usize = 12;
+pub const MAX_COOKIE_LEN: usize = 32;
+
+pub const TLS_PSK_WITH_AES_128_CCM_8: u16 = 0xc0a8;
+pub const COMPRESSION_NULL: u8 = 0;
+
+/// Handshake message types.
+pub mod handshake_type {
+    pub const CLIENT_HELLO: u8 = 1;
+    pub const SERVER_HELLO: u8 = 2;
+    pub const HELLO_VERIFY_REQUEST: u8 = 3;
+    pub const SERVER_KEY_EXCHANGE: u8 = 12;
+    pub const SERVER_HELLO_DONE: u8 = 14;
+    pub const CLIENT_KEY_EXCHANGE: u8 = 16;
+    pub const FINISHED: u8 = 20;
+}
+
+/// Alert levels and descriptions.
+pub mod alert {
+    pub const WARNING: u8 = 1;
+    pub const FATAL: u8 = 2;
+
+    pub const CLOSE_NOTIFY: u8 = 0;
+    pub const UNEXPECTED_MESSAGE: u8 = 10;
+    pub const HANDSHAKE_FAILURE: u8 = 40;
+    pub const ILLEGAL_PARAMETER: u8 = 47;
+    pub const DECRYPT_ERROR: u8 = 51;
+    pub const INTERNAL_ERROR: u8 = 80;
+    pub const UNKNOWN_PSK_IDENTITY: u8 = 115;
+}
+
+/// Reader of the fields of a message.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub fn u24(&mut self) -> Option<usize> {
+        self.bytes(3)
+            .map(|b| ((b[0] as usize) << 16) | ((b[1] as usize) << 8) | b[2] as usize)
+    }
+
+    /// A vector with a one byte length.
+    pub fn vec8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.bytes(len as usize)
+    }
+
+    /// A vector with a two byte length.
+    pub fn vec16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.bytes(len as usize)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+}
+
+/// A handshake message.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Handshake<'a> {
+    pub msg_type: u8,
+    pub message_seq: u16,
+    pub body: &'a [u8],
+}
+
+impl<'a> Handshake<'a> {
+    /// Decode the handshake message at the start of `buf`, with the length
+    /// of the message and its header. Fails with `SIZE` if `buf` is too
+    /// short, and with `NOSUPPORT` for a fragment of a message.
+    pub fn decode(buf: &'a [u8]) -> Result<(Handshake<'a>, usize), ErrorCode> {
+        let mut reader = Reader::new(buf);
+        let (msg_type, length, message_seq, offset, fragment_length) = (|| {
+            Some((
+                reader.u8()?,
+                reader.u24()?,
+                reader.u16()?,
+                reader.u24()?,
+                reader.u24()?,
+            ))
+        })()
+        .ok_or(ErrorCode::SIZE)?;
+        if offset != 0 || fragment_length != length {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let body = reader.bytes(length).ok_or(ErrorCode::SIZE)?;
+        Ok((
+            Handshake {
+                msg_type,
+                message_seq,
+                body,
+            },
+            HANDSHAKE_HEADER_LEN + length,
+        ))
+    }
+}
+
+/// The fields of a ClientHello that are used.
+pub struct ClientHello<'a> {
+    pub version: u16,
+    pub random: &'a [u8],
+    pub cookie: &'a [u8],
+    /// Whether `TLS_PSK_WITH_AES_128_CCM_8` and the null compression are
+    /// offered
+    pub acceptable: bool,
+}
+
+impl<'a> ClientHello<'a> {
+    /// Parse a ClientHello, whose extensions are ignored.
+    pub fn parse(body: &'a [u8]) -> Option<ClientHello<'a>> {
+        let mut reader = Reader::new(body);
+        let version = reader.u16()?;
+        let random = reader.bytes(RANDOM_LEN)?;
+        let _session_id = reader.vec8()?;
+        let cookie = reader.vec8()?;
+        let suites = reader.vec16()?;
+        let compressions = reader.vec8()?;
+        if cookie.len() > MAX_COOKIE_LEN || suites.len() % 2 != 0 {
+            return None;
+        }
+        let acceptable = suites
+            .chunks(2)
+            .any(|s| u16::from_be_bytes([s[0], s[1]]) == TLS_PSK_WITH_AES_128_CCM_8)
+            && compressions.contains(&COMPRESSION_NULL);
+        Some(ClientHello {
+            version,
+            random,
+            cookie,
+            acceptable,
+        })
+    }
+}
+
+/// The fields of a ServerHello that are used.
+pub struct ServerHello<'a> {
+    pub version: u16,
+    pub random: &'a [u8],
+    pub cipher_suite: u16,
+    pub compression: u8,
+}
+
+impl<'a> ServerHello<'a> {
+    pub fn parse(body: &'a [u8]) -> Option<ServerHello<'a>> {
+        let mut reader = Reader::new(body);
+        let version = reader.u16()?;
+        let random = reader.bytes(RANDOM_LEN)?;
+        let _session_id = reader.vec8()?;
+        Some(ServerHello {
+            version,
+            random,
+            cipher_suite: reader.u16()?,
+            compression: reader.u8()?,
+        })
+    }
+}
+
+/// The cookie of a HelloVerifyRequest.
+pub fn parse_hello_verify_request(body: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(body);
+    let _version = reader.u16()?;
+    let cookie = reader.vec8()?;
+    if cookie.len() > MAX_COOKIE_LEN {
+        return None;
+    }
+    Some(cookie)
+}
+
+/// The PSK identity of a ClientKeyExchange.
+pub fn parse_client_key_exchange(body: &[u8]) -> Option<&[u8]> {
+    let mut reader = Reader::new(body);
+    let identity = reader.vec16()?;
+    if reader.remaining() != 0 {
+        return None;
+    }
+    Some(identity)
+}
+
+/// Writer of a handshake message, which fills in the header on `finish()`.
+pub struct HandshakeWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    msg_type: u8,
+    message_seq: u16,
+    overflow: bool,
+}
+
+impl<'b> HandshakeWriter<'b> {
+    pub fn new(buf: &'b mut [u8], msg_type: u8, message_seq: u16) -> HandshakeWriter<'b> {
+        HandshakeWriter {
+            overflow: buf.len() < HANDSHAKE_HEADER_LEN,
+            buf,
+            len: HANDSHAKE_HEADER_LEN,
+            msg_type,
+            message_seq,
+        }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        match self.buf.get_mut(self.len..self.len + bytes.len()) {
+            Some(dst) if !self.overflow => {
+                dst.copy_from_slice(bytes);
+                self.len += bytes.len();
+            }
+            _ => self.overflow = true,
+        }
+        self
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> &mut Self {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn vec8(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u8(bytes.len() as u8).bytes(bytes)
+    }
+
+    pub fn vec16(&mut self, bytes: &[u8]) -> &mut Self {
+        self.u16(bytes.len() as u16).bytes(bytes)
+    }
+
+    /// Write the header, and return the length of the message with it.
+    pub fn finish(&mut self) -> Result<usize, ErrorCode> {
+        if self.overflow {
+            return Err(ErrorCode::SIZE);
+        }
+        let length = ((self.len - HANDSHAKE_HEADER_LEN) as u32).to_be_bytes();
+        self.buf[0] = self.msg_type;
+        self.buf[1..4].copy_from_slice(&length[1..]);
+        self.buf[4..6].copy_from_slice(&self.message_seq.to_be_bytes());
+        self.buf[6..9].copy_from_slice(&[0, 0, 0]);
+        self.buf[9..12].copy_from_slice(&length[1..]);
+        Ok(self.len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_hello_round_trip() {
+        let random = [7; RANDOM_LEN];
+        let mut buf = [0; 128];
+        let len = HandshakeWriter::new(&mut buf, handshake_type::CLIENT_HELLO, 1)
+            .u16(0xfefd)
+            .bytes(&random)
+            .vec8(&[])
+            .vec8(&[1, 2, 3])
+            .vec16(&[0x00, 0xff, 0xc0, 0xa8])
+            .vec8(&[COMPRESSION_NULL])
+            .finish()
+            .unwrap();
+        assert_eq!(&buf[..12], &[1, 0, 0, 47, 0, 1, 0, 0, 0, 0, 0, 47]);
+
+        let (handshake, consumed) = Handshake::decode(&buf[..len + 4]).unwrap();
+        assert_eq!(consumed, len);
+        assert_eq!(handshake.msg_type, handshake_type::CLIENT_HELLO);
+        assert_eq!(handshake.message_seq, 1);
+
+        let hello = ClientHello::parse(handshake.body).unwrap();
+        assert_eq!(hello.version, 0xfefd);
+        assert_eq!(hello.random, &random);
+        assert_eq!(hello.cookie, &[1, 2, 3]);
+        assert!(hello.acceptable);
+    }
+
+    #[test]
+    fn fragments_and_truncation() {
+        let mut buf = [0; 32];
+        let len = HandshakeWriter::new(&mut buf, handshake_type::FINISHED, 3)
+            .bytes(&[0xaa; VERIFY_DATA_LEN])
+            .finish()
+            .unwrap();
+        assert_eq!(Handshake::decode(&buf[..len - 1]), Err(ErrorCode::SIZE));
+
+        // A fragment at a non-zero offset
+        buf[8] = 4;
+        assert_eq!(Handshake::decode(&buf[..len]), Err(ErrorCode::NOSUPPORT));
+
+        let mut small = [0; 16];
+        assert_eq!(
+            HandshakeWriter::new(&mut small, handshake_type::FINISHED, 0)
+                .bytes(&[0; VERIFY_DATA_LEN])
+                .finish(),
+            Err(ErrorCode::SIZE)
+        );
+    }
+
+    #[test]
+    fn key_exchange() {
+        assert_eq!(
+            parse_client_key_exchange(&[0, 3, b'a', b'b', b'c']),
+            Some(&b"abc"[..])
+        );
+        assert_eq!(parse_client_key_exchange(&[0, 3, b'a', b'b']), None);
+        assert_eq!(
+            parse_hello_verify_request(&[0xfe, 0xff, 2, 9, 8]),
+            Some(&[9, 8][..])
+        );
+    }
+}
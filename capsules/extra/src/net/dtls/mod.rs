@@ -0,0 +1,52 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! DTLS 1.2 (RFC 6347) over the UDP stack.
+//!
+//! A `DtlsSession` protects the datagrams exchanged with one peer, with
+//! pre-shared keys and `TLS_PSK_WITH_AES_128_CCM_8` (RFC 7925). It runs on
+//! the AES-128-CCM and SHA-256 HILs through `DtlsCrypto`, and is placed
+//! between the UDP layer and an application, as their `UDPSender` and
+//! `UDPRecvClient`.
+//!
+//! Usage
+//! -----
+//! `components::dtls::DtlsComponent` binds a session to a UDP port. The
+//! application then sends through the session:
+//!
+//! ```rust,ignore
+//! let dtls = components::dtls::DtlsComponent::new(
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     ccm,
+//!     sha,
+//!     rng,
+//!     capsules_extra::net::dtls::Role::Client,
+//!     capsules_extra::net::dtls::DTLS_PORT,
+//! )
+//! .finalize(components::dtls_component_static!(
+//!     nrf52840::rtc::Rtc,
+//!     capsules_core::virtualizers::virtual_aes_ccm::VirtualAES128CCM<
+//!         'static,
+//!         nrf52840::aes::AesECB<'static>,
+//!     >,
+//!     capsules_extra::sha256::Sha256Software<'static>,
+//!     nrf52840::trng::Trng<'static>,
+//! ));
+//! dtls.set_psk(b"client-1", &PSK).unwrap();
+//! dtls.connect(SERVER_ADDR, capsules_extra::net::dtls::DTLS_PORT).unwrap();
+//! ```
+
+pub mod crypto;
+pub mod message;
+pub mod record;
+pub mod session;
+
+pub use self::crypto::DtlsCrypto;
+pub use self::session::{DtlsClient, DtlsSession, Role, State};
+
+/// The UDP port of CoAP over DTLS, the default port of sessions.
+pub const DTLS_PORT: u16 = 5684;
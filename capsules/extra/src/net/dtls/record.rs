@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! DTLS 1.2 records (RFC 6347, section 4.1).
+//!
+//! A record starts with a 13 byte header: the content type, the version,
+//! the epoch, a 48 bit sequence number and the length of the fragment.
+//! Records of epoch 1 are protected with AES-128-CCM-8 (RFC 6655): the
+//! fragment is the explicit part of the nonce, the ciphertext and an 8 byte
+//! tag. The nonce is the 4 byte implicit IV of the sender followed by the
+//! epoch and the sequence number of the record.
+
+pub const RECORD_HEADER_LEN: usize = 13;
+pub const EXPLICIT_NONCE_LEN: usize = 8;
+pub const TAG_LEN: usize = 8;
+/// Bytes that the protection of a record adds to its plaintext.
+pub const PROTECTION_OVERHEAD: usize = RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN + TAG_LEN;
+pub const AAD_LEN: usize = 13;
+pub const NONCE_LEN: usize = 12;
+pub const IV_LEN: usize = 4;
+
+/// Record version of DTLS 1.0, which is accepted in ClientHello records.
+pub const DTLS_1_0: u16 = 0xfeff;
+pub const DTLS_1_2: u16 = 0xfefd;
+
+const MAX_SEQ: u64 = (1 << 48) - 1;
+
+/// Content types.
+pub mod content_type {
+    pub const CHANGE_CIPHER_SPEC: u8 = 20;
+    pub const ALERT: u8 = 21;
+    pub const HANDSHAKE: u8 = 22;
+    pub const APPLICATION_DATA: u8 = 23;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RecordHeader {
+    pub content_type: u8,
+    pub version: u16,
+    pub epoch: u16,
+    /// The 48 bit sequence number
+    pub seq: u64,
+    /// Length of the fragment after the header
+    pub length: u16,
+}
+
+impl RecordHeader {
+    /// Decode the header of a record, `None` if `buf` does not hold the
+    /// whole record.
+    pub fn decode(buf: &[u8]) -> Option<RecordHeader> {
+        let header = buf.get(..RECORD_HEADER_LEN)?;
+        let mut seq = [0; 8];
+        seq[2..].copy_from_slice(&header[5..11]);
+        let record = RecordHeader {
+            content_type: header[0],
+            version: u16::from_be_bytes([header[1], header[2]]),
+            epoch: u16::from_be_bytes([header[3], header[4]]),
+            seq: u64::from_be_bytes(seq),
+            length: u16::from_be_bytes([header[11], header[12]]),
+        };
+        if buf.len() < record.record_len() {
+            return None;
+        }
+        Some(record)
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.content_type;
+        buf[1..3].copy_from_slice(&self.version.to_be_bytes());
+        buf[3..11].copy_from_slice(&self.epoch_seq());
+        buf[11..13].copy_from_slice(&self.length.to_be_bytes());
+    }
+
+    /// Length of the record, with the header.
+    pub fn record_len(&self) -> usize {
+        RECORD_HEADER_LEN + self.length as usize
+    }
+
+    /// The epoch and the sequence number, which are the explicit part of
+    /// the nonce.
+    pub fn epoch_seq(&self) -> [u8; EXPLICIT_NONCE_LEN] {
+        (((self.epoch as u64) << 48) | (self.seq & MAX_SEQ)).to_be_bytes()
+    }
+
+    /// The additional data authenticated with the `plaintext_len` bytes of
+    /// a protected record.
+    pub fn aad(&self, plaintext_len: usize) -> [u8; AAD_LEN] {
+        let mut aad = [0; AAD_LEN];
+        aad[..8].copy_from_slice(&self.epoch_seq());
+        aad[8] = self.content_type;
+        aad[9..11].copy_from_slice(&self.version.to_be_bytes());
+        aad[11..13].copy_from_slice(&(plaintext_len as u16).to_be_bytes());
+        aad
+    }
+}
+
+/// The nonce of a protected record from the implicit IV of the sender and
+/// the explicit nonce of the record.
+pub fn nonce(iv: &[u8], explicit: &[u8]) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..IV_LEN].copy_from_slice(&iv[..IV_LEN]);
+    nonce[IV_LEN..].copy_from_slice(&explicit[..EXPLICIT_NONCE_LEN]);
+    nonce
+}
+
+/// Detection of replayed records (RFC 6347, section 4.1.2.6), with a
+/// window of the 64 latest sequence numbers.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+    /// The highest sequence number received
+    latest: u64,
+    /// Bit `i` is set if `latest - i` was received
+    received: u64,
+}
+
+impl ReplayWindow {
+    /// Whether a record with `seq` was not received yet, and is not too old
+    /// to tell.
+    pub fn is_new(&self, seq: u64) -> bool {
+        if self.received == 0 || seq > self.latest {
+            return true;
+        }
+        let age = self.latest - seq;
+        age < 64 && self.received & (1 << age) == 0
+    }
+
+    /// Record that `seq` was received. Only authenticated records are marked.
+    pub fn mark(&mut self, seq: u64) {
+        if self.received == 0 {
+            self.latest = seq;
+            self.received = 1;
+        } else if seq > self.latest {
+            let shift = seq - self.latest;
+            self.received = if shift < 64 {
+                (self.received << shift) | 1
+            } else {
+                1
+            };
+            self.latest = seq;
+        } else if self.latest - seq < 64 {
+            self.received |= 1 << (self.latest - seq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = RecordHeader {
+            content_type: content_type::APPLICATION_DATA,
+            version: DTLS_1_2,
+            epoch: 1,
+            seq: 0x0102_0304_0506,
+            length: 4,
+        };
+        let mut buf = [0; RECORD_HEADER_LEN + 4];
+        header.encode(&mut buf);
+        assert_eq!(
+            &buf[..RECORD_HEADER_LEN],
+            &[23, 0xfe, 0xfd, 0, 1, 1, 2, 3, 4, 5, 6, 0, 4]
+        );
+        assert_eq!(RecordHeader::decode(&buf), Some(header));
+        assert_eq!(RecordHeader::decode(&buf[..RECORD_HEADER_LEN + 3]), None);
+
+        assert_eq!(header.epoch_seq(), [0, 1, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            header.aad(2),
+            [0, 1, 1, 2, 3, 4, 5, 6, 23, 0xfe, 0xfd, 0, 2]
+        );
+        assert_eq!(
+            nonce(&[0xa, 0xb, 0xc, 0xd], &header.epoch_seq()),
+            [0xa, 0xb, 0xc, 0xd, 0, 1, 1, 2, 3, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.is_new(5));
+        window.mark(5);
+        assert!(!window.is_new(5));
+        assert!(window.is_new(4));
+        assert!(window.is_new(6));
+
+        window.mark(3);
+        assert!(!window.is_new(3));
+        window.mark(70);
+        assert!(!window.is_new(70));
+        assert!(window.is_new(69));
+        // Sequence number 5 is now outside of the window
+        assert!(!window.is_new(5));
+        assert!(window.is_new(7));
+        window.mark(7);
+        assert!(!window.is_new(7));
+
+        window.mark(1000);
+        assert!(!window.is_new(70));
+        assert!(window.is_new(999));
+    }
+}
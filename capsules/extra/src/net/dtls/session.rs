@@ -0,0 +1,1621 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A DTLS 1.2 session with one peer, over UDP.
+//!
+//! The session runs the PSK handshake of RFC 4279 with
+//! `TLS_PSK_WITH_AES_128_CCM_8`, as a client or as a server, and then
+//! protects the datagrams of the layer above. It sits between a UDP sender
+//! and receiver and an application: it implements `UDPSender` and calls a
+//! `UDPRecvClient`, so that an application written for plain UDP, such as
+//! the MQTT-SN client, runs over DTLS unchanged.
+//!
+//! A server answers ClientHellos without a valid cookie with a stateless
+//! HelloVerifyRequest, whose cookie is a PRF of a random secret and of the
+//! address, the port and the random of the client. It serves one client at
+//! a time.
+//!
+//! Each flight of the handshake is kept, and retransmitted when its timer
+//! expires or when the peer retransmits its previous flight. The timer
+//! starts at `INITIAL_TIMEOUT_MS` and doubles for each retransmission; after
+//! `MAX_TRANSMISSIONS` transmissions the handshake fails with `NOACK`.
+//! Handshake messages have to fit in one record, and all the records of a
+//! flight in one datagram. Renegotiation and session resumption are not
+//! supported.
+//!
+//! Application data is only sent to and received from the peer once the
+//! handshake completed. One datagram is sent at a time: it is returned with
+//! `send_done()` once it went out.
+
+use core::cell::Cell;
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_port_table::UdpPortBindingTx;
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+use crate::net::udp::UDPHeader;
+
+use kernel::capabilities::UdpDriverCapability;
+use kernel::hil::digest::{Digest, Sha256};
+use kernel::hil::rng;
+use kernel::hil::symmetric_encryption::{AES128CCM, AES128_KEY_SIZE};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+use super::crypto::{CryptoClient, DtlsCrypto, HASH_LEN, MAX_PRF_LEN};
+use super::message::{
+    alert, handshake_type, parse_client_key_exchange, parse_hello_verify_request, ClientHello,
+    Handshake, HandshakeWriter, ServerHello, COMPRESSION_NULL, MAX_COOKIE_LEN, RANDOM_LEN,
+    TLS_PSK_WITH_AES_128_CCM_8, VERIFY_DATA_LEN,
+};
+use super::record::{
+    self, content_type, RecordHeader, ReplayWindow, DTLS_1_0, DTLS_1_2, EXPLICIT_NONCE_LEN, IV_LEN,
+    PROTECTION_OVERHEAD, RECORD_HEADER_LEN, TAG_LEN,
+};
+
+pub const MAX_PSK_LEN: usize = 64;
+pub const MAX_IDENTITY_LEN: usize = 32;
+/// Size of the buffer of the handshake messages hashed into the Finished
+/// messages, and of the buffer of `DtlsCrypto`.
+pub const TRANSCRIPT_LEN: usize = 512;
+/// Size of the buffer of the flight being sent.
+pub const FLIGHT_LEN: usize = 192;
+
+pub const INITIAL_TIMEOUT_MS: u32 = 1_000;
+const MAX_TIMEOUT_MS: u32 = 60_000;
+pub const MAX_TRANSMISSIONS: u8 = 7;
+
+const COOKIE_LEN: usize = 16;
+const MASTER_SECRET_LEN: usize = 48;
+/// The client and server write keys and IVs.
+const KEY_BLOCK_LEN: usize = 2 * AES128_KEY_SIZE + 2 * IV_LEN;
+const MAX_PREMASTER_LEN: usize = 2 * MAX_PSK_LEN + 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    /// A server waiting for a client
+    Listening,
+    Handshaking,
+    Connected,
+}
+
+pub trait DtlsClient {
+    /// The handshake with the peer completed. It fails with `NOACK` if the
+    /// peer stopped answering, and with `FAIL` if it rejected the handshake
+    /// or holds another key.
+    fn handshake_done(&self, result: Result<(), ErrorCode>);
+
+    /// The peer closed the connected session, or it failed.
+    fn closed(&self);
+}
+
+/// The next message expected from the peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Step {
+    ClientHello,
+    /// A HelloVerifyRequest or a ServerHello
+    ServerHello,
+    /// An optional ServerKeyExchange and the ServerHelloDone
+    ServerHelloDone,
+    ClientKeyExchange,
+    ChangeCipherSpec,
+    Finished,
+    Done,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum RandomUse {
+    CookieSecret,
+    ClientRandom,
+    ServerRandom,
+}
+
+/// The asynchronous operation the session waits for. Received records are
+/// only processed when none is pending.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Pending {
+    None,
+    Random(RandomUse),
+    Cookie,
+    MasterSecret,
+    KeyBlock,
+    OwnFinishedHash,
+    OwnFinishedPrf,
+    SealFinished,
+    PeerFinishedHash,
+    PeerFinishedPrf,
+    Open,
+    SealData,
+    SealCloseNotify,
+}
+
+/// The datagram being sent by the UDP sender.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Sending {
+    Nothing,
+    Handshake,
+    Data,
+    CloseNotify,
+}
+
+/// A protected record that was opened.
+#[derive(Copy, Clone)]
+struct Opened {
+    content_type: u8,
+    seq: u64,
+    offset: usize,
+    len: usize,
+}
+
+pub struct DtlsSession<
+    'a,
+    C: AES128CCM<'a>,
+    D: Digest<'a, 32> + Sha256,
+    R: rng::Rng<'a>,
+    A: time::Alarm<'a>,
+> {
+    sender: &'a dyn UDPSender<'a>,
+    crypto: &'a DtlsCrypto<'a, C, D>,
+    rng: &'a R,
+    alarm: &'a A,
+    net_cap: &'static NetworkCapability,
+    role: Role,
+    client: OptionalCell<&'a dyn DtlsClient>,
+    send_client: OptionalCell<&'a dyn UDPSendClient>,
+    recv_client: OptionalCell<&'a dyn UDPRecvClient>,
+
+    identity: Cell<[u8; MAX_IDENTITY_LEN]>,
+    identity_len: Cell<usize>,
+    psk: Cell<[u8; MAX_PSK_LEN]>,
+    psk_len: Cell<usize>,
+
+    state: Cell<State>,
+    step: Cell<Step>,
+    pending: Cell<Pending>,
+    peer_addr: Cell<IPAddr>,
+    peer_port: Cell<u16>,
+
+    random: Cell<[u8; RANDOM_LEN]>,
+    random_len: Cell<usize>,
+    client_random: Cell<[u8; RANDOM_LEN]>,
+    server_random: Cell<[u8; RANDOM_LEN]>,
+    cookie: Cell<[u8; MAX_COOKIE_LEN]>,
+    cookie_len: Cell<usize>,
+    cookie_secret: Cell<[u8; COOKIE_LEN]>,
+    /// Message sequence number of the ClientHello being checked
+    hello_seq: Cell<u16>,
+    master_secret: Cell<[u8; MASTER_SECRET_LEN]>,
+    key_block: Cell<[u8; KEY_BLOCK_LEN]>,
+    peer_verify_data: Cell<[u8; VERIFY_DATA_LEN]>,
+
+    write_epoch: Cell<u16>,
+    /// The next record sequence number of epochs 0 and 1
+    write_seq: Cell<[u64; 2]>,
+    read_epoch: Cell<u16>,
+    replay: Cell<ReplayWindow>,
+    send_message_seq: Cell<u16>,
+    recv_message_seq: Cell<u16>,
+
+    transcript: TakeCell<'static, [u8]>,
+    transcript_len: Cell<usize>,
+    flight: TakeCell<'static, [u8]>,
+    flight_len: Cell<usize>,
+    /// The flight is due for a transmission
+    flight_due: Cell<bool>,
+    transmissions: Cell<u8>,
+    timeout_ms: Cell<u32>,
+    /// Offset and header of the record being sealed
+    sealing: OptionalCell<(usize, RecordHeader)>,
+
+    tx_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    tx_capacity: usize,
+    sending: Cell<Sending>,
+    /// The datagram of the layer above, until it is sent
+    app_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+
+    rx_buffer: TakeCell<'static, [u8]>,
+    /// Length of the datagram being processed, 0 if there is none
+    rx_len: Cell<usize>,
+    /// Offset of the next record of the datagram
+    rx_next: Cell<usize>,
+    /// Range of the handshake messages of the current record
+    rx_messages: Cell<(usize, usize)>,
+    /// The current record was protected
+    rx_protected: Cell<bool>,
+    rx_src: Cell<(IPAddr, u16)>,
+    rx_dst: Cell<(IPAddr, u16)>,
+    opened: OptionalCell<Opened>,
+    /// The peer retransmitted a flight in the current datagram
+    rx_retransmission: Cell<bool>,
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    DtlsSession<'a, C, D, R, A>
+{
+    /// `transcript` should hold `TRANSCRIPT_LEN` bytes and `flight`
+    /// `FLIGHT_LEN` bytes. `tx_buffer` and `rx_buffer` should hold the
+    /// longest datagram of the application, plus `PROTECTION_OVERHEAD`
+    /// bytes, and at least `FLIGHT_LEN` bytes.
+    pub fn new(
+        sender: &'a dyn UDPSender<'a>,
+        crypto: &'a DtlsCrypto<'a, C, D>,
+        rng: &'a R,
+        alarm: &'a A,
+        net_cap: &'static NetworkCapability,
+        role: Role,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        flight: &'static mut [u8],
+        transcript: &'static mut [u8],
+    ) -> DtlsSession<'a, C, D, R, A> {
+        DtlsSession {
+            sender,
+            crypto,
+            rng,
+            alarm,
+            net_cap,
+            role,
+            client: OptionalCell::empty(),
+            send_client: OptionalCell::empty(),
+            recv_client: OptionalCell::empty(),
+            identity: Cell::new([0; MAX_IDENTITY_LEN]),
+            identity_len: Cell::new(0),
+            psk: Cell::new([0; MAX_PSK_LEN]),
+            psk_len: Cell::new(0),
+            state: Cell::new(State::Idle),
+            step: Cell::new(Step::Done),
+            pending: Cell::new(Pending::None),
+            peer_addr: Cell::new(IPAddr([0; 16])),
+            peer_port: Cell::new(0),
+            random: Cell::new([0; RANDOM_LEN]),
+            random_len: Cell::new(0),
+            client_random: Cell::new([0; RANDOM_LEN]),
+            server_random: Cell::new([0; RANDOM_LEN]),
+            cookie: Cell::new([0; MAX_COOKIE_LEN]),
+            cookie_len: Cell::new(0),
+            cookie_secret: Cell::new([0; COOKIE_LEN]),
+            hello_seq: Cell::new(0),
+            master_secret: Cell::new([0; MASTER_SECRET_LEN]),
+            key_block: Cell::new([0; KEY_BLOCK_LEN]),
+            peer_verify_data: Cell::new([0; VERIFY_DATA_LEN]),
+            write_epoch: Cell::new(0),
+            write_seq: Cell::new([0; 2]),
+            read_epoch: Cell::new(0),
+            replay: Cell::new(ReplayWindow::default()),
+            send_message_seq: Cell::new(0),
+            recv_message_seq: Cell::new(0),
+            transcript: TakeCell::new(transcript),
+            transcript_len: Cell::new(0),
+            flight: TakeCell::new(flight),
+            flight_len: Cell::new(0),
+            flight_due: Cell::new(false),
+            transmissions: Cell::new(0),
+            timeout_ms: Cell::new(INITIAL_TIMEOUT_MS),
+            sealing: OptionalCell::empty(),
+            tx_capacity: tx_buffer.len(),
+            tx_buffer: MapCell::new(LeasableMutableBuffer::new(tx_buffer)),
+            sending: Cell::new(Sending::Nothing),
+            app_buffer: MapCell::empty(),
+            rx_buffer: TakeCell::new(rx_buffer),
+            rx_len: Cell::new(0),
+            rx_next: Cell::new(0),
+            rx_messages: Cell::new((0, 0)),
+            rx_protected: Cell::new(false),
+            rx_src: Cell::new((IPAddr([0; 16]), 0)),
+            rx_dst: Cell::new((IPAddr([0; 16]), 0)),
+            opened: OptionalCell::empty(),
+            rx_retransmission: Cell::new(false),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn DtlsClient) {
+        self.client.set(client);
+    }
+
+    /// Set the client that receives the application data of the peer.
+    pub fn set_receive_client(&self, client: &'a dyn UDPRecvClient) {
+        self.recv_client.set(client);
+    }
+
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// The address and port of the peer of the session.
+    pub fn peer(&self) -> (IPAddr, u16) {
+        (self.peer_addr.get(), self.peer_port.get())
+    }
+
+    /// Set the identity and the key of the session, which the client sends
+    /// and the server accepts.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The key is set.
+    /// - `SIZE`: The identity or the key is empty or too long.
+    /// - `BUSY`: The session is not idle.
+    pub fn set_psk(&self, identity: &[u8], key: &[u8]) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if identity.is_empty()
+            || identity.len() > MAX_IDENTITY_LEN
+            || key.is_empty()
+            || key.len() > MAX_PSK_LEN
+        {
+            return Err(ErrorCode::SIZE);
+        }
+        let mut stored_identity = [0; MAX_IDENTITY_LEN];
+        stored_identity[..identity.len()].copy_from_slice(identity);
+        self.identity.set(stored_identity);
+        self.identity_len.set(identity.len());
+        let mut stored_key = [0; MAX_PSK_LEN];
+        stored_key[..key.len()].copy_from_slice(key);
+        self.psk.set(stored_key);
+        self.psk_len.set(key.len());
+        Ok(())
+    }
+
+    /// Start a handshake with the server at `addr` and `port`, which
+    /// completes with `handshake_done()`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The handshake started.
+    /// - `NOSUPPORT`: The session is a server.
+    /// - `RESERVE`: No key is set.
+    /// - `ALREADY`: The session is not idle.
+    /// - `BUSY`: The session is still closing.
+    pub fn connect(&self, addr: IPAddr, port: u16) -> Result<(), ErrorCode> {
+        if self.role != Role::Client {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.check_start()?;
+        self.peer_addr.set(addr);
+        self.peer_port.set(port);
+        self.request_random(RandomUse::ClientRandom)?;
+        self.state.set(State::Handshaking);
+        Ok(())
+    }
+
+    /// Wait for a client to start a handshake, which completes with
+    /// `handshake_done()`. Once the session with the client closes, the
+    /// server waits for the next one.
+    ///
+    /// Return values are the ones of `connect()`.
+    pub fn listen(&self) -> Result<(), ErrorCode> {
+        if self.role != Role::Server {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.check_start()?;
+        self.request_random(RandomUse::CookieSecret)?;
+        self.state.set(State::Listening);
+        Ok(())
+    }
+
+    fn check_start(&self) -> Result<(), ErrorCode> {
+        if self.psk_len.get() == 0 {
+            Err(ErrorCode::RESERVE)
+        } else if self.state.get() != State::Idle {
+            Err(ErrorCode::ALREADY)
+        } else if self.pending.get() != Pending::None {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.reset();
+            Ok(())
+        }
+    }
+
+    /// Close the session, and stop listening. The peer is sent a
+    /// close_notify alert if the session is connected.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The session is closed.
+    /// - `ALREADY`: The session is idle.
+    /// - `BUSY`: A datagram of the application is being sent.
+    pub fn close(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Idle {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.app_buffer.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.state.get() == State::Connected && self.pending.get() == Pending::None {
+            self.seal_close_notify();
+        }
+        self.state.set(State::Idle);
+        self.reset();
+        Ok(())
+    }
+
+    /// Clear the handshake and the keys, and go back to `Listening` or
+    /// `Idle`. The pending operation, if any, completes and is ignored.
+    fn reset(&self) {
+        let _ = self.alarm.disarm();
+        if self.state.get() != State::Idle {
+            self.state.set(match self.role {
+                Role::Client => State::Idle,
+                Role::Server => State::Listening,
+            });
+        }
+        self.step.set(match self.role {
+            Role::Client => Step::ServerHello,
+            Role::Server => Step::ClientHello,
+        });
+        self.cookie_len.set(0);
+        self.master_secret.set([0; MASTER_SECRET_LEN]);
+        self.key_block.set([0; KEY_BLOCK_LEN]);
+        self.write_epoch.set(0);
+        self.write_seq.set([0; 2]);
+        self.read_epoch.set(0);
+        self.replay.set(ReplayWindow::default());
+        self.send_message_seq.set(0);
+        self.recv_message_seq.set(0);
+        self.transcript_len.set(0);
+        self.flight_len.set(0);
+        self.flight_due.set(false);
+        self.rx_len.set(0);
+    }
+
+    /// End the handshake or the connection with an error.
+    fn fail(&self, error: ErrorCode) {
+        let state = self.state.get();
+        self.reset();
+        if self.pending.get() != Pending::SealData {
+            self.app_buffer.take().map(|buf| {
+                self.send_client
+                    .map(|client| client.send_done(Err(ErrorCode::CANCEL), buf))
+            });
+        }
+        match state {
+            State::Handshaking => self.client.map(|client| client.handshake_done(Err(error))),
+            State::Connected => self.client.map(|client| client.closed()),
+            State::Idle | State::Listening => None,
+        };
+    }
+
+    fn request_random(&self, purpose: RandomUse) -> Result<(), ErrorCode> {
+        self.random_len.set(0);
+        self.pending.set(Pending::Random(purpose));
+        self.rng.get().map_err(|e| {
+            self.pending.set(Pending::None);
+            e
+        })
+    }
+
+    /// Derive the master secret from the PSK (RFC 4279, section 2).
+    fn derive_master_secret(&self) {
+        let psk_len = self.psk_len.get();
+        let mut premaster = [0; MAX_PREMASTER_LEN];
+        premaster[..2].copy_from_slice(&(psk_len as u16).to_be_bytes());
+        premaster[2 + psk_len..4 + psk_len].copy_from_slice(&(psk_len as u16).to_be_bytes());
+        premaster[4 + psk_len..4 + 2 * psk_len].copy_from_slice(&self.psk.get()[..psk_len]);
+        self.derive(
+            Pending::MasterSecret,
+            &premaster[..4 + 2 * psk_len],
+            b"master secret",
+            &[&self.client_random.get(), &self.server_random.get()],
+            MASTER_SECRET_LEN,
+        );
+    }
+
+    fn derive(&self, pending: Pending, secret: &[u8], label: &[u8], seed: &[&[u8]], len: usize) {
+        self.pending.set(pending);
+        if let Err(e) = self.crypto.prf(secret, label, seed, len) {
+            self.pending.set(Pending::None);
+            self.fail(e);
+        }
+    }
+
+    fn finished_label(&self, own: bool) -> &'static [u8] {
+        if (self.role == Role::Client) == own {
+            b"client finished"
+        } else {
+            b"server finished"
+        }
+    }
+
+    /// The write key and IV of the session, or the ones of the peer.
+    fn keys(&self, own: bool) -> ([u8; AES128_KEY_SIZE], [u8; IV_LEN]) {
+        let key_block = self.key_block.get();
+        let client = (self.role == Role::Client) == own;
+        let (key, iv) = if client {
+            (0, 2 * AES128_KEY_SIZE)
+        } else {
+            (AES128_KEY_SIZE, 2 * AES128_KEY_SIZE + IV_LEN)
+        };
+        let mut write_key = [0; AES128_KEY_SIZE];
+        write_key.copy_from_slice(&key_block[key..key + AES128_KEY_SIZE]);
+        let mut write_iv = [0; IV_LEN];
+        write_iv.copy_from_slice(&key_block[iv..iv + IV_LEN]);
+        (write_key, write_iv)
+    }
+
+    /// A header for the next record of the current write epoch.
+    fn next_header(&self, content_type: u8, length: usize) -> RecordHeader {
+        let epoch = self.write_epoch.get();
+        let mut seqs = self.write_seq.get();
+        let seq = seqs[epoch as usize];
+        seqs[epoch as usize] += 1;
+        self.write_seq.set(seqs);
+        RecordHeader {
+            content_type,
+            version: DTLS_1_2,
+            epoch,
+            seq,
+            length: length as u16,
+        }
+    }
+
+    fn append_transcript(&self, message: &[u8]) -> Result<(), ErrorCode> {
+        let len = self.transcript_len.get();
+        self.transcript.map_or(Err(ErrorCode::FAIL), |transcript| {
+            transcript
+                .get_mut(len..len + message.len())
+                .ok_or(ErrorCode::SIZE)
+                .map(|dst| dst.copy_from_slice(message))
+        })?;
+        self.transcript_len.set(len + message.len());
+        Ok(())
+    }
+
+    /// Start a new flight, which replaces the previous one.
+    fn start_flight(&self) {
+        self.flight_len.set(0);
+        self.flight_due.set(false);
+    }
+
+    /// Append a handshake message to the flight and the transcript.
+    fn append_handshake<F>(&self, msg_type: u8, body: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&mut HandshakeWriter),
+    {
+        let seq = self.send_message_seq.get();
+        let start = self.flight_len.get();
+        let len = self.flight.map_or(Err(ErrorCode::FAIL), |flight| {
+            let fragment = flight
+                .get_mut(start + RECORD_HEADER_LEN..)
+                .ok_or(ErrorCode::SIZE)?;
+            let mut writer = HandshakeWriter::new(fragment, msg_type, seq);
+            body(&mut writer);
+            let len = writer.finish()?;
+            self.append_transcript(&fragment[..len])?;
+            self.next_header(content_type::HANDSHAKE, len)
+                .encode(&mut flight[start..]);
+            Ok(len)
+        })?;
+        self.flight_len.set(start + RECORD_HEADER_LEN + len);
+        self.send_message_seq.set(seq + 1);
+        Ok(())
+    }
+
+    /// Append a ChangeCipherSpec to the flight, and protect the records
+    /// that follow.
+    fn append_change_cipher_spec(&self) -> Result<(), ErrorCode> {
+        let start = self.flight_len.get();
+        self.flight.map_or(Err(ErrorCode::FAIL), |flight| {
+            let record = flight
+                .get_mut(start..start + RECORD_HEADER_LEN + 1)
+                .ok_or(ErrorCode::SIZE)?;
+            self.next_header(content_type::CHANGE_CIPHER_SPEC, 1)
+                .encode(record);
+            record[RECORD_HEADER_LEN] = 1;
+            Ok(())
+        })?;
+        self.flight_len.set(start + RECORD_HEADER_LEN + 1);
+        self.write_epoch.set(1);
+        Ok(())
+    }
+
+    /// Send the flight, and retransmit it until the peer answers if
+    /// `answered` is set.
+    fn send_flight(&self, answered: bool) {
+        self.transmissions.set(1);
+        self.timeout_ms.set(INITIAL_TIMEOUT_MS);
+        self.flight_due.set(true);
+        if answered {
+            self.arm_timer();
+        } else {
+            let _ = self.alarm.disarm();
+        }
+        self.service();
+    }
+
+    /// Send the flight again, with new sequence numbers for its records of
+    /// epoch 0. The protected Finished record is sent unchanged.
+    fn retransmit(&self) {
+        if self.flight_len.get() == 0 || self.flight.is_none() {
+            return;
+        }
+        let len = self.flight_len.get();
+        self.flight.map(|flight| {
+            let mut pos = 0;
+            while let Some(header) = RecordHeader::decode(&flight[pos..len]) {
+                if header.epoch == 0 {
+                    let mut seqs = self.write_seq.get();
+                    let renumbered = RecordHeader {
+                        seq: seqs[0],
+                        ..header
+                    };
+                    seqs[0] += 1;
+                    self.write_seq.set(seqs);
+                    renumbered.encode(&mut flight[pos..]);
+                }
+                pos += header.record_len();
+            }
+        });
+        self.flight_due.set(true);
+        self.service();
+    }
+
+    fn arm_timer(&self) {
+        let dt = self.alarm.ticks_from_ms(self.timeout_ms.get());
+        self.alarm.set_alarm(self.alarm.now(), dt);
+    }
+
+    /// Send a record outside of the flight, if the sender is free.
+    fn send_record<F>(&self, content_type: u8, fill: F)
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, ErrorCode>,
+    {
+        if let Some(mut tx) = self.tx_buffer.take() {
+            tx.reset();
+            match fill(&mut tx[RECORD_HEADER_LEN..]) {
+                Ok(len) => {
+                    self.next_header(content_type, len).encode(&mut tx[..]);
+                    tx.slice(..RECORD_HEADER_LEN + len);
+                    self.transmit(tx, Sending::Nothing);
+                }
+                Err(_) => {
+                    self.tx_buffer.replace(tx);
+                }
+            }
+        }
+    }
+
+    /// Send a fatal alert in the clear, which only happens during the first
+    /// handshake.
+    fn send_alert(&self, description: u8) {
+        if self.write_epoch.get() == 0 {
+            self.send_record(content_type::ALERT, |buf| {
+                buf[..2].copy_from_slice(&[alert::FATAL, description]);
+                Ok(2)
+            });
+        }
+    }
+
+    fn transmit(&self, tx: LeasableMutableBuffer<'static, u8>, sending: Sending) {
+        self.sending.set(sending);
+        let (addr, port) = match self.state.get() {
+            // A stateless HelloVerifyRequest or alert to a client
+            State::Listening => self.rx_src.get(),
+            _ => self.peer(),
+        };
+        if let Err(tx) = self.sender.send_to(addr, port, tx, self.net_cap) {
+            self.sent(Err(ErrorCode::FAIL), tx);
+        }
+    }
+
+    /// Send what is due: the flight, or the datagram of the application.
+    fn service(&self) {
+        if self.tx_buffer.is_none() {
+            return;
+        }
+        if self.flight_due.get() && self.flight.is_some() {
+            self.flight_due.set(false);
+            let len = self.flight_len.get();
+            if let Some(mut tx) = self.tx_buffer.take() {
+                tx.reset();
+                self.flight
+                    .map(|flight| tx[..len].copy_from_slice(&flight[..len]));
+                tx.slice(..len);
+                self.transmit(tx, Sending::Handshake);
+            }
+        } else if self.state.get() == State::Connected
+            && self.pending.get() == Pending::None
+            && self.app_buffer.is_some()
+        {
+            self.seal_data();
+        }
+    }
+
+    /// Protect the datagram of the application in `tx_buffer`.
+    fn seal_data(&self) {
+        let mut tx = match self.tx_buffer.take() {
+            Some(tx) => tx,
+            None => return,
+        };
+        tx.reset();
+        let offset = RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN;
+        let len = self.app_buffer.map_or(0, |app| {
+            let len = app.len();
+            tx[offset..offset + len].copy_from_slice(&app[..]);
+            len
+        });
+        let result = self.seal(
+            Pending::SealData,
+            tx.take(),
+            0,
+            content_type::APPLICATION_DATA,
+            len,
+        );
+        if let Err((_, buf)) = result {
+            self.tx_buffer.replace(LeasableMutableBuffer::new(buf));
+            self.app_buffer.take().map(|app| {
+                self.send_client
+                    .map(|client| client.send_done(Err(ErrorCode::FAIL), app))
+            });
+        }
+    }
+
+    fn seal_close_notify(&self) {
+        if let Some(mut tx) = self.tx_buffer.take() {
+            tx.reset();
+            let offset = RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN;
+            tx[offset..offset + 2].copy_from_slice(&[alert::WARNING, alert::CLOSE_NOTIFY]);
+            if let Err((_, buf)) = self.seal(
+                Pending::SealCloseNotify,
+                tx.take(),
+                0,
+                content_type::ALERT,
+                2,
+            ) {
+                self.tx_buffer.replace(LeasableMutableBuffer::new(buf));
+            }
+        }
+    }
+
+    /// Protect the `len` bytes of plaintext of the record at `offset` of
+    /// `buf`. The header and the explicit nonce are written once sealed.
+    fn seal(
+        &self,
+        pending: Pending,
+        buf: &'static mut [u8],
+        offset: usize,
+        content_type: u8,
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let header = self.next_header(content_type, EXPLICIT_NONCE_LEN + len + TAG_LEN);
+        let m_off = offset + RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN;
+        let a_off = m_off - record::AAD_LEN;
+        buf[a_off..m_off].copy_from_slice(&header.aad(len));
+        let (key, iv) = self.keys(true);
+        let nonce = record::nonce(&iv, &header.epoch_seq());
+        self.sealing.set((offset, header));
+        self.pending.set(pending);
+        self.crypto
+            .seal(&key, &nonce, buf, a_off, m_off, len)
+            .map_err(|err| {
+                self.pending.set(Pending::None);
+                err
+            })
+    }
+
+    /// Write the header of the record that was sealed in `buf`.
+    fn sealed(&self, buf: &mut [u8]) -> usize {
+        self.sealing.take().map_or(0, |(offset, header)| {
+            header.encode(&mut buf[offset..]);
+            buf[offset + RECORD_HEADER_LEN..offset + RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN]
+                .copy_from_slice(&header.epoch_seq());
+            offset + header.record_len()
+        })
+    }
+
+    /// The UDP sender is done with `tx`.
+    fn sent(&self, result: Result<(), ErrorCode>, mut tx: LeasableMutableBuffer<'static, u8>) {
+        tx.reset();
+        self.tx_buffer.replace(tx);
+        if self.sending.replace(Sending::Nothing) == Sending::Data {
+            self.app_buffer
+                .take()
+                .map(|app| self.send_client.map(|client| client.send_done(result, app)));
+        }
+        self.service();
+    }
+
+    /// Continue with the received records and what is due to be sent, once
+    /// an operation completed.
+    fn resume(&self) {
+        self.process_rx();
+        self.service();
+    }
+
+    fn process_rx(&self) {
+        while self.pending.get() == Pending::None && self.rx_len.get() > 0 {
+            let (pos, end) = self.rx_messages.get();
+            if pos < end {
+                self.rx_messages.set((end, end));
+                self.rx_buffer.map(|rx| {
+                    if let Ok((message, len)) = Handshake::decode(&rx[pos..end]) {
+                        self.rx_messages.set((pos + len, end));
+                        self.receive_handshake(&message, &rx[pos..pos + len]);
+                    }
+                });
+            } else if !self.next_record() {
+                self.rx_len.set(0);
+                if self.rx_retransmission.replace(false) {
+                    self.retransmit();
+                }
+            }
+        }
+    }
+
+    /// Start processing the next record of the datagram, `false` if there
+    /// is none.
+    fn next_record(&self) -> bool {
+        let next = self.rx_next.get();
+        let len = self.rx_len.get();
+        let rx = match self.rx_buffer.take() {
+            Some(rx) if next < len => rx,
+            Some(rx) => {
+                self.rx_buffer.replace(rx);
+                return false;
+            }
+            None => return false,
+        };
+        let header = match RecordHeader::decode(&rx[next..len]) {
+            Some(header) => header,
+            None => {
+                self.rx_buffer.replace(rx);
+                return false;
+            }
+        };
+        let start = next + RECORD_HEADER_LEN;
+        let end = next + header.record_len();
+        self.rx_next.set(end);
+        if header.version != DTLS_1_2 && header.version != DTLS_1_0 {
+            self.rx_buffer.replace(rx);
+        } else if header.epoch == 0 {
+            if header.content_type == content_type::HANDSHAKE {
+                self.rx_protected.set(false);
+                self.rx_messages.set((start, end));
+            } else {
+                self.receive_plaintext(header.content_type, &rx[start..end]);
+            }
+            self.rx_buffer.replace(rx);
+        } else if header.epoch == 1
+            && self.read_epoch.get() == 1
+            && header.length as usize >= EXPLICIT_NONCE_LEN + TAG_LEN
+            && self.replay.get().is_new(header.seq)
+        {
+            let (key, iv) = self.keys(false);
+            let nonce = record::nonce(&iv, &rx[start..start + EXPLICIT_NONCE_LEN]);
+            let m_off = start + EXPLICIT_NONCE_LEN;
+            let m_len = header.length as usize - EXPLICIT_NONCE_LEN - TAG_LEN;
+            let a_off = m_off - record::AAD_LEN;
+            rx[a_off..m_off].copy_from_slice(&header.aad(m_len));
+            self.opened.set(Opened {
+                content_type: header.content_type,
+                seq: header.seq,
+                offset: m_off,
+                len: m_len,
+            });
+            self.pending.set(Pending::Open);
+            if let Err((_, rx)) = self.crypto.open(&key, &nonce, rx, a_off, m_off, m_len) {
+                self.pending.set(Pending::None);
+                self.rx_buffer.replace(rx);
+            }
+        } else {
+            self.rx_buffer.replace(rx);
+        }
+        true
+    }
+
+    /// Process a ChangeCipherSpec or alert record of epoch 0.
+    fn receive_plaintext(&self, content_type: u8, fragment: &[u8]) {
+        match content_type {
+            content_type::CHANGE_CIPHER_SPEC => {
+                if self.step.get() == Step::ChangeCipherSpec && fragment == [1] {
+                    self.read_epoch.set(1);
+                    self.replay.set(ReplayWindow::default());
+                    self.step.set(Step::Finished);
+                }
+            }
+            content_type::ALERT => {
+                if self.state.get() == State::Handshaking
+                    && self.read_epoch.get() == 0
+                    && fragment.first() == Some(&alert::FATAL)
+                {
+                    self.fail(ErrorCode::FAIL);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Process a protected record that was opened.
+    fn receive_protected(&self, opened: Opened, rx: &[u8]) {
+        let plaintext = &rx[opened.offset..opened.offset + opened.len];
+        match opened.content_type {
+            content_type::HANDSHAKE => {
+                self.rx_protected.set(true);
+                self.rx_messages
+                    .set((opened.offset, opened.offset + opened.len));
+            }
+            content_type::APPLICATION_DATA => {
+                if self.state.get() == State::Connected {
+                    let (dst_addr, dst_port) = self.rx_dst.get();
+                    self.recv_client.map(|client| {
+                        client.receive(
+                            self.peer_addr.get(),
+                            dst_addr,
+                            self.peer_port.get(),
+                            dst_port,
+                            plaintext,
+                        )
+                    });
+                }
+            }
+            content_type::ALERT => {
+                if plaintext.get(1) == Some(&alert::CLOSE_NOTIFY)
+                    || plaintext.first() == Some(&alert::FATAL)
+                {
+                    self.fail(ErrorCode::FAIL);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn receive_handshake(&self, message: &Handshake, raw: &[u8]) {
+        let expected = self.recv_message_seq.get();
+        let step = self.step.get();
+        match (message.msg_type, step) {
+            (handshake_type::CLIENT_HELLO, Step::ClientHello)
+                if self.state.get() == State::Listening =>
+            {
+                self.receive_client_hello(message, raw)
+            }
+            (handshake_type::HELLO_VERIFY_REQUEST, Step::ServerHello) => {
+                match parse_hello_verify_request(message.body) {
+                    Some(cookie) => {
+                        let mut stored = [0; MAX_COOKIE_LEN];
+                        stored[..cookie.len()].copy_from_slice(cookie);
+                        self.cookie.set(stored);
+                        self.cookie_len.set(cookie.len());
+                        self.recv_message_seq.set(message.message_seq + 1);
+                        self.send_client_hello();
+                    }
+                    None => self.abort(alert::ILLEGAL_PARAMETER),
+                }
+            }
+            (handshake_type::SERVER_HELLO, Step::ServerHello) => {
+                match ServerHello::parse(message.body) {
+                    Some(hello)
+                        if hello.version == DTLS_1_2
+                            && hello.cipher_suite == TLS_PSK_WITH_AES_128_CCM_8
+                            && hello.compression == COMPRESSION_NULL =>
+                    {
+                        let mut random = [0; RANDOM_LEN];
+                        random.copy_from_slice(hello.random);
+                        self.server_random.set(random);
+                        self.recv_message_seq.set(message.message_seq + 1);
+                        self.step.set(Step::ServerHelloDone);
+                        self.append_or_fail(raw);
+                    }
+                    _ => self.abort(alert::HANDSHAKE_FAILURE),
+                }
+            }
+            (handshake_type::SERVER_KEY_EXCHANGE, Step::ServerHelloDone)
+                if message.message_seq == expected =>
+            {
+                // The identity hint is not used
+                self.recv_message_seq.set(expected + 1);
+                self.append_or_fail(raw);
+            }
+            (handshake_type::SERVER_HELLO_DONE, Step::ServerHelloDone)
+                if message.message_seq == expected =>
+            {
+                self.recv_message_seq.set(expected + 1);
+                let _ = self.alarm.disarm();
+                if self.append_or_fail(raw) {
+                    self.derive_master_secret();
+                }
+            }
+            (handshake_type::CLIENT_KEY_EXCHANGE, Step::ClientKeyExchange)
+                if message.message_seq == expected =>
+            {
+                let identity = self.identity.get();
+                match parse_client_key_exchange(message.body) {
+                    Some(received) if received == &identity[..self.identity_len.get()] => {
+                        self.recv_message_seq.set(expected + 1);
+                        let _ = self.alarm.disarm();
+                        if self.append_or_fail(raw) {
+                            self.derive_master_secret();
+                        }
+                    }
+                    _ => self.abort(alert::UNKNOWN_PSK_IDENTITY),
+                }
+            }
+            (handshake_type::FINISHED, Step::Finished)
+                if message.message_seq == expected && self.rx_protected.get() =>
+            {
+                if message.body.len() != VERIFY_DATA_LEN {
+                    self.fail(ErrorCode::FAIL);
+                    return;
+                }
+                let mut verify_data = [0; VERIFY_DATA_LEN];
+                verify_data.copy_from_slice(message.body);
+                self.peer_verify_data.set(verify_data);
+                self.recv_message_seq.set(expected + 1);
+                // The transcript is hashed before the Finished of the peer
+                // is added, for the Finished of the server
+                self.hash_transcript(Pending::PeerFinishedHash);
+                if self.pending.get() == Pending::PeerFinishedHash {
+                    self.append_or_fail(raw);
+                }
+            }
+            _ => {
+                if message.message_seq < expected
+                    && matches!(self.state.get(), State::Handshaking | State::Connected)
+                {
+                    self.rx_retransmission.set(true);
+                }
+            }
+        }
+    }
+
+    fn append_or_fail(&self, message: &[u8]) -> bool {
+        match self.append_transcript(message) {
+            Ok(()) => true,
+            Err(e) => {
+                self.fail(e);
+                false
+            }
+        }
+    }
+
+    /// Fail the handshake, and tell the peer with a fatal alert.
+    fn abort(&self, description: u8) {
+        self.send_alert(description);
+        self.fail(ErrorCode::FAIL);
+    }
+
+    fn receive_client_hello(&self, message: &Handshake, raw: &[u8]) {
+        let hello = match ClientHello::parse(message.body) {
+            Some(hello) => hello,
+            None => return,
+        };
+        if !hello.acceptable || hello.version != DTLS_1_2 {
+            self.send_alert(alert::HANDSHAKE_FAILURE);
+            return;
+        }
+        let mut random = [0; RANDOM_LEN];
+        random.copy_from_slice(hello.random);
+        self.client_random.set(random);
+        let mut cookie = [0; MAX_COOKIE_LEN];
+        cookie[..hello.cookie.len()].copy_from_slice(hello.cookie);
+        self.cookie.set(cookie);
+        self.cookie_len.set(hello.cookie.len());
+        self.hello_seq.set(message.message_seq);
+        // The ClientHello that is accepted starts the transcript
+        self.transcript_len.set(0);
+        if self.append_transcript(raw).is_err() {
+            return;
+        }
+
+        let (addr, port) = self.rx_src.get();
+        self.pending.set(Pending::Cookie);
+        if self
+            .crypto
+            .prf(
+                &self.cookie_secret.get(),
+                b"cookie",
+                &[&addr.0, &port.to_be_bytes(), hello.random],
+                COOKIE_LEN,
+            )
+            .is_err()
+        {
+            self.pending.set(Pending::None);
+        }
+    }
+
+    /// The cookie of the ClientHello was computed: accept the ClientHello
+    /// if it holds it, or ask the client to send it.
+    fn cookie_computed(&self, expected: &[u8]) {
+        let cookie = self.cookie.get();
+        if !equal(&cookie[..self.cookie_len.get()], expected) {
+            let seq = self.hello_seq.get();
+            self.send_record(content_type::HANDSHAKE, |buf| {
+                HandshakeWriter::new(buf, handshake_type::HELLO_VERIFY_REQUEST, seq)
+                    .u16(DTLS_1_2)
+                    .vec8(expected)
+                    .finish()
+            });
+            return;
+        }
+
+        let (addr, port) = self.rx_src.get();
+        self.peer_addr.set(addr);
+        self.peer_port.set(port);
+        self.state.set(State::Handshaking);
+        let seq = self.hello_seq.get();
+        self.recv_message_seq.set(seq + 1);
+        self.send_message_seq.set(seq);
+        if self.request_random(RandomUse::ServerRandom).is_err() {
+            self.fail(ErrorCode::FAIL);
+        }
+    }
+
+    fn send_client_hello(&self) {
+        self.start_flight();
+        // Only the ClientHello with the cookie is part of the transcript
+        self.transcript_len.set(0);
+        let random = self.client_random.get();
+        let cookie = self.cookie.get();
+        let cookie_len = self.cookie_len.get();
+        let result = self.append_handshake(handshake_type::CLIENT_HELLO, |writer| {
+            writer
+                .u16(DTLS_1_2)
+                .bytes(&random)
+                .vec8(&[])
+                .vec8(&cookie[..cookie_len])
+                .vec16(&TLS_PSK_WITH_AES_128_CCM_8.to_be_bytes())
+                .vec8(&[COMPRESSION_NULL]);
+        });
+        match result {
+            Ok(()) => self.send_flight(true),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Send the ServerHello and the ServerHelloDone. Without an identity
+    /// hint, the ServerKeyExchange is left out.
+    fn send_server_hello(&self) {
+        self.start_flight();
+        let random = self.server_random.get();
+        let result = self
+            .append_handshake(handshake_type::SERVER_HELLO, |writer| {
+                writer
+                    .u16(DTLS_1_2)
+                    .bytes(&random)
+                    .vec8(&[])
+                    .u16(TLS_PSK_WITH_AES_128_CCM_8)
+                    .u8(COMPRESSION_NULL);
+            })
+            .and_then(|()| self.append_handshake(handshake_type::SERVER_HELLO_DONE, |_| {}));
+        match result {
+            Ok(()) => {
+                self.step.set(Step::ClientKeyExchange);
+                self.send_flight(true);
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Start the last flight of the client, which is sent once its Finished
+    /// is sealed.
+    fn send_key_exchange(&self) {
+        self.start_flight();
+        let identity = self.identity.get();
+        let identity_len = self.identity_len.get();
+        let result = self
+            .append_handshake(handshake_type::CLIENT_KEY_EXCHANGE, |writer| {
+                writer.vec16(&identity[..identity_len]);
+            })
+            .and_then(|()| self.append_change_cipher_spec());
+        match result {
+            Ok(()) => self.hash_transcript(Pending::OwnFinishedHash),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn hash_transcript(&self, pending: Pending) {
+        let len = self.transcript_len.get();
+        self.pending.set(pending);
+        let result = self.transcript.map_or(Err(ErrorCode::FAIL), |transcript| {
+            self.crypto.hash(&transcript[..len])
+        });
+        if let Err(e) = result {
+            self.pending.set(Pending::None);
+            self.fail(e);
+        }
+    }
+
+    /// Append the Finished with `verify_data` to the flight, and seal it.
+    fn seal_finished(&self, verify_data: &[u8]) {
+        let flight = match self.flight.take() {
+            Some(flight) => flight,
+            None => return self.fail(ErrorCode::FAIL),
+        };
+        let offset = self.flight_len.get();
+        let seq = self.send_message_seq.get();
+        let m_off = offset + RECORD_HEADER_LEN + EXPLICIT_NONCE_LEN;
+        let written = flight
+            .get_mut(m_off..flight.len().saturating_sub(TAG_LEN))
+            .ok_or(ErrorCode::SIZE)
+            .and_then(|buf| {
+                HandshakeWriter::new(buf, handshake_type::FINISHED, seq)
+                    .bytes(verify_data)
+                    .finish()
+            })
+            .and_then(|len| {
+                self.append_transcript(&flight[m_off..m_off + len])
+                    .map(|()| len)
+            });
+        let len = match written {
+            Ok(len) => len,
+            Err(e) => {
+                self.flight.replace(flight);
+                return self.fail(e);
+            }
+        };
+        self.send_message_seq.set(seq + 1);
+        if let Err((e, flight)) = self.seal(
+            Pending::SealFinished,
+            flight,
+            offset,
+            content_type::HANDSHAKE,
+            len,
+        ) {
+            self.flight.replace(flight);
+            self.fail(e);
+        }
+    }
+
+    /// The flight with the Finished of the session is complete.
+    fn finished_sealed(&self) {
+        match self.role {
+            Role::Client => {
+                self.step.set(Step::ChangeCipherSpec);
+                self.send_flight(true);
+            }
+            Role::Server => {
+                // The client retransmits its flight if this one is lost
+                self.step.set(Step::Done);
+                self.state.set(State::Connected);
+                self.send_flight(false);
+                self.client.map(|client| client.handshake_done(Ok(())));
+            }
+        }
+    }
+
+    fn check_peer_finished(&self, expected: &[u8]) {
+        if !equal(&self.peer_verify_data.get(), expected) {
+            self.abort(alert::DECRYPT_ERROR);
+            return;
+        }
+        match self.role {
+            Role::Client => {
+                let _ = self.alarm.disarm();
+                self.step.set(Step::Done);
+                self.state.set(State::Connected);
+                self.client.map(|client| client.handshake_done(Ok(())));
+            }
+            Role::Server => {
+                self.start_flight();
+                match self.append_change_cipher_spec() {
+                    Ok(()) => self.hash_transcript(Pending::OwnFinishedHash),
+                    Err(e) => self.fail(e),
+                }
+            }
+        }
+    }
+}
+
+/// Compare `a` and `b` in constant time.
+fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    CryptoClient for DtlsSession<'a, C, D, R, A>
+{
+    fn derive_done(&self, result: Result<(), ErrorCode>) {
+        let pending = self.pending.replace(Pending::None);
+        let expected_state = match pending {
+            Pending::Cookie => State::Listening,
+            _ => State::Handshaking,
+        };
+        if self.state.get() == expected_state {
+            let output: [u8; MAX_PRF_LEN] = self.crypto.output();
+            let master_secret = self.master_secret.get();
+            match (result, pending) {
+                (Err(_), Pending::Cookie) => {}
+                (Err(e), _) => self.fail(e),
+                (Ok(()), Pending::Cookie) => self.cookie_computed(&output[..COOKIE_LEN]),
+                (Ok(()), Pending::MasterSecret) => {
+                    let mut master_secret = [0; MASTER_SECRET_LEN];
+                    master_secret.copy_from_slice(&output[..MASTER_SECRET_LEN]);
+                    self.master_secret.set(master_secret);
+                    self.derive(
+                        Pending::KeyBlock,
+                        &master_secret,
+                        b"key expansion",
+                        &[&self.server_random.get(), &self.client_random.get()],
+                        KEY_BLOCK_LEN,
+                    );
+                }
+                (Ok(()), Pending::KeyBlock) => {
+                    let mut key_block = [0; KEY_BLOCK_LEN];
+                    key_block.copy_from_slice(&output[..KEY_BLOCK_LEN]);
+                    self.key_block.set(key_block);
+                    match self.role {
+                        Role::Client => self.send_key_exchange(),
+                        Role::Server => self.step.set(Step::ChangeCipherSpec),
+                    }
+                }
+                (Ok(()), Pending::OwnFinishedHash) => self.derive(
+                    Pending::OwnFinishedPrf,
+                    &master_secret,
+                    self.finished_label(true),
+                    &[&output[..HASH_LEN]],
+                    VERIFY_DATA_LEN,
+                ),
+                (Ok(()), Pending::OwnFinishedPrf) => self.seal_finished(&output[..VERIFY_DATA_LEN]),
+                (Ok(()), Pending::PeerFinishedHash) => self.derive(
+                    Pending::PeerFinishedPrf,
+                    &master_secret,
+                    self.finished_label(false),
+                    &[&output[..HASH_LEN]],
+                    VERIFY_DATA_LEN,
+                ),
+                (Ok(()), Pending::PeerFinishedPrf) => {
+                    self.check_peer_finished(&output[..VERIFY_DATA_LEN])
+                }
+                (Ok(()), _) => {}
+            }
+        }
+        self.resume();
+    }
+
+    fn record_done(&self, buf: &'static mut [u8], result: Result<(), ErrorCode>) {
+        let pending = self.pending.replace(Pending::None);
+        match pending {
+            Pending::Open => {
+                let opened = self.opened.take();
+                if let (Some(opened), Ok(()), true) = (opened, result, self.rx_len.get() > 0) {
+                    let mut replay = self.replay.get();
+                    replay.mark(opened.seq);
+                    self.replay.set(replay);
+                    self.receive_protected(opened, buf);
+                }
+                self.rx_buffer.replace(buf);
+            }
+            Pending::SealFinished => {
+                let end = self.sealed(buf);
+                self.flight.replace(buf);
+                if self.state.get() == State::Handshaking {
+                    match result {
+                        Ok(()) => {
+                            self.flight_len.set(end);
+                            self.finished_sealed();
+                        }
+                        Err(e) => self.fail(e),
+                    }
+                }
+            }
+            Pending::SealData | Pending::SealCloseNotify => {
+                let end = self.sealed(buf);
+                let mut tx = LeasableMutableBuffer::new(buf);
+                let sending = if pending == Pending::SealData {
+                    Sending::Data
+                } else {
+                    Sending::CloseNotify
+                };
+                match result {
+                    Ok(()) => {
+                        tx.slice(..end);
+                        self.transmit(tx, sending);
+                    }
+                    Err(e) => {
+                        self.sending.set(sending);
+                        self.sent(Err(e), tx);
+                    }
+                }
+            }
+            _ => {}
+        }
+        self.resume();
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    rng::Client for DtlsSession<'a, C, D, R, A>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        let purpose = match self.pending.get() {
+            Pending::Random(purpose) => purpose,
+            _ => return rng::Continue::Done,
+        };
+        if error.is_err() {
+            self.pending.set(Pending::None);
+            if purpose == RandomUse::CookieSecret {
+                self.state.set(State::Idle);
+            } else {
+                self.fail(ErrorCode::FAIL);
+            }
+            return rng::Continue::Done;
+        }
+
+        let mut random = self.random.get();
+        let mut len = self.random_len.get();
+        while len < RANDOM_LEN {
+            match randomness.next() {
+                Some(word) => {
+                    random[len..len + 4].copy_from_slice(&word.to_le_bytes());
+                    len += 4;
+                }
+                None => break,
+            }
+        }
+        self.random.set(random);
+        self.random_len.set(len);
+        if len < RANDOM_LEN {
+            return rng::Continue::More;
+        }
+
+        self.pending.set(Pending::None);
+        match (purpose, self.state.get()) {
+            (RandomUse::CookieSecret, State::Listening) => {
+                let mut secret = [0; COOKIE_LEN];
+                secret.copy_from_slice(&random[..COOKIE_LEN]);
+                self.cookie_secret.set(secret);
+            }
+            (RandomUse::ClientRandom, State::Handshaking) => {
+                self.client_random.set(random);
+                self.send_client_hello();
+            }
+            (RandomUse::ServerRandom, State::Handshaking) => {
+                self.server_random.set(random);
+                self.send_server_hello();
+            }
+            _ => {}
+        }
+        self.resume();
+        rng::Continue::Done
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    time::AlarmClient for DtlsSession<'a, C, D, R, A>
+{
+    fn alarm(&self) {
+        if self.state.get() != State::Handshaking {
+            return;
+        }
+        let transmissions = self.transmissions.get() + 1;
+        if transmissions > MAX_TRANSMISSIONS {
+            self.fail(ErrorCode::NOACK);
+            return;
+        }
+        self.transmissions.set(transmissions);
+        self.timeout_ms
+            .set(u32::min(self.timeout_ms.get() * 2, MAX_TIMEOUT_MS));
+        self.retransmit();
+        self.arm_timer();
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    UDPRecvClient for DtlsSession<'a, C, D, R, A>
+{
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        dst_addr: IPAddr,
+        src_port: u16,
+        dst_port: u16,
+        payload: &[u8],
+    ) {
+        let accepted = match self.state.get() {
+            State::Idle => false,
+            State::Listening => true,
+            State::Handshaking | State::Connected => {
+                src_addr.0 == self.peer_addr.get().0 && src_port == self.peer_port.get()
+            }
+        };
+        // Datagrams that arrive while one is processed are dropped
+        if !accepted || payload.is_empty() || self.rx_len.get() > 0 {
+            return;
+        }
+        let copied = self.rx_buffer.map_or(false, |rx| {
+            rx.get_mut(..payload.len()).map_or(false, |dst| {
+                dst.copy_from_slice(payload);
+                true
+            })
+        });
+        if !copied {
+            return;
+        }
+        self.rx_len.set(payload.len());
+        self.rx_next.set(0);
+        self.rx_messages.set((0, 0));
+        self.rx_src.set((src_addr, src_port));
+        self.rx_dst.set((dst_addr, dst_port));
+        self.resume();
+    }
+}
+
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    UDPSendClient for DtlsSession<'a, C, D, R, A>
+{
+    fn send_done(&self, result: Result<(), ErrorCode>, dgram: LeasableMutableBuffer<'static, u8>) {
+        self.sent(result, dgram);
+    }
+}
+
+/// The layer above sends its datagrams to the peer through the session,
+/// once it is connected.
+impl<'a, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>, A: time::Alarm<'a>>
+    UDPSender<'a> for DtlsSession<'a, C, D, R, A>
+{
+    fn set_client(&self, client: &'a dyn UDPSendClient) {
+        self.send_client.set(client);
+    }
+
+    fn send_to(
+        &'a self,
+        dest: IPAddr,
+        dst_port: u16,
+        buf: LeasableMutableBuffer<'static, u8>,
+        _net_cap: &'static NetworkCapability,
+    ) -> Result<(), LeasableMutableBuffer<'static, u8>> {
+        if self.state.get() != State::Connected
+            || dest.0 != self.peer_addr.get().0
+            || dst_port != self.peer_port.get()
+            || self.app_buffer.is_some()
+            || buf.len() + PROTECTION_OVERHEAD > self.tx_capacity
+        {
+            return Err(buf);
+        }
+        self.app_buffer.replace(buf);
+        self.service();
+        Ok(())
+    }
+
+    /// Userspace datagrams are not sent through a session.
+    fn driver_send_to(
+        &'a self,
+        _dest: IPAddr,
+        _dst_port: u16,
+        _src_port: u16,
+        buf: LeasableMutableBuffer<'static, u8>,
+        _driver_send_cap: &dyn UdpDriverCapability,
+        _net_cap: &'static NetworkCapability,
+    ) -> Result<(), LeasableMutableBuffer<'static, u8>> {
+        Err(buf)
+    }
+
+    fn send(
+        &'a self,
+        dest: IPAddr,
+        udp_header: UDPHeader,
+        buf: LeasableMutableBuffer<'static, u8>,
+        net_cap: &'static NetworkCapability,
+    ) -> Result<(), LeasableMutableBuffer<'static, u8>> {
+        self.send_to(dest, udp_header.get_dst_port(), buf, net_cap)
+    }
+
+    fn get_binding(&self) -> Option<UdpPortBindingTx> {
+        self.sender.get_binding()
+    }
+
+    fn is_bound(&self) -> bool {
+        self.sender.is_bound()
+    }
+
+    fn set_binding(&self, binding: UdpPortBindingTx) -> Option<UdpPortBindingTx> {
+        self.sender.set_binding(binding)
+    }
+}
@@ -32,52 +32,63 @@ impl Bitmap {
     }
 
     // Sets bits from start_idx (inclusive) to end_idx (exclusive).
-    // Returns false if any bits set overlap with already set bits,
-    // true otherwise.
-
-    // Returns true if successfully set bits, returns false if the bits
-    // overlapped with already set bits
+    // Returns false if any bits set overlap with already set bits, or if the
+    // range does not fit in the bitmap, true otherwise.
     // Note that each bit represents a multiple of 8 bytes (as everything
     // must be in 8-byte groups), and thus we can store 8*8 = 64 "bytes" per
     // byte in the bitmap.
     pub fn set_bits(&mut self, start_idx: usize, end_idx: usize) -> bool {
-        if start_idx > end_idx {
+        if start_idx > end_idx || end_idx > BITMAP_SIZE * 8 {
             return false;
         }
-        let start_byte_idx = start_idx / 8;
-        let end_byte_idx = end_idx / 8;
-        let first = 0xff << (start_idx % 8);
-        let second = if end_idx % 8 == 0 {
-            0x00
-        } else {
-            0xff >> (8 - (end_idx % 8))
-        };
-        if start_byte_idx == end_byte_idx {
-            let result = (self.map[start_byte_idx] & (first & second)) == 0;
-            self.map[start_byte_idx] |= first & second;
-            result
-        } else {
-            let mut result = (self.map[start_byte_idx] & first) == 0;
-            result = result && ((self.map[end_byte_idx] & second) == 0);
-            self.map[start_byte_idx] |= first;
-            self.map[end_byte_idx] |= second;
-            // Set all bytes between start and end bytes.
-            for i in start_byte_idx + 1..end_byte_idx {
-                result = result && (self.map[i] == 0);
-                self.map[i] = 0xff;
-            }
-            result
+        let mut result = true;
+        for idx in start_idx..end_idx {
+            let mask = 1 << (idx % 8);
+            result = result && (self.map[idx / 8] & mask) == 0;
+            self.map[idx / 8] |= mask;
         }
+        result
     }
 
+    // Returns true if the first `total_length` bits are set.
     pub fn is_complete(&self, total_length: usize) -> bool {
-        let mut result = true;
-        for i in 0..total_length / 8 {
-            result = result && (self.map[i] == 0xff);
+        if total_length > BITMAP_SIZE * 8 {
+            return false;
+        }
+        let full_bytes = total_length / 8;
+        if self.map[..full_bytes].iter().any(|&byte| byte != 0xff) {
+            return false;
         }
         // Check last byte.
-        let mask = 0xff >> (8 - (total_length % 8));
-        result = result && (self.map[total_length / 8] == mask);
-        result
+        match total_length % 8 {
+            0 => true,
+            bits => self.map[full_bytes] == 0xff >> (8 - bits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_size_datagram() {
+        // 1280 bytes, the IPv6 minimum MTU, in 8-byte units.
+        let mut bitmap = Bitmap::new();
+        assert!(bitmap.set_bits(0, 100));
+        assert!(!bitmap.is_complete(160));
+        assert!(bitmap.set_bits(100, 160));
+        assert!(bitmap.is_complete(160));
+        assert!(!bitmap.set_bits(150, 161));
+    }
+
+    #[test]
+    fn overlapping_fragments() {
+        let mut bitmap = Bitmap::new();
+        assert!(bitmap.set_bits(3, 12));
+        assert!(!bitmap.set_bits(11, 13));
+        assert!(!bitmap.is_complete(13));
+        assert!(bitmap.set_bits(0, 3));
+        assert!(bitmap.is_complete(13));
     }
 }
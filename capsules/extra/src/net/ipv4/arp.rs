@@ -0,0 +1,158 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! ARP (RFC 826) for IPv4 over Ethernet, and a cache of the MAC addresses
+//! it resolved.
+//!
+//! The cache holds `ARP_CACHE_SIZE` entries, which are replaced in turn
+//! once it is full. Entries do not expire: hosts that change their MAC
+//! address announce it with an ARP request, which updates the cache.
+
+use core::cell::Cell;
+
+use super::ipv4::IPv4Addr;
+use kernel::hil::ethernet::MAC_ADDRESS_LEN;
+
+pub const ARP_PACKET_LEN: usize = 28;
+pub const ARP_CACHE_SIZE: usize = 8;
+
+const HARDWARE_ETHERNET: u16 = 1;
+
+pub mod operation {
+    pub const REQUEST: u16 = 1;
+    pub const REPLY: u16 = 2;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ArpPacket {
+    pub operation: u16,
+    pub sender_mac: [u8; MAC_ADDRESS_LEN],
+    pub sender_ip: IPv4Addr,
+    pub target_mac: [u8; MAC_ADDRESS_LEN],
+    pub target_ip: IPv4Addr,
+}
+
+impl ArpPacket {
+    /// Decode an ARP packet for IPv4 over Ethernet.
+    pub fn decode(buf: &[u8]) -> Option<ArpPacket> {
+        if buf.len() < ARP_PACKET_LEN
+            || u16::from_be_bytes([buf[0], buf[1]]) != HARDWARE_ETHERNET
+            || u16::from_be_bytes([buf[2], buf[3]]) != super::ipv4::ethertype::IPV4
+            || buf[4] as usize != MAC_ADDRESS_LEN
+            || buf[5] != 4
+        {
+            return None;
+        }
+        let mut packet = ArpPacket {
+            operation: u16::from_be_bytes([buf[6], buf[7]]),
+            sender_mac: [0; MAC_ADDRESS_LEN],
+            sender_ip: IPv4Addr([buf[14], buf[15], buf[16], buf[17]]),
+            target_mac: [0; MAC_ADDRESS_LEN],
+            target_ip: IPv4Addr([buf[24], buf[25], buf[26], buf[27]]),
+        };
+        packet.sender_mac.copy_from_slice(&buf[8..14]);
+        packet.target_mac.copy_from_slice(&buf[18..24]);
+        Some(packet)
+    }
+
+    /// Encode the packet into the first `ARP_PACKET_LEN` bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&HARDWARE_ETHERNET.to_be_bytes());
+        buf[2..4].copy_from_slice(&super::ipv4::ethertype::IPV4.to_be_bytes());
+        buf[4] = MAC_ADDRESS_LEN as u8;
+        buf[5] = 4;
+        buf[6..8].copy_from_slice(&self.operation.to_be_bytes());
+        buf[8..14].copy_from_slice(&self.sender_mac);
+        buf[14..18].copy_from_slice(&self.sender_ip.0);
+        buf[18..24].copy_from_slice(&self.target_mac);
+        buf[24..28].copy_from_slice(&self.target_ip.0);
+    }
+}
+
+#[derive(Copy, Clone)]
+struct ArpEntry {
+    ip: IPv4Addr,
+    mac: [u8; MAC_ADDRESS_LEN],
+}
+
+#[derive(Default)]
+pub struct ArpCache {
+    entries: [Cell<Option<ArpEntry>>; ARP_CACHE_SIZE],
+    /// The entry replaced next
+    next: Cell<usize>,
+}
+
+impl ArpCache {
+    pub fn lookup(&self, ip: IPv4Addr) -> Option<[u8; MAC_ADDRESS_LEN]> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.get())
+            .find(|entry| entry.ip == ip)
+            .map(|entry| entry.mac)
+    }
+
+    /// Add the MAC address of `ip`, or update it.
+    pub fn insert(&self, ip: IPv4Addr, mac: [u8; MAC_ADDRESS_LEN]) {
+        let entry = Some(ArpEntry { ip, mac });
+        match self
+            .entries
+            .iter()
+            .find(|e| e.get().map_or(false, |e| e.ip == ip))
+        {
+            Some(existing) => existing.set(entry),
+            None => {
+                let next = self.next.get();
+                self.entries[next].set(entry);
+                self.next.set((next + 1) % ARP_CACHE_SIZE);
+            }
+        }
+    }
+
+    pub fn clear(&self) {
+        self.entries.iter().for_each(|entry| entry.set(None));
+        self.next.set(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_round_trip() {
+        let packet = ArpPacket {
+            operation: operation::REQUEST,
+            sender_mac: [2, 0, 0, 0, 0, 1],
+            sender_ip: IPv4Addr([192, 168, 0, 2]),
+            target_mac: [0; MAC_ADDRESS_LEN],
+            target_ip: IPv4Addr([192, 168, 0, 1]),
+        };
+        let mut buf = [0; ARP_PACKET_LEN];
+        packet.encode(&mut buf);
+        assert_eq!(&buf[..8], &[0, 1, 8, 0, 6, 4, 0, 1]);
+        assert_eq!(ArpPacket::decode(&buf), Some(packet));
+        assert_eq!(ArpPacket::decode(&buf[..ARP_PACKET_LEN - 1]), None);
+    }
+
+    #[test]
+    fn cache_replacement() {
+        let cache = ArpCache::default();
+        for i in 0..ARP_CACHE_SIZE as u8 {
+            cache.insert(IPv4Addr([10, 0, 0, i]), [i; MAC_ADDRESS_LEN]);
+        }
+        cache.insert(IPv4Addr([10, 0, 0, 3]), [0xaa; MAC_ADDRESS_LEN]);
+        assert_eq!(
+            cache.lookup(IPv4Addr([10, 0, 0, 3])),
+            Some([0xaa; MAC_ADDRESS_LEN])
+        );
+
+        // The oldest entry is replaced
+        cache.insert(IPv4Addr([10, 0, 0, 100]), [0xbb; MAC_ADDRESS_LEN]);
+        assert_eq!(cache.lookup(IPv4Addr([10, 0, 0, 0])), None);
+        assert_eq!(
+            cache.lookup(IPv4Addr([10, 0, 0, 1])),
+            Some([1; MAC_ADDRESS_LEN])
+        );
+    }
+}
@@ -0,0 +1,572 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A DHCP client (RFC 2131) that configures an `IPv4Interface`.
+//!
+//! The client broadcasts a DHCPDISCOVER, requests the address of the first
+//! offer, and configures the interface with the address, subnet mask and
+//! router of the acknowledgment. Discovers are retransmitted with an
+//! exponential backoff from `RETRANSMIT_S` to `MAX_RETRANSMIT_S` seconds,
+//! and after `REQUEST_ATTEMPTS` unanswered requests the client starts over.
+//!
+//! Half way through the lease (T1), the client renews it with the server
+//! that granted it, and at seven eighths (T2) with any server. If the lease
+//! expires, or a server declines it, the address is removed from the
+//! interface and the client starts over. While the interface has no
+//! address, the client asks servers to broadcast their replies.
+//!
+//! Addresses are not probed with ARP before they are used.
+
+use core::cell::Cell;
+
+use super::interface::{IPv4Interface, UdpSocket, UdpSocketClient};
+use super::ipv4::{IPv4Addr, IPv4Config};
+
+use kernel::hil::ethernet::{EthernetAdapter, MAC_ADDRESS_LEN};
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+/// Length of the message buffer: the shortest BOOTP message, which fits
+/// the options the client sends.
+pub const DHCP_MESSAGE_LEN: usize = 300;
+
+pub const RETRANSMIT_S: u32 = 4;
+pub const MAX_RETRANSMIT_S: u32 = 64;
+pub const REQUEST_ATTEMPTS: u8 = 4;
+/// Seconds between requests while renewing or rebinding.
+pub const RENEW_RETRY_S: u32 = 60;
+
+/// Length of the fixed fields of a message.
+const BOOTP_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+const HARDWARE_ETHERNET: u8 = 1;
+const FLAG_BROADCAST: u16 = 0x8000;
+const INFINITE_LEASE: u32 = 0xffff_ffff;
+/// Longer waits are split into alarms of this many seconds.
+const MAX_ALARM_S: u32 = 60;
+
+pub mod message_type {
+    pub const DISCOVER: u8 = 1;
+    pub const OFFER: u8 = 2;
+    pub const REQUEST: u8 = 3;
+    pub const DECLINE: u8 = 4;
+    pub const ACK: u8 = 5;
+    pub const NAK: u8 = 6;
+    pub const RELEASE: u8 = 7;
+}
+
+/// Option codes (RFC 2132).
+pub mod option {
+    pub const PAD: u8 = 0;
+    pub const SUBNET_MASK: u8 = 1;
+    pub const ROUTER: u8 = 3;
+    pub const REQUESTED_ADDRESS: u8 = 50;
+    pub const LEASE_TIME: u8 = 51;
+    pub const MESSAGE_TYPE: u8 = 53;
+    pub const SERVER_ID: u8 = 54;
+    pub const PARAMETER_LIST: u8 = 55;
+    pub const END: u8 = 255;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Stopped,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+impl State {
+    fn has_lease(self) -> bool {
+        matches!(self, State::Bound | State::Renewing | State::Rebinding)
+    }
+}
+
+pub trait DhcpClient {
+    /// The interface was configured with a lease of `lease_s` seconds, or
+    /// the lease was renewed.
+    fn bound(&self, config: IPv4Config, lease_s: u32);
+
+    /// The lease expired or was declined, and the address was removed.
+    fn lost(&self);
+}
+
+/// The fields of a server message that are used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Reply {
+    pub message_type: u8,
+    pub your_addr: IPv4Addr,
+    pub server_id: Option<IPv4Addr>,
+    pub subnet_mask: Option<IPv4Addr>,
+    pub router: Option<IPv4Addr>,
+    pub lease_s: Option<u32>,
+}
+
+fn ipv4_addr(bytes: &[u8]) -> IPv4Addr {
+    IPv4Addr([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+impl Reply {
+    /// Parse a server message of the transaction `xid` to the client with
+    /// `mac`.
+    pub fn parse(buf: &[u8], xid: u32, mac: &[u8; MAC_ADDRESS_LEN]) -> Option<Reply> {
+        if buf.len() < BOOTP_LEN + MAGIC_COOKIE.len()
+            || buf[0] != BOOTREPLY
+            || buf[1] != HARDWARE_ETHERNET
+            || buf[2] as usize != MAC_ADDRESS_LEN
+            || buf[4..8] != xid.to_be_bytes()
+            || buf[28..34] != mac[..]
+            || buf[BOOTP_LEN..BOOTP_LEN + 4] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let mut reply = Reply {
+            message_type: 0,
+            your_addr: ipv4_addr(&buf[16..20]),
+            server_id: None,
+            subnet_mask: None,
+            router: None,
+            lease_s: None,
+        };
+        let mut options = &buf[BOOTP_LEN + 4..];
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                option::PAD => {
+                    options = rest;
+                    continue;
+                }
+                option::END => break,
+                _ => {}
+            }
+            let (&len, rest) = rest.split_first()?;
+            let data = rest.get(..len as usize)?;
+            match (code, len) {
+                (option::MESSAGE_TYPE, 1) => reply.message_type = data[0],
+                (option::SERVER_ID, 4) => reply.server_id = Some(ipv4_addr(data)),
+                (option::SUBNET_MASK, 4) => reply.subnet_mask = Some(ipv4_addr(data)),
+                // The first of the routers
+                (option::ROUTER, 4..) => reply.router = Some(ipv4_addr(data)),
+                (option::LEASE_TIME, 4) => {
+                    reply.lease_s = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]))
+                }
+                _ => {}
+            }
+            options = &rest[len as usize..];
+        }
+        if reply.message_type == 0 {
+            None
+        } else {
+            Some(reply)
+        }
+    }
+}
+
+/// A client message.
+pub struct Request {
+    pub message_type: u8,
+    pub xid: u32,
+    pub mac: [u8; MAC_ADDRESS_LEN],
+    /// The address of the client while it has a lease; replies are
+    /// broadcast while it is unspecified
+    pub client_addr: IPv4Addr,
+    pub requested_addr: Option<IPv4Addr>,
+    pub server_id: Option<IPv4Addr>,
+}
+
+impl Request {
+    /// Encode the message into `buf`, which has at least
+    /// `DHCP_MESSAGE_LEN` bytes, and return its length.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        let buf = &mut buf[..DHCP_MESSAGE_LEN];
+        buf.fill(0);
+        buf[0] = BOOTREQUEST;
+        buf[1] = HARDWARE_ETHERNET;
+        buf[2] = MAC_ADDRESS_LEN as u8;
+        buf[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        if self.client_addr.is_unspecified() {
+            buf[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        }
+        buf[12..16].copy_from_slice(&self.client_addr.0);
+        buf[28..34].copy_from_slice(&self.mac);
+        buf[BOOTP_LEN..BOOTP_LEN + 4].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut pos = BOOTP_LEN + 4;
+        let mut put = |code: u8, data: &[u8]| {
+            buf[pos] = code;
+            buf[pos + 1] = data.len() as u8;
+            buf[pos + 2..pos + 2 + data.len()].copy_from_slice(data);
+            pos += 2 + data.len();
+        };
+        put(option::MESSAGE_TYPE, &[self.message_type]);
+        if let Some(requested) = self.requested_addr {
+            put(option::REQUESTED_ADDRESS, &requested.0);
+        }
+        if let Some(server_id) = self.server_id {
+            put(option::SERVER_ID, &server_id.0);
+        }
+        if self.message_type != message_type::RELEASE {
+            put(
+                option::PARAMETER_LIST,
+                &[option::SUBNET_MASK, option::ROUTER, option::LEASE_TIME],
+            );
+        }
+        buf[pos] = option::END;
+        DHCP_MESSAGE_LEN
+    }
+}
+
+pub struct Dhcp<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> {
+    iface: &'a IPv4Interface<'a, E, A>,
+    socket: &'a UdpSocket<'a, E, A>,
+    alarm: &'a A,
+    tx_buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    xid: Cell<u32>,
+    /// The offered or leased address
+    address: Cell<IPv4Addr>,
+    server: Cell<IPv4Addr>,
+    lease_s: Cell<u32>,
+    /// Seconds since the lease was granted
+    elapsed_s: Cell<u32>,
+    /// Seconds until the timeout, and the seconds the alarm is set for
+    wait_s: Cell<u32>,
+    alarm_s: Cell<u32>,
+    retransmit_s: Cell<u32>,
+    attempts: Cell<u8>,
+    client: OptionalCell<&'a dyn DhcpClient>,
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> Dhcp<'a, E, A> {
+    /// `socket` should be bound to `DHCP_CLIENT_PORT`, and `tx_buffer` have
+    /// `DHCP_MESSAGE_LEN` bytes.
+    pub fn new(
+        iface: &'a IPv4Interface<'a, E, A>,
+        socket: &'a UdpSocket<'a, E, A>,
+        alarm: &'a A,
+        tx_buffer: &'static mut [u8],
+    ) -> Dhcp<'a, E, A> {
+        Dhcp {
+            iface,
+            socket,
+            alarm,
+            tx_buffer: TakeCell::new(tx_buffer),
+            state: Cell::new(State::Stopped),
+            xid: Cell::new(0),
+            address: Cell::new(IPv4Addr::UNSPECIFIED),
+            server: Cell::new(IPv4Addr::UNSPECIFIED),
+            lease_s: Cell::new(0),
+            elapsed_s: Cell::new(0),
+            wait_s: Cell::new(0),
+            alarm_s: Cell::new(0),
+            retransmit_s: Cell::new(RETRANSMIT_S),
+            attempts: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn DhcpClient) {
+        self.client.set(client);
+    }
+
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+
+    /// Start acquiring a lease.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The client is discovering servers.
+    /// - `ALREADY`: The client was started.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Stopped {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.discover();
+        Ok(())
+    }
+
+    /// Stop the client, and release its lease. The address is removed from
+    /// the interface.
+    pub fn stop(&self) -> Result<(), ErrorCode> {
+        let state = self.state.get();
+        if state == State::Stopped {
+            return Err(ErrorCode::ALREADY);
+        }
+        if state.has_lease() {
+            self.send(message_type::RELEASE, self.server.get());
+            self.iface.set_config(None);
+        }
+        self.state.set(State::Stopped);
+        let _ = self.alarm.disarm();
+        Ok(())
+    }
+
+    fn discover(&self) {
+        // The transaction id only needs to differ between clients and
+        // attempts, which the MAC address and the time do
+        let mac = self.iface.mac_address();
+        let xid = u32::from_be_bytes([mac[2], mac[3], mac[4], mac[5]])
+            ^ self.alarm.now().into_u32().rotate_left(16);
+        self.xid.set(xid);
+        self.state.set(State::Selecting);
+        self.address.set(IPv4Addr::UNSPECIFIED);
+        self.retransmit_s.set(RETRANSMIT_S);
+        self.send(message_type::DISCOVER, IPv4Addr::BROADCAST);
+        self.wait(RETRANSMIT_S);
+    }
+
+    fn request(&self) {
+        let dest = match self.state.get() {
+            State::Renewing => self.server.get(),
+            _ => IPv4Addr::BROADCAST,
+        };
+        self.send(message_type::REQUEST, dest);
+    }
+
+    fn send(&self, message_type: u8, dest: IPv4Addr) {
+        let state = self.state.get();
+        // Only a request in reply to an offer names the server and address;
+        // renewals carry the address as the client address
+        let (client_addr, requested_addr, server_id) = match (message_type, state) {
+            (message_type::REQUEST, State::Requesting) => (
+                IPv4Addr::UNSPECIFIED,
+                Some(self.address.get()),
+                Some(self.server.get()),
+            ),
+            (message_type::RELEASE, _) => (self.address.get(), None, Some(self.server.get())),
+            _ if state.has_lease() => (self.address.get(), None, None),
+            _ => (IPv4Addr::UNSPECIFIED, None, None),
+        };
+        let request = Request {
+            message_type,
+            xid: self.xid.get(),
+            mac: self.iface.mac_address(),
+            client_addr,
+            requested_addr,
+            server_id,
+        };
+        // Without the buffer, a message is being sent; the timeout
+        // retransmits this one
+        if let Some(buf) = self.tx_buffer.take() {
+            let len = request.encode(buf);
+            if let Err((_, buf)) = self.socket.send_to(dest, DHCP_SERVER_PORT, buf, len) {
+                self.tx_buffer.replace(buf);
+            }
+        }
+    }
+
+    /// Time out in `seconds`.
+    fn wait(&self, seconds: u32) {
+        self.wait_s.set(seconds);
+        self.arm();
+    }
+
+    fn arm(&self) {
+        let seconds = u32::min(self.wait_s.get(), MAX_ALARM_S);
+        self.alarm_s.set(seconds);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_seconds(seconds));
+    }
+
+    fn timeout(&self) {
+        match self.state.get() {
+            State::Stopped => {}
+            State::Selecting => {
+                let retransmit_s = u32::min(self.retransmit_s.get() * 2, MAX_RETRANSMIT_S);
+                self.retransmit_s.set(retransmit_s);
+                self.send(message_type::DISCOVER, IPv4Addr::BROADCAST);
+                self.wait(retransmit_s);
+            }
+            State::Requesting => {
+                self.attempts.set(self.attempts.get() + 1);
+                if self.attempts.get() >= REQUEST_ATTEMPTS {
+                    self.discover();
+                } else {
+                    self.request();
+                    self.wait(RETRANSMIT_S);
+                }
+            }
+            State::Bound | State::Renewing | State::Rebinding => self.lease_timeout(),
+        }
+    }
+
+    fn lease_timeout(&self) {
+        let elapsed = self.elapsed_s.get();
+        let lease = self.lease_s.get();
+        let t1 = lease / 2;
+        let t2 = lease - lease / 8;
+        if elapsed >= lease {
+            self.iface.set_config(None);
+            self.client.map(|client| client.lost());
+            self.discover();
+        } else if elapsed >= t2 {
+            self.state.set(State::Rebinding);
+            self.request();
+            self.wait(u32::min(RENEW_RETRY_S, lease - elapsed));
+        } else if elapsed >= t1 {
+            self.state.set(State::Renewing);
+            self.request();
+            self.wait(u32::min(RENEW_RETRY_S, t2 - elapsed));
+        } else {
+            self.wait(t1 - elapsed);
+        }
+    }
+
+    fn acknowledged(&self, reply: &Reply) {
+        let config = IPv4Config {
+            address: reply.your_addr,
+            netmask: reply.subnet_mask.unwrap_or(IPv4Addr([255, 255, 255, 0])),
+            gateway: reply.router.unwrap_or(IPv4Addr::UNSPECIFIED),
+        };
+        let lease = reply.lease_s.unwrap_or(INFINITE_LEASE);
+        self.address.set(reply.your_addr);
+        if let Some(server) = reply.server_id {
+            self.server.set(server);
+        }
+        self.lease_s.set(lease);
+        self.elapsed_s.set(0);
+        self.state.set(State::Bound);
+        self.iface.set_config(Some(config));
+        if lease == INFINITE_LEASE {
+            let _ = self.alarm.disarm();
+        } else {
+            self.wait(lease / 2);
+        }
+        self.client.map(|client| client.bound(config, lease));
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> UdpSocketClient for Dhcp<'a, E, A> {
+    fn send_done(&self, buf: &'static mut [u8], _result: Result<(), ErrorCode>) {
+        self.tx_buffer.replace(buf);
+    }
+
+    fn received(&self, _src_addr: IPv4Addr, src_port: u16, _dst_port: u16, payload: &[u8]) {
+        if src_port != DHCP_SERVER_PORT {
+            return;
+        }
+        let reply = match Reply::parse(payload, self.xid.get(), &self.iface.mac_address()) {
+            Some(reply) => reply,
+            None => return,
+        };
+        let state = self.state.get();
+        match reply.message_type {
+            message_type::OFFER if state == State::Selecting => {
+                let server = match reply.server_id {
+                    Some(server) => server,
+                    None => return,
+                };
+                self.address.set(reply.your_addr);
+                self.server.set(server);
+                self.state.set(State::Requesting);
+                self.attempts.set(0);
+                self.request();
+                self.wait(RETRANSMIT_S);
+            }
+            message_type::ACK if state == State::Requesting || state.has_lease() => {
+                self.acknowledged(&reply);
+            }
+            message_type::NAK if state == State::Requesting || state.has_lease() => {
+                if state.has_lease() {
+                    self.iface.set_config(None);
+                    self.client.map(|client| client.lost());
+                }
+                self.discover();
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> time::AlarmClient for Dhcp<'a, E, A> {
+    fn alarm(&self) {
+        let seconds = self.alarm_s.get();
+        if self.state.get().has_lease() {
+            self.elapsed_s
+                .set(self.elapsed_s.get().saturating_add(seconds));
+        }
+        self.wait_s.set(self.wait_s.get() - seconds);
+        if self.wait_s.get() > 0 {
+            self.arm();
+        } else {
+            self.timeout();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; MAC_ADDRESS_LEN] = [2, 0, 0, 0, 0, 7];
+
+    #[test]
+    fn request_and_reply() {
+        let mut buf = [0; DHCP_MESSAGE_LEN];
+        let len = Request {
+            message_type: message_type::REQUEST,
+            xid: 0x12345678,
+            mac: MAC,
+            client_addr: IPv4Addr::UNSPECIFIED,
+            requested_addr: Some(IPv4Addr([10, 0, 0, 20])),
+            server_id: Some(IPv4Addr([10, 0, 0, 1])),
+        }
+        .encode(&mut buf);
+        assert_eq!(len, DHCP_MESSAGE_LEN);
+        assert_eq!(&buf[..4], &[BOOTREQUEST, 1, 6, 0]);
+        assert_eq!(&buf[10..12], &[0x80, 0]);
+        assert_eq!(
+            &buf[240..243],
+            &[option::MESSAGE_TYPE, 1, message_type::REQUEST]
+        );
+        assert_eq!(
+            &buf[243..249],
+            &[option::REQUESTED_ADDRESS, 4, 10, 0, 0, 20]
+        );
+
+        // Turn the request into an acknowledgment by the server
+        buf[0] = BOOTREPLY;
+        buf[16..20].copy_from_slice(&[10, 0, 0, 20]);
+        buf[242] = message_type::ACK;
+        let options = [
+            option::PAD,
+            option::LEASE_TIME,
+            4,
+            0,
+            0,
+            0x0e,
+            0x10,
+            option::ROUTER,
+            8,
+            10,
+            0,
+            0,
+            1,
+            10,
+            0,
+            0,
+            2,
+            option::END,
+        ];
+        buf[255..255 + options.len()].copy_from_slice(&options);
+        let reply = Reply::parse(&buf, 0x12345678, &MAC).unwrap();
+        assert_eq!(reply.message_type, message_type::ACK);
+        assert_eq!(reply.your_addr, IPv4Addr([10, 0, 0, 20]));
+        assert_eq!(reply.server_id, Some(IPv4Addr([10, 0, 0, 1])));
+        assert_eq!(reply.router, Some(IPv4Addr([10, 0, 0, 1])));
+        assert_eq!(reply.lease_s, Some(3600));
+        assert_eq!(reply.subnet_mask, None);
+
+        // Another transaction
+        assert_eq!(Reply::parse(&buf, 0x12345679, &MAC), None);
+    }
+}
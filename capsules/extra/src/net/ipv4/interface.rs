@@ -0,0 +1,679 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! An IPv4 interface on an Ethernet adapter, with UDP sockets.
+//!
+//! The interface resolves the MAC addresses of its neighbors with ARP,
+//! answers ARP requests and ICMP echo requests (pings) for its address, and
+//! sends and receives the UDP datagrams of its sockets and the segments of
+//! the TCP layer (`TcpNetwork`). It has two frames: the data frame holds
+//! one datagram or segment at a time, and the control frame holds ARP
+//! packets and echo replies. A datagram waits in the data frame until the
+//! MAC address of its next hop is known; after `ARP_ATTEMPTS` unanswered
+//! ARP requests, its send fails. Replies that find the control frame in use
+//! are not sent.
+//!
+//! Until an address is configured, with `set_config()` or by DHCP, sockets
+//! can only send broadcasts, from the unspecified address, and UDP
+//! datagrams are received for any address.
+
+use core::cell::Cell;
+
+use super::arp::{self, ArpCache, ArpPacket, ARP_PACKET_LEN};
+use super::ipv4::{
+    checksum, encode_ethernet_header, ethertype, protocol, transport_checksum, udp_checksum,
+    IPv4Addr, IPv4Config, IPv4Header, BROADCAST_MAC, ETHERNET_HEADER_LEN, IPV4_HEADER_LEN,
+    UDP_HEADER_LEN,
+};
+use crate::net::tcp::segment::{CHECKSUM_OFFSET, TCP_HEADER_LEN};
+use crate::net::tcp::{TcpNetwork, TcpNetworkClient};
+
+use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::ethernet::{EthernetAdapter, EthernetAdapterClient, MAC_ADDRESS_LEN};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub const ARP_TIMEOUT_MS: u32 = 1000;
+pub const ARP_ATTEMPTS: u8 = 3;
+/// Suggested length of the control frame. Echo requests that do not fit
+/// are not answered.
+pub const CONTROL_FRAME_LEN: usize = 128;
+
+/// Frames shorter than this are padded.
+const MIN_FRAME_LEN: usize = 60;
+const ICMP_ECHO_REPLY: u8 = 0;
+const ICMP_ECHO_REQUEST: u8 = 8;
+
+pub trait UdpSocketClient {
+    /// The datagram passed to `send_to()` was sent, or failed to be sent.
+    fn send_done(&self, buf: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A datagram to the port of the socket. `payload` is only valid for
+    /// the duration of the call.
+    fn received(&self, src_addr: IPv4Addr, src_port: u16, dst_port: u16, payload: &[u8]);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Frame {
+    Data,
+    Control,
+}
+
+pub struct IPv4Interface<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> {
+    adapter: &'a E,
+    alarm: &'a A,
+    config: OptionalCell<IPv4Config>,
+    arp_cache: ArpCache,
+    sockets: List<'a, UdpSocket<'a, E, A>>,
+    data_frame: TakeCell<'static, [u8]>,
+    data_frame_len: usize,
+    /// Length of the datagram in the data frame
+    data_len: Cell<usize>,
+    /// The datagram has the MAC address of its next hop
+    data_ready: Cell<bool>,
+    /// The socket whose datagram is in the data frame
+    sender: OptionalCell<&'a UdpSocket<'a, E, A>>,
+    tcp: OptionalCell<&'a dyn TcpNetworkClient>,
+    /// Destination and length of the TCP segment to send
+    tcp_pending: Cell<Option<(IPv4Addr, usize)>>,
+    tcp_segment: TakeCell<'static, [u8]>,
+    /// The TCP segment is in the data frame
+    tcp_sending: Cell<bool>,
+    control_frame: TakeCell<'static, [u8]>,
+    control_frame_len: usize,
+    /// Length of the frame in the control frame waiting to be sent
+    control_len: Cell<Option<usize>>,
+    in_flight: Cell<Option<Frame>>,
+    /// The next hop of the datagram, while its MAC address is resolved
+    arp_target: Cell<Option<IPv4Addr>>,
+    arp_attempts: Cell<u8>,
+    /// An ARP request waits for the control frame
+    arp_request_due: Cell<bool>,
+    ident: Cell<u16>,
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> IPv4Interface<'a, E, A> {
+    /// `data_frame` should have `MAX_FRAME_LEN` bytes to send the longest
+    /// datagrams, and `control_frame` `CONTROL_FRAME_LEN`.
+    pub fn new(
+        adapter: &'a E,
+        alarm: &'a A,
+        data_frame: &'static mut [u8],
+        control_frame: &'static mut [u8],
+    ) -> IPv4Interface<'a, E, A> {
+        IPv4Interface {
+            adapter,
+            alarm,
+            config: OptionalCell::empty(),
+            arp_cache: ArpCache::default(),
+            sockets: List::new(),
+            data_frame_len: data_frame.len(),
+            data_frame: TakeCell::new(data_frame),
+            data_len: Cell::new(0),
+            data_ready: Cell::new(false),
+            sender: OptionalCell::empty(),
+            tcp: OptionalCell::empty(),
+            tcp_pending: Cell::new(None),
+            tcp_segment: TakeCell::empty(),
+            tcp_sending: Cell::new(false),
+            control_frame_len: control_frame.len(),
+            control_frame: TakeCell::new(control_frame),
+            control_len: Cell::new(None),
+            in_flight: Cell::new(None),
+            arp_target: Cell::new(None),
+            arp_attempts: Cell::new(0),
+            arp_request_due: Cell::new(false),
+            ident: Cell::new(0),
+        }
+    }
+
+    pub fn add_socket(&self, socket: &'a UdpSocket<'a, E, A>) {
+        self.sockets.push_tail(socket);
+    }
+
+    /// Configure the address of the interface, or remove it with `None`.
+    pub fn set_config(&self, config: Option<IPv4Config>) {
+        self.config.insert(config);
+    }
+
+    pub fn config(&self) -> Option<IPv4Config> {
+        self.config.extract()
+    }
+
+    pub fn mac_address(&self) -> [u8; MAC_ADDRESS_LEN] {
+        self.adapter.mac_address()
+    }
+
+    /// The longest UDP payload the data frame holds.
+    pub fn max_payload_len(&self) -> usize {
+        self.data_frame_len
+            .saturating_sub(ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + UDP_HEADER_LEN)
+    }
+
+    fn next_ident(&self) -> u16 {
+        let ident = self.ident.get();
+        self.ident.set(ident.wrapping_add(1));
+        ident
+    }
+
+    /// Put the TCP segment, or the datagram of the next socket with one,
+    /// into the data frame.
+    fn send_next(&self) {
+        if self.sender.is_some() || self.tcp_sending.get() {
+            return;
+        }
+        if let Some((dest, len)) = self.tcp_pending.take() {
+            self.tcp_sending.set(true);
+            self.prepare(dest, protocol::TCP, len, |tcp, src| {
+                self.tcp_segment
+                    .map(|segment| tcp.copy_from_slice(&segment[..len]));
+                tcp[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 2].copy_from_slice(&[0, 0]);
+                let sum = transport_checksum(protocol::TCP, src, dest, tcp);
+                tcp[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 2].copy_from_slice(&sum.to_be_bytes());
+            });
+            return;
+        }
+        let socket = match self.sockets.iter().find(|s| s.pending.get().is_some()) {
+            Some(socket) => socket,
+            None => return,
+        };
+        let (dest, dst_port, len) = match socket.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        self.sender.set(socket);
+
+        let udp_len = UDP_HEADER_LEN + len;
+        self.prepare(dest, protocol::UDP, udp_len, |udp, src| {
+            udp[0..2].copy_from_slice(&socket.port.get().to_be_bytes());
+            udp[2..4].copy_from_slice(&dst_port.to_be_bytes());
+            udp[4..6].copy_from_slice(&(udp_len as u16).to_be_bytes());
+            udp[6..8].copy_from_slice(&[0, 0]);
+            socket
+                .buffer
+                .map(|buf| udp[UDP_HEADER_LEN..].copy_from_slice(&buf[..len]));
+            // A checksum of 0 means none
+            let sum = match udp_checksum(src, dest, udp) {
+                0 => 0xffff,
+                sum => sum,
+            };
+            udp[6..8].copy_from_slice(&sum.to_be_bytes());
+        });
+    }
+
+    /// Write a packet to `dest` into the data frame, with `fill` writing its
+    /// `len` bytes of `protocol` given the source address, and resolve the
+    /// MAC address of its next hop.
+    fn prepare<F: FnOnce(&mut [u8], IPv4Addr)>(
+        &self,
+        dest: IPv4Addr,
+        protocol: u8,
+        len: usize,
+        fill: F,
+    ) {
+        let config = self.config.extract();
+        let src = match config {
+            Some(config) => config.address,
+            None if dest.is_broadcast() => IPv4Addr::UNSPECIFIED,
+            None => {
+                self.complete_send(Err(ErrorCode::FAIL));
+                return;
+            }
+        };
+        let mac = self.adapter.mac_address();
+        let ident = self.next_ident();
+        let frame_len = self.data_frame.map_or(0, |frame| {
+            let start = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN;
+            encode_ethernet_header(frame, BROADCAST_MAC, mac, ethertype::IPV4);
+            IPv4Header::new(protocol, src, dest, ident, len)
+                .encode(&mut frame[ETHERNET_HEADER_LEN..]);
+            fill(&mut frame[start..start + len], src);
+            let frame_len = usize::max(start + len, MIN_FRAME_LEN);
+            frame[start + len..frame_len].fill(0);
+            frame_len
+        });
+        self.data_len.set(frame_len);
+
+        if dest.is_broadcast() || config.map_or(false, |config| config.is_broadcast(dest)) {
+            self.resolved(BROADCAST_MAC);
+        } else if dest.is_multicast() {
+            self.resolved([0x01, 0x00, 0x5e, dest.0[1] & 0x7f, dest.0[2], dest.0[3]]);
+        } else {
+            let next_hop = config.map_or(dest, |config| config.next_hop(dest));
+            match self.arp_cache.lookup(next_hop) {
+                Some(mac) => self.resolved(mac),
+                None => {
+                    self.arp_target.set(Some(next_hop));
+                    self.arp_attempts.set(0);
+                    self.send_arp_request();
+                }
+            }
+        }
+    }
+
+    /// The MAC address of the next hop of the datagram is known.
+    fn resolved(&self, mac: [u8; MAC_ADDRESS_LEN]) {
+        self.data_frame
+            .map(|frame| frame[0..MAC_ADDRESS_LEN].copy_from_slice(&mac));
+        self.data_ready.set(true);
+        self.transmit_next();
+    }
+
+    fn complete_send(&self, result: Result<(), ErrorCode>) {
+        self.data_ready.set(false);
+        if self.tcp_sending.take() {
+            self.tcp_segment.take().map(|segment| {
+                self.tcp
+                    .map(move |client| client.segment_sent(segment, result))
+            });
+        }
+        self.sender.take().map(|socket| {
+            socket.buffer.take().map(|buf| {
+                socket
+                    .client
+                    .map(move |client| client.send_done(buf, result))
+            })
+        });
+        self.send_next();
+    }
+
+    fn send_arp_request(&self) {
+        self.arp_attempts.set(self.arp_attempts.get() + 1);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ARP_TIMEOUT_MS));
+        self.queue_arp_request();
+        self.transmit_next();
+    }
+
+    fn queue_arp_request(&self) {
+        let target_ip = match self.arp_target.get() {
+            Some(target_ip) => target_ip,
+            None => return,
+        };
+        let mac = self.adapter.mac_address();
+        let request = ArpPacket {
+            operation: arp::operation::REQUEST,
+            sender_mac: mac,
+            sender_ip: self
+                .config
+                .extract()
+                .map_or(IPv4Addr::UNSPECIFIED, |config| config.address),
+            target_mac: [0; MAC_ADDRESS_LEN],
+            target_ip,
+        };
+        let queued = self.queue_control(ETHERNET_HEADER_LEN + ARP_PACKET_LEN, |frame| {
+            encode_ethernet_header(frame, BROADCAST_MAC, mac, ethertype::ARP);
+            request.encode(&mut frame[ETHERNET_HEADER_LEN..]);
+        });
+        self.arp_request_due.set(!queued);
+    }
+
+    /// Write a frame of `len` bytes into the control frame with `f`, if it
+    /// is free and long enough.
+    fn queue_control<F: FnOnce(&mut [u8])>(&self, len: usize, f: F) -> bool {
+        if self.control_len.get().is_some() || len > self.control_frame_len {
+            return false;
+        }
+        self.control_frame
+            .map(|frame| {
+                let frame_len = usize::min(usize::max(len, MIN_FRAME_LEN), frame.len());
+                frame[len..frame_len].fill(0);
+                f(frame);
+                self.control_len.set(Some(frame_len));
+            })
+            .is_some()
+    }
+
+    /// Pass the control frame, or the datagram if it is ready, to the
+    /// adapter.
+    fn transmit_next(&self) {
+        if self.in_flight.get().is_some() {
+            return;
+        }
+        if let Some(len) = self.control_len.take() {
+            if let Some(frame) = self.control_frame.take() {
+                match self.adapter.transmit(frame, len) {
+                    Ok(()) => {
+                        self.in_flight.set(Some(Frame::Control));
+                        return;
+                    }
+                    Err((_, frame)) => {
+                        self.control_frame.replace(frame);
+                    }
+                }
+            }
+        }
+        if self.data_ready.get() {
+            if let Some(frame) = self.data_frame.take() {
+                match self.adapter.transmit(frame, self.data_len.get()) {
+                    Ok(()) => self.in_flight.set(Some(Frame::Data)),
+                    Err((e, frame)) => {
+                        self.data_frame.replace(frame);
+                        self.complete_send(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
+    fn receive_arp(&self, payload: &[u8]) {
+        let packet = match ArpPacket::decode(payload) {
+            Some(packet) => packet,
+            None => return,
+        };
+        let address = self.config.extract().map(|config| config.address);
+        let for_us = address == Some(packet.target_ip);
+        let awaited = self.arp_target.get() == Some(packet.sender_ip);
+        if for_us || awaited || self.arp_cache.lookup(packet.sender_ip).is_some() {
+            self.arp_cache.insert(packet.sender_ip, packet.sender_mac);
+        }
+        if awaited {
+            self.arp_target.set(None);
+            self.arp_request_due.set(false);
+            let _ = self.alarm.disarm();
+            self.resolved(packet.sender_mac);
+        }
+        if for_us && packet.operation == arp::operation::REQUEST {
+            let mac = self.adapter.mac_address();
+            let reply = ArpPacket {
+                operation: arp::operation::REPLY,
+                sender_mac: mac,
+                sender_ip: packet.target_ip,
+                target_mac: packet.sender_mac,
+                target_ip: packet.sender_ip,
+            };
+            self.queue_control(ETHERNET_HEADER_LEN + ARP_PACKET_LEN, |frame| {
+                encode_ethernet_header(frame, packet.sender_mac, mac, ethertype::ARP);
+                reply.encode(&mut frame[ETHERNET_HEADER_LEN..]);
+            });
+            self.transmit_next();
+        }
+    }
+
+    fn receive_ipv4(&self, src_mac: [u8; MAC_ADDRESS_LEN], payload: &[u8]) {
+        let header = match IPv4Header::decode(payload) {
+            Some(header) => header,
+            None => return,
+        };
+        let accepted = match self.config.extract() {
+            Some(config) => header.dst == config.address || config.is_broadcast(header.dst),
+            None => header.protocol == protocol::UDP,
+        };
+        if !accepted {
+            return;
+        }
+        let body = &payload[header.header_len..header.total_len];
+        match header.protocol {
+            protocol::ICMP => self.receive_icmp(src_mac, &header, body),
+            protocol::TCP => self.receive_tcp(&header, body),
+            protocol::UDP => self.receive_udp(&header, body),
+            _ => {}
+        }
+    }
+
+    fn receive_icmp(&self, src_mac: [u8; MAC_ADDRESS_LEN], header: &IPv4Header, body: &[u8]) {
+        // Only echo requests to the address of the interface are answered
+        let address = match self.config.extract() {
+            Some(config) if config.address == header.dst => config.address,
+            _ => return,
+        };
+        if body.len() < super::ipv4::ICMP_HEADER_LEN
+            || body[0] != ICMP_ECHO_REQUEST
+            || checksum(body, 0) != 0
+        {
+            return;
+        }
+        let mac = self.adapter.mac_address();
+        let ident = self.next_ident();
+        let icmp_start = ETHERNET_HEADER_LEN + IPV4_HEADER_LEN;
+        self.queue_control(icmp_start + body.len(), |frame| {
+            encode_ethernet_header(frame, src_mac, mac, ethertype::IPV4);
+            IPv4Header::new(protocol::ICMP, address, header.src, ident, body.len())
+                .encode(&mut frame[ETHERNET_HEADER_LEN..]);
+            let icmp = &mut frame[icmp_start..icmp_start + body.len()];
+            icmp.copy_from_slice(body);
+            icmp[0] = ICMP_ECHO_REPLY;
+            icmp[2..4].copy_from_slice(&[0, 0]);
+            let sum = checksum(icmp, 0);
+            icmp[2..4].copy_from_slice(&sum.to_be_bytes());
+        });
+        self.transmit_next();
+    }
+
+    fn receive_tcp(&self, header: &IPv4Header, body: &[u8]) {
+        // Connections are only to the address of the interface
+        let for_us = self
+            .config
+            .extract()
+            .map_or(false, |config| config.address == header.dst);
+        if !for_us
+            || body.len() < TCP_HEADER_LEN
+            || transport_checksum(protocol::TCP, header.src, header.dst, body) != 0
+        {
+            return;
+        }
+        self.tcp
+            .map(|client| client.segment_received(header.src, body));
+    }
+
+    fn receive_udp(&self, header: &IPv4Header, body: &[u8]) {
+        if body.len() < UDP_HEADER_LEN {
+            return;
+        }
+        let udp_len = u16::from_be_bytes([body[4], body[5]]) as usize;
+        if udp_len < UDP_HEADER_LEN || udp_len > body.len() {
+            return;
+        }
+        let udp = &body[..udp_len];
+        if udp[6..8] != [0, 0] && udp_checksum(header.src, header.dst, udp) != 0 {
+            return;
+        }
+        let src_port = u16::from_be_bytes([udp[0], udp[1]]);
+        let dst_port = u16::from_be_bytes([udp[2], udp[3]]);
+        if dst_port == 0 {
+            return;
+        }
+        if let Some(socket) = self.sockets.iter().find(|s| s.port.get() == dst_port) {
+            socket.client.map(|client| {
+                client.received(header.src, src_port, dst_port, &udp[UDP_HEADER_LEN..])
+            });
+        }
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> EthernetAdapterClient
+    for IPv4Interface<'a, E, A>
+{
+    fn transmit_done(&self, frame: &'static mut [u8], _len: usize, result: Result<(), ErrorCode>) {
+        match self.in_flight.take() {
+            Some(Frame::Control) => {
+                self.control_frame.replace(frame);
+                if self.arp_request_due.get() {
+                    self.queue_arp_request();
+                }
+                self.transmit_next();
+            }
+            Some(Frame::Data) => {
+                self.data_frame.replace(frame);
+                self.complete_send(result);
+            }
+            None => {}
+        }
+    }
+
+    fn received_frame(&self, frame: &[u8]) {
+        if frame.len() < ETHERNET_HEADER_LEN {
+            return;
+        }
+        let mac = self.adapter.mac_address();
+        if frame[0..MAC_ADDRESS_LEN] != mac && frame[0..MAC_ADDRESS_LEN] != BROADCAST_MAC {
+            return;
+        }
+        let mut src_mac = [0; MAC_ADDRESS_LEN];
+        src_mac.copy_from_slice(&frame[MAC_ADDRESS_LEN..2 * MAC_ADDRESS_LEN]);
+        let payload = &frame[ETHERNET_HEADER_LEN..];
+        match u16::from_be_bytes([frame[12], frame[13]]) {
+            ethertype::ARP => self.receive_arp(payload),
+            ethertype::IPV4 => self.receive_ipv4(src_mac, payload),
+            _ => {}
+        }
+    }
+
+    fn link_changed(&self, up: bool) {
+        if !up {
+            self.arp_cache.clear();
+        }
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> time::AlarmClient for IPv4Interface<'a, E, A> {
+    fn alarm(&self) {
+        if self.arp_target.get().is_none() {
+            return;
+        }
+        if self.arp_attempts.get() < ARP_ATTEMPTS {
+            self.send_arp_request();
+        } else {
+            self.arp_target.set(None);
+            self.arp_request_due.set(false);
+            self.complete_send(Err(ErrorCode::FAIL));
+        }
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> TcpNetwork<'a> for IPv4Interface<'a, E, A> {
+    fn set_client(&self, client: &'a dyn TcpNetworkClient) {
+        self.tcp.set(client);
+    }
+
+    fn max_segment_len(&self) -> usize {
+        self.data_frame_len
+            .saturating_sub(ETHERNET_HEADER_LEN + IPV4_HEADER_LEN)
+    }
+
+    fn send_segment(
+        &self,
+        dest: IPv4Addr,
+        segment: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tcp_segment.is_some() {
+            return Err((ErrorCode::BUSY, segment));
+        }
+        if len > segment.len() || len > self.max_segment_len() {
+            return Err((ErrorCode::SIZE, segment));
+        }
+        if !self.adapter.link_up() {
+            return Err((ErrorCode::OFF, segment));
+        }
+        if self.config.is_none() {
+            return Err((ErrorCode::FAIL, segment));
+        }
+        self.tcp_segment.replace(segment);
+        self.tcp_pending.set(Some((dest, len)));
+        self.send_next();
+        Ok(())
+    }
+}
+
+/// A UDP socket of an `IPv4Interface`, bound to one local port.
+pub struct UdpSocket<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> {
+    iface: &'a IPv4Interface<'a, E, A>,
+    port: Cell<u16>,
+    client: OptionalCell<&'a dyn UdpSocketClient>,
+    /// Destination, destination port and length of the datagram to send
+    pending: Cell<Option<(IPv4Addr, u16, usize)>>,
+    buffer: TakeCell<'static, [u8]>,
+    next: ListLink<'a, UdpSocket<'a, E, A>>,
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> ListNode<'a, UdpSocket<'a, E, A>>
+    for UdpSocket<'a, E, A>
+{
+    fn next(&'a self) -> &'a ListLink<'a, UdpSocket<'a, E, A>> {
+        &self.next
+    }
+}
+
+impl<'a, E: EthernetAdapter<'a>, A: time::Alarm<'a>> UdpSocket<'a, E, A> {
+    pub fn new(iface: &'a IPv4Interface<'a, E, A>) -> UdpSocket<'a, E, A> {
+        UdpSocket {
+            iface,
+            port: Cell::new(0),
+            client: OptionalCell::empty(),
+            pending: Cell::new(None),
+            buffer: TakeCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn UdpSocketClient) {
+        self.client.set(client);
+    }
+
+    /// Bind the socket to `port`, or unbind it with 0.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The socket sends from and receives on `port`.
+    /// - `BUSY`: Another socket of the interface is bound to `port`.
+    pub fn bind(&self, port: u16) -> Result<(), ErrorCode> {
+        if port != 0
+            && self
+                .iface
+                .sockets
+                .iter()
+                .any(|s| !core::ptr::eq(s, self) && s.port.get() == port)
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        self.port.set(port);
+        Ok(())
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port.get()
+    }
+
+    /// Send the first `len` bytes of `buf` to `dst_port` at `dest`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: `send_done()` is called with `buf` once the datagram is
+    ///   sent or failed.
+    /// - `INVAL`: The socket is not bound.
+    /// - `BUSY`: A datagram of the socket is being sent.
+    /// - `SIZE`: `len` is longer than `buf` or `max_payload_len()`.
+    /// - `OFF`: The link is down.
+    /// - `FAIL`: The interface has no address, and `dest` is not the
+    ///   broadcast address.
+    pub fn send_to(
+        &self,
+        dest: IPv4Addr,
+        dst_port: u16,
+        buf: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.port.get() == 0 {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        if self.buffer.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        if len > buf.len() || len > self.iface.max_payload_len() {
+            return Err((ErrorCode::SIZE, buf));
+        }
+        if !self.iface.adapter.link_up() {
+            return Err((ErrorCode::OFF, buf));
+        }
+        if !dest.is_broadcast() && self.iface.config.is_none() {
+            return Err((ErrorCode::FAIL, buf));
+        }
+        self.buffer.replace(buf);
+        self.pending.set(Some((dest, dst_port, len)));
+        self.iface.send_next();
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! IPv4 addresses and headers (RFC 791), the Ethernet II header and the
+//! Internet checksum (RFC 1071).
+//!
+//! Only headers of unfragmented packets are decoded; IPv4 options are
+//! skipped on receive and never sent.
+
+use kernel::hil::ethernet::MAC_ADDRESS_LEN;
+
+pub const ETHERNET_HEADER_LEN: usize = 14;
+pub const IPV4_HEADER_LEN: usize = 20;
+pub const UDP_HEADER_LEN: usize = 8;
+pub const ICMP_HEADER_LEN: usize = 8;
+
+pub const BROADCAST_MAC: [u8; MAC_ADDRESS_LEN] = [0xff; MAC_ADDRESS_LEN];
+pub const DEFAULT_TTL: u8 = 64;
+
+/// EtherTypes of the Ethernet II header.
+pub mod ethertype {
+    pub const IPV4: u16 = 0x0800;
+    pub const ARP: u16 = 0x0806;
+}
+
+/// Protocol numbers of the IPv4 header.
+pub mod protocol {
+    pub const ICMP: u8 = 1;
+    pub const TCP: u8 = 6;
+    pub const UDP: u8 = 17;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct IPv4Addr(pub [u8; 4]);
+
+impl IPv4Addr {
+    pub const UNSPECIFIED: IPv4Addr = IPv4Addr([0; 4]);
+    pub const BROADCAST: IPv4Addr = IPv4Addr([0xff; 4]);
+
+    pub fn is_unspecified(&self) -> bool {
+        *self == IPv4Addr::UNSPECIFIED
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == IPv4Addr::BROADCAST
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0xf0 == 0xe0
+    }
+
+    fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+}
+
+/// The address of an interface, its subnet and the router of the subnet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IPv4Config {
+    pub address: IPv4Addr,
+    pub netmask: IPv4Addr,
+    /// Unspecified if there is no router
+    pub gateway: IPv4Addr,
+}
+
+impl IPv4Config {
+    pub fn in_subnet(&self, addr: IPv4Addr) -> bool {
+        let mask = self.netmask.to_u32();
+        addr.to_u32() & mask == self.address.to_u32() & mask
+    }
+
+    pub fn subnet_broadcast(&self) -> IPv4Addr {
+        IPv4Addr((self.address.to_u32() | !self.netmask.to_u32()).to_be_bytes())
+    }
+
+    /// Whether packets to `addr` are for all hosts of the subnet.
+    pub fn is_broadcast(&self, addr: IPv4Addr) -> bool {
+        addr.is_broadcast() || addr == self.subnet_broadcast()
+    }
+
+    /// The host a packet to `dest` is sent to: `dest` itself in the subnet,
+    /// the gateway otherwise.
+    pub fn next_hop(&self, dest: IPv4Addr) -> IPv4Addr {
+        if self.in_subnet(dest) || self.gateway.is_unspecified() {
+            dest
+        } else {
+            self.gateway
+        }
+    }
+}
+
+/// The fields of an IPv4 header that are used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct IPv4Header {
+    pub protocol: u8,
+    pub src: IPv4Addr,
+    pub dst: IPv4Addr,
+    pub ttl: u8,
+    pub ident: u16,
+    /// Length of the header with options
+    pub header_len: usize,
+    /// Length of the packet with the header
+    pub total_len: usize,
+}
+
+impl IPv4Header {
+    pub fn new(protocol: u8, src: IPv4Addr, dst: IPv4Addr, ident: u16, payload_len: usize) -> Self {
+        IPv4Header {
+            protocol,
+            src,
+            dst,
+            ttl: DEFAULT_TTL,
+            ident,
+            header_len: IPV4_HEADER_LEN,
+            total_len: IPV4_HEADER_LEN + payload_len,
+        }
+    }
+
+    /// Decode the header at the start of `buf`. Fails for other versions,
+    /// bad lengths or checksums, and fragments.
+    pub fn decode(buf: &[u8]) -> Option<IPv4Header> {
+        if buf.len() < IPV4_HEADER_LEN || buf[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = ((buf[0] & 0x0f) as usize) * 4;
+        let total_len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        if header_len < IPV4_HEADER_LEN || total_len < header_len || total_len > buf.len() {
+            return None;
+        }
+        if checksum(&buf[..header_len], 0) != 0 {
+            return None;
+        }
+        // More fragments, or an offset
+        if u16::from_be_bytes([buf[6], buf[7]]) & 0x3fff != 0 {
+            return None;
+        }
+        Some(IPv4Header {
+            protocol: buf[9],
+            src: IPv4Addr([buf[12], buf[13], buf[14], buf[15]]),
+            dst: IPv4Addr([buf[16], buf[17], buf[18], buf[19]]),
+            ttl: buf[8],
+            ident: u16::from_be_bytes([buf[4], buf[5]]),
+            header_len,
+            total_len,
+        })
+    }
+
+    /// Encode the header without options and with the checksum into the
+    /// first `IPV4_HEADER_LEN` bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0] = 0x45;
+        buf[1] = 0;
+        buf[2..4].copy_from_slice(&(self.total_len as u16).to_be_bytes());
+        buf[4..6].copy_from_slice(&self.ident.to_be_bytes());
+        // Don't fragment
+        buf[6..8].copy_from_slice(&0x4000u16.to_be_bytes());
+        buf[8] = self.ttl;
+        buf[9] = self.protocol;
+        buf[10..12].copy_from_slice(&[0, 0]);
+        buf[12..16].copy_from_slice(&self.src.0);
+        buf[16..20].copy_from_slice(&self.dst.0);
+        let sum = checksum(&buf[..IPV4_HEADER_LEN], 0);
+        buf[10..12].copy_from_slice(&sum.to_be_bytes());
+    }
+}
+
+/// The Internet checksum of `data`, continuing the sum `initial`. It is 0
+/// over data that includes a correct checksum.
+pub fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = initial;
+    for chunk in data.chunks(2) {
+        let word = match *chunk {
+            [high, low] => u16::from_be_bytes([high, low]),
+            [high] => u16::from_be_bytes([high, 0]),
+            _ => 0,
+        };
+        sum += word as u32;
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The checksum of the UDP datagram `udp` with its pseudo header. It is 0
+/// for a datagram with a correct checksum.
+pub fn udp_checksum(src: IPv4Addr, dst: IPv4Addr, udp: &[u8]) -> u16 {
+    transport_checksum(protocol::UDP, src, dst, udp)
+}
+
+/// The checksum of the UDP datagram or TCP segment `data` of `protocol`,
+/// with its pseudo header.
+pub fn transport_checksum(protocol: u8, src: IPv4Addr, dst: IPv4Addr, data: &[u8]) -> u16 {
+    let pseudo = (src.to_u32() >> 16)
+        + (src.to_u32() & 0xffff)
+        + (dst.to_u32() >> 16)
+        + (dst.to_u32() & 0xffff)
+        + protocol as u32
+        + data.len() as u32;
+    checksum(data, pseudo)
+}
+
+/// Encode an Ethernet II header into the first `ETHERNET_HEADER_LEN` bytes
+/// of `buf`.
+pub fn encode_ethernet_header(
+    buf: &mut [u8],
+    dst: [u8; MAC_ADDRESS_LEN],
+    src: [u8; MAC_ADDRESS_LEN],
+    ethertype: u16,
+) {
+    buf[0..6].copy_from_slice(&dst);
+    buf[6..12].copy_from_slice(&src);
+    buf[12..14].copy_from_slice(&ethertype.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let src = IPv4Addr([192, 168, 1, 10]);
+        let dst = IPv4Addr([192, 168, 1, 1]);
+        let mut buf = [0; IPV4_HEADER_LEN + 4];
+        IPv4Header::new(protocol::UDP, src, dst, 7, 4).encode(&mut buf);
+        assert_eq!(checksum(&buf[..IPV4_HEADER_LEN], 0), 0);
+
+        let header = IPv4Header::decode(&buf).unwrap();
+        assert_eq!(header.src, src);
+        assert_eq!(header.dst, dst);
+        assert_eq!(header.total_len, IPV4_HEADER_LEN + 4);
+
+        buf[12] ^= 1;
+        assert_eq!(IPv4Header::decode(&buf), None);
+    }
+
+    #[test]
+    fn rfc1071_checksum() {
+        // Example of RFC 1071, section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(checksum(&data, 0), !0xddf2);
+    }
+
+    #[test]
+    fn subnet() {
+        let config = IPv4Config {
+            address: IPv4Addr([10, 0, 0, 5]),
+            netmask: IPv4Addr([255, 255, 255, 0]),
+            gateway: IPv4Addr([10, 0, 0, 1]),
+        };
+        assert_eq!(config.subnet_broadcast(), IPv4Addr([10, 0, 0, 255]));
+        assert_eq!(
+            config.next_hop(IPv4Addr([10, 0, 0, 9])),
+            IPv4Addr([10, 0, 0, 9])
+        );
+        assert_eq!(config.next_hop(IPv4Addr([8, 8, 8, 8])), config.gateway);
+    }
+}
@@ -0,0 +1,45 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A minimal IPv4 stack for Ethernet adapters (`hil::ethernet`).
+//!
+//! `IPv4Interface` sends and receives IPv4 packets on one adapter. It
+//! resolves MAC addresses with ARP, answers pings, and carries the UDP
+//! datagrams of its `UdpSocket`s. Its address is either configured
+//! statically with `set_config()`, or by the DHCP client `Dhcp`. It also
+//! carries the segments of the TCP stack of `net::tcp`. Fragments and IPv4
+//! options are not supported.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let ipv4 = components::ipv4::IPv4Component::new(cdc_ncm, mux_alarm, None)
+//!     .finalize(components::ipv4_component_static!(
+//!         capsules_extra::usb::cdc_ncm::CdcNcm<'static, nrf52::usbd::Usbd>,
+//!         nrf52840::rtc::Rtc,
+//!     ));
+//! let dhcp = components::ipv4::DhcpComponent::new(ipv4, mux_alarm)
+//!     .finalize(components::dhcp_component_static!(
+//!         capsules_extra::usb::cdc_ncm::CdcNcm<'static, nrf52::usbd::Usbd>,
+//!         nrf52840::rtc::Rtc,
+//!     ));
+//! dhcp.start().unwrap();
+//!
+//! let telemetry = static_init!(
+//!     capsules_extra::net::ipv4::UdpSocket<...>,
+//!     capsules_extra::net::ipv4::UdpSocket::new(ipv4)
+//! );
+//! ipv4.add_socket(telemetry);
+//! telemetry.bind(4000).unwrap();
+//! ```
+
+pub mod arp;
+pub mod dhcp;
+pub mod interface;
+pub mod ipv4;
+
+pub use self::dhcp::{Dhcp, DhcpClient};
+pub use self::interface::{IPv4Interface, UdpSocket, UdpSocketClient};
+pub use self::ipv4::{IPv4Addr, IPv4Config};
@@ -198,10 +198,16 @@ pub fn compute_icmp_checksum(
     let msb = (icmp_header.get_type_as_int() as u32) << 8;
     let lsb = icmp_header.get_code() as u32;
     sum += msb + lsb;
+    sum += icmp_header.get_cksum() as u32;
 
     // add options
     match icmp_header.get_options() {
-        ICMP6HeaderOptions::Type1 { unused } | ICMP6HeaderOptions::Type3 { unused } => {
+        ICMP6HeaderOptions::Type1 { unused }
+        | ICMP6HeaderOptions::Type3 { unused }
+        | ICMP6HeaderOptions::Type133 { unused }
+        | ICMP6HeaderOptions::Type135 { unused }
+        | ICMP6HeaderOptions::Type136 { flags: unused }
+        | ICMP6HeaderOptions::Type155 { base: unused } => {
             sum += unused >> 16; // upper 16 bits
             sum += unused & 0xffff; // lower 16 bits
         }
@@ -209,6 +215,14 @@ pub fn compute_icmp_checksum(
             sum += id as u32;
             sum += seqno as u32;
         }
+        ICMP6HeaderOptions::Type134 {
+            hop_limit,
+            flags,
+            router_lifetime,
+        } => {
+            sum += (hop_limit as u32) << 8 | flags as u32;
+            sum += router_lifetime as u32;
+        }
     }
 
     // add icmp payload
@@ -219,7 +233,7 @@ pub fn compute_icmp_checksum(
     while sum > 0xffff {
         let sum_upper = sum >> 16;
         let sum_lower = sum & 0xffff;
-        sum += sum_upper + sum_lower;
+        sum = sum_upper + sum_lower;
     }
 
     sum = !sum;
@@ -245,7 +259,7 @@ pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
         i += 2;
     }
 
-    sum += ip6_header.payload_len as u32;
+    sum += ip6_header.get_payload_len() as u32;
     sum += ip6_header.next_header as u32;
 
     sum
@@ -257,7 +271,12 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
     let mut i: usize = 0;
     while i < (len as usize) {
         let msb = (buf[i] as u32) << 8;
-        let lsb = buf[i + 1] as u32;
+        // An odd length is padded with a zero byte
+        let lsb = if i + 1 < len as usize {
+            buf[i + 1] as u32
+        } else {
+            0
+        };
         sum += msb + lsb;
         i += 2;
     }
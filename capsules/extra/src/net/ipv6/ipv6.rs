@@ -286,7 +286,9 @@ impl IP6Header {
                 Ok(())
             }
             ip6_nh::ICMP => {
-                // Untested (10/5/18)
+                if buf.len() < ICMP_HDR_LEN {
+                    return Err(ErrorCode::FAIL);
+                }
                 let mut icmp_header: [u8; ICMP_HDR_LEN] = [0; ICMP_HDR_LEN];
                 icmp_header.copy_from_slice(&buf[..ICMP_HDR_LEN]);
                 let checksum = match ICMP6Header::decode(&icmp_header).done() {
@@ -482,6 +484,7 @@ impl<'a> IP6Packet<'a> {
                 udp_header.set_cksum(cksum);
             }
             TransportHeader::ICMP(ref mut icmp_header) => {
+                icmp_header.set_cksum(0);
                 let cksum = compute_icmp_checksum(&self.header, &icmp_header, self.payload.payload);
                 icmp_header.set_cksum(cksum);
             }
@@ -0,0 +1,195 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! The IPv6 addresses of the network interface.
+//!
+//! `InterfaceAddresses` holds the addresses the board assigns statically and
+//! the addresses configured at runtime, e.g. by stateless address
+//! autoconfiguration in `ipv6_nd`. A configured address is tentative until
+//! duplicate address detection finished, and is not an address of the
+//! interface before. Once its preferred lifetime expired, it is deprecated:
+//! still an address of the interface, but only used as source address if no
+//! preferred address fits.
+
+use crate::net::ipv6::ip_utils::IPAddr;
+
+use core::cell::Cell;
+
+use kernel::ErrorCode;
+
+/// Number of addresses that can be configured at runtime.
+pub const MAX_CONFIGURED_ADDRS: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AddressState {
+    /// Duplicate address detection has not finished.
+    Tentative,
+    /// The address is assigned to the interface.
+    Preferred,
+    /// The address is assigned to the interface, but should not be used for
+    /// new communication.
+    Deprecated,
+}
+
+pub struct InterfaceAddresses {
+    static_addrs: &'static [IPAddr],
+    configured: [Cell<Option<(IPAddr, AddressState)>>; MAX_CONFIGURED_ADDRS],
+}
+
+impl InterfaceAddresses {
+    pub fn new(static_addrs: &'static [IPAddr]) -> InterfaceAddresses {
+        InterfaceAddresses {
+            static_addrs,
+            configured: Default::default(),
+        }
+    }
+
+    /// The addresses assigned to the interface, the static addresses first.
+    pub fn iter(&self) -> impl Iterator<Item = IPAddr> + '_ {
+        self.assigned().map(|(addr, _)| addr)
+    }
+
+    fn assigned(&self) -> impl Iterator<Item = (IPAddr, AddressState)> + '_ {
+        let static_addrs = self
+            .static_addrs
+            .iter()
+            .map(|addr| (*addr, AddressState::Preferred));
+        static_addrs.chain(
+            self.configured()
+                .filter(|(_, state)| *state != AddressState::Tentative),
+        )
+    }
+
+    /// The addresses configured at runtime, and their state.
+    pub fn configured(&self) -> impl Iterator<Item = (IPAddr, AddressState)> + '_ {
+        self.configured.iter().filter_map(|entry| entry.get())
+    }
+
+    /// Number of addresses assigned to the interface.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// The address at `index` in `iter()`.
+    pub fn get(&self, index: usize) -> Option<IPAddr> {
+        self.iter().nth(index)
+    }
+
+    /// Whether `addr` is assigned to the interface.
+    pub fn contains(&self, addr: &IPAddr) -> bool {
+        self.iter().any(|assigned| assigned == *addr)
+    }
+
+    /// The state of `addr` if it was configured at runtime.
+    pub fn state(&self, addr: &IPAddr) -> Option<AddressState> {
+        self.configured()
+            .find(|(configured, _)| configured == addr)
+            .map(|(_, state)| state)
+    }
+
+    /// Configure `addr`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The address was added.
+    /// - `ALREADY`: The address is static or configured already.
+    /// - `NOMEM`: `MAX_CONFIGURED_ADDRS` addresses are configured.
+    pub fn add(&self, addr: IPAddr, state: AddressState) -> Result<(), ErrorCode> {
+        if self.static_addrs.contains(&addr) || self.state(&addr).is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let free = self.configured.iter().find(|entry| entry.get().is_none());
+        free.map_or(Err(ErrorCode::NOMEM), |entry| {
+            entry.set(Some((addr, state)));
+            Ok(())
+        })
+    }
+
+    /// Change the state of the configured address `addr`. Returns `INVAL` if
+    /// it is not configured.
+    pub fn set_state(&self, addr: &IPAddr, state: AddressState) -> Result<(), ErrorCode> {
+        self.entry(addr).map_or(Err(ErrorCode::INVAL), |entry| {
+            entry.set(Some((*addr, state)));
+            Ok(())
+        })
+    }
+
+    /// Remove the configured address `addr`. Returns `INVAL` if it is not
+    /// configured.
+    pub fn remove(&self, addr: &IPAddr) -> Result<(), ErrorCode> {
+        self.entry(addr).map_or(Err(ErrorCode::INVAL), |entry| {
+            entry.set(None);
+            Ok(())
+        })
+    }
+
+    /// The source address for packets to `dst`: a link-local address for
+    /// link-local and link-scope multicast destinations, another address
+    /// otherwise. Preferred addresses are selected over deprecated ones.
+    pub fn source_for(&self, dst: &IPAddr) -> Option<IPAddr> {
+        let link_scope =
+            dst.is_unicast_link_local() || (dst.is_multicast() && dst.0[1] & 0x0f == 2);
+        self.assigned()
+            .filter(|(addr, _)| addr.is_unicast_link_local() == link_scope)
+            .min_by_key(|(_, state)| *state == AddressState::Deprecated)
+            .map(|(addr, _)| addr)
+    }
+
+    fn entry(&self, addr: &IPAddr) -> Option<&Cell<Option<(IPAddr, AddressState)>>> {
+        self.configured.iter().find(|entry| {
+            entry
+                .get()
+                .map_or(false, |(configured, _)| configured == *addr)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static STATIC_ADDRS: [IPAddr; 1] = [IPAddr([
+        0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+    ])];
+
+    #[test]
+    fn tentative_addresses_are_not_assigned() {
+        let addresses = InterfaceAddresses::new(&STATIC_ADDRS);
+        let mut link_local = IPAddr::new();
+        link_local.set_unicast_link_local();
+        link_local.0[15] = 1;
+
+        assert_eq!(
+            addresses.add(STATIC_ADDRS[0], AddressState::Tentative),
+            Err(ErrorCode::ALREADY)
+        );
+        assert_eq!(addresses.add(link_local, AddressState::Tentative), Ok(()));
+        assert!(!addresses.contains(&link_local));
+        assert_eq!(addresses.len(), 1);
+
+        assert_eq!(
+            addresses.set_state(&link_local, AddressState::Preferred),
+            Ok(())
+        );
+        assert_eq!(addresses.get(1), Some(link_local));
+        assert_eq!(addresses.source_for(&link_local), Some(link_local));
+        assert_eq!(
+            addresses.source_for(&STATIC_ADDRS[0]),
+            Some(STATIC_ADDRS[0])
+        );
+
+        let mut global = STATIC_ADDRS[0];
+        global.0[15] = 2;
+        assert_eq!(addresses.add(global, AddressState::Deprecated), Ok(()));
+        assert!(addresses.contains(&global));
+        assert_eq!(addresses.source_for(&global), Some(STATIC_ADDRS[0]));
+
+        assert_eq!(addresses.remove(&link_local), Ok(()));
+        assert_eq!(addresses.state(&link_local), None);
+    }
+}
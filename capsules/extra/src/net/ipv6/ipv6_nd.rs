@@ -0,0 +1,1025 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! IPv6 Neighbor Discovery (RFC 4861) and stateless address
+//! autoconfiguration (RFC 4862).
+//!
+//! Once started, `NeighborDiscovery` solicits routers, and configures an
+//! address for every prefix that routers advertise for autoconfiguration,
+//! from the prefix and the interface identifier of the link-layer address.
+//! The link-local address is configured the same way if the board did not
+//! assign it. Configured addresses are tentative until duplicate address
+//! detection found no other node using them.
+//!
+//! Router Advertisements keep the default router and the configured addresses
+//! alive for the lifetimes they advertise: an address is deprecated once its
+//! preferred lifetime expired and removed with its valid lifetime, and once
+//! the lifetime of the default router expired, routers are solicited again.
+//! The 6LoWPAN Context options of Router Advertisements (RFC 6775) set the
+//! contexts of the 6LoWPAN context table, if one was given. A context whose
+//! lifetime expired is still used to decompress for
+//! `MIN_CONTEXT_CHANGE_DELAY`, then removed. Lifetimes are counted in whole
+//! seconds, with a timer that runs at least once a minute while lifetimes are
+//! finite.
+//!
+//! It answers Neighbor Solicitations for the addresses of the interface, and
+//! keeps a neighbor cache of the link-layer addresses learned from Neighbor
+//! Solicitations, Neighbor Advertisements and Router Advertisements. IPv6
+//! senders use it as their `NextHop`: packets are sent to the cached address
+//! of a neighbor, to the link-layer address embedded in a link-local
+//! destination, or to the default router. As on 6LoWPAN links (RFC 6775),
+//! other destinations are not resolved with multicast solicitations, they
+//! are reached through the default router.
+//!
+//! Link-layer addresses are IEEE 802.15.4 addresses, the IPv6 stack runs over
+//! 6LoWPAN.
+//!
+//! Usage
+//! -----
+//! The UDP mux component sets up neighbor discovery on the same interface:
+//!
+//! ```rust
+//! let (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd) = UDPMuxComponent::new(
+//!     // ...
+//! )
+//! .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+//! ipv6_nd.start().unwrap();
+//! ```
+
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_interface::{AddressState, InterfaceAddresses, MAX_CONFIGURED_ADDRS};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader, ICMP_HDR_LEN};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::sixlowpan::sixlowpan_compression::{Context, ContextTable, MAX_CONTEXTS};
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+/// Number of neighbors in the neighbor cache.
+pub const NEIGHBOR_CACHE_SIZE: usize = 8;
+
+/// Length of the longest message sent after its ICMPv6 header: a target
+/// address and a link-layer address option.
+pub const ND_PAYLOAD_LEN: usize = 32;
+
+const ALL_NODES: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+const ALL_ROUTERS: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+// Protocol constants of RFC 4861 section 10, the time in seconds.
+const MAX_RTR_SOLICITATIONS: u8 = 3;
+const RTR_SOLICITATION_INTERVAL: u32 = 4;
+
+/// Seconds a context is used to decompress after its lifetime expired (RFC
+/// 6775 section 9).
+pub const MIN_CONTEXT_CHANGE_DELAY: u32 = 300;
+
+/// A lifetime of `INFINITE` seconds does not expire.
+const INFINITE: u32 = u32::MAX;
+/// Valid lifetime below which advertisements cannot shorten the valid
+/// lifetime of an address (RFC 4862 section 5.5.3).
+const TWO_HOURS: u32 = 2 * 60 * 60;
+/// Longest interval of the timer, in seconds.
+const MAX_TICK_INTERVAL: u32 = 60;
+
+const OPTION_SOURCE_LINK_LAYER: u8 = 1;
+const OPTION_TARGET_LINK_LAYER: u8 = 2;
+const OPTION_PREFIX_INFORMATION: u8 = 3;
+const OPTION_6LOWPAN_CONTEXT: u8 = 34;
+
+const PREFIX_ON_LINK: u8 = 0x80;
+const PREFIX_AUTONOMOUS: u8 = 0x40;
+
+const CONTEXT_COMPRESS: u8 = 0x10;
+const CONTEXT_ID: u8 = 0x0f;
+
+const NA_SOLICITED: u32 = 1 << 30;
+const NA_OVERRIDE: u32 = 1 << 29;
+
+/// Resolves the link-layer address packets to an IPv6 destination are sent
+/// to.
+pub trait NextHop {
+    /// The link-layer address of the next hop to `dst`, `None` if it is not
+    /// known.
+    fn next_hop(&self, dst: &IPAddr) -> Option<MacAddress>;
+}
+
+/// A Prefix Information option of a Router Advertisement.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PrefixInformation {
+    pub prefix_len: u8,
+    pub on_link: bool,
+    pub autonomous: bool,
+    /// Lifetimes in seconds, `u32::MAX` is infinity.
+    pub valid_lifetime: u32,
+    pub preferred_lifetime: u32,
+    pub prefix: [u8; 16],
+}
+
+/// A 6LoWPAN Context option of a Router Advertisement (RFC 6775).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ContextInformation {
+    pub context_len: u8,
+    pub compress: bool,
+    pub id: u8,
+    /// Lifetime in units of 60 seconds.
+    pub valid_lifetime: u16,
+    /// The prefix, the bits after `context_len` are 0.
+    pub prefix: [u8; 16],
+}
+
+/// An option of a Neighbor Discovery message.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum NdOption {
+    SourceLinkLayer(MacAddress),
+    TargetLinkLayer(MacAddress),
+    PrefixInformation(PrefixInformation),
+    SixLowpanContext(ContextInformation),
+    /// An option not handled here, with its type.
+    Other(u8),
+}
+
+/// Iterator over the options at the end of a Neighbor Discovery message. It
+/// stops at the first malformed option.
+pub struct NdOptions<'b> {
+    buf: &'b [u8],
+}
+
+impl<'b> NdOptions<'b> {
+    pub fn new(buf: &'b [u8]) -> NdOptions<'b> {
+        NdOptions { buf }
+    }
+}
+
+impl Iterator for NdOptions<'_> {
+    type Item = NdOption;
+
+    fn next(&mut self) -> Option<NdOption> {
+        // The length is in units of 8 bytes
+        let len = *self.buf.get(1)? as usize * 8;
+        if len == 0 || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+        let (option, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        Some(match option[0] {
+            OPTION_SOURCE_LINK_LAYER | OPTION_TARGET_LINK_LAYER => {
+                let addr = decode_link_layer_address(option);
+                match (option[0], addr) {
+                    (OPTION_SOURCE_LINK_LAYER, Some(addr)) => NdOption::SourceLinkLayer(addr),
+                    (_, Some(addr)) => NdOption::TargetLinkLayer(addr),
+                    (option_type, None) => NdOption::Other(option_type),
+                }
+            }
+            OPTION_PREFIX_INFORMATION if len == 32 => {
+                let mut prefix = [0; 16];
+                prefix.copy_from_slice(&option[16..32]);
+                NdOption::PrefixInformation(PrefixInformation {
+                    prefix_len: option[2],
+                    on_link: option[3] & PREFIX_ON_LINK != 0,
+                    autonomous: option[3] & PREFIX_AUTONOMOUS != 0,
+                    valid_lifetime: u32::from_be_bytes([
+                        option[4], option[5], option[6], option[7],
+                    ]),
+                    preferred_lifetime: u32::from_be_bytes([
+                        option[8], option[9], option[10], option[11],
+                    ]),
+                    prefix,
+                })
+            }
+            OPTION_6LOWPAN_CONTEXT if len == 16 || len == 24 => {
+                let mut prefix = [0; 16];
+                prefix[..len - 8].copy_from_slice(&option[8..len]);
+                NdOption::SixLowpanContext(ContextInformation {
+                    context_len: option[2],
+                    compress: option[3] & CONTEXT_COMPRESS != 0,
+                    id: option[3] & CONTEXT_ID,
+                    valid_lifetime: u16::from_be_bytes([option[6], option[7]]),
+                    prefix,
+                })
+            }
+            option_type => NdOption::Other(option_type),
+        })
+    }
+}
+
+/// The link-layer address of a link-layer address option, in the format of
+/// RFC 4944 section 8.
+fn decode_link_layer_address(option: &[u8]) -> Option<MacAddress> {
+    match option.len() {
+        8 => Some(MacAddress::Short(u16::from_be_bytes([
+            option[2], option[3],
+        ]))),
+        16 => {
+            let mut addr = [0; 8];
+            addr.copy_from_slice(&option[2..10]);
+            Some(MacAddress::Long(addr))
+        }
+        _ => None,
+    }
+}
+
+/// Write a link-layer address option into `buf`, and return its length.
+fn encode_link_layer_option(buf: &mut [u8], option_type: u8, addr: MacAddress) -> usize {
+    let len = match addr {
+        MacAddress::Short(_) => 8,
+        MacAddress::Long(_) => 16,
+    };
+    buf[..len].fill(0);
+    buf[0] = option_type;
+    buf[1] = (len / 8) as u8;
+    match addr {
+        MacAddress::Short(short) => buf[2..4].copy_from_slice(&short.to_be_bytes()),
+        MacAddress::Long(long) => buf[2..10].copy_from_slice(&long),
+    }
+    len
+}
+
+/// The link-layer address embedded in the interface identifier of a
+/// link-local address, the inverse of `IPAddr::generate_from_mac()`.
+pub(crate) fn mac_from_link_local(addr: &IPAddr) -> Option<MacAddress> {
+    if !addr.is_unicast_link_local() {
+        return None;
+    }
+    if addr.0[8..14] == [0, 0, 0, 0xff, 0xfe, 0] {
+        Some(MacAddress::Short(u16::from_be_bytes([
+            addr.0[14], addr.0[15],
+        ])))
+    } else {
+        let mut long = [0; 8];
+        long.copy_from_slice(&addr.0[8..16]);
+        long[0] ^= 0b00000010;
+        Some(MacAddress::Long(long))
+    }
+}
+
+/// The solicited-node multicast address of `addr`.
+fn solicited_node(addr: &IPAddr) -> IPAddr {
+    let mut multicast = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0, 0]);
+    multicast.0[13..16].copy_from_slice(&addr.0[13..16]);
+    multicast
+}
+
+/// The valid lifetime of an address with `remaining` seconds left, after an
+/// advertisement of `received` seconds (RFC 4862 section 5.5.3 e).
+fn updated_valid_lifetime(remaining: u32, received: u32) -> u32 {
+    if received > TWO_HOURS || received > remaining {
+        received
+    } else if remaining <= TWO_HOURS {
+        remaining
+    } else {
+        TWO_HOURS
+    }
+}
+
+/// `lifetime` after `seconds` passed.
+fn count_down(lifetime: u32, seconds: u32) -> u32 {
+    match lifetime {
+        INFINITE => INFINITE,
+        _ => lifetime.saturating_sub(seconds),
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Neighbor {
+    ip_addr: IPAddr,
+    mac_addr: MacAddress,
+}
+
+/// Remaining lifetimes of an autoconfigured address, in seconds.
+#[derive(Copy, Clone)]
+struct AddressLifetimes {
+    addr: IPAddr,
+    valid: u32,
+    preferred: u32,
+}
+
+/// Remaining lifetime of a context, in seconds.
+#[derive(Copy, Clone)]
+struct ContextLifetime {
+    remaining: u32,
+    /// The lifetime expired, and the context is only used to decompress.
+    phasing_out: bool,
+}
+
+pub struct NeighborDiscovery<'a, A: time::Alarm<'a>> {
+    alarm: &'a A,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    net_cap: &'static NetworkCapability,
+    addresses: &'a InterfaceAddresses,
+    mac_addr: MacAddress,
+    /// Message after the ICMPv6 header.
+    tx_buffer: TakeCell<'static, [u8]>,
+    sending: Cell<bool>,
+    started: Cell<bool>,
+    neighbors: [Cell<Option<Neighbor>>; NEIGHBOR_CACHE_SIZE],
+    next_evicted: Cell<usize>,
+    default_router: OptionalCell<Neighbor>,
+    solicitations: Cell<u8>,
+    /// Seconds until the next Router Solicitation.
+    solicitation_delay: Cell<u32>,
+    /// Tentative addresses a duplicate address detection probe was sent for.
+    probed: [Cell<Option<IPAddr>>; MAX_CONFIGURED_ADDRS],
+    address_lifetimes: [Cell<Option<AddressLifetimes>>; MAX_CONFIGURED_ADDRS],
+    /// Remaining lifetime of the default router, in seconds.
+    router_lifetime: Cell<u32>,
+    contexts: OptionalCell<&'a ContextTable>,
+    context_lifetimes: [Cell<Option<ContextLifetime>>; MAX_CONTEXTS],
+    /// Time up to which the lifetimes were counted down.
+    counted: Cell<A::Ticks>,
+}
+
+impl<'a, A: time::Alarm<'a>> NeighborDiscovery<'a, A> {
+    /// `ip_sender` must not be used by others, the source address of its
+    /// packets is set for every message. `tx_buffer` must be at least
+    /// `ND_PAYLOAD_LEN` bytes long.
+    pub fn new(
+        alarm: &'a A,
+        ip_sender: &'a dyn IP6Sender<'a>,
+        net_cap: &'static NetworkCapability,
+        addresses: &'a InterfaceAddresses,
+        mac_addr: MacAddress,
+        tx_buffer: &'static mut [u8],
+    ) -> NeighborDiscovery<'a, A> {
+        NeighborDiscovery {
+            alarm,
+            ip_sender,
+            net_cap,
+            addresses,
+            mac_addr,
+            tx_buffer: TakeCell::new(tx_buffer),
+            sending: Cell::new(false),
+            started: Cell::new(false),
+            neighbors: Default::default(),
+            next_evicted: Cell::new(0),
+            default_router: OptionalCell::empty(),
+            solicitations: Cell::new(0),
+            solicitation_delay: Cell::new(0),
+            probed: Default::default(),
+            address_lifetimes: Default::default(),
+            router_lifetime: Cell::new(0),
+            contexts: OptionalCell::empty(),
+            context_lifetimes: Default::default(),
+            counted: Cell::new(A::Ticks::from(0)),
+        }
+    }
+
+    /// Set the 6LoWPAN contexts advertised by routers in `contexts`.
+    pub fn set_context_table(&self, contexts: &'a ContextTable) {
+        self.contexts.set(contexts);
+    }
+
+    /// The addresses of the interface, including the configured ones.
+    pub fn addresses(&self) -> &'a InterfaceAddresses {
+        self.addresses
+    }
+
+    /// The address of the default router, if a router advertised itself.
+    pub fn default_router(&self) -> Option<IPAddr> {
+        self.default_router.map(|router| router.ip_addr)
+    }
+
+    /// Configure the link-local address, unless the board assigned it, and
+    /// solicit routers. Returns `ALREADY` if started before.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.started.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.started.set(true);
+
+        let link_local = IPAddr::generate_from_mac(self.mac_addr);
+        if !self.addresses.contains(&link_local) {
+            let _ = self.addresses.add(link_local, AddressState::Tentative);
+        }
+        self.solicitations.set(0);
+        self.solicitation_delay.set(0);
+        self.counted.set(self.alarm.now());
+        self.schedule_tick(1);
+        Ok(())
+    }
+
+    fn soliciting(&self) -> bool {
+        self.started.get()
+            && self.default_router.is_none()
+            && self.solicitations.get() < MAX_RTR_SOLICITATIONS
+    }
+
+    /// Run the timer in `seconds`, unless it runs earlier already.
+    fn schedule_tick(&self, seconds: u32) {
+        let now = self.alarm.now();
+        let dt = self.alarm.ticks_from_seconds(seconds);
+        if !self.alarm.is_armed() || self.alarm.get_alarm().wrapping_sub(now) > dt {
+            self.alarm.set_alarm(now, dt);
+        }
+    }
+
+    /// Seconds until the timer has to run next, `None` if there is nothing
+    /// to do.
+    fn next_tick(&self) -> Option<u32> {
+        let tentative = self
+            .addresses
+            .configured()
+            .any(|(_, state)| state == AddressState::Tentative);
+        if tentative || self.soliciting() {
+            return Some(1);
+        }
+
+        let addresses = self
+            .address_lifetimes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .flat_map(|lifetimes| [lifetimes.valid, lifetimes.preferred]);
+        let router = self
+            .default_router
+            .map(|_| self.router_lifetime.get())
+            .into_iter();
+        let contexts = self
+            .context_lifetimes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .map(|lifetime| lifetime.remaining);
+        addresses
+            .chain(router)
+            .chain(contexts)
+            .filter(|&lifetime| lifetime != 0 && lifetime != INFINITE)
+            .min()
+            .map(|lifetime| core::cmp::min(lifetime, MAX_TICK_INTERVAL))
+    }
+
+    /// Count down the lifetimes by the whole seconds since they were last
+    /// counted down, and expire the ones that reach 0.
+    fn count_down_lifetimes(&self) {
+        let now = self.alarm.now();
+        let counted = self.counted.get();
+        let seconds = self.alarm.ticks_to_ms(now.wrapping_sub(counted)) / 1000;
+        if seconds == 0 {
+            return;
+        }
+        self.counted
+            .set(counted.wrapping_add(self.alarm.ticks_from_seconds(seconds)));
+
+        for entry in self.address_lifetimes.iter() {
+            let lifetimes = match entry.get() {
+                Some(lifetimes) => lifetimes,
+                None => continue,
+            };
+            let lifetimes = AddressLifetimes {
+                valid: count_down(lifetimes.valid, seconds),
+                preferred: count_down(lifetimes.preferred, seconds),
+                ..lifetimes
+            };
+            if lifetimes.valid == 0 {
+                self.forget(&lifetimes.addr);
+                continue;
+            }
+            entry.set(Some(lifetimes));
+            if lifetimes.preferred == 0
+                && self.addresses.state(&lifetimes.addr) == Some(AddressState::Preferred)
+            {
+                let _ = self
+                    .addresses
+                    .set_state(&lifetimes.addr, AddressState::Deprecated);
+            }
+        }
+
+        if self.default_router.is_some() {
+            let lifetime = self.router_lifetime.get().saturating_sub(seconds);
+            self.router_lifetime.set(lifetime);
+            if lifetime == 0 {
+                self.default_router.clear();
+                self.solicitations.set(0);
+                self.solicitation_delay.set(0);
+            }
+        }
+
+        self.contexts.map(|contexts| {
+            for (id, entry) in self.context_lifetimes.iter().enumerate() {
+                let lifetime = match entry.get() {
+                    Some(lifetime) => lifetime,
+                    None => continue,
+                };
+                let remaining = count_down(lifetime.remaining, seconds);
+                if remaining > 0 {
+                    entry.set(Some(ContextLifetime {
+                        remaining,
+                        ..lifetime
+                    }));
+                } else if lifetime.phasing_out {
+                    entry.set(None);
+                    // Context 0 cannot be removed, it does not compress
+                    // anymore
+                    let _ = contexts.remove_context(id as u8);
+                } else {
+                    let _ = contexts.set_compress(id as u8, false);
+                    entry.set(Some(ContextLifetime {
+                        remaining: MIN_CONTEXT_CHANGE_DELAY,
+                        phasing_out: true,
+                    }));
+                }
+            }
+        });
+    }
+
+    /// The state of a configured address once duplicate address detection
+    /// finished.
+    fn assigned_state(&self, addr: &IPAddr) -> AddressState {
+        let deprecated = self
+            .address_lifetimes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .any(|lifetimes| lifetimes.addr == *addr && lifetimes.preferred == 0);
+        if deprecated {
+            AddressState::Deprecated
+        } else {
+            AddressState::Preferred
+        }
+    }
+
+    /// Runs every second while an address is tentative or routers are
+    /// solicited, and when the next lifetime expires otherwise. Messages that
+    /// cannot be sent because another one is in progress are sent at the
+    /// next tick.
+    fn tick(&self) {
+        self.count_down_lifetimes();
+
+        for (addr, state) in self.addresses.configured() {
+            if state != AddressState::Tentative {
+                continue;
+            }
+            if self.take_probed(&addr) {
+                // The probe was sent a second ago (RetransTimer) without
+                // reply.
+                let _ = self.addresses.set_state(&addr, self.assigned_state(&addr));
+            } else if self.send_solicitation(IPAddr::new(), solicited_node(&addr), addr) == Ok(()) {
+                if let Some(probed) = self.probed.iter().find(|probed| probed.get().is_none()) {
+                    probed.set(Some(addr));
+                }
+            }
+        }
+
+        if self.soliciting() {
+            let delay = self.solicitation_delay.get();
+            if delay > 0 {
+                self.solicitation_delay.set(delay - 1);
+            } else if self.send_router_solicitation() == Ok(()) {
+                self.solicitations.set(self.solicitations.get() + 1);
+                self.solicitation_delay.set(RTR_SOLICITATION_INTERVAL - 1);
+            }
+        }
+
+        if let Some(seconds) = self.next_tick() {
+            self.schedule_tick(seconds);
+        }
+    }
+
+    fn take_probed(&self, addr: &IPAddr) -> bool {
+        self.probed
+            .iter()
+            .find(|probed| probed.get() == Some(*addr))
+            .map(|probed| probed.set(None))
+            .is_some()
+    }
+
+    /// A link-local address of the interface, as source of messages that
+    /// carry a link-layer address option.
+    fn link_local(&self) -> Option<IPAddr> {
+        self.addresses
+            .iter()
+            .find(|addr| addr.is_unicast_link_local())
+    }
+
+    /// Send a message with the ICMPv6 header `icmp_header`, and the bytes
+    /// written by `encode` after it.
+    fn send(
+        &self,
+        src: IPAddr,
+        dst: IPAddr,
+        icmp_header: ICMP6Header,
+        encode: impl FnOnce(&mut [u8]) -> usize,
+    ) -> Result<(), ErrorCode> {
+        if self.sending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.tx_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let len = encode(buffer);
+        let mut payload = LeasableMutableBuffer::new(buffer);
+        payload.slice(..len);
+
+        self.ip_sender.set_addr(src);
+        let result = self.ip_sender.send_to(
+            dst,
+            TransportHeader::ICMP(icmp_header),
+            &payload,
+            self.net_cap,
+        );
+        // The sender copied the message
+        self.tx_buffer.replace(payload.take());
+        self.sending.set(result.is_ok());
+        result
+    }
+
+    fn send_router_solicitation(&self) -> Result<(), ErrorCode> {
+        let src = self.link_local();
+        let mac_addr = self.mac_addr;
+        self.send(
+            src.unwrap_or(IPAddr::new()),
+            ALL_ROUTERS,
+            ICMP6Header::new(ICMP6Type::Type133),
+            |buf| {
+                // No link-layer address from the unspecified address
+                src.map_or(0, |_| {
+                    encode_link_layer_option(buf, OPTION_SOURCE_LINK_LAYER, mac_addr)
+                })
+            },
+        )
+    }
+
+    /// Send a Neighbor Solicitation for `target`, from the unspecified
+    /// address for duplicate address detection.
+    fn send_solicitation(&self, src: IPAddr, dst: IPAddr, target: IPAddr) -> Result<(), ErrorCode> {
+        let mac_addr = self.mac_addr;
+        self.send(src, dst, ICMP6Header::new(ICMP6Type::Type135), |buf| {
+            buf[..16].copy_from_slice(&target.0);
+            if src.is_unspecified() {
+                16
+            } else {
+                16 + encode_link_layer_option(&mut buf[16..], OPTION_SOURCE_LINK_LAYER, mac_addr)
+            }
+        })
+    }
+
+    fn send_advertisement(&self, dst: IPAddr, target: IPAddr, flags: u32) -> Result<(), ErrorCode> {
+        let src = self.addresses.source_for(&dst).unwrap_or(target);
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type136);
+        icmp_header.set_options(ICMP6HeaderOptions::Type136 { flags });
+        let mac_addr = self.mac_addr;
+        self.send(src, dst, icmp_header, |buf| {
+            buf[..16].copy_from_slice(&target.0);
+            16 + encode_link_layer_option(&mut buf[16..], OPTION_TARGET_LINK_LAYER, mac_addr)
+        })
+    }
+
+    /// Duplicate address detection found another node using `addr`.
+    fn duplicate(&self, addr: &IPAddr) {
+        debug!("IPv6 ND: duplicate address {:?}", addr.0);
+        self.forget(addr);
+    }
+
+    /// Remove the configured address `addr`.
+    fn forget(&self, addr: &IPAddr) {
+        self.take_probed(addr);
+        if let Some(entry) = self.lifetimes_entry(addr) {
+            entry.set(None);
+        }
+        let _ = self.addresses.remove(addr);
+    }
+
+    fn lifetimes_entry(&self, addr: &IPAddr) -> Option<&Cell<Option<AddressLifetimes>>> {
+        self.address_lifetimes.iter().find(|entry| {
+            entry
+                .get()
+                .map_or(false, |lifetimes| lifetimes.addr == *addr)
+        })
+    }
+
+    fn update_neighbor(&self, ip_addr: IPAddr, mac_addr: MacAddress) {
+        let neighbor = Some(Neighbor { ip_addr, mac_addr });
+        let known = self
+            .neighbors
+            .iter()
+            .find(|entry| entry.get().map_or(false, |entry| entry.ip_addr == ip_addr));
+        let free = || self.neighbors.iter().find(|entry| entry.get().is_none());
+        match known.or_else(free) {
+            Some(entry) => entry.set(neighbor),
+            None => {
+                let evicted = self.next_evicted.get();
+                self.neighbors[evicted].set(neighbor);
+                self.next_evicted.set((evicted + 1) % NEIGHBOR_CACHE_SIZE);
+            }
+        }
+        if self.default_router() == Some(ip_addr) {
+            self.default_router.set(Neighbor { ip_addr, mac_addr });
+        }
+    }
+
+    fn receive_router_advertisement(&self, src: IPAddr, router_lifetime: u16, options: &[u8]) {
+        let mac_addr = NdOptions::new(options)
+            .find_map(|option| match option {
+                NdOption::SourceLinkLayer(addr) => Some(addr),
+                _ => None,
+            })
+            .or_else(|| mac_from_link_local(&src));
+        let mac_addr = match mac_addr {
+            Some(mac_addr) => mac_addr,
+            None => return,
+        };
+        self.update_neighbor(src, mac_addr);
+        // The advertised lifetimes start now
+        self.count_down_lifetimes();
+
+        if router_lifetime > 0 {
+            self.default_router.set(Neighbor {
+                ip_addr: src,
+                mac_addr,
+            });
+            self.router_lifetime.set(router_lifetime as u32);
+        } else if self.default_router() == Some(src) {
+            self.default_router.clear();
+        }
+
+        for option in NdOptions::new(options) {
+            match option {
+                NdOption::PrefixInformation(prefix) => self.autoconfigure(&prefix),
+                NdOption::SixLowpanContext(context) => self.update_context(&context),
+                _ => {}
+            }
+        }
+
+        if let Some(seconds) = self.next_tick() {
+            self.schedule_tick(seconds);
+        }
+    }
+
+    /// Configure an address for `prefix` (RFC 4862 section 5.5.3).
+    fn autoconfigure(&self, prefix: &PrefixInformation) {
+        // Interface identifiers are 64 bits long
+        if !prefix.autonomous
+            || prefix.prefix_len != 64
+            || prefix.valid_lifetime == 0
+            || prefix.preferred_lifetime > prefix.valid_lifetime
+        {
+            return;
+        }
+        let mut addr = IPAddr::generate_from_mac(self.mac_addr);
+        addr.set_prefix(&prefix.prefix, prefix.prefix_len);
+        if addr.is_unicast_link_local() || addr.is_multicast() {
+            return;
+        }
+
+        let entry = match self.lifetimes_entry(&addr) {
+            Some(entry) => entry,
+            None => {
+                let free = self
+                    .address_lifetimes
+                    .iter()
+                    .find(|entry| entry.get().is_none());
+                match free {
+                    Some(free) if self.addresses.add(addr, AddressState::Tentative) == Ok(()) => {
+                        free.set(Some(AddressLifetimes {
+                            addr,
+                            valid: prefix.valid_lifetime,
+                            preferred: prefix.preferred_lifetime,
+                        }));
+                    }
+                    // Static, or no space for the address
+                    _ => {}
+                }
+                return;
+            }
+        };
+
+        // Refresh the lifetimes of the address
+        let remaining = entry.get().map_or(0, |lifetimes| lifetimes.valid);
+        let valid = updated_valid_lifetime(remaining, prefix.valid_lifetime);
+        let preferred = core::cmp::min(prefix.preferred_lifetime, valid);
+        entry.set(Some(AddressLifetimes {
+            addr,
+            valid,
+            preferred,
+        }));
+        match self.addresses.state(&addr) {
+            Some(AddressState::Preferred) if preferred == 0 => {
+                let _ = self.addresses.set_state(&addr, AddressState::Deprecated);
+            }
+            Some(AddressState::Deprecated) if preferred > 0 => {
+                let _ = self.addresses.set_state(&addr, AddressState::Preferred);
+            }
+            _ => {}
+        }
+    }
+
+    /// Set or remove a 6LoWPAN context (RFC 6775 section 5.4.3).
+    fn update_context(&self, context: &ContextInformation) {
+        self.contexts.map(|contexts| {
+            let entry = match self.context_lifetimes.get(context.id as usize) {
+                Some(entry) => entry,
+                None => return,
+            };
+            if context.valid_lifetime == 0 {
+                entry.set(None);
+                if contexts.remove_context(context.id).is_err() {
+                    // Context 0 cannot be removed
+                    let _ = contexts.set_compress(context.id, false);
+                }
+                return;
+            }
+            let result = contexts.set_context(Context {
+                prefix: context.prefix,
+                prefix_len: context.context_len,
+                id: context.id,
+                compress: context.compress,
+            });
+            if result == Ok(()) {
+                entry.set(Some(ContextLifetime {
+                    remaining: context.valid_lifetime as u32 * 60,
+                    phasing_out: false,
+                }));
+            }
+        });
+    }
+
+    fn receive_solicitation(&self, src: IPAddr, target: IPAddr, options: &[u8]) {
+        if src.is_unspecified() {
+            // Duplicate address detection of another node
+            if self.addresses.state(&target) == Some(AddressState::Tentative) {
+                self.duplicate(&target);
+            } else if self.addresses.contains(&target) {
+                let _ = self.send_advertisement(ALL_NODES, target, NA_OVERRIDE);
+            }
+            return;
+        }
+
+        for option in NdOptions::new(options) {
+            if let NdOption::SourceLinkLayer(mac_addr) = option {
+                self.update_neighbor(src, mac_addr);
+            }
+        }
+        if self.addresses.contains(&target) {
+            let _ = self.send_advertisement(src, target, NA_SOLICITED | NA_OVERRIDE);
+        }
+    }
+
+    fn receive_advertisement(&self, target: IPAddr, options: &[u8]) {
+        if self.addresses.state(&target) == Some(AddressState::Tentative) {
+            self.duplicate(&target);
+            return;
+        }
+        for option in NdOptions::new(options) {
+            if let NdOption::TargetLinkLayer(mac_addr) = option {
+                self.update_neighbor(target, mac_addr);
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NextHop for NeighborDiscovery<'a, A> {
+    fn next_hop(&self, dst: &IPAddr) -> Option<MacAddress> {
+        self.neighbors
+            .iter()
+            .find_map(|entry| entry.get().filter(|entry| entry.ip_addr == *dst))
+            .map(|neighbor| neighbor.mac_addr)
+            .or_else(|| mac_from_link_local(dst))
+            .or_else(|| self.default_router.map(|router| router.mac_addr))
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for NeighborDiscovery<'a, A> {
+    fn receive(&self, header: IP6Header, payload: &[u8]) {
+        // Messages from off-link are not Neighbor Discovery messages
+        // (RFC 4861 section 6.1 and 7.1)
+        if header.get_next_header() != ip6_nh::ICMP || header.get_hop_limit() != 255 {
+            return;
+        }
+        let icmp_header = match ICMP6Header::decode(payload).done() {
+            Some((_, icmp_header)) if icmp_header.get_code() == 0 => icmp_header,
+            _ => return,
+        };
+        let body = &payload[ICMP_HDR_LEN..];
+        let src = header.get_src_addr();
+        let target = || {
+            let mut target = IPAddr::new();
+            target.0.copy_from_slice(&body[..16]);
+            target
+        };
+
+        match icmp_header.get_options() {
+            ICMP6HeaderOptions::Type134 {
+                router_lifetime, ..
+            } if body.len() >= 8 && src.is_unicast_link_local() => {
+                // After the reachable time and retransmission timer
+                self.receive_router_advertisement(src, router_lifetime, &body[8..]);
+            }
+            ICMP6HeaderOptions::Type135 { .. } if body.len() >= 16 => {
+                self.receive_solicitation(src, target(), &body[16..]);
+            }
+            ICMP6HeaderOptions::Type136 { .. } if body.len() >= 16 => {
+                self.receive_advertisement(target(), &body[16..]);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for NeighborDiscovery<'a, A> {
+    fn send_done(&self, _result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for NeighborDiscovery<'a, A> {
+    fn alarm(&self) {
+        self.tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let mut buf = [0; 48];
+        let len = encode_link_layer_option(
+            &mut buf,
+            OPTION_SOURCE_LINK_LAYER,
+            MacAddress::Short(0x1234),
+        );
+        buf[len..len + 4].copy_from_slice(&[OPTION_PREFIX_INFORMATION, 4, 64, 0xc0]);
+        buf[len + 4..len + 8].copy_from_slice(&3600u32.to_be_bytes());
+        buf[len + 8..len + 12].copy_from_slice(&1800u32.to_be_bytes());
+        buf[len + 16..len + 18].copy_from_slice(&[0x20, 0x01]);
+        // A zero length option ends the options
+        buf[len + 32] = 5;
+
+        let mut options = NdOptions::new(&buf);
+        assert_eq!(
+            options.next(),
+            Some(NdOption::SourceLinkLayer(MacAddress::Short(0x1234)))
+        );
+        let mut prefix = [0; 16];
+        prefix[..2].copy_from_slice(&[0x20, 0x01]);
+        assert_eq!(
+            options.next(),
+            Some(NdOption::PrefixInformation(PrefixInformation {
+                prefix_len: 64,
+                on_link: true,
+                autonomous: true,
+                valid_lifetime: 3600,
+                preferred_lifetime: 1800,
+                prefix,
+            }))
+        );
+        assert_eq!(options.next(), None);
+    }
+
+    #[test]
+    fn context_option() {
+        let mut buf = [0; 16];
+        buf[..8].copy_from_slice(&[OPTION_6LOWPAN_CONTEXT, 2, 48, 0x13, 0, 0, 0, 10]);
+        buf[8..14].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 1]);
+
+        let mut prefix = [0; 16];
+        prefix[..6].copy_from_slice(&buf[8..14]);
+        assert_eq!(
+            NdOptions::new(&buf).next(),
+            Some(NdOption::SixLowpanContext(ContextInformation {
+                context_len: 48,
+                compress: true,
+                id: 3,
+                valid_lifetime: 10,
+                prefix,
+            }))
+        );
+    }
+
+    #[test]
+    fn valid_lifetimes() {
+        // Advertisements extend the lifetime, but shorten it to no less than
+        // two hours.
+        assert_eq!(updated_valid_lifetime(600, 3600), 3600);
+        assert_eq!(updated_valid_lifetime(600, 60), 600);
+        assert_eq!(updated_valid_lifetime(INFINITE, 60), TWO_HOURS);
+        assert_eq!(
+            updated_valid_lifetime(INFINITE, 3 * TWO_HOURS),
+            3 * TWO_HOURS
+        );
+        assert_eq!(count_down(INFINITE, 60), INFINITE);
+        assert_eq!(count_down(30, 60), 0);
+    }
+
+    #[test]
+    fn link_local_addresses() {
+        let long = MacAddress::Long([0x02, 1, 2, 3, 4, 5, 6, 7]);
+        let short = MacAddress::Short(0xbeef);
+        for mac in [long, short] {
+            let addr = IPAddr::generate_from_mac(mac);
+            assert_eq!(mac_from_link_local(&addr), Some(mac));
+        }
+        let addr = IPAddr::generate_from_mac(short);
+        assert_eq!(
+            solicited_node(&addr).0,
+            [0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0, 0xbe, 0xef]
+        );
+    }
+}
@@ -2,6 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+use crate::net::ipv6::ip_utils::ip6_nh;
+use crate::net::ipv6::ipv6_interface::InterfaceAddresses;
 use crate::net::ipv6::IP6Header;
 use crate::net::sixlowpan::sixlowpan_state::SixlowpanRxClient;
 
@@ -36,6 +38,15 @@ pub trait IP6RecvClient {
     fn receive(&self, header: IP6Header, payload: &[u8]);
 }
 
+/// Receives the packets that are not addressed to the interface, e.g. a
+/// routing protocol that sends them on towards their destination.
+pub trait IP6Forwarder {
+    fn forward(&self, header: IP6Header, payload: &[u8]);
+}
+
+/// Number of clients receiving ICMPv6 packets.
+pub const MAX_ICMP_CLIENTS: usize = 2;
+
 /// Currently only one implementation of this trait should exist,
 /// as we do not multiplex received packets based on the address.
 /// The receiver receives IP packets destined for any local address.
@@ -43,22 +54,49 @@ pub trait IP6RecvClient {
 /// that are not among the local addresses of this device.
 pub trait IP6Receiver<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient);
+
+    /// Add a client receiving ICMPv6 packets, e.g. neighbor discovery. Each
+    /// of them receives every ICMPv6 packet, and ignores the messages it does
+    /// not handle. Without one, ICMPv6 packets are passed to the client set
+    /// with `set_client()`. Returns `NOMEM` if `MAX_ICMP_CLIENTS` were added.
+    fn add_icmp_client(&self, client: &'a dyn IP6RecvClient) -> Result<(), ErrorCode>;
+
+    /// Pass the unicast packets whose destination is not one of `addresses`
+    /// to `forwarder`, instead of the clients. Packets to link-local
+    /// addresses of other nodes are dropped.
+    fn set_forwarder(&self, addresses: &'a InterfaceAddresses, forwarder: &'a dyn IP6Forwarder);
 }
 
 pub struct IP6RecvStruct<'a> {
     client: OptionalCell<&'a dyn IP6RecvClient>,
+    icmp_clients: [OptionalCell<&'a dyn IP6RecvClient>; MAX_ICMP_CLIENTS],
+    forwarding: OptionalCell<(&'a InterfaceAddresses, &'a dyn IP6Forwarder)>,
 }
 
 impl<'a> IP6Receiver<'a> for IP6RecvStruct<'a> {
     fn set_client(&self, client: &'a dyn IP6RecvClient) {
         self.client.set(client);
     }
+
+    fn add_icmp_client(&self, client: &'a dyn IP6RecvClient) -> Result<(), ErrorCode> {
+        let free = self.icmp_clients.iter().find(|entry| entry.is_none());
+        free.map_or(Err(ErrorCode::NOMEM), |entry| {
+            entry.set(client);
+            Ok(())
+        })
+    }
+
+    fn set_forwarder(&self, addresses: &'a InterfaceAddresses, forwarder: &'a dyn IP6Forwarder) {
+        self.forwarding.set((addresses, forwarder));
+    }
 }
 
 impl<'a> IP6RecvStruct<'a> {
     pub fn new() -> IP6RecvStruct<'a> {
         IP6RecvStruct {
             client: OptionalCell::empty(),
+            icmp_clients: Default::default(),
+            forwarding: OptionalCell::empty(),
         }
     }
 }
@@ -79,8 +117,26 @@ impl<'a> SixlowpanRxClient for IP6RecvStruct<'a> {
                 // Note: Protocols for which checksum verification is not implemented (TCP, etc.)
                 // are automatically assumed as fine, rather than dropped
 
-                self.client
-                    .map(|client| client.receive(ip6_header, &buf[offset..len]));
+                let payload = &buf[offset..len];
+                let dst = ip6_header.get_dst_addr();
+                if let Some((addresses, forwarder)) = self.forwarding.extract() {
+                    if !dst.is_multicast() && !addresses.contains(&dst) {
+                        if !dst.is_unicast_link_local() {
+                            forwarder.forward(ip6_header, payload);
+                        }
+                        return;
+                    }
+                }
+
+                let icmp = ip6_header.get_next_header() == ip6_nh::ICMP;
+                if icmp && self.icmp_clients.iter().any(|client| client.is_some()) {
+                    for client in self.icmp_clients.iter() {
+                        client.map(|client| client.receive(ip6_header, payload));
+                    }
+                } else {
+                    self.client
+                        .map(|client| client.receive(ip6_header, payload));
+                }
             }
             None => {
                 debug!("failed to decode ipv6 header");
@@ -21,11 +21,15 @@
 // interface.
 
 use crate::ieee802154::device::{MacDevice, TxClient};
+use crate::net::icmpv6::ICMP6Header;
 use crate::net::ieee802154::MacAddress;
-use crate::net::ipv6::ip_utils::IPAddr;
-use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_interface::InterfaceAddresses;
+use crate::net::ipv6::ipv6_nd::NextHop;
+use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader, UDP_HDR_LEN};
 use crate::net::network_capabilities::{IpVisibilityCapability, NetworkCapability};
 use crate::net::sixlowpan::sixlowpan_state::TxState;
+use crate::net::udp::UDPHeader;
 
 use core::cell::Cell;
 
@@ -92,6 +96,17 @@ pub trait IP6Sender<'a> {
         payload: &LeasableMutableBuffer<'static, u8>,
         net_cap: &'static NetworkCapability,
     ) -> Result<(), ErrorCode>;
+
+    /// This method sends a received packet on towards its destination, with
+    /// the IPv6 header `header`. Unlike `send_to`, the header is not built
+    /// by the sender, and the transport checksum is not recomputed.
+    ///
+    /// # Arguments
+    /// `header` - The `IP6Header` of the packet, with the hop limit already
+    /// decremented
+    /// `payload` - The transport header and payload of the packet, only UDP
+    /// and ICMPv6 packets are supported
+    fn forward(&self, header: IP6Header, payload: &[u8]) -> Result<(), ErrorCode>;
 }
 
 /// This struct is a specific implementation of the `IP6Sender` trait. This
@@ -107,10 +122,14 @@ pub struct IP6SendStruct<'a, A: time::Alarm<'a>> {
     tx_buf: TakeCell<'static, [u8]>,
     sixlowpan: TxState<'a>,
     radio: &'a dyn MacDevice<'a>,
-    dst_mac_addr: MacAddress,
     src_mac_addr: MacAddress,
     client: OptionalCell<&'a dyn IP6SendClient>,
     ip_vis: &'static IpVisibilityCapability,
+    // Resolves the destination MAC address, the gateway is used if it does
+    // not know one
+    next_hop: OptionalCell<&'a dyn NextHop>,
+    // If set, the source address is selected from these addresses
+    addresses: OptionalCell<&'a InterfaceAddresses>,
 }
 
 impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
@@ -143,7 +162,7 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         }
         let _ = self.sixlowpan.init(
             self.src_mac_addr,
-            self.dst_mac_addr,
+            self.dst_mac_addr(&dst),
             self.radio.get_pan(),
             None,
         );
@@ -151,6 +170,43 @@ impl<'a, A: time::Alarm<'a>> IP6Sender<'a> for IP6SendStruct<'a, A> {
         let ret = self.send_next_fragment();
         ret
     }
+
+    fn forward(&self, header: IP6Header, payload: &[u8]) -> Result<(), ErrorCode> {
+        let transport_header = match header.get_next_header() {
+            ip6_nh::UDP => UDPHeader::decode(payload)
+                .done()
+                .map(|(_, udp_header)| TransportHeader::UDP(udp_header)),
+            ip6_nh::ICMP => ICMP6Header::decode(payload)
+                .done()
+                .map(|(_, mut icmp_header)| {
+                    icmp_header.set_len(payload.len() as u16);
+                    TransportHeader::ICMP(icmp_header)
+                }),
+            _ => None,
+        }
+        .ok_or(ErrorCode::NOSUPPORT)?;
+        // Both headers are 8 bytes long
+        let body = &payload[UDP_HDR_LEN..];
+        self.ip6_packet
+            .map_or(Err(ErrorCode::NOMEM), |ip6_packet| {
+                if body.len() > ip6_packet.payload.payload.len() {
+                    return Err(ErrorCode::SIZE);
+                }
+                ip6_packet.payload.payload[..body.len()].copy_from_slice(body);
+                ip6_packet.payload.header = transport_header;
+                ip6_packet.header = header;
+                Ok(())
+            })?;
+
+        let dst = header.get_dst_addr();
+        let _ = self.sixlowpan.init(
+            self.src_mac_addr,
+            self.dst_mac_addr(&dst),
+            self.radio.get_pan(),
+            None,
+        );
+        self.send_next_fragment()
+    }
 }
 
 impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
@@ -172,13 +228,38 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             tx_buf: TakeCell::new(tx_buf),
             sixlowpan: sixlowpan,
             radio: radio,
-            dst_mac_addr: dst_mac_addr,
             src_mac_addr: src_mac_addr,
             client: OptionalCell::empty(),
             ip_vis: ip_vis,
+            next_hop: OptionalCell::empty(),
+            addresses: OptionalCell::empty(),
         }
     }
 
+    /// Resolve the MAC address packets are sent to with `next_hop`, e.g.
+    /// neighbor discovery. Packets to destinations it does not know are sent
+    /// to the gateway.
+    pub fn set_next_hop(&self, next_hop: &'a dyn NextHop) {
+        self.next_hop.set(next_hop);
+    }
+
+    /// The MAC address a packet to `dst` is sent to.
+    fn dst_mac_addr(&self, dst: &IPAddr) -> MacAddress {
+        if dst.is_multicast() {
+            MacAddress::Short(0xffff)
+        } else {
+            self.next_hop
+                .map_or(None, |next_hop| next_hop.next_hop(dst))
+                .unwrap_or(self.gateway.get())
+        }
+    }
+
+    /// Select the source address of every packet from `addresses` instead of
+    /// using the address set with `set_addr()`.
+    pub fn set_interface_addresses(&self, addresses: &'a InterfaceAddresses) {
+        self.addresses.set(addresses);
+    }
+
     fn init_packet(
         &self,
         dst_addr: IPAddr,
@@ -191,7 +272,10 @@ impl<'a, A: time::Alarm<'a>> IP6SendStruct<'a, A> {
             },
             |ip6_packet| {
                 ip6_packet.header = IP6Header::default();
-                ip6_packet.header.src_addr = self.src_addr.get();
+                ip6_packet.header.src_addr = self
+                    .addresses
+                    .map_or(None, |addresses| addresses.source_for(&dst_addr))
+                    .unwrap_or(self.src_addr.get());
                 ip6_packet.header.dst_addr = dst_addr;
                 ip6_packet.set_payload(transport_header, payload);
                 ip6_packet.set_transport_checksum();
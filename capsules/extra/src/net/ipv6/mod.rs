@@ -3,8 +3,11 @@
 // Copyright Tock Contributors 2022.
 
 pub mod ip_utils;
+pub mod ipv6_interface;
+pub mod ipv6_nd;
 pub mod ipv6_recv;
 pub mod ipv6_send;
+pub mod rpl;
 
 // Reexport the exports of the [`ipv6`] module, to avoid redundant
 // module paths (e.g. `capsules::net::ipv6::ipv6::IP6Header`)
@@ -0,0 +1,1721 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! RPL, the IPv6 Routing Protocol for Low-Power and Lossy Networks (RFC
+//! 6550), in storing mode.
+//!
+//! Nodes organize themselves in a DODAG (Destination-Oriented Directed
+//! Acyclic Graph) rooted at a border router. The root advertises the DODAG
+//! in DIO messages, which nodes that joined it repeat with their own rank,
+//! paced by a Trickle timer (RFC 6206). A node selects the neighbor with the
+//! lowest rank as preferred parent, with the Objective Function Zero (RFC
+//! 6552): its rank is the rank of the parent plus three times the
+//! `MinHopRankIncrease` of the DODAG. Nodes that did not join a DODAG solicit
+//! DIOs with DIS messages.
+//!
+//! The preferred parent is the default route upwards. Downward routes are
+//! learned from DAO messages: every node reports its global addresses and
+//! the routes of its sub-DODAG to its preferred parent, which acknowledges
+//! them, keeps them for their advertised lifetime and reports them on
+//! towards the root. Nodes configure an address from the Prefix Information
+//! of DIOs, without duplicate address detection.
+//!
+//! `Rpl` is the `NextHop` of the IPv6 senders: packets are sent along a
+//! downward route to the destination, to the preferred parent, or to the
+//! next hop neighbor discovery resolves. Received packets to other nodes are
+//! forwarded the same way, while the hop limit lasts. One instance of a
+//! single DODAG is joined. RPL Packet Information (RFC 6553) is not added to
+//! forwarded packets, so loops are not detected on the data path. Routes
+//! through a former parent are not withdrawn, they expire with their
+//! lifetime.
+//!
+//! Usage
+//! -----
+//! The UDP mux component sets up RPL on the same interface. A border router
+//! starts it as root, with one of its global addresses as DODAG ID, and the
+//! other nodes join the DODAG:
+//!
+//! ```rust
+//! let (udp_send_mux, udp_recv_mux, udp_port_table, ipv6_nd, rpl) = UDPMuxComponent::new(
+//!     // ...
+//! )
+//! .finalize(components::udp_mux_component_static!(nrf52840::rtc::Rtc));
+//! ipv6_nd.start().unwrap();
+//! rpl.start().unwrap();
+//! // or on the border router:
+//! // rpl.start_root(RPL_INSTANCE_ID, local_ip_ifaces[0]).unwrap();
+//! ```
+
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ieee802154::MacAddress;
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_interface::{AddressState, InterfaceAddresses};
+use crate::net::ipv6::ipv6_nd::{mac_from_link_local, NextHop, PrefixInformation};
+use crate::net::ipv6::ipv6_recv::{IP6Forwarder, IP6RecvClient};
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+/// Number of parent candidates.
+pub const MAX_PARENTS: usize = 4;
+
+/// Number of downward routes.
+pub const ROUTE_TABLE_SIZE: usize = 8;
+
+/// Length of the longest message sent after the type, code and checksum of
+/// its ICMPv6 header: a DIO with a DODAG Configuration and a Prefix
+/// Information option, or a DAO with three targets.
+pub const RPL_PAYLOAD_LEN: usize = 96;
+
+/// The rank of nodes not in a DODAG.
+pub const INFINITE_RANK: u16 = 0xffff;
+
+const ALL_RPL_NODES: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x1a]);
+
+// Codes of the RPL control messages
+const DIS: u8 = 0x00;
+const DIO: u8 = 0x01;
+const DAO: u8 = 0x02;
+const DAO_ACK: u8 = 0x03;
+
+// Modes of operation
+const MOP_NO_DOWNWARD_ROUTES: u8 = 0;
+const MOP_STORING: u8 = 2;
+
+const OCP_OF0: u16 = 0;
+/// Rank increase per hop of OF0 (RFC 6552), in units of
+/// `MinHopRankIncrease`: the default step of rank, with a rank factor of 1
+/// and no stretch.
+const STEP_OF_RANK: u32 = 3;
+
+const OPTION_PAD1: u8 = 0x00;
+const OPTION_DODAG_CONFIGURATION: u8 = 0x04;
+const OPTION_TARGET: u8 = 0x05;
+const OPTION_TRANSIT: u8 = 0x06;
+const OPTION_PREFIX_INFORMATION: u8 = 0x08;
+
+const DIO_GROUNDED: u8 = 0x80;
+const DAO_ACK_REQUESTED: u8 = 0x80;
+const DAO_DODAG_ID: u8 = 0x40;
+const PREFIX_ON_LINK: u8 = 0x80;
+const PREFIX_AUTONOMOUS: u8 = 0x40;
+
+const DAO_ACK_ACCEPTED: u8 = 0;
+const DAO_ACK_REJECTED: u8 = 128;
+
+/// Initial value of lollipop counters (RFC 6550 section 7.2).
+const SEQUENCE_INIT: u8 = 240;
+const SEQUENCE_WINDOW: u16 = 16;
+
+/// A path lifetime of `INFINITE_PATH_LIFETIME` lifetime units does not
+/// expire.
+const INFINITE_PATH_LIFETIME: u8 = 0xff;
+/// A lifetime of `INFINITE` seconds does not expire.
+const INFINITE: u32 = u32::MAX;
+
+// Timers, in seconds
+const DIS_INTERVAL: u32 = 10;
+const DAO_DELAY: u32 = 1;
+const DAO_ACK_TIMEOUT: u32 = 4;
+const MAX_DAO_RETRIES: u8 = 3;
+/// Longest interval of the timer counting down lifetimes.
+const MAX_TICK_INTERVAL: u32 = 60;
+
+/// The parameters of a DODAG, carried in the DODAG Configuration option.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DodagConfig {
+    pub dio_interval_doublings: u8,
+    /// The shortest Trickle interval is `2^dio_interval_min` milliseconds.
+    pub dio_interval_min: u8,
+    pub dio_redundancy: u8,
+    pub max_rank_increase: u16,
+    pub min_hop_rank_increase: u16,
+    pub objective_code_point: u16,
+    /// Lifetime of routes, in units of `lifetime_unit` seconds.
+    pub default_lifetime: u8,
+    pub lifetime_unit: u16,
+}
+
+/// The configuration a root advertises. Like other embedded implementations,
+/// the shortest Trickle interval is 4 seconds rather than the 8 milliseconds
+/// of RFC 6550, and routes live for 30 minutes.
+pub const DEFAULT_CONFIG: DodagConfig = DodagConfig {
+    dio_interval_doublings: 8,
+    dio_interval_min: 12,
+    dio_redundancy: 10,
+    max_rank_increase: 7 * 256,
+    min_hop_rank_increase: 256,
+    objective_code_point: OCP_OF0,
+    default_lifetime: 30,
+    lifetime_unit: 60,
+};
+
+/// The base of a DIO message.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Dio {
+    pub instance_id: u8,
+    pub version: u8,
+    pub rank: u16,
+    pub grounded: bool,
+    pub mode_of_operation: u8,
+    pub preference: u8,
+    pub dtsn: u8,
+    pub dodag_id: IPAddr,
+}
+
+impl Dio {
+    pub const LEN: usize = 24;
+
+    pub fn decode(buf: &[u8]) -> Option<Dio> {
+        if buf.len() < Self::LEN {
+            return None;
+        }
+        let mut dodag_id = IPAddr::new();
+        dodag_id.0.copy_from_slice(&buf[8..24]);
+        Some(Dio {
+            instance_id: buf[0],
+            version: buf[1],
+            rank: u16::from_be_bytes([buf[2], buf[3]]),
+            grounded: buf[4] & DIO_GROUNDED != 0,
+            mode_of_operation: (buf[4] >> 3) & 0x07,
+            preference: buf[4] & 0x07,
+            dtsn: buf[5],
+            dodag_id,
+        })
+    }
+
+    /// Write the DIO base into `buf`, and return its length.
+    pub fn encode(&self, buf: &mut [u8]) -> usize {
+        buf[0] = self.instance_id;
+        buf[1] = self.version;
+        buf[2..4].copy_from_slice(&self.rank.to_be_bytes());
+        buf[4] = if self.grounded { DIO_GROUNDED } else { 0 }
+            | (self.mode_of_operation & 0x07) << 3
+            | self.preference & 0x07;
+        buf[5] = self.dtsn;
+        // Flags and reserved
+        buf[6] = 0;
+        buf[7] = 0;
+        buf[8..24].copy_from_slice(&self.dodag_id.0);
+        Self::LEN
+    }
+}
+
+/// An option of an RPL control message.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum RplOption {
+    DodagConfiguration(DodagConfig),
+    /// An RPL Target, the bits of the prefix after `prefix_len` are 0.
+    Target {
+        prefix_len: u8,
+        prefix: IPAddr,
+    },
+    /// A Transit Information option, for the targets before it.
+    Transit {
+        path_sequence: u8,
+        path_lifetime: u8,
+    },
+    PrefixInformation(PrefixInformation),
+    /// An option not handled here, with its type.
+    Other(u8),
+}
+
+/// Iterator over the options of an RPL control message. It stops at the
+/// first malformed option.
+pub struct RplOptions<'b> {
+    buf: &'b [u8],
+}
+
+impl<'b> RplOptions<'b> {
+    pub fn new(buf: &'b [u8]) -> RplOptions<'b> {
+        RplOptions { buf }
+    }
+}
+
+impl Iterator for RplOptions<'_> {
+    type Item = RplOption;
+
+    fn next(&mut self) -> Option<RplOption> {
+        let option_type = *self.buf.first()?;
+        if option_type == OPTION_PAD1 {
+            // The only option without a length
+            self.buf = &self.buf[1..];
+            return Some(RplOption::Other(OPTION_PAD1));
+        }
+        // The length does not include the type and length
+        let len = match self.buf.get(1) {
+            Some(&len) if 2 + len as usize <= self.buf.len() => 2 + len as usize,
+            _ => {
+                self.buf = &[];
+                return None;
+            }
+        };
+        let (option, rest) = self.buf.split_at(len);
+        self.buf = rest;
+
+        let u16_at = |index: usize| u16::from_be_bytes([option[index], option[index + 1]]);
+        let u32_at = |index: usize| {
+            u32::from_be_bytes([
+                option[index],
+                option[index + 1],
+                option[index + 2],
+                option[index + 3],
+            ])
+        };
+        Some(match option_type {
+            OPTION_DODAG_CONFIGURATION if len >= 16 => RplOption::DodagConfiguration(DodagConfig {
+                dio_interval_doublings: option[3],
+                dio_interval_min: option[4],
+                dio_redundancy: option[5],
+                max_rank_increase: u16_at(6),
+                min_hop_rank_increase: u16_at(8),
+                objective_code_point: u16_at(10),
+                default_lifetime: option[13],
+                lifetime_unit: u16_at(14),
+            }),
+            OPTION_TARGET if len >= 4 && option[3] <= 128 => {
+                let prefix_len = option[3];
+                let bytes = (prefix_len as usize + 7) / 8;
+                if 4 + bytes > len {
+                    return Some(RplOption::Other(option_type));
+                }
+                let mut prefix = IPAddr::new();
+                prefix.set_prefix(&option[4..4 + bytes], prefix_len);
+                RplOption::Target { prefix_len, prefix }
+            }
+            OPTION_TRANSIT if len >= 6 => RplOption::Transit {
+                path_sequence: option[4],
+                path_lifetime: option[5],
+            },
+            OPTION_PREFIX_INFORMATION if len == 32 => {
+                let mut prefix = [0; 16];
+                prefix.copy_from_slice(&option[16..32]);
+                RplOption::PrefixInformation(PrefixInformation {
+                    prefix_len: option[2],
+                    on_link: option[3] & PREFIX_ON_LINK != 0,
+                    autonomous: option[3] & PREFIX_AUTONOMOUS != 0,
+                    valid_lifetime: u32_at(4),
+                    preferred_lifetime: u32_at(8),
+                    prefix,
+                })
+            }
+            _ => RplOption::Other(option_type),
+        })
+    }
+}
+
+/// Write a DODAG Configuration option into `buf`, and return its length.
+fn encode_dodag_configuration(buf: &mut [u8], config: &DodagConfig) -> usize {
+    buf[..16].fill(0);
+    buf[0] = OPTION_DODAG_CONFIGURATION;
+    buf[1] = 14;
+    buf[3] = config.dio_interval_doublings;
+    buf[4] = config.dio_interval_min;
+    buf[5] = config.dio_redundancy;
+    buf[6..8].copy_from_slice(&config.max_rank_increase.to_be_bytes());
+    buf[8..10].copy_from_slice(&config.min_hop_rank_increase.to_be_bytes());
+    buf[10..12].copy_from_slice(&config.objective_code_point.to_be_bytes());
+    buf[13] = config.default_lifetime;
+    buf[14..16].copy_from_slice(&config.lifetime_unit.to_be_bytes());
+    16
+}
+
+/// Write a Prefix Information option into `buf`, and return its length.
+fn encode_prefix_information(buf: &mut [u8], prefix: &PrefixInformation) -> usize {
+    buf[..32].fill(0);
+    buf[0] = OPTION_PREFIX_INFORMATION;
+    buf[1] = 30;
+    buf[2] = prefix.prefix_len;
+    buf[3] = if prefix.on_link { PREFIX_ON_LINK } else { 0 }
+        | if prefix.autonomous {
+            PREFIX_AUTONOMOUS
+        } else {
+            0
+        };
+    buf[4..8].copy_from_slice(&prefix.valid_lifetime.to_be_bytes());
+    buf[8..12].copy_from_slice(&prefix.preferred_lifetime.to_be_bytes());
+    buf[16..32].copy_from_slice(&prefix.prefix);
+    32
+}
+
+/// The length of an RPL Target option for a prefix of `prefix_len` bits.
+fn target_len(prefix_len: u8) -> usize {
+    4 + (prefix_len as usize + 7) / 8
+}
+
+/// Write an RPL Target option into `buf`, and return its length.
+fn encode_target(buf: &mut [u8], prefix: &IPAddr, prefix_len: u8) -> usize {
+    let len = target_len(prefix_len);
+    buf[0] = OPTION_TARGET;
+    buf[1] = (len - 2) as u8;
+    buf[2] = 0;
+    buf[3] = prefix_len;
+    buf[4..len].copy_from_slice(&prefix.0[..len - 4]);
+    len
+}
+
+const TRANSIT_LEN: usize = 6;
+
+/// Write a Transit Information option into `buf`, and return its length.
+fn encode_transit(buf: &mut [u8], path_sequence: u8, path_lifetime: u8) -> usize {
+    buf[..TRANSIT_LEN].copy_from_slice(&[
+        OPTION_TRANSIT,
+        (TRANSIT_LEN - 2) as u8,
+        0,
+        0,
+        path_sequence,
+        path_lifetime,
+    ]);
+    TRANSIT_LEN
+}
+
+/// The lollipop counter after `sequence` (RFC 6550 section 7.2).
+fn lollipop_increment(sequence: u8) -> u8 {
+    match sequence {
+        127 | 255 => 0,
+        _ => sequence + 1,
+    }
+}
+
+/// Whether the lollipop counter `a` is greater than `b` (RFC 6550 section
+/// 7.2).
+fn lollipop_greater(a: u8, b: u8) -> bool {
+    let (wide_a, wide_b) = (a as u16, b as u16);
+    match (a > 127, b > 127) {
+        (true, false) => 256 + wide_b - wide_a > SEQUENCE_WINDOW,
+        (false, true) => 256 + wide_a - wide_b <= SEQUENCE_WINDOW,
+        (true, true) => a > b,
+        // Serial number arithmetic with 7 bits
+        (false, false) => {
+            let difference = a.wrapping_sub(b) & 0x7f;
+            difference != 0 && difference < 64
+        }
+    }
+}
+
+/// The rank of a node whose preferred parent has `parent_rank`, with OF0.
+pub fn of0_rank(parent_rank: u16, min_hop_rank_increase: u16) -> u16 {
+    let rank = parent_rank as u32 + STEP_OF_RANK * min_hop_rank_increase as u32;
+    core::cmp::min(rank, INFINITE_RANK as u32) as u16
+}
+
+/// Whether the first `prefix_len` bits of `addr` are `prefix`.
+fn matches_prefix(addr: &IPAddr, prefix: &IPAddr, prefix_len: u8) -> bool {
+    let mut masked = *addr;
+    masked.0[(prefix_len as usize + 7) / 8..].fill(0);
+    if prefix_len % 8 != 0 {
+        masked.0[prefix_len as usize / 8] &= 0xff << (8 - prefix_len % 8);
+    }
+    masked == *prefix
+}
+
+/// A Trickle timer (RFC 6206), in milliseconds.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Trickle {
+    imin_ms: u32,
+    imax_ms: u32,
+    redundancy: u8,
+    interval_ms: u32,
+    t_ms: u32,
+    counter: u8,
+    /// The timer runs at `t`, not at the end of the interval.
+    before_t: bool,
+}
+
+impl Trickle {
+    pub fn new(config: &DodagConfig) -> Trickle {
+        let imin_ms = 1u32 << core::cmp::min(config.dio_interval_min, 31);
+        let imax_ms = (imin_ms as u64) << core::cmp::min(config.dio_interval_doublings, 32);
+        Trickle {
+            imin_ms,
+            imax_ms: core::cmp::min(imax_ms, u32::MAX as u64) as u32,
+            redundancy: config.dio_redundancy,
+            interval_ms: imin_ms,
+            t_ms: 0,
+            counter: 0,
+            before_t: false,
+        }
+    }
+
+    /// Start over with the shortest interval. Returns the milliseconds until
+    /// the timer runs.
+    pub fn reset(&mut self, random: u32) -> u32 {
+        self.begin(self.imin_ms, random)
+    }
+
+    /// An inconsistency was heard. Returns the milliseconds until the timer
+    /// runs if the timer is reset, which it is unless the interval is the
+    /// shortest already.
+    pub fn inconsistent(&mut self, random: u32) -> Option<u32> {
+        if self.interval_ms == self.imin_ms {
+            None
+        } else {
+            Some(self.reset(random))
+        }
+    }
+
+    /// A consistent transmission was heard.
+    pub fn consistent(&mut self) {
+        self.counter = self.counter.saturating_add(1);
+    }
+
+    /// The timer ran. Returns whether to transmit, and the milliseconds until
+    /// the timer runs next.
+    pub fn fire(&mut self, random: u32) -> (bool, u32) {
+        if self.before_t {
+            self.before_t = false;
+            let transmit = self.redundancy == 0 || self.counter < self.redundancy;
+            (transmit, self.interval_ms - self.t_ms)
+        } else {
+            let doubled = core::cmp::min(self.interval_ms.saturating_mul(2), self.imax_ms);
+            (false, self.begin(doubled, random))
+        }
+    }
+
+    pub fn interval_ms(&self) -> u32 {
+        self.interval_ms
+    }
+
+    fn begin(&mut self, interval_ms: u32, random: u32) -> u32 {
+        // t is in [I/2, I)
+        let half = interval_ms / 2;
+        self.interval_ms = interval_ms;
+        self.t_ms = half + random % core::cmp::max(interval_ms - half, 1);
+        self.counter = 0;
+        self.before_t = true;
+        self.t_ms
+    }
+}
+
+/// The DODAG joined, or rooted at this node.
+#[derive(Copy, Clone)]
+struct Dodag {
+    instance_id: u8,
+    dodag_id: IPAddr,
+    version: u8,
+    mode_of_operation: u8,
+    grounded: bool,
+    preference: u8,
+    /// Destination Advertisement Trigger Sequence Number of this node.
+    dtsn: u8,
+    config: DodagConfig,
+    rank: u16,
+    /// The lowest rank of this node in this version of the DODAG.
+    lowest_rank: u16,
+    prefix: Option<PrefixInformation>,
+}
+
+#[derive(Copy, Clone)]
+struct Parent {
+    ip_addr: IPAddr,
+    mac_addr: MacAddress,
+    rank: u16,
+    dtsn: u8,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RouteState {
+    /// Not reported to the preferred parent since it changed.
+    Unreported,
+    Reported,
+    /// A child withdrew the route, which is reported as No-Path and removed.
+    Withdrawn,
+}
+
+#[derive(Copy, Clone)]
+struct Route {
+    prefix: IPAddr,
+    prefix_len: u8,
+    next_hop: MacAddress,
+    /// Remaining lifetime, in seconds.
+    lifetime: u32,
+    state: RouteState,
+}
+
+/// A time the alarm runs at: `dt` after `reference`.
+#[derive(Copy, Clone)]
+struct Deadline<T: Ticks> {
+    reference: T,
+    dt: T,
+}
+
+impl<T: Ticks> Deadline<T> {
+    fn remaining(&self, now: T) -> T {
+        let elapsed = now.wrapping_sub(self.reference);
+        if elapsed >= self.dt {
+            T::from(0)
+        } else {
+            self.dt.wrapping_sub(elapsed)
+        }
+    }
+}
+
+pub struct Rpl<'a, A: time::Alarm<'a>> {
+    alarm: &'a A,
+    ip_sender: &'a dyn IP6Sender<'a>,
+    net_cap: &'static NetworkCapability,
+    addresses: &'a InterfaceAddresses,
+    mac_addr: MacAddress,
+    /// Message after the type, code and checksum of the ICMPv6 header.
+    tx_buffer: TakeCell<'static, [u8]>,
+    sending: Cell<bool>,
+    neighbors: OptionalCell<&'a dyn NextHop>,
+    started: Cell<bool>,
+    root: Cell<bool>,
+    dodag: OptionalCell<Dodag>,
+    parents: [Cell<Option<Parent>>; MAX_PARENTS],
+    preferred_parent: OptionalCell<IPAddr>,
+    routes: [Cell<Option<Route>>; ROUTE_TABLE_SIZE],
+    trickle: Cell<Trickle>,
+    /// The address configured from the Prefix Information of the DODAG.
+    configured_addr: OptionalCell<IPAddr>,
+    dao_sequence: Cell<u8>,
+    /// Sequence number of the DAO waiting for its acknowledgement.
+    dao_pending: OptionalCell<u8>,
+    dao_retries: Cell<u8>,
+    /// Seconds until a DAO is sent.
+    dao_delay: OptionalCell<u32>,
+    /// Seconds until the next DIS.
+    dis_delay: Cell<u32>,
+    trickle_timer: OptionalCell<Deadline<A::Ticks>>,
+    tick_timer: OptionalCell<Deadline<A::Ticks>>,
+    /// Time up to which the lifetimes were counted down.
+    counted: Cell<A::Ticks>,
+    random: Cell<u32>,
+}
+
+impl<'a, A: time::Alarm<'a>> Rpl<'a, A> {
+    /// `ip_sender` must not be used by others, the source address of its
+    /// packets is set for every message. `tx_buffer` must be at least
+    /// `RPL_PAYLOAD_LEN` bytes long.
+    pub fn new(
+        alarm: &'a A,
+        ip_sender: &'a dyn IP6Sender<'a>,
+        net_cap: &'static NetworkCapability,
+        addresses: &'a InterfaceAddresses,
+        mac_addr: MacAddress,
+        tx_buffer: &'static mut [u8],
+    ) -> Rpl<'a, A> {
+        // Seed the jitter of the timers, which only has to differ between
+        // neighbors
+        let seed = match mac_addr {
+            MacAddress::Short(short) => short as u32,
+            MacAddress::Long(long) => u32::from_be_bytes([long[4], long[5], long[6], long[7]]),
+        };
+        Rpl {
+            alarm,
+            ip_sender,
+            net_cap,
+            addresses,
+            mac_addr,
+            tx_buffer: TakeCell::new(tx_buffer),
+            sending: Cell::new(false),
+            neighbors: OptionalCell::empty(),
+            started: Cell::new(false),
+            root: Cell::new(false),
+            dodag: OptionalCell::empty(),
+            parents: Default::default(),
+            preferred_parent: OptionalCell::empty(),
+            routes: Default::default(),
+            trickle: Cell::new(Trickle::new(&DEFAULT_CONFIG)),
+            configured_addr: OptionalCell::empty(),
+            dao_sequence: Cell::new(SEQUENCE_INIT),
+            dao_pending: OptionalCell::empty(),
+            dao_retries: Cell::new(0),
+            dao_delay: OptionalCell::empty(),
+            dis_delay: Cell::new(0),
+            trickle_timer: OptionalCell::empty(),
+            tick_timer: OptionalCell::empty(),
+            counted: Cell::new(A::Ticks::from(0)),
+            random: Cell::new(seed | 1),
+        }
+    }
+
+    /// Resolve the next hop to link-local destinations, and to destinations
+    /// without a route, with `neighbors`, e.g. neighbor discovery.
+    pub fn set_neighbors(&self, neighbors: &'a dyn NextHop) {
+        self.neighbors.set(neighbors);
+    }
+
+    /// Join a DODAG, soliciting DIOs until one is heard. Returns `ALREADY` if
+    /// started before.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.started.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.started.set(true);
+        self.counted.set(self.alarm.now());
+        self.dis_delay.set(0);
+        self.schedule_tick(1);
+        Ok(())
+    }
+
+    /// Root a DODAG of `instance_id` at this node, with `DEFAULT_CONFIG`. The
+    /// DODAG ID `dodag_id` must be a global address of the interface, nodes
+    /// configure addresses in its /64 prefix.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The DODAG is advertised.
+    /// - `ALREADY`: RPL was started before.
+    /// - `INVAL`: `dodag_id` is not a global address of the interface.
+    pub fn start_root(&self, instance_id: u8, dodag_id: IPAddr) -> Result<(), ErrorCode> {
+        if self.started.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if dodag_id.is_unicast_link_local() || !self.addresses.contains(&dodag_id) {
+            return Err(ErrorCode::INVAL);
+        }
+        let mut prefix = [0; 16];
+        prefix[..8].copy_from_slice(&dodag_id.0[..8]);
+        let config = DEFAULT_CONFIG;
+        self.started.set(true);
+        self.root.set(true);
+        self.counted.set(self.alarm.now());
+        self.dodag.set(Dodag {
+            instance_id,
+            dodag_id,
+            version: SEQUENCE_INIT,
+            mode_of_operation: MOP_STORING,
+            grounded: true,
+            preference: 0,
+            dtsn: SEQUENCE_INIT,
+            config,
+            rank: config.min_hop_rank_increase,
+            lowest_rank: config.min_hop_rank_increase,
+            prefix: Some(PrefixInformation {
+                prefix_len: 64,
+                on_link: false,
+                autonomous: true,
+                valid_lifetime: INFINITE,
+                preferred_lifetime: INFINITE,
+                prefix,
+            }),
+        });
+        self.restart_trickle();
+        Ok(())
+    }
+
+    /// Increment the version of the DODAG rooted at this node, which makes
+    /// all nodes select their parents again. Returns `INVAL` if this node is
+    /// not a root.
+    pub fn global_repair(&self) -> Result<(), ErrorCode> {
+        let dodag = match self.dodag.extract() {
+            Some(dodag) if self.root.get() => dodag,
+            _ => return Err(ErrorCode::INVAL),
+        };
+        self.dodag.set(Dodag {
+            version: lollipop_increment(dodag.version),
+            dtsn: lollipop_increment(dodag.dtsn),
+            ..dodag
+        });
+        self.restart_trickle();
+        Ok(())
+    }
+
+    /// The rank of this node, if it joined or roots a DODAG.
+    pub fn rank(&self) -> Option<u16> {
+        self.dodag.map(|dodag| dodag.rank)
+    }
+
+    /// The ID of the DODAG joined or rooted at this node.
+    pub fn dodag_id(&self) -> Option<IPAddr> {
+        self.dodag.map(|dodag| dodag.dodag_id)
+    }
+
+    /// The link-local address of the preferred parent.
+    pub fn preferred_parent(&self) -> Option<IPAddr> {
+        self.preferred_parent.extract()
+    }
+
+    fn next_random(&self) -> u32 {
+        // xorshift32
+        let mut x = self.random.get();
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.random.set(x);
+        x
+    }
+
+    fn set_timer(&self, timer: &OptionalCell<Deadline<A::Ticks>>, ms: u32) {
+        timer.set(Deadline {
+            reference: self.alarm.now(),
+            dt: self.alarm.ticks_from_ms(ms),
+        });
+        self.rearm();
+    }
+
+    /// Run the alarm at the earliest deadline of the timers.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let earliest = [&self.trickle_timer, &self.tick_timer]
+            .iter()
+            .filter_map(|timer| timer.extract())
+            .map(|deadline| deadline.remaining(now))
+            .min();
+        match earliest {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    /// Run the timer counting down lifetimes in `seconds`, unless it runs
+    /// earlier already.
+    fn schedule_tick(&self, seconds: u32) {
+        let now = self.alarm.now();
+        let later = self.tick_timer.map_or(true, |deadline| {
+            deadline.remaining(now) > self.alarm.ticks_from_seconds(seconds)
+        });
+        if later {
+            self.set_timer(&self.tick_timer, seconds.saturating_mul(1000));
+        }
+    }
+
+    /// Seconds until the timer counting down lifetimes has to run next,
+    /// `None` if there is nothing to do.
+    fn next_tick(&self) -> Option<u32> {
+        let soliciting = (self.started.get() && self.dodag.is_none())
+            .then(|| core::cmp::max(self.dis_delay.get(), 1));
+        let dao = self
+            .dao_delay
+            .extract()
+            .map(|delay| core::cmp::max(delay, 1));
+        let routes = self
+            .routes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .map(|route| route.lifetime)
+            .filter(|&lifetime| lifetime != 0 && lifetime != INFINITE);
+        soliciting
+            .into_iter()
+            .chain(dao)
+            .chain(routes)
+            .min()
+            .map(|seconds| core::cmp::min(seconds, MAX_TICK_INTERVAL))
+    }
+
+    /// Count down the lifetimes and delays by the whole seconds since they
+    /// were last counted down, and remove the routes that expire.
+    fn count_down_lifetimes(&self) {
+        let now = self.alarm.now();
+        let counted = self.counted.get();
+        let seconds = self.alarm.ticks_to_ms(now.wrapping_sub(counted)) / 1000;
+        if seconds == 0 {
+            return;
+        }
+        self.counted
+            .set(counted.wrapping_add(self.alarm.ticks_from_seconds(seconds)));
+
+        for entry in self.routes.iter() {
+            if let Some(route) = entry.get() {
+                if route.state == RouteState::Withdrawn || route.lifetime == INFINITE {
+                    continue;
+                }
+                let lifetime = route.lifetime.saturating_sub(seconds);
+                entry.set((lifetime > 0).then_some(Route { lifetime, ..route }));
+            }
+        }
+        self.dis_delay
+            .set(self.dis_delay.get().saturating_sub(seconds));
+        if let Some(delay) = self.dao_delay.extract() {
+            self.dao_delay.set(delay.saturating_sub(seconds));
+        }
+    }
+
+    /// Runs while DIOs are solicited, when a DAO is due and when the next
+    /// route expires. Messages that cannot be sent because another one is in
+    /// progress are sent at the next tick.
+    fn tick(&self) {
+        self.count_down_lifetimes();
+
+        if self.started.get() && self.dodag.is_none() && self.dis_delay.get() == 0 {
+            // Flags and reserved, and two Pad1 options to fill the four
+            // bytes at the end of the ICMPv6 header
+            let result = self.send(ALL_RPL_NODES, DIS, |buf| {
+                buf[..4].fill(0);
+                4
+            });
+            if result == Ok(()) {
+                self.dis_delay.set(DIS_INTERVAL);
+            }
+        }
+        if self.dao_delay.extract() == Some(0) {
+            self.dao_due();
+        }
+
+        if let Some(seconds) = self.next_tick() {
+            self.schedule_tick(seconds);
+        }
+    }
+
+    fn restart_trickle(&self) {
+        if let Some(dodag) = self.dodag.extract() {
+            let mut trickle = Trickle::new(&dodag.config);
+            let ms = trickle.reset(self.next_random());
+            self.trickle.set(trickle);
+            self.set_timer(&self.trickle_timer, ms);
+        }
+    }
+
+    fn trickle_inconsistent(&self) {
+        if self.trickle_timer.is_none() {
+            self.restart_trickle();
+            return;
+        }
+        let mut trickle = self.trickle.get();
+        let reset = trickle.inconsistent(self.next_random());
+        self.trickle.set(trickle);
+        if let Some(ms) = reset {
+            self.set_timer(&self.trickle_timer, ms);
+        }
+    }
+
+    fn trickle_consistent(&self) {
+        let mut trickle = self.trickle.get();
+        trickle.consistent();
+        self.trickle.set(trickle);
+    }
+
+    fn trickle_fired(&self) {
+        let mut trickle = self.trickle.get();
+        let (transmit, ms) = trickle.fire(self.next_random());
+        self.trickle.set(trickle);
+        if transmit {
+            let _ = self.send_dio(ALL_RPL_NODES);
+        }
+        self.set_timer(&self.trickle_timer, ms);
+    }
+
+    /// A link-local address of the interface, the source of all messages.
+    fn link_local(&self) -> Option<IPAddr> {
+        self.addresses
+            .iter()
+            .find(|addr| addr.is_unicast_link_local())
+    }
+
+    /// Send the RPL control message with `code`, written by `encode`.
+    fn send(
+        &self,
+        dst: IPAddr,
+        code: u8,
+        encode: impl FnOnce(&mut [u8]) -> usize,
+    ) -> Result<(), ErrorCode> {
+        if self.sending.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let src = self.link_local().ok_or(ErrorCode::FAIL)?;
+        let buffer = self.tx_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let len = encode(buffer);
+        // The first four bytes of the message are the last four bytes of the
+        // ICMPv6 header
+        let base = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+        let mut icmp_header = ICMP6Header::new(ICMP6Type::Type155);
+        icmp_header.set_code(code);
+        icmp_header.set_options(ICMP6HeaderOptions::Type155 { base });
+        let mut payload = LeasableMutableBuffer::new(buffer);
+        payload.slice(4..len);
+
+        self.ip_sender.set_addr(src);
+        let result = self.ip_sender.send_to(
+            dst,
+            TransportHeader::ICMP(icmp_header),
+            &payload,
+            self.net_cap,
+        );
+        // The sender copied the message
+        self.tx_buffer.replace(payload.take());
+        self.sending.set(result.is_ok());
+        result
+    }
+
+    fn send_dio(&self, dst: IPAddr) -> Result<(), ErrorCode> {
+        let dodag = self.dodag.extract().ok_or(ErrorCode::OFF)?;
+        self.send(dst, DIO, |buf| {
+            let dio = Dio {
+                instance_id: dodag.instance_id,
+                version: dodag.version,
+                rank: dodag.rank,
+                grounded: dodag.grounded,
+                mode_of_operation: dodag.mode_of_operation,
+                preference: dodag.preference,
+                dtsn: dodag.dtsn,
+                dodag_id: dodag.dodag_id,
+            };
+            let mut len = dio.encode(buf);
+            len += encode_dodag_configuration(&mut buf[len..], &dodag.config);
+            if let Some(prefix) = dodag.prefix {
+                len += encode_prefix_information(&mut buf[len..], &prefix);
+            }
+            len
+        })
+    }
+
+    /// Send a DAO with the global addresses of the interface and the routes
+    /// not reported yet to the preferred parent, and the withdrawn routes as
+    /// No-Path. Routes that do not fit are sent with the next DAO.
+    fn send_dao(&self) {
+        let (dodag, parent) = match (self.dodag.extract(), self.preferred_parent.extract()) {
+            (Some(dodag), Some(parent)) => (dodag, parent),
+            _ => return,
+        };
+        let sequence = lollipop_increment(self.dao_sequence.get());
+        let mut included = [false; ROUTE_TABLE_SIZE];
+        let mut targets = 0;
+        let result = self.send(parent, DAO, |buf| {
+            buf[..4].copy_from_slice(&[dodag.instance_id, DAO_ACK_REQUESTED, 0, sequence]);
+            let mut len = 4;
+            let fits = |len: usize, prefix_len: u8| {
+                len + target_len(prefix_len) + TRANSIT_LEN <= RPL_PAYLOAD_LEN
+            };
+
+            let own = self
+                .addresses
+                .iter()
+                .filter(|addr| !addr.is_unicast_link_local() && !addr.is_multicast());
+            for addr in own {
+                if fits(len, 128) {
+                    len += encode_target(&mut buf[len..], &addr, 128);
+                    targets += 1;
+                }
+            }
+            for (index, entry) in self.routes.iter().enumerate() {
+                match entry.get() {
+                    Some(route)
+                        if route.state == RouteState::Unreported && fits(len, route.prefix_len) =>
+                    {
+                        len += encode_target(&mut buf[len..], &route.prefix, route.prefix_len);
+                        included[index] = true;
+                        targets += 1;
+                    }
+                    _ => {}
+                }
+            }
+            if targets > 0 {
+                len += encode_transit(&mut buf[len..], sequence, dodag.config.default_lifetime);
+            }
+
+            let start = len;
+            for (index, entry) in self.routes.iter().enumerate() {
+                match entry.get() {
+                    Some(route)
+                        if route.state == RouteState::Withdrawn && fits(len, route.prefix_len) =>
+                    {
+                        len += encode_target(&mut buf[len..], &route.prefix, route.prefix_len);
+                        included[index] = true;
+                    }
+                    _ => {}
+                }
+            }
+            if len > start {
+                len += encode_transit(&mut buf[len..], sequence, 0);
+            }
+            len
+        });
+
+        match result {
+            Ok(()) => {
+                self.dao_sequence.set(sequence);
+                self.dao_pending.set(sequence);
+                for (entry, _) in self.routes.iter().zip(included).filter(|(_, incl)| *incl) {
+                    entry.set(entry.get().and_then(|route| match route.state {
+                        RouteState::Withdrawn => None,
+                        _ => Some(Route {
+                            state: RouteState::Reported,
+                            ..route
+                        }),
+                    }));
+                }
+                self.dao_delay.set(DAO_ACK_TIMEOUT);
+                self.schedule_tick(DAO_ACK_TIMEOUT);
+            }
+            Err(_) => self.schedule_dao(1),
+        }
+    }
+
+    /// Send a DAO in `seconds`, unless one is sent earlier already. While a
+    /// DAO waits for its acknowledgement, the next one is scheduled once it
+    /// is acknowledged.
+    fn schedule_dao(&self, seconds: u32) {
+        if self.dao_pending.is_some() {
+            return;
+        }
+        let delay = self
+            .dao_delay
+            .extract()
+            .map_or(seconds, |delay| core::cmp::min(delay, seconds));
+        self.dao_delay.set(delay);
+        self.schedule_tick(core::cmp::max(delay, 1));
+    }
+
+    /// Report all routes again with the next DAO.
+    fn unreport_routes(&self) {
+        for entry in self.routes.iter() {
+            if let Some(route) = entry
+                .get()
+                .filter(|route| route.state == RouteState::Reported)
+            {
+                entry.set(Some(Route {
+                    state: RouteState::Unreported,
+                    ..route
+                }));
+            }
+        }
+    }
+
+    /// The DAO delay expired: refresh the routes, or retry a DAO that was not
+    /// acknowledged.
+    fn dao_due(&self) {
+        self.dao_delay.clear();
+        if self.dao_pending.take().is_some() {
+            let retries = self.dao_retries.get() + 1;
+            if retries > MAX_DAO_RETRIES {
+                // The parent does not answer
+                self.dao_retries.set(0);
+                if let Some(parent) = self.preferred_parent.take() {
+                    self.remove_parent(&parent);
+                }
+                self.select_parent();
+                return;
+            }
+            self.dao_retries.set(retries);
+            self.unreport_routes();
+        } else {
+            let unreported = self
+                .routes
+                .iter()
+                .filter_map(|entry| entry.get())
+                .any(|route| {
+                    route.state == RouteState::Unreported || route.state == RouteState::Withdrawn
+                });
+            if !unreported {
+                self.unreport_routes();
+            }
+        }
+        self.send_dao();
+    }
+
+    fn remove_parent(&self, ip_addr: &IPAddr) {
+        for entry in self.parents.iter() {
+            if entry
+                .get()
+                .map_or(false, |parent| parent.ip_addr == *ip_addr)
+            {
+                entry.set(None);
+            }
+        }
+    }
+
+    /// Select the preferred parent and the rank with OF0, and leave the DODAG
+    /// if no parent is left. Returns whether the parent or the rank changed.
+    fn select_parent(&self) -> bool {
+        let dodag = match self.dodag.extract() {
+            Some(dodag) if !self.root.get() => dodag,
+            _ => return false,
+        };
+        let increase = dodag.config.min_hop_rank_increase;
+        let rank_via = |parent: &Parent| of0_rank(parent.rank, increase);
+        let candidates = self
+            .parents
+            .iter()
+            .filter_map(|entry| entry.get())
+            .filter(|parent| parent.rank != INFINITE_RANK);
+        let best = candidates.clone().min_by_key(rank_via);
+        let current = candidates
+            .clone()
+            .find(|parent| Some(parent.ip_addr) == self.preferred_parent.extract());
+        // Keep the current parent unless another one is better
+        let selected = match (current, best) {
+            (Some(current), Some(best)) if rank_via(&current) <= rank_via(&best) => Some(current),
+            (_, best) => best,
+        };
+        let selected = selected.filter(|parent| {
+            let rank = rank_via(parent) as u32;
+            rank != INFINITE_RANK as u32
+                && (dodag.config.max_rank_increase == 0
+                    || rank <= dodag.lowest_rank as u32 + dodag.config.max_rank_increase as u32)
+        });
+        let parent = match selected {
+            Some(parent) => parent,
+            None => {
+                self.leave();
+                return true;
+            }
+        };
+
+        let rank = rank_via(&parent);
+        let parent_changed = self.preferred_parent.extract() != Some(parent.ip_addr);
+        self.preferred_parent.set(parent.ip_addr);
+        self.dodag.set(Dodag {
+            rank,
+            lowest_rank: core::cmp::min(dodag.lowest_rank, rank),
+            ..dodag
+        });
+        if parent_changed && dodag.mode_of_operation == MOP_STORING {
+            self.dao_pending.clear();
+            self.dao_retries.set(0);
+            self.unreport_routes();
+            self.schedule_dao(DAO_DELAY);
+        }
+        parent_changed || rank != dodag.rank
+    }
+
+    /// Leave the DODAG, and solicit DIOs again.
+    fn leave(&self) {
+        if let Some(addr) = self.configured_addr.take() {
+            let _ = self.addresses.remove(&addr);
+        }
+        self.dodag.clear();
+        self.preferred_parent.clear();
+        for entry in self.parents.iter() {
+            entry.set(None);
+        }
+        for entry in self.routes.iter() {
+            entry.set(None);
+        }
+        self.dao_pending.clear();
+        self.dao_delay.clear();
+        self.trickle_timer.clear();
+        self.dis_delay.set(0);
+        self.schedule_tick(1);
+    }
+
+    fn join(&self, dio: &Dio, config: DodagConfig, prefix: Option<PrefixInformation>) {
+        self.leave();
+        self.dodag.set(Dodag {
+            instance_id: dio.instance_id,
+            dodag_id: dio.dodag_id,
+            version: dio.version,
+            mode_of_operation: dio.mode_of_operation,
+            grounded: dio.grounded,
+            preference: dio.preference,
+            dtsn: SEQUENCE_INIT,
+            config,
+            rank: INFINITE_RANK,
+            lowest_rank: INFINITE_RANK,
+            prefix,
+        });
+        // Configure an address in the prefix of the DODAG
+        let prefix = prefix.filter(|prefix| prefix.autonomous && prefix.prefix_len == 64);
+        if let Some(prefix) = prefix {
+            let mut addr = IPAddr::generate_from_mac(self.mac_addr);
+            addr.set_prefix(&prefix.prefix, prefix.prefix_len);
+            if self.addresses.add(addr, AddressState::Preferred) == Ok(()) {
+                self.configured_addr.set(addr);
+            }
+        }
+    }
+
+    fn receive_dio(&self, src: IPAddr, dio: &Dio, options: &[u8]) {
+        let mut config = None;
+        let mut prefix = None;
+        for option in RplOptions::new(options) {
+            match option {
+                RplOption::DodagConfiguration(option) => config = Some(option),
+                RplOption::PrefixInformation(option) => prefix = Some(option),
+                _ => {}
+            }
+        }
+        let same_dodag =
+            |dodag: &Dodag| dodag.instance_id == dio.instance_id && dodag.dodag_id == dio.dodag_id;
+
+        if self.root.get() {
+            if self.dodag.map_or(false, |dodag| {
+                same_dodag(dodag) && dodag.version == dio.version
+            }) {
+                self.trickle_consistent();
+            }
+            return;
+        }
+        let mac_addr = match mac_from_link_local(&src) {
+            Some(mac_addr) => mac_addr,
+            None => return,
+        };
+        if dio.mode_of_operation != MOP_STORING && dio.mode_of_operation != MOP_NO_DOWNWARD_ROUTES {
+            return;
+        }
+
+        match self.dodag.extract() {
+            Some(dodag) if !same_dodag(&dodag) => return,
+            Some(dodag) if dio.version == dodag.version => {}
+            Some(dodag) if !lollipop_greater(dio.version, dodag.version) => {
+                // Tell the sender about the new version
+                self.trickle_inconsistent();
+                return;
+            }
+            // Not joined, or a new version of the DODAG
+            _ => {
+                let config = config.unwrap_or(DEFAULT_CONFIG);
+                if dio.rank == INFINITE_RANK
+                    || config.objective_code_point != OCP_OF0
+                    || config.min_hop_rank_increase == 0
+                {
+                    return;
+                }
+                self.join(dio, config, prefix);
+            }
+        }
+        let dodag = match self.dodag.extract() {
+            Some(dodag) => dodag,
+            None => return,
+        };
+
+        // Only nodes with a lower rank are parents, to avoid loops
+        let dag_rank = |rank: u16| rank / dodag.config.min_hop_rank_increase;
+        let known = self
+            .parents
+            .iter()
+            .find(|entry| entry.get().map_or(false, |parent| parent.ip_addr == src));
+        if dio.rank == INFINITE_RANK || dag_rank(dio.rank) >= dag_rank(dodag.rank) {
+            if let Some(entry) = known {
+                entry.set(None);
+            }
+        } else {
+            let parent = Parent {
+                ip_addr: src,
+                mac_addr,
+                rank: dio.rank,
+                dtsn: dio.dtsn,
+            };
+            let previous = known.and_then(|entry| entry.get());
+            let free = || self.parents.iter().find(|entry| entry.get().is_none());
+            // Replace the worst candidate with a better one
+            let worst = || {
+                self.parents
+                    .iter()
+                    .filter(|entry| entry.get().map_or(false, |other| other.rank > dio.rank))
+                    .max_by_key(|entry| entry.get().map_or(0, |other| other.rank))
+            };
+            if let Some(entry) = known.or_else(free).or_else(worst) {
+                entry.set(Some(parent));
+            }
+
+            // The parent asks for new DAOs, which this node asks its children
+            // for in turn
+            let new_dtsn =
+                previous.map_or(false, |previous| lollipop_greater(dio.dtsn, previous.dtsn));
+            if new_dtsn
+                && self.preferred_parent.extract() == Some(src)
+                && dodag.mode_of_operation == MOP_STORING
+            {
+                self.dodag.set(Dodag {
+                    dtsn: lollipop_increment(dodag.dtsn),
+                    ..dodag
+                });
+                self.unreport_routes();
+                self.schedule_dao(DAO_DELAY);
+            }
+        }
+
+        if self.select_parent() {
+            self.trickle_inconsistent();
+        } else {
+            self.trickle_consistent();
+        }
+    }
+
+    fn receive_dis(&self, src: IPAddr, dst: IPAddr) {
+        if self.dodag.is_none() {
+            return;
+        }
+        if dst.is_multicast() {
+            self.trickle_inconsistent();
+        } else {
+            let _ = self.send_dio(src);
+        }
+    }
+
+    fn receive_dao(&self, src: IPAddr, body: &[u8]) {
+        let dodag = match self.dodag.extract() {
+            Some(dodag) if dodag.mode_of_operation == MOP_STORING => dodag,
+            _ => return,
+        };
+        let next_hop = match mac_from_link_local(&src) {
+            Some(next_hop) => next_hop,
+            None => return,
+        };
+        if body.len() < 4 || body[0] != dodag.instance_id {
+            return;
+        }
+        let (flags, sequence) = (body[1], body[3]);
+        let options = match flags & DAO_DODAG_ID {
+            0 => &body[4..],
+            _ if body.len() >= 20 && body[4..20] == dodag.dodag_id.0 => &body[20..],
+            _ => return,
+        };
+
+        let mut accepted = true;
+        for (index, option) in RplOptions::new(options).enumerate() {
+            let (prefix, prefix_len) = match option {
+                RplOption::Target { prefix, prefix_len } => (prefix, prefix_len),
+                _ => continue,
+            };
+            // The Transit Information after a target applies to it
+            let path_lifetime =
+                RplOptions::new(options)
+                    .skip(index + 1)
+                    .find_map(|option| match option {
+                        RplOption::Transit { path_lifetime, .. } => Some(path_lifetime),
+                        _ => None,
+                    });
+            let lifetime = match path_lifetime {
+                Some(0) => {
+                    self.withdraw_route(&prefix, prefix_len, next_hop);
+                    continue;
+                }
+                Some(INFINITE_PATH_LIFETIME) => INFINITE,
+                Some(path_lifetime) => path_lifetime as u32 * dodag.config.lifetime_unit as u32,
+                None => continue,
+            };
+            if !self.addresses.contains(&prefix) {
+                accepted &= self.update_route(prefix, prefix_len, next_hop, lifetime);
+            }
+        }
+
+        if flags & DAO_ACK_REQUESTED != 0 {
+            let status = if accepted {
+                DAO_ACK_ACCEPTED
+            } else {
+                DAO_ACK_REJECTED
+            };
+            let _ = self.send(src, DAO_ACK, |buf| {
+                buf[..4].copy_from_slice(&[dodag.instance_id, 0, sequence, status]);
+                4
+            });
+        }
+        if !self.root.get() {
+            self.schedule_dao(DAO_DELAY);
+        }
+        if let Some(seconds) = self.next_tick() {
+            self.schedule_tick(seconds);
+        }
+    }
+
+    fn route_entry(&self, prefix: &IPAddr, prefix_len: u8) -> Option<&Cell<Option<Route>>> {
+        self.routes.iter().find(|entry| {
+            entry.get().map_or(false, |route| {
+                route.prefix == *prefix && route.prefix_len == prefix_len
+            })
+        })
+    }
+
+    /// Add or refresh the route to `prefix`. Returns false if the route table
+    /// is full.
+    fn update_route(
+        &self,
+        prefix: IPAddr,
+        prefix_len: u8,
+        next_hop: MacAddress,
+        lifetime: u32,
+    ) -> bool {
+        let free = || self.routes.iter().find(|entry| entry.get().is_none());
+        match self.route_entry(&prefix, prefix_len).or_else(free) {
+            Some(entry) => {
+                entry.set(Some(Route {
+                    prefix,
+                    prefix_len,
+                    next_hop,
+                    lifetime,
+                    state: RouteState::Unreported,
+                }));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn withdraw_route(&self, prefix: &IPAddr, prefix_len: u8, next_hop: MacAddress) {
+        if let Some(entry) = self.route_entry(prefix, prefix_len) {
+            // A No-Path from a former next hop does not remove the new route
+            entry.set(entry.get().and_then(|route| {
+                if route.next_hop != next_hop {
+                    Some(route)
+                } else if self.root.get() {
+                    None
+                } else {
+                    Some(Route {
+                        lifetime: 0,
+                        state: RouteState::Withdrawn,
+                        ..route
+                    })
+                }
+            }));
+        }
+    }
+
+    fn receive_dao_ack(&self, body: &[u8]) {
+        if body.len() < 4 || self.dao_pending.extract() != Some(body[2]) {
+            return;
+        }
+        self.dao_pending.clear();
+        self.dao_delay.clear();
+        self.dao_retries.set(0);
+        if body[3] >= DAO_ACK_REJECTED {
+            // The parent cannot route to this node
+            if let Some(parent) = self.preferred_parent.take() {
+                self.remove_parent(&parent);
+            }
+            if self.select_parent() {
+                self.trickle_inconsistent();
+            }
+            return;
+        }
+        let dodag = match self.dodag.extract() {
+            Some(dodag) => dodag,
+            None => return,
+        };
+        let unreported = self
+            .routes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .any(|route| {
+                route.state == RouteState::Unreported || route.state == RouteState::Withdrawn
+            });
+        if unreported {
+            self.schedule_dao(DAO_DELAY);
+        } else if dodag.config.default_lifetime != INFINITE_PATH_LIFETIME {
+            // Refresh the routes halfway through their lifetime
+            let lifetime = dodag.config.default_lifetime as u32 * dodag.config.lifetime_unit as u32;
+            self.schedule_dao(core::cmp::max(lifetime / 2, 1));
+        }
+    }
+
+    /// The route with the longest prefix to `dst`.
+    fn route(&self, dst: &IPAddr) -> Option<Route> {
+        self.routes
+            .iter()
+            .filter_map(|entry| entry.get())
+            .filter(|route| route.state != RouteState::Withdrawn)
+            .filter(|route| matches_prefix(dst, &route.prefix, route.prefix_len))
+            .max_by_key(|route| route.prefix_len)
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NextHop for Rpl<'a, A> {
+    fn next_hop(&self, dst: &IPAddr) -> Option<MacAddress> {
+        let neighbors = || {
+            self.neighbors
+                .map_or(None, |neighbors| neighbors.next_hop(dst))
+        };
+        if dst.is_unicast_link_local() || self.dodag.is_none() {
+            return neighbors();
+        }
+        let parent = || {
+            let parent = self.preferred_parent.extract()?;
+            self.parents
+                .iter()
+                .filter_map(|entry| entry.get())
+                .find(|candidate| candidate.ip_addr == parent)
+                .map(|parent| parent.mac_addr)
+        };
+        self.route(dst)
+            .map(|route| route.next_hop)
+            .or_else(parent)
+            .or_else(neighbors)
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6Forwarder for Rpl<'a, A> {
+    fn forward(&self, mut header: IP6Header, payload: &[u8]) {
+        let hop_limit = header.get_hop_limit();
+        if self.dodag.is_none() || hop_limit <= 1 || self.sending.get() {
+            return;
+        }
+        header.set_hop_limit(hop_limit - 1);
+        self.sending
+            .set(self.ip_sender.forward(header, payload).is_ok());
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6RecvClient for Rpl<'a, A> {
+    fn receive(&self, header: IP6Header, payload: &[u8]) {
+        if !self.started.get() || header.get_next_header() != ip6_nh::ICMP {
+            return;
+        }
+        let icmp_header = match ICMP6Header::decode(payload).done() {
+            Some((_, icmp_header)) if icmp_header.get_type() == ICMP6Type::Type155 => icmp_header,
+            _ => return,
+        };
+        // The message starts after the type, code and checksum
+        let body = &payload[4..];
+        let src = header.get_src_addr();
+        if !src.is_unicast_link_local() {
+            return;
+        }
+        match icmp_header.get_code() {
+            DIS => self.receive_dis(src, header.get_dst_addr()),
+            DIO => {
+                if let Some(dio) = Dio::decode(body) {
+                    self.receive_dio(src, &dio, &body[Dio::LEN..]);
+                }
+            }
+            DAO => self.receive_dao(src, body),
+            DAO_ACK => self.receive_dao_ack(body),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> IP6SendClient for Rpl<'a, A> {
+    fn send_done(&self, _result: Result<(), ErrorCode>) {
+        self.sending.set(false);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for Rpl<'a, A> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let expired = |timer: &OptionalCell<Deadline<A::Ticks>>| {
+            let expired = timer.map_or(false, |deadline| {
+                deadline.remaining(now) == A::Ticks::from(0)
+            });
+            if expired {
+                timer.clear();
+            }
+            expired
+        };
+        if expired(&self.trickle_timer) {
+            self.trickle_fired();
+        }
+        if expired(&self.tick_timer) {
+            self.tick();
+        }
+        self.rearm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dio_and_options() {
+        let mut dodag_id = IPAddr::new();
+        dodag_id.0[..2].copy_from_slice(&[0x20, 0x01]);
+        dodag_id.0[15] = 1;
+        let dio = Dio {
+            instance_id: 30,
+            version: SEQUENCE_INIT,
+            rank: 256,
+            grounded: true,
+            mode_of_operation: MOP_STORING,
+            preference: 0,
+            dtsn: 241,
+            dodag_id,
+        };
+        let prefix = PrefixInformation {
+            prefix_len: 64,
+            on_link: false,
+            autonomous: true,
+            valid_lifetime: INFINITE,
+            preferred_lifetime: INFINITE,
+            prefix: [0x20, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        };
+
+        let mut buf = [0; RPL_PAYLOAD_LEN];
+        let mut len = dio.encode(&mut buf);
+        len += encode_dodag_configuration(&mut buf[len..], &DEFAULT_CONFIG);
+        // Padding is skipped
+        buf[len] = OPTION_PAD1;
+        len += 1;
+        len += encode_prefix_information(&mut buf[len..], &prefix);
+        assert_eq!(len, RPL_PAYLOAD_LEN - 23);
+
+        assert_eq!(Dio::decode(&buf[..len]), Some(dio));
+        let mut options = RplOptions::new(&buf[Dio::LEN..len]);
+        assert_eq!(
+            options.next(),
+            Some(RplOption::DodagConfiguration(DEFAULT_CONFIG))
+        );
+        assert_eq!(options.next(), Some(RplOption::Other(OPTION_PAD1)));
+        assert_eq!(options.next(), Some(RplOption::PrefixInformation(prefix)));
+        assert_eq!(options.next(), None);
+
+        let mut len = encode_target(&mut buf, &dodag_id, 128);
+        len += encode_target(&mut buf[len..], &dodag_id, 12);
+        len += encode_transit(&mut buf[len..], 7, 30);
+        let mut options = RplOptions::new(&buf[..len]);
+        assert_eq!(
+            options.next(),
+            Some(RplOption::Target {
+                prefix_len: 128,
+                prefix: dodag_id,
+            })
+        );
+        let mut prefix = IPAddr::new();
+        prefix.0[..2].copy_from_slice(&[0x20, 0x00]);
+        assert_eq!(
+            options.next(),
+            Some(RplOption::Target {
+                prefix_len: 12,
+                prefix,
+            })
+        );
+        assert_eq!(
+            options.next(),
+            Some(RplOption::Transit {
+                path_sequence: 7,
+                path_lifetime: 30,
+            })
+        );
+        assert_eq!(options.next(), None);
+        assert!(matches_prefix(&dodag_id, &prefix, 12));
+        assert!(!matches_prefix(&IPAddr::new(), &prefix, 12));
+    }
+
+    #[test]
+    fn lollipop_counters() {
+        assert!(lollipop_greater(241, SEQUENCE_INIT));
+        assert!(!lollipop_greater(SEQUENCE_INIT, SEQUENCE_INIT));
+        // The linear part leads into the circular part
+        assert_eq!(lollipop_increment(255), 0);
+        assert!(lollipop_greater(0, SEQUENCE_INIT));
+        assert!(!lollipop_greater(SEQUENCE_INIT, 0));
+        assert!(lollipop_greater(0, 127));
+        assert!(lollipop_greater(5, 3));
+        assert_eq!(lollipop_increment(127), 0);
+    }
+
+    #[test]
+    fn trickle() {
+        let mut trickle = Trickle::new(&DodagConfig {
+            dio_interval_doublings: 2,
+            dio_interval_min: 10,
+            dio_redundancy: 1,
+            ..DEFAULT_CONFIG
+        });
+        let t = trickle.reset(100);
+        assert_eq!(t, 512 + 100);
+        assert_eq!(trickle.fire(0), (true, 1024 - t));
+
+        // A consistent message suppresses the transmission
+        assert_eq!(trickle.fire(0), (false, 1024));
+        trickle.consistent();
+        assert_eq!(trickle.fire(0), (false, 1024));
+        assert_eq!(trickle.fire(0), (false, 2048));
+        assert_eq!(trickle.interval_ms(), 4096);
+        // The interval does not grow past Imax
+        trickle.fire(0);
+        assert_eq!(trickle.fire(0), (false, 2048));
+        assert_eq!(trickle.interval_ms(), 4096);
+
+        assert_eq!(trickle.inconsistent(0), Some(512));
+        assert_eq!(trickle.inconsistent(0), None);
+    }
+
+    #[test]
+    fn ranks() {
+        assert_eq!(of0_rank(256, 256), 1024);
+        assert_eq!(of0_rank(INFINITE_RANK - 1, 256), INFINITE_RANK);
+    }
+}
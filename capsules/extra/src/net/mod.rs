@@ -4,6 +4,8 @@
 
 //! Modules for IPv6 over 6LoWPAN stack
 
+pub mod coap;
+pub mod dtls;
 pub mod frag_utils;
 pub mod sixlowpan;
 pub mod util;
@@ -11,7 +13,9 @@ pub mod util;
 pub mod stream;
 pub mod icmpv6;
 pub mod ieee802154;
+pub mod ipv4;
 pub mod ipv6;
+pub mod mqttsn;
 pub mod network_capabilities;
 pub mod tcp;
 pub mod thread;
@@ -0,0 +1,780 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! An MQTT-SN client (MQTT-SN 1.2) connected to a gateway over UDP.
+//!
+//! The client connects to one gateway with a clean session, and keeps the
+//! connection alive with a PINGREQ every keep-alive period. Topic names are
+//! registered with the gateway before publishing to them, and the topic ids
+//! the gateway assigns, to registered names, subscriptions without
+//! wildcards and topics it registers for wildcard subscriptions, are kept in
+//! a table of `MAX_TOPICS` entries.
+//!
+//! One acknowledged request, CONNECT, REGISTER, a PUBLISH with QoS 1,
+//! SUBSCRIBE, UNSUBSCRIBE or PINGREQ, is outstanding at a time. It is
+//! retransmitted every `RETRY_MS`, and after `MAX_RETRANSMIT`
+//! retransmissions the gateway is considered lost and the client
+//! disconnected, as is one that misses a keep-alive. PUBLISH messages with
+//! QoS 0 are sent without waiting for the outstanding request. Incoming
+//! publications with QoS 1 are acknowledged; the client subscribes with at
+//! most QoS 1.
+//!
+//! Usage
+//! -----
+//! `components::mqttsn::MqttSnComponent` binds the client to a UDP port and
+//! registers the userspace driver. Kernel users set themselves as client:
+//!
+//! ```rust,ignore
+//! let (mqttsn, mqttsn_driver) = components::mqttsn::MqttSnComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::mqttsn::DRIVER_NUM,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     GATEWAY_ADDR,
+//!     capsules_extra::net::mqttsn::client::MQTTSN_PORT,
+//!     b"tock-sensor",
+//! )
+//! .finalize(components::mqttsn_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::mqttsn::message::{
+    flags, msg_type, return_code, Message, MessageWriter, QoS, Topic, PROTOCOL_ID,
+};
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::LeasableMutableBuffer;
+use kernel::ErrorCode;
+
+/// The UDP port of MQTT-SN gateways.
+pub const MQTTSN_PORT: u16 = 1883;
+pub const MAX_TOPICS: usize = 8;
+/// Maximum length of the client id.
+pub const MAX_CLIENT_ID_LEN: usize = 23;
+/// The keep-alive period in seconds.
+pub const KEEP_ALIVE_S: u16 = 60;
+
+const RETRY_MS: u32 = 10_000;
+const MAX_RETRANSMIT: u8 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+/// Receives the results of the requests of the client and the publications
+/// of the topics it subscribed to.
+pub trait MqttSnClient {
+    /// The CONNECT request completed.
+    fn connected(&self, result: Result<(), ErrorCode>);
+
+    /// The connection was lost, or closed by the gateway. Outstanding
+    /// requests failed before, topic ids and subscriptions are gone.
+    fn disconnected(&self);
+
+    /// A topic name was registered with the topic id.
+    fn registered(&self, result: Result<u16, ErrorCode>);
+
+    /// A publication was sent, with QoS 1 acknowledged.
+    fn published(&self, result: Result<(), ErrorCode>);
+
+    /// A subscription was accepted with the topic id, 0 for filters with
+    /// wildcards.
+    fn subscribed(&self, result: Result<u16, ErrorCode>);
+
+    fn unsubscribed(&self, result: Result<(), ErrorCode>);
+
+    /// A publication to the topic with `topic_id`.
+    fn received(&self, topic_id: u16, payload: &[u8]);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Request {
+    Connect,
+    Register(Topic),
+    Publish,
+    Subscribe(Topic),
+    Unsubscribe,
+    Ping,
+}
+
+/// The acknowledged request in flight.
+#[derive(Copy, Clone)]
+struct Outstanding {
+    request: Request,
+    msg_id: u16,
+    len: usize,
+    transmissions: u8,
+}
+
+#[derive(Copy, Clone)]
+struct TopicEntry {
+    id: u16,
+    name: Topic,
+}
+
+/// A time the alarm runs at: `dt` after `reference`.
+#[derive(Copy, Clone)]
+struct Deadline<T: Ticks> {
+    reference: T,
+    dt: T,
+}
+
+impl<T: Ticks> Deadline<T> {
+    fn remaining(&self, now: T) -> T {
+        let elapsed = now.wrapping_sub(self.reference);
+        if elapsed >= self.dt {
+            T::from(0)
+        } else {
+            self.dt.wrapping_sub(elapsed)
+        }
+    }
+}
+
+pub struct MqttSn<'a, A: time::Alarm<'a>> {
+    sender: &'a dyn UDPSender<'a>,
+    alarm: &'a A,
+    net_cap: &'static NetworkCapability,
+    gateway_addr: IPAddr,
+    gateway_port: u16,
+    client_id: &'static [u8],
+    tx_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    /// The outstanding request, copied into `tx_buffer` for each
+    /// transmission.
+    con_buffer: TakeCell<'static, [u8]>,
+    outstanding: Cell<Option<Outstanding>>,
+    /// The outstanding request is due for its next transmission.
+    retransmit: Cell<bool>,
+    retry_timer: OptionalCell<Deadline<A::Ticks>>,
+    keep_alive_timer: OptionalCell<Deadline<A::Ticks>>,
+    ping_due: Cell<bool>,
+    /// A PUBLISH with QoS 0 is being sent.
+    publishing: Cell<bool>,
+    state: Cell<State>,
+    topics: [Cell<Option<TopicEntry>>; MAX_TOPICS],
+    msg_id: Cell<u16>,
+    client: OptionalCell<&'a dyn MqttSnClient>,
+}
+
+impl<'a, A: time::Alarm<'a>> MqttSn<'a, A> {
+    /// `tx_buffer` and `con_buffer` should have the same length, and
+    /// `client_id` at most `MAX_CLIENT_ID_LEN` bytes.
+    pub fn new(
+        sender: &'a dyn UDPSender<'a>,
+        alarm: &'a A,
+        net_cap: &'static NetworkCapability,
+        gateway_addr: IPAddr,
+        gateway_port: u16,
+        client_id: &'static [u8],
+        tx_buffer: LeasableMutableBuffer<'static, u8>,
+        con_buffer: &'static mut [u8],
+    ) -> MqttSn<'a, A> {
+        MqttSn {
+            sender,
+            alarm,
+            net_cap,
+            gateway_addr,
+            gateway_port,
+            client_id: &client_id[..core::cmp::min(client_id.len(), MAX_CLIENT_ID_LEN)],
+            tx_buffer: MapCell::new(tx_buffer),
+            con_buffer: TakeCell::new(con_buffer),
+            outstanding: Cell::new(None),
+            retransmit: Cell::new(false),
+            retry_timer: OptionalCell::empty(),
+            keep_alive_timer: OptionalCell::empty(),
+            ping_due: Cell::new(false),
+            publishing: Cell::new(false),
+            state: Cell::new(State::Disconnected),
+            topics: Default::default(),
+            msg_id: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn MqttSnClient) {
+        self.client.set(client);
+    }
+
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+
+    /// Connect to the gateway, with a clean session.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The CONNECT request is sent.
+    /// - `ALREADY`: The client is connected or connecting.
+    pub fn connect(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Disconnected {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.send_request(Request::Connect, |writer, _| {
+            writer.u8(flags::CLEAN_SESSION)?;
+            writer.u8(PROTOCOL_ID)?;
+            writer.u16(KEEP_ALIVE_S)?;
+            writer.bytes(self.client_id)
+        })?;
+        self.state.set(State::Connecting);
+        self.send_next();
+        Ok(())
+    }
+
+    /// Close the connection. Outstanding requests fail with `CANCEL`.
+    pub fn disconnect(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Disconnected {
+            return Err(ErrorCode::ALREADY);
+        }
+        if let Some(mut tx) = self.tx_buffer.take() {
+            let len = MessageWriter::new(&mut tx[..], msg_type::DISCONNECT)
+                .and_then(|writer| writer.finish());
+            match len {
+                Ok(len) => {
+                    tx.slice(0..len);
+                    let _ = self.send(tx);
+                }
+                Err(_) => {
+                    self.tx_buffer.replace(tx);
+                }
+            }
+        }
+        self.close(ErrorCode::CANCEL);
+        Ok(())
+    }
+
+    /// The topic id registered for `name`.
+    pub fn topic_id(&self, name: &[u8]) -> Option<u16> {
+        self.topics
+            .iter()
+            .filter_map(|entry| entry.get())
+            .find(|entry| entry.name.as_slice() == name)
+            .map(|entry| entry.id)
+    }
+
+    /// The topic name registered for `topic_id`.
+    pub fn topic_name(&self, topic_id: u16) -> Option<Topic> {
+        self.topics
+            .iter()
+            .filter_map(|entry| entry.get())
+            .find(|entry| entry.id == topic_id)
+            .map(|entry| entry.name)
+    }
+
+    /// Register the topic `name` to publish to it.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The REGISTER request is sent.
+    /// - `OFF`: The client is not connected.
+    /// - `BUSY`: A request is outstanding.
+    /// - `INVAL`: `name` is empty, too long or has wildcards.
+    pub fn register(&self, name: &[u8]) -> Result<(), ErrorCode> {
+        self.check_request()?;
+        let topic = Topic::new(name)
+            .filter(|topic| !topic.is_filter())
+            .ok_or(ErrorCode::INVAL)?;
+        self.send_request(Request::Register(topic), |writer, msg_id| {
+            writer.u16(0)?;
+            writer.u16(msg_id)?;
+            writer.bytes(topic.as_slice())
+        })?;
+        self.send_next();
+        Ok(())
+    }
+
+    /// Publish `payload` to the registered topic `topic_id`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The PUBLISH message is sent.
+    /// - `OFF`: The client is not connected.
+    /// - `BUSY`: A request is outstanding, for QoS 1, or a message is being
+    ///   sent, for QoS 0.
+    /// - `SIZE`: The payload does not fit into a message.
+    pub fn publish(
+        &self,
+        topic_id: u16,
+        qos: QoS,
+        retain: bool,
+        payload: &[u8],
+    ) -> Result<(), ErrorCode> {
+        let flags = qos.flags() | if retain { flags::RETAIN } else { 0 } | flags::TOPIC_NORMAL;
+        let encode = |writer: &mut MessageWriter, msg_id| {
+            writer.u8(flags)?;
+            writer.u16(topic_id)?;
+            writer.u16(msg_id)?;
+            writer.bytes(payload)
+        };
+        match qos {
+            QoS::AtLeastOnce => {
+                self.check_request()?;
+                self.send_request(Request::Publish, encode)?;
+                self.send_next();
+                Ok(())
+            }
+            QoS::AtMostOnce => {
+                if self.state.get() != State::Connected {
+                    return Err(ErrorCode::OFF);
+                }
+                let mut tx = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+                let len =
+                    MessageWriter::new(&mut tx[..], msg_type::PUBLISH).and_then(|mut writer| {
+                        encode(&mut writer, 0)?;
+                        writer.finish()
+                    });
+                match len {
+                    Ok(len) => {
+                        tx.slice(0..len);
+                        self.send(tx)?;
+                        self.publishing.set(true);
+                        Ok(())
+                    }
+                    Err(error) => {
+                        self.tx_buffer.replace(tx);
+                        Err(error)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Subscribe to the topic name or filter `topic` with at most `qos`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The SUBSCRIBE request is sent.
+    /// - `OFF`: The client is not connected.
+    /// - `BUSY`: A request is outstanding.
+    /// - `INVAL`: `topic` is empty or too long.
+    pub fn subscribe(&self, topic: &[u8], qos: QoS) -> Result<(), ErrorCode> {
+        self.check_request()?;
+        let topic = Topic::new(topic).ok_or(ErrorCode::INVAL)?;
+        self.send_request(Request::Subscribe(topic), |writer, msg_id| {
+            writer.u8(qos.flags() | flags::TOPIC_NORMAL)?;
+            writer.u16(msg_id)?;
+            writer.bytes(topic.as_slice())
+        })?;
+        self.send_next();
+        Ok(())
+    }
+
+    /// Unsubscribe from the topic name or filter `topic`, with the same
+    /// return values as `subscribe`.
+    pub fn unsubscribe(&self, topic: &[u8]) -> Result<(), ErrorCode> {
+        self.check_request()?;
+        let topic = Topic::new(topic).ok_or(ErrorCode::INVAL)?;
+        self.send_request(Request::Unsubscribe, |writer, msg_id| {
+            writer.u8(flags::TOPIC_NORMAL)?;
+            writer.u16(msg_id)?;
+            writer.bytes(topic.as_slice())
+        })?;
+        self.send_next();
+        Ok(())
+    }
+
+    fn check_request(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Connected {
+            return Err(ErrorCode::OFF);
+        }
+        match self.outstanding.get() {
+            // The acknowledgement of the request proves the connection alive
+            // as well as a PINGRESP
+            None
+            | Some(Outstanding {
+                request: Request::Ping,
+                ..
+            }) => Ok(()),
+            Some(_) => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn next_msg_id(&self) -> u16 {
+        // Message id 0 is not used
+        let id = match self.msg_id.get().wrapping_add(1) {
+            0 => 1,
+            id => id,
+        };
+        self.msg_id.set(id);
+        id
+    }
+
+    /// Encode `request` into the buffer of the outstanding request with
+    /// `encode`, which receives the message id, and schedule its
+    /// transmission.
+    fn send_request<F>(&self, request: Request, encode: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&mut MessageWriter, u16) -> Result<(), ErrorCode>,
+    {
+        let msg_type = match request {
+            Request::Connect => msg_type::CONNECT,
+            Request::Register(_) => msg_type::REGISTER,
+            Request::Publish => msg_type::PUBLISH,
+            Request::Subscribe(_) => msg_type::SUBSCRIBE,
+            Request::Unsubscribe => msg_type::UNSUBSCRIBE,
+            Request::Ping => msg_type::PINGREQ,
+        };
+        let msg_id = self.next_msg_id();
+        let len = self.con_buffer.map_or(Err(ErrorCode::NOMEM), |buf| {
+            let mut writer = MessageWriter::new(buf, msg_type)?;
+            encode(&mut writer, msg_id)?;
+            writer.finish()
+        })?;
+        self.outstanding.set(Some(Outstanding {
+            request,
+            msg_id,
+            len,
+            transmissions: 0,
+        }));
+        self.retransmit.set(true);
+        Ok(())
+    }
+
+    /// Send the outstanding request if it is due, or a PINGREQ if the
+    /// keep-alive period passed.
+    fn send_next(&self) {
+        if self.outstanding.get().is_none()
+            && self.ping_due.get()
+            && self.state.get() == State::Connected
+        {
+            self.ping_due.set(false);
+            let _ = self.send_request(Request::Ping, |_, _| Ok(()));
+        }
+        if self.retransmit.get() {
+            self.transmit_outstanding();
+        }
+    }
+
+    fn transmit_outstanding(&self) {
+        let outstanding = match self.outstanding.get() {
+            Some(outstanding) => outstanding,
+            None => return,
+        };
+        let mut tx = match self.tx_buffer.take() {
+            Some(tx) => tx,
+            None => return,
+        };
+        self.retransmit.set(false);
+        self.con_buffer.map(|buf| {
+            tx[..outstanding.len].copy_from_slice(&buf[..outstanding.len]);
+            // Retransmitted publications are marked as duplicates
+            if outstanding.transmissions > 0 && outstanding.request == Request::Publish {
+                let flags_offset = if buf[0] == 0x01 { 4 } else { 2 };
+                tx[flags_offset] |= flags::DUP;
+            }
+        });
+        tx.slice(0..outstanding.len);
+        self.outstanding.set(Some(Outstanding {
+            transmissions: outstanding.transmissions + 1,
+            ..outstanding
+        }));
+        self.set_timer(&self.retry_timer, RETRY_MS);
+        // Requests that could not be sent are retransmitted
+        let _ = self.send(tx);
+    }
+
+    fn send(&self, buf: LeasableMutableBuffer<'static, u8>) -> Result<(), ErrorCode> {
+        self.sender
+            .send_to(self.gateway_addr, self.gateway_port, buf, self.net_cap)
+            .map_err(|mut buf| {
+                buf.reset();
+                self.tx_buffer.replace(buf);
+                ErrorCode::FAIL
+            })
+    }
+
+    fn publish_done(&self, result: Result<(), ErrorCode>) {
+        if self.publishing.take() {
+            self.client.map(|client| client.published(result));
+        }
+    }
+
+    /// Send an acknowledgement to the gateway, if the buffer is free.
+    fn send_ack(&self, msg_type: u8, topic_id: u16, msg_id: u16, return_code: u8) {
+        if let Some(mut tx) = self.tx_buffer.take() {
+            let len = MessageWriter::new(&mut tx[..], msg_type).and_then(|mut writer| {
+                writer.u16(topic_id)?;
+                writer.u16(msg_id)?;
+                writer.u8(return_code)?;
+                writer.finish()
+            });
+            match len {
+                Ok(len) => {
+                    tx.slice(0..len);
+                    let _ = self.send(tx);
+                }
+                Err(_) => {
+                    self.tx_buffer.replace(tx);
+                }
+            }
+        }
+    }
+
+    fn add_topic(&self, id: u16, name: Topic) -> Result<(), ErrorCode> {
+        let entry = self
+            .topics
+            .iter()
+            .find(|entry| entry.get().map_or(false, |entry| entry.name == name))
+            .or_else(|| self.topics.iter().find(|entry| entry.get().is_none()));
+        entry.map_or(Err(ErrorCode::NOMEM), |entry| {
+            entry.set(Some(TopicEntry { id, name }));
+            Ok(())
+        })
+    }
+
+    fn remove_topic(&self, id: u16) {
+        for entry in self.topics.iter() {
+            if entry.get().map_or(false, |entry| entry.id == id) {
+                entry.set(None);
+            }
+        }
+    }
+
+    /// The acknowledgement of the outstanding request with `msg_id`, `None`
+    /// for acknowledgements without message id.
+    fn acknowledge(&self, msg_id: Option<u16>) -> Option<Request> {
+        let outstanding = self.outstanding.get()?;
+        if msg_id.map_or(false, |msg_id| msg_id != outstanding.msg_id) {
+            return None;
+        }
+        self.outstanding.set(None);
+        self.retransmit.set(false);
+        self.retry_timer.clear();
+        self.rearm();
+        Some(outstanding.request)
+    }
+
+    /// Fail the outstanding request with `error`.
+    fn fail_outstanding(&self, error: ErrorCode) {
+        let outstanding = match self.outstanding.take() {
+            Some(outstanding) => outstanding,
+            None => return,
+        };
+        self.retransmit.set(false);
+        self.retry_timer.clear();
+        self.client.map(|client| match outstanding.request {
+            Request::Connect => client.connected(Err(error)),
+            Request::Register(_) => client.registered(Err(error)),
+            Request::Publish => client.published(Err(error)),
+            Request::Subscribe(_) => client.subscribed(Err(error)),
+            Request::Unsubscribe => client.unsubscribed(Err(error)),
+            Request::Ping => {}
+        });
+    }
+
+    /// Drop the connection, failing the outstanding request with `error`.
+    fn close(&self, error: ErrorCode) {
+        let was_connected = self.state.get() == State::Connected;
+        self.state.set(State::Disconnected);
+        self.ping_due.set(false);
+        self.keep_alive_timer.clear();
+        for entry in self.topics.iter() {
+            entry.set(None);
+        }
+        self.fail_outstanding(error);
+        self.rearm();
+        if was_connected {
+            self.client.map(|client| client.disconnected());
+        }
+    }
+
+    fn receive_message(&self, message: &Message) {
+        match message.msg_type {
+            msg_type::CONNACK => {
+                if self.state.get() != State::Connecting {
+                    return;
+                }
+                if let Some(Request::Connect) = self.acknowledge(None) {
+                    let result = message
+                        .u8_at(0)
+                        .map_or(Err(ErrorCode::FAIL), return_code::to_result);
+                    match result {
+                        Ok(()) => {
+                            self.state.set(State::Connected);
+                            self.set_timer(&self.keep_alive_timer, KEEP_ALIVE_S as u32 * 1000);
+                        }
+                        Err(_) => self.state.set(State::Disconnected),
+                    }
+                    self.client.map(|client| client.connected(result));
+                }
+            }
+            msg_type::REGACK => {
+                if let (Some(topic_id), Some(code)) = (message.u16_at(0), message.u8_at(4)) {
+                    if let Some(Request::Register(name)) = self.acknowledge(message.u16_at(2)) {
+                        let result = return_code::to_result(code)
+                            .and_then(|()| self.add_topic(topic_id, name))
+                            .map(|()| topic_id);
+                        self.client.map(|client| client.registered(result));
+                    }
+                }
+            }
+            msg_type::PUBACK => {
+                if let (Some(topic_id), Some(code)) = (message.u16_at(0), message.u8_at(4)) {
+                    if let Some(Request::Publish) = self.acknowledge(message.u16_at(2)) {
+                        let result = return_code::to_result(code);
+                        if result == Err(ErrorCode::INVAL) {
+                            self.remove_topic(topic_id);
+                        }
+                        self.client.map(|client| client.published(result));
+                    }
+                }
+            }
+            msg_type::SUBACK => {
+                if let (Some(topic_id), Some(code)) = (message.u16_at(1), message.u8_at(5)) {
+                    if let Some(Request::Subscribe(topic)) = self.acknowledge(message.u16_at(3)) {
+                        let result = return_code::to_result(code).map(|()| {
+                            if !topic.is_filter() {
+                                // Without room, publications to the topic
+                                // are still delivered by id
+                                let _ = self.add_topic(topic_id, topic);
+                            }
+                            topic_id
+                        });
+                        self.client.map(|client| client.subscribed(result));
+                    }
+                }
+            }
+            msg_type::UNSUBACK => {
+                if let Some(Request::Unsubscribe) = self.acknowledge(message.u16_at(0)) {
+                    self.client.map(|client| client.unsubscribed(Ok(())));
+                }
+            }
+            msg_type::PINGRESP => {
+                if let Some(Request::Ping) = self.outstanding.get().map(|o| o.request) {
+                    self.acknowledge(None);
+                }
+            }
+            msg_type::REGISTER => {
+                // The gateway registers the topics of wildcard subscriptions
+                if let (Some(topic_id), Some(msg_id)) = (message.u16_at(0), message.u16_at(2)) {
+                    let code = Topic::new(message.bytes_from(4))
+                        .map_or(Err(ErrorCode::INVAL), |name| self.add_topic(topic_id, name))
+                        .map_or(return_code::CONGESTION, |()| return_code::ACCEPTED);
+                    self.send_ack(msg_type::REGACK, topic_id, msg_id, code);
+                }
+            }
+            msg_type::PUBLISH => {
+                if let (Some(flags), Some(topic_id), Some(msg_id)) =
+                    (message.u8_at(0), message.u16_at(1), message.u16_at(3))
+                {
+                    let qos = (flags & flags::QOS_MASK) >> flags::QOS_SHIFT;
+                    if qos == QoS::AtLeastOnce as u8 {
+                        self.send_ack(msg_type::PUBACK, topic_id, msg_id, return_code::ACCEPTED);
+                    }
+                    self.client
+                        .map(|client| client.received(topic_id, message.bytes_from(5)));
+                }
+            }
+            msg_type::DISCONNECT => {
+                if self.state.get() != State::Disconnected {
+                    self.close(ErrorCode::CANCEL);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn set_timer(&self, timer: &OptionalCell<Deadline<A::Ticks>>, ms: u32) {
+        timer.set(Deadline {
+            reference: self.alarm.now(),
+            dt: self.alarm.ticks_from_ms(ms),
+        });
+        self.rearm();
+    }
+
+    /// Run the alarm at the earliest deadline of the timers.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let earliest = [&self.retry_timer, &self.keep_alive_timer]
+            .iter()
+            .filter_map(|timer| timer.extract())
+            .map(|deadline| deadline.remaining(now))
+            .min();
+        match earliest {
+            Some(dt) => self.alarm.set_alarm(now, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+
+    fn retry_timeout(&self) {
+        let outstanding = match self.outstanding.get() {
+            Some(outstanding) => outstanding,
+            None => return,
+        };
+        if outstanding.transmissions <= MAX_RETRANSMIT {
+            self.retransmit.set(true);
+            return;
+        }
+        // The gateway is lost
+        self.close(ErrorCode::NOACK);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> UDPRecvClient for MqttSn<'a, A> {
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        _dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        if src_addr != self.gateway_addr || src_port != self.gateway_port {
+            return;
+        }
+        if let Ok(message) = Message::decode(payload) {
+            self.receive_message(&message);
+        }
+        self.send_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> UDPSendClient for MqttSn<'a, A> {
+    fn send_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        mut dgram: LeasableMutableBuffer<'static, u8>,
+    ) {
+        // Lost requests are retransmitted, other messages are not
+        dgram.reset();
+        self.tx_buffer.replace(dgram);
+        self.publish_done(result);
+        self.send_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for MqttSn<'a, A> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        let expired = |timer: &OptionalCell<Deadline<A::Ticks>>| {
+            let expired = timer.map_or(false, |deadline| {
+                deadline.remaining(now) == A::Ticks::from(0)
+            });
+            if expired {
+                timer.clear();
+            }
+            expired
+        };
+        if expired(&self.retry_timer) {
+            self.retry_timeout();
+        }
+        if expired(&self.keep_alive_timer) && self.state.get() == State::Connected {
+            self.ping_due.set(true);
+            self.set_timer(&self.keep_alive_timer, KEEP_ALIVE_S as u32 * 1000);
+        }
+        self.send_next();
+        self.rearm();
+    }
+}
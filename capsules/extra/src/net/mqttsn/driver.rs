@@ -0,0 +1,527 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Userspace interface to publish and subscribe to topics with MQTT-SN.
+//!
+//! All processes share the connection of the kernel client to the gateway.
+//! A process connects it, then publishes the `PAYLOAD` buffer to the topic
+//! name in the `TOPIC` buffer, and subscribes to the topic name or filter in
+//! `TOPIC`. Topic names are registered with the gateway on the first
+//! publication. Each process has one operation in progress, which completes
+//! with an upcall; operations of different processes are queued.
+//!
+//! Publications to the topics a process subscribed to are copied into its
+//! `RECEIVE` buffer, and the topic name, if the gateway registered it, into
+//! its `RECEIVE_TOPIC` buffer.
+
+use crate::net::mqttsn::client::{MqttSn, MqttSnClient, State};
+use crate::net::mqttsn::message::{QoS, Topic, MAX_TOPIC_LEN};
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::Alarm;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use core::cell::Cell;
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::MqttSn as usize;
+
+/// Subscriptions of each process.
+pub const MAX_SUBSCRIPTIONS: usize = 4;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// The topic name or filter of a publication or subscription
+    pub const TOPIC: usize = 0;
+    /// The payload of a publication
+    pub const PAYLOAD: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// The payload of the last received publication
+    pub const RECEIVE: usize = 0;
+    /// The topic name of the last received publication
+    pub const RECEIVE_TOPIC: usize = 1;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// An operation completed
+    pub const DONE: usize = 0;
+    /// A publication was copied into the `RECEIVE` buffer
+    pub const RECEIVED: usize = 1;
+    /// The connection to the gateway was lost
+    pub const DISCONNECTED: usize = 2;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 3;
+}
+
+/// An operation of a process, numbered as its command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operation {
+    Connect,
+    Publish { qos: QoS, retain: bool },
+    Subscribe { qos: QoS },
+    Unsubscribe,
+}
+
+impl Operation {
+    fn number(&self) -> usize {
+        match self {
+            Operation::Connect => 1,
+            Operation::Publish { .. } => 2,
+            Operation::Subscribe { .. } => 3,
+            Operation::Unsubscribe => 4,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Subscription {
+    filter: Topic,
+    /// The topic id of a topic name, 0 for filters with wildcards.
+    topic_id: u16,
+}
+
+#[derive(Default)]
+pub struct App {
+    /// An operation waiting for the one in progress.
+    pending: Option<Operation>,
+    subscriptions: [Option<Subscription>; MAX_SUBSCRIPTIONS],
+}
+
+impl App {
+    fn subscribed(&self, filter: &Topic) -> bool {
+        self.subscriptions
+            .iter()
+            .flatten()
+            .any(|subscription| subscription.filter == *filter)
+    }
+}
+
+/// The operation in progress.
+#[derive(Copy, Clone)]
+struct Current {
+    processid: ProcessId,
+    operation: Operation,
+    topic: Option<Topic>,
+}
+
+pub struct MqttSnDriver<'a, A: Alarm<'a>> {
+    client: &'a MqttSn<'a, A>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    current: OptionalCell<Current>,
+    /// The payload of the publication in progress, kept while its topic is
+    /// registered.
+    payload: TakeCell<'static, [u8]>,
+    payload_len: Cell<usize>,
+}
+
+impl<'a, A: Alarm<'a>> MqttSnDriver<'a, A> {
+    pub fn new(
+        client: &'a MqttSn<'a, A>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        payload: &'static mut [u8],
+    ) -> MqttSnDriver<'a, A> {
+        MqttSnDriver {
+            client,
+            apps: grant,
+            current: OptionalCell::empty(),
+            payload: TakeCell::new(payload),
+            payload_len: Cell::new(0),
+        }
+    }
+
+    /// Start `operation` of the process, or queue it behind the one in
+    /// progress.
+    fn enqueue(&self, operation: Operation, processid: ProcessId) -> CommandReturn {
+        let result = self
+            .apps
+            .enter(processid, |app, _| {
+                let busy = app.pending.is_some()
+                    || self
+                        .current
+                        .map_or(false, |current| current.processid == processid);
+                if busy {
+                    return Err(ErrorCode::BUSY);
+                }
+                if self.current.is_some() {
+                    app.pending = Some(operation);
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        if result.is_ok() && self.current.is_none() {
+            return self.start(operation, processid).into();
+        }
+        result.into()
+    }
+
+    /// Start the next queued operation.
+    fn run_next(&self) {
+        while self.current.is_none() {
+            let next = self.apps.iter().find_map(|app| {
+                let processid = app.processid();
+                app.enter(|app, _| app.pending.take())
+                    .map(|operation| (operation, processid))
+            });
+            let (operation, processid) = match next {
+                Some(next) => next,
+                None => return,
+            };
+            if let Err(error) = self.start(operation, processid) {
+                let _ = self.apps.enter(processid, |_, kernel_data| {
+                    kernel_data.schedule_upcall(
+                        upcall::DONE,
+                        (into_statuscode(Err(error)), operation.number(), 0),
+                    )
+                });
+            }
+        }
+    }
+
+    fn start(&self, operation: Operation, processid: ProcessId) -> Result<(), ErrorCode> {
+        let topic = match operation {
+            Operation::Connect => None,
+            _ => Some(self.read_topic(processid)?),
+        };
+        let current = Current {
+            processid,
+            operation,
+            topic,
+        };
+        match (operation, topic) {
+            (Operation::Connect, _) => self.client.connect()?,
+            (Operation::Publish { qos, retain }, Some(topic)) => {
+                if topic.is_filter() {
+                    return Err(ErrorCode::INVAL);
+                }
+                self.read_payload(processid)?;
+                match self.client.topic_id(topic.as_slice()) {
+                    Some(topic_id) => self.publish(topic_id, qos, retain)?,
+                    None => self.client.register(topic.as_slice())?,
+                }
+            }
+            (Operation::Subscribe { qos }, Some(topic)) => {
+                let room = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        app.subscribed(&topic) || app.subscriptions.iter().any(Option::is_none)
+                    })
+                    .unwrap_or(false);
+                if !room {
+                    return Err(ErrorCode::NOMEM);
+                }
+                self.client.subscribe(topic.as_slice(), qos)?;
+            }
+            (Operation::Unsubscribe, Some(topic)) => {
+                let subscribed = self
+                    .apps
+                    .enter(processid, |app, _| app.subscribed(&topic))
+                    .unwrap_or(false);
+                if !subscribed {
+                    return Err(ErrorCode::INVAL);
+                }
+                // Other processes keep the subscription with the gateway
+                let shared = self.apps.iter().any(|app| {
+                    app.processid() != processid && app.enter(|app, _| app.subscribed(&topic))
+                });
+                if shared {
+                    self.current.set(current);
+                    self.unsubscribed(Ok(()));
+                    return Ok(());
+                }
+                self.client.unsubscribe(topic.as_slice())?;
+            }
+            _ => return Err(ErrorCode::FAIL),
+        }
+        self.current.set(current);
+        Ok(())
+    }
+
+    fn read_topic(&self, processid: ProcessId) -> Result<Topic, ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::TOPIC)
+                    .and_then(|topic| {
+                        topic.enter(|topic| {
+                            let mut buf = [0; MAX_TOPIC_LEN];
+                            let len = topic.len();
+                            if len > MAX_TOPIC_LEN {
+                                return None;
+                            }
+                            topic.copy_to_slice(&mut buf[..len]);
+                            Topic::new(&buf[..len])
+                        })
+                    })
+                    .unwrap_or(None)
+            })
+            .unwrap_or(None)
+            .ok_or(ErrorCode::INVAL)
+    }
+
+    fn read_payload(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::PAYLOAD)
+                    .and_then(|payload| {
+                        payload.enter(|payload| {
+                            self.payload.map_or(Err(ErrorCode::NOMEM), |buf| {
+                                let dst = buf.get_mut(..payload.len()).ok_or(ErrorCode::SIZE)?;
+                                payload.copy_to_slice(dst);
+                                Ok(payload.len())
+                            })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::FAIL))
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        self.payload_len.set(len);
+        Ok(())
+    }
+
+    fn publish(&self, topic_id: u16, qos: QoS, retain: bool) -> Result<(), ErrorCode> {
+        self.payload.map_or(Err(ErrorCode::NOMEM), |payload| {
+            self.client
+                .publish(topic_id, qos, retain, &payload[..self.payload_len.get()])
+        })
+    }
+
+    /// Complete the operation in progress, `update` the state of its
+    /// process, and start the next one.
+    fn complete<F>(&self, result: Result<(), ErrorCode>, topic_id: u16, update: F)
+    where
+        F: FnOnce(&mut App, &Current),
+    {
+        if let Some(current) = self.current.take() {
+            let _ = self.apps.enter(current.processid, |app, kernel_data| {
+                update(app, &current);
+                kernel_data.schedule_upcall(
+                    upcall::DONE,
+                    (
+                        into_statuscode(result),
+                        current.operation.number(),
+                        topic_id as usize,
+                    ),
+                )
+            });
+        }
+        self.run_next();
+    }
+
+    /// Whether the process subscribed to the publication to `topic_id`, with
+    /// the topic `name` if the gateway registered it.
+    fn matches(app: &App, topic_id: u16, name: Option<&Topic>) -> bool {
+        app.subscriptions.iter().flatten().any(|subscription| {
+            if subscription.topic_id != 0 {
+                subscription.topic_id == topic_id
+            } else {
+                name.map_or(false, |name| subscription.filter.matches(name.as_slice()))
+            }
+        })
+    }
+}
+
+impl<'a, A: Alarm<'a>> MqttSnClient for MqttSnDriver<'a, A> {
+    fn connected(&self, result: Result<(), ErrorCode>) {
+        self.complete(result, 0, |_, _| {});
+    }
+
+    fn disconnected(&self) {
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                app.subscriptions = Default::default();
+                let _ = kernel_data.schedule_upcall(upcall::DISCONNECTED, (0, 0, 0));
+            });
+        }
+    }
+
+    fn registered(&self, result: Result<u16, ErrorCode>) {
+        let publish = self.current.and_then(|current| match current.operation {
+            Operation::Publish { qos, retain } => Some((qos, retain)),
+            _ => None,
+        });
+        let result = match (result, publish) {
+            (Ok(topic_id), Some((qos, retain))) => self.publish(topic_id, qos, retain),
+            (Ok(_), None) => Ok(()),
+            (Err(error), _) => Err(error),
+        };
+        if let Err(error) = result {
+            self.complete(Err(error), 0, |_, _| {});
+        }
+    }
+
+    fn published(&self, result: Result<(), ErrorCode>) {
+        let topic_id = self
+            .current
+            .and_then(|current| current.topic)
+            .and_then(|topic| self.client.topic_id(topic.as_slice()))
+            .unwrap_or(0);
+        self.complete(result, topic_id, |_, _| {});
+    }
+
+    fn subscribed(&self, result: Result<u16, ErrorCode>) {
+        let topic_id = *result.as_ref().unwrap_or(&0);
+        self.complete(result.map(|_| ()), topic_id, |app, current| {
+            let filter = match (result, current.topic) {
+                (Ok(_), Some(filter)) => filter,
+                _ => return,
+            };
+            let subscription = Subscription { filter, topic_id };
+            let index = app
+                .subscriptions
+                .iter()
+                .position(|slot| slot.map_or(false, |s| s.filter == filter))
+                .or_else(|| app.subscriptions.iter().position(Option::is_none));
+            if let Some(index) = index {
+                app.subscriptions[index] = Some(subscription);
+            }
+        });
+    }
+
+    fn unsubscribed(&self, result: Result<(), ErrorCode>) {
+        self.complete(result, 0, |app, current| {
+            if let Some(topic) = current.topic {
+                for subscription in app.subscriptions.iter_mut() {
+                    if subscription.map_or(false, |s| s.filter == topic) {
+                        *subscription = None;
+                    }
+                }
+            }
+        });
+    }
+
+    fn received(&self, topic_id: u16, payload: &[u8]) {
+        let name = self.client.topic_name(topic_id);
+        for app in self.apps.iter() {
+            app.enter(|app, kernel_data| {
+                if !Self::matches(app, topic_id, name.as_ref()) {
+                    return;
+                }
+                let copied = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::RECEIVE)
+                    .and_then(|receive| {
+                        receive.mut_enter(|receive| {
+                            receive
+                                .get(0..payload.len())
+                                .map(|receive| receive.copy_from_slice(payload))
+                                .is_some()
+                        })
+                    })
+                    .unwrap_or(false);
+                if !copied {
+                    return;
+                }
+                let name_len = name.map_or(0, |name| {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::RECEIVE_TOPIC)
+                        .and_then(|receive_topic| {
+                            receive_topic.mut_enter(|receive_topic| {
+                                let name = name.as_slice();
+                                receive_topic.get(0..name.len()).map_or(0, |receive_topic| {
+                                    receive_topic.copy_from_slice(name);
+                                    name.len()
+                                })
+                            })
+                        })
+                        .unwrap_or(0)
+                });
+                let _ = kernel_data.schedule_upcall(
+                    upcall::RECEIVED,
+                    (payload.len(), topic_id as usize, name_len),
+                );
+            });
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for MqttSnDriver<'a, A> {
+    /// Commands for the connection to the gateway and the topics of the
+    /// process.
+    ///
+    /// Operations complete with the `DONE` upcall, with the status, the
+    /// command number and the topic id.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Connect to the gateway. The upcall reports `ALREADY` if the
+    ///   kernel is connected.
+    /// - `2`: Publish the `PAYLOAD` buffer to the topic name in the `TOPIC`
+    ///   buffer, with QoS `arg1`, 0 or 1, and retained if `arg2` is not 0.
+    /// - `3`: Subscribe to the topic name or filter in the `TOPIC` buffer,
+    ///   with at most QoS `arg1`. A process has up to 4 subscriptions.
+    /// - `4`: Unsubscribe from the topic name or filter in the `TOPIC`
+    ///   buffer.
+    /// - `5`: The state of the connection: 0 disconnected, 1 connecting, 2
+    ///   connected.
+    ///
+    /// Commands `1` to `4` return `BUSY` if an operation of the process is
+    /// in progress.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.enqueue(Operation::Connect, processid),
+
+            2 => match QoS::from_usize(arg1) {
+                Some(qos) => self.enqueue(
+                    Operation::Publish {
+                        qos,
+                        retain: arg2 != 0,
+                    },
+                    processid,
+                ),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            3 => match QoS::from_usize(arg1) {
+                Some(qos) => self.enqueue(Operation::Subscribe { qos }, processid),
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            4 => self.enqueue(Operation::Unsubscribe, processid),
+
+            5 => CommandReturn::success_u32(match self.client.state() {
+                State::Disconnected => 0,
+                State::Connecting => 1,
+                State::Connected => 2,
+            }),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
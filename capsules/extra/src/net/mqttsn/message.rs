@@ -0,0 +1,279 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! MQTT-SN messages (MQTT-SN 1.2).
+//!
+//! A message starts with its length, one byte, or three bytes `0x01` and a
+//! 16 bit length for messages longer than 255 bytes, followed by the
+//! message type and the fields of the type. Multi-byte fields are in network
+//! byte order.
+
+use kernel::ErrorCode;
+
+/// The protocol id of MQTT-SN 1.2 in CONNECT messages.
+pub const PROTOCOL_ID: u8 = 0x01;
+/// Maximum length of a topic name or filter.
+pub const MAX_TOPIC_LEN: usize = 32;
+
+/// Message types.
+pub mod msg_type {
+    pub const CONNECT: u8 = 0x04;
+    pub const CONNACK: u8 = 0x05;
+    pub const REGISTER: u8 = 0x0a;
+    pub const REGACK: u8 = 0x0b;
+    pub const PUBLISH: u8 = 0x0c;
+    pub const PUBACK: u8 = 0x0d;
+    pub const SUBSCRIBE: u8 = 0x12;
+    pub const SUBACK: u8 = 0x13;
+    pub const UNSUBSCRIBE: u8 = 0x14;
+    pub const UNSUBACK: u8 = 0x15;
+    pub const PINGREQ: u8 = 0x16;
+    pub const PINGRESP: u8 = 0x17;
+    pub const DISCONNECT: u8 = 0x18;
+}
+
+/// Bits of the flags field.
+pub mod flags {
+    pub const DUP: u8 = 1 << 7;
+    pub const QOS_SHIFT: u8 = 5;
+    pub const QOS_MASK: u8 = 0x3 << QOS_SHIFT;
+    pub const RETAIN: u8 = 1 << 4;
+    pub const CLEAN_SESSION: u8 = 1 << 2;
+    pub const TOPIC_ID_TYPE_MASK: u8 = 0x3;
+
+    /// The topic id is a registered one.
+    pub const TOPIC_NORMAL: u8 = 0;
+    /// The topic id is a predefined one.
+    pub const TOPIC_PREDEFINED: u8 = 1;
+    /// The topic id is a two byte topic name.
+    pub const TOPIC_SHORT_NAME: u8 = 2;
+}
+
+/// Return codes of acknowledgements.
+pub mod return_code {
+    use kernel::ErrorCode;
+
+    pub const ACCEPTED: u8 = 0x00;
+    pub const CONGESTION: u8 = 0x01;
+    pub const INVALID_TOPIC_ID: u8 = 0x02;
+    pub const NOT_SUPPORTED: u8 = 0x03;
+
+    pub fn to_result(code: u8) -> Result<(), ErrorCode> {
+        match code {
+            ACCEPTED => Ok(()),
+            CONGESTION => Err(ErrorCode::BUSY),
+            INVALID_TOPIC_ID => Err(ErrorCode::INVAL),
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+}
+
+/// Quality of service of a publication or subscription.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+}
+
+impl QoS {
+    pub fn from_usize(qos: usize) -> Option<QoS> {
+        match qos {
+            0 => Some(QoS::AtMostOnce),
+            1 => Some(QoS::AtLeastOnce),
+            _ => None,
+        }
+    }
+
+    pub fn flags(&self) -> u8 {
+        (*self as u8) << flags::QOS_SHIFT
+    }
+}
+
+/// A topic name, or a filter with the wildcards `+` and `#`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Topic {
+    len: u8,
+    bytes: [u8; MAX_TOPIC_LEN],
+}
+
+impl Topic {
+    /// The topic `name`, `None` if it is empty or longer than
+    /// `MAX_TOPIC_LEN`.
+    pub fn new(name: &[u8]) -> Option<Topic> {
+        if name.is_empty() || name.len() > MAX_TOPIC_LEN {
+            return None;
+        }
+        let mut bytes = [0; MAX_TOPIC_LEN];
+        bytes[..name.len()].copy_from_slice(name);
+        Some(Topic {
+            len: name.len() as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// Whether the topic is a filter with wildcards.
+    pub fn is_filter(&self) -> bool {
+        self.as_slice().iter().any(|&b| b == b'+' || b == b'#')
+    }
+
+    /// Whether the topic name `name` matches this filter.
+    pub fn matches(&self, name: &[u8]) -> bool {
+        let mut levels = name.split(|&b| b == b'/');
+        for filter in self.as_slice().split(|&b| b == b'/') {
+            match (filter, levels.next()) {
+                (b"#", _) => return true,
+                (b"+", Some(_)) => {}
+                (filter, Some(level)) if filter == level => {}
+                _ => return false,
+            }
+        }
+        levels.next().is_none()
+    }
+}
+
+/// A received message: its type and the fields after the type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub msg_type: u8,
+    pub body: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    pub fn decode(buf: &'a [u8]) -> Result<Message<'a>, ErrorCode> {
+        let (len, header_len) = match buf.first() {
+            Some(0x01) if buf.len() >= 4 => (u16::from_be_bytes([buf[1], buf[2]]) as usize, 3),
+            Some(&len) if len > 1 => (len as usize, 1),
+            _ => return Err(ErrorCode::INVAL),
+        };
+        if len <= header_len || len > buf.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(Message {
+            msg_type: buf[header_len],
+            body: &buf[header_len + 1..len],
+        })
+    }
+
+    pub fn u8_at(&self, offset: usize) -> Option<u8> {
+        self.body.get(offset).copied()
+    }
+
+    pub fn u16_at(&self, offset: usize) -> Option<u16> {
+        self.body
+            .get(offset..offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn bytes_from(&self, offset: usize) -> &'a [u8] {
+        self.body.get(offset..).unwrap_or(&[])
+    }
+}
+
+/// Encodes a message into a buffer.
+pub struct MessageWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> MessageWriter<'b> {
+    /// Start a message of `msg_type`. The length is written by `finish`.
+    pub fn new(buf: &'b mut [u8], msg_type: u8) -> Result<MessageWriter<'b>, ErrorCode> {
+        let mut writer = MessageWriter { buf, len: 1 };
+        writer.u8(msg_type)?;
+        Ok(writer)
+    }
+
+    pub fn u8(&mut self, value: u8) -> Result<(), ErrorCode> {
+        self.bytes(&[value])
+    }
+
+    pub fn u16(&mut self, value: u16) -> Result<(), ErrorCode> {
+        self.bytes(&value.to_be_bytes())
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> Result<(), ErrorCode> {
+        let dst = self
+            .buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or(ErrorCode::SIZE)?;
+        dst.copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    /// Write the length, and return the length of the message.
+    pub fn finish(self) -> Result<usize, ErrorCode> {
+        if self.len <= 0xff {
+            self.buf[0] = self.len as u8;
+            return Ok(self.len);
+        }
+        // Long messages have a three byte length
+        let len = self.len + 2;
+        if len > self.buf.len() || len > 0xffff {
+            return Err(ErrorCode::SIZE);
+        }
+        self.buf.copy_within(1..self.len, 3);
+        self.buf[0] = 0x01;
+        self.buf[1..3].copy_from_slice(&(len as u16).to_be_bytes());
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let mut buf = [0; 300];
+        let mut writer = MessageWriter::new(&mut buf, msg_type::PUBLISH).unwrap();
+        writer.u8(QoS::AtLeastOnce.flags()).unwrap();
+        writer.u16(0x1234).unwrap();
+        writer.u16(7).unwrap();
+        writer.bytes(b"21.5").unwrap();
+        let len = writer.finish().unwrap();
+        assert_eq!(&buf[..3], &[11, msg_type::PUBLISH, 0x20]);
+
+        let message = Message::decode(&buf[..len]).unwrap();
+        assert_eq!(message.msg_type, msg_type::PUBLISH);
+        assert_eq!(message.u16_at(1), Some(0x1234));
+        assert_eq!(message.u16_at(3), Some(7));
+        assert_eq!(message.bytes_from(5), b"21.5");
+        assert_eq!(message.u16_at(9), None);
+
+        // Messages longer than 255 bytes have a three byte length
+        let mut writer = MessageWriter::new(&mut buf, msg_type::PUBLISH).unwrap();
+        writer.bytes(&[0xaa; 260]).unwrap();
+        let len = writer.finish().unwrap();
+        assert_eq!(len, 264);
+        assert_eq!(&buf[..4], &[0x01, 0x01, 0x08, msg_type::PUBLISH]);
+        let message = Message::decode(&buf[..len]).unwrap();
+        assert_eq!(message.body, &[0xaa; 260][..]);
+
+        assert!(Message::decode(&[5, msg_type::PINGRESP]).is_err());
+        assert!(Message::decode(&[1]).is_err());
+    }
+
+    #[test]
+    fn topic_filters() {
+        let filter = |filter: &[u8]| Topic::new(filter).unwrap();
+        assert!(filter(b"sensors/temp").matches(b"sensors/temp"));
+        assert!(!filter(b"sensors/temp").matches(b"sensors/temp/1"));
+        assert!(filter(b"sensors/+").matches(b"sensors/temp"));
+        assert!(!filter(b"sensors/+").matches(b"sensors"));
+        assert!(filter(b"sensors/+/raw").matches(b"sensors/temp/raw"));
+        assert!(filter(b"sensors/#").matches(b"sensors/temp/raw"));
+        assert!(filter(b"#").matches(b"sensors"));
+        assert!(!filter(b"actuators/#").matches(b"sensors/temp"));
+
+        assert!(filter(b"sensors/#").is_filter());
+        assert!(!filter(b"sensors/temp").is_filter());
+        assert!(Topic::new(b"").is_none());
+        assert!(Topic::new(&[b'a'; MAX_TOPIC_LEN + 1]).is_none());
+    }
+}
@@ -10,8 +10,10 @@ use crate::net::util;
 use crate::net::util::{network_slice_to_u16, u16_to_network_slice};
 /// Implements the 6LoWPAN specification for sending IPv6 datagrams over
 /// 802.15.4 packets efficiently, as detailed in RFC 6282.
+use core::cell::Cell;
 use core::mem;
 use core::result::Result;
+use kernel::ErrorCode;
 
 /// Contains bit masks and constants related to the two-byte header of the
 /// LoWPAN_IPHC encoding format.
@@ -156,6 +158,87 @@ impl ContextStore for Context {
     }
 }
 
+/// Number of context identifiers that fit in the 4 bits of a CID.
+pub const MAX_CONTEXTS: usize = 16;
+
+/// Contexts configured at runtime, e.g. from the 6LoWPAN context options of
+/// router advertisements (RFC 6775).
+///
+/// Context 0 always exists. A context with `compress` unset is only used to
+/// decompress, as RFC 6775 asks of contexts that are being phased in or out.
+/// To compress an address, the longest matching prefix of the contexts that
+/// compress is used.
+pub struct ContextTable {
+    contexts: [Cell<Option<Context>>; MAX_CONTEXTS],
+}
+
+impl ContextTable {
+    pub fn new(context_0: Context) -> ContextTable {
+        let contexts: [Cell<Option<Context>>; MAX_CONTEXTS] = Default::default();
+        contexts[0].set(Some(Context { id: 0, ..context_0 }));
+        ContextTable { contexts }
+    }
+
+    /// Add a context, or replace the context with the same ID.
+    ///
+    /// Returns `INVAL` if the ID does not fit in 4 bits or the prefix is
+    /// longer than 128 bits.
+    pub fn set_context(&self, context: Context) -> Result<(), ErrorCode> {
+        if context.id as usize >= MAX_CONTEXTS || context.prefix_len > 128 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.contexts[context.id as usize].set(Some(context));
+        Ok(())
+    }
+
+    /// Start or stop using a context to compress.
+    pub fn set_compress(&self, id: u8, compress: bool) -> Result<(), ErrorCode> {
+        let context = self.get_context_from_id(id).ok_or(ErrorCode::INVAL)?;
+        self.set_context(Context {
+            compress,
+            ..context
+        })
+    }
+
+    /// Remove a context. Context 0 cannot be removed.
+    pub fn remove_context(&self, id: u8) -> Result<(), ErrorCode> {
+        if id == 0 || id as usize >= MAX_CONTEXTS {
+            return Err(ErrorCode::INVAL);
+        }
+        self.contexts[id as usize].set(None);
+        Ok(())
+    }
+
+    fn contexts(&self) -> impl Iterator<Item = Context> + '_ {
+        self.contexts.iter().filter_map(|context| context.get())
+    }
+}
+
+impl ContextStore for ContextTable {
+    fn get_context_from_addr(&self, ip_addr: IPAddr) -> Option<Context> {
+        let matches = || {
+            self.contexts()
+                .filter(|ctx| util::matches_prefix(&ip_addr.0, &ctx.prefix, ctx.prefix_len))
+        };
+        matches()
+            .filter(|ctx| ctx.compress)
+            .max_by_key(|ctx| ctx.prefix_len)
+            .or_else(|| matches().max_by_key(|ctx| ctx.prefix_len))
+    }
+
+    fn get_context_from_id(&self, ctx_id: u8) -> Option<Context> {
+        self.contexts
+            .get(ctx_id as usize)
+            .and_then(|context| context.get())
+    }
+
+    fn get_context_from_prefix(&self, prefix: &[u8], prefix_len: u8) -> Option<Context> {
+        self.contexts().find(|ctx| {
+            ctx.prefix_len == prefix_len && util::matches_prefix(prefix, &ctx.prefix, prefix_len)
+        })
+    }
+}
+
 pub fn is_lowpan(packet: &[u8]) -> bool {
     (packet[0] & iphc::DISPATCH[0]) == iphc::DISPATCH[0]
 }
@@ -1190,3 +1273,47 @@ fn decompress_udp_checksum(
         checksum
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(id: u8, prefix: &[u8], prefix_len: u8, compress: bool) -> Context {
+        let mut ctx = Context {
+            prefix: [0; 16],
+            prefix_len,
+            id,
+            compress,
+        };
+        ctx.prefix[..prefix.len()].copy_from_slice(prefix);
+        ctx
+    }
+
+    #[test]
+    fn context_table_picks_longest_compressing_prefix() {
+        let table = ContextTable::new(context(0, &[0xfd, 0x00], 16, true));
+        table
+            .set_context(context(3, &[0xfd, 0x00, 0x12, 0x34], 32, true))
+            .unwrap();
+        table
+            .set_context(context(5, &[0xfd, 0x00, 0x12, 0x34, 0x56], 40, false))
+            .unwrap();
+
+        let mut addr = IPAddr([0; 16]);
+        addr.0[..6].copy_from_slice(&[0xfd, 0x00, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(table.get_context_from_addr(addr).map(|ctx| ctx.id), Some(3));
+
+        // Once context 5 compresses, it is the longest match.
+        table.set_compress(5, true).unwrap();
+        assert_eq!(table.get_context_from_addr(addr).map(|ctx| ctx.id), Some(5));
+
+        table.remove_context(5).unwrap();
+        assert!(table.get_context_from_id(5).is_none());
+        assert_eq!(table.get_context_0().prefix_len, 16);
+        assert_eq!(table.remove_context(0), Err(ErrorCode::INVAL));
+        assert_eq!(
+            table.set_context(context(16, &[], 0, true)),
+            Err(ErrorCode::INVAL)
+        );
+    }
+}
@@ -243,11 +243,12 @@ use core::cmp::min;
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil::radio;
 use kernel::hil::time;
-use kernel::hil::time::{Frequency, Ticks};
+use kernel::hil::time::Ticks;
 use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::utilities::time_conversion::{self, Rounding};
 use kernel::ErrorCode;
 
-// Reassembly timeout in seconds
+// Default reassembly timeout in seconds, the maximum RFC 4944 allows
 const FRAG_TIMEOUT: u32 = 60;
 
 /// Objects that implement this trait can set themselves to be the client
@@ -257,6 +258,10 @@ pub trait SixlowpanRxClient {
     fn receive<'a>(&self, buf: &'a [u8], len: usize, result: Result<(), ErrorCode>);
 }
 
+/// Largest datagram reassembled, which is what an `RxState` buffer should
+/// hold.
+pub const MAX_DGRAM_SIZE: usize = 1280;
+
 pub mod lowpan_frag {
     pub const FRAGN_HDR: u8 = 0b11100000;
     pub const FRAG1_HDR: u8 = 0b11000000;
@@ -627,8 +632,9 @@ impl<'a> TxState<'a> {
 ///
 /// A list of `RxState`s is maintained by [Sixlowpan](struct.Sixlowpan.html) to
 /// keep track of ongoing packet reassemblies. The number of `RxState`s is the
-/// number of packets that can be reassembled at the same time. Generally,
-/// two `RxState`s are sufficient for normal-case operation.
+/// number of packets that can be reassembled at the same time, from one or
+/// several neighbors. Generally, two `RxState`s are sufficient for
+/// normal-case operation.
 pub struct RxState<'a> {
     packet: TakeCell<'static, [u8]>,
     bitmap: MapCell<Bitmap>,
@@ -686,16 +692,6 @@ impl<'a> RxState<'a> {
             && (self.dst_mac_addr.get() == dst_mac_addr)
     }
 
-    // Checks if a given RxState is free or expired (and thus, can be freed).
-    // This function implements the reassembly timeout for 6LoWPAN lazily.
-    fn is_busy(&self, frequency: u32, current_time: u32) -> bool {
-        let expired = current_time >= (self.start_time.get() + FRAG_TIMEOUT * frequency);
-        if expired {
-            self.end_receive(None, Err(ErrorCode::FAIL));
-        }
-        self.busy.get()
-    }
-
     fn start_receive(
         &self,
         src_mac_addr: MacAddress,
@@ -725,8 +721,18 @@ impl<'a> RxState<'a> {
         ctx_store: &dyn ContextStore,
     ) -> Result<bool, Result<(), ErrorCode>> {
         let mut packet = self.packet.take().ok_or(Err(ErrorCode::NOMEM))?;
+        // Fragments come from the radio: make sure they fit in the datagram
+        // and the buffer before copying them.
+        let packet_len = packet.len();
+        let fits = |len: usize| {
+            dgram_offset + len <= dgram_size as usize && dgram_offset + len <= packet_len
+        };
+        if dgram_offset != 0 && !fits(payload_len) {
+            self.packet.replace(packet);
+            return Err(Err(ErrorCode::SIZE));
+        }
         let uncompressed_len = if dgram_offset == 0 {
-            let (consumed, written) = sixlowpan_compression::decompress(
+            let decompressed = sixlowpan_compression::decompress(
                 ctx_store,
                 &payload[0..payload_len as usize],
                 self.src_mac_addr.get(),
@@ -735,8 +741,19 @@ impl<'a> RxState<'a> {
                 dgram_size,
                 true,
             )
-            .map_err(|_| Err(ErrorCode::FAIL))?;
+            .map_err(|_| Err(ErrorCode::FAIL));
+            let (consumed, written) = match decompressed {
+                Ok(lengths) => lengths,
+                Err(error) => {
+                    self.packet.replace(packet);
+                    return Err(error);
+                }
+            };
             let remaining = payload_len - consumed;
+            if !fits(written + remaining) {
+                self.packet.replace(packet);
+                return Err(Err(ErrorCode::SIZE));
+            }
             packet[written..written + remaining]
                 .copy_from_slice(&payload[consumed..consumed + remaining]);
             written + remaining
@@ -793,6 +810,17 @@ impl<'a> RxState<'a> {
 ///
 /// Finally, `set_client` controls the client that will receive transmission
 /// completion and reception callbacks.
+///
+/// # Reassembly
+///
+/// Fragments are matched to their datagram by the MAC addresses of both
+/// ends, the datagram size and the datagram tag, so datagrams from several
+/// neighbors, or several datagrams from one neighbor, are reassembled at the
+/// same time. A datagram whose fragments do not all arrive within the
+/// reassembly timeout is dropped when its `RxState` is needed again. To keep
+/// one neighbor from holding every `RxState`, `set_max_reassemblies_per_neighbor`
+/// limits how many datagrams of one neighbor are reassembled at the same
+/// time; a new datagram beyond that replaces the oldest of that neighbor.
 pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
     pub ctx_store: C,
     clock: &'a A,
@@ -801,6 +829,8 @@ pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
 
     // Receive state
     rx_states: List<'a, RxState<'a>>,
+    reassembly_timeout_s: Cell<u32>,
+    max_reassemblies_per_neighbor: Cell<usize>,
 }
 
 // This function is called after receiving a frame
@@ -878,9 +908,66 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             rx_client: Cell::new(None),
 
             rx_states: List::new(),
+            reassembly_timeout_s: Cell::new(FRAG_TIMEOUT),
+            max_reassemblies_per_neighbor: Cell::new(usize::MAX),
         }
     }
 
+    /// Sets the time after which a datagram that is not fully received is
+    /// dropped, at most 60 seconds (RFC 4944).
+    pub fn set_reassembly_timeout(&self, seconds: u32) {
+        self.reassembly_timeout_s.set(seconds.min(FRAG_TIMEOUT));
+    }
+
+    /// Sets how many datagrams of a single neighbor are reassembled at the
+    /// same time, at least 1. There is no limit by default.
+    pub fn set_max_reassemblies_per_neighbor(&self, max: usize) {
+        self.max_reassemblies_per_neighbor.set(max.max(1));
+    }
+
+    fn is_expired(&self, state: &RxState<'a>) -> bool {
+        let timeout = time_conversion::ticks_from_ms::<A::Frequency, A::Ticks>(
+            self.reassembly_timeout_s.get() * 1000,
+            Rounding::Up,
+        )
+        .unwrap_or(A::Ticks::half_max_value())
+        .min(A::Ticks::half_max_value());
+        let age = self
+            .clock
+            .now()
+            .wrapping_sub(A::Ticks::from(state.start_time.get()));
+        age >= timeout
+    }
+
+    // This function implements the reassembly timeout for 6LoWPAN lazily,
+    // freeing the states of datagrams that timed out.
+    fn expire_rx_states(&self) {
+        for state in self.rx_states.iter() {
+            if state.busy.get() && self.is_expired(state) {
+                state.end_receive(None, Err(ErrorCode::FAIL));
+            }
+        }
+    }
+
+    // Finds a state for a new datagram from `src_mac_addr`. A neighbor that
+    // already has as many datagrams in reassembly as allowed gives up its
+    // oldest one.
+    fn free_rx_state(&self, src_mac_addr: MacAddress) -> Option<&RxState<'a>> {
+        let now = self.clock.now();
+        let from_neighbor = || {
+            self.rx_states
+                .iter()
+                .filter(move |state| state.busy.get() && state.src_mac_addr.get() == src_mac_addr)
+        };
+        if from_neighbor().count() >= self.max_reassemblies_per_neighbor.get() {
+            let oldest = from_neighbor()
+                .max_by_key(|state| now.wrapping_sub(A::Ticks::from(state.start_time.get())));
+            oldest.map(|state| state.end_receive(None, Err(ErrorCode::FAIL)));
+            return oldest;
+        }
+        self.rx_states.iter().find(|state| !state.busy.get())
+    }
+
     fn receive_frame(
         &self,
         packet: &[u8],
@@ -916,10 +1003,8 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         src_mac_addr: MacAddress,
         dst_mac_addr: MacAddress,
     ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
-        let rx_state = self
-            .rx_states
-            .iter()
-            .find(|state| !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency()));
+        self.expire_rx_states();
+        let rx_state = self.rx_states.iter().find(|state| !state.busy.get());
         rx_state.map_or((None, Err(ErrorCode::NOMEM)), |state| {
             state.start_receive(
                 src_mac_addr,
@@ -951,11 +1036,17 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
                         state.dgram_size.set((written + remaining) as u16);
                     }
                     Err(_) => {
+                        state.packet.replace(packet);
+                        state.busy.set(false);
                         return (None, Err(ErrorCode::FAIL));
                     }
                 }
-            } else {
+            } else if payload_len <= packet.len() {
                 packet[0..payload_len].copy_from_slice(&payload[0..payload_len]);
+            } else {
+                state.packet.replace(packet);
+                state.busy.set(false);
+                return (None, Err(ErrorCode::SIZE));
             }
             state.packet.replace(packet);
             (Some(state), Ok(()))
@@ -975,17 +1066,20 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         dgram_tag: u16,
         dgram_offset: usize,
     ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
+        self.expire_rx_states();
+
         // First try to find an rx_state in the middle of assembly
         let mut rx_state = self
             .rx_states
             .iter()
             .find(|state| state.is_my_fragment(src_mac_addr, dst_mac_addr, dgram_size, dgram_tag));
 
-        // Else find a free state
+        // Else find a free state, unless the datagram cannot fit in one
         if rx_state.is_none() {
-            rx_state = self.rx_states.iter().find(|state| {
-                !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency())
-            });
+            if dgram_size as usize > MAX_DGRAM_SIZE {
+                return (None, Err(ErrorCode::SIZE));
+            }
+            rx_state = self.free_rx_state(src_mac_addr);
             // Initialize new state
             rx_state.map(|state| {
                 state.start_receive(
@@ -0,0 +1,332 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Userspace interface to TCP connections.
+//!
+//! A process has one connection at a time, on a socket of the stack that
+//! it takes from the sockets given to the driver when it connects or
+//! listens. The process keeps the socket until the connection is closed.
+//!
+//! The connection is a stream: the `send` command copies as much of the
+//! `SEND` buffer as fits into the send buffer of the socket, and the
+//! `receive` command moves received data into the `RECEIVE` buffer. Upcalls
+//! report when data arrives and when space is freed in the send buffer.
+
+use core::ops::Range;
+
+use crate::net::ipv4::IPv4Addr;
+use crate::net::tcp::stack::{State, TcpClient, TcpStack};
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::Alarm;
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Tcp as usize;
+
+/// Data is copied between process buffers and sockets in chunks of this
+/// length.
+const CHUNK_LEN: usize = 64;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Data to send
+    pub const SEND: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Received data
+    pub const RECEIVE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcall {
+    /// The connection is established, or failed to be
+    pub const CONNECTED: usize = 0;
+    /// Data waits to be received
+    pub const RECEIVED: usize = 1;
+    /// Space was freed in the send buffer
+    pub const SENT: usize = 2;
+    /// The peer closed its side of the connection
+    pub const PEER_CLOSED: usize = 3;
+    /// The connection is closed
+    pub const CLOSED: usize = 4;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 5;
+}
+
+#[derive(Default)]
+pub struct App {
+    socket: Option<usize>,
+}
+
+pub struct TcpDriver<'a, A: Alarm<'a>> {
+    stack: &'a TcpStack<'a, A>,
+    /// The sockets of the stack that processes use
+    sockets: Range<usize>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+}
+
+impl<'a, A: Alarm<'a>> TcpDriver<'a, A> {
+    /// The driver should be the client of `sockets` of `stack`.
+    pub fn new(
+        stack: &'a TcpStack<'a, A>,
+        sockets: Range<usize>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcall::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> TcpDriver<'a, A> {
+        TcpDriver {
+            stack,
+            sockets,
+            apps: grant,
+        }
+    }
+
+    /// The process that has socket `id`.
+    fn owner(&self, id: usize) -> Option<ProcessId> {
+        self.apps.iter().find_map(|app| {
+            let processid = app.processid();
+            app.enter(|app, _| app.socket == Some(id))
+                .then_some(processid)
+        })
+    }
+
+    fn socket(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| app.socket)
+            .map_err(ErrorCode::from)?
+            .ok_or(ErrorCode::OFF)
+    }
+
+    /// The closed socket of the process, or a free one for it.
+    fn allocate(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        match self.apps.enter(processid, |app, _| app.socket) {
+            Ok(Some(id)) if self.stack.state(id) == Ok(State::Closed) => return Ok(id),
+            Ok(Some(_)) => return Err(ErrorCode::BUSY),
+            Ok(None) => {}
+            Err(err) => return Err(err.into()),
+        }
+        let id = self
+            .sockets
+            .clone()
+            .find(|id| self.owner(*id).is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+        // The connection of a process that exited may still be open
+        if self.stack.state(id) != Ok(State::Closed) {
+            self.stack.abort(id)?;
+        }
+        self.apps
+            .enter(processid, |app, _| app.socket = Some(id))
+            .map_err(ErrorCode::from)?;
+        Ok(id)
+    }
+
+    fn release(&self, id: usize) {
+        if let Some(processid) = self.owner(id) {
+            let _ = self.apps.enter(processid, |app, _| app.socket = None);
+        }
+    }
+
+    fn schedule(&self, id: usize, upcall: usize, args: (usize, usize, usize)) {
+        if let Some(processid) = self.owner(id) {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                kernel_data.schedule_upcall(upcall, args)
+            });
+        }
+    }
+
+    /// Copy up to `len` bytes of the `SEND` buffer into the socket.
+    fn send(&self, processid: ProcessId, len: usize) -> Result<usize, ErrorCode> {
+        let id = self.socket(processid)?;
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::SEND)
+                    .and_then(|send| {
+                        send.enter(|send| {
+                            let send = send.get_to(..usize::min(len, send.len())).unwrap_or(send);
+                            let mut copied = 0;
+                            for chunk in send.chunks(CHUNK_LEN) {
+                                let mut buf = [0; CHUNK_LEN];
+                                chunk.copy_to_slice(&mut buf[..chunk.len()]);
+                                let accepted = self.stack.send(id, &buf[..chunk.len()])?;
+                                copied += accepted;
+                                if accepted < chunk.len() {
+                                    break;
+                                }
+                            }
+                            Ok(copied)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Move received data into the `RECEIVE` buffer.
+    fn receive(&self, processid: ProcessId) -> Result<usize, ErrorCode> {
+        let id = self.socket(processid)?;
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::RECEIVE)
+                    .and_then(|receive| {
+                        receive.mut_enter(|receive| {
+                            let mut received = 0;
+                            for chunk in receive.chunks(CHUNK_LEN) {
+                                let mut buf = [0; CHUNK_LEN];
+                                let count = self.stack.receive(id, &mut buf[..chunk.len()])?;
+                                chunk[..count].copy_from_slice(&buf[..count]);
+                                received += count;
+                                if count < chunk.len() {
+                                    break;
+                                }
+                            }
+                            Ok(received)
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::RESERVE))
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+}
+
+impl<'a, A: Alarm<'a>> TcpClient for TcpDriver<'a, A> {
+    fn connected(&self, id: usize, result: Result<(), ErrorCode>) {
+        let (addr, port) = self.stack.remote(id).unwrap_or((IPv4Addr::UNSPECIFIED, 0));
+        self.schedule(
+            id,
+            upcall::CONNECTED,
+            (
+                into_statuscode(result),
+                u32::from_be_bytes(addr.0) as usize,
+                port as usize,
+            ),
+        );
+        if result.is_err() {
+            self.release(id);
+        }
+    }
+
+    fn received(&self, id: usize, available: usize) {
+        self.schedule(id, upcall::RECEIVED, (available, 0, 0));
+    }
+
+    fn sent(&self, id: usize, space: usize) {
+        self.schedule(id, upcall::SENT, (space, 0, 0));
+    }
+
+    fn peer_closed(&self, id: usize) {
+        self.schedule(id, upcall::PEER_CLOSED, (0, 0, 0));
+    }
+
+    fn closed(&self, id: usize, result: Result<(), ErrorCode>) {
+        self.schedule(id, upcall::CLOSED, (into_statuscode(result), 0, 0));
+        self.release(id);
+    }
+}
+
+impl<'a, A: Alarm<'a>> SyscallDriver for TcpDriver<'a, A> {
+    /// Commands for the connection of the process.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Connect to port `arg2` of the IPv4 address `arg1`, with its
+    ///   first byte in the most significant byte. Returns `NOMEM` if all
+    ///   sockets are in use, and `BUSY` if the process has a connection.
+    /// - `2`: Listen for a connection to port `arg1`. Returns as `1`.
+    /// - `3`: Send up to `arg1` bytes of the `SEND` buffer. Returns the
+    ///   number of bytes accepted, 0 if the send buffer is full.
+    /// - `4`: Move received data into the `RECEIVE` buffer. Returns the
+    ///   number of bytes moved.
+    /// - `5`: Close the connection once its data is sent.
+    /// - `6`: Reset the connection, and free its socket.
+    /// - `7`: The state of the connection, numbered as `State`.
+    ///
+    /// Commands `3` to `7` return `OFF` if the process has no socket.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        arg2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let addr = IPv4Addr((arg1 as u32).to_be_bytes());
+                self.allocate(processid)
+                    .and_then(|id| self.stack.connect(id, addr, arg2 as u16))
+                    .into()
+            }
+
+            2 => self
+                .allocate(processid)
+                .and_then(|id| self.stack.listen(id, arg1 as u16))
+                .into(),
+
+            3 => match self.send(processid, arg1) {
+                Ok(copied) => CommandReturn::success_u32(copied as u32),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            4 => match self.receive(processid) {
+                Ok(received) => CommandReturn::success_u32(received as u32),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            5 => {
+                let result = self.socket(processid).and_then(|id| {
+                    self.stack.close(id)?;
+                    // Listening and connecting sockets close right away
+                    if self.stack.state(id) == Ok(State::Closed) {
+                        self.release(id);
+                    }
+                    Ok(())
+                });
+                result.into()
+            }
+
+            6 => self
+                .socket(processid)
+                .and_then(|id| {
+                    self.stack.abort(id)?;
+                    self.release(id);
+                    Ok(())
+                })
+                .into(),
+
+            7 => match self.socket(processid).and_then(|id| self.stack.state(id)) {
+                Ok(state) => CommandReturn::success_u32(state as u32),
+                Err(ErrorCode::OFF) => CommandReturn::success_u32(State::Closed as u32),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,37 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! TCP for the network stack.
+//!
+//! `TcpStack` runs the connections of a fixed set of sockets over a
+//! network layer that implements `TcpNetwork`, such as the
+//! `IPv4Interface` of `net::ipv4`. Kernel users address a socket by its
+//! index, and `TcpDriver` lets processes open stream connections on the
+//! sockets it is given. The IPv6 stack does not carry TCP segments.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let tcp = components::tcp::TcpComponent::new(ipv4, mux_alarm)
+//!     .finalize(components::tcp_component_static!(nrf52840::rtc::Rtc));
+//! let tcp_driver = components::tcp::TcpDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::net::tcp::driver::DRIVER_NUM,
+//!     tcp,
+//!     0..capsules_extra::net::tcp::TCP_SOCKETS,
+//! )
+//! .finalize(components::tcp_driver_component_static!(nrf52840::rtc::Rtc));
+//! ```
+
+pub mod driver;
+pub mod segment;
+pub mod stack;
+
+pub use self::driver::TcpDriver;
+pub use self::segment::TCPHeader;
+pub use self::stack::{
+    State, TcpClient, TcpNetwork, TcpNetworkClient, TcpSocket, TcpStack, TCP_BUFFER_LEN,
+    TCP_SOCKETS,
+};
@@ -0,0 +1,214 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! TCP segments (RFC 9293): the header, the MSS option and the arithmetic
+//! of sequence numbers.
+//!
+//! The checksum covers a pseudo header of the network layer, so it is
+//! computed and verified by the network layer below TCP.
+
+pub const TCP_HEADER_LEN: usize = 20;
+/// Length of the header of SYN segments, which carry the MSS option, the
+/// only option that is sent.
+pub const SYN_HEADER_LEN: usize = 24;
+/// Offset of the checksum in the header
+pub const CHECKSUM_OFFSET: usize = 16;
+/// The MSS of peers that do not send the option (RFC 9293, 3.7.1)
+pub const DEFAULT_MSS: u16 = 536;
+
+/// Control bits of the header.
+pub mod flags {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+}
+
+const OPTION_END: u8 = 0;
+const OPTION_NOP: u8 = 1;
+const OPTION_MSS: u8 = 2;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TCPHeader {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq_num: u32,
+    pub ack_num: u32,
+    pub offset_and_control: u16,
+    pub window: u16,
+    pub cksum: u16,
+    pub urg_ptr: u16,
+}
+
+impl TCPHeader {
+    /// A header of `header_len` bytes, a multiple of 4, with the control
+    /// bits `flags`.
+    pub fn new(
+        src_port: u16,
+        dst_port: u16,
+        seq_num: u32,
+        ack_num: u32,
+        flags: u8,
+        window: u16,
+        header_len: usize,
+    ) -> TCPHeader {
+        TCPHeader {
+            src_port,
+            dst_port,
+            seq_num,
+            ack_num,
+            offset_and_control: ((header_len / 4) as u16) << 12 | flags as u16,
+            window,
+            cksum: 0,
+            urg_ptr: 0,
+        }
+    }
+
+    pub fn flags(&self) -> u8 {
+        (self.offset_and_control & 0x3f) as u8
+    }
+
+    /// Whether all bits of `flags` are set.
+    pub fn has_flags(&self, flags: u8) -> bool {
+        self.flags() & flags == flags
+    }
+
+    /// Length of the header with options
+    pub fn header_len(&self) -> usize {
+        ((self.offset_and_control >> 12) as usize) * 4
+    }
+
+    /// Decode the header at the start of `buf`. Fails if `buf` is shorter
+    /// than the header with its options.
+    pub fn decode(buf: &[u8]) -> Option<TCPHeader> {
+        if buf.len() < TCP_HEADER_LEN {
+            return None;
+        }
+        let header = TCPHeader {
+            src_port: u16::from_be_bytes([buf[0], buf[1]]),
+            dst_port: u16::from_be_bytes([buf[2], buf[3]]),
+            seq_num: u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            ack_num: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            offset_and_control: u16::from_be_bytes([buf[12], buf[13]]),
+            window: u16::from_be_bytes([buf[14], buf[15]]),
+            cksum: u16::from_be_bytes([buf[16], buf[17]]),
+            urg_ptr: u16::from_be_bytes([buf[18], buf[19]]),
+        };
+        if header.header_len() < TCP_HEADER_LEN || header.header_len() > buf.len() {
+            return None;
+        }
+        Some(header)
+    }
+
+    /// Encode the header without options into the first `TCP_HEADER_LEN`
+    /// bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0..2].copy_from_slice(&self.src_port.to_be_bytes());
+        buf[2..4].copy_from_slice(&self.dst_port.to_be_bytes());
+        buf[4..8].copy_from_slice(&self.seq_num.to_be_bytes());
+        buf[8..12].copy_from_slice(&self.ack_num.to_be_bytes());
+        buf[12..14].copy_from_slice(&self.offset_and_control.to_be_bytes());
+        buf[14..16].copy_from_slice(&self.window.to_be_bytes());
+        buf[16..18].copy_from_slice(&self.cksum.to_be_bytes());
+        buf[18..20].copy_from_slice(&self.urg_ptr.to_be_bytes());
+    }
+}
+
+/// The MSS option of the segment `buf`, if it has one.
+pub fn decode_mss(buf: &[u8]) -> Option<u16> {
+    let header = TCPHeader::decode(buf)?;
+    let mut options = &buf[TCP_HEADER_LEN..header.header_len()];
+    while let Some(&kind) = options.first() {
+        match kind {
+            OPTION_END => return None,
+            OPTION_NOP => options = &options[1..],
+            _ => {
+                let len = *options.get(1)? as usize;
+                if len < 2 || len > options.len() {
+                    return None;
+                }
+                if kind == OPTION_MSS && len == 4 {
+                    return Some(u16::from_be_bytes([options[2], options[3]]));
+                }
+                options = &options[len..];
+            }
+        }
+    }
+    None
+}
+
+/// Encode the MSS option after the header in `buf`, which should be
+/// `SYN_HEADER_LEN` bytes long.
+pub fn encode_mss(buf: &mut [u8], mss: u16) {
+    buf[TCP_HEADER_LEN] = OPTION_MSS;
+    buf[TCP_HEADER_LEN + 1] = 4;
+    buf[TCP_HEADER_LEN + 2..SYN_HEADER_LEN].copy_from_slice(&mss.to_be_bytes());
+}
+
+/// Whether sequence number `a` comes before `b`, modulo 2^32.
+pub fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// Whether sequence number `a` is `b` or comes before it.
+pub fn seq_le(a: u32, b: u32) -> bool {
+    a == b || seq_lt(a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        let header = TCPHeader::new(
+            49152,
+            80,
+            0xfffffff0,
+            7,
+            flags::SYN | flags::ACK,
+            1024,
+            SYN_HEADER_LEN,
+        );
+        let mut buf = [0; SYN_HEADER_LEN];
+        header.encode(&mut buf);
+        encode_mss(&mut buf, 1460);
+        assert_eq!(buf[12], 0x60);
+        assert_eq!(buf[13], 0x12);
+
+        let decoded = TCPHeader::decode(&buf).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.header_len(), SYN_HEADER_LEN);
+        assert!(decoded.has_flags(flags::SYN | flags::ACK));
+        assert!(!decoded.has_flags(flags::FIN));
+        assert_eq!(decode_mss(&buf), Some(1460));
+
+        // The header is longer than the buffer
+        assert_eq!(TCPHeader::decode(&buf[..TCP_HEADER_LEN]), None);
+    }
+
+    #[test]
+    fn options() {
+        let mut buf = [0; 32];
+        TCPHeader::new(1, 2, 0, 0, flags::SYN, 0, 32).encode(&mut buf);
+        // Window scale, then NOPs, then the MSS
+        buf[20..32].copy_from_slice(&[
+            3, 3, 7, OPTION_NOP, OPTION_NOP, OPTION_MSS, 4, 2, 0, 0, 0, 0,
+        ]);
+        assert_eq!(decode_mss(&buf), Some(512));
+
+        // An option longer than the header
+        buf[21] = 20;
+        assert_eq!(decode_mss(&buf), None);
+    }
+
+    #[test]
+    fn sequence_wrap() {
+        assert!(seq_lt(0xffffff00, 0x10));
+        assert!(!seq_lt(0x10, 0xffffff00));
+        assert!(seq_le(5, 5));
+        assert!(!seq_lt(5, 5));
+    }
+}
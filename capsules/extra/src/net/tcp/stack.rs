@@ -0,0 +1,1303 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! TCP connections (RFC 9293) over a network layer (`TcpNetwork`).
+//!
+//! `TcpStack` has a fixed set of sockets, identified by their index. A
+//! socket opens a connection with `connect()`, or waits for one with
+//! `listen()`. A listening socket takes the first connection to its port,
+//! so that several sockets listen to the same port to accept as many
+//! connections.
+//!
+//! The data of a connection is a stream: `send()` copies data into the
+//! send buffer of the socket, from which it is sent in segments of at most
+//! the MSS of the peer and retransmitted until it is acknowledged. Received
+//! data waits in the receive buffer for `receive()`, and the free space of
+//! that buffer is the window advertised to the peer.
+//!
+//! Unacknowledged data is retransmitted, from the first unacknowledged
+//! byte, after a timeout computed from the round-trip time as in RFC 6298.
+//! The connection is aborted after `MAX_RETRANSMITS` retransmissions. The
+//! sender is only limited by the window of the peer: there is no fast
+//! retransmit and no congestion control. Segments received out of order
+//! are dropped and acknowledged, for the peer to retransmit them. Timers
+//! count in steps of `TICK_MS`.
+//!
+//! Initial sequence numbers come from a counter advanced by the time, not
+//! from a keyed hash of the connection as RFC 6528 recommends.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::segment::{
+    decode_mss, encode_mss, flags, seq_le, seq_lt, TCPHeader, DEFAULT_MSS, SYN_HEADER_LEN,
+    TCP_HEADER_LEN,
+};
+use crate::net::ipv4::IPv4Addr;
+
+use kernel::hil::time::{self, ConvertTicks, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Step of the timers
+pub const TICK_MS: u32 = 100;
+pub const INITIAL_RTO_MS: u32 = 1000;
+pub const MIN_RTO_MS: u32 = 200;
+pub const MAX_RTO_MS: u32 = 60_000;
+/// Retransmissions of a segment before its connection is aborted
+pub const MAX_RETRANSMITS: u8 = 8;
+/// Time in TIME-WAIT, much shorter than the 2 MSL of RFC 9293
+pub const TIME_WAIT_MS: u32 = 2000;
+/// Suggested number of sockets
+pub const TCP_SOCKETS: usize = 4;
+/// Suggested length of the send and receive buffers of each socket
+pub const TCP_BUFFER_LEN: usize = 512;
+/// Suggested length of the segment buffer, for segments of the default
+/// MSS
+pub const SEGMENT_BUF_LEN: usize = SYN_HEADER_LEN + DEFAULT_MSS as usize;
+
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// The network layer below TCP. It adds the checksum, with its pseudo
+/// header, to the segments it sends, and only passes up received segments
+/// with a correct checksum.
+pub trait TcpNetwork<'a> {
+    fn set_client(&self, client: &'a dyn TcpNetworkClient);
+
+    /// The longest segment, with its header, that is sent and received.
+    fn max_segment_len(&self) -> usize;
+
+    /// Send the segment in the first `len` bytes of `segment` to `dest`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: `segment_sent()` is called with `segment` once it is
+    ///   sent or failed.
+    /// - `BUSY`: A segment is being sent.
+    /// - `SIZE`: `len` is longer than `segment` or `max_segment_len()`.
+    /// - `OFF`: The link is down.
+    /// - `FAIL`: The network layer has no address.
+    fn send_segment(
+        &self,
+        dest: IPv4Addr,
+        segment: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub trait TcpNetworkClient {
+    fn segment_sent(&self, segment: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A segment from `src`, only valid for the duration of the call.
+    fn segment_received(&self, src: IPv4Addr, segment: &[u8]);
+}
+
+/// Events of the connection of socket `id`.
+pub trait TcpClient {
+    /// The connection opened by `connect()`, or accepted by `listen()`, is
+    /// established. `connect()` fails with `CANCEL` if the peer refuses the
+    /// connection, and `NOACK` if it does not answer.
+    fn connected(&self, id: usize, result: Result<(), ErrorCode>);
+
+    /// `available` bytes wait in the receive buffer.
+    fn received(&self, id: usize, available: usize);
+
+    /// Data was acknowledged, and `space` bytes of the send buffer are
+    /// free.
+    fn sent(&self, id: usize, space: usize);
+
+    /// The peer closed its side of the connection: no more data will be
+    /// received.
+    fn peer_closed(&self, id: usize);
+
+    /// The established connection is closed, and the socket is free. It
+    /// fails with `CANCEL` if the peer reset the connection, and `NOACK`
+    /// if it stopped answering.
+    fn closed(&self, id: usize, result: Result<(), ErrorCode>);
+}
+
+/// The states of RFC 9293, 3.3.2.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+/// Copy the start of `data` into the ring `buf`, after the `len` bytes
+/// from `start`. Returns the number of bytes copied.
+fn ring_write(buf: &mut [u8], start: usize, len: usize, data: &[u8]) -> usize {
+    let count = cmp::min(data.len(), buf.len() - len);
+    for (i, byte) in data[..count].iter().enumerate() {
+        buf[(start + len + i) % buf.len()] = *byte;
+    }
+    count
+}
+
+/// Fill `out` with the bytes of the ring `buf` from `offset` after `start`.
+fn ring_read(buf: &[u8], start: usize, offset: usize, out: &mut [u8]) {
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = buf[(start + offset + i) % buf.len()];
+    }
+}
+
+/// The sequence numbers taken by a segment.
+fn seq_len(header: &TCPHeader, payload_len: usize) -> u32 {
+    payload_len as u32 + header.has_flags(flags::SYN) as u32 + header.has_flags(flags::FIN) as u32
+}
+
+pub struct TcpSocket<'a> {
+    client: OptionalCell<&'a dyn TcpClient>,
+    state: Cell<State>,
+    /// The connection was accepted by a listening socket
+    passive: Cell<bool>,
+    local_port: Cell<u16>,
+    remote_addr: Cell<IPv4Addr>,
+    remote_port: Cell<u16>,
+    /// First unacknowledged sequence number
+    snd_una: Cell<u32>,
+    /// Next sequence number to send. It goes back to `snd_una` when
+    /// retransmitting.
+    snd_nxt: Cell<u32>,
+    /// Sequence number after the last one sent
+    snd_max: Cell<u32>,
+    /// Window of the peer
+    snd_wnd: Cell<usize>,
+    /// MSS of the peer
+    mss: Cell<usize>,
+    rcv_nxt: Cell<u32>,
+    /// Window advertised in the last segment
+    rcv_wnd: Cell<usize>,
+    /// The send buffer holds `tx_len` bytes from `tx_start`; the first is
+    /// at `snd_una` once the connection is established.
+    tx: TakeCell<'static, [u8]>,
+    tx_capacity: usize,
+    tx_start: Cell<usize>,
+    tx_len: Cell<usize>,
+    rx: TakeCell<'static, [u8]>,
+    rx_capacity: usize,
+    rx_start: Cell<usize>,
+    rx_len: Cell<usize>,
+    /// `close()` was called: a FIN follows the data
+    fin_queued: Cell<bool>,
+    ack_due: Cell<bool>,
+    /// Send one byte into a zero window
+    probe: Cell<bool>,
+    /// Time left until the retransmission timeout, or the end of TIME-WAIT
+    timer_ms: Cell<Option<u32>>,
+    rto_ms: Cell<u32>,
+    retransmits: Cell<u8>,
+    srtt_ms: Cell<Option<u32>>,
+    rttvar_ms: Cell<u32>,
+    /// The end of the segment whose acknowledgement measures the round-trip
+    /// time, and when it was sent
+    rtt_probe: Cell<Option<(u32, u32)>>,
+}
+
+impl<'a> TcpSocket<'a> {
+    pub fn new(tx_buffer: &'static mut [u8], rx_buffer: &'static mut [u8]) -> TcpSocket<'a> {
+        TcpSocket {
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Closed),
+            passive: Cell::new(false),
+            local_port: Cell::new(0),
+            remote_addr: Cell::new(IPv4Addr::UNSPECIFIED),
+            remote_port: Cell::new(0),
+            snd_una: Cell::new(0),
+            snd_nxt: Cell::new(0),
+            snd_max: Cell::new(0),
+            snd_wnd: Cell::new(0),
+            mss: Cell::new(DEFAULT_MSS as usize),
+            rcv_nxt: Cell::new(0),
+            rcv_wnd: Cell::new(0),
+            tx_capacity: tx_buffer.len(),
+            tx: TakeCell::new(tx_buffer),
+            tx_start: Cell::new(0),
+            tx_len: Cell::new(0),
+            rx_capacity: rx_buffer.len(),
+            rx: TakeCell::new(rx_buffer),
+            rx_start: Cell::new(0),
+            rx_len: Cell::new(0),
+            fin_queued: Cell::new(false),
+            ack_due: Cell::new(false),
+            probe: Cell::new(false),
+            timer_ms: Cell::new(None),
+            rto_ms: Cell::new(INITIAL_RTO_MS),
+            retransmits: Cell::new(0),
+            srtt_ms: Cell::new(None),
+            rttvar_ms: Cell::new(0),
+            rtt_probe: Cell::new(None),
+        }
+    }
+
+    /// Whether the socket has the connection with `port` of `addr` on
+    /// `local_port`.
+    fn is_connection(&self, addr: IPv4Addr, port: u16, local_port: u16) -> bool {
+        !matches!(self.state.get(), State::Closed | State::Listen)
+            && self.local_port.get() == local_port
+            && self.remote_port.get() == port
+            && self.remote_addr.get() == addr
+    }
+
+    /// Whether the state has sequence numbers of both sides.
+    fn is_synchronized(&self) -> bool {
+        !matches!(
+            self.state.get(),
+            State::Closed | State::Listen | State::SynSent
+        )
+    }
+
+    /// Start the connection with `port` of `addr` from `iss`.
+    fn open(&self, state: State, addr: IPv4Addr, port: u16, iss: u32) {
+        self.state.set(state);
+        self.remote_addr.set(addr);
+        self.remote_port.set(port);
+        self.snd_una.set(iss);
+        self.snd_nxt.set(iss);
+        self.snd_max.set(iss);
+        self.mss.set(DEFAULT_MSS as usize);
+    }
+
+    /// Forget the connection.
+    fn clear(&self) {
+        self.state.set(State::Closed);
+        self.passive.set(false);
+        self.tx_start.set(0);
+        self.tx_len.set(0);
+        self.rx_start.set(0);
+        self.rx_len.set(0);
+        self.fin_queued.set(false);
+        self.ack_due.set(false);
+        self.probe.set(false);
+        self.timer_ms.set(None);
+        self.rto_ms.set(INITIAL_RTO_MS);
+        self.retransmits.set(0);
+        self.srtt_ms.set(None);
+        self.rttvar_ms.set(0);
+        self.rtt_probe.set(None);
+    }
+
+    fn receive_window(&self) -> usize {
+        cmp::min(self.rx_capacity - self.rx_len.get(), u16::MAX as usize)
+    }
+
+    /// Update the retransmission timeout with a round-trip time sample
+    /// (RFC 6298, 2).
+    fn update_rto(&self, rtt: u32) {
+        let (srtt, rttvar) = match self.srtt_ms.get() {
+            None => (rtt, rtt / 2),
+            Some(srtt) => (
+                (7 * srtt + rtt) / 8,
+                (3 * self.rttvar_ms.get() + srtt.abs_diff(rtt)) / 4,
+            ),
+        };
+        self.srtt_ms.set(Some(srtt));
+        self.rttvar_ms.set(rttvar);
+        self.rto_ms
+            .set((srtt + cmp::max(TICK_MS, 4 * rttvar)).clamp(MIN_RTO_MS, MAX_RTO_MS));
+    }
+
+    /// Run the retransmission timer while sent data is unacknowledged, or
+    /// data waits for the zero window of the peer to open.
+    fn update_timer(&self) {
+        if self.state.get() == State::TimeWait {
+            return;
+        }
+        let outstanding = self.snd_una.get() != self.snd_max.get();
+        let blocked = self.snd_wnd.get() == 0
+            && self.is_synchronized()
+            && (self.tx_len.get() > 0 || self.fin_queued.get());
+        if !outstanding && !blocked {
+            self.timer_ms.set(None);
+        } else if self.timer_ms.get().is_none() {
+            self.timer_ms.set(Some(self.rto_ms.get()));
+        }
+    }
+}
+
+pub struct TcpStack<'a, A: time::Alarm<'a>> {
+    network: &'a dyn TcpNetwork<'a>,
+    alarm: &'a A,
+    sockets: &'a [TcpSocket<'a>],
+    segment: TakeCell<'static, [u8]>,
+    /// A reset to send to the source of a segment for no connection
+    reset: Cell<Option<(IPv4Addr, TCPHeader)>>,
+    /// Time counted by the timers, for round-trip times
+    now_ms: Cell<u32>,
+    iss: Cell<u32>,
+    next_port: Cell<u16>,
+    /// The socket that sent last; sockets send in turn
+    last: Cell<usize>,
+}
+
+impl<'a, A: time::Alarm<'a>> TcpStack<'a, A> {
+    /// `segment` should have at least `SEGMENT_BUF_LEN` bytes. Data is sent
+    /// in segments of at most its length.
+    pub fn new(
+        network: &'a dyn TcpNetwork<'a>,
+        alarm: &'a A,
+        sockets: &'a [TcpSocket<'a>],
+        segment: &'static mut [u8],
+    ) -> TcpStack<'a, A> {
+        TcpStack {
+            network,
+            alarm,
+            sockets,
+            segment: TakeCell::new(segment),
+            reset: Cell::new(None),
+            now_ms: Cell::new(0),
+            iss: Cell::new(0),
+            next_port: Cell::new(EPHEMERAL_PORT_START),
+            last: Cell::new(0),
+        }
+    }
+
+    pub fn num_sockets(&self) -> usize {
+        self.sockets.len()
+    }
+
+    pub fn set_client(&self, id: usize, client: &'a dyn TcpClient) -> Result<(), ErrorCode> {
+        self.socket(id)?.client.set(client);
+        Ok(())
+    }
+
+    pub fn state(&self, id: usize) -> Result<State, ErrorCode> {
+        Ok(self.socket(id)?.state.get())
+    }
+
+    /// The address and port of the peer of an open connection.
+    pub fn remote(&self, id: usize) -> Option<(IPv4Addr, u16)> {
+        self.sockets
+            .get(id)
+            .filter(|socket| !matches!(socket.state.get(), State::Closed | State::Listen))
+            .map(|socket| (socket.remote_addr.get(), socket.remote_port.get()))
+    }
+
+    fn socket(&self, id: usize) -> Result<&TcpSocket<'a>, ErrorCode> {
+        self.sockets.get(id).ok_or(ErrorCode::INVAL)
+    }
+
+    /// Open a connection to `port` of `dest` from an ephemeral port.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: `connected()` is called once the connection is
+    ///   established or failed.
+    /// - `INVAL`: There is no socket `id`, or `dest` or `port` is invalid.
+    /// - `BUSY`: The socket is not closed.
+    pub fn connect(&self, id: usize, dest: IPv4Addr, port: u16) -> Result<(), ErrorCode> {
+        let socket = self.socket(id)?;
+        if port == 0 || dest.is_unspecified() || dest.is_broadcast() || dest.is_multicast() {
+            return Err(ErrorCode::INVAL);
+        }
+        if socket.state.get() != State::Closed {
+            return Err(ErrorCode::BUSY);
+        }
+        socket.clear();
+        socket.local_port.set(self.ephemeral_port());
+        socket.open(State::SynSent, dest, port, self.next_iss());
+        self.send_next();
+        Ok(())
+    }
+
+    /// Wait for a connection to `port`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: `connected()` is called once a connection is
+    ///   established.
+    /// - `INVAL`: There is no socket `id`, or `port` is 0.
+    /// - `BUSY`: The socket is not closed.
+    pub fn listen(&self, id: usize, port: u16) -> Result<(), ErrorCode> {
+        let socket = self.socket(id)?;
+        if port == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if socket.state.get() != State::Closed {
+            return Err(ErrorCode::BUSY);
+        }
+        socket.clear();
+        socket.local_port.set(port);
+        socket.state.set(State::Listen);
+        Ok(())
+    }
+
+    /// Copy the start of `data` into the send buffer. Returns the number of
+    /// bytes copied, which is 0 if the buffer is full: `sent()` reports
+    /// when space is freed.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(len)`: `len` bytes of `data` will be sent.
+    /// - `INVAL`: There is no socket `id`.
+    /// - `OFF`: The socket has no connection, or it was closed.
+    pub fn send(&self, id: usize, data: &[u8]) -> Result<usize, ErrorCode> {
+        let socket = self.socket(id)?;
+        let open = matches!(
+            socket.state.get(),
+            State::SynSent | State::SynReceived | State::Established | State::CloseWait
+        );
+        if !open || socket.fin_queued.get() {
+            return Err(ErrorCode::OFF);
+        }
+        let copied = socket.tx.map_or(0, |tx| {
+            ring_write(tx, socket.tx_start.get(), socket.tx_len.get(), data)
+        });
+        socket.tx_len.set(socket.tx_len.get() + copied);
+        if copied > 0 {
+            self.send_next();
+        }
+        Ok(copied)
+    }
+
+    /// Move received data into `buf`. Returns the number of bytes moved.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(len)`: The first `len` bytes of `buf` are received data.
+    /// - `INVAL`: There is no socket `id`.
+    pub fn receive(&self, id: usize, buf: &mut [u8]) -> Result<usize, ErrorCode> {
+        let socket = self.socket(id)?;
+        let count = cmp::min(buf.len(), socket.rx_len.get());
+        socket
+            .rx
+            .map(|rx| ring_read(rx, socket.rx_start.get(), 0, &mut buf[..count]));
+        socket
+            .rx_start
+            .set((socket.rx_start.get() + count) % cmp::max(socket.rx_capacity, 1));
+        socket.rx_len.set(socket.rx_len.get() - count);
+
+        // Announce the window once it opened by a segment or half the
+        // buffer, not by every byte (RFC 9293, 3.8.6.2.2)
+        let opened =
+            socket.receive_window() - cmp::min(socket.rcv_wnd.get(), socket.receive_window());
+        let threshold = cmp::min(socket.mss.get(), socket.rx_capacity / 2);
+        if socket.is_synchronized() && count > 0 && opened >= threshold {
+            socket.ack_due.set(true);
+            self.send_next();
+        }
+        Ok(count)
+    }
+
+    /// Close the connection once the data of the send buffer is sent. A
+    /// listening or connecting socket is closed right away.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The socket is closed, or `closed()` is called once the
+    ///   connection is.
+    /// - `INVAL`: There is no socket `id`.
+    /// - `ALREADY`: The socket is closed, or closing.
+    pub fn close(&self, id: usize) -> Result<(), ErrorCode> {
+        let socket = self.socket(id)?;
+        match socket.state.get() {
+            State::Listen | State::SynSent => socket.clear(),
+            State::SynReceived => return self.abort(id),
+            State::Established => {
+                socket.fin_queued.set(true);
+                socket.state.set(State::FinWait1);
+            }
+            State::CloseWait => {
+                socket.fin_queued.set(true);
+                socket.state.set(State::LastAck);
+            }
+            _ => return Err(ErrorCode::ALREADY),
+        }
+        self.send_next();
+        Ok(())
+    }
+
+    /// Reset the connection, and free the socket right away.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The socket is closed.
+    /// - `INVAL`: There is no socket `id`.
+    pub fn abort(&self, id: usize) -> Result<(), ErrorCode> {
+        let socket = self.socket(id)?;
+        if socket.is_synchronized() {
+            let reset = TCPHeader::new(
+                socket.local_port.get(),
+                socket.remote_port.get(),
+                socket.snd_nxt.get(),
+                0,
+                flags::RST,
+                0,
+                TCP_HEADER_LEN,
+            );
+            self.reset.set(Some((socket.remote_addr.get(), reset)));
+        }
+        socket.clear();
+        self.send_next();
+        Ok(())
+    }
+
+    fn next_iss(&self) -> u32 {
+        let iss = self
+            .iss
+            .get()
+            .wrapping_add(self.alarm.now().into_u32())
+            .wrapping_add(64000);
+        self.iss.set(iss);
+        iss
+    }
+
+    fn ephemeral_port(&self) -> u16 {
+        loop {
+            let port = self.next_port.get();
+            self.next_port.set(match port {
+                u16::MAX => EPHEMERAL_PORT_START,
+                port => port + 1,
+            });
+            let used = self.sockets.iter().any(|socket| {
+                socket.state.get() != State::Closed && socket.local_port.get() == port
+            });
+            // There are fewer sockets than ephemeral ports
+            if !used {
+                return port;
+            }
+        }
+    }
+
+    /// The MSS advertised to peers
+    fn mss(&self) -> u16 {
+        cmp::min(
+            self.network
+                .max_segment_len()
+                .saturating_sub(TCP_HEADER_LEN),
+            u16::MAX as usize,
+        ) as u16
+    }
+
+    /// Send a reset, or the next segment of the sockets, in turn.
+    fn send_next(&self) {
+        let segment = match self.segment.take() {
+            Some(segment) => segment,
+            None => return,
+        };
+        let next = match self.reset.take() {
+            Some((dest, header)) => {
+                header.encode(segment);
+                Some((dest, TCP_HEADER_LEN))
+            }
+            None => {
+                let count = self.sockets.len();
+                (1..=count)
+                    .map(|i| (self.last.get() + i) % count)
+                    .find_map(|id| {
+                        let socket = &self.sockets[id];
+                        let output = self.output(socket, segment);
+                        socket.update_timer();
+                        output.map(|output| {
+                            self.last.set(id);
+                            output
+                        })
+                    })
+            }
+        };
+        match next {
+            Some((dest, len)) => {
+                if let Err((_, segment)) = self.network.send_segment(dest, segment, len) {
+                    // The retransmission timer sends the data again
+                    self.segment.replace(segment);
+                }
+            }
+            None => {
+                self.segment.replace(segment);
+            }
+        }
+        self.schedule();
+    }
+
+    /// Write the next segment of `socket` into `buf`, if it has one.
+    /// Returns its destination and length.
+    fn output(&self, socket: &TcpSocket, buf: &mut [u8]) -> Option<(IPv4Addr, usize)> {
+        let state = socket.state.get();
+        let sent = socket.snd_nxt.get().wrapping_sub(socket.snd_una.get()) as usize;
+        let (control, header_len, data_len) = match state {
+            State::Closed | State::Listen => return None,
+            // The SYN is sent, or retransmitted, when `snd_nxt` is at it
+            State::SynSent | State::SynReceived if sent > 0 => return None,
+            State::SynSent => (flags::SYN, SYN_HEADER_LEN, 0),
+            State::SynReceived => (flags::SYN | flags::ACK, SYN_HEADER_LEN, 0),
+            _ => {
+                let tx_len = socket.tx_len.get();
+                let window = match socket.probe.get() {
+                    true => cmp::max(socket.snd_wnd.get(), 1),
+                    false => socket.snd_wnd.get(),
+                };
+                let usable = window.saturating_sub(sent);
+                let max_data = cmp::min(socket.mss.get(), buf.len() - TCP_HEADER_LEN);
+                let data_len = cmp::min(cmp::min(tx_len.saturating_sub(sent), usable), max_data);
+                // `sent` is past the data once the FIN is sent
+                let fin = socket.fin_queued.get() && sent + data_len == tx_len;
+                if data_len == 0 && !fin && !socket.ack_due.get() {
+                    return None;
+                }
+                let mut control = flags::ACK;
+                if data_len > 0 {
+                    control |= flags::PSH;
+                }
+                if fin {
+                    control |= flags::FIN;
+                }
+                (control, TCP_HEADER_LEN, data_len)
+            }
+        };
+
+        let seq = socket.snd_nxt.get();
+        let ack = match control & flags::ACK {
+            0 => 0,
+            _ => socket.rcv_nxt.get(),
+        };
+        let window = socket.receive_window();
+        TCPHeader::new(
+            socket.local_port.get(),
+            socket.remote_port.get(),
+            seq,
+            ack,
+            control,
+            window as u16,
+            header_len,
+        )
+        .encode(buf);
+        if header_len == SYN_HEADER_LEN {
+            encode_mss(buf, self.mss());
+        }
+        socket.tx.map(|tx| {
+            ring_read(
+                tx,
+                socket.tx_start.get(),
+                sent,
+                &mut buf[header_len..header_len + data_len],
+            )
+        });
+
+        let end = seq.wrapping_add(
+            data_len as u32
+                + (control & flags::SYN != 0) as u32
+                + (control & flags::FIN != 0) as u32,
+        );
+        socket.snd_nxt.set(end);
+        if seq_lt(socket.snd_max.get(), end) {
+            // Only segments sent for the first time measure the round trip
+            // (RFC 6298, 3)
+            if seq == socket.snd_max.get() && socket.rtt_probe.get().is_none() {
+                socket.rtt_probe.set(Some((end, self.now_ms.get())));
+            }
+            socket.snd_max.set(end);
+        }
+        socket.ack_due.set(false);
+        socket.probe.set(false);
+        socket.rcv_wnd.set(window);
+        Some((socket.remote_addr.get(), header_len + data_len))
+    }
+
+    /// Run the alarm while a timer runs.
+    fn schedule(&self) {
+        let running = self.sockets.iter().any(|s| s.timer_ms.get().is_some());
+        if running && !self.alarm.is_armed() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(TICK_MS));
+        }
+    }
+
+    /// The timer of socket `id` expired.
+    fn expired(&self, id: usize, socket: &TcpSocket) {
+        socket.timer_ms.set(None);
+        match socket.state.get() {
+            State::Closed | State::Listen => {}
+            State::TimeWait => {
+                socket.clear();
+                socket.client.map(|client| client.closed(id, Ok(())));
+            }
+            _ if socket.retransmits.get() >= MAX_RETRANSMITS => {
+                self.fail(id, socket, ErrorCode::NOACK)
+            }
+            _ => {
+                socket.retransmits.set(socket.retransmits.get() + 1);
+                socket
+                    .rto_ms
+                    .set(cmp::min(2 * socket.rto_ms.get(), MAX_RTO_MS));
+                socket.rtt_probe.set(None);
+                if socket.snd_una.get() == socket.snd_max.get() {
+                    socket.probe.set(true);
+                }
+                socket.snd_nxt.set(socket.snd_una.get());
+                socket.update_timer();
+            }
+        }
+    }
+
+    /// Drop the connection of socket `id` because of `error`. A connection
+    /// that was being accepted goes back to listening.
+    fn fail(&self, id: usize, socket: &TcpSocket, error: ErrorCode) {
+        let state = socket.state.get();
+        let passive = socket.passive.get();
+        socket.clear();
+        match state {
+            State::Closed | State::Listen => {}
+            State::SynReceived if passive => socket.state.set(State::Listen),
+            State::SynSent | State::SynReceived => {
+                socket.client.map(|client| client.connected(id, Err(error)));
+            }
+            _ => {
+                socket.client.map(|client| client.closed(id, Err(error)));
+            }
+        }
+    }
+
+    /// Answer a segment that is for no connection with a reset (RFC 9293,
+    /// 3.10.7.1).
+    fn refuse(&self, src: IPv4Addr, header: &TCPHeader, payload_len: usize) {
+        if header.has_flags(flags::RST) {
+            return;
+        }
+        let reset = if header.has_flags(flags::ACK) {
+            TCPHeader::new(
+                header.dst_port,
+                header.src_port,
+                header.ack_num,
+                0,
+                flags::RST,
+                0,
+                TCP_HEADER_LEN,
+            )
+        } else {
+            TCPHeader::new(
+                header.dst_port,
+                header.src_port,
+                0,
+                header.seq_num.wrapping_add(seq_len(header, payload_len)),
+                flags::RST | flags::ACK,
+                0,
+                TCP_HEADER_LEN,
+            )
+        };
+        self.reset.set(Some((src, reset)));
+    }
+
+    fn receive_listen(&self, socket: &TcpSocket, src: IPv4Addr, header: &TCPHeader, mss: u16) {
+        if header.has_flags(flags::RST) {
+            return;
+        }
+        if header.has_flags(flags::ACK) {
+            self.refuse(src, header, 0);
+            return;
+        }
+        if !header.has_flags(flags::SYN) {
+            return;
+        }
+        socket.open(State::SynReceived, src, header.src_port, self.next_iss());
+        socket.passive.set(true);
+        socket.mss.set(mss as usize);
+        socket.snd_wnd.set(header.window as usize);
+        socket.rcv_nxt.set(header.seq_num.wrapping_add(1));
+    }
+
+    fn receive_syn_sent(&self, id: usize, socket: &TcpSocket, header: &TCPHeader, mss: u16) {
+        let iss = socket.snd_una.get();
+        let has_ack = header.has_flags(flags::ACK);
+        let ack_ok = has_ack && header.ack_num == iss.wrapping_add(1);
+        if has_ack && !ack_ok {
+            self.refuse(socket.remote_addr.get(), header, 0);
+            return;
+        }
+        if header.has_flags(flags::RST) {
+            if ack_ok {
+                self.fail(id, socket, ErrorCode::CANCEL);
+            }
+            return;
+        }
+        if !header.has_flags(flags::SYN) {
+            return;
+        }
+        socket.mss.set(mss as usize);
+        socket.snd_wnd.set(header.window as usize);
+        socket.rcv_nxt.set(header.seq_num.wrapping_add(1));
+        if ack_ok {
+            self.acknowledged(socket, header.ack_num);
+            socket.snd_una.set(header.ack_num);
+            socket.state.set(State::Established);
+            socket.ack_due.set(true);
+            socket.update_timer();
+            socket.client.map(|client| client.connected(id, Ok(())));
+        } else {
+            // Simultaneous open: send the SYN again, with an ACK
+            socket.state.set(State::SynReceived);
+            socket.snd_nxt.set(iss);
+        }
+    }
+
+    /// Take a round-trip time sample if `ack` acknowledges the timed
+    /// segment.
+    fn acknowledged(&self, socket: &TcpSocket, ack: u32) {
+        if let Some((end, start)) = socket.rtt_probe.get() {
+            if seq_le(end, ack) {
+                socket.update_rto(self.now_ms.get().wrapping_sub(start));
+                socket.rtt_probe.set(None);
+            }
+        }
+        socket.retransmits.set(0);
+        socket.timer_ms.set(None);
+    }
+
+    fn receive_synchronized(
+        &self,
+        id: usize,
+        socket: &TcpSocket,
+        header: &TCPHeader,
+        payload: &[u8],
+    ) {
+        let rcv_nxt = socket.rcv_nxt.get();
+        let mut data = payload;
+        let mut fin = header.has_flags(flags::FIN);
+
+        if header.has_flags(flags::SYN) {
+            // A retransmitted SYN, or an attempt to open the connection
+            // again, is answered with an ACK (RFC 5961, 4)
+            socket.ack_due.set(true);
+            if socket.state.get() == State::SynReceived {
+                socket.snd_nxt.set(socket.snd_una.get());
+            }
+            return;
+        }
+
+        // Drop the data that was received already
+        if seq_lt(header.seq_num, rcv_nxt) {
+            let old = rcv_nxt.wrapping_sub(header.seq_num) as usize;
+            if old >= data.len() + fin as usize {
+                if !header.has_flags(flags::RST) {
+                    socket.ack_due.set(true);
+                }
+                return;
+            }
+            data = &data[old..];
+        } else if header.seq_num != rcv_nxt {
+            // Out of order: the ACK asks for the missing data
+            if !header.has_flags(flags::RST) {
+                socket.ack_due.set(true);
+            }
+            return;
+        }
+
+        if header.has_flags(flags::RST) {
+            self.fail(id, socket, ErrorCode::CANCEL);
+            return;
+        }
+        if !header.has_flags(flags::ACK) {
+            return;
+        }
+
+        let ack = header.ack_num;
+        let mut connected = false;
+        if socket.state.get() == State::SynReceived {
+            if !(seq_lt(socket.snd_una.get(), ack) && seq_le(ack, socket.snd_max.get())) {
+                self.refuse(socket.remote_addr.get(), header, payload.len());
+                return;
+            }
+            // The SYN is acknowledged
+            socket.snd_una.set(socket.snd_una.get().wrapping_add(1));
+            socket.state.set(State::Established);
+            connected = true;
+        }
+        if seq_lt(socket.snd_max.get(), ack) {
+            // Acknowledges data that was not sent
+            socket.ack_due.set(true);
+            return;
+        }
+
+        let mut space = None;
+        let mut fin_acked = false;
+        if seq_le(socket.snd_una.get(), ack) {
+            let acked = ack.wrapping_sub(socket.snd_una.get()) as usize;
+            if acked > 0 || connected {
+                self.acknowledged(socket, ack);
+            }
+            let data_acked = cmp::min(acked, socket.tx_len.get());
+            if data_acked > 0 {
+                socket
+                    .tx_start
+                    .set((socket.tx_start.get() + data_acked) % socket.tx_capacity);
+                socket.tx_len.set(socket.tx_len.get() - data_acked);
+                space = Some(socket.tx_capacity - socket.tx_len.get());
+            }
+            fin_acked = acked > data_acked;
+            socket.snd_una.set(ack);
+            if seq_lt(socket.snd_nxt.get(), ack) {
+                socket.snd_nxt.set(ack);
+            }
+            socket.snd_wnd.set(header.window as usize);
+        }
+        if fin_acked {
+            match socket.state.get() {
+                State::FinWait1 => socket.state.set(State::FinWait2),
+                State::Closing => {
+                    socket.state.set(State::TimeWait);
+                    socket.timer_ms.set(Some(TIME_WAIT_MS));
+                }
+                State::LastAck => {
+                    socket.clear();
+                    socket.client.map(|client| client.closed(id, Ok(())));
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let mut received = false;
+        if !data.is_empty() {
+            socket.ack_due.set(true);
+            let copied = match socket.state.get() {
+                State::Established | State::FinWait1 | State::FinWait2 => {
+                    socket.rx.map_or(0, |rx| {
+                        ring_write(rx, socket.rx_start.get(), socket.rx_len.get(), data)
+                    })
+                }
+                _ => 0,
+            };
+            socket.rx_len.set(socket.rx_len.get() + copied);
+            socket
+                .rcv_nxt
+                .set(socket.rcv_nxt.get().wrapping_add(copied as u32));
+            received = copied > 0;
+            // The FIN follows data that did not fit
+            fin &= copied == data.len();
+        }
+
+        let mut peer_closed = false;
+        if fin {
+            socket.rcv_nxt.set(socket.rcv_nxt.get().wrapping_add(1));
+            socket.ack_due.set(true);
+            match socket.state.get() {
+                State::Established => {
+                    socket.state.set(State::CloseWait);
+                    peer_closed = true;
+                }
+                State::FinWait1 => socket.state.set(State::Closing),
+                State::FinWait2 | State::TimeWait => {
+                    socket.state.set(State::TimeWait);
+                    socket.timer_ms.set(Some(TIME_WAIT_MS));
+                }
+                _ => {}
+            }
+        }
+        socket.update_timer();
+
+        socket.client.map(|client| {
+            if connected {
+                client.connected(id, Ok(()));
+            }
+            if let Some(space) = space {
+                client.sent(id, space);
+            }
+            if received {
+                client.received(id, socket.rx_len.get());
+            }
+            if peer_closed {
+                client.peer_closed(id);
+            }
+        });
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> TcpNetworkClient for TcpStack<'a, A> {
+    fn segment_sent(&self, segment: &'static mut [u8], _result: Result<(), ErrorCode>) {
+        // Lost segments are retransmitted on timeout
+        self.segment.replace(segment);
+        self.send_next();
+    }
+
+    fn segment_received(&self, src: IPv4Addr, segment: &[u8]) {
+        let header = match TCPHeader::decode(segment) {
+            Some(header) => header,
+            None => return,
+        };
+        let payload = &segment[header.header_len()..];
+        let mss = decode_mss(segment).unwrap_or(DEFAULT_MSS);
+        let connection = self
+            .sockets
+            .iter()
+            .enumerate()
+            .find(|(_, s)| s.is_connection(src, header.src_port, header.dst_port))
+            .or_else(|| {
+                self.sockets.iter().enumerate().find(|(_, s)| {
+                    s.state.get() == State::Listen && s.local_port.get() == header.dst_port
+                })
+            });
+        match connection {
+            Some((_, socket)) if socket.state.get() == State::Listen => {
+                self.receive_listen(socket, src, &header, mss)
+            }
+            Some((id, socket)) if socket.state.get() == State::SynSent => {
+                self.receive_syn_sent(id, socket, &header, mss)
+            }
+            Some((id, socket)) => self.receive_synchronized(id, socket, &header, payload),
+            None => self.refuse(src, &header, payload.len()),
+        }
+        self.send_next();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for TcpStack<'a, A> {
+    fn alarm(&self) {
+        self.now_ms.set(self.now_ms.get().wrapping_add(TICK_MS));
+        for (id, socket) in self.sockets.iter().enumerate() {
+            match socket.timer_ms.get() {
+                Some(left) if left > TICK_MS => socket.timer_ms.set(Some(left - TICK_MS)),
+                Some(_) => self.expired(id, socket),
+                None => {}
+            }
+        }
+        self.send_next();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks32, Time};
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    struct FakeAlarm {
+        armed: Cell<bool>,
+    }
+
+    impl Time for FakeAlarm {
+        type Frequency = Freq1KHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Ticks32 {
+            0u32.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for FakeAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Ticks32, _dt: Ticks32) {
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Ticks32 {
+            0u32.into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Ticks32 {
+            1u32.into()
+        }
+    }
+
+    /// Keeps the last segment sent, and completes sends when asked.
+    struct FakeNetwork {
+        sent: TakeCell<'static, [u8]>,
+        sent_len: Cell<usize>,
+    }
+
+    impl<'a> TcpNetwork<'a> for FakeNetwork {
+        fn set_client(&self, _client: &'a dyn TcpNetworkClient) {}
+
+        fn max_segment_len(&self) -> usize {
+            1480
+        }
+
+        fn send_segment(
+            &self,
+            _dest: IPv4Addr,
+            segment: &'static mut [u8],
+            len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            self.sent.replace(segment);
+            self.sent_len.set(len);
+            Ok(())
+        }
+    }
+
+    const PEER: IPv4Addr = IPv4Addr([10, 0, 0, 1]);
+
+    fn leak(len: usize) -> &'static mut [u8] {
+        Box::leak(vec![0; len].into_boxed_slice())
+    }
+
+    /// Complete the send of the last segment and return its header and
+    /// payload.
+    fn take_sent<A: Alarm<'static>>(
+        stack: &TcpStack<'static, A>,
+        network: &FakeNetwork,
+    ) -> Option<(TCPHeader, Vec<u8>)> {
+        let segment = network.sent.take()?;
+        let header = TCPHeader::decode(segment).unwrap();
+        let payload = segment[header.header_len()..network.sent_len.get()].to_vec();
+        stack.segment_sent(segment, Ok(()));
+        Some((header, payload))
+    }
+
+    fn segment(header: TCPHeader, payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0; TCP_HEADER_LEN + payload.len()];
+        header.encode(&mut buf);
+        buf[TCP_HEADER_LEN..].copy_from_slice(payload);
+        buf
+    }
+
+    #[test]
+    fn connect_send_close() {
+        let network: &'static FakeNetwork = Box::leak(Box::new(FakeNetwork {
+            sent: TakeCell::empty(),
+            sent_len: Cell::new(0),
+        }));
+        let alarm: &'static FakeAlarm = Box::leak(Box::new(FakeAlarm {
+            armed: Cell::new(false),
+        }));
+        let sockets: &'static [TcpSocket<'static>] = Box::leak(Box::new([
+            TcpSocket::new(leak(64), leak(8)),
+            TcpSocket::new(leak(64), leak(8)),
+        ]));
+        let stack = TcpStack::new(network, alarm, sockets, leak(SEGMENT_BUF_LEN));
+
+        // Three-way handshake
+        stack.connect(1, PEER, 80).unwrap();
+        let (syn, _) = take_sent(&stack, network).unwrap();
+        assert_eq!(syn.flags(), flags::SYN);
+        assert_eq!(syn.dst_port, 80);
+        let iss = syn.seq_num;
+        let local_port = syn.src_port;
+        let irs = 5000;
+        let reply = |seq: u32, ack: u32, control: u8, window: u16, data: &[u8]| {
+            let header = TCPHeader::new(80, local_port, seq, ack, control, window, TCP_HEADER_LEN);
+            stack.segment_received(PEER, &segment(header, data));
+        };
+        reply(irs, iss + 1, flags::SYN | flags::ACK, 4, &[]);
+        assert_eq!(stack.state(1), Ok(State::Established));
+        let (ack, _) = take_sent(&stack, network).unwrap();
+        assert_eq!(ack.flags(), flags::ACK);
+        assert_eq!(ack.ack_num, irs + 1);
+
+        // Data is sent within the window of the peer
+        assert_eq!(stack.send(1, b"hello world"), Ok(11));
+        let (first, payload) = take_sent(&stack, network).unwrap();
+        assert_eq!(first.seq_num, iss + 1);
+        assert_eq!(payload, b"hell");
+        assert!(network.sent.is_none());
+        reply(irs + 1, iss + 5, flags::ACK, 16, &[]);
+        let (_, payload) = take_sent(&stack, network).unwrap();
+        assert_eq!(payload, b"o world");
+
+        // A retransmission timeout sends the unacknowledged data again
+        let mut ticks = 0;
+        while network.sent.is_none() {
+            stack.alarm();
+            ticks += 1;
+            assert!(ticks <= INITIAL_RTO_MS / TICK_MS);
+        }
+        let (again, payload) = take_sent(&stack, network).unwrap();
+        assert_eq!(again.seq_num, iss + 5);
+        assert_eq!(payload, b"o world");
+        reply(irs + 1, iss + 12, flags::ACK, 16, &[]);
+
+        // Received data fills the receive buffer, and the window closes
+        reply(
+            irs + 1,
+            iss + 12,
+            flags::ACK | flags::PSH,
+            16,
+            b"0123456789",
+        );
+        let (ack, _) = take_sent(&stack, network).unwrap();
+        assert_eq!(ack.ack_num, irs + 9);
+        assert_eq!(ack.window, 0);
+        let mut buf = [0; 16];
+        assert_eq!(stack.receive(1, &mut buf), Ok(8));
+        assert_eq!(&buf[..8], b"01234567");
+        let (update, _) = take_sent(&stack, network).unwrap();
+        assert_eq!(update.window, 8);
+
+        // The peer closes first
+        reply(irs + 9, iss + 12, flags::ACK | flags::FIN, 16, &[]);
+        assert_eq!(stack.state(1), Ok(State::CloseWait));
+        take_sent(&stack, network).unwrap();
+        stack.close(1).unwrap();
+        let (fin, _) = take_sent(&stack, network).unwrap();
+        assert!(fin.has_flags(flags::FIN | flags::ACK));
+        assert_eq!(fin.seq_num, iss + 12);
+        reply(irs + 10, iss + 13, flags::ACK, 16, &[]);
+        assert_eq!(stack.state(1), Ok(State::Closed));
+    }
+
+    #[test]
+    fn listen_and_refuse() {
+        let network: &'static FakeNetwork = Box::leak(Box::new(FakeNetwork {
+            sent: TakeCell::empty(),
+            sent_len: Cell::new(0),
+        }));
+        let alarm: &'static FakeAlarm = Box::leak(Box::new(FakeAlarm {
+            armed: Cell::new(false),
+        }));
+        let sockets: &'static [TcpSocket<'static>] = Box::leak(Box::new([
+            TcpSocket::new(leak(64), leak(64)),
+            TcpSocket::new(leak(64), leak(64)),
+        ]));
+        let stack = TcpStack::new(network, alarm, sockets, leak(SEGMENT_BUF_LEN));
+        stack.listen(0, 1883).unwrap();
+        stack.listen(1, 1883).unwrap();
+
+        // Two connections to the same port are accepted by both sockets
+        for (id, port) in [(0, 40000), (1, 40001)] {
+            let syn = TCPHeader::new(port, 1883, 100, 0, flags::SYN, 512, TCP_HEADER_LEN);
+            stack.segment_received(PEER, &segment(syn, &[]));
+            let (syn_ack, _) = take_sent(&stack, network).unwrap();
+            assert_eq!(syn_ack.flags(), flags::SYN | flags::ACK);
+            assert_eq!(syn_ack.ack_num, 101);
+            let ack = TCPHeader::new(
+                port,
+                1883,
+                101,
+                syn_ack.seq_num + 1,
+                flags::ACK,
+                512,
+                TCP_HEADER_LEN,
+            );
+            stack.segment_received(PEER, &segment(ack, b"hi"));
+            assert_eq!(stack.state(id), Ok(State::Established));
+            assert_eq!(stack.remote(id), Some((PEER, port)));
+            let (ack, _) = take_sent(&stack, network).unwrap();
+            assert_eq!(ack.ack_num, 103);
+        }
+        let mut buf = [0; 4];
+        assert_eq!(stack.receive(1, &mut buf), Ok(2));
+
+        // No socket listens to the port
+        let syn = TCPHeader::new(40002, 80, 7, 0, flags::SYN, 512, TCP_HEADER_LEN);
+        stack.segment_received(PEER, &segment(syn, &[]));
+        let (reset, _) = take_sent(&stack, network).unwrap();
+        assert_eq!(reset.flags(), flags::RST | flags::ACK);
+        assert_eq!(reset.ack_num, 8);
+
+        // The peer resets a connection
+        let rst = TCPHeader::new(40000, 1883, 103, 0, flags::RST, 0, TCP_HEADER_LEN);
+        stack.segment_received(PEER, &segment(rst, &[]));
+        assert_eq!(stack.state(0), Ok(State::Closed));
+        assert_eq!(stack.state(1), Ok(State::Established));
+    }
+}
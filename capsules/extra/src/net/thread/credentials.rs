@@ -0,0 +1,404 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Thread network parameters stored in the KV store.
+//!
+//! The network key, PAN ID, channel and mesh-local prefix of the Thread
+//! network are stored under the key `thread/params`, so a device image does
+//! not have to be built for a particular network. The board calls `load()`
+//! at startup, and the Thread stack receives the parameters through
+//! [`ThreadParamsClient::params_loaded()`].
+//!
+//! The parameters are set with the `thread` command of the process console,
+//! or with the thread parameters frame of the provisioning capsule, which
+//! must use the same storage ID:
+//!
+//! ```text
+//! thread                   show the parameters, without the key
+//! thread key <32 hex>      set the network key
+//! thread panid <4 hex>     set the PAN ID
+//! thread channel <11-26>   set the channel
+//! thread prefix <16 hex>   set the mesh-local prefix
+//! thread save              store the parameters
+//! ```
+//!
+//! The stored value is [`PARAMS_LEN`] bytes: a format version of 1, the
+//! network key, the PAN ID in little endian, the channel and the mesh-local
+//! prefix.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let thread_credentials = components::thread_credentials::ThreadCredentialsComponent::new(
+//!     kv_store,
+//!     0x8000_0000,
+//! )
+//! .finalize(components::thread_credentials_component_static!(
+//!     capsules_extra::tickv::TicKVStore<...>,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! thread_credentials.set_client(thread_stack);
+//! let _ = thread_credentials.load();
+//! ```
+
+use core::cell::Cell;
+use core::fmt;
+
+use crate::kv_store::KVStore;
+use crate::provisioning::KEY_LEN;
+use capsules_core::process_console::ConsoleCommand;
+use kernel::debug;
+use kernel::hil::kv_system::{self, KVSystem};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Key the parameters are stored under.
+pub const KEY_THREAD_PARAMS: &[u8] = b"thread/params";
+/// Length of the stored parameters.
+pub const PARAMS_LEN: usize = 28;
+
+const FORMAT_VERSION: u8 = 1;
+
+/// Parameters of the Thread network the device joins.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ThreadParams {
+    pub network_key: [u8; 16],
+    pub pan_id: u16,
+    pub channel: u8,
+    pub mesh_local_prefix: [u8; 8],
+}
+
+impl ThreadParams {
+    pub fn encode(&self, buf: &mut [u8; PARAMS_LEN]) {
+        buf[0] = FORMAT_VERSION;
+        buf[1..17].copy_from_slice(&self.network_key);
+        buf[17..19].copy_from_slice(&self.pan_id.to_le_bytes());
+        buf[19] = self.channel;
+        buf[20..28].copy_from_slice(&self.mesh_local_prefix);
+    }
+
+    /// Returns `None` if `buf` does not hold valid parameters.
+    pub fn decode(buf: &[u8]) -> Option<ThreadParams> {
+        if buf.len() < PARAMS_LEN || buf[0] != FORMAT_VERSION {
+            return None;
+        }
+        let mut params = ThreadParams {
+            pan_id: u16::from_le_bytes([buf[17], buf[18]]),
+            channel: buf[19],
+            ..Default::default()
+        };
+        params.network_key.copy_from_slice(&buf[1..17]);
+        params.mesh_local_prefix.copy_from_slice(&buf[20..28]);
+        params.is_valid().then_some(params)
+    }
+
+    /// Whether the channel is a 2.4 GHz IEEE 802.15.4 channel.
+    pub fn is_valid(&self) -> bool {
+        (11..=26).contains(&self.channel)
+    }
+}
+
+/// Interface of the Thread stack to receive its parameters.
+pub trait ThreadParamsClient {
+    /// Called when `load()` completes. Fails with `FAIL` if no parameters
+    /// are stored or they are invalid.
+    fn params_loaded(&self, params: Result<ThreadParams, ErrorCode>);
+
+    /// Called when `store()` completes.
+    fn params_stored(&self, params: ThreadParams, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Loading,
+    /// Removing the old value before writing the new one.
+    Deleting,
+    Writing,
+}
+
+pub struct ThreadCredentials<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> {
+    kv: &'a KVStore<'a, K, T>,
+    perms: StoragePermissions,
+    client: OptionalCell<&'a dyn ThreadParamsClient>,
+    state: Cell<State>,
+    /// Parameters loaded from or being written to the KV store.
+    params: OptionalCell<ThreadParams>,
+    /// Parameters edited by the console until they are saved.
+    staged: Cell<ThreadParams>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> ThreadCredentials<'a, K, T> {
+    pub fn new(
+        kv: &'a KVStore<'a, K, T>,
+        perms: StoragePermissions,
+        key_buffer: &'static mut [u8; KEY_LEN],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        ThreadCredentials {
+            kv,
+            perms,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            params: OptionalCell::empty(),
+            staged: Cell::new(ThreadParams::default()),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn ThreadParamsClient) {
+        self.client.set(client);
+    }
+
+    /// The parameters last loaded or stored.
+    pub fn params(&self) -> Option<ThreadParams> {
+        self.params.extract()
+    }
+
+    /// Read the parameters from the KV store.
+    pub fn load(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let value = match self.value_buffer.take() {
+            Some(value) => value,
+            None => {
+                self.key_buffer.replace(key);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+        set_key(key);
+        self.state.set(State::Loading);
+        self.kv
+            .get(key, value, self.perms)
+            .map_err(|(key, value, e)| {
+                self.key_buffer.replace(key);
+                self.value_buffer.replace(value);
+                self.state.set(State::Idle);
+                e.err().unwrap_or(ErrorCode::FAIL)
+            })
+    }
+
+    /// Replace the stored parameters with `params`.
+    pub fn store(&self, params: ThreadParams) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if !params.is_valid() {
+            return Err(ErrorCode::INVAL);
+        }
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        set_key(key);
+        self.params.set(params);
+        self.state.set(State::Deleting);
+        self.kv.delete(key, self.perms).map_err(|(key, e)| {
+            self.key_buffer.replace(key);
+            self.state.set(State::Idle);
+            e.err().unwrap_or(ErrorCode::FAIL)
+        })
+    }
+
+    fn write(&self) {
+        let result = self.key_buffer.take().map_or(Err(ErrorCode::NOMEM), |key| {
+            match self.value_buffer.take() {
+                Some(value) if value.len() >= PARAMS_LEN => {
+                    let mut encoded = [0; PARAMS_LEN];
+                    self.params.map(|params| params.encode(&mut encoded));
+                    value[..PARAMS_LEN].copy_from_slice(&encoded);
+                    self.kv
+                        .set(key, value, PARAMS_LEN, self.perms)
+                        .map_err(|(key, value, e)| {
+                            self.key_buffer.replace(key);
+                            self.value_buffer.replace(value);
+                            e.err().unwrap_or(ErrorCode::FAIL)
+                        })
+                }
+                Some(value) => {
+                    self.key_buffer.replace(key);
+                    self.value_buffer.replace(value);
+                    Err(ErrorCode::SIZE)
+                }
+                None => {
+                    self.key_buffer.replace(key);
+                    Err(ErrorCode::NOMEM)
+                }
+            }
+        });
+        match result {
+            Ok(()) => self.state.set(State::Writing),
+            Err(e) => self.stored(Err(e)),
+        }
+    }
+
+    fn stored(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        let params = self.params.extract().unwrap_or_default();
+        if let Err(e) = result {
+            debug!("Storing the Thread parameters failed: {:?}", e);
+        }
+        self.client
+            .map(|client| client.params_stored(params, result));
+    }
+}
+
+/// Zero `key` and write the key of the parameters to its start.
+fn set_key(key: &mut [u8]) {
+    key.fill(0);
+    key[..KEY_THREAD_PARAMS.len()].copy_from_slice(KEY_THREAD_PARAMS);
+}
+
+/// Parse the hexadecimal string `hex` into `out`, which it must fill
+/// exactly.
+fn parse_hex(hex: &str, out: &mut [u8]) -> bool {
+    if hex.len() != 2 * out.len() {
+        return false;
+    }
+    for (byte, digits) in out.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        match core::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+        {
+            Some(value) => *byte = value,
+            None => return false,
+        }
+    }
+    true
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> kv_system::StoreClient<T>
+    for ThreadCredentials<'a, K, T>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        let params = result.and_then(|()| ThreadParams::decode(value).ok_or(ErrorCode::FAIL));
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() != State::Loading {
+            return;
+        }
+        self.state.set(State::Idle);
+        if let Ok(params) = params {
+            self.params.set(params);
+            self.staged.set(params);
+        }
+        self.client.map(|client| client.params_loaded(params));
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() == State::Writing {
+            self.stored(result);
+        }
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: &'static mut [u8]) {
+        self.key_buffer.replace(key);
+        // Deleting fails if there is no old value, which is fine.
+        if self.state.get() == State::Deleting {
+            self.write();
+        }
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> ConsoleCommand
+    for ThreadCredentials<'a, K, T>
+{
+    fn name(&self) -> &'static str {
+        "thread"
+    }
+
+    fn execute(&self, args: &str, out: &mut dyn fmt::Write) {
+        let mut args = args.split_whitespace();
+        let mut staged = self.staged.get();
+        let valid = match (args.next(), args.next()) {
+            (None, _) => {
+                let _ = match self.params.extract() {
+                    Some(params) => write!(
+                        out,
+                        "Stored: PAN ID {:04x}, channel {}, prefix {:02x?}\r\n",
+                        params.pan_id, params.channel, params.mesh_local_prefix
+                    ),
+                    None => write!(out, "No Thread parameters stored\r\n"),
+                };
+                let _ = write!(
+                    out,
+                    "Staged: PAN ID {:04x}, channel {}, prefix {:02x?}\r\n",
+                    staged.pan_id, staged.channel, staged.mesh_local_prefix
+                );
+                return;
+            }
+            (Some("key"), Some(hex)) => parse_hex(hex, &mut staged.network_key),
+            (Some("panid"), Some(hex)) => {
+                let mut pan_id = [0; 2];
+                let valid = parse_hex(hex, &mut pan_id);
+                staged.pan_id = u16::from_be_bytes(pan_id);
+                valid
+            }
+            (Some("channel"), Some(channel)) => match channel.parse::<u8>() {
+                Ok(channel) if (11..=26).contains(&channel) => {
+                    staged.channel = channel;
+                    true
+                }
+                _ => false,
+            },
+            (Some("prefix"), Some(hex)) => parse_hex(hex, &mut staged.mesh_local_prefix),
+            (Some("save"), None) => {
+                let _ = match self.store(staged) {
+                    Ok(()) => write!(out, "Storing the Thread parameters\r\n"),
+                    Err(e) => write!(out, "Storing failed: {:?}\r\n", e),
+                };
+                return;
+            }
+            _ => {
+                let _ = write!(
+                    out,
+                    "Usage: thread [key|panid|channel|prefix <value>|save]\r\n"
+                );
+                return;
+            }
+        };
+        if valid {
+            self.staged.set(staged);
+        } else {
+            let _ = write!(out, "Invalid value\r\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_round_trip() {
+        let params = ThreadParams {
+            network_key: [0xa5; 16],
+            pan_id: 0xface,
+            channel: 15,
+            mesh_local_prefix: [0xfd, 0, 0x0d, 0xb8, 0, 0, 0, 0],
+        };
+        let mut buf = [0; PARAMS_LEN];
+        params.encode(&mut buf);
+        assert_eq!(ThreadParams::decode(&buf), Some(params));
+
+        buf[19] = 27;
+        assert_eq!(ThreadParams::decode(&buf), None);
+    }
+}
@@ -0,0 +1,413 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Mesh Link Establishment (MLE) messages of Thread 1.1 (Chapter 4).
+//!
+//! MLE messages are UDP datagrams to and from port 19788 between link-local
+//! addresses. They consist of a command type and a series of TLV
+//! parameters, see the `tlv` module.
+//!
+//! MLE for network attaching comprises a four-step handshake that works
+//! as follows:
+//!
+//! 1. A child device multicasts a Parent Request MLE command.
+//! 2. Each potential parent device on the network unicasts a Parent
+//!    Response MLE command.
+//! 3. The child device selects a parent based on a hierarchy of
+//!    connectivity metrics and unicasts a Child ID Request MLE
+//!    command.
+//! 4. The selected parent unicasts a Child ID Response MLE command.
+//!
+//! Afterwards, the child keeps the link to its parent alive with Child
+//! Update Request and Response commands.
+//!
+//! Messages are secured with keys derived from the network key (Section
+//! 7.1.4): HMAC-SHA256 of the network key over the key sequence followed by
+//! "Thread" gives the MLE key in its first 16 bytes and the MAC key in the
+//! other 16. After the security suite byte, a message has the auxiliary
+//! security header of IEEE 802.15.4 (Section 4.4.2): security level 5, key
+//! identifier mode 2 with the key sequence as key source. The command and
+//! its TLVs are encrypted with AES-128-CCM and followed by a 4 byte MIC,
+//! with the nonce of IEEE 802.15.4 from the extended address of the sender,
+//! and the IPv6 source and destination addresses and the auxiliary header as
+//! additional data.
+
+use crate::net::stream::SResult;
+use crate::net::thread::tlv::Tlv;
+
+/// UDP port of MLE messages
+pub const MLE_PORT: u16 = 19788;
+
+/// Security suite of messages secured as described above.
+pub const SECURITY_SUITE_154: u8 = 0;
+/// Security suite of unsecured messages, only used for discovery.
+pub const SECURITY_SUITE_NONE: u8 = 255;
+
+/// Length of the auxiliary security header
+pub const AUX_HEADER_LEN: usize = 10;
+pub const MIC_LEN: usize = 4;
+pub const NONCE_LEN: usize = 13;
+/// Length of the input of the key derivation, the key sequence followed by
+/// "Thread".
+pub const KEY_DERIVATION_INPUT_LEN: usize = 10;
+
+/// Security level 5 (ENC-MIC-32) with key identifier mode 2.
+const SECURITY_CONTROL: u8 = 0x15;
+const SECURITY_LEVEL: u8 = 5;
+
+/// Offset of the IPv6 source and destination addresses in the buffers
+/// messages are secured in. With the auxiliary header that follows them,
+/// they are the additional data of CCM.
+pub const ADDRESSES_OFFSET: usize = 0;
+/// Offset of the security suite in the buffers messages are secured in. It
+/// overlaps the last byte of the destination address: it is written once
+/// the message is encrypted, and the address once it is received.
+pub const SUITE_OFFSET: usize = 31;
+pub const AUX_HEADER_OFFSET: usize = 32;
+/// Offset of the command, the start of the encrypted data.
+pub const COMMAND_OFFSET: usize = AUX_HEADER_OFFSET + AUX_HEADER_LEN;
+/// The longest command with its TLVs that is sent or received.
+pub const MAX_MESSAGE_LEN: usize = 256;
+/// Length of the buffers messages are secured in.
+pub const MLE_BUFFER_LEN: usize = COMMAND_OFFSET + MAX_MESSAGE_LEN + MIC_LEN;
+
+/// Version of the Thread protocol, 1.1
+pub const THREAD_VERSION: u16 = 2;
+
+/// MLE command types (Section 4.4)
+pub mod command {
+    pub const LINK_REQUEST: u8 = 0;
+    pub const LINK_ACCEPT: u8 = 1;
+    pub const LINK_ACCEPT_AND_REQUEST: u8 = 2;
+    pub const LINK_REJECT: u8 = 3;
+    pub const ADVERTISEMENT: u8 = 4;
+    pub const DATA_REQUEST: u8 = 7;
+    pub const DATA_RESPONSE: u8 = 8;
+    pub const PARENT_REQUEST: u8 = 9;
+    pub const PARENT_RESPONSE: u8 = 10;
+    pub const CHILD_ID_REQUEST: u8 = 11;
+    pub const CHILD_ID_RESPONSE: u8 = 12;
+    pub const CHILD_UPDATE_REQUEST: u8 = 13;
+    pub const CHILD_UPDATE_RESPONSE: u8 = 14;
+    pub const ANNOUNCE: u8 = 15;
+}
+
+/// Bits of the Mode TLV (Section 4.5.2)
+pub mod mode {
+    pub const RX_ON_WHEN_IDLE: u8 = 0x08;
+    pub const FULL_THREAD_DEVICE: u8 = 0x02;
+    pub const FULL_NETWORK_DATA: u8 = 0x01;
+}
+
+/// Bits of the Scan Mask TLV (Section 4.5.14)
+pub mod scan_mask {
+    pub const ROUTERS: u8 = 0x80;
+    pub const END_DEVICES: u8 = 0x40;
+}
+
+/// The auxiliary security header of a secured message.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AuxHeader {
+    pub frame_counter: u32,
+    pub key_sequence: u32,
+}
+
+impl AuxHeader {
+    /// Encode the header into the first `AUX_HEADER_LEN` bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0] = SECURITY_CONTROL;
+        buf[1..5].copy_from_slice(&self.frame_counter.to_le_bytes());
+        buf[5..9].copy_from_slice(&self.key_sequence.to_be_bytes());
+        buf[9] = key_index(self.key_sequence);
+    }
+
+    /// Decode the header at the start of `buf`. Fails if the header does not
+    /// have the security level and key identifier mode of MLE, or its key
+    /// index does not match its key sequence.
+    pub fn decode(buf: &[u8]) -> Option<AuxHeader> {
+        if buf.len() < AUX_HEADER_LEN || buf[0] != SECURITY_CONTROL {
+            return None;
+        }
+        let header = AuxHeader {
+            frame_counter: u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]),
+            key_sequence: u32::from_be_bytes([buf[5], buf[6], buf[7], buf[8]]),
+        };
+        (buf[9] == key_index(header.key_sequence)).then_some(header)
+    }
+}
+
+/// The key index of the key sequence `key_sequence`, also used by the MAC
+/// layer with key identifier mode 1.
+pub fn key_index(key_sequence: u32) -> u8 {
+    (key_sequence & 0x7f) as u8 + 1
+}
+
+/// The message HMAC-SHA256 of the network key is computed over to derive
+/// the keys of `key_sequence`.
+pub fn key_derivation_input(key_sequence: u32) -> [u8; KEY_DERIVATION_INPUT_LEN] {
+    let mut input = [0; KEY_DERIVATION_INPUT_LEN];
+    input[..4].copy_from_slice(&key_sequence.to_be_bytes());
+    input[4..].copy_from_slice(b"Thread");
+    input
+}
+
+/// The CCM nonce of a message from `ext_addr` with `frame_counter`.
+pub fn nonce(ext_addr: &[u8; 8], frame_counter: u32) -> [u8; NONCE_LEN] {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..8].copy_from_slice(ext_addr);
+    nonce[8..12].copy_from_slice(&frame_counter.to_be_bytes());
+    nonce[12] = SECURITY_LEVEL;
+    nonce
+}
+
+/// The Leader Data TLV of a Thread partition.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeaderData {
+    pub partition_id: u32,
+    pub weighting: u8,
+    pub data_version: u8,
+    pub stable_data_version: u8,
+    pub leader_router_id: u8,
+}
+
+impl LeaderData {
+    pub fn to_tlv(&self) -> Tlv<'static> {
+        Tlv::LeaderData {
+            partition_id: self.partition_id,
+            weighting: self.weighting,
+            data_version: self.data_version,
+            stable_data_version: self.stable_data_version,
+            leader_router_id: self.leader_router_id,
+        }
+    }
+}
+
+/// The TLVs of a received message that attaching uses. TLVs that are not
+/// decoded are ignored, and of repeated TLVs the last one is kept.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageTlvs {
+    pub source_address: Option<u16>,
+    pub mode: Option<u8>,
+    pub timeout: Option<u32>,
+    pub challenge: Option<[u8; 8]>,
+    pub response: Option<[u8; 8]>,
+    pub link_frame_counter: Option<u32>,
+    pub mle_frame_counter: Option<u32>,
+    pub address16: Option<u16>,
+    pub leader_data: Option<LeaderData>,
+    pub link_margin: Option<u8>,
+    /// Parent priority and number of neighbors with link quality 3 of the
+    /// Connectivity TLV
+    pub connectivity: Option<(u8, u8)>,
+    pub status: Option<u8>,
+}
+
+impl MessageTlvs {
+    pub fn decode(mut buf: &[u8]) -> MessageTlvs {
+        let mut tlvs = MessageTlvs::default();
+        while buf.len() >= 2 {
+            let len = 2 + buf[1] as usize;
+            if len > buf.len() {
+                break;
+            }
+            if let SResult::Done(_, tlv) = Tlv::decode(&buf[..len]) {
+                match tlv {
+                    Tlv::SourceAddress(addr) => tlvs.source_address = Some(addr),
+                    Tlv::Mode(mode) => tlvs.mode = Some(mode),
+                    Tlv::Timeout(timeout) => tlvs.timeout = Some(timeout),
+                    Tlv::Challenge(challenge) => tlvs.challenge = Some(challenge),
+                    Tlv::Response(response) => tlvs.response = Some(response),
+                    Tlv::LinkLayerFrameCounter(counter) => tlvs.link_frame_counter = Some(counter),
+                    Tlv::MleFrameCounter(counter) => tlvs.mle_frame_counter = Some(counter),
+                    Tlv::Address16(addr) => tlvs.address16 = Some(addr),
+                    Tlv::LeaderData {
+                        partition_id,
+                        weighting,
+                        data_version,
+                        stable_data_version,
+                        leader_router_id,
+                    } => {
+                        tlvs.leader_data = Some(LeaderData {
+                            partition_id,
+                            weighting,
+                            data_version,
+                            stable_data_version,
+                            leader_router_id,
+                        })
+                    }
+                    Tlv::LinkMargin(margin) => tlvs.link_margin = Some(margin),
+                    Tlv::Connectivity {
+                        parent_priority,
+                        link_quality_3,
+                        ..
+                    } => tlvs.connectivity = Some((parent_priority, link_quality_3)),
+                    Tlv::Status(status) => tlvs.status = Some(status),
+                    _ => {}
+                }
+            }
+            buf = &buf[len..];
+        }
+        tlvs
+    }
+}
+
+/// Encode the command `command` followed by `tlvs` into `buf`. Returns the
+/// length of the message, `None` if it does not fit.
+pub fn encode_message(buf: &mut [u8], command: u8, tlvs: &[Tlv]) -> Option<usize> {
+    *buf.first_mut()? = command;
+    let mut offset = 1;
+    for tlv in tlvs {
+        match tlv.encode(&mut buf[offset..]) {
+            SResult::Done(len, ()) => offset += len,
+            _ => return None,
+        }
+    }
+    Some(offset)
+}
+
+/// A Parent Request of a device with `mode`, to the parents selected by
+/// `scan_mask`.
+pub fn parent_request(buf: &mut [u8], mode: u8, challenge: [u8; 8], scan: u8) -> Option<usize> {
+    encode_message(
+        buf,
+        command::PARENT_REQUEST,
+        &[
+            Tlv::Mode(mode),
+            Tlv::Challenge(challenge),
+            Tlv::ScanMask(scan),
+            Tlv::Version(THREAD_VERSION),
+        ],
+    )
+}
+
+/// A Child ID Request answering the challenge `response` of the parent,
+/// which requests a short address and the network data.
+pub fn child_id_request(
+    buf: &mut [u8],
+    mode: u8,
+    response: [u8; 8],
+    link_frame_counter: u32,
+    mle_frame_counter: u32,
+    timeout: u32,
+) -> Option<usize> {
+    const REQUESTED: [u8; 2] = [10, 12]; // Address16 and NetworkData
+    encode_message(
+        buf,
+        command::CHILD_ID_REQUEST,
+        &[
+            Tlv::Response(response),
+            Tlv::LinkLayerFrameCounter(link_frame_counter),
+            Tlv::MleFrameCounter(mle_frame_counter),
+            Tlv::Mode(mode),
+            Tlv::Timeout(timeout),
+            Tlv::Version(THREAD_VERSION),
+            Tlv::TlvRequest(&REQUESTED),
+        ],
+    )
+}
+
+/// A Child Update Request or Response of the child with short address
+/// `rloc16`. Responses answer the challenge of the parent, if it sent one.
+pub fn child_update(
+    buf: &mut [u8],
+    command: u8,
+    mode: u8,
+    rloc16: u16,
+    leader_data: LeaderData,
+    timeout: u32,
+    response: Option<[u8; 8]>,
+) -> Option<usize> {
+    let tlvs = [
+        Tlv::Mode(mode),
+        Tlv::SourceAddress(rloc16),
+        leader_data.to_tlv(),
+        Tlv::Timeout(timeout),
+        Tlv::Response(response.unwrap_or_default()),
+    ];
+    let count = if response.is_some() { 5 } else { 4 };
+    encode_message(buf, command, &tlvs[..count])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aux_header() {
+        let header = AuxHeader {
+            frame_counter: 0x01020304,
+            key_sequence: 0x80,
+        };
+        let mut buf = [0; AUX_HEADER_LEN];
+        header.encode(&mut buf);
+        assert_eq!(buf, [0x15, 4, 3, 2, 1, 0, 0, 0, 0x80, 1]);
+        assert_eq!(AuxHeader::decode(&buf), Some(header));
+
+        // The key index does not match the key sequence
+        buf[9] = 2;
+        assert_eq!(AuxHeader::decode(&buf), None);
+
+        assert_eq!(
+            nonce(&[1, 2, 3, 4, 5, 6, 7, 8], 0x01020304),
+            [1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(&key_derivation_input(1), b"\0\0\0\x01Thread");
+    }
+
+    #[test]
+    fn parent_request_encoding() {
+        let mut buf = [0; 32];
+        let len = parent_request(
+            &mut buf,
+            mode::RX_ON_WHEN_IDLE,
+            [1, 2, 3, 4, 5, 6, 7, 8],
+            scan_mask::ROUTERS,
+        )
+        .unwrap();
+        assert_eq!(len, 21);
+        assert_eq!(&buf[..5], &[command::PARENT_REQUEST, 1, 1, 0x08, 3]);
+        // Version in network byte order
+        assert_eq!(&buf[17..21], &[18, 2, 0, 2]);
+
+        assert_eq!(parent_request(&mut buf[..10], 0, [0; 8], 0), None);
+    }
+
+    #[test]
+    fn decode_response() {
+        let leader_data = LeaderData {
+            partition_id: 0x12345678,
+            weighting: 64,
+            data_version: 3,
+            stable_data_version: 2,
+            leader_router_id: 5,
+        };
+        let mut buf = [0; 64];
+        let len = child_update(
+            &mut buf,
+            command::CHILD_UPDATE_RESPONSE,
+            mode::RX_ON_WHEN_IDLE,
+            0x0401,
+            leader_data,
+            240,
+            Some([8; 8]),
+        )
+        .unwrap();
+        // Source Address in network byte order
+        assert_eq!(&buf[4..8], &[0, 2, 0x04, 0x01]);
+        // A TLV that is not decoded is skipped
+        buf[len..len + 4].copy_from_slice(&[9, 2, 0xaa, 0xbb]);
+        let tlvs = MessageTlvs::decode(&buf[1..len + 4]);
+        assert_eq!(tlvs.mode, Some(mode::RX_ON_WHEN_IDLE));
+        assert_eq!(tlvs.source_address, Some(0x0401));
+        assert_eq!(tlvs.leader_data, Some(leader_data));
+        assert_eq!(tlvs.timeout, Some(240));
+        assert_eq!(tlvs.response, Some([8; 8]));
+        assert_eq!(tlvs.challenge, None);
+
+        // A truncated TLV ends the message
+        let tlvs = MessageTlvs::decode(&buf[1..len - 1]);
+        assert_eq!(tlvs.timeout, Some(240));
+        assert_eq!(tlvs.response, None);
+    }
+}
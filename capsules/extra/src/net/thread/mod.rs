@@ -2,4 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
+pub mod credentials;
+pub mod mle;
+pub mod mtd;
 pub mod tlv;
@@ -0,0 +1,1105 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! A Minimal Thread Device (MTD) that attaches to a Thread network as the
+//! child of a router with MLE, without OpenThread.
+//!
+//! The device is a Minimal End Device: it keeps its receiver on and does not
+//! route. It joins the network of its `ThreadParams` with the network key,
+//! which it receives from the Thread credentials through
+//! `ThreadParamsClient`, or with `set_params()`. Attaching starts when the
+//! parameters are set:
+//!
+//! 1. The MLE and MAC keys are derived from the network key, with HMAC
+//!    computed on a SHA-256 engine, and a challenge is drawn.
+//! 2. A Parent Request is multicast to the routers of the link, and Parent
+//!    Responses are collected for 750 ms. Requests are repeated up to 4
+//!    times, from the third on to end devices that can become routers too,
+//!    with responses collected for 1.25 s.
+//! 3. The parent with the best link quality, then the most neighbors of link
+//!    quality 3, is sent a Child ID Request, up to 3 times.
+//! 4. The Child ID Response of the parent assigns the short address of the
+//!    device, its RLOC16, which is set on the MAC device.
+//!
+//! The client is told when attaching completes, or fails with `NOACK` if no
+//! parent answers. Once attached, a Child Update Request keeps the link
+//! alive every half `CHILD_TIMEOUT_S`. If the parent does not answer three
+//! of them, or answers with an error, the device is detached, tells its
+//! client and attaches again.
+//!
+//! MLE messages are sent through a UDP sender bound to `MLE_PORT`, from the
+//! link-local address of the extended address of the MAC device, which the
+//! IPv6 layer must use as source address of link-local packets. Messages
+//! are secured in one buffer each way, on an AES-128-CCM engine that takes
+//! 13 byte nonces. Messages received while the engine is busy are dropped,
+//! MLE repeats its requests.
+//!
+//! `ThreadMtd` implements the key and device procedures of the 802.15.4
+//! framer, to secure frames with the MAC key of the network and to know the
+//! extended address of the parent.
+//!
+//! Key sequences are not rotated: messages with another key sequence than
+//! the one set with `set_key_sequence()`, 0 by default, are dropped. Frame
+//! counters start at 0 at boot. The network data is not processed, and the
+//! radio has to be on the channel of the network.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let thread = components::thread_mtd::ThreadMtdComponent::new(
+//!     mux_mac,
+//!     udp_send_mux,
+//!     udp_recv_mux,
+//!     udp_port_table,
+//!     mux_alarm,
+//!     ccm,
+//!     sha,
+//!     rng,
+//! )
+//! .finalize(components::thread_mtd_component_static!(...));
+//! thread_credentials.set_client(thread);
+//! ```
+
+use core::cell::Cell;
+
+use crate::ieee802154::device::MacDevice;
+use crate::ieee802154::framer::{DeviceProcedure, KeyProcedure};
+use crate::net::ieee802154::{KeyId, MacAddress, SecurityLevel};
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6_nd::mac_from_link_local;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::thread::credentials::{ThreadParams, ThreadParamsClient};
+use crate::net::thread::mle::{
+    self, command, scan_mask, AuxHeader, LeaderData, MessageTlvs, AUX_HEADER_LEN,
+    AUX_HEADER_OFFSET, COMMAND_OFFSET, MAX_MESSAGE_LEN, MIC_LEN, MLE_PORT, SECURITY_SUITE_154,
+    SUITE_OFFSET,
+};
+use crate::net::udp::udp_recv::UDPRecvClient;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+use kernel::hil::digest::{self, Digest, Sha256};
+use kernel::hil::rng;
+use kernel::hil::symmetric_encryption::{CCMClient, AES128CCM};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::{LeasableBuffer, LeasableMutableBuffer};
+use kernel::ErrorCode;
+
+/// Timeout of the child, in seconds: the parent forgets the child if it
+/// does not hear from it for this long.
+pub const CHILD_TIMEOUT_S: u32 = 240;
+
+/// Length of the buffer HMAC messages are hashed from: a key pad followed
+/// by the key derivation input or the inner hash.
+pub const HASH_BUFFER_LEN: usize = BLOCK_LEN + HASH_LEN;
+
+/// Mode of the device: an MTD that keeps its receiver on and receives the
+/// full network data.
+const MODE: u8 = mle::mode::RX_ON_WHEN_IDLE | mle::mode::FULL_NETWORK_DATA;
+
+/// Size of a SHA-256 block, and of the HMAC key pad.
+const BLOCK_LEN: usize = 64;
+const HASH_LEN: usize = 32;
+const KEY_LEN: usize = 16;
+const CHALLENGE_LEN: usize = 8;
+
+const PARENT_REQUEST_ATTEMPTS: u8 = 4;
+/// Parent Requests sent to routers only, before end devices are included.
+const ROUTER_ONLY_ATTEMPTS: u8 = 2;
+const CHILD_ID_REQUEST_ATTEMPTS: u8 = 3;
+const CHILD_UPDATE_ATTEMPTS: u8 = 3;
+
+const PARENT_RESPONSE_MS: u32 = 750;
+const PARENT_RESPONSE_END_DEVICES_MS: u32 = 1250;
+const CHILD_ID_RESPONSE_MS: u32 = 1250;
+const CHILD_UPDATE_RESPONSE_MS: u32 = 1000;
+const KEEP_ALIVE_MS: u32 = CHILD_TIMEOUT_S * 1000 / 2;
+
+/// Short address of devices that do not have one.
+const NO_SHORT_ADDRESS: u16 = 0xfffe;
+
+/// ff02::2, the link-local all-routers multicast address.
+const ALL_ROUTERS: IPAddr = IPAddr([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+pub trait ThreadClient {
+    /// Attaching completed with the short address the parent assigned, or
+    /// failed with `NOACK` if no parent answered.
+    fn attached(&self, result: Result<u16, ErrorCode>);
+
+    /// The link to the parent was lost. The device attaches again.
+    fn detached(&self);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// Not attached, and not attaching
+    Detached,
+    /// Deriving the keys and drawing the challenge of the Parent Requests
+    Starting,
+    /// Collecting Parent Responses
+    ParentRequest,
+    /// Waiting for the Child ID Response of the selected parent
+    ChildIdRequest,
+    /// Attached to a parent
+    Child,
+    /// Attached, waiting for a Child Update Response of the parent
+    ChildUpdate,
+}
+
+/// A parent, or a candidate parent while attaching.
+#[derive(Copy, Clone)]
+pub struct Parent {
+    /// Link-local address
+    pub addr: IPAddr,
+    pub ext_addr: [u8; 8],
+    pub rloc16: u16,
+    /// Challenge of the Parent Response
+    challenge: [u8; CHALLENGE_LEN],
+    link_margin: u8,
+    link_quality_3: u8,
+    /// Last MLE frame counter received
+    frame_counter: u32,
+    leader_data: LeaderData,
+}
+
+impl Parent {
+    /// Whether the parent is a better choice than `other`: it has the
+    /// better link quality, then more neighbors with link quality 3, then
+    /// the higher link margin.
+    fn is_better_than(&self, other: &Parent) -> bool {
+        let rank = |parent: &Parent| {
+            (
+                link_quality(parent.link_margin),
+                parent.link_quality_3,
+                parent.link_margin,
+            )
+        };
+        rank(self) > rank(other)
+    }
+}
+
+/// The link quality of a link margin in dB (Section 4.4.1.1).
+fn link_quality(link_margin: u8) -> u8 {
+    match link_margin {
+        0..=2 => 0,
+        3..=10 => 1,
+        11..=20 => 2,
+        _ => 3,
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Keys {
+    mle: [u8; KEY_LEN],
+    mac: [u8; KEY_LEN],
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Derive {
+    Idle,
+    Inner,
+    Outer,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Crypt {
+    Idle,
+    Sealing,
+    Opening,
+}
+
+/// The message being opened.
+#[derive(Copy, Clone)]
+struct Received {
+    src: IPAddr,
+    ext_addr: [u8; 8],
+    frame_counter: u32,
+    len: usize,
+}
+
+pub struct ThreadMtd<
+    'a,
+    A: time::Alarm<'a>,
+    C: AES128CCM<'a>,
+    D: Digest<'a, 32> + Sha256,
+    R: rng::Rng<'a>,
+> {
+    mac: &'a dyn MacDevice<'a>,
+    sender: &'a dyn UDPSender<'a>,
+    net_cap: &'static NetworkCapability,
+    alarm: &'a A,
+    ccm: &'a C,
+    digest: &'a D,
+    rng: &'a R,
+    client: OptionalCell<&'a dyn ThreadClient>,
+
+    state: Cell<State>,
+    params: OptionalCell<ThreadParams>,
+    key_sequence: Cell<u32>,
+    keys: OptionalCell<Keys>,
+    derive: Cell<Derive>,
+    /// The parameters changed while the keys were derived.
+    derive_stale: Cell<bool>,
+    drawing: Cell<bool>,
+    challenge: Cell<[u8; CHALLENGE_LEN]>,
+    challenge_len: Cell<usize>,
+    attempts: Cell<u8>,
+    candidate: OptionalCell<Parent>,
+    parent: OptionalCell<Parent>,
+    rloc16: OptionalCell<u16>,
+    /// MLE frame counter of the next message sent
+    frame_counter: Cell<u32>,
+
+    crypt: Cell<Crypt>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_dest: Cell<IPAddr>,
+    tx_len: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    received: OptionalCell<Received>,
+    hash_buffer: MapCell<LeasableMutableBuffer<'static, u8>>,
+    digest_buf: TakeCell<'static, [u8; 32]>,
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    ThreadMtd<'a, A, C, D, R>
+{
+    /// `tx_buffer` and `rx_buffer` must hold `MLE_BUFFER_LEN` bytes, and
+    /// `hash_buffer` `HASH_BUFFER_LEN` bytes. `sender` must be bound to
+    /// `MLE_PORT`.
+    pub fn new(
+        mac: &'a dyn MacDevice<'a>,
+        sender: &'a dyn UDPSender<'a>,
+        net_cap: &'static NetworkCapability,
+        alarm: &'a A,
+        ccm: &'a C,
+        digest: &'a D,
+        rng: &'a R,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+        hash_buffer: &'static mut [u8],
+        digest_buf: &'static mut [u8; 32],
+    ) -> ThreadMtd<'a, A, C, D, R> {
+        ThreadMtd {
+            mac,
+            sender,
+            net_cap,
+            alarm,
+            ccm,
+            digest,
+            rng,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Detached),
+            params: OptionalCell::empty(),
+            key_sequence: Cell::new(0),
+            keys: OptionalCell::empty(),
+            derive: Cell::new(Derive::Idle),
+            derive_stale: Cell::new(false),
+            drawing: Cell::new(false),
+            challenge: Cell::new([0; CHALLENGE_LEN]),
+            challenge_len: Cell::new(0),
+            attempts: Cell::new(0),
+            candidate: OptionalCell::empty(),
+            parent: OptionalCell::empty(),
+            rloc16: OptionalCell::empty(),
+            frame_counter: Cell::new(0),
+            crypt: Cell::new(Crypt::Idle),
+            tx_buffer: TakeCell::new(tx_buffer),
+            tx_dest: Cell::new(IPAddr::new()),
+            tx_len: Cell::new(0),
+            rx_buffer: TakeCell::new(rx_buffer),
+            received: OptionalCell::empty(),
+            hash_buffer: MapCell::new(LeasableMutableBuffer::new(hash_buffer)),
+            digest_buf: TakeCell::new(digest_buf),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn ThreadClient) {
+        self.client.set(client);
+    }
+
+    pub fn state(&self) -> State {
+        self.state.get()
+    }
+
+    /// The short address assigned by the parent, while attached.
+    pub fn rloc16(&self) -> Option<u16> {
+        self.rloc16.extract()
+    }
+
+    /// The parent, while attached.
+    pub fn parent(&self) -> Option<Parent> {
+        self.parent.extract()
+    }
+
+    /// The mesh-local address of the device derived from its short address
+    /// (RLOC), while attached.
+    pub fn rloc_address(&self) -> Option<IPAddr> {
+        let params = self.params.extract()?;
+        let rloc16 = self.rloc16.extract()?;
+        let mut addr = IPAddr::new();
+        addr.0[..8].copy_from_slice(&params.mesh_local_prefix);
+        addr.0[11..14].copy_from_slice(&[0xff, 0xfe, 0]);
+        addr.0[14..].copy_from_slice(&rloc16.to_be_bytes());
+        Some(addr)
+    }
+
+    /// Set the key sequence of the network, while detached.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The keys of the sequence are derived when attaching.
+    /// - `BUSY`: The device is attached or attaching.
+    pub fn set_key_sequence(&self, key_sequence: u32) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Detached {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.key_sequence.replace(key_sequence) != key_sequence {
+            self.invalidate_keys();
+        }
+        Ok(())
+    }
+
+    /// Join the network of `params`, leaving the current one if they
+    /// differ.
+    pub fn set_params(&self, params: ThreadParams) {
+        if self.params.contains(&params) {
+            return;
+        }
+        self.params.set(params);
+        self.invalidate_keys();
+        self.stop();
+        let _ = self.attach();
+    }
+
+    /// Attach to the network.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: Attaching started, `attached()` is called when it ends.
+    /// - `OFF`: No network parameters are set.
+    /// - `ALREADY`: The device is attached or attaching.
+    pub fn attach(&self) -> Result<(), ErrorCode> {
+        let params = self.params.extract().ok_or(ErrorCode::OFF)?;
+        if self.state.get() != State::Detached {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.mac.set_pan(params.pan_id);
+        self.mac.config_commit();
+        self.state.set(State::Starting);
+        self.attempts.set(0);
+        self.start();
+        Ok(())
+    }
+
+    /// Leave the network, without telling the parent.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The device is detached.
+    /// - `ALREADY`: The device is detached already.
+    pub fn detach(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Detached {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.stop();
+        Ok(())
+    }
+
+    fn stop(&self) {
+        if self.state.replace(State::Detached) == State::Detached {
+            return;
+        }
+        let _ = self.alarm.disarm();
+        self.candidate.clear();
+        self.parent.clear();
+        if self.rloc16.take().is_some() {
+            self.mac.set_address(NO_SHORT_ADDRESS);
+            self.mac.config_commit();
+        }
+    }
+
+    fn invalidate_keys(&self) {
+        self.keys.clear();
+        if self.derive.get() != Derive::Idle {
+            self.derive_stale.set(true);
+        }
+    }
+
+    /// Derive the keys if needed, then draw the challenge of the Parent
+    /// Requests, then send the first one.
+    fn start(&self) {
+        if self.state.get() != State::Starting || self.derive.get() != Derive::Idle {
+            // Starting resumes when the keys are derived
+            return;
+        }
+        let result = if self.keys.is_none() {
+            self.derive_keys()
+        } else if !self.drawing.get() {
+            self.challenge_len.set(0);
+            self.rng.get().map(|()| self.drawing.set(true))
+        } else {
+            Ok(())
+        };
+        if result.is_err() {
+            self.fail();
+        }
+    }
+
+    /// Attaching failed.
+    fn fail(&self) {
+        self.stop();
+        self.client
+            .map(|client| client.attached(Err(ErrorCode::NOACK)));
+    }
+
+    /// The link to the parent is lost.
+    fn lost(&self) {
+        self.stop();
+        self.client.map(|client| client.detached());
+        let _ = self.attach();
+    }
+
+    fn set_timer(&self, ms: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    /// Compute HMAC-SHA256(network key, key sequence + "Thread"), starting
+    /// with the hash of the inner message.
+    fn derive_keys(&self) -> Result<(), ErrorCode> {
+        let params = self.params.extract().ok_or(ErrorCode::OFF)?;
+        let input = mle::key_derivation_input(self.key_sequence.get());
+        self.derive_stale.set(false);
+        self.start_hash(Derive::Inner, &pad(&params.network_key, 0x36), &input)
+    }
+
+    fn start_hash(&self, derive: Derive, pad: &[u8], message: &[u8]) -> Result<(), ErrorCode> {
+        let mut buffer = self.hash_buffer.take().ok_or(ErrorCode::BUSY)?;
+        buffer.reset();
+        buffer[..BLOCK_LEN].copy_from_slice(pad);
+        buffer[BLOCK_LEN..BLOCK_LEN + message.len()].copy_from_slice(message);
+        buffer.slice(..BLOCK_LEN + message.len());
+
+        self.digest.clear_data();
+        if let Err(e) = self.digest.set_mode_sha256() {
+            self.hash_buffer.replace(buffer);
+            return Err(e);
+        }
+        match self.digest.add_mut_data(buffer) {
+            Ok(()) => {
+                self.derive.set(derive);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.hash_buffer.replace(buffer);
+                Err(e)
+            }
+        }
+    }
+
+    fn derive_done(&self, result: Result<(), ErrorCode>) {
+        self.derive.set(Derive::Idle);
+        if self.derive_stale.take() {
+            // Derive the keys of the new parameters
+            self.start();
+        } else if result.is_err() {
+            if self.state.get() == State::Starting {
+                self.fail();
+            }
+        } else {
+            self.start();
+        }
+    }
+
+    fn send_parent_request(&self) {
+        let attempt = self.attempts.get() + 1;
+        self.attempts.set(attempt);
+        self.candidate.clear();
+        self.state.set(State::ParentRequest);
+        let (scan, wait_ms) = if attempt <= ROUTER_ONLY_ATTEMPTS {
+            (scan_mask::ROUTERS, PARENT_RESPONSE_MS)
+        } else {
+            (
+                scan_mask::ROUTERS | scan_mask::END_DEVICES,
+                PARENT_RESPONSE_END_DEVICES_MS,
+            )
+        };
+        let challenge = self.challenge.get();
+        // Requests that could not be sent are repeated
+        let _ = self.send(ALL_ROUTERS, |buf| {
+            mle::parent_request(buf, MODE, challenge, scan)
+        });
+        self.set_timer(wait_ms);
+    }
+
+    fn send_child_id_request(&self) {
+        let candidate = match self.candidate.extract() {
+            Some(candidate) => candidate,
+            None => return,
+        };
+        self.attempts.set(self.attempts.get() + 1);
+        self.state.set(State::ChildIdRequest);
+        let frame_counter = self.frame_counter.get();
+        let _ = self.send(candidate.addr, |buf| {
+            mle::child_id_request(
+                buf,
+                MODE,
+                candidate.challenge,
+                0,
+                frame_counter,
+                CHILD_TIMEOUT_S,
+            )
+        });
+        self.set_timer(CHILD_ID_RESPONSE_MS);
+    }
+
+    fn send_child_update(&self, command: u8, response: Option<[u8; CHALLENGE_LEN]>) {
+        let (parent, rloc16) = match (self.parent.extract(), self.rloc16.extract()) {
+            (Some(parent), Some(rloc16)) => (parent, rloc16),
+            _ => return,
+        };
+        let _ = self.send(parent.addr, |buf| {
+            mle::child_update(
+                buf,
+                command,
+                MODE,
+                rloc16,
+                parent.leader_data,
+                CHILD_TIMEOUT_S,
+                response,
+            )
+        });
+    }
+
+    fn send_keep_alive(&self) {
+        self.attempts.set(self.attempts.get() + 1);
+        self.state.set(State::ChildUpdate);
+        self.send_child_update(command::CHILD_UPDATE_REQUEST, None);
+        self.set_timer(CHILD_UPDATE_RESPONSE_MS);
+    }
+
+    /// Encode a message with `encode` and secure it, then send it to `dest`
+    /// once it is encrypted.
+    fn send<F>(&self, dest: IPAddr, encode: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&mut [u8]) -> Option<usize>,
+    {
+        let keys = self.keys.extract().ok_or(ErrorCode::OFF)?;
+        if self.crypt.get() != Crypt::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let buf = self.tx_buffer.take().ok_or(ErrorCode::BUSY)?;
+
+        let ext_addr = self.mac.get_address_long();
+        let src = IPAddr::generate_from_mac(MacAddress::Long(ext_addr));
+        let frame_counter = self.frame_counter.get();
+        buf[..16].copy_from_slice(&src.0);
+        buf[16..32].copy_from_slice(&dest.0);
+        AuxHeader {
+            frame_counter,
+            key_sequence: self.key_sequence.get(),
+        }
+        .encode(&mut buf[AUX_HEADER_OFFSET..COMMAND_OFFSET]);
+        let len = match encode(&mut buf[COMMAND_OFFSET..COMMAND_OFFSET + MAX_MESSAGE_LEN]) {
+            Some(len) => len,
+            None => {
+                self.tx_buffer.replace(buf);
+                return Err(ErrorCode::SIZE);
+            }
+        };
+
+        if let Err(e) = self
+            .ccm
+            .set_key(&keys.mle)
+            .and_then(|()| self.ccm.set_nonce(&mle::nonce(&ext_addr, frame_counter)))
+        {
+            self.tx_buffer.replace(buf);
+            return Err(e);
+        }
+        self.ccm
+            .crypt(buf, 0, COMMAND_OFFSET, len, MIC_LEN, true, true)
+            .map_err(|(e, buf)| {
+                self.tx_buffer.replace(buf);
+                e
+            })?;
+        self.frame_counter.set(frame_counter.wrapping_add(1));
+        self.crypt.set(Crypt::Sealing);
+        self.tx_dest.set(dest);
+        self.tx_len.set(len);
+        Ok(())
+    }
+
+    fn sealed(&self, buf: &'static mut [u8]) {
+        buf[SUITE_OFFSET] = SECURITY_SUITE_154;
+        let mut dgram = LeasableMutableBuffer::new(buf);
+        dgram.slice(SUITE_OFFSET..COMMAND_OFFSET + self.tx_len.get() + MIC_LEN);
+        if let Err(dgram) = self
+            .sender
+            .send_to(self.tx_dest.get(), MLE_PORT, dgram, self.net_cap)
+        {
+            self.tx_buffer.replace(dgram.take());
+        }
+    }
+
+    /// Process the opened message `message` of `received`.
+    fn process(&self, received: &Received, message: &[u8]) {
+        let tlvs = MessageTlvs::decode(&message[1..]);
+        match (message[0], self.state.get()) {
+            (command::PARENT_RESPONSE, State::ParentRequest) => {
+                self.parent_response(received, &tlvs)
+            }
+            (command::CHILD_ID_RESPONSE, State::ChildIdRequest) => {
+                self.child_id_response(received, &tlvs)
+            }
+            (command::CHILD_UPDATE_RESPONSE, State::Child | State::ChildUpdate) => {
+                if self.accept_from_parent(received) {
+                    self.child_update_response(&tlvs);
+                }
+            }
+            (command::CHILD_UPDATE_REQUEST, State::Child | State::ChildUpdate) => {
+                if self.accept_from_parent(received) {
+                    self.update_leader_data(&tlvs);
+                    self.send_child_update(command::CHILD_UPDATE_RESPONSE, tlvs.challenge);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether `received` is from the parent and was not received before, in
+    /// which case its frame counter is recorded.
+    fn accept_from_parent(&self, received: &Received) -> bool {
+        let mut parent = match self.parent.extract() {
+            Some(parent) if parent.addr == received.src => parent,
+            _ => return false,
+        };
+        if received.frame_counter < parent.frame_counter {
+            return false;
+        }
+        parent.frame_counter = received.frame_counter.wrapping_add(1);
+        self.parent.set(parent);
+        true
+    }
+
+    fn parent_response(&self, received: &Received, tlvs: &MessageTlvs) {
+        if tlvs.response != Some(self.challenge.get()) {
+            return;
+        }
+        let candidate = match (
+            tlvs.source_address,
+            tlvs.challenge,
+            tlvs.leader_data,
+            tlvs.link_margin,
+            tlvs.connectivity,
+        ) {
+            (
+                Some(rloc16),
+                Some(challenge),
+                Some(leader_data),
+                Some(link_margin),
+                Some((_, link_quality_3)),
+            ) => Parent {
+                addr: received.src,
+                ext_addr: received.ext_addr,
+                rloc16,
+                challenge,
+                link_margin,
+                link_quality_3,
+                frame_counter: received.frame_counter.wrapping_add(1),
+                leader_data,
+            },
+            _ => return,
+        };
+        let better = self
+            .candidate
+            .map_or(true, |current| candidate.is_better_than(current));
+        if better {
+            self.candidate.set(candidate);
+        }
+    }
+
+    fn child_id_response(&self, received: &Received, tlvs: &MessageTlvs) {
+        let mut parent = match self.candidate.extract() {
+            Some(candidate)
+                if candidate.addr == received.src
+                    && received.frame_counter >= candidate.frame_counter =>
+            {
+                candidate
+            }
+            _ => return,
+        };
+        let (rloc16, leader_data) = match (tlvs.address16, tlvs.leader_data) {
+            (Some(rloc16), Some(leader_data)) => (rloc16, leader_data),
+            _ => return,
+        };
+        parent.rloc16 = tlvs.source_address.unwrap_or(parent.rloc16);
+        parent.leader_data = leader_data;
+        parent.frame_counter = received.frame_counter.wrapping_add(1);
+        self.candidate.clear();
+        self.parent.set(parent);
+        self.rloc16.set(rloc16);
+        self.mac.set_address(rloc16);
+        self.mac.config_commit();
+
+        self.state.set(State::Child);
+        self.attempts.set(0);
+        self.set_timer(KEEP_ALIVE_MS);
+        self.client.map(|client| client.attached(Ok(rloc16)));
+    }
+
+    fn child_update_response(&self, tlvs: &MessageTlvs) {
+        if tlvs.status.is_some() {
+            // The parent does not have the device as child anymore
+            self.lost();
+            return;
+        }
+        self.update_leader_data(tlvs);
+        if self.state.get() == State::ChildUpdate {
+            self.state.set(State::Child);
+            self.attempts.set(0);
+            self.set_timer(KEEP_ALIVE_MS);
+        }
+    }
+
+    fn update_leader_data(&self, tlvs: &MessageTlvs) {
+        if let (Some(leader_data), Some(mut parent)) = (tlvs.leader_data, self.parent.extract()) {
+            parent.leader_data = leader_data;
+            self.parent.set(parent);
+        }
+    }
+}
+
+/// The HMAC key `key` padded to a block and xored with `byte`.
+fn pad(key: &[u8; KEY_LEN], byte: u8) -> [u8; BLOCK_LEN] {
+    let mut pad = [byte; BLOCK_LEN];
+    pad.iter_mut().zip(key.iter()).for_each(|(p, k)| *p ^= k);
+    pad
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    time::AlarmClient for ThreadMtd<'a, A, C, D, R>
+{
+    fn alarm(&self) {
+        let attempts = self.attempts.get();
+        match self.state.get() {
+            State::ParentRequest if self.candidate.is_some() => {
+                self.attempts.set(0);
+                self.send_child_id_request();
+            }
+            State::ParentRequest if attempts < PARENT_REQUEST_ATTEMPTS => {
+                self.send_parent_request()
+            }
+            State::ChildIdRequest if attempts < CHILD_ID_REQUEST_ATTEMPTS => {
+                self.send_child_id_request()
+            }
+            State::ParentRequest | State::ChildIdRequest => self.fail(),
+            State::Child => self.send_keep_alive(),
+            State::ChildUpdate if attempts < CHILD_UPDATE_ATTEMPTS => self.send_keep_alive(),
+            State::ChildUpdate => self.lost(),
+            State::Detached | State::Starting => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    rng::Client for ThreadMtd<'a, A, C, D, R>
+{
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if !self.drawing.get() {
+            return rng::Continue::Done;
+        }
+        if error.is_err() {
+            self.drawing.set(false);
+            if self.state.get() == State::Starting {
+                self.fail();
+            }
+            return rng::Continue::Done;
+        }
+
+        let mut challenge = self.challenge.get();
+        let mut len = self.challenge_len.get();
+        while len < CHALLENGE_LEN {
+            match randomness.next() {
+                Some(word) => {
+                    challenge[len..len + 4].copy_from_slice(&word.to_le_bytes());
+                    len += 4;
+                }
+                None => break,
+            }
+        }
+        self.challenge.set(challenge);
+        self.challenge_len.set(len);
+        if len < CHALLENGE_LEN {
+            return rng::Continue::More;
+        }
+
+        self.drawing.set(false);
+        if self.state.get() == State::Starting {
+            self.send_parent_request();
+        }
+        rng::Continue::Done
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    digest::ClientData<32> for ThreadMtd<'a, A, C, D, R>
+{
+    fn add_data_done(&self, _result: Result<(), ErrorCode>, _data: LeasableBuffer<'static, u8>) {}
+
+    fn add_mut_data_done(
+        &self,
+        result: Result<(), ErrorCode>,
+        data: LeasableMutableBuffer<'static, u8>,
+    ) {
+        self.hash_buffer.replace(data);
+        let result = result.and_then(|()| {
+            let digest_buf = self.digest_buf.take().ok_or(ErrorCode::FAIL)?;
+            self.digest.run(digest_buf).map_err(|(e, digest_buf)| {
+                self.digest_buf.replace(digest_buf);
+                e
+            })
+        });
+        if result.is_err() {
+            self.derive_done(result);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    digest::ClientHash<32> for ThreadMtd<'a, A, C, D, R>
+{
+    fn hash_done(&self, result: Result<(), ErrorCode>, digest: &'static mut [u8; 32]) {
+        let hash = *digest;
+        self.digest_buf.replace(digest);
+        let result = result.and_then(|()| match self.derive.get() {
+            Derive::Inner => {
+                let params = self.params.extract().ok_or(ErrorCode::OFF)?;
+                self.start_hash(Derive::Outer, &pad(&params.network_key, 0x5c), &hash)
+            }
+            Derive::Outer => {
+                let mut keys = Keys {
+                    mle: [0; KEY_LEN],
+                    mac: [0; KEY_LEN],
+                };
+                keys.mle.copy_from_slice(&hash[..KEY_LEN]);
+                keys.mac.copy_from_slice(&hash[KEY_LEN..]);
+                if !self.derive_stale.get() {
+                    self.keys.set(keys);
+                }
+                self.derive_done(Ok(()));
+                Ok(())
+            }
+            Derive::Idle => Err(ErrorCode::FAIL),
+        });
+        if result.is_err() {
+            self.derive_done(result);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    digest::ClientVerify<32> for ThreadMtd<'a, A, C, D, R>
+{
+    fn verification_done(&self, _result: Result<bool, ErrorCode>, compare: &'static mut [u8; 32]) {
+        self.digest_buf.replace(compare);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    CCMClient for ThreadMtd<'a, A, C, D, R>
+{
+    fn crypt_done(&self, buf: &'static mut [u8], res: Result<(), ErrorCode>, tag_is_valid: bool) {
+        match self.crypt.replace(Crypt::Idle) {
+            Crypt::Sealing if res.is_ok() => self.sealed(buf),
+            Crypt::Sealing => {
+                self.tx_buffer.replace(buf);
+            }
+            Crypt::Opening => {
+                if let Some(received) = self.received.take() {
+                    if res.is_ok() && tag_is_valid {
+                        self.process(
+                            &received,
+                            &buf[COMMAND_OFFSET..COMMAND_OFFSET + received.len],
+                        );
+                    }
+                }
+                self.rx_buffer.replace(buf);
+            }
+            Crypt::Idle => {
+                self.rx_buffer.replace(buf);
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    UDPRecvClient for ThreadMtd<'a, A, C, D, R>
+{
+    fn receive(
+        &self,
+        src_addr: IPAddr,
+        dst_addr: IPAddr,
+        src_port: u16,
+        _dst_port: u16,
+        payload: &[u8],
+    ) {
+        const OVERHEAD: usize = 1 + AUX_HEADER_LEN + MIC_LEN;
+        if src_port != MLE_PORT
+            || payload.first() != Some(&SECURITY_SUITE_154)
+            || payload.len() <= OVERHEAD
+            || payload.len() - OVERHEAD > MAX_MESSAGE_LEN
+            || self.crypt.get() != Crypt::Idle
+        {
+            return;
+        }
+        let header = match AuxHeader::decode(&payload[1..]) {
+            Some(header) if header.key_sequence == self.key_sequence.get() => header,
+            _ => return,
+        };
+        let (keys, ext_addr) = match (self.keys.extract(), mac_from_link_local(&src_addr)) {
+            (Some(keys), Some(MacAddress::Long(ext_addr))) => (keys, ext_addr),
+            _ => return,
+        };
+        let buf = match self.rx_buffer.take() {
+            Some(buf) => buf,
+            None => return,
+        };
+
+        // The addresses are written last: the destination overwrites the
+        // security suite
+        buf[SUITE_OFFSET..SUITE_OFFSET + payload.len()].copy_from_slice(payload);
+        buf[..16].copy_from_slice(&src_addr.0);
+        buf[16..32].copy_from_slice(&dst_addr.0);
+        let len = payload.len() - OVERHEAD;
+
+        if self
+            .ccm
+            .set_key(&keys.mle)
+            .and_then(|()| {
+                self.ccm
+                    .set_nonce(&mle::nonce(&ext_addr, header.frame_counter))
+            })
+            .is_err()
+        {
+            self.rx_buffer.replace(buf);
+            return;
+        }
+        match self
+            .ccm
+            .crypt(buf, 0, COMMAND_OFFSET, len, MIC_LEN, true, false)
+        {
+            Ok(()) => {
+                self.crypt.set(Crypt::Opening);
+                self.received.set(Received {
+                    src: src_addr,
+                    ext_addr,
+                    frame_counter: header.frame_counter,
+                    len,
+                });
+            }
+            Err((_, buf)) => {
+                self.rx_buffer.replace(buf);
+            }
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    UDPSendClient for ThreadMtd<'a, A, C, D, R>
+{
+    fn send_done(&self, _result: Result<(), ErrorCode>, dgram: LeasableMutableBuffer<'static, u8>) {
+        // Lost requests are repeated when their response does not arrive
+        self.tx_buffer.replace(dgram.take());
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    ThreadParamsClient for ThreadMtd<'a, A, C, D, R>
+{
+    fn params_loaded(&self, params: Result<ThreadParams, ErrorCode>) {
+        if let Ok(params) = params {
+            self.set_params(params);
+        }
+    }
+
+    fn params_stored(&self, params: ThreadParams, result: Result<(), ErrorCode>) {
+        if result.is_ok() {
+            self.set_params(params);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    KeyProcedure for ThreadMtd<'a, A, C, D, R>
+{
+    /// The MAC key of the network, for frames secured with key identifier
+    /// mode 1 and the key index of the key sequence.
+    fn lookup_key(&self, level: SecurityLevel, key_id: KeyId) -> Option<[u8; 16]> {
+        let index = mle::key_index(self.key_sequence.get());
+        match (level, key_id) {
+            (SecurityLevel::EncMic32, KeyId::Index(i)) if i == index => {
+                self.keys.extract().map(|keys| keys.mac)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: AES128CCM<'a>, D: Digest<'a, 32> + Sha256, R: rng::Rng<'a>>
+    DeviceProcedure for ThreadMtd<'a, A, C, D, R>
+{
+    /// The extended address of the parent, the only neighbor of the device.
+    fn lookup_addr_long(&self, addr: MacAddress) -> Option<[u8; 8]> {
+        self.parent.extract().and_then(|parent| {
+            let known = match addr {
+                MacAddress::Short(short) => short == parent.rloc16,
+                MacAddress::Long(long) => long == parent.ext_addr,
+            };
+            known.then_some(parent.ext_addr)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent(link_margin: u8, link_quality_3: u8) -> Parent {
+        Parent {
+            addr: IPAddr::new(),
+            ext_addr: [0; 8],
+            rloc16: 0x0400,
+            challenge: [0; CHALLENGE_LEN],
+            link_margin,
+            link_quality_3,
+            frame_counter: 0,
+            leader_data: LeaderData::default(),
+        }
+    }
+
+    #[test]
+    fn parent_selection() {
+        // Link quality first
+        assert!(parent(25, 0).is_better_than(&parent(15, 5)));
+        // Then the neighbors of the parent
+        assert!(parent(15, 5).is_better_than(&parent(20, 4)));
+        // Then the link margin
+        assert!(parent(18, 5).is_better_than(&parent(15, 5)));
+        assert!(!parent(15, 5).is_better_than(&parent(15, 5)));
+    }
+
+    #[test]
+    fn hmac_pads() {
+        let key = [0x0f; KEY_LEN];
+        let inner = pad(&key, 0x36);
+        assert_eq!(inner[0], 0x39);
+        assert_eq!(inner[KEY_LEN], 0x36);
+        assert_eq!(pad(&key, 0x5c)[BLOCK_LEN - 1], 0x5c);
+    }
+}
@@ -7,10 +7,11 @@
 //! Implements a userspace interface for sending and receiving UDP messages.
 //! Processes use this driver to send UDP packets from a common interface
 //! and bind to UDP ports for receiving packets.
-//! Also exposes a list of interface addresses to the application (currently
-//! hard-coded).
+//! Also exposes the list of interface addresses to the application, the
+//! addresses of the board and the addresses configured at runtime.
 
 use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::ipv6::ipv6_interface::InterfaceAddresses;
 use crate::net::network_capabilities::NetworkCapability;
 use crate::net::stream::encode_u16;
 use crate::net::stream::encode_u8;
@@ -23,8 +24,8 @@ use crate::net::util::host_slice_to_u16;
 use core::cell::Cell;
 use core::convert::TryFrom;
 use core::convert::TryInto;
+use core::mem;
 use core::mem::size_of;
-use core::{cmp, mem};
 
 use kernel::capabilities::UdpDriverCapability;
 use kernel::debug;
@@ -111,7 +112,7 @@ pub struct UDPDriver<'a> {
     current_app: Cell<Option<ProcessId>>,
 
     /// List of IP Addresses of the interfaces on the device
-    interface_list: &'static [IPAddr],
+    interface_list: &'a InterfaceAddresses,
 
     /// Maximum length payload that an app can transmit via this driver
     max_tx_pyld_len: usize,
@@ -135,7 +136,7 @@ impl<'a> UDPDriver<'a> {
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
-        interface_list: &'static [IPAddr],
+        interface_list: &'a InterfaceAddresses,
         max_tx_pyld_len: usize,
         port_table: &'static UdpPortManager,
         kernel_buffer: LeasableMutableBuffer<'static, u8>,
@@ -405,12 +406,12 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
                                     if cfg.len() != arg1 * size_of::<IPAddr>() {
                                         return CommandReturn::failure(ErrorCode::INVAL);
                                     }
-                                    let n_ifaces_to_copy =
-                                        cmp::min(arg1, self.interface_list.len());
                                     let iface_size = size_of::<IPAddr>();
-                                    for i in 0..n_ifaces_to_copy {
+                                    for (i, iface) in
+                                        self.interface_list.iter().take(arg1).enumerate()
+                                    {
                                         cfg[i * iface_size..(i + 1) * iface_size]
-                                            .copy_from_slice(&self.interface_list[i].0);
+                                            .copy_from_slice(&iface.0);
                                     }
                                     // Returns total number of interfaces
                                     CommandReturn::success_u32(self.interface_list.len() as u32)
@@ -515,13 +516,7 @@ impl<'a> SyscallDriver for UDPDriver<'a> {
                                 return Ok(None);
                             }
                             // Check that requested addr is a local interface
-                            let mut requested_is_local = false;
-                            for i in 0..self.interface_list.len() {
-                                if requested_addr.addr == self.interface_list[i] {
-                                    requested_is_local = true;
-                                }
-                            }
-                            if !requested_is_local {
+                            if !self.interface_list.contains(&requested_addr.addr) {
                                 return Err(Err(ErrorCode::INVAL));
                             }
                             Ok(Some(requested_addr))
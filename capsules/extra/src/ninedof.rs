@@ -21,7 +21,24 @@
 //! ninedof.add_driver(fxos8700);
 //! hil::sensors::NineDof::set_client(fxos8700, ninedof);
 //! ```
+//!
+//! Readings are passed to the upcall as described in
+//! `capsules_core::sensor_upcall`: accelerations in milli-g, magnetic fields
+//! in milligauss and angular rates in millidegrees per second.
+//!
+//! Several sensors
+//! ---------------
+//!
+//! The readings commands take the index of a driver in `data1`. A reading is
+//! taken by the first driver at or after that index which provides it, so
+//! with 0 a board can split the readings across drivers, e.g. a gyroscope
+//! and a separate accelerometer and magnetometer, and with the index of a
+//! second accelerometer an app reads that one. Command 2 returns the number
+//! of drivers, as does the count of the description returned by command
+//! `driver_info::COMMAND_NUM` (see `capsules_core::driver_info`).
 
+use capsules_core::driver_info::{self, DriverInfo};
+use capsules_core::sensor_upcall::{self, Unit};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -109,11 +126,12 @@ impl<'a> NineDof<'a> {
             })
     }
 
-    fn call_driver(&self, command: NineDofCommand, _: usize) -> Result<(), ErrorCode> {
+    fn call_driver(&self, command: NineDofCommand, first: usize) -> Result<(), ErrorCode> {
+        let drivers = self.drivers.get(first..).unwrap_or(&[]);
         match command {
             NineDofCommand::ReadAccelerometer => {
                 let mut data = Err(ErrorCode::NODEVICE);
-                for driver in self.drivers.iter() {
+                for driver in drivers.iter() {
                     data = driver.read_accelerometer();
                     if data == Ok(()) {
                         break;
@@ -123,7 +141,7 @@ impl<'a> NineDof<'a> {
             }
             NineDofCommand::ReadMagnetometer => {
                 let mut data = Err(ErrorCode::NODEVICE);
-                for driver in self.drivers.iter() {
+                for driver in drivers.iter() {
                     data = driver.read_magnetometer();
                     if data == Ok(()) {
                         break;
@@ -133,7 +151,7 @@ impl<'a> NineDof<'a> {
             }
             NineDofCommand::ReadGyroscope => {
                 let mut data = Err(ErrorCode::NODEVICE);
-                for driver in self.drivers.iter() {
+                for driver in drivers.iter() {
                     data = driver.read_gyroscope();
                     if data == Ok(()) {
                         break;
@@ -146,6 +164,17 @@ impl<'a> NineDof<'a> {
     }
 }
 
+/// Encode the upcall arguments for the reading of `command`.
+fn encode_reading(command: NineDofCommand, x: usize, y: usize, z: usize) -> (usize, usize, usize) {
+    let unit = match command {
+        NineDofCommand::ReadAccelerometer => Unit::StandardGravity,
+        NineDofCommand::ReadMagnetometer => Unit::Gauss,
+        NineDofCommand::ReadGyroscope => Unit::DegreesPerSecond,
+        NineDofCommand::Exists => Unit::None,
+    };
+    sensor_upcall::encode(unit, -3, &[x as i32, y as i32, z as i32])
+}
+
 impl hil::sensors::NineDofClient for NineDof<'_> {
     fn callback(&self, arg1: usize, arg2: usize, arg3: usize) {
         // Notify the current application that the command finished.
@@ -158,7 +187,9 @@ impl hil::sensors::NineDofClient for NineDof<'_> {
                 app.pending_command = false;
                 finished_command = app.command;
                 finished_command_arg = app.arg1;
-                upcalls.schedule_upcall(0, (arg1, arg2, arg3)).ok();
+                upcalls
+                    .schedule_upcall(0, encode_reading(finished_command, arg1, arg2, arg3))
+                    .ok();
             });
         });
 
@@ -173,7 +204,9 @@ impl hil::sensors::NineDofClient for NineDof<'_> {
                     // Don't bother re-issuing this command, just use
                     // the existing result.
                     app.pending_command = false;
-                    upcalls.schedule_upcall(0, (arg1, arg2, arg3)).ok();
+                    upcalls
+                        .schedule_upcall(0, encode_reading(finished_command, arg1, arg2, arg3))
+                        .ok();
                     false
                 } else if app.pending_command {
                     app.pending_command = false;
@@ -200,9 +233,16 @@ impl SyscallDriver for NineDof<'_> {
     ) -> CommandReturn {
         match command_num {
             0 => CommandReturn::success(),
+
+            // Describe the driver.
+            driver_info::COMMAND_NUM => DriverInfo::new(1).count(self.drivers.len()).into(),
+
             // Single acceleration reading.
             1 => self.enqueue_command(NineDofCommand::ReadAccelerometer, arg1, processid),
 
+            // Number of drivers.
+            2 => CommandReturn::success_u32(self.drivers.len() as u32),
+
             // Single magnetometer reading.
             100 => self.enqueue_command(NineDofCommand::ReadMagnetometer, arg1, processid),
 
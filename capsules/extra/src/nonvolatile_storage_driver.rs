@@ -4,10 +4,14 @@
 
 //! This provides kernel and userspace access to nonvolatile memory.
 //!
-//! This is an initial implementation that does not provide safety for
-//! individual userland applications. Each application has full access to
-//! the entire memory space that has been provided to userland. Future revisions
-//! should update this to limit applications to only their allocated regions.
+//! By default each application has full access to the entire memory space
+//! that has been provided to userland. A board can instead split that space
+//! into regions owned by storage IDs with `set_regions()`. A process then
+//! sees the region of the `write_id` from its TBF storage permissions header
+//! as a window starting at address 0, and can select the region of another
+//! ID if its read or modify IDs include it. Reading a region requires owning
+//! it or a read ID for it, writing requires owning it or a modify ID for it.
+//! Processes without access to any region get `NOSUPPORT`.
 //!
 //! However, the kernel accessible memory does not have to be the same range
 //! as the userspace accessible address space. The kernel memory can overlap
@@ -56,11 +60,18 @@
 //!         3000,                        // The length of the kernel region.
 //!         &mut capsules::nonvolatile_storage_driver::BUFFER));
 //! hil::nonvolatile_storage::NonvolatileStorage::set_client(fm25cl, nonvolatile_storage);
+//!
+//! // Optionally, give each storage ID its own 1000 bytes.
+//! nonvolatile_storage.set_regions(&[
+//!     StorageRegion { id: 0x1, offset: 0, length: 1000 },
+//!     StorageRegion { id: 0x2, offset: 1000, length: 1000 },
+//! ]);
 //! ```
 
 use core::cell::Cell;
 use core::cmp;
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
@@ -86,6 +97,12 @@ mod rw_allow {
     pub const COUNT: u8 = 1;
 }
 
+/// Optional features reported by command `driver_info::COMMAND_NUM`.
+pub mod feature {
+    /// Processes are isolated in regions owned by storage IDs
+    pub const REGIONS: u32 = 1 << 0;
+}
+
 pub const BUF_LEN: usize = 512;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -96,6 +113,22 @@ pub enum NonvolatileCommand {
     KernelWrite,
 }
 
+/// A part of the userspace accessible memory owned by a storage ID.
+pub struct StorageRegion {
+    /// The storage ID of the owner, i.e. the `write_id` of its processes.
+    pub id: u32,
+    /// Start of the region, relative to the userspace accessible memory.
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// What a process wants to do with a region.
+#[derive(Clone, Copy, PartialEq)]
+enum RegionAccess {
+    Read,
+    Write,
+}
+
 #[derive(Clone, Copy)]
 pub enum NonvolatileUser {
     App { processid: ProcessId },
@@ -107,6 +140,9 @@ pub struct App {
     command: NonvolatileCommand,
     offset: usize,
     length: usize,
+    // Storage ID of the selected region, or `None` for the region of the
+    // process' `write_id`.
+    region: Option<u32>,
 }
 
 impl Default for App {
@@ -116,6 +152,7 @@ impl Default for App {
             command: NonvolatileCommand::UserspaceRead,
             offset: 0,
             length: 0,
+            region: None,
         }
     }
 }
@@ -140,6 +177,9 @@ pub struct NonvolatileStorage<'a> {
     userspace_start_address: usize,
     // How many bytes allocated to userspace.
     userspace_length: usize,
+    // The regions of userspace accessible memory owned by storage IDs, if
+    // processes are isolated from each other.
+    regions: OptionalCell<&'a [StorageRegion]>,
     // The first byte that is accessible from the kernel.
     kernel_start_address: usize,
     // How many bytes allocated to kernel.
@@ -182,6 +222,7 @@ impl<'a> NonvolatileStorage<'a> {
             current_user: OptionalCell::empty(),
             userspace_start_address: userspace_start_address,
             userspace_length: userspace_length,
+            regions: OptionalCell::empty(),
             kernel_start_address: kernel_start_address,
             kernel_length: kernel_length,
             kernel_client: OptionalCell::empty(),
@@ -193,6 +234,43 @@ impl<'a> NonvolatileStorage<'a> {
         }
     }
 
+    /// Isolate processes from each other by giving each storage ID its own
+    /// region of the userspace accessible memory.
+    pub fn set_regions(&self, regions: &'a [StorageRegion]) {
+        self.regions.set(regions);
+    }
+
+    /// Find the window of userspace accessible memory `processid` sees, as
+    /// its offset and length, for `region` selected by the process.
+    fn userspace_window(
+        &self,
+        processid: ProcessId,
+        region: Option<u32>,
+        access: RegionAccess,
+    ) -> Result<(usize, usize), ErrorCode> {
+        let regions = match self.regions.extract() {
+            Some(regions) => regions,
+            None => return Ok((0, self.userspace_length)),
+        };
+        let permissions = processid
+            .get_storage_permissions()
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let id = region
+            .or(permissions.get_write_id())
+            .ok_or(ErrorCode::NOSUPPORT)?;
+        let owner = permissions.get_write_id() == Some(id);
+        let allowed = match access {
+            RegionAccess::Read => owner || permissions.check_read_permission(id),
+            RegionAccess::Write => owner || permissions.check_write_permission(id),
+        };
+        regions
+            .iter()
+            .find(|region| region.id == id && allowed)
+            .filter(|region| region.offset + region.length <= self.userspace_length)
+            .map(|region| (region.offset, region.length))
+            .ok_or(ErrorCode::NOSUPPORT)
+    }
+
     // Check so see if we are doing something. If not, go ahead and do this
     // command. If so, this is queued and will be run when the pending
     // command completes.
@@ -206,14 +284,7 @@ impl<'a> NonvolatileStorage<'a> {
         // Do bounds check.
         match command {
             NonvolatileCommand::UserspaceRead | NonvolatileCommand::UserspaceWrite => {
-                // Userspace sees memory that starts at address 0 even if it
-                // is offset in the physical memory.
-                if offset >= self.userspace_length
-                    || length > self.userspace_length
-                    || offset + length > self.userspace_length
-                {
-                    return Err(ErrorCode::INVAL);
-                }
+                // Checked against the window of the process below.
             }
             NonvolatileCommand::KernelRead | NonvolatileCommand::KernelWrite => {
                 // Because the kernel uses the NonvolatileStorage interface,
@@ -235,6 +306,23 @@ impl<'a> NonvolatileStorage<'a> {
                 processid.map_or(Err(ErrorCode::FAIL), |processid| {
                     self.apps
                         .enter(processid, |app, kernel_data| {
+                            let access = match command {
+                                NonvolatileCommand::UserspaceWrite => RegionAccess::Write,
+                                _ => RegionAccess::Read,
+                            };
+                            let (window_start, window_length) =
+                                self.userspace_window(processid, app.region, access)?;
+
+                            // Userspace sees memory that starts at address 0
+                            // even if it is offset in the physical memory.
+                            if offset >= window_length
+                                || length > window_length
+                                || offset + length > window_length
+                            {
+                                return Err(ErrorCode::INVAL);
+                            }
+                            let offset = window_start + offset;
+
                             // Get the length of the correct allowed buffer.
                             let allow_buf_len = match command {
                                 NonvolatileCommand::UserspaceRead => kernel_data
@@ -545,9 +633,15 @@ impl SyscallDriver for NonvolatileStorage<'_> {
     /// ### `command_num`
     ///
     /// - `0`: Return Ok(()) if this driver is included on the platform.
-    /// - `1`: Return the number of bytes available to userspace.
+    /// - `1`: Return the number of bytes available to the process, i.e. the
+    ///   length of its selected region if processes are isolated.
     /// - `2`: Start a read from the nonvolatile storage.
     /// - `3`: Start a write to the nonvolatile_storage.
+    /// - `4`: Select the region of storage ID `offset` for the next commands.
+    /// - `5`: Select the region of the process' own `write_id`.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`, with the number of regions as its
+    ///   count.
     fn command(
         &self,
         command_num: usize,
@@ -560,9 +654,26 @@ impl SyscallDriver for NonvolatileStorage<'_> {
                 CommandReturn::success()
             }
 
+            driver_info::COMMAND_NUM /* Describe the driver */ => {
+                let regions = self.regions.map_or(0, |regions| regions.len());
+                DriverInfo::new(1)
+                    .count(regions)
+                    .feature(feature::REGIONS, self.regions.is_some())
+                    .into()
+            }
+
             1 /* How many bytes are accessible from userspace */ => {
-                // TODO: Would break on 64-bit platforms
-                CommandReturn::success_u32(self.userspace_length as u32)
+                let window = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        self.userspace_window(processid, app.region, RegionAccess::Read)
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                match window {
+                    // TODO: Would break on 64-bit platforms
+                    Ok((_, length)) => CommandReturn::success_u32(length as u32),
+                    Err(e) => CommandReturn::failure(e),
+                }
             },
 
             2 /* Issue a read command */ => {
@@ -595,6 +706,33 @@ impl SyscallDriver for NonvolatileStorage<'_> {
                 }
             }
 
+            4 /* Select the region of another storage ID */ => {
+                let id = offset as u32;
+                let res = self
+                    .apps
+                    .enter(processid, |app, _| {
+                        // Only select regions the process may read at least.
+                        self.userspace_window(processid, Some(id), RegionAccess::Read)?;
+                        app.region = Some(id);
+                        Ok(())
+                    })
+                    .unwrap_or_else(|err| Err(err.into()));
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            5 /* Select the region of the own storage ID */ => {
+                let res = self.apps.enter(processid, |app, _| {
+                    app.region = None;
+                });
+                match res {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e.into()),
+                }
+            }
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -0,0 +1,453 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! First boot provisioning over a serial link.
+//!
+//! On a device that has not been provisioned yet, this capsule listens on a
+//! UART (typically USB CDC-ACM, or a BLE serial service that implements the
+//! UART HIL) for a simple TLV protocol and stores the received configuration
+//! in the KV store. Once the host sends the lock command, a marker is written
+//! to the KV store and the capsule stays inactive on every following boot,
+//! until a factory reset removes the marker.
+//!
+//! Boards that should only enter provisioning mode when a button is held at
+//! boot check the button before calling `start()`.
+//!
+//! Protocol
+//! --------
+//!
+//! Every request is a frame of a one byte tag, a two byte little endian
+//! length, and `length` bytes of value:
+//!
+//! | Tag    | Value                          | Stored under      |
+//! |--------|--------------------------------|-------------------|
+//! | `0x01` | device name                    | `device_name`     |
+//! | `0x02` | name length, name, key         | `netkey/<name>`   |
+//! | `0x03` | app name length, name, blob    | `app/<name>`      |
+//! | `0x04` | Thread network parameters      | `thread/params`   |
+//! | `0x7f` | none                           | `provisioned`     |
+//!
+//! Existing values are replaced. Each frame is answered with two bytes: the
+//! tag and a status, which is `0` on success or the `ErrorCode` otherwise.
+//! The host must wait for the response before sending the next frame.
+//!
+//! Keys are zero padded to `KEY_LEN` bytes, the same way the KV syscall
+//! driver pads keys, so a process with read access to `storage_id` can read
+//! its `app/<name>` blob with the KV driver. The Thread network parameters
+//! are in the format of `net::thread::credentials`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let provisioning = components::provisioning::ProvisioningComponent::new(
+//!     cdc,
+//!     kv_store,
+//!     PROVISIONING_STORAGE_ID,
+//! )
+//! .finalize(components::provisioning_component_static!(
+//!     capsules_extra::tickv::TicKVStore<...>,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! provisioning.start();
+//! ```
+
+use core::cell::Cell;
+
+use crate::kv_store::KVStore;
+use crate::net::thread::credentials::{ThreadParams, KEY_THREAD_PARAMS};
+use kernel::hil::kv_system::{self, KVSystem};
+use kernel::hil::uart;
+use kernel::storage_permissions::StoragePermissions;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Length of the buffer holding the KV key.
+pub const KEY_LEN: usize = 32;
+/// Length of the frame header: tag and little endian length.
+pub const HEADER_LEN: usize = 3;
+/// Length of a response: tag and status.
+pub const RESPONSE_LEN: usize = 2;
+
+pub const TAG_DEVICE_NAME: u8 = 0x01;
+pub const TAG_NETWORK_KEY: u8 = 0x02;
+pub const TAG_APP_CONFIG: u8 = 0x03;
+pub const TAG_THREAD_PARAMS: u8 = 0x04;
+pub const TAG_LOCK: u8 = 0x7f;
+
+const KEY_DEVICE_NAME: &[u8] = b"device_name";
+const KEY_NETWORK_KEY_PREFIX: &[u8] = b"netkey/";
+const KEY_APP_CONFIG_PREFIX: &[u8] = b"app/";
+/// Key of the marker written when provisioning is locked.
+pub const KEY_PROVISIONED: &[u8] = b"provisioned";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Reading the provisioned marker at start.
+    CheckingLock,
+    ReceivingHeader,
+    ReceivingValue {
+        tag: u8,
+        len: usize,
+    },
+    /// Dropping the value of a frame that can not be handled, then answering
+    /// with `result`.
+    Discarding {
+        tag: u8,
+        remaining: usize,
+        result: Result<(), ErrorCode>,
+    },
+    /// Removing the old value before writing the new one.
+    Deleting {
+        tag: u8,
+        len: usize,
+    },
+    Writing {
+        tag: u8,
+    },
+    Responding {
+        lock: bool,
+    },
+    Locked,
+}
+
+pub struct Provisioning<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> {
+    uart: &'a dyn uart::UartData<'a>,
+    kv: &'a KVStore<'a, K, T>,
+    perms: StoragePermissions,
+    state: Cell<State>,
+    header_buffer: TakeCell<'static, [u8]>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    response_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> Provisioning<'a, K, T> {
+    pub fn new(
+        uart: &'a dyn uart::UartData<'a>,
+        kv: &'a KVStore<'a, K, T>,
+        perms: StoragePermissions,
+        header_buffer: &'static mut [u8; HEADER_LEN],
+        key_buffer: &'static mut [u8; KEY_LEN],
+        value_buffer: &'static mut [u8],
+        response_buffer: &'static mut [u8; RESPONSE_LEN],
+    ) -> Self {
+        Provisioning {
+            uart,
+            kv,
+            perms,
+            state: Cell::new(State::Idle),
+            header_buffer: TakeCell::new(header_buffer),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            response_buffer: TakeCell::new(response_buffer),
+        }
+    }
+
+    /// Enter provisioning mode, unless the device has already been
+    /// provisioned and locked.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::ALREADY);
+        }
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let value = match self.value_buffer.take() {
+            Some(value) => value,
+            None => {
+                self.key_buffer.replace(key);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+        set_key(key, &[], KEY_PROVISIONED);
+        self.state.set(State::CheckingLock);
+        self.kv
+            .get(key, value, self.perms)
+            .map_err(|(key, value, e)| {
+                self.key_buffer.replace(key);
+                self.value_buffer.replace(value);
+                self.state.set(State::Idle);
+                e.err().unwrap_or(ErrorCode::FAIL)
+            })
+    }
+
+    /// Whether the device is provisioned and this capsule is inactive.
+    pub fn is_locked(&self) -> bool {
+        self.state.get() == State::Locked
+    }
+
+    fn listen(&self) {
+        self.state.set(State::ReceivingHeader);
+        self.header_buffer.take().map(|buffer| {
+            if let Err((_, buffer)) = self.uart.receive_buffer(buffer, HEADER_LEN) {
+                self.header_buffer.replace(buffer);
+                self.state.set(State::Idle);
+            }
+        });
+    }
+
+    /// Receive `len` bytes of the value of the current frame.
+    fn receive_value(&self, tag: u8, len: usize) {
+        self.value_buffer.take().map(|buffer| {
+            if let Err((e, buffer)) = self.uart.receive_buffer(buffer, len) {
+                self.value_buffer.replace(buffer);
+                self.respond(tag, Err(e), false);
+            }
+        });
+    }
+
+    fn header_received(&self, header: &[u8]) {
+        let tag = header[0];
+        let len = u16::from_le_bytes([header[1], header[2]]) as usize;
+        let capacity = self.value_buffer.map_or(0, |buffer| buffer.len());
+
+        let result = match tag {
+            TAG_DEVICE_NAME | TAG_NETWORK_KEY | TAG_APP_CONFIG | TAG_THREAD_PARAMS if len == 0 => {
+                Err(ErrorCode::INVAL)
+            }
+            TAG_DEVICE_NAME | TAG_NETWORK_KEY | TAG_APP_CONFIG | TAG_THREAD_PARAMS | TAG_LOCK
+                if len > capacity =>
+            {
+                Err(ErrorCode::SIZE)
+            }
+            TAG_DEVICE_NAME | TAG_NETWORK_KEY | TAG_APP_CONFIG | TAG_THREAD_PARAMS | TAG_LOCK => {
+                Ok(())
+            }
+            _ => Err(ErrorCode::NOSUPPORT),
+        };
+
+        match result {
+            Ok(()) if len == 0 => self.store(tag, 0),
+            Ok(()) => {
+                self.state.set(State::ReceivingValue { tag, len });
+                self.receive_value(tag, len);
+            }
+            Err(_) if len == 0 => self.respond(tag, result, false),
+            Err(_) => {
+                self.state.set(State::Discarding {
+                    tag,
+                    remaining: len,
+                    result,
+                });
+                self.receive_value(tag, core::cmp::min(len, capacity));
+            }
+        }
+    }
+
+    /// Build the key for the frame in the value buffer, then replace the
+    /// stored value.
+    fn store(&self, tag: u8, len: usize) {
+        let result = self.key_buffer.map_or(Err(ErrorCode::NOMEM), |key| {
+            self.value_buffer
+                .map_or(Err(ErrorCode::NOMEM), |value| match tag {
+                    TAG_DEVICE_NAME => {
+                        set_key(key, &[], KEY_DEVICE_NAME);
+                        Ok(len)
+                    }
+                    TAG_THREAD_PARAMS => {
+                        if ThreadParams::decode(&value[..len]).is_none() {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        set_key(key, &[], KEY_THREAD_PARAMS);
+                        Ok(len)
+                    }
+                    TAG_NETWORK_KEY | TAG_APP_CONFIG => {
+                        let prefix = if tag == TAG_NETWORK_KEY {
+                            KEY_NETWORK_KEY_PREFIX
+                        } else {
+                            KEY_APP_CONFIG_PREFIX
+                        };
+                        let name_len = value[0] as usize;
+                        if name_len == 0 || 1 + name_len > len {
+                            return Err(ErrorCode::INVAL);
+                        }
+                        if prefix.len() + name_len > KEY_LEN {
+                            return Err(ErrorCode::SIZE);
+                        }
+                        set_key(key, prefix, &value[1..1 + name_len]);
+                        value.copy_within(1 + name_len..len, 0);
+                        Ok(len - 1 - name_len)
+                    }
+                    _ => {
+                        set_key(key, &[], KEY_PROVISIONED);
+                        value[0] = 1;
+                        Ok(1)
+                    }
+                })
+        });
+
+        match result {
+            Ok(len) => {
+                self.state.set(State::Deleting { tag, len });
+                self.key_buffer.take().map(|key| {
+                    if let Err((key, e)) = self.kv.delete(key, self.perms) {
+                        self.key_buffer.replace(key);
+                        self.respond(tag, e, false);
+                    }
+                });
+            }
+            Err(e) => self.respond(tag, Err(e), false),
+        }
+    }
+
+    fn write(&self, tag: u8, len: usize) {
+        self.state.set(State::Writing { tag });
+        self.key_buffer.take().map(|key| {
+            self.value_buffer.take().map(|value| {
+                if let Err((key, value, e)) = self.kv.set(key, value, len, self.perms) {
+                    self.key_buffer.replace(key);
+                    self.value_buffer.replace(value);
+                    self.respond(tag, e, false);
+                }
+            });
+        });
+    }
+
+    /// Send the response for `tag`. If `lock` is set, the capsule stops
+    /// listening once the response is sent.
+    fn respond(&self, tag: u8, result: Result<(), ErrorCode>, lock: bool) {
+        self.state.set(State::Responding { lock });
+        self.response_buffer.take().map(|buffer| {
+            buffer[0] = tag;
+            buffer[1] = match result {
+                Ok(()) => 0,
+                Err(e) => usize::from(e) as u8,
+            };
+            if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, RESPONSE_LEN) {
+                self.response_buffer.replace(buffer);
+                self.responded(lock);
+            }
+        });
+    }
+
+    fn responded(&self, lock: bool) {
+        if lock {
+            self.state.set(State::Locked);
+        } else {
+            self.listen();
+        }
+    }
+}
+
+/// Zero `key` and write `prefix` followed by `name` to its start. The caller
+/// checks that both fit.
+fn set_key(key: &mut [u8], prefix: &[u8], name: &[u8]) {
+    key.fill(0);
+    key[..prefix.len()].copy_from_slice(prefix);
+    key[prefix.len()..prefix.len() + name.len()].copy_from_slice(name);
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> uart::ReceiveClient
+    for Provisioning<'a, K, T>
+{
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        match self.state.get() {
+            State::ReceivingHeader => {
+                if rval.is_ok() && rx_len == HEADER_LEN {
+                    let header = [buffer[0], buffer[1], buffer[2]];
+                    self.header_buffer.replace(buffer);
+                    self.header_received(&header);
+                } else {
+                    self.header_buffer.replace(buffer);
+                    self.listen();
+                }
+            }
+            State::ReceivingValue { tag, len } => {
+                self.value_buffer.replace(buffer);
+                match rval {
+                    Ok(()) if rx_len == len => self.store(tag, len),
+                    Ok(()) => self.respond(tag, Err(ErrorCode::SIZE), false),
+                    Err(e) => self.respond(tag, Err(e), false),
+                }
+            }
+            State::Discarding {
+                tag,
+                remaining,
+                result,
+            } => {
+                let capacity = buffer.len();
+                self.value_buffer.replace(buffer);
+                let remaining = remaining.saturating_sub(rx_len);
+                if rval.is_err() || remaining == 0 {
+                    self.respond(tag, result, false);
+                } else {
+                    self.state.set(State::Discarding {
+                        tag,
+                        remaining,
+                        result,
+                    });
+                    self.receive_value(tag, core::cmp::min(remaining, capacity));
+                }
+            }
+            _ => {
+                // Only the header buffer is used outside of a frame.
+                self.header_buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> uart::TransmitClient
+    for Provisioning<'a, K, T>
+{
+    fn transmitted_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.response_buffer.replace(buffer);
+        if let State::Responding { lock } = self.state.get() {
+            self.responded(lock);
+        }
+    }
+}
+
+impl<'a, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> kv_system::StoreClient<T>
+    for Provisioning<'a, K, T>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() == State::CheckingLock {
+            if result.is_ok() {
+                self.state.set(State::Locked);
+            } else {
+                self.listen();
+            }
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if let State::Writing { tag } = self.state.get() {
+            self.respond(tag, result, tag == TAG_LOCK && result.is_ok());
+        }
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: &'static mut [u8]) {
+        self.key_buffer.replace(key);
+        // Deleting fails if there is no old value, which is fine.
+        if let State::Deleting { tag, len } = self.state.get() {
+            self.write(tag, len);
+        }
+    }
+}
@@ -26,6 +26,8 @@
 //! * `0`: check whether the driver exist
 //! * `1`: read proximity
 //! * `2`: read proximity on interrupt
+//! * `driver_info::COMMAND_NUM`: describe the driver as in
+//!   `capsules_core::driver_info`
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
@@ -54,6 +56,7 @@
 
 use core::cell::Cell;
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -306,6 +309,9 @@ impl SyscallDriver for ProximitySensor<'_> {
             // check whether the driver exist!!
             0 => CommandReturn::success(),
 
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1).into(),
+
             // Instantaneous proximity measurement
             1 => self.enqueue_command(ProximityCommand::ReadProximity, arg1, arg2, processid),
 
@@ -0,0 +1,549 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Counts pulses of utility meters while the core sleeps.
+//!
+//! Water, gas and electricity meters often have an output that pulses once
+//! for every litre or watt-hour. This capsule keeps a 64-bit total for up to
+//! [`MAX_CHANNELS`] such outputs, each counted by a `hil::counter::EdgeCounter`.
+//! Counters based on hardware timers (e.g. `nrf52::edge_counter`) count the
+//! pulses without waking the core. `GpioEdgeCounter` from the frequency
+//! counter capsule works on any chip, but wakes the core for every pulse.
+//!
+//! An alarm wakes the core every `poll_ms` milliseconds to add the pulses the
+//! hardware counted to the totals. The totals are kept in a
+//! [`RetainedCounts`] record, which the board places in the `.noinit`
+//! section so it survives a reset, and every `sync_polls` polls they are
+//! written to the KV store under the key `pulse/counts`, so they also survive
+//! a power loss. `start()` takes the totals from the retained record if it is
+//! valid, and otherwise from the KV store. The pulses counted since the last
+//! poll are lost when the board resets, and the pulses counted since the last
+//! sync are lost when the power fails.
+//!
+//! The stored value is [`VALUE_LEN`] bytes: a format version of 1, the number
+//! of channels, and the total of each channel as 64-bit little endian
+//! integer.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let pulse_counter = components::pulse_counter::PulseCounterComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pulse_counter::DRIVER_NUM,
+//!     mux_alarm,
+//!     kv_store,
+//!     0x8000_0001,
+//!     &PULSE_COUNTERS,
+//!     10_000,
+//!     360,
+//! )
+//! .finalize(components::pulse_counter_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_extra::tickv::TicKVStore<...>,
+//!     capsules_extra::tickv::TicKVKeyType,
+//! ));
+//! let _ = pulse_counter.start();
+//! ```
+
+use core::cell::Cell;
+
+use crate::kv_store::KVStore;
+use kernel::debug;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::counter::EdgeCounter;
+use kernel::hil::kv_system::{self, KVSystem};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::storage_permissions::StoragePermissions;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::TakeCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::PulseCounter as usize;
+
+/// Key the totals are stored under.
+pub const KEY_PULSE_COUNTS: &[u8] = b"pulse/counts";
+/// Largest number of counted channels.
+pub const MAX_CHANNELS: usize = 4;
+/// Length of the stored totals.
+pub const VALUE_LEN: usize = 2 + 8 * MAX_CHANNELS;
+
+const FORMAT_VERSION: u8 = 1;
+const MAGIC: u32 = 0x5055_4c53;
+
+/// Totals that survive a reset, placed in the `.noinit` section by the
+/// board. The contents are only used if the checksum matches, so after a
+/// cold boot the record is ignored.
+#[repr(C)]
+pub struct RetainedCounts {
+    magic: u32,
+    channels: u32,
+    counts: [u64; MAX_CHANNELS],
+    checksum: u32,
+}
+
+impl RetainedCounts {
+    pub const fn new() -> Self {
+        RetainedCounts {
+            magic: 0,
+            channels: 0,
+            counts: [0; MAX_CHANNELS],
+            checksum: 0,
+        }
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        self.counts
+            .iter()
+            .flat_map(|count| count.to_le_bytes())
+            .fold(self.magic ^ self.channels, |sum, byte| {
+                sum.rotate_left(5) ^ (byte as u32)
+            })
+    }
+
+    fn is_valid(&self, channels: usize) -> bool {
+        self.magic == MAGIC
+            && self.channels as usize == channels
+            && self.checksum == self.compute_checksum()
+    }
+
+    fn set(&mut self, channels: usize, counts: &[u64; MAX_CHANNELS]) {
+        self.magic = MAGIC;
+        self.channels = channels as u32;
+        self.counts = *counts;
+        self.checksum = self.compute_checksum();
+    }
+}
+
+impl Default for RetainedCounts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn encode(counts: &[u64; MAX_CHANNELS], channels: usize, buf: &mut [u8]) {
+    buf[0] = FORMAT_VERSION;
+    buf[1] = channels as u8;
+    for (chunk, count) in buf[2..VALUE_LEN].chunks_mut(8).zip(counts) {
+        chunk.copy_from_slice(&count.to_le_bytes());
+    }
+}
+
+/// Returns `None` if `buf` does not hold the totals of `channels` channels.
+fn decode(buf: &[u8], channels: usize) -> Option<[u64; MAX_CHANNELS]> {
+    if buf.len() < VALUE_LEN || buf[0] != FORMAT_VERSION || buf[1] as usize != channels {
+        return None;
+    }
+    let mut counts = [0; MAX_CHANNELS];
+    for (count, chunk) in counts.iter_mut().zip(buf[2..VALUE_LEN].chunks(8)) {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(chunk);
+        *count = u64::from_le_bytes(bytes);
+    }
+    Some(counts)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// `start()` was not called yet.
+    Stopped,
+    /// Reading the totals from the KV store.
+    Loading,
+    Idle,
+    /// Removing the old totals before writing the new ones.
+    Deleting,
+    Writing,
+}
+
+#[derive(Default)]
+pub struct App {
+    /// Totals at which the process is notified, 0 if it is not.
+    thresholds: [u64; MAX_CHANNELS],
+}
+
+pub struct PulseCounter<
+    'a,
+    A: time::Alarm<'a>,
+    K: KVSystem<'a, K = T>,
+    T: 'static + kv_system::KeyType,
+> {
+    counters: &'a [&'a dyn EdgeCounter],
+    alarm: &'a A,
+    kv: &'a KVStore<'a, K, T>,
+    perms: StoragePermissions,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    retained: TakeCell<'static, RetainedCounts>,
+    poll_ms: u32,
+    sync_polls: u32,
+    state: Cell<State>,
+    /// Polls since the totals were last written to the KV store.
+    polls: Cell<u32>,
+    /// Hardware count of each channel at the last poll.
+    last_hw: [Cell<u32>; MAX_CHANNELS],
+    /// Totals in the KV store, and being written to it.
+    synced: Cell<[u64; MAX_CHANNELS]>,
+    writing: Cell<[u64; MAX_CHANNELS]>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, A: time::Alarm<'a>, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
+    PulseCounter<'a, A, K, T>
+{
+    /// `counters` may hold at most `MAX_CHANNELS` counters, the others are
+    /// ignored.
+    pub fn new(
+        counters: &'a [&'a dyn EdgeCounter],
+        alarm: &'a A,
+        kv: &'a KVStore<'a, K, T>,
+        perms: StoragePermissions,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+        retained: &'static mut RetainedCounts,
+        poll_ms: u32,
+        sync_polls: u32,
+        key_buffer: &'static mut [u8],
+        value_buffer: &'static mut [u8],
+    ) -> Self {
+        let channels = core::cmp::min(counters.len(), MAX_CHANNELS);
+        PulseCounter {
+            counters: &counters[..channels],
+            alarm,
+            kv,
+            perms,
+            apps: grant,
+            retained: TakeCell::new(retained),
+            poll_ms,
+            sync_polls: core::cmp::max(sync_polls, 1),
+            state: Cell::new(State::Stopped),
+            polls: Cell::new(0),
+            last_hw: Default::default(),
+            synced: Cell::new([0; MAX_CHANNELS]),
+            writing: Cell::new([0; MAX_CHANNELS]),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Start counting, restoring the totals from the retained record or the
+    /// KV store.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Stopped {
+            return Err(ErrorCode::ALREADY);
+        }
+        for (counter, last_hw) in self.counters.iter().zip(self.last_hw.iter()) {
+            counter.start()?;
+            last_hw.set(0);
+        }
+
+        let channels = self.channels();
+        let retained = self.retained.map_or(None, |retained| {
+            retained.is_valid(channels).then_some(retained.counts)
+        });
+        if let Some(counts) = retained {
+            // After a reset, the KV store holds at most the retained totals.
+            self.synced.set([0; MAX_CHANNELS]);
+            self.started(counts);
+            return Ok(());
+        }
+
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let value = match self.value_buffer.take() {
+            Some(value) => value,
+            None => {
+                self.key_buffer.replace(key);
+                return Err(ErrorCode::NOMEM);
+            }
+        };
+        set_key(key);
+        self.state.set(State::Loading);
+        self.kv
+            .get(key, value, self.perms)
+            .map_err(|(key, value, e)| {
+                self.key_buffer.replace(key);
+                self.value_buffer.replace(value);
+                e.err().unwrap_or(ErrorCode::FAIL)
+            })
+            .or_else(|e| {
+                debug!("Loading the pulse counts failed: {:?}", e);
+                self.started([0; MAX_CHANNELS]);
+                Ok(())
+            })
+    }
+
+    fn started(&self, counts: [u64; MAX_CHANNELS]) {
+        let channels = self.channels();
+        self.retained
+            .map(|retained| retained.set(channels, &counts));
+        self.state.set(State::Idle);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.poll_ms));
+    }
+
+    /// The totals of all channels, including the pulses counted since the
+    /// last poll.
+    pub fn counts(&self) -> [u64; MAX_CHANNELS] {
+        if self.state.get() == State::Stopped || self.state.get() == State::Loading {
+            return [0; MAX_CHANNELS];
+        }
+        let channels = self.channels();
+        self.retained.map_or([0; MAX_CHANNELS], |retained| {
+            for (i, counter) in self.counters.iter().enumerate() {
+                let hw = counter.count();
+                let pulses = hw.wrapping_sub(self.last_hw[i].get());
+                self.last_hw[i].set(hw);
+                retained.counts[i] += pulses as u64;
+            }
+            let counts = retained.counts;
+            retained.set(channels, &counts);
+            counts
+        })
+    }
+
+    /// Write the totals to the KV store, unless they did not change since
+    /// the last time.
+    pub fn sync(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let counts = self.counts();
+        self.polls.set(0);
+        if counts == self.synced.get() {
+            return Err(ErrorCode::ALREADY);
+        }
+        let key = self.key_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        set_key(key);
+        self.writing.set(counts);
+        self.state.set(State::Deleting);
+        self.kv.delete(key, self.perms).map_err(|(key, e)| {
+            self.key_buffer.replace(key);
+            self.state.set(State::Idle);
+            e.err().unwrap_or(ErrorCode::FAIL)
+        })
+    }
+
+    fn write(&self) {
+        let result = self.key_buffer.take().map_or(Err(ErrorCode::NOMEM), |key| {
+            match self.value_buffer.take() {
+                Some(value) if value.len() >= VALUE_LEN => {
+                    encode(&self.writing.get(), self.channels(), value);
+                    self.kv
+                        .set(key, value, VALUE_LEN, self.perms)
+                        .map_err(|(key, value, e)| {
+                            self.key_buffer.replace(key);
+                            self.value_buffer.replace(value);
+                            e.err().unwrap_or(ErrorCode::FAIL)
+                        })
+                }
+                Some(value) => {
+                    self.key_buffer.replace(key);
+                    self.value_buffer.replace(value);
+                    Err(ErrorCode::SIZE)
+                }
+                None => {
+                    self.key_buffer.replace(key);
+                    Err(ErrorCode::NOMEM)
+                }
+            }
+        });
+        match result {
+            Ok(()) => self.state.set(State::Writing),
+            Err(e) => self.written(Err(e)),
+        }
+    }
+
+    fn written(&self, result: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        match result {
+            Ok(()) => self.synced.set(self.writing.get()),
+            Err(e) => debug!("Storing the pulse counts failed: {:?}", e),
+        }
+    }
+
+    fn notify(&self, counts: &[u64; MAX_CHANNELS]) {
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, upcalls| {
+                for (channel, threshold) in app.thresholds.iter_mut().enumerate() {
+                    if *threshold != 0 && counts[channel] >= *threshold {
+                        *threshold = 0;
+                        let count = counts[channel];
+                        upcalls
+                            .schedule_upcall(
+                                0,
+                                (channel, count as u32 as usize, (count >> 32) as usize),
+                            )
+                            .ok();
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Zero `key` and write the key of the totals to its start.
+fn set_key(key: &mut [u8]) {
+    key.fill(0);
+    key[..KEY_PULSE_COUNTS.len()].copy_from_slice(KEY_PULSE_COUNTS);
+}
+
+impl<'a, A: time::Alarm<'a>, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
+    time::AlarmClient for PulseCounter<'a, A, K, T>
+{
+    fn alarm(&self) {
+        let counts = self.counts();
+        self.notify(&counts);
+
+        self.polls.set(self.polls.get() + 1);
+        if self.polls.get() >= self.sync_polls {
+            let _ = self.sync();
+        }
+
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.poll_ms));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType>
+    kv_system::StoreClient<T> for PulseCounter<'a, A, K, T>
+{
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        let counts = result.and_then(|()| decode(value, self.channels()).ok_or(ErrorCode::FAIL));
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() != State::Loading {
+            return;
+        }
+        // Without stored totals, e.g. on the first boot, count from 0.
+        let counts = counts.unwrap_or([0; MAX_CHANNELS]);
+        self.synced.set(counts);
+        self.started(counts);
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: &'static mut [u8],
+        value: &'static mut [u8],
+    ) {
+        self.key_buffer.replace(key);
+        self.value_buffer.replace(value);
+        if self.state.get() == State::Writing {
+            self.written(result);
+        }
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: &'static mut [u8]) {
+        self.key_buffer.replace(key);
+        // Deleting fails if there is no old value, which is fine.
+        if self.state.get() == State::Deleting {
+            self.write();
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, K: KVSystem<'a, K = T>, T: 'static + kv_system::KeyType> SyscallDriver
+    for PulseCounter<'a, A, K, T>
+{
+    // Setup callbacks.
+    //
+    // ### `subscribe_num`
+    //
+    // - `0`: Called when the total of a channel reaches the value set with
+    //   command 3, with the channel and the lower and upper 32 bits of the
+    //   total. The totals are compared at every poll, so the upcall may come
+    //   up to one poll interval late.
+
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Return the number of channels.
+    /// - `2`: Return the total of channel `data1` as 64-bit value.
+    /// - `3`: Notify the process once channel `data1` counted `data2` more
+    ///   pulses. If `data2` is 0, a pending notification is cancelled.
+    /// - `4`: Write the totals to the KV store now. Returns `ALREADY` if they
+    ///   did not change since the last time and `BUSY` if they are being
+    ///   written already.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.channels() as u32),
+
+            2 => {
+                if data1 >= self.channels() {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                CommandReturn::success_u64(self.counts()[data1])
+            }
+
+            3 => {
+                if data1 >= self.channels() {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                let threshold = if data2 == 0 {
+                    0
+                } else {
+                    self.counts()[data1] + data2 as u64
+                };
+                self.apps
+                    .enter(processid, |app, _| {
+                        app.thresholds[data1] = threshold;
+                        CommandReturn::success()
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+            }
+
+            4 => self.sync().into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_round_trip() {
+        let counts = [1, u32::MAX as u64 + 7, 0, u64::MAX];
+        let mut buf = [0; VALUE_LEN];
+        encode(&counts, 4, &mut buf);
+        assert_eq!(decode(&buf, 4), Some(counts));
+        assert_eq!(decode(&buf, 3), None);
+    }
+
+    #[test]
+    fn retained_checksum() {
+        let mut retained = RetainedCounts::new();
+        assert!(!retained.is_valid(2));
+        retained.set(2, &[5, 9, 0, 0]);
+        assert!(retained.is_valid(2));
+        assert!(!retained.is_valid(3));
+        retained.counts[1] += 1;
+        assert!(!retained.is_valid(2));
+    }
+}
@@ -0,0 +1,186 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace access to a hardware timed pulse generator.
+//!
+//! This can be used to trigger external equipment with microsecond accuracy.
+//! The pulses are generated by the hardware (see `hil::pulse`), so their
+//! timing does not depend on the kernel.
+//!
+//! One process at a time can use the pulse generator. It is released once the
+//! requested pulses were generated or stopped.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let pulse_generator = static_init!(
+//!     capsules_extra::pulse_generator::PulseGeneratorDriver<
+//!         'static,
+//!         nrf52::pulse_generator::PulseGenerator<'static>,
+//!     >,
+//!     capsules_extra::pulse_generator::PulseGeneratorDriver::new(
+//!         generator,
+//!         board_kernel.create_grant(
+//!             capsules_extra::pulse_generator::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! generator.set_client(pulse_generator);
+//! kernel::deferred_call::DeferredCallClient::register(generator);
+//! ```
+//!
+//! Or with the component:
+//!
+//! ```rust
+//! let pulse_generator = components::pulse_generator::PulseGeneratorComponent::new(
+//!     board_kernel,
+//!     capsules_extra::pulse_generator::DRIVER_NUM,
+//!     &base_peripherals.tim3,
+//! )
+//! .finalize(components::pulse_generator_component_static!(
+//!     stm32f429zi::tim3::Tim3<'static>
+//! ));
+//! ```
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::pulse;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::PulseGenerator as usize;
+
+#[derive(Default)]
+pub struct App {
+    /// Period used by the next pattern, in microseconds.
+    period_us: u32,
+}
+
+pub struct PulseGeneratorDriver<'a, P: pulse::PulseGenerator<'a>> {
+    generator: &'a P,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// Process currently using the pulse generator.
+    owner: OptionalCell<ProcessId>,
+}
+
+impl<'a, P: pulse::PulseGenerator<'a>> PulseGeneratorDriver<'a, P> {
+    pub fn new(
+        generator: &'a P,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Self {
+        PulseGeneratorDriver {
+            generator,
+            apps: grant,
+            owner: OptionalCell::empty(),
+        }
+    }
+
+    /// Start pulses for `processid` if the generator is free.
+    fn start(
+        &self,
+        width_us: u32,
+        period_us: u32,
+        count: usize,
+        processid: ProcessId,
+    ) -> Result<(), ErrorCode> {
+        if let Some(owner) = self.owner.extract() {
+            if self.apps.enter(owner, |_, _| ()).is_ok() {
+                return Err(ErrorCode::RESERVE);
+            }
+            // The owner no longer exists, stop its pulses. The generator is
+            // free once they are stopped.
+            if self.generator.stop().is_ok() {
+                return Err(ErrorCode::BUSY);
+            }
+            self.owner.clear();
+        }
+        self.generator.start(width_us, period_us, count)?;
+        self.owner.set(processid);
+        Ok(())
+    }
+}
+
+impl<'a, P: pulse::PulseGenerator<'a>> pulse::PulseClient for PulseGeneratorDriver<'a, P> {
+    fn pulses_done(&self, result: Result<(), ErrorCode>) {
+        self.owner.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_app, upcalls| {
+                upcalls
+                    .schedule_upcall(0, (kernel::errorcode::into_statuscode(result), 0, 0))
+                    .ok();
+            });
+        });
+    }
+}
+
+impl<'a, P: pulse::PulseGenerator<'a>> SyscallDriver for PulseGeneratorDriver<'a, P> {
+    // Setup callbacks.
+    //
+    // ### `subscribe_num`
+    //
+    // - `0`: Called once the pulses are done or stopped, with the status as
+    //   the first argument.
+
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Generate a single pulse that is `data1` microseconds long.
+    /// - `2`: Set the period of the next pattern to `data1` microseconds.
+    /// - `3`: Start a pattern of pulses `data1` microseconds long one every
+    ///   period, `data2` times. If `data2` is 0, the pattern repeats until it
+    ///   is stopped.
+    /// - `4`: Stop the pulses.
+    /// - `5`: Return the longest supported period in microseconds.
+    ///
+    /// Commands 1, 3, and 4 return `RESERVE` if another process is using the
+    /// pulse generator.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => self.start(data1 as u32, 0, 1, processid).into(),
+
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.period_us = data1 as u32;
+                    CommandReturn::success()
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+
+            3 => {
+                let period_us = self
+                    .apps
+                    .enter(processid, |app, _| app.period_us)
+                    .unwrap_or(0);
+                self.start(data1 as u32, period_us, data2, processid).into()
+            }
+
+            4 => match self.owner.extract() {
+                Some(owner) if owner == processid => self.generator.stop().into(),
+                Some(_) => CommandReturn::failure(ErrorCode::RESERVE),
+                None => CommandReturn::failure(ErrorCode::OFF),
+            },
+
+            5 => CommandReturn::success_u32(self.generator.max_period_us()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,421 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Audio output through a PWM pin, for chips without a DAC.
+//!
+//! The pin runs at a carrier frequency far above the audio band, and an
+//! alarm updates its duty cycle at the sample rate. An RC low-pass filter on
+//! the pin, with its corner between the audio band and the carrier, averages
+//! the carrier away and leaves the waveform, e.g. 1 kΩ and 10 nF (16 kHz) for
+//! a 125 kHz carrier. The duty cycle resolution is what the PWM offers at the
+//! carrier frequency, and the sample rate is limited by the alarm.
+//!
+//! The capsule plays samples as an `AudioSink`, and tones as a `Buzzer`,
+//! with one of the `Waveform`s. Square tones drive the pin at the frequency
+//! of the tone, with no duty updates.
+//!
+//! Without the filter, e.g. with a piezo buzzer on the pin, the pin can only
+//! make square waves: every tone is square, whatever the waveform, and
+//! `play()` returns `NOSUPPORT`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let pwm_audio = static_init!(
+//!     capsules_extra::pwm_audio::PwmAudio<'static, VirtualMuxAlarm<'static, Rtc>, PwmPinUser<'static, Pwm>>,
+//!     capsules_extra::pwm_audio::PwmAudio::new(pwm_pin, alarm, 125_000, Filter::RcLowPass)
+//! );
+//! alarm.set_alarm_client(pwm_audio);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::audio::{AudioSink, AudioSinkClient};
+use kernel::hil::buzzer::{Buzzer, BuzzerClient};
+use kernel::hil::pwm::PwmPin;
+use kernel::hil::time::{Alarm, AlarmClient, Frequency, Ticks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::time_conversion::{self, Rounding};
+use kernel::ErrorCode;
+
+/// Sample rate until `set_sample_rate()` is called.
+pub const DEFAULT_SAMPLE_RATE_HZ: u32 = 8000;
+
+/// First quarter of a sine period, in 64 steps.
+const QUARTER_SINE: [i16; 65] = [
+    0, 804, 1608, 2410, 3212, 4011, 4808, 5602, 6393, 7179, 7962, 8739, 9512, 10278, 11039, 11793,
+    12539, 13279, 14010, 14732, 15446, 16151, 16846, 17530, 18204, 18868, 19519, 20159, 20787,
+    21403, 22005, 22594, 23170, 23731, 24279, 24811, 25329, 25832, 26319, 26790, 27245, 27683,
+    28105, 28510, 28898, 29268, 29621, 29956, 30273, 30571, 30852, 31113, 31356, 31580, 31785,
+    31971, 32137, 32285, 32412, 32521, 32609, 32678, 32728, 32757, 32767,
+];
+
+/// What follows the PWM pin.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Filter {
+    /// An RC low-pass filter, which turns duty cycles into levels.
+    RcLowPass,
+    /// Nothing, the pin drives a buzzer or a speaker directly.
+    None,
+}
+
+/// Shape of the tones played as a `Buzzer`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Sine,
+    Square,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    /// The level at `phase`, where a period spans all `u32` values.
+    fn sample(self, phase: u32) -> i16 {
+        match self {
+            Waveform::Sine => {
+                let step = ((phase >> 24) & 0x3f) as usize;
+                match phase >> 30 {
+                    0 => QUARTER_SINE[step],
+                    1 => QUARTER_SINE[64 - step],
+                    2 => -QUARTER_SINE[step],
+                    _ => -QUARTER_SINE[64 - step],
+                }
+            }
+            Waveform::Square => {
+                if phase < 1 << 31 {
+                    i16::MAX
+                } else {
+                    -i16::MAX
+                }
+            }
+            Waveform::Triangle => {
+                // Rise from the minimum to the maximum over the first half of
+                // the period, and fall back over the second.
+                let level = (phase >> 15) as i32;
+                let level = if level < 1 << 16 {
+                    level
+                } else {
+                    (1 << 17) - 1 - level
+                };
+                (level - (1 << 15)) as i16
+            }
+            Waveform::Sawtooth => ((phase >> 16) as i32 - (1 << 15)) as i16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Playing the samples of a buffer.
+    Samples,
+    /// Playing a tone sample by sample.
+    Tone,
+    /// Playing a square tone at the frequency of the pin.
+    SquareTone,
+}
+
+pub struct PwmAudio<'a, A: Alarm<'a>, P: PwmPin> {
+    pwm_pin: &'a P,
+    alarm: &'a A,
+    carrier_hz: usize,
+    filter: Filter,
+    sample_rate: Cell<u32>,
+    waveform: Cell<Waveform>,
+    state: Cell<State>,
+    /// Time at which the last sample was output.
+    reference: Cell<A::Ticks>,
+    /// Index of the next sample, and number of samples to play.
+    index: Cell<usize>,
+    len: Cell<usize>,
+    /// Phase of the tone, and its increment per sample.
+    phase: Cell<u32>,
+    phase_step: Cell<u32>,
+    stopped: Cell<bool>,
+    buffer: TakeCell<'static, [i16]>,
+    audio_client: OptionalCell<&'a dyn AudioSinkClient>,
+    buzzer_client: OptionalCell<&'a dyn BuzzerClient>,
+}
+
+impl<'a, A: Alarm<'a>, P: PwmPin> PwmAudio<'a, A, P> {
+    pub fn new(pwm_pin: &'a P, alarm: &'a A, carrier_hz: usize, filter: Filter) -> Self {
+        PwmAudio {
+            pwm_pin,
+            alarm,
+            carrier_hz: carrier_hz.min(pwm_pin.get_maximum_frequency_hz()),
+            filter,
+            sample_rate: Cell::new(DEFAULT_SAMPLE_RATE_HZ),
+            waveform: Cell::new(Waveform::Sine),
+            state: Cell::new(State::Idle),
+            reference: Cell::new(A::Ticks::from(0)),
+            index: Cell::new(0),
+            len: Cell::new(0),
+            phase: Cell::new(0),
+            phase_step: Cell::new(0),
+            stopped: Cell::new(false),
+            buffer: TakeCell::empty(),
+            audio_client: OptionalCell::empty(),
+            buzzer_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Set the waveform of the next tones.
+    pub fn set_waveform(&self, waveform: Waveform) {
+        self.waveform.set(waveform);
+    }
+
+    /// Number of alarm ticks from the first sample to sample `index`.
+    fn offset(&self, index: usize) -> u64 {
+        time_conversion::scale(
+            index as u64,
+            A::Frequency::frequency(),
+            self.sample_rate.get(),
+            Rounding::Nearest,
+        )
+        .unwrap_or(u64::MAX)
+    }
+
+    fn output(&self, level: i16) -> Result<(), ErrorCode> {
+        let max = self.pwm_pin.get_maximum_duty_cycle();
+        let duty = ((level as i32 + (1 << 15)) as u64 * max as u64) >> 16;
+        self.pwm_pin.start(self.carrier_hz, duty as usize)
+    }
+
+    /// Output the first sample now, and the others at the sample rate.
+    fn start_samples(&self, state: State, len: usize) -> Result<(), ErrorCode> {
+        self.state.set(state);
+        self.index.set(0);
+        self.len.set(len);
+        self.stopped.set(false);
+        self.reference.set(self.alarm.now());
+        match self.output_sample() {
+            Ok(()) => {
+                self.schedule_sample();
+                Ok(())
+            }
+            Err(error) => {
+                self.state.set(State::Idle);
+                Err(error)
+            }
+        }
+    }
+
+    fn next_sample(&self) {
+        if self.index.get() >= self.len.get() {
+            self.finish(Ok(()));
+            return;
+        }
+        match self.output_sample() {
+            Ok(()) => self.schedule_sample(),
+            Err(error) => self.finish(Err(error)),
+        }
+    }
+
+    fn output_sample(&self) -> Result<(), ErrorCode> {
+        let index = self.index.get();
+        let level = match self.state.get() {
+            State::Samples => self.buffer.map_or(0, |buffer| buffer[index]),
+            _ => {
+                let phase = self.phase.get();
+                self.phase.set(phase.wrapping_add(self.phase_step.get()));
+                self.waveform.get().sample(phase)
+            }
+        };
+        self.output(level)?;
+        self.index.set(index + 1);
+        Ok(())
+    }
+
+    fn schedule_sample(&self) {
+        // Derive every sample time from the first one, so the rounding of the
+        // sample period does not add up.
+        let index = self.index.get();
+        let step = self.offset(index) - self.offset(index - 1);
+        let reference = self.reference.get();
+        let dt = A::Ticks::from(step as u32);
+        self.alarm.set_alarm(reference, dt);
+        self.reference.set(reference.wrapping_add(dt));
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        let state = self.state.replace(State::Idle);
+        let _ = self.pwm_pin.stop();
+        match state {
+            State::Idle => {}
+            State::Samples => {
+                let result = if self.stopped.get() {
+                    Err(ErrorCode::CANCEL)
+                } else {
+                    result
+                };
+                if let Some(buffer) = self.buffer.take() {
+                    self.audio_client
+                        .map(|client| client.play_done(buffer, result));
+                }
+            }
+            State::Tone | State::SquareTone => {
+                self.buzzer_client.map(|client| client.buzzer_done(result));
+            }
+        }
+    }
+
+    /// Stop at the next alarm, which is now.
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Idle {
+            return Err(ErrorCode::OFF);
+        }
+        self.stopped.set(true);
+        self.len.set(0);
+        let _ = self.alarm.disarm();
+        self.alarm.set_alarm(self.alarm.now(), A::Ticks::from(0));
+        Ok(())
+    }
+}
+
+impl<'a, A: Alarm<'a>, P: PwmPin> AudioSink<'a> for PwmAudio<'a, A, P> {
+    fn set_client(&self, client: &'a dyn AudioSinkClient) {
+        self.audio_client.set(client);
+    }
+
+    fn set_sample_rate(&self, rate_hz: u32) -> Result<(), ErrorCode> {
+        // Each sample needs at least two alarm ticks and two periods of the
+        // carrier.
+        if rate_hz == 0
+            || rate_hz > A::Frequency::frequency() / 2
+            || rate_hz as usize > self.carrier_hz / 2
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.sample_rate.set(rate_hz);
+        Ok(())
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        self.sample_rate.get()
+    }
+
+    fn play(
+        &self,
+        buffer: &'static mut [i16],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [i16])> {
+        if self.filter == Filter::None {
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+        if len == 0 || len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        self.buffer.replace(buffer);
+        match self.start_samples(State::Samples, len) {
+            Ok(()) => Ok(()),
+            Err(error) => Err((error, self.buffer.take().unwrap_or(&mut []))),
+        }
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Samples {
+            return Err(ErrorCode::OFF);
+        }
+        self.cancel()
+    }
+}
+
+impl<'a, A: Alarm<'a>, P: PwmPin> Buzzer<'a> for PwmAudio<'a, A, P> {
+    fn set_client(&self, client: &'a dyn BuzzerClient) {
+        self.buzzer_client.set(client);
+    }
+
+    fn buzz(&self, frequency_hz: usize, duration_ms: usize) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Samples {
+            return Err(ErrorCode::BUSY);
+        }
+        if frequency_hz == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        let duration_ms = u32::try_from(duration_ms).unwrap_or(u32::MAX);
+
+        if self.filter == Filter::None || self.waveform.get() == Waveform::Square {
+            self.pwm_pin
+                .start(frequency_hz, self.pwm_pin.get_maximum_duty_cycle() / 2)?;
+            self.state.set(State::SquareTone);
+            self.stopped.set(false);
+            let _ = self.alarm.disarm();
+            self.alarm.set_alarm(
+                self.alarm.now(),
+                time_conversion::ticks_from_ms::<A::Frequency, A::Ticks>(
+                    duration_ms,
+                    Rounding::Nearest,
+                )
+                .unwrap_or(A::Ticks::max_value()),
+            );
+            return Ok(());
+        }
+
+        // Tones above half the sample rate would alias.
+        let rate = self.sample_rate.get();
+        if frequency_hz as u64 * 2 > rate as u64 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.phase.set(0);
+        self.phase_step
+            .set((((frequency_hz as u64) << 32) / rate as u64) as u32);
+        let len = time_conversion::scale(duration_ms as u64, rate, 1000, Rounding::Nearest)
+            .unwrap_or(u64::MAX)
+            .max(1);
+        let _ = self.alarm.disarm();
+        self.start_samples(State::Tone, usize::try_from(len).unwrap_or(usize::MAX))
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::Samples {
+            return Err(ErrorCode::OFF);
+        }
+        self.cancel()
+    }
+}
+
+impl<'a, A: Alarm<'a>, P: PwmPin> AlarmClient for PwmAudio<'a, A, P> {
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Idle => {}
+            State::SquareTone => self.finish(Ok(())),
+            State::Samples | State::Tone => self.next_sample(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_follows_quadrants() {
+        assert_eq!(Waveform::Sine.sample(0), 0);
+        assert_eq!(Waveform::Sine.sample(1 << 30), i16::MAX);
+        assert_eq!(Waveform::Sine.sample(1 << 31), 0);
+        assert_eq!(Waveform::Sine.sample(3 << 30), -i16::MAX);
+        assert_eq!(
+            Waveform::Sine.sample(1 << 29),
+            -Waveform::Sine.sample(5 << 29)
+        );
+    }
+
+    #[test]
+    fn other_waveforms_span_full_scale() {
+        assert_eq!(Waveform::Square.sample(0), i16::MAX);
+        assert_eq!(Waveform::Square.sample(u32::MAX), -i16::MAX);
+        assert_eq!(Waveform::Triangle.sample(0), i16::MIN);
+        assert_eq!(Waveform::Triangle.sample(1 << 31), i16::MAX);
+        assert_eq!(Waveform::Triangle.sample(u32::MAX), i16::MIN);
+        assert_eq!(Waveform::Sawtooth.sample(0), i16::MIN);
+        assert_eq!(Waveform::Sawtooth.sample(u32::MAX), i16::MAX);
+    }
+}
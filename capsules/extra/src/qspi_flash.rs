@@ -0,0 +1,270 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Pages of an external NOR flash behind a quad-SPI controller.
+//!
+//! `QspiFlashPages` implements `hil::flash::Flash` over `hil::qspi`, with
+//! pages the size of the erase sectors of the flash (4 KiB). This connects
+//! external flash to the storage stack that works on pages:
+//! `nonvolatile_to_pages` for the nonvolatile storage driver, the
+//! wear-leveling translation layer, TicKV and the app flash driver.
+//!
+//! Indirect reads and writes go through a sector buffer of the capsule,
+//! which is word aligned as controllers moving data by DMA may require.
+//!
+//! If the controller can map the flash into memory (`QspiMemoryMapped`)
+//! and the mapping is enabled, pages are read by copying them from the
+//! mapping, which is much faster than an indirect read. The copy completes
+//! through a deferred call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let qspi_flash = components::qspi_flash::QspiFlashPagesComponent::new(
+//!     &nrf52840_peripherals.qspi,
+//!     Some(&nrf52840_peripherals.qspi),
+//! )
+//! .finalize(components::qspi_flash_pages_component_static!(
+//!     nrf52840::qspi::Qspi<'static>
+//! ));
+//!
+//! let nonvolatile_storage = components::nonvolatile_storage::NonvolatileStorageComponent::new(
+//!     board_kernel,
+//!     capsules_extra::nonvolatile_storage_driver::DRIVER_NUM,
+//!     qspi_flash,
+//!     0x60000, // Start address for userspace accessible region
+//!     0x20000, // Length of userspace accessible region
+//!     0,       // Start address of kernel region
+//!     0,       // Length of kernel region
+//! )
+//! .finalize(components::nonvolatile_storage_component_static!(
+//!     capsules_extra::qspi_flash::QspiFlashPages<'static, nrf52840::qspi::Qspi<'static>>
+//! ));
+//! ```
+
+use core::cell::Cell;
+use core::ops::{Index, IndexMut};
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::hil::qspi::{QspiFlash, QspiMemoryMapped, SECTOR_SIZE};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// A page of the flash, one erase sector. It is word aligned, so that one
+/// can provide the sector buffer of `QspiFlashPages`.
+#[repr(C, align(4))]
+pub struct QspiFlashSector(pub [u8; SECTOR_SIZE]);
+
+impl QspiFlashSector {
+    pub const fn new() -> Self {
+        Self([0; SECTOR_SIZE])
+    }
+}
+
+impl Default for QspiFlashSector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Index<usize> for QspiFlashSector {
+    type Output = u8;
+
+    fn index(&self, idx: usize) -> &u8 {
+        &self.0[idx]
+    }
+}
+
+impl IndexMut<usize> for QspiFlashSector {
+    fn index_mut(&mut self, idx: usize) -> &mut u8 {
+        &mut self.0[idx]
+    }
+}
+
+impl AsMut<[u8]> for QspiFlashSector {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+pub struct QspiFlashPages<'a, Q: QspiFlash<'a>> {
+    qspi: &'a Q,
+    memory_mapped: Option<&'a dyn QspiMemoryMapped>,
+    client: OptionalCell<&'a dyn hil::flash::Client<QspiFlashPages<'a, Q>>>,
+    deferred_call: DeferredCall,
+    /// Sector buffer for indirect reads and writes.
+    buffer: TakeCell<'static, [u8]>,
+    /// Page of the client while it is read or written.
+    page: TakeCell<'static, QspiFlashSector>,
+    /// Whether a page read from the mapping was copied completely.
+    read_ok: Cell<bool>,
+}
+
+impl<'a, Q: QspiFlash<'a>> QspiFlashPages<'a, Q> {
+    /// `buffer` must be `SECTOR_SIZE` bytes long, and meet the alignment
+    /// requirements of the controller.
+    pub fn new(
+        qspi: &'a Q,
+        memory_mapped: Option<&'a dyn QspiMemoryMapped>,
+        buffer: &'static mut [u8],
+    ) -> QspiFlashPages<'a, Q> {
+        QspiFlashPages {
+            qspi,
+            memory_mapped,
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+            buffer: TakeCell::new(buffer),
+            page: TakeCell::empty(),
+            read_ok: Cell::new(false),
+        }
+    }
+
+    /// Number of pages of the flash.
+    pub fn pages(&self) -> usize {
+        self.qspi.size() / SECTOR_SIZE
+    }
+}
+
+impl<'a, Q: QspiFlash<'a>, C: hil::flash::Client<Self>> hil::flash::HasClient<'a, C>
+    for QspiFlashPages<'a, Q>
+{
+    fn set_client(&self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, Q: QspiFlash<'a>> hil::flash::Flash for QspiFlashPages<'a, Q> {
+    type Page = QspiFlashSector;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if page_number >= self.pages() {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        let address = page_number * SECTOR_SIZE;
+
+        if self.page.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+
+        let mapping = self.memory_mapped.and_then(|mapped| mapped.memory_mapped());
+        if let Some(mapping) = mapping {
+            let sector = mapping.get(address..address + SECTOR_SIZE);
+            if let Some(sector) = sector {
+                buf.0.copy_from_slice(sector);
+            }
+            self.read_ok.set(sector.is_some());
+            self.page.replace(buf);
+            self.deferred_call.set();
+            return Ok(());
+        }
+
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return Err((ErrorCode::BUSY, buf)),
+        };
+        match self.qspi.read(address, buffer, SECTOR_SIZE) {
+            Ok(()) => {
+                self.page.replace(buf);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                Err((e, buf))
+            }
+        }
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if page_number >= self.pages() {
+            return Err((ErrorCode::INVAL, buf));
+        }
+        if self.page.is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return Err((ErrorCode::BUSY, buf)),
+        };
+        buffer.copy_from_slice(&buf.0);
+        match self
+            .qspi
+            .write(page_number * SECTOR_SIZE, buffer, SECTOR_SIZE)
+        {
+            Ok(()) => {
+                self.page.replace(buf);
+                Ok(())
+            }
+            Err((e, buffer)) => {
+                self.buffer.replace(buffer);
+                Err((e, buf))
+            }
+        }
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        if page_number >= self.pages() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.qspi.erase_sector(page_number * SECTOR_SIZE)
+    }
+}
+
+fn flash_error(result: Result<(), ErrorCode>) -> hil::flash::Error {
+    match result {
+        Ok(()) => hil::flash::Error::CommandComplete,
+        Err(_) => hil::flash::Error::FlashError,
+    }
+}
+
+impl<'a, Q: QspiFlash<'a>> hil::qspi::QspiFlashClient for QspiFlashPages<'a, Q> {
+    fn read_done(&self, buffer: &'static mut [u8], _len: usize, result: Result<(), ErrorCode>) {
+        self.page.take().map(|page| {
+            page.0.copy_from_slice(buffer);
+            self.client
+                .map(move |client| client.read_complete(page, flash_error(result)));
+        });
+        self.buffer.replace(buffer);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], _len: usize, result: Result<(), ErrorCode>) {
+        self.buffer.replace(buffer);
+        self.page.take().map(|page| {
+            self.client
+                .map(move |client| client.write_complete(page, flash_error(result)));
+        });
+    }
+
+    fn erase_done(&self, result: Result<(), ErrorCode>) {
+        self.client
+            .map(|client| client.erase_complete(flash_error(result)));
+    }
+}
+
+impl<'a, Q: QspiFlash<'a>> DeferredCallClient for QspiFlashPages<'a, Q> {
+    fn handle_deferred_call(&self) {
+        self.page.take().map(|page| {
+            let error = if self.read_ok.get() {
+                hil::flash::Error::CommandComplete
+            } else {
+                hil::flash::Error::FlashError
+            };
+            self.client
+                .map(move |client| client.read_complete(page, error));
+        });
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
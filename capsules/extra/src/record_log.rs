@@ -0,0 +1,485 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Records with sequence numbers and CRCs in a circular log.
+//!
+//! `RecordLog` sits on top of a log (usually a circular
+//! `capsules_extra::log::Log`) and stores each record as one entry of the
+//! log, with a header that holds:
+//!
+//! - a sequence number, which increases by one with every record appended,
+//!   also across reboots,
+//! - a timestamp given by the writer of the record, e.g. the seconds of an
+//!   RTC, and
+//! - a CRC-32C of the header and the data, so records that were corrupted,
+//!   e.g. by a power loss while their page was written, are skipped instead
+//!   of being returned.
+//!
+//! Records are read by sequence number: `read(seq)` returns the oldest
+//! record with a sequence number of at least `seq`. A reader that keeps the
+//! sequence number of the next record it wants can therefore continue where
+//! it stopped, even after the log wrapped around and the records it did not
+//! read yet were overwritten: the sequence numbers of the records it gets
+//! then jump. `find(timestamp)` returns the sequence number of the oldest
+//! record with a timestamp of at least `timestamp`, for logs written with
+//! timestamps that do not decrease.
+//!
+//! The log is read from the start when it is mounted, to find the sequence
+//! number of the next record, and whenever a record before the read position
+//! of the log is requested. Reading records in the order they were appended
+//! continues at the read position.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let record_log = components::record_log::RecordLogComponent::new(log)
+//!     .finalize(components::record_log_component_static!(
+//!         capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!         256
+//!     ));
+//! record_log.set_client(record_log_client);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::crc::CrcAlgorithm;
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::crc::CrcState;
+use kernel::ErrorCode;
+
+/// Bytes stored in front of the data of a record.
+pub const RECORD_HEADER_LEN: usize = 12;
+
+/// A record that was read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    pub seq: u32,
+    pub timestamp: u32,
+    /// The number of bytes of data copied into the buffer.
+    pub len: usize,
+}
+
+/// Callbacks from `RecordLog`.
+pub trait RecordLogClient {
+    /// The log was mounted, `next_seq` is the sequence number the next record
+    /// gets.
+    fn mounted(&self, next_seq: Result<u32, ErrorCode>);
+
+    /// A record was appended from `buffer`, with sequence number `seq`.
+    fn appended(&self, buffer: &'static mut [u8], seq: Result<u32, ErrorCode>);
+
+    /// A record was read into `buffer`. `FAIL` means that there is no record
+    /// with the requested or a larger sequence number yet.
+    fn read_done(&self, buffer: &'static mut [u8], record: Result<Record, ErrorCode>);
+
+    /// The oldest record with the requested or a later timestamp has sequence
+    /// number `seq`. `FAIL` means that there is no such record yet.
+    fn found(&self, seq: Result<u32, ErrorCode>);
+
+    /// The records appended so far were synced to storage.
+    fn synced(&self, result: Result<(), ErrorCode>);
+
+    /// All records were erased.
+    fn erased(&self, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Reading all records for the next sequence number.
+    Mount,
+    Append,
+    /// Reading the record with the lowest sequence number of at least `seq`.
+    Read {
+        seq: u32,
+    },
+    /// Reading until a record with a timestamp of at least `timestamp`.
+    Find {
+        timestamp: u32,
+    },
+    /// Seeking back to the record found, which has sequence number `seq`.
+    Found {
+        seq: u32,
+    },
+    Sync,
+    Erase,
+}
+
+/// Checks the CRC of the record in `entry` and returns its header.
+fn parse(entry: &[u8]) -> Option<(u32, u32)> {
+    if entry.len() < RECORD_HEADER_LEN {
+        return None;
+    }
+    let word = |i: usize| u32::from_le_bytes([entry[i], entry[i + 1], entry[i + 2], entry[i + 3]]);
+    let mut crc = CrcState::new(CrcAlgorithm::Crc32C);
+    crc.update(&entry[..8]);
+    crc.update(&entry[RECORD_HEADER_LEN..]);
+    if crc.finish().value() != word(8) {
+        return None;
+    }
+    Some((word(0), word(4)))
+}
+
+pub struct RecordLog<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> {
+    log: &'a L,
+    client: OptionalCell<&'a dyn RecordLogClient>,
+    state: Cell<State>,
+    scratch: TakeCell<'static, [u8]>,
+    buffer: TakeCell<'static, [u8]>,
+    mounted: Cell<bool>,
+    /// The sequence number of the next record appended.
+    next_seq: Cell<u32>,
+    /// All records before the read position of the log have lower sequence
+    /// numbers than this, if it is known.
+    cursor: OptionalCell<u32>,
+    /// The entry of the log that is being read.
+    entry: Cell<usize>,
+    /// Records skipped because their CRC did not match.
+    corrupt: Cell<u32>,
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> RecordLog<'a, L> {
+    /// `scratch` holds a record with its header, so records can have up to
+    /// `scratch.len() - RECORD_HEADER_LEN` bytes of data.
+    pub fn new(log: &'a L, scratch: &'static mut [u8]) -> RecordLog<'a, L> {
+        RecordLog {
+            log,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            scratch: TakeCell::new(scratch),
+            buffer: TakeCell::empty(),
+            mounted: Cell::new(false),
+            next_seq: Cell::new(0),
+            cursor: OptionalCell::empty(),
+            entry: Cell::new(0),
+            corrupt: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn RecordLogClient) {
+        self.client.set(client);
+    }
+
+    /// The sequence number the next record gets.
+    pub fn next_seq(&self) -> u32 {
+        self.next_seq.get()
+    }
+
+    /// The number of records skipped because they were corrupted.
+    pub fn corrupt_records(&self) -> u32 {
+        self.corrupt.get()
+    }
+
+    /// The largest number of bytes of data in a record.
+    pub fn max_record_len(&self) -> usize {
+        self.scratch
+            .map_or(0, |scratch| scratch.len() - RECORD_HEADER_LEN)
+    }
+
+    /// Read the log to find the sequence number of the next record. Records
+    /// can only be appended and read once the log was mounted.
+    pub fn mount(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.next_seq.set(0);
+        self.rewind(State::Mount)
+    }
+
+    /// Append `length` bytes of `buffer` as a record with `timestamp`.
+    pub fn append(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+        timestamp: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_idle() {
+            return Err((e, buffer));
+        }
+        let scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => return Err((ErrorCode::NOMEM, buffer)),
+        };
+        if length > buffer.len() || length > scratch.len() - RECORD_HEADER_LEN {
+            self.scratch.replace(scratch);
+            return Err((ErrorCode::SIZE, buffer));
+        }
+
+        let seq = self.next_seq.get();
+        scratch[0..4].copy_from_slice(&seq.to_le_bytes());
+        scratch[4..8].copy_from_slice(&timestamp.to_le_bytes());
+        scratch[RECORD_HEADER_LEN..RECORD_HEADER_LEN + length].copy_from_slice(&buffer[..length]);
+        let mut crc = CrcState::new(CrcAlgorithm::Crc32C);
+        crc.update(&scratch[..8]);
+        crc.update(&scratch[RECORD_HEADER_LEN..RECORD_HEADER_LEN + length]);
+        scratch[8..12].copy_from_slice(&crc.finish().value().to_le_bytes());
+
+        match self.log.append(scratch, RECORD_HEADER_LEN + length) {
+            Ok(()) => {
+                self.state.set(State::Append);
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err((e, scratch)) => {
+                self.scratch.replace(scratch);
+                Err((e, buffer))
+            }
+        }
+    }
+
+    /// Read the oldest record with a sequence number of at least `seq` into
+    /// `buffer`. Fails with `FAIL` if it is known right away that there is no
+    /// such record.
+    pub fn read(
+        &self,
+        buffer: &'static mut [u8],
+        seq: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check_idle() {
+            return Err((e, buffer));
+        }
+        if seq >= self.next_seq.get() {
+            return Err((ErrorCode::FAIL, buffer));
+        }
+
+        let result = if self.cursor.map_or(false, |cursor| *cursor <= seq) {
+            // Every record before the read position is older, continue there.
+            match self.scratch.take() {
+                Some(scratch) => {
+                    self.state.set(State::Read { seq });
+                    self.step(scratch)
+                }
+                None => Err(ErrorCode::NOMEM),
+            }
+        } else {
+            self.rewind(State::Read { seq })
+        };
+        match result {
+            Ok(()) => {
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+            Err(e) => {
+                self.state.set(State::Idle);
+                Err((e, buffer))
+            }
+        }
+    }
+
+    /// Find the oldest record with a timestamp of at least `timestamp`.
+    pub fn find(&self, timestamp: u32) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        self.rewind(State::Find { timestamp })
+    }
+
+    /// Sync the records appended so far to storage.
+    pub fn sync(&self) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        self.log.sync()?;
+        self.state.set(State::Sync);
+        Ok(())
+    }
+
+    /// Erase all records. Sequence numbers continue after the last record.
+    pub fn erase(&self) -> Result<(), ErrorCode> {
+        self.check_idle()?;
+        self.log.erase()?;
+        self.state.set(State::Erase);
+        Ok(())
+    }
+
+    fn check_idle(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            Err(ErrorCode::BUSY)
+        } else if !self.mounted.get() {
+            Err(ErrorCode::OFF)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Starts `state` by reading the log from its start.
+    fn rewind(&self, state: State) -> Result<(), ErrorCode> {
+        self.cursor.clear();
+        self.log.seek(self.log.log_start())?;
+        self.state.set(state);
+        Ok(())
+    }
+
+    /// Reads the next entry of the log into `scratch`.
+    fn step(&self, scratch: &'static mut [u8]) -> Result<(), ErrorCode> {
+        self.entry.set(self.log.next_read_entry_id());
+        let length = scratch.len();
+        self.log.read(scratch, length).map_err(|(e, scratch)| {
+            self.scratch.replace(scratch);
+            e
+        })
+    }
+
+    /// Ends the operation in progress because reading the log stopped with
+    /// `error`, which is `FAIL` at the end of the log.
+    fn end(&self, error: ErrorCode) {
+        let state = self.state.replace(State::Idle);
+        if error != ErrorCode::FAIL {
+            self.cursor.clear();
+        }
+        match state {
+            State::Mount => {
+                if error == ErrorCode::FAIL {
+                    self.mounted.set(true);
+                    self.cursor.set(self.next_seq.get());
+                    self.client
+                        .map(|client| client.mounted(Ok(self.next_seq.get())));
+                } else {
+                    self.client.map(|client| client.mounted(Err(error)));
+                }
+            }
+            State::Read { seq } => {
+                if error == ErrorCode::FAIL {
+                    self.cursor.set(seq);
+                }
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(|client| client.read_done(buffer, Err(error)));
+                });
+            }
+            State::Find { .. } | State::Found { .. } => {
+                if error == ErrorCode::FAIL {
+                    self.cursor.set(self.next_seq.get());
+                }
+                self.client.map(|client| client.found(Err(error)));
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles the record in `scratch` read while scanning the log, and
+    /// returns whether to continue with the next one.
+    fn scanned(&self, scratch: &[u8], seq: u32, timestamp: u32) -> bool {
+        match self.state.get() {
+            State::Mount => {
+                self.next_seq
+                    .set(self.next_seq.get().max(seq.wrapping_add(1)));
+                true
+            }
+            State::Read { seq: wanted } if seq >= wanted => {
+                self.state.set(State::Idle);
+                self.cursor.set(seq.wrapping_add(1));
+                self.buffer.take().map(|buffer| {
+                    let data = &scratch[RECORD_HEADER_LEN..];
+                    let len = data.len().min(buffer.len());
+                    buffer[..len].copy_from_slice(&data[..len]);
+                    let record = Record {
+                        seq,
+                        timestamp,
+                        len,
+                    };
+                    self.client
+                        .map(|client| client.read_done(buffer, Ok(record)));
+                });
+                false
+            }
+            State::Find { timestamp: wanted } if timestamp >= wanted => {
+                // Go back to the record found, so reading it continues at the
+                // read position.
+                self.cursor.set(seq);
+                match self.log.seek(self.entry.get()) {
+                    Ok(()) => self.state.set(State::Found { seq }),
+                    Err(_) => {
+                        self.state.set(State::Idle);
+                        self.cursor.set(seq.wrapping_add(1));
+                        self.client.map(|client| client.found(Ok(seq)));
+                    }
+                }
+                false
+            }
+            _ => {
+                self.cursor.set(seq.wrapping_add(1));
+                true
+            }
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> LogReadClient for RecordLog<'a, L> {
+    fn read_done(&self, scratch: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        if let Err(e) = error {
+            self.scratch.replace(scratch);
+            self.end(e);
+            return;
+        }
+
+        let more = match parse(&scratch[..length.min(scratch.len())]) {
+            Some((seq, timestamp)) => {
+                self.scanned(&scratch[..length.min(scratch.len())], seq, timestamp)
+            }
+            None => {
+                self.corrupt.set(self.corrupt.get() + 1);
+                true
+            }
+        };
+        if more {
+            if let Err(e) = self.step(scratch) {
+                self.end(e);
+            }
+        } else {
+            self.scratch.replace(scratch);
+        }
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        if let State::Found { seq } = self.state.get() {
+            self.state.set(State::Idle);
+            if error.is_err() {
+                self.cursor.clear();
+            }
+            self.client.map(|client| client.found(Ok(seq)));
+            return;
+        }
+
+        // The log is read from its start, before which there are no records.
+        let result = error.and_then(|()| {
+            self.cursor.set(0);
+            let scratch = self.scratch.take().ok_or(ErrorCode::NOMEM)?;
+            self.step(scratch)
+        });
+        if let Err(e) = result {
+            self.end(e);
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> LogWriteClient for RecordLog<'a, L> {
+    fn append_done(
+        &self,
+        scratch: &'static mut [u8],
+        _length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.scratch.replace(scratch);
+        self.state.set(State::Idle);
+        let seq = error.map(|()| {
+            let seq = self.next_seq.get();
+            self.next_seq.set(seq.wrapping_add(1));
+            seq
+        });
+        self.buffer.take().map(|buffer| {
+            self.client.map(|client| client.appended(buffer, seq));
+        });
+    }
+
+    fn sync_done(&self, error: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.synced(error));
+    }
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.cursor.set(self.next_seq.get());
+        self.client.map(|client| client.erased(error));
+    }
+}
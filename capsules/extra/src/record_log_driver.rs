@@ -0,0 +1,408 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Provides userspace with access to the records of a `RecordLog`.
+//!
+//! Processes append records to the log and read them back by sequence
+//! number. Each process has its own read position, the sequence number of
+//! the next record it reads, which advances past every record it read. A
+//! process that drains the log, e.g. a gateway that forwards the records
+//! while it is connected, can ask to be told when new records are appended
+//! once it read all of them, and so follows the tail of the log. When it was
+//! not connected for a while, it continues where it stopped, or with the
+//! oldest record that is still in the log if the log wrapped around.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let record_log_driver = components::record_log::RecordLogDriverComponent::new(
+//!     board_kernel,
+//!     capsules_extra::record_log_driver::DRIVER_NUM,
+//!     record_log,
+//! )
+//! .finalize(components::record_log_driver_component_static!(
+//!     capsules_extra::log::Log<'static, nrf52840::nvmc::Nvmc>,
+//!     244
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! Commands that finish later return success when they were queued, and
+//! schedule upcall 0 with the status and two values when they are done.
+//! Upcall 1 tells a process that follows the log about new records.
+//!
+//! Data to append is passed in read-only allow 0. Records are read into
+//! read-write allow 0: the timestamp of the record (4 bytes, little endian)
+//! followed by its data.
+
+use core::cell::Cell;
+
+use capsules_core::driver_info::{self, DriverInfo};
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
+use kernel::hil::log::{LogRead, LogWrite};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use crate::record_log::{Record, RecordLog, RecordLogClient};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::RecordLog as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const DATA: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const RECORD: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Ids for upcalls
+mod upcalls {
+    pub const DONE: usize = 0;
+    pub const NEW_RECORDS: usize = 1;
+    /// The number of upcalls the kernel stores for this grant
+    pub const COUNT: u8 = 2;
+}
+
+/// Bytes in front of the data of a record read by a process.
+const TIMESTAMP_LEN: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Append { length: usize, timestamp: u32 },
+    Read,
+    Find { timestamp: u32 },
+    Sync,
+}
+
+#[derive(Default)]
+pub struct App {
+    pending: Option<Command>,
+    /// The sequence number of the next record to read.
+    cursor: u32,
+    /// Whether to tell the process about new records.
+    follow: bool,
+    /// The process was told about new records since it last read one.
+    notified: bool,
+}
+
+pub struct RecordLogDriver<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> {
+    log: &'a RecordLog<'a, L>,
+    apps: Grant<
+        App,
+        UpcallCount<{ upcalls::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    /// The process whose command runs.
+    current: OptionalCell<ProcessId>,
+    /// Commands wait until the log is mounted.
+    ready: Cell<bool>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> RecordLogDriver<'a, L> {
+    pub fn new(
+        log: &'a RecordLog<'a, L>,
+        grant: Grant<
+            App,
+            UpcallCount<{ upcalls::COUNT }>,
+            AllowRoCount<{ ro_allow::COUNT }>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+        buffer: &'static mut [u8],
+    ) -> RecordLogDriver<'a, L> {
+        RecordLogDriver {
+            log,
+            apps: grant,
+            current: OptionalCell::empty(),
+            ready: Cell::new(false),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    fn start(
+        &self,
+        command: Command,
+        app: &App,
+        kernel_data: &GrantKernelData,
+    ) -> Result<(), ErrorCode> {
+        match command {
+            Command::Append { length, timestamp } => {
+                let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+                let length = kernel_data
+                    .get_readonly_processbuffer(ro_allow::DATA)
+                    .and_then(|data| {
+                        data.enter(|src| {
+                            let length = length.min(src.len()).min(buffer.len());
+                            src[..length].copy_to_slice(&mut buffer[..length]);
+                            length
+                        })
+                    })
+                    .unwrap_or(0);
+                self.log
+                    .append(buffer, length, timestamp)
+                    .map_err(|(e, buffer)| {
+                        self.buffer.replace(buffer);
+                        e
+                    })
+            }
+            Command::Read => {
+                let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+                self.log.read(buffer, app.cursor).map_err(|(e, buffer)| {
+                    self.buffer.replace(buffer);
+                    e
+                })
+            }
+            Command::Find { timestamp } => self.log.find(timestamp),
+            Command::Sync => self.log.sync(),
+        }
+    }
+
+    /// Starts the next queued command.
+    fn check_queue(&self) {
+        if self.current.is_some() || !self.ready.get() {
+            return;
+        }
+
+        for cntr in self.apps.iter() {
+            let processid = cntr.processid();
+            let started = cntr.enter(|app, kernel_data| {
+                app.pending.take().map_or(false, |command| {
+                    match self.start(command, app, kernel_data) {
+                        Ok(()) => true,
+                        Err(e) => {
+                            // The command was accepted when it was queued,
+                            // so the process learns about the failure from
+                            // the upcall.
+                            kernel_data
+                                .schedule_upcall(
+                                    upcalls::DONE,
+                                    (kernel::errorcode::into_statuscode(Err(e)), 0, 0),
+                                )
+                                .ok();
+                            false
+                        }
+                    }
+                })
+            });
+            if started {
+                self.current.set(processid);
+                break;
+            }
+        }
+    }
+
+    /// Ends the command of the current process with `result` and the values
+    /// of the upcall.
+    fn done(
+        &self,
+        result: Result<(), ErrorCode>,
+        values: (usize, usize),
+        f: impl FnOnce(&mut App),
+    ) {
+        self.current.take().map(|processid| {
+            let _ = self.apps.enter(processid, |app, kernel_data| {
+                f(app);
+                kernel_data
+                    .schedule_upcall(
+                        upcalls::DONE,
+                        (
+                            kernel::errorcode::into_statuscode(result),
+                            values.0,
+                            values.1,
+                        ),
+                    )
+                    .ok();
+            });
+        });
+        self.check_queue();
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> RecordLogClient
+    for RecordLogDriver<'a, L>
+{
+    fn mounted(&self, _next_seq: Result<u32, ErrorCode>) {
+        // If mounting failed, the commands fail with the error of the log.
+        self.ready.set(true);
+        self.check_queue();
+    }
+
+    fn appended(&self, buffer: &'static mut [u8], seq: Result<u32, ErrorCode>) {
+        self.buffer.replace(buffer);
+        if let Ok(seq) = seq {
+            // Tell the processes that follow the log, and read everything
+            // before this record, once.
+            for cntr in self.apps.iter() {
+                cntr.enter(|app, kernel_data| {
+                    if app.follow && !app.notified && app.cursor <= seq {
+                        app.notified = true;
+                        kernel_data
+                            .schedule_upcall(upcalls::NEW_RECORDS, (seq as usize + 1, 0, 0))
+                            .ok();
+                    }
+                });
+            }
+        }
+        self.done(
+            seq.map(|_| ()),
+            (*seq.as_ref().unwrap_or(&0) as usize, 0),
+            |_| {},
+        );
+    }
+
+    fn read_done(&self, buffer: &'static mut [u8], record: Result<Record, ErrorCode>) {
+        if let Ok(record) = record {
+            self.current.map(|processid| {
+                let _ = self.apps.enter(*processid, |app, kernel_data| {
+                    app.cursor = record.seq.wrapping_add(1);
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::RECORD)
+                        .and_then(|dest| {
+                            dest.mut_enter(|dest| {
+                                if dest.len() >= TIMESTAMP_LEN {
+                                    dest[..TIMESTAMP_LEN]
+                                        .copy_from_slice(&record.timestamp.to_le_bytes());
+                                    let dest = &dest[TIMESTAMP_LEN..];
+                                    let len = record.len.min(dest.len());
+                                    dest[..len].copy_from_slice(&buffer[..len]);
+                                }
+                            })
+                        })
+                });
+            });
+        }
+        self.buffer.replace(buffer);
+        let values = record.map_or((0, 0), |record| (record.len, record.seq as usize));
+        self.done(record.map(|_| ()), values, |app| app.notified = false);
+    }
+
+    fn found(&self, seq: Result<u32, ErrorCode>) {
+        self.done(
+            seq.map(|_| ()),
+            (*seq.as_ref().unwrap_or(&0) as usize, 0),
+            |app| {
+                if let Ok(seq) = seq {
+                    app.cursor = seq;
+                    app.notified = false;
+                }
+            },
+        );
+    }
+
+    fn synced(&self, result: Result<(), ErrorCode>) {
+        self.done(result, (0, 0), |_| {});
+    }
+
+    fn erased(&self, result: Result<(), ErrorCode>) {
+        self.done(result, (0, 0), |_| {});
+    }
+}
+
+impl<'a, L: LogRead<'a, EntryID = usize> + LogWrite<'a>> SyscallDriver for RecordLogDriver<'a, L> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Append `data1` bytes of read-only allow 0 as a record with
+    ///   timestamp `data2`. The upcall gets the sequence number of the
+    ///   record.
+    /// - `2`: Read the next record into read-write allow 0. The upcall gets
+    ///   the length of its data and its sequence number, which may be larger
+    ///   than the one expected if records were overwritten. It reports
+    ///   `FAIL` if there is no new record.
+    /// - `3`: Continue reading at the record with sequence number `data1`.
+    ///   Returns immediately.
+    /// - `4`: Continue reading at the oldest record with a timestamp of at
+    ///   least `data1`. The upcall gets its sequence number.
+    /// - `5`: Follow the log if `data1` is 1, stop following if it is 0. A
+    ///   process that follows the log gets upcall 1, with the sequence number
+    ///   of the next record after the new one, when a record is appended
+    ///   after it read all others. It gets upcall 1 again after it read
+    ///   another record.
+    /// - `6`: Return the sequence number of the next record to read and the
+    ///   sequence number the next record appended gets.
+    /// - `7`: Sync the records appended so far to storage.
+    /// - `driver_info::COMMAND_NUM`: Describe the driver, see
+    ///   `capsules_core::driver_info`.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        let command = match command_num {
+            0 => return CommandReturn::success(),
+            driver_info::COMMAND_NUM => return DriverInfo::new(1).into(),
+            1 => Command::Append {
+                length: data1,
+                timestamp: data2 as u32,
+            },
+            2 => Command::Read,
+            3 | 5 | 6 => {
+                return self
+                    .apps
+                    .enter(processid, |app, _| match command_num {
+                        3 => {
+                            app.cursor = data1 as u32;
+                            app.notified = false;
+                            CommandReturn::success()
+                        }
+                        5 => match data1 {
+                            0 | 1 => {
+                                app.follow = data1 == 1;
+                                CommandReturn::success()
+                            }
+                            _ => CommandReturn::failure(ErrorCode::INVAL),
+                        },
+                        _ => CommandReturn::success_u32_u32(app.cursor, self.log.next_seq()),
+                    })
+                    .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+            }
+            4 => Command::Find {
+                timestamp: data1 as u32,
+            },
+            7 => Command::Sync,
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        let queued = self
+            .apps
+            .enter(processid, |app, _| {
+                if app.pending.is_some() || self.current.contains(&processid) {
+                    Err(ErrorCode::BUSY)
+                } else {
+                    app.pending = Some(command);
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+        match queued {
+            Ok(()) => {
+                self.check_queue();
+                CommandReturn::success()
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
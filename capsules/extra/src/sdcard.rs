@@ -5,6 +5,28 @@
 //! Provides driver for accessing an SD Card and a userspace Driver.
 //!
 //! This allows initialization and block reads or writes on top of SPI.
+//! Transactions of several blocks use the multiple block commands of the card
+//! (CMD18 and CMD25), which stream one block after the other instead of
+//! starting a command per block. Each block is a single SPI transfer that
+//! can be done by DMA, and clients are told as each block is done, before
+//! the whole transaction completes.
+//!
+//! The userspace driver, `SDCardDriver`, works with any `SDCardDevice`: the
+//! SPI card of this module, or a card connected to a native host controller
+//! (see `sdmmc`).
+//!
+//! With a card detect pin, the card can be inserted and removed at any time.
+//! An inserted card is initialized (mounted) automatically once it settled,
+//! and removing a card aborts the transaction in progress with an error
+//! instead of leaving it pending. Clients and processes are told when a card
+//! is mounted or unmounted. The board has to call `detect_changes()` to start
+//! watching the detect pin.
+//!
+//! Userspace upcalls carry the event as the first argument: `0` card
+//! detection changed, `1` card mounted, `2` read done, `3` write done, `4`
+//! error and `5` card unmounted. Command `5` returns whether a card is
+//! mounted. Commands `6` and `7` read and write several blocks at once, as
+//! many as fit in the kernel buffer of the driver.
 //!
 //! Usage
 //! -----
@@ -51,6 +73,7 @@
 //! sdcard_spi.set_client(sdcard);
 //! sdcard_virtual_alarm.set_alarm_client(sdcard);
 //! SD_DETECT_PIN.set_client(sdcard);
+//! sdcard.detect_changes();
 //!
 //! let sdcard_kernel_buffer = static_init!([u8; capsules::sdcard::KERNEL_BUFFER_LENGTH],
 //!                                         [0; capsules::sdcard::KERNEL_BUFFER_LENGTH]);
@@ -58,14 +81,16 @@
 //! let sdcard_driver = static_init!(
 //!     capsules::sdcard::SDCardDriver<
 //!         'static,
-//!         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52833::rtc::Rtc>>,
+//!         capsules::sdcard::SDCard<
+//!             'static,
+//!             capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52833::rtc::Rtc>>>,
 //!     capsules::sdcard::SDCardDriver::new(
 //!         sdcard,
 //!         sdcard_kernel_buffer,
 //!         board_kernel.create_grant(
 //!             capsules::sdcard::DRIVER_NUM,
 //!             &memory_allocation_capability)));
-//! sdcard.set_client(sdcard_driver);
+//! SDCardDevice::set_client(sdcard, sdcard_driver);
 //! ```
 
 // Resources for SD Card API:
@@ -77,12 +102,15 @@
 use core::cell::Cell;
 use core::cmp;
 
+use capsules_core::driver_info::{self, DriverInfo};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
+use kernel::hil::crc::CrcAlgorithm;
 use kernel::hil::time::ConvertTicks;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::crc;
 use kernel::{ErrorCode, ProcessId};
 
 /// Syscall driver number.
@@ -131,6 +159,13 @@ pub struct SDCard<'a, A: hil::time::Alarm<'a>> {
     client: OptionalCell<&'a dyn SDCardClient>,
     client_buffer: TakeCell<'static, [u8]>,
     client_offset: Cell<usize>,
+    /// Whether a block of a multiple block read failed its CRC check.
+    block_crc_error: Cell<bool>,
+    /// Whether the write in progress is a multiple block write, which is
+    /// ended by a stop token.
+    multiple_write: Cell<bool>,
+    /// Blocks transferred so far by the read or write in progress.
+    blocks_done: Cell<u32>,
 }
 
 /// SD card command codes
@@ -187,9 +222,11 @@ enum SpiState {
     ReadBlocksComplete,
 
     StartWriteBlocks { count: u32 },
-    WriteBlockResponse,
-    WriteBlockBusy,
-    WaitWriteBlockBusy,
+    WriteBlockResponse { count: u32 },
+    WriteBlockBusy { count: u32 },
+    WaitWriteBlockBusy { count: u32 },
+    StopWriteBlocks,
+    WaitStopWriteBusy,
 }
 
 /// Alarm states
@@ -206,12 +243,13 @@ enum AlarmState {
     WaitForDataBlock,
     WaitForDataBlocks { count: u32 },
 
-    WaitForWriteBusy,
+    WaitForWriteBusy { count: u32 },
+    WaitForStopWriteBusy,
 }
 
 /// Error codes returned if an SD card transaction fails
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum SdCardError {
+pub(crate) enum SdCardError {
     CardStateChanged = -10001,
     InitializationFailure = -10002,
     ReadFailure = -10003,
@@ -233,14 +271,73 @@ enum SDCardType {
 const SUCCESS_STATUS: u8 = 0x00;
 const INITIALIZING_STATUS: u8 = 0x01;
 const DATA_TOKEN: u8 = 0xFE;
+const WRITE_MULTIPLE_TOKEN: u8 = 0xFC;
+const STOP_TRAN_TOKEN: u8 = 0xFD;
+
+/// Check the CRC following a received data block. Cards send it even though
+/// CRC checking is disabled in SPI mode.
+fn block_crc_valid(read_buffer: &[u8]) -> bool {
+    read_buffer.get(..514).map_or(false, |block| {
+        let crc = crc::compute(CrcAlgorithm::Crc16Xmodem, &block[..512]);
+        crc.value() as u16 == u16::from_be_bytes([block[512], block[513]])
+    })
+}
 
 /// Callback functions from SDCard
+///
+/// With a card detect pin, a card goes through a mount lifecycle: once a card
+/// is inserted and has settled, `card_detection_changed(true)` is called and
+/// the card is initialized automatically, after which `init_done` signals that
+/// the card is mounted. When the card is removed, `unmounted` is called right
+/// away, any transaction in progress fails with an error, and
+/// `card_detection_changed(false)` follows once the pin settled.
 pub trait SDCardClient {
     fn card_detection_changed(&self, installed: bool);
     fn init_done(&self, block_size: u32, total_size: u64);
     fn read_done(&self, data: &'static mut [u8], len: usize);
     fn write_done(&self, buffer: &'static mut [u8]);
-    fn error(&self, error: u32);
+    /// A transaction failed. `buffer` is the buffer passed to `read_blocks`
+    /// or `write_blocks`, if the failed transaction was a read or write.
+    fn error(&self, error: u32, buffer: Option<&'static mut [u8]>);
+    /// The card was removed or changed and can no longer be accessed until it
+    /// is initialized again.
+    fn unmounted(&self);
+    /// A block of a read or write was transferred, `blocks` blocks of it are
+    /// done so far. Read blocks are already copied to the buffer.
+    fn block_done(&self, _blocks: u32) {}
+}
+
+/// An SD card that is read and written in blocks of 512 bytes, whether it is
+/// accessed over SPI (`SDCard`) or through a native host controller
+/// (`sdmmc::SdMmcCard`).
+pub trait SDCardDevice<'a> {
+    fn set_client(&self, client: &'a dyn SDCardClient);
+
+    fn is_installed(&self) -> bool;
+
+    /// Whether the card is initialized (mounted) and can be accessed.
+    fn is_initialized(&self) -> bool;
+
+    /// Initialize the card, `init_done` is called once it is mounted.
+    fn initialize(&self) -> Result<(), ErrorCode>;
+
+    /// Read `count` blocks starting at `sector` into `buffer`. On error the
+    /// buffer is handed back.
+    fn read_blocks(
+        &self,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write `count` blocks starting at `sector` from `buffer`. On error the
+    /// buffer is handed back.
+    fn write_blocks(
+        &self,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 }
 
 /// Functions for initializing and accessing an SD card
@@ -292,6 +389,9 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             client: OptionalCell::empty(),
             client_buffer: TakeCell::empty(),
             client_offset: Cell::new(0),
+            block_crc_error: Cell::new(false),
+            multiple_write: Cell::new(false),
+            blocks_done: Cell::new(0),
         }
     }
 
@@ -507,9 +607,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -577,9 +675,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -606,9 +702,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -673,9 +767,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -711,9 +803,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -734,9 +824,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -796,9 +884,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
-                    });
+                    self.report_error(SdCardError::InitializationFailure);
                 }
             }
 
@@ -823,9 +909,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
@@ -853,19 +937,24 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
             SpiState::ReadBlockComplete => {
+                let crc_valid = block_crc_valid(read_buffer);
+
                 // replace buffers
                 self.txbuffer.replace(write_buffer);
                 self.rxbuffer.replace(read_buffer);
 
                 // read finished, perform callback
                 self.state.set(SpiState::Idle);
+                if !crc_valid {
+                    self.report_error(SdCardError::ReadFailure);
+                    return;
+                }
+                self.block_done();
                 self.rxbuffer.map(|read_buffer| {
                     self.client_buffer.take().map(move |buffer| {
                         // copy data to user buffer
@@ -911,13 +1000,15 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
             SpiState::ReceivedBlock { count } => {
+                if !block_crc_valid(read_buffer) {
+                    self.block_crc_error.set(true);
+                }
+
                 // copy block over to client buffer
                 self.client_buffer.map(|buffer| {
                     // copy block into client buffer
@@ -937,6 +1028,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     let read_len = cmp::min(read_buffer.len(), cmp::min(buffer.len(), 512));
                     self.client_offset.set(offset + read_len);
                 });
+                self.block_done();
 
                 if count <= 1 {
                     // all blocks received. Terminate multiple read
@@ -954,7 +1046,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                 // check response
                 let (r1, _, _) = self.get_response(SDResponse::R1_Status, read_buffer);
 
-                if r1 == SUCCESS_STATUS {
+                if r1 == SUCCESS_STATUS && !self.block_crc_error.get() {
                     // replace buffers
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
@@ -973,9 +1065,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
-                    });
+                    self.report_error(SdCardError::ReadFailure);
                 }
             }
 
@@ -984,52 +1074,8 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                 let (r1, _, _) = self.get_response(SDResponse::R1_Status, read_buffer);
 
                 if r1 == SUCCESS_STATUS {
-                    if count <= 1 {
-                        let bytes_written = self.client_buffer.map_or(0, |buffer| {
-                            // copy over data from client buffer
-                            // Limit to minimum length between write_buffer,
-                            // buffer, and 512 (block size)
-                            for (write_byte, &client_byte) in
-                                write_buffer.iter_mut().skip(1).zip(buffer.iter()).take(512)
-                            {
-                                *write_byte = client_byte;
-                            }
-
-                            // calculate number of bytes written
-                            cmp::min(write_buffer.len(), cmp::min(buffer.len(), 512))
-                        });
-
-                        // set a known value for remaining bytes
-                        for write_byte in write_buffer
-                            .iter_mut()
-                            .skip(1)
-                            .skip(bytes_written)
-                            .take(512)
-                        {
-                            *write_byte = 0xFF;
-                        }
-
-                        // set up remainder of data packet
-                        write_buffer[0] = DATA_TOKEN; // Data token
-                        write_buffer[513] = 0xFF; // dummy CRC
-                        write_buffer[514] = 0xFF; // dummy CRC
-
-                        // write data packet
-                        self.state.set(SpiState::WriteBlockResponse);
-                        self.write_bytes(write_buffer, read_buffer, 515);
-                    } else {
-                        // multi-block SD card writes are unimplemented
-                        // This should have returned an error already, but if
-                        // we got here somehow, return an error and quit now
-                        self.txbuffer.replace(write_buffer);
-                        self.rxbuffer.replace(read_buffer);
-                        self.state.set(SpiState::Idle);
-                        self.alarm_state.set(AlarmState::Idle);
-                        self.alarm_count.set(0);
-                        self.client.map(move |client| {
-                            client.error(SdCardError::WriteFailure as u32);
-                        });
-                    }
+                    // write first data packet
+                    self.write_block(write_buffer, read_buffer, count);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -1037,58 +1083,93 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
-                    });
+                    self.report_error(SdCardError::WriteFailure);
                 }
             }
 
-            SpiState::WriteBlockResponse => {
+            SpiState::WriteBlockResponse { count } => {
                 // Get data packet
-                self.state.set(SpiState::WriteBlockBusy);
+                self.state.set(SpiState::WriteBlockBusy { count: count });
                 self.read_bytes(write_buffer, read_buffer, 1);
             }
 
-            SpiState::WriteBlockBusy => {
+            SpiState::WriteBlockBusy { count } => {
                 if (read_buffer[0] & 0x1F) == 0x05 {
                     // check if sd card is busy
-                    self.state.set(SpiState::WaitWriteBlockBusy);
+                    self.state
+                        .set(SpiState::WaitWriteBlockBusy { count: count });
                     self.read_bytes(write_buffer, read_buffer, 1);
                 } else {
                     // error, send callback and quit
+                    // A multiple block write is left for the card to abort,
+                    //  the next command fails if it did not
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
                     self.state.set(SpiState::Idle);
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
-                    self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
-                    });
+                    self.report_error(SdCardError::WriteFailure);
                 }
             }
 
-            SpiState::WaitWriteBlockBusy => {
+            SpiState::WaitWriteBlockBusy { count } => {
                 // check if line is still held low (busy state)
                 if read_buffer[0] != 0x00 {
-                    // replace buffers
-                    self.txbuffer.replace(write_buffer);
-                    self.rxbuffer.replace(read_buffer);
-
-                    // read finished, perform callback
-                    self.state.set(SpiState::Idle);
                     self.alarm_count.set(0);
-                    self.client_buffer.take().map(move |buffer| {
-                        self.client.map(move |client| {
-                            client.write_done(buffer);
-                        });
-                    });
+                    self.block_done();
+
+                    if count > 1 {
+                        // write next data packet
+                        self.write_block(write_buffer, read_buffer, count - 1);
+                    } else if self.multiple_write.get() {
+                        // all blocks written. Terminate multiple write, the
+                        //  card is busy again after the stop token
+                        write_buffer[0] = STOP_TRAN_TOKEN;
+                        write_buffer[1] = 0xFF;
+                        self.state.set(SpiState::StopWriteBlocks);
+                        self.write_bytes(write_buffer, read_buffer, 2);
+                    } else {
+                        // replace buffers
+                        self.txbuffer.replace(write_buffer);
+                        self.rxbuffer.replace(read_buffer);
+
+                        // write finished, perform callback
+                        self.write_complete();
+                    }
                 } else {
                     // replace buffers
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
                     // try again after 1 ms
-                    self.alarm_state.set(AlarmState::WaitForWriteBusy);
+                    self.alarm_state
+                        .set(AlarmState::WaitForWriteBusy { count: count });
+                    let delay = self.alarm.ticks_from_ms(1);
+                    self.alarm.set_alarm(self.alarm.now(), delay);
+                }
+            }
+
+            SpiState::StopWriteBlocks => {
+                // check if sd card is busy
+                self.state.set(SpiState::WaitStopWriteBusy);
+                self.read_bytes(write_buffer, read_buffer, 1);
+            }
+
+            SpiState::WaitStopWriteBusy => {
+                // replace buffers
+                self.txbuffer.replace(write_buffer);
+                self.rxbuffer.replace(read_buffer);
+
+                // check if line is still held low (busy state)
+                if self
+                    .rxbuffer
+                    .map_or(false, |read_buffer| read_buffer[0] != 0x00)
+                {
+                    self.alarm_count.set(0);
+                    self.write_complete();
+                } else {
+                    // try again after 1 ms
+                    self.alarm_state.set(AlarmState::WaitForStopWriteBusy);
                     let delay = self.alarm.ticks_from_ms(1);
                     self.alarm.set_alarm(self.alarm.now(), delay);
                 }
@@ -1104,6 +1185,87 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         }
     }
 
+    /// sends the data packet of the next block to write, `count` blocks are
+    ///     left to write including this one
+    fn write_block(
+        &self,
+        write_buffer: &'static mut [u8],
+        read_buffer: &'static mut [u8],
+        count: u32,
+    ) {
+        let offset = self.client_offset.get();
+        let bytes_written = self.client_buffer.map_or(0, |buffer| {
+            // copy over data from client buffer
+            // Limit to minimum length between write_buffer, buffer, and 512
+            // (block size)
+            for (write_byte, &client_byte) in write_buffer
+                .iter_mut()
+                .skip(1)
+                .zip(buffer.iter().skip(offset))
+                .take(512)
+            {
+                *write_byte = client_byte;
+            }
+
+            // calculate number of bytes written
+            cmp::min(
+                write_buffer.len(),
+                cmp::min(buffer.len().saturating_sub(offset), 512),
+            )
+        });
+        self.client_offset.set(offset + bytes_written);
+
+        // set a known value for remaining bytes
+        for write_byte in write_buffer
+            .iter_mut()
+            .skip(1)
+            .skip(bytes_written)
+            .take(512 - bytes_written)
+        {
+            *write_byte = 0xFF;
+        }
+
+        // set up remainder of data packet
+        write_buffer[0] = if self.multiple_write.get() {
+            WRITE_MULTIPLE_TOKEN
+        } else {
+            DATA_TOKEN
+        };
+        let crc = crc::compute(CrcAlgorithm::Crc16Xmodem, &write_buffer[1..513]);
+        write_buffer[513..515].copy_from_slice(&(crc.value() as u16).to_be_bytes());
+
+        // write data packet
+        self.state
+            .set(SpiState::WriteBlockResponse { count: count });
+        self.write_bytes(write_buffer, read_buffer, 515);
+    }
+
+    /// counts a transferred block and tells the client
+    fn block_done(&self) {
+        let blocks = self.blocks_done.get() + 1;
+        self.blocks_done.set(blocks);
+        self.client.map(|client| client.block_done(blocks));
+    }
+
+    /// ends a write, handing the buffer back to the client
+    fn write_complete(&self) {
+        self.state.set(SpiState::Idle);
+        self.client_buffer.take().map(move |buffer| {
+            self.client.map(move |client| {
+                client.write_done(buffer);
+            });
+        });
+    }
+
+    /// sends an error callback, handing back the client's buffer if a read or
+    ///     write was in progress
+    fn report_error(&self, error: SdCardError) {
+        let buffer = self.client_buffer.take();
+        self.client.map(move |client| {
+            client.error(error as u32, buffer);
+        });
+    }
+
     /// updates SD card state upon timer alarm fired
     fn process_alarm_states(&self) {
         // keep track of how many times the alarm has been called in a row
@@ -1113,24 +1275,35 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
             self.alarm_count.set(0);
-            self.client.map(move |client| {
-                client.error(SdCardError::TimeoutFailure as u32);
-            });
+            self.report_error(SdCardError::TimeoutFailure);
         } else {
             self.alarm_count.set(repeats + 1);
         }
 
         match self.alarm_state.get() {
             AlarmState::DetectionChange => {
-                // perform callback
-                self.client.map(move |client| {
-                    client.card_detection_changed(self.is_installed());
-                });
-
                 // re-enable interrupts
                 self.detect_changes();
                 self.alarm_count.set(0);
                 self.alarm_state.set(AlarmState::Idle);
+
+                // perform callback
+                let installed = self.is_installed();
+                self.client.map(move |client| {
+                    client.card_detection_changed(installed);
+                });
+
+                // mount a newly inserted card, failures are reported through
+                //  the error callback
+                if installed {
+                    if let Err(e) = self.initialize() {
+                        if e == ErrorCode::NOMEM {
+                            // the SPI transaction that was killed by the
+                            //  removal still holds the buffers
+                            self.report_error(SdCardError::InitializationFailure);
+                        }
+                    }
+                }
             }
 
             AlarmState::RepeatHCSInit => {
@@ -1218,12 +1391,25 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                 self.alarm_state.set(AlarmState::Idle);
             }
 
-            AlarmState::WaitForWriteBusy => {
+            AlarmState::WaitForWriteBusy { count } => {
                 // check card initialization again
                 self.txbuffer.take().map(|write_buffer| {
                     self.rxbuffer.take().map(move |read_buffer| {
                         // check if sd card is busy
-                        self.state.set(SpiState::WaitWriteBlockBusy);
+                        self.state
+                            .set(SpiState::WaitWriteBlockBusy { count: count });
+                        self.read_bytes(write_buffer, read_buffer, 1);
+                    });
+                });
+
+                self.alarm_state.set(AlarmState::Idle);
+            }
+
+            AlarmState::WaitForStopWriteBusy => {
+                self.txbuffer.take().map(|write_buffer| {
+                    self.rxbuffer.take().map(move |read_buffer| {
+                        // check if sd card is still busy after the stop token
+                        self.state.set(SpiState::WaitStopWriteBusy);
                         self.read_bytes(write_buffer, read_buffer, 1);
                     });
                 });
@@ -1238,11 +1424,39 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         }
     }
 
-    pub fn set_client<C: SDCardClient>(&self, client: &'static C) {
+    /// watches SD card detect pin for changes, sends callback on change
+    pub fn detect_changes(&self) {
+        self.detect_pin.get().map(|pin| {
+            pin.enable_interrupts(hil::gpio::InterruptEdge::EitherEdge);
+        });
+    }
+
+    /// checks that the card can be accessed and takes the transaction buffers
+    fn take_buffers(&self) -> Result<(&'static mut [u8], &'static mut [u8]), ErrorCode> {
+        if !self.is_installed() {
+            // sd card not installed
+            return Err(ErrorCode::UNINSTALLED);
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.txbuffer.is_none() || self.rxbuffer.is_none() {
+            return Err(ErrorCode::NOMEM);
+        }
+        match (self.txbuffer.take(), self.rxbuffer.take()) {
+            (Some(txbuffer), Some(rxbuffer)) => Ok((txbuffer, rxbuffer)),
+            _ => Err(ErrorCode::NOMEM),
+        }
+    }
+}
+
+impl<'a, A: hil::time::Alarm<'a>> SDCardDevice<'a> for SDCard<'a, A> {
+    fn set_client(&self, client: &'a dyn SDCardClient) {
         self.client.set(client);
     }
 
-    pub fn is_installed(&self) -> bool {
+    fn is_installed(&self) -> bool {
         // if there is no detect pin, assume an sd card is installed
         self.detect_pin.get().map_or(true, |pin| {
             // sd card detection pin is active low
@@ -1250,18 +1464,17 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         })
     }
 
-    pub fn is_initialized(&self) -> bool {
+    fn is_initialized(&self) -> bool {
         self.is_initialized.get()
     }
 
-    /// watches SD card detect pin for changes, sends callback on change
-    pub fn detect_changes(&self) {
-        self.detect_pin.get().map(|pin| {
-            pin.enable_interrupts(hil::gpio::InterruptEdge::EitherEdge);
-        });
-    }
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        // wait for the card to settle after it was inserted, it is then
+        //  initialized automatically
+        if self.alarm_state.get() == AlarmState::DetectionChange {
+            return Err(ErrorCode::BUSY);
+        }
 
-    pub fn initialize(&self) -> Result<(), ErrorCode> {
         // if not already, set card to uninitialized again
         self.is_initialized.set(false);
 
@@ -1287,117 +1500,85 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         }
     }
 
-    pub fn read_blocks(
+    fn read_blocks(
         &self,
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
-        // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if count == 0 {
+            return Err((ErrorCode::INVAL, buffer));
+        }
 
-                                self.state.set(SpiState::StartReadBlocks { count: count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD17_ReadSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                } else {
-                                    self.send_command(
-                                        SDCmd::CMD18_ReadMultiple,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                }
+        // only if initialized and installed
+        let (txbuffer, rxbuffer) = match self.take_buffers() {
+            Ok(buffers) => buffers,
+            Err(e) => return Err((e, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+        self.block_crc_error.set(false);
+        self.blocks_done.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                // command started successfully
-                                Ok(())
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
+        self.state.set(SpiState::StartReadBlocks { count: count });
+        if count == 1 {
+            self.send_command(SDCmd::CMD17_ReadSingle, address, txbuffer, rxbuffer, 10);
         } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            self.send_command(SDCmd::CMD18_ReadMultiple, address, txbuffer, rxbuffer, 10);
         }
+
+        // command started successfully
+        Ok(())
     }
 
-    pub fn write_blocks(
+    // Blocks past the end of `buffer` are filled with `0xFF`.
+    fn write_blocks(
         &self,
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if count == 0 {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+
         // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
+        let (txbuffer, rxbuffer) = match self.take_buffers() {
+            Ok(buffers) => buffers,
+            Err(e) => return Err((e, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+        self.multiple_write.set(count > 1);
+        self.blocks_done.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                self.state.set(SpiState::StartWriteBlocks { count: count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
+        self.state.set(SpiState::StartWriteBlocks { count: count });
+        if count == 1 {
+            self.send_command(SDCmd::CMD24_WriteSingle, address, txbuffer, rxbuffer, 10);
         } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            self.send_command(SDCmd::CMD25_WriteMultiple, address, txbuffer, rxbuffer, 10);
         }
+
+        // command started successfully
+        Ok(())
     }
 }
 
@@ -1430,16 +1611,19 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
         // check if there was an open transaction with the sd card
         if self.alarm_state.get() != AlarmState::Idle || self.state.get() != SpiState::Idle {
             // something was running when this occurred. Kill the transaction and
-            //  send an error callback
+            //  send an error callback. The SPI transaction still completes,
+            //  which returns the buffers while idle.
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
-            self.client.map(move |client| {
-                client.error(SdCardError::CardStateChanged as u32);
-            });
+            self.alarm_count.set(0);
+            self.report_error(SdCardError::CardStateChanged);
         }
 
         // either the card is new or gone, in either case it isn't initialized
-        self.is_initialized.set(false);
+        if self.is_initialized.get() {
+            self.is_initialized.set(false);
+            self.client.map(|client| client.unmounted());
+        }
 
         // disable additional interrupts
         self.detect_pin.get().map(|pin| {
@@ -1457,8 +1641,8 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
 /// This is used if the SDCard is going to be attached directly to userspace
 /// syscalls. SDCardDriver can be ignored if another capsule is going to build
 /// off of the SDCard instead
-pub struct SDCardDriver<'a, A: hil::time::Alarm<'a>> {
-    sdcard: &'a SDCard<'a, A>,
+pub struct SDCardDriver<'a, D: SDCardDevice<'a>> {
+    sdcard: &'a D,
     kernel_buf: TakeCell<'static, [u8]>,
     grants: Grant<
         App,
@@ -1473,26 +1657,28 @@ pub struct SDCardDriver<'a, A: hil::time::Alarm<'a>> {
 #[derive(Default)]
 pub struct App;
 
-/// Buffer for SD card driver, assigned in board `main.rs` files
+/// Buffer for SD card driver, assigned in board `main.rs` files. A longer
+/// buffer, of a multiple of 512 bytes, allows reading and writing more blocks
+/// with one command.
 pub const KERNEL_BUFFER_LENGTH: usize = 512;
 
 /// Functions for SDCardDriver
-impl<'a, A: hil::time::Alarm<'a>> SDCardDriver<'a, A> {
+impl<'a, D: SDCardDevice<'a>> SDCardDriver<'a, D> {
     /// Create new SD card userland interface
     ///
     /// sdcard - SDCard interface to provide application access to
     /// kernel_buf - buffer used to hold SD card blocks, must be at least 512
     ///     bytes in length
     pub fn new(
-        sdcard: &'a SDCard<'a, A>,
-        kernel_buf: &'static mut [u8; 512],
+        sdcard: &'a D,
+        kernel_buf: &'static mut [u8],
         grants: Grant<
             App,
             UpcallCount<1>,
             AllowRoCount<{ ro_allow::COUNT }>,
             AllowRwCount<{ rw_allow::COUNT }>,
         >,
-    ) -> SDCardDriver<'a, A> {
+    ) -> SDCardDriver<'a, D> {
         // return new SDCardDriver
         SDCardDriver {
             sdcard,
@@ -1501,28 +1687,98 @@ impl<'a, A: hil::time::Alarm<'a>> SDCardDriver<'a, A> {
             current_process: OptionalCell::empty(),
         }
     }
+
+    /// Checks that `count` blocks, at least one, fit in the kernel buffer
+    fn check_count(&self, kernel_buf: &[u8], count: usize) -> Result<u32, ErrorCode> {
+        if count == 0 || count > kernel_buf.len() / 512 {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(count as u32)
+        }
+    }
+
+    /// Read `count` blocks starting at `sector`
+    fn read(&self, sector: u32, count: usize) -> Result<(), ErrorCode> {
+        self.kernel_buf
+            .take()
+            .map_or(Err(ErrorCode::BUSY), |kernel_buf| {
+                let result = match self.check_count(kernel_buf, count) {
+                    Ok(count) => self.sdcard.read_blocks(kernel_buf, sector, count),
+                    Err(e) => Err((e, kernel_buf)),
+                };
+                result.map_err(|(e, kernel_buf)| {
+                    self.kernel_buf.replace(kernel_buf);
+                    e
+                })
+            })
+    }
+
+    /// Write `count` blocks starting at `sector` from the buffer of the
+    /// process
+    fn write(&self, process_id: ProcessId, sector: u32, count: usize) -> Result<(), ErrorCode> {
+        self.grants
+            .enter(process_id, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::WRITE)
+                    .and_then(|write| {
+                        write.enter(|write_buffer| {
+                            self.kernel_buf
+                                .take()
+                                .map_or(Err(ErrorCode::BUSY), |kernel_buf| {
+                                    let count = match self.check_count(kernel_buf, count) {
+                                        Ok(count) => count,
+                                        Err(e) => {
+                                            self.kernel_buf.replace(kernel_buf);
+                                            return Err(e);
+                                        }
+                                    };
+
+                                    // copy over write data from application
+                                    // Limit to minimum length between kernel_buf,
+                                    // write_buffer, and the blocks to write
+                                    for (kernel_byte, ref write_byte) in kernel_buf
+                                        .iter_mut()
+                                        .zip(write_buffer.iter())
+                                        .take(count as usize * 512)
+                                    {
+                                        *kernel_byte = write_byte.get();
+                                    }
+
+                                    // begin writing
+                                    self.sdcard.write_blocks(kernel_buf, sector, count).map_err(
+                                        |(e, kernel_buf)| {
+                                            self.kernel_buf.replace(kernel_buf);
+                                            e
+                                        },
+                                    )
+                                })
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::NOMEM))
+            })
+            .unwrap_or(Err(ErrorCode::NOMEM))
+    }
 }
 
 /// Handle callbacks from SDCard
-impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardDriver<'a, A> {
+impl<'a, D: SDCardDevice<'a>> SDCardClient for SDCardDriver<'a, D> {
     fn card_detection_changed(&self, installed: bool) {
-        self.current_process.map(|process_id| {
-            let _ = self.grants.enter(*process_id, |_app, kernel_data| {
-                kernel_data
-                    .schedule_upcall(0, (0, installed as usize, 0))
-                    .ok();
-            });
+        // every process is told about the card being inserted or removed
+        self.grants.each(|_, _app, kernel_data| {
+            kernel_data
+                .schedule_upcall(0, (0, installed as usize, 0))
+                .ok();
         });
     }
 
     fn init_done(&self, block_size: u32, total_size: u64) {
-        self.current_process.map(|process_id| {
-            let _ = self.grants.enter(*process_id, |_app, kernel_data| {
-                let size_in_kb = ((total_size >> 10) & 0xFFFFFFFF) as usize;
-                kernel_data
-                    .schedule_upcall(0, (1, block_size as usize, size_in_kb))
-                    .ok();
-            });
+        // the card may have been mounted automatically after insertion, so
+        //  every process is told
+        let size_in_kb = ((total_size >> 10) & 0xFFFFFFFF) as usize;
+        self.grants.each(|_, _app, kernel_data| {
+            kernel_data
+                .schedule_upcall(0, (1, block_size as usize, size_in_kb))
+                .ok();
         });
     }
 
@@ -1571,28 +1827,39 @@ impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardDriver<'a, A> {
         });
     }
 
-    fn error(&self, error: u32) {
+    fn error(&self, error: u32, buffer: Option<&'static mut [u8]>) {
+        buffer.map(|buffer| self.kernel_buf.replace(buffer));
+
         self.current_process.map(|process_id| {
             let _ = self.grants.enter(*process_id, |_app, kernel_data| {
                 kernel_data.schedule_upcall(0, (4, error as usize, 0)).ok();
             });
         });
     }
+
+    fn unmounted(&self) {
+        self.grants.each(|_, _app, kernel_data| {
+            kernel_data.schedule_upcall(0, (5, 0, 0)).ok();
+        });
+    }
 }
 
 /// Connections to userspace syscalls
-impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
+impl<'a, D: SDCardDevice<'a>> SyscallDriver for SDCardDriver<'a, D> {
     fn command(
         &self,
         command_num: usize,
         data: usize,
-        _: usize,
+        count: usize,
         process_id: ProcessId,
     ) -> CommandReturn {
         if command_num == 0 {
             // Handle this first as it should be returned unconditionally.
             return CommandReturn::success();
         }
+        if command_num == driver_info::COMMAND_NUM {
+            return DriverInfo::new(1).into();
+        }
 
         // Check if this driver is free, or already dedicated to this process.
         let match_or_empty_or_nonexistant = self.current_process.map_or(true, |current_process| {
@@ -1620,48 +1887,23 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
             },
 
             // read_block
-            3 => self.kernel_buf.take().map_or(
-                CommandReturn::failure(ErrorCode::BUSY),
-                |kernel_buf| {
-                    CommandReturn::from(self.sdcard.read_blocks(kernel_buf, data as u32, 1))
-                },
-            ),
+            3 => CommandReturn::from(self.read(data as u32, 1)),
 
             // write_block
-            4 => {
-                let result: Result<(), ErrorCode> = self
-                    .grants
-                    .enter(process_id, |_, kernel_data| {
-                        kernel_data
-                            .get_readonly_processbuffer(ro_allow::WRITE)
-                            .and_then(|write| {
-                                write.enter(|write_buffer| {
-                                    self.kernel_buf.take().map_or(
-                                        Err(ErrorCode::BUSY),
-                                        |kernel_buf| {
-                                            // copy over write data from application
-                                            // Limit to minimum length between kernel_buf,
-                                            // write_buffer, and 512 (block size)
-                                            for (kernel_byte, ref write_byte) in kernel_buf
-                                                .iter_mut()
-                                                .zip(write_buffer.iter())
-                                                .take(512)
-                                            {
-                                                *kernel_byte = write_byte.get();
-                                            }
-
-                                            // begin writing
-                                            self.sdcard.write_blocks(kernel_buf, data as u32, 1)
-                                        },
-                                    )
-                                })
-                            })
-                            .unwrap_or(Err(ErrorCode::NOMEM))
-                    })
-                    .unwrap_or(Err(ErrorCode::NOMEM));
-                CommandReturn::from(result)
+            4 => CommandReturn::from(self.write(process_id, data as u32, 1)),
+
+            // is_mounted
+            5 => {
+                let value = self.sdcard.is_initialized() as u32;
+                CommandReturn::success_u32(value)
             }
 
+            // read_blocks
+            6 => CommandReturn::from(self.read(data as u32, count)),
+
+            // write_blocks
+            7 => CommandReturn::from(self.write(process_id, data as u32, count)),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
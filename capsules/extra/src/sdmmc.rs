@@ -0,0 +1,659 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! SD card connected to a native SD host controller (`hil::sdmmc`).
+//!
+//! This runs the card in its native mode instead of SPI: the card is
+//! initialized, selected and switched to the widest bus the controller
+//! supports, and blocks are moved by the controller (usually by DMA) with
+//! the CRCs checked in hardware. Like `sdcard::SDCard` it implements
+//! `sdcard::SDCardDevice`, so `sdcard::SDCardDriver` serves it to userspace.
+//!
+//! Only SD cards (SDSC and SDHC/SDXC) are initialized. There is no card
+//! detection, the card is assumed to be installed, and is initialized when
+//! `initialize()` is called.
+//!
+//! Data is transferred directly to and from the buffer passed to
+//! `read_blocks()` or `write_blocks()`, which must hold all `count` blocks.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! # use kernel::static_init;
+//!
+//! let sdmmc_virtual_alarm = static_init!(
+//!     capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, stm32f429zi::tim2::Tim2>,
+//!     capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm::new(mux_alarm));
+//! sdmmc_virtual_alarm.setup();
+//!
+//! let sdcard = static_init!(
+//!     capsules_extra::sdmmc::SdMmcCard<
+//!         'static,
+//!         stm32f429zi::sdio::Sdio<'static>,
+//!         capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, stm32f429zi::tim2::Tim2>>,
+//!     capsules_extra::sdmmc::SdMmcCard::new(&base_peripherals.sdio, sdmmc_virtual_alarm));
+//! kernel::hil::sdmmc::SdMmcHost::set_client(&base_peripherals.sdio, sdcard);
+//! sdmmc_virtual_alarm.set_alarm_client(sdcard);
+//!
+//! let sdcard_kernel_buffer = static_init!([u8; capsules_extra::sdcard::KERNEL_BUFFER_LENGTH],
+//!                                         [0; capsules_extra::sdcard::KERNEL_BUFFER_LENGTH]);
+//!
+//! let sdcard_driver = static_init!(
+//!     capsules_extra::sdcard::SDCardDriver<'static, capsules_extra::sdmmc::SdMmcCard<...>>,
+//!     capsules_extra::sdcard::SDCardDriver::new(
+//!         sdcard,
+//!         sdcard_kernel_buffer,
+//!         board_kernel.create_grant(
+//!             capsules_extra::sdcard::DRIVER_NUM,
+//!             &memory_allocation_capability)));
+//! SDCardDevice::set_client(sdcard, sdcard_driver);
+//! ```
+
+// Resources:
+//  * SD Specifications Part 1 Physical Layer Simplified Specification
+
+use core::cell::Cell;
+
+use kernel::hil;
+use kernel::hil::sdmmc::{BusWidth, Direction, Response, ResponseType, SdMmcHost};
+use kernel::hil::time::ConvertTicks;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::sdcard::{SDCardClient, SDCardDevice, SdCardError};
+
+/// Size of the blocks read and written.
+const BLOCK_SIZE: usize = 512;
+
+/// Card clock while identifying the card.
+const IDENTIFICATION_CLOCK: u32 = 400_000;
+
+/// Card clock in default speed mode.
+const TRANSFER_CLOCK: u32 = 25_000_000;
+
+/// Delay between polls of a card that is busy.
+const POLL_INTERVAL_MS: u32 = 10;
+
+/// Polls of a busy card before giving up, one second in total.
+const MAX_POLLS: u8 = 100;
+
+/// Bits of the card status (R1) that report an error.
+const R1_ERRORS: u32 = 0xFDF9_8008;
+
+/// Card status bit set when the card can accept data.
+const R1_READY_FOR_DATA: u32 = 1 << 8;
+
+/// Card status bits of the current state.
+const R1_CURRENT_STATE: u32 = 0xF << 9;
+
+/// Current state of a card in transfer state.
+const R1_STATE_TRAN: u32 = 4 << 9;
+
+/// Bit of the OCR that is clear while the card is powering up.
+const OCR_POWER_UP_DONE: u32 = 1 << 31;
+
+/// Bit of the OCR set for high capacity (block addressed) cards.
+const OCR_CCS: u32 = 1 << 30;
+
+/// Voltage window (2.7 V to 3.6 V) requested by ACMD41.
+const OCR_VOLTAGE_WINDOW: u32 = 0x00FF_8000;
+
+/// SD card command codes
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SdCmd {
+    CMD0_Reset = 0,
+    CMD2_AllSendCid = 2,
+    CMD3_SendRelativeAddress = 3,
+    CMD7_SelectCard = 7,
+    CMD8_CheckVoltage = 8,
+    CMD9_SendCsd = 9,
+    CMD12_StopTransmission = 12,
+    CMD13_SendStatus = 13,
+    CMD16_SetBlockLen = 16,
+    CMD17_ReadSingle = 17,
+    CMD18_ReadMultiple = 18,
+    CMD24_WriteSingle = 24,
+    CMD25_WriteMultiple = 25,
+    CMD55_AppCommand = 55,
+}
+
+/// Application specific command codes, sent after CMD55
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SdAppCmd {
+    ACMD6_SetBusWidth = 6,
+    ACMD41_SendOpCond = 41,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+
+    InitReset,
+    InitCheckVersion,
+    InitAppCommand { hcs: bool },
+    InitSendOpCond { hcs: bool },
+    InitSendCid,
+    InitSendRelativeAddress,
+    InitSendCsd,
+    InitSelectCard,
+    InitAppCommandBusWidth,
+    InitSetBusWidth,
+    InitSetBlockLen,
+
+    ReadBlocks { count: u32 },
+    StopRead { count: u32 },
+
+    WriteBlocks { count: u32 },
+    StopWrite { count: u32 },
+    WriteStatus { count: u32 },
+}
+
+/// SD card accessed through a native host controller.
+pub struct SdMmcCard<'a, H: SdMmcHost<'a>, A: hil::time::Alarm<'a>> {
+    host: &'a H,
+    alarm: &'a A,
+    client: OptionalCell<&'a dyn SDCardClient>,
+
+    state: Cell<State>,
+    /// State to continue in once the alarm fires.
+    alarm_state: Cell<State>,
+    polls: Cell<u8>,
+
+    is_initialized: Cell<bool>,
+    /// Whether the card is addressed by block instead of by byte.
+    block_addressed: Cell<bool>,
+    /// Relative card address, assigned by the card during initialization.
+    rca: Cell<u32>,
+    total_size: Cell<u64>,
+
+    client_buffer: TakeCell<'static, [u8]>,
+    /// Whether the data of the transfer in progress failed, reported once
+    /// the transfer is stopped.
+    transfer_failed: Cell<bool>,
+}
+
+impl<'a, H: SdMmcHost<'a>, A: hil::time::Alarm<'a>> SdMmcCard<'a, H, A> {
+    pub fn new(host: &'a H, alarm: &'a A) -> SdMmcCard<'a, H, A> {
+        SdMmcCard {
+            host,
+            alarm,
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            alarm_state: Cell::new(State::Idle),
+            polls: Cell::new(0),
+            is_initialized: Cell::new(false),
+            block_addressed: Cell::new(false),
+            rca: Cell::new(0),
+            total_size: Cell::new(0),
+            client_buffer: TakeCell::empty(),
+            transfer_failed: Cell::new(false),
+        }
+    }
+
+    /// Sends command `index`, the response is handled in `state`
+    fn send_command(&self, state: State, index: u8, argument: u32, response: ResponseType) {
+        self.state.set(state);
+        if self.host.send_command(index, argument, response).is_err() {
+            self.fail(state);
+        }
+    }
+
+    /// Polls the card again after `POLL_INTERVAL_MS`, or fails once it was
+    /// polled `MAX_POLLS` times.
+    fn poll_later(&self, state: State) {
+        let polls = self.polls.get() + 1;
+        if polls > MAX_POLLS {
+            self.polls.set(0);
+            self.state.set(State::Idle);
+            self.report_error(SdCardError::TimeoutFailure);
+            return;
+        }
+        self.polls.set(polls);
+        self.state.set(State::Idle);
+        self.alarm_state.set(state);
+        let delay = self.alarm.ticks_from_ms(POLL_INTERVAL_MS);
+        self.alarm.set_alarm(self.alarm.now(), delay);
+    }
+
+    /// The card address of a command, a block number or a byte offset
+    /// depending on the card.
+    fn address(&self, sector: u32) -> u32 {
+        if self.block_addressed.get() {
+            sector
+        } else {
+            sector.wrapping_mul(BLOCK_SIZE as u32)
+        }
+    }
+
+    /// Whether a card status response is free of errors.
+    fn status_ok(result: Result<Response, ErrorCode>) -> bool {
+        result.map_or(false, |response| response.short() & R1_ERRORS == 0)
+    }
+
+    /// Size of the card in bytes, from the CSD register.
+    fn csd_total_size(csd: [u32; 4]) -> u64 {
+        // the CSD is most significant word first, `csd[0]` holds bits 127-96
+        if csd[0] >> 30 == 0 {
+            // CSD version 1.0
+            let read_bl_len = (csd[1] >> 16) & 0x0F;
+            let c_size = ((csd[1] & 0x3FF) << 2) | (csd[2] >> 30);
+            let c_size_mult = (csd[2] >> 15) & 0x07;
+            let block_count = (c_size as u64 + 1) << (c_size_mult + 2);
+            block_count << read_bl_len
+        } else {
+            // CSD version 2.0
+            let c_size = ((csd[1] & 0x3F) << 16) | (csd[2] >> 16);
+            (c_size as u64 + 1) * 512 * 1024
+        }
+    }
+
+    /// Ends the transaction in `state` with an error.
+    fn fail(&self, state: State) {
+        self.state.set(State::Idle);
+        let error = match state {
+            State::Idle => return,
+            State::ReadBlocks { .. } | State::StopRead { .. } => SdCardError::ReadFailure,
+            State::WriteBlocks { .. } | State::StopWrite { .. } | State::WriteStatus { .. } => {
+                SdCardError::WriteFailure
+            }
+            _ => SdCardError::InitializationFailure,
+        };
+        self.report_error(error);
+    }
+
+    /// sends an error callback, handing back the client's buffer if a read or
+    ///     write was in progress
+    fn report_error(&self, error: SdCardError) {
+        let buffer = self.client_buffer.take();
+        self.client.map(move |client| {
+            client.error(error as u32, buffer);
+        });
+    }
+
+    fn init_complete(&self) {
+        self.state.set(State::Idle);
+        self.is_initialized.set(true);
+        let total_size = self.total_size.get();
+        self.client
+            .map(|client| client.init_done(BLOCK_SIZE as u32, total_size));
+    }
+
+    fn read_complete(&self, count: u32) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.block_done(count));
+        self.client_buffer.take().map(|buffer| {
+            self.client
+                .map(move |client| client.read_done(buffer, count as usize * BLOCK_SIZE));
+        });
+    }
+
+    fn write_complete(&self, count: u32) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.block_done(count));
+        self.client_buffer.take().map(|buffer| {
+            self.client.map(move |client| client.write_done(buffer));
+        });
+    }
+
+    /// Starts a read or write of `count` blocks at `sector`.
+    fn start_transfer(
+        &self,
+        direction: Direction,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if count == 0 || buffer.len() < count as usize * BLOCK_SIZE {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        if !self.is_initialized.get() {
+            return Err((ErrorCode::RESERVE, buffer));
+        }
+        if self.state.get() != State::Idle || self.alarm_state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+
+        let (state, cmd) = match (direction, count) {
+            (Direction::Read, 1) => (State::ReadBlocks { count }, SdCmd::CMD17_ReadSingle),
+            (Direction::Read, _) => (State::ReadBlocks { count }, SdCmd::CMD18_ReadMultiple),
+            (Direction::Write, 1) => (State::WriteBlocks { count }, SdCmd::CMD24_WriteSingle),
+            (Direction::Write, _) => (State::WriteBlocks { count }, SdCmd::CMD25_WriteMultiple),
+        };
+        self.host.transfer(
+            cmd as u8,
+            self.address(sector),
+            direction,
+            buffer,
+            BLOCK_SIZE,
+            count as usize,
+        )?;
+        self.state.set(state);
+        self.polls.set(0);
+        self.transfer_failed.set(false);
+        Ok(())
+    }
+
+    /// Continues initializing a selected card in transfer state.
+    fn set_block_len(&self) {
+        if self.block_addressed.get() {
+            // high capacity cards always use 512 byte blocks
+            self.init_complete();
+        } else {
+            self.send_command(
+                State::InitSetBlockLen,
+                SdCmd::CMD16_SetBlockLen as u8,
+                BLOCK_SIZE as u32,
+                ResponseType::Short,
+            );
+        }
+    }
+
+    /// Stops a multiple block transfer, or checks that a single block was
+    /// written.
+    fn end_transfer(&self, state: State) {
+        match state {
+            State::ReadBlocks { count } if count > 1 => self.send_command(
+                State::StopRead { count },
+                SdCmd::CMD12_StopTransmission as u8,
+                0,
+                ResponseType::Short,
+            ),
+            State::WriteBlocks { count } if count > 1 => self.send_command(
+                State::StopWrite { count },
+                SdCmd::CMD12_StopTransmission as u8,
+                0,
+                ResponseType::Short,
+            ),
+            State::ReadBlocks { count } => {
+                if self.transfer_failed.get() {
+                    self.fail(state);
+                } else {
+                    self.read_complete(count);
+                }
+            }
+            State::WriteBlocks { count } => {
+                if self.transfer_failed.get() {
+                    self.fail(state);
+                } else {
+                    self.send_command(
+                        State::WriteStatus { count },
+                        SdCmd::CMD13_SendStatus as u8,
+                        self.rca.get(),
+                        ResponseType::Short,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, H: SdMmcHost<'a>, A: hil::time::Alarm<'a>> SDCardDevice<'a> for SdMmcCard<'a, H, A> {
+    fn set_client(&self, client: &'a dyn SDCardClient) {
+        self.client.set(client);
+    }
+
+    fn is_installed(&self) -> bool {
+        // without card detection, assume an sd card is installed
+        true
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.is_initialized.get()
+    }
+
+    fn initialize(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle || self.alarm_state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.is_initialized.set(false);
+        self.block_addressed.set(false);
+        self.polls.set(0);
+
+        // identify the card slowly on a single data line
+        self.host.set_clock(IDENTIFICATION_CLOCK)?;
+        self.host.set_bus_width(BusWidth::One)?;
+        self.host
+            .send_command(SdCmd::CMD0_Reset as u8, 0, ResponseType::None)?;
+        self.state.set(State::InitReset);
+        Ok(())
+    }
+
+    fn read_blocks(
+        &self,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_transfer(Direction::Read, buffer, sector, count)
+    }
+
+    fn write_blocks(
+        &self,
+        buffer: &'static mut [u8],
+        sector: u32,
+        count: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_transfer(Direction::Write, buffer, sector, count)
+    }
+}
+
+/// Handle callbacks from the host controller
+impl<'a, H: SdMmcHost<'a>, A: hil::time::Alarm<'a>> hil::sdmmc::Client for SdMmcCard<'a, H, A> {
+    fn command_done(&self, result: Result<Response, ErrorCode>) {
+        let state = self.state.get();
+        match state {
+            State::InitReset => {
+                if result.is_err() {
+                    return self.fail(state);
+                }
+                // only SDv2 cards answer the check voltage command, and echo
+                //  the check pattern 0xAA with the accepted voltage 0x1
+                self.send_command(
+                    State::InitCheckVersion,
+                    SdCmd::CMD8_CheckVoltage as u8,
+                    0x1AA,
+                    ResponseType::Short,
+                );
+            }
+
+            State::InitCheckVersion => {
+                let hcs = match result {
+                    Ok(response) if response.short() & 0xFFF == 0x1AA => true,
+                    // SDv1 cards do not know the command
+                    Err(ErrorCode::NOACK) => false,
+                    _ => return self.fail(state),
+                };
+                self.send_command(
+                    State::InitAppCommand { hcs },
+                    SdCmd::CMD55_AppCommand as u8,
+                    0,
+                    ResponseType::Short,
+                );
+            }
+
+            State::InitAppCommand { hcs } => {
+                if result.is_err() {
+                    return self.fail(state);
+                }
+                let mut argument = OCR_VOLTAGE_WINDOW;
+                if hcs {
+                    argument |= OCR_CCS;
+                }
+                self.send_command(
+                    State::InitSendOpCond { hcs },
+                    SdAppCmd::ACMD41_SendOpCond as u8,
+                    argument,
+                    ResponseType::ShortNoCrc,
+                );
+            }
+
+            State::InitSendOpCond { hcs } => match result {
+                Ok(response) if response.short() & OCR_POWER_UP_DONE != 0 => {
+                    self.polls.set(0);
+                    self.block_addressed.set(response.short() & OCR_CCS != 0);
+                    self.send_command(
+                        State::InitSendCid,
+                        SdCmd::CMD2_AllSendCid as u8,
+                        0,
+                        ResponseType::Long,
+                    );
+                }
+                // the card is still powering up, ask again
+                Ok(_) => self.poll_later(State::InitAppCommand { hcs }),
+                Err(_) => self.fail(state),
+            },
+
+            State::InitSendCid => {
+                if result.is_err() {
+                    return self.fail(state);
+                }
+                self.send_command(
+                    State::InitSendRelativeAddress,
+                    SdCmd::CMD3_SendRelativeAddress as u8,
+                    0,
+                    ResponseType::Short,
+                );
+            }
+
+            State::InitSendRelativeAddress => match result {
+                Ok(response) => {
+                    // the address is in the upper half of the response, where
+                    //  commands expect it as well
+                    self.rca.set(response.short() & 0xFFFF_0000);
+                    self.send_command(
+                        State::InitSendCsd,
+                        SdCmd::CMD9_SendCsd as u8,
+                        self.rca.get(),
+                        ResponseType::Long,
+                    );
+                }
+                Err(_) => self.fail(state),
+            },
+
+            State::InitSendCsd => match result {
+                Ok(Response::Long(csd)) => {
+                    self.total_size.set(Self::csd_total_size(csd));
+                    self.send_command(
+                        State::InitSelectCard,
+                        SdCmd::CMD7_SelectCard as u8,
+                        self.rca.get(),
+                        ResponseType::Short,
+                    );
+                }
+                _ => self.fail(state),
+            },
+
+            State::InitSelectCard => {
+                if !Self::status_ok(result) || self.host.set_clock(TRANSFER_CLOCK).is_err() {
+                    return self.fail(state);
+                }
+                if self.host.max_bus_width() == BusWidth::One {
+                    self.set_block_len();
+                } else {
+                    self.send_command(
+                        State::InitAppCommandBusWidth,
+                        SdCmd::CMD55_AppCommand as u8,
+                        self.rca.get(),
+                        ResponseType::Short,
+                    );
+                }
+            }
+
+            State::InitAppCommandBusWidth => {
+                if !Self::status_ok(result) {
+                    return self.fail(state);
+                }
+                // argument 2 selects the 4-bit bus
+                self.send_command(
+                    State::InitSetBusWidth,
+                    SdAppCmd::ACMD6_SetBusWidth as u8,
+                    2,
+                    ResponseType::Short,
+                );
+            }
+
+            State::InitSetBusWidth => {
+                if !Self::status_ok(result) || self.host.set_bus_width(BusWidth::Four).is_err() {
+                    return self.fail(state);
+                }
+                self.set_block_len();
+            }
+
+            State::InitSetBlockLen => {
+                if !Self::status_ok(result) {
+                    return self.fail(state);
+                }
+                self.init_complete();
+            }
+
+            State::StopRead { count } => {
+                if self.transfer_failed.get() || !Self::status_ok(result) {
+                    return self.fail(state);
+                }
+                self.read_complete(count);
+            }
+
+            State::StopWrite { count } => {
+                if self.transfer_failed.get() || !Self::status_ok(result) {
+                    return self.fail(state);
+                }
+                self.send_command(
+                    State::WriteStatus { count },
+                    SdCmd::CMD13_SendStatus as u8,
+                    self.rca.get(),
+                    ResponseType::Short,
+                );
+            }
+
+            State::WriteStatus { count } => match result {
+                Ok(response) if response.short() & R1_ERRORS == 0 => {
+                    let status = response.short();
+                    if status & R1_READY_FOR_DATA != 0 && status & R1_CURRENT_STATE == R1_STATE_TRAN
+                    {
+                        self.write_complete(count);
+                    } else {
+                        // the card is still programming the blocks
+                        self.poll_later(state);
+                    }
+                }
+                _ => self.fail(state),
+            },
+
+            State::Idle | State::ReadBlocks { .. } | State::WriteBlocks { .. } => {}
+        }
+    }
+
+    fn transfer_done(&self, buffer: &'static mut [u8], result: Result<Response, ErrorCode>) {
+        self.client_buffer.replace(buffer);
+        if !Self::status_ok(result) {
+            self.transfer_failed.set(true);
+        }
+        self.end_transfer(self.state.get());
+    }
+}
+
+/// Handle callbacks from the timer
+impl<'a, H: SdMmcHost<'a>, A: hil::time::Alarm<'a>> hil::time::AlarmClient for SdMmcCard<'a, H, A> {
+    fn alarm(&self) {
+        match self.alarm_state.replace(State::Idle) {
+            State::InitAppCommand { hcs } => self.send_command(
+                State::InitAppCommand { hcs },
+                SdCmd::CMD55_AppCommand as u8,
+                0,
+                ResponseType::Short,
+            ),
+            State::WriteStatus { count } => self.send_command(
+                State::WriteStatus { count },
+                SdCmd::CMD13_SendStatus as u8,
+                self.rca.get(),
+                ResponseType::Short,
+            ),
+            _ => {}
+        }
+    }
+}
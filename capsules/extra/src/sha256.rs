@@ -8,8 +8,12 @@
 //! algorithm. It performs the hash using 32-bit native values,
 //! translating the input data into the endianness of the processor
 //! and translating the output into big endian format.
+//!
+//! Added data is hashed in the background, `BLOCKS_PER_STEP` blocks at a
+//! time, so that hashing large buffers does not keep processes from running.
 
 use core::cell::Cell;
+use kernel::background_call::{BackgroundCall, BackgroundCallClient, Progress};
 use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 
 use kernel::hil::digest::Client;
@@ -35,6 +39,8 @@ pub enum State {
 const SHA_BLOCK_LEN_BYTES: usize = 64;
 const SHA_256_OUTPUT_LEN_BYTES: usize = 32;
 const NUM_ROUND_CONSTANTS: usize = 64;
+/// Blocks of added data hashed in each step of the background call.
+const BLOCKS_PER_STEP: usize = 8;
 
 const ROUND_CONSTANTS: [u32; NUM_ROUND_CONSTANTS] = [
     0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
@@ -61,6 +67,7 @@ pub struct Sha256Software<'a> {
 
     hash_values: Cell<[u32; 8]>,
     deferred_call: DeferredCall,
+    background_call: BackgroundCall,
 }
 
 impl<'a> Sha256Software<'a> {
@@ -77,6 +84,7 @@ impl<'a> Sha256Software<'a> {
             hash_values: Cell::new([0; 8]),
 
             deferred_call: DeferredCall::new(),
+            background_call: BackgroundCall::new(),
         };
         s.initialize();
         s
@@ -176,11 +184,11 @@ impl<'a> Sha256Software<'a> {
     // the implementation first fills temp_buffer and computes
     // on it, then operates on input_data. If the end of
     // input_data does not complete a block then the remainder
-    // is stored in data_buffer.
-    fn compute_sha256(&self) {
+    // is stored in data_buffer. At most `BLOCKS_PER_STEP` blocks
+    // of input_data are hashed, returns whether all of it was.
+    fn compute_sha256(&self) -> bool {
         if let Some(mut data) = self.input_data.take() {
             let data_length = data.len();
-            self.total_length.set(self.total_length.get() + data_length);
             let mut buffered_length = self.buffered_length.get();
             if buffered_length != 0 {
                 // Copy bytes into the front of the temp buffer and
@@ -205,9 +213,16 @@ impl<'a> Sha256Software<'a> {
                 });
             }
             // Process blocks
+            let mut blocks = 0;
             while data.len() >= 64 {
+                if blocks == BLOCKS_PER_STEP {
+                    self.input_data.set(data);
+                    self.buffered_length.set(buffered_length);
+                    return false;
+                }
                 self.compute_buffer(&data[0..64]);
                 data.slice(64..data.len());
+                blocks += 1;
             }
             // Process tail end of block
             if data.len() != 0 {
@@ -224,6 +239,7 @@ impl<'a> Sha256Software<'a> {
             self.buffered_length.set(buffered_length);
         } else { /* do nothing, no data */
         }
+        true
     }
 
     fn right_rotate(&self, x: u32, rotate: u32) -> u32 {
@@ -304,9 +320,9 @@ impl<'a> DigestData<'a, 32> for Sha256Software<'a> {
             Err((ErrorCode::BUSY, data))
         } else {
             self.state.set(State::Data);
-            self.deferred_call.set();
+            self.total_length.set(self.total_length.get() + data.len());
             self.input_data.set(LeasableBufferDynamic::Immutable(data));
-            self.compute_sha256();
+            self.background_call.set();
             Ok(())
         }
     }
@@ -319,9 +335,9 @@ impl<'a> DigestData<'a, 32> for Sha256Software<'a> {
             Err((ErrorCode::BUSY, data))
         } else {
             self.state.set(State::Data);
-            self.deferred_call.set();
+            self.total_length.set(self.total_length.get() + data.len());
             self.input_data.set(LeasableBufferDynamic::Mutable(data));
-            self.compute_sha256();
+            self.background_call.set();
             Ok(())
         }
     }
@@ -407,7 +423,7 @@ impl<'a> DeferredCallClient for Sha256Software<'a> {
                 });
             }
             State::Data => {
-                // Data already computed in method call
+                // Data already computed by the background call
                 let data = self.input_data.take().unwrap();
                 self.state.set(State::Idle);
                 match data {
@@ -470,6 +486,28 @@ impl<'a> DeferredCallClient for Sha256Software<'a> {
 
     fn register(&'static self) {
         self.deferred_call.register(self);
+        self.background_call.register(self);
+    }
+}
+
+impl<'a> BackgroundCallClient for Sha256Software<'a> {
+    fn step(&self) -> Progress {
+        match self.state.get() {
+            State::Data => {
+                if self.compute_sha256() {
+                    self.deferred_call.set();
+                    Progress::Done
+                } else {
+                    Progress::Continue
+                }
+            }
+            // Cancelled while hashing the data.
+            State::CancelData => {
+                self.deferred_call.set();
+                Progress::Done
+            }
+            _ => Progress::Done,
+        }
     }
 }
 
@@ -12,8 +12,9 @@ use core::cell::Cell;
 use enum_primitive::cast::FromPrimitive;
 use enum_primitive::enum_from_primitive;
 use kernel::hil::i2c;
-use kernel::hil::time::{self, Alarm, ConvertTicks};
+use kernel::hil::time::{self, Alarm};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::time_conversion::{CheckedConvertTicks, Rounding};
 use kernel::ErrorCode;
 
 pub static BASE_ADDR: u8 = 0x44;
@@ -202,7 +203,7 @@ impl<'a, A: Alarm<'a>, I: i2c::I2CDevice> i2c::I2CClient for SHT3x<'a, A, I> {
                     }
                     State::Read => {
                         self.buffer.replace(buffer);
-                        let interval = self.alarm.ticks_from_ms(20);
+                        let interval = self.alarm.saturating_ticks_from_ms(20, Rounding::Up);
                         self.alarm.set_alarm(self.alarm.now(), interval);
                     }
                     _ => {}
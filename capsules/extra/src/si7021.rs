@@ -44,8 +44,9 @@
 
 use core::cell::Cell;
 use kernel::hil::i2c;
-use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::time;
 use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::time_conversion::{CheckedConvertTicks, Rounding};
 use kernel::ErrorCode;
 
 #[allow(dead_code)]
@@ -135,7 +136,7 @@ impl<'a, A: time::Alarm<'a>, I: i2c::I2CDevice> SI7021<'a, A, I> {
     }
 
     fn init_measurement(&self, buffer: &'static mut [u8]) {
-        let delay = self.alarm.ticks_from_ms(20);
+        let delay = self.alarm.saturating_ticks_from_ms(20, Rounding::Up);
         self.alarm.set_alarm(self.alarm.now(), delay);
 
         // Now wait for timer to expire
@@ -27,6 +27,8 @@
 //!
 //! * `0`: check whether the driver exist
 //! * `1`: read the sound_pressure
+//! * `driver_info::COMMAND_NUM`: describe the driver as in
+//!   `capsules_core::driver_info`
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
@@ -56,6 +58,7 @@
 //! kernel::hil::sensors::SoundPressure::set_client(si7021, temp);
 //! ```
 
+use capsules_core::driver_info::{self, DriverInfo};
 use core::cell::Cell;
 use core::convert::TryFrom;
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
@@ -155,6 +158,9 @@ impl SyscallDriver for SoundPressureSensor<'_> {
             // check whether the driver exists!!
             0 => CommandReturn::success(),
 
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1).into(),
+
             // read sound_pressure
             1 => self.enqueue_command(processid),
 
@@ -0,0 +1,232 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Serial Wire Debug (SWD) probe over two GPIO pins.
+//!
+//! Implements the SWD wire protocol in software, so any board can debug
+//! another chip by connecting two of its pins to SWCLK and SWDIO of the
+//! target, and optionally a third one to the reset line of the target.
+//! SWDIO should have a pull-up resistor, the pin is also configured with its
+//! internal pull-up while it is an input.
+//!
+//! The clock is generated by toggling SWCLK and waiting in between with a
+//! busy loop, so its frequency is only approximate, and the kernel does not
+//! run other code during a transfer.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let swd = static_init!(
+//!     capsules_extra::swd_bitbang::SwdBitbang<'static, RPGpioPin<'static>>,
+//!     capsules_extra::swd_bitbang::SwdBitbang::new(
+//!         &peripherals.pins.get_pin(RPGpio::GPIO2),
+//!         &peripherals.pins.get_pin(RPGpio::GPIO3),
+//!         None,
+//!         125_000_000,
+//!     )
+//! );
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::swd::{Configuration, Swd, TransferError};
+use kernel::ErrorCode;
+
+/// Approximate number of CPU cycles of one iteration of the delay loop.
+const LOOP_CYCLES: u32 = 4;
+/// Approximate number of CPU cycles it takes to clock a bit without any
+/// delay, for the GPIO accesses.
+const BIT_CYCLES: u32 = 40;
+
+const ACK_OK: u32 = 0b001;
+const ACK_WAIT: u32 = 0b010;
+const ACK_FAULT: u32 = 0b100;
+
+fn parity(value: u32) -> bool {
+    value.count_ones() % 2 == 1
+}
+
+pub struct SwdBitbang<'a, P: gpio::Pin> {
+    swclk: &'a P,
+    swdio: &'a P,
+    reset: Option<&'a P>,
+    cpu_frequency_hz: u32,
+    /// Iterations of the delay loop for half a clock period.
+    half_period: Cell<u32>,
+    configuration: Cell<Configuration>,
+}
+
+impl<'a, P: gpio::Pin> SwdBitbang<'a, P> {
+    pub fn new(swclk: &'a P, swdio: &'a P, reset: Option<&'a P>, cpu_frequency_hz: u32) -> Self {
+        SwdBitbang {
+            swclk,
+            swdio,
+            reset,
+            cpu_frequency_hz,
+            half_period: Cell::new(0),
+            configuration: Cell::new(Configuration::default()),
+        }
+    }
+
+    fn delay(&self) {
+        for _ in 0..self.half_period.get() {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Run one clock cycle. The target samples SWDIO on the rising edge, and
+    /// changes it after the rising edge, so it is read before.
+    fn clock(&self) -> bool {
+        self.swclk.clear();
+        self.delay();
+        let bit = self.swdio.read();
+        self.swclk.set();
+        self.delay();
+        bit
+    }
+
+    fn write_bits(&self, value: u32, count: usize) {
+        for i in 0..count {
+            if value >> i & 1 == 1 {
+                self.swdio.set();
+            } else {
+                self.swdio.clear();
+            }
+            self.clock();
+        }
+    }
+
+    /// Read up to 32 bits.
+    fn read_bits(&self, count: usize) -> u32 {
+        (0..count).fold(0, |value, i| value | (self.clock() as u32) << i)
+    }
+
+    /// Hand SWDIO over to the target.
+    fn turnaround_to_target(&self) {
+        self.swdio.make_input();
+        self.swdio.set_floating_state(gpio::FloatingState::PullUp);
+        for _ in 0..self.configuration.get().turnaround_cycles {
+            self.clock();
+        }
+    }
+
+    /// Take SWDIO back from the target.
+    fn turnaround_to_probe(&self) {
+        for _ in 0..self.configuration.get().turnaround_cycles {
+            self.clock();
+        }
+        self.swdio.make_output();
+    }
+}
+
+impl<'a, P: gpio::Pin> Swd for SwdBitbang<'a, P> {
+    fn enable(&self) {
+        self.swclk.set();
+        self.swclk.make_output();
+        self.swdio.set();
+        self.swdio.make_output();
+    }
+
+    fn disable(&self) {
+        self.swclk.make_input();
+        self.swdio.make_input();
+    }
+
+    fn set_clock_hz(&self, frequency_hz: u32) -> u32 {
+        // Round the delay up, so the clock does not get faster than asked.
+        let half_period_cycles = self.cpu_frequency_hz / frequency_hz.max(1) / 2;
+        let loops =
+            (half_period_cycles.saturating_sub(BIT_CYCLES / 2) + LOOP_CYCLES - 1) / LOOP_CYCLES;
+        self.half_period.set(loops);
+        self.cpu_frequency_hz / (BIT_CYCLES + 2 * loops * LOOP_CYCLES)
+    }
+
+    fn configure(&self, configuration: Configuration) {
+        self.configuration.set(Configuration {
+            turnaround_cycles: configuration.turnaround_cycles.clamp(1, 4),
+            ..configuration
+        });
+    }
+
+    fn write_sequence(&self, data: &[u8], count: usize) {
+        for (i, byte) in data.iter().enumerate().take((count + 7) / 8) {
+            self.write_bits(*byte as u32, (count - i * 8).min(8));
+        }
+    }
+
+    fn transfer(&self, request: u8, data: &mut u32) -> Result<(), TransferError> {
+        let configuration = self.configuration.get();
+        let request = (request & 0b1111) as u32;
+        let read = request & kernel::hil::swd::REQUEST_READ as u32 != 0;
+
+        // Start bit, the request, its parity, the stop bit and the park bit.
+        let header = 1 | request << 1 | (parity(request) as u32) << 5 | 1 << 7;
+        self.write_bits(header, 8);
+        self.turnaround_to_target();
+        let ack = self.read_bits(3);
+
+        let result = match ack {
+            ACK_OK if read => {
+                let value = self.read_bits(32);
+                let value_parity = self.clock();
+                self.turnaround_to_probe();
+                if value_parity == parity(value) {
+                    *data = value;
+                    Ok(())
+                } else {
+                    Err(TransferError::Parity)
+                }
+            }
+            ACK_OK => {
+                self.turnaround_to_probe();
+                self.write_bits(*data, 32);
+                self.write_bits(parity(*data) as u32, 1);
+                Ok(())
+            }
+            ACK_WAIT | ACK_FAULT => {
+                if configuration.data_phase && read {
+                    self.read_bits(32);
+                    self.clock();
+                }
+                self.turnaround_to_probe();
+                if configuration.data_phase && !read {
+                    self.write_bits(0, 32);
+                    self.write_bits(0, 1);
+                }
+                Err(if ack == ACK_WAIT {
+                    TransferError::Wait
+                } else {
+                    TransferError::Fault
+                })
+            }
+            _ => {
+                // Nobody answered, or the answer was garbled. Clock the
+                // longest data phase the target may still send.
+                self.read_bits(32);
+                self.clock();
+                self.turnaround_to_probe();
+                Err(TransferError::NoAck)
+            }
+        };
+
+        self.write_bits(0, configuration.idle_cycles as usize);
+        self.swdio.set();
+        result
+    }
+
+    fn set_reset(&self, asserted: bool) -> Result<(), ErrorCode> {
+        let reset = self.reset.ok_or(ErrorCode::NOSUPPORT)?;
+        // The reset line is open drain: driven low, or left to its pull-up.
+        if asserted {
+            reset.clear();
+            reset.make_output();
+        } else {
+            reset.make_input();
+            reset.set_floating_state(gpio::FloatingState::PullUp);
+        }
+        Ok(())
+    }
+}
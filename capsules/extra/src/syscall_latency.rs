@@ -0,0 +1,99 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Syscall latency histograms on the process console.
+//!
+//! Shows the histograms of a `kernel::syscall_latency::LatencyHistograms`,
+//! such as `kernel::syscall_latency::SyscallLatencyHistograms`, with the
+//! `latency` command. Each line is a driver number and syscall class, its
+//! number of syscalls and longest latency, and the non-empty buckets as
+//! `<start>us:<count>`, where `start` is the shortest latency of the
+//! bucket. `latency reset` clears the histograms.
+//!
+//! ```text
+//! tock$ latency
+//! 0x00000 command   n=12 max=61us 0us:2 16us:1 32us:9
+//! 0x00001 allow     n=2 max=30us 16us:2
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let latency_console = static_init!(
+//!     capsules_extra::syscall_latency::SyscallLatencyConsole<'static>,
+//!     capsules_extra::syscall_latency::SyscallLatencyConsole::new(latency)
+//! );
+//! ```
+
+use core::fmt;
+
+use capsules_core::process_console::ConsoleCommand;
+use kernel::syscall_latency::{Histogram, LatencyHistograms, SyscallClass, BUCKETS};
+
+pub struct SyscallLatencyConsole<'a> {
+    histograms: &'a dyn LatencyHistograms,
+}
+
+impl<'a> SyscallLatencyConsole<'a> {
+    pub fn new(histograms: &'a dyn LatencyHistograms) -> Self {
+        SyscallLatencyConsole { histograms }
+    }
+}
+
+impl<'a> ConsoleCommand for SyscallLatencyConsole<'a> {
+    fn name(&self) -> &'static str {
+        "latency"
+    }
+
+    fn execute(&self, args: &str, out: &mut dyn fmt::Write) {
+        if args.trim() == "reset" {
+            self.histograms.reset();
+            let _ = write!(out, "Syscall latency histograms reset\r\n");
+            return;
+        }
+
+        let mut index = 0;
+        while let Some(driver_number) = self.histograms.driver_number(index) {
+            for class in SyscallClass::ALL {
+                let histogram = match self.histograms.histogram(index, class) {
+                    Some(histogram) => histogram,
+                    None => continue,
+                };
+                let total: u32 = histogram.counts.iter().map(|&count| count as u32).sum();
+                if total == 0 {
+                    continue;
+                }
+                let _ = write!(
+                    out,
+                    "{:#07x} {:<9} n={} max={}us",
+                    driver_number,
+                    class.name(),
+                    total,
+                    histogram.max_us
+                );
+                for bucket in (0..BUCKETS).filter(|&bucket| histogram.counts[bucket] > 0) {
+                    let _ = write!(
+                        out,
+                        " {}us:{}",
+                        Histogram::bucket_start_us(bucket),
+                        histogram.counts[bucket]
+                    );
+                }
+                let _ = write!(out, "\r\n");
+            }
+            index += 1;
+        }
+        if index == 0 {
+            let _ = write!(out, "No syscalls recorded\r\n");
+        }
+        if self.histograms.untracked() > 0 {
+            let _ = write!(
+                out,
+                "{} syscalls of other drivers not recorded\r\n",
+                self.histograms.untracked()
+            );
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Information about the system a process runs on.
+//!
+//! Lets processes read the version of the kernel, the name of the board,
+//! the unique ID of the chip, the cause of the last reset, the uptime, and
+//! the name and version of every loaded application (from their TBF
+//! headers), so that a deployed application can report what it is running
+//! on.
+//!
+//! The uptime is counted from when the driver is started, early during
+//! boot, and does not wrap: an alarm fires every half period of the timer
+//! to accumulate the elapsed ticks into a 64-bit count.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let system_info = components::system_info::SystemInfoComponent::new(
+//!     board_kernel,
+//!     capsules_extra::system_info::DRIVER_NUM,
+//!     mux_alarm,
+//!     "nrf52840dk",
+//!     Some(&nrf52840::ficr::FICR_INSTANCE),
+//!     Some(&base_peripherals.pwr_clk),
+//! )
+//! .finalize(components::system_info_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! Strings and the chip ID are copied into read-write allow 0, truncated to
+//! its length. The commands return the full length, so a process can tell
+//! whether its buffer was too short.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::device_id::{DeviceId, MAX_ID_LEN};
+use kernel::hil::reset::{ResetReason, ResetReasonSource};
+use kernel::hil::time::{self, Frequency, Ticks};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SystemInfo as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const BUFFER: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+pub struct SystemInfo<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    apps: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    alarm: &'a A,
+    board_name: &'static str,
+    device_id: Option<&'a dyn DeviceId>,
+    reset_reason: ResetReason,
+    /// Ticks of the alarm counted up to `last`.
+    uptime_ticks: Cell<u64>,
+    last: Cell<A::Ticks>,
+    capability: C,
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> SystemInfo<'a, A, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<(), UpcallCount<0>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+        alarm: &'a A,
+        board_name: &'static str,
+        device_id: Option<&'a dyn DeviceId>,
+        reset_reason: Option<&'a dyn ResetReasonSource>,
+        capability: C,
+    ) -> Self {
+        SystemInfo {
+            kernel,
+            apps: grant,
+            alarm,
+            board_name,
+            device_id,
+            reset_reason: reset_reason.map_or(ResetReason::Unknown, |source| source.reset_reason()),
+            uptime_ticks: Cell::new(0),
+            last: Cell::new(alarm.now()),
+            capability,
+        }
+    }
+
+    /// Start counting the uptime.
+    pub fn start(&self) {
+        self.last.set(self.alarm.now());
+        self.alarm.set_alarm(self.last.get(), Self::period());
+    }
+
+    /// Interval of the alarm, short enough that the elapsed ticks fit into
+    /// a u32 and the timer does not wrap in between.
+    fn period() -> A::Ticks {
+        A::Ticks::half_max_value().min(A::Ticks::from(1 << 31))
+    }
+
+    /// Add the ticks elapsed since the last update to the uptime.
+    fn update_uptime(&self) -> u64 {
+        let now = self.alarm.now();
+        let elapsed = now.wrapping_sub(self.last.get());
+        self.last.set(now);
+        let uptime = self.uptime_ticks.get() + elapsed.into_u32() as u64;
+        self.uptime_ticks.set(uptime);
+        uptime
+    }
+
+    /// Copy `data` into the read-write buffer of `processid`.
+    fn copy_out(&self, processid: ProcessId, data: &[u8]) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::BUFFER)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| {
+                            let len = data.len().min(buffer.len());
+                            buffer[..len].copy_from_slice(&data[..len]);
+                        })
+                    })
+                    .map_err(ErrorCode::from)
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+    }
+
+    /// Copy `data` into the read-write buffer of `processid`, and return
+    /// the length of `data`.
+    fn copy_out_len(&self, processid: ProcessId, data: &[u8]) -> CommandReturn {
+        match self.copy_out(processid, data) {
+            Ok(()) => CommandReturn::success_u32(data.len() as u32),
+            Err(err) => CommandReturn::failure(err),
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> time::AlarmClient
+    for SystemInfo<'a, A, C>
+{
+    fn alarm(&self) {
+        self.update_uptime();
+        self.alarm.set_alarm(self.last.get(), Self::period());
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> SyscallDriver
+    for SystemInfo<'a, A, C>
+{
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Copy the kernel version into the buffer and return its length.
+    /// - `2`: Copy the board name into the buffer and return its length.
+    /// - `3`: Copy the unique ID of the chip into the buffer and return its
+    ///   length. Returns `NOSUPPORT` if the chip has no unique ID.
+    /// - `4`: Return the cause of the last reset: 0 unknown, 1 power on,
+    ///   2 reset pin, 3 watchdog, 4 software, 5 lockup, 6 brownout and
+    ///   7 wakeup from a low power mode.
+    /// - `5`: Return the uptime in microseconds, as a u64.
+    /// - `6`: Return the number of loaded applications.
+    /// - `7`: Copy the name of application number `data1` into the buffer,
+    ///   and return the length of the name and the version of the
+    ///   application. Returns `INVAL` if there are fewer applications.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                let version = option_env!("TOCK_KERNEL_VERSION").unwrap_or("unknown");
+                self.copy_out_len(processid, version.as_bytes())
+            }
+
+            2 => self.copy_out_len(processid, self.board_name.as_bytes()),
+
+            3 => match self.device_id {
+                Some(device_id) => {
+                    let mut id = [0; MAX_ID_LEN];
+                    let len = device_id.unique_id(&mut id);
+                    self.copy_out_len(processid, &id[..len])
+                }
+                None => CommandReturn::failure(ErrorCode::NOSUPPORT),
+            },
+
+            4 => CommandReturn::success_u32(self.reset_reason as u32),
+
+            5 => {
+                let ticks = self.update_uptime();
+                let frequency = A::Frequency::frequency() as u64;
+                // Split the conversion so it does not overflow.
+                let us =
+                    (ticks / frequency) * 1_000_000 + (ticks % frequency) * 1_000_000 / frequency;
+                CommandReturn::success_u64(us)
+            }
+
+            6 => {
+                let mut count = 0;
+                self.kernel
+                    .process_each_capability(&self.capability, |_| count += 1);
+                CommandReturn::success_u32(count)
+            }
+
+            7 => {
+                let mut index = 0;
+                let mut application = None;
+                self.kernel
+                    .process_each_capability(&self.capability, |process| {
+                        if index == data1 {
+                            application =
+                                Some((process.get_process_name(), process.binary_version()));
+                        }
+                        index += 1;
+                    });
+                match application {
+                    Some((name, version)) => match self.copy_out(processid, name.as_bytes()) {
+                        Ok(()) => CommandReturn::success_u32_u32(name.len() as u32, version),
+                        Err(err) => CommandReturn::failure(err),
+                    },
+                    None => CommandReturn::failure(ErrorCode::INVAL),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
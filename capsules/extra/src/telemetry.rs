@@ -0,0 +1,278 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Telemetry record mapped read-only into processes.
+//!
+//! The kernel keeps a small record of the health of the system (uptime,
+//! battery voltage, last error) up to date in memory that processes can
+//! read. A process maps the record once with a command, which adds an MPU
+//! region covering it to the process, and afterwards reads it directly, so
+//! it can include health data in every message it sends without any
+//! syscall.
+//!
+//! The uptime is refreshed every `period` milliseconds. The battery
+//! voltage and errors are reported by other capsules through [`Report`].
+//!
+//! Record Layout
+//! -------------
+//!
+//! The record is 8 little-endian `u32` words:
+//!
+//! | Word | Contents                                                     |
+//! |------|--------------------------------------------------------------|
+//! | 0    | Version (1) in the lower 16 bits, record length in the upper |
+//! | 1    | Sequence number, odd while the record is being written       |
+//! | 2, 3 | Uptime in milliseconds, lower and upper 32 bits              |
+//! | 4    | Battery voltage in millivolts, 0 if unknown                  |
+//! | 5    | Code of the last error, 0 if none                            |
+//! | 6    | Lower 32 bits of the uptime in milliseconds at the last error|
+//! | 7    | Number of errors                                             |
+//!
+//! A process may be interrupted while it reads the record, and the kernel
+//! may update it meanwhile. Readers copy the record and then check that the
+//! sequence number is even and did not change, otherwise they copy it
+//! again. Fields are only added at the end, with a new version.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let telemetry = components::telemetry::TelemetryComponent::new(
+//!     board_kernel,
+//!     capsules_extra::telemetry::DRIVER_NUM,
+//!     mux_alarm,
+//!     1000,
+//! )
+//! .finalize(components::telemetry_component_static!(
+//!     nrf52840::rtc::Rtc<'static>
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! - Command `0`: Driver existence check.
+//! - Command `1`: Map the record into the process, and return its address
+//!   and length. Returns `NOMEM` if the process has no MPU region left.
+
+use core::cell::Cell;
+
+use kernel::capabilities::ProcessManagementCapability;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::time::{self, Frequency, Ticks};
+use kernel::platform::mpu;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::VolatileCell;
+use kernel::utilities::time_conversion::{self, Rounding};
+use kernel::{ErrorCode, Kernel, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Telemetry as usize;
+
+/// Version of the layout of the record.
+pub const VERSION: u16 = 1;
+
+const WORDS: usize = 8;
+
+mod word {
+    pub const HEADER: usize = 0;
+    pub const SEQUENCE: usize = 1;
+    pub const UPTIME_LOW: usize = 2;
+    pub const UPTIME_HIGH: usize = 3;
+    pub const BATTERY: usize = 4;
+    pub const LAST_ERROR: usize = 5;
+    pub const LAST_ERROR_TIME: usize = 6;
+    pub const ERRORS: usize = 7;
+}
+
+/// The memory of the record. It is aligned to its size, so an MPU region
+/// can cover exactly it.
+#[repr(C, align(32))]
+pub struct TelemetryRecord {
+    words: [VolatileCell<u32>; WORDS],
+}
+
+impl TelemetryRecord {
+    pub const fn new() -> Self {
+        TelemetryRecord {
+            words: [
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+                VolatileCell::new(0),
+            ],
+        }
+    }
+}
+
+/// Reports values for the telemetry record.
+pub trait Report {
+    /// Set the battery voltage in millivolts.
+    fn set_battery_voltage(&self, millivolts: u32);
+
+    /// Record an error. `code` is any non-zero value meaningful to the
+    /// readers, e.g. an `ErrorCode` or a board specific code.
+    fn record_error(&self, code: u32);
+}
+
+#[derive(Default)]
+pub struct App {
+    mapped: bool,
+}
+
+pub struct Telemetry<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> {
+    kernel: &'static Kernel,
+    apps: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+    alarm: &'a A,
+    record: &'a TelemetryRecord,
+    /// Interval of the updates of the uptime.
+    period: A::Ticks,
+    /// Ticks of the alarm counted up to `last`.
+    uptime_ticks: Cell<u64>,
+    last: Cell<A::Ticks>,
+    capability: C,
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> Telemetry<'a, A, C> {
+    pub fn new(
+        kernel: &'static Kernel,
+        grant: Grant<App, UpcallCount<0>, AllowRoCount<0>, AllowRwCount<0>>,
+        alarm: &'a A,
+        record: &'a TelemetryRecord,
+        period_ms: u32,
+        capability: C,
+    ) -> Self {
+        // The elapsed ticks must fit into a u32, and the timer must not wrap
+        // between two updates.
+        let max_period = A::Ticks::half_max_value().min(A::Ticks::from(1 << 31));
+        let period =
+            time_conversion::ticks_from_ms::<A::Frequency, A::Ticks>(period_ms, Rounding::Nearest)
+                .map_or(max_period, |period| period.min(max_period));
+        Telemetry {
+            kernel,
+            apps: grant,
+            alarm,
+            record,
+            period,
+            uptime_ticks: Cell::new(0),
+            last: Cell::new(alarm.now()),
+            capability,
+        }
+    }
+
+    /// Start updating the record.
+    pub fn start(&self) {
+        self.update(|record| {
+            record[word::HEADER].set(VERSION as u32 | ((WORDS * 4) as u32) << 16);
+        });
+        self.last.set(self.alarm.now());
+        self.alarm.set_alarm(self.last.get(), self.period);
+    }
+
+    /// Change the record with `f`, with the sequence number odd meanwhile.
+    fn update<F: FnOnce(&[VolatileCell<u32>; WORDS])>(&self, f: F) {
+        let sequence = &self.record.words[word::SEQUENCE];
+        sequence.set(sequence.get().wrapping_add(1));
+        f(&self.record.words);
+        sequence.set(sequence.get().wrapping_add(1));
+    }
+
+    /// The uptime in milliseconds.
+    fn uptime_ms(&self) -> u64 {
+        let now = self.alarm.now();
+        let elapsed = now.wrapping_sub(self.last.get());
+        self.last.set(now);
+        let ticks = self.uptime_ticks.get() + elapsed.into_u32() as u64;
+        self.uptime_ticks.set(ticks);
+        time_conversion::scale(ticks, 1000, A::Frequency::frequency(), Rounding::Down)
+            .unwrap_or(u64::MAX)
+    }
+
+    fn map(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        let mapped = self
+            .apps
+            .enter(processid, |app, _| app.mapped)
+            .map_err(ErrorCode::from)?;
+        if mapped {
+            return Ok(());
+        }
+
+        let address = self.record as *const TelemetryRecord as *const u8;
+        let len = core::mem::size_of::<TelemetryRecord>();
+        self.kernel.process_map_or_external(
+            Err(ErrorCode::INVAL),
+            processid,
+            |process| {
+                process
+                    .add_mpu_region_with_permissions(address, len, len, mpu::Permissions::ReadOnly)
+                    .map(|_| ())
+                    .ok_or(ErrorCode::NOMEM)
+            },
+            &self.capability,
+        )?;
+        let _ = self.apps.enter(processid, |app, _| app.mapped = true);
+        Ok(())
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> Report for Telemetry<'a, A, C> {
+    fn set_battery_voltage(&self, millivolts: u32) {
+        self.update(|record| record[word::BATTERY].set(millivolts));
+    }
+
+    fn record_error(&self, code: u32) {
+        let now = self.uptime_ms();
+        self.update(|record| {
+            record[word::LAST_ERROR].set(code);
+            record[word::LAST_ERROR_TIME].set(now as u32);
+            record[word::ERRORS].set(record[word::ERRORS].get().wrapping_add(1));
+        });
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> time::AlarmClient
+    for Telemetry<'a, A, C>
+{
+    fn alarm(&self) {
+        let uptime = self.uptime_ms();
+        self.update(|record| {
+            record[word::UPTIME_LOW].set(uptime as u32);
+            record[word::UPTIME_HIGH].set((uptime >> 32) as u32);
+        });
+        self.alarm.set_alarm(self.last.get(), self.period);
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, C: ProcessManagementCapability> SyscallDriver for Telemetry<'a, A, C> {
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.map(processid) {
+                Ok(()) => CommandReturn::success_u32_u32(
+                    self.record as *const TelemetryRecord as usize as u32,
+                    core::mem::size_of::<TelemetryRecord>() as u32,
+                ),
+                Err(err) => CommandReturn::failure(err),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
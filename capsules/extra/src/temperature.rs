@@ -26,7 +26,13 @@
 //! operation, currently the following cmd's are supported:
 //!
 //! * `0`: check whether the driver exist
-//! * `1`: read the temperature
+//! * `1`: read the temperature of sensor `data1`
+//! * `2`: return the number of sensors
+//! * `driver_info::COMMAND_NUM`: describe the driver as in
+//!   `capsules_core::driver_info`, with the number of sensors as its count
+//!
+//! The reading is passed to the upcall as described in
+//! `capsules_core::sensor_upcall`, in hundredths of degrees Celsius.
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
@@ -36,6 +42,7 @@
 //! * `ENOSUPPORT`: Invalid `cmd`.
 //! * `NOMEM`:     No sufficient memory available.
 //! * `INVAL`:     Invalid address of the buffer or other error.
+//! * `NODEVICE`:  There is no sensor `data1`.
 //!
 //! Usage
 //! -----
@@ -48,17 +55,27 @@
 //! let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
 //! let grant_temperature = board_kernel.create_grant(&grant_cap);
 //!
+//! let sensors = static_init!(
+//!     [&'static dyn kernel::hil::sensors::TemperatureDriver<'static>; 2],
+//!     [si7021, mcu_temp]
+//! );
 //! let temp = static_init!(
 //!        capsules::temperature::TemperatureSensor<'static>,
-//!        capsules::temperature::TemperatureSensor::new(si7021,
+//!        capsules::temperature::TemperatureSensor::new(sensors,
 //!                                                 board_kernel.create_grant(&grant_cap)));
 //!
 //! kernel::hil::sensors::TemperatureDriver::set_client(si7021, temp);
+//! kernel::hil::sensors::TemperatureDriver::set_client(mcu_temp, temp);
 //! ```
+//!
+//! Sensor `i` is element `i` of `sensors`. As only one reading is taken at a
+//! time, the sensors can share the capsule as their client.
 
 use core::cell::Cell;
 use core::convert::TryFrom;
 
+use capsules_core::driver_info::{self, DriverInfo};
+use capsules_core::sensor_upcall::{self, Unit};
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::syscall::{CommandReturn, SyscallDriver};
@@ -74,33 +91,41 @@ pub struct App {
 }
 
 pub struct TemperatureSensor<'a> {
-    driver: &'a dyn hil::sensors::TemperatureDriver<'a>,
+    drivers: &'a [&'a dyn hil::sensors::TemperatureDriver<'a>],
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     busy: Cell<bool>,
 }
 
 impl<'a> TemperatureSensor<'a> {
     pub fn new(
-        driver: &'a dyn hil::sensors::TemperatureDriver<'a>,
+        drivers: &'a [&'a dyn hil::sensors::TemperatureDriver<'a>],
         grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     ) -> TemperatureSensor<'a> {
         TemperatureSensor {
-            driver: driver,
+            drivers: drivers,
             apps: grant,
             busy: Cell::new(false),
         }
     }
 
-    fn enqueue_command(&self, processid: ProcessId) -> CommandReturn {
+    fn enqueue_command(&self, instance: usize, processid: ProcessId) -> CommandReturn {
+        let driver = match self.drivers.get(instance) {
+            Some(driver) => driver,
+            None => return CommandReturn::failure(ErrorCode::NODEVICE),
+        };
         self.apps
             .enter(processid, |app, _| {
                 if !self.busy.get() {
                     app.subscribed = true;
                     self.busy.set(true);
-                    let rcode = self.driver.read_temperature();
+                    let rcode = driver.read_temperature();
                     let eres = ErrorCode::try_from(rcode);
                     match eres {
-                        Ok(ecode) => CommandReturn::failure(ecode),
+                        Ok(ecode) => {
+                            app.subscribed = false;
+                            self.busy.set(false);
+                            CommandReturn::failure(ecode)
+                        }
                         _ => CommandReturn::success(),
                     }
                 } else {
@@ -113,17 +138,18 @@ impl<'a> TemperatureSensor<'a> {
 
 impl hil::sensors::TemperatureClient for TemperatureSensor<'_> {
     fn callback(&self, temp_val: Result<i32, ErrorCode>) {
-        if let Ok(temp_val) = temp_val {
-            // TODO: forward error conditions
-            for cntr in self.apps.iter() {
-                cntr.enter(|app, upcalls| {
-                    if app.subscribed {
-                        self.busy.set(false);
-                        app.subscribed = false;
-                        upcalls.schedule_upcall(0, (temp_val as usize, 0, 0)).ok();
-                    }
-                });
-            }
+        let args = match temp_val {
+            Ok(temp_val) => sensor_upcall::encode(Unit::DegreesCelsius, -2, &[temp_val]),
+            Err(e) => sensor_upcall::encode_error(e),
+        };
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, upcalls| {
+                if app.subscribed {
+                    self.busy.set(false);
+                    app.subscribed = false;
+                    upcalls.schedule_upcall(0, args).ok();
+                }
+            });
         }
     }
 }
@@ -132,7 +158,7 @@ impl SyscallDriver for TemperatureSensor<'_> {
     fn command(
         &self,
         command_num: usize,
-        _: usize,
+        instance: usize,
         _: usize,
         processid: ProcessId,
     ) -> CommandReturn {
@@ -140,8 +166,14 @@ impl SyscallDriver for TemperatureSensor<'_> {
             // check whether the driver exists!!
             0 => CommandReturn::success(),
 
+            // describe the driver
+            driver_info::COMMAND_NUM => DriverInfo::new(1).count(self.drivers.len()).into(),
+
             // read temperature
-            1 => self.enqueue_command(processid),
+            1 => self.enqueue_command(instance, processid),
+
+            // number of sensors
+            2 => CommandReturn::success_u32(self.drivers.len() as u32),
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
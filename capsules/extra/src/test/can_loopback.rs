@@ -0,0 +1,246 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Test the CAN peripheral and its driver in internal loopback mode.
+//!
+//! The peripheral receives the messages it sends without driving the bus, so
+//! the test needs no transceiver or second node. `MESSAGES` messages of
+//! increasing length are sent with different identifiers, and each received
+//! message is compared with the one sent. The peripheral is disabled again
+//! at the end.
+//!
+//! ```rust
+//! let can_test = static_init!(
+//!     capsules_extra::test::can_loopback::TestCanLoopback<'static, stm32f429zi::can::Can>,
+//!     capsules_extra::test::can_loopback::TestCanLoopback::new(
+//!         &peripherals.can1,
+//!         static_init!([u8; 8], [0; 8]),
+//!         static_init!([u8; 8], [0; 8]),
+//!     )
+//! );
+//! kernel::hil::can::Controller::set_client(&peripherals.can1, Some(can_test));
+//! kernel::hil::can::Transmit::set_client(&peripherals.can1, Some(can_test));
+//! kernel::hil::can::Receive::set_client(&peripherals.can1, Some(can_test));
+//! can_test.run(500_000);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::can::{self, STANDARD_CAN_PACKET_SIZE};
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Number of messages sent.
+pub const MESSAGES: usize = 8;
+
+const BASE_ID: u16 = 0x120;
+
+/// Byte `index` of message `message`.
+fn pattern(message: usize, index: usize) -> u8 {
+    (message * 16 + index) as u8 ^ 0xa5
+}
+
+pub struct TestCanLoopback<'a, C: can::Can> {
+    can: &'a C,
+    tx_buffer: TakeCell<'static, [u8; STANDARD_CAN_PACKET_SIZE]>,
+    rx_buffer: TakeCell<'static, [u8; STANDARD_CAN_PACKET_SIZE]>,
+    sent: Cell<usize>,
+    received: Cell<usize>,
+    failed: Cell<bool>,
+}
+
+impl<'a, C: can::Can> TestCanLoopback<'a, C> {
+    pub fn new(
+        can: &'a C,
+        tx_buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE],
+        rx_buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE],
+    ) -> Self {
+        TestCanLoopback {
+            can,
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            sent: Cell::new(0),
+            received: Cell::new(0),
+            failed: Cell::new(false),
+        }
+    }
+
+    pub fn run(&self, bitrate: u32) {
+        self.sent.set(0);
+        self.received.set(0);
+        self.failed.set(false);
+        let result = self
+            .can
+            .set_operation_mode(can::OperationMode::InternalLoopback)
+            .and_then(|()| self.can.set_bitrate(bitrate))
+            .and_then(|()| self.can.enable());
+        if let Err(e) = result {
+            debug!(
+                "CanLoopbackTest ERROR: failed to enable the peripheral: {:?}",
+                e
+            );
+        }
+    }
+
+    fn send_next(&self) {
+        let message = self.sent.get();
+        let len = message % STANDARD_CAN_PACKET_SIZE + 1;
+        let result = self
+            .tx_buffer
+            .take()
+            .map_or(Err(ErrorCode::NOMEM), |buffer| {
+                for (index, byte) in buffer.iter_mut().enumerate() {
+                    *byte = pattern(message, index);
+                }
+                self.can
+                    .send(can::Id::Standard(BASE_ID + message as u16), buffer, len)
+                    .map_err(|(e, buffer)| {
+                        self.tx_buffer.replace(buffer);
+                        e
+                    })
+            });
+        match result {
+            Ok(()) => self.sent.set(message + 1),
+            Err(e) => self.fail(format_args!("failed to send message {}: {:?}", message, e)),
+        }
+    }
+
+    fn fail(&self, reason: core::fmt::Arguments) {
+        debug!("CanLoopbackTest ERROR: {}", reason);
+        self.failed.set(true);
+        self.finish();
+    }
+
+    fn finish(&self) {
+        if let Err(e) = self.can.stop_receive() {
+            debug!("CanLoopbackTest ERROR: failed to stop receiving: {:?}", e);
+            let _ = self.can.disable();
+        }
+    }
+}
+
+impl<C: can::Can> can::ControllerClient for TestCanLoopback<'_, C> {
+    fn state_changed(&self, _state: can::State) {}
+
+    fn enabled(&self, status: Result<(), ErrorCode>) {
+        if let Err(e) = status {
+            debug!("CanLoopbackTest ERROR: enabling failed: {:?}", e);
+            return;
+        }
+        let result = self
+            .rx_buffer
+            .take()
+            .map_or(Err(ErrorCode::NOMEM), |buffer| {
+                self.can
+                    .start_receive_process(buffer)
+                    .map_err(|(e, buffer)| {
+                        self.rx_buffer.replace(buffer);
+                        e
+                    })
+            });
+        match result {
+            Ok(()) => self.send_next(),
+            Err(e) => {
+                debug!("CanLoopbackTest ERROR: failed to start receiving: {:?}", e);
+                let _ = self.can.disable();
+            }
+        }
+    }
+
+    fn disabled(&self, status: Result<(), ErrorCode>) {
+        if let Err(e) = status {
+            debug!("CanLoopbackTest ERROR: disabling failed: {:?}", e);
+        } else if !self.failed.get() {
+            debug!(
+                "CanLoopbackTest: {} messages received back, passed",
+                MESSAGES
+            );
+        }
+    }
+}
+
+impl<C: can::Can> can::TransmitClient<STANDARD_CAN_PACKET_SIZE> for TestCanLoopback<'_, C> {
+    fn transmit_complete(
+        &self,
+        status: Result<(), can::Error>,
+        buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE],
+    ) {
+        self.tx_buffer.replace(buffer);
+        if let Err(e) = status {
+            self.fail(format_args!(
+                "message {} not sent: {:?}",
+                self.sent.get() - 1,
+                e
+            ));
+        } else if !self.failed.get()
+            && self.sent.get() < MESSAGES
+            && self.received.get() == self.sent.get()
+        {
+            self.send_next();
+        }
+    }
+}
+
+impl<C: can::Can> can::ReceiveClient<STANDARD_CAN_PACKET_SIZE> for TestCanLoopback<'_, C> {
+    fn message_received(
+        &self,
+        id: can::Id,
+        buffer: &mut [u8; STANDARD_CAN_PACKET_SIZE],
+        len: usize,
+        status: Result<(), can::Error>,
+    ) {
+        if self.failed.get() {
+            return;
+        }
+        let message = self.received.get();
+        if let Err(e) = status {
+            return self.fail(format_args!("message {} not received: {:?}", message, e));
+        }
+        let expected_id = BASE_ID + message as u16;
+        let expected_len = message % STANDARD_CAN_PACKET_SIZE + 1;
+        match id {
+            can::Id::Standard(id) if id == expected_id => {}
+            _ => {
+                return self.fail(format_args!(
+                    "message {} has identifier {:?}, expected {:#x}",
+                    message, id, expected_id
+                ))
+            }
+        }
+        let len = len.min(STANDARD_CAN_PACKET_SIZE);
+        if len != expected_len
+            || buffer[..len]
+                .iter()
+                .enumerate()
+                .any(|(index, byte)| *byte != pattern(message, index))
+        {
+            return self.fail(format_args!(
+                "message {} has data {:02x?}, expected {} bytes",
+                message,
+                &buffer[..len],
+                expected_len
+            ));
+        }
+
+        self.received.set(message + 1);
+        if message + 1 == MESSAGES {
+            self.finish();
+        } else if self.tx_buffer.is_some() {
+            // Otherwise the next message is sent once the transmission of
+            // this one completes.
+            self.send_next();
+        }
+    }
+
+    fn stopped(&self, buffer: &'static mut [u8; STANDARD_CAN_PACKET_SIZE]) {
+        self.rx_buffer.replace(buffer);
+        if let Err(e) = self.can.disable() {
+            debug!(
+                "CanLoopbackTest ERROR: failed to disable the peripheral: {:?}",
+                e
+            );
+        }
+    }
+}
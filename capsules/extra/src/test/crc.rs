@@ -91,6 +91,18 @@ impl<'a, C: Crc<'a>> Client for TestCrc<'a, C> {
                     }
                     CrcOutput::Crc16CCITT(x) => {
                         debug!("CRC16CCITT: {:#x}", x);
+                        self.run_test(CrcAlgorithm::Crc16Modbus);
+                    }
+                    CrcOutput::Crc16Modbus(x) => {
+                        debug!("CRC16Modbus: {:#x}", x);
+                        self.run_test(CrcAlgorithm::Crc8Maxim);
+                    }
+                    CrcOutput::Crc8Maxim(x) => {
+                        debug!("CRC8Maxim: {:#x}", x);
+                        self.run_test(CrcAlgorithm::Crc16Xmodem);
+                    }
+                    CrcOutput::Crc16Xmodem(x) => {
+                        debug!("CRC16Xmodem: {:#x}", x);
                     }
                 }
             }
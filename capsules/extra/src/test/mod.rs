@@ -5,6 +5,7 @@
 pub mod aes;
 pub mod aes_ccm;
 pub mod aes_gcm;
+pub mod can_loopback;
 pub mod crc;
 pub mod kv_system;
 pub mod sha256;
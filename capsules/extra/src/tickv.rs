@@ -35,8 +35,19 @@
 //!
 //!    hil::flash
 //! ```
+//!
+//! Boards that reserve the flash page after the store as the spare region of
+//! TicKV can call `enable_compaction()` before `initialise()`. Once a garbage
+//! collection completes, the regions of the store are then compacted in the
+//! background, one region per deferred call, so that the space of deleted
+//! keys between valid keys is reclaimed as well. Other operations run
+//! between the regions. Boards can also start a compaction with
+//! `start_compaction()`. Each region is copied to the spare region before it
+//! is erased, and `initialise()` finishes a compaction that was interrupted
+//! by a power loss.
 
 use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::flash::{self, Flash};
 use kernel::hil::hasher::{self, Hasher};
 use kernel::hil::kv_system::{self, KVSystem};
@@ -53,6 +64,9 @@ enum Operation {
     AppendKey,
     InvalidateKey,
     GarbageCollect,
+    Compact,
+    /// Finishing an interrupted compaction before the init
+    Recover,
 }
 
 pub struct TickFSFlashCtrl<'a, F: Flash + 'static> {
@@ -138,6 +152,12 @@ pub struct TicKVStore<'a, F: Flash + 'static, H: Hasher<'a, 8>, const PAGE_SIZE:
     key_buf: TakeCell<'static, [u8; 8]>,
 
     client: OptionalCell<&'a dyn kv_system::Client<TicKVKeyType>>,
+
+    // Whether the page after the store is reserved for compaction.
+    compaction: Cell<bool>,
+    // The next region to compact, while a compaction is in progress.
+    compact_region: OptionalCell<usize>,
+    deferred_call: DeferredCall,
 }
 
 impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVStore<'a, F, H, PAGE_SIZE> {
@@ -166,10 +186,82 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVStore<'a, F, H
             unhashed_key_buf: TakeCell::empty(),
             key_buf: TakeCell::empty(),
             client: OptionalCell::empty(),
+            compaction: Cell::new(false),
+            compact_region: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Compact the store after garbage collections, using the flash page
+    /// after the store as the spare region. Must be called before
+    /// `initialise()`.
+    pub fn enable_compaction(&self) {
+        self.compaction.set(true);
+    }
+
+    /// Start compacting the regions of the store in the background, if
+    /// compaction is enabled.
+    pub fn start_compaction(&self) {
+        if self.compaction.get() && self.compact_region.is_none() {
+            self.compact_region.set(0);
+            self.deferred_call.set();
+        }
+    }
+
+    /// Continue the compaction with the next region.
+    fn compact_region_done(&self) {
+        self.operation.set(Operation::None);
+        self.compact_region.map(|region| *region += 1);
+        self.resume_compaction();
+    }
+
+    /// Continue the compaction if it waits for another operation to finish.
+    fn resume_compaction(&self) {
+        if self.compact_region.is_some() && self.operation.get() == Operation::None {
+            self.deferred_call.set();
+        }
+    }
+
+    /// Continue the compaction, or the init after a recovery, once a step
+    /// of it has completed with `ret`.
+    fn compaction_step(
+        &self,
+        ret: Result<tickv::success_codes::SuccessCode, tickv::error_codes::ErrorCode>,
+    ) {
+        match ret {
+            Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+            | Err(tickv::error_codes::ErrorCode::EraseNotReady(_))
+            | Err(tickv::error_codes::ErrorCode::WriteNotReady(_)) => {}
+            _ if self.operation.get() == Operation::Recover => {
+                // If the recovery fails the spare region is left as it is,
+                // and nothing is compacted until the next boot.
+                if ret.is_err() {
+                    self.compaction.set(false);
+                }
+                self.initialise_store();
+            }
+            Err(_) => {
+                // The spare region may hold the only complete copy of the
+                // region, leave it for the recovery at the next boot.
+                self.compaction.set(false);
+                self.compact_region.clear();
+                self.operation.set(Operation::None);
+            }
+            Ok(_) => self.compact_region_done(),
         }
     }
 
     pub fn initialise(&self) {
+        if self.compaction.get() {
+            self.operation.set(Operation::Recover);
+            let ret = self.tickv.recover_compaction();
+            self.compaction_step(ret);
+        } else {
+            self.initialise_store();
+        }
+    }
+
+    fn initialise_store(&self) {
         let _ret = self.tickv.initialise(0x7bc9f7ff4f76f244);
         self.operation.set(Operation::Init);
     }
@@ -177,7 +269,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVStore<'a, F, H
     fn complete_init(&self) {
         self.operation.set(Operation::None);
         match self.next_operation.get() {
-            Operation::None | Operation::Init => {}
+            Operation::None | Operation::Init | Operation::Compact | Operation::Recover => {}
             Operation::AppendKey => {
                 match self.append_key(
                     self.key_buffer.take().unwrap(),
@@ -315,11 +407,14 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                     self.client.map(|cb| {
                         cb.garbage_collect_complete(Ok(()));
                     });
+                    self.start_compaction();
                 }
                 _ => {}
             },
+            Operation::Compact | Operation::Recover => self.compaction_step(ret),
             _ => unreachable!(),
         }
+        self.resume_compaction();
     }
 
     fn write_complete(&self, pagebuffer: &'static mut F::Page, _error: flash::Error) {
@@ -349,8 +444,13 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                     cb.invalidate_key_complete(Ok(()), self.key_buffer.take().unwrap());
                 });
             }
+            Operation::Compact | Operation::Recover => {
+                let (ret, _) = self.tickv.continue_operation();
+                self.compaction_step(ret);
+            }
             _ => unreachable!(),
         }
+        self.resume_compaction();
     }
 
     fn erase_complete(&self, _error: flash::Error) {
@@ -375,11 +475,45 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                     self.client.map(|cb| {
                         cb.garbage_collect_complete(Ok(()));
                     });
+                    self.start_compaction();
                 }
                 _ => {}
             },
+            Operation::Compact | Operation::Recover => self.compaction_step(ret),
             _ => unreachable!(),
         }
+        self.resume_compaction();
+    }
+}
+
+impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> DeferredCallClient
+    for TicKVStore<'a, F, H, PAGE_SIZE>
+{
+    fn handle_deferred_call(&self) {
+        // Wait for other operations, the compaction is resumed when they
+        // complete.
+        if self.operation.get() != Operation::None {
+            return;
+        }
+        let region = match self.compact_region.extract() {
+            Some(region) => region,
+            None => return,
+        };
+        if region >= self.tickv.num_regions() {
+            self.compact_region.clear();
+            return;
+        }
+
+        self.operation.set(Operation::Compact);
+        let ret = self
+            .tickv
+            .compact_region(region)
+            .map(|_| tickv::success_codes::SuccessCode::Complete);
+        self.compaction_step(ret);
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
     }
 }
 
@@ -447,7 +581,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::Recover => {
                 // The init process is still occurring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::AppendKey);
@@ -493,7 +627,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::Recover => {
                 // The init process is still occurring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::GetKey);
@@ -531,7 +665,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::Recover => {
                 // The init process is still occurring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::InvalidateKey);
@@ -559,7 +693,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                     },
                 }
             }
-            Operation::Init => {
+            Operation::Init | Operation::Recover => {
                 // The init process is still occurring.
                 // We can save this request and start it after init
                 self.next_operation.set(Operation::GarbageCollect);
@@ -0,0 +1,160 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Export of kernel trace events to a host.
+//!
+//! Takes the events recorded by a `kernel::trace::TraceSource`, such as a
+//! `kernel::trace::TraceBuffer`, and sends them as text lines over a UART,
+//! which may be a Segger RTT channel or a console UART. The events are sent
+//! periodically, so recording an event stays cheap.
+//!
+//! The lines are:
+//!
+//! - `TRACE 1 <frequency>`: sent first, with the version of the format and
+//!   the frequency of the timestamps in Hertz, in decimal.
+//! - `E <timestamp> <kind> <arg0> <arg1>`: an event, with the timestamp and
+//!   the arguments in hexadecimal, and the `kernel::trace::EventKind` in
+//!   decimal.
+//! - `D <count>`: `count` events were overwritten before they were sent, in
+//!   decimal.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let trace_export = static_init!(
+//!     capsules_extra::trace_export::TraceExport<'static, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::trace_export::TraceExport::new(
+//!         trace, rtt, trace_alarm, &mut capsules_extra::trace_export::BUFFER
+//!     )
+//! );
+//! trace_alarm.set_alarm_client(trace_export);
+//! rtt.set_transmit_client(trace_export);
+//! trace_export.start();
+//! ```
+
+use core::cell::Cell;
+use core::fmt::Write;
+
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::trace::TraceSource;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+pub const BUF_LEN: usize = 256;
+pub static mut BUFFER: [u8; BUF_LEN] = [0; BUF_LEN];
+
+/// Interval between checks for new events, in milliseconds.
+const POLL_INTERVAL_MS: u32 = 100;
+/// Length of an `E` line with the largest kind.
+const EVENT_LINE_LEN: usize = 31;
+
+/// Writes into a buffer, and fails when it is full.
+struct Cursor<'b> {
+    buffer: &'b mut [u8],
+    used: usize,
+}
+
+impl<'b> Write for Cursor<'b> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let slice = self
+            .buffer
+            .get_mut(self.used..self.used + s.len())
+            .ok_or(core::fmt::Error)?;
+        slice.copy_from_slice(s.as_bytes());
+        self.used += s.len();
+        Ok(())
+    }
+}
+
+pub struct TraceExport<'a, A: Alarm<'a>> {
+    source: &'a dyn TraceSource,
+    uart: &'a dyn uart::Transmit<'a>,
+    alarm: &'a A,
+    buffer: TakeCell<'static, [u8]>,
+    header_sent: Cell<bool>,
+}
+
+impl<'a, A: Alarm<'a>> TraceExport<'a, A> {
+    pub fn new(
+        source: &'a dyn TraceSource,
+        uart: &'a dyn uart::Transmit<'a>,
+        alarm: &'a A,
+        buffer: &'static mut [u8],
+    ) -> Self {
+        TraceExport {
+            source,
+            uart,
+            alarm,
+            buffer: TakeCell::new(buffer),
+            header_sent: Cell::new(false),
+        }
+    }
+
+    pub fn start(&self) {
+        self.send();
+    }
+
+    /// Fill the buffer with as many lines as fit, and send them. Waits for
+    /// the next poll if there is nothing to send.
+    fn send(&self) {
+        let buffer = match self.buffer.take() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        let mut cursor = Cursor { buffer, used: 0 };
+
+        if !self.header_sent.get() {
+            let _ = writeln!(cursor, "TRACE 1 {}", self.source.frequency_hz());
+            self.header_sent.set(true);
+        }
+        let dropped = self.source.take_dropped();
+        if dropped > 0 {
+            let _ = writeln!(cursor, "D {}", dropped);
+        }
+        while cursor.buffer.len() - cursor.used >= EVENT_LINE_LEN {
+            match self.source.pop() {
+                Some(event) => {
+                    let _ = writeln!(
+                        cursor,
+                        "E {:08x} {} {:08x} {:08x}",
+                        event.timestamp, event.kind as u8, event.arg0, event.arg1
+                    );
+                }
+                None => break,
+            }
+        }
+
+        let Cursor { buffer, used } = cursor;
+        if used == 0 {
+            self.buffer.replace(buffer);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(POLL_INTERVAL_MS));
+        } else if let Err((_, buffer)) = self.uart.transmit_buffer(buffer, used) {
+            // The events in the buffer are lost, try again at the next poll.
+            self.buffer.replace(buffer);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(POLL_INTERVAL_MS));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for TraceExport<'a, A> {
+    fn alarm(&self) {
+        self.send();
+    }
+}
+
+impl<'a, A: Alarm<'a>> uart::TransmitClient for TraceExport<'a, A> {
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        _tx_len: usize,
+        _rval: Result<(), ErrorCode>,
+    ) {
+        self.buffer.replace(tx_buffer);
+        self.send();
+    }
+}
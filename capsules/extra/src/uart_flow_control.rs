@@ -0,0 +1,273 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! RTS/CTS flow control for UARTs without hardware support, using two GPIO
+//! pins.
+//!
+//! `SoftwareFlowControl` wraps a UART and implements the UART HIL itself. When
+//! configured with `hw_flow_control: true` it:
+//!
+//! - Asserts RTS (drives it low) while a receive is outstanding, and
+//!   deasserts it (drives it high) as soon as the receive completes, so the
+//!   peer stops sending while there is no buffer to put data in.
+//! - Holds back transmissions while CTS is deasserted (high) and starts them
+//!   once the peer pulls CTS low.
+//!
+//! Because CTS is only checked before a transmission starts, the peer can not
+//! pause a buffer half way through. Peers that need byte-level pausing
+//! should use a UART with hardware flow control instead.
+//!
+//! The underlying UART is always configured with `hw_flow_control: false`.
+//! With flow control disabled, RTS is left asserted and CTS is ignored.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let flow_control = static_init!(
+//!     capsules_extra::uart_flow_control::SoftwareFlowControl<
+//!         'static,
+//!         sam4l::usart::USART,
+//!         sam4l::gpio::GPIOPin,
+//!         sam4l::gpio::GPIOPin,
+//!     >,
+//!     capsules_extra::uart_flow_control::SoftwareFlowControl::new(
+//!         &sam4l::usart::USART2,
+//!         &sam4l::gpio::PA[11],
+//!         &sam4l::gpio::PA[12],
+//!     )
+//! );
+//! sam4l::usart::USART2.set_transmit_client(flow_control);
+//! sam4l::usart::USART2.set_receive_client(flow_control);
+//! sam4l::gpio::PA[12].set_client(flow_control);
+//! flow_control.initialize();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub struct SoftwareFlowControl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> {
+    uart: &'a U,
+    rts: &'a R,
+    cts: &'a C,
+    enabled: Cell<bool>,
+    receiving: Cell<bool>,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    /// Buffer waiting for the peer to assert CTS.
+    tx_pending: TakeCell<'static, [u8]>,
+    tx_pending_len: Cell<usize>,
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>>
+    SoftwareFlowControl<'a, U, R, C>
+{
+    pub fn new(uart: &'a U, rts: &'a R, cts: &'a C) -> Self {
+        SoftwareFlowControl {
+            uart,
+            rts,
+            cts,
+            enabled: Cell::new(false),
+            receiving: Cell::new(false),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            tx_pending: TakeCell::empty(),
+            tx_pending_len: Cell::new(0),
+        }
+    }
+
+    /// Set up the RTS and CTS pins. RTS starts asserted, since flow control is
+    /// disabled until `configure()` enables it.
+    pub fn initialize(&self) {
+        self.rts.make_output();
+        self.rts.clear();
+        self.cts.make_input();
+        self.cts.set_floating_state(gpio::FloatingState::PullUp);
+    }
+
+    fn clear_to_send(&self) -> bool {
+        !self.enabled.get() || !self.cts.read()
+    }
+
+    fn set_receiving(&self, receiving: bool) {
+        self.receiving.set(receiving);
+        // RTS is active low. Without flow control it stays asserted.
+        if receiving || !self.enabled.get() {
+            self.rts.clear();
+        } else {
+            self.rts.set();
+        }
+    }
+
+    fn transmit_pending(&self) {
+        if let Some(buffer) = self.tx_pending.take() {
+            self.cts.disable_interrupts();
+            let len = self.tx_pending_len.get();
+            if let Err((ecode, buffer)) = self.uart.transmit_buffer(buffer, len) {
+                self.tx_client
+                    .map(move |client| client.transmitted_buffer(buffer, 0, Err(ecode)));
+            }
+        }
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> uart::Configure
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.uart.configure(uart::Parameters {
+            hw_flow_control: false,
+            ..params
+        })?;
+        self.enabled.set(params.hw_flow_control);
+        self.set_receiving(self.receiving.get());
+        if self.clear_to_send() {
+            self.transmit_pending();
+        }
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> uart::Transmit<'a>
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_pending.is_some() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+
+        if self.clear_to_send() {
+            self.uart.transmit_buffer(tx_buffer, tx_len)
+        } else {
+            self.cts.enable_interrupts(gpio::InterruptEdge::FallingEdge);
+            // CTS may have been asserted before the interrupt was enabled.
+            if self.clear_to_send() {
+                self.cts.disable_interrupts();
+                return self.uart.transmit_buffer(tx_buffer, tx_len);
+            }
+            self.tx_pending.replace(tx_buffer);
+            self.tx_pending_len.set(tx_len);
+            Ok(())
+        }
+    }
+
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        if self.tx_pending.is_some() || !self.clear_to_send() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.uart.transmit_word(word)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        match self.tx_pending.take() {
+            Some(buffer) => {
+                // The buffer never reached the UART, so hand it back right
+                // away.
+                self.cts.disable_interrupts();
+                self.tx_client.map(move |client| {
+                    client.transmitted_buffer(buffer, 0, Err(ErrorCode::CANCEL))
+                });
+                Err(ErrorCode::BUSY)
+            }
+            None => self.uart.transmit_abort(),
+        }
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> uart::Receive<'a>
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.uart.receive_buffer(rx_buffer, rx_len).map(|()| {
+            self.set_receiving(true);
+        })
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_word().map(|()| {
+            self.set_receiving(true);
+        })
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_abort().map(|()| {
+            self.set_receiving(false);
+        })
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> uart::TransmitClient
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn transmitted_word(&self, rval: Result<(), ErrorCode>) {
+        self.tx_client.map(|client| client.transmitted_word(rval));
+    }
+
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_client
+            .map(move |client| client.transmitted_buffer(tx_buffer, tx_len, rval));
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> uart::ReceiveClient
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn received_word(&self, word: u32, rval: Result<(), ErrorCode>, error: uart::Error) {
+        self.set_receiving(false);
+        self.rx_client
+            .map(|client| client.received_word(word, rval, error));
+    }
+
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        error: uart::Error,
+    ) {
+        // Stop the peer before handing the buffer to the client. If the
+        // client receives again RTS is asserted again.
+        self.set_receiving(false);
+        self.rx_client
+            .map(move |client| client.received_buffer(rx_buffer, rx_len, rval, error));
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, R: gpio::Pin, C: gpio::InterruptPin<'a>> gpio::Client
+    for SoftwareFlowControl<'a, U, R, C>
+{
+    fn fired(&self) {
+        if self.clear_to_send() {
+            self.transmit_pending();
+        }
+    }
+}
@@ -0,0 +1,318 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Idle-line reception for UARTs without hardware support, using an alarm.
+//!
+//! `SoftwareIdleReceive` wraps a UART and implements the UART HIL itself,
+//! adding `hil::uart::ReceiveAdvanced`. `receive_automatic()` receives one
+//! byte at a time into a buffer of its own and restarts the alarm after every
+//! byte. Once the line has been idle for `interbyte_timeout` bit periods (at
+//! the baud rate last passed to `configure()`) the receive completes with
+//! the bytes received so far, the same as with hardware idle-line detection.
+//!
+//! This takes an interrupt per byte, so UARTs that can detect an idle line
+//! themselves (e.g. STM32F4 USARTs and nRF52 UARTEs) should be used directly
+//! instead. Everything else is passed through to the UART.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let idle_receive = static_init!(
+//!     capsules_extra::uart_idle::SoftwareIdleReceive<
+//!         'static,
+//!         sam4l::usart::USART,
+//!         VirtualMuxAlarm<'static, sam4l::ast::Ast>,
+//!     >,
+//!     capsules_extra::uart_idle::SoftwareIdleReceive::new(
+//!         &sam4l::usart::USART2,
+//!         virtual_alarm,
+//!         static_init!([u8; 1], [0; 1]),
+//!     )
+//! );
+//! sam4l::usart::USART2.set_transmit_client(idle_receive);
+//! sam4l::usart::USART2.set_receive_client(idle_receive);
+//! virtual_alarm.set_alarm_client(idle_receive);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::uart;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// A `receive_buffer()` or `receive_word()` passed to the UART.
+    Receiving,
+    /// A `receive_automatic()`, receiving one byte at a time.
+    Automatic,
+    /// The byte receive of a `receive_automatic()` is being aborted, either
+    /// because the line went idle or because the client aborted.
+    Aborting {
+        idle: bool,
+    },
+}
+
+pub struct SoftwareIdleReceive<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    baud_rate: Cell<u32>,
+    state: Cell<State>,
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    /// One byte buffer for the receives passed to the UART.
+    byte: TakeCell<'static, [u8]>,
+    /// Buffer of the client during a `receive_automatic()`.
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_count: Cell<usize>,
+    interbyte_timeout: Cell<u8>,
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> SoftwareIdleReceive<'a, U, A> {
+    /// `byte` must be at least one byte long.
+    pub fn new(uart: &'a U, alarm: &'a A, byte: &'static mut [u8]) -> Self {
+        SoftwareIdleReceive {
+            uart,
+            alarm,
+            baud_rate: Cell::new(0),
+            state: Cell::new(State::Idle),
+            tx_client: OptionalCell::empty(),
+            rx_client: OptionalCell::empty(),
+            byte: TakeCell::new(byte),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_count: Cell::new(0),
+            interbyte_timeout: Cell::new(0),
+        }
+    }
+
+    fn receive_byte(&self) -> Result<(), ErrorCode> {
+        let byte = self.byte.take().ok_or(ErrorCode::BUSY)?;
+        self.uart.receive_buffer(byte, 1).map_err(|(ecode, byte)| {
+            self.byte.replace(byte);
+            ecode
+        })
+    }
+
+    /// Restart the alarm for `interbyte_timeout` bit periods.
+    fn restart_alarm(&self) {
+        let bit_periods = self.interbyte_timeout.get() as u32;
+        let baud_rate = self.baud_rate.get().max(1);
+        let us = ((bit_periods * 1_000_000 + baud_rate - 1) / baud_rate).max(1);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_us(us));
+    }
+
+    fn complete(&self, rval: Result<(), ErrorCode>, error: uart::Error) {
+        let _ = self.alarm.disarm();
+        self.state.set(State::Idle);
+        let len = self.rx_count.get();
+        self.rx_buffer.take().map(|rx_buffer| {
+            self.rx_client
+                .map(move |client| client.received_buffer(rx_buffer, len, rval, error));
+        });
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::Configure for SoftwareIdleReceive<'a, U, A> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        self.uart.configure(params)?;
+        self.baud_rate.set(params.baud_rate);
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::Transmit<'a>
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.uart.transmit_buffer(tx_buffer, tx_len)
+    }
+
+    fn transmit_word(&self, word: u32) -> Result<(), ErrorCode> {
+        self.uart.transmit_word(word)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        self.uart.transmit_abort()
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::Receive<'a>
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        self.uart.receive_buffer(rx_buffer, rx_len).map(|()| {
+            self.state.set(State::Receiving);
+        })
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.uart.receive_word().map(|()| {
+            self.state.set(State::Receiving);
+        })
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => Ok(()),
+            State::Receiving => self.uart.receive_abort(),
+            State::Automatic => {
+                let _ = self.alarm.disarm();
+                self.state.set(State::Aborting { idle: false });
+                if self.uart.receive_abort().is_ok() {
+                    // The UART will not return the byte buffer, it was not
+                    // receiving.
+                    self.complete(Err(ErrorCode::CANCEL), uart::Error::Aborted);
+                }
+                Err(ErrorCode::BUSY)
+            }
+            State::Aborting { .. } => {
+                self.state.set(State::Aborting { idle: false });
+                Err(ErrorCode::BUSY)
+            }
+        }
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::ReceiveAdvanced<'a>
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn receive_automatic(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        interbyte_timeout: u8,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        if rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        if rx_len == 0 {
+            return Err((ErrorCode::INVAL, rx_buffer));
+        }
+
+        if let Err(ecode) = self.receive_byte() {
+            return Err((ecode, rx_buffer));
+        }
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_len.set(rx_len);
+        self.rx_count.set(0);
+        self.interbyte_timeout.set(interbyte_timeout);
+        // The alarm starts with the first byte.
+        self.state.set(State::Automatic);
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> time::AlarmClient
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn alarm(&self) {
+        if self.state.get() == State::Automatic {
+            self.state.set(State::Aborting { idle: true });
+            if self.uart.receive_abort().is_ok() {
+                self.complete(Ok(()), uart::Error::None);
+            }
+        }
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::TransmitClient
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn transmitted_word(&self, rval: Result<(), ErrorCode>) {
+        self.tx_client.map(|client| client.transmitted_word(rval));
+    }
+
+    fn transmitted_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+        rval: Result<(), ErrorCode>,
+    ) {
+        self.tx_client
+            .map(move |client| client.transmitted_buffer(tx_buffer, tx_len, rval));
+    }
+}
+
+impl<'a, U: uart::Uart<'a>, A: time::Alarm<'a>> uart::ReceiveClient
+    for SoftwareIdleReceive<'a, U, A>
+{
+    fn received_word(&self, word: u32, rval: Result<(), ErrorCode>, error: uart::Error) {
+        self.state.set(State::Idle);
+        self.rx_client
+            .map(|client| client.received_word(word, rval, error));
+    }
+
+    fn received_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        error: uart::Error,
+    ) {
+        let state = self.state.get();
+        if state == State::Receiving {
+            self.state.set(State::Idle);
+            self.rx_client
+                .map(move |client| client.received_buffer(rx_buffer, rx_len, rval, error));
+            return;
+        }
+
+        // This is the byte buffer of a `receive_automatic()`. A byte may
+        // still have arrived while the receive was being aborted.
+        if rx_len == 1 {
+            let count = self.rx_count.get();
+            self.rx_buffer.map(|buffer| buffer[count] = rx_buffer[0]);
+            self.rx_count.set(count + 1);
+        }
+        self.byte.replace(rx_buffer);
+
+        match state {
+            State::Aborting { idle: true } => self.complete(Ok(()), uart::Error::None),
+            State::Aborting { idle: false } => {
+                self.complete(Err(ErrorCode::CANCEL), uart::Error::Aborted)
+            }
+            _ if rval.is_err() => self.complete(rval, error),
+            _ if self.rx_count.get() == self.rx_len.get() => {
+                self.complete(Ok(()), uart::Error::None)
+            }
+            _ => {
+                self.restart_alarm();
+                if let Err(ecode) = self.receive_byte() {
+                    self.complete(Err(ecode), uart::Error::None);
+                }
+            }
+        }
+    }
+}
@@ -12,6 +12,7 @@ use core::cmp;
 use super::descriptors;
 use super::descriptors::Buffer64;
 use super::descriptors::CdcInterfaceDescriptor;
+use super::descriptors::Descriptor;
 use super::descriptors::EndpointAddress;
 use super::descriptors::EndpointDescriptor;
 use super::descriptors::InterfaceDescriptor;
@@ -207,23 +208,23 @@ impl<'a, U: hil::usb::UsbController<'a>, A: 'a + Alarm<'a>> CdcAcm<'a, U, A> {
             },
         ];
 
-        let cdc_descriptors: &mut [CdcInterfaceDescriptor] = &mut [
-            CdcInterfaceDescriptor {
+        let cdc_descriptors: &[&dyn Descriptor] = &[
+            &CdcInterfaceDescriptor {
                 subtype: descriptors::CdcInterfaceDescriptorSubType::Header,
                 field1: 0x10, // CDC
                 field2: 0x11, // CDC
             },
-            CdcInterfaceDescriptor {
+            &CdcInterfaceDescriptor {
                 subtype: descriptors::CdcInterfaceDescriptorSubType::CallManagement,
                 field1: 0x00, // Capabilities
                 field2: 0x01, // Data interface 1
             },
-            CdcInterfaceDescriptor {
+            &CdcInterfaceDescriptor {
                 subtype: descriptors::CdcInterfaceDescriptorSubType::AbstractControlManagement,
                 field1: 0x06, // Capabilities
                 field2: 0x00, // unused
             },
-            CdcInterfaceDescriptor {
+            &CdcInterfaceDescriptor {
                 subtype: descriptors::CdcInterfaceDescriptorSubType::Union,
                 field1: 0x00, // Interface 0
                 field2: 0x01, // Interface 1
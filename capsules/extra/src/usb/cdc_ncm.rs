@@ -0,0 +1,766 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Network Control Model (NCM) of the Communications Class Device over USB.
+//!
+//! This capsule presents the board to the host as a USB network interface
+//! (Ethernet-over-USB), which Linux, macOS and Windows 11 support with their
+//! built-in drivers. Ethernet frames are exchanged with the host inside NCM
+//! Transfer Blocks (NTBs), and passed to and from the kernel through
+//! `hil::ethernet::EthernetAdapter`.
+//!
+//! Frames from the host are accumulated into the receive NTB buffer and each
+//! datagram in it is passed to `EthernetAdapterClient::received_frame()`.
+//! Every transmitted frame is sent in an NTB of its own.
+//!
+//! The link is up once the host selects the alternate setting of the data
+//! interface that has the bulk endpoints, which is when it starts using the
+//! interface, and down after a bus reset or when it selects the setting
+//! without endpoints again.
+//!
+//! The host uses the MAC address in the fourth string for its side of the
+//! link, which must differ from the MAC address of the device passed to
+//! `new()`.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::CdcEthernetNetworkingDescriptor;
+use super::descriptors::CdcInterfaceDescriptor;
+use super::descriptors::CdcNcmDescriptor;
+use super::descriptors::Descriptor;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::RequestType;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::ethernet::{EthernetAdapterClient, MAC_ADDRESS_LEN, MAX_FRAME_LEN};
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::cells::VolatileCell;
+use kernel::ErrorCode;
+
+/// Endpoint for notifications to the host.
+const ENDPOINT_NOTIFY_NUM: usize = 1;
+/// Endpoint for NTBs to the host.
+const ENDPOINT_IN_NUM: usize = 2;
+/// Endpoint for NTBs from the host.
+const ENDPOINT_OUT_NUM: usize = 3;
+
+const N_ENDPOINTS: usize = 3;
+
+/// Interface number of the data interface.
+const DATA_INTERFACE: u16 = 1;
+
+/// Maximum size of the NTBs the host sends. The receive buffer must be this
+/// long.
+pub const NTB_OUT_MAX_SIZE: usize = 2048;
+/// Maximum size of the NTBs sent to the host. The transmit buffer must be
+/// this long.
+pub const NTB_IN_MAX_SIZE: usize = 2048;
+
+/// Bit rate reported to the host, that of a full speed device.
+const BIT_RATE: u32 = 12_000_000;
+
+/// "NCMH"
+const NTH16_SIGNATURE: u32 = 0x484d434e;
+/// "NCM0"
+const NDP16_SIGNATURE: u32 = 0x304d434e;
+const NTH16_LEN: usize = 12;
+/// Header of an NDP16 followed by one datagram pointer and the terminating
+/// null pointer.
+const NDP16_LEN: usize = 16;
+/// Offset of the datagram in the NTBs sent to the host, a multiple of the
+/// NDP alignment.
+const DATAGRAM_OFFSET: usize = NTH16_LEN + NDP16_LEN;
+/// NDPs followed in an NTB from the host.
+const MAX_NDPS: usize = 8;
+
+static LANGUAGES: &'static [u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+#[derive(PartialEq)]
+enum NcmCntrlMessage {
+    NotSupported,
+    SetEthernetPacketFilter = 0x43,
+    GetNtbParameters = 0x80,
+    GetNtbFormat = 0x83,
+    SetNtbFormat = 0x84,
+    GetNtbInputSize = 0x85,
+    SetNtbInputSize = 0x86,
+}
+
+impl From<u8> for NcmCntrlMessage {
+    fn from(num: u8) -> Self {
+        match num {
+            0x43 => NcmCntrlMessage::SetEthernetPacketFilter,
+            0x80 => NcmCntrlMessage::GetNtbParameters,
+            0x83 => NcmCntrlMessage::GetNtbFormat,
+            0x84 => NcmCntrlMessage::SetNtbFormat,
+            0x85 => NcmCntrlMessage::GetNtbInputSize,
+            0x86 => NcmCntrlMessage::SetNtbInputSize,
+            _ => NcmCntrlMessage::NotSupported,
+        }
+    }
+}
+
+/// States of the Control Endpoint related to CDC-NCM.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CtrlState {
+    /// No ongoing ctrl transaction.
+    Idle,
+    /// Host has sent a SET_NTB_INPUT_SIZE request.
+    SetNtbInputSize,
+    /// Host has selected an alternate setting of the data interface.
+    SetInterface(u8),
+}
+
+/// Notification to send to the host next.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Notification {
+    None,
+    ConnectionSpeedChange,
+    NetworkConnection,
+}
+
+pub struct CdcNcm<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    ctrl_state: Cell<CtrlState>,
+    /// Selected alternate setting of the data interface.
+    alt_setting: Cell<u8>,
+    notification: Cell<Notification>,
+    link_up: Cell<bool>,
+    mac_address: [u8; MAC_ADDRESS_LEN],
+    /// Maximum NTB size the host accepts.
+    ntb_input_size: Cell<usize>,
+
+    /// NTB being received from the host.
+    rx_ntb: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    /// The NTB being received did not fit into `rx_ntb`, and is dropped.
+    rx_overflow: Cell<bool>,
+
+    /// NTB being sent to the host.
+    tx_ntb: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    tx_offset: Cell<usize>,
+    /// A zero length packet needs to end the NTB.
+    tx_zlp: Cell<bool>,
+    tx_sequence: Cell<u16>,
+    /// The frame of the client while it is sent.
+    tx_frame: TakeCell<'static, [u8]>,
+    tx_frame_len: Cell<usize>,
+
+    client: OptionalCell<&'a dyn EthernetAdapterClient>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> CdcNcm<'a, U> {
+    /// `strings` are the manufacturer, product, serial number and the MAC
+    /// address of the host as 12 hexadecimal digits. `rx_ntb` must be
+    /// `NTB_OUT_MAX_SIZE` and `tx_ntb` `NTB_IN_MAX_SIZE` bytes long.
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 4],
+        mac_address: [u8; MAC_ADDRESS_LEN],
+        rx_ntb: &'static mut [u8],
+        tx_ntb: &'static mut [u8],
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [
+            InterfaceDescriptor {
+                interface_number: 0,
+                interface_class: 0x02,    // CDC communication
+                interface_subclass: 0x0d, // network control model (NCM)
+                interface_protocol: 0x00, // none
+                ..InterfaceDescriptor::default()
+            },
+            InterfaceDescriptor {
+                interface_number: 1,
+                alternate_setting: 0,
+                interface_class: 0x0a,    // CDC data
+                interface_subclass: 0x00, // none
+                interface_protocol: 0x01, // network transfer block
+                ..InterfaceDescriptor::default()
+            },
+            InterfaceDescriptor {
+                interface_number: 1,
+                alternate_setting: 1,
+                interface_class: 0x0a,    // CDC data
+                interface_subclass: 0x00, // none
+                interface_protocol: 0x01, // network transfer block
+                ..InterfaceDescriptor::default()
+            },
+        ];
+
+        let cdc_descriptors: &[&dyn Descriptor] = &[
+            &CdcInterfaceDescriptor {
+                subtype: descriptors::CdcInterfaceDescriptorSubType::Header,
+                field1: 0x10, // CDC 1.10
+                field2: 0x01, // CDC 1.10
+            },
+            &CdcInterfaceDescriptor {
+                subtype: descriptors::CdcInterfaceDescriptorSubType::Union,
+                field1: 0x00, // Interface 0
+                field2: 0x01, // Interface 1
+            },
+            &CdcEthernetNetworkingDescriptor {
+                mac_address_string: 4,
+                ethernet_statistics: 0,
+                max_segment_size: MAX_FRAME_LEN as u16,
+                number_mc_filters: 0,
+                number_power_filters: 0,
+            },
+            &CdcNcmDescriptor {
+                ncm_version: 0x0100,
+                network_capabilities: 0x00,
+            },
+        ];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[
+            &[EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_NOTIFY_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Interrupt,
+                max_packet_size: 16,
+                interval: 16,
+            }],
+            &[],
+            &[
+                EndpointDescriptor {
+                    endpoint_address: EndpointAddress::new_const(
+                        ENDPOINT_IN_NUM,
+                        TransferDirection::DeviceToHost,
+                    ),
+                    transfer_type: TransferType::Bulk,
+                    max_packet_size: 64,
+                    interval: 0,
+                },
+                EndpointDescriptor {
+                    endpoint_address: EndpointAddress::new_const(
+                        ENDPOINT_OUT_NUM,
+                        TransferDirection::HostToDevice,
+                    ),
+                    transfer_type: TransferType::Bulk,
+                    max_packet_size: 64,
+                    interval: 0,
+                },
+            ],
+        ];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    class: 0x2, // Class: CDC
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor {
+                    ..descriptors::ConfigurationDescriptor::default()
+                },
+                interfaces,
+                endpoints,
+                None, // No HID descriptor
+                Some(cdc_descriptors),
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [
+                Buffer64::default(),
+                Buffer64::default(),
+                Buffer64::default(),
+            ],
+            ctrl_state: Cell::new(CtrlState::Idle),
+            alt_setting: Cell::new(0),
+            notification: Cell::new(Notification::None),
+            link_up: Cell::new(false),
+            mac_address,
+            ntb_input_size: Cell::new(NTB_IN_MAX_SIZE),
+            rx_ntb: TakeCell::new(rx_ntb),
+            rx_len: Cell::new(0),
+            rx_overflow: Cell::new(false),
+            tx_ntb: TakeCell::new(tx_ntb),
+            tx_len: Cell::new(0),
+            tx_offset: Cell::new(0),
+            tx_zlp: Cell::new(false),
+            tx_sequence: Cell::new(0),
+            tx_frame: TakeCell::empty(),
+            tx_frame_len: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    #[inline]
+    fn buffer(&'a self, i: usize) -> &'a [VolatileCell<u8>; 64] {
+        &self.buffers[i - 1].buf
+    }
+
+    /// Apply the alternate setting of the data interface selected by the
+    /// host.
+    fn set_alt_setting(&'a self, alt_setting: u8) {
+        self.alt_setting.set(alt_setting);
+        self.rx_len.set(0);
+        self.rx_overflow.set(false);
+        if alt_setting == 1 {
+            // Tell the host the link is up, which it takes as the go ahead to
+            // use the interface.
+            self.notification.set(Notification::ConnectionSpeedChange);
+            self.controller().endpoint_resume_in(ENDPOINT_NOTIFY_NUM);
+        } else {
+            self.set_link_down();
+        }
+    }
+
+    fn set_link_down(&self) {
+        self.notification.set(Notification::None);
+        if self.link_up.replace(false) {
+            self.tx_frame.take().map(|frame| {
+                self.client.map(move |client| {
+                    client.transmit_done(frame, self.tx_frame_len.get(), Err(ErrorCode::OFF))
+                });
+            });
+            self.client.map(|client| client.link_changed(false));
+        }
+    }
+
+    /// Pass the datagrams of the NTB received from the host to the client.
+    fn receive_ntb(&self) {
+        let len = self.rx_len.get();
+        self.rx_len.set(0);
+        if self.rx_overflow.replace(false) {
+            return;
+        }
+        self.rx_ntb.map(|ntb| {
+            self.client.map(|client| {
+                for_each_datagram(&ntb[..len], |frame| client.received_frame(frame));
+            });
+        });
+    }
+
+    /// Put `len` bytes of `frame` into an NTB in `ntb`. Returns the length
+    /// of the NTB.
+    fn build_ntb(&self, ntb: &mut [u8], frame: &[u8], len: usize) -> usize {
+        let block_len = DATAGRAM_OFFSET + len;
+        let sequence = self.tx_sequence.get();
+        self.tx_sequence.set(sequence.wrapping_add(1));
+
+        // NTH16
+        ntb[0..4].copy_from_slice(&NTH16_SIGNATURE.to_le_bytes());
+        ntb[4..6].copy_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+        ntb[6..8].copy_from_slice(&sequence.to_le_bytes());
+        ntb[8..10].copy_from_slice(&(block_len as u16).to_le_bytes());
+        ntb[10..12].copy_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+
+        // NDP16 with a single datagram.
+        ntb[12..16].copy_from_slice(&NDP16_SIGNATURE.to_le_bytes());
+        ntb[16..18].copy_from_slice(&(NDP16_LEN as u16).to_le_bytes());
+        ntb[18..20].copy_from_slice(&0u16.to_le_bytes());
+        ntb[20..22].copy_from_slice(&(DATAGRAM_OFFSET as u16).to_le_bytes());
+        ntb[22..24].copy_from_slice(&(len as u16).to_le_bytes());
+        ntb[24..28].copy_from_slice(&[0; 4]);
+
+        ntb[DATAGRAM_OFFSET..block_len].copy_from_slice(&frame[..len]);
+        block_len
+    }
+}
+
+/// Read a little endian `u16` at `offset` of `buf`.
+fn read_u16(buf: &[u8], offset: usize) -> Option<usize> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+/// Read a little endian `u32` at `offset` of `buf`.
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Call `f` with every datagram of the NTB16 `ntb`. Datagrams outside of the
+/// NTB are skipped.
+fn for_each_datagram<F: FnMut(&[u8])>(ntb: &[u8], mut f: F) {
+    if read_u32(ntb, 0) != Some(NTH16_SIGNATURE) {
+        return;
+    }
+    let ntb = match read_u16(ntb, 8) {
+        Some(block_len) if block_len <= ntb.len() => &ntb[..block_len],
+        _ => return,
+    };
+
+    let mut ndp = read_u16(ntb, 10).unwrap_or(0);
+    for _ in 0..MAX_NDPS {
+        if ndp == 0 || read_u32(ntb, ndp) != Some(NDP16_SIGNATURE) {
+            return;
+        }
+        let ndp_len = read_u16(ntb, ndp + 4).unwrap_or(0);
+        let mut pointer = ndp + 8;
+        while pointer + 4 <= ndp + ndp_len {
+            match (read_u16(ntb, pointer), read_u16(ntb, pointer + 2)) {
+                (Some(0), _) | (_, Some(0)) | (None, _) | (_, None) => break,
+                (Some(index), Some(len)) => {
+                    if let Some(datagram) = ntb.get(index..index + len) {
+                        f(datagram);
+                    }
+                }
+            }
+            pointer += 4;
+        }
+        ndp = read_u16(ntb, ndp + 6).unwrap_or(0);
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CdcNcm<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_NOTIFY_NUM, self.buffer(ENDPOINT_NOTIFY_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Interrupt, ENDPOINT_NOTIFY_NUM);
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, self.buffer(ENDPOINT_IN_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, self.buffer(ENDPOINT_OUT_NUM));
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.alt_setting.set(0);
+        self.set_link_down();
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// The NCM class requests and the alternate setting of the data
+    /// interface are handled here, everything else by `ClientCtrl`.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        let setup_data = match descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf) {
+            Some(setup_data) => setup_data,
+            None => return self.client_ctrl.ctrl_setup(endpoint),
+        };
+
+        match setup_data.request_type.request_type() {
+            RequestType::Standard => match setup_data.get_standard_request() {
+                Some(descriptors::StandardRequest::SetInterface)
+                    if setup_data.index == DATA_INTERFACE =>
+                {
+                    if setup_data.value > 1 {
+                        return hil::usb::CtrlSetupResult::ErrGeneric;
+                    }
+                    self.ctrl_state
+                        .set(CtrlState::SetInterface(setup_data.value as u8));
+                    self.client_ctrl.ctrl_setup_out_data(endpoint)
+                }
+                Some(descriptors::StandardRequest::GetInterface { interface }) => {
+                    let alt_setting = match interface {
+                        DATA_INTERFACE => self.alt_setting.get(),
+                        _ => 0,
+                    };
+                    self.client_ctrl
+                        .ctrl_setup_in_data(endpoint, &[alt_setting], setup_data.length)
+                }
+                _ => self.client_ctrl.ctrl_setup(endpoint),
+            },
+            RequestType::Class => match NcmCntrlMessage::from(setup_data.request_code) {
+                NcmCntrlMessage::GetNtbParameters => {
+                    let mut parameters = [0; 28];
+                    parameters[0..2].copy_from_slice(&28u16.to_le_bytes());
+                    // NTB16 only.
+                    parameters[2..4].copy_from_slice(&1u16.to_le_bytes());
+                    parameters[4..8].copy_from_slice(&(NTB_IN_MAX_SIZE as u32).to_le_bytes());
+                    // Divisor and alignment of the datagrams and NDPs in
+                    // NTBs to the host.
+                    parameters[8..10].copy_from_slice(&4u16.to_le_bytes());
+                    parameters[12..14].copy_from_slice(&4u16.to_le_bytes());
+                    parameters[16..20].copy_from_slice(&(NTB_OUT_MAX_SIZE as u32).to_le_bytes());
+                    // Divisor and alignment of the datagrams and NDPs in
+                    // NTBs from the host.
+                    parameters[20..22].copy_from_slice(&4u16.to_le_bytes());
+                    parameters[24..26].copy_from_slice(&4u16.to_le_bytes());
+                    self.client_ctrl
+                        .ctrl_setup_in_data(endpoint, &parameters, setup_data.length)
+                }
+                NcmCntrlMessage::GetNtbFormat => {
+                    // NTB16
+                    self.client_ctrl
+                        .ctrl_setup_in_data(endpoint, &[0, 0], setup_data.length)
+                }
+                NcmCntrlMessage::GetNtbInputSize => {
+                    let size = (self.ntb_input_size.get() as u32).to_le_bytes();
+                    self.client_ctrl
+                        .ctrl_setup_in_data(endpoint, &size, setup_data.length)
+                }
+                NcmCntrlMessage::SetNtbInputSize => {
+                    self.ctrl_state.set(CtrlState::SetNtbInputSize);
+                    self.client_ctrl.ctrl_setup_out_data(endpoint)
+                }
+                NcmCntrlMessage::SetNtbFormat if setup_data.value != 0 => {
+                    // Only NTB16 is supported.
+                    hil::usb::CtrlSetupResult::ErrGeneric
+                }
+                NcmCntrlMessage::SetNtbFormat | NcmCntrlMessage::SetEthernetPacketFilter => {
+                    // All frames are passed to the client, so there is
+                    // nothing to filter.
+                    self.client_ctrl.ctrl_setup_out_data(endpoint)
+                }
+                NcmCntrlMessage::NotSupported => hil::usb::CtrlSetupResult::ErrNonstandardRequest,
+            },
+            _ => self.client_ctrl.ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        self.client_ctrl.ctrl_in(endpoint)
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        if self.ctrl_state.get() == CtrlState::SetNtbInputSize && packet_bytes >= 4 {
+            let buf = &self.client_ctrl.ctrl_buffer.buf;
+            let size = u32::from_le_bytes([buf[0].get(), buf[1].get(), buf[2].get(), buf[3].get()]);
+            self.ntb_input_size
+                .set(cmp::min(size as usize, NTB_IN_MAX_SIZE));
+        }
+
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        if let CtrlState::SetInterface(alt_setting) = self.ctrl_state.get() {
+            self.set_alt_setting(alt_setting);
+        }
+        self.ctrl_state.set(CtrlState::Idle);
+
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction.
+    fn packet_in(&'a self, transfer_type: TransferType, endpoint: usize) -> hil::usb::InResult {
+        match transfer_type {
+            TransferType::Interrupt => {
+                let packet = self.buffer(endpoint);
+                // Both notifications are sent to interface 0.
+                let mut notification = [0; 16];
+                notification[0] = 0xa1;
+                let len = match self.notification.get() {
+                    Notification::None => return hil::usb::InResult::Delay,
+                    Notification::ConnectionSpeedChange => {
+                        notification[1] = 0x2a; // CONNECTION_SPEED_CHANGE
+                        notification[6] = 8;
+                        // Downlink and uplink bit rates.
+                        notification[8..12].copy_from_slice(&BIT_RATE.to_le_bytes());
+                        notification[12..16].copy_from_slice(&BIT_RATE.to_le_bytes());
+                        16
+                    }
+                    Notification::NetworkConnection => {
+                        notification[1] = 0x00; // NETWORK_CONNECTION
+                        notification[2] = 1; // Connected
+                        8
+                    }
+                };
+                for i in 0..len {
+                    packet[i].set(notification[i]);
+                }
+                hil::usb::InResult::Packet(len)
+            }
+            TransferType::Bulk => {
+                if self.tx_frame.is_none() {
+                    return hil::usb::InResult::Delay;
+                }
+                let offset = self.tx_offset.get();
+                let remaining = self.tx_len.get() - offset;
+                if remaining > 0 {
+                    let packet = self.buffer(endpoint);
+                    let to_send = cmp::min(packet.len(), remaining);
+                    self.tx_ntb.map(|ntb| {
+                        for i in 0..to_send {
+                            packet[i].set(ntb[offset + i]);
+                        }
+                    });
+                    self.tx_offset.set(offset + to_send);
+                    hil::usb::InResult::Packet(to_send)
+                } else if self.tx_zlp.replace(false) {
+                    // The NTB is a multiple of the packet size, end the
+                    // transfer with a zero length packet.
+                    hil::usb::InResult::Packet(0)
+                } else {
+                    hil::usb::InResult::Delay
+                }
+            }
+            TransferType::Control | TransferType::Isochronous => hil::usb::InResult::Delay,
+        }
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        match transfer_type {
+            TransferType::Bulk => {
+                let packet = self.buffer(endpoint);
+                let packet_bytes = cmp::min(packet_bytes as usize, packet.len());
+                let rx_len = self.rx_len.get();
+
+                self.rx_ntb.map(|ntb| {
+                    if rx_len + packet_bytes > ntb.len() {
+                        self.rx_overflow.set(true);
+                    } else {
+                        for i in 0..packet_bytes {
+                            ntb[rx_len + i] = packet[i].get();
+                        }
+                    }
+                });
+                let rx_len = rx_len + packet_bytes;
+                self.rx_len.set(rx_len);
+
+                // An NTB ends with a short packet, or when the block length
+                // in its header has been received.
+                let block_len = self
+                    .rx_ntb
+                    .map_or(None, |ntb| read_u16(&ntb[..cmp::min(rx_len, ntb.len())], 8));
+                let complete = match block_len {
+                    Some(block_len) => rx_len >= block_len,
+                    None => false,
+                };
+                if complete || packet_bytes < packet.len() {
+                    if rx_len > 0 {
+                        self.receive_ntb();
+                    }
+                }
+
+                hil::usb::OutResult::Ok
+            }
+            TransferType::Control | TransferType::Isochronous | TransferType::Interrupt => {
+                hil::usb::OutResult::Ok
+            }
+        }
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        match endpoint {
+            ENDPOINT_NOTIFY_NUM => match self.notification.get() {
+                Notification::ConnectionSpeedChange => {
+                    self.notification.set(Notification::NetworkConnection);
+                    self.controller().endpoint_resume_in(ENDPOINT_NOTIFY_NUM);
+                }
+                Notification::NetworkConnection => {
+                    self.notification.set(Notification::None);
+                    if !self.link_up.replace(true) {
+                        self.client.map(|client| client.link_changed(true));
+                    }
+                }
+                Notification::None => {}
+            },
+            _ => {
+                if self.tx_offset.get() < self.tx_len.get() || self.tx_zlp.get() {
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                } else {
+                    self.tx_frame.take().map(|frame| {
+                        self.client.map(move |client| {
+                            client.transmit_done(frame, self.tx_frame_len.get(), Ok(()))
+                        });
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::ethernet::EthernetAdapter<'a> for CdcNcm<'a, U> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterClient) {
+        self.client.set(client);
+    }
+
+    fn mac_address(&self) -> [u8; MAC_ADDRESS_LEN] {
+        self.mac_address
+    }
+
+    fn link_up(&self) -> bool {
+        self.link_up.get()
+    }
+
+    fn transmit(
+        &self,
+        frame: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if !self.link_up.get() {
+            return Err((ErrorCode::OFF, frame));
+        }
+        if len > frame.len()
+            || len > MAX_FRAME_LEN
+            || DATAGRAM_OFFSET + len > self.ntb_input_size.get()
+        {
+            return Err((ErrorCode::SIZE, frame));
+        }
+        if self.tx_frame.is_some() {
+            return Err((ErrorCode::BUSY, frame));
+        }
+
+        let block_len = self.tx_ntb.map_or(0, |ntb| self.build_ntb(ntb, frame, len));
+        self.tx_len.set(block_len);
+        self.tx_offset.set(0);
+        self.tx_zlp
+            .set(block_len % 64 == 0 && block_len < self.ntb_input_size.get());
+        self.tx_frame_len.set(len);
+        self.tx_frame.replace(frame);
+
+        self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        Ok(())
+    }
+}
@@ -111,6 +111,26 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
         vendor_id: u16,
         product_id: u16,
         strings: &'static [&'static str; 3],
+    ) -> Self {
+        Self::new_with_report(
+            controller,
+            vendor_id,
+            product_id,
+            strings,
+            &HID_DESCRIPTOR,
+            &REPORT,
+        )
+    }
+
+    /// Create a HID device with the same 64 byte reports, but another report
+    /// descriptor, e.g. for other protocols with vendor-defined reports.
+    pub fn new_with_report(
+        controller: &'a U,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        hid_descriptor: &'static HIDDescriptor<'static>,
+        report: &'static ReportDescriptor<'static>,
     ) -> Self {
         let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
             interface_number: 0,
@@ -158,7 +178,7 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
                 },
                 interfaces,
                 endpoints,
-                Some(&HID_DESCRIPTOR),
+                Some(hid_descriptor),
                 None,
             );
 
@@ -167,8 +187,8 @@ impl<'a, U: hil::usb::UsbController<'a>> CtapHid<'a, U> {
                 controller,
                 device_descriptor_buffer,
                 other_descriptor_buffer,
-                Some(&HID_DESCRIPTOR),
-                Some(&REPORT),
+                Some(hid_descriptor),
+                Some(report),
                 LANGUAGES,
                 strings,
             ),
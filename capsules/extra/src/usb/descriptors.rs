@@ -11,7 +11,7 @@ use core::cmp::min;
 use core::convert::From;
 use core::fmt;
 
-use kernel::hil::usb::TransferType;
+use kernel::hil::usb::{IsoSynchronization, IsoUsage, TransferType};
 use kernel::utilities::cells::VolatileCell;
 
 // On Nordic, USB buffers must be 32-bit aligned, with a power-of-2 size. For
@@ -413,24 +413,9 @@ impl DescriptorBuffer {
     }
 }
 
-/// Transform descriptor structs into descriptor buffers that can be
-/// passed into the control endpoint handler. Each endpoint descriptor list
-/// corresponds to the matching index in the interface descriptor list. For
-/// example, if the interface descriptor list contains `[ID1, ID2, ID3]`,
-/// and the endpoint descriptors list is `[[ED1, ED2], [ED3, ED4, ED5],
-/// [ED6]]`, then the third interface descriptor (`ID3`) has one
-/// corresponding endpoint descriptor (`ED6`).
-pub fn create_descriptor_buffers(
-    device_descriptor: DeviceDescriptor,
-    mut configuration_descriptor: ConfigurationDescriptor,
-    interface_descriptor: &mut [InterfaceDescriptor],
-    endpoint_descriptors: &[&[EndpointDescriptor]],
-    hid_descriptor: Option<&HIDDescriptor>,
-    cdc_descriptor: Option<&[CdcInterfaceDescriptor]>,
-) -> (DeviceBuffer, DescriptorBuffer) {
-    // Create device descriptor buffer and fill.
+fn new_device_buffer() -> DeviceBuffer {
     // Cell doesn't implement Copy, so here we are.
-    let mut dev_buf = DeviceBuffer {
+    DeviceBuffer {
         buf: [
             Cell::default(),
             Cell::default(),
@@ -453,14 +438,14 @@ pub fn create_descriptor_buffers(
             Cell::default(),
         ],
         len: 0,
-    };
-    dev_buf.len = device_descriptor.write_to(&dev_buf.buf);
+    }
+}
 
-    // Create other descriptors buffer.
+fn new_descriptor_buffer() -> DescriptorBuffer {
     // For the moment, the Default trait is not implemented for arrays
     // of length > 32, and the Cell type is not Copy, so we have to
     // initialize each element manually.
-    let mut other_buf = DescriptorBuffer {
+    DescriptorBuffer {
         #[rustfmt::skip]
         buf: [
             Cell::default(), Cell::default(), Cell::default(), Cell::default(), Cell::default(),
@@ -491,14 +476,41 @@ pub fn create_descriptor_buffers(
             Cell::default(), Cell::default(), Cell::default(),
         ],
         len: 0,
-    };
+    }
+}
+
+/// Transform descriptor structs into descriptor buffers that can be
+/// passed into the control endpoint handler. Each endpoint descriptor list
+/// corresponds to the matching index in the interface descriptor list. For
+/// example, if the interface descriptor list contains `[ID1, ID2, ID3]`,
+/// and the endpoint descriptors list is `[[ED1, ED2], [ED3, ED4, ED5],
+/// [ED6]]`, then the third interface descriptor (`ID3`) has one
+/// corresponding endpoint descriptor (`ED6`).
+pub fn create_descriptor_buffers(
+    device_descriptor: DeviceDescriptor,
+    mut configuration_descriptor: ConfigurationDescriptor,
+    interface_descriptor: &mut [InterfaceDescriptor],
+    endpoint_descriptors: &[&[EndpointDescriptor]],
+    hid_descriptor: Option<&HIDDescriptor>,
+    cdc_descriptor: Option<&[&dyn Descriptor]>,
+) -> (DeviceBuffer, DescriptorBuffer) {
+    // Create device descriptor buffer and fill.
+    let mut dev_buf = new_device_buffer();
+    dev_buf.len = device_descriptor.write_to(&dev_buf.buf);
+
+    // Create other descriptors buffer.
+    let mut other_buf = new_descriptor_buffer();
 
     // Setup certain descriptor fields since now we know the tree of
     // descriptors.
 
     // Configuration Descriptor. We assume there is only one configuration
-    // descriptor, since this is very common for most USB devices.
-    configuration_descriptor.num_interfaces = interface_descriptor.len() as u8;
+    // descriptor, since this is very common for most USB devices. Alternate
+    // settings of an interface are not counted as separate interfaces.
+    configuration_descriptor.num_interfaces = interface_descriptor
+        .iter()
+        .filter(|d| d.alternate_setting == 0)
+        .count() as u8;
 
     // Calculate the length of all dependent descriptors.
     // TODO should we be erroring here if len > 128? Otherwise we'll probably
@@ -557,6 +569,32 @@ pub fn create_descriptor_buffers(
     (dev_buf, other_buf)
 }
 
+/// Transform descriptor structs into descriptor buffers, for classes whose
+/// class-specific descriptors do not all follow the first interface
+/// descriptor, e.g. audio and MIDI. The `descriptors` are written after the
+/// configuration descriptor in the order given. The caller sets the number
+/// of interfaces in the configuration descriptor, and the number of
+/// endpoints in the interface descriptors.
+pub fn create_descriptor_buffers_in_order(
+    device_descriptor: DeviceDescriptor,
+    mut configuration_descriptor: ConfigurationDescriptor,
+    descriptors: &[&dyn Descriptor],
+) -> (DeviceBuffer, DescriptorBuffer) {
+    let mut dev_buf = new_device_buffer();
+    dev_buf.len = device_descriptor.write_to(&dev_buf.buf);
+
+    let mut other_buf = new_descriptor_buffer();
+    configuration_descriptor.related_descriptor_length =
+        descriptors.iter().map(|d| d.size()).sum::<usize>();
+    let mut len = configuration_descriptor.write_to(&other_buf.buf);
+    for d in descriptors {
+        len += d.write_to(&other_buf.buf[len..]);
+    }
+    other_buf.len = min(len, other_buf.buf.len());
+
+    (dev_buf, other_buf)
+}
+
 pub struct ConfigurationDescriptor {
     pub num_interfaces: u8,
     pub configuration_value: u8,
@@ -707,6 +745,38 @@ impl Descriptor for EndpointDescriptor {
     }
 }
 
+/// Endpoint descriptor of an isochronous endpoint, which also carries the
+/// synchronization and usage types.
+pub struct IsoEndpointDescriptor {
+    pub endpoint_address: EndpointAddress,
+    pub synchronization: IsoSynchronization,
+    pub usage: IsoUsage,
+    pub max_packet_size: u16,
+    /// Transfer every 2^(interval - 1) frames; 1 for every frame.
+    pub interval: u8,
+}
+
+impl Descriptor for IsoEndpointDescriptor {
+    fn size(&self) -> usize {
+        7
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        let len = self.size();
+        buf[0].set(len as u8);
+        buf[1].set(DescriptorType::Endpoint as u8);
+        buf[2].set(self.endpoint_address.0);
+        buf[3].set(
+            TransferType::Isochronous as u8
+                | (self.synchronization as u8) << 2
+                | (self.usage as u8) << 4,
+        );
+        put_u16(&buf[4..6], self.max_packet_size & 0x7ff);
+        buf[6].set(self.interval);
+        len
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum HIDCountryCode {
     NotSupported = 0,
@@ -818,6 +888,7 @@ pub enum CdcInterfaceDescriptorSubType {
     CapiControlManagement = 0x0e,
     EthernetNetworking = 0x0f,
     AtmNetworking = 0x10,
+    Ncm = 0x1a,
 }
 
 pub struct CdcInterfaceDescriptor {
@@ -846,6 +917,7 @@ impl Descriptor for CdcInterfaceDescriptor {
             CdcInterfaceDescriptorSubType::CapiControlManagement => 1,
             CdcInterfaceDescriptorSubType::EthernetNetworking => 1,
             CdcInterfaceDescriptorSubType::AtmNetworking => 1,
+            CdcInterfaceDescriptorSubType::Ncm => 1,
         }
     }
 
@@ -864,6 +936,238 @@ impl Descriptor for CdcInterfaceDescriptor {
     }
 }
 
+/// Ethernet Networking functional descriptor of the CDC ECM and NCM models.
+pub struct CdcEthernetNetworkingDescriptor {
+    /// Index of the string holding the MAC address as 12 hex digits.
+    pub mac_address_string: u8,
+    pub ethernet_statistics: u32,
+    pub max_segment_size: u16,
+    pub number_mc_filters: u16,
+    pub number_power_filters: u8,
+}
+
+impl Descriptor for CdcEthernetNetworkingDescriptor {
+    fn size(&self) -> usize {
+        13
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(13);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(CdcInterfaceDescriptorSubType::EthernetNetworking as u8);
+        buf[3].set(self.mac_address_string);
+        put_u32(&buf[4..8], self.ethernet_statistics);
+        put_u16(&buf[8..10], self.max_segment_size);
+        put_u16(&buf[10..12], self.number_mc_filters);
+        buf[12].set(self.number_power_filters);
+        13
+    }
+}
+
+/// NCM functional descriptor.
+pub struct CdcNcmDescriptor {
+    /// Release of the NCM specification, 0x0100.
+    pub ncm_version: u16,
+    pub network_capabilities: u8,
+}
+
+impl Descriptor for CdcNcmDescriptor {
+    fn size(&self) -> usize {
+        6
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(6);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(CdcInterfaceDescriptorSubType::Ncm as u8);
+        put_u16(&buf[3..5], self.ncm_version);
+        buf[5].set(self.network_capabilities);
+        6
+    }
+}
+
+/// DFU functional descriptor, following the DFU interface descriptor.
+pub struct DfuFunctionalDescriptor {
+    /// Bit 0: can download, bit 1: can upload, bit 2: manifestation
+    /// tolerant, bit 3: will detach.
+    pub attributes: u8,
+    /// Time in ms the device waits for a reset after a `DFU_DETACH`.
+    pub detach_timeout: u16,
+    /// Maximum number of bytes per control write.
+    pub transfer_size: u16,
+    /// Release of the DFU specification, 0x0110.
+    pub dfu_version: u16,
+}
+
+impl Descriptor for DfuFunctionalDescriptor {
+    fn size(&self) -> usize {
+        9
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(9);
+        // DFU FUNCTIONAL, which has the same type as the HID descriptor.
+        buf[1].set(0x21);
+        buf[2].set(self.attributes);
+        put_u16(&buf[3..5], self.detach_timeout);
+        put_u16(&buf[5..7], self.transfer_size);
+        put_u16(&buf[7..9], self.dfu_version);
+        9
+    }
+}
+
+/// Standard endpoint descriptor of the audio class, with the two bytes for
+/// synchronization that audio and MIDI streaming endpoints carry.
+pub struct AudioEndpointDescriptor {
+    pub endpoint: EndpointDescriptor,
+    pub refresh: u8,
+    pub synch_address: u8,
+}
+
+impl Descriptor for AudioEndpointDescriptor {
+    fn size(&self) -> usize {
+        9
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        self.endpoint.write_to_unchecked(buf);
+        buf[0].set(9);
+        buf[7].set(self.refresh);
+        buf[8].set(self.synch_address);
+        9
+    }
+}
+
+/// Class-specific header of an audio control interface with one streaming
+/// interface.
+pub struct AudioControlHeaderDescriptor {
+    /// Release of the audio class specification, 0x0100.
+    pub audio_version: u16,
+    pub streaming_interface: u8,
+}
+
+impl Descriptor for AudioControlHeaderDescriptor {
+    fn size(&self) -> usize {
+        9
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(9);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(0x01); // HEADER
+        put_u16(&buf[3..5], self.audio_version);
+        // Total length of the class-specific descriptors, only this one.
+        put_u16(&buf[5..7], 9);
+        buf[7].set(1);
+        buf[8].set(self.streaming_interface);
+        9
+    }
+}
+
+/// Class-specific header of a MIDI streaming interface.
+pub struct MidiStreamingHeaderDescriptor {
+    /// Release of the MIDI class specification, 0x0100.
+    pub midi_version: u16,
+    /// Length of this and all following class-specific descriptors of the
+    /// interface, including those of its endpoints.
+    pub total_length: u16,
+}
+
+impl Descriptor for MidiStreamingHeaderDescriptor {
+    fn size(&self) -> usize {
+        7
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(7);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(0x01); // MS_HEADER
+        put_u16(&buf[3..5], self.midi_version);
+        put_u16(&buf[5..7], self.total_length);
+        7
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum MidiJackType {
+    /// Connected to a USB endpoint.
+    Embedded = 0x01,
+    /// Connected to the outside, e.g. a DIN connector or a synthesizer.
+    External = 0x02,
+}
+
+/// A MIDI IN jack, where MIDI data enters the function.
+pub struct MidiInJackDescriptor {
+    pub jack_type: MidiJackType,
+    pub jack_id: u8,
+}
+
+impl Descriptor for MidiInJackDescriptor {
+    fn size(&self) -> usize {
+        6
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(6);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(0x02); // MIDI_IN_JACK
+        buf[3].set(self.jack_type as u8);
+        buf[4].set(self.jack_id);
+        buf[5].set(0);
+        6
+    }
+}
+
+/// A MIDI OUT jack, where MIDI data leaves the function, with one source.
+pub struct MidiOutJackDescriptor {
+    pub jack_type: MidiJackType,
+    pub jack_id: u8,
+    /// The jack the data comes from.
+    pub source_id: u8,
+}
+
+impl Descriptor for MidiOutJackDescriptor {
+    fn size(&self) -> usize {
+        9
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(9);
+        buf[1].set(DescriptorType::CdcInterface as u8);
+        buf[2].set(0x03); // MIDI_OUT_JACK
+        buf[3].set(self.jack_type as u8);
+        buf[4].set(self.jack_id);
+        buf[5].set(1);
+        buf[6].set(self.source_id);
+        buf[7].set(1);
+        buf[8].set(0);
+        9
+    }
+}
+
+/// Class-specific descriptor of a MIDI streaming endpoint, following its
+/// standard endpoint descriptor.
+pub struct MidiEndpointDescriptor {
+    /// The embedded jack connected to the endpoint.
+    pub jack_id: u8,
+}
+
+impl Descriptor for MidiEndpointDescriptor {
+    fn size(&self) -> usize {
+        5
+    }
+
+    fn write_to_unchecked(&self, buf: &[Cell<u8>]) -> usize {
+        buf[0].set(5);
+        // CS_ENDPOINT
+        buf[1].set(0x25);
+        buf[2].set(0x01); // MS_GENERAL
+        buf[3].set(1);
+        buf[4].set(self.jack_id);
+        5
+    }
+}
+
 /// The data structure sent in a CDC-ACM Set Line Coding message.
 #[derive(Debug, Copy, Clone)]
 pub struct CdcAcmSetLineCodingData {
@@ -953,3 +1257,9 @@ fn put_u16<'a>(buf: &'a [Cell<u8>], n: u16) {
     buf[0].set((n & 0xff) as u8);
     buf[1].set((n >> 8) as u8);
 }
+
+/// Write a `u32` to a buffer for transmission on the bus
+fn put_u32<'a>(buf: &'a [Cell<u8>], n: u32) {
+    put_u16(&buf[0..2], (n & 0xffff) as u16);
+    put_u16(&buf[2..4], (n >> 16) as u16);
+}
@@ -0,0 +1,630 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Device Firmware Upgrade (DFU 1.1) class over USB.
+//!
+//! This lets the host flash the board with the standard `dfu-util`, without
+//! a debugger or a board-specific tool.
+//!
+//! The device enumerates with a DFU runtime interface. `dfu-util` sends a
+//! `DFU_DETACH` request to switch the device to DFU mode, in which it
+//! downloads the image with a series of `DFU_DNLOAD` requests:
+//!
+//! - If the board passes a `bootloader` function, the device calls it on
+//!   `DFU_DETACH`. It is expected to reset into a bootloader that
+//!   implements DFU mode itself, e.g. to flash kernel images.
+//! - Otherwise the device switches to DFU mode at the USB reset that the
+//!   host issues after `DFU_DETACH`, and re-enumerates with a DFU mode
+//!   interface. Images downloaded in this mode are TBF objects that are
+//!   passed to an [`AppUpdater`], e.g. `app_install::AppInstall` to load
+//!   new applications without a reset, or `app_update::AppUpdate` to
+//!   replace all applications at the next reset. The length of the image
+//!   is taken from its TBF header.
+//!
+//! ```shell
+//! dfu-util -D app.tbf
+//! ```
+//!
+//! After an image was installed, the next USB reset, e.g. with
+//! `dfu-util -R`, switches the device back to the runtime interface.
+//! Uploading images from the device is not supported.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::DfuFunctionalDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::RequestType;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use crate::app_update::{AppUpdater, AppUpdaterClient};
+use kernel::hil;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+static LANGUAGES: &'static [u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+/// Time in ms the host waits after a request that starts a flash operation
+/// before asking for the status again.
+const POLL_TIMEOUT_MS: u32 = 10;
+
+/// Time in ms the device waits for the reset after `DFU_DETACH`.
+const DETACH_TIMEOUT_MS: u16 = 1000;
+
+const ATTRIBUTE_CAN_DOWNLOAD: u8 = 1 << 0;
+const ATTRIBUTE_MANIFESTATION_TOLERANT: u8 = 1 << 2;
+const ATTRIBUTE_WILL_DETACH: u8 = 1 << 3;
+
+/// Class requests.
+const DFU_DETACH: u8 = 0;
+const DFU_DNLOAD: u8 = 1;
+const DFU_UPLOAD: u8 = 2;
+const DFU_GETSTATUS: u8 = 3;
+const DFU_CLRSTATUS: u8 = 4;
+const DFU_GETSTATE: u8 = 5;
+const DFU_ABORT: u8 = 6;
+
+/// Device states reported to the host.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum DfuState {
+    AppIdle = 0,
+    AppDetach = 1,
+    DfuIdle = 2,
+    DownloadSync = 3,
+    DownloadBusy = 4,
+    DownloadIdle = 5,
+    ManifestSync = 6,
+    Manifest = 7,
+    Error = 10,
+}
+
+/// Status codes reported to the host.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum DfuStatus {
+    Ok = 0x00,
+    /// The file is not targeted for this device.
+    ErrTarget = 0x01,
+    /// The file failed a verification.
+    ErrFile = 0x02,
+    ErrWrite = 0x03,
+    /// The installed image failed its credential checks.
+    ErrVerify = 0x07,
+    /// The image does not fit.
+    ErrAddress = 0x08,
+    /// A `DFU_DNLOAD` without data came before all of the image.
+    ErrNotDone = 0x09,
+    ErrUnknown = 0x0e,
+    /// The request is not valid in the current state.
+    ErrStalledPacket = 0x0f,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Runtime,
+    Dfu,
+}
+
+/// Control transfer in progress.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum CtrlState {
+    Idle,
+    Detach,
+    /// Receiving a block of the given length.
+    Download(usize),
+}
+
+/// Progress of the installation of a downloaded image.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Manifestation {
+    NotStarted,
+    Running,
+    Done,
+}
+
+pub struct Dfu<'a, U: 'a> {
+    /// Handlers of the default control endpoint, with the descriptors of
+    /// the runtime and of the DFU mode interface.
+    runtime_ctrl: ClientCtrl<'a, 'static, U>,
+    dfu_ctrl: ClientCtrl<'a, 'static, U>,
+    mode: Cell<Mode>,
+
+    updater: &'a dyn AppUpdater<'a>,
+    bootloader: Option<fn() -> !>,
+
+    state: Cell<DfuState>,
+    status: Cell<DfuStatus>,
+    ctrl_state: Cell<CtrlState>,
+    manifestation: Cell<Manifestation>,
+    /// An image was installed since entering DFU mode.
+    installed: Cell<bool>,
+
+    /// The block being downloaded.
+    block: TakeCell<'static, [u8]>,
+    /// Bytes of the block received so far.
+    block_len: Cell<usize>,
+    /// Range of the block not passed to the updater yet.
+    pending: Cell<(usize, usize)>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> Dfu<'a, U> {
+    /// `block` receives the blocks of the download, its length is the
+    /// largest block the host sends. `bootloader` resets the board into a
+    /// bootloader with DFU support, if there is one.
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        updater: &'a dyn AppUpdater<'a>,
+        bootloader: Option<fn() -> !>,
+        block: &'static mut [u8],
+    ) -> Self {
+        let transfer_size = cmp::min(block.len(), u16::MAX as usize) as u16;
+        let runtime_attributes = match bootloader {
+            Some(_) => ATTRIBUTE_CAN_DOWNLOAD | ATTRIBUTE_WILL_DETACH,
+            None => ATTRIBUTE_CAN_DOWNLOAD | ATTRIBUTE_MANIFESTATION_TOLERANT,
+        };
+
+        let (runtime_device_buffer, runtime_other_buffer) = Self::descriptor_buffers(
+            vendor_id,
+            product_id,
+            max_ctrl_packet_size,
+            0x01, // Runtime protocol
+            runtime_attributes,
+            transfer_size,
+        );
+        let (dfu_device_buffer, dfu_other_buffer) = Self::descriptor_buffers(
+            vendor_id,
+            product_id,
+            max_ctrl_packet_size,
+            0x02, // DFU mode protocol
+            ATTRIBUTE_CAN_DOWNLOAD | ATTRIBUTE_MANIFESTATION_TOLERANT,
+            transfer_size,
+        );
+
+        Self {
+            runtime_ctrl: ClientCtrl::new(
+                controller,
+                runtime_device_buffer,
+                runtime_other_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            dfu_ctrl: ClientCtrl::new(
+                controller,
+                dfu_device_buffer,
+                dfu_other_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            mode: Cell::new(Mode::Runtime),
+            updater,
+            bootloader,
+            state: Cell::new(DfuState::AppIdle),
+            status: Cell::new(DfuStatus::Ok),
+            ctrl_state: Cell::new(CtrlState::Idle),
+            manifestation: Cell::new(Manifestation::NotStarted),
+            installed: Cell::new(false),
+            block: TakeCell::new(block),
+            block_len: Cell::new(0),
+            pending: Cell::new((0, 0)),
+        }
+    }
+
+    fn descriptor_buffers(
+        vendor_id: u16,
+        product_id: u16,
+        max_ctrl_packet_size: u8,
+        protocol: u8,
+        attributes: u8,
+        transfer_size: u16,
+    ) -> (descriptors::DeviceBuffer, descriptors::DescriptorBuffer) {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0xfe,    // Application specific
+            interface_subclass: 0x01, // Device firmware upgrade
+            interface_protocol: protocol,
+            ..InterfaceDescriptor::default()
+        }];
+
+        let functional = DfuFunctionalDescriptor {
+            attributes,
+            detach_timeout: DETACH_TIMEOUT_MS,
+            transfer_size,
+            dfu_version: 0x0110,
+        };
+
+        descriptors::create_descriptor_buffers(
+            descriptors::DeviceDescriptor {
+                vendor_id,
+                product_id,
+                manufacturer_string: 1,
+                product_string: 2,
+                serial_number_string: 3,
+                max_packet_size_ep0: max_ctrl_packet_size,
+                ..descriptors::DeviceDescriptor::default()
+            },
+            descriptors::ConfigurationDescriptor {
+                ..descriptors::ConfigurationDescriptor::default()
+            },
+            interfaces,
+            &[&[]], // Only the default control endpoint
+            None,   // No HID descriptor
+            Some(&[&functional]),
+        )
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.runtime_ctrl.controller()
+    }
+
+    /// The control endpoint handler of the current mode.
+    #[inline]
+    fn ctrl(&'a self) -> &'a ClientCtrl<'a, 'static, U> {
+        match self.mode.get() {
+            Mode::Runtime => &self.runtime_ctrl,
+            Mode::Dfu => &self.dfu_ctrl,
+        }
+    }
+
+    /// Abandons the download in progress and enters the error state.
+    fn fail(&self, status: DfuStatus) {
+        let _ = self.updater.abort();
+        self.pending.set((0, 0));
+        self.manifestation.set(Manifestation::NotStarted);
+        self.status.set(status);
+        self.state.set(DfuState::Error);
+    }
+
+    /// Rejects a request that is not valid in the current state.
+    fn stall(&self) -> hil::usb::CtrlSetupResult {
+        self.fail(DfuStatus::ErrStalledPacket);
+        hil::usb::CtrlSetupResult::ErrGeneric
+    }
+
+    fn status_response(&self, poll_timeout: u32) -> [u8; 6] {
+        let timeout = poll_timeout.to_le_bytes();
+        [
+            self.status.get() as u8,
+            timeout[0],
+            timeout[1],
+            timeout[2],
+            self.state.get() as u8,
+            0, // No status string
+        ]
+    }
+
+    fn runtime_request(
+        &'a self,
+        endpoint: usize,
+        setup_data: descriptors::SetupData,
+    ) -> hil::usb::CtrlSetupResult {
+        match setup_data.request_code {
+            DFU_DETACH => {
+                self.state.set(DfuState::AppDetach);
+                self.ctrl_state.set(CtrlState::Detach);
+                self.ctrl().ctrl_setup_out_data(endpoint)
+            }
+            DFU_GETSTATUS => {
+                let status = self.status_response(0);
+                self.ctrl()
+                    .ctrl_setup_in_data(endpoint, &status, setup_data.length)
+            }
+            DFU_GETSTATE => self.ctrl().ctrl_setup_in_data(
+                endpoint,
+                &[self.state.get() as u8],
+                setup_data.length,
+            ),
+            _ => hil::usb::CtrlSetupResult::ErrNonstandardRequest,
+        }
+    }
+
+    fn dfu_request(
+        &'a self,
+        endpoint: usize,
+        setup_data: descriptors::SetupData,
+    ) -> hil::usb::CtrlSetupResult {
+        let state = self.state.get();
+        match setup_data.request_code {
+            DFU_DNLOAD => {
+                let length = setup_data.length as usize;
+                match state {
+                    DfuState::DownloadIdle if length == 0 => {
+                        self.state.set(DfuState::ManifestSync);
+                        self.ctrl().ctrl_setup_out_data(endpoint)
+                    }
+                    DfuState::DfuIdle | DfuState::DownloadIdle
+                        if length > 0 && length <= self.block.map_or(0, |block| block.len()) =>
+                    {
+                        self.block_len.set(0);
+                        self.ctrl_state.set(CtrlState::Download(length));
+                        self.ctrl().ctrl_setup_out_data(endpoint)
+                    }
+                    _ => self.stall(),
+                }
+            }
+            DFU_GETSTATUS => {
+                let poll_timeout = self.poll_state();
+                let status = self.status_response(poll_timeout);
+                self.ctrl()
+                    .ctrl_setup_in_data(endpoint, &status, setup_data.length)
+            }
+            DFU_CLRSTATUS if state == DfuState::Error => {
+                self.status.set(DfuStatus::Ok);
+                self.state.set(DfuState::DfuIdle);
+                self.ctrl().ctrl_setup_out_data(endpoint)
+            }
+            DFU_GETSTATE => {
+                self.ctrl()
+                    .ctrl_setup_in_data(endpoint, &[state as u8], setup_data.length)
+            }
+            DFU_ABORT => match state {
+                DfuState::DfuIdle | DfuState::DownloadSync | DfuState::DownloadIdle => {
+                    let _ = self.updater.abort();
+                    self.pending.set((0, 0));
+                    self.state.set(DfuState::DfuIdle);
+                    self.ctrl().ctrl_setup_out_data(endpoint)
+                }
+                _ => self.stall(),
+            },
+            DFU_UPLOAD | DFU_CLRSTATUS | DFU_DETACH => self.stall(),
+            _ => hil::usb::CtrlSetupResult::ErrNonstandardRequest,
+        }
+    }
+
+    /// Advances the state on a `DFU_GETSTATUS`, as the host only learns
+    /// about progress through it. Returns the time the host should wait
+    /// before asking again.
+    fn poll_state(&self) -> u32 {
+        match self.state.get() {
+            DfuState::DownloadSync | DfuState::DownloadBusy => {
+                let (start, end) = self.pending.get();
+                if start < end {
+                    self.state.set(DfuState::DownloadBusy);
+                    POLL_TIMEOUT_MS
+                } else {
+                    self.state.set(DfuState::DownloadIdle);
+                    0
+                }
+            }
+            DfuState::ManifestSync => match self.manifestation.get() {
+                Manifestation::NotStarted => match self.updater.finish() {
+                    Ok(()) => {
+                        self.manifestation.set(Manifestation::Running);
+                        self.state.set(DfuState::Manifest);
+                        POLL_TIMEOUT_MS
+                    }
+                    Err(ErrorCode::SIZE) => {
+                        self.fail(DfuStatus::ErrNotDone);
+                        0
+                    }
+                    Err(_) => {
+                        self.fail(DfuStatus::ErrUnknown);
+                        0
+                    }
+                },
+                Manifestation::Running => POLL_TIMEOUT_MS,
+                Manifestation::Done => {
+                    self.manifestation.set(Manifestation::NotStarted);
+                    self.state.set(DfuState::DfuIdle);
+                    0
+                }
+            },
+            DfuState::Manifest => POLL_TIMEOUT_MS,
+            _ => 0,
+        }
+    }
+
+    /// Starts passing a received block to the updater. The first block of
+    /// an image starts the update, with the length from its TBF header.
+    fn block_received(&self, length: usize) {
+        if self.block_len.get() != length {
+            return self.fail(DfuStatus::ErrUnknown);
+        }
+        if self.state.get() == DfuState::DfuIdle {
+            // A TBF header starts with its version (2), its length and the
+            // length of the whole object.
+            let image_length = self.block.map_or(None, |block| match block.get(..8) {
+                Some(&[2, 0, _, _, l0, l1, l2, l3]) => {
+                    Some(u32::from_le_bytes([l0, l1, l2, l3]) as usize)
+                }
+                _ => None,
+            });
+            let result = match image_length {
+                Some(image_length) => self.updater.start(image_length),
+                None => return self.fail(DfuStatus::ErrFile),
+            };
+            match result {
+                Ok(()) => {}
+                Err(ErrorCode::SIZE) => return self.fail(DfuStatus::ErrAddress),
+                Err(_) => return self.fail(DfuStatus::ErrTarget),
+            }
+        }
+        self.state.set(DfuState::DownloadSync);
+        self.pending.set((0, length));
+        self.write_pending();
+    }
+
+    /// Passes the pending bytes of the block to the updater.
+    fn write_pending(&self) {
+        let (mut start, end) = self.pending.get();
+        let result = self.block.map_or(Err(ErrorCode::FAIL), |block| {
+            while start < end {
+                match self.updater.write(&block[start..end]) {
+                    Ok(0) => break,
+                    Ok(count) => start += count,
+                    Err(ErrorCode::BUSY) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        });
+        self.pending.set((start, end));
+        if result.is_err() {
+            self.fail(DfuStatus::ErrWrite);
+        }
+    }
+
+    /// Switches to the DFU mode interface, which the host enumerates after
+    /// the current reset.
+    fn enter_dfu_mode(&'a self) {
+        self.mode.set(Mode::Dfu);
+        self.controller()
+            .endpoint_set_ctrl_buffer(&self.dfu_ctrl.ctrl_buffer.buf);
+        self.state.set(DfuState::DfuIdle);
+        self.status.set(DfuStatus::Ok);
+        self.installed.set(false);
+    }
+
+    fn enter_runtime_mode(&'a self) {
+        self.mode.set(Mode::Runtime);
+        self.controller()
+            .endpoint_set_ctrl_buffer(&self.runtime_ctrl.ctrl_buffer.buf);
+        self.state.set(DfuState::AppIdle);
+        self.status.set(DfuStatus::Ok);
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for Dfu<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.ctrl().enable();
+    }
+
+    fn attach(&'a self) {
+        self.ctrl().attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.ctrl_state.set(CtrlState::Idle);
+        match (self.mode.get(), self.state.get()) {
+            (Mode::Runtime, DfuState::AppDetach) => self.enter_dfu_mode(),
+            (Mode::Runtime, _) => {}
+            (Mode::Dfu, DfuState::DfuIdle) if self.installed.get() => self.enter_runtime_mode(),
+            (Mode::Dfu, _) => {
+                // Enumeration resets the bus as well, so stay in DFU mode,
+                // only abandon a download in progress.
+                let _ = self.updater.abort();
+                self.pending.set((0, 0));
+                self.manifestation.set(Manifestation::NotStarted);
+                self.status.set(DfuStatus::Ok);
+                self.state.set(DfuState::DfuIdle);
+            }
+        }
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// DFU requests are class requests to the interface, everything else is
+    /// handled by `ClientCtrl`.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        let setup_data = match descriptors::SetupData::get(&self.ctrl().ctrl_buffer.buf) {
+            Some(setup_data) => setup_data,
+            None => return self.ctrl().ctrl_setup(endpoint),
+        };
+
+        match setup_data.request_type.request_type() {
+            RequestType::Class => match self.mode.get() {
+                Mode::Runtime => self.runtime_request(endpoint, setup_data),
+                Mode::Dfu => self.dfu_request(endpoint, setup_data),
+            },
+            _ => self.ctrl().ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        self.ctrl().ctrl_in(endpoint)
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        if let CtrlState::Download(length) = self.ctrl_state.get() {
+            let packet = &self.ctrl().ctrl_buffer.buf;
+            let offset = self.block_len.get();
+            let count = cmp::min(packet_bytes as usize, length - offset);
+            self.block.map(|block| {
+                for i in 0..count {
+                    block[offset + i] = packet[i].get();
+                }
+            });
+            self.block_len.set(offset + count);
+        }
+
+        self.ctrl().ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.ctrl().ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        match self.ctrl_state.replace(CtrlState::Idle) {
+            CtrlState::Detach => {
+                if let Some(bootloader) = self.bootloader {
+                    bootloader();
+                }
+            }
+            CtrlState::Download(length) => self.block_received(length),
+            CtrlState::Idle => {}
+        }
+
+        self.ctrl().ctrl_status_complete(endpoint)
+    }
+
+    fn packet_in(
+        &'a self,
+        _transfer_type: hil::usb::TransferType,
+        _endpoint: usize,
+    ) -> hil::usb::InResult {
+        // DFU only uses the default control endpoint.
+        hil::usb::InResult::Error
+    }
+
+    fn packet_out(
+        &'a self,
+        _transfer_type: hil::usb::TransferType,
+        _endpoint: usize,
+        _packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        hil::usb::OutResult::Error
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {}
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> AppUpdaterClient for Dfu<'a, U> {
+    fn write_done(&self, result: Result<(), ErrorCode>) {
+        match result {
+            Ok(()) => self.write_pending(),
+            Err(_) => self.fail(DfuStatus::ErrWrite),
+        }
+    }
+
+    fn update_done(&self, result: Result<(), ErrorCode>) {
+        if self.state.get() != DfuState::Manifest {
+            return;
+        }
+        match result {
+            Ok(()) => {
+                self.manifestation.set(Manifestation::Done);
+                self.installed.set(true);
+                self.state.set(DfuState::ManifestSync);
+            }
+            Err(ErrorCode::INVAL) => self.fail(DfuStatus::ErrFile),
+            Err(ErrorCode::NOMEM) | Err(ErrorCode::SIZE) => self.fail(DfuStatus::ErrAddress),
+            Err(_) => self.fail(DfuStatus::ErrVerify),
+        }
+    }
+}
@@ -0,0 +1,442 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! USB HID device with several reports, e.g. a keyboard and a mouse.
+//!
+//! Unlike `keyboard_hid`, which has the single fixed report of a boot
+//! keyboard, this device takes the HID and report descriptors to present
+//! to the host, and the list of input reports they describe. Reports are
+//! distinguished by their report ID, which is the first byte of every
+//! buffer passed to `send_buffer()`. Only as many bytes as the report has
+//! are sent.
+//!
+//! [`KEYBOARD_MOUSE_HID_DESCRIPTOR`] and [`KEYBOARD_MOUSE_REPORT`] describe
+//! a keyboard with report ID [`KEYBOARD_REPORT_ID`] and a mouse with report
+//! ID [`MOUSE_REPORT_ID`], as used by `capsules_extra::hid_input`.
+//!
+//! The report descriptor must fit into the 128 byte descriptor buffer of
+//! `ClientCtrl`.
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::DescriptorType;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::HIDCountryCode;
+use super::descriptors::HIDDescriptor;
+use super::descriptors::HIDSubordinateDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::ReportDescriptor;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+/// Use 1 Interrupt transfer IN endpoint
+const ENDPOINT_NUM: usize = 1;
+
+const IN_BUFFER: usize = 0;
+
+static LANGUAGES: &'static [u16; 1] = &[
+    0x0409, // English (United States)
+];
+/// Max packet size specified by spec
+pub const MAX_CTRL_PACKET_SIZE: u8 = 64;
+
+const N_ENDPOINTS: usize = 1;
+
+/// An input report the device sends.
+pub struct HidReport {
+    pub id: u8,
+    /// Length of the report, without the report ID.
+    pub len: u8,
+}
+
+/// Report ID of the keyboard of the keyboard and mouse descriptor. The
+/// report is the modifier byte, a reserved byte and six key codes.
+pub const KEYBOARD_REPORT_ID: u8 = 1;
+/// Report ID of the mouse of the keyboard and mouse descriptor. The report
+/// is the buttons and the signed X, Y and wheel movements.
+pub const MOUSE_REPORT_ID: u8 = 2;
+
+static KEYBOARD_MOUSE_REPORT_DESCRIPTOR: &'static [u8] = &[
+    0x05,
+    0x01, // Usage Page (Generic Desktop),
+    0x09,
+    0x06, // Usage (Keyboard),
+    0xA1,
+    0x01, // Collection (Application),
+    0x85,
+    KEYBOARD_REPORT_ID, // Report ID (1),
+    0x05,
+    0x07, // Usage Page (Key Codes),
+    0x19,
+    0xE0, // Usage Minimum (224),
+    0x29,
+    0xE7, // Usage Maximum (231),
+    0x15,
+    0x00, // Logical Minimum (0),
+    0x25,
+    0x01, // Logical Maximum (1),
+    0x75,
+    0x01, // Report Size (1),
+    0x95,
+    0x08, // Report Count (8),
+    0x81,
+    0x02, // Input (Data, Variable, Absolute),
+    // ;Modifier byte
+    0x95,
+    0x01, // Report Count (1),
+    0x75,
+    0x08, // Report Size (8),
+    0x81,
+    0x01, // Input (Constant),
+    // ;Reserved byte
+    0x95,
+    0x06, // Report Count (6),
+    0x75,
+    0x08, // Report Size (8),
+    0x15,
+    0x00, // Logical Minimum (0),
+    0x25,
+    0x65, // Logical Maximum (101),
+    0x05,
+    0x07, // Usage Page (Key Codes),
+    0x19,
+    0x00, // Usage Minimum (0),
+    0x29,
+    0x65, // Usage Maximum (101),
+    0x81,
+    0x00, // Input (Data, Array),
+    0xC0, // End Collection
+    0x05,
+    0x01, // Usage Page (Generic Desktop),
+    0x09,
+    0x02, // Usage (Mouse),
+    0xA1,
+    0x01, // Collection (Application),
+    0x85,
+    MOUSE_REPORT_ID, // Report ID (2),
+    0x09,
+    0x01, // Usage (Pointer),
+    0xA1,
+    0x00, // Collection (Physical),
+    0x05,
+    0x09, // Usage Page (Buttons),
+    0x19,
+    0x01, // Usage Minimum (1),
+    0x29,
+    0x03, // Usage Maximum (3),
+    0x15,
+    0x00, // Logical Minimum (0),
+    0x25,
+    0x01, // Logical Maximum (1),
+    0x95,
+    0x03, // Report Count (3),
+    0x75,
+    0x01, // Report Size (1),
+    0x81,
+    0x02, // Input (Data, Variable, Absolute),
+    0x95,
+    0x01, // Report Count (1),
+    0x75,
+    0x05, // Report Size (5),
+    0x81,
+    0x01, // Input (Constant),
+    // ;Buttons and padding
+    0x05,
+    0x01, // Usage Page (Generic Desktop),
+    0x09,
+    0x30, // Usage (X),
+    0x09,
+    0x31, // Usage (Y),
+    0x09,
+    0x38, // Usage (Wheel),
+    0x15,
+    0x81, // Logical Minimum (-127),
+    0x25,
+    0x7F, // Logical Maximum (127),
+    0x75,
+    0x08, // Report Size (8),
+    0x95,
+    0x03, // Report Count (3),
+    0x81,
+    0x06, // Input (Data, Variable, Relative),
+    0xC0, // End Collection
+    0xC0, // End Collection
+];
+
+pub static KEYBOARD_MOUSE_REPORT: ReportDescriptor<'static> = ReportDescriptor {
+    desc: KEYBOARD_MOUSE_REPORT_DESCRIPTOR,
+};
+
+static KEYBOARD_MOUSE_SUB_HID_DESCRIPTOR: &'static [HIDSubordinateDescriptor] =
+    &[HIDSubordinateDescriptor {
+        typ: DescriptorType::Report,
+        len: KEYBOARD_MOUSE_REPORT_DESCRIPTOR.len() as u16,
+    }];
+
+pub static KEYBOARD_MOUSE_HID_DESCRIPTOR: HIDDescriptor<'static> = HIDDescriptor {
+    hid_class: 0x0111,
+    country_code: HIDCountryCode::NotSupported,
+    sub_descriptors: KEYBOARD_MOUSE_SUB_HID_DESCRIPTOR,
+};
+
+/// Input reports of the keyboard and mouse descriptor.
+pub static KEYBOARD_MOUSE_REPORTS: &'static [HidReport] = &[
+    HidReport {
+        id: KEYBOARD_REPORT_ID,
+        len: 8,
+    },
+    HidReport {
+        id: MOUSE_REPORT_ID,
+        len: 4,
+    },
+];
+
+pub struct CompositeHid<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    /// Input reports described by the report descriptor.
+    reports: &'static [HidReport],
+
+    client: OptionalCell<&'a dyn hil::usb_hid::Client<'a, [u8; 64]>>,
+
+    /// A buffer to hold the report we want to send
+    send_buffer: TakeCell<'static, [u8; 64]>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> CompositeHid<'a, U> {
+    pub fn new(
+        controller: &'a U,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        hid_descriptor: &'static HIDDescriptor<'static>,
+        report_descriptor: &'static ReportDescriptor<'static>,
+        reports: &'static [HidReport],
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x03,    // HID
+            interface_subclass: 0x00, // No boot subclass, reports have IDs
+            interface_protocol: 0x00, // None
+            ..InterfaceDescriptor::default()
+        }];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[&[EndpointDescriptor {
+            endpoint_address: EndpointAddress::new_const(
+                ENDPOINT_NUM,
+                TransferDirection::DeviceToHost,
+            ),
+            transfer_type: TransferType::Interrupt,
+            max_packet_size: 64,
+            interval: 10,
+        }]];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: MAX_CTRL_PACKET_SIZE,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor {
+                    attributes: descriptors::ConfigurationAttributes::new(true, true),
+                    max_power: 0x32,
+                    ..descriptors::ConfigurationDescriptor::default()
+                },
+                interfaces,
+                endpoints,
+                Some(hid_descriptor),
+                None,
+            );
+
+        CompositeHid {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                Some(hid_descriptor),
+                Some(report_descriptor),
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [Buffer64::default()],
+            reports,
+            client: OptionalCell::empty(),
+            send_buffer: TakeCell::empty(),
+        }
+    }
+
+    #[inline]
+    fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    pub fn set_client(&'a self, client: &'a dyn hil::usb_hid::Client<'a, [u8; 64]>) {
+        self.client.set(client);
+    }
+
+    /// Length of the report with ID `id`, including the ID.
+    fn report_len(&self, id: u8) -> Option<usize> {
+        self.reports
+            .iter()
+            .find(|report| report.id == id)
+            .map(|report| 1 + report.len as usize)
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb_hid::UsbHid<'a, [u8; 64]>
+    for CompositeHid<'a, U>
+{
+    /// Send the report in `send`, whose first byte is the report ID.
+    /// Returns `INVAL` if there is no input report with that ID.
+    fn send_buffer(
+        &'a self,
+        send: &'static mut [u8; 64],
+    ) -> Result<usize, (ErrorCode, &'static mut [u8; 64])> {
+        let len = match self.report_len(send[0]) {
+            Some(len) if len <= send.len() => len,
+            _ => return Err((ErrorCode::INVAL, send)),
+        };
+        if self.send_buffer.is_some() {
+            return Err((ErrorCode::BUSY, send));
+        }
+
+        self.send_buffer.replace(send);
+        self.controller().endpoint_resume_in(ENDPOINT_NUM);
+
+        Ok(len)
+    }
+
+    fn send_cancel(&'a self) -> Result<&'static mut [u8; 64], ErrorCode> {
+        match self.send_buffer.take() {
+            Some(buf) => Ok(buf),
+            None => Err(ErrorCode::INVAL),
+        }
+    }
+
+    // Only input reports are supported.
+    fn receive_buffer(
+        &'a self,
+        recv: &'static mut [u8; 64],
+    ) -> Result<(), (ErrorCode, &'static mut [u8; 64])> {
+        Err((ErrorCode::NOSUPPORT, recv))
+    }
+
+    // Only input reports are supported.
+    fn receive_cancel(&'a self) -> Result<&'static mut [u8; 64], ErrorCode> {
+        Err(ErrorCode::INVAL)
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for CompositeHid<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_NUM, &self.buffers[IN_BUFFER].buf);
+        self.controller()
+            .endpoint_in_enable(TransferType::Interrupt, ENDPOINT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {}
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// The HID class requests (e.g. SET_IDLE) are accepted by `ClientCtrl`.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        self.client_ctrl.ctrl_setup(endpoint)
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        self.client_ctrl.ctrl_in(endpoint)
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        if self.send_buffer.is_some() {
+            self.controller().endpoint_resume_in(ENDPOINT_NUM);
+        }
+
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction.
+    fn packet_in(&'a self, transfer_type: TransferType, _endpoint: usize) -> hil::usb::InResult {
+        match transfer_type {
+            TransferType::Interrupt => {
+                self.send_buffer.map_or(hil::usb::InResult::Delay, |buf| {
+                    let len = self.report_len(buf[0]).unwrap_or(0);
+
+                    // Copy the report to the outgoing USB packet.
+                    let packet = &self.buffers[IN_BUFFER].buf;
+                    for i in 0..len {
+                        packet[i].set(buf[i]);
+                    }
+
+                    hil::usb::InResult::Packet(len)
+                })
+            }
+            TransferType::Bulk | TransferType::Control | TransferType::Isochronous => {
+                hil::usb::InResult::Error
+            }
+        }
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    ///
+    /// Unused, there are no output reports.
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        _endpoint: usize,
+        _packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        match transfer_type {
+            TransferType::Interrupt => hil::usb::OutResult::Ok,
+
+            TransferType::Bulk | TransferType::Control | TransferType::Isochronous => {
+                hil::usb::OutResult::Error
+            }
+        }
+    }
+
+    fn packet_transmitted(&'a self, endpoint: usize) {
+        self.send_buffer.take().map(|buf| {
+            self.client.map(move |client| {
+                client.packet_transmitted(Ok(()), buf, endpoint);
+            });
+        });
+    }
+}
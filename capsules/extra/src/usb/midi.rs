@@ -0,0 +1,420 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! MIDI streaming over USB, with the USB MIDI 1.0 class.
+//!
+//! The board shows up as a MIDI port on the host, with the class drivers of
+//! Linux, macOS and Windows, so it can act as a controller sending notes or
+//! as a synthesizer receiving them. The function has one MIDI IN and one
+//! MIDI OUT jack to the outside, connected to a bulk OUT and a bulk IN
+//! endpoint. MIDI messages are moved in 4 byte event packets, see
+//! `kernel::hil::midi`, up to 16 per USB packet.
+//!
+//! Packets from the host are held back (the endpoint NAKs) while no receive
+//! buffer is given, so none are lost.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::AudioControlHeaderDescriptor;
+use super::descriptors::AudioEndpointDescriptor;
+use super::descriptors::Buffer64;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::MidiEndpointDescriptor;
+use super::descriptors::MidiInJackDescriptor;
+use super::descriptors::MidiJackType;
+use super::descriptors::MidiOutJackDescriptor;
+use super::descriptors::MidiStreamingHeaderDescriptor;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil;
+use kernel::hil::midi::{self, EVENT_PACKET_LEN};
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::VolatileCell;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Endpoint for packets to the host.
+const ENDPOINT_IN_NUM: usize = 1;
+/// Endpoint for packets from the host.
+const ENDPOINT_OUT_NUM: usize = 2;
+
+const N_ENDPOINTS: usize = 2;
+
+const PACKET_SIZE: usize = 64;
+
+/// Jack receiving from the OUT endpoint.
+const JACK_EMBEDDED_IN: u8 = 1;
+/// Jack receiving from the outside.
+const JACK_EXTERNAL_IN: u8 = 2;
+/// Jack sending to the IN endpoint.
+const JACK_EMBEDDED_OUT: u8 = 3;
+/// Jack sending to the outside.
+const JACK_EXTERNAL_OUT: u8 = 4;
+
+static LANGUAGES: &'static [u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+pub struct UsbMidi<'a, U: 'a> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    client: OptionalCell<&'a dyn midi::Client>,
+
+    /// The packets being sent.
+    tx_buffer: TakeCell<'static, [u8]>,
+    tx_len: Cell<usize>,
+    /// Bytes of `tx_buffer` passed to the controller so far.
+    tx_offset: Cell<usize>,
+
+    rx_buffer: TakeCell<'static, [u8]>,
+    /// Bytes of a packet from the host held in the OUT endpoint buffer
+    /// while there is no receive buffer. The endpoint is delayed meanwhile.
+    rx_held: Cell<usize>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> UsbMidi<'a, U> {
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+    ) -> Self {
+        let audio_control = InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x01,    // Audio
+            interface_subclass: 0x01, // Audio control
+            interface_protocol: 0x00,
+            ..InterfaceDescriptor::default()
+        };
+        let midi_streaming = InterfaceDescriptor {
+            interface_number: 1,
+            num_endpoints: 2,
+            interface_class: 0x01,    // Audio
+            interface_subclass: 0x03, // MIDI streaming
+            interface_protocol: 0x00,
+            ..InterfaceDescriptor::default()
+        };
+
+        let jacks_in = [
+            MidiInJackDescriptor {
+                jack_type: MidiJackType::Embedded,
+                jack_id: JACK_EMBEDDED_IN,
+            },
+            MidiInJackDescriptor {
+                jack_type: MidiJackType::External,
+                jack_id: JACK_EXTERNAL_IN,
+            },
+        ];
+        let jacks_out = [
+            MidiOutJackDescriptor {
+                jack_type: MidiJackType::Embedded,
+                jack_id: JACK_EMBEDDED_OUT,
+                source_id: JACK_EXTERNAL_IN,
+            },
+            MidiOutJackDescriptor {
+                jack_type: MidiJackType::External,
+                jack_id: JACK_EXTERNAL_OUT,
+                source_id: JACK_EMBEDDED_IN,
+            },
+        ];
+        let endpoint_out = AudioEndpointDescriptor {
+            endpoint: EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_OUT_NUM,
+                    TransferDirection::HostToDevice,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+            refresh: 0,
+            synch_address: 0,
+        };
+        let endpoint_out_midi = MidiEndpointDescriptor {
+            jack_id: JACK_EMBEDDED_IN,
+        };
+        let endpoint_in = AudioEndpointDescriptor {
+            endpoint: EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_IN_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+            refresh: 0,
+            synch_address: 0,
+        };
+        let endpoint_in_midi = MidiEndpointDescriptor {
+            jack_id: JACK_EMBEDDED_OUT,
+        };
+
+        let streaming: [&dyn descriptors::Descriptor; 8] = [
+            &jacks_in[0],
+            &jacks_in[1],
+            &jacks_out[0],
+            &jacks_out[1],
+            &endpoint_out,
+            &endpoint_out_midi,
+            &endpoint_in,
+            &endpoint_in_midi,
+        ];
+        let header = MidiStreamingHeaderDescriptor {
+            midi_version: 0x0100,
+            total_length: 7 + streaming.iter().map(|d| d.size()).sum::<usize>() as u16,
+        };
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers_in_order(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor {
+                    num_interfaces: 2,
+                    ..descriptors::ConfigurationDescriptor::default()
+                },
+                &[
+                    &audio_control,
+                    &AudioControlHeaderDescriptor {
+                        audio_version: 0x0100,
+                        streaming_interface: 1,
+                    },
+                    &midi_streaming,
+                    &header,
+                    streaming[0],
+                    streaming[1],
+                    streaming[2],
+                    streaming[3],
+                    streaming[4],
+                    streaming[5],
+                    streaming[6],
+                    streaming[7],
+                ],
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [Buffer64::default(), Buffer64::default()],
+            client: OptionalCell::empty(),
+            tx_buffer: TakeCell::empty(),
+            tx_len: Cell::new(0),
+            tx_offset: Cell::new(0),
+            rx_buffer: TakeCell::empty(),
+            rx_held: Cell::new(0),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    #[inline]
+    fn buffer(&'a self, i: usize) -> &'a [VolatileCell<u8>; 64] {
+        &self.buffers[i - 1].buf
+    }
+
+    /// Pass `len` bytes of the OUT endpoint buffer to the client. Returns
+    /// false if there is no receive buffer.
+    fn deliver(&self, len: usize) -> bool {
+        match self.rx_buffer.take() {
+            Some(buffer) => {
+                let packet = &self.buffers[ENDPOINT_OUT_NUM - 1].buf;
+                let len = cmp::min(len, buffer.len() / EVENT_PACKET_LEN * EVENT_PACKET_LEN);
+                for i in 0..len {
+                    buffer[i] = packet[i].get();
+                }
+                self.client
+                    .map(move |client| client.packets_received(buffer, len, Ok(())));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> midi::Midi<'a> for UsbMidi<'a, U> {
+    fn set_client(&self, client: &'a dyn midi::Client) {
+        self.client.set(client);
+    }
+
+    fn send(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.tx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if len == 0 || len % EVENT_PACKET_LEN != 0 || len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.tx_buffer.replace(buffer);
+        self.tx_len.set(len);
+        self.tx_offset.set(0);
+        self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        Ok(())
+    }
+
+    fn receive(&self, buffer: &'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if buffer.len() < EVENT_PACKET_LEN {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.rx_buffer.replace(buffer);
+        if self.rx_held.get() > 0 {
+            // Pass the held packet from a deferred call, not from within
+            // this call.
+            self.deferred_call.set();
+        }
+        Ok(())
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> DeferredCallClient for UsbMidi<'a, U> {
+    fn handle_deferred_call(&self) {
+        let held = self.rx_held.get();
+        if held > 0 && self.deliver(held) {
+            self.rx_held.set(0);
+            self.controller().endpoint_resume_out(ENDPOINT_OUT_NUM);
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>> hil::usb::Client<'a> for UsbMidi<'a, U> {
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, self.buffer(ENDPOINT_IN_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, self.buffer(ENDPOINT_OUT_NUM));
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {}
+
+    /// Handle a Control Setup transaction.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        self.client_ctrl.ctrl_setup(endpoint)
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        self.client_ctrl.ctrl_in(endpoint)
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction.
+    fn packet_in(&'a self, transfer_type: TransferType, endpoint: usize) -> hil::usb::InResult {
+        if !matches!(transfer_type, TransferType::Bulk) {
+            return hil::usb::InResult::Delay;
+        }
+        let packet = self.buffer(endpoint);
+        self.tx_buffer.map_or(hil::usb::InResult::Delay, |buffer| {
+            let offset = self.tx_offset.get();
+            let len = cmp::min(self.tx_len.get() - offset, PACKET_SIZE);
+            if len == 0 {
+                return hil::usb::InResult::Delay;
+            }
+            for i in 0..len {
+                packet[i].set(buffer[offset + i]);
+            }
+            self.tx_offset.set(offset + len);
+            hil::usb::InResult::Packet(len)
+        })
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        _endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        if !matches!(transfer_type, TransferType::Bulk) {
+            return hil::usb::OutResult::Ok;
+        }
+        // Drop any partial event packet.
+        let len =
+            cmp::min(packet_bytes as usize, PACKET_SIZE) / EVENT_PACKET_LEN * EVENT_PACKET_LEN;
+        if len == 0 || self.deliver(len) {
+            hil::usb::OutResult::Ok
+        } else {
+            // Keep the packet in the endpoint buffer until there is a
+            // receive buffer.
+            self.rx_held.set(len);
+            hil::usb::OutResult::Delay
+        }
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        if self.tx_offset.get() < self.tx_len.get() {
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        } else {
+            self.tx_buffer.take().map(|buffer| {
+                self.client
+                    .map(move |client| client.send_done(buffer, Ok(())));
+            });
+        }
+    }
+}
@@ -3,9 +3,14 @@
 // Copyright Tock Contributors 2022.
 
 pub mod cdc;
+pub mod cdc_ncm;
 pub mod ctap;
 pub mod descriptors;
+pub mod dfu;
+pub mod hid_composite;
 pub mod keyboard_hid;
+pub mod midi;
+pub mod msc;
 pub mod usb_user;
 pub mod usbc_client;
 pub mod usbc_client_ctrl;
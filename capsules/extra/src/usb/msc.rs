@@ -0,0 +1,722 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Mass Storage Class over USB, with the Bulk-Only Transport.
+//!
+//! This capsule presents a block device (`hil::block_storage::BlockStorage`),
+//! e.g. an SD card or flash behind the wear-leveling translation layer, to
+//! the host as a USB drive, which Linux, macOS and Windows mount with their
+//! built-in drivers. Data logged to the storage can then be copied off the
+//! board without any custom software on the host.
+//!
+//! The host sends SCSI commands in Command Block Wrappers (CBWs) on the bulk
+//! OUT endpoint, the data of the command is moved on one of the bulk
+//! endpoints, and the status of every command is returned in a Command
+//! Status Wrapper (CSW) on the bulk IN endpoint.
+//!
+//! The commands operating systems use with a direct access block device are
+//! supported: INQUIRY, TEST UNIT READY, REQUEST SENSE, READ CAPACITY(10),
+//! READ FORMAT CAPACITIES, MODE SENSE(6) and (10), READ(10) and WRITE(10),
+//! and START STOP UNIT, PREVENT ALLOW MEDIUM REMOVAL, VERIFY(10) and
+//! SYNCHRONIZE CACHE(10), which need nothing from a device without a cache
+//! or a removable medium. A command that fails reports its cause in the
+//! sense data, which the host reads with REQUEST SENSE. Data the host
+//! expects beyond what a command returns is padded with zeros.
+//!
+//! Blocks are moved one at a time through a buffer of one block, whose size
+//! must be a multiple of the packet size.
+//!
+//! The host caches the filesystem on the drive, so nothing else may write to
+//! the storage while it is exported.
+
+use core::cell::Cell;
+use core::cmp;
+
+use super::descriptors;
+use super::descriptors::Buffer64;
+use super::descriptors::EndpointAddress;
+use super::descriptors::EndpointDescriptor;
+use super::descriptors::InterfaceDescriptor;
+use super::descriptors::RequestType;
+use super::descriptors::TransferDirection;
+use super::usbc_client_ctrl::ClientCtrl;
+
+use kernel::hil;
+use kernel::hil::block_storage::{self, BlockStorage};
+use kernel::hil::usb::TransferType;
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::cells::VolatileCell;
+use kernel::ErrorCode;
+
+/// Endpoint for data and status to the host.
+const ENDPOINT_IN_NUM: usize = 1;
+/// Endpoint for commands and data from the host.
+const ENDPOINT_OUT_NUM: usize = 2;
+
+const N_ENDPOINTS: usize = 2;
+
+const PACKET_SIZE: usize = 64;
+
+/// "USBC"
+const CBW_SIGNATURE: u32 = 0x43425355;
+/// "USBS"
+const CSW_SIGNATURE: u32 = 0x53425355;
+const CBW_LEN: usize = 31;
+const CSW_LEN: usize = 13;
+
+const CSW_PASSED: u8 = 0;
+const CSW_FAILED: u8 = 1;
+
+/// Class request to reset the transport.
+const BULK_ONLY_RESET: u8 = 0xff;
+/// Class request for the highest logical unit number.
+const GET_MAX_LUN: u8 = 0xfe;
+
+/// Length of the longest response, to INQUIRY.
+const RESPONSE_LEN: usize = 36;
+
+static LANGUAGES: &'static [u16; 1] = &[
+    0x0409, // English (United States)
+];
+
+mod scsi {
+    pub const TEST_UNIT_READY: u8 = 0x00;
+    pub const REQUEST_SENSE: u8 = 0x03;
+    pub const INQUIRY: u8 = 0x12;
+    pub const MODE_SENSE_6: u8 = 0x1a;
+    pub const START_STOP_UNIT: u8 = 0x1b;
+    pub const PREVENT_ALLOW_MEDIUM_REMOVAL: u8 = 0x1e;
+    pub const READ_FORMAT_CAPACITIES: u8 = 0x23;
+    pub const READ_CAPACITY_10: u8 = 0x25;
+    pub const READ_10: u8 = 0x28;
+    pub const WRITE_10: u8 = 0x2a;
+    pub const VERIFY_10: u8 = 0x2f;
+    pub const SYNCHRONIZE_CACHE_10: u8 = 0x35;
+    pub const MODE_SENSE_10: u8 = 0x5a;
+}
+
+/// Sense key and additional sense code of the last failed command.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Sense {
+    NoSense,
+    MediumNotPresent,
+    ReadError,
+    WriteError,
+    InvalidCommand,
+    LbaOutOfRange,
+    InvalidField,
+}
+
+impl Sense {
+    fn key_and_code(self) -> (u8, u8) {
+        match self {
+            Sense::NoSense => (0x00, 0x00),
+            Sense::MediumNotPresent => (0x02, 0x3a),
+            Sense::ReadError => (0x03, 0x11),
+            Sense::WriteError => (0x03, 0x0c),
+            Sense::InvalidCommand => (0x05, 0x20),
+            Sense::LbaOutOfRange => (0x05, 0x21),
+            Sense::InvalidField => (0x05, 0x24),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// Waiting for a CBW.
+    Command,
+    /// Sending data to the host.
+    DataIn,
+    /// Receiving data from the host.
+    DataOut,
+    /// Sending the CSW.
+    Status,
+}
+
+/// Where the data of a command comes from or goes to.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Data {
+    /// The response buffer.
+    Response,
+    /// The blocks of the storage.
+    Blocks,
+    /// Nothing, the data is padding or discarded.
+    None,
+}
+
+pub struct MassStorage<'a, U: 'a, B: BlockStorage<'a>> {
+    /// Helper USB client library for handling many USB operations.
+    client_ctrl: ClientCtrl<'a, 'static, U>,
+
+    /// 64 byte buffers for each endpoint.
+    buffers: [Buffer64; N_ENDPOINTS],
+
+    storage: &'a B,
+    /// Product name returned by INQUIRY.
+    product: &'static str,
+
+    state: Cell<State>,
+    data: Cell<Data>,
+    /// Tag of the current command, repeated in its CSW.
+    tag: Cell<u32>,
+    /// Length of the data stage the host expects.
+    data_len: Cell<u32>,
+    /// Bytes of the data stage moved so far.
+    transferred: Cell<u32>,
+    /// Bytes of the data stage that are not padding.
+    valid_len: Cell<u32>,
+    status: Cell<u8>,
+    sense: Cell<Sense>,
+    response: Cell<[u8; RESPONSE_LEN]>,
+
+    /// Next block to read or write.
+    lba: Cell<u32>,
+    /// Blocks left to read or write, including the one in progress.
+    blocks_left: Cell<u32>,
+    block: TakeCell<'static, [u8]>,
+    /// Bytes of `block` sent to or received from the host.
+    block_offset: Cell<usize>,
+    /// `block` holds a block read from the storage.
+    block_ready: Cell<bool>,
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, B: BlockStorage<'a>> MassStorage<'a, U, B> {
+    /// `strings` are the manufacturer, product and serial number. Operating
+    /// systems require a serial number of at least 12 hexadecimal digits.
+    /// `block` must be as long as a block of `storage`.
+    pub fn new(
+        controller: &'a U,
+        max_ctrl_packet_size: u8,
+        vendor_id: u16,
+        product_id: u16,
+        strings: &'static [&'static str; 3],
+        storage: &'a B,
+        block: &'static mut [u8],
+    ) -> Self {
+        let interfaces: &mut [InterfaceDescriptor] = &mut [InterfaceDescriptor {
+            interface_number: 0,
+            interface_class: 0x08,    // Mass storage
+            interface_subclass: 0x06, // SCSI transparent command set
+            interface_protocol: 0x50, // Bulk-only transport
+            ..InterfaceDescriptor::default()
+        }];
+
+        let endpoints: &[&[EndpointDescriptor]] = &[&[
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_IN_NUM,
+                    TransferDirection::DeviceToHost,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+            EndpointDescriptor {
+                endpoint_address: EndpointAddress::new_const(
+                    ENDPOINT_OUT_NUM,
+                    TransferDirection::HostToDevice,
+                ),
+                transfer_type: TransferType::Bulk,
+                max_packet_size: PACKET_SIZE as u16,
+                interval: 0,
+            },
+        ]];
+
+        let (device_descriptor_buffer, other_descriptor_buffer) =
+            descriptors::create_descriptor_buffers(
+                descriptors::DeviceDescriptor {
+                    vendor_id,
+                    product_id,
+                    manufacturer_string: 1,
+                    product_string: 2,
+                    serial_number_string: 3,
+                    max_packet_size_ep0: max_ctrl_packet_size,
+                    ..descriptors::DeviceDescriptor::default()
+                },
+                descriptors::ConfigurationDescriptor {
+                    ..descriptors::ConfigurationDescriptor::default()
+                },
+                interfaces,
+                endpoints,
+                None, // No HID descriptor
+                None, // No CDC descriptors
+            );
+
+        Self {
+            client_ctrl: ClientCtrl::new(
+                controller,
+                device_descriptor_buffer,
+                other_descriptor_buffer,
+                None, // No HID descriptor
+                None, // No report descriptor
+                LANGUAGES,
+                strings,
+            ),
+            buffers: [Buffer64::default(), Buffer64::default()],
+            storage,
+            product: strings[1],
+            state: Cell::new(State::Command),
+            data: Cell::new(Data::None),
+            tag: Cell::new(0),
+            data_len: Cell::new(0),
+            transferred: Cell::new(0),
+            valid_len: Cell::new(0),
+            status: Cell::new(CSW_PASSED),
+            sense: Cell::new(Sense::NoSense),
+            response: Cell::new([0; RESPONSE_LEN]),
+            lba: Cell::new(0),
+            blocks_left: Cell::new(0),
+            block: TakeCell::new(block),
+            block_offset: Cell::new(0),
+            block_ready: Cell::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn controller(&self) -> &'a U {
+        self.client_ctrl.controller()
+    }
+
+    #[inline]
+    fn buffer(&'a self, i: usize) -> &'a [VolatileCell<u8>; 64] {
+        &self.buffers[i - 1].buf
+    }
+
+    /// Wait for the next command.
+    fn reset(&self) {
+        self.state.set(State::Command);
+        self.data.set(Data::None);
+        self.blocks_left.set(0);
+        self.block_ready.set(false);
+    }
+
+    /// Parse the CBW in `cbw` and start its command.
+    fn command(&self, cbw: &[u8]) -> hil::usb::OutResult {
+        if cbw.len() != CBW_LEN
+            || u32::from_le_bytes([cbw[0], cbw[1], cbw[2], cbw[3]]) != CBW_SIGNATURE
+        {
+            // Not a valid CBW, the host has to reset the transport.
+            return hil::usb::OutResult::Error;
+        }
+        self.tag
+            .set(u32::from_le_bytes([cbw[4], cbw[5], cbw[6], cbw[7]]));
+        self.data_len
+            .set(u32::from_le_bytes([cbw[8], cbw[9], cbw[10], cbw[11]]));
+        let data_in = cbw[12] & 0x80 != 0;
+        let cb = &cbw[15..31];
+
+        self.transferred.set(0);
+        self.valid_len.set(0);
+        self.status.set(CSW_PASSED);
+        self.data.set(Data::None);
+
+        match cb[0] {
+            scsi::TEST_UNIT_READY => {
+                if self.storage.block_count() == 0 {
+                    self.fail(Sense::MediumNotPresent);
+                }
+            }
+            scsi::REQUEST_SENSE => {
+                let (key, code) = self.sense.replace(Sense::NoSense).key_and_code();
+                let mut sense = [0; 18];
+                sense[0] = 0x70; // Current error, fixed format
+                sense[2] = key;
+                sense[7] = 10; // Additional sense length
+                sense[12] = code;
+                self.respond(&sense, cb[4] as usize);
+            }
+            scsi::INQUIRY => {
+                let mut inquiry = [b' '; RESPONSE_LEN];
+                inquiry[0] = 0x00; // Direct access block device
+                inquiry[1] = 0x80; // Removable
+                inquiry[2] = 0x04; // SPC-2
+                inquiry[3] = 0x02; // Response data format
+                inquiry[4] = (RESPONSE_LEN - 5) as u8;
+                inquiry[5..8].copy_from_slice(&[0, 0, 0]);
+                inquiry[8..12].copy_from_slice(b"Tock");
+                let product = self.product.as_bytes();
+                let product_len = cmp::min(product.len(), 16);
+                inquiry[16..16 + product_len].copy_from_slice(&product[..product_len]);
+                inquiry[32..36].copy_from_slice(b"1.0 ");
+                self.respond(&inquiry, u16::from_be_bytes([cb[3], cb[4]]) as usize);
+            }
+            scsi::MODE_SENSE_6 => {
+                // No mode pages, not write protected.
+                self.respond(&[3, 0, 0, 0], cb[4] as usize);
+            }
+            scsi::MODE_SENSE_10 => {
+                self.respond(
+                    &[0, 6, 0, 0, 0, 0, 0, 0],
+                    u16::from_be_bytes([cb[7], cb[8]]) as usize,
+                );
+            }
+            scsi::START_STOP_UNIT
+            | scsi::PREVENT_ALLOW_MEDIUM_REMOVAL
+            | scsi::VERIFY_10
+            | scsi::SYNCHRONIZE_CACHE_10 => {}
+            scsi::READ_FORMAT_CAPACITIES => {
+                let blocks = self.storage.block_count();
+                let block_size = self.storage.block_size() as u32;
+                let mut capacities = [0; 12];
+                capacities[3] = 8; // Capacity list length
+                capacities[4..8].copy_from_slice(&blocks.to_be_bytes());
+                capacities[8..12].copy_from_slice(&block_size.to_be_bytes());
+                // Formatted media, or no media present.
+                capacities[8] = if blocks == 0 { 0x03 } else { 0x02 };
+                self.respond(&capacities, u16::from_be_bytes([cb[7], cb[8]]) as usize);
+            }
+            scsi::READ_CAPACITY_10 => {
+                let blocks = self.storage.block_count();
+                if blocks == 0 {
+                    self.fail(Sense::MediumNotPresent);
+                } else {
+                    let mut capacity = [0; 8];
+                    capacity[0..4].copy_from_slice(&(blocks - 1).to_be_bytes());
+                    capacity[4..8]
+                        .copy_from_slice(&(self.storage.block_size() as u32).to_be_bytes());
+                    self.respond(&capacity, capacity.len());
+                }
+            }
+            scsi::READ_10 | scsi::WRITE_10 => {
+                let lba = u32::from_be_bytes([cb[2], cb[3], cb[4], cb[5]]);
+                let blocks = u16::from_be_bytes([cb[7], cb[8]]) as u32;
+                let write = cb[0] == scsi::WRITE_10;
+                let len = blocks as u64 * self.storage.block_size() as u64;
+                if self.storage.block_count() == 0 {
+                    self.fail(Sense::MediumNotPresent);
+                } else if lba as u64 + blocks as u64 > self.storage.block_count() as u64 {
+                    self.fail(Sense::LbaOutOfRange);
+                } else if len != self.data_len.get() as u64 || data_in == write {
+                    self.fail(Sense::InvalidField);
+                } else if blocks > 0 {
+                    self.lba.set(lba);
+                    self.blocks_left.set(blocks);
+                    self.block_offset.set(0);
+                    self.block_ready.set(false);
+                    self.valid_len.set(len as u32);
+                    self.data.set(Data::Blocks);
+                    if !write {
+                        self.read_next_block();
+                    }
+                }
+            }
+            _ => self.fail(Sense::InvalidCommand),
+        }
+
+        if self.data_len.get() == 0 {
+            self.send_status();
+        } else if data_in {
+            self.state.set(State::DataIn);
+            self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+        } else {
+            self.state.set(State::DataOut);
+        }
+        hil::usb::OutResult::Ok
+    }
+
+    /// Return `response`, truncated to the allocation length of the
+    /// command.
+    fn respond(&self, response: &[u8], allocation_len: usize) {
+        let mut buffer = [0; RESPONSE_LEN];
+        buffer[..response.len()].copy_from_slice(response);
+        self.response.set(buffer);
+        let len = cmp::min(response.len(), allocation_len);
+        self.valid_len
+            .set(cmp::min(len as u32, self.data_len.get()));
+        self.data.set(Data::Response);
+    }
+
+    fn fail(&self, sense: Sense) {
+        self.sense.set(sense);
+        self.status.set(CSW_FAILED);
+        self.data.set(Data::None);
+        self.valid_len.set(self.transferred.get());
+    }
+
+    fn send_status(&self) {
+        self.state.set(State::Status);
+        self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+    }
+
+    fn read_next_block(&self) {
+        let lba = self.lba.get();
+        if let Some(block) = self.block.take() {
+            if let Err((_, block)) = self.storage.read_block(block, lba) {
+                self.block.replace(block);
+                self.fail(Sense::ReadError);
+            }
+        }
+    }
+
+    /// Fill `packet` with the next bytes of the data stage. Returns `None`
+    /// if they are not available yet.
+    fn data_in(&self, packet: &[VolatileCell<u8>; 64]) -> Option<usize> {
+        let transferred = self.transferred.get();
+        let len = cmp::min(packet.len(), (self.data_len.get() - transferred) as usize);
+        let valid = cmp::min(
+            len,
+            self.valid_len.get().saturating_sub(transferred) as usize,
+        );
+
+        match self.data.get() {
+            Data::Blocks if valid > 0 => {
+                if !self.block_ready.get() {
+                    return None;
+                }
+                let offset = self.block_offset.get();
+                self.block.map(|block| {
+                    for i in 0..valid {
+                        packet[i].set(block[offset + i]);
+                    }
+                });
+                if offset + valid >= self.storage.block_size() {
+                    self.block_offset.set(0);
+                    self.block_ready.set(false);
+                    self.lba.set(self.lba.get() + 1);
+                    self.blocks_left.set(self.blocks_left.get() - 1);
+                    if self.blocks_left.get() > 0 {
+                        self.read_next_block();
+                    }
+                } else {
+                    self.block_offset.set(offset + valid);
+                }
+            }
+            Data::Response => {
+                let response = self.response.get();
+                for i in 0..valid {
+                    packet[i].set(response[transferred as usize + i]);
+                }
+            }
+            _ => {}
+        }
+        for i in valid..len {
+            packet[i].set(0);
+        }
+        self.transferred.set(transferred + len as u32);
+        Some(len)
+    }
+
+    /// Take the next bytes of the data stage from `packet`. Returns whether
+    /// more packets can be received now.
+    fn data_out(&self, packet: &[VolatileCell<u8>; 64], len: usize) -> bool {
+        let transferred = self.transferred.get();
+        let len = cmp::min(len, (self.data_len.get() - transferred) as usize);
+        self.transferred.set(transferred + len as u32);
+
+        if self.data.get() == Data::Blocks {
+            let offset = self.block_offset.get();
+            let block_size = self.storage.block_size();
+            let len = cmp::min(len, block_size - offset);
+            self.block.map(|block| {
+                for i in 0..len {
+                    block[offset + i] = packet[i].get();
+                }
+            });
+            if offset + len == block_size {
+                self.block_offset.set(0);
+                if let Some(block) = self.block.take() {
+                    match self.storage.write_block(block, self.lba.get()) {
+                        // Wait for the block to be written before receiving
+                        // the next one.
+                        Ok(()) => return false,
+                        Err((_, block)) => {
+                            self.block.replace(block);
+                            self.fail(Sense::WriteError);
+                        }
+                    }
+                }
+            } else {
+                self.block_offset.set(offset + len);
+            }
+        }
+
+        if self.transferred.get() >= self.data_len.get() {
+            self.send_status();
+        }
+        true
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, B: BlockStorage<'a>> block_storage::Client
+    for MassStorage<'a, U, B>
+{
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.block.replace(buffer);
+        if self.state.get() != State::DataIn {
+            // The transport was reset.
+            return;
+        }
+        match result {
+            Ok(()) => self.block_ready.set(true),
+            Err(_) => self.fail(Sense::ReadError),
+        }
+        self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.block.replace(buffer);
+        if self.state.get() == State::DataOut {
+            match result {
+                Ok(()) => {
+                    self.lba.set(self.lba.get() + 1);
+                    self.blocks_left.set(self.blocks_left.get() - 1);
+                }
+                Err(_) => self.fail(Sense::WriteError),
+            }
+            if self.transferred.get() >= self.data_len.get() {
+                self.send_status();
+            }
+        }
+        self.controller().endpoint_resume_out(ENDPOINT_OUT_NUM);
+    }
+}
+
+impl<'a, U: hil::usb::UsbController<'a>, B: BlockStorage<'a>> hil::usb::Client<'a>
+    for MassStorage<'a, U, B>
+{
+    fn enable(&'a self) {
+        // Set up the default control endpoint
+        self.client_ctrl.enable();
+
+        // Setup buffers for IN and OUT data transfer.
+        self.controller()
+            .endpoint_set_in_buffer(ENDPOINT_IN_NUM, self.buffer(ENDPOINT_IN_NUM));
+        self.controller()
+            .endpoint_in_enable(TransferType::Bulk, ENDPOINT_IN_NUM);
+
+        self.controller()
+            .endpoint_set_out_buffer(ENDPOINT_OUT_NUM, self.buffer(ENDPOINT_OUT_NUM));
+        self.controller()
+            .endpoint_out_enable(TransferType::Bulk, ENDPOINT_OUT_NUM);
+    }
+
+    fn attach(&'a self) {
+        self.client_ctrl.attach();
+    }
+
+    fn bus_reset(&'a self) {
+        self.reset();
+    }
+
+    /// Handle a Control Setup transaction.
+    ///
+    /// The two class requests of the Bulk-Only Transport are handled here,
+    /// everything else by `ClientCtrl`.
+    fn ctrl_setup(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        let setup_data = match descriptors::SetupData::get(&self.client_ctrl.ctrl_buffer.buf) {
+            Some(setup_data) => setup_data,
+            None => return self.client_ctrl.ctrl_setup(endpoint),
+        };
+
+        match setup_data.request_type.request_type() {
+            RequestType::Class => match setup_data.request_code {
+                GET_MAX_LUN => {
+                    // A single logical unit.
+                    self.client_ctrl
+                        .ctrl_setup_in_data(endpoint, &[0], setup_data.length)
+                }
+                BULK_ONLY_RESET => {
+                    self.reset();
+                    self.client_ctrl.ctrl_setup_out_data(endpoint)
+                }
+                _ => hil::usb::CtrlSetupResult::ErrNonstandardRequest,
+            },
+            _ => self.client_ctrl.ctrl_setup(endpoint),
+        }
+    }
+
+    /// Handle a Control In transaction
+    fn ctrl_in(&'a self, endpoint: usize) -> hil::usb::CtrlInResult {
+        self.client_ctrl.ctrl_in(endpoint)
+    }
+
+    /// Handle a Control Out transaction
+    fn ctrl_out(&'a self, endpoint: usize, packet_bytes: u32) -> hil::usb::CtrlOutResult {
+        self.client_ctrl.ctrl_out(endpoint, packet_bytes)
+    }
+
+    fn ctrl_status(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status(endpoint)
+    }
+
+    /// Handle the completion of a Control transfer
+    fn ctrl_status_complete(&'a self, endpoint: usize) {
+        self.client_ctrl.ctrl_status_complete(endpoint)
+    }
+
+    /// Handle a Bulk/Interrupt IN transaction.
+    fn packet_in(&'a self, transfer_type: TransferType, endpoint: usize) -> hil::usb::InResult {
+        if !matches!(transfer_type, TransferType::Bulk) {
+            return hil::usb::InResult::Delay;
+        }
+        let packet = self.buffer(endpoint);
+        match self.state.get() {
+            State::DataIn => match self.data_in(packet) {
+                Some(len) => hil::usb::InResult::Packet(len),
+                None => hil::usb::InResult::Delay,
+            },
+            State::Status => {
+                let residue =
+                    self.data_len.get() - cmp::min(self.valid_len.get(), self.data_len.get());
+                let mut csw = [0; CSW_LEN];
+                csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+                csw[4..8].copy_from_slice(&self.tag.get().to_le_bytes());
+                csw[8..12].copy_from_slice(&residue.to_le_bytes());
+                csw[12] = self.status.get();
+                for i in 0..CSW_LEN {
+                    packet[i].set(csw[i]);
+                }
+                hil::usb::InResult::Packet(CSW_LEN)
+            }
+            State::Command | State::DataOut => hil::usb::InResult::Delay,
+        }
+    }
+
+    /// Handle a Bulk/Interrupt OUT transaction
+    fn packet_out(
+        &'a self,
+        transfer_type: TransferType,
+        endpoint: usize,
+        packet_bytes: u32,
+    ) -> hil::usb::OutResult {
+        if !matches!(transfer_type, TransferType::Bulk) {
+            return hil::usb::OutResult::Ok;
+        }
+        let packet = self.buffer(endpoint);
+        let packet_bytes = cmp::min(packet_bytes as usize, packet.len());
+        match self.state.get() {
+            State::Command => {
+                let mut cbw = [0; CBW_LEN];
+                for i in 0..cmp::min(packet_bytes, CBW_LEN) {
+                    cbw[i] = packet[i].get();
+                }
+                self.command(&cbw[..cmp::min(packet_bytes, CBW_LEN)])
+            }
+            State::DataOut => {
+                if self.data_out(packet, packet_bytes) {
+                    hil::usb::OutResult::Ok
+                } else {
+                    // The packet is consumed, hold off the next one until
+                    // the block is written.
+                    hil::usb::OutResult::Delay
+                }
+            }
+            State::DataIn | State::Status => hil::usb::OutResult::Error,
+        }
+    }
+
+    fn packet_transmitted(&'a self, _endpoint: usize) {
+        match self.state.get() {
+            State::DataIn => {
+                if self.transferred.get() >= self.data_len.get() {
+                    self.send_status();
+                } else {
+                    self.controller().endpoint_resume_in(ENDPOINT_IN_NUM);
+                }
+            }
+            State::Status => self.reset(),
+            State::Command | State::DataOut => {}
+        }
+    }
+}
@@ -236,6 +236,34 @@ impl<'a, 'b, U: hil::usb::UsbController<'a>> ClientCtrl<'a, 'b, U> {
         )
     }
 
+    /// Answer a class or vendor Control In request, which the user of
+    /// `ClientCtrl` handled itself, with `data`. At most `requested_length`
+    /// bytes are sent.
+    pub fn ctrl_setup_in_data(
+        &'a self,
+        endpoint: usize,
+        data: &[u8],
+        requested_length: u16,
+    ) -> hil::usb::CtrlSetupResult {
+        let buf = self.descriptor_buf();
+        if data.len() > buf.len() {
+            return hil::usb::CtrlSetupResult::ErrGeneric;
+        }
+        for (cell, byte) in buf.iter().zip(data.iter()) {
+            cell.set(*byte);
+        }
+        let end = min(data.len(), requested_length as usize);
+        self.state[endpoint].set(State::CtrlIn(0, end));
+        hil::usb::CtrlSetupResult::Ok
+    }
+
+    /// Accept the data of a class or vendor Control Out request, which the
+    /// user of `ClientCtrl` handles itself in `ctrl_out()`.
+    pub fn ctrl_setup_out_data(&'a self, endpoint: usize) -> hil::usb::CtrlSetupResult {
+        self.state[endpoint].set(State::CtrlOut);
+        hil::usb::CtrlSetupResult::Ok
+    }
+
     fn handle_standard_device_request(
         &'a self,
         endpoint: usize,
@@ -0,0 +1,421 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Class driver for USB serial adapters of the CDC ACM class.
+//!
+//! The driver claims a device with a CDC abstract control model interface
+//! and a data interface with a bulk IN and a bulk OUT endpoint. It sets the
+//! line coding to the baud rate it was created with, 8N1, raises DTR and
+//! RTS, and then provides the data interface as a `hil::uart`.
+//!
+//! Data is only read from the device while a receive is in progress. Bytes
+//! of a packet beyond the receive buffer are kept for the next receive.
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::uart;
+use kernel::hil::usb::TransferType;
+use kernel::hil::usb_host::{self, Direction, Endpoint};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::stack::{self, Class, Descriptors, Device};
+
+/// Length of the buffer for the packets of the bulk IN endpoint. It must
+/// hold a packet of the device.
+pub const PACKET_BUFFER_LEN: usize = 64;
+
+const INTERFACE_CLASS_COMMUNICATIONS: u8 = 2;
+const INTERFACE_SUBCLASS_ACM: u8 = 2;
+const INTERFACE_CLASS_DATA: u8 = 0x0a;
+
+/// CDC class requests.
+mod request {
+    pub const SET_LINE_CODING: u8 = 0x20;
+    pub const SET_CONTROL_LINE_STATE: u8 = 0x22;
+}
+
+const LINE_CODING_LEN: usize = 7;
+/// DTR and RTS.
+const CONTROL_LINE_STATE: u16 = 0x03;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Detached,
+    SettingLineCoding,
+    SettingControlLineState,
+    Ready,
+}
+
+pub struct CdcAcm<'a, H: usb_host::UsbHost<'a>> {
+    host: &'a H,
+    baud_rate: u32,
+    state: Cell<State>,
+    device: Cell<Option<Device>>,
+    interface: Cell<u8>,
+    endpoint_in: Cell<Option<Endpoint>>,
+    endpoint_out: Cell<Option<Endpoint>>,
+    pipe_in: OptionalCell<usize>,
+    pipe_out: OptionalCell<usize>,
+
+    /// The buffer of the IN pipe, while no transfer is in progress on it.
+    /// It also carries the control transfers.
+    packet: TakeCell<'static, [u8]>,
+    /// Bytes of the last packet not received yet.
+    packet_start: Cell<usize>,
+    packet_end: Cell<usize>,
+
+    rx_client: OptionalCell<&'a dyn uart::ReceiveClient>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    rx_len: Cell<usize>,
+    rx_index: Cell<usize>,
+    /// The result of the receive to return from the deferred call.
+    rx_done: Cell<Option<Result<(), ErrorCode>>>,
+
+    tx_client: OptionalCell<&'a dyn uart::TransmitClient>,
+    tx_in_progress: Cell<bool>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> CdcAcm<'a, H> {
+    pub fn new(host: &'a H, baud_rate: u32, packet: &'static mut [u8; PACKET_BUFFER_LEN]) -> Self {
+        CdcAcm {
+            host,
+            baud_rate,
+            state: Cell::new(State::Detached),
+            device: Cell::new(None),
+            interface: Cell::new(0),
+            endpoint_in: Cell::new(None),
+            endpoint_out: Cell::new(None),
+            pipe_in: OptionalCell::empty(),
+            pipe_out: OptionalCell::empty(),
+            packet: TakeCell::new(packet),
+            packet_start: Cell::new(0),
+            packet_end: Cell::new(0),
+            rx_client: OptionalCell::empty(),
+            rx_buffer: TakeCell::empty(),
+            rx_len: Cell::new(0),
+            rx_index: Cell::new(0),
+            rx_done: Cell::new(None),
+            tx_client: OptionalCell::empty(),
+            tx_in_progress: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Send a class request to the communications interface, with `data`.
+    fn control(&self, state: State, request: u8, value: u16, data: &[u8]) {
+        let device = match self.device.get() {
+            Some(device) => device,
+            None => return,
+        };
+        if let Some(buffer) = self.packet.take() {
+            buffer[..data.len()].copy_from_slice(data);
+            let setup = stack::setup_packet(
+                0x21,
+                request,
+                value,
+                self.interface.get() as u16,
+                data.len() as u16,
+            );
+            match self.host.control_transfer(
+                device.address,
+                device.max_packet_size,
+                setup,
+                buffer,
+                data.len(),
+            ) {
+                Ok(()) => self.state.set(state),
+                Err((_, buffer)) => {
+                    self.packet.replace(buffer);
+                }
+            }
+        }
+    }
+
+    /// Open the pipes of the data interface.
+    fn open_pipes(&self) {
+        let pipes = self.endpoint_in.get().zip(self.endpoint_out.get()).map(
+            |(endpoint_in, endpoint_out)| {
+                (
+                    self.host.open_pipe(endpoint_in),
+                    self.host.open_pipe(endpoint_out),
+                )
+            },
+        );
+        match pipes {
+            Some((Ok(pipe_in), Ok(pipe_out))) => {
+                self.pipe_in.set(pipe_in);
+                self.pipe_out.set(pipe_out);
+                self.state.set(State::Ready);
+                self.receive_more();
+            }
+            Some((pipe_in, pipe_out)) => {
+                let _ = pipe_in.map(|pipe| self.host.close_pipe(pipe));
+                let _ = pipe_out.map(|pipe| self.host.close_pipe(pipe));
+            }
+            None => {}
+        }
+    }
+
+    /// Copy the bytes kept from the last packet into the receive buffer.
+    /// Returns whether the receive buffer is full.
+    fn drain_packet(&self) -> bool {
+        let start = self.packet_start.get();
+        let end = self.packet_end.get();
+        let index = self.rx_index.get();
+        let count = (end - start).min(self.rx_len.get() - index);
+        self.rx_buffer.map(|rx_buffer| {
+            self.packet.map(|packet| {
+                rx_buffer[index..index + count].copy_from_slice(&packet[start..start + count]);
+            });
+        });
+        self.packet_start.set(start + count);
+        self.rx_index.set(index + count);
+        self.rx_index.get() == self.rx_len.get()
+    }
+
+    /// Continue the receive in progress: complete it, or read the next
+    /// packet from the device.
+    fn receive_more(&self) {
+        if self.rx_buffer.is_none() || self.rx_done.get().is_some() {
+            return;
+        }
+        if self.drain_packet() {
+            self.rx_done.set(Some(Ok(())));
+            self.deferred_call.set();
+            return;
+        }
+        if self.state.get() != State::Ready {
+            return;
+        }
+        if let (Some(pipe), Some(packet)) = (self.pipe_in.extract(), self.packet.take()) {
+            let len = packet.len();
+            if let Err((_, packet)) = self.host.transfer(pipe, packet, len) {
+                self.packet.replace(packet);
+            }
+        }
+    }
+
+    fn receive_complete(&self, result: Result<(), ErrorCode>) {
+        if let Some(rx_buffer) = self.rx_buffer.take() {
+            let len = self.rx_index.get();
+            self.rx_client
+                .map(|client| client.received_buffer(rx_buffer, len, result, uart::Error::None));
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> Class for CdcAcm<'a, H> {
+    fn probe(&self, device: &Device, configuration: &[u8]) -> bool {
+        let mut communications = None;
+        let mut data = false;
+        let mut endpoint_in = None;
+        let mut endpoint_out = None;
+        for descriptor in Descriptors::new(configuration) {
+            if stack::is_interface(descriptor) {
+                data = false;
+                if descriptor[5] == INTERFACE_CLASS_COMMUNICATIONS
+                    && descriptor[6] == INTERFACE_SUBCLASS_ACM
+                    && communications.is_none()
+                {
+                    communications = Some(descriptor[2]);
+                } else if descriptor[5] == INTERFACE_CLASS_DATA && communications.is_some() {
+                    data = true;
+                }
+            } else if let Some(endpoint) = stack::endpoint(device.address, descriptor) {
+                if data && matches!(endpoint.transfer_type, TransferType::Bulk) {
+                    match endpoint.direction {
+                        Direction::In => endpoint_in = endpoint_in.or(Some(endpoint)),
+                        Direction::Out => endpoint_out = endpoint_out.or(Some(endpoint)),
+                    }
+                }
+            }
+        }
+        match (communications, endpoint_in, endpoint_out) {
+            (Some(interface), Some(endpoint_in), Some(endpoint_out))
+                if endpoint_in.max_packet_size as usize <= PACKET_BUFFER_LEN =>
+            {
+                self.interface.set(interface);
+                self.endpoint_in.set(Some(endpoint_in));
+                self.endpoint_out.set(Some(endpoint_out));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn configured(&self, device: &Device) {
+        self.device.set(Some(*device));
+        self.packet_start.set(0);
+        self.packet_end.set(0);
+        let mut line_coding = [0; LINE_CODING_LEN];
+        line_coding[..4].copy_from_slice(&self.baud_rate.to_le_bytes());
+        // 1 stop bit, no parity, 8 data bits.
+        line_coding[6] = 8;
+        self.control(
+            State::SettingLineCoding,
+            request::SET_LINE_CODING,
+            0,
+            &line_coding,
+        );
+    }
+
+    fn disconnected(&self) {
+        self.state.set(State::Detached);
+        self.device.set(None);
+        self.pipe_in.clear();
+        self.pipe_out.clear();
+        if self.rx_buffer.is_some() && self.rx_done.get().is_none() {
+            self.rx_done.set(Some(Err(ErrorCode::NODEVICE)));
+            self.deferred_call.set();
+        }
+    }
+
+    fn control_done(&self, buffer: &'static mut [u8], _len: usize, result: Result<(), ErrorCode>) {
+        self.packet.replace(buffer);
+        match (self.state.get(), result) {
+            // Some adapters have fixed settings, and stall the requests.
+            (State::SettingLineCoding, _) => self.control(
+                State::SettingControlLineState,
+                request::SET_CONTROL_LINE_STATE,
+                CONTROL_LINE_STATE,
+                &[],
+            ),
+            (State::SettingControlLineState, _) => self.open_pipes(),
+            _ => {}
+        }
+    }
+
+    fn transfer_done(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    ) {
+        if self.pipe_out.contains(&pipe) {
+            self.tx_in_progress.set(false);
+            self.tx_client
+                .map(|client| client.transmitted_buffer(buffer, len, result));
+            return;
+        }
+
+        self.packet.replace(buffer);
+        match result {
+            Ok(()) => {
+                self.packet_start.set(0);
+                self.packet_end.set(len);
+                self.receive_more();
+            }
+            Err(ErrorCode::NODEVICE) | Err(ErrorCode::CANCEL) => {}
+            Err(err) => {
+                if self.rx_buffer.is_some() && self.rx_done.get().is_none() {
+                    self.rx_done.set(Some(Err(err)));
+                    self.deferred_call.set();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> DeferredCallClient for CdcAcm<'a, H> {
+    fn handle_deferred_call(&self) {
+        if let Some(result) = self.rx_done.take() {
+            self.receive_complete(result);
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> uart::Transmit<'a> for CdcAcm<'a, H> {
+    fn set_transmit_client(&self, client: &'a dyn uart::TransmitClient) {
+        self.tx_client.set(client);
+    }
+
+    fn transmit_buffer(
+        &self,
+        tx_buffer: &'static mut [u8],
+        tx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Ready {
+            return Err((ErrorCode::OFF, tx_buffer));
+        }
+        if self.tx_in_progress.get() {
+            return Err((ErrorCode::BUSY, tx_buffer));
+        }
+        if tx_len > tx_buffer.len() {
+            return Err((ErrorCode::SIZE, tx_buffer));
+        }
+        match self.pipe_out.extract() {
+            Some(pipe) => {
+                self.host.transfer(pipe, tx_buffer, tx_len)?;
+                self.tx_in_progress.set(true);
+                Ok(())
+            }
+            None => Err((ErrorCode::OFF, tx_buffer)),
+        }
+    }
+
+    fn transmit_word(&self, _word: u32) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn transmit_abort(&self) -> Result<(), ErrorCode> {
+        // The packets handed to the controller are sent anyway.
+        if self.tx_in_progress.get() {
+            Err(ErrorCode::FAIL)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> uart::Receive<'a> for CdcAcm<'a, H> {
+    fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+        self.rx_client.set(client);
+    }
+
+    fn receive_buffer(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Ready {
+            return Err((ErrorCode::OFF, rx_buffer));
+        }
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        if rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+        self.rx_buffer.replace(rx_buffer);
+        self.rx_len.set(rx_len);
+        self.rx_index.set(0);
+        self.receive_more();
+        Ok(())
+    }
+
+    fn receive_word(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::FAIL)
+    }
+
+    fn receive_abort(&self) -> Result<(), ErrorCode> {
+        if self.rx_buffer.is_none() {
+            return Ok(());
+        }
+        // A packet being read is kept for the next receive.
+        if self.rx_done.get().is_none() {
+            self.rx_done.set(Some(Err(ErrorCode::CANCEL)));
+            self.deferred_call.set();
+        }
+        Err(ErrorCode::BUSY)
+    }
+}
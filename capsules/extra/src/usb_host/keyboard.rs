@@ -0,0 +1,189 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Class driver for USB keyboards, with the HID boot protocol.
+//!
+//! The driver claims the first boot keyboard interface of a device, switches
+//! it to the boot protocol, whose reports have a fixed layout, and polls its
+//! interrupt IN endpoint. Every time the keys pressed change, the client gets
+//! the modifier keys and the usage IDs (HID usage page 7) of the other keys
+//! pressed.
+
+use core::cell::Cell;
+
+use kernel::hil::usb::TransferType;
+use kernel::hil::usb_host::{self, Direction, Endpoint};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use super::stack::{self, Class, Descriptors, Device};
+
+/// Length of a boot keyboard report.
+pub const REPORT_LEN: usize = 8;
+
+const INTERFACE_CLASS_HID: u8 = 3;
+const INTERFACE_SUBCLASS_BOOT: u8 = 1;
+const INTERFACE_PROTOCOL_KEYBOARD: u8 = 1;
+
+/// HID class request to select the boot or the report protocol.
+const REQUEST_SET_PROTOCOL: u8 = 0x0b;
+const BOOT_PROTOCOL: u16 = 0;
+
+/// Receives the keys pressed on the keyboard.
+pub trait KeyboardClient {
+    /// The keys pressed changed. `modifiers` has a bit per modifier key,
+    /// `keys` the usage IDs of up to 6 other keys pressed.
+    fn keys_changed(&self, modifiers: u8, keys: &[u8]);
+}
+
+pub struct Keyboard<'a, H: usb_host::UsbHost<'a>> {
+    host: &'a H,
+    client: OptionalCell<&'a dyn KeyboardClient>,
+    interface: Cell<u8>,
+    endpoint: Cell<Option<Endpoint>>,
+    pipe: OptionalCell<usize>,
+    buffer: TakeCell<'static, [u8]>,
+    /// The last report, to only notify changes.
+    last: Cell<[u8; REPORT_LEN]>,
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> Keyboard<'a, H> {
+    pub fn new(host: &'a H, buffer: &'static mut [u8; REPORT_LEN]) -> Self {
+        Keyboard {
+            host,
+            client: OptionalCell::empty(),
+            interface: Cell::new(0),
+            endpoint: Cell::new(None),
+            pipe: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            last: Cell::new([0; REPORT_LEN]),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn KeyboardClient) {
+        self.client.set(client);
+    }
+
+    /// Wait for the next report.
+    fn poll(&self, buffer: &'static mut [u8]) {
+        match self.pipe.extract() {
+            Some(pipe) => {
+                if let Err((_, buffer)) = self.host.transfer(pipe, buffer, REPORT_LEN) {
+                    self.buffer.replace(buffer);
+                }
+            }
+            None => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>> Class for Keyboard<'a, H> {
+    fn probe(&self, device: &Device, configuration: &[u8]) -> bool {
+        let mut interface = None;
+        for descriptor in Descriptors::new(configuration) {
+            if stack::is_interface(descriptor) {
+                if interface.is_some() {
+                    break;
+                }
+                if descriptor[5] == INTERFACE_CLASS_HID
+                    && descriptor[6] == INTERFACE_SUBCLASS_BOOT
+                    && descriptor[7] == INTERFACE_PROTOCOL_KEYBOARD
+                {
+                    interface = Some(descriptor[2]);
+                }
+            } else if let (Some(number), Some(endpoint)) =
+                (interface, stack::endpoint(device.address, descriptor))
+            {
+                if endpoint.direction == Direction::In
+                    && matches!(endpoint.transfer_type, TransferType::Interrupt)
+                {
+                    self.interface.set(number);
+                    self.endpoint.set(Some(endpoint));
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn configured(&self, device: &Device) {
+        self.last.set([0; REPORT_LEN]);
+        if let Some(buffer) = self.buffer.take() {
+            let setup = stack::setup_packet(
+                0x21,
+                REQUEST_SET_PROTOCOL,
+                BOOT_PROTOCOL,
+                self.interface.get() as u16,
+                0,
+            );
+            if let Err((_, buffer)) =
+                self.host
+                    .control_transfer(device.address, device.max_packet_size, setup, buffer, 0)
+            {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+
+    fn disconnected(&self) {
+        self.pipe.clear();
+        self.endpoint.set(None);
+        if self.last.get()[2..].iter().any(|&key| key != 0) || self.last.get()[0] != 0 {
+            self.client.map(|client| client.keys_changed(0, &[]));
+        }
+        self.last.set([0; REPORT_LEN]);
+    }
+
+    fn control_done(&self, buffer: &'static mut [u8], _len: usize, _result: Result<(), ErrorCode>) {
+        // Keyboards that do not support the request are in the boot
+        // protocol already.
+        match self
+            .endpoint
+            .get()
+            .map(|endpoint| self.host.open_pipe(endpoint))
+        {
+            Some(Ok(pipe)) => {
+                self.pipe.set(pipe);
+                self.poll(buffer);
+            }
+            _ => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+
+    fn transfer_done(
+        &self,
+        _pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    ) {
+        match result {
+            Ok(()) if len >= 3 => {
+                let mut report = [0; REPORT_LEN];
+                report[..len.min(REPORT_LEN)].copy_from_slice(&buffer[..len.min(REPORT_LEN)]);
+                if report != self.last.get() {
+                    self.last.set(report);
+                    let mut keys = [0; REPORT_LEN - 2];
+                    let mut count = 0;
+                    for &key in report[2..].iter().filter(|&&key| key != 0) {
+                        keys[count] = key;
+                        count += 1;
+                    }
+                    self.client
+                        .map(|client| client.keys_changed(report[0], &keys[..count]));
+                }
+                self.poll(buffer);
+            }
+            Ok(()) => self.poll(buffer),
+            Err(ErrorCode::NODEVICE) | Err(ErrorCode::CANCEL) | Err(ErrorCode::FAIL) => {
+                self.buffer.replace(buffer);
+            }
+            Err(_) => self.poll(buffer),
+        }
+    }
+}
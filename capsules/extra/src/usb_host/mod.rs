@@ -0,0 +1,9 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! USB host stack, for a single device connected without a hub.
+
+pub mod cdc_acm;
+pub mod keyboard;
+pub mod stack;
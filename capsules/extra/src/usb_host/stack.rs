@@ -0,0 +1,507 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Enumerates the device connected to a USB host controller.
+//!
+//! When a device is connected, the stack waits for the connection to
+//! settle, resets the port, assigns the device an address and reads its
+//! device and configuration descriptors. It then offers the device to its
+//! class drivers in order, and sets the configuration for the first one that
+//! claims it. From then on the class driver talks to the device through the
+//! controller, and gets the completions of its transfers through the stack.
+//!
+//! Devices no class driver claims, and devices that fail to enumerate, are
+//! left alone until they are disconnected.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let (usb_host, keyboard, serial) = components::usb_host::UsbHostComponent::new(
+//!     &peripherals.usb_otg_fs,
+//!     mux_alarm,
+//!     115200,
+//! )
+//! .finalize(components::usb_host_component_static!(
+//!     stm32f429zi::usb_otg_fs::UsbOtgFs<'static>,
+//!     stm32f429zi::tim2::Tim2
+//! ));
+//!
+//! usb_host.start().unwrap();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::hil::usb::TransferType;
+use kernel::hil::usb_host::{self, Direction, Endpoint, Speed};
+use kernel::utilities::cells::TakeCell;
+use kernel::ErrorCode;
+
+use crate::usb::descriptors::DescriptorType;
+
+/// The address the device is given.
+pub const DEVICE_ADDRESS: u8 = 1;
+
+/// Length of the buffer for the descriptors. Longer configuration
+/// descriptors are truncated.
+pub const DESCRIPTOR_BUFFER_LEN: usize = 256;
+
+const DEVICE_DESCRIPTOR_LEN: usize = 18;
+const CONFIGURATION_DESCRIPTOR_LEN: usize = 9;
+
+/// Standard request codes.
+mod request {
+    pub const SET_ADDRESS: u8 = 5;
+    pub const GET_DESCRIPTOR: u8 = 6;
+    pub const SET_CONFIGURATION: u8 = 9;
+}
+
+/// Times of the enumeration, in milliseconds.
+const DEBOUNCE_MS: u32 = 100;
+const RESET_MS: u32 = 20;
+const RESET_RECOVERY_MS: u32 = 10;
+const SET_ADDRESS_RECOVERY_MS: u32 = 2;
+
+/// Build a setup packet.
+pub fn setup_packet(request_type: u8, request: u8, value: u16, index: u16, length: u16) -> [u8; 8] {
+    let mut setup = [request_type, request, 0, 0, 0, 0, 0, 0];
+    setup[2..4].copy_from_slice(&value.to_le_bytes());
+    setup[4..6].copy_from_slice(&index.to_le_bytes());
+    setup[6..8].copy_from_slice(&length.to_le_bytes());
+    setup
+}
+
+/// Iterates over the descriptors in a configuration descriptor.
+pub struct Descriptors<'b> {
+    data: &'b [u8],
+}
+
+impl<'b> Descriptors<'b> {
+    pub fn new(configuration: &'b [u8]) -> Self {
+        Descriptors {
+            data: configuration,
+        }
+    }
+}
+
+impl<'b> Iterator for Descriptors<'b> {
+    type Item = &'b [u8];
+
+    fn next(&mut self) -> Option<&'b [u8]> {
+        let len = *self.data.first()? as usize;
+        if len < 2 || len > self.data.len() {
+            return None;
+        }
+        let (descriptor, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(descriptor)
+    }
+}
+
+/// Whether `descriptor` is an interface descriptor.
+pub fn is_interface(descriptor: &[u8]) -> bool {
+    descriptor.len() >= 9 && descriptor[1] == DescriptorType::Interface as u8
+}
+
+/// The bulk or interrupt endpoint of the device at `address` described by
+/// `descriptor`, if it is one.
+pub fn endpoint(address: u8, descriptor: &[u8]) -> Option<Endpoint> {
+    if descriptor.len() < 7 || descriptor[1] != DescriptorType::Endpoint as u8 {
+        return None;
+    }
+    let transfer_type = match descriptor[3] & 0x03 {
+        2 => TransferType::Bulk,
+        3 => TransferType::Interrupt,
+        _ => return None,
+    };
+    Some(Endpoint {
+        address,
+        number: descriptor[2] & 0x0f,
+        direction: if descriptor[2] & 0x80 != 0 {
+            Direction::In
+        } else {
+            Direction::Out
+        },
+        transfer_type,
+        max_packet_size: u16::from_le_bytes([descriptor[4], descriptor[5]]) & 0x7ff,
+    })
+}
+
+/// The device being enumerated or configured.
+#[derive(Clone, Copy, Debug)]
+pub struct Device {
+    pub address: u8,
+    pub speed: Speed,
+    /// Maximum packet size of the default control endpoint.
+    pub max_packet_size: u16,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub protocol: u8,
+}
+
+/// A driver for a class of devices.
+pub trait Class {
+    /// Whether the driver handles `device`, whose configuration descriptor,
+    /// with the descriptors that follow it, is `configuration`.
+    fn probe(&self, device: &Device, configuration: &[u8]) -> bool;
+
+    /// The configuration of the claimed device was set. The driver opens
+    /// its pipes and starts talking to the device.
+    fn configured(&self, device: &Device);
+
+    /// The device was disconnected, and the pipes are closed.
+    fn disconnected(&self);
+
+    /// A control transfer of the driver finished.
+    fn control_done(&self, buffer: &'static mut [u8], len: usize, result: Result<(), ErrorCode>);
+
+    /// A transfer on a pipe of the driver finished.
+    fn transfer_done(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    );
+}
+
+/// A standard request of the enumeration.
+struct Request {
+    /// The state while the request is in progress.
+    state: State,
+    request_type: u8,
+    request: u8,
+    value: u16,
+    len: usize,
+}
+
+impl Request {
+    fn get_descriptor(state: State, descriptor_type: DescriptorType, len: usize) -> Self {
+        Request {
+            state,
+            request_type: 0x80,
+            request: request::GET_DESCRIPTOR,
+            value: (descriptor_type as u16) << 8,
+            len,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Detached,
+    /// Waiting for the connection to settle.
+    Debouncing,
+    Resetting,
+    /// Waiting for the device to recover from the reset.
+    Recovering,
+    ReadingMaxPacketSize,
+    SettingAddress,
+    /// Waiting for the device to take its address.
+    Addressing,
+    ReadingDevice,
+    ReadingConfigurationLength,
+    ReadingConfiguration,
+    Configuring(usize),
+    Configured(usize),
+    /// No class driver claimed the device, or it failed to enumerate.
+    Idle,
+}
+
+pub struct UsbHostStack<'a, H: usb_host::UsbHost<'a>, A: time::Alarm<'a>> {
+    host: &'a H,
+    alarm: &'a A,
+    classes: &'a [&'a dyn Class],
+    state: Cell<State>,
+    device: Cell<Device>,
+    buffer: TakeCell<'static, [u8]>,
+}
+
+impl<'a, H: usb_host::UsbHost<'a>, A: time::Alarm<'a>> UsbHostStack<'a, H, A> {
+    pub fn new(
+        host: &'a H,
+        alarm: &'a A,
+        classes: &'a [&'a dyn Class],
+        buffer: &'static mut [u8; DESCRIPTOR_BUFFER_LEN],
+    ) -> Self {
+        UsbHostStack {
+            host,
+            alarm,
+            classes,
+            state: Cell::new(State::Detached),
+            device: Cell::new(Device {
+                address: 0,
+                speed: Speed::Full,
+                max_packet_size: 8,
+                vendor_id: 0,
+                product_id: 0,
+                class: 0,
+                subclass: 0,
+                protocol: 0,
+            }),
+            buffer: TakeCell::new(buffer),
+        }
+    }
+
+    /// Power the port and start enumerating connected devices.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        self.host.enable()
+    }
+
+    /// The device, once it was enumerated.
+    pub fn device(&self) -> Option<Device> {
+        match self.state.get() {
+            State::Configuring(_) | State::Configured(_) | State::Idle => Some(self.device.get()),
+            _ => None,
+        }
+    }
+
+    fn wait(&self, ms: u32) {
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    /// Send a standard request to the device, with the response read into
+    /// our buffer.
+    fn send(&self, request: Request) {
+        let result = match self.buffer.take() {
+            Some(buffer) => {
+                let device = self.device.get();
+                let len = request.len.min(buffer.len());
+                let setup = setup_packet(
+                    request.request_type,
+                    request.request,
+                    request.value,
+                    0,
+                    len as u16,
+                );
+                self.host
+                    .control_transfer(device.address, device.max_packet_size, setup, buffer, len)
+                    .map_err(|(err, buffer)| {
+                        self.buffer.replace(buffer);
+                        err
+                    })
+            }
+            None => Err(ErrorCode::BUSY),
+        };
+        self.state.set(match result {
+            Ok(()) => request.state,
+            Err(_) => State::Idle,
+        });
+    }
+
+    /// The next step of the enumeration, after a control transfer of the
+    /// stack completed with `buffer[..len]`. Returns the next request.
+    fn enumerate(&self, buffer: &[u8], len: usize) -> Option<Request> {
+        let mut device = self.device.get();
+        match self.state.get() {
+            State::ReadingMaxPacketSize if len >= 8 => {
+                device.max_packet_size = buffer[7] as u16;
+                self.device.set(device);
+                Some(Request {
+                    state: State::SettingAddress,
+                    request_type: 0x00,
+                    request: request::SET_ADDRESS,
+                    value: DEVICE_ADDRESS as u16,
+                    len: 0,
+                })
+            }
+            State::SettingAddress => {
+                device.address = DEVICE_ADDRESS;
+                self.device.set(device);
+                self.state.set(State::Addressing);
+                self.wait(SET_ADDRESS_RECOVERY_MS);
+                None
+            }
+            State::ReadingDevice if len >= DEVICE_DESCRIPTOR_LEN => {
+                device.vendor_id = u16::from_le_bytes([buffer[8], buffer[9]]);
+                device.product_id = u16::from_le_bytes([buffer[10], buffer[11]]);
+                device.class = buffer[4];
+                device.subclass = buffer[5];
+                device.protocol = buffer[6];
+                self.device.set(device);
+                Some(Request::get_descriptor(
+                    State::ReadingConfigurationLength,
+                    DescriptorType::Configuration,
+                    CONFIGURATION_DESCRIPTOR_LEN,
+                ))
+            }
+            State::ReadingConfigurationLength if len >= CONFIGURATION_DESCRIPTOR_LEN => {
+                let total = u16::from_le_bytes([buffer[2], buffer[3]]) as usize;
+                Some(Request::get_descriptor(
+                    State::ReadingConfiguration,
+                    DescriptorType::Configuration,
+                    total,
+                ))
+            }
+            State::ReadingConfiguration if len >= CONFIGURATION_DESCRIPTOR_LEN => {
+                let configuration = &buffer[..len];
+                let claimed = self
+                    .classes
+                    .iter()
+                    .position(|class| class.probe(&device, configuration));
+                if claimed.is_none() {
+                    self.state.set(State::Idle);
+                }
+                claimed.map(|index| Request {
+                    state: State::Configuring(index),
+                    request_type: 0x00,
+                    request: request::SET_CONFIGURATION,
+                    value: configuration[5] as u16,
+                    len: 0,
+                })
+            }
+            State::Configuring(index) => {
+                self.state.set(State::Configured(index));
+                self.classes[index].configured(&device);
+                None
+            }
+            _ => {
+                self.state.set(State::Idle);
+                None
+            }
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>, A: time::Alarm<'a>> time::AlarmClient
+    for UsbHostStack<'a, H, A>
+{
+    fn alarm(&self) {
+        match self.state.get() {
+            State::Debouncing => match self.host.start_reset() {
+                Ok(()) => {
+                    self.state.set(State::Resetting);
+                    self.wait(RESET_MS);
+                }
+                Err(_) => self.state.set(State::Detached),
+            },
+            State::Resetting => self.host.end_reset(),
+            State::Recovering => {
+                // The maximum packet size of the default endpoint is in the
+                // first 8 bytes, which fit in a packet of any device.
+                let mut device = self.device.get();
+                device.address = 0;
+                device.max_packet_size = 8;
+                self.device.set(device);
+                self.send(Request::get_descriptor(
+                    State::ReadingMaxPacketSize,
+                    DescriptorType::Device,
+                    8,
+                ));
+            }
+            State::Addressing => self.send(Request::get_descriptor(
+                State::ReadingDevice,
+                DescriptorType::Device,
+                DEVICE_DESCRIPTOR_LEN,
+            )),
+            _ => {}
+        }
+    }
+}
+
+impl<'a, H: usb_host::UsbHost<'a>, A: time::Alarm<'a>> usb_host::Client for UsbHostStack<'a, H, A> {
+    fn connected(&self) {
+        if let State::Configured(index) = self.state.get() {
+            self.classes[index].disconnected();
+        }
+        self.state.set(State::Debouncing);
+        self.wait(DEBOUNCE_MS);
+    }
+
+    fn disconnected(&self) {
+        if let State::Configured(index) = self.state.get() {
+            self.classes[index].disconnected();
+        }
+        self.state.set(State::Detached);
+        let _ = self.alarm.disarm();
+    }
+
+    fn reset_done(&self, result: Result<Speed, ErrorCode>) {
+        if self.state.get() != State::Resetting {
+            return;
+        }
+        match result {
+            Ok(speed) => {
+                let mut device = self.device.get();
+                device.speed = speed;
+                self.device.set(device);
+                self.state.set(State::Recovering);
+                self.wait(RESET_RECOVERY_MS);
+            }
+            Err(_) => self.state.set(State::Idle),
+        }
+    }
+
+    fn control_done(&self, buffer: &'static mut [u8], len: usize, result: Result<(), ErrorCode>) {
+        if let State::Configured(index) = self.state.get() {
+            self.classes[index].control_done(buffer, len, result);
+            return;
+        }
+        let next = match result {
+            Ok(()) => self.enumerate(buffer, len),
+            Err(_) => {
+                self.state.set(State::Idle);
+                None
+            }
+        };
+        self.buffer.replace(buffer);
+        if let Some(request) = next {
+            self.send(request);
+        }
+    }
+
+    fn transfer_done(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    ) {
+        if let State::Configured(index) = self.state.get() {
+            self.classes[index].transfer_done(pipe, buffer, len, result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boot_keyboard_descriptors() {
+        let configuration = [
+            9, 2, 34, 0, 1, 1, 0, 0xa0, 50, // configuration
+            9, 4, 0, 0, 1, 3, 1, 1, 0, // interface: HID boot keyboard
+            9, 0x21, 0x11, 1, 0, 1, 0x22, 63, 0, // HID
+            7, 5, 0x81, 3, 8, 0, 10, // endpoint 1 IN, interrupt
+        ];
+        let descriptors: [&[u8]; 4] = [
+            &configuration[..9],
+            &configuration[9..18],
+            &configuration[18..27],
+            &configuration[27..],
+        ];
+        assert!(Descriptors::new(&configuration).eq(descriptors.iter().copied()));
+        assert!(is_interface(descriptors[1]));
+        assert!(endpoint(1, descriptors[2]).is_none());
+
+        let endpoint = endpoint(1, descriptors[3]).unwrap();
+        assert_eq!(endpoint.number, 1);
+        assert_eq!(endpoint.direction, Direction::In);
+        assert!(matches!(endpoint.transfer_type, TransferType::Interrupt));
+        assert_eq!(endpoint.max_packet_size, 8);
+    }
+
+    #[test]
+    fn truncated_descriptors() {
+        // A descriptor longer than the data left ends the iteration.
+        let configuration = [9, 2, 34, 0, 1, 1, 0, 0xa0, 50, 9, 4, 0];
+        assert_eq!(Descriptors::new(&configuration).count(), 1);
+        assert_eq!(Descriptors::new(&[0, 2]).count(), 0);
+    }
+}
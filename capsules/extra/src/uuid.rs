@@ -0,0 +1,374 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Generates version 4 UUIDs and nonces from random numbers.
+//!
+//! Applications that need unique identifiers or nonces get them from this
+//! driver instead of formatting the output of the raw RNG driver themselves.
+//! The randomness comes from a `hil::rng::Rng`, i.e. conditioned entropy.
+//! [`uuid_from_random()`] and [`format_uuid()`] provide the same to the
+//! kernel.
+//!
+//! Each process may make `burst` requests at once, after which it gets one
+//! more request every `interval_ms` milliseconds. Requests exceeding the
+//! limit fail with `BUSY`, so one process cannot starve the others of
+//! randomness.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let uuid = static_init!(
+//!     capsules_extra::uuid::UuidDriver<'static, nrf52840::rtc::Rtc>,
+//!     capsules_extra::uuid::UuidDriver::new(
+//!         rng,
+//!         &base_peripherals.rtc,
+//!         4,
+//!         1000,
+//!         board_kernel.create_grant(capsules_extra::uuid::DRIVER_NUM, &grant_cap),
+//!     )
+//! );
+//! rng.set_client(uuid);
+//! ```
+//!
+//! The `Rng` must not be shared with another client, such as the RNG driver.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::rng;
+use kernel::hil::time::{ConvertTicks, Ticks, Time};
+use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::Uuid as usize;
+
+/// Length of a UUID in bytes.
+pub const UUID_LEN: usize = 16;
+/// Length of the canonical text form of a UUID.
+pub const UUID_STRING_LEN: usize = 36;
+/// Maximum length of a nonce in bytes.
+pub const MAX_NONCE_LEN: usize = 32;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const BUFFER: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Turns 16 random bytes into a version 4 UUID, as defined in RFC 4122.
+pub fn uuid_from_random(mut bytes: [u8; UUID_LEN]) -> [u8; UUID_LEN] {
+    bytes[6] = (bytes[6] & 0x0F) | 0x40;
+    bytes[8] = (bytes[8] & 0x3F) | 0x80;
+    bytes
+}
+
+/// Returns the canonical text form of `uuid`, e.g.
+/// `"f81d4fae-7dec-41d0-a765-00a0c91e6bf6"`.
+pub fn format_uuid(uuid: &[u8; UUID_LEN]) -> [u8; UUID_STRING_LEN] {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+    let mut text = [b'-'; UUID_STRING_LEN];
+    let mut pos = 0;
+    for (i, byte) in uuid.iter().enumerate() {
+        if i == 4 || i == 6 || i == 8 || i == 10 {
+            pos += 1;
+        }
+        text[pos] = HEX[(byte >> 4) as usize];
+        text[pos + 1] = HEX[(byte & 0xF) as usize];
+        pos += 2;
+    }
+    text
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Request {
+    Uuid,
+    UuidString,
+    Nonce(usize),
+}
+
+impl Request {
+    /// Number of random bytes needed.
+    fn random_len(&self) -> usize {
+        match self {
+            Request::Uuid | Request::UuidString => UUID_LEN,
+            Request::Nonce(len) => *len,
+        }
+    }
+
+    /// Number of bytes written to the buffer of the process.
+    fn output_len(&self) -> usize {
+        match self {
+            Request::Uuid => UUID_LEN,
+            Request::UuidString => UUID_STRING_LEN,
+            Request::Nonce(len) => *len,
+        }
+    }
+}
+
+pub struct App<T: Ticks> {
+    request: Option<Request>,
+    random: [u8; MAX_NONCE_LEN],
+    /// Number of bytes of `random` received.
+    filled: usize,
+    /// Number of requests the process can make right now.
+    tokens: u32,
+    /// When `tokens` was last refilled, `None` before the first request.
+    refilled: Option<T>,
+}
+
+impl<T: Ticks> Default for App<T> {
+    fn default() -> Self {
+        App {
+            request: None,
+            random: [0; MAX_NONCE_LEN],
+            filled: 0,
+            tokens: 0,
+            refilled: None,
+        }
+    }
+}
+
+pub struct UuidDriver<'a, A: Time> {
+    rng: &'a dyn rng::Rng<'a>,
+    time: &'a A,
+    /// Number of requests a process can make at once.
+    burst: u32,
+    /// Time in milliseconds for a process to get another request.
+    interval_ms: u32,
+    apps: Grant<App<A::Ticks>, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    getting_randomness: Cell<bool>,
+}
+
+impl<'a, A: Time> UuidDriver<'a, A> {
+    pub fn new(
+        rng: &'a dyn rng::Rng<'a>,
+        time: &'a A,
+        burst: u32,
+        interval_ms: u32,
+        grant: Grant<
+            App<A::Ticks>,
+            UpcallCount<1>,
+            AllowRoCount<0>,
+            AllowRwCount<{ rw_allow::COUNT }>,
+        >,
+    ) -> UuidDriver<'a, A> {
+        UuidDriver {
+            rng,
+            time,
+            burst,
+            interval_ms: interval_ms.max(1),
+            apps: grant,
+            getting_randomness: Cell::new(false),
+        }
+    }
+
+    /// Takes a request from the tokens of `app`. Returns `false` if the
+    /// process exceeded its rate limit.
+    fn take_token(&self, app: &mut App<A::Ticks>) -> bool {
+        let now = self.time.now();
+        match app.refilled {
+            None => {
+                app.tokens = self.burst;
+                app.refilled = Some(now);
+            }
+            Some(refilled) => {
+                let elapsed_ms = self.time.ticks_to_ms(now.wrapping_sub(refilled));
+                let new_tokens = elapsed_ms / self.interval_ms;
+                if new_tokens > 0 {
+                    app.tokens = app.tokens.saturating_add(new_tokens).min(self.burst);
+                    // Keep the time towards the next token.
+                    let used = self.time.ticks_from_ms(new_tokens * self.interval_ms);
+                    app.refilled = Some(refilled.wrapping_add(used));
+                }
+                if app.tokens == self.burst {
+                    app.refilled = Some(now);
+                }
+            }
+        }
+        if app.tokens == 0 {
+            return false;
+        }
+        app.tokens -= 1;
+        true
+    }
+
+    fn request(&self, request: Request, processid: ProcessId) -> CommandReturn {
+        let result = self
+            .apps
+            .enter(processid, |app, kernel_data| {
+                if app.request.is_some() {
+                    return Err(ErrorCode::BUSY);
+                }
+                let buffer_len = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::BUFFER)
+                    .map_or(0, |buffer| buffer.len());
+                if buffer_len < request.output_len() {
+                    return Err(ErrorCode::SIZE);
+                }
+                if !self.take_token(app) {
+                    return Err(ErrorCode::BUSY);
+                }
+                app.request = Some(request);
+                app.filled = 0;
+                Ok(())
+            })
+            .unwrap_or_else(|err| Err(err.into()));
+
+        if result.is_ok() && !self.getting_randomness.get() {
+            if let Err(e) = self.rng.get() {
+                let _ = self.apps.enter(processid, |app, _| app.request = None);
+                return CommandReturn::failure(e);
+            }
+            self.getting_randomness.set(true);
+        }
+        result.into()
+    }
+}
+
+impl<'a, A: Time> rng::Client for UuidDriver<'a, A> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        _error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        let mut done = true;
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, kernel_data| {
+                let request = match app.request {
+                    Some(request) => request,
+                    None => return,
+                };
+                let mut random = app.random;
+                let mut filled = app.filled;
+                while filled < request.random_len() {
+                    match randomness.next() {
+                        Some(word) => {
+                            let count = (request.random_len() - filled).min(4);
+                            random[filled..filled + count]
+                                .copy_from_slice(&word.to_le_bytes()[..count]);
+                            filled += count;
+                        }
+                        None => {
+                            app.random = random;
+                            app.filled = filled;
+                            done = false;
+                            return;
+                        }
+                    }
+                }
+
+                let mut uuid = [0; UUID_LEN];
+                let text;
+                let output: &[u8] = match request {
+                    Request::Uuid | Request::UuidString => {
+                        uuid.copy_from_slice(&random[..UUID_LEN]);
+                        uuid = uuid_from_random(uuid);
+                        if request == Request::Uuid {
+                            &uuid
+                        } else {
+                            text = format_uuid(&uuid);
+                            &text
+                        }
+                    }
+                    Request::Nonce(len) => &random[..len],
+                };
+                let result = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::BUFFER)
+                    .and_then(|buffer| {
+                        buffer.mut_enter(|buffer| match buffer.get(0..output.len()) {
+                            Some(buffer) => {
+                                buffer.copy_from_slice(output);
+                                Ok(())
+                            }
+                            // The process swapped buffers.
+                            None => Err(ErrorCode::SIZE),
+                        })
+                    })
+                    .unwrap_or(Err(ErrorCode::NOMEM));
+                app.request = None;
+                app.random = [0; MAX_NONCE_LEN];
+                kernel_data
+                    .schedule_upcall(
+                        0,
+                        (kernel::errorcode::into_statuscode(result), output.len(), 0),
+                    )
+                    .ok();
+            });
+
+            // Only continue with the other processes if there was enough
+            // randomness for this one.
+            if !done {
+                break;
+            }
+        }
+
+        if done {
+            self.getting_randomness.set(false);
+            rng::Continue::Done
+        } else {
+            rng::Continue::More
+        }
+    }
+}
+
+/// Provide a syscall interface for generating identifiers.
+///
+/// The result is written to read-write allow buffer 0. Once it is written,
+/// upcall 0 is scheduled with the status and the number of bytes written.
+///
+/// ### `command_num`
+///
+/// - `0`: Driver existence check.
+/// - `1`: Generate a version 4 UUID in binary form (16 bytes).
+/// - `2`: Generate a version 4 UUID in canonical text form (36 ASCII
+///   characters, e.g. `f81d4fae-7dec-41d0-a765-00a0c91e6bf6`).
+/// - `3`: Generate a nonce of `data` bytes, at most 32.
+///
+/// Commands 1 to 3 return `SIZE` if the buffer is too small, and `BUSY` if a
+/// request of the process is pending or the process exceeded its rate limit.
+impl<'a, A: Time> SyscallDriver for UuidDriver<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.request(Request::Uuid, processid),
+            2 => self.request(Request::UuidString, processid),
+            3 => {
+                if data == 0 || data > MAX_NONCE_LEN {
+                    return CommandReturn::failure(ErrorCode::INVAL);
+                }
+                self.request(Request::Nonce(data), processid)
+            }
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid_format() {
+        let uuid = uuid_from_random([
+            0xf8, 0x1d, 0x4f, 0xae, 0x7d, 0xec, 0xf1, 0xd0, 0x27, 0x65, 0x00, 0xa0, 0xc9, 0x1e,
+            0x6b, 0xf6,
+        ]);
+        assert_eq!(&format_uuid(&uuid), b"f81d4fae-7dec-41d0-a765-00a0c91e6bf6");
+    }
+}
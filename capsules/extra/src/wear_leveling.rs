@@ -0,0 +1,726 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Wear-leveling flash translation layer.
+//!
+//! `WearLeveling` manages `P` physical pages of a flash (`hil::flash::Flash`),
+//! starting at `first_page`, and implements `hil::flash::Flash` itself with
+//! fewer, logical, pages. It can be used underneath TicKV, the app flash
+//! driver, `nonvolatile_to_pages` or a filesystem, which then do not have to
+//! care about the endurance of the flash.
+//!
+//! A logical page is never written in place. Every write goes to the free
+//! physical page that was erased the least often (dynamic wear leveling), and
+//! the page that held the old contents becomes free. When the free pages wear
+//! out much faster than a page holding data that never changes, that data is
+//! moved to the most worn free page (static wear leveling), so that its page
+//! is used for new writes as well. An erase or a write that fails marks the
+//! physical page bad, it is not used again and the operation is retried on
+//! another page. The `spare` pages that the translation layer keeps beyond
+//! the ones it needs itself absorb the bad pages.
+//!
+//! The mapping of logical to physical pages, the erase count and the state of
+//! each physical page are kept in RAM and written to flash after every
+//! operation that changes them. This metadata is stored in a physical page of
+//! its own, chosen like the pages of writes, so it is leveled as well. It is
+//! protected by a checksum and a sequence number: when mounting, which is
+//! done on the first operation, every page is read and the valid metadata
+//! with the highest sequence number is used. Because the new contents of a
+//! page are written before the metadata that refers to them, and pages are
+//! only reused once metadata not referring to them anymore is on flash, a
+//! write or erase interrupted by a power loss leaves either the old or the
+//! new contents.
+//!
+//! Erasing a logical page only frees its physical page. Logical pages that
+//! were never written, or were erased, read as all `0xFF`. A write costs two
+//! erases and two writes of the flash, one for the data and one for the
+//! metadata. The metadata needs `16 + 6 * P` bytes, which has to fit in one
+//! page.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! // Level 64 pages starting at page 192, with 4 spare pages.
+//! let wear_leveling = components::wear_leveling::WearLevelingComponent::new(flash_user, 192, 4)
+//!     .finalize(components::wear_leveling_component_static!(
+//!         capsules_core::virtualizers::virtual_flash::FlashUser<'static, nrf52840::nvmc::Nvmc>,
+//!         64
+//!     ));
+//! // Use `wear_leveling` as the flash of the storage stack, e.g. TicKV, with
+//! // `wear_leveling.logical_pages()` pages.
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::flash::{self, Flash};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Owner of a physical page that holds no data.
+const FREE: u16 = 0xFFFF;
+/// Owner of a physical page that failed to erase or write.
+const BAD: u16 = 0xFFFE;
+/// Owner of the physical page that holds the current metadata.
+const METADATA: u16 = 0xFFFD;
+
+const MAGIC: &[u8; 8] = b"TockFTL1";
+const HEADER_LENGTH: usize = 16;
+const ENTRY_LENGTH: usize = 6;
+
+/// Free pages that may be erased this many times more than the least erased
+/// page holding data before that data is moved.
+const WEAR_THRESHOLD: u32 = 64;
+
+/// Physical pages the translation layer needs beyond the logical pages: the
+/// metadata, and a free page each for the data and the metadata of a write.
+const RESERVED_PAGES: usize = 3;
+
+/// Operation requested by the client, on a logical page.
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Read(usize),
+    Write(usize),
+    Erase(usize),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Looking for the metadata in a physical page while mounting.
+    Scan(usize),
+    /// Reading a physical page for the client.
+    Read,
+    /// Erasing a physical page for the data written by the client.
+    DataErase(usize),
+    /// Writing the data of the client to a physical page.
+    DataWrite(usize),
+    /// Erasing a physical page for the metadata.
+    CommitErase(usize),
+    /// Writing the metadata to a physical page.
+    CommitWrite(usize),
+    /// Reading the page with the least erased data, to move it to the
+    /// second page.
+    RelocateRead(usize, usize),
+    /// Erasing the page the data is moved to.
+    RelocateErase(usize, usize),
+    /// Writing the moved data.
+    RelocateWrite(usize, usize),
+}
+
+fn checksum(mut hash: u32, bytes: &[u8]) -> u32 {
+    // FNV-1a.
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+fn metadata_checksum(buffer: &[u8], length: usize) -> u32 {
+    let hash = checksum(0x811c_9dc5, &buffer[8..12]);
+    checksum(hash, &buffer[HEADER_LENGTH..length])
+}
+
+pub struct WearLeveling<'a, F: Flash + 'static, const P: usize> {
+    flash: &'a F,
+    first_page: usize,
+    spare: usize,
+    /// Logical page held by each physical page, or `FREE`, `BAD` or
+    /// `METADATA`.
+    owners: [Cell<u16>; P],
+    erases: [Cell<u32>; P],
+    /// Physical page holding the current metadata.
+    metadata_page: OptionalCell<usize>,
+    sequence: Cell<u32>,
+    mounted: Cell<bool>,
+    /// Valid metadata was found by the scan while mounting.
+    found: Cell<bool>,
+    state: Cell<State>,
+    /// Operation of the client in progress.
+    op: Cell<Option<Op>>,
+    /// Physical page with the new contents of a logical page, until the
+    /// metadata is on flash.
+    written: OptionalCell<usize>,
+    /// Physical page with the old contents of a logical page, that becomes
+    /// free once the metadata is on flash.
+    released: OptionalCell<usize>,
+    /// Data is being moved for static wear leveling.
+    relocating: Cell<bool>,
+    page_length: usize,
+    client_buffer: TakeCell<'static, F::Page>,
+    metadata_buffer: TakeCell<'static, F::Page>,
+    /// Operation of the client that completed, reported from the deferred
+    /// call.
+    completion: Cell<Option<(Op, flash::Error)>>,
+    client: OptionalCell<&'a dyn flash::Client<WearLeveling<'a, F, P>>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, F: Flash + 'static, const P: usize> WearLeveling<'a, F, P> {
+    pub fn new(
+        flash: &'a F,
+        first_page: usize,
+        spare: usize,
+        metadata_buffer: &'static mut F::Page,
+    ) -> Self {
+        let page_length = metadata_buffer.as_mut().len();
+        WearLeveling {
+            flash,
+            first_page,
+            spare,
+            owners: [(); P].map(|()| Cell::new(FREE)),
+            erases: [(); P].map(|()| Cell::new(0)),
+            metadata_page: OptionalCell::empty(),
+            sequence: Cell::new(0),
+            mounted: Cell::new(false),
+            found: Cell::new(false),
+            state: Cell::new(State::Idle),
+            op: Cell::new(None),
+            written: OptionalCell::empty(),
+            released: OptionalCell::empty(),
+            relocating: Cell::new(false),
+            page_length,
+            client_buffer: TakeCell::empty(),
+            metadata_buffer: TakeCell::new(metadata_buffer),
+            completion: Cell::new(None),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    /// Returns the number of logical pages.
+    pub fn logical_pages(&self) -> usize {
+        P.saturating_sub(RESERVED_PAGES + self.spare)
+    }
+
+    /// Returns the number of physical pages that went bad.
+    pub fn bad_pages(&self) -> usize {
+        self.owners
+            .iter()
+            .filter(|owner| owner.get() == BAD)
+            .count()
+    }
+
+    /// Returns how often physical page `page` was erased, as far as known to
+    /// the metadata.
+    pub fn erase_count(&self, page: usize) -> Option<u32> {
+        self.erases.get(page).map(|count| count.get())
+    }
+
+    fn metadata_length(&self) -> usize {
+        HEADER_LENGTH + ENTRY_LENGTH * P
+    }
+
+    fn lookup(&self, logical: usize) -> Option<usize> {
+        self.owners
+            .iter()
+            .position(|owner| owner.get() as usize == logical)
+    }
+
+    fn free_pages(&self) -> usize {
+        self.owners
+            .iter()
+            .filter(|owner| owner.get() == FREE)
+            .count()
+    }
+
+    /// Returns the free physical page erased the least often.
+    fn least_worn_free(&self) -> Option<usize> {
+        (0..P)
+            .filter(|&page| self.owners[page].get() == FREE)
+            .min_by_key(|&page| self.erases[page].get())
+    }
+
+    /// Whether an operation on logical page `logical` can start.
+    fn check(&self, logical: usize) -> Result<(), ErrorCode> {
+        if self.metadata_length() > self.page_length {
+            Err(ErrorCode::SIZE)
+        } else if self.op.get().is_some() || self.completion.get().is_some() {
+            Err(ErrorCode::BUSY)
+        } else if logical >= self.logical_pages() {
+            Err(ErrorCode::INVAL)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Start an operation of the client, mounting first if needed. The
+    /// buffer of the client, if any, is in `client_buffer` unless an error is
+    /// returned.
+    fn start(&self, op: Op) -> Result<(), ErrorCode> {
+        self.op.set(Some(op));
+        let result = if self.mounted.get() {
+            self.run_op(op)
+        } else {
+            self.found.set(false);
+            self.scan(0)
+        };
+        if result.is_err() {
+            self.op.set(None);
+            self.released.clear();
+            self.state.set(State::Idle);
+        }
+        result
+    }
+
+    /// Read the next physical page that may hold the metadata, starting at
+    /// `page`. Once all are read, either the metadata found is used or the
+    /// flash is formatted.
+    fn scan(&self, mut page: usize) -> Result<(), ErrorCode> {
+        while page < P {
+            let buffer = self.metadata_buffer.take().ok_or(ErrorCode::NOMEM)?;
+            self.state.set(State::Scan(page));
+            match self.flash.read_page(self.first_page + page, buffer) {
+                Ok(()) => return Ok(()),
+                Err((_, buffer)) => {
+                    // Not readable, so it does not hold the metadata.
+                    self.metadata_buffer.replace(buffer);
+                    page += 1;
+                }
+            }
+        }
+
+        if self.found.get() {
+            self.mounted.set(true);
+            match self.op.get() {
+                Some(op) => self.run_op(op),
+                None => Ok(()),
+            }
+        } else {
+            // Nothing was stored yet.
+            for page in 0..P {
+                self.owners[page].set(FREE);
+                self.erases[page].set(0);
+            }
+            self.metadata_page.clear();
+            self.sequence.set(0);
+            self.commit()
+        }
+    }
+
+    /// Use the metadata in `buffer`, read from physical page `page`, if it is
+    /// valid and newer than what was found so far.
+    fn load(&self, page: usize, buffer: &[u8]) {
+        let length = self.metadata_length();
+        if buffer[0..8] != MAGIC[..] {
+            return;
+        }
+        let sequence = u32::from_le_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+        let stored = u32::from_le_bytes([buffer[12], buffer[13], buffer[14], buffer[15]]);
+        if stored != metadata_checksum(buffer, length)
+            || (self.found.get() && sequence <= self.sequence.get())
+        {
+            return;
+        }
+        let entry = |index: usize| {
+            let offset = HEADER_LENGTH + ENTRY_LENGTH * index;
+            let owner = u16::from_le_bytes([buffer[offset], buffer[offset + 1]]);
+            let erases = u32::from_le_bytes([
+                buffer[offset + 2],
+                buffer[offset + 3],
+                buffer[offset + 4],
+                buffer[offset + 5],
+            ]);
+            (owner, erases)
+        };
+        // Metadata for a different layout.
+        if (0..P).any(|index| {
+            let (owner, _) = entry(index);
+            owner < METADATA && owner as usize >= self.logical_pages()
+        }) {
+            return;
+        }
+        for index in 0..P {
+            let (owner, erases) = entry(index);
+            self.owners[index].set(owner);
+            self.erases[index].set(erases);
+        }
+        self.owners[page].set(METADATA);
+        self.metadata_page.set(page);
+        self.sequence.set(sequence);
+        self.found.set(true);
+    }
+
+    fn run_op(&self, op: Op) -> Result<(), ErrorCode> {
+        match op {
+            Op::Read(logical) => match self.lookup(logical) {
+                Some(page) => {
+                    let buffer = self.client_buffer.take().ok_or(ErrorCode::NOMEM)?;
+                    self.state.set(State::Read);
+                    self.flash
+                        .read_page(self.first_page + page, buffer)
+                        .map_err(|(e, buffer)| {
+                            self.client_buffer.replace(buffer);
+                            e
+                        })
+                }
+                None => {
+                    self.client_buffer.map(|buffer| buffer.as_mut().fill(0xFF));
+                    self.complete_later(op);
+                    Ok(())
+                }
+            },
+            Op::Write(_) => {
+                // The metadata needs a free page as well.
+                if self.free_pages() < 2 {
+                    return Err(ErrorCode::NOMEM);
+                }
+                self.erase_for_data()
+            }
+            Op::Erase(logical) => match self.lookup(logical) {
+                Some(page) => {
+                    self.released.set(page);
+                    self.commit()
+                }
+                None => {
+                    self.complete_later(op);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    fn complete_later(&self, op: Op) {
+        self.op.set(None);
+        self.state.set(State::Idle);
+        self.completion
+            .set(Some((op, flash::Error::CommandComplete)));
+        self.deferred_call.set();
+    }
+
+    /// Report the operation of the client as complete.
+    fn finish(&self, error: flash::Error) {
+        self.state.set(State::Idle);
+        self.relocating.set(false);
+        if let Some(op) = self.op.take() {
+            self.report(op, error);
+        }
+    }
+
+    fn report(&self, op: Op, error: flash::Error) {
+        match op {
+            Op::Read(_) => self.client_buffer.take().map(|buffer| {
+                self.client
+                    .map(move |client| client.read_complete(buffer, error));
+            }),
+            Op::Write(_) => self.client_buffer.take().map(|buffer| {
+                self.client
+                    .map(move |client| client.write_complete(buffer, error));
+            }),
+            Op::Erase(_) => self.client.map(|client| client.erase_complete(error)),
+        };
+    }
+
+    /// Continue after a step that completed asynchronously, failing the
+    /// operation of the client if it cannot.
+    fn continue_with(&self, result: Result<(), ErrorCode>) {
+        if result.is_err() {
+            self.written.take().map(|page| self.owners[page].set(FREE));
+            self.released.clear();
+            self.finish(flash::Error::FlashError);
+        }
+    }
+
+    fn mark_bad(&self, page: usize) {
+        self.owners[page].set(BAD);
+    }
+
+    fn erase_for_data(&self) -> Result<(), ErrorCode> {
+        let page = self.least_worn_free().ok_or(ErrorCode::NOMEM)?;
+        self.erases[page].set(self.erases[page].get().saturating_add(1));
+        self.state.set(State::DataErase(page));
+        self.flash.erase_page(self.first_page + page)
+    }
+
+    /// Write the current mapping to the free page erased the least often.
+    fn commit(&self) -> Result<(), ErrorCode> {
+        let page = self.least_worn_free().ok_or(ErrorCode::NOMEM)?;
+        self.erases[page].set(self.erases[page].get().saturating_add(1));
+        self.state.set(State::CommitErase(page));
+        self.flash.erase_page(self.first_page + page)
+    }
+
+    fn write_metadata(&self, page: usize) -> Result<(), ErrorCode> {
+        let buffer = self.metadata_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let length = self.metadata_length();
+        {
+            let bytes = buffer.as_mut();
+            bytes.fill(0xFF);
+            bytes[0..8].copy_from_slice(MAGIC);
+            bytes[8..12].copy_from_slice(&self.sequence.get().wrapping_add(1).to_le_bytes());
+            for index in 0..P {
+                let owner = if index == page {
+                    METADATA
+                } else if self.metadata_page.contains(&index) || self.released.contains(&index) {
+                    FREE
+                } else {
+                    self.owners[index].get()
+                };
+                let offset = HEADER_LENGTH + ENTRY_LENGTH * index;
+                bytes[offset..offset + 2].copy_from_slice(&owner.to_le_bytes());
+                bytes[offset + 2..offset + 6]
+                    .copy_from_slice(&self.erases[index].get().to_le_bytes());
+            }
+            let hash = metadata_checksum(bytes, length);
+            bytes[12..16].copy_from_slice(&hash.to_le_bytes());
+        }
+        self.state.set(State::CommitWrite(page));
+        self.flash
+            .write_page(self.first_page + page, buffer)
+            .map_err(|(e, buffer)| {
+                self.metadata_buffer.replace(buffer);
+                e
+            })
+    }
+
+    /// The metadata in physical page `page` is on flash.
+    fn committed(&self, page: usize) {
+        self.metadata_page
+            .take()
+            .map(|old| self.owners[old].set(FREE));
+        self.released.take().map(|old| self.owners[old].set(FREE));
+        self.written.clear();
+        self.owners[page].set(METADATA);
+        self.metadata_page.set(page);
+        self.sequence.set(self.sequence.get().wrapping_add(1));
+
+        if !self.mounted.get() {
+            // The flash was formatted.
+            self.mounted.set(true);
+            let result = match self.op.get() {
+                Some(op) => self.run_op(op),
+                None => Ok(()),
+            };
+            self.continue_with(result);
+        } else if self.relocating.get() {
+            self.finish(flash::Error::CommandComplete);
+        } else if !self.relocate() {
+            self.finish(flash::Error::CommandComplete);
+        }
+    }
+
+    /// Start moving the data erased the least often to the most worn free
+    /// page, if they drifted apart too far. Returns whether it started.
+    fn relocate(&self) -> bool {
+        let cold = (0..P)
+            .filter(|&page| self.owners[page].get() < METADATA)
+            .min_by_key(|&page| self.erases[page].get());
+        let worn = (0..P)
+            .filter(|&page| self.owners[page].get() == FREE)
+            .max_by_key(|&page| self.erases[page].get());
+        let (cold, worn) = match (cold, worn) {
+            (Some(cold), Some(worn)) if self.free_pages() >= 2 => (cold, worn),
+            _ => return false,
+        };
+        if self.erases[worn].get() <= self.erases[cold].get().saturating_add(WEAR_THRESHOLD) {
+            return false;
+        }
+        let buffer = match self.metadata_buffer.take() {
+            Some(buffer) => buffer,
+            None => return false,
+        };
+        self.relocating.set(true);
+        self.state.set(State::RelocateRead(cold, worn));
+        match self.flash.read_page(self.first_page + cold, buffer) {
+            Ok(()) => true,
+            Err((_, buffer)) => {
+                self.metadata_buffer.replace(buffer);
+                self.relocating.set(false);
+                false
+            }
+        }
+    }
+
+    /// Moving data did not work out. The operation of the client itself
+    /// completed, so it is reported as such.
+    fn relocation_failed(&self, worn: Option<usize>) {
+        worn.map(|page| self.mark_bad(page));
+        self.finish(flash::Error::CommandComplete);
+    }
+}
+
+impl<'a, F: Flash + 'static, C: flash::Client<Self>, const P: usize> flash::HasClient<'a, C>
+    for WearLeveling<'a, F, P>
+{
+    fn set_client(&'a self, client: &'a C) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, F: Flash + 'static, const P: usize> Flash for WearLeveling<'a, F, P> {
+    type Page = F::Page;
+
+    fn read_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if let Err(e) = self.check(page_number) {
+            return Err((e, buf));
+        }
+        self.client_buffer.replace(buf);
+        self.start(Op::Read(page_number))
+            .map_err(|e| (e, self.client_buffer.take().unwrap()))
+    }
+
+    fn write_page(
+        &self,
+        page_number: usize,
+        buf: &'static mut Self::Page,
+    ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+        if let Err(e) = self.check(page_number) {
+            return Err((e, buf));
+        }
+        self.client_buffer.replace(buf);
+        self.start(Op::Write(page_number))
+            .map_err(|e| (e, self.client_buffer.take().unwrap()))
+    }
+
+    fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+        self.check(page_number)?;
+        self.start(Op::Erase(page_number))
+    }
+}
+
+impl<'a, F: Flash + 'static, const P: usize> flash::Client<F> for WearLeveling<'a, F, P> {
+    fn read_complete(&self, read_buffer: &'static mut F::Page, error: flash::Error) {
+        match self.state.get() {
+            State::Scan(page) => {
+                if error == flash::Error::CommandComplete {
+                    self.load(page, read_buffer.as_mut());
+                }
+                self.metadata_buffer.replace(read_buffer);
+                let result = self.scan(page + 1);
+                self.continue_with(result);
+            }
+            State::Read => {
+                self.client_buffer.replace(read_buffer);
+                self.finish(error);
+            }
+            State::RelocateRead(cold, worn) => {
+                self.metadata_buffer.replace(read_buffer);
+                if error != flash::Error::CommandComplete {
+                    self.relocation_failed(None);
+                    return;
+                }
+                self.erases[worn].set(self.erases[worn].get().saturating_add(1));
+                self.state.set(State::RelocateErase(cold, worn));
+                if self.flash.erase_page(self.first_page + worn).is_err() {
+                    self.relocation_failed(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_complete(&self, write_buffer: &'static mut F::Page, error: flash::Error) {
+        match self.state.get() {
+            State::DataWrite(page) => {
+                self.client_buffer.replace(write_buffer);
+                if error != flash::Error::CommandComplete {
+                    self.mark_bad(page);
+                    let result = self.erase_for_data();
+                    self.continue_with(result);
+                    return;
+                }
+                if let Some(Op::Write(logical)) = self.op.get() {
+                    self.lookup(logical).map(|old| self.released.set(old));
+                    self.owners[page].set(logical as u16);
+                    self.written.set(page);
+                }
+                let result = self.commit();
+                self.continue_with(result);
+            }
+            State::CommitWrite(page) => {
+                self.metadata_buffer.replace(write_buffer);
+                if error != flash::Error::CommandComplete {
+                    self.mark_bad(page);
+                    let result = self.commit();
+                    self.continue_with(result);
+                    return;
+                }
+                self.committed(page);
+            }
+            State::RelocateWrite(cold, worn) => {
+                self.metadata_buffer.replace(write_buffer);
+                if error != flash::Error::CommandComplete {
+                    self.relocation_failed(Some(worn));
+                    return;
+                }
+                self.owners[worn].set(self.owners[cold].get());
+                self.written.set(worn);
+                self.released.set(cold);
+                let result = self.commit();
+                self.continue_with(result);
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_complete(&self, error: flash::Error) {
+        match self.state.get() {
+            State::DataErase(page) => {
+                if error != flash::Error::CommandComplete {
+                    self.mark_bad(page);
+                    let result = self.erase_for_data();
+                    self.continue_with(result);
+                    return;
+                }
+                let result = match self.client_buffer.take() {
+                    Some(buffer) => {
+                        self.state.set(State::DataWrite(page));
+                        self.flash
+                            .write_page(self.first_page + page, buffer)
+                            .map_err(|(e, buffer)| {
+                                self.client_buffer.replace(buffer);
+                                e
+                            })
+                    }
+                    None => Err(ErrorCode::NOMEM),
+                };
+                self.continue_with(result);
+            }
+            State::CommitErase(page) => {
+                if error != flash::Error::CommandComplete {
+                    self.mark_bad(page);
+                    let result = self.commit();
+                    self.continue_with(result);
+                    return;
+                }
+                let result = self.write_metadata(page);
+                self.continue_with(result);
+            }
+            State::RelocateErase(cold, worn) => {
+                if error != flash::Error::CommandComplete {
+                    self.relocation_failed(Some(worn));
+                    return;
+                }
+                let buffer = match self.metadata_buffer.take() {
+                    Some(buffer) => buffer,
+                    None => return self.relocation_failed(None),
+                };
+                self.state.set(State::RelocateWrite(cold, worn));
+                if let Err((_, buffer)) = self.flash.write_page(self.first_page + worn, buffer) {
+                    self.metadata_buffer.replace(buffer);
+                    self.relocation_failed(None);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a, F: Flash + 'static, const P: usize> DeferredCallClient for WearLeveling<'a, F, P> {
+    fn handle_deferred_call(&self) {
+        if let Some((op, error)) = self.completion.take() {
+            self.report(op, error);
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -809,9 +809,8 @@ impl<'a> hil::uart::Configure for Lpuart<'a> {
             || params.hw_flow_control != false
             || params.width != hil::uart::Width::Eight
         {
-            panic!(
-                "Currently we only support uart setting of 115200bps 8N1, no hardware flow control"
-            );
+            // Currently we only support uart setting of 115200bps 8N1, no hardware flow control
+            return Err(ErrorCode::NOSUPPORT);
         }
 
         self.enable_clock();
@@ -364,6 +364,8 @@ pub struct FlashCtrl<'a> {
     write_buf: TakeCell<'static, LowRiscPage>,
     write_index: Cell<usize>,
     write_word_addr: Cell<usize>,
+    /// Page of the erase stopped by `suspend_erase()`.
+    erase_suspended: OptionalCell<usize>,
     region_num: FlashRegion,
 }
 
@@ -379,6 +381,7 @@ impl<'a> FlashCtrl<'a> {
             write_buf: TakeCell::empty(),
             write_index: Cell::new(0),
             write_word_addr: Cell::new(0),
+            erase_suspended: OptionalCell::empty(),
             region_num,
         }
     }
@@ -1098,6 +1101,9 @@ impl hil::flash::Flash for FlashCtrl<'_> {
         if page_number >= FLASH_MAX_PAGES {
             return Err(ErrorCode::INVAL);
         }
+        if self.erase_suspended.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
         let addr = page_number.saturating_mul(PAGE_SIZE);
 
         if !self.data_configured.get() {
@@ -1141,4 +1147,42 @@ impl hil::flash::Flash for FlashCtrl<'_> {
         );
         Ok(())
     }
+
+    /// The controller stops the erase, which leaves the page partly erased.
+    /// `resume_erase()` erases the page again.
+    fn suspend_erase(&self) -> Result<(), ErrorCode> {
+        if self.erase_suspended.is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if self.registers.ctrl_regwen.is_set(CTRL_REGWEN::EN)
+            || !self.registers.control.matches_all(CONTROL::OP::ERASE)
+        {
+            return Err(ErrorCode::OFF);
+        }
+        let page_number = self.registers.addr.read(ADDR::START) as usize / PAGE_SIZE;
+
+        // The request is cleared once the erase stopped, or right away if
+        // the erase just completed.
+        self.registers.erase_suspend.write(ERASE_SUSPEND::REQ::SET);
+        while self.registers.erase_suspend.is_set(ERASE_SUSPEND::REQ) {}
+
+        // Drop the completion of the stopped erase.
+        self.disable_interrupts();
+        self.registers.op_status.set(0);
+
+        self.erase_suspended.set(page_number);
+        Ok(())
+    }
+
+    fn resume_erase(&self) -> Result<(), ErrorCode> {
+        let page_number = self.erase_suspended.extract().ok_or(ErrorCode::OFF)?;
+        if self.read_buf.is_some() || self.write_buf.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.erase_suspended.clear();
+        self.erase_page(page_number).map_err(|e| {
+            self.erase_suspended.set(page_number);
+            e
+        })
+    }
 }
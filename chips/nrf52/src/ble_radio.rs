@@ -678,6 +678,19 @@ impl<'a> Radio<'a> {
         self.registers.intenclr.set(0xffffffff);
     }
 
+    /// Stop receiving an advertisement and report `error` to the receive
+    /// client, e.g. when the receive timeslot of the radio arbiter ended.
+    pub(crate) fn cancel_receive(&self, error: ErrorCode) {
+        if self.is_enabled() {
+            self.disable_all_interrupts();
+            self.radio_off();
+        }
+        unsafe {
+            self.rx_client
+                .map(|client| client.receive_event(&mut PAYLOAD, 0, Err(error)));
+        }
+    }
+
     fn replace_radio_buffer(&self, buf: &'static mut [u8]) -> &'static mut [u8] {
         // set payload
         for (i, c) in buf.as_ref().iter().enumerate() {
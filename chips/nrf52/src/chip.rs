@@ -47,8 +47,10 @@ pub struct Nrf52DefaultPeripherals<'a> {
     pub spim2: crate::spi::SPIM<'a>,
     pub adc: crate::adc::Adc<'a>,
     pub nvmc: crate::nvmc::Nvmc,
+    pub pdm: crate::pdm::Pdm<'a>,
     pub clock: crate::clock::Clock,
     pub pwm0: crate::pwm::Pwm,
+    pub ppi: crate::ppi::Ppi,
 }
 
 impl<'a> Nrf52DefaultPeripherals<'a> {
@@ -73,8 +75,10 @@ impl<'a> Nrf52DefaultPeripherals<'a> {
             spim2: crate::spi::SPIM::new(2),
             adc: crate::adc::Adc::new(),
             nvmc: crate::nvmc::Nvmc::new(),
+            pdm: crate::pdm::Pdm::new(),
             clock: crate::clock::Clock::new(),
             pwm0: crate::pwm::Pwm::new(),
+            ppi: crate::ppi::Ppi::new(),
         }
     }
     // Necessary for setting up circular dependencies
@@ -82,6 +86,7 @@ impl<'a> Nrf52DefaultPeripherals<'a> {
         self.ieee802154_radio.set_timer_ref(&self.timer0);
         self.timer0.set_alarm_client(&self.ieee802154_radio);
         kernel::deferred_call::DeferredCallClient::register(&self.nvmc);
+        kernel::deferred_call::DeferredCallClient::register(&self.ieee802154_radio);
     }
 }
 impl<'a> kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'a> {
@@ -103,6 +108,7 @@ impl<'a> kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'a
                     ),
                 }
             }
+            crate::peripheral_interrupts::PDM => self.pdm.handle_interrupt(),
             crate::peripheral_interrupts::RNG => self.trng.handle_interrupt(),
             crate::peripheral_interrupts::RTC1 => self.rtc.handle_interrupt(),
             crate::peripheral_interrupts::TEMP => self.temp.handle_interrupt(),
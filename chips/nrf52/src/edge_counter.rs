@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Edge counter using a TIMER in counter mode, GPIOTE, and PPI.
+//!
+//! A GPIOTE channel generates an event on each rising edge of the pin, and a
+//! PPI channel connects that event to the `COUNT` task of the timer. The
+//! edges are therefore counted entirely by hardware, without interrupts.
+//!
+//! The pin must not have a GPIO interrupt client, as the GPIOTE interrupt
+//! handler reports the events of every channel.
+
+use core::cell::Cell;
+
+use kernel::hil::counter;
+use kernel::hil::gpio::{self, Configure};
+use kernel::ErrorCode;
+
+use crate::gpio::GPIOPin;
+use crate::ppi::Ppi;
+use crate::timer::Timer;
+
+pub struct EdgeCounter<'a> {
+    timer: &'a Timer,
+    ppi: &'a Ppi,
+    ppi_channel: usize,
+    pin: &'a GPIOPin<'a>,
+    counting: Cell<bool>,
+    /// Count captured when counting stopped.
+    count: Cell<u32>,
+}
+
+impl<'a> EdgeCounter<'a> {
+    /// Count edges on `pin` with `timer` and an unused PPI channel (0-19).
+    /// The timer must not be used for anything else.
+    pub fn new(timer: &'a Timer, ppi: &'a Ppi, ppi_channel: usize, pin: &'a GPIOPin<'a>) -> Self {
+        EdgeCounter {
+            timer,
+            ppi,
+            ppi_channel,
+            pin,
+            counting: Cell::new(false),
+            count: Cell::new(0),
+        }
+    }
+}
+
+impl counter::EdgeCounter for EdgeCounter<'_> {
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.counting.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pin.make_input();
+        let event = self.pin.enable_event(gpio::InterruptEdge::RisingEdge)?;
+        self.timer.configure_counter();
+        self.ppi.configure_channel(
+            self.ppi_channel,
+            event,
+            self.timer.task_count_address(),
+            None,
+        );
+        self.ppi.enable(Ppi::channel(self.ppi_channel));
+        self.count.set(0);
+        self.counting.set(true);
+        self.timer.start();
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.counting.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.ppi.disable(Ppi::channel(self.ppi_channel));
+        self.count.set(self.timer.capture(0));
+        self.timer.stop();
+        self.pin.disable_event();
+        self.counting.set(false);
+        Ok(())
+    }
+
+    fn count(&self) -> u32 {
+        if self.counting.get() {
+            self.timer.capture(0)
+        } else {
+            self.count.get()
+        }
+    }
+
+    fn max_frequency_hz(&self) -> u32 {
+        // The timer counts on the 16 MHz peripheral clock, and GPIOTE needs
+        // the input to be stable for a few cycles to detect an edge.
+        4_000_000
+    }
+}
@@ -12,6 +12,7 @@
 //! - Date: November 27, 2017
 
 use core::fmt;
+use kernel::hil::device_id::{self, DeviceId};
 use kernel::utilities::registers::interfaces::Readable;
 use kernel::utilities::registers::{register_bitfields, ReadOnly};
 use kernel::utilities::StaticRef;
@@ -477,6 +478,24 @@ impl Ficr {
     }
 }
 
+impl DeviceId for Ficr {
+    /// The 64-bit `DEVICEID`, most significant byte first.
+    fn unique_id(&self, buf: &mut [u8; device_id::MAX_ID_LEN]) -> usize {
+        let lo = self.registers.deviceid0.read(DeviceId0::DEVICEID);
+        let hi = self.registers.deviceid1.read(DeviceId1::DEVICEID);
+        buf[..4].copy_from_slice(&hi.to_be_bytes());
+        buf[4..8].copy_from_slice(&lo.to_be_bytes());
+        8
+    }
+
+    /// Use the factory programmed BLE device address.
+    fn mac48(&self) -> [u8; 6] {
+        let mut mac = self.address();
+        mac.reverse();
+        mac
+    }
+}
+
 impl fmt::Display for Ficr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -5,8 +5,10 @@
 //! IEEE 802.15.4 radio driver for nRF52
 
 use core::cell::Cell;
+use core::cmp::min;
 use core::convert::TryFrom;
 use kernel;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
 use kernel::hil::radio::{self, PowerClient};
 use kernel::hil::time::{Alarm, AlarmClient};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -47,6 +49,13 @@ pub const PREBUF_LEN_BYTES: usize = 2;
 // to return the frame buffer.
 const MIMIC_PSDU_OFFSET: u32 = 1;
 
+// The duration of one energy detection, which EDCNT repeats.
+const ED_PERIOD_US: u32 = 128;
+// The power of an energy detection level of 0, in dBm.
+const ED_RSSIOFFS: i16 = -94;
+// Scales the LQI of the radio to the range of IEEE 802.15.4.
+const LQI_SCALE: u16 = 4;
+
 // IEEEStd 802.15.4-2011 Section 8.1.2.2
 // Frequency is 2405 + 5 * (k - 11) MHz, where k = 11, 12, ... , 26.
 #[derive(PartialEq, Debug, Copy, Clone)]
@@ -147,8 +156,12 @@ struct RadioRegisters {
     /// Stop the bit counter
     /// - Address: 0x020 - 0x024
     task_bcstop: WriteOnly<u32, Task::Register>,
-    /// Reserved
-    _reserved1: [u32; 2],
+    /// Start the energy detect measurement used in IEEE 802.15.4 mode
+    /// - Address: 0x024 - 0x028
+    task_edstart: WriteOnly<u32, Task::Register>,
+    /// Stop the energy detect measurement
+    /// - Address: 0x028 - 0x02c
+    task_edstop: WriteOnly<u32, Task::Register>,
     /// Stop the bit counter
     /// - Address: 0x02c - 0x030
     task_ccastart: WriteOnly<u32, Task::Register>,
@@ -197,8 +210,12 @@ struct RadioRegisters {
     /// IEEE 802.15.4 length field received
     /// - Address: 0x138 - 0x13c
     event_framestart: ReadWrite<u32, Event::Register>,
-    /// Reserved
-    _reserved5: [u32; 2],
+    /// Sampling of energy detection complete
+    /// - Address: 0x13c - 0x140
+    event_edend: ReadWrite<u32, Event::Register>,
+    /// The sampling of energy detection has stopped
+    /// - Address: 0x140 - 0x144
+    event_edstopped: ReadWrite<u32, Event::Register>,
     /// Wireless medium in idle - clear to send
     /// - Address: 0x144-0x148
     event_ccaidle: ReadWrite<u32, Event::Register>,
@@ -325,7 +342,13 @@ struct RadioRegisters {
     /// - Address: 0x650 - 0x654
     modecnf0: ReadWrite<u32, RadioModeConfig::Register>,
     /// Reserved
-    _reserved16: [u32; 6],
+    _reserved16: [u32; 4],
+    /// IEEE 802.15.4 energy detect loop count
+    /// - Address: 0x664 - 0x668
+    edcnt: ReadWrite<u32, EnergyDetectCount::Register>,
+    /// IEEE 802.15.4 energy detect level
+    /// - Address: 0x668 - 0x66C
+    edsample: ReadOnly<u32, EnergyDetectSample::Register>,
     /// Clear Channel Assesment (CCA) control register
     /// - Address: 0x66C - 0x670
     ccactrl: ReadWrite<u32, CCAControl::Register>,
@@ -392,6 +415,10 @@ register_bitfields! [u32,
         CRCERROR OFFSET(13) NUMBITS(1),
         /// CCAIDLE event
         FRAMESTART OFFSET(14) NUMBITS(1),
+        /// EDEND event
+        EDEND OFFSET(15) NUMBITS(1),
+        /// EDSTOPPED event
+        EDSTOPPED OFFSET(16) NUMBITS(1),
         /// CCAIDLE event
         CCAIDLE OFFSET(17) NUMBITS(1),
         /// CCABUSY event
@@ -582,6 +609,16 @@ register_bitfields! [u32,
         /// RSSI sample result
         RSSISAMPLE OFFSET(0) NUMBITS(7)
     ],
+    /// Energy detect loop count register
+    EnergyDetectCount [
+        /// The energy detection is repeated EDCNT + 1 times, each for 128 us
+        EDCNT OFFSET(0) NUMBITS(21)
+    ],
+    /// Energy detect level register
+    EnergyDetectSample [
+        /// The peak energy level of the energy detection
+        EDLVL OFFSET(0) NUMBITS(8)
+    ],
     /// Radio state register
     State [
         /// Current radio state
@@ -678,6 +715,16 @@ pub struct Radio<'a> {
     channel: Cell<RadioChannel>,
     transmitting: Cell<bool>,
     timer0: OptionalCell<&'a crate::timer::TimerAlarm<'a>>,
+    ed_client: OptionalCell<&'a dyn radio::EnergyDetectClient>,
+    /// The channel of the energy detection in progress.
+    ed_channel: OptionalCell<RadioChannel>,
+    link_quality: Cell<radio::LinkQuality>,
+    /// Whether the radio listens, rather than sleeping after `stop()`.
+    on: Cell<bool>,
+    /// A frame is being received, between FRAMESTART and END.
+    receiving: Cell<bool>,
+    power_client: OptionalCell<&'a dyn PowerClient>,
+    deferred_call: DeferredCall,
 }
 
 impl<'a> AlarmClient for Radio<'a> {
@@ -704,6 +751,13 @@ impl<'a> Radio<'a> {
             channel: Cell::new(RadioChannel::DataChannel26),
             transmitting: Cell::new(false),
             timer0: OptionalCell::empty(),
+            ed_client: OptionalCell::empty(),
+            ed_channel: OptionalCell::empty(),
+            link_quality: Cell::new(radio::LinkQuality::default()),
+            on: Cell::new(true),
+            receiving: Cell::new(false),
+            power_client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
         }
     }
 
@@ -779,6 +833,8 @@ impl<'a> Radio<'a> {
                 && self.registers.state.get() == nrf5x::constants::RADIO_STATE_RXIDLE
             {
                 self.registers.task_ccastart.write(Task::ENABLE::SET);
+            } else if self.ed_channel.is_some() {
+                self.registers.task_edstart.write(Task::ENABLE::SET);
             } else {
                 self.registers.task_start.write(Task::ENABLE::SET);
             }
@@ -786,6 +842,24 @@ impl<'a> Radio<'a> {
 
         if self.registers.event_framestart.is_set(Event::READY) {
             self.registers.event_framestart.write(Event::READY::CLEAR);
+            if !self.transmitting.get() {
+                self.receiving.set(true);
+            }
+        }
+
+        if self.registers.event_edend.is_set(Event::READY) {
+            self.registers.event_edend.write(Event::READY::CLEAR);
+            let level = self.registers.edsample.read(EnergyDetectSample::EDLVL) as i16;
+            if let Some(channel) = self.ed_channel.take() {
+                // Listen on the configured channel again, unless stopped.
+                self.radio_off();
+                if self.on.get() {
+                    self.radio_initialize();
+                }
+                let dbm = (ED_RSSIOFFS + level).clamp(i8::MIN as i16, i8::MAX as i16) as i8;
+                self.ed_client
+                    .map(|client| client.energy_detect_done(channel.get_channel_index(), Ok(dbm)));
+            }
         }
 
         //   IF we receive the go ahead (channel is clear)
@@ -834,6 +908,7 @@ impl<'a> Radio<'a> {
         // tx or rx finished!
         if self.registers.event_end.is_set(Event::READY) {
             self.registers.event_end.write(Event::READY::CLEAR);
+            self.receiving.set(false);
 
             let result = if self.registers.crcstatus.is_set(Event::READY) {
                 Ok(())
@@ -863,20 +938,35 @@ impl<'a> Radio<'a> {
                     self.rx_client.map(|client| {
                         let rbuf = self.rx_buf.take().unwrap(); // Unwrap fail = RX Buffer produced error when sending received packet to requestor
 
-                        let frame_len = rbuf[MIMIC_PSDU_OFFSET as usize] as usize - radio::MFR_SIZE;
+                        let psdu_len = rbuf[MIMIC_PSDU_OFFSET as usize] as usize;
+                        let frame_len = psdu_len - radio::MFR_SIZE;
                         // Length is: S0 (0 Byte) + Length (1 Byte) + S1 (0 Bytes) + Payload
                         // And because the length field is directly read from the packet
                         // We need to add 2 to length to get the total length
 
-                        client.receive(rbuf, frame_len, self.registers.crcstatus.get() == 1, result)
+                        let crc_valid = self.registers.crcstatus.get() == 1;
+                        if crc_valid {
+                            // The radio stores the LQI in place of the
+                            // first byte of the FCS.
+                            let lqi = rbuf[MIMIC_PSDU_OFFSET as usize + psdu_len - 1] as u16;
+                            let rssi = self.registers.rssisample.read(RssiSample::RSSISAMPLE);
+                            let mut link_quality = self.link_quality.get();
+                            link_quality.add(-(rssi as i8), min(lqi * LQI_SCALE, 255) as u8);
+                            self.link_quality.set(link_quality);
+                        }
+
+                        client.receive(rbuf, frame_len, crc_valid, result)
                     });
                 }
                 // Radio state - Disabled
                 _ => (),
             }
             self.radio_off();
-            self.radio_initialize();
-            self.rx();
+            // The client may have stopped the radio in its callback.
+            if self.on.get() {
+                self.radio_initialize();
+                self.rx();
+            }
         }
         self.enable_interrupts();
     }
@@ -887,7 +977,8 @@ impl<'a> Radio<'a> {
                 + Interrupt::CCAIDLE::SET
                 + Interrupt::CCABUSY::SET
                 + Interrupt::END::SET
-                + Interrupt::FRAMESTART::SET,
+                + Interrupt::FRAMESTART::SET
+                + Interrupt::EDEND::SET,
         );
     }
 
@@ -906,6 +997,7 @@ impl<'a> Radio<'a> {
 
     fn radio_initialize(&self) {
         self.radio_on();
+        self.receiving.set(false);
 
         // Radio disable
         self.registers.event_disabled.set(0);
@@ -925,11 +1017,16 @@ impl<'a> Radio<'a> {
 
         self.ieee802154_set_tx_power();
 
-        self.ieee802154_set_channel_freq(self.channel.get());
+        self.ieee802154_set_channel_freq(self.ed_channel.unwrap_or(self.channel.get()));
 
         self.set_tx_address();
         self.set_rx_address();
 
+        // Sample the RSSI of every received frame.
+        self.registers
+            .shorts
+            .write(Shortcut::ADDRESS_RSSISTART::SET);
+
         // First step in transmitting or receiving is entering rx mode
         self.rx();
     }
@@ -990,6 +1087,26 @@ impl<'a> Radio<'a> {
         self.set_tx_power();
     }
 
+    /// Stop listening and power the radio off, so that the radio arbiter can
+    /// hand it to the BLE radio.
+    pub(crate) fn suspend(&self) {
+        self.disable_all_interrupts();
+        self.registers.event_disabled.set(0);
+        self.registers.task_disable.write(Task::ENABLE::SET);
+        while self.registers.event_disabled.get() == 0 {}
+        self.radio_off();
+        self.receiving.set(false);
+    }
+
+    /// Listen again after `suspend()`, with the current configuration,
+    /// unless the radio was stopped meanwhile.
+    pub(crate) fn resume(&self) {
+        if self.on.get() && self.rx_buf.is_some() {
+            self.radio_off();
+            self.radio_initialize();
+        }
+    }
+
     pub fn startup(&self) -> Result<(), ErrorCode> {
         self.radio_initialize();
         Ok(())
@@ -1021,27 +1138,48 @@ impl<'a> kernel::hil::radio::RadioConfig<'a> for Radio<'a> {
         Ok(())
     }
 
-    fn set_power_client(&self, _client: &'a dyn PowerClient) {
-        //
+    fn set_power_client(&self, client: &'a dyn PowerClient) {
+        self.power_client.set(client);
     }
 
     fn reset(&self) -> Result<(), ErrorCode> {
         self.radio_on();
         Ok(())
     }
+    /// Listen again after `stop()`. The radio is ready as soon as this
+    /// returns, but the power client is told in a deferred call.
     fn start(&self) -> Result<(), ErrorCode> {
-        let _ = self.reset();
+        if !self.on.get() {
+            self.on.set(true);
+            if self.rx_buf.is_some() {
+                self.radio_off();
+                self.radio_initialize();
+            }
+        }
+        self.deferred_call.set();
         Ok(())
     }
+
+    /// Power the radio off until `start()`, e.g. between the receive
+    /// windows of a low power MAC. A frame being received is dropped.
     fn stop(&self) -> Result<(), ErrorCode> {
-        self.radio_off();
+        if self.transmitting.get() || self.ed_channel.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.on.get() {
+            self.on.set(false);
+            self.suspend();
+        }
+        self.deferred_call.set();
         Ok(())
     }
+
     fn is_on(&self) -> bool {
-        true
+        self.on.get()
     }
+
     fn busy(&self) -> bool {
-        false
+        self.transmitting.get() || self.receiving.get() || self.ed_channel.is_some()
     }
 
     //#################################################
@@ -1056,8 +1194,11 @@ impl<'a> kernel::hil::radio::RadioConfig<'a> for Radio<'a> {
     /// PAN ID, TX power, and channel to the specified values, issues
     /// a callback to the config client when done.
     fn config_commit(&self) {
+        // A stopped radio applies the configuration when it starts.
         self.radio_off();
-        self.radio_initialize();
+        if self.on.get() {
+            self.radio_initialize();
+        }
     }
 
     fn set_config_client(&self, _client: &'a dyn radio::ConfigClient) {}
@@ -1107,6 +1248,9 @@ impl<'a> kernel::hil::radio::RadioConfig<'a> for Radio<'a> {
         match RadioChannel::try_from(chan) {
             Err(_) => Err(ErrorCode::NOSUPPORT),
             Ok(res) => {
+                if res != self.channel.get() {
+                    self.link_quality.set(radio::LinkQuality::default());
+                }
                 self.channel.set(res);
                 Ok(())
             }
@@ -1146,8 +1290,10 @@ impl<'a> kernel::hil::radio::RadioData<'a> for Radio<'a> {
         buf: &'static mut [u8],
         frame_len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        if self.tx_buf.is_some() || self.transmitting.get() {
+        if self.tx_buf.is_some() || self.transmitting.get() || self.ed_channel.is_some() {
             return Err((ErrorCode::BUSY, buf));
+        } else if !self.on.get() {
+            return Err((ErrorCode::OFF, buf));
         } else if radio::PSDU_OFFSET + frame_len >= buf.len() {
             // Not enough room for CRC
             return Err((ErrorCode::SIZE, buf));
@@ -1166,3 +1312,48 @@ impl<'a> kernel::hil::radio::RadioData<'a> for Radio<'a> {
         Ok(())
     }
 }
+
+impl<'a> kernel::hil::radio::RadioChannelQuality<'a> for Radio<'a> {
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.ed_client.set(client);
+    }
+
+    fn energy_detect(&self, channel: u8, duration_us: u32) -> Result<(), ErrorCode> {
+        if self.transmitting.get() || self.ed_channel.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.rx_buf.is_none() || !self.on.get() {
+            return Err(ErrorCode::OFF);
+        }
+        let channel = RadioChannel::try_from(channel).map_err(|_| ErrorCode::INVAL)?;
+
+        self.ed_channel.set(channel);
+        self.radio_off();
+        self.radio_initialize();
+        // Measured EDCNT + 1 times after the radio is ready.
+        let count = (duration_us / ED_PERIOD_US).clamp(1, 1 << 21) - 1;
+        self.registers
+            .edcnt
+            .write(EnergyDetectCount::EDCNT.val(count));
+        Ok(())
+    }
+
+    fn link_quality(&self) -> Option<radio::LinkQuality> {
+        Some(self.link_quality.get()).filter(|quality| quality.frames > 0)
+    }
+
+    fn reset_link_quality(&self) {
+        self.link_quality.set(radio::LinkQuality::default());
+    }
+}
+
+impl DeferredCallClient for Radio<'_> {
+    fn handle_deferred_call(&self) {
+        self.power_client
+            .map(|client| client.changed(self.on.get()));
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
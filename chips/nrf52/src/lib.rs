@@ -13,13 +13,17 @@ pub mod ble_radio;
 pub mod chip;
 pub mod clock;
 pub mod crt1;
+pub mod edge_counter;
 pub mod ficr;
 pub mod i2c;
 pub mod ieee802154_radio;
 pub mod nvmc;
+pub mod pdm;
 pub mod power;
 pub mod ppi;
+pub mod pulse_generator;
 pub mod pwm;
+pub mod radio_arbiter;
 pub mod spi;
 pub mod uart;
 pub mod uicr;
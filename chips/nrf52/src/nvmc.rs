@@ -5,6 +5,11 @@
 //! Non-Volatile Memory Controller
 //!
 //! Used in order read and write to internal flash.
+//!
+//! Pages are erased with partial erases of `ERASE_PARTIAL_MS` each, one per
+//! deferred call, instead of one page erase that stalls the CPU for tens of
+//! milliseconds. The kernel runs between the partial erases, and the erase can be
+//! suspended there to read and write other pages first.
 
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
@@ -41,8 +46,14 @@ struct NvmcRegisters {
     /// Register for erasing User Information Configuration Registers
     /// Address: 0x514 - 0x518
     pub eraseuicr: ReadWrite<u32, EraseUicr::Register>,
+    /// Register for partial erase of a page in Code area
+    /// Address: 0x518 - 0x51C
+    pub erasepagepartial: ReadWrite<u32, ErasePage::Register>,
+    /// Register for partial erase configuration
+    /// Address: 0x51C - 0x520
+    pub erasepagepartialcfg: ReadWrite<u32, ErasePagePartialConfig::Register>,
     /// Reserved
-    _reserved3: [u32; 10],
+    _reserved3: [u32; 8],
     /// Configuration register
     /// Address: 0x540 - 0x544
     pub icachecnf: ReadWrite<u32, CacheConfiguration::Register>,
@@ -110,6 +121,11 @@ register_bitfields! [u32,
             ERASE = 1
         ]
     ],
+    /// Register for partial erase configuration
+    ErasePagePartialConfig [
+        /// Duration of the partial erase in milliseconds
+        DURATION OFFSET(0) NUMBITS(7) []
+    ],
     /// I-Code cache configuration register
     CacheConfiguration [
         /// Cache enabled
@@ -141,6 +157,13 @@ register_bitfields! [u32,
 
 const PAGE_SIZE: usize = 4096;
 
+/// Duration of each partial erase.
+const ERASE_PARTIAL_MS: u32 = 2;
+
+/// Accumulated duration of partial erases after which a page is erased, the
+/// longest page erase time of the nRF52 chips.
+const ERASE_PAGE_MS: u32 = 90;
+
 /// This is a wrapper around a u8 array that is sized to a single page for the
 /// nrf. Users of this module must pass an object of this type to use the
 /// `hil::flash::Flash` interface.
@@ -195,7 +218,6 @@ pub enum FlashState {
     Ready, // Flash is ready to complete a command.
     Read,  // Performing a read operation.
     Write, // Performing a write operation.
-    Erase, // Performing an erase operation.
 }
 
 pub struct Nvmc {
@@ -203,6 +225,9 @@ pub struct Nvmc {
     client: OptionalCell<&'static dyn hil::flash::Client<Nvmc>>,
     buffer: TakeCell<'static, NrfPage>,
     state: Cell<FlashState>,
+    /// Page being erased, and for how many milliseconds it was so far.
+    erase: OptionalCell<(usize, u32)>,
+    erase_suspended: Cell<bool>,
     deferred_call: DeferredCall,
 }
 
@@ -213,6 +238,8 @@ impl Nvmc {
             client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
             state: Cell::new(FlashState::Ready),
+            erase: OptionalCell::empty(),
+            erase_suspended: Cell::new(false),
             deferred_call: DeferredCall::new(),
         }
     }
@@ -259,12 +286,38 @@ impl Nvmc {
                     });
                 });
             }
-            FlashState::Erase => {
-                self.client.map(|client| {
-                    client.erase_complete(hil::flash::Error::CommandComplete);
-                });
-            }
-            _ => {}
+            FlashState::Ready => {}
+        }
+
+        if !self.erase_suspended.get() {
+            self.erase
+                .take()
+                .map(|(page_number, erased_ms)| self.erase_page_partial(page_number, erased_ms));
+        }
+    }
+
+    /// Erase `page_number` for another `ERASE_PARTIAL_MS`, and signal the
+    /// end of the erase once it was erased for `ERASE_PAGE_MS`.
+    fn erase_page_partial(&self, page_number: usize, erased_ms: u32) {
+        self.registers.config.write(Configuration::WEN::Een);
+        self.registers
+            .erasepagepartialcfg
+            .write(ErasePagePartialConfig::DURATION.val(ERASE_PARTIAL_MS));
+        self.registers
+            .erasepagepartial
+            .write(ErasePage::ERASEPAGE.val((page_number * PAGE_SIZE) as u32));
+
+        // The CPU is blocked during the partial erase, as for a page erase.
+        while !self.registers.ready.is_set(Ready::READY) {}
+
+        let erased_ms = erased_ms + ERASE_PARTIAL_MS;
+        if erased_ms < ERASE_PAGE_MS {
+            self.erase.set((page_number, erased_ms));
+            self.deferred_call.set();
+        } else {
+            self.client.map(|client| {
+                client.erase_complete(hil::flash::Error::CommandComplete);
+            });
         }
     }
 
@@ -346,16 +399,42 @@ impl Nvmc {
     }
 
     fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
-        // Do the basic erase.
-        self.erase_page_helper(page_number);
+        if self.erase.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
 
-        // Mark that we want to trigger a pseudo interrupt so that we can issue
-        // the callback even though the NVMC is completely blocking.
-        self.state.set(FlashState::Erase);
+        // The partial erases run from deferred calls.
+        self.erase.set((page_number, 0));
+        self.erase_suspended.set(false);
         self.deferred_call.set();
 
         Ok(())
     }
+
+    fn suspend_erase(&self) -> Result<(), ErrorCode> {
+        if self.erase.is_none() {
+            Err(ErrorCode::OFF)
+        } else if self.erase_suspended.get() {
+            Err(ErrorCode::ALREADY)
+        } else {
+            // No partial erase runs outside of the deferred call, so the
+            // erase stops after the current one.
+            self.erase_suspended.set(true);
+            Ok(())
+        }
+    }
+
+    fn resume_erase(&self) -> Result<(), ErrorCode> {
+        if self.erase.is_none() || !self.erase_suspended.get() {
+            Err(ErrorCode::OFF)
+        } else if self.state.get() != FlashState::Ready {
+            Err(ErrorCode::BUSY)
+        } else {
+            self.erase_suspended.set(false);
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
 }
 
 impl<C: hil::flash::Client<Self>> hil::flash::HasClient<'static, C> for Nvmc {
@@ -386,6 +465,14 @@ impl hil::flash::Flash for Nvmc {
     fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
         self.erase_page(page_number)
     }
+
+    fn suspend_erase(&self) -> Result<(), ErrorCode> {
+        self.suspend_erase()
+    }
+
+    fn resume_erase(&self) -> Result<(), ErrorCode> {
+        self.resume_erase()
+    }
 }
 
 impl DeferredCallClient for Nvmc {
@@ -0,0 +1,271 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Sound pressure from a PDM microphone, for the nRF52 PDM peripheral.
+//!
+//! The peripheral decimates the pulse density modulated signal of a digital
+//! microphone to 16 bit PCM samples at 16 kHz, and writes them with EasyDMA
+//! to a buffer. A sound pressure reading fills the buffer twice: the first
+//! buffer is discarded, as the decimation filter settles, and the sound
+//! pressure level is the RMS of the second one.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let buffer = static_init!([i16; 512], [0; 512]);
+//! base_peripherals.pdm.configure(
+//!     unsafe { nrf52840::pinmux::Pinmux::new(PDM_CLK_PIN as u32) },
+//!     unsafe { nrf52840::pinmux::Pinmux::new(PDM_DIN_PIN as u32) },
+//!     120,
+//! );
+//! base_peripherals.pdm.set_buffer(buffer);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::sensors::{SoundPressure, SoundPressureClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell, VolatileCell};
+use kernel::utilities::math;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+use nrf5x::pinmux::Pinmux;
+
+const PDM_BASE: StaticRef<PdmRegisters> =
+    unsafe { StaticRef::new(0x4001D000 as *const PdmRegisters) };
+
+/// The largest buffer EasyDMA can fill, in samples.
+pub const MAX_SAMPLES: usize = (1 << 15) - 1;
+
+register_structs! {
+    PdmRegisters {
+        /// Starts continuous PDM transfer
+        (0x000 => task_start: WriteOnly<u32, TASK::Register>),
+        /// Stops PDM transfer
+        (0x004 => task_stop: WriteOnly<u32, TASK::Register>),
+        (0x008 => _reserved0),
+        /// PDM transfer has started
+        (0x100 => events_started: ReadWrite<u32, EVENT::Register>),
+        /// PDM transfer has finished
+        (0x104 => events_stopped: ReadWrite<u32, EVENT::Register>),
+        /// The PDM has written the last sample specified by SAMPLE.MAXCNT
+        (0x108 => events_end: ReadWrite<u32, EVENT::Register>),
+        (0x10C => _reserved1),
+        /// Enable interrupt
+        (0x304 => intenset: ReadWrite<u32, INTE::Register>),
+        /// Disable interrupt
+        (0x308 => intenclr: ReadWrite<u32, INTE::Register>),
+        (0x30C => _reserved2),
+        /// PDM module enable register
+        (0x500 => enable: ReadWrite<u32, ENABLE::Register>),
+        /// PDM clock generator control
+        (0x504 => pdmclkctrl: ReadWrite<u32>),
+        /// Defines the routing of the connected PDM microphones' signals
+        (0x508 => mode: ReadWrite<u32, MODE::Register>),
+        (0x50C => _reserved3),
+        /// Left output gain adjustment
+        (0x518 => gainl: ReadWrite<u32, GAIN::Register>),
+        /// Right output gain adjustment
+        (0x51C => gainr: ReadWrite<u32, GAIN::Register>),
+        (0x520 => _reserved4),
+        /// Pin number configuration for PDM CLK signal
+        (0x540 => psel_clk: VolatileCell<Pinmux>),
+        /// Pin number configuration for PDM DIN signal
+        (0x544 => psel_din: VolatileCell<Pinmux>),
+        (0x548 => _reserved5),
+        /// RAM address pointer to write samples to with EasyDMA
+        (0x560 => sample_ptr: ReadWrite<u32>),
+        /// Number of samples to allocate memory for in EasyDMA mode
+        (0x564 => sample_maxcnt: ReadWrite<u32, MAXCNT::Register>),
+        (0x568 => @END),
+    }
+}
+
+register_bitfields![u32,
+    TASK [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    EVENT [
+        READY OFFSET(0) NUMBITS(1)
+    ],
+    INTE [
+        STARTED OFFSET(0) NUMBITS(1),
+        STOPPED OFFSET(1) NUMBITS(1),
+        END OFFSET(2) NUMBITS(1)
+    ],
+    ENABLE [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    MODE [
+        OPERATION OFFSET(0) NUMBITS(1) [
+            Stereo = 0,
+            Mono = 1
+        ],
+        EDGE OFFSET(1) NUMBITS(1) [
+            LeftFalling = 0,
+            LeftRising = 1
+        ]
+    ],
+    GAIN [
+        /// 0x00 is -20 dB, 0x28 0 dB and 0x50 +20 dB, in 0.5 dB steps
+        GAIN OFFSET(0) NUMBITS(7) [
+            Default = 0x28
+        ]
+    ],
+    MAXCNT [
+        BUFFSIZE OFFSET(0) NUMBITS(15)
+    ]
+];
+
+/// PDM clock of 1.032 MHz, for a sample rate of 16.125 kHz.
+const PDMCLKCTRL_1032K: u32 = 0x08400000;
+
+/// The number of buffers discarded while the decimation filter settles.
+const SETTLING_BUFFERS: u8 = 1;
+
+pub struct Pdm<'a> {
+    registers: StaticRef<PdmRegisters>,
+    client: OptionalCell<&'a dyn SoundPressureClient>,
+    buffer: TakeCell<'static, [i16]>,
+    /// Powers the microphone, if it has a supply pin.
+    power_pin: OptionalCell<&'a dyn gpio::Pin>,
+    /// The sound pressure level in dB SPL at which the microphone reaches
+    /// full scale.
+    full_scale_spl: Cell<u8>,
+    /// The buffers filled since sampling started.
+    buffers: Cell<u8>,
+    sampling: Cell<bool>,
+}
+
+impl<'a> Pdm<'a> {
+    pub fn new() -> Self {
+        Self {
+            registers: PDM_BASE,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            power_pin: OptionalCell::empty(),
+            full_scale_spl: Cell::new(120),
+            buffers: Cell::new(0),
+            sampling: Cell::new(false),
+        }
+    }
+
+    /// Connect the microphone at `clk` and `din`, which reaches full scale
+    /// at `full_scale_spl` dB SPL, i.e. 94 dB minus its sensitivity in
+    /// dBFS.
+    pub fn configure(&self, clk: Pinmux, din: Pinmux, full_scale_spl: u8) {
+        self.registers.psel_clk.set(clk);
+        self.registers.psel_din.set(din);
+        self.full_scale_spl.set(full_scale_spl);
+    }
+
+    /// The buffer the samples of a reading are written to, of at most
+    /// [`MAX_SAMPLES`] samples.
+    pub fn set_buffer(&self, buffer: &'static mut [i16]) {
+        self.buffer.replace(buffer);
+    }
+
+    /// A pin that supplies the microphone, set by `enable()`.
+    pub fn set_power_pin(&self, pin: &'a dyn gpio::Pin) {
+        pin.make_output();
+        self.power_pin.set(pin);
+    }
+
+    pub fn handle_interrupt(&self) {
+        if self.registers.events_end.is_set(EVENT::READY) {
+            self.registers.events_end.write(EVENT::READY::CLEAR);
+            // The buffer pointer is double buffered, so the next buffer is
+            // written to the same memory.
+            self.buffers.set(self.buffers.get() + 1);
+            if self.buffers.get() > SETTLING_BUFFERS {
+                self.registers.task_stop.write(TASK::ENABLE::SET);
+            }
+        }
+
+        if self.registers.events_stopped.is_set(EVENT::READY) {
+            self.registers.events_stopped.write(EVENT::READY::CLEAR);
+            self.registers
+                .intenclr
+                .write(INTE::END::SET + INTE::STOPPED::SET);
+            self.registers.enable.write(ENABLE::ENABLE::CLEAR);
+            self.sampling.set(false);
+            let spl = self.buffer.map_or(0, |buffer| self.compute_spl(buffer));
+            self.client.map(|client| client.callback(Ok(()), spl));
+        }
+    }
+
+    /// The sound pressure level of `samples`, from their RMS.
+    fn compute_spl(&self, samples: &[i16]) -> u8 {
+        if samples.is_empty() {
+            return 0;
+        }
+        let len = samples.len() as i32;
+        let mean = samples.iter().map(|sample| *sample as i32).sum::<i32>() / len;
+        let power = samples
+            .iter()
+            .map(|sample| {
+                let ac = (*sample as i32 - mean) as f32;
+                ac * ac
+            })
+            .sum::<f32>()
+            / len as f32;
+        let full_scale = i16::MAX as f32 * i16::MAX as f32;
+        // 10 log10 of the power is 20 log10 of the RMS.
+        let dbfs = 10.0 * math::log10(power.max(1.0) / full_scale);
+        (self.full_scale_spl.get() as f32 + dbfs).clamp(0.0, u8::MAX as f32) as u8
+    }
+}
+
+impl<'a> SoundPressure<'a> for Pdm<'a> {
+    fn read_sound_pressure(&self) -> Result<(), ErrorCode> {
+        if self.sampling.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let len = self.buffer.map_or(0, |buffer| buffer.len());
+        if len == 0 || len > MAX_SAMPLES {
+            return Err(ErrorCode::NOMEM);
+        }
+        self.buffer.map(|buffer| {
+            self.registers.sample_ptr.set(buffer.as_mut_ptr() as u32);
+        });
+        self.registers
+            .sample_maxcnt
+            .write(MAXCNT::BUFFSIZE.val(len as u32));
+
+        self.registers.pdmclkctrl.set(PDMCLKCTRL_1032K);
+        self.registers
+            .mode
+            .write(MODE::OPERATION::Mono + MODE::EDGE::LeftFalling);
+        self.registers.gainl.write(GAIN::GAIN::Default);
+        self.registers.gainr.write(GAIN::GAIN::Default);
+        self.registers.enable.write(ENABLE::ENABLE::SET);
+
+        self.buffers.set(0);
+        self.sampling.set(true);
+        self.registers.events_end.write(EVENT::READY::CLEAR);
+        self.registers.events_stopped.write(EVENT::READY::CLEAR);
+        self.registers
+            .intenset
+            .write(INTE::END::SET + INTE::STOPPED::SET);
+        self.registers.task_start.write(TASK::ENABLE::SET);
+        Ok(())
+    }
+
+    fn enable(&self) -> Result<(), ErrorCode> {
+        self.power_pin.map(|pin| pin.set());
+        Ok(())
+    }
+
+    fn disable(&self) -> Result<(), ErrorCode> {
+        self.power_pin.map(|pin| pin.clear());
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn SoundPressureClient) {
+        self.client.set(client);
+    }
+}
@@ -4,6 +4,7 @@
 
 //! Power management
 
+use kernel::hil;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 use kernel::utilities::registers::{
@@ -369,3 +370,24 @@ impl<'a> Power<'a> {
         self.registers.gpregret.write(Byte::VALUE.val(val as u32));
     }
 }
+
+impl<'a> hil::reset::ResetReasonSource for Power<'a> {
+    fn reset_reason(&self) -> hil::reset::ResetReason {
+        // RESETREAS accumulates the causes until cleared, it is empty after a
+        // power-on reset or a brownout.
+        let reasons = self.registers.resetreas.extract();
+        if reasons.is_set(ResetReason::DOG) {
+            hil::reset::ResetReason::Watchdog
+        } else if reasons.is_set(ResetReason::LOCKUP) {
+            hil::reset::ResetReason::Lockup
+        } else if reasons.is_set(ResetReason::SREQ) {
+            hil::reset::ResetReason::Software
+        } else if reasons.is_set(ResetReason::RESETPIN) {
+            hil::reset::ResetReason::Pin
+        } else if reasons.get() != 0 {
+            hil::reset::ResetReason::Wakeup
+        } else {
+            hil::reset::ResetReason::PowerOn
+        }
+    }
+}
@@ -0,0 +1,185 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Pulse generator using a TIMER, GPIOTE, and PPI.
+//!
+//! The pin is handed over to a GPIOTE channel configured to toggle the pin.
+//! A TIMER counting at 1 MHz generates a compare event at the end of each
+//! pulse and at the end of each period, and two PPI channels connect these
+//! events to the GPIOTE toggle task. The pin is therefore driven entirely by
+//! hardware.
+//!
+//! The kernel only counts the pulses, in the interrupt at the end of each
+//! pulse. The pulses are stopped in that interrupt, so when generating a fixed
+//! number of pulses the time between the end of one pulse and the start of
+//! the next (`period_us - width_us`) must be longer than the interrupt
+//! latency, a few tens of microseconds to be safe.
+//!
+//! Stopping the pulses is reported with a deferred call, which must be
+//! registered, e.g. with `with_deferred_call()` of
+//! `components::pulse_generator::PulseGeneratorComponent`.
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::gpio::{self, Configure, Output};
+use kernel::hil::pulse::{PulseClient, PulseGenerator as PulseGeneratorHil};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+use crate::gpio::GPIOPin;
+use crate::ppi::Ppi;
+use crate::timer::{CompareClient, Timer};
+
+pub struct PulseGenerator<'a> {
+    timer: &'a Timer,
+    ppi: &'a Ppi,
+    ppi_channels: [usize; 2],
+    pin: &'a GPIOPin<'a>,
+    mode: gpio::ActivationMode,
+    running: Cell<bool>,
+    /// Pulses left to generate, 0 if the pulses repeat until stopped.
+    remaining: Cell<usize>,
+    client: OptionalCell<&'a dyn PulseClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> PulseGenerator<'a> {
+    /// Generate pulses on `pin` with `timer` and two unused PPI channels
+    /// (0-19). The timer must not be used for anything else.
+    pub fn new(
+        timer: &'a Timer,
+        ppi: &'a Ppi,
+        ppi_channels: [usize; 2],
+        pin: &'a GPIOPin<'a>,
+        mode: gpio::ActivationMode,
+    ) -> Self {
+        PulseGenerator {
+            timer,
+            ppi,
+            ppi_channels,
+            pin,
+            mode,
+            running: Cell::new(false),
+            remaining: Cell::new(0),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn set_inactive(&self) {
+        match self.mode {
+            gpio::ActivationMode::ActiveHigh => self.pin.clear(),
+            gpio::ActivationMode::ActiveLow => self.pin.set(),
+        }
+    }
+
+    fn halt(&self) {
+        self.ppi
+            .disable(Ppi::channel(self.ppi_channels[0]) + Ppi::channel(self.ppi_channels[1]));
+        self.timer.disable_compare0_interrupt();
+        self.timer.stop();
+        self.pin.disable_task_output();
+        self.running.set(false);
+    }
+}
+
+impl<'a> PulseGeneratorHil<'a> for PulseGenerator<'a> {
+    fn set_client(&self, client: &'a dyn PulseClient) {
+        self.client.set(client);
+    }
+
+    fn start(&self, width_us: u32, period_us: u32, count: usize) -> Result<(), ErrorCode> {
+        if self.running.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let repeat = count != 1;
+        if width_us == 0 || (repeat && width_us >= period_us) {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // The pin returns to this level once the pulses are done.
+        self.pin.make_output();
+        self.set_inactive();
+        self.timer.configure_pulses_us(width_us, period_us, repeat);
+        // The pin becomes active as soon as the GPIOTE channel takes it over,
+        // and is toggled at the end of each pulse and at the end of each
+        // period.
+        let active = match self.mode {
+            gpio::ActivationMode::ActiveHigh => true,
+            gpio::ActivationMode::ActiveLow => false,
+        };
+        let toggle = self.pin.enable_task_output(active)?;
+        self.ppi.configure_channel(
+            self.ppi_channels[0],
+            self.timer.event_compare0_address(),
+            toggle,
+            None,
+        );
+        self.ppi.configure_channel(
+            self.ppi_channels[1],
+            self.timer.event_compare1_address(),
+            toggle,
+            None,
+        );
+        let channels = if repeat {
+            Ppi::channel(self.ppi_channels[0]) + Ppi::channel(self.ppi_channels[1])
+        } else {
+            Ppi::channel(self.ppi_channels[0])
+        };
+        self.ppi.enable(channels);
+
+        self.remaining.set(count);
+        if count != 0 {
+            self.timer.enable_compare0_interrupt();
+        }
+        self.running.set(true);
+        self.timer.start();
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.running.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.halt();
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn max_period_us(&self) -> u32 {
+        u32::MAX
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+impl CompareClient for PulseGenerator<'_> {
+    fn compare(&self, bitmask: u8) {
+        if bitmask & 0b1 == 0 || !self.running.get() {
+            return;
+        }
+        let remaining = self.remaining.get().saturating_sub(1);
+        self.remaining.set(remaining);
+        if remaining == 0 {
+            self.halt();
+            self.client.map(|client| client.pulses_done(Ok(())));
+        } else {
+            self.timer.enable_compare0_interrupt();
+        }
+    }
+}
+
+impl DeferredCallClient for PulseGenerator<'_> {
+    fn handle_deferred_call(&self) {
+        self.client
+            .map(|client| client.pulses_done(Err(ErrorCode::CANCEL)));
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
@@ -0,0 +1,539 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Timeslot arbitration of the radio between BLE and IEEE 802.15.4.
+//!
+//! The nRF52 has a single radio, which both `ble_radio` and
+//! `ieee802154_radio` drive, so without arbitration a board can only use one
+//! of them. `RadioArbiter` sits between the two radio drivers and their
+//! users, implementing the BLE advertising and the 802.15.4 radio HILs, and
+//! hands the radio out in timeslots:
+//!
+//! - a BLE timeslot is one advertisement transmitted or received,
+//! - an 802.15.4 timeslot is one frame transmitted, including CSMA-CA, or
+//!   one energy detection.
+//!
+//! Between timeslots the 802.15.4 radio listens. Frames that arrive while
+//! BLE has the radio are lost.
+//!
+//! If a protocol asks for the radio while the other one has it or waits for
+//! it, the request of the protocol with priority waits for the end of the
+//! timeslot and is granted then, and a request of the other protocol is
+//! denied with `BUSY`. 802.15.4 has priority by default, as BLE advertising
+//! goes on with the next channel when an advertisement is denied. A BLE
+//! receive timeslot ends after [`BLE_RECEIVE_SLOT_MS`] if nothing was
+//! received, so that scanning does not starve 802.15.4.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let arbiter_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! arbiter_alarm.setup();
+//! let arbiter = static_init!(
+//!     nrf52840::radio_arbiter::RadioArbiter<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     nrf52840::radio_arbiter::RadioArbiter::new(
+//!         &base_peripherals.ble_radio,
+//!         &base_peripherals.ieee802154_radio,
+//!         arbiter_alarm,
+//!     )
+//! );
+//! arbiter_alarm.set_alarm_client(arbiter);
+//! base_peripherals.ble_radio.set_transmit_client(arbiter);
+//! base_peripherals.ble_radio.set_receive_client(arbiter);
+//! base_peripherals.ieee802154_radio.set_transmit_client(arbiter);
+//! base_peripherals.ieee802154_radio.set_energy_detect_client(arbiter);
+//! kernel::deferred_call::DeferredCallClient::register(arbiter);
+//! ```
+//!
+//! The BLE and 802.15.4 components then take `arbiter` as their radio.
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::ble_advertising::{self, BleAdvertisementDriver, RadioChannel};
+use kernel::hil::radio::{self, RadioChannelQuality, RadioConfig, RadioData};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::ble_radio;
+use crate::ieee802154_radio;
+
+/// The longest a BLE receive timeslot lasts if nothing is received.
+pub const BLE_RECEIVE_SLOT_MS: u32 = 10;
+
+/// The protocols sharing the radio.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Protocol {
+    Ble,
+    Ieee802154,
+}
+
+/// Notified of the decisions of the arbiter, e.g. to count them.
+pub trait RadioArbiterClient {
+    /// `protocol` was granted the radio for a timeslot.
+    fn granted(&self, protocol: Protocol);
+    /// A request of `protocol` was denied, because the other protocol has
+    /// the radio and priority.
+    fn denied(&self, protocol: Protocol);
+}
+
+#[derive(Clone, Copy)]
+enum BleRequest {
+    Transmit { len: usize, channel: RadioChannel },
+    Receive(RadioChannel),
+}
+
+pub struct RadioArbiter<'a, A: Alarm<'a>> {
+    ble: &'a ble_radio::Radio<'a>,
+    ieee802154: &'a ieee802154_radio::Radio<'a>,
+    alarm: &'a A,
+    priority: Cell<Protocol>,
+    /// The protocol with the current timeslot.
+    owner: OptionalCell<Protocol>,
+    /// A BLE request waiting for the radio.
+    ble_request: Cell<Option<BleRequest>>,
+    ble_tx_buf: TakeCell<'static, [u8]>,
+    /// A denied BLE request, reported from a deferred call as the BLE HIL
+    /// has no synchronous errors.
+    ble_denied: Cell<Option<BleRequest>>,
+    ble_denied_buf: TakeCell<'static, [u8]>,
+    /// The length of an 802.15.4 frame waiting for the radio.
+    ieee802154_request: Cell<Option<usize>>,
+    ieee802154_tx_buf: TakeCell<'static, [u8]>,
+    /// The 802.15.4 radio was listening when BLE took the radio, or was
+    /// configured while BLE had it, and must be restarted.
+    ieee802154_suspended: Cell<bool>,
+    ble_rx_client: OptionalCell<&'a dyn ble_advertising::RxClient>,
+    ble_tx_client: OptionalCell<&'a dyn ble_advertising::TxClient>,
+    ieee802154_tx_client: OptionalCell<&'a dyn radio::TxClient>,
+    ieee802154_ed_client: OptionalCell<&'a dyn radio::EnergyDetectClient>,
+    client: OptionalCell<&'a dyn RadioArbiterClient>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a, A: Alarm<'a>> RadioArbiter<'a, A> {
+    pub fn new(
+        ble: &'a ble_radio::Radio<'a>,
+        ieee802154: &'a ieee802154_radio::Radio<'a>,
+        alarm: &'a A,
+    ) -> Self {
+        Self {
+            ble,
+            ieee802154,
+            alarm,
+            priority: Cell::new(Protocol::Ieee802154),
+            owner: OptionalCell::empty(),
+            ble_request: Cell::new(None),
+            ble_tx_buf: TakeCell::empty(),
+            ble_denied: Cell::new(None),
+            ble_denied_buf: TakeCell::empty(),
+            ieee802154_request: Cell::new(None),
+            ieee802154_tx_buf: TakeCell::empty(),
+            ieee802154_suspended: Cell::new(false),
+            ble_rx_client: OptionalCell::empty(),
+            ble_tx_client: OptionalCell::empty(),
+            ieee802154_tx_client: OptionalCell::empty(),
+            ieee802154_ed_client: OptionalCell::empty(),
+            client: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn RadioArbiterClient) {
+        self.client.set(client);
+    }
+
+    /// Give requests of `protocol` precedence over the other protocol.
+    pub fn set_priority(&self, protocol: Protocol) {
+        self.priority.set(protocol);
+    }
+
+    pub fn priority(&self) -> Protocol {
+        self.priority.get()
+    }
+
+    /// Whether the 802.15.4 radio may use the hardware right now.
+    fn ieee802154_has_radio(&self) -> bool {
+        !self.owner.contains(&Protocol::Ble) && !self.ieee802154_suspended.get()
+    }
+
+    fn request_ble(&self, request: BleRequest, buf: Option<&'static mut [u8]>) {
+        let ieee802154_busy =
+            self.owner.contains(&Protocol::Ieee802154) || self.ieee802154_request.get().is_some();
+        if self.ble_request.get().is_some()
+            || (ieee802154_busy && self.priority.get() != Protocol::Ble)
+        {
+            self.client.map(|client| client.denied(Protocol::Ble));
+            buf.map(|buf| self.ble_denied_buf.replace(buf));
+            self.ble_denied.set(Some(request));
+        } else {
+            // BLE is always granted from a deferred call, as the request
+            // may come from an interrupt handler of the 802.15.4 radio,
+            // which restarts listening when it returns.
+            buf.map(|buf| self.ble_tx_buf.replace(buf));
+            self.ble_request.set(Some(request));
+        }
+        self.deferred_call.set();
+    }
+
+    fn grant_ble(&self, request: BleRequest) {
+        if self.ieee802154.is_enabled() {
+            self.ieee802154.suspend();
+            self.ieee802154_suspended.set(true);
+        }
+        self.owner.set(Protocol::Ble);
+        self.client.map(|client| client.granted(Protocol::Ble));
+        match request {
+            BleRequest::Transmit { len, channel } => {
+                self.ble_tx_buf
+                    .take()
+                    .map(|buf| self.ble.transmit_advertisement(buf, len, channel));
+            }
+            BleRequest::Receive(channel) => {
+                self.ble.receive_advertisement(channel);
+                self.alarm.set_alarm(
+                    self.alarm.now(),
+                    self.alarm.ticks_from_ms(BLE_RECEIVE_SLOT_MS),
+                );
+            }
+        }
+    }
+
+    fn grant_ieee802154(&self, frame_len: usize) {
+        let buf = match self.ieee802154_tx_buf.take() {
+            Some(buf) => buf,
+            None => return,
+        };
+        self.owner.set(Protocol::Ieee802154);
+        self.client
+            .map(|client| client.granted(Protocol::Ieee802154));
+        match self.ieee802154.transmit(buf, frame_len) {
+            Ok(()) => self.ieee802154_suspended.set(false),
+            Err((err, buf)) => {
+                self.owner.clear();
+                self.ieee802154_tx_client
+                    .map(move |client| client.send_done(buf, false, Err(err)));
+                // Let the 802.15.4 radio listen again.
+                self.deferred_call.set();
+            }
+        }
+    }
+
+    /// End the BLE timeslot.
+    fn ble_done(&self) {
+        if self.owner.contains(&Protocol::Ble) {
+            self.owner.clear();
+            self.deferred_call.set();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> DeferredCallClient for RadioArbiter<'a, A> {
+    fn handle_deferred_call(&self) {
+        match self.ble_denied.take() {
+            Some(BleRequest::Transmit { .. }) => {
+                self.ble_denied_buf.take().map(|buf| {
+                    self.ble_tx_client
+                        .map(move |client| client.transmit_event(buf, Err(ErrorCode::BUSY)))
+                });
+            }
+            Some(BleRequest::Receive(_)) => self.ble.cancel_receive(ErrorCode::BUSY),
+            None => {}
+        }
+
+        if self.owner.is_some() {
+            return;
+        }
+        let ble_first =
+            self.priority.get() == Protocol::Ble || self.ieee802154_request.get().is_none();
+        if let Some(request) = self.ble_request.get().filter(|_| ble_first) {
+            self.ble_request.set(None);
+            self.grant_ble(request);
+        } else if let Some(frame_len) = self.ieee802154_request.take() {
+            self.grant_ieee802154(frame_len);
+        } else if self.ieee802154_suspended.take() {
+            self.ieee802154.resume();
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for RadioArbiter<'a, A> {
+    fn alarm(&self) {
+        // Nothing was received in the BLE receive timeslot.
+        if self.owner.contains(&Protocol::Ble) {
+            self.ble.cancel_receive(ErrorCode::CANCEL);
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> ble_advertising::TxClient for RadioArbiter<'a, A> {
+    fn transmit_event(&self, buf: &'static mut [u8], result: Result<(), ErrorCode>) {
+        self.ble_tx_client
+            .map(move |client| client.transmit_event(buf, result));
+        // Requests made by the client still see the timeslot, and are
+        // granted from the deferred call after the interrupt handler of the
+        // BLE radio returned.
+        self.ble_done();
+    }
+}
+
+impl<'a, A: Alarm<'a>> ble_advertising::RxClient for RadioArbiter<'a, A> {
+    fn receive_event(&self, buf: &'static mut [u8], len: u8, result: Result<(), ErrorCode>) {
+        let _ = self.alarm.disarm();
+        self.ble_rx_client
+            .map(move |client| client.receive_event(buf, len, result));
+        self.ble_done();
+    }
+}
+
+impl<'a, A: Alarm<'a>> radio::TxClient for RadioArbiter<'a, A> {
+    fn send_done(&self, buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
+        self.owner.clear();
+        self.ieee802154_tx_client
+            .map(move |client| client.send_done(buf, acked, result));
+        if self.owner.is_none() {
+            self.deferred_call.set();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> radio::EnergyDetectClient for RadioArbiter<'a, A> {
+    fn energy_detect_done(&self, channel: u8, result: Result<i8, ErrorCode>) {
+        self.owner.clear();
+        self.ieee802154_ed_client
+            .map(|client| client.energy_detect_done(channel, result));
+        if self.owner.is_none() {
+            self.deferred_call.set();
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> ble_advertising::BleAdvertisementDriver<'a> for RadioArbiter<'a, A> {
+    fn transmit_advertisement(&self, buf: &'static mut [u8], len: usize, channel: RadioChannel) {
+        self.request_ble(BleRequest::Transmit { len, channel }, Some(buf));
+    }
+
+    fn receive_advertisement(&self, channel: RadioChannel) {
+        self.request_ble(BleRequest::Receive(channel), None);
+    }
+
+    fn set_receive_client(&self, client: &'a dyn ble_advertising::RxClient) {
+        self.ble_rx_client.set(client);
+    }
+
+    fn set_transmit_client(&self, client: &'a dyn ble_advertising::TxClient) {
+        self.ble_tx_client.set(client);
+    }
+}
+
+impl<'a, A: Alarm<'a>> ble_advertising::BleConfig for RadioArbiter<'a, A> {
+    fn set_tx_power(&self, tx_power: u8) -> Result<(), ErrorCode> {
+        ble_advertising::BleConfig::set_tx_power(self.ble, tx_power)
+    }
+}
+
+impl<'a, A: Alarm<'a>> RadioConfig<'a> for RadioArbiter<'a, A> {
+    fn initialize(
+        &self,
+        spi_buf: &'static mut [u8],
+        reg_write: &'static mut [u8],
+        reg_read: &'static mut [u8],
+    ) -> Result<(), ErrorCode> {
+        if self.ieee802154_has_radio() {
+            self.ieee802154.initialize(spi_buf, reg_write, reg_read)
+        } else {
+            self.ieee802154_suspended.set(true);
+            Ok(())
+        }
+    }
+
+    fn set_power_client(&self, client: &'a dyn radio::PowerClient) {
+        self.ieee802154.set_power_client(client);
+    }
+
+    fn reset(&self) -> Result<(), ErrorCode> {
+        if self.ieee802154_has_radio() {
+            self.ieee802154.reset()
+        } else {
+            self.ieee802154_suspended.set(true);
+            Ok(())
+        }
+    }
+
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.ieee802154_has_radio() {
+            self.ieee802154.start()
+        } else {
+            self.ieee802154_suspended.set(true);
+            Ok(())
+        }
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if self.ieee802154_has_radio() {
+            self.ieee802154.stop()
+        } else {
+            // Do not listen again after the BLE timeslot.
+            self.ieee802154_suspended.set(false);
+            Ok(())
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.ieee802154.is_on()
+    }
+
+    fn busy(&self) -> bool {
+        self.ieee802154.busy()
+    }
+
+    fn config_commit(&self) {
+        if self.ieee802154_has_radio() {
+            self.ieee802154.config_commit();
+        } else {
+            // The configuration is applied when 802.15.4 gets the radio
+            // back.
+            self.ieee802154_suspended.set(true);
+        }
+    }
+
+    fn set_config_client(&self, client: &'a dyn radio::ConfigClient) {
+        self.ieee802154.set_config_client(client);
+    }
+
+    fn get_address(&self) -> u16 {
+        self.ieee802154.get_address()
+    }
+
+    fn get_address_long(&self) -> [u8; 8] {
+        self.ieee802154.get_address_long()
+    }
+
+    fn get_pan(&self) -> u16 {
+        self.ieee802154.get_pan()
+    }
+
+    fn get_tx_power(&self) -> i8 {
+        self.ieee802154.get_tx_power()
+    }
+
+    fn get_channel(&self) -> u8 {
+        self.ieee802154.get_channel()
+    }
+
+    fn set_address(&self, addr: u16) {
+        self.ieee802154.set_address(addr);
+    }
+
+    fn set_address_long(&self, addr: [u8; 8]) {
+        self.ieee802154.set_address_long(addr);
+    }
+
+    fn set_pan(&self, id: u16) {
+        self.ieee802154.set_pan(id);
+    }
+
+    fn set_tx_power(&self, power: i8) -> Result<(), ErrorCode> {
+        RadioConfig::set_tx_power(self.ieee802154, power)
+    }
+
+    fn set_channel(&self, chan: u8) -> Result<(), ErrorCode> {
+        self.ieee802154.set_channel(chan)
+    }
+}
+
+impl<'a, A: Alarm<'a>> RadioData<'a> for RadioArbiter<'a, A> {
+    fn set_transmit_client(&self, client: &'a dyn radio::TxClient) {
+        self.ieee802154_tx_client.set(client);
+    }
+
+    fn set_receive_client(
+        &self,
+        client: &'a dyn radio::RxClient,
+        receive_buffer: &'static mut [u8],
+    ) {
+        self.ieee802154.set_receive_client(client, receive_buffer);
+    }
+
+    fn set_receive_buffer(&self, receive_buffer: &'static mut [u8]) {
+        self.ieee802154.set_receive_buffer(receive_buffer);
+    }
+
+    fn transmit(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.owner.contains(&Protocol::Ieee802154) || self.ieee802154_request.get().is_some() {
+            return Err((ErrorCode::BUSY, buf));
+        }
+
+        let ble_busy = self.owner.contains(&Protocol::Ble) || self.ble_request.get().is_some();
+        if ble_busy && self.priority.get() != Protocol::Ieee802154 {
+            self.client
+                .map(|client| client.denied(Protocol::Ieee802154));
+            Err((ErrorCode::BUSY, buf))
+        } else if self.ieee802154_has_radio() {
+            self.owner.set(Protocol::Ieee802154);
+            self.client
+                .map(|client| client.granted(Protocol::Ieee802154));
+            self.ieee802154.transmit(buf, frame_len).map_err(|err| {
+                self.owner.clear();
+                err
+            })
+        } else {
+            // Wait for the end of the BLE timeslot.
+            self.ieee802154_tx_buf.replace(buf);
+            self.ieee802154_request.set(Some(frame_len));
+            self.deferred_call.set();
+            Ok(())
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> RadioChannelQuality<'a> for RadioArbiter<'a, A> {
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.ieee802154_ed_client.set(client);
+    }
+
+    fn energy_detect(&self, channel: u8, duration_us: u32) -> Result<(), ErrorCode> {
+        // Energy detections are not queued, as they are not urgent.
+        if self.owner.is_some()
+            || self.ble_request.get().is_some()
+            || self.ieee802154_request.get().is_some()
+            || !self.ieee802154_has_radio()
+        {
+            return Err(ErrorCode::BUSY);
+        }
+        self.owner.set(Protocol::Ieee802154);
+        self.client
+            .map(|client| client.granted(Protocol::Ieee802154));
+        self.ieee802154
+            .energy_detect(channel, duration_us)
+            .map_err(|err| {
+                self.owner.clear();
+                err
+            })
+    }
+
+    fn link_quality(&self) -> Option<radio::LinkQuality> {
+        self.ieee802154.link_quality()
+    }
+
+    fn reset_link_quality(&self) {
+        self.ieee802154.reset_link_quality();
+    }
+}
@@ -4,6 +4,12 @@
 
 //! Universal asynchronous receiver/transmitter with EasyDMA (UARTE)
 //!
+//! `receive_automatic()` is supported once a spare TIMER and two PPI channels
+//! are provided with `set_idle_line_detection()`. Every received byte restarts
+//! the timer through PPI, and the timer expiring stops the reception through
+//! PPI, so a receive completes after the line has been idle for the requested
+//! time without any per-byte interrupts.
+//!
 //! Author
 //! -------------------
 //!
@@ -15,12 +21,15 @@ use core::cell::Cell;
 use core::cmp::min;
 use kernel::hil::uart;
 use kernel::utilities::cells::OptionalCell;
-use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 use nrf5x::pinmux;
 
+use crate::ppi::Ppi;
+use crate::timer::Timer;
+
 const UARTE_MAX_BUFFER_SIZE: u32 = 0xff;
 
 static mut BYTE: u8 = 0;
@@ -39,7 +48,8 @@ struct UarteRegisters {
     _reserved2: [u32; 52],
     event_cts: ReadWrite<u32, Event::Register>,
     event_ncts: ReadWrite<u32, Event::Register>,
-    _reserved3: [u32; 2],
+    event_rxdrdy: ReadWrite<u32, Event::Register>,
+    _reserved3: [u32; 1],
     event_endrx: ReadWrite<u32, Event::Register>,
     _reserved4: [u32; 3],
     event_endtx: ReadWrite<u32, Event::Register>,
@@ -173,7 +183,18 @@ pub struct Uarte<'a> {
     rx_buffer: kernel::utilities::cells::TakeCell<'static, [u8]>,
     rx_remaining_bytes: Cell<usize>,
     rx_abort_in_progress: Cell<bool>,
+    rx_automatic: Cell<bool>,
     offset: Cell<usize>,
+    baud_rate: Cell<u32>,
+    idle_line_detection: OptionalCell<IdleLineDetection<'a>>,
+}
+
+/// Hardware used to detect an idle RX line.
+#[derive(Copy, Clone)]
+struct IdleLineDetection<'a> {
+    timer: &'a Timer,
+    ppi: &'a Ppi,
+    channels: [usize; 2],
 }
 
 #[derive(Copy, Clone)]
@@ -195,7 +216,10 @@ impl<'a> Uarte<'a> {
             rx_buffer: kernel::utilities::cells::TakeCell::empty(),
             rx_remaining_bytes: Cell::new(0),
             rx_abort_in_progress: Cell::new(false),
+            rx_automatic: Cell::new(false),
             offset: Cell::new(0),
+            baud_rate: Cell::new(115200),
+            idle_line_detection: OptionalCell::empty(),
         }
     }
 
@@ -240,7 +264,59 @@ impl<'a> Uarte<'a> {
         self.enable_uart();
     }
 
+    /// Provide a TIMER and two unused PPI channels (0-19) used to implement
+    /// `receive_automatic()`. The timer must not be used for anything else.
+    pub fn set_idle_line_detection(&self, timer: &'a Timer, ppi: &'a Ppi, channels: [usize; 2]) {
+        self.idle_line_detection.set(IdleLineDetection {
+            timer,
+            ppi,
+            channels,
+        });
+    }
+
+    /// Restart the idle timer on every received byte, and stop receiving once
+    /// the timer fires after `interbyte_timeout` bit periods.
+    fn start_idle_line_detection(&self, idle: IdleLineDetection, interbyte_timeout: u8) {
+        let bits = core::cmp::max(interbyte_timeout, 1) as u32;
+        let baud_rate = self.baud_rate.get();
+        let timeout_us = (bits * 1_000_000 + baud_rate - 1) / baud_rate;
+
+        idle.timer.configure_one_shot_us(timeout_us);
+        idle.ppi.configure_channel(
+            idle.channels[0],
+            &self.registers.event_rxdrdy as *const _ as u32,
+            idle.timer.task_clear_address(),
+            Some(idle.timer.task_start_address()),
+        );
+        idle.ppi.configure_channel(
+            idle.channels[1],
+            idle.timer.event_compare0_address(),
+            &self.registers.task_stoprx as *const _ as u32,
+            None,
+        );
+        self.registers.event_rxdrdy.write(Event::READY::CLEAR);
+        idle.ppi
+            .enable(Ppi::channel(idle.channels[0]) + Ppi::channel(idle.channels[1]));
+        self.rx_automatic.set(true);
+    }
+
+    fn stop_idle_line_detection(&self) {
+        if self.rx_automatic.get() {
+            self.rx_automatic.set(false);
+            self.idle_line_detection.map(|idle| {
+                idle.ppi
+                    .disable(Ppi::channel(idle.channels[0]) + Ppi::channel(idle.channels[1]));
+                idle.timer.stop();
+            });
+        }
+    }
+
     fn set_baud_rate(&self, baud_rate: u32) {
+        self.baud_rate.set(match baud_rate {
+            1200 | 2400 | 4800 | 9600 | 14400 | 19200 | 28800 | 38400 | 57600 | 76800 | 115200
+            | 230400 | 250000 | 460800 | 921600 | 1000000 => baud_rate,
+            _ => 115200,
+        });
         match baud_rate {
             1200 => self.registers.baudrate.set(0x0004F000),
             2400 => self.registers.baudrate.set(0x0009D000),
@@ -330,11 +406,13 @@ impl<'a> Uarte<'a> {
 
             // Get the number of bytes in the buffer that was received this time
             let rx_bytes = self.registers.rxd_amount.get() as usize;
+            let rx_requested = self.registers.rxd_maxcnt.read(Counter::COUNTER) as usize;
 
             // Check if this ENDRX is due to an abort. If so, we want to
             // do the receive callback immediately.
             if self.rx_abort_in_progress.get() {
                 self.rx_abort_in_progress.set(false);
+                self.stop_idle_line_detection();
                 self.rx_client.map(|client| {
                     self.rx_buffer.take().map(|rx_buffer| {
                         client.received_buffer(
@@ -356,7 +434,11 @@ impl<'a> Uarte<'a> {
                 self.offset.set(self.offset.get() + rx_bytes);
 
                 let rem = self.rx_remaining_bytes.get();
-                if rem == 0 {
+                // With idle line detection a short read means the timer
+                // stopped the reception.
+                let idle = self.rx_automatic.get() && rx_bytes < rx_requested;
+                if rem == 0 || idle {
+                    self.stop_idle_line_detection();
                     // Signal client that the read is done
                     self.rx_client.map(|client| {
                         self.rx_buffer.take().map(|rx_buffer| {
@@ -438,6 +520,27 @@ impl<'a> Uarte<'a> {
 
         self.enable_tx_interrupts();
     }
+
+    // Helper function used by both receive_buffer and receive_automatic
+    fn setup_buffer_receive(&self, rx_buf: &'static mut [u8], rx_len: usize) {
+        // truncate rx_len if necessary
+        let truncated_length = core::cmp::min(rx_len, rx_buf.len());
+
+        self.rx_remaining_bytes.set(truncated_length);
+        self.offset.set(0);
+        self.rx_buffer.replace(rx_buf);
+        self.set_rx_dma_pointer_to_buffer();
+
+        let truncated_uart_max_length = core::cmp::min(truncated_length, 255);
+
+        self.registers
+            .rxd_maxcnt
+            .write(Counter::COUNTER.val(truncated_uart_max_length as u32));
+        self.registers.task_stoprx.write(Task::ENABLE::SET);
+        self.registers.task_startrx.write(Task::ENABLE::SET);
+
+        self.enable_rx_interrupts();
+    }
 }
 
 impl<'a> uart::Transmit<'a> for Uarte<'a> {
@@ -479,11 +582,20 @@ impl<'a> uart::Configure for Uarte<'a> {
         if params.parity != uart::Parity::None {
             return Err(ErrorCode::NOSUPPORT);
         }
-        if params.hw_flow_control != false {
-            return Err(ErrorCode::NOSUPPORT);
+        if params.hw_flow_control {
+            // RTS/CTS flow control needs both pins, which are selected in
+            // `initialize()`.
+            if self.registers.pselcts.is_set(Psel::CONNECT)
+                || self.registers.pselrts.is_set(Psel::CONNECT)
+            {
+                return Err(ErrorCode::NOSUPPORT);
+            }
         }
 
         self.set_baud_rate(params.baud_rate);
+        self.registers
+            .config
+            .modify(Config::HWFC.val(params.hw_flow_control as u32));
 
         Ok(())
     }
@@ -502,23 +614,7 @@ impl<'a> uart::Receive<'a> for Uarte<'a> {
         if self.rx_buffer.is_some() {
             return Err((ErrorCode::BUSY, rx_buf));
         }
-        // truncate rx_len if necessary
-        let truncated_length = core::cmp::min(rx_len, rx_buf.len());
-
-        self.rx_remaining_bytes.set(truncated_length);
-        self.offset.set(0);
-        self.rx_buffer.replace(rx_buf);
-        self.set_rx_dma_pointer_to_buffer();
-
-        let truncated_uart_max_length = core::cmp::min(truncated_length, 255);
-
-        self.registers
-            .rxd_maxcnt
-            .write(Counter::COUNTER.val(truncated_uart_max_length as u32));
-        self.registers.task_stoprx.write(Task::ENABLE::SET);
-        self.registers.task_startrx.write(Task::ENABLE::SET);
-
-        self.enable_rx_interrupts();
+        self.setup_buffer_receive(rx_buf, rx_len);
         Ok(())
     }
 
@@ -537,3 +633,23 @@ impl<'a> uart::Receive<'a> for Uarte<'a> {
         }
     }
 }
+
+impl<'a> uart::ReceiveAdvanced<'a> for Uarte<'a> {
+    fn receive_automatic(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        interbyte_timeout: u8,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let idle = match self.idle_line_detection.extract() {
+            Some(idle) => idle,
+            None => return Err((ErrorCode::NOSUPPORT, rx_buffer)),
+        };
+        if self.rx_buffer.is_some() {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+        self.start_idle_line_detection(idle, interbyte_timeout);
+        self.setup_buffer_receive(rx_buffer, rx_len);
+        Ok(())
+    }
+}
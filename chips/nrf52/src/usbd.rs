@@ -3,6 +3,9 @@
 // Copyright Tock Contributors 2022.
 
 //! Universal Serial Bus Device with EasyDMA (USBD)
+//!
+//! Endpoints 1 to 7 are bulk or interrupt endpoints, endpoint 8 is the
+//! isochronous endpoint.
 
 use core::cell::Cell;
 use cortexm4::support::atomic;
@@ -67,6 +70,7 @@ const USBERRATA_BASE: StaticRef<UsbErrataRegisters> =
     unsafe { StaticRef::new(0x4006E000 as *const UsbErrataRegisters) };
 
 const NUM_ENDPOINTS: usize = 8;
+const ISO_ENDPOINT: usize = 8;
 
 register_structs! {
     ChipInfoRegisters {
@@ -629,6 +633,7 @@ pub enum EndpointState {
     Disabled,
     Ctrl(CtrlState),
     Bulk(TransferType, Option<BulkInState>, Option<BulkOutState>),
+    Iso { has_in: bool, has_out: bool },
 }
 
 impl EndpointState {
@@ -716,7 +721,12 @@ pub struct Usbd<'a> {
     state: OptionalCell<UsbState>,
     dma_pending: Cell<bool>,
     client: OptionalCell<&'a dyn hil::usb::Client<'a>>,
-    descriptors: [Endpoint<'a>; NUM_ENDPOINTS],
+    descriptors: [Endpoint<'a>; NUM_ENDPOINTS + 1],
+    // Size of the packet to load into the isochronous IN endpoint.
+    iso_in_size: Cell<usize>,
+    // Frame and size of the packet received on the isochronous OUT endpoint.
+    iso_out_frame: Cell<u16>,
+    iso_out_size: Cell<u32>,
     power: OptionalCell<&'a power::Power<'a>>,
 }
 
@@ -736,7 +746,11 @@ impl<'a> Usbd<'a> {
                 Endpoint::new(),
                 Endpoint::new(),
                 Endpoint::new(),
+                Endpoint::new(),
             ],
+            iso_in_size: Cell::new(0),
+            iso_out_frame: Cell::new(0),
+            iso_out_size: Cell::new(0),
             power: OptionalCell::empty(),
         }
     }
@@ -1038,7 +1052,7 @@ impl<'a> Usbd<'a> {
         self.descriptors[endpoint].state.set(match endpoint {
             0 => EndpointState::Ctrl(CtrlState::Init),
             1..=7 => EndpointState::Bulk(transfer_type, Some(BulkInState::Init), None),
-            8 => unimplemented!("isochronous endpoint"),
+            8 => self.enable_iso_endpoint(true, false),
             _ => unreachable!("unexisting endpoint"),
         });
     }
@@ -1076,7 +1090,7 @@ impl<'a> Usbd<'a> {
         self.descriptors[endpoint].state.set(match endpoint {
             0 => EndpointState::Ctrl(CtrlState::Init),
             1..=7 => EndpointState::Bulk(transfer_type, None, Some(BulkOutState::Init)),
-            8 => unimplemented!("isochronous endpoint"),
+            8 => self.enable_iso_endpoint(false, true),
             _ => unreachable!("unexisting endpoint"),
         });
     }
@@ -1130,11 +1144,35 @@ impl<'a> Usbd<'a> {
                 Some(BulkInState::Init),
                 Some(BulkOutState::Init),
             ),
-            8 => unimplemented!("isochronous endpoint"),
+            8 => self.enable_iso_endpoint(true, true),
             _ => unreachable!("unexisting endpoint"),
         });
     }
 
+    /// Add directions to the isochronous endpoint, which moves its packets on
+    /// start of frame events.
+    fn enable_iso_endpoint(&self, has_in: bool, has_out: bool) -> EndpointState {
+        let (has_in, has_out) = match self.descriptors[ISO_ENDPOINT].state.get() {
+            EndpointState::Iso {
+                has_in: had_in,
+                has_out: had_out,
+            } => (has_in || had_in, has_out || had_out),
+            _ => (has_in, has_out),
+        };
+        // Both directions share the 1 KiB of isochronous buffer.
+        self.registers.isosplit.write(if has_in && has_out {
+            IsoSplit::SPLIT::HalfIN
+        } else {
+            IsoSplit::SPLIT::OneDir
+        });
+        // Answer with a zero length packet in frames without data.
+        self.registers
+            .isoinconfig
+            .write(IsoInConfig::RESPONSE::ZeroData);
+        self.registers.intenset.write(Interrupt::SOF::SET);
+        EndpointState::Iso { has_in, has_out }
+    }
+
     fn ep_abort_all(&self) {
         internal_warn!("ep_abort_all() not implemented");
     }
@@ -1320,6 +1358,7 @@ impl<'a> Usbd<'a> {
                         self.registers.size_epout[ep].set(0);
                     }
                 }
+                EndpointState::Iso { .. } => {}
             }
             // Clear the DMA status.
             desc.request_transmit_in.set(false);
@@ -1414,7 +1453,11 @@ impl<'a> Usbd<'a> {
     }
 
     fn handle_endisoin(&self) {
-        unimplemented!("handle_endisoin");
+        // Make DMA available again for other endpoints.
+        self.clear_pending_dma();
+
+        // The packet is now in the endpoint, and goes out at the next IN
+        // token of this frame.
     }
 
     fn handle_endepout(&self, endpoint: usize) {
@@ -1506,11 +1549,58 @@ impl<'a> Usbd<'a> {
     }
 
     fn handle_endisoout(&self) {
-        unimplemented!("handle_endisoout");
+        // Make DMA available again for other endpoints.
+        self.clear_pending_dma();
+
+        self.client.map(|client| {
+            client.iso_packet_out(
+                ISO_ENDPOINT,
+                self.iso_out_frame.get(),
+                self.iso_out_size.get(),
+            )
+        });
     }
 
     fn handle_sof(&self) {
-        unimplemented!("handle_sof");
+        let frame = self.registers.framecntr.read(FrameCounter::FRAMECNTR) as u16;
+        let (has_in, has_out) = match self.descriptors[ISO_ENDPOINT].state.get() {
+            EndpointState::Iso { has_in, has_out } => (has_in, has_out),
+            _ => return,
+        };
+
+        if has_out {
+            // The packet, if any, was received during the previous frame.
+            let size = self.registers.size_iosout.extract();
+            let out_frame = frame.wrapping_sub(1) & 0x7ff;
+            if size.read(IsoEndpointSize::SIZE) > 0 {
+                self.iso_out_frame.set(out_frame);
+                self.iso_out_size.set(size.read(IsoEndpointSize::SIZE));
+                if self.dma_pending.get() {
+                    self.descriptors[ISO_ENDPOINT]
+                        .request_transmit_out
+                        .set(true);
+                } else {
+                    self.start_dma_iso_out();
+                }
+            } else if size.is_set(IsoEndpointSize::ZERO) {
+                self.client
+                    .map(|client| client.iso_packet_out(ISO_ENDPOINT, out_frame, 0));
+            }
+        }
+
+        if has_in {
+            let size = self
+                .client
+                .map_or(0, |client| client.iso_packet_in(ISO_ENDPOINT, frame));
+            if size > 0 {
+                self.iso_in_size.set(size);
+                if self.dma_pending.get() {
+                    self.descriptors[ISO_ENDPOINT].request_transmit_in.set(true);
+                } else {
+                    self.start_dma_iso_in();
+                }
+            }
+        }
     }
 
     fn handle_usbevent(&self) {
@@ -1749,6 +1839,8 @@ impl<'a> Usbd<'a> {
             if desc.request_transmit_in.take() {
                 if endpoint == 0 {
                     self.transmit_in_ep0();
+                } else if endpoint == ISO_ENDPOINT {
+                    self.start_dma_iso_in();
                 } else {
                     self.transmit_in(endpoint);
                 }
@@ -1759,6 +1851,8 @@ impl<'a> Usbd<'a> {
             if desc.request_transmit_out.take() {
                 if endpoint == 0 {
                     self.transmit_out_ep0();
+                } else if endpoint == ISO_ENDPOINT {
+                    self.start_dma_iso_out();
                 } else {
                     self.transmit_out(endpoint);
                 }
@@ -1892,6 +1986,30 @@ impl<'a> Usbd<'a> {
         self.registers.task_startepout[endpoint].write(Task::ENABLE::SET);
     }
 
+    fn start_dma_iso_in(&self) {
+        let slice = self.descriptors[ISO_ENDPOINT].slice_in.unwrap_or_panic(); // Unwrap fail = No IN slice set for the isochronous endpoint
+        let size = self.iso_in_size.get();
+        if size > slice.len() {
+            panic!("Packet is too large: {}", size);
+        }
+
+        // Start DMA transfer
+        self.set_pending_dma();
+        self.registers.isoin.set_buffer(&slice[..size]);
+        debug_tasks!("- task: startisoin");
+        self.registers.task_startisoin.write(Task::ENABLE::SET);
+    }
+
+    fn start_dma_iso_out(&self) {
+        let slice = self.descriptors[ISO_ENDPOINT].slice_out.unwrap_or_panic(); // Unwrap fail = No OUT slice set for the isochronous endpoint
+
+        // Start DMA transfer
+        self.set_pending_dma();
+        self.registers.isoout.set_buffer(slice);
+        debug_tasks!("- task: startisoout");
+        self.registers.task_startisoout.write(Task::ENABLE::SET);
+    }
+
     // Debug-only function
     fn debug_in_packet(&self, size: usize, endpoint: usize) {
         let slice = self.descriptors[endpoint].slice_in.unwrap_or_panic(); // Unwrap fail = No IN slice set for this descriptor
@@ -1954,12 +2072,16 @@ impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
         if buf.len() < 8 {
             panic!("Endpoint buffer must be at least 8 bytes");
         }
-        if !buf.len().is_power_of_two() {
-            panic!("Buffer size must be a power of 2");
-        }
-        if endpoint == 0 || endpoint >= NUM_ENDPOINTS {
+        if endpoint == 0 || endpoint > ISO_ENDPOINT {
             panic!("Endpoint number is invalid");
         }
+        if endpoint == ISO_ENDPOINT {
+            if buf.len() > 1023 {
+                panic!("Isochronous buffer must be at most 1023 bytes");
+            }
+        } else if !buf.len().is_power_of_two() {
+            panic!("Buffer size must be a power of 2");
+        }
         self.descriptors[endpoint].slice_in.set(buf);
     }
 
@@ -1967,12 +2089,16 @@ impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
         if buf.len() < 8 {
             panic!("Endpoint buffer must be at least 8 bytes");
         }
-        if !buf.len().is_power_of_two() {
-            panic!("Buffer size must be a power of 2");
-        }
-        if endpoint == 0 || endpoint >= NUM_ENDPOINTS {
+        if endpoint == 0 || endpoint > ISO_ENDPOINT {
             panic!("Endpoint number is invalid");
         }
+        if endpoint == ISO_ENDPOINT {
+            if buf.len() > 1023 {
+                panic!("Isochronous buffer must be at most 1023 bytes");
+            }
+        } else if !buf.len().is_power_of_two() {
+            panic!("Buffer size must be a power of 2");
+        }
         self.descriptors[endpoint].slice_out.set(buf);
     }
 
@@ -2016,7 +2142,12 @@ impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
                 }
                 self.enable_in_endpoint_(transfer_type, endpoint);
             }
-            TransferType::Isochronous => unimplemented!("isochronous endpoint"),
+            TransferType::Isochronous => {
+                if endpoint != ISO_ENDPOINT {
+                    panic!("The isochronous endpoint is endpoint 8");
+                }
+                self.enable_in_endpoint_(transfer_type, endpoint);
+            }
         }
     }
 
@@ -2034,7 +2165,12 @@ impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
                 }
                 self.enable_out_endpoint_(transfer_type, endpoint);
             }
-            TransferType::Isochronous => unimplemented!("isochronous endpoint"),
+            TransferType::Isochronous => {
+                if endpoint != ISO_ENDPOINT {
+                    panic!("The isochronous endpoint is endpoint 8");
+                }
+                self.enable_out_endpoint_(transfer_type, endpoint);
+            }
         }
     }
 
@@ -2049,7 +2185,12 @@ impl<'a> hil::usb::UsbController<'a> for Usbd<'a> {
                 }
                 self.enable_in_out_endpoint_(transfer_type, endpoint);
             }
-            TransferType::Isochronous => unimplemented!("isochronous endpoint"),
+            TransferType::Isochronous => {
+                if endpoint != ISO_ENDPOINT {
+                    panic!("The isochronous endpoint is endpoint 8");
+                }
+                self.enable_in_out_endpoint_(transfer_type, endpoint);
+            }
         }
     }
 
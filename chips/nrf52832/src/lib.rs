@@ -5,9 +5,9 @@
 #![no_std]
 
 pub use nrf52::{
-    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, ficr, i2c, ieee802154_radio, init,
-    nvmc, peripheral_interrupts as base_interrupts, pinmux, power, ppi, pwm, rtc, spi, temperature,
-    timer, trng, uart, uicr,
+    acomp, adc, aes, ble_radio, chip, clock, constants, crt1, edge_counter, ficr, i2c,
+    ieee802154_radio, init, nvmc, pdm, peripheral_interrupts as base_interrupts, pinmux, power,
+    ppi, pulse_generator, pwm, rtc, spi, temperature, timer, trng, uart, uicr,
 };
 pub mod gpio;
 pub mod interrupt_service;
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 // Copyright Tock Contributors 2022.
 
-pub use nrf52::gpio::{GPIOPin, Pin, Port};
+pub use nrf52::gpio::{Bank, GPIOPin, Pin, Port};
 
 pub const NUM_PINS: usize = 48;
 
@@ -13,6 +13,7 @@ pub struct Nrf52840DefaultPeripherals<'a> {
     pub nrf52: Nrf52DefaultPeripherals<'a>,
     pub usbd: crate::usbd::Usbd<'a>,
     pub gpio_port: crate::gpio::Port<'a, { crate::gpio::NUM_PINS }>,
+    pub qspi: crate::qspi::Qspi<'a>,
 }
 
 impl<'a> Nrf52840DefaultPeripherals<'a> {
@@ -21,6 +22,7 @@ impl<'a> Nrf52840DefaultPeripherals<'a> {
             nrf52: Nrf52DefaultPeripherals::new(),
             usbd: crate::usbd::Usbd::new(),
             gpio_port: crate::gpio::nrf52840_gpio_create(),
+            qspi: crate::qspi::Qspi::new(),
         }
     }
     // Necessary for setting up circular dependencies
@@ -35,6 +37,7 @@ impl<'a> kernel::platform::chip::InterruptService for Nrf52840DefaultPeripherals
         match interrupt {
             crate::peripheral_interrupts::USBD => self.usbd.handle_interrupt(),
             nrf52::peripheral_interrupts::GPIOTE => self.gpio_port.handle_interrupt(),
+            crate::peripheral_interrupts::QSPI => self.qspi.handle_interrupt(),
             _ => return self.nrf52.service_interrupt(interrupt),
         }
         true
@@ -0,0 +1,420 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! QSPI controller for external NOR flash, nRF52840 only.
+//!
+//! Reads, writes and erases use EasyDMA, which has some constraints: the
+//! buffer must be word aligned and in RAM, and the flash address and length
+//! must be multiples of four. The controller issues the write enable
+//! commands and waits for the flash to finish programming or erasing by
+//! itself.
+//!
+//! While the controller is active, the flash is also mapped at
+//! `0x1200_0000` (XIP). Reads through the mapping wait while a task is in
+//! progress.
+//!
+//! The flash must support the quad I/O read (0xEB) and quad page program
+//! (0x32) commands, and have quad mode enabled. `configure()` enables it
+//! with a write of the status register for flashes that have the quad
+//! enable bit there, e.g. Macronix and ISSI.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let qspi = &nrf52840_peripherals.qspi;
+//! qspi.configure(
+//!     Pinmux::new(19), // SCK
+//!     Pinmux::new(17), // CSN
+//!     [Pinmux::new(20), Pinmux::new(21), Pinmux::new(22), Pinmux::new(23)],
+//!     8 * 1024 * 1024, // MX25R6435F
+//!     Some(0x40),
+//! );
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::qspi::{QspiFlash, QspiFlashClient, QspiMemoryMapped, SECTOR_SIZE};
+use kernel::utilities::cells::{OptionalCell, TakeCell, VolatileCell};
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+use nrf52::pinmux::Pinmux;
+
+const QSPI_BASE: StaticRef<QspiRegisters> =
+    unsafe { StaticRef::new(0x40029000 as *const QspiRegisters) };
+
+/// Start of the XIP region the flash is mapped to.
+const XIP_BASE: usize = 0x1200_0000;
+
+/// Largest READ.CNT and WRITE.CNT.
+const MAX_COUNT: usize = 0x3FFFF;
+
+register_structs! {
+    QspiRegisters {
+        (0x000 => tasks_activate: WriteOnly<u32, TASK::Register>),
+        (0x004 => tasks_readstart: WriteOnly<u32, TASK::Register>),
+        (0x008 => tasks_writestart: WriteOnly<u32, TASK::Register>),
+        (0x00C => tasks_erasestart: WriteOnly<u32, TASK::Register>),
+        (0x010 => tasks_deactivate: WriteOnly<u32, TASK::Register>),
+        (0x014 => _reserved0),
+        (0x100 => events_ready: ReadWrite<u32, EVENT::Register>),
+        (0x104 => _reserved1),
+        (0x304 => intenset: ReadWrite<u32, INTE::Register>),
+        (0x308 => intenclr: ReadWrite<u32, INTE::Register>),
+        (0x30C => _reserved2),
+        (0x500 => enable: ReadWrite<u32, ENABLE::Register>),
+        (0x504 => read_src: ReadWrite<u32>),
+        (0x508 => read_dst: ReadWrite<u32>),
+        (0x50C => read_cnt: ReadWrite<u32>),
+        (0x510 => write_dst: ReadWrite<u32>),
+        (0x514 => write_src: ReadWrite<u32>),
+        (0x518 => write_cnt: ReadWrite<u32>),
+        (0x51C => erase_ptr: ReadWrite<u32>),
+        (0x520 => erase_len: ReadWrite<u32, ERASE_LEN::Register>),
+        (0x524 => psel_sck: VolatileCell<Pinmux>),
+        (0x528 => psel_csn: VolatileCell<Pinmux>),
+        (0x52C => _reserved3),
+        (0x530 => psel_io: [VolatileCell<Pinmux>; 4]),
+        (0x540 => xipoffset: ReadWrite<u32>),
+        (0x544 => ifconfig0: ReadWrite<u32, IFCONFIG0::Register>),
+        (0x548 => _reserved4),
+        (0x600 => ifconfig1: ReadWrite<u32, IFCONFIG1::Register>),
+        (0x604 => status: ReadOnly<u32, STATUS::Register>),
+        (0x608 => _reserved5),
+        (0x634 => cinstrconf: ReadWrite<u32, CINSTRCONF::Register>),
+        (0x638 => cinstrdat0: ReadWrite<u32>),
+        (0x63C => cinstrdat1: ReadWrite<u32>),
+        (0x640 => @END),
+    }
+}
+
+register_bitfields![u32,
+    TASK [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    EVENT [
+        READY OFFSET(0) NUMBITS(1)
+    ],
+    INTE [
+        /// Interrupt on EVENTS_READY
+        READY OFFSET(0) NUMBITS(1)
+    ],
+    ENABLE [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    ERASE_LEN [
+        LEN OFFSET(0) NUMBITS(2) [
+            Sector4KB = 0,
+            Block64KB = 1,
+            All = 2
+        ]
+    ],
+    IFCONFIG0 [
+        /// Opcode of read operations
+        READOC OFFSET(0) NUMBITS(3) [
+            FastRead = 0,
+            Read2O = 1,
+            Read2IO = 2,
+            Read4O = 3,
+            Read4IO = 4
+        ],
+        /// Opcode of write operations
+        WRITEOC OFFSET(3) NUMBITS(3) [
+            PP = 0,
+            PP2O = 1,
+            PP4O = 2,
+            PP4IO = 3
+        ],
+        /// 24 or 32 bit addresses
+        ADDRMODE OFFSET(6) NUMBITS(1) [
+            Bit24 = 0,
+            Bit32 = 1
+        ],
+        /// Page size of page programs
+        PPSIZE OFFSET(12) NUMBITS(1) [
+            Bytes256 = 0,
+            Bytes512 = 1
+        ]
+    ],
+    IFCONFIG1 [
+        /// Minimum time CSN is high, in 62.5 ns steps
+        SCKDELAY OFFSET(0) NUMBITS(8) [],
+        /// SPI mode 0 or 3
+        SPIMODE OFFSET(25) NUMBITS(1) [
+            Mode0 = 0,
+            Mode3 = 1
+        ],
+        /// SCK frequency is 32 MHz / (SCKFREQ + 1)
+        SCKFREQ OFFSET(28) NUMBITS(4) []
+    ],
+    STATUS [
+        /// Whether the controller is ready for a new task
+        READY OFFSET(3) NUMBITS(1) [],
+        /// Value of the status register of the flash
+        SREG OFFSET(24) NUMBITS(8) []
+    ],
+    CINSTRCONF [
+        OPCODE OFFSET(0) NUMBITS(8) [],
+        /// Length of the instruction, opcode included, in bytes
+        LENGTH OFFSET(8) NUMBITS(4) [],
+        LIO2 OFFSET(12) NUMBITS(1) [],
+        LIO3 OFFSET(13) NUMBITS(1) [],
+        /// Wait for the write in progress bit to clear afterwards
+        WIPWAIT OFFSET(15) NUMBITS(1) [],
+        /// Send a write enable before the instruction
+        WREN OFFSET(16) NUMBITS(1) []
+    ]
+];
+
+/// Write status register command of NOR flashes.
+const WRITE_STATUS_OPCODE: u32 = 0x01;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Read { len: usize },
+    Write { len: usize },
+    Erase,
+}
+
+pub struct Qspi<'a> {
+    registers: StaticRef<QspiRegisters>,
+    client: OptionalCell<&'a dyn QspiFlashClient>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    size: Cell<usize>,
+    memory_mapped: Cell<bool>,
+}
+
+impl<'a> Qspi<'a> {
+    pub fn new() -> Self {
+        Self {
+            registers: QSPI_BASE,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            state: Cell::new(State::Idle),
+            size: Cell::new(0),
+            memory_mapped: Cell::new(false),
+        }
+    }
+
+    /// Configure the pins and the flash, which has `size` bytes, and
+    /// activate the controller. If `quad_enable` is given, it is written to
+    /// the status register of the flash to enable the quad mode.
+    ///
+    /// This waits for the flash to be activated and the status register to
+    /// be written.
+    pub fn configure(
+        &self,
+        sck: Pinmux,
+        csn: Pinmux,
+        io: [Pinmux; 4],
+        size: usize,
+        quad_enable: Option<u8>,
+    ) {
+        let regs = &*self.registers;
+        regs.psel_sck.set(sck);
+        regs.psel_csn.set(csn);
+        for (psel, pin) in regs.psel_io.iter().zip(io) {
+            psel.set(pin);
+        }
+        regs.xipoffset.set(0);
+        regs.ifconfig0.write(
+            IFCONFIG0::READOC::Read4IO
+                + IFCONFIG0::WRITEOC::PP4O
+                + if size > 1 << 24 {
+                    IFCONFIG0::ADDRMODE::Bit32
+                } else {
+                    IFCONFIG0::ADDRMODE::Bit24
+                }
+                + IFCONFIG0::PPSIZE::Bytes256,
+        );
+        // 32 MHz / 2, which NOR flashes support for quad reads
+        regs.ifconfig1.write(
+            IFCONFIG1::SCKDELAY.val(1) + IFCONFIG1::SPIMODE::Mode0 + IFCONFIG1::SCKFREQ.val(1),
+        );
+        self.size.set(size);
+
+        regs.enable.write(ENABLE::ENABLE::SET);
+        regs.events_ready.set(0);
+        regs.tasks_activate.write(TASK::ENABLE::SET);
+        self.wait_ready();
+
+        if let Some(status) = quad_enable {
+            regs.cinstrdat0.set(status as u32);
+            regs.cinstrconf.write(
+                CINSTRCONF::OPCODE.val(WRITE_STATUS_OPCODE)
+                    + CINSTRCONF::LENGTH.val(2)
+                    + CINSTRCONF::LIO2::SET
+                    + CINSTRCONF::LIO3::SET
+                    + CINSTRCONF::WIPWAIT::SET
+                    + CINSTRCONF::WREN::SET,
+            );
+            self.wait_ready();
+        }
+        regs.intenset.write(INTE::READY::SET);
+    }
+
+    fn wait_ready(&self) {
+        let regs = &*self.registers;
+        while !regs.events_ready.is_set(EVENT::READY) {}
+        regs.events_ready.set(0);
+    }
+
+    /// Deactivate and disable the controller, which stops the mapping.
+    pub fn disable(&self) {
+        let regs = &*self.registers;
+        regs.intenclr.write(INTE::READY::SET);
+        regs.tasks_deactivate.write(TASK::ENABLE::SET);
+        regs.enable.write(ENABLE::ENABLE::CLEAR);
+        self.memory_mapped.set(false);
+        self.size.set(0);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = &*self.registers;
+        if !regs.events_ready.is_set(EVENT::READY) {
+            return;
+        }
+        regs.events_ready.set(0);
+
+        let state = self.state.replace(State::Idle);
+        match state {
+            State::Idle => {}
+            State::Read { len } => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.read_done(buffer, len, Ok(())));
+                });
+            }
+            State::Write { len } => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.write_done(buffer, len, Ok(())));
+                });
+            }
+            State::Erase => {
+                self.client.map(|client| client.erase_done(Ok(())));
+            }
+        }
+    }
+
+    /// Checks that an operation on `len` bytes at `address` with `buffer`
+    /// can be started.
+    fn check(&self, address: usize, buffer: &[u8], len: usize) -> Result<(), ErrorCode> {
+        if self.size.get() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if len == 0
+            || len > buffer.len()
+            || len > MAX_COUNT
+            || address + len > self.size.get()
+            || address % 4 != 0
+            || len % 4 != 0
+            || buffer.as_ptr() as usize % 4 != 0
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> QspiFlash<'a> for Qspi<'a> {
+    fn set_client(&self, client: &'a dyn QspiFlashClient) {
+        self.client.set(client);
+    }
+
+    fn size(&self) -> usize {
+        self.size.get()
+    }
+
+    fn read(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check(address, buffer, len) {
+            return Err((e, buffer));
+        }
+        let regs = &*self.registers;
+        regs.read_src.set(address as u32);
+        regs.read_dst.set(buffer.as_mut_ptr() as u32);
+        regs.read_cnt.set(len as u32);
+        self.buffer.replace(buffer);
+        self.state.set(State::Read { len });
+        regs.tasks_readstart.write(TASK::ENABLE::SET);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check(address, buffer, len) {
+            return Err((e, buffer));
+        }
+        let regs = &*self.registers;
+        regs.write_dst.set(address as u32);
+        regs.write_src.set(buffer.as_ptr() as u32);
+        regs.write_cnt.set(len as u32);
+        self.buffer.replace(buffer);
+        self.state.set(State::Write { len });
+        regs.tasks_writestart.write(TASK::ENABLE::SET);
+        Ok(())
+    }
+
+    fn erase_sector(&self, address: usize) -> Result<(), ErrorCode> {
+        if self.size.get() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if address % SECTOR_SIZE != 0 || address >= self.size.get() {
+            return Err(ErrorCode::INVAL);
+        }
+        let regs = &*self.registers;
+        regs.erase_ptr.set(address as u32);
+        regs.erase_len.write(ERASE_LEN::LEN::Sector4KB);
+        self.state.set(State::Erase);
+        regs.tasks_erasestart.write(TASK::ENABLE::SET);
+        Ok(())
+    }
+}
+
+impl QspiMemoryMapped for Qspi<'_> {
+    fn enable_memory_mapped(&self) -> Result<(), ErrorCode> {
+        // the flash is mapped whenever the controller is active
+        if self.size.get() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        self.memory_mapped.set(true);
+        Ok(())
+    }
+
+    fn disable_memory_mapped(&self) -> Result<(), ErrorCode> {
+        self.memory_mapped.set(false);
+        Ok(())
+    }
+
+    fn memory_mapped(&self) -> Option<&'static [u8]> {
+        if self.memory_mapped.get() {
+            // Safety: the XIP region maps the whole flash and is read only.
+            // Reads while a task is in progress are stalled by the
+            // controller until the task is done.
+            Some(unsafe { core::slice::from_raw_parts(XIP_BASE as *const u8, self.size.get()) })
+        } else {
+            None
+        }
+    }
+}
@@ -17,6 +17,7 @@ use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 #[cfg(feature = "nrf51")]
 const NUM_GPIOTE: usize = 4;
@@ -519,6 +520,59 @@ impl<'a> hil::gpio::Interrupt<'a> for GPIOPin<'a> {
 }
 
 impl GPIOPin<'_> {
+    /// Hand the pin over to a GPIOTE channel, so other peripherals can toggle
+    /// it through PPI. The pin starts at level `high`. Returns the address of
+    /// the channel's `TASKS_OUT` register.
+    pub fn enable_task_output(&self, high: bool) -> Result<u32, ErrorCode> {
+        let channel = self.allocate_channel().map_err(|()| ErrorCode::NOMEM)?;
+        let pin: u32 = (GPIO_PER_PORT as u32 * self.port as u32) + self.pin as u32;
+        let init = if high {
+            Config::OUTINIT::High
+        } else {
+            Config::OUTINIT::Low
+        };
+        self.gpiote_registers.config[channel]
+            .write(Config::MODE::Task + Config::PSEL.val(pin) + Config::POLARITY::Toggle + init);
+        Ok(&self.gpiote_registers.task_out[channel] as *const _ as u32)
+    }
+
+    /// Return the pin to the GPIO module.
+    pub fn disable_task_output(&self) {
+        if let Ok(channel) = self.find_channel(self.pin) {
+            self.gpiote_registers.config[channel].write(
+                Config::MODE::CLEAR
+                    + Config::PSEL::CLEAR
+                    + Config::POLARITY::CLEAR
+                    + Config::OUTINIT::CLEAR,
+            );
+        }
+    }
+
+    /// Generate a GPIOTE event on each `edge` of the pin, without an
+    /// interrupt, so other peripherals can react to it through PPI. Returns
+    /// the address of the channel's `EVENTS_IN` register.
+    pub fn enable_event(&self, edge: hil::gpio::InterruptEdge) -> Result<u32, ErrorCode> {
+        let channel = self.allocate_channel().map_err(|()| ErrorCode::NOMEM)?;
+        let polarity = match edge {
+            hil::gpio::InterruptEdge::EitherEdge => Config::POLARITY::Toggle,
+            hil::gpio::InterruptEdge::RisingEdge => Config::POLARITY::LoToHi,
+            hil::gpio::InterruptEdge::FallingEdge => Config::POLARITY::HiToLo,
+        };
+        let pin: u32 = (GPIO_PER_PORT as u32 * self.port as u32) + self.pin as u32;
+        self.gpiote_registers.event_in[channel].write(EventsIn::EVENT::NotReady);
+        self.gpiote_registers.config[channel]
+            .write(Config::MODE::Event + Config::PSEL.val(pin) + polarity);
+        Ok(&self.gpiote_registers.event_in[channel] as *const _ as u32)
+    }
+
+    /// Release the GPIOTE channel used by `enable_event()`.
+    pub fn disable_event(&self) {
+        if let Ok(channel) = self.find_channel(self.pin) {
+            self.gpiote_registers.config[channel]
+                .write(Config::MODE::CLEAR + Config::PSEL::CLEAR + Config::POLARITY::CLEAR);
+        }
+    }
+
     /// Allocate a GPIOTE channel
     /// If the channel couldn't be allocated return error instead
     fn allocate_channel(&self) -> Result<usize, ()> {
@@ -549,6 +603,63 @@ impl GPIOPin<'_> {
     }
 }
 
+/// A hardware GPIO port, P0 or P1, to operate on several of its pins at once.
+pub struct Bank {
+    gpio_registers: StaticRef<GpioRegisters>,
+}
+
+impl Bank {
+    /// The bank of port `port`, i.e. 0 for P0 and 1 for P1.
+    pub const fn new(port: usize) -> Bank {
+        Bank {
+            gpio_registers: unsafe {
+                StaticRef::new((GPIO_BASE_ADDRESS + port * GPIO_SIZE) as *const GpioRegisters)
+            },
+        }
+    }
+}
+
+impl hil::gpio::Port for Bank {
+    fn width(&self) -> usize {
+        GPIO_PER_PORT
+    }
+
+    fn make_output(&self, mask: u32) {
+        self.gpio_registers.dirset.set(mask);
+    }
+
+    fn make_input(&self, mask: u32) {
+        for (pin, cnf) in self.gpio_registers.pin_cnf.iter().enumerate() {
+            if mask & (1 << pin) != 0 {
+                cnf.modify(PinConfig::DIR::Input + PinConfig::INPUT::Connect);
+            }
+        }
+    }
+
+    fn read(&self) -> u32 {
+        self.gpio_registers.in_.get()
+    }
+
+    fn set(&self, mask: u32) {
+        self.gpio_registers.outset.set(mask);
+    }
+
+    fn clear(&self, mask: u32) {
+        self.gpio_registers.outclr.set(mask);
+    }
+
+    fn toggle(&self, mask: u32) {
+        self.gpio_registers
+            .out
+            .set(self.gpio_registers.out.get() ^ mask);
+    }
+
+    fn write(&self, mask: u32, value: u32) {
+        let out = self.gpio_registers.out.get();
+        self.gpio_registers.out.set((out & !mask) | (value & mask));
+    }
+}
+
 pub struct Port<'a, const N: usize> {
     pub pins: [GPIOPin<'a>; N],
 }
@@ -231,6 +231,106 @@ impl Timer {
         self.client.set(client);
     }
 
+    /// Configure the timer to count at 1 MHz and stop itself `us`
+    /// microseconds after it is started, generating `EVENTS_COMPARE[0]`.
+    /// This is meant to be started and cleared by other peripherals through
+    /// PPI.
+    pub fn configure_one_shot_us(&self, us: u32) {
+        self.stop();
+        self.registers.mode.set(0);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        // 16 MHz / 2^4 = 1 MHz
+        self.registers.prescaler.set(4);
+        self.registers.cc[0].write(CC::CC.val(us));
+        self.registers
+            .shorts
+            .write(Shorts::COMPARE0_STOP::EnableShortcut + Shorts::COMPARE0_CLEAR::EnableShortcut);
+        self.registers.events_compare[0].write(Event::READY::CLEAR);
+    }
+
+    /// Configure the timer to count at 1 MHz and generate
+    /// `EVENTS_COMPARE[0]` `width_us` and `EVENTS_COMPARE[1]` `period_us`
+    /// microseconds after it is started. With `repeat` the timer restarts at
+    /// `period_us`, otherwise it stops at `width_us`. This is meant to drive
+    /// other peripherals through PPI.
+    pub fn configure_pulses_us(&self, width_us: u32, period_us: u32, repeat: bool) {
+        self.stop();
+        self.registers.mode.set(0);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        // 16 MHz / 2^4 = 1 MHz
+        self.registers.prescaler.set(4);
+        self.registers.cc[0].write(CC::CC.val(width_us));
+        self.registers.cc[1].write(CC::CC.val(period_us));
+        self.registers.shorts.write(if repeat {
+            Shorts::COMPARE1_CLEAR::EnableShortcut
+        } else {
+            Shorts::COMPARE0_STOP::EnableShortcut + Shorts::COMPARE0_CLEAR::EnableShortcut
+        });
+        self.registers.events_compare[0].write(Event::READY::CLEAR);
+        self.registers.events_compare[1].write(Event::READY::CLEAR);
+    }
+
+    /// Configure the timer as a 32 bit counter, incremented by `TASKS_COUNT`.
+    /// This is meant to count events of other peripherals through PPI.
+    pub fn configure_counter(&self) {
+        self.stop();
+        // Counter mode
+        self.registers.mode.set(1);
+        self.registers.bitmode.write(Bitmode::BITMODE::Bit32);
+        self.registers.shorts.set(0);
+    }
+
+    /// Capture the counter into CC[`index`] and return it.
+    pub fn capture(&self, index: usize) -> u32 {
+        self.registers.tasks_capture[index].write(Task::ENABLE::SET);
+        self.registers.cc[index].read(CC::CC)
+    }
+
+    pub fn start(&self) {
+        self.registers.tasks_start.write(Task::ENABLE::SET);
+    }
+
+    /// Enable the interrupt for the next `EVENTS_COMPARE[0]`.
+    pub fn enable_compare0_interrupt(&self) {
+        self.registers.intenset.write(Inte::COMPARE0::SET);
+    }
+
+    /// Disable the interrupt for `EVENTS_COMPARE[0]`.
+    pub fn disable_compare0_interrupt(&self) {
+        self.registers.intenclr.write(Inte::COMPARE0::SET);
+    }
+
+    /// Stop the timer and reset its counter.
+    pub fn stop(&self) {
+        self.registers.tasks_stop.write(Task::ENABLE::SET);
+        self.registers.tasks_clear.write(Task::ENABLE::SET);
+    }
+
+    /// Address of `TASKS_START`, for connecting it through PPI.
+    pub fn task_start_address(&self) -> u32 {
+        &self.registers.tasks_start as *const _ as u32
+    }
+
+    /// Address of `TASKS_CLEAR`, for connecting it through PPI.
+    pub fn task_clear_address(&self) -> u32 {
+        &self.registers.tasks_clear as *const _ as u32
+    }
+
+    /// Address of `TASKS_COUNT`, for connecting it through PPI.
+    pub fn task_count_address(&self) -> u32 {
+        &self.registers.tasks_count as *const _ as u32
+    }
+
+    /// Address of `EVENTS_COMPARE[0]`, for connecting it through PPI.
+    pub fn event_compare0_address(&self) -> u32 {
+        &self.registers.events_compare[0] as *const _ as u32
+    }
+
+    /// Address of `EVENTS_COMPARE[1]`, for connecting it through PPI.
+    pub fn event_compare1_address(&self) -> u32 {
+        &self.registers.events_compare[1] as *const _ as u32
+    }
+
     /// When an interrupt occurs, check if any of the 4 compares have
     /// created an event, and if so, add it to the bitmask of triggered
     /// events that is passed to the client.
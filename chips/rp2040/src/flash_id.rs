@@ -0,0 +1,146 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Unique ID of the external QSPI flash.
+//!
+//! The RP2040 does not have a unique ID of its own. Like the Pico SDK, we use
+//! the 64-bit unique ID of the external flash chip instead, read with the
+//! `0x4B` flash command.
+//!
+//! Sending a command to the flash requires taking it out of XIP mode, during
+//! which no code can execute from flash. The code that talks to the flash
+//! therefore runs from RAM (`.ramfunc`) with interrupts disabled and only
+//! calls boot ROM functions. Afterwards XIP is restored by running a copy of
+//! the second stage bootloader, exactly as the Pico SDK does.
+//!
+//! Because of this, the ID is read once, during board setup, and cached.
+
+use core::ptr::{read_volatile, write_volatile};
+
+use cortexm0p::support;
+use kernel::hil::device_id::{self, DeviceId};
+
+/// Location of the ROM function table and lookup function
+/// (section 2.8.3 of the RP2040 datasheet).
+const ROM_FUNC_TABLE: *const u16 = 0x0000_0014 as *const u16;
+const ROM_TABLE_LOOKUP: *const u16 = 0x0000_0018 as *const u16;
+
+/// Start of the flash, where the second stage bootloader lives.
+const XIP_BASE: *const u32 = 0x1000_0000 as *const u32;
+const BOOT2_SIZE_WORDS: usize = 64;
+
+const SSI_SR: *const u32 = 0x1800_0028 as *const u32;
+const SSI_DR0: *mut u32 = 0x1800_0060 as *mut u32;
+const SSI_SR_TFNF: u32 = 1 << 1;
+const SSI_SR_RFNE: u32 = 1 << 3;
+
+/// `GPIO_QSPI_SS_CTRL`, used to drive the flash chip-select manually.
+const IO_QSPI_SS_CTRL: *mut u32 = 0x4001_800c as *mut u32;
+const SS_OUTOVER_MASK: u32 = 0b11 << 8;
+const SS_OUTOVER_LOW: u32 = 0b10 << 8;
+const SS_OUTOVER_HIGH: u32 = 0b11 << 8;
+
+const FLASH_RUID_CMD: u32 = 0x4B;
+const FLASH_RUID_DUMMY_BYTES: usize = 4;
+const FLASH_RUID_DATA_BYTES: usize = 8;
+const FLASH_RUID_TOTAL_BYTES: usize = 1 + FLASH_RUID_DUMMY_BYTES + FLASH_RUID_DATA_BYTES;
+
+type RomFn = unsafe extern "C" fn();
+type RomTableLookupFn = unsafe extern "C" fn(table: *const u16, code: u32) -> usize;
+
+struct RomFunctions {
+    connect_internal_flash: RomFn,
+    flash_exit_xip: RomFn,
+    flash_flush_cache: RomFn,
+}
+
+/// Copy of the second stage bootloader, used to re-enable fast XIP.
+static mut BOOT2_COPY: [u32; BOOT2_SIZE_WORDS] = [0; BOOT2_SIZE_WORDS];
+
+unsafe fn rom_func_lookup(code: &[u8; 2]) -> RomFn {
+    let lookup: RomTableLookupFn = core::mem::transmute(read_volatile(ROM_TABLE_LOOKUP) as usize);
+    let table = read_volatile(ROM_FUNC_TABLE) as usize as *const u16;
+    core::mem::transmute(lookup(table, u16::from_le_bytes(*code) as u32))
+}
+
+/// Talk to the flash while XIP is disabled.
+///
+/// This must not call any function located in flash, including panics and
+/// non-inlined core functions.
+#[inline(never)]
+#[cfg_attr(
+    all(target_arch = "arm", target_os = "none"),
+    link_section = ".ramfunc"
+)]
+unsafe extern "C" fn read_unique_id_from_ram(rom: &RomFunctions, boot2: RomFn, id: *mut u8) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+
+    write_volatile(
+        IO_QSPI_SS_CTRL,
+        (read_volatile(IO_QSPI_SS_CTRL) & !SS_OUTOVER_MASK) | SS_OUTOVER_LOW,
+    );
+
+    let mut tx = 0;
+    let mut rx = 0;
+    while rx < FLASH_RUID_TOTAL_BYTES {
+        let status = read_volatile(SSI_SR);
+        if status & SSI_SR_TFNF != 0 && tx < FLASH_RUID_TOTAL_BYTES {
+            write_volatile(SSI_DR0, if tx == 0 { FLASH_RUID_CMD } else { 0 });
+            tx += 1;
+        }
+        if status & SSI_SR_RFNE != 0 {
+            let byte = read_volatile(SSI_DR0) as u8;
+            if rx > FLASH_RUID_DUMMY_BYTES {
+                *id.add(rx - FLASH_RUID_DUMMY_BYTES - 1) = byte;
+            }
+            rx += 1;
+        }
+    }
+
+    write_volatile(
+        IO_QSPI_SS_CTRL,
+        (read_volatile(IO_QSPI_SS_CTRL) & !SS_OUTOVER_MASK) | SS_OUTOVER_HIGH,
+    );
+
+    (rom.flash_flush_cache)();
+    boot2();
+}
+
+pub struct FlashUniqueId {
+    id: [u8; FLASH_RUID_DATA_BYTES],
+}
+
+impl FlashUniqueId {
+    /// Read the unique ID of the flash chip.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called during board setup, while the second core is not
+    /// running and nothing else is using the flash.
+    pub unsafe fn read() -> FlashUniqueId {
+        let rom = RomFunctions {
+            connect_internal_flash: rom_func_lookup(b"IF"),
+            flash_exit_xip: rom_func_lookup(b"EX"),
+            flash_flush_cache: rom_func_lookup(b"FC"),
+        };
+
+        for (i, word) in BOOT2_COPY.iter_mut().enumerate() {
+            *word = read_volatile(XIP_BASE.add(i));
+        }
+        // Thumb function, so set the lowest bit of the address.
+        let boot2: RomFn = core::mem::transmute(BOOT2_COPY.as_ptr() as usize | 1);
+
+        let mut id = [0; FLASH_RUID_DATA_BYTES];
+        support::atomic(|| read_unique_id_from_ram(&rom, boot2, id.as_mut_ptr()));
+        FlashUniqueId { id }
+    }
+}
+
+impl DeviceId for FlashUniqueId {
+    fn unique_id(&self, buf: &mut [u8; device_id::MAX_ID_LEN]) -> usize {
+        buf[..self.id.len()].copy_from_slice(&self.id);
+        self.id.len()
+    }
+}
@@ -661,6 +661,52 @@ impl hil::gpio::Input for RPGpioPin<'_> {
     }
 }
 
+impl hil::gpio::Port for RPPins<'_> {
+    fn width(&self) -> usize {
+        self.pins.len()
+    }
+
+    fn make_output(&self, mask: u32) {
+        for (i, pin) in self.pins.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                hil::gpio::Configure::make_output(pin);
+            }
+        }
+    }
+
+    fn make_input(&self, mask: u32) {
+        for (i, pin) in self.pins.iter().enumerate() {
+            if mask & (1 << i) != 0 {
+                hil::gpio::Configure::make_input(pin);
+            }
+        }
+    }
+
+    fn read(&self) -> u32 {
+        SIO_BASE.gpio_in.read(GPIO_IN::IN)
+    }
+
+    fn set(&self, mask: u32) {
+        SIO_BASE.gpio_out_set.set(mask & SIO_BASE.gpio_oe.get());
+    }
+
+    fn clear(&self, mask: u32) {
+        SIO_BASE.gpio_out_clr.set(mask & SIO_BASE.gpio_oe.get());
+    }
+
+    fn toggle(&self, mask: u32) {
+        SIO_BASE.gpio_out_xor.set(mask & SIO_BASE.gpio_oe.get());
+    }
+
+    fn write(&self, mask: u32, value: u32) {
+        // Flip exactly the selected outputs that differ from `value`.
+        let mask = mask & SIO_BASE.gpio_oe.get();
+        SIO_BASE
+            .gpio_out_xor
+            .set((SIO_BASE.gpio_out.get() ^ value) & mask);
+    }
+}
+
 pub struct SIO {
     registers: StaticRef<SIORegisters>,
 }
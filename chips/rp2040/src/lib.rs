@@ -7,6 +7,7 @@
 pub mod adc;
 pub mod chip;
 pub mod clocks;
+pub mod flash_id;
 pub mod gpio;
 pub mod i2c;
 pub mod interrupts;
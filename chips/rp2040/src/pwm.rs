@@ -12,6 +12,8 @@
 //! + Configurable top and compare values
 //! + Independent configuration for each channel and for each output/input pin
 //! + Duty cycle from 0% to 100% **inclusive**
+//! + Synchronized channels with phase offsets (PwmPhase)
+//! + Complementary outputs with dead time on pins A and B of a channel (PwmComplementary)
 //!
 //! # Examples
 //!
@@ -668,21 +670,8 @@ impl<'a> Pwm<'a> {
         Ok((top, int as u8, frac))
     }
 
-    // Starts a PWM pin with the given frequency and duty cycle.
-    //
-    // Note: the actual values may vary due to rounding errors.
-    fn start_pwm_pin(
-        &self,
-        channel_number: ChannelNumber,
-        channel_pin: ChannelPin,
-        frequency_hz: usize,
-        duty_cycle: usize,
-    ) -> Result<(), ErrorCode> {
-        let (top, int, frac) = match self.compute_top_int_frac(frequency_hz) {
-            Ok(result) => result,
-            Err(_) => return Result::from(ErrorCode::INVAL),
-        };
-
+    // Helper function to compute the compare value for the given duty cycle
+    fn compute_compare_value(&self, top: u16, duty_cycle: usize) -> Result<u16, ErrorCode> {
         let max_duty_cycle = hil::pwm::Pwm::get_maximum_duty_cycle(self);
         // Return an error if the selected duty cycle is higher than the maximum value
         if duty_cycle > max_duty_cycle {
@@ -690,20 +679,48 @@ impl<'a> Pwm<'a> {
         }
         // If top value is equal to u16::MAX, then it is impossible to
         // have a 100% duty cycle, so an error will be returned.
-        let compare_value = if duty_cycle == max_duty_cycle {
+        if duty_cycle == max_duty_cycle {
             if top == u16::MAX {
-                return Result::from(ErrorCode::INVAL);
+                Err(ErrorCode::INVAL)
             } else {
                 // counter compare value for 100% glitch-free duty cycle
-                top + 1
+                Ok(top + 1)
             }
         } else {
             // Normally, no overflow should occur if duty_cycle is less than or
             // equal to get_maximum_duty_cycle(). It is in user's responsability to
             // ensure the value is valid.
-            ((top as usize + 1) * duty_cycle / max_duty_cycle) as u16
+            Ok(((top as usize + 1) * duty_cycle / max_duty_cycle) as u16)
+        }
+    }
+
+    // Starts a PWM pin with the given frequency and duty cycle.
+    //
+    // Note: the actual values may vary due to rounding errors.
+    fn start_pwm_pin(
+        &self,
+        channel_number: ChannelNumber,
+        channel_pin: ChannelPin,
+        frequency_hz: usize,
+        duty_cycle: usize,
+    ) -> Result<(), ErrorCode> {
+        let (top, int, frac) = match self.compute_top_int_frac(frequency_hz) {
+            Ok(result) => result,
+            Err(_) => return Result::from(ErrorCode::INVAL),
         };
 
+        let compare_value = self.compute_compare_value(top, duty_cycle)?;
+
+        // A channel started with complementary outputs runs in phase-correct
+        // mode with an inverted pin, which `start()` does not use.
+        if self.registers.ch[channel_number as usize]
+            .csr
+            .is_set(CSR::PH_CORRECT)
+        {
+            self.set_ph_correct(channel_number, false);
+            self.set_invert_polarity(channel_number, false, false);
+        }
+
         // Configure the channel accordingly
         self.set_top(channel_number, top);
         self.set_divider_int_frac(channel_number, int, frac);
@@ -727,6 +744,133 @@ impl<'a> Pwm<'a> {
         self.set_enabled(channel_number, false);
         Ok(())
     }
+
+    // Starts the channels of the given outputs at the same time, each with its
+    // counter set so that its period starts after the delay given by its phase.
+    //
+    // Both pins of a channel share its counter, so they must have the same
+    // phase.
+    fn start_phased_outputs(
+        &self,
+        outputs: &[hil::pwm::PhasedOutput<RPGpio>],
+        frequency_hz: usize,
+    ) -> Result<(), ErrorCode> {
+        let (top, int, frac) = match self.compute_top_int_frac(frequency_hz) {
+            Ok(result) => result,
+            Err(_) => return Result::from(ErrorCode::INVAL),
+        };
+        let max_duty_cycle = hil::pwm::Pwm::get_maximum_duty_cycle(self);
+        let period = top as usize + 1;
+
+        // Check all outputs before touching the hardware
+        let mut counters: [Option<u16>; NUMBER_CHANNELS] = [None; NUMBER_CHANNELS];
+        for output in outputs {
+            let (channel_number, _) = self.gpio_to_pwm(*output.pin);
+            self.compute_compare_value(top, output.duty_cycle)?;
+            if output.phase > max_duty_cycle {
+                return Err(ErrorCode::INVAL);
+            }
+            // The counter starts this many counts before wrapping to 0,
+            // where the period of the output starts.
+            let delay = period * output.phase / max_duty_cycle;
+            let counter = ((period - delay) % period) as u16;
+            match counters[channel_number as usize] {
+                Some(other) if other != counter => return Err(ErrorCode::INVAL),
+                _ => counters[channel_number as usize] = Some(counter),
+            }
+        }
+
+        let mask = counters
+            .iter()
+            .enumerate()
+            .filter(|(_, counter)| counter.is_some())
+            .fold(0, |mask, (channel, _)| mask | 1 << channel);
+        let enabled = self.registers.en.read(CH::CH);
+        self.registers.en.modify(CH::CH.val(enabled & !mask));
+
+        for output in outputs {
+            let (channel_number, channel_pin) = self.gpio_to_pwm(*output.pin);
+            let compare_value = self.compute_compare_value(top, output.duty_cycle)?;
+            self.set_ph_correct(channel_number, false);
+            self.set_top(channel_number, top);
+            self.set_divider_int_frac(channel_number, int, frac);
+            if channel_pin == ChannelPin::A {
+                self.set_compare_value_a(channel_number, compare_value);
+            } else {
+                self.set_compare_value_b(channel_number, compare_value);
+            }
+        }
+        for (channel_number, counter) in CHANNEL_NUMBERS.iter().zip(counters) {
+            if let Some(counter) = counter {
+                self.set_counter(*channel_number, counter);
+            }
+        }
+
+        // Writing all enable bits at once starts the counters in sync
+        let enabled = self.registers.en.read(CH::CH);
+        self.registers.en.modify(CH::CH.val(enabled | mask));
+        Ok(())
+    }
+
+    // Starts both pins of a channel as complementary outputs.
+    //
+    // The channel runs in phase-correct mode, so the counter counts up to top
+    // and back down, and both edges of the pulse of the main pin move with the
+    // duty cycle. The complementary pin is inverted and compares against a
+    // value that is larger by the dead time, so it rises the dead time after
+    // the main pin fell, and falls the dead time before the main pin rises.
+    fn start_complementary_pins(
+        &self,
+        pin: RPGpio,
+        complementary_pin: RPGpio,
+        frequency_hz: usize,
+        duty_cycle: usize,
+        dead_time_ns: usize,
+    ) -> Result<(), ErrorCode> {
+        let (channel_number, channel_pin) = self.gpio_to_pwm(pin);
+        let (complementary_channel, complementary_channel_pin) =
+            self.gpio_to_pwm(complementary_pin);
+        if channel_number != complementary_channel || channel_pin == complementary_channel_pin {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // A phase-correct period is twice as long as the one of trailing-edge
+        // modulation with the same top value
+        let (top, int, frac) = match self.compute_top_int_frac(frequency_hz.saturating_mul(2)) {
+            Ok(result) => result,
+            Err(_) => return Result::from(ErrorCode::INVAL),
+        };
+        let compare_value = self.compute_compare_value(top, duty_cycle)?;
+
+        // The counter counts at the system clock divided by int + frac / 16
+        let system_clock_hz = hil::pwm::Pwm::get_maximum_frequency_hz(self) as u64;
+        let divider = (int as u64) << 4 | frac as u64;
+        // Round up, so the dead time is never shorter than requested
+        let denominator = divider * 1_000_000_000;
+        let dead_time =
+            (dead_time_ns as u64 * system_clock_hz * 16 + denominator - 1) / denominator;
+        if dead_time > top as u64 {
+            return Err(ErrorCode::INVAL);
+        }
+        let complementary_value = (compare_value as u64 + dead_time)
+            .min(top as u64 + 1)
+            .min(u16::MAX as u64) as u16;
+
+        self.set_enabled(channel_number, false);
+        self.set_ph_correct(channel_number, true);
+        self.set_top(channel_number, top);
+        self.set_divider_int_frac(channel_number, int, frac);
+        if channel_pin == ChannelPin::A {
+            self.set_invert_polarity(channel_number, false, true);
+            self.set_compare_values_a_and_b(channel_number, compare_value, complementary_value);
+        } else {
+            self.set_invert_polarity(channel_number, true, false);
+            self.set_compare_values_a_and_b(channel_number, complementary_value, compare_value);
+        }
+        self.set_counter(channel_number, 0);
+        self.set_enabled(channel_number, true);
+        Ok(())
+    }
 }
 
 /// Implementation of the Hardware Interface Layer (HIL)
@@ -799,6 +943,49 @@ impl hil::pwm::Pwm for Pwm<'_> {
     }
 }
 
+impl hil::pwm::PwmPhase for Pwm<'_> {
+    /// Start the given PWM pins synchronized, with the given phases
+    ///
+    /// Each PWM channel has its own counter, and the counters of the channels are started
+    /// together. Both pins of a channel share its counter, so they must be given the same
+    /// phase.
+    ///
+    /// **Note**: the pins must be set as PWM pins prior to calling this method.
+    fn start_phased(
+        &self,
+        outputs: &[hil::pwm::PhasedOutput<Self::Pin>],
+        frequency_hz: usize,
+    ) -> Result<(), ErrorCode> {
+        self.start_phased_outputs(outputs, frequency_hz)
+    }
+}
+
+impl hil::pwm::PwmComplementary for Pwm<'_> {
+    /// Start complementary outputs on pins A and B of a PWM channel
+    ///
+    /// The pins must be the two pins of the same channel, in either order. The channel is
+    /// switched to phase-correct modulation, so the highest frequency is half of the one of
+    /// `start()`. Starting a pin of the channel with `start()` switches it back.
+    ///
+    /// **Note**: the pins must be set as PWM pins prior to calling this method.
+    fn start_complementary(
+        &self,
+        pin: &Self::Pin,
+        complementary_pin: &Self::Pin,
+        frequency_hz: usize,
+        duty_cycle: usize,
+        dead_time_ns: usize,
+    ) -> Result<(), ErrorCode> {
+        self.start_complementary_pins(
+            *pin,
+            *complementary_pin,
+            frequency_hz,
+            duty_cycle,
+            dead_time_ns,
+        )
+    }
+}
+
 /// Helper structure to control a PWM pin
 pub struct PwmPin<'a> {
     pwm_struct: &'a Pwm<'a>,
@@ -198,13 +198,14 @@ impl TCR {
     }
 }
 
-fn poly_for_alg(alg: CrcAlgorithm) -> FieldValue<u32, Mode::Register> {
+fn poly_for_alg(alg: CrcAlgorithm) -> Option<FieldValue<u32, Mode::Register>> {
     match alg {
-        CrcAlgorithm::Crc32 => Mode::PTYPE::Ccit8023,
-        CrcAlgorithm::Crc32C => Mode::PTYPE::Castagnoli,
-        CrcAlgorithm::Crc16CCITT => Mode::PTYPE::Ccit16,
+        CrcAlgorithm::Crc32 => Some(Mode::PTYPE::Ccit8023),
+        CrcAlgorithm::Crc32C => Some(Mode::PTYPE::Castagnoli),
+        CrcAlgorithm::Crc16CCITT => Some(Mode::PTYPE::Ccit16),
         // CrcAlg::Sam4L32 => Mode::PTYPE::Ccit8023,
         // CrcAlg::Sam4L32C => Mode::PTYPE::Castagnoli,
+        CrcAlgorithm::Crc16Xmodem | CrcAlgorithm::Crc16Modbus | CrcAlgorithm::Crc8Maxim => None,
     }
 }
 
@@ -215,6 +216,10 @@ fn post_process(result: u32, alg: CrcAlgorithm) -> CrcOutput {
         CrcAlgorithm::Crc16CCITT => CrcOutput::Crc16CCITT(result as u16),
         // CrcAlg::Sam4L32 => result,
         // CrcAlg::Sam4L32C => result,
+        // Not supported by the unit, refused by `set_algorithm`
+        CrcAlgorithm::Crc16Xmodem => CrcOutput::Crc16Xmodem(result as u16),
+        CrcAlgorithm::Crc16Modbus => CrcOutput::Crc16Modbus(result as u16),
+        CrcAlgorithm::Crc8Maxim => CrcOutput::Crc8Maxim(result as u8),
     }
 }
 
@@ -409,6 +414,9 @@ impl<'a> Crc<'a> for Crccu<'a> {
             CrcAlgorithm::Crc32 => true,
             CrcAlgorithm::Crc32C => true,
             CrcAlgorithm::Crc16CCITT => true,
+            CrcAlgorithm::Crc16Xmodem => false,
+            CrcAlgorithm::Crc16Modbus => false,
+            CrcAlgorithm::Crc8Maxim => false,
         }
     }
 
@@ -420,6 +428,10 @@ impl<'a> Crc<'a> for Crccu<'a> {
             return Err(ErrorCode::BUSY);
         }
 
+        if !self.algorithm_supported(algorithm) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+
         self.init();
         // Clear the descriptor contents
         self.descriptor.addr.set(0);
@@ -442,6 +454,10 @@ impl<'a> Crc<'a> for Crccu<'a> {
         } else {
             return Err((ErrorCode::RESERVE, data));
         };
+        let poly = match poly_for_alg(algorithm) {
+            Some(poly) => poly,
+            None => return Err((ErrorCode::NOSUPPORT, data)),
+        };
 
         if TCR(self.descriptor.ctrl.get()).interrupt_enabled() || self.compute_requested.get() {
             // A computation is already in progress
@@ -514,12 +530,9 @@ impl<'a> Crc<'a> for Crccu<'a> {
             .set(&self.descriptor as *const Descriptor as u32);
 
         // Configure the unit to compute a checksum
-        self.registers.mr.write(
-            Mode::DIVIDER.val(0)
-                + poly_for_alg(algorithm)
-                + Mode::COMPARE::CLEAR
-                + Mode::ENABLE::Enabled,
-        );
+        self.registers
+            .mr
+            .write(Mode::DIVIDER.val(0) + poly + Mode::COMPARE::CLEAR + Mode::ENABLE::Enabled);
 
         // Enable DMA channel
         self.registers.dmaen.write(DmaEnable::DMAEN::SET);
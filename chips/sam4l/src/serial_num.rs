@@ -5,6 +5,7 @@
 //! Provides a struct that enables access to the unique 120 bit serial number stored in read-only
 //! flash on the sam4l.
 
+use kernel::hil::device_id::{self, DeviceId};
 use kernel::utilities::StaticRef;
 
 // The sam4l stores a unique 120 bit serial number readable from address 0x0080020C to 0x0080021A
@@ -51,3 +52,11 @@ impl SerialNum {
             .fold(0u64, |sum, (i, &val)| sum + ((val as u64) << i * 8))
     }
 }
+
+impl DeviceId for SerialNum {
+    fn unique_id(&self, buf: &mut [u8; device_id::MAX_ID_LEN]) -> usize {
+        let serial_num = self.get();
+        buf[..serial_num.len()].copy_from_slice(&serial_num);
+        serial_num.len()
+    }
+}
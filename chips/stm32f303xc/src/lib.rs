@@ -24,6 +24,7 @@ pub mod rcc;
 pub mod spi;
 pub mod syscfg;
 pub mod tim2;
+pub mod uid;
 pub mod usart;
 pub mod wdt;
 
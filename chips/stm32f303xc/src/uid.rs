@@ -0,0 +1,43 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! 96-bit unique device ID.
+//!
+//! See the "Unique device ID register" section of the RM0316 reference manual.
+
+use kernel::hil::device_id::{self, DeviceId};
+use kernel::utilities::registers::interfaces::Readable;
+use kernel::utilities::registers::ReadOnly;
+use kernel::utilities::StaticRef;
+
+#[repr(C)]
+struct UidRegisters {
+    /// Bits 0 to 95 of the unique ID, least significant word first
+    uid: [ReadOnly<u32>; 3],
+}
+
+const UID_BASE: StaticRef<UidRegisters> =
+    unsafe { StaticRef::new(0x1FFFF7AC as *const UidRegisters) };
+
+pub struct Uid {
+    registers: StaticRef<UidRegisters>,
+}
+
+impl Uid {
+    pub const fn new() -> Uid {
+        Uid {
+            registers: UID_BASE,
+        }
+    }
+}
+
+impl DeviceId for Uid {
+    /// The 96-bit unique ID, most significant byte first.
+    fn unique_id(&self, buf: &mut [u8; device_id::MAX_ID_LEN]) -> usize {
+        for (i, word) in self.registers.uid.iter().rev().enumerate() {
+            buf[i * 4..(i + 1) * 4].copy_from_slice(&word.get().to_be_bytes());
+        }
+        12
+    }
+}
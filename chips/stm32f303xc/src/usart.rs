@@ -568,12 +568,10 @@ impl hil::uart::Configure for Usart<'_> {
         if params.baud_rate != 115200
             || params.stop_bits != hil::uart::StopBits::One
             || params.parity != hil::uart::Parity::None
-            || params.hw_flow_control != false
             || params.width != hil::uart::Width::Eight
         {
-            panic!(
-                "Currently we only support uart setting of 115200bps 8N1, no hardware flow control"
-            );
+            // Currently we only support uart setting of 115200bps 8N1
+            return Err(ErrorCode::NOSUPPORT);
         }
 
         // Configure the word length - 0: 1 Start bit, 8 Data bits, n Stop bits
@@ -586,6 +584,17 @@ impl hil::uart::Configure for Usart<'_> {
         // Set no parity
         self.registers.cr1.modify(CR1::PCE::CLEAR);
 
+        // RTS/CTS hardware flow control. The CR3 flow control bits can only
+        // be written while the USART is disabled.
+        self.registers.cr1.modify(CR1::UE::CLEAR);
+        if params.hw_flow_control {
+            self.registers.cr3.modify(CR3::RTSE::SET + CR3::CTSE::SET);
+        } else {
+            self.registers
+                .cr3
+                .modify(CR3::RTSE::CLEAR + CR3::CTSE::CLEAR);
+        }
+
         // Set the baud rate. By default OVER8 is 0 (oversampling by 16) and
         // PCLK1 is at 8Mhz. The desired baud rate is 115.2KBps. So according
         // to Table 159 of reference manual, the value for BRR is 69.444 (0x45)
@@ -10,6 +10,7 @@ pub struct Stm32f412gDefaultPeripherals<'a> {
     pub stm32f4: Stm32f4xxDefaultPeripherals<'a>,
     // Once implemented, place Stm32f412g specific peripherals here
     pub trng: stm32f4xx::trng::Trng<'a>,
+    pub quadspi: stm32f4xx::quadspi::Quadspi<'a>,
 }
 
 impl<'a> Stm32f412gDefaultPeripherals<'a> {
@@ -22,6 +23,7 @@ impl<'a> Stm32f412gDefaultPeripherals<'a> {
         Self {
             stm32f4: Stm32f4xxDefaultPeripherals::new(rcc, exti, dma1, dma2),
             trng: stm32f4xx::trng::Trng::new(trng_registers::RNG_BASE, rcc),
+            quadspi: stm32f4xx::quadspi::Quadspi::new(rcc),
         }
     }
     // Necessary for setting up circular dependencies & registering deferred calls
@@ -37,6 +39,10 @@ impl<'a> kernel::platform::chip::InterruptService for Stm32f412gDefaultPeriphera
                 self.trng.handle_interrupt();
                 true
             }
+            stm32f412g_nvic::SQPI => {
+                self.quadspi.handle_interrupt();
+                true
+            }
             _ => self.stm32f4.service_interrupt(interrupt),
         }
     }
@@ -7,7 +7,8 @@
 use cortexm4::{CortexM4, CortexMVariant};
 
 pub use stm32f4xx::{
-    adc, chip, dbg, dma, exti, fsmc, gpio, i2c, nvic, rcc, spi, syscfg, tim2, trng, usart,
+    adc, chip, dbg, dma, exti, fsmc, gpio, i2c, nvic, quadspi, rcc, spi, syscfg, tim2, tim3, trng,
+    usart,
 };
 
 pub mod interrupt_service;
@@ -11,6 +11,7 @@ pub struct Stm32f429ziDefaultPeripherals<'a> {
     // Once implemented, place Stm32f429zi specific peripherals here
     pub trng: stm32f4xx::trng::Trng<'a>,
     pub can1: stm32f4xx::can::Can<'a>,
+    pub usb_otg_fs: stm32f4xx::usb_otg_fs::UsbOtgFs<'a>,
 }
 
 impl<'a> Stm32f429ziDefaultPeripherals<'a> {
@@ -24,6 +25,7 @@ impl<'a> Stm32f429ziDefaultPeripherals<'a> {
             stm32f4: Stm32f4xxDefaultPeripherals::new(rcc, exti, dma1, dma2),
             trng: stm32f4xx::trng::Trng::new(trng_registers::RNG_BASE, rcc),
             can1: stm32f4xx::can::Can::new(rcc, can_registers::CAN1_BASE),
+            usb_otg_fs: stm32f4xx::usb_otg_fs::UsbOtgFs::new(rcc),
         }
     }
     // Necessary for setting up circular dependencies and registering deferred calls
@@ -56,6 +58,10 @@ impl<'a> kernel::platform::chip::InterruptService for Stm32f429ziDefaultPeripher
                 self.can1.handle_error_status_interrupt();
                 true
             }
+            stm32f4xx::nvic::OTG_FS => {
+                self.usb_otg_fs.handle_interrupt();
+                true
+            }
             _ => self.stm32f4.service_interrupt(interrupt),
         }
     }
@@ -7,7 +7,8 @@
 use cortexm4::{CortexM4, CortexMVariant};
 
 pub use stm32f4xx::{
-    adc, can, chip, dbg, dma, exti, gpio, nvic, rcc, spi, syscfg, tim2, trng, usart,
+    adc, can, chip, dbg, dma, exti, gpio, nvic, rcc, spi, syscfg, tim2, tim3, trng, usart,
+    usb_otg_fs,
 };
 
 pub mod can_registers;
@@ -4,9 +4,12 @@
 
 use stm32f4xx::chip::Stm32f4xxDefaultPeripherals;
 
+use crate::stm32f446re_nvic;
+
 pub struct Stm32f446reDefaultPeripherals<'a> {
     pub stm32f4: Stm32f4xxDefaultPeripherals<'a>,
     // Once implemented, place Stm32f446re specific peripherals here
+    pub quadspi: stm32f4xx::quadspi::Quadspi<'a>,
 }
 
 impl<'a> Stm32f446reDefaultPeripherals<'a> {
@@ -18,6 +21,7 @@ impl<'a> Stm32f446reDefaultPeripherals<'a> {
     ) -> Self {
         Self {
             stm32f4: Stm32f4xxDefaultPeripherals::new(rcc, exti, dma1, dma2),
+            quadspi: stm32f4xx::quadspi::Quadspi::new(rcc),
         }
     }
     // Necessary for setting up circular dependencies & registering deferred
@@ -30,6 +34,10 @@ impl<'a> kernel::platform::chip::InterruptService for Stm32f446reDefaultPeripher
     unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
         match interrupt {
             // put Stm32f446re specific interrupts here
+            stm32f446re_nvic::QUADSPI => {
+                self.quadspi.handle_interrupt();
+                true
+            }
             _ => self.stm32f4.service_interrupt(interrupt),
         }
     }
@@ -4,7 +4,9 @@
 
 #![no_std]
 
-pub use stm32f4xx::{adc, chip, dbg, dma, exti, gpio, nvic, rcc, spi, syscfg, tim2, usart};
+pub use stm32f4xx::{
+    adc, chip, dbg, dma, exti, gpio, nvic, quadspi, rcc, spi, syscfg, tim2, tim3, usart,
+};
 
 pub mod interrupt_service;
 pub mod stm32f446re_nvic;
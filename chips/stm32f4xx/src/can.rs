@@ -558,9 +558,17 @@ impl<'a> Can<'a> {
             false => self.registers.can_mcr.modify(CAN_MCR::NART::SET),
         }
 
+        // clear the test mode bits of an earlier operation mode
+        self.registers
+            .can_btr
+            .modify(CAN_BTR::LBKM::CLEAR + CAN_BTR::SILM::CLEAR);
         if let Some(operating_mode_settings) = self.operating_mode.extract() {
             match operating_mode_settings {
                 can::OperationMode::Loopback => self.registers.can_btr.modify(CAN_BTR::LBKM::SET),
+                can::OperationMode::InternalLoopback => self
+                    .registers
+                    .can_btr
+                    .modify(CAN_BTR::LBKM::SET + CAN_BTR::SILM::SET),
                 can::OperationMode::Monitoring => self.registers.can_btr.modify(CAN_BTR::SILM::SET),
                 can::OperationMode::Freeze => return Err(kernel::ErrorCode::INVAL),
                 _ => {}
@@ -24,8 +24,10 @@ pub struct Stm32f4xxDefaultPeripherals<'a> {
     pub dma2_streams: [crate::dma::Stream<'a, dma::Dma2<'a>>; 8],
     pub exti: &'a crate::exti::Exti<'a>,
     pub i2c1: crate::i2c::I2C<'a>,
+    pub sdio: crate::sdio::Sdio<'a>,
     pub spi3: crate::spi::Spi<'a>,
     pub tim2: crate::tim2::Tim2<'a>,
+    pub tim3: crate::tim3::Tim3<'a>,
     pub usart1: crate::usart::Usart<'a, dma::Dma2<'a>>,
     pub usart2: crate::usart::Usart<'a, dma::Dma1<'a>>,
     pub usart3: crate::usart::Usart<'a, dma::Dma1<'a>>,
@@ -46,6 +48,7 @@ impl<'a> Stm32f4xxDefaultPeripherals<'a> {
             dma2_streams: dma::new_dma2_stream(dma2),
             exti,
             i2c1: crate::i2c::I2C::new(rcc),
+            sdio: crate::sdio::Sdio::new(rcc),
             spi3: crate::spi::Spi::new(
                 crate::spi::SPI3_BASE,
                 crate::spi::SpiClock(crate::rcc::PeripheralClock::new(
@@ -56,6 +59,7 @@ impl<'a> Stm32f4xxDefaultPeripherals<'a> {
                 dma::Dma1Peripheral::SPI3_RX,
             ),
             tim2: crate::tim2::Tim2::new(rcc),
+            tim3: crate::tim3::Tim3::new(rcc),
             usart1: crate::usart::Usart::new_usart1(rcc),
             usart2: crate::usart::Usart::new_usart2(rcc),
             usart3: crate::usart::Usart::new_usart3(rcc),
@@ -107,9 +111,15 @@ impl<'a> InterruptService for Stm32f4xxDefaultPeripherals<'a> {
                 self.dma1_streams[dma::Dma1Peripheral::SPI3_TX.get_stream_idx()].handle_interrupt()
             }
 
+            nvic::DMA2_Stream3 => {
+                self.dma2_streams[dma::Dma2Peripheral::SDIO_RX.get_stream_idx()].handle_interrupt()
+            }
             nvic::DMA2_Stream5 => self.dma2_streams
                 [dma::Dma2Peripheral::USART1_RX.get_stream_idx()]
             .handle_interrupt(),
+            nvic::DMA2_Stream6 => {
+                self.dma2_streams[dma::Dma2Peripheral::SDIO_TX.get_stream_idx()].handle_interrupt()
+            }
             nvic::DMA2_Stream7 => self.dma2_streams
                 [dma::Dma2Peripheral::USART1_TX.get_stream_idx()]
             .handle_interrupt(),
@@ -125,6 +135,8 @@ impl<'a> InterruptService for Stm32f4xxDefaultPeripherals<'a> {
 
             nvic::SPI3 => self.spi3.handle_interrupt(),
 
+            nvic::SDIO => self.sdio.handle_interrupt(),
+
             nvic::EXTI0 => self.exti.handle_interrupt(),
             nvic::EXTI1 => self.exti.handle_interrupt(),
             nvic::EXTI2 => self.exti.handle_interrupt(),
@@ -134,6 +146,7 @@ impl<'a> InterruptService for Stm32f4xxDefaultPeripherals<'a> {
             nvic::EXTI15_10 => self.exti.handle_interrupt(),
 
             nvic::TIM2 => self.tim2.handle_interrupt(),
+            nvic::TIM3 => self.tim3.handle_interrupt(),
 
             _ => return false,
         }
@@ -12,6 +12,7 @@ use kernel::utilities::StaticRef;
 
 use crate::nvic;
 use crate::rcc;
+use crate::sdio;
 use crate::spi;
 use crate::usart;
 
@@ -774,6 +775,16 @@ pub enum TransferMode {
     Fifo(FifoSize),
 }
 
+/// Number of beats of a burst transfer. Section 9.3.11
+#[repr(u32)]
+#[derive(Copy, Clone)]
+pub enum Burst {
+    Single = 0b00,
+    Incr4 = 0b01,
+    Incr8 = 0b10,
+    Incr16 = 0b11,
+}
+
 /// This struct refers to a DMA Stream
 ///
 /// What other microcontrollers refer to as "channel", STM32F4XX refers to as "streams".
@@ -839,6 +850,7 @@ impl<'a, DMA: StreamServer<'a>> Stream<'a, DMA> {
         self.set_transfer_mode_for_peripheral();
         // 9
         self.set_data_width_for_peripheral();
+        self.set_flow_control_for_peripheral();
     }
 
     pub fn do_transfer(&self, buf: &'static mut [u8], len: usize) {
@@ -1177,6 +1189,57 @@ impl<'a, DMA: StreamServer<'a>> Stream<'a, DMA> {
         }
     }
 
+    fn set_flow_control_for_peripheral(&self) {
+        self.peripheral.map(|pid| {
+            self.stream_set_flow_control(pid.peripheral_flow_control(), pid.burst());
+        });
+    }
+
+    fn stream_set_flow_control(&self, flow_control: bool, burst: Burst) {
+        match self.streamid {
+            StreamId::Stream0 => self.dma.registers().s0cr.modify(
+                S0CR::PFCTRL.val(flow_control as u32)
+                    + S0CR::MBURST.val(burst as u32)
+                    + S0CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream1 => self.dma.registers().s1cr.modify(
+                S1CR::PFCTRL.val(flow_control as u32)
+                    + S1CR::MBURST.val(burst as u32)
+                    + S1CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream2 => self.dma.registers().s2cr.modify(
+                S2CR::PFCTRL.val(flow_control as u32)
+                    + S2CR::MBURST.val(burst as u32)
+                    + S2CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream3 => self.dma.registers().s3cr.modify(
+                S3CR::PFCTRL.val(flow_control as u32)
+                    + S3CR::MBURST.val(burst as u32)
+                    + S3CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream4 => self.dma.registers().s4cr.modify(
+                S4CR::PFCTRL.val(flow_control as u32)
+                    + S4CR::MBURST.val(burst as u32)
+                    + S4CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream5 => self.dma.registers().s5cr.modify(
+                S5CR::PFCTRL.val(flow_control as u32)
+                    + S5CR::MBURST.val(burst as u32)
+                    + S5CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream6 => self.dma.registers().s6cr.modify(
+                S6CR::PFCTRL.val(flow_control as u32)
+                    + S6CR::MBURST.val(burst as u32)
+                    + S6CR::PBURST.val(burst as u32),
+            ),
+            StreamId::Stream7 => self.dma.registers().s7cr.modify(
+                S7CR::PFCTRL.val(flow_control as u32)
+                    + S7CR::MBURST.val(burst as u32)
+                    + S7CR::PBURST.val(burst as u32),
+            ),
+        }
+    }
+
     fn set_transfer_mode_for_peripheral(&self) {
         self.peripheral.map(|pid| {
             self.stream_set_transfer_mode(pid.transfer_mode());
@@ -1361,6 +1424,18 @@ pub trait StreamPeripheral {
     fn direction(&self) -> Direction;
 
     fn address(&self) -> u32;
+
+    /// Whether the peripheral signals the end of the transfer, instead of
+    /// the stream stopping after the number of data items.
+    fn peripheral_flow_control(&self) -> bool {
+        false
+    }
+
+    /// Burst length of both memory and peripheral accesses. Bursts need
+    /// FIFO mode.
+    fn burst(&self) -> Burst {
+        Burst::Single
+    }
 }
 
 pub trait StreamServer<'a> {
@@ -1581,6 +1656,8 @@ impl<'a> StreamServer<'a> for Dma1<'a> {
 pub enum Dma2Peripheral {
     USART1_TX,
     USART1_RX,
+    SDIO_TX,
+    SDIO_RX,
 }
 
 impl Dma2Peripheral {
@@ -1590,6 +1667,8 @@ impl Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => nvic::DMA2_Stream7,
             Dma2Peripheral::USART1_RX => nvic::DMA2_Stream5, // could also be Stream 2, chosen arbitrarily
+            Dma2Peripheral::SDIO_TX => nvic::DMA2_Stream6,
+            Dma2Peripheral::SDIO_RX => nvic::DMA2_Stream3, // could also be Stream 6
         }
     }
 
@@ -1603,6 +1682,8 @@ impl From<Dma2Peripheral> for StreamId {
         match pid {
             Dma2Peripheral::USART1_TX => StreamId::Stream7,
             Dma2Peripheral::USART1_RX => StreamId::Stream5,
+            Dma2Peripheral::SDIO_TX => StreamId::Stream6,
+            Dma2Peripheral::SDIO_RX => StreamId::Stream3,
         }
     }
 }
@@ -1613,7 +1694,14 @@ impl StreamPeripheral for Dma2Peripheral {
     }
 
     fn data_width(&self) -> (Msize, Psize) {
-        (Msize(Size::Byte), Psize(Size::Byte))
+        match self {
+            // The SDIO FIFO is accessed by words, the FIFO of the stream
+            // packs them into the byte buffer.
+            Dma2Peripheral::SDIO_TX | Dma2Peripheral::SDIO_RX => {
+                (Msize(Size::Byte), Psize(Size::Word))
+            }
+            _ => (Msize(Size::Byte), Psize(Size::Byte)),
+        }
     }
 
     fn channel_id(&self) -> ChannelId {
@@ -1622,6 +1710,10 @@ impl StreamPeripheral for Dma2Peripheral {
             Dma2Peripheral::USART1_TX => ChannelId::Channel4,
             // USART1_RX Stream 5, Channel 4
             Dma2Peripheral::USART1_RX => ChannelId::Channel4,
+            // SDIO Stream 6, Channel 4
+            Dma2Peripheral::SDIO_TX => ChannelId::Channel4,
+            // SDIO Stream 3, Channel 4
+            Dma2Peripheral::SDIO_RX => ChannelId::Channel4,
         }
     }
 
@@ -1629,6 +1721,8 @@ impl StreamPeripheral for Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => Direction::MemoryToPeripheral,
             Dma2Peripheral::USART1_RX => Direction::PeripheralToMemory,
+            Dma2Peripheral::SDIO_TX => Direction::MemoryToPeripheral,
+            Dma2Peripheral::SDIO_RX => Direction::PeripheralToMemory,
         }
     }
 
@@ -1636,6 +1730,21 @@ impl StreamPeripheral for Dma2Peripheral {
         match self {
             Dma2Peripheral::USART1_TX => usart::get_address_dr(usart::USART1_BASE),
             Dma2Peripheral::USART1_RX => usart::get_address_dr(usart::USART1_BASE),
+            Dma2Peripheral::SDIO_TX | Dma2Peripheral::SDIO_RX => sdio::FIFO_ADDRESS,
+        }
+    }
+
+    fn peripheral_flow_control(&self) -> bool {
+        // The SDIO knows how many words a transfer has, and the stream
+        // cannot count that many items.
+        matches!(self, Dma2Peripheral::SDIO_TX | Dma2Peripheral::SDIO_RX)
+    }
+
+    fn burst(&self) -> Burst {
+        match self {
+            // The SDIO only supports bursts of 4 words.
+            Dma2Peripheral::SDIO_TX | Dma2Peripheral::SDIO_RX => Burst::Incr4,
+            _ => Burst::Single,
         }
     }
 }
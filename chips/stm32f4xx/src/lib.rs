@@ -22,12 +22,17 @@ pub mod exti;
 pub mod fsmc;
 pub mod gpio;
 pub mod i2c;
+pub mod quadspi;
 pub mod rcc;
+pub mod sdio;
 pub mod spi;
 pub mod syscfg;
 pub mod tim2;
+pub mod tim3;
 pub mod trng;
+pub mod uid;
 pub mod usart;
+pub mod usb_otg_fs;
 
 use cortexm4::{initialize_ram_jump_to_main, unhandled_interrupt, CortexM4, CortexMVariant};
 
@@ -0,0 +1,693 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! QUADSPI controller for external NOR flash (STM32F412, STM32F446 and
+//! STM32F469).
+//!
+//! Indirect operations move the data through the FIFO of the controller in
+//! interrupts. Reads use the quad output fast read command (0x6B), writes
+//! the page program command (0x02) on one line, one page at a time, and
+//! erases the sector erase command (0x20). After each page program or
+//! erase, the controller polls the status register of the flash until the
+//! write in progress bit clears.
+//!
+//! In memory-mapped mode the flash is mapped at `0x9000_0000` with the same
+//! read command. The controller aborts memory-mapped mode for indirect
+//! operations, so these return `OFF` while it is enabled.
+//!
+//! The board configures the pins (alternate functions 9 and 10) and calls
+//! `configure()`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! // QUADSPI clock is HCLK / (1 + 1)
+//! let qspi = &peripherals.quadspi;
+//! qspi.configure(1, 16 * 1024 * 1024, Some(QuadEnable::StatusRegister2Bit1));
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::hil::qspi::{
+    QspiFlash, QspiFlashClient, QspiMemoryMapped, PROGRAM_PAGE_SIZE, SECTOR_SIZE,
+};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, register_structs, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::rcc;
+
+register_structs! {
+    pub QuadspiRegisters {
+        /// control register
+        (0x00 => cr: ReadWrite<u32, CR::Register>),
+        /// device configuration register
+        (0x04 => dcr: ReadWrite<u32, DCR::Register>),
+        /// status register
+        (0x08 => sr: ReadWrite<u32, SR::Register>),
+        /// flag clear register
+        (0x0C => fcr: ReadWrite<u32, SR::Register>),
+        /// data length register
+        (0x10 => dlr: ReadWrite<u32>),
+        /// communication configuration register
+        (0x14 => ccr: ReadWrite<u32, CCR::Register>),
+        /// address register
+        (0x18 => ar: ReadWrite<u32>),
+        /// alternate bytes register
+        (0x1C => abr: ReadWrite<u32>),
+        /// data register, accessed by byte
+        (0x20 => dr: ReadWrite<u8>),
+        (0x21 => _reserved0),
+        /// polling status mask register
+        (0x24 => psmkr: ReadWrite<u32>),
+        /// polling status match register
+        (0x28 => psmar: ReadWrite<u32>),
+        /// polling interval register
+        (0x2C => pir: ReadWrite<u32>),
+        /// low-power timeout register
+        (0x30 => lptr: ReadWrite<u32>),
+        (0x34 => @END),
+    }
+}
+
+register_bitfields![u32,
+    CR [
+        /// Clock prescaler, the clock is HCLK / (PRESCALER + 1)
+        PRESCALER OFFSET(24) NUMBITS(8) [],
+        /// Polling match mode (0: AND, 1: OR)
+        PMM OFFSET(23) NUMBITS(1) [],
+        /// Automatic poll mode stop
+        APMS OFFSET(22) NUMBITS(1) [],
+        /// TimeOut interrupt enable
+        TOIE OFFSET(20) NUMBITS(1) [],
+        /// Status match interrupt enable
+        SMIE OFFSET(19) NUMBITS(1) [],
+        /// FIFO threshold interrupt enable
+        FTIE OFFSET(18) NUMBITS(1) [],
+        /// Transfer complete interrupt enable
+        TCIE OFFSET(17) NUMBITS(1) [],
+        /// Transfer error interrupt enable
+        TEIE OFFSET(16) NUMBITS(1) [],
+        /// FIFO threshold level, minus one
+        FTHRES OFFSET(8) NUMBITS(5) [],
+        /// Sample shift
+        SSHIFT OFFSET(4) NUMBITS(1) [],
+        /// Timeout counter enable
+        TCEN OFFSET(3) NUMBITS(1) [],
+        /// DMA enable
+        DMAEN OFFSET(2) NUMBITS(1) [],
+        /// Abort request
+        ABORT OFFSET(1) NUMBITS(1) [],
+        /// Enable
+        EN OFFSET(0) NUMBITS(1) []
+    ],
+    DCR [
+        /// Flash memory size, 2^(FSIZE + 1) bytes
+        FSIZE OFFSET(16) NUMBITS(5) [],
+        /// Chip select high time, minus one, in clock cycles
+        CSHT OFFSET(8) NUMBITS(3) [],
+        /// Mode 0 or mode 3
+        CKMODE OFFSET(0) NUMBITS(1) []
+    ],
+    SR [
+        /// FIFO level
+        FLEVEL OFFSET(8) NUMBITS(7) [],
+        /// Busy
+        BUSY OFFSET(5) NUMBITS(1) [],
+        /// Timeout flag
+        TOF OFFSET(4) NUMBITS(1) [],
+        /// Status match flag
+        SMF OFFSET(3) NUMBITS(1) [],
+        /// FIFO threshold flag
+        FTF OFFSET(2) NUMBITS(1) [],
+        /// Transfer complete flag
+        TCF OFFSET(1) NUMBITS(1) [],
+        /// Transfer error flag
+        TEF OFFSET(0) NUMBITS(1) []
+    ],
+    CCR [
+        /// Functional mode
+        FMODE OFFSET(26) NUMBITS(2) [
+            IndirectWrite = 0b00,
+            IndirectRead = 0b01,
+            AutomaticPolling = 0b10,
+            MemoryMapped = 0b11
+        ],
+        /// Data mode
+        DMODE OFFSET(24) NUMBITS(2) [
+            None = 0b00,
+            Single = 0b01,
+            Dual = 0b10,
+            Quad = 0b11
+        ],
+        /// Number of dummy cycles
+        DCYC OFFSET(18) NUMBITS(5) [],
+        /// Address size
+        ADSIZE OFFSET(12) NUMBITS(2) [
+            Bits24 = 0b10,
+            Bits32 = 0b11
+        ],
+        /// Address mode
+        ADMODE OFFSET(10) NUMBITS(2) [
+            None = 0b00,
+            Single = 0b01
+        ],
+        /// Instruction mode
+        IMODE OFFSET(8) NUMBITS(2) [
+            None = 0b00,
+            Single = 0b01
+        ],
+        /// Instruction
+        INSTRUCTION OFFSET(0) NUMBITS(8) []
+    ]
+];
+
+type FieldValue = kernel::utilities::registers::FieldValue<u32, CCR::Register>;
+
+const QUADSPI_BASE: StaticRef<QuadspiRegisters> =
+    unsafe { StaticRef::new(0xA000_1000 as *const QuadspiRegisters) };
+
+/// Start of the region the flash is mapped to.
+const MEMORY_MAPPED_BASE: usize = 0x9000_0000;
+
+/// Size of the FIFO in bytes.
+const FIFO_SIZE: u32 = 32;
+
+// Flash commands
+const WRITE_ENABLE: u32 = 0x06;
+const READ_STATUS: u32 = 0x05;
+const WRITE_STATUS: u32 = 0x01;
+const WRITE_STATUS_2: u32 = 0x31;
+const PAGE_PROGRAM: u32 = 0x02;
+const SECTOR_ERASE: u32 = 0x20;
+const QUAD_OUTPUT_FAST_READ: u32 = 0x6B;
+
+/// Dummy cycles of the quad output fast read.
+const READ_DUMMY_CYCLES: u32 = 8;
+
+/// Write in progress bit of the status register.
+const STATUS_WIP: u32 = 0x01;
+
+/// How the quad mode of the flash is enabled, which quad reads need.
+#[derive(Clone, Copy, PartialEq)]
+pub enum QuadEnable {
+    /// Bit 6 of the status register (Macronix, ISSI).
+    StatusRegisterBit6,
+    /// Bit 1 of status register 2, written with command 0x31 (Winbond,
+    /// GigaDevice).
+    StatusRegister2Bit1,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operation {
+    Write,
+    Erase,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    Read,
+    /// Write enable before a page program or erase.
+    WriteEnable(Operation),
+    /// Page program of `len` bytes.
+    Program {
+        len: usize,
+    },
+    Erase,
+    /// Polling the status register until the flash is done.
+    Poll(Operation),
+    MemoryMapped,
+}
+
+pub struct Quadspi<'a> {
+    registers: StaticRef<QuadspiRegisters>,
+    clock: QuadspiClock<'a>,
+    client: OptionalCell<&'a dyn QspiFlashClient>,
+
+    size: Cell<usize>,
+    state: Cell<State>,
+
+    buffer: TakeCell<'static, [u8]>,
+    /// Flash address of the operation in progress.
+    address: Cell<usize>,
+    len: Cell<usize>,
+    /// Bytes of the buffer that are read or written.
+    position: Cell<usize>,
+    /// Bytes of the buffer that are moved through the FIFO.
+    fifo_position: Cell<usize>,
+}
+
+impl<'a> Quadspi<'a> {
+    pub fn new(rcc: &'a rcc::Rcc) -> Self {
+        Self {
+            registers: QUADSPI_BASE,
+            clock: QuadspiClock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::AHB3(rcc::HCLK3::QSPI),
+                rcc,
+            )),
+            client: OptionalCell::empty(),
+            size: Cell::new(0),
+            state: Cell::new(State::Idle),
+            buffer: TakeCell::empty(),
+            address: Cell::new(0),
+            len: Cell::new(0),
+            position: Cell::new(0),
+            fifo_position: Cell::new(0),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Enable the controller for a flash of `size` bytes, a power of two,
+    /// with a clock of HCLK / (`prescaler` + 1). If `quad_enable` is given,
+    /// the quad mode of the flash is enabled.
+    ///
+    /// This waits for the status register of the flash to be written.
+    pub fn configure(&self, prescaler: u8, size: usize, quad_enable: Option<QuadEnable>) {
+        let regs = &*self.registers;
+        self.enable_clock();
+        regs.dcr.write(
+            DCR::FSIZE.val(size.trailing_zeros().saturating_sub(1))
+                + DCR::CSHT.val(2)
+                + DCR::CKMODE::CLEAR,
+        );
+        regs.cr.write(
+            CR::PRESCALER.val(prescaler as u32) + CR::FTHRES.val(3) + CR::SSHIFT::SET + CR::EN::SET,
+        );
+        self.size.set(size);
+
+        if let Some(quad_enable) = quad_enable {
+            let (command, value) = match quad_enable {
+                QuadEnable::StatusRegisterBit6 => (WRITE_STATUS, 1 << 6),
+                QuadEnable::StatusRegister2Bit1 => (WRITE_STATUS_2, 1 << 1),
+            };
+            self.command_blocking(WRITE_ENABLE, None);
+            self.command_blocking(command, Some(value));
+            // wait for the flash to write the register
+            regs.psmkr.set(STATUS_WIP);
+            regs.psmar.set(0);
+            regs.pir.set(0x10);
+            regs.dlr.set(0);
+            regs.cr.modify(CR::APMS::SET);
+            regs.ccr.write(
+                CCR::FMODE::AutomaticPolling
+                    + CCR::DMODE::Single
+                    + CCR::IMODE::Single
+                    + CCR::INSTRUCTION.val(READ_STATUS),
+            );
+            while !regs.sr.is_set(SR::SMF) {}
+            regs.fcr.write(SR::SMF::SET);
+            while regs.sr.is_set(SR::BUSY) {}
+        }
+
+        regs.cr.modify(CR::TEIE::SET);
+    }
+
+    /// Sends `instruction`, with a data byte if given, and waits for it to
+    /// be sent.
+    fn command_blocking(&self, instruction: u32, data: Option<u8>) {
+        let regs = &*self.registers;
+        while regs.sr.is_set(SR::BUSY) {}
+        match data {
+            Some(byte) => {
+                regs.dlr.set(0);
+                regs.ccr.write(
+                    CCR::FMODE::IndirectWrite
+                        + CCR::DMODE::Single
+                        + CCR::IMODE::Single
+                        + CCR::INSTRUCTION.val(instruction),
+                );
+                regs.dr.set(byte);
+            }
+            None => regs.ccr.write(
+                CCR::FMODE::IndirectWrite + CCR::IMODE::Single + CCR::INSTRUCTION.val(instruction),
+            ),
+        }
+        while !regs.sr.is_set(SR::TCF) {}
+        regs.fcr.write(SR::TCF::SET);
+    }
+
+    /// Disable the controller.
+    pub fn disable(&self) {
+        if self.is_enabled_clock() {
+            self.abort();
+            self.registers.cr.write(CR::EN::CLEAR);
+            self.disable_clock();
+        }
+        self.size.set(0);
+        self.state.set(State::Idle);
+    }
+
+    fn abort(&self) {
+        let regs = &*self.registers;
+        regs.cr.modify(CR::ABORT::SET);
+        while regs.cr.is_set(CR::ABORT) {}
+    }
+
+    fn address_size(&self) -> FieldValue {
+        if self.size.get() > 1 << 24 {
+            CCR::ADSIZE::Bits32
+        } else {
+            CCR::ADSIZE::Bits24
+        }
+    }
+
+    fn read_configuration(&self) -> FieldValue {
+        CCR::DMODE::Quad
+            + CCR::DCYC.val(READ_DUMMY_CYCLES)
+            + self.address_size()
+            + CCR::ADMODE::Single
+            + CCR::IMODE::Single
+            + CCR::INSTRUCTION.val(QUAD_OUTPUT_FAST_READ)
+    }
+
+    fn write_enable(&self, operation: Operation) {
+        self.state.set(State::WriteEnable(operation));
+        self.registers.cr.modify(CR::TCIE::SET);
+        self.registers.ccr.write(
+            CCR::FMODE::IndirectWrite + CCR::IMODE::Single + CCR::INSTRUCTION.val(WRITE_ENABLE),
+        );
+    }
+
+    /// Programs the next part of the buffer, up to the end of the page.
+    fn program(&self) {
+        let regs = &*self.registers;
+        let address = self.address.get() + self.position.get();
+        let len = cmp::min(
+            self.len.get() - self.position.get(),
+            PROGRAM_PAGE_SIZE - address % PROGRAM_PAGE_SIZE,
+        );
+        self.state.set(State::Program { len });
+        self.fifo_position.set(self.position.get());
+        regs.dlr.set(len as u32 - 1);
+        regs.ccr.write(
+            CCR::FMODE::IndirectWrite
+                + CCR::DMODE::Single
+                + self.address_size()
+                + CCR::ADMODE::Single
+                + CCR::IMODE::Single
+                + CCR::INSTRUCTION.val(PAGE_PROGRAM),
+        );
+        regs.ar.set(address as u32);
+        regs.cr.modify(CR::FTIE::SET + CR::TCIE::SET);
+    }
+
+    fn erase(&self) {
+        let regs = &*self.registers;
+        self.state.set(State::Erase);
+        regs.cr.modify(CR::TCIE::SET);
+        regs.ccr.write(
+            CCR::FMODE::IndirectWrite
+                + self.address_size()
+                + CCR::ADMODE::Single
+                + CCR::IMODE::Single
+                + CCR::INSTRUCTION.val(SECTOR_ERASE),
+        );
+        regs.ar.set(self.address.get() as u32);
+    }
+
+    /// Polls the status register until the write in progress bit clears.
+    fn poll(&self, operation: Operation) {
+        let regs = &*self.registers;
+        self.state.set(State::Poll(operation));
+        regs.psmkr.set(STATUS_WIP);
+        regs.psmar.set(0);
+        regs.pir.set(0x10);
+        regs.dlr.set(0);
+        regs.cr.modify(CR::APMS::SET + CR::SMIE::SET);
+        regs.ccr.write(
+            CCR::FMODE::AutomaticPolling
+                + CCR::DMODE::Single
+                + CCR::IMODE::Single
+                + CCR::INSTRUCTION.val(READ_STATUS),
+        );
+    }
+
+    /// Moves data between the FIFO and the buffer.
+    fn service_fifo(&self) {
+        let regs = &*self.registers;
+        match self.state.get() {
+            State::Read => {
+                self.buffer.map(|buffer| {
+                    let mut position = self.position.get();
+                    while position < self.len.get() && regs.sr.read(SR::FLEVEL) > 0 {
+                        buffer[position] = regs.dr.get();
+                        position += 1;
+                    }
+                    self.position.set(position);
+                });
+            }
+            State::Program { len } => {
+                let end = self.position.get() + len;
+                self.buffer.map(|buffer| {
+                    let mut position = self.fifo_position.get();
+                    while position < end && regs.sr.read(SR::FLEVEL) < FIFO_SIZE {
+                        regs.dr.set(buffer[position]);
+                        position += 1;
+                    }
+                    self.fifo_position.set(position);
+                    if position == end {
+                        regs.cr.modify(CR::FTIE::CLEAR);
+                    }
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        let regs = &*self.registers;
+        regs.cr
+            .modify(CR::FTIE::CLEAR + CR::TCIE::CLEAR + CR::SMIE::CLEAR);
+        let state = self.state.replace(State::Idle);
+        let len = self.len.get();
+        match state {
+            State::Read => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.read_done(buffer, len, result));
+                });
+            }
+            State::WriteEnable(Operation::Write)
+            | State::Program { .. }
+            | State::Poll(Operation::Write) => {
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.write_done(buffer, len, result));
+                });
+            }
+            State::WriteEnable(Operation::Erase) | State::Erase | State::Poll(Operation::Erase) => {
+                self.client.map(|client| client.erase_done(result));
+            }
+            State::Idle | State::MemoryMapped => {}
+        }
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = &*self.registers;
+        let status = regs.sr.extract();
+
+        if status.is_set(SR::TEF) {
+            regs.fcr.write(SR::TEF::SET);
+            self.abort();
+            self.finish(Err(ErrorCode::FAIL));
+            return;
+        }
+
+        match self.state.get() {
+            State::Read => {
+                self.service_fifo();
+                if status.is_set(SR::TCF) {
+                    regs.fcr.write(SR::TCF::SET);
+                    // the FIFO may still hold the last bytes
+                    self.service_fifo();
+                    self.finish(Ok(()));
+                }
+            }
+            State::WriteEnable(operation) => {
+                if status.is_set(SR::TCF) {
+                    regs.fcr.write(SR::TCF::SET);
+                    match operation {
+                        Operation::Write => self.program(),
+                        Operation::Erase => self.erase(),
+                    }
+                }
+            }
+            State::Program { len } => {
+                self.service_fifo();
+                if status.is_set(SR::TCF) {
+                    regs.fcr.write(SR::TCF::SET);
+                    self.position.set(self.position.get() + len);
+                    self.poll(Operation::Write);
+                }
+            }
+            State::Erase => {
+                if status.is_set(SR::TCF) {
+                    regs.fcr.write(SR::TCF::SET);
+                    self.poll(Operation::Erase);
+                }
+            }
+            State::Poll(operation) => {
+                if status.is_set(SR::SMF) {
+                    regs.fcr.write(SR::SMF::SET);
+                    regs.cr.modify(CR::SMIE::CLEAR);
+                    if operation == Operation::Write && self.position.get() < self.len.get() {
+                        self.write_enable(Operation::Write);
+                    } else {
+                        self.finish(Ok(()));
+                    }
+                }
+            }
+            State::Idle | State::MemoryMapped => {
+                regs.fcr.write(SR::TCF::SET + SR::SMF::SET + SR::TOF::SET);
+            }
+        }
+    }
+
+    /// Checks that an operation on `len` bytes at `address` can be started.
+    fn check(&self, address: usize, buffer_len: usize, len: usize) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            _ if self.size.get() == 0 => Err(ErrorCode::OFF),
+            State::MemoryMapped => Err(ErrorCode::OFF),
+            State::Idle => {
+                if len == 0 || len > buffer_len || address + len > self.size.get() {
+                    Err(ErrorCode::INVAL)
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+}
+
+impl<'a> QspiFlash<'a> for Quadspi<'a> {
+    fn set_client(&self, client: &'a dyn QspiFlashClient) {
+        self.client.set(client);
+    }
+
+    fn size(&self) -> usize {
+        self.size.get()
+    }
+
+    fn read(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check(address, buffer.len(), len) {
+            return Err((e, buffer));
+        }
+        let regs = &*self.registers;
+        self.buffer.replace(buffer);
+        self.address.set(address);
+        self.len.set(len);
+        self.position.set(0);
+        self.state.set(State::Read);
+
+        regs.dlr.set(len as u32 - 1);
+        regs.ccr
+            .write(CCR::FMODE::IndirectRead + self.read_configuration());
+        regs.cr.modify(CR::FTIE::SET + CR::TCIE::SET);
+        regs.ar.set(address as u32);
+        Ok(())
+    }
+
+    fn write(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if let Err(e) = self.check(address, buffer.len(), len) {
+            return Err((e, buffer));
+        }
+        self.buffer.replace(buffer);
+        self.address.set(address);
+        self.len.set(len);
+        self.position.set(0);
+        self.write_enable(Operation::Write);
+        Ok(())
+    }
+
+    fn erase_sector(&self, address: usize) -> Result<(), ErrorCode> {
+        self.check(address, SECTOR_SIZE, SECTOR_SIZE)?;
+        if address % SECTOR_SIZE != 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.address.set(address);
+        self.write_enable(Operation::Erase);
+        Ok(())
+    }
+}
+
+impl QspiMemoryMapped for Quadspi<'_> {
+    fn enable_memory_mapped(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            _ if self.size.get() == 0 => Err(ErrorCode::OFF),
+            State::MemoryMapped => Ok(()),
+            State::Idle => {
+                self.state.set(State::MemoryMapped);
+                self.registers
+                    .ccr
+                    .write(CCR::FMODE::MemoryMapped + self.read_configuration());
+                Ok(())
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn disable_memory_mapped(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == State::MemoryMapped {
+            self.abort();
+            self.state.set(State::Idle);
+        }
+        Ok(())
+    }
+
+    fn memory_mapped(&self) -> Option<&'static [u8]> {
+        if self.state.get() == State::MemoryMapped {
+            // Safety: the region maps the whole flash, and is only read while
+            // no indirect operation can change the flash.
+            Some(unsafe {
+                core::slice::from_raw_parts(MEMORY_MAPPED_BASE as *const u8, self.size.get())
+            })
+        } else {
+            None
+        }
+    }
+}
+
+struct QuadspiClock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for QuadspiClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
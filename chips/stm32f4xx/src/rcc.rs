@@ -743,6 +743,19 @@ impl Rcc {
         self.registers.cr.modify(CR::PLLON::SET);
     }
 
+    fn configure_otgfs_clock(&self) {
+        // The PLL can only be configured while it is off. Its reset
+        // configuration gives 48 MHz on the Q output.
+        if !self.registers.cr.is_set(CR::PLLON) {
+            self.registers.pllcfgr.modify(PLLCFGR::PLLQ.val(4));
+            self.registers.cr.modify(CR::PLLON::SET);
+        }
+    }
+
+    fn is_ready_pll(&self) -> bool {
+        self.registers.cr.is_set(CR::PLLRDY)
+    }
+
     // I2C1 clock
 
     fn is_enabled_i2c1_clock(&self) -> bool {
@@ -787,6 +800,20 @@ impl Rcc {
         self.registers.apb1enr.modify(APB1ENR::TIM2EN::CLEAR)
     }
 
+    // TIM3 clock
+
+    fn is_enabled_tim3_clock(&self) -> bool {
+        self.registers.apb1enr.is_set(APB1ENR::TIM3EN)
+    }
+
+    fn enable_tim3_clock(&self) {
+        self.registers.apb1enr.modify(APB1ENR::TIM3EN::SET)
+    }
+
+    fn disable_tim3_clock(&self) {
+        self.registers.apb1enr.modify(APB1ENR::TIM3EN::CLEAR)
+    }
+
     // SYSCFG clock
 
     fn is_enabled_syscfg_clock(&self) -> bool {
@@ -801,6 +828,22 @@ impl Rcc {
         self.registers.apb2enr.modify(APB2ENR::SYSCFGEN::CLEAR)
     }
 
+    // SDIO clock
+
+    fn is_enabled_sdio_clock(&self) -> bool {
+        self.registers.apb2enr.is_set(APB2ENR::SDIOEN)
+    }
+
+    fn enable_sdio_clock(&self) {
+        self.registers.apb2enr.modify(APB2ENR::SDIOEN::SET);
+        self.registers.apb2rstr.modify(APB2RSTR::SDIORST::SET);
+        self.registers.apb2rstr.modify(APB2RSTR::SDIORST::CLEAR);
+    }
+
+    fn disable_sdio_clock(&self) {
+        self.registers.apb2enr.modify(APB2ENR::SDIOEN::CLEAR)
+    }
+
     // DMA1 clock
 
     fn is_enabled_dma1_clock(&self) -> bool {
@@ -954,6 +997,22 @@ impl Rcc {
         self.registers.ahb3enr.modify(AHB3ENR::FMCEN::CLEAR)
     }
 
+    // QUADSPI
+
+    fn is_enabled_qspi_clock(&self) -> bool {
+        self.registers.ahb3enr.is_set(AHB3ENR::QSPIEN)
+    }
+
+    fn enable_qspi_clock(&self) {
+        self.registers.ahb3enr.modify(AHB3ENR::QSPIEN::SET);
+        self.registers.ahb3rstr.modify(AHB3RSTR::QSPIRST::SET);
+        self.registers.ahb3rstr.modify(AHB3RSTR::QSPIRST::CLEAR);
+    }
+
+    fn disable_qspi_clock(&self) {
+        self.registers.ahb3enr.modify(AHB3ENR::QSPIEN::CLEAR)
+    }
+
     // USART1 clock
     fn is_enabled_usart1_clock(&self) -> bool {
         self.registers.apb2enr.is_set(APB2ENR::USART1EN)
@@ -1093,6 +1152,7 @@ pub enum HCLK1 {
 /// Peripherals clocked by HCLK3
 pub enum HCLK3 {
     FMC,
+    QSPI,
 }
 
 /// Peripherals clocked by HCLK2
@@ -1104,6 +1164,7 @@ pub enum HCLK2 {
 /// Peripherals clocked by PCLK1
 pub enum PCLK1 {
     TIM2,
+    TIM3,
     USART2,
     USART3,
     SPI3,
@@ -1116,6 +1177,7 @@ pub enum PCLK2 {
     USART1,
     ADC1,
     SYSCFG,
+    SDIO,
 }
 
 impl<'a> PeripheralClock<'a> {
@@ -1126,6 +1188,14 @@ impl<'a> PeripheralClock<'a> {
     pub fn configure_rng_clock(&self) {
         self.rcc.configure_rng_clock();
     }
+
+    pub fn configure_otgfs_clock(&self) {
+        self.rcc.configure_otgfs_clock();
+    }
+
+    pub fn is_ready_pll(&self) -> bool {
+        self.rcc.is_ready_pll()
+    }
 }
 
 impl<'a> ClockInterface for PeripheralClock<'a> {
@@ -1149,9 +1219,11 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
             },
             PeripheralClockType::AHB3(ref v) => match v {
                 HCLK3::FMC => self.rcc.is_enabled_fmc_clock(),
+                HCLK3::QSPI => self.rcc.is_enabled_qspi_clock(),
             },
             PeripheralClockType::APB1(ref v) => match v {
                 PCLK1::TIM2 => self.rcc.is_enabled_tim2_clock(),
+                PCLK1::TIM3 => self.rcc.is_enabled_tim3_clock(),
                 PCLK1::USART2 => self.rcc.is_enabled_usart2_clock(),
                 PCLK1::USART3 => self.rcc.is_enabled_usart3_clock(),
                 PCLK1::I2C1 => self.rcc.is_enabled_i2c1_clock(),
@@ -1162,6 +1234,7 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::USART1 => self.rcc.is_enabled_usart1_clock(),
                 PCLK2::ADC1 => self.rcc.is_enabled_adc1_clock(),
                 PCLK2::SYSCFG => self.rcc.is_enabled_syscfg_clock(),
+                PCLK2::SDIO => self.rcc.is_enabled_sdio_clock(),
             },
         }
     }
@@ -1210,11 +1283,15 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
             },
             PeripheralClockType::AHB3(ref v) => match v {
                 HCLK3::FMC => self.rcc.enable_fmc_clock(),
+                HCLK3::QSPI => self.rcc.enable_qspi_clock(),
             },
             PeripheralClockType::APB1(ref v) => match v {
                 PCLK1::TIM2 => {
                     self.rcc.enable_tim2_clock();
                 }
+                PCLK1::TIM3 => {
+                    self.rcc.enable_tim3_clock();
+                }
                 PCLK1::USART2 => {
                     self.rcc.enable_usart2_clock();
                 }
@@ -1241,6 +1318,9 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::SYSCFG => {
                     self.rcc.enable_syscfg_clock();
                 }
+                PCLK2::SDIO => {
+                    self.rcc.enable_sdio_clock();
+                }
             },
         }
     }
@@ -1289,11 +1369,15 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
             },
             PeripheralClockType::AHB3(ref v) => match v {
                 HCLK3::FMC => self.rcc.disable_fmc_clock(),
+                HCLK3::QSPI => self.rcc.disable_qspi_clock(),
             },
             PeripheralClockType::APB1(ref v) => match v {
                 PCLK1::TIM2 => {
                     self.rcc.disable_tim2_clock();
                 }
+                PCLK1::TIM3 => {
+                    self.rcc.disable_tim3_clock();
+                }
                 PCLK1::USART2 => {
                     self.rcc.disable_usart2_clock();
                 }
@@ -1320,6 +1404,9 @@ impl<'a> ClockInterface for PeripheralClock<'a> {
                 PCLK2::SYSCFG => {
                     self.rcc.disable_syscfg_clock();
                 }
+                PCLK2::SDIO => {
+                    self.rcc.disable_sdio_clock();
+                }
             },
         }
     }
@@ -0,0 +1,578 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! SDIO host controller, used for SD cards and eMMC in native 1 or 4-bit
+//! mode.
+//!
+//! Data is moved between the FIFO and the buffers by DMA2 (streams 3 and 6).
+//! SDIOCLK is the 48 MHz output of the main PLL, which the board has to
+//! configure. If it configures a different frequency, it must call
+//! `set_input_clock()`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! use stm32f429zi::dma::Dma2Peripheral;
+//! use stm32f429zi::sdio;
+//!
+//! let sdio_tx_stream = &base_peripherals.dma2_streams[Dma2Peripheral::SDIO_TX.get_stream_idx()];
+//! let sdio_rx_stream = &base_peripherals.dma2_streams[Dma2Peripheral::SDIO_RX.get_stream_idx()];
+//! base_peripherals.sdio.set_dma(sdio::TxDMA(sdio_tx_stream), sdio::RxDMA(sdio_rx_stream));
+//! sdio_tx_stream.set_client(&base_peripherals.sdio);
+//! sdio_rx_stream.set_client(&base_peripherals.sdio);
+//! sdio_tx_stream.setup(Dma2Peripheral::SDIO_TX);
+//! sdio_rx_stream.setup(Dma2Peripheral::SDIO_RX);
+//! cortexm4::nvic::Nvic::new(Dma2Peripheral::SDIO_TX.get_stream_irqn()).enable();
+//! cortexm4::nvic::Nvic::new(Dma2Peripheral::SDIO_RX.get_stream_irqn()).enable();
+//! cortexm4::nvic::Nvic::new(stm32f429zi::nvic::SDIO).enable();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::sdmmc::{self, BusWidth, Direction, Response, ResponseType};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, LocalRegisterCopy, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::dma::{self, Dma2, Dma2Peripheral};
+use crate::rcc;
+
+register_structs! {
+    pub SdioRegisters {
+        /// power control register
+        (0x00 => power: ReadWrite<u32, POWER::Register>),
+        /// clock control register
+        (0x04 => clkcr: ReadWrite<u32, CLKCR::Register>),
+        /// argument register
+        (0x08 => arg: ReadWrite<u32>),
+        /// command register
+        (0x0C => cmd: ReadWrite<u32, CMD::Register>),
+        /// command response register
+        (0x10 => respcmd: ReadOnly<u32>),
+        /// response 1..4 registers
+        (0x14 => resp: [ReadOnly<u32>; 4]),
+        /// data timer register
+        (0x24 => dtimer: ReadWrite<u32>),
+        /// data length register
+        (0x28 => dlen: ReadWrite<u32>),
+        /// data control register
+        (0x2C => dctrl: ReadWrite<u32, DCTRL::Register>),
+        /// data counter register
+        (0x30 => dcount: ReadOnly<u32>),
+        /// status register
+        (0x34 => sta: ReadOnly<u32, STA::Register>),
+        /// interrupt clear register
+        (0x38 => icr: WriteOnly<u32, STA::Register>),
+        /// mask register
+        (0x3C => mask: ReadWrite<u32, STA::Register>),
+        (0x40 => _reserved0),
+        /// FIFO counter register
+        (0x48 => fifocnt: ReadOnly<u32>),
+        (0x4C => _reserved1),
+        /// data FIFO register
+        (0x80 => fifo: ReadWrite<u32>),
+        (0x84 => @END),
+    }
+}
+
+register_bitfields![u32,
+    POWER [
+        /// Power supply control bits
+        PWRCTRL OFFSET(0) NUMBITS(2) [
+            Off = 0b00,
+            On = 0b11
+        ]
+    ],
+    CLKCR [
+        /// HW flow control enable
+        HWFC_EN OFFSET(14) NUMBITS(1) [],
+        /// SDIO_CK dephasing selection bit
+        NEGEDGE OFFSET(13) NUMBITS(1) [],
+        /// Wide bus mode enable bit
+        WIDBUS OFFSET(11) NUMBITS(2) [
+            One = 0b00,
+            Four = 0b01,
+            Eight = 0b10
+        ],
+        /// Clock divider bypass enable bit
+        BYPASS OFFSET(10) NUMBITS(1) [],
+        /// Power saving configuration bit
+        PWRSAV OFFSET(9) NUMBITS(1) [],
+        /// Clock enable bit
+        CLKEN OFFSET(8) NUMBITS(1) [],
+        /// Clock divide factor
+        CLKDIV OFFSET(0) NUMBITS(8) []
+    ],
+    CMD [
+        /// CPSM enable bit
+        CPSMEN OFFSET(10) NUMBITS(1) [],
+        /// CPSM waits for ends of data transfer
+        WAITPEND OFFSET(9) NUMBITS(1) [],
+        /// CPSM waits for interrupt request
+        WAITINT OFFSET(8) NUMBITS(1) [],
+        /// Wait for response bits
+        WAITRESP OFFSET(6) NUMBITS(2) [
+            None = 0b00,
+            Short = 0b01,
+            Long = 0b11
+        ],
+        /// Command index
+        CMDINDEX OFFSET(0) NUMBITS(6) []
+    ],
+    DCTRL [
+        /// Data block size
+        DBLOCKSIZE OFFSET(4) NUMBITS(4) [],
+        /// DMA enable bit
+        DMAEN OFFSET(3) NUMBITS(1) [],
+        /// Data transfer mode selection (0: block, 1: stream)
+        DTMODE OFFSET(2) NUMBITS(1) [],
+        /// Data transfer direction selection (1: from card to controller)
+        DTDIR OFFSET(1) NUMBITS(1) [],
+        /// Data transfer enabled bit
+        DTEN OFFSET(0) NUMBITS(1) []
+    ],
+    STA [
+        /// Data block sent/received (CRC check passed)
+        DBCKEND OFFSET(10) NUMBITS(1) [],
+        /// Start bit not detected on all data signals in wide bus mode
+        STBITERR OFFSET(9) NUMBITS(1) [],
+        /// Data end (data counter, SDIDCOUNT, is zero)
+        DATAEND OFFSET(8) NUMBITS(1) [],
+        /// Command sent (no response required)
+        CMDSENT OFFSET(7) NUMBITS(1) [],
+        /// Command response received (CRC check passed)
+        CMDREND OFFSET(6) NUMBITS(1) [],
+        /// Received FIFO overrun error
+        RXOVERR OFFSET(5) NUMBITS(1) [],
+        /// Transmit FIFO underrun error
+        TXUNDERR OFFSET(4) NUMBITS(1) [],
+        /// Data timeout
+        DTIMEOUT OFFSET(3) NUMBITS(1) [],
+        /// Command response timeout
+        CTIMEOUT OFFSET(2) NUMBITS(1) [],
+        /// Data block sent/received (CRC check failed)
+        DCRCFAIL OFFSET(1) NUMBITS(1) [],
+        /// Command response received (CRC check failed)
+        CCRCFAIL OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+const SDIO_BASE: StaticRef<SdioRegisters> =
+    unsafe { StaticRef::new(0x4001_2C00 as *const SdioRegisters) };
+
+/// Address of the data FIFO, for use by DMA2.
+pub(crate) const FIFO_ADDRESS: u32 = 0x4001_2C80;
+
+/// Default frequency of SDIOCLK, the 48 MHz clock of the main PLL.
+const DEFAULT_INPUT_CLOCK: u32 = 48_000_000;
+
+/// Largest DLEN value.
+const MAX_DATA_LENGTH: usize = (1 << 25) - 1;
+
+/// Bits of the static flags in STA, ICR and MASK.
+const STATIC_FLAGS: u32 = 0x0000_07FF;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// Waiting for the response to a command without data.
+    Command,
+    /// Waiting for the response to the command starting a transfer.
+    TransferCommand,
+    /// Waiting for the data and the DMA transfer to end.
+    Transfer,
+}
+
+// for use by `set_dma`
+pub struct TxDMA<'a>(pub &'a dma::Stream<'a, Dma2<'a>>);
+pub struct RxDMA<'a>(pub &'a dma::Stream<'a, Dma2<'a>>);
+
+pub struct Sdio<'a> {
+    registers: StaticRef<SdioRegisters>,
+    clock: SdioClock<'a>,
+    client: OptionalCell<&'a dyn sdmmc::Client>,
+
+    tx_dma: OptionalCell<&'a dma::Stream<'a, Dma2<'a>>>,
+    rx_dma: OptionalCell<&'a dma::Stream<'a, Dma2<'a>>>,
+
+    input_clock: Cell<u32>,
+    card_clock: Cell<u32>,
+    max_bus_width: Cell<BusWidth>,
+
+    state: Cell<State>,
+    response_type: Cell<ResponseType>,
+    direction: Cell<Direction>,
+    // Log2 of the block length, as written to DBLOCKSIZE.
+    block_size: Cell<u32>,
+    response: Cell<Response>,
+    data_done: Cell<bool>,
+    dma_done: Cell<bool>,
+}
+
+impl<'a> Sdio<'a> {
+    pub fn new(rcc: &'a rcc::Rcc) -> Self {
+        Self {
+            registers: SDIO_BASE,
+            clock: SdioClock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::APB2(rcc::PCLK2::SDIO),
+                rcc,
+            )),
+            client: OptionalCell::empty(),
+            tx_dma: OptionalCell::empty(),
+            rx_dma: OptionalCell::empty(),
+            input_clock: Cell::new(DEFAULT_INPUT_CLOCK),
+            card_clock: Cell::new(0),
+            max_bus_width: Cell::new(BusWidth::Four),
+            state: Cell::new(State::Idle),
+            response_type: Cell::new(ResponseType::None),
+            direction: Cell::new(Direction::Read),
+            block_size: Cell::new(9),
+            response: Cell::new(Response::None),
+            data_done: Cell::new(false),
+            dma_done: Cell::new(false),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    pub fn set_dma(&self, tx_dma: TxDMA<'a>, rx_dma: RxDMA<'a>) {
+        self.tx_dma.set(tx_dma.0);
+        self.rx_dma.set(rx_dma.0);
+    }
+
+    /// Set the frequency of SDIOCLK, if the board configures the PLL to
+    /// something else than 48 MHz.
+    pub fn set_input_clock(&self, hz: u32) {
+        self.input_clock.set(hz);
+    }
+
+    /// Set the number of data lines the board connects. Defaults to four.
+    pub fn set_max_bus_width(&self, width: BusWidth) {
+        self.max_bus_width.set(width);
+    }
+
+    /// Turn off the card clock and the power of the controller.
+    pub fn disable(&self) {
+        if self.is_enabled_clock() {
+            self.registers.clkcr.modify(CLKCR::CLKEN::CLEAR);
+            self.registers.power.write(POWER::PWRCTRL::Off);
+            self.disable_clock();
+        }
+        self.card_clock.set(0);
+    }
+
+    pub fn handle_interrupt(&self) {
+        let status = self.registers.sta.extract();
+        match self.state.get() {
+            State::Idle => {
+                self.registers.mask.set(0);
+                self.registers.icr.set(STATIC_FLAGS);
+            }
+            State::Command | State::TransferCommand => {
+                let result = if status.is_set(STA::CTIMEOUT) {
+                    Err(ErrorCode::NOACK)
+                } else if status.is_set(STA::CMDREND) || status.is_set(STA::CMDSENT) {
+                    Ok(self.read_response())
+                } else if status.is_set(STA::CCRCFAIL) {
+                    // R3 and R4 have no valid CRC.
+                    if self.response_type.get() == ResponseType::ShortNoCrc {
+                        Ok(self.read_response())
+                    } else {
+                        Err(ErrorCode::FAIL)
+                    }
+                } else {
+                    // Only data flags, handled once the response is in.
+                    return;
+                };
+                self.registers.icr.write(
+                    STA::CTIMEOUT::SET + STA::CMDREND::SET + STA::CMDSENT::SET + STA::CCRCFAIL::SET,
+                );
+
+                if self.state.get() == State::Command {
+                    self.finish_command(result);
+                } else {
+                    match result {
+                        Ok(response) => {
+                            self.response.set(response);
+                            self.state.set(State::Transfer);
+                            if self.direction.get() == Direction::Write {
+                                self.start_data();
+                            }
+                            // Data flags may already be set for reads.
+                            self.handle_data(self.registers.sta.extract());
+                        }
+                        Err(e) => self.finish_transfer(Err(e)),
+                    }
+                }
+            }
+            State::Transfer => self.handle_data(status),
+        }
+    }
+
+    fn handle_data(&self, status: LocalRegisterCopy<u32, STA::Register>) {
+        if status.is_set(STA::DCRCFAIL)
+            || status.is_set(STA::DTIMEOUT)
+            || status.is_set(STA::STBITERR)
+        {
+            self.finish_transfer(Err(ErrorCode::FAIL));
+        } else if status.is_set(STA::RXOVERR) || status.is_set(STA::TXUNDERR) {
+            self.finish_transfer(Err(ErrorCode::SIZE));
+        } else if status.is_set(STA::DATAEND) {
+            self.registers
+                .icr
+                .write(STA::DATAEND::SET + STA::DBCKEND::SET);
+            self.registers.mask.modify(STA::DATAEND::CLEAR);
+            self.data_done.set(true);
+            if self.dma_done.get() {
+                self.finish_transfer(Ok(self.response.get()));
+            }
+        }
+    }
+
+    fn read_response(&self) -> Response {
+        match self.response_type.get() {
+            ResponseType::None => Response::None,
+            ResponseType::Short | ResponseType::ShortNoCrc => {
+                Response::Short(self.registers.resp[0].get())
+            }
+            ResponseType::Long => Response::Long([
+                self.registers.resp[0].get(),
+                self.registers.resp[1].get(),
+                self.registers.resp[2].get(),
+                self.registers.resp[3].get(),
+            ]),
+        }
+    }
+
+    fn start_command(&self, index: u8, argument: u32, response: ResponseType, data: bool) {
+        self.response_type.set(response);
+        self.registers.icr.set(STATIC_FLAGS);
+        let mut mask = STA::CTIMEOUT::SET + STA::CCRCFAIL::SET;
+        mask += match response {
+            ResponseType::None => STA::CMDSENT::SET,
+            _ => STA::CMDREND::SET,
+        };
+        if data {
+            mask += STA::DCRCFAIL::SET
+                + STA::DTIMEOUT::SET
+                + STA::STBITERR::SET
+                + STA::RXOVERR::SET
+                + STA::TXUNDERR::SET
+                + STA::DATAEND::SET;
+        }
+        self.registers.mask.write(mask);
+        self.registers.arg.set(argument);
+        self.registers.cmd.write(
+            CMD::CMDINDEX.val(index as u32)
+                + match response {
+                    ResponseType::None => CMD::WAITRESP::None,
+                    ResponseType::Short | ResponseType::ShortNoCrc => CMD::WAITRESP::Short,
+                    ResponseType::Long => CMD::WAITRESP::Long,
+                }
+                + CMD::CPSMEN::SET,
+        );
+    }
+
+    /// Start the data path state machine, the DMA is already running.
+    fn start_data(&self) {
+        self.registers.dctrl.write(
+            DCTRL::DTEN::SET
+                + DCTRL::DTDIR.val((self.direction.get() == Direction::Read) as u32)
+                + DCTRL::DMAEN::SET
+                + DCTRL::DBLOCKSIZE.val(self.block_size.get()),
+        );
+    }
+
+    fn finish_command(&self, result: Result<Response, ErrorCode>) {
+        self.registers.mask.set(0);
+        self.state.set(State::Idle);
+        self.client.map(|client| client.command_done(result));
+    }
+
+    fn finish_transfer(&self, result: Result<Response, ErrorCode>) {
+        self.registers.mask.set(0);
+        self.registers.dctrl.set(0);
+        self.registers.icr.set(STATIC_FLAGS);
+        let stream = match self.direction.get() {
+            Direction::Read => &self.rx_dma,
+            Direction::Write => &self.tx_dma,
+        };
+        let buffer = stream.and_then(|stream| {
+            if self.dma_done.get() {
+                stream.return_buffer()
+            } else {
+                stream.abort_transfer().0
+            }
+        });
+        self.state.set(State::Idle);
+        buffer.map(|buffer| {
+            self.client
+                .map(|client| client.transfer_done(buffer, result));
+        });
+    }
+}
+
+impl<'a> sdmmc::SdMmcHost<'a> for Sdio<'a> {
+    fn set_client(&self, client: &'a dyn sdmmc::Client) {
+        self.client.set(client);
+    }
+
+    fn set_clock(&self, hz: u32) -> Result<u32, ErrorCode> {
+        if hz == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if !self.is_enabled_clock() {
+            self.enable_clock();
+            self.registers.power.write(POWER::PWRCTRL::On);
+        }
+        // The card clock is SDIOCLK / (CLKDIV + 2).
+        let input = self.input_clock.get();
+        let divider = ((input + hz - 1) / hz).clamp(2, 257);
+        self.registers
+            .clkcr
+            .modify(CLKCR::CLKDIV.val(divider - 2) + CLKCR::BYPASS::CLEAR + CLKCR::CLKEN::SET);
+
+        let card_clock = input / divider;
+        self.card_clock.set(card_clock);
+        Ok(card_clock)
+    }
+
+    fn set_bus_width(&self, width: BusWidth) -> Result<(), ErrorCode> {
+        let supported = match self.max_bus_width.get() {
+            BusWidth::One => width == BusWidth::One,
+            BusWidth::Four => width != BusWidth::Eight,
+            BusWidth::Eight => true,
+        };
+        if !supported {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.registers.clkcr.modify(match width {
+            BusWidth::One => CLKCR::WIDBUS::One,
+            BusWidth::Four => CLKCR::WIDBUS::Four,
+            BusWidth::Eight => CLKCR::WIDBUS::Eight,
+        });
+        Ok(())
+    }
+
+    fn max_bus_width(&self) -> BusWidth {
+        self.max_bus_width.get()
+    }
+
+    fn send_command(
+        &self,
+        index: u8,
+        argument: u32,
+        response: ResponseType,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if self.card_clock.get() == 0 {
+            return Err(ErrorCode::OFF);
+        }
+        self.state.set(State::Command);
+        self.start_command(index, argument, response, false);
+        Ok(())
+    }
+
+    fn transfer(
+        &self,
+        index: u8,
+        argument: u32,
+        direction: Direction,
+        buffer: &'static mut [u8],
+        block_len: usize,
+        blocks: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if self.card_clock.get() == 0 {
+            return Err((ErrorCode::OFF, buffer));
+        }
+        let len = block_len * blocks;
+        if !block_len.is_power_of_two()
+            || block_len > 1 << 14
+            || blocks == 0
+            || len > MAX_DATA_LENGTH
+            || buffer.len() < len
+        {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let stream = match direction {
+            Direction::Read => self.rx_dma.extract(),
+            Direction::Write => self.tx_dma.extract(),
+        };
+        let stream = match stream {
+            Some(stream) => stream,
+            None => return Err((ErrorCode::OFF, buffer)),
+        };
+
+        self.state.set(State::TransferCommand);
+        self.direction.set(direction);
+        self.block_size.set(block_len.trailing_zeros());
+        self.data_done.set(false);
+        self.dma_done.set(false);
+
+        // Allow the card one second per transfer, which covers the write
+        // times of slow cards.
+        self.registers.dtimer.set(self.card_clock.get());
+        self.registers.dlen.set(len as u32);
+        stream.do_transfer(buffer, len);
+        if direction == Direction::Read {
+            // The card starts sending data right after the response.
+            self.start_data();
+        }
+        self.start_command(index, argument, ResponseType::Short, true);
+        Ok(())
+    }
+}
+
+impl<'a> dma::StreamClient<'a, Dma2<'a>> for Sdio<'a> {
+    fn transfer_done(&self, pid: Dma2Peripheral) {
+        let expected = match self.direction.get() {
+            Direction::Read => Dma2Peripheral::SDIO_RX,
+            Direction::Write => Dma2Peripheral::SDIO_TX,
+        };
+        if pid != expected || self.state.get() == State::Idle {
+            return;
+        }
+        self.dma_done.set(true);
+        if self.data_done.get() {
+            self.finish_transfer(Ok(self.response.get()));
+        }
+    }
+}
+
+struct SdioClock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for SdioClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
@@ -0,0 +1,302 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! TIM3 as a pulse generator.
+//!
+//! Pulses are generated on channel 1 (`TIM3_CH1`, alternate function 2 of
+//! PA6, PB4, or PC6) with PWM mode 2: the output is inactive while the
+//! counter is below CCR1 and active from CCR1 until the end of the period.
+//! The timer counts at 1 MHz and is 16 bits wide, so periods can be up to
+//! 65535 microseconds.
+//!
+//! The kernel counts the pulses in the update interrupt at the end of each
+//! period, and enables one-pulse mode before the last period starts, so the
+//! timer stops itself after the last pulse.
+//!
+//! The board must configure the pin for the alternate function, e.g.:
+//!
+//! ```rust,ignore
+//! gpio_ports.get_pin(PinId::PA06).map(|pin| {
+//!     pin.set_mode(Mode::AlternateFunctionMode);
+//!     pin.set_alternate_function(AlternateFunction::AF2);
+//! });
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio::ActivationMode;
+use kernel::hil::pulse::{PulseClient, PulseGenerator};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::rcc;
+
+#[repr(C)]
+struct Tim3Registers {
+    /// control register 1
+    cr1: ReadWrite<u32, CR1::Register>,
+    /// control register 2
+    cr2: ReadWrite<u32>,
+    /// slave mode control register
+    smcr: ReadWrite<u32>,
+    /// DMA/Interrupt enable register
+    dier: ReadWrite<u32, DIER::Register>,
+    /// status register
+    sr: ReadWrite<u32, SR::Register>,
+    /// event generation register
+    egr: WriteOnly<u32, EGR::Register>,
+    /// capture/compare mode register 1 (output mode)
+    ccmr1_output: ReadWrite<u32, CCMR1_Output::Register>,
+    /// capture/compare mode register 2 (output mode)
+    ccmr2_output: ReadWrite<u32>,
+    /// capture/compare enable register
+    ccer: ReadWrite<u32, CCER::Register>,
+    /// counter
+    cnt: ReadWrite<u32>,
+    /// prescaler
+    psc: ReadWrite<u32>,
+    /// auto-reload register
+    arr: ReadWrite<u32>,
+    _reserved0: [u8; 4],
+    /// capture/compare register 1
+    ccr1: ReadWrite<u32>,
+    /// capture/compare register 2
+    ccr2: ReadWrite<u32>,
+    /// capture/compare register 3
+    ccr3: ReadWrite<u32>,
+    /// capture/compare register 4
+    ccr4: ReadWrite<u32>,
+    _reserved1: [u8; 4],
+    /// DMA control register
+    dcr: ReadWrite<u32>,
+    /// DMA address for full transfer
+    dmar: ReadWrite<u32>,
+}
+
+register_bitfields![u32,
+    CR1 [
+        /// Auto-reload preload enable
+        ARPE OFFSET(7) NUMBITS(1) [],
+        /// One-pulse mode
+        OPM OFFSET(3) NUMBITS(1) [],
+        /// Update request source
+        URS OFFSET(2) NUMBITS(1) [],
+        /// Counter enable
+        CEN OFFSET(0) NUMBITS(1) []
+    ],
+    DIER [
+        /// Update interrupt enable
+        UIE OFFSET(0) NUMBITS(1) []
+    ],
+    SR [
+        /// Update interrupt flag
+        UIF OFFSET(0) NUMBITS(1) []
+    ],
+    EGR [
+        /// Update generation
+        UG OFFSET(0) NUMBITS(1) []
+    ],
+    CCMR1_Output [
+        /// OC1M
+        OC1M OFFSET(4) NUMBITS(3) [
+            ForceInactive = 0b100,
+            Pwm2 = 0b111
+        ],
+        /// OC1PE
+        OC1PE OFFSET(3) NUMBITS(1) [],
+        /// CC1S
+        CC1S OFFSET(0) NUMBITS(2) []
+    ],
+    CCER [
+        /// Capture/Compare 1 output Polarity
+        CC1P OFFSET(1) NUMBITS(1) [],
+        /// Capture/Compare 1 output enable
+        CC1E OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+const TIM3_BASE: StaticRef<Tim3Registers> =
+    unsafe { StaticRef::new(0x40000400 as *const Tim3Registers) };
+
+/// TIM3 uses PCLK1, which runs from the 16 MHz HSI by default.
+const PRESCALER_1MHZ: u32 = 16 - 1;
+
+pub struct Tim3<'a> {
+    registers: StaticRef<Tim3Registers>,
+    clock: Tim3Clock<'a>,
+    mode: Cell<ActivationMode>,
+    running: Cell<bool>,
+    /// Pulses left to generate, 0 if the pulses repeat until stopped.
+    remaining: Cell<usize>,
+    /// Set while the update event generated by `stop()` is pending.
+    cancelled: Cell<bool>,
+    client: OptionalCell<&'a dyn PulseClient>,
+}
+
+impl<'a> Tim3<'a> {
+    pub const fn new(rcc: &'a rcc::Rcc) -> Self {
+        Self {
+            registers: TIM3_BASE,
+            clock: Tim3Clock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::APB1(rcc::PCLK1::TIM3),
+                rcc,
+            )),
+            mode: Cell::new(ActivationMode::ActiveHigh),
+            running: Cell::new(false),
+            remaining: Cell::new(0),
+            cancelled: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Select whether the pulses drive the pin high or low.
+    pub fn set_activation_mode(&self, mode: ActivationMode) {
+        self.mode.set(mode);
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    pub fn handle_interrupt(&self) {
+        self.registers.sr.modify(SR::UIF::CLEAR);
+
+        if self.cancelled.get() {
+            self.cancelled.set(false);
+            self.registers.dier.modify(DIER::UIE::CLEAR);
+            self.client
+                .map(|client| client.pulses_done(Err(ErrorCode::CANCEL)));
+            return;
+        }
+        if !self.running.get() {
+            return;
+        }
+
+        let remaining = self.remaining.get().saturating_sub(1);
+        self.remaining.set(remaining);
+        if remaining == 1 {
+            // Stop after the next period.
+            self.registers.cr1.modify(CR1::OPM::SET);
+        } else if remaining == 0 {
+            self.halt();
+            self.client.map(|client| client.pulses_done(Ok(())));
+        }
+    }
+
+    fn halt(&self) {
+        self.registers.cr1.modify(CR1::CEN::CLEAR);
+        self.registers.dier.modify(DIER::UIE::CLEAR);
+        self.registers
+            .ccmr1_output
+            .modify(CCMR1_Output::OC1M::ForceInactive);
+        self.running.set(false);
+    }
+}
+
+impl<'a> PulseGenerator<'a> for Tim3<'a> {
+    fn set_client(&self, client: &'a dyn PulseClient) {
+        self.client.set(client);
+    }
+
+    fn start(&self, width_us: u32, period_us: u32, count: usize) -> Result<(), ErrorCode> {
+        if self.running.get() || self.cancelled.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        // With a single pulse, the output is inactive for one tick before
+        // the pulse starts.
+        let (compare, reload) = if count == 1 {
+            (1, width_us)
+        } else {
+            if period_us <= width_us {
+                return Err(ErrorCode::INVAL);
+            }
+            (period_us - width_us, period_us - 1)
+        };
+        if width_us == 0 || reload > self.max_period_us() {
+            return Err(ErrorCode::INVAL);
+        }
+
+        if !self.is_enabled_clock() {
+            self.enable_clock();
+        }
+
+        let regs = self.registers;
+        regs.cr1.write(CR1::URS::SET + CR1::ARPE::SET);
+        regs.psc.set(PRESCALER_1MHZ);
+        regs.arr.set(reload);
+        regs.ccr1.set(compare);
+        regs.cnt.set(0);
+        regs.ccmr1_output
+            .write(CCMR1_Output::OC1M::Pwm2 + CCMR1_Output::OC1PE::SET);
+        let polarity = match self.mode.get() {
+            ActivationMode::ActiveHigh => CCER::CC1P::CLEAR,
+            ActivationMode::ActiveLow => CCER::CC1P::SET,
+        };
+        regs.ccer.write(CCER::CC1E::SET + polarity);
+        // Load the prescaler and preloaded registers. With URS set this does
+        // not raise an interrupt.
+        regs.egr.write(EGR::UG::SET);
+        regs.sr.modify(SR::UIF::CLEAR);
+
+        self.remaining.set(count);
+        if count == 1 {
+            regs.cr1.modify(CR1::OPM::SET);
+        }
+        if count != 0 {
+            regs.dier.modify(DIER::UIE::SET);
+        }
+        self.running.set(true);
+        regs.cr1.modify(CR1::CEN::SET);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        if !self.running.get() {
+            return Err(ErrorCode::OFF);
+        }
+        self.halt();
+        // Generate an update interrupt to report the cancellation.
+        self.cancelled.set(true);
+        self.registers.cr1.modify(CR1::URS::CLEAR);
+        self.registers.dier.modify(DIER::UIE::SET);
+        self.registers.egr.write(EGR::UG::SET);
+        Ok(())
+    }
+
+    fn max_period_us(&self) -> u32 {
+        u16::MAX as u32
+    }
+
+    fn is_running(&self) -> bool {
+        self.running.get()
+    }
+}
+
+struct Tim3Clock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for Tim3Clock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
@@ -204,6 +204,9 @@ pub struct Usart<'a, DMA: dma::StreamServer<'a>> {
     partial_rx_buffer: TakeCell<'static, [u8]>,
     partial_rx_len: Cell<usize>,
 
+    // Set while a `receive_automatic` is waiting for an idle line.
+    rx_idle_detection: Cell<bool>,
+
     deferred_call: DeferredCall,
 }
 
@@ -282,6 +285,8 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
             partial_rx_buffer: TakeCell::empty(),
             partial_rx_len: Cell::new(0),
 
+            rx_idle_detection: Cell::new(false),
+
             deferred_call: DeferredCall::new(),
         }
     }
@@ -306,6 +311,14 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
     // According to section 25.4.13, we need to make sure that USART TC flag is
     // set before disabling the DMA TX on the peripheral side.
     pub fn handle_interrupt(&self) {
+        if self.registers.cr1.is_set(CR1::IDLEIE) && self.registers.sr.is_set(SR::IDLE) {
+            self.idle_line_detected();
+        }
+
+        if !self.registers.cr1.is_set(CR1::TCIE) {
+            return;
+        }
+
         self.clear_transmit_complete();
         self.disable_transmit_complete_interrupt();
 
@@ -385,6 +398,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
 
     fn abort_rx(&self, rcode: Result<(), ErrorCode>, error: hil::uart::Error) {
         self.disable_rx();
+        self.disable_idle_line_interrupt();
 
         // get buffer
         let (mut buffer, len) = self.rx_dma.map_or((None, 0), |rx_dma| {
@@ -409,6 +423,71 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
         }
     }
 
+    fn start_dma_receive(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.usart_rx_state.get() != USARTStateRX::Idle {
+            return Err((ErrorCode::BUSY, rx_buffer));
+        }
+
+        if rx_len > rx_buffer.len() {
+            return Err((ErrorCode::SIZE, rx_buffer));
+        }
+
+        // setup and enable dma stream
+        self.rx_dma.map(move |dma| {
+            self.rx_len.set(rx_len);
+            dma.do_transfer(rx_buffer, rx_len);
+        });
+
+        self.usart_rx_state.set(USARTStateRX::DMA_Receiving);
+
+        // enable dma rx on the peripheral side
+        self.enable_rx();
+        Ok(())
+    }
+
+    fn enable_idle_line_interrupt(&self) {
+        // Reading SR followed by DR clears any stale IDLE flag.
+        self.registers.sr.get();
+        self.registers.dr.get();
+        self.rx_idle_detection.set(true);
+        self.registers.cr1.modify(CR1::IDLEIE::SET);
+    }
+
+    fn disable_idle_line_interrupt(&self) {
+        self.rx_idle_detection.set(false);
+        self.registers.cr1.modify(CR1::IDLEIE::CLEAR);
+    }
+
+    // The line went idle for one frame after at least one byte was received,
+    // so stop the DMA transfer and return what was received so far.
+    fn idle_line_detected(&self) {
+        // SR was just read by the caller, reading DR clears the IDLE flag.
+        self.registers.dr.get();
+
+        if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving && self.rx_idle_detection.get()
+        {
+            self.disable_idle_line_interrupt();
+            self.disable_rx();
+            self.usart_rx_state.set(USARTStateRX::Idle);
+
+            let (buffer, remaining) = self
+                .rx_dma
+                .map_or((None, 0), |rx_dma| rx_dma.abort_transfer());
+            let length = self.rx_len.get() - remaining as usize;
+            self.rx_len.set(0);
+
+            self.rx_client.map(|client| {
+                buffer.map(|buf| {
+                    client.received_buffer(buf, length, Ok(()), hil::uart::Error::None);
+                });
+            });
+        }
+    }
+
     fn enable_transmit_complete_interrupt(&self) {
         self.registers.cr1.modify(CR1::TCIE::SET);
     }
@@ -430,6 +509,7 @@ impl<'a, DMA: dma::StreamServer<'a>> Usart<'a, DMA> {
             // to trigger an interrupt.
             if self.usart_rx_state.get() == USARTStateRX::DMA_Receiving {
                 self.disable_rx();
+                self.disable_idle_line_interrupt();
                 self.usart_rx_state.set(USARTStateRX::Idle);
 
                 // get buffer
@@ -528,12 +608,10 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Configure for Usart<'a, DMA> {
         if params.baud_rate != 115200
             || params.stop_bits != hil::uart::StopBits::One
             || params.parity != hil::uart::Parity::None
-            || params.hw_flow_control != false
             || params.width != hil::uart::Width::Eight
         {
-            panic!(
-                "Currently we only support uart setting of 115200bps 8N1, no hardware flow control"
-            );
+            // Currently we only support uart setting of 115200bps 8N1
+            return Err(ErrorCode::NOSUPPORT);
         }
 
         // Configure the word length - 0: 1 Start bit, 8 Data bits, n Stop bits
@@ -545,6 +623,15 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Configure for Usart<'a, DMA> {
         // Set no parity
         self.registers.cr1.modify(CR1::PCE::CLEAR);
 
+        // RTS/CTS hardware flow control
+        if params.hw_flow_control {
+            self.registers.cr3.modify(CR3::RTSE::SET + CR3::CTSE::SET);
+        } else {
+            self.registers
+                .cr3
+                .modify(CR3::RTSE::CLEAR + CR3::CTSE::CLEAR);
+        }
+
         // Set the baud rate. By default OVER8 is 0 (oversampling by 16) and
         // PCLK1 is at 16Mhz. The desired baud rate is 115.2KBps. So according
         // to Table 149 of reference manual, the value for BRR is 8.6875
@@ -576,25 +663,7 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Receive<'a> for Usart<'a, DMA> {
         rx_buffer: &'static mut [u8],
         rx_len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        if self.usart_rx_state.get() != USARTStateRX::Idle {
-            return Err((ErrorCode::BUSY, rx_buffer));
-        }
-
-        if rx_len > rx_buffer.len() {
-            return Err((ErrorCode::SIZE, rx_buffer));
-        }
-
-        // setup and enable dma stream
-        self.rx_dma.map(move |dma| {
-            self.rx_len.set(rx_len);
-            dma.do_transfer(rx_buffer, rx_len);
-        });
-
-        self.usart_rx_state.set(USARTStateRX::DMA_Receiving);
-
-        // enable dma rx on the peripheral side
-        self.enable_rx();
-        Ok(())
+        self.start_dma_receive(rx_buffer, rx_len)
     }
 
     fn receive_word(&self) -> Result<(), ErrorCode> {
@@ -607,6 +676,21 @@ impl<'a, DMA: dma::StreamServer<'a>> hil::uart::Receive<'a> for Usart<'a, DMA> {
     }
 }
 
+impl<'a, DMA: dma::StreamServer<'a>> hil::uart::ReceiveAdvanced<'a> for Usart<'a, DMA> {
+    /// Receive using DMA until the line is idle for one frame. The USART can
+    /// not measure longer idle periods, so `interbyte_timeout` is ignored.
+    fn receive_automatic(
+        &self,
+        rx_buffer: &'static mut [u8],
+        rx_len: usize,
+        _interbyte_timeout: u8,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.start_dma_receive(rx_buffer, rx_len)?;
+        self.enable_idle_line_interrupt();
+        Ok(())
+    }
+}
+
 impl<'a> dma::StreamClient<'a, dma::Dma1<'a>> for Usart<'a, dma::Dma1<'a>> {
     fn transfer_done(&self, pid: dma::Dma1Peripheral) {
         self.transfer_done(pid);
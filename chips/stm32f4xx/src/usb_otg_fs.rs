@@ -0,0 +1,1000 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! USB on-the-go full-speed controller, in host mode.
+//!
+//! The controller runs in slave mode: the driver moves every packet through
+//! the FIFOs. Channel 0 carries the control transfers, channels 1 to 7 are
+//! the pipes. Every packet is started on its own, so NAKed and failed
+//! transactions are retried from the packet they happened on.
+//!
+//! The controller needs a 48 MHz clock from the Q output of the PLL. If the
+//! PLL is off when the controller is enabled, it is started with its reset
+//! configuration, which gives 48 MHz from the HSI. The HSI is not accurate
+//! enough for some devices, boards should configure the PLL from the HSE.
+//!
+//! The registers are those of the STM32F405/407/415/417/427/429/437/439.
+//! Pins PA11 (DM) and PA12 (DP) must be set to alternate function 10, and
+//! the board has to power VBUS.
+
+use core::cell::Cell;
+
+use kernel::hil::usb::TransferType;
+use kernel::hil::usb_host::{self, Direction, Endpoint, Speed};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, FieldValue, ReadOnly, ReadWrite,
+};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::rcc;
+
+/// Number of host channels.
+pub const CHANNELS: usize = 8;
+
+/// The channel of control transfers.
+const CONTROL: usize = 0;
+
+/// Failed transactions retried before a transfer fails.
+const MAX_ERRORS: u8 = 3;
+
+/// Loops waiting for the core, before giving up.
+const TIMEOUT: usize = 1_000_000;
+
+register_structs! {
+    pub UsbOtgFsRegisters {
+        /// Control and status register
+        (0x000 => gotgctl: ReadWrite<u32>),
+        /// Interrupt register
+        (0x004 => gotgint: ReadWrite<u32>),
+        /// AHB configuration register
+        (0x008 => gahbcfg: ReadWrite<u32, GAHBCFG::Register>),
+        /// USB configuration register
+        (0x00c => gusbcfg: ReadWrite<u32, GUSBCFG::Register>),
+        /// Reset register
+        (0x010 => grstctl: ReadWrite<u32, GRSTCTL::Register>),
+        /// Core interrupt register
+        (0x014 => gintsts: ReadWrite<u32, GINT::Register>),
+        /// Interrupt mask register
+        (0x018 => gintmsk: ReadWrite<u32, GINT::Register>),
+        /// Receive status debug read register
+        (0x01c => grxstsr: ReadOnly<u32, GRXSTS::Register>),
+        /// Status read and pop register
+        (0x020 => grxstsp: ReadOnly<u32, GRXSTS::Register>),
+        /// Receive FIFO size register
+        (0x024 => grxfsiz: ReadWrite<u32>),
+        /// Host non-periodic transmit FIFO size register
+        (0x028 => hnptxfsiz: ReadWrite<u32, TXFSIZ::Register>),
+        /// Non-periodic transmit FIFO/queue status register
+        (0x02c => hnptxsts: ReadOnly<u32, TXSTS::Register>),
+        (0x030 => _reserved0),
+        /// General core configuration register
+        (0x038 => gccfg: ReadWrite<u32, GCCFG::Register>),
+        /// Core ID register
+        (0x03c => cid: ReadWrite<u32>),
+        (0x040 => _reserved1),
+        /// Host periodic transmit FIFO size register
+        (0x100 => hptxfsiz: ReadWrite<u32, TXFSIZ::Register>),
+        (0x104 => _reserved2),
+        /// Host configuration register
+        (0x400 => hcfg: ReadWrite<u32, HCFG::Register>),
+        /// Host frame interval register
+        (0x404 => hfir: ReadWrite<u32>),
+        /// Host frame number/frame time remaining register
+        (0x408 => hfnum: ReadOnly<u32>),
+        (0x40c => _reserved3),
+        /// Host periodic transmit FIFO/queue status register
+        (0x410 => hptxsts: ReadOnly<u32, TXSTS::Register>),
+        /// Host all channels interrupt register
+        (0x414 => haint: ReadOnly<u32>),
+        /// Host all channels interrupt mask register
+        (0x418 => haintmsk: ReadWrite<u32>),
+        (0x41c => _reserved4),
+        /// Host port control and status register
+        (0x440 => hprt: ReadWrite<u32, HPRT::Register>),
+        (0x444 => _reserved5),
+        /// Host channel registers
+        (0x500 => channels: [Channel; CHANNELS]),
+        (0x600 => _reserved6),
+        /// Power and clock gating control register
+        (0xe00 => pcgcctl: ReadWrite<u32>),
+        (0xe04 => _reserved7),
+        /// Data FIFOs of the channels
+        (0x1000 => fifos: [Fifo; CHANNELS]),
+        (0x9000 => @END),
+    },
+
+    Channel {
+        /// Channel characteristics register
+        (0x00 => hcchar: ReadWrite<u32, HCCHAR::Register>),
+        (0x04 => _reserved0),
+        /// Channel interrupt register
+        (0x08 => hcint: ReadWrite<u32, HCINT::Register>),
+        /// Channel interrupt mask register
+        (0x0c => hcintmsk: ReadWrite<u32, HCINT::Register>),
+        /// Channel transfer size register
+        (0x10 => hctsiz: ReadWrite<u32, HCTSIZ::Register>),
+        (0x14 => _reserved1),
+        (0x20 => @END),
+    },
+
+    Fifo {
+        (0x000 => data: ReadWrite<u32>),
+        (0x004 => _reserved),
+        (0x1000 => @END),
+    }
+}
+
+register_bitfields![u32,
+    GAHBCFG [
+        /// Global interrupt mask
+        GINT OFFSET(0) NUMBITS(1) []
+    ],
+    GUSBCFG [
+        /// Force device mode
+        FDMOD OFFSET(30) NUMBITS(1) [],
+        /// Force host mode
+        FHMOD OFFSET(29) NUMBITS(1) [],
+        /// Full speed serial transceiver select
+        PHYSEL OFFSET(6) NUMBITS(1) []
+    ],
+    GRSTCTL [
+        /// AHB master idle
+        AHBIDL OFFSET(31) NUMBITS(1) [],
+        /// TxFIFO number
+        TXFNUM OFFSET(6) NUMBITS(5) [
+            All = 0x10
+        ],
+        /// TxFIFO flush
+        TXFFLSH OFFSET(5) NUMBITS(1) [],
+        /// RxFIFO flush
+        RXFFLSH OFFSET(4) NUMBITS(1) [],
+        /// Core soft reset
+        CSRST OFFSET(0) NUMBITS(1) []
+    ],
+    GINT [
+        /// Disconnect detected interrupt
+        DISCINT OFFSET(29) NUMBITS(1) [],
+        /// Host channels interrupt
+        HCINT OFFSET(25) NUMBITS(1) [],
+        /// Host port interrupt
+        HPRTINT OFFSET(24) NUMBITS(1) [],
+        /// RxFIFO non-empty
+        RXFLVL OFFSET(4) NUMBITS(1) [],
+        /// Current mode of operation
+        CMOD OFFSET(0) NUMBITS(1) [
+            Device = 0,
+            Host = 1
+        ]
+    ],
+    GRXSTS [
+        /// Packet status
+        PKTSTS OFFSET(17) NUMBITS(4) [
+            InData = 0b0010,
+            InComplete = 0b0011,
+            DataToggleError = 0b0101,
+            ChannelHalted = 0b0111
+        ],
+        /// Byte count
+        BCNT OFFSET(4) NUMBITS(11) [],
+        /// Channel number
+        CHNUM OFFSET(0) NUMBITS(4) []
+    ],
+    TXFSIZ [
+        /// FIFO depth, in words
+        DEPTH OFFSET(16) NUMBITS(16) [],
+        /// FIFO start address, in words
+        START OFFSET(0) NUMBITS(16) []
+    ],
+    TXSTS [
+        /// FIFO space available, in words
+        FSAV OFFSET(0) NUMBITS(16) []
+    ],
+    GCCFG [
+        /// VBUS sensing disable
+        NOVBUSSENS OFFSET(21) NUMBITS(1) [],
+        /// VBUS sensing "B" device
+        VBUSBSEN OFFSET(19) NUMBITS(1) [],
+        /// VBUS sensing "A" device
+        VBUSASEN OFFSET(18) NUMBITS(1) [],
+        /// Power down
+        PWRDWN OFFSET(16) NUMBITS(1) []
+    ],
+    HCFG [
+        /// FS- and LS-only support
+        FSLSS OFFSET(2) NUMBITS(1) [],
+        /// FS/LS PHY clock select
+        FSLSPCS OFFSET(0) NUMBITS(2) [
+            Clock48MHz = 1,
+            Clock6MHz = 2
+        ]
+    ],
+    HPRT [
+        /// Port speed
+        PSPD OFFSET(17) NUMBITS(2) [
+            Full = 1,
+            Low = 2
+        ],
+        /// Port power
+        PPWR OFFSET(12) NUMBITS(1) [],
+        /// Port reset
+        PRST OFFSET(8) NUMBITS(1) [],
+        /// Port overcurrent change
+        POCCHNG OFFSET(5) NUMBITS(1) [],
+        /// Port enable/disable change
+        PENCHNG OFFSET(3) NUMBITS(1) [],
+        /// Port enable
+        PENA OFFSET(2) NUMBITS(1) [],
+        /// Port connect detected
+        PCDET OFFSET(1) NUMBITS(1) [],
+        /// Port connect status
+        PCSTS OFFSET(0) NUMBITS(1) []
+    ],
+    HCCHAR [
+        /// Channel enable
+        CHENA OFFSET(31) NUMBITS(1) [],
+        /// Channel disable
+        CHDIS OFFSET(30) NUMBITS(1) [],
+        /// Odd frame
+        ODDFRM OFFSET(29) NUMBITS(1) [],
+        /// Device address
+        DAD OFFSET(22) NUMBITS(7) [],
+        /// Multicount
+        MCNT OFFSET(20) NUMBITS(2) [],
+        /// Endpoint type
+        EPTYP OFFSET(18) NUMBITS(2) [
+            Control = 0,
+            Isochronous = 1,
+            Bulk = 2,
+            Interrupt = 3
+        ],
+        /// Low-speed device
+        LSDEV OFFSET(17) NUMBITS(1) [],
+        /// Endpoint direction
+        EPDIR OFFSET(15) NUMBITS(1) [
+            Out = 0,
+            In = 1
+        ],
+        /// Endpoint number
+        EPNUM OFFSET(11) NUMBITS(4) [],
+        /// Maximum packet size
+        MPSIZ OFFSET(0) NUMBITS(11) []
+    ],
+    HCINT [
+        /// Data toggle error
+        DTERR OFFSET(10) NUMBITS(1) [],
+        /// Frame overrun
+        FRMOR OFFSET(9) NUMBITS(1) [],
+        /// Babble error
+        BBERR OFFSET(8) NUMBITS(1) [],
+        /// Transaction error
+        TXERR OFFSET(7) NUMBITS(1) [],
+        /// ACK response received/transmitted
+        ACK OFFSET(5) NUMBITS(1) [],
+        /// NAK response received
+        NAK OFFSET(4) NUMBITS(1) [],
+        /// STALL response received
+        STALL OFFSET(3) NUMBITS(1) [],
+        /// Channel halted
+        CHH OFFSET(1) NUMBITS(1) [],
+        /// Transfer completed
+        XFRC OFFSET(0) NUMBITS(1) []
+    ],
+    HCTSIZ [
+        /// Data PID
+        DPID OFFSET(29) NUMBITS(2) [
+            Data0 = 0,
+            Data1 = 2,
+            Setup = 3
+        ],
+        /// Packet count
+        PKTCNT OFFSET(19) NUMBITS(10) [],
+        /// Transfer size
+        XFRSIZ OFFSET(0) NUMBITS(19) []
+    ]
+];
+
+/// Bits of HPRT that are cleared, or disable the port, when written 1.
+const HPRT_WRITE_CLEAR: u32 = (1 << 1) | (1 << 2) | (1 << 3) | (1 << 5);
+
+/// FIFO sizes, in words.
+const RX_FIFO_DEPTH: u32 = 128;
+const NON_PERIODIC_TX_FIFO_DEPTH: u32 = 96;
+const PERIODIC_TX_FIFO_DEPTH: u32 = 64;
+
+const OTG_FS_BASE: StaticRef<UsbOtgFsRegisters> =
+    unsafe { StaticRef::new(0x5000_0000 as *const UsbOtgFsRegisters) };
+
+/// What a channel was halted for.
+#[derive(Clone, Copy)]
+enum Outcome {
+    None,
+    /// A packet was moved.
+    Packet,
+    /// The packet has to be sent again.
+    Retry,
+    Failed(ErrorCode),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ControlStage {
+    Setup,
+    Data,
+    Status,
+}
+
+/// The transactions a channel carries.
+#[derive(Clone, Copy)]
+struct Target {
+    address: u8,
+    number: u8,
+    direction: Direction,
+    transfer_type: TransferType,
+    max_packet_size: u16,
+}
+
+struct ChannelState {
+    /// The endpoint of the pipe, if it is open.
+    endpoint: Cell<Option<Endpoint>>,
+    /// The buffer of the transfer in progress.
+    buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    /// Bytes moved so far.
+    offset: Cell<usize>,
+    /// Bytes of the current packet.
+    packet: Cell<usize>,
+    /// Whether the next packet is DATA1.
+    toggle: Cell<bool>,
+    errors: Cell<u8>,
+    outcome: Cell<Outcome>,
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        ChannelState {
+            endpoint: Cell::new(None),
+            buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            offset: Cell::new(0),
+            packet: Cell::new(0),
+            toggle: Cell::new(false),
+            errors: Cell::new(0),
+            outcome: Cell::new(Outcome::None),
+        }
+    }
+}
+
+pub struct UsbOtgFs<'a> {
+    registers: StaticRef<UsbOtgFsRegisters>,
+    clock: UsbOtgFsClock<'a>,
+    client: OptionalCell<&'a dyn usb_host::Client>,
+    speed: Cell<Speed>,
+    resetting: Cell<bool>,
+    channels: [ChannelState; CHANNELS],
+    /// The control transfer in progress.
+    control: Cell<Option<(Target, ControlStage)>>,
+    setup: Cell<[u8; 8]>,
+    /// Bytes moved by the data stage of the control transfer.
+    control_len: Cell<usize>,
+}
+
+impl<'a> UsbOtgFs<'a> {
+    pub fn new(rcc: &'a rcc::Rcc) -> Self {
+        UsbOtgFs {
+            registers: OTG_FS_BASE,
+            clock: UsbOtgFsClock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::AHB2(rcc::HCLK2::OTGFS),
+                rcc,
+            )),
+            client: OptionalCell::empty(),
+            speed: Cell::new(Speed::Full),
+            resetting: Cell::new(false),
+            channels: [
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+                ChannelState::new(),
+            ],
+            control: Cell::new(None),
+            setup: Cell::new([0; 8]),
+            control_len: Cell::new(0),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Wait until `condition` holds, or give up.
+    fn wait<F: Fn() -> bool>(condition: F) -> Result<(), ErrorCode> {
+        for _ in 0..TIMEOUT {
+            if condition() {
+                return Ok(());
+            }
+        }
+        Err(ErrorCode::FAIL)
+    }
+
+    fn init_core(&self) -> Result<(), ErrorCode> {
+        let regs = &*self.registers;
+
+        self.clock.0.configure_otgfs_clock();
+        Self::wait(|| self.clock.0.is_ready_pll())?;
+        self.enable_clock();
+
+        regs.gusbcfg.modify(GUSBCFG::PHYSEL::SET);
+        Self::wait(|| regs.grstctl.is_set(GRSTCTL::AHBIDL))?;
+        regs.grstctl.modify(GRSTCTL::CSRST::SET);
+        Self::wait(|| !regs.grstctl.is_set(GRSTCTL::CSRST))?;
+
+        // Enable the transceiver, the port is powered by the board.
+        regs.gccfg
+            .write(GCCFG::PWRDWN::SET + GCCFG::NOVBUSSENS::SET);
+        regs.gusbcfg
+            .modify(GUSBCFG::FDMOD::CLEAR + GUSBCFG::FHMOD::SET);
+        Self::wait(|| regs.gintsts.matches_all(GINT::CMOD::Host))?;
+        regs.pcgcctl.set(0);
+
+        regs.hcfg
+            .write(HCFG::FSLSS::SET + HCFG::FSLSPCS::Clock48MHz);
+        regs.hfir.set(48000);
+
+        regs.grxfsiz.set(RX_FIFO_DEPTH);
+        regs.hnptxfsiz.write(
+            TXFSIZ::START.val(RX_FIFO_DEPTH) + TXFSIZ::DEPTH.val(NON_PERIODIC_TX_FIFO_DEPTH),
+        );
+        regs.hptxfsiz.write(
+            TXFSIZ::START.val(RX_FIFO_DEPTH + NON_PERIODIC_TX_FIFO_DEPTH)
+                + TXFSIZ::DEPTH.val(PERIODIC_TX_FIFO_DEPTH),
+        );
+        self.flush_fifos()?;
+
+        for channel in regs.channels.iter() {
+            channel.hcint.set(0xffff_ffff);
+            channel.hcintmsk.write(
+                HCINT::XFRC::SET
+                    + HCINT::CHH::SET
+                    + HCINT::STALL::SET
+                    + HCINT::NAK::SET
+                    + HCINT::TXERR::SET
+                    + HCINT::BBERR::SET
+                    + HCINT::FRMOR::SET
+                    + HCINT::DTERR::SET,
+            );
+        }
+        regs.haintmsk.set((1 << CHANNELS) - 1);
+
+        regs.gintsts.set(0xffff_ffff);
+        regs.gintmsk
+            .write(GINT::RXFLVL::SET + GINT::HPRTINT::SET + GINT::HCINT::SET + GINT::DISCINT::SET);
+        regs.gahbcfg.write(GAHBCFG::GINT::SET);
+        Ok(())
+    }
+
+    fn flush_fifos(&self) -> Result<(), ErrorCode> {
+        let regs = &*self.registers;
+        regs.grstctl
+            .write(GRSTCTL::TXFFLSH::SET + GRSTCTL::TXFNUM::All);
+        Self::wait(|| !regs.grstctl.is_set(GRSTCTL::TXFFLSH))?;
+        regs.grstctl.write(GRSTCTL::RXFFLSH::SET);
+        Self::wait(|| !regs.grstctl.is_set(GRSTCTL::RXFFLSH))
+    }
+
+    /// Change the fields of HPRT in `field`, without touching its flags.
+    fn port_modify(&self, field: FieldValue<u32, HPRT::Register>) {
+        let value = self.registers.hprt.get() & !HPRT_WRITE_CLEAR;
+        self.registers.hprt.set(field.modify(value));
+    }
+
+    /// Clear the flags of HPRT in `flags`.
+    fn port_clear(&self, flags: FieldValue<u32, HPRT::Register>) {
+        let value = self.registers.hprt.get() & !HPRT_WRITE_CLEAR;
+        self.registers.hprt.set(value | flags.value);
+    }
+
+    /// The target of channel `ch`.
+    fn target(&self, ch: usize) -> Option<Target> {
+        if ch == CONTROL {
+            self.control.get().map(|(target, _)| target)
+        } else {
+            self.channels[ch].endpoint.get().map(|endpoint| Target {
+                address: endpoint.address,
+                number: endpoint.number,
+                direction: endpoint.direction,
+                transfer_type: endpoint.transfer_type,
+                max_packet_size: endpoint.max_packet_size,
+            })
+        }
+    }
+
+    /// Start the next packet on channel `ch`.
+    fn start_packet(&self, ch: usize) {
+        let target = match self.target(ch) {
+            Some(target) => target,
+            None => return,
+        };
+        let state = &self.channels[ch];
+        let channel = &self.registers.channels[ch];
+        let setup_stage =
+            ch == CONTROL && matches!(self.control.get(), Some((_, ControlStage::Setup)));
+        let max_packet_size = target.max_packet_size as usize;
+
+        let packet = match target.direction {
+            Direction::In => max_packet_size,
+            Direction::Out => (state.len.get() - state.offset.get()).min(max_packet_size),
+        };
+        state.packet.set(if target.direction == Direction::In {
+            0
+        } else {
+            packet
+        });
+
+        let pid = if setup_stage {
+            HCTSIZ::DPID::Setup
+        } else if state.toggle.get() {
+            HCTSIZ::DPID::Data1
+        } else {
+            HCTSIZ::DPID::Data0
+        };
+        channel
+            .hctsiz
+            .write(HCTSIZ::XFRSIZ.val(packet as u32) + HCTSIZ::PKTCNT.val(1) + pid);
+
+        let transfer_type = match target.transfer_type {
+            TransferType::Control => HCCHAR::EPTYP::Control,
+            TransferType::Isochronous => HCCHAR::EPTYP::Isochronous,
+            TransferType::Bulk => HCCHAR::EPTYP::Bulk,
+            TransferType::Interrupt => HCCHAR::EPTYP::Interrupt,
+        };
+        let direction = match target.direction {
+            Direction::In => HCCHAR::EPDIR::In,
+            Direction::Out => HCCHAR::EPDIR::Out,
+        };
+        // Periodic transactions go out in the next frame.
+        let odd_frame = self.registers.hfnum.get() & 1 == 0;
+        channel.hcchar.write(
+            HCCHAR::MPSIZ.val(max_packet_size as u32)
+                + HCCHAR::EPNUM.val(target.number as u32)
+                + direction
+                + HCCHAR::LSDEV.val((self.speed.get() == Speed::Low) as u32)
+                + transfer_type
+                + HCCHAR::MCNT.val(1)
+                + HCCHAR::DAD.val(target.address as u32)
+                + HCCHAR::ODDFRM.val(odd_frame as u32)
+                + HCCHAR::CHENA::SET,
+        );
+
+        if target.direction == Direction::Out && packet > 0 {
+            let space = if matches!(target.transfer_type, TransferType::Interrupt) {
+                self.registers.hptxsts.read(TXSTS::FSAV)
+            } else {
+                self.registers.hnptxsts.read(TXSTS::FSAV)
+            } as usize;
+            if space * 4 < packet {
+                state.outcome.set(Outcome::Failed(ErrorCode::BUSY));
+                self.halt(ch);
+                return;
+            }
+            let fifo = &self.registers.fifos[ch].data;
+            if setup_stage {
+                let setup = self.setup.get();
+                for word in setup.chunks(4) {
+                    fifo.set(u32::from_le_bytes([word[0], word[1], word[2], word[3]]));
+                }
+            } else {
+                let offset = state.offset.get();
+                state.buffer.map(|buffer| {
+                    for word in buffer[offset..offset + packet].chunks(4) {
+                        let mut bytes = [0; 4];
+                        bytes[..word.len()].copy_from_slice(word);
+                        fifo.set(u32::from_le_bytes(bytes));
+                    }
+                });
+            }
+        }
+    }
+
+    /// Disable channel `ch`. Its outcome is handled once it halted.
+    fn halt(&self, ch: usize) {
+        let channel = &self.registers.channels[ch];
+        if channel.hcchar.is_set(HCCHAR::CHENA) {
+            channel
+                .hcchar
+                .modify(HCCHAR::CHDIS::SET + HCCHAR::CHENA::SET);
+        } else {
+            self.halted(ch);
+        }
+    }
+
+    fn halted(&self, ch: usize) {
+        let state = &self.channels[ch];
+        match state.outcome.replace(Outcome::None) {
+            Outcome::None => {}
+            Outcome::Retry => self.start_packet(ch),
+            Outcome::Failed(err) => self.complete(ch, Err(err)),
+            Outcome::Packet => {
+                let target = match self.target(ch) {
+                    Some(target) => target,
+                    None => return,
+                };
+                let packet = state.packet.get();
+                state.offset.set(state.offset.get() + packet);
+                state.toggle.set(!state.toggle.get());
+                let done = state.offset.get() >= state.len.get()
+                    || (target.direction == Direction::In
+                        && packet < target.max_packet_size as usize);
+                if done {
+                    self.complete(ch, Ok(()));
+                } else {
+                    self.start_packet(ch);
+                }
+            }
+        }
+    }
+
+    /// Start moving `len` bytes on channel `ch`.
+    fn start_transfer(&self, ch: usize, buffer: &'static mut [u8], len: usize) {
+        let state = &self.channels[ch];
+        state.buffer.replace(buffer);
+        state.len.set(len);
+        state.offset.set(0);
+        state.errors.set(0);
+        state.outcome.set(Outcome::None);
+        self.start_packet(ch);
+    }
+
+    /// The transfer on channel `ch` finished.
+    fn complete(&self, ch: usize, result: Result<(), ErrorCode>) {
+        let state = &self.channels[ch];
+        if ch != CONTROL {
+            if let Some(buffer) = state.buffer.take() {
+                self.client
+                    .map(|client| client.transfer_done(ch, buffer, state.offset.get(), result));
+            }
+            return;
+        }
+
+        let (target, stage) = match self.control.get() {
+            Some(control) => control,
+            None => return,
+        };
+        let setup = self.setup.get();
+        let data_in = setup[0] & 0x80 != 0;
+        let data_len = u16::from_le_bytes([setup[6], setup[7]]) as usize;
+        let next = match (result, stage) {
+            (Err(_), _) | (Ok(()), ControlStage::Status) => None,
+            (Ok(()), ControlStage::Setup) if data_len > 0 => Some(ControlStage::Data),
+            (Ok(()), ControlStage::Setup) | (Ok(()), ControlStage::Data) => {
+                if stage == ControlStage::Data {
+                    self.control_len.set(state.offset.get());
+                }
+                Some(ControlStage::Status)
+            }
+        };
+        match next {
+            Some(next) => {
+                let (direction, len) = match next {
+                    ControlStage::Data if data_in => (Direction::In, data_len),
+                    ControlStage::Data => (Direction::Out, data_len),
+                    _ if data_in && data_len > 0 => (Direction::Out, 0),
+                    _ => (Direction::In, 0),
+                };
+                self.control.set(Some((
+                    Target {
+                        direction,
+                        ..target
+                    },
+                    next,
+                )));
+                state.len.set(len);
+                state.offset.set(0);
+                state.errors.set(0);
+                state.toggle.set(true);
+                self.start_packet(ch);
+            }
+            None => {
+                self.control.set(None);
+                let len = self.control_len.get();
+                if let Some(buffer) = state.buffer.take() {
+                    self.client
+                        .map(|client| client.control_done(buffer, len, result));
+                }
+            }
+        }
+    }
+
+    fn handle_channel_interrupt(&self, ch: usize) {
+        let channel = &self.registers.channels[ch];
+        let state = &self.channels[ch];
+        let status = channel.hcint.extract();
+        channel.hcint.set(status.get());
+
+        if status.is_set(HCINT::CHH) {
+            self.halted(ch);
+            return;
+        }
+        if status.is_set(HCINT::ACK) {
+            state.errors.set(0);
+        }
+
+        let outcome = if status.is_set(HCINT::XFRC) {
+            Outcome::Packet
+        } else if status.is_set(HCINT::STALL) {
+            Outcome::Failed(ErrorCode::FAIL)
+        } else if status.is_set(HCINT::NAK) || status.is_set(HCINT::FRMOR) {
+            Outcome::Retry
+        } else if status.is_set(HCINT::BBERR) {
+            Outcome::Failed(ErrorCode::NOACK)
+        } else if status.is_set(HCINT::TXERR) || status.is_set(HCINT::DTERR) {
+            let errors = state.errors.get() + 1;
+            state.errors.set(errors);
+            if errors < MAX_ERRORS {
+                Outcome::Retry
+            } else {
+                Outcome::Failed(ErrorCode::NOACK)
+            }
+        } else {
+            return;
+        };
+        state.outcome.set(outcome);
+        self.halt(ch);
+    }
+
+    /// Pop the next entry of the receive FIFO.
+    fn receive_packet(&self) {
+        let status = self.registers.grxstsp.extract();
+        let ch = status.read(GRXSTS::CHNUM) as usize;
+        let count = status.read(GRXSTS::BCNT) as usize;
+        if !status.matches_all(GRXSTS::PKTSTS::InData) {
+            return;
+        }
+
+        let fifo = &self.registers.fifos[0].data;
+        let state = &self.channels[ch];
+        let start = state.offset.get() + state.packet.get();
+        state.buffer.map(|buffer| {
+            let end = state.len.get().min(buffer.len());
+            for word in 0..(count + 3) / 4 {
+                let bytes = fifo.get().to_le_bytes();
+                for (i, byte) in bytes.iter().enumerate() {
+                    let index = start + word * 4 + i;
+                    if word * 4 + i < count && index < end {
+                        buffer[index] = *byte;
+                    }
+                }
+            }
+        });
+        // Bytes beyond the buffer are dropped.
+        let room = state.len.get().saturating_sub(start);
+        state.packet.set(state.packet.get() + count.min(room));
+    }
+
+    fn handle_port_interrupt(&self) {
+        let port = self.registers.hprt.extract();
+        if port.is_set(HPRT::PCDET) {
+            self.port_clear(HPRT::PCDET::SET);
+            self.client.map(|client| client.connected());
+        }
+        if port.is_set(HPRT::POCCHNG) {
+            self.port_clear(HPRT::POCCHNG::SET);
+        }
+        if port.is_set(HPRT::PENCHNG) {
+            self.port_clear(HPRT::PENCHNG::SET);
+            if port.is_set(HPRT::PENA) {
+                let speed = if port.matches_all(HPRT::PSPD::Low) {
+                    self.registers.hcfg.modify(HCFG::FSLSPCS::Clock6MHz);
+                    self.registers.hfir.set(6000);
+                    Speed::Low
+                } else {
+                    self.registers.hcfg.modify(HCFG::FSLSPCS::Clock48MHz);
+                    self.registers.hfir.set(48000);
+                    Speed::Full
+                };
+                self.speed.set(speed);
+                if self.resetting.replace(false) {
+                    self.client.map(|client| client.reset_done(Ok(speed)));
+                }
+            }
+        }
+    }
+
+    /// Abort all transfers and close all pipes.
+    fn abort_all(&self, err: ErrorCode) {
+        for ch in 0..CHANNELS {
+            let channel = &self.registers.channels[ch];
+            if channel.hcchar.is_set(HCCHAR::CHENA) {
+                channel
+                    .hcchar
+                    .modify(HCCHAR::CHDIS::SET + HCCHAR::CHENA::SET);
+            }
+            let state = &self.channels[ch];
+            state.outcome.set(Outcome::None);
+            state.endpoint.set(None);
+            if let Some(buffer) = state.buffer.take() {
+                self.client.map(|client| {
+                    if ch == CONTROL {
+                        client.control_done(buffer, 0, Err(err))
+                    } else {
+                        client.transfer_done(ch, buffer, 0, Err(err))
+                    }
+                });
+            }
+        }
+        self.control.set(None);
+        let _ = self.flush_fifos();
+    }
+
+    pub fn handle_interrupt(&self) {
+        let regs = &*self.registers;
+        let status = regs.gintsts.get() & regs.gintmsk.get();
+
+        while regs.gintsts.is_set(GINT::RXFLVL) {
+            self.receive_packet();
+        }
+        if status & GINT::HPRTINT::SET.value != 0 {
+            self.handle_port_interrupt();
+        }
+        if status & GINT::HCINT::SET.value != 0 {
+            let channels = regs.haint.get();
+            for ch in 0..CHANNELS {
+                if channels & (1 << ch) != 0 {
+                    self.handle_channel_interrupt(ch);
+                }
+            }
+        }
+        if status & GINT::DISCINT::SET.value != 0 {
+            regs.gintsts.write(GINT::DISCINT::SET);
+            self.resetting.set(false);
+            self.abort_all(ErrorCode::NODEVICE);
+            self.client.map(|client| client.disconnected());
+        }
+    }
+}
+
+impl<'a> usb_host::UsbHost<'a> for UsbOtgFs<'a> {
+    fn set_client(&self, client: &'a dyn usb_host::Client) {
+        self.client.set(client);
+    }
+
+    fn enable(&self) -> Result<(), ErrorCode> {
+        self.init_core()?;
+        self.port_modify(HPRT::PPWR::SET);
+        Ok(())
+    }
+
+    fn disable(&self) {
+        self.abort_all(ErrorCode::CANCEL);
+        self.port_modify(HPRT::PPWR::CLEAR);
+        self.registers.gahbcfg.write(GAHBCFG::GINT::CLEAR);
+        self.disable_clock();
+    }
+
+    fn start_reset(&self) -> Result<(), ErrorCode> {
+        if !self.registers.hprt.is_set(HPRT::PCSTS) {
+            return Err(ErrorCode::NODEVICE);
+        }
+        self.abort_all(ErrorCode::CANCEL);
+        self.resetting.set(true);
+        self.port_modify(HPRT::PRST::SET);
+        Ok(())
+    }
+
+    fn end_reset(&self) {
+        self.port_modify(HPRT::PRST::CLEAR);
+    }
+
+    fn control_transfer(
+        &self,
+        address: u8,
+        max_packet_size: u16,
+        setup: [u8; 8],
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if self.control.get().is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        let target = Target {
+            address,
+            number: 0,
+            direction: Direction::Out,
+            transfer_type: TransferType::Control,
+            max_packet_size,
+        };
+        // The data stage moves at most `len` bytes.
+        let mut setup = setup;
+        let data_len = u16::from_le_bytes([setup[6], setup[7]]).min(len as u16);
+        setup[6..8].copy_from_slice(&data_len.to_le_bytes());
+        self.setup.set(setup);
+        self.control.set(Some((target, ControlStage::Setup)));
+        self.control_len.set(0);
+        self.channels[CONTROL].toggle.set(false);
+        // The setup stage sends the 8 bytes of `setup`.
+        self.start_transfer(CONTROL, buffer, 8);
+        Ok(())
+    }
+
+    fn open_pipe(&self, endpoint: Endpoint) -> Result<usize, ErrorCode> {
+        if !matches!(
+            endpoint.transfer_type,
+            TransferType::Bulk | TransferType::Interrupt
+        ) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let ch = (1..CHANNELS)
+            .find(|&ch| {
+                self.channels[ch].endpoint.get().is_none() && self.channels[ch].buffer.is_none()
+            })
+            .ok_or(ErrorCode::NOMEM)?;
+        self.channels[ch].endpoint.set(Some(endpoint));
+        self.channels[ch].toggle.set(false);
+        Ok(ch)
+    }
+
+    fn close_pipe(&self, pipe: usize) {
+        if pipe == CONTROL || pipe >= CHANNELS {
+            return;
+        }
+        let state = &self.channels[pipe];
+        if state.buffer.is_some() {
+            // Keep the target until the channel halted.
+            state.outcome.set(Outcome::Failed(ErrorCode::CANCEL));
+            self.halt(pipe);
+        }
+        state.endpoint.set(None);
+    }
+
+    fn transfer(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if pipe == CONTROL || pipe >= CHANNELS || self.channels[pipe].endpoint.get().is_none() {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        if self.channels[pipe].buffer.is_some() {
+            return Err((ErrorCode::BUSY, buffer));
+        }
+        if len > buffer.len() {
+            return Err((ErrorCode::SIZE, buffer));
+        }
+        self.start_transfer(pipe, buffer, len);
+        Ok(())
+    }
+}
+
+struct UsbOtgFsClock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for UsbOtgFsClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
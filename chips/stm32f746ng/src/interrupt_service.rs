@@ -0,0 +1,31 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+use stm32f7xx::chip::Stm32f7xxDefaultPeripherals;
+
+pub struct Stm32f746ngDefaultPeripherals<'a> {
+    pub stm32f7: Stm32f7xxDefaultPeripherals<'a>,
+    // Once implemented, place Stm32f746ng specific peripherals here
+    pub ltdc: stm32f7xx::ltdc::Ltdc<'a>,
+}
+
+impl<'a> Stm32f746ngDefaultPeripherals<'a> {
+    pub unsafe fn new(rcc: &'a crate::rcc::Rcc, exti: &'a crate::exti::Exti<'a>) -> Self {
+        Self {
+            stm32f7: Stm32f7xxDefaultPeripherals::new(rcc, exti),
+            ltdc: stm32f7xx::ltdc::Ltdc::new(rcc),
+        }
+    }
+    // Necessary for setting up circular dependencies & registering deferred calls
+    pub fn init(&'static self) {
+        self.stm32f7.setup_circular_deps();
+        kernel::deferred_call::DeferredCallClient::register(&self.ltdc);
+    }
+}
+impl<'a> kernel::platform::chip::InterruptService for Stm32f746ngDefaultPeripherals<'a> {
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        // The LTDC is used without interrupts
+        self.stm32f7.service_interrupt(interrupt)
+    }
+}
@@ -0,0 +1,18 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Named constants for NVIC ids specific to this chip
+
+#![allow(non_upper_case_globals)]
+
+pub const LTDC: u32 = 88;
+pub const LTDC_ER: u32 = 89;
+pub const DMA2D: u32 = 90;
+pub const SAI2: u32 = 91;
+pub const QUADSPI: u32 = 92;
+pub const LPTIM1: u32 = 93;
+pub const CEC: u32 = 94;
+pub const I2C4_EV: u32 = 95;
+pub const I2C4_ER: u32 = 96;
+pub const SPDIF_RX: u32 = 97;
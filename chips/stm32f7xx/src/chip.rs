@@ -0,0 +1,150 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Chip trait setup.
+
+use core::fmt::Write;
+use cortexm7::{self, CortexM7, CortexMVariant};
+use kernel::platform::chip::Chip;
+use kernel::platform::chip::InterruptService;
+
+use crate::nvic;
+
+pub struct Stm32f7xx<'a, I: InterruptService + 'a> {
+    mpu: cortexm7::mpu::MPU,
+    userspace_kernel_boundary: cortexm7::syscall::SysCall,
+    interrupt_service: &'a I,
+}
+
+pub struct Stm32f7xxDefaultPeripherals<'a> {
+    pub exti: &'a crate::exti::Exti<'a>,
+    pub fmc: crate::fmc::Fmc<'a>,
+    pub i2c1: crate::i2c::I2C<'a>,
+    pub i2c3: crate::i2c::I2C<'a>,
+    pub tim2: crate::tim2::Tim2<'a>,
+    pub usart1: crate::usart::Usart<'a>,
+    pub usart2: crate::usart::Usart<'a>,
+    pub usart3: crate::usart::Usart<'a>,
+    pub usart6: crate::usart::Usart<'a>,
+    pub gpio_ports: crate::gpio::GpioPorts<'a>,
+}
+
+impl<'a> Stm32f7xxDefaultPeripherals<'a> {
+    pub fn new(rcc: &'a crate::rcc::Rcc, exti: &'a crate::exti::Exti<'a>) -> Self {
+        Self {
+            exti,
+            fmc: crate::fmc::Fmc::new(rcc),
+            i2c1: crate::i2c::I2C::new_i2c1(rcc),
+            i2c3: crate::i2c::I2C::new_i2c3(rcc),
+            tim2: crate::tim2::Tim2::new(rcc),
+            usart1: crate::usart::Usart::new_usart1(rcc),
+            usart2: crate::usart::Usart::new_usart2(rcc),
+            usart3: crate::usart::Usart::new_usart3(rcc),
+            usart6: crate::usart::Usart::new_usart6(rcc),
+            gpio_ports: crate::gpio::GpioPorts::new(rcc, exti),
+        }
+    }
+
+    // Setup any circular dependencies and register deferred calls
+    pub fn setup_circular_deps(&'static self) {
+        self.gpio_ports.setup_circular_deps();
+
+        kernel::deferred_call::DeferredCallClient::register(&self.usart1);
+        kernel::deferred_call::DeferredCallClient::register(&self.usart2);
+        kernel::deferred_call::DeferredCallClient::register(&self.usart3);
+        kernel::deferred_call::DeferredCallClient::register(&self.usart6);
+    }
+}
+
+impl<'a> InterruptService for Stm32f7xxDefaultPeripherals<'a> {
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        match interrupt {
+            nvic::USART1 => self.usart1.handle_interrupt(),
+            nvic::USART2 => self.usart2.handle_interrupt(),
+            nvic::USART3 => self.usart3.handle_interrupt(),
+            nvic::USART6 => self.usart6.handle_interrupt(),
+
+            nvic::I2C1_EV => self.i2c1.handle_event(),
+            nvic::I2C1_ER => self.i2c1.handle_error(),
+            nvic::I2C3_EV => self.i2c3.handle_event(),
+            nvic::I2C3_ER => self.i2c3.handle_error(),
+
+            nvic::EXTI0 => self.exti.handle_interrupt(),
+            nvic::EXTI1 => self.exti.handle_interrupt(),
+            nvic::EXTI2 => self.exti.handle_interrupt(),
+            nvic::EXTI3 => self.exti.handle_interrupt(),
+            nvic::EXTI4 => self.exti.handle_interrupt(),
+            nvic::EXTI9_5 => self.exti.handle_interrupt(),
+            nvic::EXTI15_10 => self.exti.handle_interrupt(),
+
+            nvic::TIM2 => self.tim2.handle_interrupt(),
+
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl<'a, I: InterruptService + 'a> Stm32f7xx<'a, I> {
+    pub unsafe fn new(interrupt_service: &'a I) -> Self {
+        Self {
+            mpu: cortexm7::mpu::MPU::new(),
+            userspace_kernel_boundary: cortexm7::syscall::SysCall::new(),
+            interrupt_service,
+        }
+    }
+}
+
+impl<'a, I: InterruptService + 'a> Chip for Stm32f7xx<'a, I> {
+    type MPU = cortexm7::mpu::MPU;
+    type UserspaceKernelBoundary = cortexm7::syscall::SysCall;
+
+    fn service_pending_interrupts(&self) {
+        unsafe {
+            loop {
+                if let Some(interrupt) = cortexm7::nvic::next_pending() {
+                    if !self.interrupt_service.service_interrupt(interrupt) {
+                        panic!("unhandled interrupt {}", interrupt);
+                    }
+
+                    let n = cortexm7::nvic::Nvic::new(interrupt);
+                    n.clear_pending();
+                    n.enable();
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn has_pending_interrupts(&self) -> bool {
+        unsafe { cortexm7::nvic::has_pending() }
+    }
+
+    fn mpu(&self) -> &cortexm7::mpu::MPU {
+        &self.mpu
+    }
+
+    fn userspace_kernel_boundary(&self) -> &cortexm7::syscall::SysCall {
+        &self.userspace_kernel_boundary
+    }
+
+    fn sleep(&self) {
+        unsafe {
+            cortexm7::scb::unset_sleepdeep();
+            cortexm7::support::wfi();
+        }
+    }
+
+    unsafe fn atomic<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        cortexm7::support::atomic(f)
+    }
+
+    unsafe fn print_state(&self, write: &mut dyn Write) {
+        CortexM7::print_cortexm_state(write);
+    }
+}
@@ -0,0 +1,285 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! SDRAM controller of the Flexible Memory Controller (FMC).
+//!
+//! Only SDRAM bank 1 (`SDCKE0`/`SDNE0`) is supported. Once initialized, the
+//! SDRAM is mapped at `SDRAM_BANK1_ADDRESS` and is used like internal RAM,
+//! for instance as a framebuffer for the LTDC.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! base_peripherals.fmc.enable_clock();
+//! base_peripherals.fmc.init_sdram(&SDRAM_CONFIG);
+//! ```
+
+use crate::rcc;
+use cortexm7::support::nop;
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{register_bitfields, FieldValue, ReadOnly, ReadWrite};
+use kernel::utilities::StaticRef;
+
+/// FMC SDRAM registers
+#[repr(C)]
+struct FmcSdramRegisters {
+    /// SDRAM control register 1
+    sdcr1: ReadWrite<u32, SDCR::Register>,
+    /// SDRAM control register 2
+    sdcr2: ReadWrite<u32, SDCR::Register>,
+    /// SDRAM timing register 1
+    sdtr1: ReadWrite<u32, SDTR::Register>,
+    /// SDRAM timing register 2
+    sdtr2: ReadWrite<u32, SDTR::Register>,
+    /// SDRAM command mode register
+    sdcmr: ReadWrite<u32, SDCMR::Register>,
+    /// SDRAM refresh timer register
+    sdrtr: ReadWrite<u32, SDRTR::Register>,
+    /// SDRAM status register
+    sdsr: ReadOnly<u32, SDSR::Register>,
+}
+
+register_bitfields![u32,
+    SDCR [
+        /// Read pipe
+        RPIPE OFFSET(13) NUMBITS(2) [],
+        /// Burst read
+        RBURST OFFSET(12) NUMBITS(1) [],
+        /// SDRAM clock configuration
+        SDCLK OFFSET(10) NUMBITS(2) [
+            Disabled = 0b00,
+            HclkDividedBy2 = 0b10,
+            HclkDividedBy3 = 0b11
+        ],
+        /// Write protection
+        WP OFFSET(9) NUMBITS(1) [],
+        /// CAS latency
+        CAS OFFSET(7) NUMBITS(2) [],
+        /// Number of internal banks
+        NB OFFSET(6) NUMBITS(1) [
+            TwoBanks = 0,
+            FourBanks = 1
+        ],
+        /// Memory data bus width
+        MWID OFFSET(4) NUMBITS(2) [
+            Bits8 = 0b00,
+            Bits16 = 0b01,
+            Bits32 = 0b10
+        ],
+        /// Number of row address bits, minus 11
+        NR OFFSET(2) NUMBITS(2) [],
+        /// Number of column address bits, minus 8
+        NC OFFSET(0) NUMBITS(2) []
+    ],
+    SDTR [
+        /// Row to column delay
+        TRCD OFFSET(24) NUMBITS(4) [],
+        /// Row precharge delay
+        TRP OFFSET(20) NUMBITS(4) [],
+        /// Recovery delay
+        TWR OFFSET(16) NUMBITS(4) [],
+        /// Row cycle delay
+        TRC OFFSET(12) NUMBITS(4) [],
+        /// Self refresh time
+        TRAS OFFSET(8) NUMBITS(4) [],
+        /// Exit self-refresh delay
+        TXSR OFFSET(4) NUMBITS(4) [],
+        /// Load mode register to active
+        TMRD OFFSET(0) NUMBITS(4) []
+    ],
+    SDCMR [
+        /// Mode register definition
+        MRD OFFSET(9) NUMBITS(13) [],
+        /// Number of auto-refresh, minus 1
+        NRFS OFFSET(5) NUMBITS(4) [],
+        /// Command target bank 1
+        CTB1 OFFSET(4) NUMBITS(1) [],
+        /// Command target bank 2
+        CTB2 OFFSET(3) NUMBITS(1) [],
+        /// Command mode
+        MODE OFFSET(0) NUMBITS(3) [
+            Normal = 0b000,
+            ClockConfigurationEnable = 0b001,
+            PrechargeAll = 0b010,
+            AutoRefresh = 0b011,
+            LoadModeRegister = 0b100,
+            SelfRefresh = 0b101,
+            PowerDown = 0b110
+        ]
+    ],
+    SDRTR [
+        /// RES interrupt enable
+        REIE OFFSET(14) NUMBITS(1) [],
+        /// Refresh timer count
+        COUNT OFFSET(1) NUMBITS(13) [],
+        /// Clear refresh error flag
+        CRE OFFSET(0) NUMBITS(1) []
+    ],
+    SDSR [
+        /// Busy status
+        BUSY OFFSET(5) NUMBITS(1) [],
+        /// Status mode for bank 1
+        MODES1 OFFSET(1) NUMBITS(2) [],
+        /// Refresh error flag
+        RE OFFSET(0) NUMBITS(1) []
+    ]
+];
+
+const FMC_SDRAM_BASE: StaticRef<FmcSdramRegisters> =
+    unsafe { StaticRef::new(0xA000_0140 as *const FmcSdramRegisters) };
+
+/// Address at which SDRAM bank 1 is mapped.
+pub const SDRAM_BANK1_ADDRESS: usize = 0xC000_0000;
+
+#[derive(Copy, Clone)]
+pub enum SdramWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+/// Geometry and timings of an SDRAM chip.
+///
+/// The timings are in SDRAM clock cycles, which run at half of HCLK.
+pub struct SdramConfig {
+    /// Number of column address bits, 8 to 11
+    pub column_bits: u8,
+    /// Number of row address bits, 11 to 13
+    pub row_bits: u8,
+    pub width: SdramWidth,
+    /// Whether the chip has four internal banks, rather than two
+    pub four_banks: bool,
+    /// CAS latency, 1 to 3
+    pub cas_latency: u8,
+    /// Load mode register to active delay
+    pub load_to_active: u8,
+    /// Exit self-refresh delay
+    pub exit_self_refresh: u8,
+    /// Self refresh time
+    pub self_refresh: u8,
+    /// Row cycle delay
+    pub row_cycle: u8,
+    /// Recovery delay
+    pub write_recovery: u8,
+    /// Row precharge delay
+    pub row_precharge: u8,
+    /// Row to column delay
+    pub row_to_column: u8,
+    /// Time between two row refreshes in ns, i.e. the refresh period
+    /// divided by the number of rows
+    pub refresh_interval_ns: u32,
+}
+
+pub struct Fmc<'a> {
+    registers: StaticRef<FmcSdramRegisters>,
+    clock: FmcClock<'a>,
+}
+
+impl<'a> Fmc<'a> {
+    pub const fn new(rcc: &'a rcc::Rcc) -> Self {
+        Self {
+            registers: FMC_SDRAM_BASE,
+            clock: FmcClock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::AHB3(rcc::HCLK3::FMC),
+                rcc,
+            )),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Configure SDRAM bank 1 and run the SDRAM power-up sequence.
+    ///
+    /// The FMC clock and the pins have to be enabled before. This blocks
+    /// for about 100 µs, as required by the SDRAM after its clock starts.
+    pub fn init_sdram(&self, config: &SdramConfig) {
+        let width = match config.width {
+            SdramWidth::Bits8 => SDCR::MWID::Bits8,
+            SdramWidth::Bits16 => SDCR::MWID::Bits16,
+            SdramWidth::Bits32 => SDCR::MWID::Bits32,
+        };
+        let banks = if config.four_banks {
+            SDCR::NB::FourBanks
+        } else {
+            SDCR::NB::TwoBanks
+        };
+        self.registers.sdcr1.write(
+            SDCR::NC.val(config.column_bits.saturating_sub(8) as u32)
+                + SDCR::NR.val(config.row_bits.saturating_sub(11) as u32)
+                + width
+                + banks
+                + SDCR::CAS.val(config.cas_latency as u32)
+                + SDCR::SDCLK::HclkDividedBy2
+                + SDCR::RBURST::SET,
+        );
+
+        // The timing registers hold the delays minus one
+        let delay = |cycles: u8| cycles.saturating_sub(1) as u32;
+        self.registers.sdtr1.write(
+            SDTR::TMRD.val(delay(config.load_to_active))
+                + SDTR::TXSR.val(delay(config.exit_self_refresh))
+                + SDTR::TRAS.val(delay(config.self_refresh))
+                + SDTR::TRC.val(delay(config.row_cycle))
+                + SDTR::TWR.val(delay(config.write_recovery))
+                + SDTR::TRP.val(delay(config.row_precharge))
+                + SDTR::TRCD.val(delay(config.row_to_column)),
+        );
+
+        self.command(SDCMR::MODE::ClockConfigurationEnable, 0, 0);
+        // The SDRAM needs at least 100 µs of stable clock, at most 216
+        // cycles per µs
+        for _ in 0..(216 * 100) {
+            nop();
+        }
+        self.command(SDCMR::MODE::PrechargeAll, 0, 0);
+        self.command(SDCMR::MODE::AutoRefresh, 7, 0);
+
+        // Burst length 1, sequential, standard operation and single write
+        // bursts
+        let mode = ((config.cas_latency as u32) << 4) | (1 << 9);
+        self.command(SDCMR::MODE::LoadModeRegister, 0, mode);
+
+        // The count is decreased by 20 to account for the refresh of the
+        // rows being read
+        let sdclk_khz = self.clock.0.get_frequency() / 2 / 1000;
+        let count = sdclk_khz * config.refresh_interval_ns / 1_000_000 - 20;
+        self.registers.sdrtr.write(SDRTR::COUNT.val(count));
+    }
+
+    fn command(&self, mode: FieldValue<u32, SDCMR::Register>, refreshes: u32, mode_register: u32) {
+        while self.registers.sdsr.is_set(SDSR::BUSY) {}
+        self.registers.sdcmr.write(
+            mode + SDCMR::CTB1::SET + SDCMR::NRFS.val(refreshes) + SDCMR::MRD.val(mode_register),
+        );
+        while self.registers.sdsr.is_set(SDSR::BUSY) {}
+    }
+}
+
+struct FmcClock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for FmcClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
@@ -0,0 +1,64 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Peripheral implementations for the STM32F7xx MCU.
+//!
+//! STM32F746NG: <https://www.st.com/en/microcontrollers/stm32f7-series.html>
+
+#![crate_name = "stm32f7xx"]
+#![crate_type = "rlib"]
+#![no_std]
+
+pub mod chip;
+pub mod nvic;
+
+// Peripherals
+pub mod exti;
+pub mod fmc;
+pub mod gpio;
+pub mod i2c;
+pub mod ltdc;
+pub mod rcc;
+pub mod syscfg;
+pub mod tim2;
+pub mod usart;
+
+use cortexm7::{initialize_ram_jump_to_main, unhandled_interrupt, CortexM7, CortexMVariant};
+
+extern "C" {
+    // _estack is not really a function, but it makes the types work
+    // You should never actually invoke it!!
+    fn _estack();
+}
+
+#[cfg_attr(
+    all(target_arch = "arm", target_os = "none"),
+    link_section = ".vectors"
+)]
+// used Ensures that the symbol is kept until the final binary
+#[cfg_attr(all(target_arch = "arm", target_os = "none"), used)]
+pub static BASE_VECTORS: [unsafe extern "C" fn(); 16] = [
+    _estack,
+    initialize_ram_jump_to_main,
+    unhandled_interrupt,          // NMI
+    CortexM7::HARD_FAULT_HANDLER, // Hard Fault
+    unhandled_interrupt,          // MemManage
+    unhandled_interrupt,          // BusFault
+    unhandled_interrupt,          // UsageFault
+    unhandled_interrupt,
+    unhandled_interrupt,
+    unhandled_interrupt,
+    unhandled_interrupt,
+    CortexM7::SVC_HANDLER, // SVC
+    unhandled_interrupt,   // DebugMon
+    unhandled_interrupt,
+    unhandled_interrupt,       // PendSV
+    CortexM7::SYSTICK_HANDLER, // SysTick
+];
+
+pub unsafe fn init() {
+    cortexm7::nvic::disable_all();
+    cortexm7::nvic::clear_all_pending();
+    cortexm7::nvic::enable_all();
+}
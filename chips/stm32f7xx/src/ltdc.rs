@@ -0,0 +1,654 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! LCD-TFT display controller (LTDC).
+//!
+//! The LTDC continuously refreshes an RGB panel from a framebuffer in memory.
+//! This driver uses a single layer that covers the whole panel, and exposes
+//! the framebuffer through `hil::screen`: writes are copied into the current
+//! write frame of the framebuffer, and show up on the next refresh. As no
+//! bus transfer is involved, all callbacks are delivered from a deferred
+//! call.
+//!
+//! Pixels are written most significant byte first, like to the displays
+//! with a serial or parallel bus, and are stored in the little endian order
+//! that the LTDC reads.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let framebuffer = core::slice::from_raw_parts_mut(
+//!     stm32f7xx::fmc::SDRAM_BANK1_ADDRESS as *mut u8,
+//!     480 * 272 * 2,
+//! );
+//! ltdc.init(&RK043FN48H, framebuffer, ScreenPixelFormat::RGB_565)?;
+//! ltdc.set_pins(Some(display_enable_pin), Some(backlight_pin));
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::gpio;
+use kernel::hil::screen::{
+    Screen, ScreenClient, ScreenPixelFormat, ScreenRotation, ScreenSetup, ScreenSetupClient,
+};
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::registers::interfaces::{ReadWriteable, Writeable};
+use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+use crate::rcc;
+
+/// LTDC layer
+#[repr(C)]
+struct LtdcLayerRegisters {
+    /// layer control register
+    cr: ReadWrite<u32, LxCR::Register>,
+    /// layer window horizontal position configuration register
+    whpcr: ReadWrite<u32, LxWHPCR::Register>,
+    /// layer window vertical position configuration register
+    wvpcr: ReadWrite<u32, LxWVPCR::Register>,
+    /// layer color keying configuration register
+    ckcr: ReadWrite<u32>,
+    /// layer pixel format configuration register
+    pfcr: ReadWrite<u32, LxPFCR::Register>,
+    /// layer constant alpha configuration register
+    cacr: ReadWrite<u32>,
+    /// layer default color configuration register
+    dccr: ReadWrite<u32>,
+    /// layer blending factors configuration register
+    bfcr: ReadWrite<u32, LxBFCR::Register>,
+    _reserved0: [u32; 2],
+    /// layer color frame buffer address register
+    cfbar: ReadWrite<u32>,
+    /// layer color frame buffer length register
+    cfblr: ReadWrite<u32, LxCFBLR::Register>,
+    /// layer color frame buffer line number register
+    cfblnr: ReadWrite<u32>,
+    _reserved1: [u32; 3],
+    /// layer CLUT write register
+    clutwr: WriteOnly<u32>,
+    _reserved2: [u32; 15],
+}
+
+/// LCD-TFT display controller
+#[repr(C)]
+struct LtdcRegisters {
+    _reserved0: [u32; 2],
+    /// synchronization size configuration register
+    sscr: ReadWrite<u32, SSCR::Register>,
+    /// back porch configuration register
+    bpcr: ReadWrite<u32, BPCR::Register>,
+    /// active width configuration register
+    awcr: ReadWrite<u32, AWCR::Register>,
+    /// total width configuration register
+    twcr: ReadWrite<u32, TWCR::Register>,
+    /// global control register
+    gcr: ReadWrite<u32, GCR::Register>,
+    _reserved1: [u32; 2],
+    /// shadow reload configuration register
+    srcr: ReadWrite<u32, SRCR::Register>,
+    _reserved2: u32,
+    /// background color configuration register
+    bccr: ReadWrite<u32>,
+    _reserved3: u32,
+    /// interrupt enable register
+    ier: ReadWrite<u32>,
+    /// interrupt status register
+    isr: ReadOnly<u32>,
+    /// interrupt clear register
+    icr: WriteOnly<u32>,
+    /// line interrupt position configuration register
+    lipcr: ReadWrite<u32>,
+    /// current position status register
+    cpsr: ReadOnly<u32>,
+    /// current display status register
+    cdsr: ReadOnly<u32>,
+    _reserved4: [u32; 14],
+    layers: [LtdcLayerRegisters; 2],
+}
+
+register_bitfields![u32,
+    SSCR [
+        /// Horizontal synchronization width (in units of pixel clock period)
+        HSW OFFSET(16) NUMBITS(12) [],
+        /// Vertical synchronization height (in units of horizontal scan line)
+        VSH OFFSET(0) NUMBITS(11) []
+    ],
+    BPCR [
+        /// Accumulated horizontal back porch
+        AHBP OFFSET(16) NUMBITS(12) [],
+        /// Accumulated vertical back porch
+        AVBP OFFSET(0) NUMBITS(11) []
+    ],
+    AWCR [
+        /// Accumulated active width
+        AAW OFFSET(16) NUMBITS(12) [],
+        /// Accumulated active height
+        AAH OFFSET(0) NUMBITS(11) []
+    ],
+    TWCR [
+        /// Total width
+        TOTALW OFFSET(16) NUMBITS(12) [],
+        /// Total height
+        TOTALH OFFSET(0) NUMBITS(11) []
+    ],
+    GCR [
+        /// Horizontal synchronization polarity
+        HSPOL OFFSET(31) NUMBITS(1) [],
+        /// Vertical synchronization polarity
+        VSPOL OFFSET(30) NUMBITS(1) [],
+        /// Data enable polarity
+        DEPOL OFFSET(29) NUMBITS(1) [],
+        /// Pixel clock polarity
+        PCPOL OFFSET(28) NUMBITS(1) [],
+        /// Dither enable
+        DEN OFFSET(16) NUMBITS(1) [],
+        /// LCD-TFT controller enable
+        LTDCEN OFFSET(0) NUMBITS(1) []
+    ],
+    SRCR [
+        /// Vertical blanking reload
+        VBR OFFSET(1) NUMBITS(1) [],
+        /// Immediate reload
+        IMR OFFSET(0) NUMBITS(1) []
+    ],
+    LxCR [
+        /// Color look-up table enable
+        CLUTEN OFFSET(4) NUMBITS(1) [],
+        /// Color keying enable
+        COLKEN OFFSET(1) NUMBITS(1) [],
+        /// Layer enable
+        LEN OFFSET(0) NUMBITS(1) []
+    ],
+    LxWHPCR [
+        /// Window horizontal stop position
+        WHSPPOS OFFSET(16) NUMBITS(12) [],
+        /// Window horizontal start position
+        WHSTPOS OFFSET(0) NUMBITS(12) []
+    ],
+    LxWVPCR [
+        /// Window vertical stop position
+        WVSPPOS OFFSET(16) NUMBITS(11) [],
+        /// Window vertical start position
+        WVSTPOS OFFSET(0) NUMBITS(11) []
+    ],
+    LxPFCR [
+        /// Pixel format
+        PF OFFSET(0) NUMBITS(3) [
+            ARGB8888 = 0b000,
+            RGB888 = 0b001,
+            RGB565 = 0b010
+        ]
+    ],
+    LxBFCR [
+        /// Blending factor 1
+        BF1 OFFSET(8) NUMBITS(3) [
+            ConstantAlpha = 0b100,
+            PixelAlphaTimesConstantAlpha = 0b110
+        ],
+        /// Blending factor 2
+        BF2 OFFSET(0) NUMBITS(3) [
+            OneMinusConstantAlpha = 0b101,
+            OneMinusPixelAlphaTimesConstantAlpha = 0b111
+        ]
+    ],
+    LxCFBLR [
+        /// Color frame buffer pitch in bytes
+        CFBP OFFSET(16) NUMBITS(13) [],
+        /// Color frame buffer line length, in bytes plus 3
+        CFBLL OFFSET(0) NUMBITS(13) []
+    ]
+];
+
+const LTDC_BASE: StaticRef<LtdcRegisters> =
+    unsafe { StaticRef::new(0x4001_6800 as *const LtdcRegisters) };
+
+/// Timings of an RGB panel.
+///
+/// The horizontal timings are in pixel clock cycles, the vertical ones in
+/// lines.
+pub struct PanelTimings {
+    pub width: usize,
+    pub height: usize,
+    pub hsync_width: usize,
+    pub hback_porch: usize,
+    pub hfront_porch: usize,
+    pub vsync_height: usize,
+    pub vback_porch: usize,
+    pub vfront_porch: usize,
+    /// Whether the synchronization and data enable signals are active high
+    pub active_high: bool,
+    pub pixel_clock_khz: u32,
+}
+
+const PIXEL_FORMATS: [ScreenPixelFormat; 3] = [
+    ScreenPixelFormat::RGB_565,
+    ScreenPixelFormat::RGB_888,
+    ScreenPixelFormat::ARGB_8888,
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum PendingCall {
+    None,
+    CommandComplete(Result<(), ErrorCode>),
+    SetupComplete(Result<(), ErrorCode>),
+    WriteComplete(Result<(), ErrorCode>),
+    ScreenReady,
+}
+
+pub struct Ltdc<'a> {
+    registers: StaticRef<LtdcRegisters>,
+    clock: LtdcClock<'a>,
+    rcc: &'a rcc::Rcc,
+
+    framebuffer: TakeCell<'static, [u8]>,
+    width: Cell<usize>,
+    height: Cell<usize>,
+    pixel_format: Cell<ScreenPixelFormat>,
+
+    /// The write frame: x, y, width and height in pixels
+    frame: Cell<(usize, usize, usize, usize)>,
+    /// Position of the next byte in the write frame
+    position: Cell<usize>,
+
+    display_enable: OptionalCell<&'a dyn gpio::Pin>,
+    backlight: OptionalCell<&'a dyn gpio::Pin>,
+    brightness: Cell<usize>,
+    powered: Cell<bool>,
+
+    client: OptionalCell<&'a dyn ScreenClient>,
+    setup_client: OptionalCell<&'a dyn ScreenSetupClient>,
+    buffer: TakeCell<'static, [u8]>,
+    pending: Cell<PendingCall>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> Ltdc<'a> {
+    pub fn new(rcc: &'a rcc::Rcc) -> Self {
+        Self {
+            registers: LTDC_BASE,
+            clock: LtdcClock(rcc::PeripheralClock::new(
+                rcc::PeripheralClockType::APB2(rcc::PCLK2::LTDC),
+                rcc,
+            )),
+            rcc,
+            framebuffer: TakeCell::empty(),
+            width: Cell::new(0),
+            height: Cell::new(0),
+            pixel_format: Cell::new(ScreenPixelFormat::RGB_565),
+            frame: Cell::new((0, 0, 0, 0)),
+            position: Cell::new(0),
+            display_enable: OptionalCell::empty(),
+            backlight: OptionalCell::empty(),
+            brightness: Cell::new(1),
+            powered: Cell::new(false),
+            client: OptionalCell::empty(),
+            setup_client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            pending: Cell::new(PendingCall::None),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn is_enabled_clock(&self) -> bool {
+        self.clock.is_enabled()
+    }
+
+    pub fn enable_clock(&self) {
+        self.clock.enable();
+    }
+
+    pub fn disable_clock(&self) {
+        self.clock.disable();
+    }
+
+    /// Set the GPIO pins that enable the panel and its backlight. Both are
+    /// active high and are driven by `set_power` and `set_brightness`.
+    pub fn set_pins(
+        &self,
+        display_enable: Option<&'a dyn gpio::Pin>,
+        backlight: Option<&'a dyn gpio::Pin>,
+    ) {
+        if let Some(pin) = display_enable {
+            pin.make_output();
+            pin.clear();
+            self.display_enable.set(pin);
+        }
+        if let Some(pin) = backlight {
+            pin.make_output();
+            pin.clear();
+            self.backlight.set(pin);
+        }
+    }
+
+    /// Configure the controller for `panel` and the layer for `framebuffer`.
+    ///
+    /// This starts the pixel clock and enables the LTDC clock. The panel
+    /// stays off until `set_power` is called. The framebuffer has to hold a
+    /// full frame in `pixel_format`, otherwise `SIZE` is returned.
+    pub fn init(
+        &self,
+        panel: &PanelTimings,
+        framebuffer: &'static mut [u8],
+        pixel_format: ScreenPixelFormat,
+    ) -> Result<(), ErrorCode> {
+        if !PIXEL_FORMATS.contains(&pixel_format) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        if framebuffer.len() < panel.width * panel.height * Self::bytes_per_pixel(pixel_format) {
+            return Err(ErrorCode::SIZE);
+        }
+        self.rcc.enable_ltdc_pixel_clock(panel.pixel_clock_khz)?;
+        self.enable_clock();
+
+        let hsync = panel.hsync_width as u32;
+        let vsync = panel.vsync_height as u32;
+        let ahbp = hsync + panel.hback_porch as u32;
+        let avbp = vsync + panel.vback_porch as u32;
+        let aaw = ahbp + panel.width as u32;
+        let aah = avbp + panel.height as u32;
+        self.registers
+            .sscr
+            .write(SSCR::HSW.val(hsync - 1) + SSCR::VSH.val(vsync - 1));
+        self.registers
+            .bpcr
+            .write(BPCR::AHBP.val(ahbp - 1) + BPCR::AVBP.val(avbp - 1));
+        self.registers
+            .awcr
+            .write(AWCR::AAW.val(aaw - 1) + AWCR::AAH.val(aah - 1));
+        self.registers.twcr.write(
+            TWCR::TOTALW.val(aaw + panel.hfront_porch as u32 - 1)
+                + TWCR::TOTALH.val(aah + panel.vfront_porch as u32 - 1),
+        );
+        let polarity = panel.active_high as u32;
+        self.registers.gcr.write(
+            GCR::HSPOL.val(polarity)
+                + GCR::VSPOL.val(polarity)
+                + GCR::DEPOL.val(polarity)
+                + GCR::PCPOL::CLEAR,
+        );
+        self.registers.bccr.set(0);
+
+        let layer = &self.registers.layers[0];
+        layer
+            .whpcr
+            .write(LxWHPCR::WHSTPOS.val(ahbp) + LxWHPCR::WHSPPOS.val(aaw - 1));
+        layer
+            .wvpcr
+            .write(LxWVPCR::WVSTPOS.val(avbp) + LxWVPCR::WVSPPOS.val(aah - 1));
+        layer.cacr.set(0xFF);
+        layer.dccr.set(0);
+        layer.bfcr.write(
+            LxBFCR::BF1::PixelAlphaTimesConstantAlpha
+                + LxBFCR::BF2::OneMinusPixelAlphaTimesConstantAlpha,
+        );
+        layer.cfbar.set(framebuffer.as_ptr() as u32);
+        layer.cfblnr.set(panel.height as u32);
+        layer.cr.write(LxCR::LEN::SET);
+        self.registers.layers[1].cr.write(LxCR::LEN::CLEAR);
+
+        self.width.set(panel.width);
+        self.height.set(panel.height);
+        self.framebuffer.replace(framebuffer);
+        self.configure_pixel_format(pixel_format);
+        Ok(())
+    }
+
+    fn bytes_per_pixel(pixel_format: ScreenPixelFormat) -> usize {
+        pixel_format.get_bits_per_pixel() / 8
+    }
+
+    fn configure_pixel_format(&self, pixel_format: ScreenPixelFormat) {
+        let pf = match pixel_format {
+            ScreenPixelFormat::ARGB_8888 => LxPFCR::PF::ARGB8888,
+            ScreenPixelFormat::RGB_888 => LxPFCR::PF::RGB888,
+            _ => LxPFCR::PF::RGB565,
+        };
+        let line_length = (self.width.get() * Self::bytes_per_pixel(pixel_format)) as u32;
+        let layer = &self.registers.layers[0];
+        layer.pfcr.write(pf);
+        layer
+            .cfblr
+            .write(LxCFBLR::CFBP.val(line_length) + LxCFBLR::CFBLL.val(line_length + 3));
+        // Reload during the vertical blanking, so no frame shows a mix of
+        // both formats
+        self.registers.srcr.write(SRCR::VBR::SET);
+        self.pixel_format.set(pixel_format);
+        self.frame.set((0, 0, self.width.get(), self.height.get()));
+        self.position.set(0);
+    }
+
+    fn schedule(&self, call: PendingCall) -> Result<(), ErrorCode> {
+        if self.pending.get() != PendingCall::None {
+            return Err(ErrorCode::BUSY);
+        }
+        self.pending.set(call);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn update_backlight(&self) {
+        let on = self.powered.get() && self.brightness.get() > 0;
+        self.backlight
+            .map(|pin| if on { pin.set() } else { pin.clear() });
+    }
+
+    /// Copy `data` into the write frame, starting at the current position.
+    fn copy_to_frame(&self, data: &[u8]) -> Result<(), ErrorCode> {
+        let bpp = Self::bytes_per_pixel(self.pixel_format.get());
+        let (x, y, width, height) = self.frame.get();
+        let frame_len = width * height * bpp;
+        let position = self.position.get();
+        if position + data.len() > frame_len {
+            return Err(ErrorCode::INVAL);
+        }
+        let screen_width = self.width.get();
+        self.framebuffer.map(|framebuffer| {
+            for (offset, byte) in (position..).zip(data.iter()) {
+                let pixel = offset / bpp;
+                let (row, column) = (pixel / width, pixel % width);
+                let index = ((y + row) * screen_width + x + column) * bpp;
+                framebuffer[index + bpp - 1 - offset % bpp] = *byte;
+            }
+        });
+        self.position.set(position + data.len());
+        Ok(())
+    }
+
+    /// Copy `len` bytes of `buffer` into the write frame, from its start or
+    /// from the current position. The buffer is always returned through
+    /// `write_complete`, along with the result of the copy.
+    fn write_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        continue_write: bool,
+    ) -> Result<(), ErrorCode> {
+        if self.pending.get() != PendingCall::None {
+            return Err(ErrorCode::BUSY);
+        }
+        if !continue_write {
+            self.position.set(0);
+        }
+        let result = buffer
+            .get(..len)
+            .ok_or(ErrorCode::INVAL)
+            .and_then(|data| self.copy_to_frame(data));
+        self.buffer.replace(buffer);
+        self.schedule(PendingCall::WriteComplete(result))
+    }
+}
+
+impl<'a> Screen<'a> for Ltdc<'a> {
+    fn get_resolution(&self) -> (usize, usize) {
+        (self.width.get(), self.height.get())
+    }
+
+    fn get_pixel_format(&self) -> ScreenPixelFormat {
+        self.pixel_format.get()
+    }
+
+    fn get_rotation(&self) -> ScreenRotation {
+        ScreenRotation::Normal
+    }
+
+    fn set_write_frame(
+        &self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Result<(), ErrorCode> {
+        if width == 0
+            || height == 0
+            || x + width > self.width.get()
+            || y + height > self.height.get()
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        self.schedule(PendingCall::CommandComplete(Ok(())))?;
+        self.frame.set((x, y, width, height));
+        self.position.set(0);
+        Ok(())
+    }
+
+    fn write(&self, buffer: &'static mut [u8], len: usize) -> Result<(), ErrorCode> {
+        self.write_buffer(buffer, len, false)
+    }
+
+    fn write_continue(&self, buffer: &'static mut [u8], len: usize) -> Result<(), ErrorCode> {
+        self.write_buffer(buffer, len, true)
+    }
+
+    fn set_client(&self, client: Option<&'a dyn ScreenClient>) {
+        match client {
+            Some(client) => self.client.set(client),
+            None => self.client.clear(),
+        }
+    }
+
+    fn set_brightness(&self, brightness: usize) -> Result<(), ErrorCode> {
+        self.schedule(PendingCall::CommandComplete(Ok(())))?;
+        self.brightness.set(brightness);
+        self.update_backlight();
+        Ok(())
+    }
+
+    fn set_power(&self, enabled: bool) -> Result<(), ErrorCode> {
+        if self.framebuffer.is_none() {
+            return Err(ErrorCode::OFF);
+        }
+        self.schedule(PendingCall::ScreenReady)?;
+        self.registers.gcr.modify(GCR::LTDCEN.val(enabled as u32));
+        self.display_enable
+            .map(|pin| if enabled { pin.set() } else { pin.clear() });
+        self.powered.set(enabled);
+        self.update_backlight();
+        Ok(())
+    }
+
+    fn set_invert(&self, _enabled: bool) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+impl<'a> ScreenSetup<'a> for Ltdc<'a> {
+    fn set_client(&self, client: Option<&'a dyn ScreenSetupClient>) {
+        match client {
+            Some(client) => self.setup_client.set(client),
+            None => self.setup_client.clear(),
+        }
+    }
+
+    fn set_resolution(&self, resolution: (usize, usize)) -> Result<(), ErrorCode> {
+        if resolution != (self.width.get(), self.height.get()) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.schedule(PendingCall::SetupComplete(Ok(())))
+    }
+
+    fn set_pixel_format(&self, pixel_format: ScreenPixelFormat) -> Result<(), ErrorCode> {
+        if !PIXEL_FORMATS.contains(&pixel_format) {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        let frame_size = self.width.get() * self.height.get() * Self::bytes_per_pixel(pixel_format);
+        if self.framebuffer.map_or(0, |framebuffer| framebuffer.len()) < frame_size {
+            return Err(ErrorCode::INVAL);
+        }
+        self.schedule(PendingCall::SetupComplete(Ok(())))?;
+        self.configure_pixel_format(pixel_format);
+        Ok(())
+    }
+
+    fn set_rotation(&self, rotation: ScreenRotation) -> Result<(), ErrorCode> {
+        if rotation != ScreenRotation::Normal {
+            return Err(ErrorCode::NOSUPPORT);
+        }
+        self.schedule(PendingCall::SetupComplete(Ok(())))
+    }
+
+    fn get_num_supported_resolutions(&self) -> usize {
+        1
+    }
+
+    fn get_supported_resolution(&self, index: usize) -> Option<(usize, usize)> {
+        match index {
+            0 => Some((self.width.get(), self.height.get())),
+            _ => None,
+        }
+    }
+
+    fn get_num_supported_pixel_formats(&self) -> usize {
+        PIXEL_FORMATS.len()
+    }
+
+    fn get_supported_pixel_format(&self, index: usize) -> Option<ScreenPixelFormat> {
+        PIXEL_FORMATS.get(index).copied()
+    }
+}
+
+impl DeferredCallClient for Ltdc<'_> {
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+
+    fn handle_deferred_call(&self) {
+        match self.pending.replace(PendingCall::None) {
+            PendingCall::None => {}
+            PendingCall::CommandComplete(r) => {
+                self.client.map(|client| client.command_complete(r));
+            }
+            PendingCall::SetupComplete(r) => {
+                self.setup_client.map(|client| client.command_complete(r));
+            }
+            PendingCall::WriteComplete(r) => {
+                self.buffer.take().map(|buffer| {
+                    self.client.map(|client| client.write_complete(buffer, r));
+                });
+            }
+            PendingCall::ScreenReady => {
+                self.client.map(|client| client.screen_is_ready());
+            }
+        }
+    }
+}
+
+struct LtdcClock<'a>(rcc::PeripheralClock<'a>);
+
+impl ClockInterface for LtdcClock<'_> {
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+
+    fn enable(&self) {
+        self.0.enable();
+    }
+
+    fn disable(&self) {
+        self.0.disable();
+    }
+}
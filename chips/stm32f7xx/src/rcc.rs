@@ -0,0 +1,561 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Reset and clock control for the STM32F7 family.
+//!
+//! After reset the system runs from the 16 MHz HSI oscillator.
+//! `Rcc::enable_pll_system_clock` switches it to the main PLL. The bus
+//! frequencies are always derived from the registers, so peripherals that
+//! depend on them (USART baud rates, timer prescalers, I2C timings) can ask
+//! their `PeripheralClock` for the current frequency.
+
+use kernel::platform::chip::ClockInterface;
+use kernel::utilities::registers::interfaces::{ReadWriteable, Readable};
+use kernel::utilities::registers::{register_bitfields, Field, ReadWrite};
+use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
+
+/// Reset and clock control
+#[repr(C)]
+struct RccRegisters {
+    /// clock control register
+    cr: ReadWrite<u32, CR::Register>,
+    /// PLL configuration register
+    pllcfgr: ReadWrite<u32, PLLCFGR::Register>,
+    /// clock configuration register
+    cfgr: ReadWrite<u32, CFGR::Register>,
+    /// clock interrupt register
+    cir: ReadWrite<u32>,
+    /// AHB1 peripheral reset register
+    ahb1rstr: ReadWrite<u32>,
+    /// AHB2 peripheral reset register
+    ahb2rstr: ReadWrite<u32>,
+    /// AHB3 peripheral reset register
+    ahb3rstr: ReadWrite<u32>,
+    _reserved0: [u8; 4],
+    /// APB1 peripheral reset register
+    apb1rstr: ReadWrite<u32>,
+    /// APB2 peripheral reset register
+    apb2rstr: ReadWrite<u32>,
+    _reserved1: [u8; 8],
+    /// AHB1 peripheral clock register
+    ahb1enr: ReadWrite<u32, AHB1ENR::Register>,
+    /// AHB2 peripheral clock enable register
+    ahb2enr: ReadWrite<u32>,
+    /// AHB3 peripheral clock enable register
+    ahb3enr: ReadWrite<u32, AHB3ENR::Register>,
+    _reserved2: [u8; 4],
+    /// APB1 peripheral clock enable register
+    apb1enr: ReadWrite<u32, APB1ENR::Register>,
+    /// APB2 peripheral clock enable register
+    apb2enr: ReadWrite<u32, APB2ENR::Register>,
+    _reserved3: [u8; 8],
+    /// AHB1 peripheral clock enable in low power mode register
+    ahb1lpenr: ReadWrite<u32>,
+    /// AHB2 peripheral clock enable in low power mode register
+    ahb2lpenr: ReadWrite<u32>,
+    /// AHB3 peripheral clock enable in low power mode register
+    ahb3lpenr: ReadWrite<u32>,
+    _reserved4: [u8; 4],
+    /// APB1 peripheral clock enable in low power mode register
+    apb1lpenr: ReadWrite<u32>,
+    /// APB2 peripheral clock enabled in low power mode register
+    apb2lpenr: ReadWrite<u32>,
+    _reserved5: [u8; 8],
+    /// Backup domain control register
+    bdcr: ReadWrite<u32>,
+    /// clock control & status register
+    csr: ReadWrite<u32>,
+    _reserved6: [u8; 8],
+    /// spread spectrum clock generation register
+    sscgr: ReadWrite<u32>,
+    /// PLLI2S configuration register
+    plli2scfgr: ReadWrite<u32>,
+    /// PLLSAI configuration register
+    pllsaicfgr: ReadWrite<u32, PLLSAICFGR::Register>,
+    /// dedicated clocks configuration register 1
+    dckcfgr1: ReadWrite<u32, DCKCFGR1::Register>,
+    /// dedicated clocks configuration register 2
+    dckcfgr2: ReadWrite<u32>,
+}
+
+/// Flash interface, of which only the access control register is used to
+/// set the wait states for the system clock.
+#[repr(C)]
+struct FlashRegisters {
+    /// Flash access control register
+    acr: ReadWrite<u32, ACR::Register>,
+}
+
+register_bitfields![u32,
+    CR [
+        /// PLLSAI clock ready flag
+        PLLSAIRDY OFFSET(29) NUMBITS(1) [],
+        /// PLLSAI enable
+        PLLSAION OFFSET(28) NUMBITS(1) [],
+        /// Main PLL (PLL) clock ready flag
+        PLLRDY OFFSET(25) NUMBITS(1) [],
+        /// Main PLL (PLL) enable
+        PLLON OFFSET(24) NUMBITS(1) [],
+        /// HSE clock ready flag
+        HSERDY OFFSET(17) NUMBITS(1) [],
+        /// HSE clock enable
+        HSEON OFFSET(16) NUMBITS(1) [],
+        /// Internal high-speed clock ready flag
+        HSIRDY OFFSET(1) NUMBITS(1) [],
+        /// Internal high-speed clock enable
+        HSION OFFSET(0) NUMBITS(1) []
+    ],
+    PLLCFGR [
+        /// Main PLL (PLL) division factor for USB OTG FS, SDMMC and RNG
+        PLLQ OFFSET(24) NUMBITS(4) [],
+        /// Main PLL (PLL) and PLLSAI entry clock source
+        PLLSRC OFFSET(22) NUMBITS(1) [
+            HSI = 0,
+            HSE = 1
+        ],
+        /// Main PLL (PLL) division factor for main system clock
+        PLLP OFFSET(16) NUMBITS(2) [
+            DivideBy2 = 0b00,
+            DivideBy4 = 0b01,
+            DivideBy6 = 0b10,
+            DivideBy8 = 0b11
+        ],
+        /// Main PLL (PLL) multiplication factor for VCO
+        PLLN OFFSET(6) NUMBITS(9) [],
+        /// Division factor for the main PLL (PLL) and PLLSAI input clock
+        PLLM OFFSET(0) NUMBITS(6) []
+    ],
+    CFGR [
+        /// APB high-speed prescaler (APB2)
+        PPRE2 OFFSET(13) NUMBITS(3) [],
+        /// APB Low speed prescaler (APB1)
+        PPRE1 OFFSET(10) NUMBITS(3) [],
+        /// AHB prescaler
+        HPRE OFFSET(4) NUMBITS(4) [],
+        /// System clock switch status
+        SWS OFFSET(2) NUMBITS(2) [
+            HSI = 0b00,
+            HSE = 0b01,
+            PLL = 0b10
+        ],
+        /// System clock switch
+        SW OFFSET(0) NUMBITS(2) [
+            HSI = 0b00,
+            HSE = 0b01,
+            PLL = 0b10
+        ]
+    ],
+    AHB1ENR [
+        /// DMA2 clock enable
+        DMA2EN OFFSET(22) NUMBITS(1) [],
+        /// DMA1 clock enable
+        DMA1EN OFFSET(21) NUMBITS(1) [],
+        /// IO port K clock enable
+        GPIOKEN OFFSET(10) NUMBITS(1) [],
+        /// IO port J clock enable
+        GPIOJEN OFFSET(9) NUMBITS(1) [],
+        /// IO port I clock enable
+        GPIOIEN OFFSET(8) NUMBITS(1) [],
+        /// IO port H clock enable
+        GPIOHEN OFFSET(7) NUMBITS(1) [],
+        /// IO port G clock enable
+        GPIOGEN OFFSET(6) NUMBITS(1) [],
+        /// IO port F clock enable
+        GPIOFEN OFFSET(5) NUMBITS(1) [],
+        /// IO port E clock enable
+        GPIOEEN OFFSET(4) NUMBITS(1) [],
+        /// IO port D clock enable
+        GPIODEN OFFSET(3) NUMBITS(1) [],
+        /// IO port C clock enable
+        GPIOCEN OFFSET(2) NUMBITS(1) [],
+        /// IO port B clock enable
+        GPIOBEN OFFSET(1) NUMBITS(1) [],
+        /// IO port A clock enable
+        GPIOAEN OFFSET(0) NUMBITS(1) []
+    ],
+    AHB3ENR [
+        /// Flexible memory controller module clock enable
+        FMCEN OFFSET(0) NUMBITS(1) []
+    ],
+    APB1ENR [
+        /// Power interface clock enable
+        PWREN OFFSET(28) NUMBITS(1) [],
+        /// I2C3 clock enable
+        I2C3EN OFFSET(23) NUMBITS(1) [],
+        /// I2C1 clock enable
+        I2C1EN OFFSET(21) NUMBITS(1) [],
+        /// USART 3 clock enable
+        USART3EN OFFSET(18) NUMBITS(1) [],
+        /// USART 2 clock enable
+        USART2EN OFFSET(17) NUMBITS(1) [],
+        /// TIM2 clock enable
+        TIM2EN OFFSET(0) NUMBITS(1) []
+    ],
+    APB2ENR [
+        /// LTDC clock enable
+        LTDCEN OFFSET(26) NUMBITS(1) [],
+        /// System configuration controller clock enable
+        SYSCFGEN OFFSET(14) NUMBITS(1) [],
+        /// USART6 clock enable
+        USART6EN OFFSET(5) NUMBITS(1) [],
+        /// USART1 clock enable
+        USART1EN OFFSET(4) NUMBITS(1) []
+    ],
+    PLLSAICFGR [
+        /// PLLSAI division factor for LCD clock
+        PLLSAIR OFFSET(28) NUMBITS(3) [],
+        /// PLLSAI division factor for SAI clock
+        PLLSAIQ OFFSET(24) NUMBITS(4) [],
+        /// PLLSAI division factor for 48MHz clock
+        PLLSAIP OFFSET(16) NUMBITS(2) [],
+        /// PLLSAI division factor for VCO
+        PLLSAIN OFFSET(6) NUMBITS(9) []
+    ],
+    DCKCFGR1 [
+        /// division factor for LCD_CLK
+        PLLSAIDIVR OFFSET(16) NUMBITS(2) [
+            DivideBy2 = 0b00,
+            DivideBy4 = 0b01,
+            DivideBy8 = 0b10,
+            DivideBy16 = 0b11
+        ]
+    ],
+    ACR [
+        /// ART Accelerator enable
+        ARTEN OFFSET(9) NUMBITS(1) [],
+        /// Prefetch enable
+        PRFTEN OFFSET(8) NUMBITS(1) [],
+        /// Latency
+        LATENCY OFFSET(0) NUMBITS(4) []
+    ]
+];
+
+const RCC_BASE: StaticRef<RccRegisters> =
+    unsafe { StaticRef::new(0x40023800 as *const RccRegisters) };
+
+const FLASH_BASE: StaticRef<FlashRegisters> =
+    unsafe { StaticRef::new(0x40023C00 as *const FlashRegisters) };
+
+/// Frequency of the HSI oscillator
+const HSI_FREQUENCY: u32 = 16_000_000;
+
+/// Frequency of the system clock after `Rcc::enable_pll_system_clock`
+pub const PLL_SYSTEM_CLOCK_FREQUENCY: u32 = 180_000_000;
+
+pub struct Rcc {
+    registers: StaticRef<RccRegisters>,
+    flash: StaticRef<FlashRegisters>,
+}
+
+impl Rcc {
+    pub const fn new() -> Rcc {
+        Rcc {
+            registers: RCC_BASE,
+            flash: FLASH_BASE,
+        }
+    }
+
+    /// Run the system clock at 180 MHz from the main PLL, fed by the HSI.
+    ///
+    /// The PLL input is 2 MHz (HSI / 8), which PLLSAI shares. AHB runs at
+    /// 180 MHz, APB1 at 45 MHz and APB2 at 90 MHz. 180 MHz is the highest
+    /// frequency that does not require the over-drive mode of the voltage
+    /// regulator.
+    pub fn enable_pll_system_clock(&self) {
+        if self.registers.cfgr.matches_all(CFGR::SWS::PLL) {
+            return;
+        }
+
+        // The PLL can only be configured while it is off
+        self.registers.cr.modify(CR::PLLON::CLEAR);
+        self.registers.pllcfgr.modify(
+            PLLCFGR::PLLSRC::HSI
+                + PLLCFGR::PLLM.val(8)
+                + PLLCFGR::PLLN.val(180)
+                + PLLCFGR::PLLP::DivideBy2,
+        );
+        self.registers.cr.modify(CR::PLLON::SET);
+        while !self.registers.cr.is_set(CR::PLLRDY) {}
+
+        // APB1 at most 54 MHz, APB2 at most 108 MHz. The prescalers are set
+        // before switching, so the buses are never overclocked.
+        self.registers
+            .cfgr
+            .modify(CFGR::HPRE.val(0) + CFGR::PPRE1.val(0b101) + CFGR::PPRE2.val(0b100));
+
+        // 5 wait states are needed between 150 MHz and 180 MHz at 2.7-3.6 V
+        self.flash
+            .acr
+            .modify(ACR::LATENCY.val(5) + ACR::PRFTEN::SET + ACR::ARTEN::SET);
+        while self.flash.acr.read(ACR::LATENCY) != 5 {}
+
+        self.registers.cfgr.modify(CFGR::SW::PLL);
+        while !self.registers.cfgr.matches_all(CFGR::SWS::PLL) {}
+    }
+
+    /// The frequency of the system clock in Hz.
+    pub fn get_sys_clock_frequency(&self) -> u32 {
+        match self.registers.cfgr.read_as_enum(CFGR::SWS) {
+            Some(CFGR::SWS::Value::PLL) => {
+                let input = self.get_pll_input_frequency();
+                let n = self.registers.pllcfgr.read(PLLCFGR::PLLN);
+                let p = (self.registers.pllcfgr.read(PLLCFGR::PLLP) + 1) * 2;
+                input * n / p
+            }
+            // The HSE frequency depends on the board, and the HSE is never
+            // selected by this driver
+            _ => HSI_FREQUENCY,
+        }
+    }
+
+    /// The frequency of the AHB bus in Hz.
+    pub fn get_ahb_frequency(&self) -> u32 {
+        let hpre = self.registers.cfgr.read(CFGR::HPRE);
+        let shift = match hpre {
+            0b1000..=0b1011 => hpre - 0b0111,
+            // 64 is skipped
+            0b1100..=0b1111 => hpre - 0b0110,
+            _ => 0,
+        };
+        self.get_sys_clock_frequency() >> shift
+    }
+
+    /// The frequency of the APB1 bus in Hz.
+    pub fn get_apb1_frequency(&self) -> u32 {
+        self.get_ahb_frequency() >> Self::apb_shift(self.registers.cfgr.read(CFGR::PPRE1))
+    }
+
+    /// The frequency of the APB2 bus in Hz.
+    pub fn get_apb2_frequency(&self) -> u32 {
+        self.get_ahb_frequency() >> Self::apb_shift(self.registers.cfgr.read(CFGR::PPRE2))
+    }
+
+    /// The frequency of the timers on APB1 in Hz, which is twice the APB1
+    /// frequency if APB1 is prescaled.
+    pub fn get_apb1_timer_frequency(&self) -> u32 {
+        if self.registers.cfgr.read(CFGR::PPRE1) < 0b100 {
+            self.get_apb1_frequency()
+        } else {
+            self.get_apb1_frequency() * 2
+        }
+    }
+
+    fn apb_shift(ppre: u32) -> u32 {
+        if ppre < 0b100 {
+            0
+        } else {
+            ppre - 0b011
+        }
+    }
+
+    fn get_pll_input_frequency(&self) -> u32 {
+        HSI_FREQUENCY / self.registers.pllcfgr.read(PLLCFGR::PLLM)
+    }
+
+    /// Run the LCD-TFT pixel clock at `frequency_khz` from PLLSAI.
+    ///
+    /// PLLSAI shares its input with the main PLL. The VCO, the R division
+    /// factor and the LCD divider are chosen to hit the frequency exactly;
+    /// if that is not possible, `INVAL` is returned.
+    pub fn enable_ltdc_pixel_clock(&self, frequency_khz: u32) -> Result<(), ErrorCode> {
+        let input_khz = self.get_pll_input_frequency() / 1000;
+        let dividers = [
+            (2, DCKCFGR1::PLLSAIDIVR::DivideBy2),
+            (4, DCKCFGR1::PLLSAIDIVR::DivideBy4),
+            (8, DCKCFGR1::PLLSAIDIVR::DivideBy8),
+            (16, DCKCFGR1::PLLSAIDIVR::DivideBy16),
+        ];
+        let (n, r, divr) = (2..=7)
+            .flat_map(|r| dividers.iter().map(move |&(div, divr)| (r, div, divr)))
+            .find_map(|(r, div, divr)| {
+                let vco_khz = frequency_khz * r * div;
+                let n = vco_khz / input_khz;
+                // The VCO output has to be between 100 MHz and 432 MHz
+                let valid = (100_000..=432_000).contains(&vco_khz) && (50..=432).contains(&n);
+                (valid && n * input_khz == vco_khz).then_some((n, r, divr))
+            })
+            .ok_or(ErrorCode::INVAL)?;
+
+        self.registers.cr.modify(CR::PLLSAION::CLEAR);
+        while self.registers.cr.is_set(CR::PLLSAIRDY) {}
+        self.registers
+            .pllsaicfgr
+            .modify(PLLSAICFGR::PLLSAIN.val(n) + PLLSAICFGR::PLLSAIR.val(r));
+        self.registers.dckcfgr1.modify(divr);
+        self.registers.cr.modify(CR::PLLSAION::SET);
+        while !self.registers.cr.is_set(CR::PLLSAIRDY) {}
+        Ok(())
+    }
+
+    fn is_enabled_ahb1(&self, field: Field<u32, AHB1ENR::Register>) -> bool {
+        self.registers.ahb1enr.is_set(field)
+    }
+
+    fn set_ahb1(&self, field: Field<u32, AHB1ENR::Register>, enabled: bool) {
+        self.registers.ahb1enr.modify(field.val(enabled as u32));
+    }
+
+    fn is_enabled_ahb3(&self, field: Field<u32, AHB3ENR::Register>) -> bool {
+        self.registers.ahb3enr.is_set(field)
+    }
+
+    fn set_ahb3(&self, field: Field<u32, AHB3ENR::Register>, enabled: bool) {
+        self.registers.ahb3enr.modify(field.val(enabled as u32));
+    }
+
+    fn is_enabled_apb1(&self, field: Field<u32, APB1ENR::Register>) -> bool {
+        self.registers.apb1enr.is_set(field)
+    }
+
+    fn set_apb1(&self, field: Field<u32, APB1ENR::Register>, enabled: bool) {
+        self.registers.apb1enr.modify(field.val(enabled as u32));
+    }
+
+    fn is_enabled_apb2(&self, field: Field<u32, APB2ENR::Register>) -> bool {
+        self.registers.apb2enr.is_set(field)
+    }
+
+    fn set_apb2(&self, field: Field<u32, APB2ENR::Register>, enabled: bool) {
+        self.registers.apb2enr.modify(field.val(enabled as u32));
+    }
+}
+
+pub struct PeripheralClock<'a> {
+    pub clock: PeripheralClockType,
+    rcc: &'a Rcc,
+}
+
+/// Bus + Clock name for the peripherals
+pub enum PeripheralClockType {
+    AHB1(HCLK1),
+    AHB3(HCLK3),
+    APB1(PCLK1),
+    APB2(PCLK2),
+}
+
+/// Peripherals clocked by HCLK1
+pub enum HCLK1 {
+    DMA1,
+    DMA2,
+    GPIOK,
+    GPIOJ,
+    GPIOI,
+    GPIOH,
+    GPIOG,
+    GPIOF,
+    GPIOE,
+    GPIOD,
+    GPIOC,
+    GPIOB,
+    GPIOA,
+}
+
+/// Peripherals clocked by HCLK3
+pub enum HCLK3 {
+    FMC,
+}
+
+/// Peripherals clocked by PCLK1
+pub enum PCLK1 {
+    TIM2,
+    USART2,
+    USART3,
+    I2C1,
+    I2C3,
+    PWR,
+}
+
+/// Peripherals clocked by PCLK2
+pub enum PCLK2 {
+    USART1,
+    USART6,
+    SYSCFG,
+    LTDC,
+}
+
+impl<'a> PeripheralClock<'a> {
+    pub const fn new(clock: PeripheralClockType, rcc: &'a Rcc) -> Self {
+        Self { clock, rcc }
+    }
+
+    /// The frequency in Hz of the clock that drives the peripheral. For the
+    /// timers, this is the timer clock of the bus.
+    pub fn get_frequency(&self) -> u32 {
+        match self.clock {
+            PeripheralClockType::AHB1(_) | PeripheralClockType::AHB3(_) => {
+                self.rcc.get_ahb_frequency()
+            }
+            PeripheralClockType::APB1(PCLK1::TIM2) => self.rcc.get_apb1_timer_frequency(),
+            PeripheralClockType::APB1(_) => self.rcc.get_apb1_frequency(),
+            PeripheralClockType::APB2(_) => self.rcc.get_apb2_frequency(),
+        }
+    }
+
+    fn set(&self, enabled: bool) {
+        match self.clock {
+            PeripheralClockType::AHB1(ref v) => self.rcc.set_ahb1(hclk1_field(v), enabled),
+            PeripheralClockType::AHB3(HCLK3::FMC) => self.rcc.set_ahb3(AHB3ENR::FMCEN, enabled),
+            PeripheralClockType::APB1(ref v) => self.rcc.set_apb1(pclk1_field(v), enabled),
+            PeripheralClockType::APB2(ref v) => self.rcc.set_apb2(pclk2_field(v), enabled),
+        }
+    }
+}
+
+fn hclk1_field(clock: &HCLK1) -> Field<u32, AHB1ENR::Register> {
+    match clock {
+        HCLK1::DMA1 => AHB1ENR::DMA1EN,
+        HCLK1::DMA2 => AHB1ENR::DMA2EN,
+        HCLK1::GPIOK => AHB1ENR::GPIOKEN,
+        HCLK1::GPIOJ => AHB1ENR::GPIOJEN,
+        HCLK1::GPIOI => AHB1ENR::GPIOIEN,
+        HCLK1::GPIOH => AHB1ENR::GPIOHEN,
+        HCLK1::GPIOG => AHB1ENR::GPIOGEN,
+        HCLK1::GPIOF => AHB1ENR::GPIOFEN,
+        HCLK1::GPIOE => AHB1ENR::GPIOEEN,
+        HCLK1::GPIOD => AHB1ENR::GPIODEN,
+        HCLK1::GPIOC => AHB1ENR::GPIOCEN,
+        HCLK1::GPIOB => AHB1ENR::GPIOBEN,
+        HCLK1::GPIOA => AHB1ENR::GPIOAEN,
+    }
+}
+
+fn pclk1_field(clock: &PCLK1) -> Field<u32, APB1ENR::Register> {
+    match clock {
+        PCLK1::TIM2 => APB1ENR::TIM2EN,
+        PCLK1::USART2 => APB1ENR::USART2EN,
+        PCLK1::USART3 => APB1ENR::USART3EN,
+        PCLK1::I2C1 => APB1ENR::I2C1EN,
+        PCLK1::I2C3 => APB1ENR::I2C3EN,
+        PCLK1::PWR => APB1ENR::PWREN,
+    }
+}
+
+fn pclk2_field(clock: &PCLK2) -> Field<u32, APB2ENR::Register> {
+    match clock {
+        PCLK2::USART1 => APB2ENR::USART1EN,
+        PCLK2::USART6 => APB2ENR::USART6EN,
+        PCLK2::SYSCFG => APB2ENR::SYSCFGEN,
+        PCLK2::LTDC => APB2ENR::LTDCEN,
+    }
+}
+
+impl<'a> ClockInterface for PeripheralClock<'a> {
+    fn is_enabled(&self) -> bool {
+        match self.clock {
+            PeripheralClockType::AHB1(ref v) => self.rcc.is_enabled_ahb1(hclk1_field(v)),
+            PeripheralClockType::AHB3(HCLK3::FMC) => self.rcc.is_enabled_ahb3(AHB3ENR::FMCEN),
+            PeripheralClockType::APB1(ref v) => self.rcc.is_enabled_apb1(pclk1_field(v)),
+            PeripheralClockType::APB2(ref v) => self.rcc.is_enabled_apb2(pclk2_field(v)),
+        }
+    }
+
+    fn enable(&self) {
+        self.set(true);
+    }
+
+    fn disable(&self) {
+        self.set(false);
+    }
+}
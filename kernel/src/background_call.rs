@@ -0,0 +1,194 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Long computations in the kernel, run in steps between processes.
+//!
+//! Work like software crypto, compression or sensor fusion can take much
+//! longer than processes should wait for the CPU. A capsule doing such work
+//! splits it into steps and sets its [`BackgroundCall`]. The kernel loop
+//! then runs one step of one background call at a time, round robin, when
+//! there is no interrupt or deferred call to handle, and schedules a process
+//! after each step. The time the kernel holds the CPU for a background call
+//! is therefore bounded by the length of a single step, which the capsule
+//! chooses.
+//!
+//! Unlike a deferred call that sets itself again, a background call does not
+//! keep processes from running. While background calls have work the chip
+//! does not go to sleep.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! use kernel::background_call::{BackgroundCall, BackgroundCallClient, Progress};
+//!
+//! struct Compressor {
+//!     background_call: BackgroundCall,
+//! }
+//!
+//! impl BackgroundCallClient for Compressor {
+//!     fn step(&self) -> Progress {
+//!         // Compress the next block of the input.
+//!         if self.done() {
+//!             Progress::Done
+//!         } else {
+//!             Progress::Continue
+//!         }
+//!     }
+//! }
+//!
+//! // Usually together with the registration of a deferred call.
+//! compressor.background_call.register(compressor);
+//! // Start the computation.
+//! compressor.background_call.set();
+//! ```
+
+use core::cell::Cell;
+
+use crate::utilities::cells::OptionalCell;
+
+/// Whether a background call has more work after a step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Run another step.
+    Continue,
+    /// The work is done, no further steps are run until the background call
+    /// is set again.
+    Done,
+}
+
+/// Clients of a background call run their work in steps.
+pub trait BackgroundCallClient {
+    /// Do the next step of the work. It should take a bounded and short time,
+    /// as processes wait for it to return.
+    fn step(&self) -> Progress;
+}
+
+const EMPTY: OptionalCell<&'static dyn BackgroundCallClient> = OptionalCell::empty();
+
+// As for deferred calls, these statics are only accessed by immutable
+// references from the single kernel thread.
+/// Counter of the background calls created.
+static mut CTR: Cell<usize> = Cell::new(0);
+
+/// Background calls with work, bit `i` is background call `i`.
+static mut BITMASK: Cell<u32> = Cell::new(0);
+
+/// The background call whose step runs next, if it has work.
+static mut NEXT: Cell<usize> = Cell::new(0);
+
+static mut CLIENTS: [OptionalCell<&'static dyn BackgroundCallClient>; 32] = [EMPTY; 32];
+
+pub struct BackgroundCall {
+    idx: usize,
+}
+
+impl BackgroundCall {
+    /// Creates a new background call with a unique ID.
+    pub fn new() -> Self {
+        // SAFETY: No accesses to CTR are via an &mut, and the Tock kernel is
+        // single-threaded so all accesses will occur from this thread.
+        let ctr = unsafe { &CTR };
+        let idx = ctr.get();
+        ctr.set(idx + 1);
+        BackgroundCall { idx }
+    }
+
+    /// Run the steps of `client` when this background call is set. At most 32
+    /// background calls are supported, `verify_setup()` checks this when the
+    /// kernel loop starts.
+    pub fn register(&self, client: &'static dyn BackgroundCallClient) {
+        // SAFETY: No accesses to CLIENTS are via an &mut, and the Tock kernel
+        // is single-threaded so all accesses will occur from this thread.
+        let clients = unsafe { &CLIENTS };
+        if let Some(slot) = clients.get(self.idx) {
+            slot.set(client);
+        }
+    }
+
+    /// Run steps of the client until it is done.
+    pub fn set(&self) {
+        if self.idx < 32 {
+            // SAFETY: No accesses to BITMASK are via an &mut, and the Tock
+            // kernel is single-threaded so all accesses will occur from this
+            // thread.
+            let bitmask = unsafe { &BITMASK };
+            bitmask.set(bitmask.get() | (1 << self.idx));
+        }
+    }
+
+    /// Stop running steps of the client.
+    pub fn clear(&self) {
+        if self.idx < 32 {
+            // SAFETY: see `set()`.
+            let bitmask = unsafe { &BITMASK };
+            bitmask.set(bitmask.get() & !(1 << self.idx));
+        }
+    }
+
+    /// Whether the client has work left.
+    pub fn is_pending(&self) -> bool {
+        // SAFETY: see `set()`.
+        let bitmask = unsafe { &BITMASK };
+        self.idx < 32 && bitmask.get() & (1 << self.idx) != 0
+    }
+
+    /// Returns true if any background call has work.
+    pub fn has_tasks() -> bool {
+        // SAFETY: see `set()`.
+        let bitmask = unsafe { &BITMASK };
+        bitmask.get() != 0
+    }
+
+    /// Runs one step of the next background call with work, after the one
+    /// that ran last. Returns which background call ran, if any.
+    pub fn service_next_pending() -> Option<usize> {
+        // SAFETY: No accesses to BITMASK/NEXT/CLIENTS are via an &mut, and the
+        // Tock kernel is single-threaded so all accesses will occur from this
+        // thread.
+        let bitmask = unsafe { &BITMASK };
+        let next = unsafe { &NEXT };
+        let clients = unsafe { &CLIENTS };
+        let val = bitmask.get();
+        if val == 0 {
+            return None;
+        }
+
+        // The first with work at or after `next`, wrapping around.
+        let start = next.get() % 32;
+        let rotated = val.rotate_right(start as u32);
+        let idx = (start + rotated.trailing_zeros() as usize) % 32;
+        next.set(idx + 1);
+
+        // Cleared first so that the client can set itself again in its last
+        // step. A background call without client never has work.
+        bitmask.set(bitmask.get() & !(1 << idx));
+        if clients[idx].map(|client| client.step()) == Some(Progress::Continue) {
+            bitmask.set(bitmask.get() | (1 << idx));
+        }
+        Some(idx)
+    }
+
+    /// This function should be called at the beginning of the kernel loop to
+    /// verify that background calls have been correctly initialized, like
+    /// `DeferredCall::verify_setup()`. It checks that at most `CLIENTS.len()`
+    /// background calls have been created, and that each of them was
+    /// registered, as a background call beyond the 32nd, or without client,
+    /// would never run its steps.
+    pub fn verify_setup() {
+        // SAFETY: No accesses to CTR/CLIENTS are via an &mut, and the Tock
+        // kernel is single-threaded so all accesses will occur from this
+        // thread.
+        let ctr = unsafe { &CTR };
+        let clients = unsafe { &CLIENTS };
+        let num_background_calls = ctr.get();
+        if num_background_calls > clients.len()
+            || clients.iter().filter(|opt| opt.is_some()).count() != num_background_calls
+        {
+            panic!(
+                "ERROR: > 32 background calls, or a component forgot to register a background call."
+            );
+        }
+    }
+}
@@ -105,3 +105,8 @@ pub unsafe trait CreatePortTableCapability {}
 /// of the networking stack. A capsule would never hold this capability although
 /// it may hold capabilities created via this capability.
 pub unsafe trait NetworkCapabilityCreationCapability {}
+
+/// The `KerneluserStorageCapability` capability allows the holder to create
+/// permissions to access values in persistent storage on behalf of the
+/// kernel, for example board configuration written during provisioning.
+pub unsafe trait KerneluserStorageCapability {}
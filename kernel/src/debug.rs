@@ -60,10 +60,11 @@ use core::str;
 
 use crate::collections::queue::Queue;
 use crate::collections::ring_buffer::RingBuffer;
+use crate::config::{self, DebugVerbosity};
 use crate::hil;
 use crate::platform::chip::Chip;
-use crate::process::Process;
 use crate::process::ProcessPrinter;
+use crate::process::ProcessSlot;
 use crate::processbuffer::ReadableProcessSlice;
 use crate::utilities::binary_write::BinaryToWriteWrapper;
 use crate::utilities::cells::NumericCellExt;
@@ -112,7 +113,7 @@ pub unsafe fn panic_print<W: Write + IoWrite, C: Chip, PP: ProcessPrinter>(
     writer: &mut W,
     panic_info: &PanicInfo,
     nop: &dyn Fn(),
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
     chip: &'static Option<&'static C>,
     process_printer: &'static Option<&'static PP>,
 ) {
@@ -132,7 +133,7 @@ pub unsafe fn panic<L: hil::led::Led, W: Write + IoWrite, C: Chip, PP: ProcessPr
     writer: &mut W,
     panic_info: &PanicInfo,
     nop: &dyn Fn(),
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [ProcessSlot],
     chip: &'static Option<&'static C>,
     process_printer: &'static Option<&'static PP>,
 ) -> ! {
@@ -188,7 +189,7 @@ pub unsafe fn panic_cpu_state<W: Write, C: Chip>(
 ///
 /// **NOTE:** The supplied `writer` must be synchronous.
 pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
-    procs: &'static [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     process_printer: &'static Option<&'static PP>,
     writer: &mut W,
 ) {
@@ -196,7 +197,7 @@ pub unsafe fn panic_process_info<PP: ProcessPrinter, W: Write>(
         // print data about each process
         let _ = writer.write_fmt(format_args!("\r\n---| App Status |---\r\n"));
         for idx in 0..procs.len() {
-            procs[idx].map(|process| {
+            procs[idx].get().map(|process| {
                 // Print the memory map and basic process info.
                 //
                 // Because we are using a synchronous printer we do not need to
@@ -561,6 +562,9 @@ impl Write for DebugWriterWrapper {
 }
 
 pub fn debug_print(args: Arguments) {
+    if config::CONFIG.debug_verbosity == DebugVerbosity::Quiet {
+        return;
+    }
     let writer = unsafe { get_debug_writer() };
 
     let _ = write(writer, args);
@@ -568,8 +572,16 @@ pub fn debug_print(args: Arguments) {
 }
 
 pub fn debug_println(args: Arguments) {
+    if config::CONFIG.debug_verbosity == DebugVerbosity::Quiet {
+        return;
+    }
     let writer = unsafe { get_debug_writer() };
 
+    if config::CONFIG.debug_verbosity == DebugVerbosity::Verbose {
+        writer.increment_count();
+        let count = writer.get_count();
+        let _ = writer.write_fmt(format_args!("TOCK_DEBUG({}): ", count));
+    }
     let _ = write(writer, args);
     let _ = writer.write_str("\r\n");
     writer.publish_bytes();
@@ -603,6 +615,9 @@ fn write_header(writer: &mut DebugWriterWrapper, (file, line): &(&'static str, u
 }
 
 pub fn debug_verbose_print(args: Arguments, file_line: &(&'static str, u32)) {
+    if config::CONFIG.debug_verbosity == DebugVerbosity::Quiet {
+        return;
+    }
     let writer = unsafe { get_debug_writer() };
 
     let _ = write_header(writer, file_line);
@@ -611,6 +626,9 @@ pub fn debug_verbose_print(args: Arguments, file_line: &(&'static str, u32)) {
 }
 
 pub fn debug_verbose_println(args: Arguments, file_line: &(&'static str, u32)) {
+    if config::CONFIG.debug_verbosity == DebugVerbosity::Quiet {
+        return;
+    }
     let writer = unsafe { get_debug_writer() };
 
     let _ = write_header(writer, file_line);
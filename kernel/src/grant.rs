@@ -136,7 +136,7 @@ use core::ptr::{write, NonNull};
 use core::slice;
 
 use crate::kernel::Kernel;
-use crate::process::{Error, Process, ProcessCustomGrantIdentifier, ProcessId};
+use crate::process::{Error, Process, ProcessCustomGrantIdentifier, ProcessId, ProcessSlot};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
 use crate::processbuffer::{ReadOnlyProcessBufferRef, ReadWriteProcessBufferRef};
 use crate::upcall::{Upcall, UpcallError, UpcallId};
@@ -179,6 +179,32 @@ impl<const NUM: u8> AllowRwSize for AllowRwCount<NUM> {
     const COUNT: u8 = NUM;
 }
 
+/// Description of how much memory a single grant consumes in the grant region
+/// of every process that uses the corresponding capsule.
+///
+/// The footprint is fully determined by the grant's types, so it can be
+/// computed without any process having allocated the grant. Boards can use
+/// this to understand which capsules dominate the per-process grant region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GrantFootprint {
+    /// Syscall driver number of the capsule owning the grant.
+    pub driver_num: usize,
+    /// Size in bytes of the kernel managed part of the grant (counters, saved
+    /// upcalls and saved allows), including any padding needed to align T.
+    pub kernel_managed_size: usize,
+    /// Size in bytes of the capsule-defined data type T.
+    pub data_size: usize,
+    /// Alignment required for the entire grant allocation.
+    pub align: usize,
+}
+
+impl GrantFootprint {
+    /// Total number of bytes each process will allocate for this grant.
+    pub fn total_size(&self) -> usize {
+        self.kernel_managed_size + self.data_size
+    }
+}
+
 /// Helper that calculated offsets within the kernel owned memory (i.e. the
 /// non-T part of grant).
 ///
@@ -1681,6 +1707,31 @@ impl<T: Default, Upcalls: UpcallSize, AllowROs: AllowRoSize, AllowRWs: AllowRwSi
         }
     }
 
+    /// Returns the amount of memory this grant will consume in the grant
+    /// region of each process that allocates it.
+    pub fn footprint(&self) -> GrantFootprint {
+        Self::footprint_for_driver(self.driver_num)
+    }
+
+    /// Computes the footprint of a grant with these types for the given driver
+    /// number. This does not require an instance of the grant.
+    pub(crate) fn footprint_for_driver(driver_num: usize) -> GrantFootprint {
+        let grant_t_align = GrantDataAlign(align_of::<T>());
+        let total = EnteredGrantKernelManagedLayout::grant_size(
+            UpcallItems(Upcalls::COUNT),
+            AllowRoItems(AllowROs::COUNT),
+            AllowRwItems(AllowRWs::COUNT),
+            GrantDataSize(size_of::<T>()),
+            grant_t_align,
+        );
+        GrantFootprint {
+            driver_num,
+            kernel_managed_size: total - size_of::<T>(),
+            data_size: size_of::<T>(),
+            align: EnteredGrantKernelManagedLayout::grant_align(grant_t_align),
+        }
+    }
+
     /// Enter the grant for a specific process.
     ///
     /// This creates a `ProcessGrant` which is a handle for a grant allocated
@@ -1771,8 +1822,8 @@ pub struct Iter<
 
     /// Iterator over valid processes.
     subiter: core::iter::FilterMap<
-        core::slice::Iter<'a, Option<&'static dyn Process>>,
-        fn(&Option<&'static dyn Process>) -> Option<&'static dyn Process>,
+        core::slice::Iter<'a, ProcessSlot>,
+        fn(&ProcessSlot) -> Option<&'static dyn Process>,
     >,
 }
 
@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for audio output.
+//!
+//! An `AudioSink` plays mono, signed 16-bit PCM samples at a fixed sample
+//! rate. 0 is the rest level of the output, `i16::MIN` and `i16::MAX` its
+//! two extremes.
+
+use crate::ErrorCode;
+
+pub trait AudioSinkClient {
+    /// Playback of `buffer` finished, after its last sample or because it
+    /// was stopped.
+    ///
+    /// - `Ok(())`: All samples were played.
+    /// - `CANCEL`: `stop()` was called.
+    /// - `FAIL`: The output failed.
+    fn play_done(&self, buffer: &'static mut [i16], result: Result<(), ErrorCode>);
+}
+
+pub trait AudioSink<'a> {
+    fn set_client(&self, client: &'a dyn AudioSinkClient);
+
+    /// Set the number of samples per second played.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The rate applies to the next `play()`.
+    /// - `INVAL`: The output does not support the rate.
+    /// - `BUSY`: A buffer is playing.
+    fn set_sample_rate(&self, rate_hz: u32) -> Result<(), ErrorCode>;
+
+    /// The number of samples per second played.
+    fn get_sample_rate(&self) -> u32;
+
+    /// Play the first `len` samples of `buffer`. `play_done()` returns the
+    /// buffer once they have all been played.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The playback started.
+    /// - `SIZE`: `len` is 0 or longer than `buffer`.
+    /// - `BUSY`: A buffer is playing already.
+    /// - `NOSUPPORT`: The output cannot play samples in its current
+    ///   configuration.
+    fn play(
+        &self,
+        buffer: &'static mut [i16],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [i16])>;
+
+    /// Stop the playback. `play_done()` is called with `CANCEL`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The playback will stop.
+    /// - `OFF`: No buffer is playing.
+    fn stop(&self) -> Result<(), ErrorCode>;
+}
@@ -0,0 +1,58 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for storage that is read and written in blocks.
+//!
+//! A block device has a number of blocks of a fixed size, usually 512 bytes,
+//! that are read and written as a whole, like a disk. It lets storage such
+//! as an SD card or flash behind a translation layer be exported over
+//! protocols that expect a disk, e.g. USB mass storage.
+//!
+//! One operation is in progress at a time, others return `BUSY` until it
+//! completes.
+
+use crate::ErrorCode;
+
+pub trait BlockStorage<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// The size of a block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// The number of blocks, 0 while there is no medium, e.g. no SD card is
+    /// inserted or it is not initialized yet.
+    fn block_count(&self) -> u32;
+
+    /// Read block number `block` into the first `block_size()` bytes of
+    /// `buffer`. `read_done()` is called once it is read.
+    ///
+    /// Errors:
+    ///
+    /// - `BUSY`: An operation is in progress.
+    /// - `INVAL`: There is no such block.
+    /// - `SIZE`: `buffer` is shorter than a block.
+    /// - `OFF`: There is no medium.
+    fn read_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write the first `block_size()` bytes of `buffer` to block number
+    /// `block`. `write_done()` is called once it is written. Returns the
+    /// same errors as `read_block()`.
+    fn write_block(
+        &self,
+        buffer: &'static mut [u8],
+        block: u32,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub trait Client {
+    /// A read completed. `FAIL` if the storage could not be read.
+    fn read_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// A write completed. `FAIL` if the storage could not be written.
+    fn write_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+}
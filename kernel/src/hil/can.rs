@@ -200,12 +200,20 @@ pub struct BitTiming {
 #[derive(Debug, Copy, Clone)]
 pub enum OperationMode {
     /// Loopback mode means that each message is transmitted on the
-    /// TX channel and immediately received on the RX channel
+    /// TX channel and immediately received on the RX channel. The messages
+    /// are also sent on the bus, but need no acknowledgement from another
+    /// node.
     Loopback,
 
-    /// Monitoring mode means that the CAN peripheral sends only the recessive
-    /// bits on the bus and cannot start a transmission, but can receive
-    /// valid data frames and valid remote frames
+    /// Internal loopback mode is loopback mode without sending anything on
+    /// the bus, which is left recessive. It tests the controller and the
+    /// software using it without a transceiver or a second node, and does
+    /// not disturb a bus that is connected.
+    InternalLoopback,
+
+    /// Monitoring (listen-only) mode means that the CAN peripheral sends only
+    /// the recessive bits on the bus and cannot start a transmission, but can
+    /// receive valid data frames and valid remote frames
     Monitoring,
 
     /// Freeze mode means that no transmission or reception of frames is
@@ -411,6 +419,7 @@ pub trait Configure {
     /// # Return values:
     ///
     /// * `Ok()` - The parameters were stored.
+    /// * `Err(NOSUPPORT)` - The peripheral does not support this mode
     /// * `Err(ErrorCode)` - Indicates the error because of which the request
     ///                      cannot be completed
     fn set_operation_mode(&self, mode: OperationMode) -> Result<(), ErrorCode>;
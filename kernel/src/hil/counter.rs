@@ -0,0 +1,36 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for counting edges on a pin.
+//!
+//! Implementations based on hardware counters can count signals of several
+//! MHz without involving the CPU. Implementations based on GPIO interrupts
+//! are limited by interrupt latency.
+
+use crate::ErrorCode;
+
+pub trait EdgeCounter {
+    /// Reset the count to 0 and start counting rising edges.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: Counting started.
+    /// - `BUSY`: The counter is already counting.
+    /// - `NOMEM`: The hardware resources needed are in use.
+    fn start(&self) -> Result<(), ErrorCode>;
+
+    /// Stop counting. The count is kept until the next `start()`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: Counting stopped.
+    /// - `OFF`: The counter was not counting.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    /// Number of rising edges since `start()`. Wraps around on overflow.
+    fn count(&self) -> u32;
+
+    /// Highest frequency in Hz at which no edges are missed.
+    fn max_frequency_hz(&self) -> u32;
+}
@@ -23,12 +23,16 @@ pub trait Client {
 
 /// CRC algorithms
 ///
-/// In all cases, input bytes are bit-reversed (i.e., consumed from LSB to MSB.)
+/// Unless noted otherwise, input bytes are bit-reversed (i.e., consumed from
+/// LSB to MSB.)
 ///
 /// Algorithms prefixed with `Sam4L` are native to that chip and thus require
 /// no software post-processing on platforms using it.
 ///
-#[derive(Copy, Clone)]
+/// Chips without a CRC unit, or whose unit does not support an algorithm, can
+/// use the software implementation in `kernel::utilities::crc`.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CrcAlgorithm {
     /// Polynomial 0x04C11DB7, output reversed then inverted
     /// ("CRC-32")
@@ -36,8 +40,19 @@ pub enum CrcAlgorithm {
     /// Polynomial 0x1EDC6F41, output reversed then inverted
     /// ("CRC-32C" / "Castagnoli")
     Crc32C,
-    /// Polynomial 0x1021, no output post-processing ("CRC-16-CCITT")
+    /// Polynomial 0x1021, initial value 0xFFFF, no output post-processing
+    /// ("CRC-16-CCITT")
     Crc16CCITT,
+    /// Polynomial 0x1021, initial value 0, input bytes consumed from MSB to
+    /// LSB, no output post-processing ("CRC-16/XMODEM", as used by SD cards
+    /// for data blocks)
+    Crc16Xmodem,
+    /// Polynomial 0x8005, initial value 0xFFFF, output reversed
+    /// ("CRC-16/MODBUS", as used by Modbus RTU)
+    Crc16Modbus,
+    /// Polynomial 0x31, initial value 0, output reversed
+    /// ("CRC-8-MAXIM", as used by 1-Wire devices)
+    Crc8Maxim,
 }
 
 /// CRC output type
@@ -45,7 +60,7 @@ pub enum CrcAlgorithm {
 /// Individual CRC algorithms can have different output lengths. This
 /// type represents the different [`CrcAlgorithm`] outputs
 /// respectively.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum CrcOutput {
     /// Output of [`CrcAlgorithm::Crc32`]
     Crc32(u32),
@@ -53,6 +68,12 @@ pub enum CrcOutput {
     Crc32C(u32),
     /// Output of [`CrcAlgorithm::Crc16CCITT`]
     Crc16CCITT(u16),
+    /// Output of [`CrcAlgorithm::Crc16Xmodem`]
+    Crc16Xmodem(u16),
+    /// Output of [`CrcAlgorithm::Crc16Modbus`]
+    Crc16Modbus(u16),
+    /// Output of [`CrcAlgorithm::Crc8Maxim`]
+    Crc8Maxim(u8),
 }
 
 impl CrcOutput {
@@ -61,6 +82,20 @@ impl CrcOutput {
             CrcOutput::Crc32(_) => CrcAlgorithm::Crc32,
             CrcOutput::Crc32C(_) => CrcAlgorithm::Crc32C,
             CrcOutput::Crc16CCITT(_) => CrcAlgorithm::Crc16CCITT,
+            CrcOutput::Crc16Xmodem(_) => CrcAlgorithm::Crc16Xmodem,
+            CrcOutput::Crc16Modbus(_) => CrcAlgorithm::Crc16Modbus,
+            CrcOutput::Crc8Maxim(_) => CrcAlgorithm::Crc8Maxim,
+        }
+    }
+
+    /// Returns the CRC as an integer, whatever its width.
+    pub fn value(&self) -> u32 {
+        match *self {
+            CrcOutput::Crc32(crc) | CrcOutput::Crc32C(crc) => crc,
+            CrcOutput::Crc16CCITT(crc)
+            | CrcOutput::Crc16Xmodem(crc)
+            | CrcOutput::Crc16Modbus(crc) => crc as u32,
+            CrcOutput::Crc8Maxim(crc) => crc as u32,
         }
     }
 }
@@ -0,0 +1,60 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for reading a chip's unique device identifier.
+//!
+//! Most microcontrollers are programmed with a unique, read-only identifier
+//! at the factory. This interface exposes that identifier and derives stable
+//! network addresses from it, so that the 802.15.4, BLE, and USB stacks do not
+//! each need to invent their own address on every board.
+//!
+//! Chips only need to implement `unique_id()`. The derived addresses are
+//! locally administered unicast addresses computed by folding the unique ID,
+//! so they are stable across reboots but are not IEEE assigned. Chips that
+//! have factory assigned addresses (e.g. the nRF52 `DEVICEADDR`) can override
+//! the default implementations.
+
+/// Upper bound on the length of a unique ID in bytes.
+pub const MAX_ID_LEN: usize = 16;
+
+pub trait DeviceId {
+    /// Copy the unique identifier of this chip into `buf` and return its
+    /// length in bytes. The ID is the same every time the chip boots.
+    fn unique_id(&self, buf: &mut [u8; MAX_ID_LEN]) -> usize;
+
+    /// Return an EUI-64 derived from the unique ID, most significant byte
+    /// first.
+    fn eui64(&self) -> [u8; 8] {
+        let mut id = [0; MAX_ID_LEN];
+        let len = self.unique_id(&mut id);
+        let mut eui64 = [0; 8];
+        fold_into(&id[..len], &mut eui64);
+        eui64
+    }
+
+    /// Return a 48-bit MAC address derived from the unique ID, most
+    /// significant byte first.
+    fn mac48(&self) -> [u8; 6] {
+        let mut mac = [0; 6];
+        fold_into(&self.eui64(), &mut mac);
+        mac
+    }
+
+    /// Return a 16-bit IEEE 802.15.4 short address derived from the
+    /// unique ID.
+    fn short_address(&self) -> u16 {
+        let mac = self.mac48();
+        u16::from_be_bytes([mac[4], mac[5]])
+    }
+}
+
+/// XOR `id` into `out`, wrapping around as needed, and turn the result into a
+/// locally administered unicast address.
+fn fold_into(id: &[u8], out: &mut [u8]) {
+    for (i, byte) in id.iter().enumerate() {
+        out[i % out.len()] ^= byte;
+    }
+    // Set the U/L bit and clear the I/G (multicast) bit.
+    out[0] = (out[0] | 0x02) & !0x01;
+}
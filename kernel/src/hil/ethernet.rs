@@ -0,0 +1,53 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for Ethernet network adapters.
+//!
+//! An adapter sends and receives whole Ethernet II frames: destination and
+//! source MAC address, EtherType and payload, without preamble or frame
+//! check sequence. The adapter may be a MAC with a PHY, or a link that only
+//! carries Ethernet frames, such as USB CDC-NCM
+//! (`capsules_extra::usb::cdc_ncm`).
+
+use crate::ErrorCode;
+
+/// Length of a MAC address.
+pub const MAC_ADDRESS_LEN: usize = 6;
+
+/// Maximum length of a frame without VLAN tag: 14 bytes of header and a
+/// 1500 byte payload.
+pub const MAX_FRAME_LEN: usize = 1514;
+
+pub trait EthernetAdapter<'a> {
+    fn set_client(&self, client: &'a dyn EthernetAdapterClient);
+
+    /// MAC address of the adapter, used as the source address of frames.
+    fn mac_address(&self) -> [u8; MAC_ADDRESS_LEN];
+
+    /// Whether the link is up, i.e. frames can be sent and received.
+    fn link_up(&self) -> bool;
+
+    /// Send the first `len` bytes of `frame`. On success, `transmit_done()`
+    /// is called once the frame has been sent.
+    ///
+    /// Returns `OFF` if the link is down, `SIZE` if `len` is larger than
+    /// `frame` or `MAX_FRAME_LEN`, and `BUSY` if a frame is being sent.
+    fn transmit(
+        &self,
+        frame: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub trait EthernetAdapterClient {
+    /// A frame passed to `transmit()` was sent, or failed to be sent.
+    fn transmit_done(&self, frame: &'static mut [u8], len: usize, result: Result<(), ErrorCode>);
+
+    /// A frame was received. `frame` is only valid for the duration of the
+    /// call.
+    fn received_frame(&self, frame: &[u8]);
+
+    /// The link went up or down.
+    fn link_changed(&self, up: bool);
+}
@@ -0,0 +1,143 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for filesystems with named files.
+//!
+//! Files are opened by name and addressed by the handle the filesystem
+//! returns, a small integer that is valid until the file is closed. All
+//! operations but `seek` and `size` are split-phase and complete with a
+//! callback to the client. One operation is in progress at a time, others
+//! return `BUSY` until it completes.
+//!
+//! Errors common to all operations are:
+//!
+//! - `BUSY`: Another operation is in progress.
+//! - `FAIL`: The storage holds no valid filesystem or it is corrupted.
+//! - `INVAL`: The handle is not an open file or an argument is invalid.
+
+use crate::ErrorCode;
+
+/// How a file is opened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Read an existing file.
+    Read,
+    /// Write the file from the beginning, creating it if it does not exist.
+    /// The old contents are replaced when the file is synced or closed.
+    Write,
+    /// Write after the end of the file, creating it if it does not exist.
+    Append,
+}
+
+/// The kind of a directory entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// An entry of a directory, as returned by `read_dir`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    pub kind: EntryKind,
+    /// The size of a file in bytes, 0 for directories.
+    pub size: u32,
+    /// The length of the name. Only the part that fits is copied into the
+    /// name buffer.
+    pub name_len: usize,
+}
+
+pub trait FileSystem<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Erase the storage and create an empty filesystem on it. All open files
+    /// are closed.
+    fn format(&self) -> Result<(), ErrorCode>;
+
+    /// Open the file named by the first `name_len` bytes of `name`.
+    ///
+    /// Errors reported by `opened` besides the common ones:
+    ///
+    /// - `NODEVICE`: There is no such file to read.
+    /// - `BUSY`: The file is open for writing, or for reading and it is to be
+    ///   written.
+    /// - `NOMEM`: All handles are in use, or the storage is full.
+    fn open(
+        &self,
+        name: &'static mut [u8],
+        name_len: usize,
+        mode: OpenMode,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write what was written to `file` to storage and close it.
+    fn close(&self, file: usize) -> Result<(), ErrorCode>;
+
+    /// Write what was written to `file` to storage, so that it survives a
+    /// reset.
+    fn sync(&self, file: usize) -> Result<(), ErrorCode>;
+
+    /// Read up to `len` bytes of `file` from its position on. Fewer bytes
+    /// are read at the end of the file.
+    fn read(
+        &self,
+        file: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Append the first `len` bytes of `buffer` to `file`.
+    fn write(
+        &self,
+        file: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Move the position that `file` is read from to `offset`. Returns
+    /// `INVAL` if `offset` is past the end of the file.
+    fn seek(&self, file: usize, offset: u32) -> Result<(), ErrorCode>;
+
+    /// The size of `file` in bytes, including what was written but not yet
+    /// synced.
+    fn size(&self, file: usize) -> Result<u32, ErrorCode>;
+
+    /// Remove the file named by the first `name_len` bytes of `name`.
+    /// `removed` reports `NODEVICE` if there is no such file and `BUSY` if
+    /// it is open.
+    fn remove(
+        &self,
+        name: &'static mut [u8],
+        name_len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Copy entry `index` of the root directory into `name`. `dir_entry`
+    /// reports `NODEVICE` once `index` is past the last entry.
+    fn read_dir(
+        &self,
+        index: usize,
+        name: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
+
+pub trait Client {
+    fn formatted(&self, result: Result<(), ErrorCode>);
+
+    /// The file was opened with the returned handle.
+    fn opened(&self, result: Result<usize, ErrorCode>, name: &'static mut [u8]);
+
+    fn closed(&self, file: usize, result: Result<(), ErrorCode>);
+
+    fn synced(&self, file: usize, result: Result<(), ErrorCode>);
+
+    /// The file was read into `buffer`, returns the number of bytes read.
+    fn read_done(&self, file: usize, buffer: &'static mut [u8], result: Result<usize, ErrorCode>);
+
+    /// Returns the number of bytes written, which is fewer than asked for if
+    /// the storage filled up.
+    fn write_done(&self, file: usize, buffer: &'static mut [u8], result: Result<usize, ErrorCode>);
+
+    fn removed(&self, result: Result<(), ErrorCode>, name: &'static mut [u8]);
+
+    fn dir_entry(&self, result: Result<DirEntry, ErrorCode>, name: &'static mut [u8]);
+}
@@ -136,6 +136,36 @@ pub trait Flash {
 
     /// Erase a page of flash by setting every byte to 0xFF.
     fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode>;
+
+    /// Suspend the page erase in progress, so that other pages can be read
+    /// and written before it finishes. Erases take milliseconds on most
+    /// flashes, which a read or write would have to wait otherwise.
+    ///
+    /// `erase_complete()` is not called while the erase is suspended. The
+    /// page being erased must not be read or written until it completed.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The erase is suspended, reads and writes can be started.
+    /// - `OFF`: No erase is in progress.
+    /// - `ALREADY`: The erase is suspended already.
+    /// - `NOSUPPORT`: The flash cannot suspend erases.
+    fn suspend_erase(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// Resume the erase suspended with `suspend_erase()`. `erase_complete()`
+    /// is called once it finished.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The erase continues.
+    /// - `OFF`: No erase is suspended.
+    /// - `BUSY`: A read or write has not completed yet.
+    /// - `NOSUPPORT`: The flash cannot suspend erases.
+    fn resume_erase(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 /// Implement `Client` to receive callbacks from `Flash`.
@@ -149,3 +179,24 @@ pub trait Client<F: Flash> {
     /// Flash erase complete.
     fn erase_complete(&self, error: Error);
 }
+
+/// Flash that holds back writes and erases, such as a write-back cache. An
+/// operation that completed is not necessarily on flash yet, and may be lost
+/// on power loss or reset until it is flushed.
+pub trait Flush<'a> {
+    fn set_flush_client(&self, client: &'a dyn FlushClient);
+
+    /// Start writing every page that was written or erased before this call
+    /// to flash. `flush_done()` is called once they are on flash, so the
+    /// flush acts as a barrier: nothing written before it can be lost after
+    /// it completed.
+    ///
+    /// Returns `BUSY` if a flush is already in progress.
+    fn flush(&self) -> Result<(), ErrorCode>;
+}
+
+pub trait FlushClient {
+    /// The flush completed. `FAIL` means that a page could not be written to
+    /// flash, it stays held back.
+    fn flush_done(&self, result: Result<(), ErrorCode>);
+}
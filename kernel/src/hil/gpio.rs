@@ -196,6 +196,45 @@ pub trait Input {
     }
 }
 
+/// Operations on several pins of a port at once.
+///
+/// The pins are selected with a mask in which bit `i` is pin `i` of the
+/// port. Chips with registers that set, clear or toggle several pins at once
+/// change all selected pins in a single write, so a parallel bus or an LED
+/// array driven through a port avoids the overhead of one call per pin.
+/// Selected pins that are not outputs are left unchanged by the output
+/// operations.
+pub trait Port {
+    /// The number of pins of the port, at most 32.
+    fn width(&self) -> usize;
+
+    /// Make the selected pins outputs.
+    fn make_output(&self, mask: u32);
+
+    /// Make the selected pins inputs.
+    fn make_input(&self, mask: u32);
+
+    /// Get the current state of all pins, bit `i` being pin `i`.
+    fn read(&self) -> u32;
+
+    /// Set the selected pins high.
+    fn set(&self, mask: u32);
+
+    /// Set the selected pins low.
+    fn clear(&self, mask: u32);
+
+    /// Toggle the selected pins.
+    fn toggle(&self, mask: u32);
+
+    /// Set each selected pin to its bit in `value`. Chips that can should
+    /// change all selected pins at once, the default sets the high pins
+    /// before clearing the low ones.
+    fn write(&self, mask: u32, value: u32) {
+        self.set(mask & value);
+        self.clear(mask & !value);
+    }
+}
+
 pub trait Interrupt<'a>: Input {
     /// Set the client for interrupt events.
     fn set_client(&self, client: &'a dyn Client);
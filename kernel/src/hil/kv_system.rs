@@ -32,6 +32,11 @@
 //! This level is also in charge of generating the key hash by calling into
 //! level 2.
 //!
+//! This level can group changes of several keys into a transaction, which
+//! is committed atomically: after a power failure either all or none of its
+//! changes are visible. Level 2 only needs to append, read and invalidate
+//! keys for this, no operation of this HIL is atomic over several keys.
+//!
 //! The expected setup inside Tock will look like this:
 //! +-----------------------+
 //! |                       |
@@ -96,6 +101,18 @@ pub trait StoreClient<K: KeyType> {
     /// `result`: Nothing on success, 'ErrorCode' on error
     /// `key`: The key buffer
     fn delete_complete(&self, result: Result<(), ErrorCode>, key: &'static mut [u8]);
+
+    /// This callback is called when the commit of a transaction completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error. The transaction
+    /// stays open if no change was made, and its changes will still be made
+    /// if it failed afterwards.
+    fn commit_complete(&self, _result: Result<(), ErrorCode>) {}
+
+    /// This callback is called when the abort of a transaction completes
+    ///
+    /// `result`: Nothing on success, 'ErrorCode' on error
+    fn abort_complete(&self, _result: Result<(), ErrorCode>) {}
 }
 
 /// Implement this trait and use `set_client()` in order to receive callbacks.
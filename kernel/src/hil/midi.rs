@@ -0,0 +1,52 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for sending and receiving MIDI event packets.
+//!
+//! MIDI messages are moved in the 4 byte event packets of the USB MIDI
+//! class: a header byte with the cable number in the upper nibble and the
+//! code index number (the kind of message) in the lower nibble, followed by
+//! up to 3 bytes of the MIDI message, padded with zeros. A buffer holds any
+//! number of packets back to back.
+
+use crate::ErrorCode;
+
+/// Length of a MIDI event packet.
+pub const EVENT_PACKET_LEN: usize = 4;
+
+/// Receives the completions of a `Midi` device.
+pub trait Client {
+    /// Called when the packets of `send()` were sent, or failed to be.
+    fn send_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>);
+
+    /// Called when packets were received into the buffer of `receive()`.
+    /// `len` is the number of bytes received, a multiple of
+    /// `EVENT_PACKET_LEN`.
+    fn packets_received(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    );
+}
+
+/// A device sending and receiving MIDI event packets, e.g. a USB MIDI
+/// function.
+pub trait Midi<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Send the packets in `buffer[..len]`. `len` must be a non-zero
+    /// multiple of `EVENT_PACKET_LEN`, otherwise `SIZE` is returned. Returns
+    /// `BUSY` if a send is in progress.
+    fn send(
+        &self,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Receive the next packets into `buffer`, which must hold at least one
+    /// packet. Returns `BUSY` if a receive is in progress. Packets that
+    /// arrive while no receive is in progress are held back.
+    fn receive(&self, buffer: &'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
@@ -6,15 +6,21 @@
 
 pub mod adc;
 pub mod analog_comparator;
+pub mod audio;
 pub mod ble_advertising;
+pub mod block_storage;
 pub mod bus8080;
 pub mod buzzer;
 pub mod can;
+pub mod counter;
 pub mod crc;
 pub mod dac;
+pub mod device_id;
 pub mod digest;
 pub mod eic;
+pub mod ethernet;
 pub mod entropy;
+pub mod filesystem;
 pub mod flash;
 pub mod gpio;
 pub mod gpio_async;
@@ -23,14 +29,20 @@ pub mod i2c;
 pub mod kv_system;
 pub mod led;
 pub mod log;
+pub mod midi;
 pub mod nonvolatile_storage;
 pub mod public_key_crypto;
+pub mod pulse;
 pub mod pwm;
+pub mod qspi;
 pub mod radio;
+pub mod reset;
 pub mod rng;
 pub mod screen;
+pub mod sdmmc;
 pub mod sensors;
 pub mod spi;
+pub mod swd;
 pub mod symmetric_encryption;
 pub mod text_screen;
 pub mod time;
@@ -38,6 +50,7 @@ pub mod touch;
 pub mod uart;
 pub mod usb;
 pub mod usb_hid;
+pub mod usb_host;
 
 /// Shared interface for configuring components.
 pub trait Controller {
@@ -0,0 +1,51 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for generating timed pulses on a pin.
+//!
+//! Unlike toggling a GPIO from an alarm callback, implementations drive the
+//! pin from a hardware timer, so the pulse width and period are not affected
+//! by interrupt latency.
+
+use crate::ErrorCode;
+
+pub trait PulseClient {
+    /// Called once all requested pulses were generated, or after the pulses
+    /// were stopped with `stop()`.
+    fn pulses_done(&self, result: Result<(), ErrorCode>);
+}
+
+pub trait PulseGenerator<'a> {
+    fn set_client(&self, client: &'a dyn PulseClient);
+
+    /// Generate `count` pulses that are `width_us` microseconds long, one
+    /// every `period_us` microseconds. If `count` is 0, pulses are generated
+    /// until `stop()` is called. If `count` is 1, `period_us` is ignored and
+    /// the pulse starts right away. Otherwise the first pulse starts within
+    /// one period.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: Pulses are being generated. `pulses_done()` is called
+    ///   once they are done, unless `count` is 0.
+    /// - `BUSY`: Pulses are already being generated.
+    /// - `INVAL`: `width_us` is 0, not shorter than `period_us`, or
+    ///   `period_us` is larger than `max_period_us()`.
+    /// - `FAIL`: The hardware could not be configured.
+    fn start(&self, width_us: u32, period_us: u32, count: usize) -> Result<(), ErrorCode>;
+
+    /// Stop generating pulses and return the pin to its inactive level.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The pulses were stopped. `pulses_done()` is called with
+    ///   `CANCEL`.
+    /// - `OFF`: No pulses were being generated.
+    fn stop(&self) -> Result<(), ErrorCode>;
+
+    /// Longest supported pulse period (and width) in microseconds.
+    fn max_period_us(&self) -> u32;
+
+    fn is_running(&self) -> bool;
+}
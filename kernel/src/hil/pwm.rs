@@ -68,3 +68,56 @@ pub trait PwmPin {
     /// Same as the `get_maximum_duty_cycle` function in the `Pwm` trait.
     fn get_maximum_duty_cycle(&self) -> usize;
 }
+
+/// An output started with `PwmPhase::start_phased()`.
+pub struct PhasedOutput<'a, P> {
+    pub pin: &'a P,
+    /// Duty cycle, as for `Pwm::start()`.
+    pub duty_cycle: usize,
+    /// Delay of the period of the output against the period of the other
+    /// outputs, as a portion of `get_maximum_duty_cycle()`. For example, the
+    /// outputs of a two-phase interleaved converter use 0 and
+    /// `get_maximum_duty_cycle() / 2`.
+    pub phase: usize,
+}
+
+/// PWM outputs that run synchronized, with a phase offset between them.
+pub trait PwmPhase: Pwm {
+    /// Start all `outputs` at `frequency_hz`, synchronized to each other, and
+    /// each delayed by its phase.
+    ///
+    /// Returns `INVAL` if a duty cycle or phase is out of range, or if the
+    /// hardware cannot give the outputs different phases, e.g. because they
+    /// share a counter and were given different phases. In this case, none
+    /// of the outputs are started.
+    fn start_phased(
+        &self,
+        outputs: &[PhasedOutput<Self::Pin>],
+        frequency_hz: usize,
+    ) -> Result<(), ErrorCode>;
+}
+
+/// A pair of complementary PWM outputs, e.g. for the high and the low side
+/// switch of a half bridge.
+pub trait PwmComplementary: Pwm {
+    /// Generate a PWM signal on `pin` at the given frequency and duty cycle,
+    /// as `Pwm::start()` does, and its inverse on `complementary_pin`.
+    ///
+    /// After each edge of either output, both outputs are low for
+    /// `dead_time_ns` nanoseconds, so the switches they drive are never on
+    /// at the same time. The dead time is taken from the time the
+    /// complementary output is high, and is rounded up to the resolution of
+    /// the hardware.
+    ///
+    /// Returns `INVAL` if the hardware cannot drive the pins as a pair, or
+    /// if the dead time is longer than half of the period. Both outputs are
+    /// stopped with `Pwm::stop()` on `pin`.
+    fn start_complementary(
+        &self,
+        pin: &Self::Pin,
+        complementary_pin: &Self::Pin,
+        frequency_hz: usize,
+        duty_cycle: usize,
+        dead_time_ns: usize,
+    ) -> Result<(), ErrorCode>;
+}
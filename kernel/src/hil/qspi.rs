@@ -0,0 +1,95 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for external NOR flash connected to a quad-SPI controller.
+//!
+//! Quad-SPI controllers send the flash commands themselves: a read, write or
+//! erase is a single operation on a byte address of the flash, without the
+//! caller composing command bytes as it would over `hil::spi`. Reads,
+//! writes and erases through `QspiFlash` are *indirect*, the controller
+//! moves the data to or from a buffer.
+//!
+//! Many controllers can also map the flash into the address space of the
+//! CPU, so that it can be read (or code can be executed from it, XIP) like
+//! internal memory. Controllers that support this implement
+//! `QspiMemoryMapped`. While the flash is memory mapped, indirect operations
+//! may not be possible, and writes or erases change what the mapping shows.
+
+use crate::ErrorCode;
+
+/// Size of the sectors erased by `erase_sector()`, the smallest unit that
+/// NOR flashes erase.
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Size of the pages of a page program command. Writes are split into
+/// page programs by the implementation, callers do not have to align them.
+pub const PROGRAM_PAGE_SIZE: usize = 256;
+
+pub trait QspiFlashClient {
+    /// A read started with `read()` completed. `len` bytes were read into
+    /// `buffer`.
+    fn read_done(&self, buffer: &'static mut [u8], len: usize, result: Result<(), ErrorCode>);
+
+    /// A write started with `write()` completed. `len` bytes of `buffer`
+    /// were written.
+    fn write_done(&self, buffer: &'static mut [u8], len: usize, result: Result<(), ErrorCode>);
+
+    /// An erase started with `erase_sector()` completed.
+    fn erase_done(&self, result: Result<(), ErrorCode>);
+}
+
+pub trait QspiFlash<'a> {
+    fn set_client(&self, client: &'a dyn QspiFlashClient);
+
+    /// Size of the flash in bytes.
+    fn size(&self) -> usize;
+
+    /// Read `len` bytes starting at `address` into `buffer`.
+    ///
+    /// Returns `INVAL` if the range is outside the flash or `buffer` is
+    /// shorter than `len`, `BUSY` if an operation is in progress, and
+    /// `OFF` if the flash is memory mapped and the controller cannot do
+    /// indirect operations meanwhile. Implementations may require `address`
+    /// and `len` to be multiples of four, or `buffer` to be word aligned,
+    /// and return `INVAL` otherwise.
+    fn read(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Write `len` bytes of `buffer` starting at `address`. The bytes must
+    /// be erased before, NOR flash can only clear bits when writing.
+    ///
+    /// Returns the same errors as `read()`.
+    fn write(
+        &self,
+        address: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Erase the sector of `SECTOR_SIZE` bytes starting at `address`, which
+    /// must be a multiple of `SECTOR_SIZE`.
+    ///
+    /// Returns `INVAL` if the address is not the start of a sector, and
+    /// `BUSY` or `OFF` like `read()`.
+    fn erase_sector(&self, address: usize) -> Result<(), ErrorCode>;
+}
+
+/// Reads of the flash through the memory map of the CPU.
+pub trait QspiMemoryMapped {
+    /// Map the flash into memory. Returns `BUSY` if an indirect operation
+    /// is in progress.
+    fn enable_memory_mapped(&self) -> Result<(), ErrorCode>;
+
+    /// Stop mapping the flash, so that indirect operations can be used
+    /// again.
+    fn disable_memory_mapped(&self) -> Result<(), ErrorCode>;
+
+    /// The contents of the flash while it is memory mapped, `None` while it
+    /// is not.
+    fn memory_mapped(&self) -> Option<&'static [u8]>;
+}
@@ -35,6 +35,12 @@ pub trait PowerClient {
     fn changed(&self, on: bool);
 }
 
+pub trait EnergyDetectClient {
+    /// An energy detection on `channel` finished, with the peak energy
+    /// measured, in dBm.
+    fn energy_detect_done(&self, channel: u8, result: Result<i8, ErrorCode>);
+}
+
 /// These constants are used for interacting with the SPI buffer, which contains
 /// a 1-byte SPI command, a 1-byte PHY header, and then the 802.15.4 frame. In
 /// theory, the number of extra bytes in front of the frame can depend on the
@@ -107,6 +113,75 @@ pub trait RadioConfig<'a> {
     fn set_channel(&self, chan: u8) -> Result<(), ErrorCode>;
 }
 
+/// The average link quality of the frames received on a channel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkQuality {
+    /// Received signal strength, in dBm
+    pub rssi: i8,
+    /// Link quality indicator, from 0 (worst) to 255 (best)
+    pub lqi: u8,
+    /// The number of frames averaged
+    pub frames: u32,
+}
+
+impl LinkQuality {
+    /// Add a frame to the exponential moving average, which weighs it
+    /// with 1/8. The first frame initializes the average.
+    pub fn add(&mut self, rssi: i8, lqi: u8) {
+        if self.frames == 0 {
+            self.rssi = rssi;
+            self.lqi = lqi;
+        } else {
+            self.rssi = (self.rssi as i16 + (rssi as i16 - self.rssi as i16) / 8) as i8;
+            self.lqi = (self.lqi as i16 + (lqi as i16 - self.lqi as i16) / 8) as u8;
+        }
+        self.frames = self.frames.saturating_add(1);
+    }
+}
+
+/// Measure how congested channels are, and how well frames are received on
+/// the current channel, e.g. to pick the channel of a network.
+pub trait RadioChannelQuality<'a> {
+    fn set_energy_detect_client(&self, client: &'a dyn EnergyDetectClient);
+
+    /// Measure the energy on `channel` for `duration_us` microseconds, and
+    /// report its peak to the client. The radio does not receive frames
+    /// meanwhile, and listens on the configured channel again afterwards.
+    /// Returns `BUSY` while transmitting or measuring, and `INVAL` for a
+    /// channel the radio does not support.
+    fn energy_detect(&self, channel: u8, duration_us: u32) -> Result<(), ErrorCode>;
+
+    /// The average link quality of the frames received with a valid CRC
+    /// on the current channel, or `None` if none were received since the
+    /// channel changed or the average was reset.
+    fn link_quality(&self) -> Option<LinkQuality>;
+    fn reset_link_quality(&self);
+}
+
+/// The unit of the period and phase of coordinated sampled listening, in
+/// microseconds: 10 symbols of the 2.4 GHz PHY.
+pub const CSL_UNIT_US: u32 = 160;
+
+/// Coordinated sampled listening (CSL, IEEE 802.15.4-2015 6.12.2.6).
+///
+/// Instead of listening continuously, a receiver listens in a short window
+/// once every period and keeps its radio off in between. The frames it
+/// sends carry a CSL IE with its period and phase, the time until its next
+/// window, so that its parent sends frames to it when it listens.
+pub trait RadioCsl {
+    /// Listen for `window_us` microseconds every `period` units of
+    /// `CSL_UNIT_US`, or continuously if `period` is 0. Returns `INVAL` if
+    /// the window does not fit in the period.
+    fn set_csl_period(&self, period: u16, window_us: u32) -> Result<(), ErrorCode>;
+
+    /// The period, or 0 when listening continuously.
+    fn csl_period(&self) -> u16;
+
+    /// The time from now until the middle of the next receive window, in
+    /// units of `CSL_UNIT_US`, for the CSL IE of a frame sent now.
+    fn csl_phase(&self) -> u16;
+}
+
 pub trait RadioData<'a> {
     fn set_transmit_client(&self, client: &'a dyn TxClient);
     fn set_receive_client(&self, client: &'a dyn RxClient, receive_buffer: &'static mut [u8]);
@@ -118,3 +193,26 @@ pub trait RadioData<'a> {
         frame_len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_quality_average() {
+        let mut quality = LinkQuality::default();
+        quality.add(-60, 200);
+        assert_eq!(
+            quality,
+            LinkQuality {
+                rssi: -60,
+                lqi: 200,
+                frames: 1
+            }
+        );
+        quality.add(-84, 120);
+        assert_eq!(quality.rssi, -63);
+        assert_eq!(quality.lqi, 190);
+        assert_eq!(quality.frames, 2);
+    }
+}
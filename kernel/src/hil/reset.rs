@@ -0,0 +1,31 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for reading why the chip was last reset.
+
+/// Cause of the last reset of the chip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResetReason {
+    /// The chip does not report the cause.
+    Unknown = 0,
+    /// The chip was powered on.
+    PowerOn = 1,
+    /// The reset pin was asserted.
+    Pin = 2,
+    Watchdog = 3,
+    /// Software requested a reset, e.g. with `SYSRESETREQ`.
+    Software = 4,
+    /// The CPU locked up.
+    Lockup = 5,
+    Brownout = 6,
+    /// The chip woke up from a power mode it leaves through a reset, e.g.
+    /// System OFF on the nRF52.
+    Wakeup = 7,
+}
+
+pub trait ResetReasonSource {
+    /// The cause of the last reset, the same for the whole time the chip is
+    /// running.
+    fn reset_reason(&self) -> ResetReason;
+}
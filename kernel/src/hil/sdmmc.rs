@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for native SD and eMMC host controllers.
+//!
+//! Unlike accessing a card over SPI, a host controller speaks the native SD
+//! protocol: commands and responses use a dedicated command line, and data
+//! is transferred on 1, 4 or 8 data lines with the CRCs computed by the
+//! hardware. This HIL only sends commands and moves data blocks, the card
+//! initialization sequence and the meaning of the commands are left to the
+//! capsule using it (e.g. `capsules_extra::sdmmc`).
+
+use crate::ErrorCode;
+
+/// Number of data lines used for transfers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BusWidth {
+    One,
+    Four,
+    Eight,
+}
+
+/// Response the card sends to a command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResponseType {
+    /// No response, e.g. CMD0.
+    None,
+    /// 48-bit response with a CRC (R1, R1b, R5, R6 and R7).
+    Short,
+    /// 48-bit response without a valid CRC (R3 and R4), whose CRC error is
+    /// ignored.
+    ShortNoCrc,
+    /// 136-bit response (R2).
+    Long,
+}
+
+/// Response received from the card.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Response {
+    None,
+    /// The 32 bits of card status or register contents.
+    Short(u32),
+    /// The 128 bits of the CID or CSD register, most significant word first.
+    /// Bits 0-7 of the last word do not hold register contents.
+    Long([u32; 4]),
+}
+
+impl Response {
+    /// The contents of a short response, 0 for other responses.
+    pub fn short(&self) -> u32 {
+        match self {
+            Response::Short(value) => *value,
+            _ => 0,
+        }
+    }
+}
+
+/// Direction of a data transfer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// From the card to the buffer.
+    Read,
+    /// From the buffer to the card.
+    Write,
+}
+
+pub trait Client {
+    /// A command started with `send_command()` completed.
+    ///
+    /// Errors:
+    ///
+    /// - `NOACK`: The card did not respond.
+    /// - `FAIL`: The CRC of the response was wrong.
+    fn command_done(&self, result: Result<Response, ErrorCode>);
+
+    /// A transfer started with `transfer()` completed, or failed. `response`
+    /// is the response to the command that started the transfer.
+    ///
+    /// Errors:
+    ///
+    /// - `NOACK`: The card did not respond to the command.
+    /// - `FAIL`: The CRC of the response or of a data block was wrong, or
+    ///   the card did not send data in time.
+    /// - `SIZE`: A FIFO overrun or underrun, the data was not transferred
+    ///   fast enough.
+    fn transfer_done(&self, buffer: &'static mut [u8], result: Result<Response, ErrorCode>);
+}
+
+pub trait SdMmcHost<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Set the card clock to at most `hz`. Returns the frequency that is
+    /// used. The clock must be at most 400 kHz until the card is
+    /// initialized.
+    fn set_clock(&self, hz: u32) -> Result<u32, ErrorCode>;
+
+    /// Use `width` data lines. The card must be switched to the same width
+    /// before. Returns `NOSUPPORT` if the controller or the board does not
+    /// have that many lines.
+    fn set_bus_width(&self, width: BusWidth) -> Result<(), ErrorCode>;
+
+    /// Widest bus the controller and board support.
+    fn max_bus_width(&self) -> BusWidth;
+
+    /// Send command `index` with `argument` and wait for the response.
+    /// `command_done()` is called once it is received.
+    ///
+    /// Returns `BUSY` if a command or transfer is in progress.
+    fn send_command(
+        &self,
+        index: u8,
+        argument: u32,
+        response: ResponseType,
+    ) -> Result<(), ErrorCode>;
+
+    /// Send command `index` with `argument`, which starts a data transfer of
+    /// `blocks` blocks of `block_len` bytes, and transfer the data to or
+    /// from `buffer`. `transfer_done()` is called once all blocks are
+    /// transferred. Multiple block transfers are not stopped by the
+    /// controller, the caller sends the stop command (CMD12) afterwards.
+    ///
+    /// Returns `INVAL` if `block_len` is not a supported block length or
+    /// `buffer` is too short, and `BUSY` if a command or transfer is in
+    /// progress.
+    fn transfer(
+        &self,
+        index: u8,
+        argument: u32,
+        direction: Direction,
+        buffer: &'static mut [u8],
+        block_len: usize,
+        blocks: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
@@ -159,19 +159,19 @@ pub trait NineDof<'a> {
     fn set_client(&self, client: &'a dyn NineDofClient);
 
     /// Get a single instantaneous reading of the acceleration in the
-    /// X,Y,Z directions.
+    /// X,Y,Z directions, in milli-g.
     fn read_accelerometer(&self) -> Result<(), ErrorCode> {
         Err(ErrorCode::NODEVICE)
     }
 
     /// Get a single instantaneous reading from the magnetometer in all
-    /// three directions.
+    /// three directions, in milligauss.
     fn read_magnetometer(&self) -> Result<(), ErrorCode> {
         Err(ErrorCode::NODEVICE)
     }
 
     /// Get a single instantaneous reading from the gyroscope of the rotation
-    /// around all three axes.
+    /// around all three axes, in millidegrees per second.
     fn read_gyroscope(&self) -> Result<(), ErrorCode> {
         Err(ErrorCode::NODEVICE)
     }
@@ -179,8 +179,8 @@ pub trait NineDof<'a> {
 
 /// Client for receiving done events from the chip.
 pub trait NineDofClient {
-    /// Signals a command has finished. The arguments will most likely be passed
-    /// over the syscall interface to an application.
+    /// Signals a command has finished, with the X, Y and Z values of the
+    /// reading as `i32`.
     fn callback(&self, arg1: usize, arg2: usize, arg3: usize);
 }
 
@@ -0,0 +1,92 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for Serial Wire Debug (SWD) probes.
+//!
+//! A probe accesses the debug port (DP) and the access ports (AP) of the
+//! debug interface of a target chip over two wires, SWCLK and SWDIO. Each
+//! transfer reads or writes one 32-bit register. Transfers are short, so the
+//! interface is synchronous: a transfer has finished when `transfer()`
+//! returns.
+
+use crate::ErrorCode;
+
+/// Bit of a transfer request that selects an access port register instead of
+/// a debug port register.
+pub const REQUEST_AP: u8 = 1 << 0;
+/// Bit of a transfer request that makes it a read.
+pub const REQUEST_READ: u8 = 1 << 1;
+/// Bits of a transfer request with bits 2 and 3 of the register address.
+pub const REQUEST_ADDRESS: u8 = 0b11 << 2;
+
+/// Why a transfer failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferError {
+    /// The target is busy, the transfer should be repeated.
+    Wait,
+    /// The target reported a fault, which must be cleared through the ABORT
+    /// register of the debug port.
+    Fault,
+    /// The target did not acknowledge the request.
+    NoAck,
+    /// The parity of the data read from the target was wrong.
+    Parity,
+}
+
+/// Timing of the wire protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Configuration {
+    /// Number of turnaround cycles when the direction of SWDIO changes,
+    /// from 1 to 4.
+    pub turnaround_cycles: u8,
+    /// Number of idle cycles after each transfer.
+    pub idle_cycles: u8,
+    /// Whether the data phase is clocked after a `Wait` or `Fault`
+    /// acknowledgement. This is needed if sticky overrun detection is
+    /// enabled in the debug port.
+    pub data_phase: bool,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Configuration {
+            turnaround_cycles: 1,
+            idle_cycles: 0,
+            data_phase: false,
+        }
+    }
+}
+
+pub trait Swd {
+    /// Start driving SWCLK and SWDIO.
+    fn enable(&self);
+
+    /// Stop driving SWCLK and SWDIO, so that another probe can be connected.
+    fn disable(&self);
+
+    /// Set the frequency of SWCLK. Returns the frequency the probe came
+    /// closest to without exceeding it, or the lowest frequency it supports.
+    fn set_clock_hz(&self, frequency_hz: u32) -> u32;
+
+    /// Set the timing of the wire protocol.
+    fn configure(&self, configuration: Configuration);
+
+    /// Clock the first `count` bits of `data` out on SWDIO, starting with the
+    /// least significant bit of the first byte. This is used for line resets
+    /// and to switch a target from JTAG to SWD.
+    fn write_sequence(&self, data: &[u8], count: usize);
+
+    /// Run a transfer described by the `REQUEST_*` bits of `request`. A read
+    /// stores the value read in `data`, a write writes `data`.
+    ///
+    /// Reads of access port registers are posted by the target: they return
+    /// the value of the previous access port read, and the value of this
+    /// read is returned by the next one, or by reading the RDBUFF register
+    /// of the debug port.
+    fn transfer(&self, request: u8, data: &mut u32) -> Result<(), TransferError>;
+
+    /// Assert or release the reset line of the target. Returns `NOSUPPORT`
+    /// if the probe is not connected to it.
+    fn set_reset(&self, asserted: bool) -> Result<(), ErrorCode>;
+}
@@ -120,6 +120,8 @@ pub trait CCMClient {
 }
 
 pub const CCM_NONCE_LENGTH: usize = 13;
+/// The shortest nonce of CCM, which leaves 8 bytes for the message length.
+pub const CCM_MIN_NONCE_LENGTH: usize = 7;
 
 pub trait AES128CCM<'a> {
     /// Set the client instance which will receive `crypt_done()` callbacks
@@ -129,6 +131,11 @@ pub trait AES128CCM<'a> {
     fn set_key(&self, key: &[u8]) -> Result<(), ErrorCode>;
 
     /// Set the nonce (length NONCE_LENGTH) to be used for CCM encryption
+    ///
+    /// Implementations may also accept shorter nonces, down to
+    /// `CCM_MIN_NONCE_LENGTH`, as used by TLS and DTLS with 12 bytes: the
+    /// length field of the message then takes `15 - nonce.len()` bytes.
+    /// Returns `INVAL` for lengths that are not supported.
     fn set_nonce(&self, nonce: &[u8]) -> Result<(), ErrorCode>;
 
     /// Try to begin the encryption/decryption process
@@ -35,6 +35,9 @@ pub trait Ticks: Clone + Copy + From<u32> + fmt::Debug + Ord + PartialOrd + Eq {
     /// are 32 bits.
     fn into_u32(self) -> u32;
 
+    /// Converts the type into a `u64`, filling the higher bits with 0.
+    fn into_u64(self) -> u64;
+
     /// Add two values, wrapping around on overflow using standard
     /// unsigned arithmetic.
     fn wrapping_add(self, other: Self) -> Self;
@@ -405,6 +408,10 @@ impl Ticks for Ticks32 {
         self.0
     }
 
+    fn into_u64(self) -> u64 {
+        self.0 as u64
+    }
+
     fn wrapping_add(self, other: Self) -> Self {
         Ticks32(self.0.wrapping_add(other.0))
     }
@@ -486,6 +493,10 @@ impl Ticks for Ticks24 {
         self.0
     }
 
+    fn into_u64(self) -> u64 {
+        self.0 as u64
+    }
+
     fn wrapping_add(self, other: Self) -> Self {
         Ticks24(self.0.wrapping_add(other.0) & 0x00FFFFFF)
     }
@@ -579,6 +590,10 @@ impl Ticks for Ticks16 {
         self.0 as u32
     }
 
+    fn into_u64(self) -> u64 {
+        self.0 as u64
+    }
+
     fn wrapping_add(self, other: Self) -> Self {
         Ticks16(self.0.wrapping_add(other.0))
     }
@@ -672,6 +687,10 @@ impl Ticks for Ticks64 {
         self.0 as u32
     }
 
+    fn into_u64(self) -> u64 {
+        self.0
+    }
+
     fn wrapping_add(self, other: Self) -> Self {
         Ticks64(self.0.wrapping_add(other.0))
     }
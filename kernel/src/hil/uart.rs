@@ -34,6 +34,9 @@ pub struct Parameters {
     pub width: Width,
     pub parity: Parity,
     pub stop_bits: StopBits,
+    /// Use RTS/CTS hardware flow control. UARTs that cannot do this return
+    /// `NOSUPPORT` from `configure()`; see
+    /// `capsules_extra::uart_flow_control` for a GPIO-based alternative.
     pub hw_flow_control: bool,
 }
 
@@ -316,12 +319,29 @@ pub trait ReceiveClient {
 /// - `receive_len_then_message`: This would do a one byte read to get a length
 ///   byte and then read that many more bytes from UART before returning to the
 ///   client.
+///
+/// `receive_automatic` receives frames of unknown length, such as Modbus RTU
+/// frames, NMEA sentences or modem responses, which end with the line going
+/// idle. UARTs implement it with hardware idle-line detection where they
+/// have it; `capsules_extra::uart_idle` implements it in software, with an
+/// alarm, for UARTs that do not.
 pub trait ReceiveAdvanced<'a>: Receive<'a> {
     /// Receive data until `interbyte_timeout` bit periods have passed since the
     /// last byte or buffer is full. Does not timeout until at least one byte
     /// has been received.
     ///
     /// * `interbyte_timeout`: number of bit periods since last data received.
+    ///   Hardware that can only detect a fixed idle period (e.g. one frame)
+    ///   may ignore it.
+    ///
+    /// The receive completes with `received_buffer`. If the line went idle,
+    /// `rval` is `Ok(())` and `rx_len` is the number of bytes received,
+    /// which is less than the requested `rx_len`. A full buffer completes
+    /// like `receive_buffer`. Aborts and receive errors are reported as
+    /// for `receive_buffer`.
+    ///
+    /// Returns the same errors as `receive_buffer`, and `NOSUPPORT` if the
+    /// UART is not set up to detect an idle line.
     fn receive_automatic(
         &self,
         rx_buffer: &'static mut [u8],
@@ -3,6 +3,13 @@
 // Copyright Tock Contributors 2022.
 
 //! Interface to USB controller hardware
+//!
+//! Isochronous endpoints carry one packet per frame, without handshake or
+//! retries. Instead of `packet_in()` and `packet_out()`, the controller calls
+//! `iso_packet_in()` at the start of every frame to get the packet to send in
+//! that frame, and `iso_packet_out()` with the packet the host sent in the
+//! previous frame. Which endpoint numbers can be isochronous is specific to
+//! the controller.
 
 use crate::utilities::cells::VolatileCell;
 
@@ -45,6 +52,25 @@ pub enum TransferType {
     Interrupt,
 }
 
+/// Synchronization type of an isochronous endpoint, bits 3..2 of the
+/// endpoint attributes.
+#[derive(Clone, Copy, Debug)]
+pub enum IsoSynchronization {
+    NoSynchronization = 0,
+    Asynchronous,
+    Adaptive,
+    Synchronous,
+}
+
+/// Usage type of an isochronous endpoint, bits 5..4 of the endpoint
+/// attributes.
+#[derive(Clone, Copy, Debug)]
+pub enum IsoUsage {
+    Data = 0,
+    Feedback,
+    ImplicitFeedbackData,
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum DeviceSpeed {
     Full,
@@ -72,6 +98,19 @@ pub trait Client<'a> {
     ) -> OutResult;
 
     fn packet_transmitted(&'a self, endpoint: usize);
+
+    /// A new frame started on an isochronous IN endpoint. The client writes
+    /// the packet to send during `frame` into the endpoint buffer and returns
+    /// its size. If it returns 0, the controller answers the host with a zero
+    /// length packet.
+    fn iso_packet_in(&'a self, _endpoint: usize, _frame: u16) -> usize {
+        0
+    }
+
+    /// A packet of `packet_bytes` bytes that the host sent during `frame` is
+    /// in the buffer of an isochronous OUT endpoint. The buffer is only
+    /// valid until the next frame starts.
+    fn iso_packet_out(&'a self, _endpoint: usize, _frame: u16, _packet_bytes: u32) {}
 }
 
 #[derive(Debug)]
@@ -0,0 +1,136 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Interface for USB host controllers.
+//!
+//! A host controller drives a single port with one device connected to it
+//! directly, hubs are not supported. The client is told when a device is
+//! connected, resets the port, and then enumerates the device with control
+//! transfers on its default endpoint. Its other endpoints are used through
+//! pipes: a pipe is opened for an endpoint once the device is configured,
+//! and keeps the data toggle of the endpoint across transfers.
+//!
+//! Transfers fail with:
+//!
+//! - `NOACK` if the device did not answer, or its answers were corrupted.
+//! - `FAIL` if the device answered with a STALL handshake.
+//! - `NODEVICE` if the device was disconnected.
+//! - `CANCEL` if the pipe was closed.
+
+use crate::hil::usb::TransferType;
+use crate::ErrorCode;
+
+/// Speed of the device connected to the port.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Low,
+    Full,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    /// From the device to the host.
+    In,
+    /// From the host to the device.
+    Out,
+}
+
+/// An endpoint of the device, other than its default control endpoint.
+#[derive(Clone, Copy, Debug)]
+pub struct Endpoint {
+    /// Address of the device.
+    pub address: u8,
+    /// Number of the endpoint, 1 to 15.
+    pub number: u8,
+    pub direction: Direction,
+    /// `Bulk` or `Interrupt`.
+    pub transfer_type: TransferType,
+    pub max_packet_size: u16,
+}
+
+/// Receives the events of a `UsbHost` controller.
+pub trait Client {
+    /// A device was connected to the port. It must be reset before it can
+    /// be addressed.
+    fn connected(&self);
+
+    /// The device was disconnected. Transfers in progress completed with
+    /// `NODEVICE` before this call, and all pipes are closed.
+    fn disconnected(&self);
+
+    /// The port is enabled after a reset, and the device answers at address
+    /// 0 with `speed`.
+    fn reset_done(&self, result: Result<Speed, ErrorCode>);
+
+    /// The control transfer finished. `len` is the number of bytes moved in
+    /// its data stage.
+    fn control_done(&self, buffer: &'static mut [u8], len: usize, result: Result<(), ErrorCode>);
+
+    /// The transfer on `pipe` finished. `len` is the number of bytes moved.
+    fn transfer_done(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+        result: Result<(), ErrorCode>,
+    );
+}
+
+/// A USB host controller with a single port.
+pub trait UsbHost<'a> {
+    fn set_client(&self, client: &'a dyn Client);
+
+    /// Power the port and start detecting devices. A device that is already
+    /// connected is reported to `Client::connected()`.
+    fn enable(&self) -> Result<(), ErrorCode>;
+
+    /// Power the port off. Transfers in progress are aborted.
+    fn disable(&self);
+
+    /// Start driving a reset on the port. Transfers in progress are
+    /// aborted. Returns `NODEVICE` if no device is connected.
+    fn start_reset(&self) -> Result<(), ErrorCode>;
+
+    /// Stop driving the reset, at least 10 ms after `start_reset()`.
+    /// `Client::reset_done()` is called once the port is enabled.
+    fn end_reset(&self);
+
+    /// Start a control transfer on the default endpoint of the device at
+    /// `address`, with packets of at most `max_packet_size` bytes.
+    ///
+    /// `setup` is the setup packet. Its direction and length select the data
+    /// stage, which moves `buffer[..len]`: `len` must not be more than the
+    /// length of the setup packet. Returns `BUSY` if a control transfer is
+    /// in progress.
+    fn control_transfer(
+        &self,
+        address: u8,
+        max_packet_size: u16,
+        setup: [u8; 8],
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Open a pipe to `endpoint`, and return its number. Returns `NOMEM` if
+    /// the controller has no pipe left, and `NOSUPPORT` for control and
+    /// isochronous endpoints.
+    fn open_pipe(&self, endpoint: Endpoint) -> Result<usize, ErrorCode>;
+
+    /// Close `pipe`. A transfer in progress on it completes with `CANCEL`.
+    fn close_pipe(&self, pipe: usize);
+
+    /// Move `buffer[..len]` on `pipe`.
+    ///
+    /// The controller retries the transactions the device does not accept
+    /// yet, so an IN transfer completes once the device sent data: `len`
+    /// bytes, or a shorter packet. An interrupt IN pipe is polled once every
+    /// frame meanwhile. Returns `BUSY` if a transfer is in progress on the
+    /// pipe, and `INVAL` if the pipe is not open.
+    fn transfer(
+        &self,
+        pipe: usize,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+}
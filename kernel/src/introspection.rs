@@ -127,6 +127,37 @@ impl KernelInfo {
             .process_map_or(0, app, |process| process.debug_timeslice_expiration_count())
     }
 
+    /// Returns the total time in microseconds this app has spent executing.
+    pub fn app_execution_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_execution_time_us())
+    }
+
+    /// Returns the part of the time this app has spent executing, in
+    /// microseconds, that the kernel spent handling its syscalls.
+    pub fn app_syscall_time_us(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> u64 {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_syscall_time_us())
+    }
+
+    /// Returns the number of times this app has been preempted.
+    pub fn number_app_preemptions(
+        &self,
+        app: ProcessId,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> usize {
+        self.kernel
+            .process_map_or(0, app, |process| process.debug_preemption_count())
+    }
+
     /// Returns a tuple of the (the number of grants in the grant region this
     /// app has allocated, total number of grants that exist in the system).
     pub fn number_app_grant_uses(
@@ -147,6 +178,18 @@ impl KernelInfo {
         (used, number_of_grants)
     }
 
+    /// Returns a tuple of (the number of grants in the system, the number of
+    /// bytes a single process would consume in its grant region if it
+    /// allocated all of them). This is an upper bound on the grant memory a
+    /// process needs, excluding padding between individual grants.
+    pub fn grant_region_footprint(
+        &self,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> (usize, usize) {
+        let number_of_grants = self.kernel.get_grant_count_and_finalize();
+        (number_of_grants, self.kernel.get_grant_footprint())
+    }
+
     /// Returns the total number of times all processes have exceeded
     /// their timeslices.
     pub fn timeslice_expirations(&self, _capability: &dyn ProcessManagementCapability) -> usize {
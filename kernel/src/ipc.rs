@@ -6,6 +6,9 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! Processes that exchange messages rather than share memory can use the
+//! message queue driver in `capsules_extra::message_queue` instead.
 
 use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
@@ -0,0 +1,261 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Lending of buffers between processes.
+//!
+//! Passing a large buffer, e.g. a frame of a camera, from a driver process
+//! to a processing process through IPC messages copies it twice. With this
+//! driver, a process instead lends a buffer it allowed to another process.
+//! The kernel adds an MPU region covering the buffer to the borrowing
+//! process, which then accesses the buffer directly, until it returns the
+//! buffer or the lender revokes the loan. The MPU configuration of a process
+//! is loaded when the kernel switches to it, so the region only grants
+//! access while the borrower runs.
+//!
+//! A buffer allowed read-only is lent read-only, a buffer allowed read-write
+//! is lent read-write. The lender must not use the buffer until the loan
+//! ends. The MPU must be able to cover the buffer with a region, so its
+//! address and length must meet the alignment requirements of the MPU.
+//!
+//! Loans end when the lender or the borrower terminates. The driver must be
+//! added to the observers of the kernel for this:
+//!
+//! ```rust,ignore
+//! let buffer_lending = static_init!(
+//!     kernel::ipc_lending::BufferLending<4>,
+//!     kernel::ipc_lending::BufferLending::new(
+//!         board_kernel,
+//!         kernel::ipc_lending::DRIVER_NUM,
+//!         &memory_allocation_cap,
+//!     )
+//! );
+//! board_kernel.add_process_observer(buffer_lending, &process_management_capability);
+//! ```
+
+use core::cell::Cell;
+
+use crate::capabilities::MemoryAllocationCapability;
+use crate::collections::list::ListLink;
+use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use crate::kernel::Kernel;
+use crate::platform::mpu;
+use crate::process::ProcessId;
+use crate::process_observer::ProcessObserver;
+use crate::processbuffer::ReadableProcessBuffer;
+use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::ErrorCode;
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x1000C;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Buffer lent read-only.
+    pub(super) const LEND: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer lent read-write.
+    pub(super) const LEND: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 1;
+}
+
+mod upcall {
+    /// A buffer was lent to the process.
+    pub(super) const LENT: usize = 0;
+    /// A loan of or to the process ended.
+    pub(super) const ENDED: usize = 1;
+    /// The number of upcalls the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 2;
+}
+
+#[derive(Clone, Copy)]
+struct Loan {
+    lender: ProcessId,
+    borrower: ProcessId,
+    /// The region of the buffer in the MPU configuration of the borrower.
+    region: mpu::Region,
+}
+
+pub struct BufferLending<const N: usize> {
+    kernel: &'static Kernel,
+    loans: [Cell<Option<Loan>>; N],
+    apps: Grant<
+        (),
+        UpcallCount<{ upcall::COUNT }>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    next_observer: ListLink<'static, dyn ProcessObserver>,
+}
+
+impl<const N: usize> BufferLending<N> {
+    pub fn new(
+        kernel: &'static Kernel,
+        driver_num: usize,
+        capability: &dyn MemoryAllocationCapability,
+    ) -> Self {
+        Self {
+            kernel,
+            loans: [(); N].map(|()| Cell::new(None)),
+            apps: kernel.create_grant(driver_num, capability),
+            next_observer: ListLink::empty(),
+        }
+    }
+
+    /// Lend the buffer the process allowed read-only, or read-write if
+    /// `writable`, to process `borrower_id`.
+    fn lend(
+        &self,
+        processid: ProcessId,
+        borrower_id: usize,
+        writable: bool,
+    ) -> Result<usize, ErrorCode> {
+        let borrower = self
+            .kernel
+            .process_until(|process| {
+                (process.processid().id() == borrower_id).then(|| process.processid())
+            })
+            .filter(|borrower| *borrower != processid)
+            .ok_or(ErrorCode::INVAL)?;
+        let index = self
+            .loans
+            .iter()
+            .position(|loan| loan.get().is_none())
+            .ok_or(ErrorCode::NOMEM)?;
+
+        let (address, len) = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                if writable {
+                    kernel_data
+                        .get_readwrite_processbuffer(rw_allow::LEND)
+                        .map(|buffer| (buffer.ptr(), buffer.len()))
+                } else {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::LEND)
+                        .map(|buffer| (buffer.ptr(), buffer.len()))
+                }
+            })
+            .map_err(ErrorCode::from)?
+            .map_err(ErrorCode::from)?;
+        if len == 0 {
+            return Err(ErrorCode::RESERVE);
+        }
+
+        let permissions = if writable {
+            mpu::Permissions::ReadWriteOnly
+        } else {
+            mpu::Permissions::ReadOnly
+        };
+        let region = self
+            .kernel
+            .process_map_or(Err(ErrorCode::INVAL), borrower, |process| {
+                process
+                    .add_mpu_region_with_permissions(address, len, len, permissions)
+                    .ok_or(ErrorCode::NOMEM)
+            })?;
+        self.loans[index].set(Some(Loan {
+            lender: processid,
+            borrower,
+            region,
+        }));
+
+        let _ = self.apps.enter(borrower, |_, kernel_data| {
+            kernel_data
+                .schedule_upcall(upcall::LENT, (index, address as usize, len))
+                .ok();
+        });
+        Ok(index)
+    }
+
+    /// End loan `index`, and tell the other party of the loan.
+    fn end(&self, index: usize, loan: Loan, ended_by: ProcessId) {
+        self.loans[index].set(None);
+        let _ = self
+            .kernel
+            .process_map_or(Err(ErrorCode::FAIL), loan.borrower, |process| {
+                process.remove_mpu_region(loan.region)
+            });
+        let notify = if ended_by == loan.lender {
+            loan.borrower
+        } else {
+            loan.lender
+        };
+        let _ = self.apps.enter(notify, |_, kernel_data| {
+            kernel_data
+                .schedule_upcall(upcall::ENDED, (index, 0, 0))
+                .ok();
+        });
+    }
+}
+
+impl<const N: usize> ProcessObserver for BufferLending<N> {
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        &self.next_observer
+    }
+
+    /// End the loans of a process that terminated.
+    fn process_terminated(&self, processid: ProcessId) {
+        for (index, loan) in self.loans.iter().enumerate() {
+            if let Some(loan) = loan
+                .get()
+                .filter(|loan| loan.lender == processid || loan.borrower == processid)
+            {
+                self.end(index, loan, processid);
+            }
+        }
+    }
+}
+
+impl<const N: usize> SyscallDriver for BufferLending<N> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Lend the buffer in read-only allow buffer 0 to the process with
+    ///   identifier `data1`, or the one in read-write allow buffer 0 if
+    ///   `data2` is 1. Returns the number of the loan. Returns `INVAL` if
+    ///   there is no such process, `RESERVE` if no buffer is allowed, and
+    ///   `NOMEM` if there are too many loans or the MPU cannot cover the
+    ///   buffer.
+    /// - `2`: End loan `data1`, as its lender or its borrower.
+    ///
+    /// Upcall 0 is scheduled for the borrower when a buffer is lent to it,
+    /// with the number of the loan, and the address and the length of the
+    /// buffer. Upcall 1 is scheduled when the other party ends a loan, with
+    /// the number of the loan.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => match self.lend(processid, data1, data2 == 1) {
+                Ok(index) => CommandReturn::success_u32(index as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+            2 => match self.loans.get(data1).and_then(|loan| loan.get()) {
+                Some(loan) if loan.lender == processid || loan.borrower == processid => {
+                    self.end(data1, loan, processid);
+                    CommandReturn::success()
+                }
+                _ => CommandReturn::failure(ErrorCode::INVAL),
+            },
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), crate::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
@@ -0,0 +1,338 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Registry of IPC services.
+//!
+//! IPC discovery finds a service by the package name of the process that
+//! provides it, so a client breaks when the service moves to another
+//! process, and cannot tell which version of the service it found. With this
+//! driver, processes instead publish services under a UTF-8 name and a
+//! semantic version. Clients look them up by name and the version they need,
+//! and get the IPC service descriptor of the provider, which they pass to
+//! the notify commands of the IPC driver.
+//!
+//! Clients can also subscribe to the registry to learn when services are
+//! published or withdrawn. The services of a process are withdrawn when it
+//! terminates, including when it restarts, after which it publishes them
+//! again.
+//!
+//! Versions are passed as `major << 24 | minor << 16 | patch`. A service
+//! matches a lookup if it has the same major version and is not older than
+//! the requested version.
+//!
+//! The registry must be added to the observers of the kernel so it learns
+//! about processes that terminate:
+//!
+//! ```rust,ignore
+//! let ipc_registry = static_init!(
+//!     kernel::ipc_registry::ServiceRegistry<8>,
+//!     kernel::ipc_registry::ServiceRegistry::new(
+//!         board_kernel,
+//!         kernel::ipc_registry::DRIVER_NUM,
+//!         &memory_allocation_cap,
+//!     )
+//! );
+//! board_kernel.add_process_observer(ipc_registry, &process_management_capability);
+//! ```
+
+use core::cell::Cell;
+
+use crate::capabilities::MemoryAllocationCapability;
+use crate::collections::list::ListLink;
+use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use crate::kernel::Kernel;
+use crate::process::ProcessId;
+use crate::process_observer::ProcessObserver;
+use crate::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::ErrorCode;
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x1000B;
+
+/// Maximum length of the name of a service, in bytes.
+pub const NAME_LEN: usize = 32;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Name of the service to publish or look up.
+    pub(super) const NAME: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 1;
+}
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the name of an enumerated service is copied to.
+    pub(super) const NAME: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant.
+    pub(super) const COUNT: u8 = 1;
+}
+
+#[derive(Clone, Copy)]
+struct Service {
+    provider: ProcessId,
+    name: [u8; NAME_LEN],
+    name_len: usize,
+    version: u32,
+}
+
+impl Service {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    /// Whether this service can be used by a client that needs `version`.
+    fn compatible(&self, version: u32) -> bool {
+        self.version >> 24 == version >> 24 && self.version >= version
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    subscribed: bool,
+}
+
+pub struct ServiceRegistry<const N: usize> {
+    services: [Cell<Option<Service>>; N],
+    apps: Grant<
+        App,
+        UpcallCount<1>,
+        AllowRoCount<{ ro_allow::COUNT }>,
+        AllowRwCount<{ rw_allow::COUNT }>,
+    >,
+    next_observer: ListLink<'static, dyn ProcessObserver>,
+}
+
+impl<const N: usize> ServiceRegistry<N> {
+    pub fn new(
+        kernel: &'static Kernel,
+        driver_num: usize,
+        capability: &dyn MemoryAllocationCapability,
+    ) -> Self {
+        Self {
+            services: [(); N].map(|()| Cell::new(None)),
+            apps: kernel.create_grant(driver_num, capability),
+            next_observer: ListLink::empty(),
+        }
+    }
+
+    /// Copy the name in the read-only allow buffer of the process.
+    fn read_name(&self, processid: ProcessId) -> Result<([u8; NAME_LEN], usize), ErrorCode> {
+        let mut name = [0; NAME_LEN];
+        let len = self
+            .apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readonly_processbuffer(ro_allow::NAME)
+                    .map_err(ErrorCode::from)
+                    .and_then(|buffer| {
+                        buffer
+                            .enter(|buffer| {
+                                if buffer.len() > NAME_LEN {
+                                    return Err(ErrorCode::SIZE);
+                                }
+                                buffer.copy_to_slice(&mut name[..buffer.len()]);
+                                Ok(buffer.len())
+                            })
+                            .unwrap_or(Err(ErrorCode::INVAL))
+                    })
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+        if len == 0 || core::str::from_utf8(&name[..len]).is_err() {
+            return Err(ErrorCode::INVAL);
+        }
+        Ok((name, len))
+    }
+
+    /// Tell the subscribed processes that the service in `slot` was
+    /// published or withdrawn.
+    fn notify(&self, slot: usize, published: bool, version: u32) {
+        for cntr in self.apps.iter() {
+            cntr.enter(|app, kernel_data| {
+                if app.subscribed {
+                    kernel_data
+                        .schedule_upcall(0, (published as usize, slot, version as usize))
+                        .ok();
+                }
+            });
+        }
+    }
+
+    fn publish(&self, processid: ProcessId, version: u32) -> CommandReturn {
+        let (name, name_len) = match self.read_name(processid) {
+            Ok(name) => name,
+            Err(e) => return CommandReturn::failure(e),
+        };
+        if let Some(service) = self
+            .services
+            .iter()
+            .filter_map(|slot| slot.get())
+            .find(|service| service.name() == &name[..name_len])
+        {
+            return CommandReturn::failure(if service.provider == processid {
+                ErrorCode::ALREADY
+            } else {
+                ErrorCode::BUSY
+            });
+        }
+        let slot = match self.services.iter().position(|slot| slot.get().is_none()) {
+            Some(slot) => slot,
+            None => return CommandReturn::failure(ErrorCode::NOMEM),
+        };
+        self.services[slot].set(Some(Service {
+            provider: processid,
+            name,
+            name_len,
+            version,
+        }));
+        self.notify(slot, true, version);
+        CommandReturn::success_u32(slot as u32)
+    }
+
+    fn withdraw(&self, processid: ProcessId, slot: usize) -> CommandReturn {
+        match self.services.get(slot).and_then(|slot| slot.get()) {
+            Some(service) if service.provider == processid => {
+                self.services[slot].set(None);
+                self.notify(slot, false, service.version);
+                CommandReturn::success()
+            }
+            _ => CommandReturn::failure(ErrorCode::INVAL),
+        }
+    }
+
+    /// Return the IPC service descriptor and the version of the service with
+    /// the name in the read-only allow buffer, if it is compatible with
+    /// `version`.
+    fn find(&self, processid: ProcessId, version: u32) -> CommandReturn {
+        let (name, name_len) = match self.read_name(processid) {
+            Ok(name) => name,
+            Err(e) => return CommandReturn::failure(e),
+        };
+        self.services
+            .iter()
+            .filter_map(|slot| slot.get())
+            .find(|service| service.name() == &name[..name_len])
+            .filter(|service| service.compatible(version))
+            .and_then(|service| {
+                service
+                    .provider
+                    .index()
+                    .map(|index| (index, service.version))
+            })
+            .map_or(
+                CommandReturn::failure(ErrorCode::NODEVICE),
+                |(index, version)| CommandReturn::success_u32_u32(index as u32, version),
+            )
+    }
+
+    /// Copy the name of the service in `slot` to the read-write allow buffer.
+    /// Returns the length of the name and the version, or `(0, 0)` if the
+    /// slot is empty.
+    fn enumerate(&self, processid: ProcessId, slot: usize) -> CommandReturn {
+        let service = match self.services.get(slot) {
+            Some(slot) => slot.get(),
+            None => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        let service = match service {
+            Some(service) => service,
+            None => return CommandReturn::success_u32_u32(0, 0),
+        };
+        self.apps
+            .enter(processid, |_, kernel_data| {
+                kernel_data
+                    .get_readwrite_processbuffer(rw_allow::NAME)
+                    .map_err(ErrorCode::from)
+                    .and_then(|buffer| {
+                        buffer
+                            .mut_enter(|buffer| match buffer.get(0..service.name_len) {
+                                Some(buffer) => {
+                                    buffer.copy_from_slice(service.name());
+                                    Ok(())
+                                }
+                                None => Err(ErrorCode::SIZE),
+                            })
+                            .unwrap_or(Err(ErrorCode::INVAL))
+                    })
+            })
+            .unwrap_or_else(|err| Err(err.into()))
+            .map_or_else(CommandReturn::failure, |()| {
+                CommandReturn::success_u32_u32(service.name_len as u32, service.version)
+            })
+    }
+}
+
+impl<const N: usize> ProcessObserver for ServiceRegistry<N> {
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        &self.next_observer
+    }
+
+    /// Withdraw the services of a process that terminated.
+    fn process_terminated(&self, processid: ProcessId) {
+        for (slot, service) in self.services.iter().enumerate() {
+            if let Some(service) = service
+                .get()
+                .filter(|service| service.provider == processid)
+            {
+                self.services[slot].set(None);
+                self.notify(slot, false, service.version);
+            }
+        }
+    }
+}
+
+impl<const N: usize> SyscallDriver for ServiceRegistry<N> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Publish a service with the name in read-only allow buffer 0 and
+    ///   version `data1`. Returns the slot of the service. Returns `ALREADY`
+    ///   if the calling process published a service with this name, `BUSY`
+    ///   if another process did, and `NOMEM` if the registry is full.
+    /// - `2`: Withdraw the service the calling process published in slot
+    ///   `data1`.
+    /// - `3`: Look up the service with the name in read-only allow buffer 0
+    ///   that is compatible with version `data1`. Returns the IPC service
+    ///   descriptor of its provider and its version, or `NODEVICE` if there
+    ///   is none.
+    /// - `4`: Copy the name of the service in slot `data1` to read-write
+    ///   allow buffer 0. Returns the length of the name and the version of
+    ///   the service, or `(0, 0)` if the slot is empty, and `INVAL` after the
+    ///   last slot.
+    /// - `5`: Subscribe to the registry if `data1` is 1, unsubscribe if it is
+    ///   0.
+    ///
+    /// Upcall 0 is scheduled for subscribed processes when a service is
+    /// published or withdrawn, with 1 or 0 respectively, the slot and the
+    /// version of the service.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.publish(processid, data1 as u32),
+            2 => self.withdraw(processid, data1),
+            3 => self.find(processid, data1 as u32),
+            4 => self.enumerate(processid, data1),
+            5 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.subscribed = data1 != 0;
+                    CommandReturn::success()
+                })
+                .unwrap_or_else(|err| CommandReturn::failure(err.into())),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), crate::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
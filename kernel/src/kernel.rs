@@ -13,7 +13,9 @@ use core::cell::Cell;
 use core::ptr::NonNull;
 use core::slice;
 
+use crate::background_call::BackgroundCall;
 use crate::capabilities;
+use crate::collections::list::List;
 use crate::config;
 use crate::debug;
 use crate::deferred_call::DeferredCall;
@@ -24,6 +26,7 @@ use crate::memop;
 use crate::platform::chip::Chip;
 use crate::platform::mpu::MPU;
 use crate::platform::platform::ContextSwitchCallback;
+use crate::platform::platform::DriverNumberMapping;
 use crate::platform::platform::KernelResources;
 use crate::platform::platform::{ProcessFault, SyscallDriverLookup, SyscallFilter};
 use crate::platform::scheduler_timer::SchedulerTimer;
@@ -31,11 +34,15 @@ use crate::platform::watchdog::WatchDog;
 use crate::process::{self, Process, ProcessId, ShortID, Task};
 use crate::process_checker::{self, CredentialsCheckingPolicy};
 use crate::process_loading::ProcessLoadError;
+use crate::process_observer::{AllowType, ProcessObserver};
 use crate::scheduler::{Scheduler, SchedulingDecision};
 use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::syscall_latency::SyscallClass;
+use crate::syscall_ring;
+use crate::trace::{self, EventKind};
 use crate::upcall::{Upcall, UpcallId};
 use crate::utilities::cells::{NumericCellExt, OptionalCell};
 
@@ -50,7 +57,7 @@ pub(crate) const MIN_QUANTA_THRESHOLD_US: u32 = 500;
 /// Main object for the kernel. Each board will need to create one.
 pub struct Kernel {
     /// This holds a pointer to the static array of Process pointers.
-    processes: &'static [Option<&'static dyn process::Process>],
+    processes: &'static [process::ProcessSlot],
 
     /// A counter which keeps track of how many process identifiers have been
     /// created. This is used to create new unique identifiers for processes.
@@ -61,6 +68,11 @@ pub struct Kernel {
     /// processes are created they can be allocated pointers for each grant.
     grant_counter: Cell<usize>,
 
+    /// Number of bytes a single process would need in its grant region if it
+    /// allocated every grant created with `create_grant()`. This is a worst
+    /// case bound that ignores alignment padding between grants.
+    grant_footprint: Cell<usize>,
+
     /// Flag to mark that grants have been finalized. This means that the kernel
     /// cannot support creating new grants because processes have already been
     /// created and the data structures for grants have already been
@@ -70,11 +82,15 @@ pub struct Kernel {
     init_cap: KernelProcessInitCapability,
 
     checker: ProcessCheckerMachine,
+
+    /// Observers notified about processes, such as the IPC service registry
+    /// and tracers.
+    observers: List<'static, dyn ProcessObserver>,
 }
 
 /// Enum used to inform scheduler why a process stopped executing (aka why
 /// `do_process()` returned).
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum StoppedExecutingReason {
     /// The process returned because it is no longer ready to run.
     NoWorkLeft,
@@ -117,6 +133,72 @@ fn try_allocate_grant(driver: &dyn SyscallDriver, process: &dyn process::Process
     }
 }
 
+/// Replace the driver number of `syscall` as the board maps it.
+fn remap_driver_number(map: &[DriverNumberMapping], syscall: Syscall) -> Syscall {
+    if map.is_empty() {
+        return syscall;
+    }
+    let remap = |driver_number| DriverNumberMapping::remap(map, driver_number);
+    match syscall {
+        Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            upcall_ptr,
+            appdata,
+        } => Syscall::Subscribe {
+            driver_number: remap(driver_number),
+            subdriver_number,
+            upcall_ptr,
+            appdata,
+        },
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            arg0,
+            arg1,
+        } => Syscall::Command {
+            driver_number: remap(driver_number),
+            subdriver_number,
+            arg0,
+            arg1,
+        },
+        Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number,
+            allow_address,
+            allow_size,
+        } => Syscall::ReadWriteAllow {
+            driver_number: remap(driver_number),
+            subdriver_number,
+            allow_address,
+            allow_size,
+        },
+        Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            allow_address,
+            allow_size,
+        } => Syscall::UserspaceReadableAllow {
+            driver_number: remap(driver_number),
+            subdriver_number,
+            allow_address,
+            allow_size,
+        },
+        Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number,
+            allow_address,
+            allow_size,
+        } => Syscall::ReadOnlyAllow {
+            driver_number: remap(driver_number),
+            subdriver_number,
+            allow_address,
+            allow_size,
+        },
+        Syscall::Yield { .. } | Syscall::Memop { .. } | Syscall::Exit { .. } => syscall,
+    }
+}
+
 struct KernelProcessInitCapability {}
 unsafe impl capabilities::ProcessInitCapability for KernelProcessInitCapability {}
 
@@ -124,11 +206,12 @@ struct KernelProcessApprovalCapability {}
 unsafe impl capabilities::ProcessApprovalCapability for KernelProcessApprovalCapability {}
 
 impl Kernel {
-    pub fn new(processes: &'static [Option<&'static dyn process::Process>]) -> Kernel {
+    pub fn new(processes: &'static [process::ProcessSlot]) -> Kernel {
         Kernel {
             processes,
             process_identifier_max: Cell::new(0),
             grant_counter: Cell::new(0),
+            grant_footprint: Cell::new(0),
             grants_finalized: Cell::new(false),
             init_cap: KernelProcessInitCapability {},
             checker: ProcessCheckerMachine {
@@ -137,7 +220,64 @@ impl Kernel {
                 policy: OptionalCell::empty(),
                 processes: processes,
                 approve_cap: KernelProcessApprovalCapability {},
+                checking: Cell::new(false),
+                rescan: Cell::new(false),
             },
+            observers: List::new(),
+        }
+    }
+
+    /// Register an observer that is notified about processes. Observers are
+    /// notified in the order they are registered.
+    pub fn add_process_observer(
+        &self,
+        observer: &'static dyn ProcessObserver,
+        _capability: &dyn capabilities::ProcessManagementCapability,
+    ) {
+        self.observers.push_tail(observer);
+    }
+
+    /// Notify the observers that a process has terminated.
+    pub(crate) fn process_terminated(&self, processid: ProcessId) {
+        self.observers
+            .iter()
+            .for_each(|observer| observer.process_terminated(processid));
+    }
+
+    /// Record an event defined by a capsule with the observers.
+    pub fn trace_event(&self, id: u32, value: u32) {
+        self.trace(EventKind::Capsule, id, value);
+    }
+
+    /// Record an event with the observers.
+    pub(crate) fn trace(&self, kind: EventKind, arg0: u32, arg1: u32) {
+        self.observers
+            .iter()
+            .for_each(|observer| observer.event(kind, arg0, arg1));
+    }
+
+    /// Notify the observers about a successful allow.
+    fn audit_allow(
+        &self,
+        process: &dyn process::Process,
+        driver_num: usize,
+        allow_num: usize,
+        allow_type: AllowType,
+        address: *const u8,
+        size: usize,
+        res: &SyscallReturn,
+    ) {
+        if res.is_success() {
+            self.observers.iter().for_each(|observer| {
+                observer.allowed(
+                    process.processid(),
+                    driver_num,
+                    allow_num,
+                    allow_type,
+                    address,
+                    size,
+                )
+            });
         }
     }
 
@@ -148,18 +288,12 @@ impl Kernel {
         // However, we are not guaranteed that the app still exists at that
         // index in the processes array. To avoid additional overhead, we do the
         // lookup and check here, rather than calling `.index()`.
-        match self.processes.get(processid.index) {
-            Some(Some(process)) => {
-                // Check that the process stored here matches the identifier
-                // in the `processid`.
-                if process.processid() == processid {
-                    Some(*process)
-                } else {
-                    None
-                }
-            }
-            _ => None,
-        }
+        self.processes
+            .get(processid.index)
+            .and_then(process::ProcessSlot::get)
+            // Check that the process stored here matches the identifier in
+            // the `processid`.
+            .filter(|process| process.processid() == processid)
     }
 
     /// Run a closure on a specific process if it exists. If the process with a
@@ -218,13 +352,8 @@ impl Kernel {
     where
         F: FnMut(&dyn process::Process),
     {
-        for process in self.processes.iter() {
-            match process {
-                Some(p) => {
-                    closure(*p);
-                }
-                None => {}
-            }
+        for process in self.get_process_iter() {
+            closure(process);
         }
     }
 
@@ -232,15 +361,15 @@ impl Kernel {
     pub(crate) fn get_process_iter(
         &self,
     ) -> core::iter::FilterMap<
-        core::slice::Iter<Option<&dyn process::Process>>,
-        fn(&Option<&'static dyn process::Process>) -> Option<&'static dyn process::Process>,
+        core::slice::Iter<process::ProcessSlot>,
+        fn(&process::ProcessSlot) -> Option<&'static dyn process::Process>,
     > {
-        fn keep_some(
-            &x: &Option<&'static dyn process::Process>,
-        ) -> Option<&'static dyn process::Process> {
-            x
-        }
-        self.processes.iter().filter_map(keep_some)
+        self.processes.iter().filter_map(process::ProcessSlot::get)
+    }
+
+    /// The processes array, into whose empty slots processes are loaded.
+    pub(crate) fn process_slots(&self) -> &'static [process::ProcessSlot] {
+        self.processes
     }
 
     /// Run a closure on every valid process. This will iterate the array of
@@ -256,13 +385,8 @@ impl Kernel {
     ) where
         F: FnMut(&dyn process::Process),
     {
-        for process in self.processes.iter() {
-            match process {
-                Some(p) => {
-                    closure(*p);
-                }
-                None => {}
-            }
+        for process in self.get_process_iter() {
+            closure(process);
         }
     }
 
@@ -273,15 +397,10 @@ impl Kernel {
     where
         F: Fn(&dyn process::Process) -> Option<T>,
     {
-        for process in self.processes.iter() {
-            match process {
-                Some(p) => {
-                    let ret = closure(*p);
-                    if ret.is_some() {
-                        return ret;
-                    }
-                }
-                None => {}
+        for process in self.get_process_iter() {
+            let ret = closure(process);
+            if ret.is_some() {
+                return ret;
             }
         }
         None
@@ -294,9 +413,10 @@ impl Kernel {
     /// This is needed for `ProcessId` itself to implement the `.index()` command to
     /// verify that the referenced app is still at the correct index.
     pub(crate) fn processid_is_valid(&self, processid: &ProcessId) -> bool {
-        self.processes.get(processid.index).map_or(false, |p| {
-            p.map_or(false, |process| process.processid().id() == processid.id())
-        })
+        self.processes
+            .get(processid.index)
+            .and_then(process::ProcessSlot::get)
+            .map_or(false, |process| process.processid().id() == processid.id())
     }
 
     /// Create a new grant. This is used in board initialization to setup grants
@@ -324,12 +444,22 @@ impl Kernel {
             panic!("Grants finalized. Cannot create a new grant.");
         }
 
+        // Track how much each process would need to allocate for this grant.
+        let footprint = Grant::<T, Upcalls, AllowROs, AllowRWs>::footprint_for_driver(driver_num);
+        self.grant_footprint.add(footprint.total_size());
+
         // Create and return a new grant.
         let grant_index = self.grant_counter.get();
         self.grant_counter.increment();
         Grant::new(self, driver_num, grant_index)
     }
 
+    /// Returns the number of bytes a process would use in its grant region if
+    /// it allocated every grant that has been created.
+    pub(crate) fn get_grant_footprint(&self) -> usize {
+        self.grant_footprint.get()
+    }
+
     /// Returns the number of grants that have been setup in the system and
     /// marks the grants as "finalized". This means that no more grants can
     /// be created because data structures have been setup based on the number
@@ -379,10 +509,8 @@ impl Kernel {
     /// function, since capsules should not be able to arbitrarily restart all
     /// apps.
     pub fn hardfault_all_apps<C: capabilities::ProcessManagementCapability>(&self, _c: &C) {
-        for p in self.processes.iter() {
-            p.map(|process| {
-                process.set_fault_state();
-            });
+        for process in self.get_process_iter() {
+            process.set_fault_state();
         }
     }
 
@@ -395,7 +523,7 @@ impl Kernel {
     ///    kernel to run.
     /// 2. Check if any processes have any work to be done, and if so if the
     ///    scheduler wants to allow any processes to run now, and if so which
-    ///    one.
+    ///    one. Before that, one step of a pending background call is run.
     /// 3. After ensuring the scheduler does not want to complete any kernel or
     ///    process work (or there is no work to be done), are there are no
     ///    outstanding interrupts to handle, put the chip to sleep.
@@ -423,15 +551,25 @@ impl Kernel {
                     // Execute kernel work. This includes handling
                     // interrupts and is how code in the chips/ and capsules
                     // crates is able to execute.
+                    if chip.has_pending_interrupts() {
+                        self.trace(EventKind::Interrupts, 0, 0);
+                    }
                     scheduler.execute_kernel_work(chip);
                 }
                 false => {
+                    // Long computations of the kernel are run a step at a
+                    // time, each followed by a process.
+                    BackgroundCall::service_next_pending();
+
                     // No kernel work ready, so ask scheduler for a process.
                     match scheduler.next() {
                         SchedulingDecision::RunProcess((processid, timeslice_us)) => {
                             self.process_map_or((), processid, |process| {
+                                let id = processid.id() as u32;
+                                self.trace(EventKind::Schedule, id, timeslice_us.unwrap_or(0));
                                 let (reason, time_executed) =
                                     self.do_process(resources, chip, process, ipc, timeslice_us);
+                                self.trace(EventKind::Stop, id, reason as u32);
                                 scheduler.result(reason, time_executed);
                             });
                         }
@@ -451,7 +589,9 @@ impl Kernel {
                                     // starts, the interrupt will not be
                                     // serviced and the chip will never wake
                                     // from sleep.
-                                    if !chip.has_pending_interrupts() && !DeferredCall::has_tasks()
+                                    if !chip.has_pending_interrupts()
+                                        && !DeferredCall::has_tasks()
+                                        && !BackgroundCall::has_tasks()
                                     {
                                         resources.watchdog().suspend();
                                         chip.sleep();
@@ -478,8 +618,10 @@ impl Kernel {
         capability: &dyn capabilities::MainLoopCapability,
     ) -> ! {
         resources.watchdog().setup();
-        // Before we begin, verify that deferred calls were soundly setup.
+        // Before we begin, verify that deferred and background calls were
+        // soundly setup.
         DeferredCall::verify_setup();
+        BackgroundCall::verify_setup();
         loop {
             self.kernel_loop_operation(resources, chip, ipc, false, capability);
         }
@@ -540,6 +682,18 @@ impl Kernel {
         scheduler_timer.reset();
         timeslice_us.map(|timeslice| scheduler_timer.start(timeslice));
 
+        // `get_remaining_us()` must not be called again once it returned
+        // `None`, so remember when the timeslice expired.
+        let expired = Cell::new(false);
+        let remaining_us = || {
+            if expired.get() {
+                return None;
+            }
+            let remaining = scheduler_timer.get_remaining_us();
+            expired.set(remaining.is_none());
+            remaining
+        };
+
         // Need to track why the process is no longer executing so that we can
         // inform the scheduler.
         let mut return_reason = StoppedExecutingReason::NoWorkLeft;
@@ -551,7 +705,7 @@ impl Kernel {
         // no longer wants to execute this process or if it exceeds its
         // timeslice.
         loop {
-            let stop_running = match scheduler_timer.get_remaining_us() {
+            let stop_running = match remaining_us() {
                 Some(us) => us <= MIN_QUANTA_THRESHOLD_US,
                 None => true,
             };
@@ -614,10 +768,17 @@ impl Kernel {
                             }
                         }
                         Some(ContextSwitchReason::SyscallFired { syscall }) => {
+                            // Record the time the kernel spends on the
+                            // syscall, if the timeslice is measured.
+                            let before_us = timeslice_us.and_then(|_| remaining_us());
                             self.handle_syscall(resources, process, syscall);
+                            before_us.map(|before_us| {
+                                let after_us = remaining_us().unwrap_or(0);
+                                process.debug_syscall_time_add(before_us.saturating_sub(after_us));
+                            });
                         }
                         Some(ContextSwitchReason::Interrupted) => {
-                            if scheduler_timer.get_remaining_us().is_none() {
+                            if remaining_us().is_none() {
                                 // This interrupt was a timeslice expiration.
                                 process.debug_timeslice_expired();
                                 return_reason = StoppedExecutingReason::TimesliceExpired;
@@ -748,13 +909,24 @@ impl Kernel {
                 // used the whole timeslice
                 Some(timeslice)
             } else {
-                match scheduler_timer.get_remaining_us() {
+                match remaining_us() {
                     Some(remaining) => Some(timeslice - remaining),
                     None => Some(timeslice), // used whole timeslice
                 }
             }
         });
 
+        // Record the time for per-process CPU usage statistics.
+        time_executed_us.map(|time_us| process.debug_execution_time_add(time_us));
+        match return_reason {
+            StoppedExecutingReason::TimesliceExpired | StoppedExecutingReason::KernelPreemption => {
+                process.debug_preempted()
+            }
+            StoppedExecutingReason::NoWorkLeft
+            | StoppedExecutingReason::StoppedFaulted
+            | StoppedExecutingReason::Stopped => {}
+        }
+
         // Reset the scheduler timer in case it unconditionally triggers
         // interrupts upon expiration. We do not want it to expire while the
         // chip is sleeping, for example.
@@ -763,6 +935,117 @@ impl Kernel {
         (return_reason, time_executed_us)
     }
 
+    /// Hooks for process debugging and tracing, for every system call a
+    /// process issues.
+    fn syscall_called(&self, process: &dyn process::Process, syscall: &Syscall) {
+        process.debug_syscall_called(*syscall);
+        self.trace(
+            EventKind::Syscall,
+            process.processid().id() as u32,
+            trace::syscall_argument(syscall),
+        );
+    }
+
+    /// Apply the system call filtering policy of the platform to `syscall`.
+    fn filter_syscall<KR: KernelResources<C>, C: Chip>(
+        &self,
+        resources: &KR,
+        process: &dyn process::Process,
+        syscall: &Syscall,
+    ) -> Result<(), ErrorCode> {
+        resources
+            .syscall_filter()
+            .filter_syscall(process, syscall)
+            .map_err(|response| {
+                if config::CONFIG.trace_syscalls {
+                    debug!(
+                        "[{:?}] Filtered: {:?} was rejected with {:?}",
+                        process.processid(),
+                        syscall,
+                        response
+                    );
+                }
+                response
+            })
+    }
+
+    /// Run a command on the driver with number `driver_number`.
+    fn command<KR: KernelResources<C>, C: Chip>(
+        &self,
+        resources: &KR,
+        process: &dyn process::Process,
+        driver_number: usize,
+        subdriver_number: usize,
+        arg0: usize,
+        arg1: usize,
+    ) -> SyscallReturn {
+        self.observers
+            .iter()
+            .for_each(|observer| observer.syscall_dispatched(driver_number));
+        let cres = resources
+            .syscall_driver_lookup()
+            .with_driver(driver_number, |driver| match driver {
+                Some(d) => d.command(subdriver_number, arg0, arg1, process.processid()),
+                None => CommandReturn::failure(ErrorCode::NODEVICE),
+            });
+        let res = SyscallReturn::from_command_return(cres);
+        self.observers
+            .iter()
+            .for_each(|observer| observer.syscall_returned(SyscallClass::Command, driver_number));
+
+        if config::CONFIG.trace_syscalls {
+            debug!(
+                "[{:?}] cmd({:#x}, {}, {:#x}, {:#x}) = {:?}",
+                process.processid(),
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+                res,
+            );
+        }
+        res
+    }
+
+    /// Handle a command `process` issued through the syscall ring, like a
+    /// command system call: with the debugging and tracing hooks, the
+    /// filtering policy and the driver number mapping of the platform.
+    pub(crate) fn handle_ring_command<KR: KernelResources<C>, C: Chip>(
+        &self,
+        resources: &KR,
+        process: &dyn process::Process,
+        syscall: Syscall,
+    ) -> SyscallReturn {
+        self.syscall_called(process, &syscall);
+        if let Err(response) = self.filter_syscall(resources, process, &syscall) {
+            return SyscallReturn::Failure(response);
+        }
+        match remap_driver_number(
+            resources.syscall_driver_lookup().driver_number_map(),
+            syscall,
+        ) {
+            // Rings do not nest.
+            Syscall::Command {
+                driver_number: syscall_ring::DRIVER_NUM,
+                ..
+            } => SyscallReturn::Failure(ErrorCode::INVAL),
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => self.command(
+                resources,
+                process,
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            ),
+            _ => SyscallReturn::Failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
     /// Method to invoke a system call on a particular process. Applies the
     /// kernel system call filtering policy (if any). Handles `Yield` and
     /// `Exit`, dispatches `Memop` to `memop::memop`, and dispatches peripheral
@@ -775,8 +1058,7 @@ impl Kernel {
         process: &dyn process::Process,
         syscall: Syscall,
     ) {
-        // Hook for process debugging.
-        process.debug_syscall_called(syscall);
+        self.syscall_called(process, &syscall);
 
         // Enforce platform-specific syscall filtering here.
         //
@@ -804,24 +1086,19 @@ impl Kernel {
             } => {} // Memop is not filterable.
             _ => {
                 // Check all other syscalls for filtering.
-                if let Err(response) = resources.syscall_filter().filter_syscall(process, &syscall)
-                {
+                if let Err(response) = self.filter_syscall(resources, process, &syscall) {
                     process.set_syscall_return_value(SyscallReturn::Failure(response));
-
-                    if config::CONFIG.trace_syscalls {
-                        debug!(
-                            "[{:?}] Filtered: {:?} was rejected with {:?}",
-                            process.processid(),
-                            syscall,
-                            response
-                        );
-                    }
-
                     return;
                 }
             }
         }
 
+        // Dispatch to the driver number the board maps the number to.
+        let syscall = remap_driver_number(
+            resources.syscall_driver_lookup().driver_number_map(),
+            syscall,
+        );
+
         // Handle each of the syscalls.
         match syscall {
             Syscall::Memop { operand, arg0 } => {
@@ -882,11 +1159,46 @@ impl Kernel {
                     process.set_yielded_state();
                 }
             }
+            Syscall::Command {
+                driver_number,
+                subdriver_number,
+                arg0,
+                arg1,
+            } => {
+                let res = match resources.syscall_ring() {
+                    Some(ring) if driver_number == syscall_ring::DRIVER_NUM => {
+                        let res =
+                            ring.command(self, resources, process, subdriver_number, arg0, arg1);
+                        if config::CONFIG.trace_syscalls {
+                            debug!(
+                                "[{:?}] ring({}, {:#x}, {:#x}) = {:?}",
+                                process.processid(),
+                                subdriver_number,
+                                arg0,
+                                arg1,
+                                res,
+                            );
+                        }
+                        res
+                    }
+                    _ => self.command(
+                        resources,
+                        process,
+                        driver_number,
+                        subdriver_number,
+                        arg0,
+                        arg1,
+                    ),
+                };
+                process.set_syscall_return_value(res);
+            }
             Syscall::Subscribe { driver_number, .. }
-            | Syscall::Command { driver_number, .. }
             | Syscall::ReadWriteAllow { driver_number, .. }
             | Syscall::UserspaceReadableAllow { driver_number, .. }
             | Syscall::ReadOnlyAllow { driver_number, .. } => {
+                self.observers
+                    .iter()
+                    .for_each(|observer| observer.syscall_dispatched(driver_number));
                 resources
                 .syscall_driver_lookup()
                 .with_driver(driver_number, |driver| match syscall {
@@ -1027,32 +1339,6 @@ impl Kernel {
 
                         process.set_syscall_return_value(rval);
                     }
-                    Syscall::Command {
-                        driver_number,
-                        subdriver_number,
-                        arg0,
-                        arg1,
-                    } => {
-                        let cres = match driver {
-                            Some(d) => d.command(subdriver_number, arg0, arg1, process.processid()),
-                            None => CommandReturn::failure(ErrorCode::NODEVICE),
-                        };
-
-                        let res = SyscallReturn::from_command_return(cres);
-
-                        if config::CONFIG.trace_syscalls {
-                            debug!(
-                                "[{:?}] cmd({:#x}, {}, {:#x}, {:#x}) = {:?}",
-                                process.processid(),
-                                driver_number,
-                                subdriver_number,
-                                arg0,
-                                arg1,
-                                res,
-                            );
-                        }
-                        process.set_syscall_return_value(res);
-                    }
                     Syscall::ReadWriteAllow {
                         driver_number,
                         subdriver_number,
@@ -1157,6 +1443,16 @@ impl Kernel {
                             ),
                         };
 
+                        self.audit_allow(
+                            process,
+                            driver_number,
+                            subdriver_number,
+                            AllowType::ReadWrite,
+                            allow_address as *const u8,
+                            allow_size,
+                            &res,
+                        );
+
                         if config::CONFIG.trace_syscalls {
                             debug!(
                                 "[{:?}] read-write allow({:#x}, {}, @{:#x}, {}) = {:?}",
@@ -1236,6 +1532,16 @@ impl Kernel {
                             ),
                         };
 
+                        self.audit_allow(
+                            process,
+                            driver_number,
+                            subdriver_number,
+                            AllowType::UserspaceReadable,
+                            allow_address as *const u8,
+                            allow_size,
+                            &res,
+                        );
+
                         if config::CONFIG.trace_syscalls {
                             debug!(
                                 "[{:?}] userspace readable allow({:#x}, {}, @{:#x}, {}) = {:?}",
@@ -1353,6 +1659,16 @@ impl Kernel {
                             ),
                         };
 
+                        self.audit_allow(
+                            process,
+                            driver_number,
+                            subdriver_number,
+                            AllowType::ReadOnly,
+                            allow_address as *const u8,
+                            allow_size,
+                            &res,
+                        );
+
                         if config::CONFIG.trace_syscalls {
                             debug!(
                                 "[{:?}] read-only allow({:#x}, {}, @{:#x}, {}) = {:?}",
@@ -1369,12 +1685,20 @@ impl Kernel {
                     }
                     Syscall::Yield { .. }
                     | Syscall::Exit { .. }
-                    | Syscall::Memop { .. } => {
+                    | Syscall::Memop { .. }
+                    | Syscall::Command { .. } => {
                         // These variants must not be reachable due to
                         // the outer match statement:
                         debug_assert!(false, "Kernel system call handling invariant violated!");
                     },
-                })
+                });
+                let class = match syscall {
+                    Syscall::Subscribe { .. } => SyscallClass::Subscribe,
+                    _ => SyscallClass::Allow,
+                };
+                self.observers
+                    .iter()
+                    .for_each(|observer| observer.syscall_returned(class, driver_number));
             }
             Syscall::Exit {
                 which,
@@ -1404,8 +1728,13 @@ pub struct ProcessCheckerMachine {
     process: Cell<usize>,
     footer: Cell<usize>,
     policy: OptionalCell<&'static dyn CredentialsCheckingPolicy<'static>>,
-    processes: &'static [Option<&'static dyn Process>],
+    processes: &'static [process::ProcessSlot],
     approve_cap: KernelProcessApprovalCapability,
+    /// Whether a footer is being checked.
+    checking: Cell<bool>,
+    /// Whether processes were created since checking started, so that the
+    /// processes array has to be scanned again.
+    rescan: Cell<bool>,
 }
 
 #[derive(Debug)]
@@ -1431,23 +1760,38 @@ impl ProcessCheckerMachine {
             // checking a process, it just increments to the next
             // index. In case the array has None entries or the
             // process array changes under us, don't actually trust
-            // this value.
-            while proc_index < self.processes.len() && self.processes[proc_index].is_none() {
+            // this value. Processes whose credentials were already
+            // checked are skipped.
+            while proc_index < self.processes.len()
+                && self.processes[proc_index].get().map_or(true, |p| {
+                    p.get_state() != process::State::CredentialsUnchecked
+                })
+            {
                 proc_index = proc_index + 1;
                 self.process.set(proc_index);
                 self.footer.set(0);
             }
             if proc_index >= self.processes.len() {
+                if self.rescan.take() {
+                    // Processes were created while checking, possibly
+                    // before the current index.
+                    self.process.set(0);
+                    self.footer.set(0);
+                    continue;
+                }
                 // No more processes to check.
+                self.checking.set(false);
                 return Ok(false);
             }
 
             let footer_index = self.footer.get();
             // Try to check the next footer.
             let check_result = self.policy.map_or(FooterCheckResult::Error, |c| {
-                self.processes[proc_index].map_or(FooterCheckResult::NoProcess, |p| {
-                    check_footer(p, *c, footer_index)
-                })
+                self.processes[proc_index]
+                    .get()
+                    .map_or(FooterCheckResult::NoProcess, |p| {
+                        check_footer(p, *c, footer_index)
+                    })
             });
 
             if config::CONFIG.debug_process_credentials {
@@ -1458,6 +1802,7 @@ impl ProcessCheckerMachine {
             }
             match check_result {
                 FooterCheckResult::Checking => {
+                    self.checking.set(true);
                     return Ok(true);
                 }
                 FooterCheckResult::PastLastFooter => {
@@ -1467,7 +1812,7 @@ impl ProcessCheckerMachine {
                     // should be allowed to run.
                     self.policy.map(|policy| {
                         let requires = policy.require_credentials();
-                        let _res = self.processes[proc_index].map_or(
+                        let _res = self.processes[proc_index].get().map_or(
                             Err(ProcessLoadError::InternalError),
                             |p| {
                                 if requires {
@@ -1509,6 +1854,7 @@ impl ProcessCheckerMachine {
                     self.footer.set(self.footer.get() + 1);
                 }
                 FooterCheckResult::Error => {
+                    self.checking.set(false);
                     return Err(ProcessLoadError::InternalError);
                 }
             }
@@ -1518,6 +1864,23 @@ impl ProcessCheckerMachine {
     pub fn set_policy(&self, policy: &'static dyn CredentialsCheckingPolicy<'static>) {
         self.policy.replace(policy);
     }
+
+    /// Returns whether a checking policy was set, i.e. whether processes
+    /// were loaded with `load_and_check_processes`.
+    pub(crate) fn has_policy(&self) -> bool {
+        self.policy.is_some()
+    }
+
+    /// Check the credentials of processes created after the processes
+    /// loaded at boot, e.g. by a `DynamicProcessLoader`. If a check is in
+    /// progress, the new processes are checked once it completes.
+    pub(crate) fn check_new_processes(&self) -> Result<(), ProcessLoadError> {
+        self.rescan.set(true);
+        if !self.checking.get() {
+            self.next()?;
+        }
+        Ok(())
+    }
 }
 
 // Returns whether a footer is being checked or not, and if not, why.
@@ -1643,7 +2006,7 @@ impl process_checker::Client<'static> for ProcessCheckerMachine {
         }
         match result {
             Ok(process_checker::CheckResult::Accept) => {
-                self.processes[self.process.get()].map(|p| {
+                self.processes[self.process.get()].get().map(|p| {
                     let short_id = self.policy.map_or(ShortID::LocallyUnique, |policy| {
                         policy.to_short_id(&credentials)
                     });
@@ -1656,7 +2019,7 @@ impl process_checker::Client<'static> for ProcessCheckerMachine {
                 self.footer.set(self.footer.get() + 1);
             }
             Ok(process_checker::CheckResult::Reject) => {
-                self.processes[self.process.get()].map(|p| {
+                self.processes[self.process.get()].get().map(|p| {
                     let _r = p.mark_credentials_fail(&self.approve_cap);
                 });
                 self.process.set(self.process.get() + 1);
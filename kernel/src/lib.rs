@@ -97,9 +97,11 @@
 pub const KERNEL_MAJOR_VERSION: u16 = 2;
 pub const KERNEL_MINOR_VERSION: u16 = 1;
 
+pub mod background_call;
 pub mod capabilities;
 pub mod collections;
 pub mod component;
+pub mod config;
 pub mod debug;
 pub mod deferred_call;
 pub mod errorcode;
@@ -107,17 +109,25 @@ pub mod grant;
 pub mod hil;
 pub mod introspection;
 pub mod ipc;
+pub mod ipc_lending;
+pub mod ipc_registry;
+pub mod liveness;
+pub mod passthrough;
 pub mod platform;
+pub mod postmortem;
 pub mod process;
 pub mod process_checker;
+pub mod process_observer;
 pub mod processbuffer;
 pub mod scheduler;
 pub mod storage_permissions;
 pub mod syscall;
+pub mod syscall_latency;
+pub mod syscall_ring;
+pub mod trace;
 pub mod upcall;
 pub mod utilities;
 
-mod config;
 mod kernel;
 mod memop;
 mod process_loading;
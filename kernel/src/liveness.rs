@@ -0,0 +1,220 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Liveness monitor with per-capsule heartbeats.
+//!
+//! The kernel loop tickles the hardware watchdog, so the watchdog only resets
+//! the board if the kernel loop itself stops running. A capsule that is stuck,
+//! for example waiting for an interrupt that never comes, does not stop the
+//! kernel loop, and a bug that does usually leads to a reset without any
+//! indication of what went wrong.
+//!
+//! The `LivenessMonitor` wraps the hardware watchdog and is used as the
+//! board's `KernelResources::WatchDog`. Long-running capsules register a
+//! `Heartbeat` with it, and must call `Heartbeat::checkin()` at least once per
+//! timeout while the heartbeat is armed. The monitor checks the heartbeats
+//! every `period_ms` milliseconds. Once a heartbeat misses its timeout, the
+//! monitor records its name in the postmortem region (see `postmortem`) and
+//! stops tickling the hardware watchdog, which then resets the board. After
+//! the reset, the name can be read with the `lastpanic` process console
+//! command.
+//!
+//! The monitor cannot detect a stuck kernel loop by itself: that is still up
+//! to the hardware watchdog, which resets the board without a record.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let liveness = static_init!(
+//!     kernel::liveness::LivenessMonitor<'static, VirtualMuxAlarm<'static, Ast>, Wdt>,
+//!     kernel::liveness::LivenessMonitor::new(liveness_alarm, &peripherals.wdt, 1000)
+//! );
+//! liveness_alarm.set_alarm_client(liveness);
+//!
+//! let heartbeat = static_init!(
+//!     kernel::liveness::Heartbeat<'static>,
+//!     kernel::liveness::Heartbeat::new("sdcard", 5000)
+//! );
+//! liveness.register(heartbeat);
+//! ```
+
+use core::cell::Cell;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::debug;
+use crate::hil::time::{self, ConvertTicks};
+use crate::platform::watchdog::WatchDog;
+use crate::postmortem;
+use crate::utilities::cells::OptionalCell;
+
+/// A check-in obligation of one capsule.
+pub struct Heartbeat<'a> {
+    name: &'static str,
+    timeout_ms: u32,
+    armed: Cell<bool>,
+    checked_in: Cell<bool>,
+    /// Time since the last check-in, as of the last check of the monitor.
+    elapsed_ms: Cell<u32>,
+    next: ListLink<'a, Heartbeat<'a>>,
+}
+
+impl<'a> Heartbeat<'a> {
+    /// Create a heartbeat identified by `name` that must check in at least
+    /// once every `timeout_ms` milliseconds while it is armed.
+    pub const fn new(name: &'static str, timeout_ms: u32) -> Self {
+        Heartbeat {
+            name,
+            timeout_ms,
+            armed: Cell::new(false),
+            checked_in: Cell::new(false),
+            elapsed_ms: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Start monitoring this heartbeat. The first check-in is due within the
+    /// timeout.
+    pub fn arm(&self) {
+        self.elapsed_ms.set(0);
+        self.checked_in.set(false);
+        self.armed.set(true);
+    }
+
+    /// Stop monitoring this heartbeat, e.g. while the capsule is idle.
+    pub fn disarm(&self) {
+        self.armed.set(false);
+    }
+
+    /// Report that the capsule is making progress.
+    pub fn checkin(&self) {
+        self.checked_in.set(true);
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+
+    /// Account for `period_ms` milliseconds since the last check of the
+    /// monitor, and return whether the heartbeat missed its timeout.
+    fn check(&self, period_ms: u32) -> bool {
+        if !self.armed.get() {
+            return false;
+        }
+        if self.checked_in.take() {
+            self.elapsed_ms.set(0);
+            return false;
+        }
+        let elapsed_ms = self.elapsed_ms.get().saturating_add(period_ms);
+        self.elapsed_ms.set(elapsed_ms);
+        elapsed_ms >= self.timeout_ms
+    }
+}
+
+impl<'a> ListNode<'a, Heartbeat<'a>> for Heartbeat<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, Heartbeat<'a>> {
+        &self.next
+    }
+}
+
+/// Watchdog wrapper that lets the hardware watchdog reset the board once a
+/// heartbeat misses its timeout.
+pub struct LivenessMonitor<'a, A: time::Alarm<'a>, W: WatchDog> {
+    alarm: &'a A,
+    watchdog: &'a W,
+    period_ms: u32,
+    heartbeats: List<'a, Heartbeat<'a>>,
+    /// Name of the heartbeat that missed its timeout.
+    expired: OptionalCell<&'static str>,
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog> LivenessMonitor<'a, A, W> {
+    /// Check the heartbeats every `period_ms` milliseconds. The timeouts of
+    /// the heartbeats are rounded up to a multiple of the period.
+    pub fn new(alarm: &'a A, watchdog: &'a W, period_ms: u32) -> Self {
+        LivenessMonitor {
+            alarm,
+            watchdog,
+            period_ms,
+            heartbeats: List::new(),
+            expired: OptionalCell::empty(),
+        }
+    }
+
+    pub fn register(&self, heartbeat: &'a Heartbeat<'a>) {
+        self.heartbeats.push_head(heartbeat);
+    }
+
+    /// Returns the name of the heartbeat that missed its timeout, if any. The
+    /// board resets once the hardware watchdog expires.
+    pub fn expired(&self) -> Option<&'static str> {
+        self.expired.extract()
+    }
+
+    fn check_heartbeats(&self) {
+        if self.expired.is_some() {
+            return;
+        }
+        // Every heartbeat is checked, so they all account for the period.
+        let mut expired = None;
+        for heartbeat in self.heartbeats.iter() {
+            if heartbeat.check(self.period_ms) && expired.is_none() {
+                expired = Some(heartbeat);
+            }
+        }
+        if let Some(heartbeat) = expired {
+            postmortem::record_fault(format_args!(
+                "Liveness monitor: heartbeat `{}` did not check in for {} ms, \
+                 letting the watchdog reset the board.\r\n",
+                heartbeat.name,
+                heartbeat.elapsed_ms.get()
+            ));
+            debug!(
+                "Liveness monitor: heartbeat `{}` missed its timeout",
+                heartbeat.name
+            );
+            self.expired.set(heartbeat.name);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog> time::AlarmClient for LivenessMonitor<'a, A, W> {
+    fn alarm(&self) {
+        self.check_heartbeats();
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, W: WatchDog> WatchDog for LivenessMonitor<'a, A, W> {
+    fn setup(&self) {
+        self.watchdog.setup();
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+    }
+
+    fn tickle(&self) {
+        if self.expired.is_none() {
+            self.watchdog.tickle();
+        }
+    }
+
+    fn suspend(&self) {
+        // Keep the watchdog running while sleeping once a heartbeat expired,
+        // so that it still resets the board.
+        if self.expired.is_none() {
+            self.watchdog.suspend();
+        }
+    }
+
+    fn resume(&self) {
+        if self.expired.is_none() {
+            self.watchdog.resume();
+        }
+    }
+}
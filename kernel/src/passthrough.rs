@@ -0,0 +1,244 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Passthrough of peripherals to userspace drivers.
+//!
+//! This is a special syscall driver that maps the registers of a peripheral
+//! into a single process and forwards the interrupts of the peripheral to it
+//! as upcalls. Experimental drivers can then be developed in userspace,
+//! without rebuilding the kernel.
+//!
+//! The board lists the peripherals that can be passed through, and the name
+//! of the process each one may be mapped into. The kernel must not use these
+//! peripherals itself. The process also needs the
+//! permission for command 1 of this driver in its TBF header. The MPU must be
+//! able to cover the registers of the peripheral with a region, so `base` and
+//! `size` must meet its alignment requirements.
+//!
+//! The kernel does not know how to acknowledge the interrupt of a peripheral
+//! it gave away. Before forwarding an interrupt it writes `mask.1` to the
+//! register at offset `mask.0` of the peripheral, which must disable the
+//! interrupt at the peripheral (e.g. `INTENCLR` on the nRF52). The process
+//! handles the interrupt and enables it again. Interrupts of peripherals
+//! that are not mapped are masked as well.
+//!
+//! `Passthrough` wraps the `InterruptService` of the chip, and must be given
+//! to the chip in its place:
+//!
+//! ```rust,ignore
+//! const PASSTHROUGH: [PassthroughPeripheral; 1] = [PassthroughPeripheral {
+//!     name: "spim2",
+//!     base: 0x40023000,
+//!     size: 0x1000,
+//!     interrupt: Some(35),
+//!     mask: Some((0x308, 0xffff_ffff)),
+//!     owner: "spi_driver",
+//! }];
+//! let passthrough = static_init!(
+//!     kernel::passthrough::Passthrough<Nrf52840DefaultPeripherals, 1>,
+//!     kernel::passthrough::Passthrough::new(
+//!         board_kernel,
+//!         base_peripherals,
+//!         &PASSTHROUGH,
+//!         &memory_allocation_cap,
+//!     )
+//! );
+//! let chip = static_init!(
+//!     nrf52840::chip::NRF52<Passthrough<Nrf52840DefaultPeripherals, 1>>,
+//!     nrf52840::chip::NRF52::new(passthrough)
+//! );
+//! ```
+
+use core::cell::Cell;
+use core::ptr;
+
+use crate::capabilities::MemoryAllocationCapability;
+use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use crate::kernel::Kernel;
+use crate::platform::chip::InterruptService;
+use crate::platform::mpu;
+use crate::process::ProcessId;
+use crate::syscall_driver::{CommandReturn, SyscallDriver};
+use crate::ErrorCode;
+use tock_tbf::types::CommandPermissions;
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x1000A;
+
+/// A peripheral that can be mapped into a process.
+pub struct PassthroughPeripheral {
+    pub name: &'static str,
+    /// Address of the registers of the peripheral.
+    pub base: usize,
+    /// Size of the registers of the peripheral, in bytes.
+    pub size: usize,
+    /// Interrupt line of the peripheral.
+    pub interrupt: Option<u32>,
+    /// Offset of a register and the value written to it to disable the
+    /// interrupt at the peripheral.
+    pub mask: Option<(usize, u32)>,
+    /// Name of the process the peripheral may be mapped into.
+    pub owner: &'static str,
+}
+
+pub struct Passthrough<I: 'static + InterruptService, const N: usize> {
+    kernel: &'static Kernel,
+    interrupt_service: &'static I,
+    peripherals: &'static [PassthroughPeripheral; N],
+    /// Process each peripheral is mapped into, and the MPU region used.
+    mapped: [Cell<Option<(ProcessId, mpu::Region)>>; N],
+    apps: Grant<(), UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<I: 'static + InterruptService, const N: usize> Passthrough<I, N> {
+    pub fn new(
+        kernel: &'static Kernel,
+        interrupt_service: &'static I,
+        peripherals: &'static [PassthroughPeripheral; N],
+        capability: &dyn MemoryAllocationCapability,
+    ) -> Self {
+        Self {
+            kernel,
+            interrupt_service,
+            peripherals,
+            mapped: [(); N].map(|()| Cell::new(None)),
+            apps: kernel.create_grant(DRIVER_NUM, capability),
+        }
+    }
+
+    /// Return the process peripheral `index` is mapped into, if the process
+    /// still runs as the same instance.
+    fn owner(&self, index: usize) -> Option<ProcessId> {
+        self.mapped[index].get().and_then(|(processid, _)| {
+            self.kernel.process_map_or(None, processid, |process| {
+                process.is_running().then_some(processid)
+            })
+        })
+    }
+
+    fn map(&self, index: usize, processid: ProcessId) -> CommandReturn {
+        let peripheral = match self.peripherals.get(index) {
+            Some(peripheral) => peripheral,
+            None => return CommandReturn::failure(ErrorCode::INVAL),
+        };
+        match self.owner(index) {
+            Some(owner) if owner == processid => return CommandReturn::failure(ErrorCode::ALREADY),
+            Some(_) => return CommandReturn::failure(ErrorCode::BUSY),
+            None => {}
+        }
+
+        let region = self
+            .kernel
+            .process_map_or(Err(ErrorCode::NODEVICE), processid, |process| {
+                let permitted = match process.get_command_permissions(DRIVER_NUM, 0) {
+                    CommandPermissions::Mask(mask) => mask & (1 << 1) != 0,
+                    _ => false,
+                };
+                if !permitted || process.get_process_name() != peripheral.owner {
+                    return Err(ErrorCode::NODEVICE);
+                }
+                process
+                    .add_mpu_region(
+                        peripheral.base as *const u8,
+                        peripheral.size,
+                        peripheral.size,
+                    )
+                    .ok_or(ErrorCode::NOMEM)
+            });
+        match region {
+            Ok(region) => {
+                self.mapped[index].set(Some((processid, region)));
+                // The MPU configuration of the process is only loaded when it
+                // is switched to, so the mapping applies when the command
+                // returns.
+                CommandReturn::success_u32_u32(peripheral.base as u32, peripheral.size as u32)
+            }
+            Err(e) => CommandReturn::failure(e),
+        }
+    }
+
+    fn unmap(&self, index: usize, processid: ProcessId) -> CommandReturn {
+        if index >= N {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        match self.mapped[index].get() {
+            Some((owner, region)) if owner == processid => {
+                self.mapped[index].set(None);
+                self.kernel
+                    .process_map_or(Err(ErrorCode::FAIL), processid, |process| {
+                        process.remove_mpu_region(region)
+                    })
+                    .into()
+            }
+            _ => CommandReturn::failure(ErrorCode::RESERVE),
+        }
+    }
+}
+
+impl<I: 'static + InterruptService, const N: usize> InterruptService for Passthrough<I, N> {
+    unsafe fn service_interrupt(&self, interrupt: u32) -> bool {
+        let index = match self
+            .peripherals
+            .iter()
+            .position(|peripheral| peripheral.interrupt == Some(interrupt))
+        {
+            Some(index) => index,
+            None => return self.interrupt_service.service_interrupt(interrupt),
+        };
+
+        let peripheral = &self.peripherals[index];
+        if let Some((offset, value)) = peripheral.mask {
+            // ### Safety
+            //
+            // The board promises that this register disables the interrupt
+            // of the peripheral.
+            ptr::write_volatile((peripheral.base + offset) as *mut u32, value);
+        }
+        if let Some(owner) = self.owner(index) {
+            let _ = self.apps.enter(owner, |_, kernel_data| {
+                kernel_data
+                    .schedule_upcall(0, (index, interrupt as usize, 0))
+                    .ok();
+            });
+        }
+        true
+    }
+}
+
+impl<I: 'static + InterruptService, const N: usize> SyscallDriver for Passthrough<I, N> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if this driver is included on the platform.
+    /// - `1`: Map the registers of peripheral `data1` into the calling
+    ///   process. Returns the address and the size of the registers.
+    ///   Returns `NODEVICE` if the process may not map the peripheral,
+    ///   `BUSY` if it is mapped into another process, and `NOMEM` if the
+    ///   MPU cannot map it.
+    /// - `2`: Unmap peripheral `data1` from the calling process.
+    /// - `3`: Return the number of peripherals.
+    ///
+    /// Upcall 0 is scheduled when a mapped peripheral interrupts, with the
+    /// index of the peripheral and the interrupt number.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.map(data1, processid),
+            2 => self.unmap(data1, processid),
+            3 => CommandReturn::success_u32(N as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), crate::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
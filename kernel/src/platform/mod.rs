@@ -14,8 +14,10 @@ pub mod watchdog;
 pub(crate) mod platform;
 
 pub use self::platform::ContextSwitchCallback;
+pub use self::platform::DriverNumberMapping;
 pub use self::platform::KernelResources;
 pub use self::platform::ProcessFault;
 pub use self::platform::SyscallDriverLookup;
 pub use self::platform::SyscallFilter;
+pub use self::platform::TbfHeaderAllowedDriversFilter;
 pub use self::platform::TbfHeaderFilterDefaultAllow;
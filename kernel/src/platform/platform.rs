@@ -13,6 +13,7 @@ use crate::process_checker::CredentialsCheckingPolicy;
 use crate::scheduler::Scheduler;
 use crate::syscall;
 use crate::syscall_driver::SyscallDriver;
+use crate::syscall_ring::SyscallRing;
 use tock_tbf::types::CommandPermissions;
 
 /// Combination trait that boards provide to the kernel that includes all of
@@ -82,6 +83,12 @@ pub trait KernelResources<C: Chip> {
     /// Returns a reference to the implementation of the WatchDog on this
     /// platform.
     fn watchdog(&self) -> &Self::WatchDog;
+
+    /// Returns the syscall ring of this platform, if processes may batch
+    /// commands with it. See `syscall_ring`.
+    fn syscall_ring(&self) -> Option<&SyscallRing> {
+        None
+    }
 }
 
 /// Configure the system call dispatch mapping.
@@ -123,6 +130,40 @@ pub trait SyscallDriverLookup {
     fn with_driver<F, R>(&self, driver_num: usize, f: F) -> R
     where
         F: FnOnce(Option<&dyn SyscallDriver>) -> R;
+
+    /// Board-specific table of driver numbers that processes use for other
+    /// driver numbers. The kernel replaces the driver number of each system
+    /// call with the one it is mapped to before dispatching it, after the
+    /// system call filter. This lets a board e.g. expose a second instance of
+    /// a driver, whose grant uses a board-chosen number, at the number an
+    /// application expects. The default is an empty table.
+    fn driver_number_map(&self) -> &[DriverNumberMapping] {
+        &[]
+    }
+}
+
+/// Entry of `SyscallDriverLookup::driver_number_map()`: system calls to
+/// driver number `from` are dispatched to driver number `to`.
+///
+/// ```ignore
+/// const DRIVER_NUMBER_MAP: [DriverNumberMapping; 1] = [DriverNumberMapping {
+///     from: capsules_extra::temperature::DRIVER_NUM,
+///     to: OUTDOOR_TEMPERATURE_DRIVER_NUM,
+/// }];
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct DriverNumberMapping {
+    pub from: usize,
+    pub to: usize,
+}
+
+impl DriverNumberMapping {
+    /// Return the driver number `driver_num` is mapped to by `map`.
+    pub fn remap(map: &[DriverNumberMapping], driver_num: usize) -> usize {
+        map.iter()
+            .find(|mapping| mapping.from == driver_num)
+            .map_or(driver_num, |mapping| mapping.to)
+    }
 }
 
 /// Trait for implementing system call filters that the kernel uses to decide
@@ -293,3 +334,38 @@ pub trait ContextSwitchCallback {
 impl ContextSwitchCallback for () {
     fn context_switch_hook(&self, _process: &dyn process::Process) {}
 }
+
+/// An allow list system call filter based on the driver numbers listed in the
+/// TBF header, with a default allow all fallback.
+///
+/// If the process has a TbfHeaderAllowedDrivers header, it may only use the
+/// drivers listed in it, and other subscribe, command and allow system calls
+/// fail with `NODEVICE`. Processes without the header may use any driver.
+pub struct TbfHeaderAllowedDriversFilter {}
+
+impl SyscallFilter for TbfHeaderAllowedDriversFilter {
+    fn filter_syscall(
+        &self,
+        process: &dyn process::Process,
+        syscall: &syscall::Syscall,
+    ) -> Result<(), errorcode::ErrorCode> {
+        let driver_number = match *syscall {
+            syscall::Syscall::Subscribe { driver_number, .. }
+            | syscall::Syscall::Command { driver_number, .. }
+            | syscall::Syscall::ReadWriteAllow { driver_number, .. }
+            | syscall::Syscall::UserspaceReadableAllow { driver_number, .. }
+            | syscall::Syscall::ReadOnlyAllow { driver_number, .. } => driver_number,
+
+            // Non-filterable system calls
+            syscall::Syscall::Yield { .. }
+            | syscall::Syscall::Memop { .. }
+            | syscall::Syscall::Exit { .. } => return Ok(()),
+        };
+        match process.get_allowed_drivers() {
+            Some(allowed) if !allowed.contains(&(driver_number as u32)) => {
+                Err(errorcode::ErrorCode::NODEVICE)
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Record of the last panic that survives a reset.
+//!
+//! While a board panics, everything the panic handler prints (the panic
+//! message, the CPU state, the contents of the debug buffer and debug queue,
+//! and the state and registers of every process) is also copied into a region
+//! of RAM in the `.noinit` section. That section is not zeroed at boot, so
+//! after the board is reset (for example by a watchdog) the record can still
+//! be read, e.g. with the `lastpanic` process console command.
+//!
+//! A record holds up to `POSTMORTEM_LEN` bytes, anything printed after that
+//! is not recorded. The record is protected by a checksum, so after a cold
+//! boot, or if the RAM was overwritten by a bootloader, no record is
+//! reported.
+//!
+//! The record is kept in a `Record` the board provides, so that only boards
+//! that record panics spend the RAM on it. To record panics, a board sets the
+//! record with `components::postmortem::PostmortemComponent`, and wraps its
+//! panic writer in a `PostmortemWriter`:
+//!
+//! ```ignore
+//! components::postmortem::PostmortemComponent::new()
+//!     .finalize(components::postmortem_component_static!());
+//!
+//! let writer = &mut kernel::postmortem::PostmortemWriter::new(&mut WRITER);
+//! debug::panic(&mut [led], writer, pi, &cortexm4::support::nop, &PROCESSES, &CHIP, &PROCESS_PRINTER)
+//! ```
+//!
+//! Without a record, nothing is recorded and no record is reported.
+//!
+//! Faults that reset the board without a panic, such as a watchdog reset, can
+//! be recorded with `record_fault()` before the reset happens.
+
+use core::fmt::{self, Result, Write};
+
+use crate::debug::IoWrite;
+
+/// Maximum number of bytes recorded for a panic.
+pub const POSTMORTEM_LEN: usize = 4096;
+
+const MAGIC: u32 = 0x504d_5254;
+
+/// Storage for the record of the last panic. It must be placed in a section
+/// that is not zeroed at boot, such as `.noinit`.
+#[repr(C)]
+pub struct Record {
+    magic: u32,
+    len: u32,
+    checksum: u32,
+    data: [u8; POSTMORTEM_LEN],
+}
+
+impl Record {
+    pub const fn new() -> Self {
+        Record {
+            magic: 0,
+            len: 0,
+            checksum: 0,
+            data: [0; POSTMORTEM_LEN],
+        }
+    }
+}
+
+/// The record set by the board, or null.
+static mut RECORD: *mut Record = core::ptr::null_mut();
+
+/// Whether `with_last_panic()` is borrowing the record.
+static mut READING: bool = false;
+
+fn update_checksum(checksum: u32, bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(checksum, |sum, byte| sum.rotate_left(5) ^ (*byte as u32))
+}
+
+fn valid(record: &Record) -> bool {
+    let len = record.len as usize;
+    record.magic == MAGIC
+        && len <= POSTMORTEM_LEN
+        && update_checksum(MAGIC, &record.data[..len]) == record.checksum
+}
+
+/// Use `record` to keep the record of the last panic. Whatever `record` held
+/// before, e.g. the record of a panic before the last reset, is kept.
+pub fn set_record(record: &'static mut Record) {
+    // Safety: the kernel is single-threaded, and the previous record, if
+    // any, is not borrowed, as `with_last_panic()` does not call back into
+    // the kernel.
+    unsafe {
+        RECORD = record;
+    }
+}
+
+/// Calls `f` with the record of the last panic, if there is one.
+///
+/// The record is only borrowed for the duration of `f`, as `record_fault()`
+/// can replace it later on. Faults recorded from within `f` are dropped.
+pub fn with_last_panic<R, F: FnOnce(&[u8]) -> R>(f: F) -> Option<R> {
+    // Safety: the kernel is single-threaded, and the record is only written
+    // while panicking, after which no other code runs, or by
+    // `record_fault()`, which does not write it while `READING` is set.
+    unsafe {
+        let record = RECORD.as_ref()?;
+        if !valid(record) {
+            return None;
+        }
+        let reading = READING;
+        READING = true;
+        let result = f(&record.data[..record.len as usize]);
+        READING = reading;
+        Some(result)
+    }
+}
+
+/// Discard the record of the last panic.
+pub fn clear() {
+    // Safety: only a volatile write to a field the borrow in
+    // `with_last_panic()` does not cover.
+    unsafe {
+        if !RECORD.is_null() {
+            core::ptr::addr_of_mut!((*RECORD).magic).write_volatile(0);
+        }
+    }
+}
+
+/// Start a new, empty record.
+///
+/// # Safety
+///
+/// Must not be called from within `with_last_panic()`.
+unsafe fn start() {
+    if let Some(record) = RECORD.as_mut() {
+        record.magic = MAGIC;
+        record.len = 0;
+        record.checksum = MAGIC;
+    }
+}
+
+/// Append `bytes` to the record, dropping whatever does not fit.
+///
+/// # Safety
+///
+/// Same as `start()`.
+unsafe fn append(bytes: &[u8]) {
+    let record = match RECORD.as_mut() {
+        Some(record) => record,
+        None => return,
+    };
+    let start = record.len as usize;
+    let len = core::cmp::min(bytes.len(), POSTMORTEM_LEN - start);
+    record.data[start..start + len].copy_from_slice(&bytes[..len]);
+    record.checksum = update_checksum(record.checksum, &bytes[..len]);
+    record.len = (start + len) as u32;
+}
+
+struct Recorder;
+
+impl Write for Recorder {
+    fn write_str(&mut self, s: &str) -> Result {
+        // Safety: see `record_fault()`.
+        unsafe { append(s.as_bytes()) };
+        Ok(())
+    }
+}
+
+/// Replace the record of the last panic with `args`, for a fault that is
+/// about to reset the board without panicking.
+///
+/// Does nothing when called from within `with_last_panic()`.
+pub fn record_fault(args: fmt::Arguments) {
+    // Safety: the record is not borrowed unless `READING` is set.
+    unsafe {
+        if READING {
+            return;
+        }
+        start();
+    }
+    let _ = Recorder.write_fmt(args);
+}
+
+/// Writer that passes everything through to `writer` and records it as the
+/// last panic.
+pub struct PostmortemWriter<'a, W: Write + IoWrite> {
+    writer: &'a mut W,
+}
+
+impl<'a, W: Write + IoWrite> PostmortemWriter<'a, W> {
+    /// Start a new record, replacing the previous one.
+    ///
+    /// # Safety
+    ///
+    /// Must only be used while panicking.
+    pub unsafe fn new(writer: &'a mut W) -> Self {
+        start();
+        PostmortemWriter { writer }
+    }
+
+    fn record(&mut self, bytes: &[u8]) {
+        // Safety: `new()` requires that we are panicking.
+        unsafe { append(bytes) };
+    }
+}
+
+impl<'a, W: Write + IoWrite> Write for PostmortemWriter<'a, W> {
+    fn write_str(&mut self, s: &str) -> Result {
+        self.record(s.as_bytes());
+        self.writer.write_str(s)
+    }
+}
+
+impl<'a, W: Write + IoWrite> IoWrite for PostmortemWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> usize {
+        self.record(buf);
+        self.writer.write(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clear, record_fault, set_record, with_last_panic, Record};
+
+    static mut TEST_RECORD: Record = Record::new();
+
+    #[test]
+    fn fault_while_reading_is_dropped() {
+        assert_eq!(with_last_panic(|record| record.len()), None);
+        record_fault(format_args!("no record"));
+        assert_eq!(with_last_panic(|record| record.len()), None);
+
+        set_record(unsafe { &mut *core::ptr::addr_of_mut!(TEST_RECORD) });
+        record_fault(format_args!("first"));
+        let nested = with_last_panic(|record| {
+            record_fault(format_args!("second"));
+            record == b"first"
+        });
+        assert_eq!(nested, Some(true));
+        assert_eq!(with_last_panic(|record| record == b"first"), Some(true));
+        clear();
+        assert_eq!(with_last_panic(|record| record.len()), None);
+    }
+}
@@ -4,6 +4,7 @@
 
 //! Types for Tock-compatible processes.
 
+use core::cell::Cell;
 use core::fmt;
 use core::fmt::Write;
 use core::ptr::NonNull;
@@ -23,6 +24,7 @@ use tock_tbf::types::{CommandPermissions, TbfFooterV2Credentials};
 // Export all process related types via `kernel::process::`.
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::{load_and_check_processes, load_processes};
+pub use crate::process_loading::{DynamicProcessLoader, DynamicProcessLoading};
 pub use crate::process_policies::{
     PanicFaultPolicy, ProcessFaultPolicy, RestartFaultPolicy, StopFaultPolicy,
     StopWithDebugFaultPolicy, ThresholdRestartFaultPolicy, ThresholdRestartThenPanicFaultPolicy,
@@ -198,6 +200,15 @@ impl ProcessId {
         self.kernel
             .process_map_or(None, *self, |process| process.get_storage_permissions())
     }
+
+    /// Get the ShortID of the process. Returns `ShortID::LocallyUnique` if
+    /// the process no longer exists.
+    pub fn short_app_id(&self) -> ShortID {
+        self.kernel
+            .process_map_or(ShortID::LocallyUnique, *self, |process| {
+                process.short_app_id()
+            })
+    }
 }
 
 /// A compressed form of an Application Identifer.
@@ -217,6 +228,42 @@ impl PartialEq for ShortID {
 }
 impl Eq for ShortID {}
 
+/// A slot of the processes array.
+///
+/// The kernel, the schedulers and the board share the processes array, and
+/// processes are loaded into its empty slots at boot and, with a
+/// `DynamicProcessLoader`, while the kernel runs. Slots therefore can only be
+/// changed through a shared reference, by the kernel.
+pub struct ProcessSlot {
+    proc: Cell<Option<&'static dyn Process>>,
+}
+
+impl ProcessSlot {
+    /// An empty slot, to initialize the processes array of a board with:
+    ///
+    /// ```rust,ignore
+    /// static mut PROCESSES: [ProcessSlot; NUM_PROCS] = [ProcessSlot::EMPTY; NUM_PROCS];
+    /// ```
+    #[allow(clippy::declare_interior_mutable_const)]
+    pub const EMPTY: ProcessSlot = ProcessSlot {
+        proc: Cell::new(None),
+    };
+
+    /// The process in this slot, if any.
+    pub fn get(&self) -> Option<&'static dyn Process> {
+        self.proc.get()
+    }
+
+    /// Whether this slot holds no process.
+    pub fn is_none(&self) -> bool {
+        self.proc.get().is_none()
+    }
+
+    pub(crate) fn set(&self, proc: &'static dyn Process) {
+        self.proc.set(Some(proc));
+    }
+}
+
 /// This trait represents a generic process that the Tock scheduler can
 /// schedule.
 pub trait Process {
@@ -502,6 +549,10 @@ pub trait Process {
     /// The offset indicates the multiple of 64 command numbers to get permissions for.
     fn get_command_permissions(&self, driver_num: usize, offset: usize) -> CommandPermissions;
 
+    /// Return the driver numbers this process may use, if its TBF header
+    /// restricts them.
+    fn get_allowed_drivers(&self) -> Option<&[u32]>;
+
     /// Get the storage permissions for the process.
     ///
     /// Returns `None` if the process has no storage permissions.
@@ -528,6 +579,16 @@ pub trait Process {
         min_region_size: usize,
     ) -> Option<mpu::Region>;
 
+    /// Allocate a new MPU region like `add_mpu_region()`, which the process
+    /// can access with `permissions` rather than read and write.
+    fn add_mpu_region_with_permissions(
+        &self,
+        unallocated_memory_start: *const u8,
+        unallocated_memory_size: usize,
+        min_region_size: usize,
+        permissions: mpu::Permissions,
+    ) -> Option<mpu::Region>;
+
     /// Removes an MPU region from the process that has been previouly added with
     /// `add_mpu_region`.
     ///
@@ -579,6 +640,26 @@ pub trait Process {
         align: usize,
     ) -> Option<(ProcessCustomGrantIdentifier, NonNull<u8>)>;
 
+    /// Allocate `size` bytes from the grant region whose contents survive
+    /// restarts of the process. When the process is restarted, e.g. after a
+    /// fault, the kernel moves the region into the memory of the new instance
+    /// with the contents the previous instance left in it. A process has at
+    /// most one preserved region, and it is zeroed when allocated.
+    ///
+    /// Returns `ALREADY` if the process has a preserved region, `NOMEM` if
+    /// there is not enough grant memory, and `FAIL` if the process is
+    /// inactive.
+    fn allocate_preserved_region(&self, size: usize) -> Result<(), ErrorCode>;
+
+    /// Return the size of the preserved region and whether its contents were
+    /// recovered from a previous instance of the process, or `None` if the
+    /// process has no preserved region.
+    fn get_preserved_region_info(&self) -> Option<(usize, bool)>;
+
+    /// Call `fun` with the contents of the preserved region. Does nothing if
+    /// the process is inactive or has no preserved region.
+    fn map_preserved_region(&self, fun: &mut dyn FnMut(&mut [u8]));
+
     /// Enter the grant based on `grant_num` for this process.
     ///
     /// Entering a grant means getting access to the actual memory for the
@@ -627,6 +708,13 @@ pub trait Process {
     /// Useful for debugging/inspecting the system.
     fn grant_allocated_count(&self) -> Option<usize>;
 
+    /// Call `f` with the driver number and the size in bytes of each grant
+    /// the process allocated. This does not include custom grants, or padding
+    /// for the alignment of grants.
+    ///
+    /// Useful for debugging/inspecting the system.
+    fn grant_allocations(&self, f: &mut dyn FnMut(usize, usize));
+
     /// Get the grant number (grant_num) associated with a given driver number
     /// if there is a grant associated with that driver_num.
     fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error>;
@@ -706,6 +794,32 @@ pub trait Process {
     /// Increment the number of times the process has exceeded its timeslice.
     fn debug_timeslice_expired(&self);
 
+    /// Returns the total time in microseconds this process has spent
+    /// executing since it last started, as measured by the scheduler timer.
+    /// Time is only accounted when the scheduler runs the process with a
+    /// timeslice.
+    fn debug_execution_time_us(&self) -> u64;
+
+    /// Add `time_us` microseconds to the time this process has spent
+    /// executing.
+    fn debug_execution_time_add(&self, time_us: u32);
+
+    /// Returns the part of `debug_execution_time_us()` in microseconds the
+    /// kernel spent handling syscalls of this process.
+    fn debug_syscall_time_us(&self) -> u64;
+
+    /// Add `time_us` microseconds to the time spent handling syscalls of this
+    /// process.
+    fn debug_syscall_time_add(&self, time_us: u32);
+
+    /// Returns how many times this process was stopped while it still had
+    /// work to do, because its timeslice expired or the kernel had work to
+    /// do.
+    fn debug_preemption_count(&self) -> usize;
+
+    /// Increment the number of times the process was preempted.
+    fn debug_preempted(&self);
+
     /// Increment the number of times the process called a syscall and record
     /// the last syscall that was called.
     fn debug_syscall_called(&self, last_syscall: Syscall);
@@ -10,7 +10,7 @@ pub mod basic;
 
 use crate::config;
 use crate::debug;
-use crate::process::{Process, ShortID, State};
+use crate::process::{Process, ProcessSlot, ShortID, State};
 use crate::ErrorCode;
 use tock_tbf::types::TbfFooterV2Credentials;
 
@@ -86,7 +86,7 @@ impl<'a> AppCredentialsChecker<'a> for () {
 /// runs at boot), but it can be stopped to let a lower version number run.
 pub fn is_runnable<AU: AppUniqueness>(
     process: &dyn Process,
-    processes: &[Option<&dyn Process>],
+    processes: &[ProcessSlot],
     id_differ: &AU,
 ) -> bool {
     let len = processes.len();
@@ -101,7 +101,7 @@ pub fn is_runnable<AU: AppUniqueness>(
     // however, since `process` is not running and its version number
     // is the same, it will not block itself from running.
     for i in 0..len {
-        let other_process = processes[i];
+        let other_process = processes[i].get();
         let other_name = other_process.map_or("None", |c| c.get_process_name());
 
         let blocks = other_process.map_or(false, |other| {
@@ -7,6 +7,7 @@
 //| the [AppID TRD](../../doc/reference/trd-appid.md).
 
 use crate::deferred_call::{DeferredCall, DeferredCallClient};
+use crate::hil::crc::{CrcAlgorithm, CrcOutput};
 use crate::hil::digest::{ClientData, ClientHash, ClientVerify};
 use crate::hil::digest::{DigestDataVerify, Sha256};
 use crate::process::{Process, ShortID};
@@ -14,6 +15,7 @@ use crate::process_checker::{AppCredentialsChecker, AppUniqueness};
 use crate::process_checker::{CheckResult, Client, Compress};
 use crate::utilities::cells::OptionalCell;
 use crate::utilities::cells::TakeCell;
+use crate::utilities::crc;
 use crate::utilities::leasable_buffer::{LeasableBuffer, LeasableMutableBuffer};
 use crate::ErrorCode;
 use tock_tbf::types::TbfFooterV2Credentials;
@@ -262,6 +264,98 @@ impl Compress for AppCheckerSha256 {
     }
 }
 
+/// A Credentials Checking Policy that only runs Userspace Binaries with
+/// a valid CRC-32C credential. This protects against corrupted or
+/// partially written binaries on boards without hashing hardware, but
+/// not against tampered ones: a CRC provides integrity, not
+/// authenticity. As a CRC does not identify an application, it uses
+/// the process name as the Application Identifier, like
+/// `AppCheckerSimulated`.
+pub struct AppCheckerCrc32C<'a> {
+    deferred_call: DeferredCall,
+    client: OptionalCell<&'a dyn Client<'a>>,
+    credentials: OptionalCell<TbfFooterV2Credentials>,
+    binary: OptionalCell<&'a [u8]>,
+}
+
+impl<'a> AppCheckerCrc32C<'a> {
+    pub fn new() -> AppCheckerCrc32C<'a> {
+        Self {
+            deferred_call: DeferredCall::new(),
+            client: OptionalCell::empty(),
+            credentials: OptionalCell::empty(),
+            binary: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a> DeferredCallClient for AppCheckerCrc32C<'a> {
+    fn handle_deferred_call(&self) {
+        self.client.map(|c| {
+            let binary = self.binary.take().unwrap();
+            let cred = self.credentials.take().unwrap();
+            let expected = cred
+                .data()
+                .get(0..4)
+                .map(|crc| u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]));
+            let result = if expected.map_or(false, |expected| {
+                crc::compute(CrcAlgorithm::Crc32C, binary) == CrcOutput::Crc32C(expected)
+            }) {
+                Ok(CheckResult::Accept)
+            } else {
+                Ok(CheckResult::Reject)
+            };
+
+            c.check_done(result, cred, binary)
+        });
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> AppCredentialsChecker<'a> for AppCheckerCrc32C<'a> {
+    fn require_credentials(&self) -> bool {
+        true
+    }
+
+    fn check_credentials(
+        &self,
+        credentials: TbfFooterV2Credentials,
+        binary: &'a [u8],
+    ) -> Result<(), (ErrorCode, TbfFooterV2Credentials, &'a [u8])> {
+        if credentials.format() != TbfFooterV2CredentialsType::CRC32C {
+            Err((ErrorCode::NOSUPPORT, credentials, binary))
+        } else if self.credentials.is_none() {
+            self.credentials.replace(credentials);
+            self.binary.replace(binary);
+            self.deferred_call.set();
+            Ok(())
+        } else {
+            Err((ErrorCode::BUSY, credentials, binary))
+        }
+    }
+
+    fn set_client(&self, client: &'a dyn Client<'a>) {
+        self.client.replace(client);
+    }
+}
+
+impl AppUniqueness for AppCheckerCrc32C<'_> {
+    fn different_identifier(&self, process_a: &dyn Process, process_b: &dyn Process) -> bool {
+        let a = process_a.get_process_name();
+        let b = process_b.get_process_name();
+        !a.eq(b)
+    }
+}
+
+impl Compress for AppCheckerCrc32C<'_> {
+    fn to_short_id(&self, _credentials: &TbfFooterV2Credentials) -> ShortID {
+        ShortID::LocallyUnique
+    }
+}
+
 /// A sample Credentials Checking Policy that loads and runs Userspace
 /// Binaries that have RSA3072 or RSA4096 credentials. It uses the
 /// public key stored in the credentials as the Application
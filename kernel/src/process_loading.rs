@@ -8,6 +8,7 @@
 //! checking whether they are allowed to be loaded, and if so initializing a process
 //! structure to run it.
 
+use core::cell::Cell;
 use core::convert::TryInto;
 use core::fmt;
 
@@ -18,10 +19,11 @@ use crate::debug;
 use crate::kernel::{Kernel, ProcessCheckerMachine};
 use crate::platform::chip::Chip;
 use crate::platform::platform::KernelResources;
-use crate::process::{Process, ShortID};
+use crate::process::{Process, ProcessSlot, ShortID, State};
 use crate::process_checker::AppCredentialsChecker;
 use crate::process_policies::ProcessFaultPolicy;
 use crate::process_standard::ProcessStandard;
+use crate::utilities::cells::TakeCell;
 
 /// Errors that can occur when trying to load and create processes.
 pub enum ProcessLoadError {
@@ -86,6 +88,10 @@ pub enum ProcessLoadError {
     /// this counter.
     CredentialsReject(u32),
 
+    /// A process could be loaded, but the processes array has no free slot
+    /// for it. Increase the number of processes the board supports.
+    NoProcessSlot,
+
     /// Process loading error due (likely) to a bug in the kernel. If you get
     /// this error please open a bug report.
     InternalError,
@@ -161,6 +167,8 @@ impl fmt::Debug for ProcessLoadError {
                 write!(f, "Credentials index {} rejected.", index)
             }
 
+            ProcessLoadError::NoProcessSlot => write!(f, "No free slot in processes array"),
+
             ProcessLoadError::InternalError => write!(f, "Error in kernel. Likely a bug."),
         }
     }
@@ -179,7 +187,7 @@ pub fn load_and_check_processes<KR: KernelResources<C>, C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    mut procs: &'static mut [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
     capability_management: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError>
@@ -191,7 +199,7 @@ where
         chip,
         app_flash,
         app_memory,
-        &mut procs,
+        procs,
         fault_policy,
         capability_management,
     )?;
@@ -213,7 +221,7 @@ pub fn load_processes<C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    mut procs: &'static mut [Option<&'static dyn Process>],
+    procs: &'static [ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
     capability_management: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError> {
@@ -222,7 +230,7 @@ pub fn load_processes<C: Chip>(
         chip,
         app_flash,
         app_memory,
-        &mut procs,
+        procs,
         fault_policy,
         capability_management,
     )?;
@@ -232,7 +240,7 @@ pub fn load_processes<C: Chip>(
     }
     let capability = create_capability!(ProcessApprovalCapability);
     for proc in procs.iter() {
-        let res = proc.map(|p| {
+        let res = proc.get().map(|p| {
             p.mark_credentials_pass(None, ShortID::LocallyUnique, &capability)
                 .or(Err(ProcessLoadError::InternalError))?;
             if config::CONFIG.debug_process_credentials {
@@ -275,7 +283,7 @@ fn load_processes_from_flash<C: Chip>(
     chip: &'static C,
     app_flash: &'static [u8],
     app_memory: &'static mut [u8],
-    procs: &mut &'static mut [Option<&'static dyn Process>],
+    procs: &[ProcessSlot],
     fault_policy: &'static dyn ProcessFaultPolicy,
     capability: &dyn ProcessManagementCapability,
 ) -> Result<(), ProcessLoadError> {
@@ -308,11 +316,11 @@ fn load_processes_from_flash<C: Chip>(
             Ok((new_flash, new_mem, proc)) => {
                 remaining_flash = new_flash;
                 remaining_memory = new_mem;
-                if proc.is_some() {
+                if let Some(proc) = proc {
                     if config::CONFIG.debug_load_processes {
-                        proc.map(|p| debug!("Loaded process {}", p.get_process_name()));
+                        debug!("Loaded process {}", proc.get_process_name());
                     }
-                    procs[index] = proc;
+                    procs[index].set(proc);
                     index = index + 1;
                 } else {
                     if config::CONFIG.debug_load_processes {
@@ -485,3 +493,168 @@ fn load_process<C: Chip>(
     };
     Ok((remaining_flash, remaining_memory, process_option))
 }
+
+/// Loads processes from flash while the kernel is running.
+///
+/// Implemented by `DynamicProcessLoader`. Capsules use this trait so they do
+/// not depend on the chip type.
+pub trait DynamicProcessLoading {
+    /// Discover processes written to the flash region of the loader since
+    /// the last call, create them, and start checking their credentials.
+    /// Processes are started by the kernel once their credentials are
+    /// approved. Returns the number of processes created.
+    ///
+    /// Processes that do not fit in the remaining RAM or for which there is
+    /// no free slot in the processes array are not loaded, and the error is
+    /// returned. Discovery continues after such a process on the next call.
+    fn load_new_processes(&self) -> Result<usize, ProcessLoadError>;
+
+    /// Returns the number of bytes of the flash region not yet used by
+    /// loaded processes, i.e. how much flash is left for new processes.
+    fn remaining_flash(&self) -> usize;
+}
+
+/// Discovers, verifies and starts processes written into a spare flash
+/// region at runtime, instead of only scanning the app flash region at boot.
+///
+/// The loader owns a flash region separate from the one scanned at boot,
+/// into which new TBF objects are written back-to-back, and a pool of RAM to
+/// allocate the memory of new processes from. Each call to
+/// `load_new_processes()` continues scanning the flash region where the
+/// previous call stopped, so processes can be added one after the other,
+/// e.g. after each over-the-air install. Erased flash ends the scan, like the
+/// end of the app linked list at boot.
+///
+/// New processes are put in free slots of the processes array and go
+/// through the same credential checks as the processes loaded at boot, if the
+/// board loaded those with `load_and_check_processes`. Otherwise they are
+/// approved as `load_processes` does.
+pub struct DynamicProcessLoader<C: 'static + Chip> {
+    kernel: &'static Kernel,
+    chip: &'static C,
+    fault_policy: &'static dyn ProcessFaultPolicy,
+    /// Part of the flash region that was not scanned yet.
+    flash: Cell<&'static [u8]>,
+    memory: TakeCell<'static, [u8]>,
+}
+
+impl<C: 'static + Chip> DynamicProcessLoader<C> {
+    /// Create a loader for processes written into `flash`, whose memory is
+    /// allocated from `memory`. Both regions must be disjoint from the ones
+    /// passed to `load_processes` at boot. New processes are put in the
+    /// processes array of `kernel`.
+    ///
+    /// As with `load_processes`, creating processes from slices of flash and
+    /// memory is fundamentally unsafe, so this requires the
+    /// `ProcessManagementCapability`.
+    pub fn new(
+        kernel: &'static Kernel,
+        chip: &'static C,
+        flash: &'static [u8],
+        memory: &'static mut [u8],
+        fault_policy: &'static dyn ProcessFaultPolicy,
+        _capability: &dyn ProcessManagementCapability,
+    ) -> Self {
+        DynamicProcessLoader {
+            kernel,
+            chip,
+            fault_policy,
+            flash: Cell::new(flash),
+            memory: TakeCell::new(memory),
+        }
+    }
+
+    /// Approve the processes in `procs` which were just created, if the
+    /// board does not check credentials.
+    fn approve(&self, procs: &[ProcessSlot]) -> Result<(), ProcessLoadError> {
+        let capability = create_capability!(ProcessApprovalCapability);
+        for proc in procs.iter().filter_map(ProcessSlot::get) {
+            if proc.get_state() == State::CredentialsUnchecked {
+                proc.mark_credentials_pass(None, ShortID::LocallyUnique, &capability)
+                    .or(Err(ProcessLoadError::InternalError))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: 'static + Chip> DynamicProcessLoading for DynamicProcessLoader<C> {
+    fn load_new_processes(&self) -> Result<usize, ProcessLoadError> {
+        let capability = create_capability!(ProcessManagementCapability);
+        let procs = self.kernel.process_slots();
+        let mut loaded = 0;
+        let mut result = Ok(());
+
+        let mut memory = self.memory.take().ok_or(ProcessLoadError::InternalError)?;
+        loop {
+            let flash = self.flash.get();
+            let index = match procs.iter().position(ProcessSlot::is_none) {
+                Some(index) => index,
+                None => {
+                    // Only an error if there is another process.
+                    if flash.get(0..8).map_or(false, |header| {
+                        header.try_into().map_or(false, |header| {
+                            tock_tbf::parse::parse_tbf_header_lengths(header).is_ok()
+                        })
+                    }) {
+                        result = Err(ProcessLoadError::NoProcessSlot);
+                    }
+                    break;
+                }
+            };
+            match load_process(
+                self.kernel,
+                self.chip,
+                flash,
+                memory,
+                index,
+                self.fault_policy,
+                &capability,
+            ) {
+                Ok((remaining_flash, remaining_memory, proc)) => {
+                    self.flash.set(remaining_flash);
+                    memory = remaining_memory;
+                    if let Some(proc) = proc {
+                        if config::CONFIG.debug_load_processes {
+                            debug!("Dynamically loaded process {}", proc.get_process_name());
+                        }
+                        procs[index].set(proc);
+                        loaded += 1;
+                    }
+                }
+                Err((remaining_flash, remaining_memory, err)) => {
+                    memory = remaining_memory;
+                    match err {
+                        // No (complete) process written yet.
+                        ProcessLoadError::TbfHeaderNotFound | ProcessLoadError::NotEnoughFlash => {}
+                        _ => {
+                            // Skip the process that failed to load.
+                            self.flash.set(remaining_flash);
+                            result = Err(err);
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        self.memory.replace(memory);
+
+        if loaded > 0 {
+            let checker = self.kernel.get_checker();
+            let checked = if checker.has_policy() {
+                checker.check_new_processes()
+            } else {
+                self.approve(procs)
+            };
+            if checked.is_err() {
+                result = checked;
+            }
+        }
+
+        result.map(|()| loaded)
+    }
+
+    fn remaining_flash(&self) -> usize {
+        self.flash.get().len()
+    }
+}
@@ -0,0 +1,96 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Observers of what processes do and what the kernel does for them.
+//!
+//! Kernel services that keep state about processes, and debugging aids that
+//! watch processes, register a `ProcessObserver` with the kernel instead of
+//! a hook of their own. The kernel notifies every registered observer, in
+//! the order they were registered, when:
+//!
+//! - it records a kernel event, e.g. a scheduling decision or a syscall,
+//! - it dispatches a subscribe, command or allow syscall to a driver, and
+//!   when the driver returns,
+//! - a process allows a buffer to a driver,
+//! - a process terminates, before its grant regions are released.
+//!
+//! All methods but `next_observer()` default to doing nothing, so an
+//! observer only implements the ones it needs.
+//!
+//! Usage
+//! -----
+//! ```rust,ignore
+//! pub struct Auditor {
+//!     next_observer: ListLink<'static, dyn ProcessObserver>,
+//! }
+//!
+//! impl ProcessObserver for Auditor {
+//!     fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+//!         &self.next_observer
+//!     }
+//!
+//!     fn process_terminated(&self, processid: ProcessId) {
+//!         debug!("[{:?}] terminated", processid);
+//!     }
+//! }
+//!
+//! board_kernel.add_process_observer(auditor, &process_management_capability);
+//! ```
+
+use crate::collections::list::{ListLink, ListNode};
+use crate::process::ProcessId;
+use crate::syscall_latency::SyscallClass;
+use crate::trace::EventKind;
+
+/// The allow system call a buffer was shared with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AllowType {
+    ReadWrite,
+    ReadOnly,
+    UserspaceReadable,
+}
+
+/// Notified by the kernel about processes.
+pub trait ProcessObserver {
+    /// The link to the next observer registered with the kernel.
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver>;
+
+    /// The kernel recorded an event of `kind`, see `trace::EventKind` for
+    /// the arguments.
+    fn event(&self, _kind: EventKind, _arg0: u32, _arg1: u32) {}
+
+    /// The kernel is about to dispatch a subscribe, command or allow syscall
+    /// to the driver `driver_number`. Syscalls are not nested, so every call
+    /// is followed by `syscall_returned()` before the next one.
+    fn syscall_dispatched(&self, _driver_number: usize) {}
+
+    /// The driver `driver_number` handled a syscall of `class`.
+    fn syscall_returned(&self, _class: SyscallClass, _driver_number: usize) {}
+
+    /// A process successfully allowed a buffer of `size` bytes at `address`
+    /// to `allow_num` of the syscall driver `driver_num`. This replaces any
+    /// buffer previously allowed to the same slot. A `size` of 0 means the
+    /// process revoked the buffer.
+    fn allowed(
+        &self,
+        _processid: ProcessId,
+        _driver_num: usize,
+        _allow_num: usize,
+        _allow_type: AllowType,
+        _address: *const u8,
+        _size: usize,
+    ) {
+    }
+
+    /// A process terminated, including before it restarts. Its grant
+    /// regions, and with them all of its allowed buffers, are released
+    /// afterwards.
+    fn process_terminated(&self, _processid: ProcessId) {}
+}
+
+impl ListNode<'static, dyn ProcessObserver> for dyn ProcessObserver {
+    fn next(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        self.next_observer()
+    }
+}
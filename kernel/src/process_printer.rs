@@ -111,6 +111,9 @@ impl ProcessPrinter for ProcessPrinterText {
         let syscall_count = process.debug_syscall_count();
         let dropped_upcall_count = process.debug_dropped_upcall_count();
         let restart_count = process.get_restart_count();
+        let execution_time_us = process.debug_execution_time_us();
+        let syscall_time_us = process.debug_syscall_time_us();
+        let preemption_count = process.debug_preemption_count();
 
         let addresses = process.get_addresses();
         let sizes = process.get_sizes();
@@ -128,7 +131,8 @@ impl ProcessPrinter for ProcessPrinterText {
             "\
                  𝐀𝐩𝐩: {}   -   [{:?}]\
                  \r\n Events Queued: {}   Syscall Count: {}   Dropped Upcall Count: {}\
-                 \r\n Restart Count: {}\
+                 \r\n Restart Count: {}   Preemption Count: {}\
+                 \r\n CPU Time: {} us   Syscall Time: {} us\
                  \r\n",
             process.get_process_name(),
             process.get_state(),
@@ -136,6 +140,9 @@ impl ProcessPrinter for ProcessPrinterText {
             syscall_count,
             dropped_upcall_count,
             restart_count,
+            preemption_count,
+            execution_time_us,
+            syscall_time_us,
         ));
 
         let _ = match process.debug_syscall_last() {
@@ -285,6 +292,22 @@ impl ProcessPrinter for ProcessPrinterText {
             ));
         }
 
+        if !bww.bytes_remaining() {
+            let _ = bww.write_fmt(format_args!(
+                "\
+                 \r\n Grants: {} bytes used, {} bytes free\
+                 \r\n",
+                sram_grant_size,
+                addresses.sram_grant_start - addresses.sram_app_brk,
+            ));
+            process.grant_allocations(&mut |driver_num, size| {
+                let _ = bww.write_fmt(format_args!(
+                    "  Driver {:#07x}: {:6} bytes\r\n",
+                    driver_num, size
+                ));
+            });
+        }
+
         if bww.bytes_remaining() {
             // The underlying writer is indicating there are still bytes
             // remaining to be sent. That means we want to return a context so
@@ -74,6 +74,15 @@ struct ProcessStandardDebug {
     /// How many times this process has been paused because it exceeded its
     /// timeslice.
     timeslice_expiration_count: usize,
+
+    /// Total time in microseconds this process has been executing.
+    execution_time_us: u64,
+
+    /// Part of `execution_time_us` spent handling syscalls.
+    syscall_time_us: u64,
+
+    /// How many times this process has been preempted.
+    preemption_count: usize,
 }
 
 /// Entry that is stored in the grant pointer table at the top of process
@@ -95,6 +104,11 @@ struct GrantPointerEntry {
     /// The start of the memory location where the grant has been allocated, or
     /// null if the grant has not been allocated.
     grant_ptr: *mut u8,
+
+    /// The number of bytes allocated for the grant, or 0 if the grant has not
+    /// been allocated. This does not include padding needed for its
+    /// alignment.
+    size: usize,
 }
 
 /// A type for userspace processes in Tock.
@@ -220,6 +234,14 @@ pub struct ProcessStandard<'a, C: 'static + Chip> {
     /// determine if the process should be restarted or not.
     restart_count: Cell<usize>,
 
+    /// Location and size of the region of the grant memory that is kept when
+    /// the process restarts, if the process allocated one.
+    preserved_region: Cell<Option<(*mut u8, usize)>>,
+
+    /// Whether the contents of `preserved_region` were recovered from a
+    /// previous instance of the process.
+    preserved_region_recovered: Cell<bool>,
+
     /// The completion code set by the process when it last exited, restarted,
     /// or was terminated. If the process is has never terminated, then the
     /// `OptionalCell` will be empty (i.e. `None`). If the process has exited,
@@ -271,7 +293,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             }
         });
 
-        if ret.is_err() {
+        if ret.is_err() && config::CONFIG.debug_counters {
             // On any error we were unable to enqueue the task. Record the
             // error, but importantly do _not_ increment kernel work.
             self.debug.map(|debug| {
@@ -482,6 +504,10 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
             tasks.empty();
         });
 
+        // Let the observers know the buffers and grants of this process are
+        // about to be released.
+        self.kernel.process_terminated(self.processid());
+
         // Clear any grant regions this app has setup with any capsules.
         unsafe {
             self.grant_ptrs_reset();
@@ -514,6 +540,10 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         self.header.get_command_permissions(driver_num, offset)
     }
 
+    fn get_allowed_drivers(&self) -> Option<&[u32]> {
+        self.header.get_allowed_drivers()
+    }
+
     fn get_storage_permissions(&self) -> Option<storage_permissions::StoragePermissions> {
         let (read_count, read_storage_ids) = self
             .header
@@ -575,13 +605,28 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         unallocated_memory_start: *const u8,
         unallocated_memory_size: usize,
         min_region_size: usize,
+    ) -> Option<mpu::Region> {
+        self.add_mpu_region_with_permissions(
+            unallocated_memory_start,
+            unallocated_memory_size,
+            min_region_size,
+            mpu::Permissions::ReadWriteOnly,
+        )
+    }
+
+    fn add_mpu_region_with_permissions(
+        &self,
+        unallocated_memory_start: *const u8,
+        unallocated_memory_size: usize,
+        min_region_size: usize,
+        permissions: mpu::Permissions,
     ) -> Option<mpu::Region> {
         self.mpu_config.and_then(|mut config| {
             let new_region = self.chip.mpu().allocate_region(
                 unallocated_memory_start,
                 unallocated_memory_size,
                 min_region_size,
-                mpu::Permissions::ReadWriteOnly,
+                permissions,
                 &mut config,
             );
 
@@ -880,6 +925,7 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
                         // Actually set the driver num and grant pointer.
                         grant_entry.driver_num = driver_num;
                         grant_entry.grant_ptr = grant_ptr.as_ptr() as *mut u8;
+                        grant_entry.size = size;
 
                         // If all of this worked, return true.
                         true
@@ -915,6 +961,57 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         }
     }
 
+    fn allocate_preserved_region(&self, size: usize) -> Result<(), ErrorCode> {
+        // Do not modify an inactive process.
+        if !self.is_running() {
+            return Err(ErrorCode::FAIL);
+        }
+        if self.preserved_region.get().is_some() {
+            return Err(ErrorCode::ALREADY);
+        }
+        if size == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+
+        let ptr = self
+            .allocate_in_grant_region_internal(size, mem::align_of::<usize>())
+            .ok_or(ErrorCode::NOMEM)?;
+        // ### Safety
+        //
+        // The memory was just allocated from the grant region of this process,
+        // so nothing else refers to it.
+        unsafe {
+            ptr::write_bytes(ptr.as_ptr(), 0, size);
+        }
+        self.preserved_region.set(Some((ptr.as_ptr(), size)));
+        self.preserved_region_recovered.set(false);
+        Ok(())
+    }
+
+    fn get_preserved_region_info(&self) -> Option<(usize, bool)> {
+        self.preserved_region
+            .get()
+            .map(|(_, size)| (size, self.preserved_region_recovered.get()))
+    }
+
+    fn map_preserved_region(&self, fun: &mut dyn FnMut(&mut [u8])) {
+        if !self.is_running() {
+            return;
+        }
+        // Take the region while `fun` runs, so a nested call cannot create a
+        // second reference to it.
+        if let Some((ptr, size)) = self.preserved_region.take() {
+            // ### Safety
+            //
+            // The region is in the grant region of this process, which is only
+            // accessible to the kernel, and it is never freed while the process
+            // is running.
+            let region = unsafe { slice::from_raw_parts_mut(ptr, size) };
+            fun(region);
+            self.preserved_region.set(Some((ptr, size)));
+        }
+    }
+
     fn enter_grant(&self, grant_num: usize) -> Result<NonNull<u8>, Error> {
         // Do not try to access the grant region of inactive process.
         if !self.is_running() {
@@ -1013,6 +1110,17 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
         })
     }
 
+    fn grant_allocations(&self, f: &mut dyn FnMut(usize, usize)) {
+        self.grant_pointers.map(|grant_pointers| {
+            for grant_entry in grant_pointers.iter() {
+                // A grant is allocated if its grant pointer is non null.
+                if !grant_entry.grant_ptr.is_null() {
+                    f(grant_entry.driver_num, grant_entry.size);
+                }
+            }
+        });
+    }
+
     fn lookup_grant_from_driver_num(&self, driver_num: usize) -> Result<usize, Error> {
         self.grant_pointers
             .map_or(Err(Error::KernelError), |grant_pointers| {
@@ -1181,13 +1289,49 @@ impl<C: Chip> Process for ProcessStandard<'_, C> {
     }
 
     fn debug_timeslice_expired(&self) {
-        self.debug
-            .map(|debug| debug.timeslice_expiration_count += 1);
+        if config::CONFIG.debug_counters {
+            self.debug
+                .map(|debug| debug.timeslice_expiration_count += 1);
+        }
+    }
+
+    fn debug_execution_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.execution_time_us)
+    }
+
+    fn debug_execution_time_add(&self, time_us: u32) {
+        if config::CONFIG.debug_counters {
+            self.debug
+                .map(|debug| debug.execution_time_us += time_us as u64);
+        }
+    }
+
+    fn debug_syscall_time_us(&self) -> u64 {
+        self.debug.map_or(0, |debug| debug.syscall_time_us)
+    }
+
+    fn debug_syscall_time_add(&self, time_us: u32) {
+        if config::CONFIG.debug_counters {
+            self.debug
+                .map(|debug| debug.syscall_time_us += time_us as u64);
+        }
+    }
+
+    fn debug_preemption_count(&self) -> usize {
+        self.debug.map_or(0, |debug| debug.preemption_count)
+    }
+
+    fn debug_preempted(&self) {
+        if config::CONFIG.debug_counters {
+            self.debug.map(|debug| debug.preemption_count += 1);
+        }
     }
 
     fn debug_syscall_called(&self, last_syscall: Syscall) {
         self.debug.map(|debug| {
-            debug.syscall_count += 1;
+            if config::CONFIG.debug_counters {
+                debug.syscall_count += 1;
+            }
             debug.last_syscall = Some(last_syscall);
         });
     }
@@ -1680,6 +1824,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         for grant_entry in grant_pointers.iter_mut() {
             grant_entry.driver_num = 0;
             grant_entry.grant_ptr = ptr::null_mut();
+            grant_entry.size = 0;
         }
 
         // Now that we know we have the space we can setup the memory for the
@@ -1744,6 +1889,8 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         process.state = Cell::new(State::CredentialsUnchecked);
         process.fault_policy = fault_policy;
         process.restart_count = Cell::new(0);
+        process.preserved_region = Cell::new(None);
+        process.preserved_region_recovered = Cell::new(false);
         process.completion_code = OptionalCell::empty();
 
         process.mpu_config = MapCell::new(mpu_config);
@@ -1768,6 +1915,9 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             last_syscall: None,
             dropped_upcall_count: 0,
             timeslice_expiration_count: 0,
+            execution_time_us: 0,
+            syscall_time_us: 0,
+            preemption_count: 0,
         });
 
         // Handle any architecture-specific requirements for a new process.
@@ -1831,6 +1981,9 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             debug.last_syscall = None;
             debug.dropped_upcall_count = 0;
             debug.timeslice_expiration_count = 0;
+            debug.execution_time_us = 0;
+            debug.syscall_time_us = 0;
+            debug.preemption_count = 0;
         });
 
         // Reset MPU region configuration.
@@ -1911,8 +2064,28 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         // process's memory region.
         self.allow_high_water_mark.set(app_mpu_mem_start);
 
-        // Drop the old config and use the clean one
+        // Drop the old config and use the clean one. The regions added to the
+        // old config do not exist in the new one.
         self.mpu_config.replace(mpu_config);
+        for region in self.mpu_regions.iter() {
+            region.set(None);
+        }
+
+        // Move the preserved region of the previous instance to the grant
+        // region of the new one. As nothing of the new grant region was
+        // allocated yet, the contents are still intact, but the new location
+        // may overlap the old one.
+        if let Some((old_region, size)) = self.preserved_region.take() {
+            if let Some(new_region) =
+                self.allocate_in_grant_region_internal(size, mem::align_of::<usize>())
+            {
+                unsafe {
+                    ptr::copy(old_region, new_region.as_ptr(), size);
+                }
+                self.preserved_region.set(Some((new_region.as_ptr(), size)));
+                self.preserved_region_recovered.set(true);
+            }
+        }
 
         // Handle any architecture-specific requirements for a process when it
         // first starts (as it would when it is new).
@@ -1978,6 +2151,7 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             for grant_entry in grant_pointers.iter_mut() {
                 grant_entry.driver_num = 0;
                 grant_entry.grant_ptr = ptr::null_mut();
+                grant_entry.size = 0;
             }
         });
     }
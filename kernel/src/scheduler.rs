@@ -5,6 +5,8 @@
 //! Interface for Tock kernel schedulers.
 
 pub mod cooperative;
+pub mod duty_cycle;
+pub mod edf;
 pub mod mlfq;
 pub mod priority;
 pub mod round_robin;
@@ -13,6 +15,7 @@ use crate::deferred_call::DeferredCall;
 use crate::kernel::StoppedExecutingReason;
 use crate::platform::chip::Chip;
 use crate::process::ProcessId;
+use crate::ErrorCode;
 
 /// Trait which any scheduler must implement.
 pub trait Scheduler<C: Chip> {
@@ -82,6 +85,33 @@ pub trait Scheduler<C: Chip> {
     unsafe fn continue_process(&self, _id: ProcessId, chip: &C) -> bool {
         !(chip.has_pending_interrupts() || DeferredCall::has_tasks())
     }
+
+    /// Change how the scheduler treats process `id` at runtime, e.g. on
+    /// request of a management process.
+    ///
+    /// Schedulers only support the hints that map to their policy, and
+    /// return `NOSUPPORT` for the others, which is what this default
+    /// implementation does for all hints. They return `INVAL` if the value
+    /// is out of range or the process does not exist.
+    fn set_hint(&self, _id: ProcessId, _hint: SchedulingHint) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+}
+
+/// Per-process scheduling parameters that can be passed to
+/// `Scheduler::set_hint()`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SchedulingHint {
+    /// Run the process at priority level `0` (highest) or lower.
+    Priority(u32),
+    /// Run the process with a timeslice of this many microseconds.
+    Timeslice(u32),
+    /// Give the process `budget_us` microseconds of CPU time every
+    /// `period_us` microseconds.
+    Budget { period_us: u32, budget_us: u32 },
+    /// Drop the hints given for the process and treat it like the scheduler
+    /// does by default.
+    Reset,
 }
 
 /// Enum representing the actions the scheduler can request in each call to
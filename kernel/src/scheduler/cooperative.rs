@@ -18,17 +18,17 @@
 use crate::collections::list::{List, ListLink, ListNode};
 use crate::kernel::StoppedExecutingReason;
 use crate::platform::chip::Chip;
-use crate::process::Process;
+use crate::process::ProcessSlot;
 use crate::scheduler::{Scheduler, SchedulingDecision};
 
 /// A node in the linked list the scheduler uses to track processes
 pub struct CoopProcessNode<'a> {
-    proc: &'static Option<&'static dyn Process>,
+    proc: &'static ProcessSlot,
     next: ListLink<'a, CoopProcessNode<'a>>,
 }
 
 impl<'a> CoopProcessNode<'a> {
-    pub fn new(proc: &'static Option<&'static dyn Process>) -> CoopProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> CoopProcessNode<'a> {
         CoopProcessNode {
             proc,
             next: ListLink::empty(),
@@ -73,7 +73,7 @@ impl<'a, C: Chip> Scheduler<C> for CooperativeSched<'a> {
                     }
                 }
             }
-            match node.proc {
+            match node.proc.get() {
                 Some(proc) => {
                     if proc.ready() {
                         next = Some(proc.processid());
@@ -0,0 +1,289 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Duty Cycling Scheduler for Tock
+//!
+//! This scheduler runs processes round robin, but enforces a CPU time budget
+//! per process: every process may run for at most `budget_us` microseconds in
+//! every replenishment period of `period_us` microseconds. Once a process used
+//! up its budget it is throttled, i.e. not run again until its next period
+//! starts, even if it is ready. As CPU time is the main driver of energy use,
+//! this bounds the share of the battery a misbehaving process can drain,
+//! regardless of what the other processes do.
+//!
+//! All processes start with the budget passed to `DutyCycleSched::new()`.
+//! Budgets of single processes can be changed at runtime with
+//! `SchedulingHint::Budget`, and restored with `SchedulingHint::Reset`.
+//! Budgets of 500 microseconds or less, the shortest time the kernel starts a
+//! process for, are rejected.
+//!
+//! When every ready process is throttled, the scheduler sets an alarm for the
+//! start of the next period and lets the chip sleep.
+
+use core::cell::Cell;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::kernel::{StoppedExecutingReason, MIN_QUANTA_THRESHOLD_US};
+use crate::platform::chip::Chip;
+use crate::process::{ProcessId, ProcessSlot};
+use crate::scheduler::{Scheduler, SchedulingDecision, SchedulingHint};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Budget of a process, with the state of its current period.
+#[derive(Clone, Copy)]
+struct Budget {
+    processid: ProcessId,
+    period_us: u32,
+    budget_us: u32,
+    /// End of the current period, in ticks of the scheduler's alarm.
+    period_end: u32,
+    /// CPU time used in the current period.
+    used_us: u32,
+}
+
+/// Nodes store per-process state
+pub struct DutyCycleProcessNode<'a> {
+    proc: &'static ProcessSlot,
+    budget: OptionalCell<Budget>,
+    /// Budget set with a hint, used instead of the default one.
+    custom: OptionalCell<(ProcessId, u32, u32)>,
+    /// Number of periods in which the process used up its budget.
+    throttle_count: Cell<usize>,
+    next: ListLink<'a, DutyCycleProcessNode<'a>>,
+}
+
+impl<'a> DutyCycleProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> DutyCycleProcessNode<'a> {
+        DutyCycleProcessNode {
+            proc,
+            budget: OptionalCell::empty(),
+            custom: OptionalCell::empty(),
+            throttle_count: Cell::new(0),
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, DutyCycleProcessNode<'a>> for DutyCycleProcessNode<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, DutyCycleProcessNode<'a>> {
+        &self.next
+    }
+}
+
+pub struct DutyCycleSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    pub processes: List<'a, DutyCycleProcessNode<'a>>,
+    default_period_us: u32,
+    default_budget_us: u32,
+    /// Node of the process that is running.
+    running: OptionalCell<&'a DutyCycleProcessNode<'a>>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> DutyCycleSched<'a, A> {
+    /// How long a process can run before being preempted, if it has that much
+    /// budget left.
+    const TIMESLICE_US: u32 = 10000;
+    /// A process with less budget left is throttled, as the kernel does not
+    /// start a process with `MIN_QUANTA_THRESHOLD_US` or less left of its
+    /// timeslice.
+    const MIN_TIMESLICE_US: u32 = MIN_QUANTA_THRESHOLD_US + 1;
+    /// Longest replenishment period.
+    pub const MAX_PERIOD_US: u32 = 10_000_000;
+
+    /// Create a scheduler that gives every process `budget_us` microseconds
+    /// of CPU time per `period_us` microseconds. Shorter budgets than the
+    /// kernel can run a process for are extended to the shortest timeslice.
+    pub fn new(alarm: &'static A, period_us: u32, budget_us: u32) -> Self {
+        let period_us = period_us.clamp(Self::MIN_TIMESLICE_US, Self::MAX_PERIOD_US);
+        Self {
+            alarm,
+            processes: List::new(),
+            default_period_us: period_us,
+            default_budget_us: budget_us.clamp(Self::MIN_TIMESLICE_US, period_us),
+            running: OptionalCell::empty(),
+        }
+    }
+
+    /// Returns the number of periods in which process `processid` used up its
+    /// budget and was throttled.
+    pub fn throttle_count(&self, processid: ProcessId) -> usize {
+        self.find_node(processid)
+            .map_or(0, |node| node.throttle_count.get())
+    }
+
+    fn find_node(&self, processid: ProcessId) -> Option<&'a DutyCycleProcessNode<'a>> {
+        self.processes.iter().find(|node| {
+            node.proc
+                .get()
+                .map_or(false, |proc| proc.processid() == processid)
+        })
+    }
+
+    /// Returns the budget of the process of `node`, after starting a new
+    /// period if the current one ended. State of processes that restarted or
+    /// were removed is dropped.
+    fn update(&self, node: &DutyCycleProcessNode<'a>, now: A::Ticks) -> Option<Budget> {
+        let proc = match node.proc.get() {
+            Some(proc) => proc,
+            None => {
+                node.budget.clear();
+                return None;
+            }
+        };
+        let processid = proc.processid();
+        if node
+            .custom
+            .extract()
+            .map_or(false, |(custom, _, _)| custom != processid)
+        {
+            node.custom.clear();
+        }
+
+        let budget = match node.budget.extract() {
+            Some(budget) if budget.processid == processid => {
+                let period_end = A::Ticks::from(budget.period_end);
+                let period_start =
+                    period_end.wrapping_sub(self.alarm.ticks_from_us(budget.period_us));
+                if now.within_range(period_start, period_end) {
+                    return Some(budget);
+                }
+                budget
+            }
+            _ => {
+                node.throttle_count.set(0);
+                Budget {
+                    processid,
+                    period_us: 0,
+                    budget_us: 0,
+                    period_end: 0,
+                    used_us: 0,
+                }
+            }
+        };
+
+        // Start a new period
+        let (period_us, budget_us) = node.custom.extract().map_or(
+            (self.default_period_us, self.default_budget_us),
+            |(_, period_us, budget_us)| (period_us, budget_us),
+        );
+        let budget = Budget {
+            period_us,
+            budget_us,
+            period_end: now
+                .wrapping_add(self.alarm.ticks_from_us(period_us))
+                .into_u32(),
+            used_us: 0,
+            ..budget
+        };
+        node.budget.set(budget);
+        Some(budget)
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> time::AlarmClient for DutyCycleSched<'a, A> {
+    fn alarm(&self) {
+        // Only used to wake up the kernel when a period starts.
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for DutyCycleSched<'a, A> {
+    fn next(&self) -> SchedulingDecision {
+        let now = self.alarm.now();
+
+        // Find the next ready process that has budget left, and move the ones
+        // before it to the back of the queue. Also remember when the period of
+        // a throttled process ends.
+        let mut next = None;
+        let mut next_period_us: Option<u32> = None;
+        for _ in 0..self.processes.iter().count() {
+            let node = match self.processes.head() {
+                Some(node) => node,
+                None => break,
+            };
+            if let Some(budget) = self.update(node, now) {
+                if node.proc.get().map_or(false, |proc| proc.ready()) {
+                    let left_us = budget.budget_us.saturating_sub(budget.used_us);
+                    if left_us >= Self::MIN_TIMESLICE_US {
+                        next = Some((node, left_us.min(Self::TIMESLICE_US)));
+                        break;
+                    }
+                    let until_us = self
+                        .alarm
+                        .ticks_to_us(A::Ticks::from(budget.period_end).wrapping_sub(now));
+                    next_period_us = Some(next_period_us.map_or(until_us, |us| us.min(until_us)));
+                }
+            }
+            self.processes.push_tail(self.processes.pop_head().unwrap());
+        }
+
+        match next {
+            Some((node, timeslice)) => {
+                self.running.set(node);
+                let processid = node.proc.get().unwrap().processid();
+                SchedulingDecision::RunProcess((processid, Some(timeslice)))
+            }
+            None => {
+                // Wake up when the period of a throttled process ends.
+                if let Some(us) = next_period_us {
+                    self.alarm.set_alarm(
+                        now,
+                        self.alarm.ticks_from_us(us.max(Self::MIN_TIMESLICE_US)),
+                    );
+                }
+                self.running.clear();
+                SchedulingDecision::TrySleep
+            }
+        }
+    }
+
+    fn result(&self, result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let execution_time_us = execution_time_us.unwrap(); // should never fail as we never run cooperatively
+        self.running.take().map(|node| {
+            node.budget.take().map(|mut budget| {
+                let was_throttled =
+                    budget.used_us.saturating_add(Self::MIN_TIMESLICE_US) > budget.budget_us;
+                budget.used_us = budget.used_us.saturating_add(execution_time_us);
+                if !was_throttled
+                    && budget.used_us.saturating_add(Self::MIN_TIMESLICE_US) > budget.budget_us
+                {
+                    node.throttle_count.set(node.throttle_count.get() + 1);
+                }
+                node.budget.set(budget);
+            });
+        });
+        // Keep running the same process after an interrupt, as long as it
+        // has budget left.
+        if result != StoppedExecutingReason::KernelPreemption {
+            self.processes.push_tail(self.processes.pop_head().unwrap());
+        }
+    }
+
+    fn set_hint(&self, id: ProcessId, hint: SchedulingHint) -> Result<(), ErrorCode> {
+        let node = self.find_node(id).ok_or(ErrorCode::INVAL)?;
+        match hint {
+            SchedulingHint::Budget {
+                period_us,
+                budget_us,
+            } => {
+                // A process with less budget than the shortest timeslice
+                // would never run.
+                if budget_us < Self::MIN_TIMESLICE_US
+                    || budget_us > period_us
+                    || period_us > Self::MAX_PERIOD_US
+                {
+                    return Err(ErrorCode::INVAL);
+                }
+                node.custom.set((id, period_us, budget_us));
+            }
+            SchedulingHint::Reset => node.custom.clear(),
+            SchedulingHint::Priority(_) | SchedulingHint::Timeslice(_) => {
+                return Err(ErrorCode::NOSUPPORT)
+            }
+        }
+        // The new budget applies from the next period on.
+        Ok(())
+    }
+}
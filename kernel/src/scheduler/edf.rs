@@ -0,0 +1,409 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Earliest Deadline First Scheduler for Tock
+//!
+//! Processes with soft real-time requirements, such as control loops, reserve
+//! a budget of CPU time per period (for example through the `deadline`
+//! syscall driver). Each period ends with a deadline. Among the processes
+//! with a reservation that are ready and have budget left in their current
+//! period, the scheduler runs the one with the earliest deadline. A process
+//! that used up its budget is not run again until its next period starts, so
+//! it cannot delay the other reservations.
+//!
+//! Processes without a reservation are run round robin whenever no process
+//! with a reservation is ready, and are preempted as soon as one becomes
+//! ready.
+//!
+//! Reservations are subject to admission control: the sum of `budget/period`
+//! over all reservations may not exceed `MAX_UTILIZATION_PPM`, which leaves
+//! time for the kernel and for the processes without a reservation. With this
+//! bound EDF meets every deadline, as long as processes stay within their
+//! budget and the kernel does not delay them for too long. The board also
+//! chooses the share of the CPU a single process can reserve, so that one
+//! process cannot take the reservable time from all others.
+//!
+//! If a process still has work to do when its period ends, it missed its
+//! deadline and the scheduler tells its `DeadlineMissClient`.
+//!
+//! Besides through `DeadlineScheduler`, reservations can be made with
+//! `SchedulingHint::Budget` and removed with `SchedulingHint::Reset`.
+
+use core::cell::Cell;
+
+use crate::collections::list::{List, ListLink, ListNode};
+use crate::deferred_call::DeferredCall;
+use crate::hil::time::{self, ConvertTicks, Ticks};
+use crate::kernel::{StoppedExecutingReason, MIN_QUANTA_THRESHOLD_US};
+use crate::platform::chip::Chip;
+use crate::process::{ProcessId, ProcessSlot};
+use crate::scheduler::{Scheduler, SchedulingDecision, SchedulingHint};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
+
+/// Told about processes that missed their deadline.
+pub trait DeadlineMissClient {
+    /// `processid` still had work to do when its period ended.
+    fn deadline_missed(&self, processid: ProcessId);
+}
+
+/// Interface for reserving CPU time with a deadline scheduler.
+pub trait DeadlineScheduler<'a> {
+    fn set_client(&self, client: &'a dyn DeadlineMissClient);
+
+    /// Reserve `budget_us` microseconds of CPU time every `period_us`
+    /// microseconds for `processid`, replacing its current reservation. The
+    /// first period starts now.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The reservation was admitted.
+    /// - `INVAL`: `budget_us` is not longer than the kernel's shortest quanta
+    ///   (`MIN_QUANTA_THRESHOLD_US`) or larger than `period_us`, `period_us` is
+    ///   too long, or the process does not exist.
+    /// - `NOMEM`: Admitting the reservation would exceed the utilization bound,
+    ///   or the share of the CPU a single process can reserve.
+    fn reserve(
+        &self,
+        processid: ProcessId,
+        period_us: u32,
+        budget_us: u32,
+    ) -> Result<(), ErrorCode>;
+
+    /// Remove the reservation of `processid`.
+    ///
+    /// Return values:
+    ///
+    /// - `Ok(())`: The reservation was removed.
+    /// - `OFF`: The process has no reservation.
+    fn release(&self, processid: ProcessId) -> Result<(), ErrorCode>;
+}
+
+/// Reservation of a process, with the state of its current period. Times are
+/// in ticks of the scheduler's alarm.
+#[derive(Clone, Copy)]
+struct Reservation {
+    processid: ProcessId,
+    period_us: u32,
+    budget_us: u32,
+    /// Start of the current period.
+    release: u32,
+    /// End of the current period.
+    deadline: u32,
+    /// CPU time used in the current period.
+    used_us: u32,
+}
+
+impl Reservation {
+    fn utilization_ppm(&self) -> u32 {
+        (self.budget_us as u64 * 1_000_000 / self.period_us as u64) as u32
+    }
+
+    fn budget_left_us(&self) -> u32 {
+        self.budget_us.saturating_sub(self.used_us)
+    }
+}
+
+/// Nodes store per-process state
+pub struct EdfProcessNode<'a> {
+    proc: &'static ProcessSlot,
+    reservation: OptionalCell<Reservation>,
+    next: ListLink<'a, EdfProcessNode<'a>>,
+}
+
+impl<'a> EdfProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> EdfProcessNode<'a> {
+        EdfProcessNode {
+            proc,
+            reservation: OptionalCell::empty(),
+            next: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a> ListNode<'a, EdfProcessNode<'a>> for EdfProcessNode<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, EdfProcessNode<'a>> {
+        &self.next
+    }
+}
+
+pub struct EdfSched<'a, A: 'static + time::Alarm<'static>> {
+    alarm: &'static A,
+    pub processes: List<'a, EdfProcessNode<'a>>,
+    /// Node of the process that is running.
+    running: OptionalCell<&'a EdfProcessNode<'a>>,
+    /// Index of the process without a reservation that ran last.
+    last_background: Cell<usize>,
+    /// Share of the CPU in parts per million one process can reserve.
+    max_process_utilization_ppm: u32,
+    client: OptionalCell<&'a dyn DeadlineMissClient>,
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> EdfSched<'a, A> {
+    /// Share of the CPU in parts per million that can be reserved.
+    pub const MAX_UTILIZATION_PPM: u32 = 900_000;
+    /// Longest period of a reservation.
+    pub const MAX_PERIOD_US: u32 = 10_000_000;
+    /// How long a process without a reservation can run before being
+    /// preempted.
+    const BACKGROUND_TIMESLICE_US: u32 = 10000;
+    /// Shortest timeslice given to a process. The kernel does not start a
+    /// process with `MIN_QUANTA_THRESHOLD_US` or less left of its timeslice,
+    /// so a reservation with less budget left than this is used up.
+    const MIN_TIMESLICE_US: u32 = MIN_QUANTA_THRESHOLD_US + 1;
+
+    /// Creates a scheduler where each process can reserve at most
+    /// `max_process_utilization_ppm` of the CPU, in parts per million. With
+    /// `MAX_UTILIZATION_PPM` a single process can reserve all of the time that
+    /// can be reserved.
+    pub fn new(alarm: &'static A, max_process_utilization_ppm: u32) -> Self {
+        Self {
+            alarm,
+            processes: List::new(),
+            running: OptionalCell::empty(),
+            last_background: Cell::new(0),
+            max_process_utilization_ppm,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Returns the share of the CPU in parts per million that is reserved.
+    pub fn utilization_ppm(&self) -> u32 {
+        self.processes
+            .iter()
+            .filter_map(|node| node.reservation.extract())
+            .map(|reservation| reservation.utilization_ppm())
+            .sum()
+    }
+
+    fn find_node(&self, processid: ProcessId) -> Option<&'a EdfProcessNode<'a>> {
+        self.processes.iter().find(|node| {
+            node.proc
+                .get()
+                .map_or(false, |proc| proc.processid() == processid)
+        })
+    }
+
+    /// Returns the reservation of the process of `node`, after starting a new
+    /// period if the current one ended. Reservations of processes that
+    /// restarted or were removed are dropped.
+    fn update(&self, node: &EdfProcessNode<'a>, now: A::Ticks) -> Option<Reservation> {
+        let proc = node.proc.get()?;
+        let mut reservation = node.reservation.extract()?;
+        if reservation.processid != proc.processid() {
+            node.reservation.clear();
+            return None;
+        }
+
+        let release = A::Ticks::from(reservation.release);
+        let deadline = A::Ticks::from(reservation.deadline);
+        if !now.within_range(release, deadline) {
+            if proc.ready() {
+                self.client
+                    .map(|client| client.deadline_missed(reservation.processid));
+            }
+            // Start the period that contains `now`, or a new one if more than
+            // one period passed.
+            let period = self.alarm.ticks_from_us(reservation.period_us);
+            let next_deadline = deadline.wrapping_add(period);
+            let (release, deadline) = if now.within_range(deadline, next_deadline) {
+                (deadline, next_deadline)
+            } else {
+                (now, now.wrapping_add(period))
+            };
+            reservation.release = release.into_u32();
+            reservation.deadline = deadline.into_u32();
+            reservation.used_us = 0;
+            node.reservation.set(reservation);
+        }
+        Some(reservation)
+    }
+
+    /// Returns whether `node` has a reservation and can run now.
+    fn runnable_reserved(&self, node: &EdfProcessNode<'a>) -> bool {
+        node.reservation.map_or(false, |reservation| {
+            reservation.budget_left_us() >= Self::MIN_TIMESLICE_US
+                && node.proc.get().map_or(false, |proc| proc.ready())
+        })
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> DeadlineScheduler<'a> for EdfSched<'a, A> {
+    fn set_client(&self, client: &'a dyn DeadlineMissClient) {
+        self.client.set(client);
+    }
+
+    fn reserve(
+        &self,
+        processid: ProcessId,
+        period_us: u32,
+        budget_us: u32,
+    ) -> Result<(), ErrorCode> {
+        if budget_us < Self::MIN_TIMESLICE_US
+            || budget_us > period_us
+            || period_us > Self::MAX_PERIOD_US
+        {
+            return Err(ErrorCode::INVAL);
+        }
+        let node = self.find_node(processid).ok_or(ErrorCode::INVAL)?;
+
+        let now = self.alarm.now();
+        let reservation = Reservation {
+            processid,
+            period_us,
+            budget_us,
+            release: now.into_u32(),
+            deadline: now
+                .wrapping_add(self.alarm.ticks_from_us(period_us))
+                .into_u32(),
+            used_us: 0,
+        };
+        if reservation.utilization_ppm() > self.max_process_utilization_ppm {
+            return Err(ErrorCode::NOMEM);
+        }
+        let current_ppm = node
+            .reservation
+            .map_or(0, |reservation| reservation.utilization_ppm());
+        let utilization_ppm = self.utilization_ppm() - current_ppm + reservation.utilization_ppm();
+        if utilization_ppm > Self::MAX_UTILIZATION_PPM {
+            return Err(ErrorCode::NOMEM);
+        }
+        node.reservation.set(reservation);
+        Ok(())
+    }
+
+    fn release(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.find_node(processid)
+            .and_then(|node| node.reservation.take())
+            .map(|_| ())
+            .ok_or(ErrorCode::OFF)
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>> time::AlarmClient for EdfSched<'a, A> {
+    fn alarm(&self) {
+        // Only used to wake up the kernel when a period starts.
+    }
+}
+
+impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for EdfSched<'a, A> {
+    fn next(&self) -> SchedulingDecision {
+        let now = self.alarm.now();
+
+        // Run the process with the earliest deadline that can run. Also
+        // remember when the next period of a process that is waiting for its
+        // budget starts.
+        let mut earliest: Option<(&'a EdfProcessNode<'a>, Reservation)> = None;
+        let mut next_release: Option<u32> = None;
+        for node in self.processes.iter() {
+            let reservation = match self.update(node, now) {
+                Some(reservation) => reservation,
+                None => continue,
+            };
+            let until_deadline = A::Ticks::from(reservation.deadline).wrapping_sub(now);
+            if self.runnable_reserved(node) {
+                let earlier = earliest.map_or(true, |(_, other)| {
+                    until_deadline < A::Ticks::from(other.deadline).wrapping_sub(now)
+                });
+                if earlier {
+                    earliest = Some((node, reservation));
+                }
+            } else if node.proc.get().map_or(false, |proc| proc.ready()) {
+                let until_us = self.alarm.ticks_to_us(until_deadline);
+                next_release = Some(next_release.map_or(until_us, |us| us.min(until_us)));
+            }
+        }
+
+        if let Some((node, reservation)) = earliest {
+            let until_deadline_us = self
+                .alarm
+                .ticks_to_us(A::Ticks::from(reservation.deadline).wrapping_sub(now));
+            let timeslice = reservation
+                .budget_left_us()
+                .min(until_deadline_us)
+                .max(Self::MIN_TIMESLICE_US);
+            self.running.set(node);
+            let processid = node.proc.get().unwrap().processid();
+            return SchedulingDecision::RunProcess((processid, Some(timeslice)));
+        }
+
+        // Run the next process without a reservation that is ready, after the
+        // one that ran last, or else the first one.
+        let last = self.last_background.get();
+        let mut first = None;
+        let mut after_last = None;
+        for (index, node) in self.processes.iter().enumerate() {
+            let ready =
+                node.reservation.is_none() && node.proc.get().map_or(false, |proc| proc.ready());
+            if !ready {
+                continue;
+            }
+            if index > last {
+                after_last = Some((index, node));
+                break;
+            }
+            if first.is_none() {
+                first = Some((index, node));
+            }
+        }
+        let background = after_last.or(first);
+        if let Some((index, node)) = background {
+            self.last_background.set(index);
+            self.running.set(node);
+            let timeslice = next_release.map_or(Self::BACKGROUND_TIMESLICE_US, |us| {
+                us.clamp(Self::MIN_TIMESLICE_US, Self::BACKGROUND_TIMESLICE_US)
+            });
+            let processid = node.proc.get().unwrap().processid();
+            return SchedulingDecision::RunProcess((processid, Some(timeslice)));
+        }
+
+        // Wake up when the next period of a waiting process starts.
+        if let Some(us) = next_release {
+            self.alarm.set_alarm(
+                now,
+                self.alarm.ticks_from_us(us.max(Self::MIN_TIMESLICE_US)),
+            );
+        }
+        self.running.clear();
+        SchedulingDecision::TrySleep
+    }
+
+    fn result(&self, _result: StoppedExecutingReason, execution_time_us: Option<u32>) {
+        let execution_time_us = execution_time_us.unwrap(); // should never fail as we never run cooperatively
+        self.running.take().map(|node| {
+            node.reservation.take().map(|mut reservation| {
+                reservation.used_us = reservation.used_us.saturating_add(execution_time_us);
+                node.reservation.set(reservation);
+            });
+        });
+    }
+
+    unsafe fn continue_process(&self, _: ProcessId, chip: &C) -> bool {
+        // Also stop a process without a reservation once a process with a
+        // reservation becomes ready, e.g. because of IPC.
+        let preempt_background = self.running.map_or(false, |running| {
+            running.reservation.is_none()
+                && self
+                    .processes
+                    .iter()
+                    .any(|node| self.runnable_reserved(node))
+        });
+        !(chip.has_pending_interrupts() || DeferredCall::has_tasks() || preempt_background)
+    }
+
+    fn set_hint(&self, id: ProcessId, hint: SchedulingHint) -> Result<(), ErrorCode> {
+        match hint {
+            SchedulingHint::Budget {
+                period_us,
+                budget_us,
+            } => self.reserve(id, period_us, budget_us),
+            SchedulingHint::Reset => {
+                let node = self.find_node(id).ok_or(ErrorCode::INVAL)?;
+                node.reservation.clear();
+                Ok(())
+            }
+            SchedulingHint::Priority(_) | SchedulingHint::Timeslice(_) => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+}
@@ -20,6 +20,11 @@
 //!           reduced (i.e., it moves down one queue).
 //! - Rule 5: After some time period S, move all the jobs in the system to the
 //!           topmost queue.
+//!
+//! A process can be pinned to a queue at runtime with
+//! `SchedulingHint::Priority`. Rules 3 to 5 do not apply to pinned processes,
+//! they stay in their queue until they are unpinned with
+//! `SchedulingHint::Reset`.
 
 use core::cell::Cell;
 
@@ -27,25 +32,29 @@ use crate::collections::list::{List, ListLink, ListNode};
 use crate::hil::time::{self, ConvertTicks, Ticks};
 use crate::kernel::StoppedExecutingReason;
 use crate::platform::chip::Chip;
-use crate::process::Process;
 use crate::process::ProcessId;
-use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::process::ProcessSlot;
+use crate::scheduler::{Scheduler, SchedulingDecision, SchedulingHint};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
 
 #[derive(Default)]
 struct MfProcState {
     /// Total CPU time used by this process while in current queue
     us_used_this_queue: Cell<u32>,
+    /// Queue the process is pinned to, and the process it was pinned for
+    pinned: OptionalCell<(ProcessId, usize)>,
 }
 
 /// Nodes store per-process state
 pub struct MLFQProcessNode<'a> {
-    proc: &'static Option<&'static dyn Process>,
+    proc: &'static ProcessSlot,
     state: MfProcState,
     next: ListLink<'a, MLFQProcessNode<'a>>,
 }
 
 impl<'a> MLFQProcessNode<'a> {
-    pub fn new(proc: &'static Option<&'static dyn Process>) -> MLFQProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> MLFQProcessNode<'a> {
         MLFQProcessNode {
             proc,
             state: MfProcState::default(),
@@ -67,6 +76,8 @@ pub struct MLFQSched<'a, A: 'static + time::Alarm<'static>> {
     last_reset_check: Cell<A::Ticks>,
     last_timeslice: Cell<u32>,
     last_queue_idx: Cell<usize>,
+    /// Whether a process was pinned to a queue it is not in yet
+    pins_changed: Cell<bool>,
 }
 
 impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
@@ -82,6 +93,37 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
             last_reset_check: Cell::new(A::Ticks::from(0)),
             last_timeslice: Cell::new(0),
             last_queue_idx: Cell::new(0),
+            pins_changed: Cell::new(false),
+        }
+    }
+
+    /// Returns the queue the process of `node` is pinned to, if any. A pin
+    /// for a process that was since restarted or replaced is dropped.
+    fn pinned_queue(&self, node: &MLFQProcessNode<'a>) -> Option<usize> {
+        let (processid, queue_idx) = node.state.pinned.extract()?;
+        if node
+            .proc
+            .get()
+            .map_or(false, |proc| proc.processid() == processid)
+        {
+            Some(queue_idx)
+        } else {
+            node.state.pinned.clear();
+            None
+        }
+    }
+
+    /// Move pinned processes to the queue they are pinned to
+    fn apply_pins(&self) {
+        for (idx, queue) in self.processes.iter().enumerate() {
+            for _ in 0..queue.iter().count() {
+                let node = queue.pop_head().unwrap();
+                let target = self.pinned_queue(node).unwrap_or(idx);
+                if target != idx {
+                    node.state.us_used_this_queue.set(0);
+                }
+                self.processes[target].push_tail(node);
+            }
         }
     }
 
@@ -97,7 +139,10 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
     fn redeem_all_procs(&self) {
         for queue in self.processes.iter().skip(1) {
             match queue.pop_head() {
-                Some(proc) => self.processes[0].push_tail(proc),
+                Some(proc) => {
+                    let target = self.pinned_queue(proc).unwrap_or(0);
+                    self.processes[target].push_tail(proc)
+                }
                 None => continue,
             }
         }
@@ -110,7 +155,7 @@ impl<'a, A: 'static + time::Alarm<'static>> MLFQSched<'a, A> {
         for (idx, queue) in self.processes.iter().enumerate() {
             let next = queue
                 .iter()
-                .find(|node_ref| node_ref.proc.map_or(false, |proc| proc.ready()));
+                .find(|node_ref| node_ref.proc.get().map_or(false, |proc| proc.ready()));
             if next.is_some() {
                 // pop procs to back until we get to match
                 loop {
@@ -149,13 +194,16 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
             self.redeem_all_procs();
         }
         self.last_reset_check.set(now);
+        if self.pins_changed.take() {
+            self.apply_pins();
+        }
         let (node_ref_opt, queue_idx) = self.get_next_ready_process_node();
         if node_ref_opt.is_none() {
             return SchedulingDecision::TrySleep;
         }
         let node_ref = node_ref_opt.unwrap();
         let timeslice = self.get_timeslice_us(queue_idx) - node_ref.state.us_used_this_queue.get();
-        let next = node_ref.proc.unwrap().processid();
+        let next = node_ref.proc.get().unwrap().processid();
         self.last_queue_idx.set(queue_idx);
         self.last_timeslice.set(timeslice);
 
@@ -175,7 +223,9 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
         let punish = result == StoppedExecutingReason::TimesliceExpired;
         if punish {
             node_ref.state.us_used_this_queue.set(0);
-            let next_queue = if queue_idx == Self::NUM_QUEUES - 1 {
+            let next_queue = if let Some(pinned) = self.pinned_queue(node_ref) {
+                pinned
+            } else if queue_idx == Self::NUM_QUEUES - 1 {
                 queue_idx
             } else {
                 queue_idx + 1
@@ -190,4 +240,34 @@ impl<'a, A: 'static + time::Alarm<'static>, C: Chip> Scheduler<C> for MLFQSched<
         // This MLFQ scheduler only preempts processes if there is a timeslice expiration
         true
     }
+
+    fn set_hint(&self, id: ProcessId, hint: SchedulingHint) -> Result<(), ErrorCode> {
+        let node = self
+            .processes
+            .iter()
+            .flat_map(|queue| queue.iter())
+            .find(|node| node.proc.get().map_or(false, |proc| proc.processid() == id))
+            .ok_or(ErrorCode::INVAL)?;
+        match hint {
+            SchedulingHint::Priority(priority) => {
+                let queue_idx = priority as usize;
+                if queue_idx >= Self::NUM_QUEUES {
+                    return Err(ErrorCode::INVAL);
+                }
+                node.state.pinned.set((id, queue_idx));
+                // The node is moved before the next scheduling decision, as
+                // `result()` expects the last process to stay at the head of
+                // its queue.
+                self.pins_changed.set(true);
+                Ok(())
+            }
+            SchedulingHint::Reset => {
+                node.state.pinned.clear();
+                Ok(())
+            }
+            SchedulingHint::Timeslice(_) | SchedulingHint::Budget { .. } => {
+                Err(ErrorCode::NOSUPPORT)
+            }
+        }
+    }
 }
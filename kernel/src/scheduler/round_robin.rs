@@ -17,26 +17,36 @@
 //! userspace processes are interrupted the scheduler timer is paused, and the
 //! same process is resumed with the same scheduler timer value from when it was
 //! interrupted.
+//!
+//! The timeslice of a process can be changed at runtime with
+//! `SchedulingHint::Timeslice`. Timeslices shorter than the minimum quantum of
+//! the kernel, 500 microseconds, are rejected.
 
 use core::cell::Cell;
 
 use crate::collections::list::{List, ListLink, ListNode};
-use crate::kernel::StoppedExecutingReason;
+use crate::kernel::{StoppedExecutingReason, MIN_QUANTA_THRESHOLD_US};
 use crate::platform::chip::Chip;
-use crate::process::Process;
-use crate::scheduler::{Scheduler, SchedulingDecision};
+use crate::process::{ProcessId, ProcessSlot};
+use crate::scheduler::{Scheduler, SchedulingDecision, SchedulingHint};
+use crate::utilities::cells::OptionalCell;
+use crate::ErrorCode;
 
 /// A node in the linked list the scheduler uses to track processes
 /// Each node holds a pointer to a slot in the processes array
 pub struct RoundRobinProcessNode<'a> {
-    proc: &'static Option<&'static dyn Process>,
+    proc: &'static ProcessSlot,
+    /// Timeslice used instead of the default one, and the process it was set
+    /// for.
+    timeslice_us: OptionalCell<(ProcessId, u32)>,
     next: ListLink<'a, RoundRobinProcessNode<'a>>,
 }
 
 impl<'a> RoundRobinProcessNode<'a> {
-    pub fn new(proc: &'static Option<&'static dyn Process>) -> RoundRobinProcessNode<'a> {
+    pub fn new(proc: &'static ProcessSlot) -> RoundRobinProcessNode<'a> {
         RoundRobinProcessNode {
             proc,
+            timeslice_us: OptionalCell::empty(),
             next: ListLink::empty(),
         }
     }
@@ -65,12 +75,28 @@ impl<'a> RoundRobinSched<'a> {
             last_rescheduled: Cell::new(false),
         }
     }
+
+    /// Returns the timeslice of the process of `node`. A timeslice set for a
+    /// process that was since restarted or replaced is dropped.
+    fn timeslice_us(&self, node: &RoundRobinProcessNode<'a>) -> u32 {
+        match (node.timeslice_us.extract(), node.proc.get()) {
+            (Some((processid, timeslice)), Some(proc)) if proc.processid() == processid => {
+                timeslice
+            }
+            (Some(_), _) => {
+                node.timeslice_us.clear();
+                Self::DEFAULT_TIMESLICE_US
+            }
+            (None, _) => Self::DEFAULT_TIMESLICE_US,
+        }
+    }
 }
 
 impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
     fn next(&self) -> SchedulingDecision {
         let mut first_head = None;
         let mut next = None;
+        let mut next_timeslice = Self::DEFAULT_TIMESLICE_US;
 
         // Find next ready process. Place any *empty* process slots, or not-ready
         // processes, at the back of the queue.
@@ -85,10 +111,11 @@ impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
                     }
                 }
             }
-            match node.proc {
+            match node.proc.get() {
                 Some(proc) => {
                     if proc.ready() {
                         next = Some(proc.processid());
+                        next_timeslice = self.timeslice_us(node);
                         break;
                     }
                     self.processes.push_tail(self.processes.pop_head().unwrap());
@@ -102,8 +129,8 @@ impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
             self.time_remaining.get()
         } else {
             // grant a fresh timeslice
-            self.time_remaining.set(Self::DEFAULT_TIMESLICE_US);
-            Self::DEFAULT_TIMESLICE_US
+            self.time_remaining.set(next_timeslice);
+            next_timeslice
         };
         assert!(timeslice != 0);
 
@@ -131,4 +158,28 @@ impl<'a, C: Chip> Scheduler<C> for RoundRobinSched<'a> {
             self.processes.push_tail(self.processes.pop_head().unwrap());
         }
     }
+
+    fn set_hint(&self, id: ProcessId, hint: SchedulingHint) -> Result<(), ErrorCode> {
+        let node = self
+            .processes
+            .iter()
+            .find(|node| node.proc.get().map_or(false, |proc| proc.processid() == id))
+            .ok_or(ErrorCode::INVAL)?;
+        match hint {
+            SchedulingHint::Timeslice(timeslice) if timeslice < MIN_QUANTA_THRESHOLD_US => {
+                Err(ErrorCode::INVAL)
+            }
+            SchedulingHint::Timeslice(timeslice) => {
+                node.timeslice_us.set((id, timeslice));
+                Ok(())
+            }
+            SchedulingHint::Reset => {
+                node.timeslice_us.clear();
+                Ok(())
+            }
+            SchedulingHint::Priority(_) | SchedulingHint::Budget { .. } => {
+                Err(ErrorCode::NOSUPPORT)
+            }
+        }
+    }
 }
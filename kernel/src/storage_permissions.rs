@@ -6,6 +6,8 @@
 
 use core::cmp;
 
+use crate::capabilities;
+
 /// List of storage permissions for a storage user.
 ///
 /// These identifiers signify what permissions a storage user has. The storage
@@ -56,6 +58,18 @@ impl StoragePermissions {
         }
     }
 
+    /// Create permissions for a kernel storage user. The user creates new
+    /// objects with `storage_id` and can read and update exactly the objects
+    /// created with that ID.
+    pub fn new_kernel_permissions(
+        storage_id: u32,
+        _cap: &dyn capabilities::KerneluserStorageCapability,
+    ) -> Self {
+        let mut ids = [0; 8];
+        ids[0] = storage_id;
+        StoragePermissions::new(1, ids, 1, ids, Some(storage_id))
+    }
+
     /// Check if this permission object grants read access to the specified
     /// `storage_id`. Returns `true` if access is permitted, `false` otherwise.
     pub fn check_read_permission(&self, storage_id: u32) -> bool {
@@ -0,0 +1,223 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Latency histograms of the syscalls handled by capsules.
+//!
+//! With `SyscallLatencyHistograms` added to the observers of the kernel, it
+//! measures how long capsules take to handle each subscribe, command and
+//! allow syscall, from dispatching it to the driver until the return value is
+//! set.
+//!
+//! `SyscallLatencyHistograms` counts these latencies per driver number and
+//! syscall class in histograms with power of two buckets: bucket 0 counts
+//! latencies below 1 microsecond, bucket `i` latencies from `2^(i-1)` up to
+//! `2^i` microseconds, and the last bucket all longer latencies. Capsules
+//! like `capsules_extra::syscall_latency` read them through
+//! `LatencyHistograms`, e.g. to show them on the process console, so that
+//! the responsiveness of capsules can be compared across releases.
+//!
+//! ```rust,ignore
+//! let latency = static_init!(
+//!     kernel::syscall_latency::SyscallLatencyHistograms<'static, Rtc<'static>, 16>,
+//!     kernel::syscall_latency::SyscallLatencyHistograms::new(&base_peripherals.rtc)
+//! );
+//! board_kernel.add_process_observer(latency, &process_management_capability);
+//! ```
+
+use core::cell::Cell;
+
+use crate::collections::list::ListLink;
+use crate::hil::time::{ConvertTicks, Ticks, Time};
+use crate::process_observer::ProcessObserver;
+
+/// Number of buckets of a histogram.
+pub const BUCKETS: usize = 16;
+
+/// Classes of syscalls handled by capsules. Read-write, read-only and
+/// userspace readable allows are all `Allow`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyscallClass {
+    Subscribe = 0,
+    Command = 1,
+    Allow = 2,
+}
+
+impl SyscallClass {
+    pub const ALL: [SyscallClass; 3] = [
+        SyscallClass::Subscribe,
+        SyscallClass::Command,
+        SyscallClass::Allow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyscallClass::Subscribe => "subscribe",
+            SyscallClass::Command => "command",
+            SyscallClass::Allow => "allow",
+        }
+    }
+}
+
+/// A latency histogram.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    /// Number of syscalls per bucket, saturating at `u16::MAX`.
+    pub counts: [u16; BUCKETS],
+    /// Longest latency in microseconds.
+    pub max_us: u32,
+}
+
+impl Histogram {
+    /// The bucket of a latency of `us` microseconds.
+    pub fn bucket(us: u32) -> usize {
+        core::cmp::min((u32::BITS - us.leading_zeros()) as usize, BUCKETS - 1)
+    }
+
+    /// The shortest latency in microseconds counted in `bucket`.
+    pub fn bucket_start_us(bucket: usize) -> u32 {
+        match bucket {
+            0 => 0,
+            _ => 1 << (bucket - 1),
+        }
+    }
+
+    fn add(&mut self, us: u32) {
+        let count = &mut self.counts[Self::bucket(us)];
+        *count = count.saturating_add(1);
+        self.max_us = core::cmp::max(self.max_us, us);
+    }
+}
+
+/// Reads the recorded histograms.
+pub trait LatencyHistograms {
+    /// The driver number with histograms at `index`, `None` past the last
+    /// one. Drivers are numbered in the order of their first syscall.
+    fn driver_number(&self, index: usize) -> Option<usize>;
+
+    /// The histogram of syscalls of `class` of the driver at `index`.
+    fn histogram(&self, index: usize, class: SyscallClass) -> Option<Histogram>;
+
+    /// Number of syscalls that were not recorded, because the histograms of
+    /// all drivers are in use.
+    fn untracked(&self) -> u32;
+
+    /// Clear all histograms.
+    fn reset(&self);
+}
+
+#[derive(Default)]
+struct DriverHistograms {
+    driver_number: Cell<Option<usize>>,
+    histograms: [Cell<Histogram>; 3],
+}
+
+/// Histograms of up to `N` drivers, timed with `T`.
+pub struct SyscallLatencyHistograms<'a, T: Time, const N: usize> {
+    time: &'a T,
+    drivers: [DriverHistograms; N],
+    untracked: Cell<u32>,
+    /// When the syscall being handled was dispatched.
+    start: Cell<T::Ticks>,
+    next_observer: ListLink<'static, dyn ProcessObserver>,
+}
+
+impl<'a, T: Time, const N: usize> SyscallLatencyHistograms<'a, T, N> {
+    pub fn new(time: &'a T) -> Self {
+        SyscallLatencyHistograms {
+            time,
+            drivers: [(); N].map(|()| DriverHistograms::default()),
+            untracked: Cell::new(0),
+            start: Cell::new(T::Ticks::from(0)),
+            next_observer: ListLink::empty(),
+        }
+    }
+}
+
+impl<'a, T: Time, const N: usize> ProcessObserver for SyscallLatencyHistograms<'a, T, N> {
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        &self.next_observer
+    }
+
+    fn syscall_dispatched(&self, _driver_number: usize) {
+        self.start.set(self.time.now());
+    }
+
+    fn syscall_returned(&self, class: SyscallClass, driver_number: usize) {
+        let elapsed = self.time.now().wrapping_sub(self.start.get());
+        let us = self.time.ticks_to_us(elapsed);
+
+        // Drivers are never removed, so the first free entry follows the
+        // entries in use.
+        let driver = self.drivers.iter().find(|driver| {
+            driver
+                .driver_number
+                .get()
+                .map_or(true, |number| number == driver_number)
+        });
+        match driver {
+            Some(driver) => {
+                driver.driver_number.set(Some(driver_number));
+                let cell = &driver.histograms[class as usize];
+                let mut histogram = cell.get();
+                histogram.add(us);
+                cell.set(histogram);
+            }
+            None => self.untracked.set(self.untracked.get().saturating_add(1)),
+        }
+    }
+}
+
+impl<'a, T: Time, const N: usize> LatencyHistograms for SyscallLatencyHistograms<'a, T, N> {
+    fn driver_number(&self, index: usize) -> Option<usize> {
+        self.drivers.get(index)?.driver_number.get()
+    }
+
+    fn histogram(&self, index: usize, class: SyscallClass) -> Option<Histogram> {
+        let driver = self.drivers.get(index)?;
+        driver
+            .driver_number
+            .get()
+            .map(|_| driver.histograms[class as usize].get())
+    }
+
+    fn untracked(&self) -> u32 {
+        self.untracked.get()
+    }
+
+    fn reset(&self) {
+        for driver in self.drivers.iter() {
+            driver.driver_number.set(None);
+            for histogram in driver.histograms.iter() {
+                histogram.set(Histogram::default());
+            }
+        }
+        self.untracked.set(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        assert_eq!(Histogram::bucket(0), 0);
+        assert_eq!(Histogram::bucket(1), 1);
+        assert_eq!(Histogram::bucket(3), 2);
+        assert_eq!(Histogram::bucket(4), 3);
+        assert_eq!(Histogram::bucket(u32::MAX), BUCKETS - 1);
+        for bucket in 0..BUCKETS {
+            assert_eq!(
+                Histogram::bucket(Histogram::bucket_start_us(bucket)),
+                bucket
+            );
+        }
+
+        let mut histogram = Histogram::default();
+        histogram.add(100);
+        histogram.add(120);
+        assert_eq!(histogram.counts[7], 2);
+        assert_eq!(histogram.max_us, 120);
+    }
+}
@@ -0,0 +1,262 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Batched commands through a ring in process memory.
+//!
+//! Every command system call traps into the kernel, and every completion
+//! that is reported with an upcall costs another trip through the kernel.
+//! On small cores this limits how fast a process can e.g. toggle GPIO pins
+//! or start sensor readings. With this mechanism, a process instead writes
+//! the commands into a submission queue in its own memory and issues them
+//! all with a single command on `DRIVER_NUM`. The kernel writes the result
+//! of each command into a completion queue, and can also move the upcalls
+//! pending for the process into the completion queue, so the process reaps
+//! them without running the upcall functions.
+//!
+//! The ring is owned by the process, and the kernel only accesses it during
+//! the command, so no allow or grant is needed. Boards that support rings
+//! return a `SyscallRing` from `KernelResources::syscall_ring()`, on other
+//! boards `DRIVER_NUM` is handled like any other driver number. The submitted
+//! commands are handled just like commands issued directly: they go through
+//! the debugging and tracing hooks, the syscall filter and the driver number
+//! mapping of the board.
+//!
+//! The ring consists of little-endian `u32` words:
+//!
+//! | Word | Field                                                      |
+//! |------|------------------------------------------------------------|
+//! | 0    | Number of entries `n` of each queue, written by the process |
+//! | 1    | Submission head, written by the kernel                     |
+//! | 2    | Submission tail, written by the process                    |
+//! | 3    | Completion head, written by the process                    |
+//! | 4    | Completion tail, written by the kernel                     |
+//!
+//! followed by `n` submission entries of 5 words (driver number, command
+//! number, two arguments and a value returned with the completion), and `n`
+//! completion entries of 5 words. The head and tail indices count up and
+//! wrap around at `u32::MAX`; entry `i` of a queue is at `i % n`.
+//! `n` is at most the number of entries the board passes to
+//! `SyscallRing::new()`.
+//!
+//! A completion of a command holds the value of the submission, followed by
+//! the four registers of the system call return. A completion of an upcall
+//! holds the driver number, `UPCALL_COMPLETION` or'd with the subscribe
+//! number, and the three arguments of the upcall.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let syscall_ring = static_init!(
+//!     kernel::syscall_ring::SyscallRing,
+//!     kernel::syscall_ring::SyscallRing::new(16)
+//! );
+//!
+//! impl KernelResources<Chip> for Platform {
+//!     fn syscall_ring(&self) -> Option<&SyscallRing> {
+//!         Some(self.syscall_ring)
+//!     }
+//! }
+//! ```
+
+use crate::errorcode::ErrorCode;
+use crate::kernel::Kernel;
+use crate::platform::chip::Chip;
+use crate::platform::platform::KernelResources;
+use crate::process::{self, FunctionCallSource, Task};
+use crate::processbuffer::{ReadWriteProcessBuffer, WriteableProcessBuffer};
+use crate::syscall::{Syscall, SyscallReturn};
+
+/// Syscall number
+pub const DRIVER_NUM: usize = 0x1000D;
+
+/// Marks the completion of an upcall.
+pub const UPCALL_COMPLETION: u32 = 0x8000_0000;
+
+/// Most entries of each queue a board may allow.
+pub const MAX_ENTRIES: u32 = 64;
+
+const HEADER_WORDS: usize = 5;
+const ENTRY_WORDS: usize = 5;
+
+const ENTRIES: usize = 0;
+/// The four indices follow each other, starting with the submission head.
+const SUBMISSION_HEAD: usize = 1;
+const COMPLETION_TAIL: usize = 4;
+
+/// Reap pending upcalls into the completion queue.
+const FLAG_REAP_UPCALLS: usize = 1 << 0;
+
+fn read_words(
+    ring: &ReadWriteProcessBuffer,
+    word: usize,
+    words: &mut [u32],
+) -> Result<(), ErrorCode> {
+    ring.mut_enter(|ring| {
+        let bytes = ring
+            .get(word * 4..(word + words.len()) * 4)
+            .ok_or(ErrorCode::SIZE)?;
+        for (i, value) in words.iter_mut().enumerate() {
+            let mut buf = [0; 4];
+            bytes[i * 4..i * 4 + 4].copy_to_slice(&mut buf);
+            *value = u32::from_le_bytes(buf);
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(ErrorCode::FAIL))
+}
+
+fn write_words(ring: &ReadWriteProcessBuffer, word: usize, words: &[u32]) -> Result<(), ErrorCode> {
+    ring.mut_enter(|ring| {
+        let bytes = ring
+            .get(word * 4..(word + words.len()) * 4)
+            .ok_or(ErrorCode::SIZE)?;
+        for (i, value) in words.iter().enumerate() {
+            bytes[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(ErrorCode::FAIL))
+}
+
+/// Lets processes batch commands through a ring.
+pub struct SyscallRing {
+    max_entries: u32,
+}
+
+impl SyscallRing {
+    /// Allow rings with up to `max_entries` entries per queue, at most
+    /// `MAX_ENTRIES`.
+    pub const fn new(max_entries: u32) -> Self {
+        SyscallRing {
+            max_entries: if max_entries < MAX_ENTRIES {
+                max_entries
+            } else {
+                MAX_ENTRIES
+            },
+        }
+    }
+
+    /// Handle a command on `DRIVER_NUM`.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Return Ok(()) if the board supports rings.
+    /// - `1`: Run the commands in the submission queue of the ring at address
+    ///   `arg0`. If bit 0 of `arg1` is set, also move the pending upcalls into
+    ///   the completion queue. Stops when the completion queue is full.
+    ///   Returns the number of commands run and the number of upcalls reaped.
+    pub(crate) fn command<KR: KernelResources<C>, C: Chip>(
+        &self,
+        kernel: &Kernel,
+        resources: &KR,
+        process: &dyn process::Process,
+        command_num: usize,
+        arg0: usize,
+        arg1: usize,
+    ) -> SyscallReturn {
+        match command_num {
+            0 => SyscallReturn::Success,
+            1 => match self.enter(kernel, resources, process, arg0, arg1) {
+                Ok((commands, upcalls)) => SyscallReturn::SuccessU32U32(commands, upcalls),
+                Err(e) => SyscallReturn::Failure(e),
+            },
+            _ => SyscallReturn::Failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn enter<KR: KernelResources<C>, C: Chip>(
+        &self,
+        kernel: &Kernel,
+        resources: &KR,
+        process: &dyn process::Process,
+        address: usize,
+        flags: usize,
+    ) -> Result<(u32, u32), ErrorCode> {
+        let header =
+            process.build_readwrite_process_buffer(address as *mut u8, HEADER_WORDS * 4)?;
+        let mut entries = [0];
+        read_words(&header, ENTRIES, &mut entries)?;
+        let n = entries[0];
+        if n == 0 || n > self.max_entries {
+            return Err(ErrorCode::INVAL);
+        }
+        let len = (HEADER_WORDS + 2 * n as usize * ENTRY_WORDS) * 4;
+        let ring = process.build_readwrite_process_buffer(address as *mut u8, len)?;
+        let submissions = HEADER_WORDS;
+        let completions = HEADER_WORDS + n as usize * ENTRY_WORDS;
+
+        let mut indices = [0; 4];
+        read_words(&ring, SUBMISSION_HEAD, &mut indices)?;
+        let [mut submission_head, submission_tail, completion_head, mut completion_tail] = indices;
+        let completion_full = |tail: u32| tail.wrapping_sub(completion_head) >= n;
+
+        let mut commands = 0;
+        while submission_head != submission_tail && !completion_full(completion_tail) {
+            let mut entry = [0; ENTRY_WORDS];
+            let slot = (submission_head % n) as usize;
+            read_words(&ring, submissions + slot * ENTRY_WORDS, &mut entry)?;
+            let [driver_number, subdriver_number, arg0, arg1, user_data] = entry;
+
+            let syscall = Syscall::Command {
+                driver_number: driver_number as usize,
+                subdriver_number: subdriver_number as usize,
+                arg0: arg0 as usize,
+                arg1: arg1 as usize,
+            };
+            // The ring is not entered while the driver runs, as the driver
+            // may access buffers of the process that overlap with it.
+            let result = kernel.handle_ring_command(resources, process, syscall);
+
+            let mut completion = [user_data, 0, 0, 0, 0];
+            {
+                let [_, a0, a1, a2, a3] = &mut completion;
+                result.encode_syscall_return(a0, a1, a2, a3);
+            }
+            let slot = (completion_tail % n) as usize;
+            write_words(&ring, completions + slot * ENTRY_WORDS, &completion)?;
+            submission_head = submission_head.wrapping_add(1);
+            completion_tail = completion_tail.wrapping_add(1);
+            commands += 1;
+        }
+
+        let mut upcalls = 0;
+        if flags & FLAG_REAP_UPCALLS != 0 {
+            // Go through the tasks once, and queue the ones that are not reaped
+            // again so they keep their order.
+            for _ in 0..process.pending_tasks() {
+                let task = match process.dequeue_task() {
+                    Some(task) => task,
+                    None => break,
+                };
+                match task {
+                    Task::FunctionCall(call) if !completion_full(completion_tail) => {
+                        if let FunctionCallSource::Driver(upcall_id) = call.source {
+                            let completion = [
+                                upcall_id.driver_num as u32,
+                                UPCALL_COMPLETION | upcall_id.subscribe_num as u32,
+                                call.argument0 as u32,
+                                call.argument1 as u32,
+                                call.argument2 as u32,
+                            ];
+                            let slot = (completion_tail % n) as usize;
+                            write_words(&ring, completions + slot * ENTRY_WORDS, &completion)?;
+                            completion_tail = completion_tail.wrapping_add(1);
+                            upcalls += 1;
+                        } else {
+                            let _ = process.enqueue_task(Task::FunctionCall(call));
+                        }
+                    }
+                    task => {
+                        let _ = process.enqueue_task(task);
+                    }
+                }
+            }
+        }
+
+        write_words(&ring, SUBMISSION_HEAD, &[submission_head])?;
+        write_words(&ring, COMPLETION_TAIL, &[completion_tail])?;
+        Ok((commands, upcalls))
+    }
+}
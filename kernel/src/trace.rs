@@ -0,0 +1,207 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Tracing of kernel events into a ring buffer.
+//!
+//! To see why a system misses a deadline or drains its battery, it helps to
+//! know what the kernel did and when: which process ran for how long, which
+//! syscalls it made, and when interrupts were handled. With a tracer added to
+//! the observers of the kernel, the kernel records these events, together
+//! with a timestamp. Capsules can record their own events as well, with
+//! `Kernel::trace_event()`.
+//!
+//! `TraceBuffer` is a tracer that keeps the last events in a static ring
+//! buffer, from which a capsule like `capsules_extra::trace_export` takes
+//! them to send them to a host. The timestamps are ticks of a `Time`, so with
+//! a cycle counter, events are timed to the cycle.
+//!
+//! ```rust,ignore
+//! let trace = static_init!(
+//!     kernel::trace::TraceBuffer<'static, Rtc<'static>, 256>,
+//!     kernel::trace::TraceBuffer::new(&base_peripherals.rtc)
+//! );
+//! board_kernel.add_process_observer(trace, &process_management_capability);
+//! ```
+
+use core::cell::Cell;
+
+use crate::collections::list::ListLink;
+use crate::hil::time::{Frequency, Ticks, Time};
+use crate::process_observer::ProcessObserver;
+use crate::syscall::Syscall;
+
+/// Kinds of events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// The scheduler chose a process to run. The arguments are the process
+    /// identifier and the timeslice in microseconds, or 0 if the process runs
+    /// without a timeslice.
+    Schedule = 0,
+    /// A process stopped running. The arguments are the process identifier
+    /// and the reason, the position of the `StoppedExecutingReason` variant
+    /// starting from 0.
+    Stop = 1,
+    /// A process made a syscall. The arguments are the process identifier,
+    /// and the syscall class in bits 28 to 31, the driver number (or the
+    /// memop operand) in bits 8 to 27, and the subscribe, command or allow
+    /// number in bits 0 to 7.
+    Syscall = 2,
+    /// The kernel started handling interrupts. The arguments are 0.
+    Interrupts = 3,
+    /// An event defined by a capsule, with an identifier and a value.
+    Capsule = 4,
+}
+
+impl TryFrom<u8> for EventKind {
+    type Error = ();
+
+    fn try_from(kind: u8) -> Result<Self, ()> {
+        match kind {
+            0 => Ok(EventKind::Schedule),
+            1 => Ok(EventKind::Stop),
+            2 => Ok(EventKind::Syscall),
+            3 => Ok(EventKind::Interrupts),
+            4 => Ok(EventKind::Capsule),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A recorded event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub timestamp: u32,
+    pub kind: EventKind,
+    pub arg0: u32,
+    pub arg1: u32,
+}
+
+/// Reads recorded events, to export them.
+pub trait TraceSource {
+    /// Take the oldest event.
+    fn pop(&self) -> Option<Event>;
+
+    /// Return the number of events that were overwritten since the last call,
+    /// because they were not taken in time.
+    fn take_dropped(&self) -> u32;
+
+    /// Frequency of the timestamps, in Hertz.
+    fn frequency_hz(&self) -> u32;
+}
+
+/// The second argument of a `Syscall` event.
+pub(crate) fn syscall_argument(syscall: &Syscall) -> u32 {
+    let (class, driver, number) = match *syscall {
+        Syscall::Yield { which, .. } => (0, 0, which),
+        Syscall::Subscribe {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (1, driver_number, subdriver_number),
+        Syscall::Command {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (2, driver_number, subdriver_number),
+        Syscall::ReadWriteAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (3, driver_number, subdriver_number),
+        Syscall::ReadOnlyAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (4, driver_number, subdriver_number),
+        Syscall::Memop { operand, .. } => (5, operand, 0),
+        Syscall::Exit { which, .. } => (6, 0, which),
+        Syscall::UserspaceReadableAllow {
+            driver_number,
+            subdriver_number,
+            ..
+        } => (7, driver_number, subdriver_number),
+    };
+    class << 28 | (driver as u32 & 0xF_FFFF) << 8 | (number as u32 & 0xFF)
+}
+
+/// A tracer that keeps the last `N` events.
+pub struct TraceBuffer<'a, T: Time, const N: usize> {
+    time: &'a T,
+    events: [Cell<Option<Event>>; N],
+    /// Index of the oldest event.
+    head: Cell<usize>,
+    len: Cell<usize>,
+    dropped: Cell<u32>,
+    /// Bit `1 << kind` is set for each kind of event that is recorded.
+    mask: Cell<u32>,
+    next_observer: ListLink<'static, dyn ProcessObserver>,
+}
+
+impl<'a, T: Time, const N: usize> TraceBuffer<'a, T, N> {
+    pub fn new(time: &'a T) -> Self {
+        TraceBuffer {
+            time,
+            events: [(); N].map(|()| Cell::new(None)),
+            head: Cell::new(0),
+            len: Cell::new(0),
+            dropped: Cell::new(0),
+            mask: Cell::new(u32::MAX),
+            next_observer: ListLink::empty(),
+        }
+    }
+
+    /// Only record the kinds of events with their bit `1 << kind` set in
+    /// `mask`. All kinds are recorded by default.
+    pub fn set_mask(&self, mask: u32) {
+        self.mask.set(mask);
+    }
+}
+
+impl<'a, T: Time, const N: usize> ProcessObserver for TraceBuffer<'a, T, N> {
+    fn next_observer(&'static self) -> &'static ListLink<'static, dyn ProcessObserver> {
+        &self.next_observer
+    }
+
+    fn event(&self, kind: EventKind, arg0: u32, arg1: u32) {
+        if self.mask.get() & 1 << kind as u32 == 0 || N == 0 {
+            return;
+        }
+        let event = Event {
+            timestamp: self.time.now().into_u32(),
+            kind,
+            arg0,
+            arg1,
+        };
+        let len = self.len.get();
+        if len == N {
+            // Overwrite the oldest event.
+            self.events[self.head.get()].set(Some(event));
+            self.head.set((self.head.get() + 1) % N);
+            self.dropped.set(self.dropped.get().saturating_add(1));
+        } else {
+            self.events[(self.head.get() + len) % N].set(Some(event));
+            self.len.set(len + 1);
+        }
+    }
+}
+
+impl<'a, T: Time, const N: usize> TraceSource for TraceBuffer<'a, T, N> {
+    fn pop(&self) -> Option<Event> {
+        if self.len.get() == 0 {
+            return None;
+        }
+        let event = self.events[self.head.get()].take();
+        self.head.set((self.head.get() + 1) % N);
+        self.len.set(self.len.get() - 1);
+        event
+    }
+
+    fn take_dropped(&self) -> u32 {
+        self.dropped.take()
+    }
+
+    fn frequency_hz(&self) -> u32 {
+        T::Frequency::frequency()
+    }
+}
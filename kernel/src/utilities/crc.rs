@@ -0,0 +1,258 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Software implementation of the CRC algorithms of `hil::crc`.
+//!
+//! This is used by the kernel where it needs a CRC regardless of the chip,
+//! and by `capsules_extra::crc_software` to provide the CRC HIL on chips
+//! without CRC hardware. It computes the same values as the hardware
+//! implementations of each [`CrcAlgorithm`].
+//!
+//! Algorithms that consume input bytes from LSB to MSB are computed on a
+//! bit-reversed register. The CRC is updated four bits at a time from a
+//! 16-entry table per algorithm, which is much faster than updating it bit by
+//! bit while only taking 64 bytes of flash per algorithm.
+//!
+//! ```rust
+//! use kernel::hil::crc::{CrcAlgorithm, CrcOutput};
+//! use kernel::utilities::crc::{compute, CrcState};
+//!
+//! let mut state = CrcState::new(CrcAlgorithm::Crc32);
+//! state.update(b"1234");
+//! state.update(b"56789");
+//! assert_eq!(state.finish(), CrcOutput::Crc32(0xCBF43926));
+//! assert_eq!(compute(CrcAlgorithm::Crc32, b"123456789"), state.finish());
+//! ```
+
+use crate::hil::crc::{CrcAlgorithm, CrcOutput};
+
+/// Parameters of an algorithm. For reflected algorithms, `init` is that of
+/// the bit-reversed register.
+struct Params {
+    table: &'static [u32; 16],
+    width: u32,
+    /// Whether input bytes are consumed from LSB to MSB.
+    reflected: bool,
+    init: u32,
+    xor_out: u32,
+    /// Whether the register is bit-reversed again to get the output, i.e.
+    /// whether the output is not reflected.
+    reverse_out: bool,
+}
+
+/// Returns the table to update the CRC four bits at a time, for the
+/// bit-reversed polynomial `poly`.
+const fn reflected_table(poly: u32) -> [u32; 16] {
+    let mut table = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 4 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the table to update the CRC four bits at a time, for the
+/// polynomial `poly` of a 16 bit CRC.
+const fn table16(poly: u32) -> [u32; 16] {
+    let mut table = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        let mut crc = (i as u32) << 12;
+        let mut bit = 0;
+        while bit < 4 {
+            crc = if crc & 0x8000 != 0 {
+                ((crc << 1) ^ poly) & 0xFFFF
+            } else {
+                (crc << 1) & 0xFFFF
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32_TABLE: [u32; 16] = reflected_table(0xEDB88320);
+static CRC32C_TABLE: [u32; 16] = reflected_table(0x82F63B78);
+static CRC16_CCITT_TABLE: [u32; 16] = reflected_table(0x8408);
+static CRC16_XMODEM_TABLE: [u32; 16] = table16(0x1021);
+static CRC16_MODBUS_TABLE: [u32; 16] = reflected_table(0xA001);
+static CRC8_MAXIM_TABLE: [u32; 16] = reflected_table(0x8C);
+
+fn params(algorithm: CrcAlgorithm) -> Params {
+    match algorithm {
+        CrcAlgorithm::Crc32 => Params {
+            table: &CRC32_TABLE,
+            width: 32,
+            reflected: true,
+            init: 0xFFFFFFFF,
+            xor_out: 0xFFFFFFFF,
+            reverse_out: false,
+        },
+        CrcAlgorithm::Crc32C => Params {
+            table: &CRC32C_TABLE,
+            width: 32,
+            reflected: true,
+            init: 0xFFFFFFFF,
+            xor_out: 0xFFFFFFFF,
+            reverse_out: false,
+        },
+        CrcAlgorithm::Crc16CCITT => Params {
+            table: &CRC16_CCITT_TABLE,
+            width: 16,
+            reflected: true,
+            init: 0xFFFF,
+            xor_out: 0,
+            reverse_out: true,
+        },
+        CrcAlgorithm::Crc16Xmodem => Params {
+            table: &CRC16_XMODEM_TABLE,
+            width: 16,
+            reflected: false,
+            init: 0,
+            xor_out: 0,
+            reverse_out: false,
+        },
+        CrcAlgorithm::Crc16Modbus => Params {
+            table: &CRC16_MODBUS_TABLE,
+            width: 16,
+            reflected: true,
+            init: 0xFFFF,
+            xor_out: 0,
+            reverse_out: false,
+        },
+        CrcAlgorithm::Crc8Maxim => Params {
+            table: &CRC8_MAXIM_TABLE,
+            width: 8,
+            reflected: true,
+            init: 0,
+            xor_out: 0,
+            reverse_out: false,
+        },
+    }
+}
+
+/// State of a CRC computation over data passed in chunks.
+#[derive(Clone, Copy)]
+pub struct CrcState {
+    algorithm: CrcAlgorithm,
+    crc: u32,
+}
+
+impl CrcState {
+    pub fn new(algorithm: CrcAlgorithm) -> CrcState {
+        CrcState {
+            algorithm,
+            crc: params(algorithm).init,
+        }
+    }
+
+    pub fn algorithm(&self) -> CrcAlgorithm {
+        self.algorithm
+    }
+
+    /// Add `data` to the CRC.
+    pub fn update(&mut self, data: &[u8]) {
+        let params = params(self.algorithm);
+        let table = params.table;
+        let mut crc = self.crc;
+        if params.reflected {
+            for &byte in data {
+                crc ^= byte as u32;
+                crc = (crc >> 4) ^ table[(crc & 0xF) as usize];
+                crc = (crc >> 4) ^ table[(crc & 0xF) as usize];
+            }
+        } else {
+            // Only 16 bit CRCs are not reflected.
+            for &byte in data {
+                crc ^= (byte as u32) << 8;
+                crc = ((crc << 4) & 0xFFFF) ^ table[(crc >> 12) as usize];
+                crc = ((crc << 4) & 0xFFFF) ^ table[(crc >> 12) as usize];
+            }
+        }
+        self.crc = crc;
+    }
+
+    /// Returns the CRC of the data added so far. More data can still be
+    /// added afterwards.
+    pub fn finish(&self) -> CrcOutput {
+        let params = params(self.algorithm);
+        let mut crc = self.crc;
+        if params.reverse_out {
+            crc = crc.reverse_bits() >> (32 - params.width);
+        }
+        crc ^= params.xor_out;
+        match self.algorithm {
+            CrcAlgorithm::Crc32 => CrcOutput::Crc32(crc),
+            CrcAlgorithm::Crc32C => CrcOutput::Crc32C(crc),
+            CrcAlgorithm::Crc16CCITT => CrcOutput::Crc16CCITT(crc as u16),
+            CrcAlgorithm::Crc16Xmodem => CrcOutput::Crc16Xmodem(crc as u16),
+            CrcAlgorithm::Crc16Modbus => CrcOutput::Crc16Modbus(crc as u16),
+            CrcAlgorithm::Crc8Maxim => CrcOutput::Crc8Maxim(crc as u8),
+        }
+    }
+}
+
+/// Returns the CRC of `data`.
+pub fn compute(algorithm: CrcAlgorithm, data: &[u8]) -> CrcOutput {
+    let mut state = CrcState::new(algorithm);
+    state.update(data);
+    state.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_values() {
+        let check = b"123456789";
+        assert_eq!(
+            compute(CrcAlgorithm::Crc32, check),
+            CrcOutput::Crc32(0xCBF43926)
+        );
+        assert_eq!(
+            compute(CrcAlgorithm::Crc32C, check),
+            CrcOutput::Crc32C(0xE3069283)
+        );
+        assert_eq!(
+            compute(CrcAlgorithm::Crc16Modbus, check),
+            CrcOutput::Crc16Modbus(0x4B37)
+        );
+        assert_eq!(
+            compute(CrcAlgorithm::Crc16Xmodem, check),
+            CrcOutput::Crc16Xmodem(0x31C3)
+        );
+        assert_eq!(
+            compute(CrcAlgorithm::Crc8Maxim, check),
+            CrcOutput::Crc8Maxim(0xA1)
+        );
+        // Value computed by the SAM4L CRCCU.
+        assert_eq!(
+            compute(CrcAlgorithm::Crc16CCITT, b"ABCDEFG"),
+            CrcOutput::Crc16CCITT(0x1541)
+        );
+    }
+
+    #[test]
+    fn chunked_input() {
+        let mut state = CrcState::new(CrcAlgorithm::Crc32C);
+        state.update(b"12");
+        state.update(b"");
+        state.update(b"3456789");
+        assert_eq!(state.finish(), compute(CrcAlgorithm::Crc32C, b"123456789"));
+    }
+}
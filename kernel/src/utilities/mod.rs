@@ -6,6 +6,7 @@
 
 pub mod binary_write;
 pub mod copy_slice;
+pub mod crc;
 pub mod helpers;
 pub mod leasable_buffer;
 pub mod math;
@@ -13,6 +14,7 @@ pub mod mut_imut_buffer;
 pub mod peripheral_management;
 pub mod static_init;
 pub mod storage_volume;
+pub mod time_conversion;
 
 mod static_ref;
 
@@ -53,6 +53,51 @@ pub fn static_buf_check_used(used: &mut bool) {
     }
 }
 
+/// Number of bytes of the type name stored in a `FootprintRecord`. Longer
+/// names are truncated.
+pub const FOOTPRINT_NAME_LEN: usize = 104;
+
+/// Describes one buffer allocated with `static_buf!()`.
+///
+/// On embedded targets every `static_buf!()` emits one of these records into
+/// the `.tock_footprint` section. The kernel linker script marks that section
+/// as not allocated, so the records exist only in the ELF file and consume no
+/// flash or RAM on the board. `tools/print_tock_footprint.py` reads them back
+/// to report how much RAM each capsule and driver uses.
+#[repr(C)]
+pub struct FootprintRecord {
+    /// Number of bytes of RAM used by the buffer.
+    pub size: u32,
+    /// Alignment of the buffer.
+    pub align: u32,
+    /// Type name of the buffer contents as written at the `static_buf!()`
+    /// call site with whitespace removed, padded with zeros.
+    pub name: [u8; FOOTPRINT_NAME_LEN],
+}
+
+impl FootprintRecord {
+    pub const fn new(size: usize, align: usize, name: &str) -> FootprintRecord {
+        let bytes = name.as_bytes();
+        let mut buf = [0; FOOTPRINT_NAME_LEN];
+        let mut i = 0;
+        let mut len = 0;
+        while i < bytes.len() && len < FOOTPRINT_NAME_LEN {
+            // `stringify!()` separates every token with a space, drop those
+            // so names look like the type as written in source.
+            if bytes[i] != b' ' {
+                buf[len] = bytes[i];
+                len += 1;
+            }
+            i += 1;
+        }
+        FootprintRecord {
+            size: size as u32,
+            align: align as u32,
+            name: buf,
+        }
+    }
+}
+
 /// Allocates a statically-sized global region of memory for data structures but
 /// does not initialize the memory. Checks that the buffer is not aliased and is
 /// only used once.
@@ -85,6 +130,18 @@ macro_rules! static_buf {
         static mut BUF: (core::mem::MaybeUninit<$T>, bool) =
             (core::mem::MaybeUninit::uninit(), false);
 
+        // Describe this buffer for the build-time footprint report. This is
+        // placed in a non-allocated section and never referenced at runtime.
+        #[cfg(target_os = "none")]
+        #[used]
+        #[link_section = ".tock_footprint"]
+        static FOOTPRINT: $crate::utilities::static_init::FootprintRecord =
+            $crate::utilities::static_init::FootprintRecord::new(
+                core::mem::size_of::<(core::mem::MaybeUninit<$T>, bool)>(),
+                core::mem::align_of::<(core::mem::MaybeUninit<$T>, bool)>(),
+                stringify!($T),
+            );
+
         // To minimize the amount of code duplicated across every invocation
         // of this macro, all of the logic for checking if the buffer has been
         // used is contained within the static_buf_check_used function,
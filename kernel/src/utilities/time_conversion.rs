@@ -0,0 +1,241 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2023.
+
+//! Overflow-checked conversions between milliseconds, microseconds and
+//! ticks.
+//!
+//! `hil::time::ConvertTicks` always rounds down, so a wait converted with it
+//! can be shorter than requested, and it silently saturates. Code that does
+//! the conversion by hand, e.g. `ms * frequency / 1000` in `u32`, wraps
+//! around on fast clocks or long durations. The helpers here never
+//! overflow in intermediate results, round as the caller asks and return
+//! `None` if the result does not fit.
+//!
+//! The functions are generic over the `Frequency` of the clock, and
+//! `CheckedConvertTicks` provides them as methods of every `Time`:
+//!
+//! ```rust,ignore
+//! use kernel::utilities::time_conversion::{CheckedConvertTicks, Rounding};
+//!
+//! // The sensor needs at least 6.4 ms to convert.
+//! let delay = alarm.saturating_ticks_from_us(6400, Rounding::Up);
+//! alarm.set_alarm(alarm.now(), delay);
+//! ```
+
+use crate::hil::time::{Frequency, Ticks, Time};
+
+/// How to round the result of a conversion that is not exact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards zero. Durations may get shorter.
+    Down,
+    /// To the nearest integer, halves up.
+    Nearest,
+    /// Away from zero. Durations may get longer, never shorter, which is
+    /// what waits for hardware need.
+    Up,
+}
+
+/// Returns `value * numerator / denominator` rounded as asked, or `None` if
+/// `denominator` is 0 or the result does not fit in a `u64`.
+pub fn scale(value: u64, numerator: u32, denominator: u32, rounding: Rounding) -> Option<u64> {
+    if denominator == 0 {
+        return None;
+    }
+    let numerator = numerator as u64;
+    let denominator = denominator as u64;
+
+    // Scale the quotient and the remainder separately, so neither product
+    // can overflow unless the result does.
+    let whole = (value / denominator).checked_mul(numerator)?;
+    let remainder = (value % denominator) * numerator;
+    let fraction = match rounding {
+        Rounding::Down => remainder / denominator,
+        Rounding::Nearest => (remainder + denominator / 2) / denominator,
+        Rounding::Up => (remainder + denominator - 1) / denominator,
+    };
+    whole.checked_add(fraction)
+}
+
+/// `value` as ticks, if it fits.
+fn checked_ticks<T: Ticks>(value: u64) -> Option<T> {
+    if value <= T::max_value().into_u64() {
+        Some(T::from_or_max(value))
+    } else {
+        None
+    }
+}
+
+/// `value` as a `u32`, if it fits.
+fn checked_u32(value: u64) -> Option<u32> {
+    u32::try_from(value).ok()
+}
+
+/// Returns the number of ticks of a clock of frequency `F` in `ms`
+/// milliseconds, or `None` if it does not fit in `T`.
+pub fn ticks_from_ms<F: Frequency, T: Ticks>(ms: u32, rounding: Rounding) -> Option<T> {
+    checked_ticks(scale(ms as u64, F::frequency(), 1_000, rounding)?)
+}
+
+/// Returns the number of ticks of a clock of frequency `F` in `us`
+/// microseconds, or `None` if it does not fit in `T`.
+pub fn ticks_from_us<F: Frequency, T: Ticks>(us: u32, rounding: Rounding) -> Option<T> {
+    checked_ticks(scale(us as u64, F::frequency(), 1_000_000, rounding)?)
+}
+
+/// Returns the number of milliseconds in `ticks` of a clock of frequency
+/// `F`, or `None` if it does not fit in a `u32`.
+pub fn ticks_to_ms<F: Frequency, T: Ticks>(ticks: T, rounding: Rounding) -> Option<u32> {
+    checked_u32(scale(ticks.into_u64(), 1_000, F::frequency(), rounding)?)
+}
+
+/// Returns the number of microseconds in `ticks` of a clock of frequency
+/// `F`, or `None` if it does not fit in a `u32`.
+pub fn ticks_to_us<F: Frequency, T: Ticks>(ticks: T, rounding: Rounding) -> Option<u32> {
+    checked_u32(scale(
+        ticks.into_u64(),
+        1_000_000,
+        F::frequency(),
+        rounding,
+    )?)
+}
+
+/// The conversions of this module for the frequency and ticks of a `Time`.
+pub trait CheckedConvertTicks: Time {
+    /// Returns the number of ticks in `ms` milliseconds, or `None` if it
+    /// does not fit in `Ticks`.
+    fn checked_ticks_from_ms(&self, ms: u32, rounding: Rounding) -> Option<Self::Ticks> {
+        ticks_from_ms::<Self::Frequency, Self::Ticks>(ms, rounding)
+    }
+
+    /// Returns the number of ticks in `us` microseconds, or `None` if it
+    /// does not fit in `Ticks`.
+    fn checked_ticks_from_us(&self, us: u32, rounding: Rounding) -> Option<Self::Ticks> {
+        ticks_from_us::<Self::Frequency, Self::Ticks>(us, rounding)
+    }
+
+    /// Returns the number of milliseconds in `ticks`, or `None` if it does
+    /// not fit in a `u32`.
+    fn checked_ticks_to_ms(&self, ticks: Self::Ticks, rounding: Rounding) -> Option<u32> {
+        ticks_to_ms::<Self::Frequency, Self::Ticks>(ticks, rounding)
+    }
+
+    /// Returns the number of microseconds in `ticks`, or `None` if it does
+    /// not fit in a `u32`.
+    fn checked_ticks_to_us(&self, ticks: Self::Ticks, rounding: Rounding) -> Option<u32> {
+        ticks_to_us::<Self::Frequency, Self::Ticks>(ticks, rounding)
+    }
+
+    /// Returns the number of ticks in `ms` milliseconds, or
+    /// `Ticks::max_value()` if it does not fit.
+    fn saturating_ticks_from_ms(&self, ms: u32, rounding: Rounding) -> Self::Ticks {
+        self.checked_ticks_from_ms(ms, rounding)
+            .unwrap_or(Self::Ticks::max_value())
+    }
+
+    /// Returns the number of ticks in `us` microseconds, or
+    /// `Ticks::max_value()` if it does not fit.
+    fn saturating_ticks_from_us(&self, us: u32, rounding: Rounding) -> Self::Ticks {
+        self.checked_ticks_from_us(us, rounding)
+            .unwrap_or(Self::Ticks::max_value())
+    }
+}
+
+impl<T: Time + ?Sized> CheckedConvertTicks for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hil::time::{Freq1MHz, Freq32KHz, Ticks16, Ticks24, Ticks32, Ticks64};
+
+    #[test]
+    fn scale_rounds() {
+        assert_eq!(scale(10, 1, 3, Rounding::Down), Some(3));
+        assert_eq!(scale(10, 1, 3, Rounding::Nearest), Some(3));
+        assert_eq!(scale(10, 1, 3, Rounding::Up), Some(4));
+        assert_eq!(scale(3, 1, 2, Rounding::Nearest), Some(2));
+        assert_eq!(scale(9, 1, 3, Rounding::Up), Some(3));
+        assert_eq!(scale(1, 1, 0, Rounding::Down), None);
+    }
+
+    #[test]
+    fn scale_does_not_overflow() {
+        assert_eq!(
+            scale(u64::MAX, 1_000_000, 1_000_000, Rounding::Up),
+            Some(u64::MAX)
+        );
+        assert_eq!(scale(u64::MAX, 2, 1, Rounding::Down), None);
+        assert_eq!(
+            scale(u32::MAX as u64, u32::MAX, u32::MAX, Rounding::Up),
+            Some(u32::MAX as u64)
+        );
+    }
+
+    #[test]
+    fn ticks_from_time() {
+        // 6.4 ms is 209.7152 ticks at 32768 Hz.
+        assert_eq!(
+            ticks_from_us::<Freq32KHz, Ticks32>(6400, Rounding::Down),
+            Some(Ticks32::from(209))
+        );
+        assert_eq!(
+            ticks_from_us::<Freq32KHz, Ticks32>(6400, Rounding::Up),
+            Some(Ticks32::from(210))
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq32KHz, Ticks32>(1000, Rounding::Nearest),
+            Some(Ticks32::from(32768))
+        );
+        // Wraps in u32 arithmetic, but fits in the ticks.
+        assert_eq!(
+            ticks_from_ms::<Freq1MHz, Ticks32>(4_000_000, Rounding::Down),
+            Some(Ticks32::from(4_000_000_000))
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq1MHz, Ticks32>(5_000_000, Rounding::Down),
+            None
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq1MHz, Ticks64>(5_000_000, Rounding::Down)
+                .map(|ticks| ticks.into_u64()),
+            Some(5_000_000_000)
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq32KHz, Ticks16>(1999, Rounding::Down),
+            Some(Ticks16::from(65503u16))
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq32KHz, Ticks16>(2000, Rounding::Down),
+            None
+        );
+        assert_eq!(
+            ticks_from_ms::<Freq1MHz, Ticks24>(20_000, Rounding::Down),
+            None
+        );
+    }
+
+    #[test]
+    fn time_from_ticks() {
+        assert_eq!(
+            ticks_to_ms::<Freq32KHz, Ticks32>(Ticks32::from(32768), Rounding::Down),
+            Some(1000)
+        );
+        assert_eq!(
+            ticks_to_us::<Freq32KHz, Ticks32>(Ticks32::from(1), Rounding::Down),
+            Some(30)
+        );
+        assert_eq!(
+            ticks_to_us::<Freq32KHz, Ticks32>(Ticks32::from(1), Rounding::Up),
+            Some(31)
+        );
+        assert_eq!(
+            ticks_to_us::<Freq1MHz, Ticks32>(Ticks32::from(u32::MAX), Rounding::Down),
+            Some(u32::MAX)
+        );
+        assert_eq!(
+            ticks_to_us::<Freq32KHz, Ticks32>(Ticks32::from(u32::MAX), Rounding::Down),
+            None
+        );
+    }
+}
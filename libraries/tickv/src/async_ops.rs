@@ -267,6 +267,28 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         self.tickv.garbage_collect()
     }
 
+    /// Compact a single region of TicKV, see `TicKV::compact_region()`.
+    ///
+    /// On success the number of bytes freed will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn compact_region(&self, region: usize) -> Result<usize, ErrorCode> {
+        self.tickv.compact_region(region)
+    }
+
+    /// Finish an interrupted compaction, see
+    /// `TicKV::recover_compaction()`.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn recover_compaction(&self) -> Result<SuccessCode, ErrorCode> {
+        self.tickv.recover_compaction()
+    }
+
+    /// The number of regions of the flash used for TicKV.
+    pub fn num_regions(&self) -> usize {
+        self.tickv.num_regions()
+    }
+
     /// Copy data from `read_buffer` argument to the internal read_buffer.
     /// This should be used to copy the data that the implementation wanted
     /// to read when calling `read_region` after the async operation has
@@ -290,7 +312,8 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     }
 
     /// Continue the last operation after the async operation has completed.
-    /// This should be called from a read/erase complete callback.
+    /// This should be called from a read/erase complete callback, and
+    /// during a compaction from a write complete callback as well.
     /// NOTE: If called from a read callback, `set_read_buffer` should be
     /// called first to update the data.
     ///
@@ -324,6 +347,10 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                 Ok(_) => Ok(SuccessCode::Complete),
                 Err(e) => Err(e),
             },
+            State::Compact(_) => match self.tickv.continue_compaction() {
+                Ok(_) => Ok(SuccessCode::Complete),
+                Err(e) => Err(e),
+            },
             _ => unreachable!(),
         };
 
@@ -340,6 +367,10 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             Err(e) => match e {
                 ErrorCode::ReadNotReady(_) | ErrorCode::EraseNotReady(_) => (ret, None),
                 ErrorCode::WriteNotReady(_) => {
+                    // A compaction continues once the write completes.
+                    if let State::Compact(_) = self.tickv.state.get() {
+                        return (ret, None);
+                    }
                     self.tickv.state.set(State::None);
                     (ret, None)
                 }
@@ -4,7 +4,9 @@
 
 use crate::error_codes::ErrorCode;
 use crate::flash_controller::FlashController;
-use crate::tickv::{TicKV, HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
+use crate::tickv::{
+    TicKV, CHECK_SUM_LEN, HASH_OFFSET, HEADER_LENGTH, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET,
+};
 use core::hash::{Hash, Hasher};
 use std::cell::Cell;
 use std::cell::RefCell;
@@ -424,13 +426,14 @@ mod no_check_store_flast_ctrl {
     use super::*;
     // An example FlashCtrl implementation
     struct FlashCtrl {
-        buf: RefCell<[[u8; 256]; 2]>,
+        // The two regions of TicKV, and the spare region for compaction.
+        buf: RefCell<[[u8; 256]; 3]>,
     }
 
     impl FlashCtrl {
         fn new() -> Self {
             Self {
-                buf: RefCell::new([[0xFF; 256]; 2]),
+                buf: RefCell::new([[0xFF; 256]; 3]),
             }
         }
     }
@@ -467,9 +470,7 @@ mod no_check_store_flast_ctrl {
 
         fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
             println!("Erase region: {}", region_number);
-            let mut local_buf = self.buf.borrow_mut()[region_number];
-
-            for d in local_buf.iter_mut() {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
                 *d = 0xFF;
             }
 
@@ -561,4 +562,197 @@ mod no_check_store_flast_ctrl {
             Err(ErrorCode::KeyNotFound)
         );
     }
+
+    #[test]
+    fn test_compact_region() {
+        let mut read_buf: [u8; 256] = [0; 256];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x200);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 64] = [0x23; 64];
+        let mut buf: [u8; 64] = [0; 64];
+
+        for key in [&b"ONE"[..], b"TWO", b"THREE", b"FOUR", b"FIVE", b"SIX"] {
+            tickv.append_key(get_hashed_key(key), &value).unwrap();
+        }
+        assert_eq!(
+            tickv.append_key(get_hashed_key(b"SEVEN"), &value),
+            Err(ErrorCode::FlashFull)
+        );
+
+        println!("Compact flash without deleted keys");
+        assert_eq!(tickv.compact_region(0), Ok(0));
+        assert_eq!(tickv.compact_region(1), Ok(0));
+
+        println!("Delete Keys ONE and FOUR");
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"FOUR")).unwrap();
+
+        println!("Compact flash with deleted keys");
+        let freed: usize = (0..tickv.num_regions())
+            .map(|region| tickv.compact_region(region).unwrap())
+            .sum();
+        assert_eq!(freed, 2 * (HEADER_LENGTH + value.len() + CHECK_SUM_LEN));
+
+        println!("Get remaining keys");
+        for key in [&b"TWO"[..], b"THREE", b"FIVE", b"SIX"] {
+            tickv.get_key(get_hashed_key(key), &mut buf).unwrap();
+        }
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+
+        println!("Add Key SEVEN in the freed space");
+        tickv.append_key(get_hashed_key(b"SEVEN"), &value).unwrap();
+        tickv.get_key(get_hashed_key(b"SEVEN"), &mut buf).unwrap();
+    }
+}
+
+/// Tests using a flash controller that loses power after a number of writes
+/// and erases
+mod power_loss_flash_ctrl {
+    use super::*;
+
+    type Flash = [[u8; 256]; 3];
+
+    struct FlashCtrl {
+        buf: RefCell<Flash>,
+        // The writes and erases that complete before the power is lost.
+        operations_left: Cell<usize>,
+    }
+
+    impl FlashCtrl {
+        fn new(buf: Flash, operations_left: usize) -> Self {
+            Self {
+                buf: RefCell::new(buf),
+                operations_left: Cell::new(operations_left),
+            }
+        }
+
+        /// Returns how much of an operation is done: all of it, half of it
+        /// when the power is lost during it, or none of it after that.
+        fn power(&self, len: usize) -> usize {
+            match self.operations_left.get() {
+                0 => 0,
+                1 => {
+                    self.operations_left.set(0);
+                    len / 2
+                }
+                left => {
+                    self.operations_left.set(left - 1);
+                    len
+                }
+            }
+        }
+    }
+
+    impl FlashController<256> for FlashCtrl {
+        fn read_region(
+            &self,
+            region_number: usize,
+            offset: usize,
+            buf: &mut [u8; 256],
+        ) -> Result<(), ErrorCode> {
+            for (i, b) in buf.iter_mut().enumerate() {
+                *b = self.buf.borrow()[region_number][offset + i]
+            }
+
+            Ok(())
+        }
+
+        fn write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+            let len = self.power(buf.len());
+            for (i, d) in buf[..len].iter().enumerate() {
+                self.buf.borrow_mut()[address / 256][(address % 256) + i] = *d;
+            }
+
+            if len == buf.len() {
+                Ok(())
+            } else {
+                Err(ErrorCode::WriteFail)
+            }
+        }
+
+        fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
+            let len = self.power(256);
+            for d in self.buf.borrow_mut()[region_number][..len].iter_mut() {
+                *d = 0xFF;
+            }
+
+            if len == 256 {
+                Ok(())
+            } else {
+                Err(ErrorCode::EraseFail)
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_power_loss() {
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let value: [u8; 64] = [0x23; 64];
+        let mut buf: [u8; 64] = [0; 64];
+
+        let mut read_buf: [u8; 256] = [0; 256];
+        let tickv = TicKV::<FlashCtrl, 256>::new(
+            FlashCtrl::new([[0xFF; 256]; 3], usize::MAX),
+            &mut read_buf,
+            0x200,
+        );
+        tickv.initialise(hash).unwrap();
+        for key in [&b"ONE"[..], b"TWO", b"THREE", b"FOUR", b"FIVE", b"SIX"] {
+            tickv.append_key(get_hashed_key(key), &value).unwrap();
+        }
+        tickv.invalidate_key(get_hashed_key(b"ONE")).unwrap();
+        tickv.invalidate_key(get_hashed_key(b"FOUR")).unwrap();
+        let flash = *tickv.controller.buf.borrow();
+
+        // Lose the power during each write and erase of the compaction, until
+        // it completes.
+        for operations in 1.. {
+            println!("Lose power during operation {}", operations);
+            let mut read_buf: [u8; 256] = [0; 256];
+            let tickv = TicKV::<FlashCtrl, 256>::new(
+                FlashCtrl::new(flash, operations),
+                &mut read_buf,
+                0x200,
+            );
+            let compacted = (0..tickv.num_regions())
+                .map(|region| tickv.compact_region(region))
+                .all(|ret| ret.is_ok());
+            let lost = *tickv.controller.buf.borrow();
+
+            let mut read_buf: [u8; 256] = [0; 256];
+            let tickv = TicKV::<FlashCtrl, 256>::new(
+                FlashCtrl::new(lost, usize::MAX),
+                &mut read_buf,
+                0x200,
+            );
+            tickv.recover_compaction().unwrap();
+            assert!(tickv.controller.buf.borrow()[2].iter().all(|d| *d == 0xFF));
+
+            tickv.initialise(hash).unwrap();
+            for key in [&b"TWO"[..], b"THREE", b"FIVE", b"SIX"] {
+                tickv.get_key(get_hashed_key(key), &mut buf).unwrap();
+            }
+            assert_eq!(
+                tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+                Err(ErrorCode::KeyNotFound)
+            );
+
+            if compacted {
+                // The deleted keys left space for a new one.
+                tickv.append_key(get_hashed_key(b"SEVEN"), &value).unwrap();
+                break;
+            }
+        }
+    }
 }
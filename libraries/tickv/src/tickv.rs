@@ -37,6 +37,30 @@ pub(crate) enum RubbishState {
     EraseRegion(usize),
 }
 
+/// The steps of a compaction. Steps that follow an asynchronous erase or
+/// write are stored once it has started, steps that read a region are
+/// stored while the read is pending.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum CompactState {
+    /// Trying to read the region to compact
+    ReadRegion(usize),
+    /// Trying to read the spare region, to recover a compaction
+    ReadSpare,
+    /// Trying to erase the spare region, with the region and the number of
+    /// bytes that will be freed
+    EraseSpare(usize, usize),
+    /// Trying to copy the compacted region to the spare region
+    WriteSpare(usize, usize),
+    /// Trying to erase the region
+    EraseRegion(usize, usize),
+    /// Trying to write the compacted region back
+    WriteRegion(usize, usize),
+    /// Trying to erase the spare region, with the number of bytes freed
+    ClearSpare(usize),
+    /// The spare region is being erased
+    Done(usize),
+}
+
 #[derive(Clone, Copy, PartialEq)]
 /// The current state machine when trying to complete a previous operation.
 /// This is used when returning from a complete async `FlashController` call.
@@ -53,6 +77,8 @@ pub(crate) enum State {
     InvalidateKey(KeyState),
     /// Running garbage collection
     GarbageCollect(RubbishState),
+    /// Compacting a region
+    Compact(CompactState),
 }
 
 /// The struct storing all of the TicKV information.
@@ -95,6 +121,12 @@ pub(crate) const HASH_OFFSET: usize = 3;
 pub(crate) const HEADER_LENGTH: usize = HASH_OFFSET + 8;
 pub(crate) const CHECK_SUM_LEN: usize = 4;
 
+/// Marks the spare region as holding a complete copy of a region, see
+/// `compact_region()`.
+const MARKER_MAGIC: u32 = 0x5443_4d50;
+/// The marker is the magic value, the region number and a checksum.
+const MARKER_LENGTH: usize = 12;
+
 /// The main key. A hashed version of this should be passed to
 /// `initialise()`.
 pub const MAIN_KEY: &[u8; 15] = b"tickv-super-key";
@@ -202,6 +234,11 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
     }
 
+    /// The number of regions of the flash used for TicKV.
+    pub fn num_regions(&self) -> usize {
+        self.flash_size / S
+    }
+
     /// Get region number from a hashed key
     fn get_region(&self, hash: u64) -> usize {
         assert_ne!(hash, 0xFFFF_FFFF_FFFF_FFFF);
@@ -996,4 +1033,242 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
 
         Ok(flash_freed)
     }
+
+    /// Compact a single region of TicKV.
+    ///
+    /// Garbage collection only erases regions in which all objects are
+    /// marked for deletion. This moves the valid objects of `region` to the
+    /// start of it, so that the space of the deleted objects between them
+    /// can be used again. Only one region is handled per call, so the work
+    /// done is bounded and a caller can compact the regions one by one
+    /// while other operations run in between.
+    ///
+    /// The region is never erased while the only copy of its valid objects
+    /// is in RAM: the compacted region is first written to the spare region,
+    /// the region after the last one of TicKV (`num_regions()`), along with
+    /// a marker naming the region. Only then is the region erased and
+    /// written, and the spare region erased again. The flash controller must
+    /// provide the spare region and nothing else may use it. If the power is
+    /// lost during a compaction, `recover_compaction()` finishes it.
+    ///
+    /// Regions with so little space freed that the marker does not fit are
+    /// left as they are.
+    ///
+    /// On success the number of bytes freed will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn compact_region(&self, region: usize) -> Result<usize, ErrorCode> {
+        if region >= self.num_regions() {
+            return Err(ErrorCode::CorruptData);
+        }
+        match self.state.get() {
+            State::Compact(step) => self.compact(step),
+            _ => self.compact(CompactState::ReadRegion(region)),
+        }
+    }
+
+    /// Finish a compaction that was interrupted by a power loss.
+    ///
+    /// If the spare region holds a region copied by `compact_region()`,
+    /// the region is written again from the copy. This must be called
+    /// before any other operation, including `initialise()`, by users of
+    /// `compact_region()`.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn recover_compaction(&self) -> Result<SuccessCode, ErrorCode> {
+        let step = match self.state.get() {
+            State::Compact(step) => step,
+            _ => CompactState::ReadSpare,
+        };
+        self.compact(step).map(|_| SuccessCode::Complete)
+    }
+
+    /// Continue `compact_region()` or `recover_compaction()` after an
+    /// asynchronous flash operation completed.
+    pub(crate) fn continue_compaction(&self) -> Result<usize, ErrorCode> {
+        match self.state.get() {
+            State::Compact(step) => self.compact(step),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Run the steps of a compaction, starting with `step`.
+    fn compact(&self, step: CompactState) -> Result<usize, ErrorCode> {
+        let spare = self.num_regions();
+        // A read that completed asynchronously has already filled the buffer.
+        let read_done = self.state.get() == State::Compact(step);
+        let region_data = self.read_buffer.take().unwrap();
+        let mut step = step;
+
+        let ret = loop {
+            let (ret, next) = match step {
+                CompactState::ReadRegion(region) => {
+                    if !read_done {
+                        if let Err(e) = self.controller.read_region(region, 0, region_data) {
+                            break Err(e);
+                        }
+                    }
+                    let freed = match Self::compact_objects(region_data) {
+                        Ok(freed) => freed,
+                        Err(e) => break Err(e),
+                    };
+                    if freed == 0 || !Self::add_marker(region_data, region) {
+                        break Ok(0);
+                    }
+                    (Ok(()), CompactState::EraseSpare(region, freed))
+                }
+                CompactState::ReadSpare => {
+                    if !read_done {
+                        if let Err(e) = self.controller.read_region(spare, 0, region_data) {
+                            break Err(e);
+                        }
+                    }
+                    match Self::check_marker(region_data) {
+                        Some(region) if region < spare => {
+                            (Ok(()), CompactState::EraseRegion(region, 0))
+                        }
+                        // A copy that was not completed, the region it was
+                        // taken from is unchanged.
+                        _ if region_data.iter().any(|d| *d != 0xFF) => {
+                            (Ok(()), CompactState::ClearSpare(0))
+                        }
+                        _ => break Ok(0),
+                    }
+                }
+                CompactState::EraseSpare(region, freed) => (
+                    self.controller.erase_region(spare),
+                    CompactState::WriteSpare(region, freed),
+                ),
+                CompactState::WriteSpare(region, freed) => (
+                    self.controller.write(spare * S, &region_data[..]),
+                    CompactState::EraseRegion(region, freed),
+                ),
+                CompactState::EraseRegion(region, freed) => (
+                    self.controller.erase_region(region),
+                    CompactState::WriteRegion(region, freed),
+                ),
+                CompactState::WriteRegion(region, freed) => {
+                    // The marker only belongs in the spare region.
+                    for d in region_data[S - MARKER_LENGTH..].iter_mut() {
+                        *d = 0xFF;
+                    }
+                    let ret = if region_data.iter().any(|d| *d != 0xFF) {
+                        self.controller.write(region * S, &region_data[..])
+                    } else {
+                        Ok(())
+                    };
+                    (ret, CompactState::ClearSpare(freed))
+                }
+                CompactState::ClearSpare(freed) => {
+                    if let Err(e) = self.controller.erase_region(spare) {
+                        if let ErrorCode::EraseNotReady(_) = e {
+                            self.state.set(State::Compact(CompactState::Done(freed)));
+                        }
+                        break Err(e);
+                    }
+                    break Ok(freed);
+                }
+                CompactState::Done(freed) => break Ok(freed),
+            };
+
+            match ret {
+                Ok(()) => step = next,
+                Err(ErrorCode::EraseNotReady(reg)) => {
+                    self.state.set(State::Compact(next));
+                    break Err(ErrorCode::EraseNotReady(reg));
+                }
+                Err(ErrorCode::WriteNotReady(reg)) => {
+                    self.state.set(State::Compact(next));
+                    break Err(ErrorCode::WriteNotReady(reg));
+                }
+                Err(e) => break Err(e),
+            }
+        };
+        self.read_buffer.replace(Some(region_data));
+
+        match ret {
+            Err(ErrorCode::ReadNotReady(reg)) => {
+                self.state.set(State::Compact(step));
+                Err(ErrorCode::ReadNotReady(reg))
+            }
+            Err(ErrorCode::EraseNotReady(reg)) => Err(ErrorCode::EraseNotReady(reg)),
+            Err(ErrorCode::WriteNotReady(reg)) => Err(ErrorCode::WriteNotReady(reg)),
+            ret => {
+                self.state.set(State::None);
+                ret
+            }
+        }
+    }
+
+    /// Store the marker for a copy of `region` at the end of
+    /// `region_data`. Returns false if the valid objects leave no space for
+    /// it.
+    fn add_marker(region_data: &mut [u8; S], region: usize) -> bool {
+        let start = S - MARKER_LENGTH;
+        if region_data[start..].iter().any(|d| *d != 0xFF) {
+            return false;
+        }
+        let region = (region as u32).to_le_bytes();
+        let mut check_sum = crc32::Crc32::new();
+        check_sum.update(&region_data[..start]);
+        check_sum.update(&region);
+        region_data[start..start + 4].copy_from_slice(&MARKER_MAGIC.to_le_bytes());
+        region_data[start + 4..start + 8].copy_from_slice(&region);
+        region_data[start + 8..].copy_from_slice(&check_sum.finalise().to_le_bytes());
+        true
+    }
+
+    /// The region the spare region holds a complete copy of, if any.
+    fn check_marker(region_data: &[u8; S]) -> Option<usize> {
+        let start = S - MARKER_LENGTH;
+        let word = |offset: usize| {
+            let mut bytes = [0; 4];
+            bytes.copy_from_slice(&region_data[start + offset..start + offset + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        let region = word(4);
+        let mut check_sum = crc32::Crc32::new();
+        check_sum.update(&region_data[..start]);
+        check_sum.update(&region.to_le_bytes());
+        if word(0) == MARKER_MAGIC && word(8) == check_sum.finalise() {
+            Some(region as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Move the valid objects in `region_data` to the start of it, and fill
+    /// the rest with 0xFF. Returns the number of bytes freed.
+    fn compact_objects(region_data: &mut [u8; S]) -> Result<usize, ErrorCode> {
+        let mut offset: usize = 0;
+        let mut valid_end: usize = 0;
+
+        while offset + HEADER_LENGTH < S && region_data[offset + VERSION_OFFSET] != 0xFF {
+            if region_data[offset + VERSION_OFFSET] != VERSION {
+                return Err(ErrorCode::UnsupportedVersion);
+            }
+
+            let total_length = (((region_data[offset + LEN_OFFSET] as u16) & !0xF0) << 8
+                | region_data[offset + LEN_OFFSET + 1] as u16)
+                as usize;
+            if total_length == 0 || offset + total_length > S {
+                return Err(ErrorCode::CorruptData);
+            }
+
+            if region_data[offset + LEN_OFFSET] & 0x80 == 0x80 {
+                region_data.copy_within(offset..offset + total_length, valid_end);
+                valid_end += total_length;
+            }
+            offset += total_length;
+        }
+
+        if valid_end == offset {
+            // Nothing is marked for deletion.
+            return Ok(0);
+        }
+        for d in region_data[valid_end..].iter_mut() {
+            *d = 0xFF;
+        }
+        Ok(offset - valid_end)
+    }
 }
@@ -138,6 +138,7 @@ pub fn parse_tbf_header(
                 let mut permissions_pointer: Option<types::TbfHeaderV2Permissions<8>> = None;
                 let mut persistent_acls_pointer: Option<types::TbfHeaderV2PersistentAcl<8>> = None;
                 let mut kernel_version: Option<types::TbfHeaderV2KernelVersion> = None;
+                let mut allowed_drivers: Option<types::TbfHeaderV2AllowedDrivers<16>> = None;
 
                 // Iterate the remainder of the header looking for TLV entries.
                 while remaining.len() > 0 {
@@ -279,6 +280,15 @@ pub fn parse_tbf_header(
                             }
                         }
 
+                        types::TbfHeaderTypes::TbfHeaderAllowedDrivers => {
+                            allowed_drivers = Some(
+                                remaining
+                                    .get(0..tlv_header.length as usize)
+                                    .ok_or(types::TbfParseError::NotEnoughFlash)?
+                                    .try_into()?,
+                            );
+                        }
+
                         _ => {}
                     }
 
@@ -300,6 +310,7 @@ pub fn parse_tbf_header(
                     permissions: permissions_pointer,
                     persistent_acls: persistent_acls_pointer,
                     kernel_version: kernel_version,
+                    allowed_drivers: allowed_drivers,
                 };
 
                 Ok(types::TbfHeader::TbfHeaderV2(tbf_header))
@@ -9,6 +9,7 @@ use core::fmt;
 use core::mem::size_of;
 
 const NUM_PERSISTENT_ACLS: usize = 8;
+const NUM_ALLOWED_DRIVERS: usize = 16;
 
 /// Error when parsing just the beginning of the TBF header. This is only used
 /// when establishing the linked list structure of apps installed in flash.
@@ -129,6 +130,7 @@ pub enum TbfHeaderTypes {
     TbfHeaderPersistentAcl = 7,
     TbfHeaderKernelVersion = 8,
     TbfHeaderProgram = 9,
+    TbfHeaderAllowedDrivers = 10,
     TbfFooterCredentials = 128,
 
     /// Some field in the header that we do not understand. Since the TLV format
@@ -240,6 +242,13 @@ pub struct TbfHeaderV2KernelVersion {
     minor: u16,
 }
 
+/// The driver numbers this app may use
+#[derive(Clone, Copy, Debug)]
+pub struct TbfHeaderV2AllowedDrivers<const L: usize> {
+    length: u16,
+    driver_numbers: [u32; L],
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum TbfFooterV2CredentialsType {
     Reserved = 0,
@@ -248,6 +257,7 @@ pub enum TbfFooterV2CredentialsType {
     SHA256 = 3,
     SHA384 = 4,
     SHA512 = 5,
+    CRC32C = 6,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -318,6 +328,7 @@ impl core::convert::TryFrom<u16> for TbfHeaderTypes {
             7 => Ok(TbfHeaderTypes::TbfHeaderPersistentAcl),
             8 => Ok(TbfHeaderTypes::TbfHeaderKernelVersion),
             9 => Ok(TbfHeaderTypes::TbfHeaderProgram),
+            10 => Ok(TbfHeaderTypes::TbfHeaderAllowedDrivers),
             128 => Ok(TbfHeaderTypes::TbfFooterCredentials),
             _ => Ok(TbfHeaderTypes::Unknown),
         }
@@ -599,6 +610,33 @@ impl core::convert::TryFrom<&[u8]> for TbfHeaderV2KernelVersion {
     }
 }
 
+impl<const L: usize> core::convert::TryFrom<&[u8]> for TbfHeaderV2AllowedDrivers<L> {
+    type Error = TbfParseError;
+
+    /// Parse the driver numbers from the value of the TLV, which must be
+    /// exactly `b`.
+    fn try_from(b: &[u8]) -> Result<TbfHeaderV2AllowedDrivers<L>, Self::Error> {
+        if b.len() % size_of::<u32>() != 0 || b.len() / size_of::<u32>() > L {
+            return Err(TbfParseError::BadTlvEntry(
+                TbfHeaderTypes::TbfHeaderAllowedDrivers as usize,
+            ));
+        }
+
+        let mut driver_numbers = [0; L];
+        for (driver_number, bytes) in driver_numbers
+            .iter_mut()
+            .zip(b.chunks_exact(size_of::<u32>()))
+        {
+            *driver_number = u32::from_le_bytes(bytes.try_into()?);
+        }
+
+        Ok(TbfHeaderV2AllowedDrivers {
+            length: (b.len() / size_of::<u32>()) as u16,
+            driver_numbers,
+        })
+    }
+}
+
 impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
     type Error = TbfParseError;
 
@@ -615,6 +653,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             3 => TbfFooterV2CredentialsType::SHA256,
             4 => TbfFooterV2CredentialsType::SHA384,
             5 => TbfFooterV2CredentialsType::SHA512,
+            6 => TbfFooterV2CredentialsType::CRC32C,
             _ => {
                 return Err(TbfParseError::InternalError);
             }
@@ -626,6 +665,7 @@ impl core::convert::TryFrom<&'static [u8]> for TbfFooterV2Credentials {
             TbfFooterV2CredentialsType::SHA256 => 32,
             TbfFooterV2CredentialsType::SHA384 => 48,
             TbfFooterV2CredentialsType::SHA512 => 64,
+            TbfFooterV2CredentialsType::CRC32C => 4,
         };
         let data = &b
             .get(4..(length + 4))
@@ -667,6 +707,7 @@ pub struct TbfHeaderV2 {
     pub(crate) permissions: Option<TbfHeaderV2Permissions<8>>,
     pub(crate) persistent_acls: Option<TbfHeaderV2PersistentAcl<NUM_PERSISTENT_ACLS>>,
     pub(crate) kernel_version: Option<TbfHeaderV2KernelVersion>,
+    pub(crate) allowed_drivers: Option<TbfHeaderV2AllowedDrivers<NUM_ALLOWED_DRIVERS>>,
 }
 
 /// Type that represents the fields of the Tock Binary Format header.
@@ -928,6 +969,20 @@ impl TbfHeader {
         }
     }
 
+    /// Get the driver numbers this process may use.
+    /// Returns `None` if the allowed drivers header is not included.
+    pub fn get_allowed_drivers(&self) -> Option<&[u32]> {
+        match self {
+            TbfHeader::TbfHeaderV2(hd) => match &hd.allowed_drivers {
+                Some(allowed_drivers) => allowed_drivers
+                    .driver_numbers
+                    .get(..allowed_drivers.length as usize),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Return the offset where the binary ends in the TBF or 0 if there
     /// is no binary. If there is a Main header the end offset is the size
     /// of the TBF, while if there is a Program header it can be smaller.
@@ -0,0 +1,148 @@
+#!/usr/bin/env python3
+
+# Licensed under the Apache License, Version 2.0 or the MIT License.
+# SPDX-License-Identifier: Apache-2.0 OR MIT
+# Copyright Tock Contributors 2023.
+
+# Prints the static RAM footprint of a Tock kernel, grouped by the crate and
+# module of the type stored in each `static_buf!()`.
+#
+# Usage: print_tock_footprint.py ELF
+
+# pylint: disable=superfluous-parens
+"""
+Script to print out the per-capsule RAM footprint of a Tock kernel ELF.
+
+The kernel's `static_buf!()` macro emits a record into the non-allocated
+`.tock_footprint` section for every statically allocated object. This script
+extracts that section and sums the records by type path.
+
+Usage: print_tock_footprint.py ELF
+Options:
+  -dn, --depth=n      Group types at depth n of their path. E.g., depth=2
+                      reports capsules_core::console and
+                      capsules_core::alarm separately. Default: 2
+  -a, --all           Print every record individually.
+  -s, --size          Sort groups by size (normally lexicographic)
+      --objcopy       Path to the llvm-objcopy executable
+"""
+
+import getopt
+import os
+import struct
+import subprocess
+import sys
+import tempfile
+
+OBJCOPY = "llvm-objcopy"
+SECTION = ".tock_footprint"
+
+# Must match `FOOTPRINT_NAME_LEN` in kernel/src/utilities/static_init.rs.
+NAME_LEN = 104
+RECORD_FORMAT = "<II%ds" % NAME_LEN
+RECORD_SIZE = struct.calcsize(RECORD_FORMAT)
+
+
+def usage(message):
+    """Prints out an error message and usage"""
+    if message != "":
+        print("error: " + message)
+    print(
+        """Usage: print_tock_footprint.py ELF
+Options:
+  -a, --all           Print every record individually.
+  -dn, --depth=n      Group types at depth n of their path. Default: 2
+  -s, --size          Sort groups by size (normally lexicographic)
+      --objcopy       Path to the llvm-objcopy executable"""
+    )
+
+
+def read_records(elf_name):
+    """Extracts the footprint section from the ELF and returns a list of
+    (name, size, align) tuples."""
+    with tempfile.TemporaryDirectory() as tmp:
+        out = os.path.join(tmp, "footprint.bin")
+        subprocess.run(
+            [OBJCOPY, "--dump-section", SECTION + "=" + out, elf_name, os.devnull],
+            check=True,
+        )
+        with open(out, "rb") as f:
+            data = f.read()
+
+    records = []
+    for offset in range(0, len(data) - RECORD_SIZE + 1, RECORD_SIZE):
+        (size, align, name) = struct.unpack_from(RECORD_FORMAT, data, offset)
+        name = name.split(b"\0", 1)[0].decode("utf-8", errors="replace")
+        records.append((name, size, align))
+    return records
+
+
+def group_name(name, depth):
+    """Returns the first `depth` path components of a type name, ignoring any
+    generic parameters."""
+    base = name.split("<", 1)[0].lstrip("&")
+    return "::".join(base.split("::")[:depth])
+
+
+def main():
+    """Parses arguments and prints the report."""
+    global OBJCOPY
+    depth = 2
+    print_all = False
+    sort_by_size = False
+
+    try:
+        opts, args = getopt.getopt(
+            sys.argv[1:], "ad:s", ["all", "depth=", "size", "objcopy="]
+        )
+    except getopt.GetoptError as err:
+        usage(str(err))
+        sys.exit(2)
+
+    for opt, val in opts:
+        if opt in ("-a", "--all"):
+            print_all = True
+        elif opt in ("-d", "--depth"):
+            depth = int(val)
+        elif opt in ("-s", "--size"):
+            sort_by_size = True
+        elif opt == "--objcopy":
+            OBJCOPY = val
+
+    if len(args) != 1:
+        usage("no ELF specified")
+        sys.exit(2)
+
+    records = read_records(args[0])
+    if len(records) == 0:
+        print("No footprint records found in " + args[0])
+        return
+
+    total = sum(size for (_, size, _) in records)
+    print("Static kernel RAM footprint: " + str(total) + " bytes in "
+          + str(len(records)) + " buffers")
+
+    if print_all:
+        entries = [(name, size) for (name, size, _) in records]
+    else:
+        groups = {}
+        for (name, size, _) in records:
+            key = group_name(name, depth)
+            groups[key] = groups.get(key, 0) + size
+        entries = list(groups.items())
+
+    if sort_by_size:
+        entries.sort(key=lambda e: e[1], reverse=True)
+    else:
+        entries.sort()
+
+    for (name, size) in entries:
+        print("  {:>7} {:>5.1f}%  {}".format(size, 100.0 * size / total, name))
+
+    print()
+    print("Per-process grant region usage is reported at runtime by")
+    print("kernel::introspection::KernelInfo::grant_region_footprint().")
+
+
+if __name__ == "__main__":
+    main()